@@ -0,0 +1,307 @@
+//! Audit logging.
+//!
+//! [`record`] writes an entry to `audit_logs` for every mutating request
+//! under `/api/v1`, once the handler has run: who made it, the action and
+//! resource derived from the route, and the request and response bodies as
+//! the resource's new state. Handlers that load a resource before changing
+//! it can attach its previous state with [`AuditBefore`].
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use axum::body::{Body, Bytes, HttpBody};
+use axum::extract::{ConnectInfo, MatchedPath, OriginalUri, Request, State};
+use axum::http::{HeaderMap, Method, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponseParts, Response, ResponseParts};
+use buildit_db::{AuditLog, OrganizationRepo};
+use serde::Serialize;
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::auth::AuthContext;
+
+/// Largest request or response body recorded, in bytes. Larger bodies,
+/// and bodies that are not JSON, are left out of the entry.
+const BODY_LIMIT: u64 = 64 * 1024;
+
+/// Keys whose values are never recorded.
+const REDACTED: &str = "[redacted]";
+
+/// State of a resource before the request changed it. Handlers return it
+/// as a response part; it ends up in the entry's `before` metadata.
+#[derive(Debug, Clone)]
+pub struct AuditBefore(pub Value);
+
+impl AuditBefore {
+    pub fn of<T: Serialize>(value: &T) -> Self {
+        Self(serde_json::to_value(value).unwrap_or(Value::Null))
+    }
+}
+
+impl IntoResponseParts for AuditBefore {
+    type Error = Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        res.extensions_mut().insert(self);
+        Ok(res)
+    }
+}
+
+/// Middleware recording mutating requests. Runs inside [`require_auth`],
+/// so requests that fail authentication are not recorded; requests denied
+/// by a permission check are, with their `403` status.
+///
+/// [`require_auth`]: crate::auth::require_auth
+pub async fn record(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    if matches!(method, Method::GET | Method::HEAD | Method::OPTIONS) {
+        return next.run(request).await;
+    }
+
+    let path = request
+        .extensions()
+        .get::<OriginalUri>()
+        .map(|uri| uri.path().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| path.clone());
+    let auth = request.extensions().get::<AuthContext>().cloned();
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|c| c.0.ip().to_string());
+    let ip_address = client_ip(request.headers()).or(peer);
+    let user_agent = request
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.chars().take(512).collect::<String>());
+
+    let (request, body) = buffer_request(request).await;
+    let response = next.run(request).await;
+    let before = response.extensions().get::<AuditBefore>().cloned();
+    let status = response.status();
+    let (response, after) = buffer_response(response).await;
+
+    let (action, resource_type) = describe(&method, &route);
+    let mut metadata = json!({
+        "method": method.as_str(),
+        "path": path,
+        "status": status.as_u16(),
+    });
+    if let Some(auth) = &auth {
+        metadata["auth_method"] = json!(auth.method);
+        if let Some(key) = auth.api_key_id {
+            metadata["api_key_id"] = json!(key);
+        }
+    }
+    if let Some(body) = body {
+        metadata["request"] = body;
+    }
+    if let Some(before) = before {
+        metadata["before"] = redact(before.0);
+    }
+    if let Some(after) = after.filter(|_| status.is_success()) {
+        metadata["after"] = after;
+    }
+
+    let entry = AuditLog {
+        id: Uuid::new_v4(),
+        organization_id: auth.as_ref().and_then(|a| a.organization_id),
+        tenant_id: auth.as_ref().and_then(|a| a.tenant_id),
+        user_id: auth.as_ref().and_then(|a| a.user_id),
+        action,
+        resource_type,
+        resource_id: path.split('/').find_map(|s| s.parse::<Uuid>().ok()),
+        metadata,
+        ip_address,
+        user_agent,
+        created_at: chrono::Utc::now(),
+    };
+    if let Err(e) = state.organization_repo.create_audit_log(&entry).await {
+        tracing::warn!(action = %entry.action, path = %path, "Failed to write audit log: {}", e);
+    }
+
+    response
+}
+
+/// Action and resource type of a request, from its route: the route's
+/// literal segments and a verb for the method, e.g. `PUT
+/// /pipelines/{id}/owners` is `pipelines.owners.update` on `pipelines`.
+/// A `POST` to a singular segment after a parameter names the action
+/// itself: `POST /stacks/{id}/runs/{run_id}/approve` is
+/// `stacks.runs.approve`.
+fn describe(method: &Method, route: &str) -> (String, Option<String>) {
+    let route = route.strip_prefix("/api/v1").unwrap_or(route);
+    let segments: Vec<&str> = route.split('/').filter(|s| !s.is_empty()).collect();
+    let literals: Vec<&str> = segments.iter().copied().filter(|s| !is_param(s)).collect();
+
+    let named = *method == Method::POST
+        && segments.len() >= 2
+        && is_param(segments[segments.len() - 2])
+        && segments
+            .last()
+            .is_some_and(|s| !is_param(s) && !s.ends_with('s'));
+    let mut action = literals.join(".");
+    if !named {
+        let verb = match *method {
+            Method::POST => "create".to_string(),
+            Method::PUT | Method::PATCH => "update".to_string(),
+            Method::DELETE => "delete".to_string(),
+            ref other => other.as_str().to_lowercase(),
+        };
+        if !action.is_empty() {
+            action.push('.');
+        }
+        action.push_str(&verb);
+    }
+    action.truncate(100);
+
+    let resource_type = literals.first().map(|s| s.chars().take(50).collect());
+    (action, resource_type)
+}
+
+fn is_param(segment: &str) -> bool {
+    segment.starts_with('{')
+}
+
+/// The client's address as reported by a proxy in front of the server.
+fn client_ip(headers: &HeaderMap) -> Option<String> {
+    let forwarded = headers
+        .get("x-forwarded-for")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(',').next())
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty());
+    forwarded.or_else(|| {
+        headers
+            .get("x-real-ip")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_string())
+    })
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"))
+}
+
+/// Read a small JSON body, putting it back into the request.
+async fn buffer_request(request: Request) -> (Request, Option<Value>) {
+    if !is_json(request.headers()) || !fits(request.body()) {
+        return (request, None);
+    }
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, BODY_LIMIT as usize)
+        .await
+        .unwrap_or_default();
+    let value = parse(&bytes);
+    (Request::from_parts(parts, Body::from(bytes)), value)
+}
+
+/// Read a small JSON body, putting it back into the response.
+async fn buffer_response(response: Response) -> (Response, Option<Value>) {
+    if !is_json(response.headers()) || !fits(response.body()) {
+        return (response, None);
+    }
+    let (parts, body) = response.into_parts();
+    let bytes = axum::body::to_bytes(body, BODY_LIMIT as usize)
+        .await
+        .unwrap_or_default();
+    let value = parse(&bytes);
+    (Response::from_parts(parts, Body::from(bytes)), value)
+}
+
+/// Whether the body is known to be within [`BODY_LIMIT`].
+fn fits(body: &Body) -> bool {
+    body.size_hint().upper().is_some_and(|n| n <= BODY_LIMIT)
+}
+
+fn parse(bytes: &Bytes) -> Option<Value> {
+    serde_json::from_slice(bytes).ok().map(redact)
+}
+
+/// Blank out values that may be secrets: passwords, tokens, keys,
+/// variable values and kubeconfigs.
+fn redact(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(k, v)| {
+                    if is_secret(&k) && !v.is_null() {
+                        (k, Value::String(REDACTED.to_string()))
+                    } else {
+                        (k, redact(v))
+                    }
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(redact).collect()),
+        other => other,
+    }
+}
+
+fn is_secret(key: &str) -> bool {
+    let key = key.to_lowercase();
+    ["password", "secret", "token", "private_key", "credential"]
+        .iter()
+        .any(|s| key.contains(s))
+        || matches!(key.as_str(), "value" | "kubeconfig" | "key_hash")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_action() {
+        let describe = |method, route| describe(&method, route);
+        assert_eq!(
+            describe(Method::PUT, "/api/v1/pipelines/{id}/owners"),
+            (
+                "pipelines.owners.update".to_string(),
+                Some("pipelines".to_string())
+            )
+        );
+        assert_eq!(
+            describe(Method::POST, "/api/v1/stacks/{id}/runs/{run_id}/approve").0,
+            "stacks.runs.approve"
+        );
+        assert_eq!(
+            describe(Method::POST, "/api/v1/pipelines/{id}/runs").0,
+            "pipelines.runs.create"
+        );
+        assert_eq!(
+            describe(Method::DELETE, "/api/v1/tenants/{id}").0,
+            "tenants.delete"
+        );
+    }
+
+    #[test]
+    fn test_secrets_are_redacted() {
+        let body = json!({
+            "name": "deploy",
+            "token": "bk_live_123",
+            "credentials": {"kubeconfig": "apiVersion: v1"},
+            "variables": [{"name": "DB_URL", "value": "postgres://secret"}],
+            "password": null,
+        });
+        assert_eq!(
+            redact(body),
+            json!({
+                "name": "deploy",
+                "token": REDACTED,
+                "credentials": REDACTED,
+                "variables": [{"name": "DB_URL", "value": REDACTED}],
+                "password": null,
+            })
+        );
+    }
+}
@@ -0,0 +1,1093 @@
+//! Authentication for `/api/v1`.
+//!
+//! Requests authenticate with either the session cookie set at sign-in, a
+//! CLI access token (a session signed in with the device flow, see
+//! [`ACCESS_TOKEN_PREFIX`]), or an API key. Tokens are sent as a Bearer
+//! token or as the Basic auth password (for clients like Terraform's `http`
+//! backend). [`require_auth`] resolves the
+//! caller into an [`AuthContext`], which handlers take as an extractor.
+//!
+//! Sessions act in one organization, and optionally one tenant, chosen with
+//! the `X-BuildIt-Organization` and `X-BuildIt-Tenant` headers; a user in a
+//! single organization needs neither. The tenant may also come from the host
+//! or the session's earlier switch, see [`crate::tenancy`]. API keys act in
+//! the organization (and tenant, if scoped to one) they were created for.
+//!
+//! API key scopes are checked per area, the first path segment under
+//! `/api/v1`: `admin` and `write` allow everything, `read` allows `GET`,
+//! and `<area>:write` / `<area>:read` (e.g. `stacks:write`) do the same for
+//! one area.
+//!
+//! Handlers then check the caller's [`Permission`]s in the tenant they act
+//! on with [`AuthContext::require`].
+//!
+//! Missing or invalid credentials get `401`, credentials that do not reach
+//! the requested organization, tenant or area, or lack a permission, get
+//! `403`.
+
+use axum::Json;
+use axum::extract::{FromRequestParts, Request, State};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, Method, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum_extra::extract::CookieJar;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::Serialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::error::ApiError;
+use crate::tenancy::requested_tenant;
+use buildit_config::system;
+use buildit_core::ResourceId;
+use buildit_core::rbac::{Permission, Role};
+use buildit_db::{ApiKey, DbError, OrganizationRepo, Session, Tenant, TenantRepo};
+
+/// Cookie holding the session token.
+pub const SESSION_COOKIE: &str = "buildit_session";
+
+/// Cookie holding the token of the invitation being answered, so that
+/// signing in returns to it.
+pub const INVITATION_COOKIE: &str = "buildit_invitation";
+
+/// Prefix of access tokens issued to the CLI, which authenticate as a
+/// session rather than an API key.
+pub const ACCESS_TOKEN_PREFIX: &str = "bs_";
+
+/// Prefix of refresh tokens issued to the CLI.
+pub const REFRESH_TOKEN_PREFIX: &str = "br_";
+
+/// Prefix of issued API keys. Keys are looked up by their first
+/// [`API_KEY_LOOKUP_LEN`] characters and checked against their hash.
+pub const API_KEY_PREFIX: &str = "bld_";
+
+/// Prefix of invitation tokens, sent in the invitee's link.
+pub const INVITATION_TOKEN_PREFIX: &str = "bi_";
+
+/// Length of the stored, displayable API key prefix.
+pub const API_KEY_LOOKUP_LEN: usize = 12;
+
+/// Header selecting the organization a session acts in.
+pub const ORGANIZATION_HEADER: &str = "x-buildit-organization";
+
+/// Header selecting the tenant a request acts in, by ID or slug.
+pub const TENANT_HEADER: &str = "x-buildit-tenant";
+
+/// Authentication settings.
+#[derive(Debug, Clone)]
+pub struct AuthConfig {
+    /// Let requests without credentials through as anonymous, for local
+    /// development (`BUILDIT_AUTH_DISABLED`). Credentials that are sent are
+    /// still checked.
+    pub disabled: bool,
+    /// How long a session lasts after sign-in (`BUILDIT_SESSION_TTL_HOURS`).
+    /// CLI sessions can be refreshed for this long.
+    pub session_ttl: chrono::Duration,
+    /// How long a CLI access token lasts before it must be refreshed
+    /// (`BUILDIT_ACCESS_TOKEN_TTL_MINUTES`).
+    pub access_token_ttl: chrono::Duration,
+    /// URL users reach the server at, for links such as the device
+    /// verification page (`BUILDIT_PUBLIC_URL`). Defaults to the request's
+    /// host.
+    pub public_url: Option<String>,
+    /// Domain whose subdomains name tenants (`BUILDIT_TENANT_DOMAIN`), e.g.
+    /// `buildit.example.com` for `acme.buildit.example.com`.
+    pub tenant_domain: Option<String>,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            disabled: false,
+            session_ttl: chrono::Duration::days(30),
+            access_token_ttl: chrono::Duration::hours(1),
+            public_url: None,
+            tenant_domain: None,
+        }
+    }
+}
+
+impl AuthConfig {
+    /// Settings of the system configuration's `auth`, defaults for the
+    /// unset ones.
+    pub fn from_config(config: &system::AuthConfig) -> Self {
+        let defaults = Self::default();
+        let disabled = config.disabled.unwrap_or(defaults.disabled);
+        if disabled {
+            tracing::warn!("Authentication is disabled");
+        }
+        Self {
+            disabled,
+            session_ttl: config
+                .session_ttl_hours
+                .map(chrono::Duration::hours)
+                .unwrap_or(defaults.session_ttl),
+            access_token_ttl: config
+                .access_token_ttl_minutes
+                .map(chrono::Duration::minutes)
+                .unwrap_or(defaults.access_token_ttl),
+            public_url: config
+                .public_url
+                .as_deref()
+                .map(|url| url.trim().trim_end_matches('/').to_string())
+                .filter(|url| !url.is_empty()),
+            tenant_domain: config
+                .tenant_domain
+                .as_deref()
+                .map(|domain| domain.trim().trim_matches('.').to_lowercase())
+                .filter(|domain| !domain.is_empty()),
+        }
+    }
+}
+
+/// How the caller authenticated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMethod {
+    Session,
+    ApiKey,
+    /// No credentials, with authentication disabled.
+    Anonymous,
+}
+
+/// The authenticated caller of a request.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AuthContext {
+    pub method: AuthMethod,
+    pub user_id: Option<Uuid>,
+    pub organization_id: Option<Uuid>,
+    pub tenant_id: Option<Uuid>,
+    /// The API key used, if any.
+    pub api_key_id: Option<Uuid>,
+    /// Scopes of the API key; `None` for sessions, which are not scoped.
+    pub scopes: Option<Vec<String>>,
+    /// The API key only reaches `tenant_id`.
+    pub tenant_scoped: bool,
+    /// The session used, if any.
+    #[serde(skip)]
+    pub session_id: Option<Uuid>,
+}
+
+impl AuthContext {
+    fn anonymous(tenant_id: Option<Uuid>) -> Self {
+        Self {
+            method: AuthMethod::Anonymous,
+            user_id: None,
+            organization_id: None,
+            tenant_id,
+            api_key_id: None,
+            scopes: None,
+            tenant_scoped: false,
+            session_id: None,
+        }
+    }
+
+    /// A user acting in `tenant_id` without a session, through a Slack
+    /// account linked to them.
+    pub fn linked_user(user_id: Uuid, tenant_id: Uuid) -> Self {
+        Self {
+            method: AuthMethod::Session,
+            user_id: Some(user_id),
+            organization_id: None,
+            tenant_id: Some(tenant_id),
+            api_key_id: None,
+            scopes: None,
+            tenant_scoped: false,
+            session_id: None,
+        }
+    }
+
+    /// Whether the caller's scopes allow `method` on `area`.
+    pub fn scope_allows(&self, method: &Method, area: &str) -> bool {
+        let Some(scopes) = &self.scopes else {
+            return true;
+        };
+        let read_only = matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS);
+        scopes.iter().any(|scope| match scope.split_once(':') {
+            None => match scope.as_str() {
+                "admin" | "write" => true,
+                "read" => read_only,
+                _ => false,
+            },
+            Some((scope_area, access)) => {
+                scope_area == area && (access == "write" || (access == "read" && read_only))
+            }
+        })
+    }
+}
+
+impl AuthContext {
+    /// The caller's role in `tenant_id`, `None` if it cannot reach it.
+    ///
+    /// API keys hold the role their scopes amount to in their organization's
+    /// tenants. Users hold the higher of their organization and tenant
+    /// roles.
+    pub async fn role_in(
+        &self,
+        state: &AppState,
+        tenant_id: Uuid,
+    ) -> Result<Option<Role>, ApiError> {
+        let repo = &state.organization_repo;
+        match self.method {
+            AuthMethod::Anonymous => Ok(Some(Role::Owner)),
+            AuthMethod::ApiKey => {
+                if self.tenant_scoped && self.tenant_id != Some(tenant_id) {
+                    return Ok(None);
+                }
+                let organization = repo
+                    .get_tenant_organization(ResourceId::from_uuid(tenant_id))
+                    .await?;
+                if organization.is_none() || organization != self.organization_id {
+                    return Ok(None);
+                }
+                Ok(Some(self.scope_role()))
+            }
+            AuthMethod::Session => {
+                let Some(user_id) = self.user_id else {
+                    return Ok(None);
+                };
+                let user = ResourceId::from_uuid(user_id);
+                let org_role = match repo
+                    .get_tenant_organization(ResourceId::from_uuid(tenant_id))
+                    .await?
+                {
+                    Some(org_id) => membership_role(
+                        repo.get_org_membership(ResourceId::from_uuid(org_id), user)
+                            .await
+                            .map(|m| m.role),
+                    )?,
+                    None => None,
+                };
+                let tenant_role = membership_role(
+                    repo.get_tenant_membership(ResourceId::from_uuid(tenant_id), user)
+                        .await
+                        .map(|m| m.role),
+                )?;
+                Ok(org_role.max(tenant_role))
+            }
+        }
+    }
+
+    /// The caller's role across the organization `org_id`.
+    pub async fn org_role(&self, state: &AppState, org_id: Uuid) -> Result<Option<Role>, ApiError> {
+        match self.method {
+            AuthMethod::Anonymous => Ok(Some(Role::Owner)),
+            // A key scoped to one tenant holds no role across the organization
+            AuthMethod::ApiKey if self.tenant_scoped => Ok(None),
+            AuthMethod::ApiKey if self.organization_id != Some(org_id) => Ok(None),
+            AuthMethod::ApiKey => Ok(Some(self.scope_role())),
+            AuthMethod::Session => {
+                let Some(user_id) = self.user_id else {
+                    return Ok(None);
+                };
+                membership_role(
+                    state
+                        .organization_repo
+                        .get_org_membership(
+                            ResourceId::from_uuid(org_id),
+                            ResourceId::from_uuid(user_id),
+                        )
+                        .await
+                        .map(|m| m.role),
+                )
+            }
+        }
+    }
+
+    /// The tenant the request acts in: the one it selected, else the only
+    /// one the caller reaches.
+    pub async fn tenant(&self, state: &AppState) -> Result<Tenant, ApiError> {
+        if let Some(tenant_id) = self.tenant_id {
+            return Ok(state
+                .tenant_repo
+                .get_by_id(ResourceId::from_uuid(tenant_id))
+                .await?);
+        }
+        let candidates = match (self.method, self.organization_id, self.user_id) {
+            (_, Some(org_id), _) => {
+                state
+                    .tenant_repo
+                    .list_by_organization(ResourceId::from_uuid(org_id))
+                    .await?
+            }
+            (AuthMethod::Session, None, Some(user_id)) => {
+                state
+                    .tenant_repo
+                    .list_for_user(ResourceId::from_uuid(user_id))
+                    .await?
+            }
+            (AuthMethod::Anonymous, None, _) => state.tenant_repo.list().await?,
+            _ => Vec::new(),
+        };
+        match <[Tenant; 1]>::try_from(candidates) {
+            Ok([only]) => Ok(only),
+            Err(_) => Err(ApiError::BadRequest(format!(
+                "No tenant selected; set the {} header or switch with PUT /api/v1/me/tenant",
+                TENANT_HEADER
+            ))),
+        }
+    }
+
+    /// `tenant_id` if given, else the tenant the request acts in.
+    pub async fn tenant_or(
+        &self,
+        state: &AppState,
+        tenant_id: Option<Uuid>,
+    ) -> Result<Uuid, ApiError> {
+        match tenant_id {
+            Some(tenant_id) => Ok(tenant_id),
+            None => Ok(self.tenant(state).await?.id),
+        }
+    }
+
+    /// Fail with `403` unless the caller holds `permission` in `tenant_id`.
+    pub async fn require(
+        &self,
+        state: &AppState,
+        tenant_id: Uuid,
+        permission: Permission,
+    ) -> Result<(), ApiError> {
+        let role = self.role_in(state, tenant_id).await?;
+        if role.is_some_and(|r| r.grants(permission)) {
+            return Ok(());
+        }
+        Err(ApiError::Forbidden(format!(
+            "Missing permission {} in tenant {}",
+            permission, tenant_id
+        )))
+    }
+
+    /// Fail with `403` unless the caller holds `permission` across the
+    /// organization `org_id`.
+    pub async fn require_org(
+        &self,
+        state: &AppState,
+        org_id: Uuid,
+        permission: Permission,
+    ) -> Result<(), ApiError> {
+        let role = self.org_role(state, org_id).await?;
+        if role.is_some_and(|r| r.grants(permission)) {
+            return Ok(());
+        }
+        Err(ApiError::Forbidden(format!(
+            "Missing permission {} in organization {}",
+            permission, org_id
+        )))
+    }
+
+    /// The organization the caller acts in, which requests that act on the
+    /// organization rather than a tenant need.
+    pub fn organization(&self) -> Result<Uuid, ApiError> {
+        self.organization_id.ok_or_else(|| {
+            ApiError::BadRequest(format!(
+                "No organization selected; set the {} header",
+                ORGANIZATION_HEADER
+            ))
+        })
+    }
+
+    /// Role an API key's scopes amount to. Area scopes are narrowed further
+    /// by [`require_auth`].
+    fn scope_role(&self) -> Role {
+        scopes_role(self.scopes.as_deref().unwrap_or_default())
+    }
+}
+
+/// The role an API key with `scopes` acts with.
+pub fn scopes_role(scopes: &[String]) -> Role {
+    if scopes.iter().any(|s| s == "admin") {
+        Role::Admin
+    } else if scopes.iter().any(|s| s == "write" || s.ends_with(":write")) {
+        Role::Member
+    } else {
+        Role::Viewer
+    }
+}
+
+/// Whether `scope` is one [`AuthContext::scope_allows`] understands, or
+/// `runners:register`.
+pub fn is_valid_scope(scope: &str) -> bool {
+    match scope.split_once(':') {
+        None => matches!(scope, "admin" | "write" | "read"),
+        Some(("runners", "register")) => true,
+        Some((area, access)) => {
+            !area.is_empty()
+                && area
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c == '-' || c == '_')
+                && matches!(access, "read" | "write")
+        }
+    }
+}
+
+/// The role of a membership, `None` if there is none.
+fn membership_role(membership: Result<String, DbError>) -> Result<Option<Role>, ApiError> {
+    match membership {
+        Ok(role) => Ok(Role::parse(&role)),
+        Err(DbError::NotFound(_)) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for AuthContext {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<AuthContext>()
+            .cloned()
+            .ok_or_else(|| unauthorized("Authentication required"))
+    }
+}
+
+/// Middleware authenticating every request it wraps.
+pub async fn require_auth(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let context = match resolve(&state, request.headers()).await {
+        Ok(Some(context)) => context,
+        Ok(None) if state.auth.disabled => {
+            match requested_tenant(&state, request.headers()).await {
+                Ok(tenant_id) => AuthContext::anonymous(tenant_id),
+                Err(e) => return e.into_response(),
+            }
+        }
+        Ok(None) => return unauthorized("Authentication required"),
+        Err(response) => return response,
+    };
+
+    // Any caller may ask who they are
+    let area = area(request.uri().path());
+    if area != "me" && !context.scope_allows(request.method(), area) {
+        return ApiError::Forbidden(format!(
+            "API token has no scope for {} on {}",
+            request.method(),
+            area
+        ))
+        .into_response();
+    }
+
+    request.extensions_mut().insert(context);
+    next.run(request).await
+}
+
+/// First path segment under `/api/v1`.
+fn area(path: &str) -> &str {
+    let path = path.strip_prefix("/api/v1").unwrap_or(path);
+    path.trim_start_matches('/').split('/').next().unwrap_or("")
+}
+
+/// Authenticate the request's credentials, if it has any.
+async fn resolve(state: &AppState, headers: &HeaderMap) -> Result<Option<AuthContext>, Response> {
+    let tenant = requested_tenant(state, headers)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    let token = match bearer_or_basic_password(headers) {
+        Some(token) if token.starts_with(ACCESS_TOKEN_PREFIX) => Some(token),
+        _ if headers.contains_key(header::AUTHORIZATION) => None,
+        _ => match CookieJar::from_headers(headers).get(SESSION_COOKIE) {
+            Some(cookie) => Some(cookie.value().to_string()),
+            None => return Ok(None),
+        },
+    };
+
+    let Some(token) = token else {
+        let key = authenticate_api_key(state, headers).await?;
+        let tenant_id = api_key_tenant(state, &key, tenant)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        return Ok(Some(AuthContext {
+            method: AuthMethod::ApiKey,
+            user_id: key.user_id,
+            organization_id: Some(key.organization_id),
+            tenant_id,
+            api_key_id: Some(key.id),
+            scopes: Some(key.scopes),
+            tenant_scoped: key.tenant_id.is_some(),
+            session_id: None,
+        }));
+    };
+
+    let session = match state
+        .organization_repo
+        .get_session_by_token(&token_hash(&token))
+        .await
+    {
+        Ok(session) => session,
+        Err(DbError::NotFound(_)) => return Err(unauthorized("Session expired")),
+        Err(e) => return Err(ApiError::from(e).into_response()),
+    };
+
+    let organization =
+        header_uuid(headers, ORGANIZATION_HEADER).map_err(IntoResponse::into_response)?;
+    let scope = match (tenant, session.tenant_id) {
+        (None, Some(selected)) => {
+            match session_scope(state, session.user_id, organization, Some(selected)).await {
+                // The tenant switched to is out of reach now, or not in the
+                // requested organization
+                Err(ApiError::Forbidden(_) | ApiError::BadRequest(_)) => {
+                    session_scope(state, session.user_id, organization, None).await
+                }
+                scope => scope,
+            }
+        }
+        _ => session_scope(state, session.user_id, organization, tenant).await,
+    };
+    let (organization_id, tenant_id) = scope.map_err(IntoResponse::into_response)?;
+    Ok(Some(AuthContext {
+        method: AuthMethod::Session,
+        user_id: Some(session.user_id),
+        organization_id,
+        tenant_id,
+        api_key_id: None,
+        scopes: None,
+        tenant_scoped: false,
+        session_id: Some(session.id),
+    }))
+}
+
+/// The user signed in with the request's session cookie, for pages outside
+/// `/api/v1`.
+pub(crate) async fn cookie_user(
+    state: &AppState,
+    headers: &HeaderMap,
+) -> Result<Option<Uuid>, ApiError> {
+    Ok(cookie_session(state, headers).await?.map(|s| s.user_id))
+}
+
+/// The session of the request's session cookie, if it is valid.
+pub(crate) async fn cookie_session(
+    state: &AppState,
+    headers: &HeaderMap,
+) -> Result<Option<Session>, ApiError> {
+    let jar = CookieJar::from_headers(headers);
+    let Some(cookie) = jar.get(SESSION_COOKIE) else {
+        return Ok(None);
+    };
+    match state
+        .organization_repo
+        .get_session_by_token(&token_hash(cookie.value()))
+        .await
+    {
+        Ok(session) => Ok(Some(session)),
+        Err(DbError::NotFound(_)) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The tenant an API key acts in: the one it is scoped to, or the requested
+/// one if it belongs to the key's organization.
+async fn api_key_tenant(
+    state: &AppState,
+    key: &ApiKey,
+    requested: Option<Uuid>,
+) -> Result<Option<Uuid>, ApiError> {
+    match (key.tenant_id, requested) {
+        (Some(scoped), Some(requested)) if scoped != requested => Err(ApiError::Forbidden(
+            format!("API token has no access to tenant {}", requested),
+        )),
+        (Some(scoped), _) => Ok(Some(scoped)),
+        (None, Some(requested)) => {
+            let organization = state
+                .organization_repo
+                .get_tenant_organization(ResourceId::from_uuid(requested))
+                .await?;
+            if organization != Some(key.organization_id) {
+                return Err(ApiError::Forbidden(format!(
+                    "API token has no access to tenant {}",
+                    requested
+                )));
+            }
+            Ok(Some(requested))
+        }
+        (None, None) => Ok(None),
+    }
+}
+
+/// The organization and tenant a session acts in. The user must be a member
+/// of the organization, and of the tenant or the tenant's organization.
+async fn session_scope(
+    state: &AppState,
+    user_id: Uuid,
+    organization: Option<Uuid>,
+    tenant: Option<Uuid>,
+) -> Result<(Option<Uuid>, Option<Uuid>), ApiError> {
+    let repo = &state.organization_repo;
+    let user = ResourceId::from_uuid(user_id);
+
+    if let Some(tenant_id) = tenant {
+        let tenant_org = repo
+            .get_tenant_organization(ResourceId::from_uuid(tenant_id))
+            .await?;
+        if organization.is_some() && organization != tenant_org {
+            return Err(ApiError::BadRequest(format!(
+                "Tenant {} is not in the requested organization",
+                tenant_id
+            )));
+        }
+        let member = match repo
+            .get_tenant_membership(ResourceId::from_uuid(tenant_id), user)
+            .await
+        {
+            Ok(_) => true,
+            Err(DbError::NotFound(_)) => match tenant_org {
+                Some(org_id) => is_org_member(state, org_id, user_id).await?,
+                None => false,
+            },
+            Err(e) => return Err(e.into()),
+        };
+        if !member {
+            return Err(ApiError::Forbidden(format!(
+                "No access to tenant {}",
+                tenant_id
+            )));
+        }
+        return Ok((tenant_org, Some(tenant_id)));
+    }
+
+    if let Some(org_id) = organization {
+        if !is_org_member(state, org_id, user_id).await? {
+            return Err(ApiError::Forbidden(format!(
+                "No access to organization {}",
+                org_id
+            )));
+        }
+        return Ok((Some(org_id), None));
+    }
+
+    // A user in a single organization need not pick it.
+    let organizations = repo.list_user_organizations(user).await?;
+    match organizations.as_slice() {
+        [only] => Ok((Some(only.id), None)),
+        _ => Ok((None, None)),
+    }
+}
+
+async fn is_org_member(state: &AppState, org_id: Uuid, user_id: Uuid) -> Result<bool, ApiError> {
+    match state
+        .organization_repo
+        .get_org_membership(
+            ResourceId::from_uuid(org_id),
+            ResourceId::from_uuid(user_id),
+        )
+        .await
+    {
+        Ok(_) => Ok(true),
+        Err(DbError::NotFound(_)) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn header_uuid(headers: &HeaderMap, name: &str) -> Result<Option<Uuid>, ApiError> {
+    let Some(value) = headers.get(name) else {
+        return Ok(None);
+    };
+    value
+        .to_str()
+        .ok()
+        .and_then(|v| Uuid::parse_str(v.trim()).ok())
+        .map(Some)
+        .ok_or_else(|| ApiError::BadRequest(format!("Invalid {} header", name)))
+}
+
+/// Hex SHA-256 of a token, as stored for API keys, sessions and runners.
+pub fn token_hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Resolve the API key sent with the request.
+pub(crate) async fn authenticate_api_key(
+    state: &AppState,
+    headers: &HeaderMap,
+) -> Result<ApiKey, Response> {
+    let token = bearer_or_basic_password(headers)
+        .ok_or_else(|| unauthorized("An API token is required"))?;
+    let prefix = token
+        .get(..API_KEY_LOOKUP_LEN)
+        .ok_or_else(|| unauthorized("Invalid API token"))?;
+
+    let key = match state
+        .organization_repo
+        .validate_api_key(prefix, &token_hash(&token))
+        .await
+    {
+        Ok(key) => key,
+        Err(DbError::NotFound(_)) => return Err(unauthorized("Invalid API token")),
+        Err(e) => return Err(ApiError::from(e).into_response()),
+    };
+    let _ = state
+        .organization_repo
+        .update_api_key_last_used(ResourceId::from_uuid(key.id))
+        .await;
+    Ok(key)
+}
+
+pub(crate) fn bearer_or_basic_password(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, credentials) = value.split_once(' ')?;
+    let credentials = credentials.trim();
+    if scheme.eq_ignore_ascii_case("bearer") {
+        return Some(credentials.to_string());
+    }
+    if scheme.eq_ignore_ascii_case("basic") {
+        let decoded = String::from_utf8(STANDARD.decode(credentials).ok()?).ok()?;
+        let (_, password) = decoded.split_once(':')?;
+        return Some(password.to_string());
+    }
+    None
+}
+
+pub(crate) fn unauthorized(message: &str) -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, "Bearer realm=\"buildit\"")],
+        Json(json!({ "error": message })),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_key(tenant_id: Uuid, scopes: &[&str]) -> AuthContext {
+        AuthContext {
+            method: AuthMethod::ApiKey,
+            user_id: None,
+            organization_id: Some(Uuid::new_v4()),
+            tenant_id: Some(tenant_id),
+            api_key_id: Some(Uuid::new_v4()),
+            scopes: Some(scopes.iter().map(|s| s.to_string()).collect()),
+            tenant_scoped: true,
+            session_id: None,
+        }
+    }
+
+    #[test]
+    fn test_roles_grant_permissions() {
+        assert!(Role::Viewer.grants(Permission::PipelineRead));
+        assert!(!Role::Viewer.grants(Permission::PipelineTrigger));
+        assert!(Role::Member.grants(Permission::PipelineTrigger));
+        assert!(!Role::Member.grants(Permission::StackApply));
+        assert!(Role::Admin.grants(Permission::StackApply));
+    }
+
+    #[tokio::test]
+    async fn test_permission_denied_outside_the_keys_tenant() {
+        let state = AppState::without_database();
+        let tenant = Uuid::new_v4();
+        let other = Uuid::new_v4();
+        let auth = api_key(tenant, &["admin"]);
+
+        assert_eq!(auth.role_in(&state, other).await.unwrap(), None);
+        assert!(matches!(
+            auth.require(&state, other, Permission::PipelineRead).await,
+            Err(ApiError::Forbidden(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_anonymous_callers_own_everything() {
+        let state = AppState::without_database();
+        let auth = AuthContext::anonymous(None);
+        assert!(
+            auth.require(&state, Uuid::new_v4(), Permission::TenantManage)
+                .await
+                .is_ok()
+        );
+    }
+
+    fn headers(pairs: &[(&str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(
+                header::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                value.parse().unwrap(),
+            );
+        }
+        headers
+    }
+
+    fn status(result: Result<Option<AuthContext>, Response>) -> StatusCode {
+        match result {
+            Ok(context) => panic!("expected a rejection, got {:?}", context),
+            Err(response) => response.status(),
+        }
+    }
+
+    fn api_key_record(organization_id: Uuid, tenant_id: Option<Uuid>) -> ApiKey {
+        ApiKey {
+            id: Uuid::new_v4(),
+            organization_id,
+            user_id: None,
+            tenant_id,
+            name: "ci".to_string(),
+            key_prefix: String::new(),
+            scopes: vec!["read".to_string()],
+            last_used_at: None,
+            expires_at: None,
+            revoked_at: None,
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_scopes_allow_their_area_and_methods() {
+        let tenant = Uuid::new_v4();
+        let read = api_key(tenant, &["read"]);
+        assert!(read.scope_allows(&Method::GET, "pipelines"));
+        assert!(read.scope_allows(&Method::HEAD, "stacks"));
+        assert!(read.scope_allows(&Method::OPTIONS, "stacks"));
+        assert!(!read.scope_allows(&Method::POST, "pipelines"));
+        assert!(!read.scope_allows(&Method::DELETE, "pipelines"));
+
+        for broad in ["write", "admin"] {
+            let key = api_key(tenant, &[broad]);
+            assert!(key.scope_allows(&Method::POST, "pipelines"), "{}", broad);
+            assert!(key.scope_allows(&Method::DELETE, "stacks"), "{}", broad);
+        }
+
+        let area = api_key(tenant, &["pipelines:write", "stacks:read"]);
+        assert!(area.scope_allows(&Method::POST, "pipelines"));
+        assert!(area.scope_allows(&Method::GET, "pipelines"));
+        assert!(area.scope_allows(&Method::GET, "stacks"));
+        assert!(!area.scope_allows(&Method::PUT, "stacks"));
+        assert!(!area.scope_allows(&Method::GET, "tenants"));
+
+        // Only keys are scoped; odd scopes grant nothing.
+        assert!(!api_key(tenant, &["runners:register"]).scope_allows(&Method::GET, "runners"));
+        assert!(!api_key(tenant, &["pipelines:admin"]).scope_allows(&Method::GET, "pipelines"));
+        assert!(!api_key(tenant, &[]).scope_allows(&Method::GET, "pipelines"));
+        assert!(AuthContext::anonymous(None).scope_allows(&Method::DELETE, "tenants"));
+    }
+
+    #[test]
+    fn test_scopes_map_to_roles() {
+        let scopes = |s: &[&str]| s.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(scopes_role(&scopes(&["read", "admin"])), Role::Admin);
+        assert_eq!(scopes_role(&scopes(&["write"])), Role::Member);
+        assert_eq!(scopes_role(&scopes(&["stacks:write"])), Role::Member);
+        assert_eq!(scopes_role(&scopes(&["stacks:read"])), Role::Viewer);
+        assert_eq!(scopes_role(&[]), Role::Viewer);
+
+        assert!(is_valid_scope("read"));
+        assert!(is_valid_scope("runners:register"));
+        assert!(is_valid_scope("test_results:read"));
+        assert!(!is_valid_scope("stacks:register"));
+        assert!(!is_valid_scope("Stacks:read"));
+        assert!(!is_valid_scope(":read"));
+        assert!(!is_valid_scope("owner"));
+    }
+
+    #[test]
+    fn test_area_is_the_first_segment_under_the_api() {
+        assert_eq!(area("/api/v1/pipelines/123/runs"), "pipelines");
+        assert_eq!(area("/api/v1/me"), "me");
+        assert_eq!(area("/api/v1"), "");
+        assert_eq!(area("/pipelines/123"), "pipelines");
+    }
+
+    #[test]
+    fn test_bearer_or_basic_password() {
+        let token = |value: &str| {
+            bearer_or_basic_password(&headers(&[(header::AUTHORIZATION.as_str(), value)]))
+        };
+        assert_eq!(token("Bearer bld_abc").as_deref(), Some("bld_abc"));
+        assert_eq!(token("bearer  bld_abc ").as_deref(), Some("bld_abc"));
+        // `git` and Docker send the token as a Basic password.
+        let basic = STANDARD.encode("x-token:bld_abc");
+        assert_eq!(
+            token(&format!("Basic {}", basic)).as_deref(),
+            Some("bld_abc")
+        );
+        let no_user = STANDARD.encode(":bld_abc");
+        assert_eq!(
+            token(&format!("basic {}", no_user)).as_deref(),
+            Some("bld_abc")
+        );
+
+        assert_eq!(
+            token(&format!("Basic {}", STANDARD.encode("bld_abc"))),
+            None
+        );
+        assert_eq!(token("Basic not-base64!"), None);
+        assert_eq!(token("Digest bld_abc"), None);
+        assert_eq!(token("bld_abc"), None);
+        assert_eq!(bearer_or_basic_password(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn test_organization_header_must_be_a_uuid() {
+        let org = Uuid::new_v4();
+        let found = header_uuid(
+            &headers(&[(ORGANIZATION_HEADER, &format!(" {} ", org))]),
+            ORGANIZATION_HEADER,
+        );
+        assert_eq!(found.ok().flatten(), Some(org));
+        assert!(matches!(
+            header_uuid(&HeaderMap::new(), ORGANIZATION_HEADER),
+            Ok(None)
+        ));
+        assert!(matches!(
+            header_uuid(
+                &headers(&[(ORGANIZATION_HEADER, "acme")]),
+                ORGANIZATION_HEADER
+            ),
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_tenant_scoped_keys_stay_in_their_tenant() {
+        let state = AppState::without_database();
+        let (org, tenant) = (Uuid::new_v4(), Uuid::new_v4());
+        let key = api_key_record(org, Some(tenant));
+
+        assert_eq!(
+            api_key_tenant(&state, &key, None).await.ok(),
+            Some(Some(tenant))
+        );
+        assert_eq!(
+            api_key_tenant(&state, &key, Some(tenant)).await.ok(),
+            Some(Some(tenant))
+        );
+        assert!(matches!(
+            api_key_tenant(&state, &key, Some(Uuid::new_v4())).await,
+            Err(ApiError::Forbidden(_))
+        ));
+        let org_key = api_key_record(org, None);
+        assert_eq!(
+            api_key_tenant(&state, &org_key, None).await.ok(),
+            Some(None)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_malformed_credentials_are_unauthorized() {
+        let state = AppState::without_database();
+        assert!(matches!(resolve(&state, &HeaderMap::new()).await, Ok(None)));
+
+        for value in ["Bearer bld_short", "Basic not-base64!", "Token bld_abc"] {
+            let headers = headers(&[(header::AUTHORIZATION.as_str(), value)]);
+            assert_eq!(
+                status(resolve(&state, &headers).await),
+                StatusCode::UNAUTHORIZED,
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bad_and_expired_keys_are_refused() {
+        let Ok(url) = std::env::var("DATABASE_URL") else {
+            return;
+        };
+        let pool = sqlx::PgPool::connect(&url).await.unwrap();
+        buildit_db::run_migrations(&pool).await.unwrap();
+        let state = AppState::with_pool(pool.clone());
+        let (org, other_org) = (Uuid::new_v4(), Uuid::new_v4());
+        let (tenant, other_tenant) = (Uuid::new_v4(), Uuid::new_v4());
+        for (org_id, tenant_id) in [(org, tenant), (other_org, other_tenant)] {
+            let slug = Uuid::new_v4().to_string();
+            sqlx::query("INSERT INTO organizations (id, name, slug) VALUES ($1, $2, $2)")
+                .bind(org_id)
+                .bind(&slug)
+                .execute(&pool)
+                .await
+                .unwrap();
+            sqlx::query(
+                "INSERT INTO tenants (id, name, slug, organization_id) VALUES ($1, $2, $2, $3)",
+            )
+            .bind(tenant_id)
+            .bind(&slug)
+            .bind(org_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        let create = |expires_at: Option<chrono::DateTime<chrono::Utc>>| {
+            let state = state.clone();
+            async move {
+                let token = format!("{}{}", API_KEY_PREFIX, Uuid::new_v4().simple());
+                let mut key = api_key_record(org, None);
+                key.key_prefix = token[..API_KEY_LOOKUP_LEN].to_string();
+                key.expires_at = expires_at;
+                state
+                    .organization_repo
+                    .create_api_key(&key, &token_hash(&token))
+                    .await
+                    .unwrap();
+                (key.id, token)
+            }
+        };
+        let bearer = |token: &str, tenant: Option<Uuid>| {
+            let mut headers =
+                headers(&[(header::AUTHORIZATION.as_str(), &format!("Bearer {}", token))]);
+            if let Some(tenant) = tenant {
+                headers.insert(TENANT_HEADER, tenant.to_string().parse().unwrap());
+            }
+            headers
+        };
+
+        let (key_id, token) = create(None).await;
+        let context = resolve(&state, &bearer(&token, Some(tenant)))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(context.method, AuthMethod::ApiKey);
+        assert_eq!(context.api_key_id, Some(key_id));
+        assert_eq!(context.organization_id, Some(org));
+        assert_eq!(context.tenant_id, Some(tenant));
+
+        // A good key asking for a tenant of another organization is
+        // forbidden; anything that is not a live key is unauthorized.
+        assert_eq!(
+            status(resolve(&state, &bearer(&token, Some(other_tenant))).await),
+            StatusCode::FORBIDDEN
+        );
+        let wrong = format!("{}x", &token[..token.len() - 1]);
+        assert_eq!(
+            status(resolve(&state, &bearer(&wrong, None)).await),
+            StatusCode::UNAUTHORIZED
+        );
+        let (_, expired) = create(Some(chrono::Utc::now() - chrono::Duration::hours(1))).await;
+        assert_eq!(
+            status(resolve(&state, &bearer(&expired, None)).await),
+            StatusCode::UNAUTHORIZED
+        );
+        state
+            .organization_repo
+            .revoke_api_key(ResourceId::from_uuid(org), ResourceId::from_uuid(key_id))
+            .await
+            .unwrap();
+        assert_eq!(
+            status(resolve(&state, &bearer(&token, None)).await),
+            StatusCode::UNAUTHORIZED
+        );
+        let cookie = headers(&[(
+            header::COOKIE.as_str(),
+            &format!("{}=bs_unknown", SESSION_COOKIE),
+        )]);
+        assert_eq!(
+            status(resolve(&state, &cookie).await),
+            StatusCode::UNAUTHORIZED
+        );
+
+        sqlx::query("DELETE FROM organizations WHERE id = ANY($1)")
+            .bind(vec![org, other_org])
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+}
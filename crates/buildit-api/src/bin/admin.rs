@@ -0,0 +1,134 @@
+//! BuildIt administration tool: backups and disaster recovery.
+//!
+//! Reads the same system configuration as the server
+//! (`BUILDIT_SYSTEM_CONFIG` and `BUILDIT_*` overrides) for the database and
+//! artifact store, and needs `pg_dump` and `pg_restore` on the `PATH`.
+
+use anyhow::{Context, bail};
+use buildit_api::services::artifacts::FilesystemArtifactStore;
+use buildit_api::services::backup;
+use buildit_config::system::{SystemConfig, load_system_config};
+use buildit_db::create_pool;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Parser)]
+#[command(name = "buildit-admin")]
+#[command(about = "BuildIt administration", long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Back up the database, Terraform states and optionally artifacts
+    /// from one consistent snapshot
+    Backup {
+        /// Directory to write the backup to
+        dir: PathBuf,
+        /// Copy the content of build artifacts too, not just their listing
+        #[arg(long)]
+        artifacts: bool,
+    },
+    /// Check a backup's files against its manifest
+    Verify {
+        /// Backup directory
+        dir: PathBuf,
+    },
+    /// Replace the database with a backup and put its artifacts back; stop
+    /// every server first
+    Restore {
+        /// Backup directory
+        dir: PathBuf,
+        /// Confirm the database may be overwritten
+        #[arg(long)]
+        yes: bool,
+    },
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .init();
+    let cli = Cli::parse();
+
+    let config_path = std::env::var("BUILDIT_SYSTEM_CONFIG").ok();
+    let config = load_system_config(config_path.as_deref()).map_err(|e| {
+        anyhow::anyhow!(
+            "Invalid system configuration {}: {}",
+            config_path.as_deref().unwrap_or("(environment)"),
+            e
+        )
+    })?;
+    let artifact_store = FilesystemArtifactStore::from_config(config.artifact_store.as_ref());
+
+    match cli.command {
+        Commands::Backup { dir, artifacts } => {
+            let database_url = database_url(&config)?;
+            let pool = create_pool(&database_url).await?;
+            let manifest =
+                backup::create(&pool, &database_url, &artifact_store, &dir, artifacts).await?;
+            let copied = manifest
+                .artifacts
+                .iter()
+                .filter(|a| a.copy.is_some())
+                .count();
+            println!(
+                "Backed up to {}: database ({} bytes), {} Terraform states, {} artifacts listed, {} copied",
+                dir.display(),
+                manifest.database.size,
+                manifest.stack_states.len(),
+                manifest.artifacts.len(),
+                copied
+            );
+        }
+        Commands::Verify { dir } => {
+            let manifest = backup::verify(&dir).await?;
+            println!(
+                "Backup of {} is intact (schema version {})",
+                manifest.created_at,
+                manifest
+                    .schema_version
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|| "unknown".to_string())
+            );
+        }
+        Commands::Restore { dir, yes } => {
+            if !yes {
+                bail!("Restoring replaces everything in the database; pass --yes to go ahead");
+            }
+            let database_url = database_url(&config)?;
+            let report = backup::restore(&dir, &database_url, &artifact_store).await?;
+            println!(
+                "Restored from {}: {} artifacts written back ({} relocated), {} tasks released",
+                dir.display(),
+                report.artifacts_restored,
+                report.artifacts_relocated,
+                report.tasks_released
+            );
+            if !report.artifacts_missing.is_empty() {
+                println!(
+                    "{} artifacts are missing from the store:",
+                    report.artifacts_missing.len()
+                );
+                for artifact in &report.artifacts_missing {
+                    println!("  {}", artifact);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn database_url(config: &SystemConfig) -> anyhow::Result<String> {
+    config
+        .database
+        .url
+        .clone()
+        .context("No database URL; set database url in the system configuration or DATABASE_URL")
+}
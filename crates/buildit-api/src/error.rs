@@ -3,7 +3,8 @@
 use axum::Json;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
-use serde_json::json;
+use serde::Serialize;
+use utoipa::ToSchema;
 
 /// API error type.
 #[derive(Debug)]
@@ -13,6 +14,8 @@ pub enum ApiError {
     Unauthorized(String),
     Forbidden(String),
     Conflict(String),
+    /// The tenant is over its quota.
+    TooManyRequests(String),
     Internal(String),
 }
 
@@ -24,17 +27,20 @@ impl IntoResponse for ApiError {
             ApiError::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, msg),
             ApiError::Forbidden(msg) => (StatusCode::FORBIDDEN, msg),
             ApiError::Conflict(msg) => (StatusCode::CONFLICT, msg),
+            ApiError::TooManyRequests(msg) => (StatusCode::TOO_MANY_REQUESTS, msg),
             ApiError::Internal(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
         };
 
-        let body = Json(json!({
-            "error": message
-        }));
-
-        (status, body).into_response()
+        (status, Json(ErrorBody { error: message })).into_response()
     }
 }
 
+/// Body of an error response.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    pub error: String,
+}
+
 impl From<buildit_core::Error> for ApiError {
     fn from(err: buildit_core::Error) -> Self {
         match err {
@@ -53,6 +59,7 @@ impl From<buildit_db::DbError> for ApiError {
         match err {
             buildit_db::DbError::NotFound(msg) => ApiError::NotFound(msg),
             buildit_db::DbError::Duplicate(msg) => ApiError::Conflict(msg),
+            buildit_db::DbError::Conflict(msg) => ApiError::Conflict(msg),
             _ => ApiError::Internal(err.to_string()),
         }
     }
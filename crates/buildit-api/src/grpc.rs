@@ -0,0 +1,214 @@
+//! gRPC API for runners and machine-to-machine integrations.
+//!
+//! Serves the `buildit.runner.v1.Runner` service on the HTTP port, next to
+//! the REST API, for clients that would otherwise make a request per log
+//! batch: logs go up one long-lived stream and every batch is acked on it.
+//! Calls authenticate like the REST runner endpoints, with the runner's
+//! token as a Bearer token and its ID in `x-buildit-runner-id`, and go
+//! through the same leases as [`crate::routes::runners`].
+
+use axum::Router;
+use buildit_core::ResourceId;
+use buildit_core::executor::LogLine;
+use buildit_db::{DbError, DbResult, RunnerJobRecord, RunnerRecord, RunnerRepo};
+use buildit_grpc::RUNNER_ID_METADATA;
+use buildit_grpc::parse_id;
+use buildit_grpc::runner::runner_server::{Runner, RunnerServer};
+use buildit_grpc::runner::{
+    GetArtifactUrlsRequest, GetArtifactUrlsResponse, JobLease, LeaseJobRequest, LeaseJobResponse,
+    LogBatch, ReportAck, ReportStatusRequest,
+};
+use futures::{Stream, StreamExt};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::time::Duration;
+use tonic::service::Routes;
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::AppState;
+use crate::auth::{bearer_or_basic_password, token_hash};
+use crate::error::ApiError;
+use crate::routes::runners::{LEASE_SECS, wait_for_lease};
+use crate::services::artifact_dependencies::artifact_link;
+
+/// Prefix of the variables jobs read artifact links from.
+const ARTIFACT_URL_PREFIX: &str = "BUILDIT_ARTIFACT_URL_";
+
+/// The gRPC services, routed by their `/<package>.<service>/` paths.
+pub fn router(state: AppState) -> Router {
+    Routes::new(RunnerServer::new(RunnerService { state })).into_axum_router()
+}
+
+impl From<ApiError> for Status {
+    fn from(err: ApiError) -> Self {
+        match err {
+            ApiError::NotFound(msg) => Status::not_found(msg),
+            ApiError::BadRequest(msg) => Status::invalid_argument(msg),
+            ApiError::Unauthorized(msg) => Status::unauthenticated(msg),
+            ApiError::Forbidden(msg) => Status::permission_denied(msg),
+            ApiError::Conflict(msg) => Status::failed_precondition(msg),
+            ApiError::TooManyRequests(msg) => Status::resource_exhausted(msg),
+            ApiError::Internal(msg) => Status::internal(msg),
+        }
+    }
+}
+
+struct RunnerService {
+    state: AppState,
+}
+
+impl RunnerService {
+    /// Resolve the runner making the call from its metadata.
+    async fn authenticate<T>(&self, request: &Request<T>) -> Result<RunnerRecord, Status> {
+        let id = request
+            .metadata()
+            .get(RUNNER_ID_METADATA)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| Status::unauthenticated("A runner ID is required"))?;
+        let id = parse_id(id)?;
+        let token = bearer_or_basic_password(&request.metadata().clone().into_headers())
+            .ok_or_else(|| Status::unauthenticated("A runner token is required"))?;
+        match self
+            .state
+            .runner_repo
+            .authenticate_runner(ResourceId::from_uuid(id), &token_hash(&token))
+            .await
+        {
+            Ok(runner) => Ok(runner),
+            Err(DbError::NotFound(_)) => Err(Status::unauthenticated("Invalid runner token")),
+            Err(e) => Err(ApiError::from(e).into()),
+        }
+    }
+
+    /// A fresh link for each artifact link in the job's environment.
+    fn refresh_artifact_urls(
+        &self,
+        job: &RunnerJobRecord,
+    ) -> Result<HashMap<String, String>, Status> {
+        let spec = job.job_spec().map_err(ApiError::from)?;
+        Ok(spec
+            .env
+            .into_iter()
+            .filter(|(name, _)| name.starts_with(ARTIFACT_URL_PREFIX))
+            .filter_map(|(name, url)| {
+                let url = self.resign(&url)?;
+                Some((name, url))
+            })
+            .collect())
+    }
+
+    /// `url` with a new token, if it is an artifact link.
+    fn resign(&self, url: &str) -> Option<String> {
+        let start = url.rfind("/artifacts/")?;
+        let mut parts = url[start + "/artifacts/".len()..].split('/');
+        let run_id: uuid::Uuid = parts.next()?.parse().ok()?;
+        let artifact_id: uuid::Uuid = parts.next()?.parse().ok()?;
+        let token = self.state.report_signer.sign_artifact(artifact_id);
+        Some(format!(
+            "{}{}",
+            &url[..start],
+            artifact_link(run_id, artifact_id, &token)
+        ))
+    }
+}
+
+/// The ack for a report. A job no longer leased to the runner is answered
+/// as cancelled, so the runner stops it.
+fn ack(job_id: uuid::Uuid, result: DbResult<RunnerJobRecord>) -> Result<ReportAck, Status> {
+    match result {
+        Ok(job) => Ok(ReportAck::new(job_id, job.cancel_requested)),
+        Err(DbError::Conflict(_)) => Ok(ReportAck::new(job_id, true)),
+        Err(e) => Err(ApiError::from(e).into()),
+    }
+}
+
+#[tonic::async_trait]
+impl Runner for RunnerService {
+    type UploadLogsStream = Pin<Box<dyn Stream<Item = Result<ReportAck, Status>> + Send>>;
+
+    async fn lease_job(
+        &self,
+        request: Request<LeaseJobRequest>,
+    ) -> Result<Response<LeaseJobResponse>, Status> {
+        let runner = self.authenticate(&request).await?;
+        let wait = Duration::from_secs(request.get_ref().wait_seconds.into());
+        let lease = match wait_for_lease(&self.state, &runner, wait).await? {
+            Some(lease) => Some(JobLease::try_from(&lease)?),
+            None => None,
+        };
+        Ok(Response::new(LeaseJobResponse { lease }))
+    }
+
+    async fn report_status(
+        &self,
+        request: Request<ReportStatusRequest>,
+    ) -> Result<Response<ReportAck>, Status> {
+        let runner = self.authenticate(&request).await?;
+        let request = request.into_inner();
+        let job_id = parse_id(&request.job_id)?;
+        let report = request.report()?;
+        let result = self
+            .state
+            .runner_repo
+            .report_job(
+                ResourceId::from_uuid(job_id),
+                ResourceId::from_uuid(runner.id),
+                report.state,
+                report.exit_code,
+                report.error.as_deref(),
+                LEASE_SECS,
+            )
+            .await;
+        Ok(Response::new(ack(job_id, result)?))
+    }
+
+    async fn upload_logs(
+        &self,
+        request: Request<Streaming<LogBatch>>,
+    ) -> Result<Response<Self::UploadLogsStream>, Status> {
+        let runner = self.authenticate(&request).await?;
+        let state = self.state.clone();
+        let acks = request.into_inner().then(move |batch| {
+            let state = state.clone();
+            async move {
+                let batch = batch?;
+                let job_id = parse_id(&batch.job_id)?;
+                let lines: Vec<LogLine> = batch.lines.into_iter().map(LogLine::from).collect();
+                let result = state
+                    .runner_repo
+                    .append_job_logs(
+                        ResourceId::from_uuid(job_id),
+                        ResourceId::from_uuid(runner.id),
+                        &lines,
+                        LEASE_SECS,
+                    )
+                    .await;
+                ack(job_id, result)
+            }
+        });
+        Ok(Response::new(Box::pin(acks)))
+    }
+
+    async fn get_artifact_urls(
+        &self,
+        request: Request<GetArtifactUrlsRequest>,
+    ) -> Result<Response<GetArtifactUrlsResponse>, Status> {
+        let runner = self.authenticate(&request).await?;
+        let job_id = parse_id(&request.get_ref().job_id)?;
+        let job = self
+            .state
+            .runner_repo
+            .get_job(ResourceId::from_uuid(job_id))
+            .await
+            .map_err(ApiError::from)?;
+        if job.runner_id != Some(runner.id) || !matches!(job.status.as_str(), "leased" | "running")
+        {
+            return Err(Status::failed_precondition(format!(
+                "Job {} is not leased to this runner",
+                job_id
+            )));
+        }
+        let urls = self.refresh_artifact_urls(&job)?;
+        Ok(Response::new(GetArtifactUrlsResponse { urls }))
+    }
+}
@@ -1,11 +1,16 @@
 //! API server for BuildIt CI/CD.
 //!
-//! Provides HTTP REST API and WebSocket endpoints.
+//! Provides HTTP REST API, WebSocket and gRPC endpoints.
 
+pub mod audit;
+pub mod auth;
 pub mod error;
+pub mod grpc;
 pub mod routes;
 pub mod services;
+pub mod sse;
 pub mod state;
+pub mod tenancy;
 pub mod ws;
 
 pub use state::{AppState, ExecutorType};
@@ -0,0 +1,374 @@
+//! Pipeline analytics, cost estimates and stage right-sizing for the
+//! tenant the request acts in.
+
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    routing::get,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::auth::AuthContext;
+use crate::error::ApiError;
+use buildit_core::ResourceId;
+use buildit_core::rbac::Permission;
+use buildit_db::{
+    AnalyticsFilter, AnalyticsRepo, BranchStats, CostRates, CostSummary, DailyCost, DailyRuns,
+    FlakyStage, PipelineCost, PipelineStats, RunCost, RunSummary, StageStats,
+};
+use buildit_scheduler::rightsizing::{self, cpu_quantity, memory_quantity};
+
+/// Days of runs analysed when the query sets none.
+pub const DEFAULT_DAYS: i64 = 30;
+const MAX_DAYS: i64 = 365;
+
+/// Flaky stages listed when the query sets no limit.
+const DEFAULT_FLAKY_LIMIT: i64 = 10;
+const MAX_FLAKY_LIMIT: i64 = 100;
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    overview,
+    pipeline_stats,
+    branch_stats,
+    stage_stats,
+    flaky_stages,
+    costs,
+    right_sizing
+))]
+pub struct ApiDoc;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(overview))
+        .route("/pipelines", get(pipeline_stats))
+        .route("/branches", get(branch_stats))
+        .route("/stages", get(stage_stats))
+        .route("/flaky-stages", get(flaky_stages))
+        .route("/costs", get(costs))
+        .route("/right-sizing", get(right_sizing))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AnalyticsQuery {
+    /// Analyse the runs of the last `days` days.
+    pub days: Option<i64>,
+    /// Only the runs of this pipeline.
+    pub pipeline_id: Option<Uuid>,
+    /// Flaky stages, or most expensive runs, to list.
+    pub limit: Option<i64>,
+    /// Only stages over- or under-provisioned, when right-sizing.
+    pub flagged: Option<bool>,
+}
+
+impl AnalyticsQuery {
+    fn days(&self) -> i64 {
+        self.days.unwrap_or(DEFAULT_DAYS).clamp(1, MAX_DAYS)
+    }
+
+    fn limit(&self) -> i64 {
+        self.limit
+            .unwrap_or(DEFAULT_FLAKY_LIMIT)
+            .clamp(1, MAX_FLAKY_LIMIT)
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AnalyticsOverview {
+    pub since: DateTime<Utc>,
+    pub summary: RunSummary,
+    pub daily: Vec<DailyRuns>,
+    pub pipelines: Vec<PipelineStats>,
+    pub flaky_stages: Vec<FlakyStage>,
+}
+
+/// Summary, daily runs, pipeline stats and the flakiest stages.
+#[utoipa::path(
+    get,
+    path = "",
+    params(AnalyticsQuery),
+    responses((status = 200, description = "Tenant-wide figures", body = AnalyticsOverview))
+)]
+async fn overview(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Query(query): Query<AnalyticsQuery>,
+) -> Result<Json<AnalyticsOverview>, ApiError> {
+    let filter = authorized_filter(&state, &auth, &query).await?;
+    let repo = &state.analytics_repo;
+    Ok(Json(AnalyticsOverview {
+        since: filter.since,
+        summary: repo.summary(&filter).await?,
+        daily: repo.daily_runs(&filter).await?,
+        pipelines: repo.pipeline_stats(&filter).await?,
+        flaky_stages: repo.flaky_stages(&filter, query.limit()).await?,
+    }))
+}
+
+/// Duration, failure rate and queue wait of each pipeline.
+#[utoipa::path(
+    get,
+    path = "/pipelines",
+    params(AnalyticsQuery),
+    responses((status = 200, description = "Per-pipeline figures", body = Vec<PipelineStats>))
+)]
+async fn pipeline_stats(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Query(query): Query<AnalyticsQuery>,
+) -> Result<Json<Vec<PipelineStats>>, ApiError> {
+    let filter = authorized_filter(&state, &auth, &query).await?;
+    Ok(Json(state.analytics_repo.pipeline_stats(&filter).await?))
+}
+
+/// Duration and failure rate of each branch of each pipeline.
+#[utoipa::path(
+    get,
+    path = "/branches",
+    params(AnalyticsQuery),
+    responses((status = 200, description = "Per-branch figures", body = Vec<BranchStats>))
+)]
+async fn branch_stats(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Query(query): Query<AnalyticsQuery>,
+) -> Result<Json<Vec<BranchStats>>, ApiError> {
+    let filter = authorized_filter(&state, &auth, &query).await?;
+    Ok(Json(state.analytics_repo.branch_stats(&filter).await?))
+}
+
+/// Duration and failure rate of each stage, slowest first.
+#[utoipa::path(
+    get,
+    path = "/stages",
+    params(AnalyticsQuery),
+    responses((status = 200, description = "Per-stage figures", body = Vec<StageStats>))
+)]
+async fn stage_stats(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Query(query): Query<AnalyticsQuery>,
+) -> Result<Json<Vec<StageStats>>, ApiError> {
+    let filter = authorized_filter(&state, &auth, &query).await?;
+    Ok(Json(state.analytics_repo.stage_stats(&filter).await?))
+}
+
+/// Stages that flip between passing and failing, flakiest first.
+#[utoipa::path(
+    get,
+    path = "/flaky-stages",
+    params(AnalyticsQuery),
+    responses((status = 200, description = "Flaky stages, flakiest first", body = Vec<FlakyStage>))
+)]
+async fn flaky_stages(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Query(query): Query<AnalyticsQuery>,
+) -> Result<Json<Vec<FlakyStage>>, ApiError> {
+    let filter = authorized_filter(&state, &auth, &query).await?;
+    Ok(Json(
+        state
+            .analytics_repo
+            .flaky_stages(&filter, query.limit())
+            .await?,
+    ))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CostReport {
+    pub since: DateTime<Utc>,
+    /// Currency costs are in.
+    pub currency: String,
+    pub rates: CostRates,
+    pub summary: CostSummary,
+    /// What a 30-day month costs at the window's daily average.
+    pub projected_monthly: f64,
+    pub daily: Vec<DailyCost>,
+    pub pipelines: Vec<PipelineCost>,
+    /// The most expensive runs.
+    pub runs: Vec<RunCost>,
+}
+
+/// Estimated cost of the tenant's jobs, per day, pipeline and run.
+///
+/// Jobs are priced at the server's rates for the CPU and memory they
+/// used, or requested where their executor could not tell.
+#[utoipa::path(
+    get,
+    path = "/costs",
+    params(AnalyticsQuery),
+    responses((status = 200, description = "Cost estimates", body = CostReport))
+)]
+async fn costs(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Query(query): Query<AnalyticsQuery>,
+) -> Result<Json<CostReport>, ApiError> {
+    let filter = authorized_filter(&state, &auth, &query).await?;
+    Ok(Json(
+        cost_report(&state, &filter, query.days(), query.limit()).await?,
+    ))
+}
+
+/// The tenant's costs over the `days` of `filter`.
+pub(crate) async fn cost_report(
+    state: &AppState,
+    filter: &AnalyticsFilter,
+    days: i64,
+    limit: i64,
+) -> Result<CostReport, ApiError> {
+    let costs = &state.system_config.costs;
+    let rates = CostRates {
+        cpu_core_hour: costs.cpu_core_hour,
+        memory_gib_hour: costs.memory_gib_hour,
+    };
+    let repo = &state.analytics_repo;
+    let summary = repo.cost_summary(filter, rates).await?;
+    Ok(CostReport {
+        since: filter.since,
+        currency: costs.currency.clone(),
+        rates,
+        projected_monthly: summary.cost / days as f64 * 30.0,
+        summary,
+        daily: repo.daily_costs(filter, rates).await?,
+        pipelines: repo.pipeline_costs(filter, rates).await?,
+        runs: repo.run_costs(filter, rates, limit).await?,
+    })
+}
+
+/// Requests and limits, as written in a pipeline.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct StageResources {
+    pub cpu_request: String,
+    pub cpu_limit: String,
+    pub memory_request: String,
+    pub memory_limit: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct StageRecommendation {
+    pub pipeline_id: Uuid,
+    pub pipeline_name: String,
+    pub stage_name: String,
+    /// Measured jobs the recommendation is based on.
+    pub jobs: i64,
+    /// CPU and memory the latest job requested (its limits without
+    /// requests); `None` if it set none.
+    pub cpu_requested: Option<String>,
+    pub memory_requested: Option<String>,
+    /// 95th percentile of the jobs' average CPU and peak CPU and memory.
+    pub p95_avg_cpu: String,
+    pub p95_peak_cpu: String,
+    pub p95_peak_memory: String,
+    /// Highest memory any job reached.
+    pub max_peak_memory: String,
+    pub recommended: StageResources,
+    /// How the CPU request compares with use: `unset`, `fit`, `over` or
+    /// `under`.
+    pub cpu: String,
+    /// How the memory request compares with use, as for CPU.
+    pub memory: String,
+    /// Whether either request is over- or under-provisioned.
+    pub flagged: bool,
+}
+
+/// Recommended requests and limits of each stage, from the peak and
+/// average CPU and memory its jobs used. Flagged stages come first.
+///
+/// Only stages with at least three measured jobs in the window are listed.
+#[utoipa::path(
+    get,
+    path = "/right-sizing",
+    params(AnalyticsQuery),
+    responses((status = 200, description = "Stage recommendations", body = Vec<StageRecommendation>))
+)]
+async fn right_sizing(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Query(query): Query<AnalyticsQuery>,
+) -> Result<Json<Vec<StageRecommendation>>, ApiError> {
+    let filter = authorized_filter(&state, &auth, &query).await?;
+    let usage = state.analytics_repo.stage_usage(&filter).await?;
+
+    let quantity = |value: f64, format: fn(u64) -> String| format(value.max(0.0).ceil() as u64);
+    let mut recommendations: Vec<StageRecommendation> = usage
+        .into_iter()
+        .filter_map(|stage| {
+            let recommendation = rightsizing::recommend(&stage)?;
+            Some(StageRecommendation {
+                cpu_requested: (stage.cpu_request_millis > 0)
+                    .then(|| cpu_quantity(stage.cpu_request_millis as u64)),
+                memory_requested: (stage.memory_request_bytes > 0)
+                    .then(|| memory_quantity(stage.memory_request_bytes as u64)),
+                p95_avg_cpu: quantity(stage.p95_avg_cpu_millis, cpu_quantity),
+                p95_peak_cpu: quantity(stage.p95_peak_cpu_millis, cpu_quantity),
+                p95_peak_memory: quantity(stage.p95_peak_memory_bytes, memory_quantity),
+                max_peak_memory: memory_quantity(stage.max_peak_memory_bytes.max(0) as u64),
+                recommended: StageResources {
+                    cpu_request: cpu_quantity(recommendation.cpu_request_millis),
+                    cpu_limit: cpu_quantity(recommendation.cpu_limit_millis),
+                    memory_request: memory_quantity(recommendation.memory_request_bytes),
+                    memory_limit: memory_quantity(recommendation.memory_limit_bytes),
+                },
+                cpu: recommendation.cpu.as_str().to_string(),
+                memory: recommendation.memory.as_str().to_string(),
+                flagged: recommendation.flagged(),
+                pipeline_id: stage.pipeline_id,
+                pipeline_name: stage.pipeline_name,
+                stage_name: stage.stage_name,
+                jobs: stage.jobs,
+            })
+        })
+        .filter(|r| r.flagged || query.flagged != Some(true))
+        .collect();
+    // Stable, so stages keep their pipeline and name order within each group
+    recommendations.sort_by_key(|r| !r.flagged);
+    Ok(Json(recommendations))
+}
+
+/// The filter of the query, once the caller may read the tenant's
+/// pipelines.
+async fn authorized_filter(
+    state: &AppState,
+    auth: &AuthContext,
+    query: &AnalyticsQuery,
+) -> Result<AnalyticsFilter, ApiError> {
+    let tenant = auth.tenant(state).await?;
+    auth.require(state, tenant.id, Permission::PipelineRead)
+        .await?;
+    Ok(AnalyticsFilter {
+        tenant_id: ResourceId::from_uuid(tenant.id),
+        since: Utc::now() - Duration::days(query.days()),
+        pipeline_id: query.pipeline_id,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(days: Option<i64>, limit: Option<i64>) -> AnalyticsQuery {
+        AnalyticsQuery {
+            days,
+            pipeline_id: None,
+            limit,
+            flagged: None,
+        }
+    }
+
+    #[test]
+    fn test_window_and_limit_are_bounded() {
+        assert_eq!(query(None, None).days(), DEFAULT_DAYS);
+        assert_eq!(query(Some(0), None).days(), 1);
+        assert_eq!(query(Some(-7), None).days(), 1);
+        assert_eq!(query(Some(10_000), None).days(), MAX_DAYS);
+        assert_eq!(query(None, None).limit(), DEFAULT_FLAKY_LIMIT);
+        assert_eq!(query(None, Some(0)).limit(), 1);
+        assert_eq!(query(None, Some(5_000)).limit(), MAX_FLAKY_LIMIT);
+    }
+}
@@ -1,31 +1,56 @@
 //! Application (GitOps) management endpoints.
 
 use axum::extract::{Path, Query, State};
-use axum::routing::get;
+use axum::routing::{get, put};
 use axum::{Json, Router};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, OpenApi, ToSchema};
 use uuid::Uuid;
 
 use crate::AppState;
+use crate::audit::AuditBefore;
+use crate::auth::AuthContext;
 use crate::error::ApiError;
+use crate::services::tasks::{self, Task};
 use buildit_core::ResourceId;
-use buildit_core::application::{SyncPolicy, SyncTriggerType};
+use buildit_core::application::{
+    Application, ApplicationSync, HelmSource, SyncPolicy, SyncTriggerType,
+};
+use buildit_core::rbac::Permission;
 use buildit_db::ApplicationRepo;
 
+#[derive(OpenApi)]
+#[openapi(paths(
+    list_applications,
+    create_application,
+    get_application,
+    update_helm,
+    delete_application,
+    get_diff,
+    list_syncs,
+    trigger_sync,
+    list_resources
+))]
+pub struct ApiDoc;
+
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/", get(list_applications).post(create_application))
         .route("/{id}", get(get_application).delete(delete_application))
+        .route("/{id}/helm", put(update_helm))
+        .route("/{id}/diff", get(get_diff))
         .route("/{id}/syncs", get(list_syncs).post(trigger_sync))
         .route("/{id}/resources", get(list_resources))
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct ListApplicationsQuery {
-    tenant_id: Uuid,
+    /// Defaults to the request's tenant.
+    tenant_id: Option<Uuid>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 struct ApplicationResponse {
     id: String,
     name: String,
@@ -39,21 +64,15 @@ struct ApplicationResponse {
     last_synced_at: Option<String>,
     repository_id: Option<String>,
     environment_id: Option<String>,
+    /// "helm" or "directory"
+    source_type: String,
+    helm: Option<HelmSource>,
+    rendered_digest: Option<String>,
 }
 
-async fn list_applications(
-    State(state): State<AppState>,
-    Query(query): Query<ListApplicationsQuery>,
-) -> Result<Json<Vec<ApplicationResponse>>, ApiError> {
-    let tenant_id = ResourceId::from_uuid(query.tenant_id);
-    let apps = state
-        .application_repo
-        .list_applications_by_tenant(tenant_id)
-        .await?;
-
-    let response: Vec<ApplicationResponse> = apps
-        .into_iter()
-        .map(|a| ApplicationResponse {
+impl From<Application> for ApplicationResponse {
+    fn from(a: Application) -> Self {
+        Self {
             id: a.id.to_string(),
             name: a.name,
             description: a.description,
@@ -66,15 +85,48 @@ async fn list_applications(
             last_synced_at: a.last_synced_at.map(|t| t.to_rfc3339()),
             repository_id: a.repository_id.map(|id| id.to_string()),
             environment_id: a.environment_id.map(|id| id.to_string()),
-        })
-        .collect();
+            source_type: if a.helm.is_some() {
+                "helm"
+            } else {
+                "directory"
+            }
+            .to_string(),
+            helm: a.helm,
+            rendered_digest: a.rendered_digest,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "",
+    params(ListApplicationsQuery),
+    responses((status = 200, description = "Applications", body = Vec<ApplicationResponse>))
+)]
+async fn list_applications(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Query(query): Query<ListApplicationsQuery>,
+) -> Result<Json<Vec<ApplicationResponse>>, ApiError> {
+    let tenant_id = auth.tenant_or(&state, query.tenant_id).await?;
+    auth.require(&state, tenant_id, Permission::ApplicationRead)
+        .await?;
+    let tenant_id = ResourceId::from_uuid(tenant_id);
+    let apps = state
+        .application_repo
+        .list_applications_by_tenant(tenant_id)
+        .await?;
+
+    let response: Vec<ApplicationResponse> =
+        apps.into_iter().map(ApplicationResponse::from).collect();
 
     Ok(Json(response))
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 struct CreateApplicationRequest {
-    tenant_id: Uuid,
+    /// Defaults to the request's tenant.
+    tenant_id: Option<Uuid>,
     name: String,
     description: Option<String>,
     repository_id: Option<Uuid>,
@@ -82,12 +134,24 @@ struct CreateApplicationRequest {
     path: String,
     target_namespace: String,
     sync_policy: Option<String>,
+    /// Render this Helm chart instead of the manifests at `path`
+    helm: Option<HelmSource>,
 }
 
+#[utoipa::path(
+    post,
+    path = "",
+    request_body = CreateApplicationRequest,
+    responses((status = 200, description = "The application", body = ApplicationResponse))
+)]
 async fn create_application(
     State(state): State<AppState>,
+    auth: AuthContext,
     Json(req): Json<CreateApplicationRequest>,
 ) -> Result<Json<ApplicationResponse>, ApiError> {
+    let tenant_id = auth.tenant_or(&state, req.tenant_id).await?;
+    auth.require(&state, tenant_id, Permission::ApplicationWrite)
+        .await?;
     let sync_policy = match req.sync_policy.as_deref() {
         Some("auto") => SyncPolicy::Auto,
         _ => SyncPolicy::Manual,
@@ -96,7 +160,7 @@ async fn create_application(
     let app = state
         .application_repo
         .create_application(
-            ResourceId::from_uuid(req.tenant_id),
+            ResourceId::from_uuid(tenant_id),
             &req.name,
             req.description.as_deref(),
             req.repository_id.map(ResourceId::from_uuid),
@@ -104,62 +168,76 @@ async fn create_application(
             &req.path,
             &req.target_namespace,
             sync_policy,
+            req.helm.as_ref(),
         )
         .await?;
 
-    Ok(Json(ApplicationResponse {
-        id: app.id.to_string(),
-        name: app.name,
-        description: app.description,
-        path: app.path,
-        target_namespace: app.target_namespace,
-        sync_policy: app.sync_policy.to_string(),
-        sync_status: app.sync_status.to_string(),
-        health_status: app.health_status.to_string(),
-        synced_revision: app.synced_revision,
-        last_synced_at: app.last_synced_at.map(|t| t.to_rfc3339()),
-        repository_id: app.repository_id.map(|id| id.to_string()),
-        environment_id: app.environment_id.map(|id| id.to_string()),
-    }))
+    Ok(Json(app.into()))
 }
 
+#[utoipa::path(
+    get,
+    path = "/{id}",
+    params(("id" = Uuid, Path, description = "Application ID")),
+    responses((status = 200, description = "The application", body = ApplicationResponse))
+)]
 async fn get_application(
     State(state): State<AppState>,
+    auth: AuthContext,
     Path(id): Path<Uuid>,
 ) -> Result<Json<ApplicationResponse>, ApiError> {
+    let app = authorized_application(&state, &auth, id, Permission::ApplicationRead).await?;
+
+    Ok(Json(app.into()))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct UpdateHelmRequest {
+    /// `null` switches the application back to plain manifests
+    helm: Option<HelmSource>,
+}
+
+#[utoipa::path(
+    put,
+    path = "/{id}/helm",
+    params(("id" = Uuid, Path, description = "Application ID")),
+    request_body = UpdateHelmRequest,
+    responses((status = 200, description = "The application", body = ApplicationResponse))
+)]
+async fn update_helm(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    Json(req): Json<UpdateHelmRequest>,
+) -> Result<(AuditBefore, Json<ApplicationResponse>), ApiError> {
+    let before = authorized_application(&state, &auth, id, Permission::ApplicationWrite).await?;
     let app = state
         .application_repo
-        .get_application(ResourceId::from_uuid(id))
+        .update_application_helm(ResourceId::from_uuid(id), req.helm.as_ref())
         .await?;
-
-    Ok(Json(ApplicationResponse {
-        id: app.id.to_string(),
-        name: app.name,
-        description: app.description,
-        path: app.path,
-        target_namespace: app.target_namespace,
-        sync_policy: app.sync_policy.to_string(),
-        sync_status: app.sync_status.to_string(),
-        health_status: app.health_status.to_string(),
-        synced_revision: app.synced_revision,
-        last_synced_at: app.last_synced_at.map(|t| t.to_rfc3339()),
-        repository_id: app.repository_id.map(|id| id.to_string()),
-        environment_id: app.environment_id.map(|id| id.to_string()),
-    }))
+    Ok((AuditBefore::of(&before.helm), Json(app.into())))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/{id}",
+    params(("id" = Uuid, Path, description = "Application ID")),
+    responses((status = 200, description = "Deleted"))
+)]
 async fn delete_application(
     State(state): State<AppState>,
+    auth: AuthContext,
     Path(id): Path<Uuid>,
-) -> Result<(), ApiError> {
+) -> Result<(AuditBefore, ()), ApiError> {
+    let app = authorized_application(&state, &auth, id, Permission::ApplicationWrite).await?;
     state
         .application_repo
         .delete_application(ResourceId::from_uuid(id))
         .await?;
-    Ok(())
+    Ok((AuditBefore::of(&app), ()))
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 struct SyncResponse {
     id: String,
     application_id: String,
@@ -170,23 +248,15 @@ struct SyncResponse {
     resources_updated: i32,
     resources_deleted: i32,
     error_message: Option<String>,
+    rendered_digest: Option<String>,
     started_at: Option<String>,
     finished_at: Option<String>,
     created_at: String,
 }
 
-async fn list_syncs(
-    State(state): State<AppState>,
-    Path(id): Path<Uuid>,
-) -> Result<Json<Vec<SyncResponse>>, ApiError> {
-    let syncs = state
-        .application_repo
-        .list_syncs(ResourceId::from_uuid(id), 20)
-        .await?;
-
-    let response: Vec<SyncResponse> = syncs
-        .into_iter()
-        .map(|s| SyncResponse {
+impl From<ApplicationSync> for SyncResponse {
+    fn from(s: ApplicationSync) -> Self {
+        Self {
             id: s.id.to_string(),
             application_id: s.application_id.to_string(),
             revision: s.revision,
@@ -196,30 +266,56 @@ async fn list_syncs(
             resources_updated: s.resources_updated,
             resources_deleted: s.resources_deleted,
             error_message: s.error_message,
+            rendered_digest: s.rendered_digest,
             started_at: s.started_at.map(|t| t.to_rfc3339()),
             finished_at: s.finished_at.map(|t| t.to_rfc3339()),
             created_at: s.created_at.to_rfc3339(),
-        })
-        .collect();
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/{id}/syncs",
+    params(("id" = Uuid, Path, description = "Application ID")),
+    responses((status = 200, description = "Syncs", body = Vec<SyncResponse>))
+)]
+async fn list_syncs(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<SyncResponse>>, ApiError> {
+    authorized_application(&state, &auth, id, Permission::ApplicationRead).await?;
+    let syncs = state
+        .application_repo
+        .list_syncs(ResourceId::from_uuid(id), 20)
+        .await?;
+
+    let response: Vec<SyncResponse> = syncs.into_iter().map(SyncResponse::from).collect();
 
     Ok(Json(response))
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 struct TriggerSyncRequest {
     revision: Option<String>,
 }
 
+#[utoipa::path(
+    post,
+    path = "/{id}/syncs",
+    params(("id" = Uuid, Path, description = "Application ID")),
+    request_body = TriggerSyncRequest,
+    responses((status = 200, description = "The queued sync", body = SyncResponse))
+)]
 async fn trigger_sync(
     State(state): State<AppState>,
+    auth: AuthContext,
     Path(id): Path<Uuid>,
     Json(req): Json<TriggerSyncRequest>,
 ) -> Result<Json<SyncResponse>, ApiError> {
     // Get the application to find the repository
-    let app = state
-        .application_repo
-        .get_application(ResourceId::from_uuid(id))
-        .await?;
+    let app = authorized_application(&state, &auth, id, Permission::ApplicationSync).await?;
 
     // Determine revision - use provided or get latest from repo
     let revision = req.revision.unwrap_or_else(|| "HEAD".to_string());
@@ -230,36 +326,18 @@ async fn trigger_sync(
         .create_sync(
             ResourceId::from_uuid(app.id),
             &revision,
-            None, // TODO: Get current user
+            auth.user_id.map(ResourceId::from_uuid),
             SyncTriggerType::Manual,
         )
         .await?;
 
-    // TODO: Actually perform the sync in background
-    // For now, just return the sync record
-    // In a real implementation, this would:
-    // 1. Clone/fetch the repository
-    // 2. Parse manifests from app.path
-    // 3. Apply to target cluster
-    // 4. Update sync status
-
-    Ok(Json(SyncResponse {
-        id: sync.id.to_string(),
-        application_id: sync.application_id.to_string(),
-        revision: sync.revision,
-        status: sync.status.to_string(),
-        trigger_type: sync.trigger_type.to_string(),
-        resources_created: sync.resources_created,
-        resources_updated: sync.resources_updated,
-        resources_deleted: sync.resources_deleted,
-        error_message: sync.error_message,
-        started_at: sync.started_at.map(|t| t.to_rfc3339()),
-        finished_at: sync.finished_at.map(|t| t.to_rfc3339()),
-        created_at: sync.created_at.to_rfc3339(),
-    }))
+    // Render and apply in the background; the sync record tracks progress
+    tasks::enqueue(&state, Task::ApplicationSync { sync_id: sync.id }).await?;
+
+    Ok(Json(sync.into()))
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 struct ResourceResponse {
     id: String,
     api_version: String,
@@ -271,10 +349,18 @@ struct ResourceResponse {
     out_of_sync: bool,
 }
 
+#[utoipa::path(
+    get,
+    path = "/{id}/resources",
+    params(("id" = Uuid, Path, description = "Application ID")),
+    responses((status = 200, description = "Managed resources", body = Vec<ResourceResponse>))
+)]
 async fn list_resources(
     State(state): State<AppState>,
+    auth: AuthContext,
     Path(id): Path<Uuid>,
 ) -> Result<Json<Vec<ResourceResponse>>, ApiError> {
+    authorized_application(&state, &auth, id, Permission::ApplicationRead).await?;
     let resources = state
         .application_repo
         .list_resources(ResourceId::from_uuid(id))
@@ -296,3 +382,76 @@ async fn list_resources(
 
     Ok(Json(response))
 }
+
+#[derive(Debug, Serialize, ToSchema)]
+struct DiffResponse {
+    application_id: String,
+    sync_status: String,
+    synced_revision: Option<String>,
+    rendered_digest: Option<String>,
+    /// Drifted resources only
+    resources: Vec<ResourceDiffResponse>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct ResourceDiffResponse {
+    api_version: String,
+    kind: String,
+    name: String,
+    namespace: String,
+    status: String,
+    diff: Option<String>,
+    checked_at: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/{id}/diff",
+    params(("id" = Uuid, Path, description = "Application ID")),
+    responses((status = 200, description = "Drift from the synced revision", body = DiffResponse))
+)]
+async fn get_diff(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<DiffResponse>, ApiError> {
+    let app_id = ResourceId::from_uuid(id);
+    let app = authorized_application(&state, &auth, id, Permission::ApplicationRead).await?;
+    let resources = state.application_repo.list_resources(app_id).await?;
+
+    Ok(Json(DiffResponse {
+        application_id: app.id.to_string(),
+        sync_status: app.sync_status.to_string(),
+        synced_revision: app.synced_revision,
+        rendered_digest: app.rendered_digest,
+        resources: resources
+            .into_iter()
+            .filter(|r| r.out_of_sync)
+            .map(|r| ResourceDiffResponse {
+                api_version: r.api_version,
+                kind: r.kind,
+                name: r.name,
+                namespace: r.namespace,
+                status: r.status.to_string(),
+                diff: r.diff,
+                checked_at: r.updated_at.to_rfc3339(),
+            })
+            .collect(),
+    }))
+}
+
+/// Load an application, checking the caller holds `permission` in its
+/// tenant.
+async fn authorized_application(
+    state: &AppState,
+    auth: &AuthContext,
+    id: Uuid,
+    permission: Permission,
+) -> Result<Application, ApiError> {
+    let app = state
+        .application_repo
+        .get_application(ResourceId::from_uuid(id))
+        .await?;
+    auth.require(state, app.tenant_id, permission).await?;
+    Ok(app)
+}
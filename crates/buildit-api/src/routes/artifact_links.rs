@@ -0,0 +1,63 @@
+//! Artifacts downloaded by the jobs that need them.
+//!
+//! - `GET /artifacts/{run_id}/{artifact_id}/{token}` - the artifact, as an attachment
+//!
+//! The token is an expiring signature handed to a job whose stage needs an
+//! artifact of another pipeline, so the job can fetch it without credentials.
+
+use axum::Router;
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::error::ApiError;
+use buildit_core::ResourceId;
+use buildit_db::ArtifactRepo;
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/{run_id}/{artifact_id}/{token}", get(download_artifact))
+}
+
+async fn download_artifact(
+    State(state): State<AppState>,
+    Path((run_id, artifact_id, token)): Path<(Uuid, Uuid, String)>,
+) -> Result<Response, ApiError> {
+    if !state.report_signer.verify_artifact(artifact_id, &token) {
+        return Err(ApiError::Forbidden(
+            "Artifact link is invalid or has expired".to_string(),
+        ));
+    }
+    let artifact = state
+        .artifact_repo
+        .get_artifact(
+            ResourceId::from_uuid(run_id),
+            ResourceId::from_uuid(artifact_id),
+        )
+        .await?;
+    let stream = state.artifact_store.stream(&artifact.reference()).await?;
+
+    let file_name = artifact
+        .name
+        .rsplit('/')
+        .next()
+        .unwrap_or("artifact")
+        .replace(['"', '\\', '\r', '\n'], "_");
+    Ok((
+        [
+            (header::CONTENT_TYPE, artifact.content_type.clone()),
+            (header::CONTENT_LENGTH, artifact.size_bytes.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", file_name),
+            ),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+            (header::REFERRER_POLICY, "no-referrer".to_string()),
+        ],
+        Body::from_stream(stream),
+    )
+        .into_response())
+}
@@ -0,0 +1,243 @@
+//! UI assets embedded in the server binary.
+//!
+//! - `GET /static/{path}` - a file under `crates/buildit-api/static`
+//!
+//! Templates link assets through [`url`], which puts a hash of the file's
+//! contents in its name (`css/custom.3f2a9b1c.css`). Hashed URLs change
+//! whenever the file does, so they are cached for a year; unhashed ones are
+//! revalidated with their ETag on every use. Responses are compressed when
+//! the client accepts it.
+//!
+//! Third-party libraries are served from `static/vendor` when they were
+//! downloaded there before building (`scripts/vendor-ui-assets.sh`), and
+//! from their CDN otherwise.
+
+use axum::Router;
+use axum::extract::Path;
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use rust_embed::{EmbeddedFile, RustEmbed};
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use tower_http::compression::CompressionLayer;
+
+use crate::AppState;
+
+/// Cache policy of URLs with a content hash.
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+/// Cache policy of URLs without one.
+const REVALIDATE: &str = "public, no-cache";
+
+/// Third-party libraries and the CDN URL used when they aren't vendored.
+const VENDORED: &[(&str, &str)] = &[
+    ("vendor/tailwindcss.js", "https://cdn.tailwindcss.com"),
+    ("vendor/htmx.min.js", "https://unpkg.com/htmx.org@2.0.4"),
+    (
+        "vendor/htmx-ext-ws.js",
+        "https://unpkg.com/htmx-ext-ws@2.0.2/ws.js",
+    ),
+    (
+        "vendor/htmx-ext-sse.js",
+        "https://unpkg.com/htmx-ext-sse@2.2.2/sse.js",
+    ),
+    (
+        "vendor/xterm.css",
+        "https://unpkg.com/@xterm/xterm@5.5.0/css/xterm.css",
+    ),
+    (
+        "vendor/xterm.js",
+        "https://unpkg.com/@xterm/xterm@5.5.0/lib/xterm.js",
+    ),
+    (
+        "vendor/addon-fit.js",
+        "https://unpkg.com/@xterm/addon-fit@0.10.0/lib/addon-fit.js",
+    ),
+    (
+        "vendor/swagger-ui.css",
+        "https://unpkg.com/swagger-ui-dist@5/swagger-ui.css",
+    ),
+    (
+        "vendor/swagger-ui-bundle.js",
+        "https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js",
+    ),
+];
+
+#[derive(RustEmbed)]
+#[folder = "static/"]
+struct Assets;
+
+/// Hashed URL of every embedded file, by path.
+static URLS: LazyLock<HashMap<String, String>> = LazyLock::new(|| {
+    Assets::iter()
+        .filter_map(|path| {
+            let file = Assets::get(&path)?;
+            let url = hashed_path(&path, &short_hash(&file));
+            Some((path.into_owned(), format!("/static/{}", url)))
+        })
+        .collect()
+});
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/{*path}", get(asset))
+        .layer(CompressionLayer::new())
+}
+
+/// URL of an asset for templates: hashed when the file is embedded, the
+/// CDN for a library that wasn't vendored.
+pub fn url(path: &str) -> String {
+    if let Some(url) = URLS.get(path) {
+        return url.clone();
+    }
+    match VENDORED.iter().find(|(vendored, _)| *vendored == path) {
+        Some((_, cdn)) => cdn.to_string(),
+        None => format!("/static/{}", path),
+    }
+}
+
+fn short_hash(file: &EmbeddedFile) -> String {
+    hex::encode(&file.metadata.sha256_hash()[..4])
+}
+
+/// `css/custom.css` with hash `3f2a9b1c` becomes `css/custom.3f2a9b1c.css`.
+fn hashed_path(path: &str, hash: &str) -> String {
+    let (dir, name) = path.rsplit_once('/').map_or(("", path), |(d, n)| (d, n));
+    let name = match name.split_once('.') {
+        Some((stem, extension)) => format!("{}.{}.{}", stem, hash, extension),
+        None => format!("{}.{}", name, hash),
+    };
+    if dir.is_empty() {
+        name
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+/// The embedded path and hash of a hashed URL path, if it is one.
+fn unhashed_path(path: &str) -> Option<(String, &str)> {
+    let (dir, name) = path.rsplit_once('/').map_or(("", path), |(d, n)| (d, n));
+    let mut parts = name.splitn(3, '.');
+    let stem = parts.next()?;
+    let hash = parts.next()?;
+    if hash.len() != 8 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let name = match parts.next() {
+        Some(extension) => format!("{}.{}", stem, extension),
+        None => stem.to_string(),
+    };
+    let path = if dir.is_empty() {
+        name
+    } else {
+        format!("{}/{}", dir, name)
+    };
+    Some((path, hash))
+}
+
+async fn asset(Path(path): Path<String>, headers: HeaderMap) -> Response {
+    // A hashed URL for another version of the file, e.g. from a page
+    // rendered before a deploy, gets the current file but isn't cached.
+    let (file, cache_control) = match Assets::get(&path) {
+        Some(file) => (file, REVALIDATE),
+        None => {
+            let Some((original, hash)) = unhashed_path(&path) else {
+                return StatusCode::NOT_FOUND.into_response();
+            };
+            let Some(file) = Assets::get(&original) else {
+                return StatusCode::NOT_FOUND.into_response();
+            };
+            let cache_control = if short_hash(&file) == hash {
+                IMMUTABLE
+            } else {
+                REVALIDATE
+            };
+            (file, cache_control)
+        }
+    };
+
+    let etag = format!("\"{}\"", hex::encode(file.metadata.sha256_hash()));
+    let unchanged = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|tags| tags.split(',').any(|tag| tag.trim() == etag));
+    let cache_headers = [
+        (
+            header::CACHE_CONTROL,
+            HeaderValue::from_static(cache_control),
+        ),
+        (
+            header::ETAG,
+            HeaderValue::from_str(&etag).unwrap_or_else(|_| HeaderValue::from_static("\"\"")),
+        ),
+    ];
+    if unchanged {
+        return (StatusCode::NOT_MODIFIED, cache_headers).into_response();
+    }
+
+    let content_type = mime_guess::from_path(&path).first_or_octet_stream();
+    (
+        cache_headers,
+        [
+            (header::CONTENT_TYPE, content_type.essence_str().to_string()),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        ],
+        file.data.into_owned(),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn get(path: &str) -> Response {
+        let path = path.trim_start_matches("/static/").to_string();
+        asset(Path(path), HeaderMap::new()).await
+    }
+
+    #[test]
+    fn test_hashed_paths_round_trip() {
+        assert_eq!(
+            hashed_path("css/custom.css", "3f2a9b1c"),
+            "css/custom.3f2a9b1c.css"
+        );
+        assert_eq!(hashed_path("LICENSE", "3f2a9b1c"), "LICENSE.3f2a9b1c");
+        assert_eq!(
+            unhashed_path("vendor/htmx.3f2a9b1c.min.js"),
+            Some(("vendor/htmx.min.js".to_string(), "3f2a9b1c"))
+        );
+        assert_eq!(unhashed_path("js/shortcuts.js"), None);
+        assert_eq!(unhashed_path("js/shortcuts.notahash.js"), None);
+    }
+
+    #[tokio::test]
+    async fn test_only_current_hashed_urls_are_cached_for_good() {
+        let url = url("css/custom.css");
+        assert_ne!(url, "/static/css/custom.css");
+        let response = get(&url).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], IMMUTABLE);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/css");
+
+        let stale = get("/static/css/custom.00000000.css").await;
+        assert_eq!(stale.status(), StatusCode::OK);
+        assert_eq!(stale.headers()[header::CACHE_CONTROL], REVALIDATE);
+
+        let plain = get("/static/css/custom.css").await;
+        assert_eq!(plain.headers()[header::CACHE_CONTROL], REVALIDATE);
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, plain.headers()[header::ETAG].clone());
+        let revalidated = asset(Path("css/custom.css".to_string()), headers).await;
+        assert_eq!(revalidated.status(), StatusCode::NOT_MODIFIED);
+
+        assert_eq!(
+            get("/static/css/missing.css").await.status(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            get("/static/../Cargo.toml").await.status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+}
@@ -0,0 +1,73 @@
+//! Audit log queries.
+
+use axum::Json;
+use axum::Router;
+use axum::extract::{Query, State};
+use axum::routing::get;
+use buildit_core::rbac::Permission;
+use buildit_db::{AuditLog, AuditLogFilter, OrganizationRepo};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use utoipa::{IntoParams, OpenApi};
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::auth::AuthContext;
+use crate::error::ApiError;
+
+/// Entries returned when the query sets no limit.
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+#[derive(OpenApi)]
+#[openapi(paths(list_audit_logs))]
+pub struct ApiDoc;
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/", get(list_audit_logs))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct AuditLogQuery {
+    tenant_id: Option<Uuid>,
+    user_id: Option<Uuid>,
+    resource_type: Option<String>,
+    resource_id: Option<Uuid>,
+    /// Action prefix, e.g. `stacks` or `stacks.runs.approve`.
+    action: Option<String>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    limit: Option<i64>,
+}
+
+/// The request organization's audit log, newest first.
+#[utoipa::path(
+    get,
+    path = "",
+    params(AuditLogQuery),
+    responses((status = 200, description = "Audit log entries", body = Vec<AuditLog>))
+)]
+async fn list_audit_logs(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Query(query): Query<AuditLogQuery>,
+) -> Result<Json<Vec<AuditLog>>, ApiError> {
+    let org_id = auth.organization()?;
+    auth.require_org(&state, org_id, Permission::AuditRead)
+        .await?;
+
+    let filter = AuditLogFilter {
+        organization_id: Some(org_id),
+        tenant_id: query.tenant_id,
+        user_id: query.user_id,
+        resource_type: query.resource_type.filter(|s| !s.is_empty()),
+        resource_id: query.resource_id,
+        action: query.action.filter(|s| !s.is_empty()),
+        from: query.from,
+        to: query.to,
+        limit: query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+    };
+    let logs = state.organization_repo.query_audit_logs(&filter).await?;
+    Ok(Json(logs))
+}
@@ -1,22 +1,39 @@
 //! Authentication routes (GitHub OAuth, etc.)
 
-use axum::extract::{Query, State};
-use axum::http::{HeaderMap, StatusCode, header};
+use axum::extract::{Form, Query, State};
+use axum::http::{HeaderMap, header};
 use axum::response::{IntoResponse, Redirect, Response};
-use axum::routing::get;
+use axum::routing::{get, post};
 use axum::{Json, Router};
 use axum_extra::extract::CookieJar;
 use axum_extra::extract::cookie::Cookie;
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::AppState;
+use crate::auth::{
+    ACCESS_TOKEN_PREFIX, INVITATION_COOKIE, REFRESH_TOKEN_PREFIX, SESSION_COOKIE,
+    bearer_or_basic_password, cookie_user, token_hash,
+};
 use crate::error::ApiError;
-use crate::services::github::{GitHubClient, GitHubConfig, GitHubRepo};
+use crate::services::github::{GitHubClient, GitHubConfig, GitHubRepo, GitHubUser};
+use buildit_core::ResourceId;
+use buildit_db::{DbError, DeviceAuthorization, OrganizationRepo, Session, User};
 
 /// Cookie name for storing GitHub access token.
 const GITHUB_TOKEN_COOKIE: &str = "github_token";
 
+/// How long a device code waits for approval.
+const DEVICE_CODE_TTL_SECS: i64 = 600;
+
+/// Seconds the CLI should wait between polls for a device code.
+const DEVICE_POLL_INTERVAL_SECS: i64 = 5;
+
+/// Letters of user codes: no vowels, so codes spell no words, and nothing
+/// easily misread.
+const USER_CODE_ALPHABET: &[u8] = b"BCDFGHJKLMNPQRSTVWXZ";
+
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/github", get(github_auth))
@@ -25,11 +42,17 @@ pub fn router() -> Router<AppState> {
         .route("/github/repos/search", get(search_github_repos))
         .route("/github/status", get(github_status))
         .route("/github/disconnect", get(github_disconnect))
+        .route("/logout", get(logout))
+        .route("/device", post(decide_device))
+        .route("/device/code", post(device_code))
+        .route("/device/token", post(device_token))
+        .route("/token/refresh", post(refresh_token))
+        .route("/token/revoke", post(revoke_token))
 }
 
 /// Redirect to GitHub OAuth.
-async fn github_auth() -> Result<Response, ApiError> {
-    let config = GitHubConfig::from_env().ok_or_else(|| {
+async fn github_auth(State(state): State<AppState>) -> Result<Response, ApiError> {
+    let config = GitHubConfig::from_config(&state.system_config.auth).ok_or_else(|| {
         ApiError::Internal(
             "GitHub OAuth not configured. Set GITHUB_CLIENT_ID and GITHUB_CLIENT_SECRET"
                 .to_string(),
@@ -53,11 +76,12 @@ pub struct CallbackQuery {
 
 /// Handle GitHub OAuth callback.
 async fn github_callback(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     jar: CookieJar,
+    headers: HeaderMap,
     Query(query): Query<CallbackQuery>,
 ) -> Result<(CookieJar, Redirect), ApiError> {
-    let config = GitHubConfig::from_env()
+    let config = GitHubConfig::from_config(&state.system_config.auth)
         .ok_or_else(|| ApiError::Internal("GitHub OAuth not configured".to_string()))?;
 
     // Exchange code for token
@@ -84,12 +108,499 @@ async fn github_callback(
         .same_site(axum_extra::extract::cookie::SameSite::Lax)
         .build();
 
-    let jar = jar.add(cookie);
+    let mut jar = jar.add(cookie);
+    let invitation = jar.get(INVITATION_COOKIE).map(|c| c.value().to_string());
+
+    // Sign in the BuildIt user linked to this GitHub account. Someone
+    // answering an invitation gets an account if they have none.
+    let user_id = match linked_user(&state, &user).await {
+        Ok(None) if invitation.is_some() => {
+            invited_user(&state, &user, invitation.as_deref().unwrap_or_default()).await
+        }
+        result => result,
+    };
+    match user_id {
+        Ok(Some(user_id)) => jar = jar.add(sign_in(&state, user_id, &headers).await?),
+        Ok(None) => tracing::info!(user = %user.login, "No BuildIt user for GitHub account"),
+        Err(e) => tracing::warn!(user = %user.login, error = %e, "Failed to look up user"),
+    }
 
-    // Redirect back to the pipeline creation page
+    // Back to the invitation being answered, else the pipeline creation page
+    if let Some(token) = invitation {
+        let jar = jar.remove(Cookie::build(INVITATION_COOKIE).path("/"));
+        return Ok((
+            jar,
+            Redirect::to(&format!("/invitations/{}", urlencoding::encode(&token))),
+        ));
+    }
     Ok((jar, Redirect::to("/pipelines/new?github_connected=true")))
 }
 
+/// The user with a GitHub connection for `github_user`, or with its email.
+async fn linked_user(state: &AppState, github_user: &GitHubUser) -> Result<Option<Uuid>, DbError> {
+    let repo = &state.organization_repo;
+    match repo
+        .get_oauth_connection_by_provider("github", &github_user.id.to_string())
+        .await
+    {
+        Ok(connection) => return Ok(Some(connection.user_id)),
+        Err(DbError::NotFound(_)) => {}
+        Err(e) => return Err(e),
+    }
+    let Some(email) = &github_user.email else {
+        return Ok(None);
+    };
+    match repo.get_user_by_email(email).await {
+        Ok(user) => Ok(Some(user.id)),
+        Err(DbError::NotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Create a user for a GitHub account answering a pending invitation,
+/// with its GitHub email, else the invited one.
+async fn invited_user(
+    state: &AppState,
+    github_user: &GitHubUser,
+    token: &str,
+) -> Result<Option<Uuid>, DbError> {
+    let repo = &state.organization_repo;
+    let invitation = match repo.get_invitation_by_token(&token_hash(token)).await {
+        Ok(invitation) if invitation.status == "pending" && invitation.expires_at > Utc::now() => {
+            invitation
+        }
+        Ok(_) | Err(DbError::NotFound(_)) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let now = Utc::now();
+    let user = repo
+        .create_user(&User {
+            id: Uuid::new_v4(),
+            email: github_user.email.clone().unwrap_or(invitation.email),
+            name: github_user
+                .name
+                .clone()
+                .unwrap_or_else(|| github_user.login.clone()),
+            avatar_url: Some(github_user.avatar_url.clone()),
+            password_hash: None,
+            email_verified_at: None,
+            last_login_at: None,
+            settings: serde_json::json!({}),
+            created_at: now,
+            updated_at: now,
+        })
+        .await?;
+    tracing::info!(user = %github_user.login, "Created user for invitation");
+    Ok(Some(user.id))
+}
+
+/// Start a session for `user_id`, returning its cookie.
+async fn sign_in(
+    state: &AppState,
+    user_id: Uuid,
+    headers: &HeaderMap,
+) -> Result<Cookie<'static>, ApiError> {
+    let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let now = Utc::now();
+    let header_value = |name: header::HeaderName| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    state
+        .organization_repo
+        .create_session(&Session {
+            id: Uuid::new_v4(),
+            user_id,
+            token_hash: token_hash(&token),
+            ip_address: header_value(header::HeaderName::from_static("x-forwarded-for")),
+            user_agent: header_value(header::USER_AGENT),
+            expires_at: now + state.auth.session_ttl,
+            created_at: now,
+            refresh_token_hash: None,
+            refresh_expires_at: None,
+            tenant_id: None,
+        })
+        .await?;
+    let _ = state
+        .organization_repo
+        .update_last_login(ResourceId::from_uuid(user_id))
+        .await;
+
+    Ok(Cookie::build((SESSION_COOKIE, token))
+        .path("/")
+        .http_only(true)
+        .max_age(time::Duration::seconds(
+            state.auth.session_ttl.num_seconds(),
+        ))
+        .same_site(axum_extra::extract::cookie::SameSite::Lax)
+        .build())
+}
+
+/// End the session and drop its cookie.
+async fn logout(State(state): State<AppState>, jar: CookieJar) -> (CookieJar, Redirect) {
+    if let Some(cookie) = jar.get(SESSION_COOKIE) {
+        let repo = &state.organization_repo;
+        if let Ok(session) = repo.get_session_by_token(&token_hash(cookie.value())).await {
+            let _ = repo.delete_session(ResourceId::from_uuid(session.id)).await;
+        }
+    }
+    let jar = jar.remove(Cookie::build(SESSION_COOKIE).path("/"));
+    (jar, Redirect::to("/"))
+}
+
+// ============================================================================
+// Device authorization (CLI sign-in)
+// ============================================================================
+//
+// `buildit login` follows RFC 8628: it asks for a device code, shows the
+// user code and the verification page (`/device`), and polls for a token
+// while the user, signed in to the web UI, approves the code there. The
+// token is a CLI session: a short-lived access token and a refresh token.
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DeviceCodeRequest {
+    /// Shown on the verification page, e.g. the CLI's host name.
+    pub client_name: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeviceCodeResponse {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    pub verification_uri_complete: String,
+    pub expires_in: i64,
+    pub interval: i64,
+}
+
+/// Start a device authorization.
+async fn device_code(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Option<Json<DeviceCodeRequest>>,
+) -> Result<Json<DeviceCodeResponse>, ApiError> {
+    let req = body.map(|Json(b)| b).unwrap_or_default();
+    let device_code = random_token("");
+    let user_code = user_code();
+    let now = Utc::now();
+    state
+        .organization_repo
+        .create_device_authorization(&DeviceAuthorization {
+            id: Uuid::new_v4(),
+            device_code_hash: token_hash(&device_code),
+            user_code: user_code.clone(),
+            client_name: req.client_name.map(|n| n.chars().take(255).collect()),
+            status: "pending".to_string(),
+            user_id: None,
+            last_polled_at: None,
+            expires_at: now + chrono::Duration::seconds(DEVICE_CODE_TTL_SECS),
+            created_at: now,
+        })
+        .await?;
+
+    let verification_uri = format!("{}/device", public_url(&state, &headers));
+    Ok(Json(DeviceCodeResponse {
+        device_code,
+        verification_uri_complete: format!("{}?user_code={}", verification_uri, user_code),
+        user_code,
+        verification_uri,
+        expires_in: DEVICE_CODE_TTL_SECS,
+        interval: DEVICE_POLL_INTERVAL_SECS,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeviceTokenRequest {
+    pub device_code: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub token_type: &'static str,
+    /// Seconds until the access token expires.
+    pub expires_in: i64,
+    /// Seconds until the refresh token expires.
+    pub refresh_expires_in: i64,
+}
+
+/// Poll a device authorization, returning tokens once it is approved.
+/// Until then this fails with the RFC 8628 error codes:
+/// `authorization_pending`, `slow_down`, `access_denied` or `expired_token`.
+async fn device_token(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<DeviceTokenRequest>,
+) -> Result<Json<TokenResponse>, ApiError> {
+    let repo = &state.organization_repo;
+    let authorization = match repo
+        .get_device_authorization(&token_hash(&req.device_code))
+        .await
+    {
+        Ok(authorization) => authorization,
+        Err(DbError::NotFound(_)) => return Err(ApiError::BadRequest("invalid_grant".into())),
+        Err(e) => return Err(e.into()),
+    };
+    if authorization.expires_at <= Utc::now() && authorization.status != "approved" {
+        return Err(ApiError::BadRequest("expired_token".into()));
+    }
+
+    let id = ResourceId::from_uuid(authorization.id);
+    match authorization.status.as_str() {
+        "pending" => {
+            let previous = repo.poll_device_authorization(id).await?;
+            let too_soon = previous.is_some_and(|at| {
+                Utc::now() - at < chrono::Duration::seconds(DEVICE_POLL_INTERVAL_SECS)
+            });
+            Err(ApiError::BadRequest(
+                if too_soon {
+                    "slow_down"
+                } else {
+                    "authorization_pending"
+                }
+                .into(),
+            ))
+        }
+        "denied" => Err(ApiError::BadRequest("access_denied".into())),
+        "approved" => {
+            let authorization = match repo.redeem_device_authorization(id).await {
+                Ok(authorization) => authorization,
+                Err(DbError::NotFound(_)) => {
+                    return Err(ApiError::BadRequest("invalid_grant".into()));
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let user_id = authorization
+                .user_id
+                .ok_or_else(|| ApiError::Internal("Approved without a user".to_string()))?;
+            let tokens = cli_sign_in(&state, user_id, &headers).await?;
+            tracing::info!(%user_id, client = ?authorization.client_name, "CLI signed in");
+            Ok(Json(tokens))
+        }
+        _ => Err(ApiError::BadRequest("invalid_grant".into())),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DecideDeviceForm {
+    pub user_code: String,
+    /// `approve` or `deny`.
+    pub action: String,
+}
+
+/// Approve or deny a device code, from the verification page.
+async fn decide_device(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(form): Form<DecideDeviceForm>,
+) -> Result<Redirect, ApiError> {
+    let user_id = cookie_user(&state, &headers)
+        .await?
+        .ok_or_else(|| ApiError::Unauthorized("Sign in to approve devices".to_string()))?;
+    let user_code = normalize_user_code(&form.user_code);
+    let repo = &state.organization_repo;
+    let authorization = match repo.get_pending_device_authorization(&user_code).await {
+        Ok(authorization) => authorization,
+        Err(DbError::NotFound(_)) => {
+            return Ok(Redirect::to(&format!(
+                "/device?user_code={}&result=invalid",
+                urlencoding::encode(&user_code)
+            )));
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let approved = form.action == "approve";
+    repo.decide_device_authorization(
+        ResourceId::from_uuid(authorization.id),
+        ResourceId::from_uuid(user_id),
+        approved,
+    )
+    .await?;
+    let result = if approved { "approved" } else { "denied" };
+    Ok(Redirect::to(&format!("/device?result={}", result)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RefreshTokenRequest {
+    pub refresh_token: String,
+}
+
+/// Rotate a CLI session's tokens. The old refresh token stops working.
+async fn refresh_token(
+    State(state): State<AppState>,
+    Json(req): Json<RefreshTokenRequest>,
+) -> Result<Json<TokenResponse>, ApiError> {
+    let repo = &state.organization_repo;
+    let session = match repo
+        .get_session_by_refresh_token(&token_hash(&req.refresh_token))
+        .await
+    {
+        Ok(session) => session,
+        Err(DbError::NotFound(_)) => {
+            return Err(ApiError::Unauthorized(
+                "Refresh token expired; run `buildit login`".to_string(),
+            ));
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let access_token = random_token(ACCESS_TOKEN_PREFIX);
+    let refresh_token = random_token(REFRESH_TOKEN_PREFIX);
+    let now = Utc::now();
+    repo.rotate_session(
+        ResourceId::from_uuid(session.id),
+        &token_hash(&access_token),
+        now + state.auth.access_token_ttl,
+        &token_hash(&refresh_token),
+        now + state.auth.session_ttl,
+    )
+    .await?;
+    Ok(Json(token_response(&state, access_token, refresh_token)))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct RevokeTokenRequest {
+    pub refresh_token: Option<String>,
+}
+
+/// End a CLI session, given its refresh token or, as a Bearer token, its
+/// access token.
+async fn revoke_token(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Option<Json<RevokeTokenRequest>>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let req = body.map(|Json(b)| b).unwrap_or_default();
+    let repo = &state.organization_repo;
+    let session = match (req.refresh_token, bearer_or_basic_password(&headers)) {
+        (Some(refresh_token), _) => {
+            repo.get_session_by_refresh_token(&token_hash(&refresh_token))
+                .await
+        }
+        (None, Some(token)) if token.starts_with(ACCESS_TOKEN_PREFIX) => {
+            repo.get_session_by_token(&token_hash(&token)).await
+        }
+        _ => {
+            return Err(ApiError::BadRequest(
+                "A refresh token or CLI access token is required".to_string(),
+            ));
+        }
+    };
+    let revoked = match session {
+        Ok(session) => {
+            repo.delete_session(ResourceId::from_uuid(session.id))
+                .await?;
+            true
+        }
+        Err(DbError::NotFound(_)) => false,
+        Err(e) => return Err(e.into()),
+    };
+    Ok(Json(serde_json::json!({ "revoked": revoked })))
+}
+
+/// Start a CLI session for `user_id`.
+async fn cli_sign_in(
+    state: &AppState,
+    user_id: Uuid,
+    headers: &HeaderMap,
+) -> Result<TokenResponse, ApiError> {
+    let access_token = random_token(ACCESS_TOKEN_PREFIX);
+    let refresh_token = random_token(REFRESH_TOKEN_PREFIX);
+    let now = Utc::now();
+    state
+        .organization_repo
+        .create_session(&Session {
+            id: Uuid::new_v4(),
+            user_id,
+            token_hash: token_hash(&access_token),
+            ip_address: headers
+                .get("x-forwarded-for")
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string),
+            user_agent: headers
+                .get(header::USER_AGENT)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string),
+            expires_at: now + state.auth.access_token_ttl,
+            created_at: now,
+            refresh_token_hash: Some(token_hash(&refresh_token)),
+            refresh_expires_at: Some(now + state.auth.session_ttl),
+            tenant_id: None,
+        })
+        .await?;
+    let _ = state
+        .organization_repo
+        .update_last_login(ResourceId::from_uuid(user_id))
+        .await;
+    Ok(token_response(state, access_token, refresh_token))
+}
+
+fn token_response(state: &AppState, access_token: String, refresh_token: String) -> TokenResponse {
+    TokenResponse {
+        access_token,
+        refresh_token,
+        token_type: "Bearer",
+        expires_in: state.auth.access_token_ttl.num_seconds(),
+        refresh_expires_in: state.auth.session_ttl.num_seconds(),
+    }
+}
+
+/// A random secret token with `prefix`.
+pub(crate) fn random_token(prefix: &str) -> String {
+    format!(
+        "{}{}{}",
+        prefix,
+        Uuid::new_v4().simple(),
+        Uuid::new_v4().simple()
+    )
+}
+
+/// A random user code like `BCDF-GHJK`.
+fn user_code() -> String {
+    let letters: String = Uuid::new_v4()
+        .as_bytes()
+        .iter()
+        .take(8)
+        .map(|b| USER_CODE_ALPHABET[*b as usize % USER_CODE_ALPHABET.len()] as char)
+        .collect();
+    format!("{}-{}", &letters[..4], &letters[4..])
+}
+
+/// Accept user codes typed in lower case or without the dash.
+pub(crate) fn normalize_user_code(code: &str) -> String {
+    let letters: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphabetic())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    if letters.len() == 8 {
+        format!("{}-{}", &letters[..4], &letters[4..])
+    } else {
+        letters
+    }
+}
+
+/// Base URL users reach the server at.
+pub(crate) fn public_url(state: &AppState, headers: &HeaderMap) -> String {
+    if let Some(url) = &state.auth.public_url {
+        return url.clone();
+    }
+    let host = headers
+        .get(header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("localhost:3000");
+    let scheme = headers
+        .get("x-forwarded-proto")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("http");
+    format!("{}://{}", scheme, host)
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ReposQuery {
     pub page: Option<u32>,
@@ -187,8 +698,11 @@ pub struct GitHubStatusResponse {
 }
 
 /// Check if GitHub OAuth is configured and if user is connected.
-async fn github_status(jar: CookieJar) -> Result<Json<GitHubStatusResponse>, ApiError> {
-    let config = GitHubConfig::from_env();
+async fn github_status(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<Json<GitHubStatusResponse>, ApiError> {
+    let config = GitHubConfig::from_config(&state.system_config.auth);
     let configured = config.is_some();
 
     // Check if user has a valid token
@@ -214,3 +728,33 @@ async fn github_disconnect(jar: CookieJar) -> (CookieJar, Redirect) {
     let jar = jar.remove(Cookie::from(GITHUB_TOKEN_COOKIE));
     (jar, Redirect::to("/pipelines/new"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_codes_are_typed_leniently() {
+        let code = user_code();
+        assert_eq!(code.len(), 9);
+        assert_eq!(normalize_user_code(&code), code);
+        assert_eq!(normalize_user_code("bcdf ghjk"), "BCDF-GHJK");
+        assert_eq!(normalize_user_code("bcdf-ghjk"), "BCDF-GHJK");
+        // Too short to be a code; looked up as typed and not found
+        assert_eq!(normalize_user_code("bcd"), "BCD");
+    }
+
+    #[tokio::test]
+    async fn test_approving_a_device_needs_a_session() {
+        let result = decide_device(
+            State(AppState::without_database()),
+            HeaderMap::new(),
+            Form(DecideDeviceForm {
+                user_code: "BCDF-GHJK".to_string(),
+                action: "approve".to_string(),
+            }),
+        )
+        .await;
+        assert!(matches!(result, Err(ApiError::Unauthorized(_))));
+    }
+}
@@ -0,0 +1,250 @@
+//! Status badges of pipelines, for embedding in READMEs.
+//!
+//! - `GET /badge/{pipeline}/status.svg` - the latest run of the pipeline's
+//!   default branch
+//! - `GET /badge/{pipeline}/branches/{branch}/status.svg` - the latest run
+//!   of a branch (URL-encoded)
+//! - `GET .../status.json` - either in the shields.io endpoint format
+//!
+//! Badges are served without authentication, as image proxies such as
+//! GitHub's fetch them anonymously. They show nothing but the status of the
+//! latest run of a pipeline whose ID is known. Clients may cache a badge for
+//! a minute and revalidate it with its ETag.
+
+use axum::Router;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::error::ApiError;
+use crate::services::badges::Badge;
+use buildit_core::ResourceId;
+use buildit_db::repo::pipeline::PipelineRunRecord;
+use buildit_db::{PipelineRepo, RepositoryRepo};
+
+/// Label of the badges' left half.
+const LABEL: &str = "build";
+
+/// How long clients and proxies may show a badge before checking again.
+const CACHE_CONTROL: &str = "public, max-age=60, must-revalidate";
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/{id}/status.svg", get(default_branch_svg))
+        .route("/{id}/status.json", get(default_branch_json))
+        .route("/{id}/branches/{branch}/status.svg", get(branch_svg))
+        .route("/{id}/branches/{branch}/status.json", get(branch_json))
+}
+
+#[derive(Clone, Copy)]
+enum Format {
+    Svg,
+    Json,
+}
+
+async fn default_branch_svg(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let run = default_branch_run(&state, id).await?;
+    Ok(badge_response(run.as_ref(), &headers, Format::Svg))
+}
+
+async fn default_branch_json(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let run = default_branch_run(&state, id).await?;
+    Ok(badge_response(run.as_ref(), &headers, Format::Json))
+}
+
+async fn branch_svg(
+    State(state): State<AppState>,
+    Path((id, branch)): Path<(Uuid, String)>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let run = branch_run(&state, id, &branch).await?;
+    Ok(badge_response(run.as_ref(), &headers, Format::Svg))
+}
+
+async fn branch_json(
+    State(state): State<AppState>,
+    Path((id, branch)): Path<(Uuid, String)>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let run = branch_run(&state, id, &branch).await?;
+    Ok(badge_response(run.as_ref(), &headers, Format::Json))
+}
+
+/// Latest run of the default branch of the pipeline's repository, or of
+/// any branch when the pipeline has no linked repository.
+async fn default_branch_run(
+    state: &AppState,
+    id: Uuid,
+) -> Result<Option<PipelineRunRecord>, ApiError> {
+    let pipeline_id = ResourceId::from_uuid(id);
+    let pipeline = state.pipeline_repo.get_by_id(pipeline_id).await?;
+    match pipeline.repository_id {
+        Some(repository_id) => {
+            let repository = state
+                .repository_repo
+                .get_by_id(ResourceId::from_uuid(repository_id))
+                .await?;
+            Ok(state
+                .pipeline_repo
+                .latest_branch_run(pipeline_id, &repository.default_branch, None)
+                .await?)
+        }
+        None => Ok(state
+            .pipeline_repo
+            .list_runs(pipeline_id, 1)
+            .await?
+            .into_iter()
+            .next()),
+    }
+}
+
+async fn branch_run(
+    state: &AppState,
+    id: Uuid,
+    branch: &str,
+) -> Result<Option<PipelineRunRecord>, ApiError> {
+    let pipeline_id = ResourceId::from_uuid(id);
+    // Unknown pipelines are a 404 rather than a "no runs" badge
+    state.pipeline_repo.get_by_id(pipeline_id).await?;
+    Ok(state
+        .pipeline_repo
+        .latest_branch_run(pipeline_id, branch, None)
+        .await?)
+}
+
+/// The badge for `run`, or `304 Not Modified` if the client's copy, named
+/// by `If-None-Match`, shows the same run and status.
+fn badge_response(
+    run: Option<&PipelineRunRecord>,
+    headers: &HeaderMap,
+    format: Format,
+) -> Response {
+    let etag = match run {
+        Some(run) => format!("\"{}-{}\"", run.id.simple(), run.status),
+        None => "\"none\"".to_string(),
+    };
+    let cache_headers = [
+        (
+            header::CACHE_CONTROL,
+            HeaderValue::from_static(CACHE_CONTROL),
+        ),
+        (
+            header::ETAG,
+            HeaderValue::from_str(&etag).unwrap_or_else(|_| HeaderValue::from_static("\"none\"")),
+        ),
+    ];
+    let unchanged = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|tags| tags.split(',').any(|tag| tag.trim() == etag));
+    if unchanged {
+        return (StatusCode::NOT_MODIFIED, cache_headers).into_response();
+    }
+
+    let badge = Badge::for_status(LABEL, run.map(|r| r.status.as_str()));
+    match format {
+        Format::Svg => (
+            cache_headers,
+            [(header::CONTENT_TYPE, "image/svg+xml;charset=utf-8")],
+            badge.svg(),
+        )
+            .into_response(),
+        Format::Json => (cache_headers, axum::Json(badge.shields_json())).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::pipelines::tests::{branch_run as queue_run, new_pipeline};
+
+    #[test]
+    fn test_unchanged_badges_are_not_sent_again() {
+        let response = badge_response(None, &HeaderMap::new(), Format::Json);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::ETAG], "\"none\"");
+        assert_eq!(response.headers()[header::CACHE_CONTROL], CACHE_CONTROL);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::IF_NONE_MATCH,
+            HeaderValue::from_static("\"abc-failed\", \"none\""),
+        );
+        let response = badge_response(None, &headers, Format::Svg);
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        headers.insert(
+            header::IF_NONE_MATCH,
+            HeaderValue::from_static("\"abc-failed\""),
+        );
+        let response = badge_response(None, &headers, Format::Svg);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "image/svg+xml;charset=utf-8"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_badges_show_the_latest_run_of_the_branch() {
+        let Ok(url) = std::env::var("DATABASE_URL") else {
+            return;
+        };
+        let pool = sqlx::PgPool::connect(&url).await.unwrap();
+        buildit_db::run_migrations(&pool).await.unwrap();
+        let state = AppState::with_pool(pool.clone());
+        let (org, pipeline) = new_pipeline(&state).await;
+
+        let status = |run: Option<PipelineRunRecord>| run.map(|r| r.status);
+        assert_eq!(
+            status(branch_run(&state, pipeline.id, "main").await.unwrap()),
+            None
+        );
+        assert_eq!(
+            status(default_branch_run(&state, pipeline.id).await.unwrap()),
+            None
+        );
+
+        queue_run(&state, &pipeline, "main", "abc123", "succeeded").await;
+        let feature = queue_run(&state, &pipeline, "feature/x", "def456", "failed").await;
+        let run = branch_run(&state, pipeline.id, "main").await.unwrap();
+        assert_eq!(status(run), Some("succeeded".to_string()));
+        let run = branch_run(&state, pipeline.id, "feature/x").await.unwrap();
+        assert_eq!(status(run.clone()), Some("failed".to_string()));
+        // Without a linked repository, the default branch is any branch.
+        let latest = default_branch_run(&state, pipeline.id).await.unwrap();
+        assert_eq!(latest.map(|r| r.id), Some(feature.id));
+
+        let response = badge_response(run.as_ref(), &HeaderMap::new(), Format::Json);
+        assert_eq!(
+            response.headers()[header::ETAG],
+            format!("\"{}-failed\"", feature.id.simple()).as_str()
+        );
+
+        assert!(matches!(
+            branch_run(&state, Uuid::new_v4(), "main").await,
+            Err(ApiError::NotFound(_))
+        ));
+        assert!(matches!(
+            default_branch_run(&state, Uuid::new_v4()).await,
+            Err(ApiError::NotFound(_))
+        ));
+
+        sqlx::query("DELETE FROM organizations WHERE id = $1")
+            .bind(org)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+}
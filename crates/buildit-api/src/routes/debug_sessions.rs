@@ -0,0 +1,230 @@
+//! Debug session endpoints: follow, close and open a terminal in a debug
+//! session of a failed stage. Sessions are opened on the stage, under
+//! `/pipelines/{id}/runs/{run_id}/stages/{stage}/debug`.
+
+use axum::body::Bytes;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, State};
+use axum::response::Response;
+use axum::routing::get;
+use axum::{Json, Router};
+use buildit_core::ResourceId;
+use buildit_core::executor::TerminalSession;
+use buildit_core::rbac::Permission;
+use buildit_db::{DebugSessionRecord, PipelineRepo};
+
+use futures::{SinkExt, StreamExt};
+use serde::Serialize;
+use utoipa::{OpenApi, ToSchema};
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::auth::AuthContext;
+use crate::error::ApiError;
+use crate::services::tasks::Task;
+
+#[derive(OpenApi)]
+#[openapi(paths(get_debug_session, close_debug_session, open_terminal))]
+pub struct ApiDoc;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/{id}", get(get_debug_session).delete(close_debug_session))
+        .route("/{id}/terminal", get(open_terminal))
+}
+
+/// Shell started in a session's job: bash where the image has it.
+const SHELL: &str = "command -v bash >/dev/null && exec bash || exec sh";
+
+/// A debug session: an idle copy of a failed stage's job. It is `starting`
+/// until the job runs, then `ready` for a terminal, and ends `closed`,
+/// `expired` or `failed`.
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct DebugSessionResponse {
+    id: Uuid,
+    run_id: Uuid,
+    stage_name: String,
+    status: String,
+    /// Executor running the session's job.
+    executor: Option<String>,
+    error_message: Option<String>,
+    /// When the job is removed, unless the session is closed first.
+    expires_at: String,
+    created_at: String,
+    finished_at: Option<String>,
+}
+
+impl From<DebugSessionRecord> for DebugSessionResponse {
+    fn from(s: DebugSessionRecord) -> Self {
+        Self {
+            id: s.id,
+            run_id: s.pipeline_run_id,
+            stage_name: s.stage_name,
+            status: s.status,
+            executor: s.executor_name,
+            error_message: s.error_message,
+            expires_at: s.expires_at.to_rfc3339(),
+            created_at: s.created_at.to_rfc3339(),
+            finished_at: s.finished_at.map(|t| t.to_rfc3339()),
+        }
+    }
+}
+
+/// Load a session the caller may debug in.
+async fn authorized_session(
+    state: &AppState,
+    auth: &AuthContext,
+    id: Uuid,
+) -> Result<DebugSessionRecord, ApiError> {
+    let session = state
+        .pipeline_repo
+        .get_debug_session(ResourceId::from_uuid(id))
+        .await?;
+    auth.require(state, session.tenant_id, Permission::PipelineDebug)
+        .await?;
+    Ok(session)
+}
+
+#[utoipa::path(
+    get,
+    path = "/{id}",
+    params(("id" = Uuid, Path, description = "Debug session ID")),
+    responses((status = 200, description = "The debug session", body = DebugSessionResponse))
+)]
+async fn get_debug_session(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<DebugSessionResponse>, ApiError> {
+    Ok(Json(authorized_session(&state, &auth, id).await?.into()))
+}
+
+/// Close a session and remove its job. Closing a session that already
+/// ended changes nothing.
+#[utoipa::path(
+    delete,
+    path = "/{id}",
+    params(("id" = Uuid, Path, description = "Debug session ID")),
+    responses((status = 200, description = "The closed session", body = DebugSessionResponse))
+)]
+async fn close_debug_session(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<DebugSessionResponse>, ApiError> {
+    let session = authorized_session(&state, &auth, id).await?;
+    let session_id = ResourceId::from_uuid(session.id);
+    if state
+        .pipeline_repo
+        .finish_debug_session(session_id, "closed", None)
+        .await?
+    {
+        // The session's task removes the job
+        let key = Task::DebugSession { session_id: id }.idempotency_key();
+        state
+            .job_queue
+            .run_task_now(&key)
+            .await
+            .map_err(|e| ApiError::Internal(format!("Failed to close debug session: {}", e)))?;
+    }
+    Ok(Json(
+        state
+            .pipeline_repo
+            .get_debug_session(session_id)
+            .await?
+            .into(),
+    ))
+}
+
+/// Open a shell in a `ready` session's job over a WebSocket. Binary and
+/// text frames are the terminal's input; its output comes back as binary
+/// frames. The socket closes when the shell exits or the session ends.
+#[utoipa::path(
+    get,
+    path = "/{id}/terminal",
+    params(("id" = Uuid, Path, description = "Debug session ID")),
+    responses((status = 101, description = "Switching to the terminal WebSocket"))
+)]
+async fn open_terminal(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Response, ApiError> {
+    let session = authorized_session(&state, &auth, id).await?;
+    if session.status != "ready" {
+        return Err(ApiError::Conflict(format!(
+            "Debug session {} is {}",
+            id, session.status
+        )));
+    }
+    let Some(handle) = session.job_handle() else {
+        return Err(ApiError::Conflict(format!(
+            "Debug session {} has no job",
+            id
+        )));
+    };
+    let Some(executor) = state
+        .orchestrator
+        .as_ref()
+        .and_then(|o| o.executors().get(&handle.executor_name))
+    else {
+        return Err(ApiError::Conflict(format!(
+            "Debug session {} runs on executor {}, which this server does not reach",
+            id, handle.executor_name
+        )));
+    };
+    let cmd = vec!["/bin/sh".to_string(), "-c".to_string(), SHELL.to_string()];
+    let terminal = executor
+        .exec_interactive(&handle, cmd)
+        .await
+        .map_err(|e| ApiError::Conflict(format!("Failed to open a terminal: {}", e)))?;
+    tracing::info!(session_id = %id, user_id = ?auth.user_id, "Debug terminal opened");
+    Ok(ws.on_upgrade(move |socket| pipe_terminal(socket, terminal)))
+}
+
+/// Copy frames to the terminal's input and its output to frames until
+/// either side closes.
+async fn pipe_terminal(socket: WebSocket, terminal: TerminalSession) {
+    let (mut sender, mut receiver) = socket.split();
+    let TerminalSession {
+        mut stdin,
+        mut stdout,
+    } = terminal;
+    loop {
+        tokio::select! {
+            msg = receiver.next() => {
+                let input = match msg {
+                    Some(Ok(Message::Binary(bytes))) => bytes,
+                    Some(Ok(Message::Text(text))) => Bytes::copy_from_slice(text.as_bytes()),
+                    Some(Ok(Message::Close(_))) | None => break,
+                    Some(Err(e)) => {
+                        tracing::warn!(error = %e, "Debug terminal socket error");
+                        break;
+                    }
+                    _ => continue,
+                };
+                if let Err(e) = stdin.send(input).await {
+                    tracing::warn!(error = %e, "Failed to write to debug terminal");
+                    break;
+                }
+            }
+            output = stdout.next() => match output {
+                Some(Ok(bytes)) => {
+                    if sender.send(Message::Binary(bytes)).await.is_err() {
+                        break;
+                    }
+                }
+                Some(Err(e)) => {
+                    tracing::warn!(error = %e, "Failed to read from debug terminal");
+                    break;
+                }
+                None => {
+                    let _ = sender.send(Message::Close(None)).await;
+                    break;
+                }
+            },
+        }
+    }
+    let _ = stdin.close().await;
+}
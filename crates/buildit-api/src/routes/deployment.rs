@@ -2,16 +2,89 @@
 
 use axum::{
     Json, Router,
-    extract::{Path, State},
-    routing::{delete, get, post},
+    extract::{Path, Query, State},
+    routing::{get, post, put},
 };
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::sync::Arc;
+use utoipa::{IntoParams, OpenApi, ToSchema};
 use uuid::Uuid;
 
 use crate::AppState;
+use crate::audit::AuditBefore;
+use crate::auth::AuthContext;
 use crate::error::ApiError;
+use crate::services::approval_context::ApprovalContext;
+use crate::services::deployment_diff::DeploymentDiff;
+use crate::services::deployment_preview::DeploymentPreview;
+use crate::services::freeze::{self, FreezeOccurrence};
+use crate::services::protection;
+use crate::services::release_notes::{ReleaseNotes, ReleaseNotesService, is_production};
+use crate::services::targets::{self, TargetCheck};
+use crate::services::tasks::{self, Task};
+use crate::ws::BroadcastEvent;
 use buildit_core::ResourceId;
-use buildit_db::{DeploymentRepo, TenantRepo};
+use buildit_core::deployer::{Deployer, DeploymentHandle, RollbackTarget};
+use buildit_core::rbac::{Permission, Role};
+use buildit_core::target::{TargetCredentials, TargetKind, TargetStatus};
+use buildit_db::{
+    Deployment, DeploymentRepo, DeploymentReview, Environment, EnvironmentProtection, FreezeWindow,
+    OrganizationRepo, Service, ServiceCatalog, ServiceSpecVersion, Target,
+};
+use chrono::{DateTime, Duration, Utc};
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    list_environments,
+    create_environment,
+    get_environment,
+    delete_environment,
+    list_freeze_windows,
+    create_freeze_window,
+    delete_freeze_window,
+    get_environment_freeze,
+    get_freeze_calendar,
+    get_environment_protection,
+    set_environment_protection,
+    delete_environment_protection,
+    list_targets,
+    create_target,
+    get_target,
+    update_target,
+    check_target,
+    delete_target,
+    list_services,
+    get_service,
+    update_service_catalog,
+    get_promotion_chain,
+    set_promotion_chain,
+    promote_service,
+    get_service_spec,
+    update_service_spec,
+    list_spec_versions,
+    get_spec_version,
+    diff_spec_versions,
+    redeploy_spec_version,
+    list_deployments,
+    create_deployment,
+    preview_deployment,
+    get_deployment,
+    promote_deployment,
+    rollback_deployment,
+    approve_deployment,
+    reject_deployment,
+    list_deployment_reviews,
+    list_pending_approvals,
+    get_deployment_lineage,
+    diff_deployments,
+    override_freeze,
+    get_approval_context,
+    get_release_notes,
+    generate_release_notes
+))]
+pub struct ApiDoc;
 
 pub fn router() -> Router<AppState> {
     Router::new()
@@ -24,16 +97,88 @@ pub fn router() -> Router<AppState> {
             "/environments/{id}",
             get(get_environment).delete(delete_environment),
         )
+        // Freeze windows
+        .route(
+            "/environments/{id}/freeze-windows",
+            get(list_freeze_windows).post(create_freeze_window),
+        )
+        .route(
+            "/environments/{id}/freeze-windows/{window_id}",
+            axum::routing::delete(delete_freeze_window),
+        )
+        .route("/environments/{id}/freeze", get(get_environment_freeze))
+        .route("/freeze-calendar", get(get_freeze_calendar))
+        // Protection rules
+        .route(
+            "/environments/{id}/protection",
+            get(get_environment_protection)
+                .put(set_environment_protection)
+                .delete(delete_environment_protection),
+        )
         // Targets
         .route("/targets", get(list_targets).post(create_target))
-        .route("/targets/{id}", get(get_target).delete(delete_target))
+        .route(
+            "/targets/{id}",
+            get(get_target).put(update_target).delete(delete_target),
+        )
+        .route("/targets/{id}/check", post(check_target))
+        // Services
+        .route("/services", get(list_services))
+        .route("/services/{id}", get(get_service))
+        .route("/services/{id}/catalog", put(update_service_catalog))
+        // Environment promotion
+        .route(
+            "/services/{id}/promotion-chain",
+            get(get_promotion_chain).put(set_promotion_chain),
+        )
+        .route("/services/{id}/promote", post(promote_service))
+        // Service deployment specs
+        .route(
+            "/services/{id}/spec",
+            get(get_service_spec).put(update_service_spec),
+        )
+        .route("/services/{id}/spec/versions", get(list_spec_versions))
+        .route(
+            "/services/{id}/spec/versions/{version}",
+            get(get_spec_version),
+        )
+        .route("/services/{id}/spec/diff", get(diff_spec_versions))
+        .route(
+            "/services/{id}/spec/versions/{version}/redeploy",
+            post(redeploy_spec_version),
+        )
+        // Deployments
+        .route(
+            "/deployments",
+            get(list_deployments).post(create_deployment),
+        )
+        .route("/deployments/{id}", get(get_deployment))
+        .route("/preview", post(preview_deployment))
+        // Blue/green traffic switching
+        .route("/deployments/{id}/promote", post(promote_deployment))
+        .route("/deployments/{id}/rollback", post(rollback_deployment))
+        .route("/deployments/{id}/approve", post(approve_deployment))
+        .route("/deployments/{id}/reject", post(reject_deployment))
+        .route("/deployments/{id}/reviews", get(list_deployment_reviews))
+        .route("/approvals", get(list_pending_approvals))
+        .route("/deployments/{id}/lineage", get(get_deployment_lineage))
+        .route("/deployments/{id}/diff", get(diff_deployments))
+        .route("/deployments/{id}/override-freeze", post(override_freeze))
+        .route(
+            "/deployments/{id}/approval-context",
+            get(get_approval_context),
+        )
+        .route(
+            "/deployments/{id}/release-notes",
+            get(get_release_notes).post(generate_release_notes),
+        )
 }
 
 // ============================================================================
 // Request/Response types
 // ============================================================================
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct CreateEnvironmentRequest {
     pub name: String,
     pub description: Option<String>,
@@ -44,7 +189,7 @@ pub struct CreateEnvironmentRequest {
     pub auto_deploy: bool,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct EnvironmentResponse {
     pub id: Uuid,
     pub name: String,
@@ -55,36 +200,456 @@ pub struct EnvironmentResponse {
     pub health_status: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct CreateTargetRequest {
     pub name: String,
+    /// `kubernetes`, `fly`, `aws` or `docker`.
     pub target_type: String,
     pub region: Option<String>,
+    /// Settings that are not secret, e.g. `namespace`, `org` or `host`.
     #[serde(default)]
     pub config: serde_json::Value,
+    /// Stored encrypted and never returned.
+    pub credentials: Option<TargetCredentials>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateTargetRequest {
+    pub name: String,
+    pub region: Option<String>,
+    #[serde(default)]
+    pub config: serde_json::Value,
+    /// Replaces the stored credentials; left out, they are kept.
+    pub credentials: Option<TargetCredentials>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
 pub struct TargetResponse {
     pub id: Uuid,
     pub name: String,
     pub target_type: String,
     pub region: Option<String>,
+    pub config: serde_json::Value,
+    /// `unknown`, `connected`, `degraded` or `disconnected`.
+    pub status: String,
+    /// What the last check found, or why it failed.
+    pub status_message: Option<String>,
+    pub checked_at: Option<String>,
+    pub has_credentials: bool,
+}
+
+impl From<Target> for TargetResponse {
+    fn from(t: Target) -> Self {
+        Self {
+            id: t.id,
+            has_credentials: t.credentials.is_some(),
+            name: t.name,
+            target_type: t.target_type,
+            region: t.region,
+            config: t.config,
+            status: t.status,
+            status_message: t.status_message,
+            checked_at: t.checked_at.map(|t| t.to_rfc3339()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateServiceSpecRequest {
+    pub spec: serde_json::Value,
+    pub author: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SpecVersionResponse {
+    pub version: i32,
+    pub spec: serde_json::Value,
+    pub created_by: Option<String>,
+    pub created_at: String,
+}
+
+impl From<ServiceSpecVersion> for SpecVersionResponse {
+    fn from(v: ServiceSpecVersion) -> Self {
+        Self {
+            version: v.version,
+            spec: v.spec,
+            created_by: v.created_by,
+            created_at: v.created_at.to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SpecDiffQuery {
+    pub from: i32,
+    pub to: i32,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeploymentDiffQuery {
+    /// Deployment to compare against; the previous successful deployment
+    /// of the service to the same environment when omitted.
+    pub from: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SpecChange {
+    /// Dotted path of the changed field, e.g. `resources.cpu_limit`.
+    pub path: String,
+    /// `added`, `removed` or `changed`.
+    pub change: &'static str,
+    pub old: Option<serde_json::Value>,
+    pub new: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SpecDiffResponse {
+    pub from: i32,
+    pub to: i32,
+    pub changes: Vec<SpecChange>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RedeployRequest {
+    pub environment_id: Uuid,
+    /// Branch the spec comes from, checked against the environment's
+    /// branch restrictions.
+    pub branch: Option<String>,
+    /// Deploy even if the environment is frozen, for this reason.
+    pub override_reason: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct GenerateReleaseNotesRequest {
+    /// Post to the Slack/GitHub targets configured on the environment.
+    #[serde(default)]
+    pub publish: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DeploymentResponse {
+    pub id: Uuid,
+    pub service_id: Uuid,
+    pub environment_id: Uuid,
+    pub version: String,
+    pub spec_version: Option<i32>,
+    pub status: String,
+    /// The deployment in the previous environment this was promoted from.
+    pub promoted_from: Option<Uuid>,
+    /// Who deployed through a freeze window, and why.
+    pub freeze_override: Option<serde_json::Value>,
+    pub branch: Option<String>,
+}
+
+impl From<Deployment> for DeploymentResponse {
+    fn from(d: Deployment) -> Self {
+        Self {
+            id: d.id,
+            service_id: d.service_id,
+            environment_id: d.environment_id,
+            version: d.version,
+            spec_version: d.spec_version,
+            status: d.status,
+            promoted_from: d.promoted_from,
+            freeze_override: d.freeze_override,
+            branch: d.branch,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetPromotionChainRequest {
+    /// Environment IDs in promotion order, e.g. dev, staging, production.
+    /// An empty list removes the chain.
+    pub environments: Vec<Uuid>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PromotionStage {
+    pub environment_id: Uuid,
+    pub environment_name: String,
+    pub requires_approval: bool,
+    /// The last successful deployment in this environment.
+    pub current: Option<DeploymentResponse>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PromoteServiceRequest {
+    /// Environment to promote into. The release is taken from the
+    /// environment before it in the service's promotion chain.
+    pub environment_id: Uuid,
+    /// Promote even if the environment is frozen, for this reason.
+    pub override_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListServicesQuery {
+    pub name: Option<String>,
+    /// Only services owned by this team.
+    pub owner_team: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ServiceResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub image: Option<String>,
     pub status: String,
+    /// Team that owns the service and is paged when it fails.
+    pub owner_team: Option<String>,
+    pub repository_url: Option<String>,
+    pub runbook_url: Option<String>,
+    pub dashboard_urls: Vec<String>,
+    /// How critical the service is, from 1 (most) to 4 (least).
+    pub tier: Option<i32>,
+}
+
+impl From<Service> for ServiceResponse {
+    fn from(s: Service) -> Self {
+        Self {
+            id: s.id,
+            name: s.name,
+            image: s.image,
+            status: s.status,
+            owner_team: s.owner_team,
+            repository_url: s.repository_url,
+            runbook_url: s.runbook_url,
+            dashboard_urls: s.dashboard_urls,
+            tier: s.tier,
+        }
+    }
+}
+
+/// A service's catalog entry; fields left out are cleared.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateServiceCatalogRequest {
+    pub owner_team: Option<String>,
+    /// Link to the service's source repository.
+    pub repository_url: Option<String>,
+    pub runbook_url: Option<String>,
+    #[serde(default)]
+    pub dashboard_urls: Vec<String>,
+    /// 1 (most critical) to 4 (least).
+    pub tier: Option<i32>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListDeploymentsQuery {
+    pub service_id: Uuid,
+    pub environment_id: Option<Uuid>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateDeploymentRequest {
+    pub service_id: Uuid,
+    pub environment_id: Uuid,
+    /// Deploy this image instead of the one in the current spec. Recorded
+    /// as a new spec version.
+    pub image: Option<String>,
+    /// Branch the release comes from, checked against the environment's
+    /// branch restrictions.
+    pub branch: Option<String>,
+    /// Deploy even if the environment is frozen, for this reason.
+    pub override_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PreviewDeploymentRequest {
+    pub service_id: Uuid,
+    pub environment_id: Uuid,
+    /// Preview this image instead of the one in the current spec.
+    pub image: Option<String>,
+    /// Branch to check out manifest sources with a repository at.
+    pub branch: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct RollbackRequest {
+    /// Redeploy the last good deployment of this version instead of
+    /// flipping back to the previous one.
+    pub to: Option<String>,
+    /// Redeploy even if the environment is frozen, for this reason.
+    pub override_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateFreezeWindowRequest {
+    pub name: String,
+    pub reason: Option<String>,
+    /// Start of a one-off window; give `ends_at` with it.
+    pub starts_at: Option<DateTime<Utc>>,
+    pub ends_at: Option<DateTime<Utc>>,
+    /// Five-field cron expression (UTC) starting a recurring window; give
+    /// `duration_minutes` with it.
+    pub cron: Option<String>,
+    pub duration_minutes: Option<i32>,
+    /// `queue` (default) holds deployments until the window ends,
+    /// `reject` refuses them.
+    pub policy: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FreezeWindowResponse {
+    pub id: Uuid,
+    pub environment_id: Uuid,
+    pub name: String,
+    pub reason: Option<String>,
+    pub starts_at: Option<DateTime<Utc>>,
+    pub ends_at: Option<DateTime<Utc>>,
+    pub cron: Option<String>,
+    pub duration_minutes: Option<i32>,
+    pub policy: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<FreezeWindow> for FreezeWindowResponse {
+    fn from(w: FreezeWindow) -> Self {
+        Self {
+            id: w.id,
+            environment_id: w.environment_id,
+            name: w.name,
+            reason: w.reason,
+            starts_at: w.starts_at,
+            ends_at: w.ends_at,
+            cron: w.cron,
+            duration_minutes: w.duration_minutes,
+            policy: w.policy,
+            created_at: w.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct EnvironmentFreezeResponse {
+    pub environment_id: Uuid,
+    pub frozen: bool,
+    /// When the last freeze in effect ends.
+    pub until: Option<DateTime<Utc>>,
+    pub freezes: Vec<FreezeOccurrence>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FreezeCalendarQuery {
+    /// Defaults to now.
+    pub from: Option<DateTime<Utc>>,
+    /// Defaults to 30 days after `from`; at most a year after it.
+    pub to: Option<DateTime<Utc>>,
+    pub environment_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct OverrideFreezeRequest {
+    pub reason: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetProtectionRequest {
+    /// Users who may review deployments.
+    #[serde(default)]
+    pub reviewers: Vec<Uuid>,
+    /// Roles (`viewer`, `member`, `admin`, `owner`) whose holders, or
+    /// holders of a higher role, may review deployments.
+    #[serde(default)]
+    pub reviewer_roles: Vec<String>,
+    /// Approvals a deployment needs. Defaults to 1 when reviewers are
+    /// named, otherwise 0.
+    pub required_approvals: Option<i32>,
+    /// Minutes to wait after approval before rolling out.
+    #[serde(default)]
+    pub wait_minutes: i32,
+    /// Branches deployments must come from, as glob patterns such as
+    /// `main` or `release/*`. Empty allows any.
+    #[serde(default)]
+    pub branches: Vec<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ProtectionResponse {
+    pub environment_id: Uuid,
+    pub reviewers: Vec<Uuid>,
+    pub reviewer_roles: Vec<String>,
+    pub required_approvals: i32,
+    pub wait_minutes: i32,
+    pub branches: Vec<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<EnvironmentProtection> for ProtectionResponse {
+    fn from(p: EnvironmentProtection) -> Self {
+        Self {
+            environment_id: p.environment_id,
+            reviewers: p.reviewers,
+            reviewer_roles: p.reviewer_roles,
+            required_approvals: p.required_approvals,
+            wait_minutes: p.wait_minutes,
+            branches: p.branches,
+            updated_at: p.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct ReviewRequest {
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReviewResponse {
+    pub id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub api_key_id: Option<Uuid>,
+    pub approved: bool,
+    pub comment: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<DeploymentReview> for ReviewResponse {
+    fn from(r: DeploymentReview) -> Self {
+        Self {
+            id: r.id,
+            user_id: r.user_id,
+            api_key_id: r.api_key_id,
+            approved: r.approved,
+            comment: r.comment,
+            created_at: r.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PendingApprovalResponse {
+    pub deployment: DeploymentResponse,
+    pub service_name: String,
+    pub environment_name: String,
+    pub approvals: i32,
+    pub required_approvals: i32,
+    pub reviews: Vec<ReviewResponse>,
+    /// Whether the caller may review it.
+    pub can_review: bool,
 }
 
 // ============================================================================
 // Environment handlers
 // ============================================================================
 
+#[utoipa::path(
+    get,
+    path = "/environments",
+    responses((status = 200, description = "Environments", body = Vec<EnvironmentResponse>))
+)]
 async fn list_environments(
     State(state): State<AppState>,
+    auth: AuthContext,
 ) -> Result<Json<Vec<EnvironmentResponse>>, ApiError> {
-    let tenant = state
-        .tenant_repo
-        .get_by_slug("default")
-        .await
-        .map_err(|_| ApiError::Internal("No default tenant".to_string()))?;
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::DeploymentRead)
+        .await?;
 
     let envs = state
         .deployment_repo
@@ -107,15 +672,20 @@ async fn list_environments(
     Ok(Json(response))
 }
 
+#[utoipa::path(
+    post,
+    path = "/environments",
+    request_body = CreateEnvironmentRequest,
+    responses((status = 200, description = "The environment", body = EnvironmentResponse))
+)]
 async fn create_environment(
     State(state): State<AppState>,
+    auth: AuthContext,
     Json(req): Json<CreateEnvironmentRequest>,
 ) -> Result<Json<EnvironmentResponse>, ApiError> {
-    let tenant = state
-        .tenant_repo
-        .get_by_slug("default")
-        .await
-        .map_err(|_| ApiError::Internal("No default tenant".to_string()))?;
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::DeploymentWrite)
+        .await?;
 
     let env = state
         .deployment_repo
@@ -123,7 +693,10 @@ async fn create_environment(
             ResourceId::from_uuid(tenant.id),
             ResourceId::from_uuid(req.target_id),
             &req.name,
-            serde_json::json!({}),
+            serde_json::json!({
+                "requires_approval": req.requires_approval,
+                "auto_deploy": req.auto_deploy,
+            }),
         )
         .await?;
 
@@ -144,14 +717,23 @@ async fn create_environment(
     }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/environments/{id}",
+    params(("id" = Uuid, Path, description = "Environment ID")),
+    responses((status = 200, description = "The environment", body = EnvironmentResponse))
+)]
 async fn get_environment(
     State(state): State<AppState>,
+    auth: AuthContext,
     Path(id): Path<Uuid>,
 ) -> Result<Json<EnvironmentResponse>, ApiError> {
     let env = state
         .deployment_repo
         .get_environment(ResourceId::from_uuid(id))
         .await?;
+    auth.require(&state, env.tenant_id, Permission::DeploymentRead)
+        .await?;
 
     let target = state
         .deployment_repo
@@ -169,106 +751,2343 @@ async fn get_environment(
     }))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/environments/{id}",
+    params(("id" = Uuid, Path, description = "Environment ID")),
+    responses((status = 200, description = "Deleted", body = Object, example = json!({"deleted": true})))
+)]
 async fn delete_environment(
     State(state): State<AppState>,
+    auth: AuthContext,
     Path(id): Path<Uuid>,
-) -> Result<Json<serde_json::Value>, ApiError> {
+) -> Result<(AuditBefore, Json<serde_json::Value>), ApiError> {
+    let env = state
+        .deployment_repo
+        .get_environment(ResourceId::from_uuid(id))
+        .await?;
+    auth.require(&state, env.tenant_id, Permission::DeploymentWrite)
+        .await?;
     state
         .deployment_repo
         .delete_environment(ResourceId::from_uuid(id))
         .await?;
 
-    Ok(Json(serde_json::json!({"deleted": true})))
+    Ok((
+        AuditBefore::of(&env),
+        Json(serde_json::json!({"deleted": true})),
+    ))
+}
+
+// ============================================================================
+// Freeze window handlers
+// ============================================================================
+
+/// Load an environment, checking the caller holds `permission` in its tenant.
+async fn authorized_environment(
+    state: &AppState,
+    auth: &AuthContext,
+    id: Uuid,
+    permission: Permission,
+) -> Result<Environment, ApiError> {
+    let environment = state
+        .deployment_repo
+        .get_environment(ResourceId::from_uuid(id))
+        .await?;
+    auth.require(state, environment.tenant_id, permission)
+        .await?;
+    Ok(environment)
+}
+
+#[utoipa::path(
+    get,
+    path = "/environments/{id}/freeze-windows",
+    params(("id" = Uuid, Path, description = "Environment ID")),
+    responses((status = 200, description = "Freeze windows", body = Vec<FreezeWindowResponse>))
+)]
+async fn list_freeze_windows(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<FreezeWindowResponse>>, ApiError> {
+    let environment = authorized_environment(&state, &auth, id, Permission::DeploymentRead).await?;
+    let windows = state
+        .deployment_repo
+        .list_freeze_windows(
+            ResourceId::from_uuid(environment.tenant_id),
+            Some(ResourceId::from_uuid(environment.id)),
+        )
+        .await?;
+    Ok(Json(windows.into_iter().map(Into::into).collect()))
+}
+
+/// Freeze an environment for a date range, or repeatedly on a cron schedule.
+#[utoipa::path(
+    post,
+    path = "/environments/{id}/freeze-windows",
+    params(("id" = Uuid, Path, description = "Environment ID")),
+    request_body = CreateFreezeWindowRequest,
+    responses((status = 200, description = "The freeze window", body = FreezeWindowResponse))
+)]
+async fn create_freeze_window(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    Json(req): Json<CreateFreezeWindowRequest>,
+) -> Result<Json<FreezeWindowResponse>, ApiError> {
+    let environment =
+        authorized_environment(&state, &auth, id, Permission::DeploymentWrite).await?;
+    if req.name.trim().is_empty() {
+        return Err(ApiError::BadRequest("Name is required".to_string()));
+    }
+    let policy = req
+        .policy
+        .unwrap_or_else(|| freeze::POLICY_QUEUE.to_string());
+    if policy != freeze::POLICY_QUEUE && policy != freeze::POLICY_REJECT {
+        return Err(ApiError::BadRequest(format!(
+            "Unknown policy '{}'; use '{}' or '{}'",
+            policy,
+            freeze::POLICY_QUEUE,
+            freeze::POLICY_REJECT
+        )));
+    }
+
+    let window = FreezeWindow {
+        id: Uuid::now_v7(),
+        tenant_id: environment.tenant_id,
+        environment_id: environment.id,
+        name: req.name.trim().to_string(),
+        reason: req.reason,
+        starts_at: req.starts_at,
+        ends_at: req.ends_at,
+        cron: req.cron.map(|c| c.trim().to_string()),
+        duration_minutes: req.duration_minutes,
+        policy,
+        created_by: auth.user_id,
+        created_at: Utc::now(),
+    };
+    freeze::schedule(&window).map_err(|e| ApiError::BadRequest(e.to_string()))?;
+
+    let window = state.deployment_repo.create_freeze_window(&window).await?;
+    Ok(Json(window.into()))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/environments/{id}/freeze-windows/{window_id}",
+    params(("id" = Uuid, Path, description = "Environment ID"), ("window_id" = Uuid, Path, description = "Freeze window ID")),
+    responses((status = 200, description = "Deleted", body = Object, example = json!({"deleted": true})))
+)]
+async fn delete_freeze_window(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((id, window_id)): Path<(Uuid, Uuid)>,
+) -> Result<(AuditBefore, Json<serde_json::Value>), ApiError> {
+    authorized_environment(&state, &auth, id, Permission::DeploymentWrite).await?;
+    let window = state
+        .deployment_repo
+        .get_freeze_window(ResourceId::from_uuid(window_id))
+        .await?;
+    if window.environment_id != id {
+        return Err(ApiError::NotFound(format!(
+            "Freeze window {} not found",
+            window_id
+        )));
+    }
+    state
+        .deployment_repo
+        .delete_freeze_window(ResourceId::from_uuid(window_id))
+        .await?;
+
+    Ok((
+        AuditBefore::of(&window),
+        Json(serde_json::json!({"deleted": true})),
+    ))
+}
+
+/// Whether an environment is frozen now, and until when.
+#[utoipa::path(
+    get,
+    path = "/environments/{id}/freeze",
+    params(("id" = Uuid, Path, description = "Environment ID")),
+    responses((status = 200, description = "Freezes in effect", body = EnvironmentFreezeResponse))
+)]
+async fn get_environment_freeze(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<EnvironmentFreezeResponse>, ApiError> {
+    let environment = authorized_environment(&state, &auth, id, Permission::DeploymentRead).await?;
+    let freezes = freeze::active(&state.deployment_repo, &environment, Utc::now()).await?;
+    Ok(Json(EnvironmentFreezeResponse {
+        environment_id: environment.id,
+        frozen: !freezes.is_empty(),
+        until: freeze::frozen_until(&freezes),
+        freezes,
+    }))
+}
+
+/// Maintenance calendar: every freeze in the tenant's environments
+/// between two times.
+#[utoipa::path(
+    get,
+    path = "/freeze-calendar",
+    params(FreezeCalendarQuery),
+    responses((status = 200, description = "Freezes, in order of their start", body = Vec<FreezeOccurrence>))
+)]
+async fn get_freeze_calendar(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Query(query): Query<FreezeCalendarQuery>,
+) -> Result<Json<Vec<FreezeOccurrence>>, ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::DeploymentRead)
+        .await?;
+
+    let from = query.from.unwrap_or_else(Utc::now);
+    let to = query.to.unwrap_or(from + Duration::days(30));
+    if to <= from || to - from > Duration::days(366) {
+        return Err(ApiError::BadRequest(
+            "'to' must be after 'from' and at most a year later".to_string(),
+        ));
+    }
+
+    let windows = state
+        .deployment_repo
+        .list_freeze_windows(
+            ResourceId::from_uuid(tenant.id),
+            query.environment_id.map(ResourceId::from_uuid),
+        )
+        .await?;
+    Ok(Json(freeze::occurrences(&windows, from, to)))
+}
+
+// ============================================================================
+// Protection rule handlers
+// ============================================================================
+
+#[utoipa::path(
+    get,
+    path = "/environments/{id}/protection",
+    params(("id" = Uuid, Path, description = "Environment ID")),
+    responses((status = 200, description = "Protection rules", body = ProtectionResponse))
+)]
+async fn get_environment_protection(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ProtectionResponse>, ApiError> {
+    let environment = authorized_environment(&state, &auth, id, Permission::DeploymentRead).await?;
+    let protection = state
+        .deployment_repo
+        .get_environment_protection(ResourceId::from_uuid(id))
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("{} is not protected", environment.name)))?;
+    Ok(Json(protection.into()))
+}
+
+/// Protect an environment: require reviews, a wait timer after approval,
+/// or deployments from particular branches.
+#[utoipa::path(
+    put,
+    path = "/environments/{id}/protection",
+    params(("id" = Uuid, Path, description = "Environment ID")),
+    request_body = SetProtectionRequest,
+    responses((status = 200, description = "Protection rules", body = ProtectionResponse))
+)]
+async fn set_environment_protection(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    Json(req): Json<SetProtectionRequest>,
+) -> Result<(AuditBefore, Json<ProtectionResponse>), ApiError> {
+    let environment =
+        authorized_environment(&state, &auth, id, Permission::DeploymentProtect).await?;
+
+    for role in &req.reviewer_roles {
+        if Role::parse(role).is_none() {
+            return Err(ApiError::BadRequest(format!("Unknown role '{}'", role)));
+        }
+    }
+    for reviewer in &req.reviewers {
+        state
+            .organization_repo
+            .get_user(ResourceId::from_uuid(*reviewer))
+            .await
+            .map_err(|_| ApiError::BadRequest(format!("Unknown reviewer {}", reviewer)))?;
+    }
+    let designated = !req.reviewers.is_empty() || !req.reviewer_roles.is_empty();
+    let required_approvals = req.required_approvals.unwrap_or(designated as i32);
+    if !(0..=protection::MAX_APPROVALS).contains(&required_approvals) {
+        return Err(ApiError::BadRequest(format!(
+            "required_approvals must be between 0 and {}",
+            protection::MAX_APPROVALS
+        )));
+    }
+    if designated && required_approvals == 0 {
+        return Err(ApiError::BadRequest(
+            "Reviewers are named but no approvals are required".to_string(),
+        ));
+    }
+    if !(0..=protection::MAX_WAIT_MINUTES).contains(&req.wait_minutes) {
+        return Err(ApiError::BadRequest(format!(
+            "wait_minutes must be between 0 and {}",
+            protection::MAX_WAIT_MINUTES
+        )));
+    }
+    let branches: Vec<String> = req.branches.iter().map(|b| b.trim().to_string()).collect();
+    if branches.iter().any(String::is_empty) {
+        return Err(ApiError::BadRequest(
+            "Branch patterns must not be empty".to_string(),
+        ));
+    }
+
+    let before = state
+        .deployment_repo
+        .get_environment_protection(ResourceId::from_uuid(id))
+        .await?;
+    let protection = state
+        .deployment_repo
+        .set_environment_protection(&EnvironmentProtection {
+            environment_id: environment.id,
+            tenant_id: environment.tenant_id,
+            reviewers: req.reviewers,
+            reviewer_roles: req
+                .reviewer_roles
+                .iter()
+                .map(|r| r.trim().to_lowercase())
+                .collect(),
+            required_approvals,
+            wait_minutes: req.wait_minutes,
+            branches,
+            updated_by: auth.user_id,
+            updated_at: Utc::now(),
+        })
+        .await?;
+    Ok((AuditBefore::of(&before), Json(protection.into())))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/environments/{id}/protection",
+    params(("id" = Uuid, Path, description = "Environment ID")),
+    responses((status = 200, description = "Deleted", body = Object, example = json!({"deleted": true})))
+)]
+async fn delete_environment_protection(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<(AuditBefore, Json<serde_json::Value>), ApiError> {
+    authorized_environment(&state, &auth, id, Permission::DeploymentProtect).await?;
+    let before = state
+        .deployment_repo
+        .get_environment_protection(ResourceId::from_uuid(id))
+        .await?;
+    state
+        .deployment_repo
+        .delete_environment_protection(ResourceId::from_uuid(id))
+        .await?;
+    Ok((
+        AuditBefore::of(&before),
+        Json(serde_json::json!({"deleted": true})),
+    ))
 }
 
 // ============================================================================
 // Target handlers
 // ============================================================================
 
+#[utoipa::path(
+    get,
+    path = "/targets",
+    responses((status = 200, description = "Deployment targets", body = Vec<TargetResponse>))
+)]
 async fn list_targets(
     State(state): State<AppState>,
+    auth: AuthContext,
 ) -> Result<Json<Vec<TargetResponse>>, ApiError> {
-    let tenant = state
-        .tenant_repo
-        .get_by_slug("default")
-        .await
-        .map_err(|_| ApiError::Internal("No default tenant".to_string()))?;
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::DeploymentRead)
+        .await?;
 
     let targets = state
         .deployment_repo
         .list_targets(ResourceId::from_uuid(tenant.id))
         .await?;
 
-    let response: Vec<TargetResponse> = targets
-        .into_iter()
-        .map(|t| TargetResponse {
-            id: t.id,
-            name: t.name,
-            target_type: t.target_type,
-            region: t.region,
-            status: t.status,
-        })
-        .collect();
-
-    Ok(Json(response))
+    Ok(Json(
+        targets.into_iter().map(TargetResponse::from).collect(),
+    ))
 }
 
+/// Register a target. Its credentials are checked first: a target that
+/// cannot be reached with them is refused, one that lacks permissions is
+/// stored as `degraded`.
+#[utoipa::path(
+    post,
+    path = "/targets",
+    request_body = CreateTargetRequest,
+    responses((status = 200, description = "The target", body = TargetResponse))
+)]
 async fn create_target(
     State(state): State<AppState>,
+    auth: AuthContext,
     Json(req): Json<CreateTargetRequest>,
 ) -> Result<Json<TargetResponse>, ApiError> {
-    let tenant = state
-        .tenant_repo
-        .get_by_slug("default")
-        .await
-        .map_err(|_| ApiError::Internal("No default tenant".to_string()))?;
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::DeploymentWrite)
+        .await?;
+
+    let kind: TargetKind = req.target_type.parse().map_err(ApiError::BadRequest)?;
+    if matches!(kind, TargetKind::Fly | TargetKind::Aws) && req.credentials.is_none() {
+        return Err(ApiError::BadRequest(format!(
+            "A {} target needs credentials",
+            kind
+        )));
+    }
+    let config = target_config(req.config)?;
+    let sealed = seal_credentials(&state, kind, req.credentials.as_ref())?;
+    let outcome = connect(
+        kind,
+        req.region.as_deref(),
+        &config,
+        req.credentials.as_ref(),
+    )
+    .await?;
 
     let target = state
         .deployment_repo
         .create_target(
             ResourceId::from_uuid(tenant.id),
             &req.name,
-            &req.target_type,
+            kind.as_str(),
             req.region.as_deref(),
-            req.config,
+            config,
+            sealed,
+        )
+        .await?;
+    let target = state
+        .deployment_repo
+        .update_target_status(
+            ResourceId::from_uuid(target.id),
+            outcome.status.as_str(),
+            outcome.message.as_deref(),
         )
         .await?;
 
-    Ok(Json(TargetResponse {
-        id: target.id,
-        name: target.name,
-        target_type: target.target_type,
-        region: target.region,
-        status: target.status,
-    }))
+    Ok(Json(target.into()))
 }
 
+#[utoipa::path(
+    get,
+    path = "/targets/{id}",
+    params(("id" = Uuid, Path, description = "Target ID")),
+    responses((status = 200, description = "The target", body = TargetResponse))
+)]
 async fn get_target(
     State(state): State<AppState>,
+    auth: AuthContext,
     Path(id): Path<Uuid>,
 ) -> Result<Json<TargetResponse>, ApiError> {
     let target = state
         .deployment_repo
         .get_target(ResourceId::from_uuid(id))
         .await?;
+    auth.require(&state, target.tenant_id, Permission::DeploymentRead)
+        .await?;
 
-    Ok(Json(TargetResponse {
-        id: target.id,
-        name: target.name,
-        target_type: target.target_type,
-        region: target.region,
-        status: target.status,
-    }))
+    Ok(Json(target.into()))
 }
 
-async fn delete_target(
+/// Change a target, e.g. to rotate its credentials. The target is checked
+/// with the new settings before they are stored.
+#[utoipa::path(
+    put,
+    path = "/targets/{id}",
+    params(("id" = Uuid, Path, description = "Target ID")),
+    request_body = UpdateTargetRequest,
+    responses((status = 200, description = "The target", body = TargetResponse))
+)]
+async fn update_target(
     State(state): State<AppState>,
+    auth: AuthContext,
     Path(id): Path<Uuid>,
-) -> Result<Json<serde_json::Value>, ApiError> {
+    Json(req): Json<UpdateTargetRequest>,
+) -> Result<(AuditBefore, Json<TargetResponse>), ApiError> {
+    let before = state
+        .deployment_repo
+        .get_target(ResourceId::from_uuid(id))
+        .await?;
+    auth.require(&state, before.tenant_id, Permission::DeploymentWrite)
+        .await?;
+
+    let kind = targets::target_kind(&before).map_err(ApiError::BadRequest)?;
+    let config = target_config(req.config)?;
+    let sealed = seal_credentials(&state, kind, req.credentials.as_ref())?;
+    let credentials = match req.credentials {
+        Some(credentials) => Some(credentials),
+        None => targets::stored_credentials(state.credential_cipher.as_deref(), &before)
+            .map_err(ApiError::BadRequest)?,
+    };
+    let outcome = connect(kind, req.region.as_deref(), &config, credentials.as_ref()).await?;
+
     state
         .deployment_repo
-        .delete_target(ResourceId::from_uuid(id))
+        .update_target(
+            ResourceId::from_uuid(id),
+            &req.name,
+            req.region.as_deref(),
+            config,
+            sealed,
+        )
+        .await?;
+    let target = state
+        .deployment_repo
+        .update_target_status(
+            ResourceId::from_uuid(id),
+            outcome.status.as_str(),
+            outcome.message.as_deref(),
+        )
         .await?;
 
-    Ok(Json(serde_json::json!({"deleted": true})))
+    Ok((AuditBefore::of(&before), Json(target.into())))
+}
+
+/// Check a target's connection now rather than waiting for the monitor.
+#[utoipa::path(
+    post,
+    path = "/targets/{id}/check",
+    params(("id" = Uuid, Path, description = "Target ID")),
+    responses((status = 200, description = "The target with its new status", body = TargetResponse))
+)]
+async fn check_target(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<TargetResponse>, ApiError> {
+    let target = state
+        .deployment_repo
+        .get_target(ResourceId::from_uuid(id))
+        .await?;
+    auth.require(&state, target.tenant_id, Permission::DeploymentRead)
+        .await?;
+
+    let target = targets::refresh(
+        &state.deployment_repo,
+        state.credential_cipher.as_deref(),
+        state.event_bus.as_ref(),
+        target,
+    )
+    .await?;
+    Ok(Json(target.into()))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/targets/{id}",
+    params(("id" = Uuid, Path, description = "Target ID")),
+    responses((status = 200, description = "Deleted", body = Object, example = json!({"deleted": true})))
+)]
+async fn delete_target(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<(AuditBefore, Json<serde_json::Value>), ApiError> {
+    let target = state
+        .deployment_repo
+        .get_target(ResourceId::from_uuid(id))
+        .await?;
+    auth.require(&state, target.tenant_id, Permission::DeploymentWrite)
+        .await?;
+    state
+        .deployment_repo
+        .delete_target(ResourceId::from_uuid(id))
+        .await?;
+
+    Ok((
+        AuditBefore::of(&target),
+        Json(serde_json::json!({"deleted": true})),
+    ))
+}
+
+/// A target's plain settings, which must be an object.
+pub(crate) fn target_config(config: serde_json::Value) -> Result<serde_json::Value, ApiError> {
+    match config {
+        serde_json::Value::Null => Ok(serde_json::json!({})),
+        config @ serde_json::Value::Object(_) => Ok(config),
+        _ => Err(ApiError::BadRequest(
+            "Target config must be an object".to_string(),
+        )),
+    }
+}
+
+/// Encrypt credentials for storage, checking they fit the target.
+fn seal_credentials(
+    state: &AppState,
+    kind: TargetKind,
+    credentials: Option<&TargetCredentials>,
+) -> Result<Option<Vec<u8>>, ApiError> {
+    let Some(credentials) = credentials else {
+        return Ok(None);
+    };
+    if credentials.kind() != kind {
+        return Err(ApiError::BadRequest(format!(
+            "{} credentials do not fit a {} target",
+            credentials.kind(),
+            kind
+        )));
+    }
+    let cipher = state.credential_cipher.as_ref().ok_or_else(|| {
+        ApiError::BadRequest(
+            "Storing credentials needs BUILDIT_ENCRYPTION_KEY to be set".to_string(),
+        )
+    })?;
+    cipher
+        .seal(credentials)
+        .map(Some)
+        .map_err(ApiError::Internal)
+}
+
+/// Check a target about to be stored, refusing it when it cannot be
+/// reached.
+async fn connect(
+    kind: TargetKind,
+    region: Option<&str>,
+    config: &serde_json::Value,
+    credentials: Option<&TargetCredentials>,
+) -> Result<TargetCheck, ApiError> {
+    let outcome = targets::check(kind, region, config, credentials).await;
+    if outcome.status == TargetStatus::Disconnected {
+        return Err(ApiError::BadRequest(format!(
+            "Cannot connect to the target: {}",
+            outcome.message.unwrap_or_default()
+        )));
+    }
+    Ok(outcome)
+}
+
+// ============================================================================
+// Service spec handlers
+// ============================================================================
+
+#[utoipa::path(
+    get,
+    path = "/services/{id}/spec",
+    params(("id" = Uuid, Path, description = "Service ID")),
+    responses((status = 200, description = "The current spec", body = SpecVersionResponse))
+)]
+async fn get_service_spec(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<SpecVersionResponse>, ApiError> {
+    authorized_service(&state, &auth, id, Permission::DeploymentRead).await?;
+    let latest = state
+        .deployment_repo
+        .list_service_spec_versions(ResourceId::from_uuid(id))
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| ApiError::NotFound(format!("No spec recorded for service {}", id)))?;
+
+    Ok(Json(latest.into()))
+}
+
+#[utoipa::path(
+    put,
+    path = "/services/{id}/spec",
+    params(("id" = Uuid, Path, description = "Service ID")),
+    request_body = UpdateServiceSpecRequest,
+    responses((status = 200, description = "The new spec version", body = SpecVersionResponse))
+)]
+async fn update_service_spec(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    Json(req): Json<UpdateServiceSpecRequest>,
+) -> Result<(AuditBefore, Json<SpecVersionResponse>), ApiError> {
+    if !req.spec.is_object() {
+        return Err(ApiError::BadRequest(
+            "Spec must be a JSON object".to_string(),
+        ));
+    }
+    authorized_service(&state, &auth, id, Permission::DeploymentWrite).await?;
+    let previous = state
+        .deployment_repo
+        .list_service_spec_versions(ResourceId::from_uuid(id))
+        .await?
+        .into_iter()
+        .next()
+        .map(|v| v.spec);
+
+    let version = state
+        .deployment_repo
+        .update_service_spec(ResourceId::from_uuid(id), req.spec, req.author.as_deref())
+        .await?;
+
+    Ok((AuditBefore::of(&previous), Json(version.into())))
+}
+
+#[utoipa::path(
+    get,
+    path = "/services/{id}/spec/versions",
+    params(("id" = Uuid, Path, description = "Service ID")),
+    responses((status = 200, description = "Spec versions, newest first", body = Vec<SpecVersionResponse>))
+)]
+async fn list_spec_versions(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<SpecVersionResponse>>, ApiError> {
+    authorized_service(&state, &auth, id, Permission::DeploymentRead).await?;
+    let versions = state
+        .deployment_repo
+        .list_service_spec_versions(ResourceId::from_uuid(id))
+        .await?;
+
+    Ok(Json(versions.into_iter().map(Into::into).collect()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/services/{id}/spec/versions/{version}",
+    params(("id" = Uuid, Path, description = "Service ID"), ("version" = i32, Path, description = "Spec version")),
+    responses((status = 200, description = "The spec version", body = SpecVersionResponse))
+)]
+async fn get_spec_version(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((id, version)): Path<(Uuid, i32)>,
+) -> Result<Json<SpecVersionResponse>, ApiError> {
+    authorized_service(&state, &auth, id, Permission::DeploymentRead).await?;
+    let spec = state
+        .deployment_repo
+        .get_service_spec_version(ResourceId::from_uuid(id), version)
+        .await?;
+
+    Ok(Json(spec.into()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/services/{id}/spec/diff",
+    params(("id" = Uuid, Path, description = "Service ID"), SpecDiffQuery),
+    responses((status = 200, description = "Field-level changes", body = SpecDiffResponse))
+)]
+async fn diff_spec_versions(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    Query(query): Query<SpecDiffQuery>,
+) -> Result<Json<SpecDiffResponse>, ApiError> {
+    authorized_service(&state, &auth, id, Permission::DeploymentRead).await?;
+    let service_id = ResourceId::from_uuid(id);
+    let from = state
+        .deployment_repo
+        .get_service_spec_version(service_id, query.from)
+        .await?;
+    let to = state
+        .deployment_repo
+        .get_service_spec_version(service_id, query.to)
+        .await?;
+
+    let mut changes = Vec::new();
+    diff_json("", &from.spec, &to.spec, &mut changes);
+
+    Ok(Json(SpecDiffResponse {
+        from: query.from,
+        to: query.to,
+        changes,
+    }))
+}
+
+/// Queue a deployment of a stored spec version, bypassing git.
+#[utoipa::path(
+    post,
+    path = "/services/{id}/spec/versions/{version}/redeploy",
+    params(("id" = Uuid, Path, description = "Service ID"), ("version" = i32, Path, description = "Spec version")),
+    request_body = RedeployRequest,
+    responses((status = 200, description = "The queued deployment", body = DeploymentResponse))
+)]
+async fn redeploy_spec_version(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((id, version)): Path<(Uuid, i32)>,
+    Json(req): Json<RedeployRequest>,
+) -> Result<Json<DeploymentResponse>, ApiError> {
+    let service = authorized_service(&state, &auth, id, Permission::DeploymentDeploy).await?;
+    let environment = state
+        .deployment_repo
+        .get_environment(ResourceId::from_uuid(req.environment_id))
+        .await?;
+    if environment.tenant_id != service.tenant_id {
+        return Err(ApiError::BadRequest(
+            "Environment belongs to a different tenant".to_string(),
+        ));
+    }
+
+    let protection = check_protection(&state, &environment, req.branch.as_deref()).await?;
+    let freeze_override =
+        check_freeze(&state, &auth, &environment, req.override_reason.as_deref()).await?;
+
+    let spec = state
+        .deployment_repo
+        .get_service_spec_version(ResourceId::from_uuid(id), version)
+        .await?;
+
+    let label = version_label(&spec);
+    let mut deployment = state
+        .deployment_repo
+        .create_deployment(
+            ResourceId::from_uuid(service.tenant_id),
+            ResourceId::from_uuid(service.id),
+            ResourceId::from_uuid(environment.id),
+            &label,
+            Some(version),
+            spec.spec,
+        )
+        .await?;
+
+    record_branch(&state, &mut deployment, req.branch).await?;
+    submit_deployment(
+        &state,
+        &mut deployment,
+        &environment,
+        protection.as_ref(),
+        freeze_override,
+    )
+    .await?;
+    Ok(Json(deployment.into()))
+}
+
+/// Load a service, checking the caller holds `permission` in its tenant.
+async fn authorized_service(
+    state: &AppState,
+    auth: &AuthContext,
+    id: Uuid,
+    permission: Permission,
+) -> Result<Service, ApiError> {
+    let service = state
+        .deployment_repo
+        .get_service(ResourceId::from_uuid(id))
+        .await?;
+    auth.require(state, service.tenant_id, permission).await?;
+    Ok(service)
+}
+
+/// Load a deployment, checking the caller holds `permission` in its tenant.
+async fn authorized_deployment(
+    state: &AppState,
+    auth: &AuthContext,
+    id: Uuid,
+    permission: Permission,
+) -> Result<Deployment, ApiError> {
+    let deployment = state
+        .deployment_repo
+        .get_deployment(ResourceId::from_uuid(id))
+        .await?;
+    auth.require(state, deployment.tenant_id, permission)
+        .await?;
+    Ok(deployment)
+}
+
+/// Deployment version label: the image tag when there is one.
+fn version_label(spec: &ServiceSpecVersion) -> String {
+    spec.spec
+        .get("image")
+        .and_then(|i| i.as_str())
+        .and_then(|image| image.rsplit_once(':'))
+        .filter(|(_, tag)| !tag.contains('/'))
+        .map(|(_, tag)| tag.to_string())
+        .unwrap_or_else(|| format!("spec-v{}", spec.version))
+}
+
+/// Check an environment's freezes before deploying to it.
+///
+/// Deployments are refused while a `reject` window is in effect unless the
+/// caller may override freezes and gives a reason; the override to record
+/// on the deployment is returned then.
+async fn check_freeze(
+    state: &AppState,
+    auth: &AuthContext,
+    environment: &Environment,
+    override_reason: Option<&str>,
+) -> Result<Option<serde_json::Value>, ApiError> {
+    let freezes = freeze::active(&state.deployment_repo, environment, Utc::now()).await?;
+    if freezes.is_empty() {
+        return Ok(None);
+    }
+    match override_reason.map(str::trim).filter(|r| !r.is_empty()) {
+        Some(reason) => freeze_override(state, auth, environment, &freezes, reason)
+            .await
+            .map(Some),
+        None if freezes.iter().any(|f| f.policy == freeze::POLICY_REJECT) => {
+            Err(ApiError::Conflict(format!(
+                "{}; give an override_reason to deploy anyway",
+                freeze::describe(environment, &freezes)
+            )))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Authorize deploying through `freezes` and describe the override.
+async fn freeze_override(
+    state: &AppState,
+    auth: &AuthContext,
+    environment: &Environment,
+    freezes: &[FreezeOccurrence],
+    reason: &str,
+) -> Result<serde_json::Value, ApiError> {
+    auth.require(
+        state,
+        environment.tenant_id,
+        Permission::DeploymentOverrideFreeze,
+    )
+    .await?;
+    tracing::warn!(
+        environment = %environment.name,
+        user = ?auth.user_id,
+        api_key = ?auth.api_key_id,
+        reason,
+        "Deploying through a freeze"
+    );
+    Ok(serde_json::json!({
+        "reason": reason,
+        "user_id": auth.user_id,
+        "api_key_id": auth.api_key_id,
+        "windows": freezes.iter().map(|f| f.window_id).collect::<Vec<_>>(),
+        "at": Utc::now(),
+    }))
+}
+
+/// Load an environment's protection rules, refusing deployments from
+/// branches it does not allow.
+async fn check_protection(
+    state: &AppState,
+    environment: &Environment,
+    branch: Option<&str>,
+) -> Result<Option<EnvironmentProtection>, ApiError> {
+    let protection = state
+        .deployment_repo
+        .get_environment_protection(ResourceId::from_uuid(environment.id))
+        .await?;
+    protection::check_branch(&environment.name, protection.as_ref(), branch)
+        .map_err(ApiError::Forbidden)?;
+    Ok(protection)
+}
+
+/// Record the branch a new deployment comes from.
+async fn record_branch(
+    state: &AppState,
+    deployment: &mut Deployment,
+    branch: Option<String>,
+) -> Result<(), ApiError> {
+    if let Some(branch) = branch {
+        state
+            .deployment_repo
+            .set_deployment_branch(ResourceId::from_uuid(deployment.id), &branch)
+            .await?;
+        deployment.branch = Some(branch);
+    }
+    Ok(())
+}
+
+/// Approvals a deployment needs before it rolls out: what the
+/// environment's protection rules require, and at least one for promotions
+/// into environments created with `requires_approval`.
+pub(crate) fn required_approvals(
+    environment: &Environment,
+    protection: Option<&EnvironmentProtection>,
+    deployment: &Deployment,
+) -> i32 {
+    let required = protection.map_or(0, |p| p.required_approvals);
+    if deployment.promoted_from.is_some() && requires_approval(environment) {
+        required.max(1)
+    } else {
+        required
+    }
+}
+
+/// Start a freshly recorded deployment, or hold it in `awaiting_approval`
+/// if its environment requires reviews.
+async fn submit_deployment(
+    state: &AppState,
+    deployment: &mut Deployment,
+    environment: &Environment,
+    protection: Option<&EnvironmentProtection>,
+    freeze_override: Option<serde_json::Value>,
+) -> Result<(), ApiError> {
+    if required_approvals(environment, protection, deployment) == 0 {
+        return start_deployment(state, deployment, environment, freeze_override).await;
+    }
+
+    let id = ResourceId::from_uuid(deployment.id);
+    if let Some(freeze_override) = freeze_override {
+        state
+            .deployment_repo
+            .set_freeze_override(id, freeze_override.clone())
+            .await?;
+        deployment.freeze_override = Some(freeze_override);
+    }
+    state
+        .deployment_repo
+        .update_deployment_status(id, "awaiting_approval")
+        .await?;
+    deployment.status = "awaiting_approval".to_string();
+    state.event_bus.publish(BroadcastEvent::DeploymentUpdate {
+        deployment_id: deployment.id.to_string(),
+        status: deployment.status.clone(),
+    });
+    Ok(())
+}
+
+/// Queue background work for a freshly recorded deployment: release
+/// notes for production, and the rollout itself when a deployer is
+/// configured. Without a deployer the deployment stays `pending` for an
+/// external system to pick up.
+///
+/// Unless it overrides them, a deployment into a frozen environment is
+/// marked `frozen`; its rollout task waits for the freeze to end.
+async fn start_deployment(
+    state: &AppState,
+    deployment: &mut Deployment,
+    environment: &Environment,
+    freeze_override: Option<serde_json::Value>,
+) -> Result<(), ApiError> {
+    let deployment_id = deployment.id;
+    if let Some(freeze_override) = freeze_override {
+        state
+            .deployment_repo
+            .set_freeze_override(
+                ResourceId::from_uuid(deployment_id),
+                freeze_override.clone(),
+            )
+            .await?;
+        deployment.freeze_override = Some(freeze_override);
+    } else if deployment.freeze_override.is_none()
+        && state.clusters.available()
+        && !freeze::active(&state.deployment_repo, environment, Utc::now())
+            .await?
+            .is_empty()
+    {
+        state
+            .deployment_repo
+            .update_deployment_status(ResourceId::from_uuid(deployment_id), "frozen")
+            .await?;
+        deployment.status = "frozen".to_string();
+    }
+    if is_production(environment) {
+        tasks::enqueue(state, Task::ReleaseNotes { deployment_id }).await?;
+    }
+    if state.clusters.available() {
+        tasks::enqueue(state, Task::Deployment { deployment_id }).await?;
+    }
+    Ok(())
+}
+
+/// Collect field-level differences between two JSON documents.
+pub(crate) fn diff_json(
+    path: &str,
+    old: &serde_json::Value,
+    new: &serde_json::Value,
+    changes: &mut Vec<SpecChange>,
+) {
+    use serde_json::Value;
+
+    let child = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        }
+    };
+
+    match (old, new) {
+        (Value::Object(a), Value::Object(b)) => {
+            let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                match (a.get(key), b.get(key)) {
+                    (Some(x), Some(y)) => diff_json(&child(key), x, y, changes),
+                    (Some(x), None) => changes.push(SpecChange {
+                        path: child(key),
+                        change: "removed",
+                        old: Some(x.clone()),
+                        new: None,
+                    }),
+                    (None, Some(y)) => changes.push(SpecChange {
+                        path: child(key),
+                        change: "added",
+                        old: None,
+                        new: Some(y.clone()),
+                    }),
+                    (None, None) => {}
+                }
+            }
+        }
+        (a, b) if a != b => changes.push(SpecChange {
+            path: path.to_string(),
+            change: "changed",
+            old: Some(a.clone()),
+            new: Some(b.clone()),
+        }),
+        _ => {}
+    }
+}
+
+// ============================================================================
+// Blue/green handlers
+// ============================================================================
+
+/// Build the deployer handle for a recorded deployment.
+///
+/// The deployer is the one for the environment's cluster. It addresses
+/// services as `namespace/service`; the namespace comes from the
+/// environment config and falls back to the deployer default.
+async fn deployment_handle(
+    state: &AppState,
+    id: Uuid,
+) -> Result<(Arc<dyn Deployer>, DeploymentHandle), ApiError> {
+    let deployment = state
+        .deployment_repo
+        .get_deployment(ResourceId::from_uuid(id))
+        .await?;
+    let service = state
+        .deployment_repo
+        .get_service(ResourceId::from_uuid(deployment.service_id))
+        .await?;
+    let environment = state
+        .deployment_repo
+        .get_environment(ResourceId::from_uuid(deployment.environment_id))
+        .await?;
+    let deployer = state
+        .clusters
+        .for_environment(&environment)
+        .await
+        .map_err(ApiError::Internal)?;
+
+    let deployer_id = match environment.config.get("namespace").and_then(|n| n.as_str()) {
+        Some(namespace) => format!("{}/{}", namespace, service.name),
+        None => service.name,
+    };
+
+    let handle = DeploymentHandle {
+        id: ResourceId::from_uuid(deployment.id),
+        deployer_id,
+        deployer_name: deployer.name().to_string(),
+    };
+    Ok((deployer, handle))
+}
+
+/// Switch traffic to the color waiting for promotion.
+#[utoipa::path(
+    post,
+    path = "/deployments/{id}/promote",
+    params(("id" = Uuid, Path, description = "Deployment ID")),
+    responses((status = 200, description = "Promoted", body = Object, example = json!({"promoted": true})))
+)]
+async fn promote_deployment(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    authorized_deployment(&state, &auth, id, Permission::DeploymentDeploy).await?;
+    let (deployer, handle) = deployment_handle(&state, id).await?;
+    deployer.promote(&handle).await?;
+    state
+        .deployment_repo
+        .update_deployment_status(handle.id, "succeeded")
+        .await?;
+
+    Ok(Json(serde_json::json!({"promoted": true})))
+}
+
+/// Roll a deployment back.
+///
+/// Without a target this flips traffic back to the previously active color.
+/// With `to`, the last good deployment of that version is deployed again as
+/// a new deployment, which is returned.
+#[utoipa::path(
+    post,
+    path = "/deployments/{id}/rollback",
+    params(("id" = Uuid, Path, description = "Deployment ID")),
+    request_body = Option<RollbackRequest>,
+    responses((status = 200, description = "The deployment rolled back, or the new one", body = DeploymentResponse))
+)]
+async fn rollback_deployment(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    body: Option<Json<RollbackRequest>>,
+) -> Result<Json<DeploymentResponse>, ApiError> {
+    let req = body.map(|Json(b)| b).unwrap_or_default();
+    let current = authorized_deployment(&state, &auth, id, Permission::DeploymentDeploy).await?;
+
+    let Some(version) = req.to else {
+        let (deployer, handle) = deployment_handle(&state, id).await?;
+        deployer.rollback(&handle, RollbackTarget::Previous).await?;
+        state
+            .deployment_repo
+            .update_deployment_status(handle.id, "rolled_back")
+            .await?;
+        let deployment = state.deployment_repo.get_deployment(handle.id).await?;
+        return Ok(Json(deployment.into()));
+    };
+
+    let target = state
+        .deployment_repo
+        .list_service_deployments(
+            ResourceId::from_uuid(current.service_id),
+            Some(ResourceId::from_uuid(current.environment_id)),
+            200,
+        )
+        .await?
+        .into_iter()
+        .find(|d| d.version == version && matches!(d.status.as_str(), "succeeded" | "rolled_back"))
+        .ok_or_else(|| {
+            ApiError::NotFound(format!(
+                "No successful deployment of version {} to this environment",
+                version
+            ))
+        })?;
+
+    let environment = state
+        .deployment_repo
+        .get_environment(ResourceId::from_uuid(current.environment_id))
+        .await?;
+    let protection = check_protection(&state, &environment, target.branch.as_deref()).await?;
+    let freeze_override =
+        check_freeze(&state, &auth, &environment, req.override_reason.as_deref()).await?;
+
+    let mut deployment = state
+        .deployment_repo
+        .create_deployment(
+            ResourceId::from_uuid(current.tenant_id),
+            ResourceId::from_uuid(current.service_id),
+            ResourceId::from_uuid(current.environment_id),
+            &target.version,
+            target.spec_version,
+            target.config,
+        )
+        .await?;
+    state
+        .deployment_repo
+        .update_deployment_status(ResourceId::from_uuid(current.id), "rolled_back")
+        .await?;
+
+    record_branch(&state, &mut deployment, target.branch).await?;
+    submit_deployment(
+        &state,
+        &mut deployment,
+        &environment,
+        protection.as_ref(),
+        freeze_override,
+    )
+    .await?;
+    Ok(Json(deployment.into()))
+}
+
+// ============================================================================
+// Promotion handlers
+// ============================================================================
+
+/// Whether deployments promoted into an environment wait for approval.
+fn requires_approval(environment: &Environment) -> bool {
+    environment
+        .config
+        .get("requires_approval")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
+/// The environment a promotion into `environment` takes its release from:
+/// the one before it in the service's promotion chain.
+fn promotion_source(service: &Service, environment: &Environment) -> Result<Uuid, ApiError> {
+    let position = service
+        .promotion_chain
+        .iter()
+        .position(|env_id| *env_id == environment.id)
+        .ok_or_else(|| {
+            ApiError::BadRequest(format!(
+                "{} is not in the promotion chain of {}",
+                environment.name, service.name
+            ))
+        })?;
+    if position == 0 {
+        return Err(ApiError::BadRequest(format!(
+            "{} is the first environment in the promotion chain of {}; deploy to it directly",
+            environment.name, service.name
+        )));
+    }
+    Ok(service.promotion_chain[position - 1])
+}
+
+/// The environments a service is promoted through, with what each runs.
+#[utoipa::path(
+    get,
+    path = "/services/{id}/promotion-chain",
+    params(("id" = Uuid, Path, description = "Service ID")),
+    responses((status = 200, description = "The chain, in promotion order", body = Vec<PromotionStage>))
+)]
+async fn get_promotion_chain(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<PromotionStage>>, ApiError> {
+    let service = authorized_service(&state, &auth, id, Permission::DeploymentRead).await?;
+    Ok(Json(promotion_stages(&state, &service).await?))
+}
+
+/// Set the environments a service is promoted through.
+#[utoipa::path(
+    put,
+    path = "/services/{id}/promotion-chain",
+    params(("id" = Uuid, Path, description = "Service ID")),
+    request_body = SetPromotionChainRequest,
+    responses((status = 200, description = "The chain, in promotion order", body = Vec<PromotionStage>))
+)]
+async fn set_promotion_chain(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    Json(req): Json<SetPromotionChainRequest>,
+) -> Result<(AuditBefore, Json<Vec<PromotionStage>>), ApiError> {
+    let service = authorized_service(&state, &auth, id, Permission::DeploymentWrite).await?;
+    for (i, env_id) in req.environments.iter().enumerate() {
+        if req.environments[..i].contains(env_id) {
+            return Err(ApiError::BadRequest(format!(
+                "Environment {} appears twice in the chain",
+                env_id
+            )));
+        }
+        let environment = state
+            .deployment_repo
+            .get_environment(ResourceId::from_uuid(*env_id))
+            .await?;
+        if environment.tenant_id != service.tenant_id {
+            return Err(ApiError::BadRequest(
+                "Environment belongs to a different tenant".to_string(),
+            ));
+        }
+    }
+
+    let updated = state
+        .deployment_repo
+        .set_promotion_chain(ResourceId::from_uuid(service.id), &req.environments)
+        .await?;
+    Ok((
+        AuditBefore::of(&service.promotion_chain),
+        Json(promotion_stages(&state, &updated).await?),
+    ))
+}
+
+async fn promotion_stages(
+    state: &AppState,
+    service: &Service,
+) -> Result<Vec<PromotionStage>, ApiError> {
+    let mut stages = Vec::with_capacity(service.promotion_chain.len());
+    for env_id in &service.promotion_chain {
+        let environment = state
+            .deployment_repo
+            .get_environment(ResourceId::from_uuid(*env_id))
+            .await?;
+        let current = state
+            .deployment_repo
+            .get_previous_deployment(
+                ResourceId::from_uuid(service.id),
+                ResourceId::from_uuid(environment.id),
+                chrono::Utc::now(),
+            )
+            .await?;
+        stages.push(PromotionStage {
+            environment_id: environment.id,
+            requires_approval: requires_approval(&environment),
+            environment_name: environment.name,
+            current: current.map(Into::into),
+        });
+    }
+    Ok(stages)
+}
+
+/// Promote a service into an environment.
+///
+/// The last successful deployment in the previous environment of the
+/// service's promotion chain is deployed again, with the same version and
+/// spec. If the target environment requires approval the new deployment
+/// waits in `awaiting_approval` until someone approves it.
+#[utoipa::path(
+    post,
+    path = "/services/{id}/promote",
+    params(("id" = Uuid, Path, description = "Service ID")),
+    request_body = PromoteServiceRequest,
+    responses((status = 200, description = "The promoted deployment", body = DeploymentResponse))
+)]
+async fn promote_service(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    Json(req): Json<PromoteServiceRequest>,
+) -> Result<Json<DeploymentResponse>, ApiError> {
+    let service = authorized_service(&state, &auth, id, Permission::DeploymentDeploy).await?;
+    let environment = state
+        .deployment_repo
+        .get_environment(ResourceId::from_uuid(req.environment_id))
+        .await?;
+
+    let previous = state
+        .deployment_repo
+        .get_environment(ResourceId::from_uuid(promotion_source(
+            &service,
+            &environment,
+        )?))
+        .await?;
+    let source = state
+        .deployment_repo
+        .get_previous_deployment(
+            ResourceId::from_uuid(service.id),
+            ResourceId::from_uuid(previous.id),
+            chrono::Utc::now(),
+        )
+        .await?
+        .ok_or_else(|| {
+            ApiError::NotFound(format!(
+                "{} has no successful deployment in {} to promote",
+                service.name, previous.name
+            ))
+        })?;
+
+    let protection = check_protection(&state, &environment, source.branch.as_deref()).await?;
+    let freeze_override =
+        check_freeze(&state, &auth, &environment, req.override_reason.as_deref()).await?;
+
+    let mut deployment = state
+        .deployment_repo
+        .create_promotion(&source, ResourceId::from_uuid(environment.id), "pending")
+        .await?;
+    submit_deployment(
+        &state,
+        &mut deployment,
+        &environment,
+        protection.as_ref(),
+        freeze_override,
+    )
+    .await?;
+    Ok(Json(deployment.into()))
+}
+
+/// Whether a caller holding `role` may review deployments to an
+/// environment: one of its designated reviewers if it names any, else
+/// anyone who may deploy.
+fn may_review(
+    auth: &AuthContext,
+    role: Option<Role>,
+    protection: Option<&EnvironmentProtection>,
+) -> bool {
+    match protection.filter(|p| protection::designates_reviewers(p)) {
+        Some(p) => auth.user_id.is_some() && protection::is_reviewer(p, auth.user_id, role),
+        None => role.is_some_and(|r| r.grants(Permission::DeploymentDeploy)),
+    }
+}
+
+/// A deployment waiting for approval, with what it needs to be reviewed.
+struct UnderReview {
+    deployment: Deployment,
+    environment: Environment,
+    protection: Option<EnvironmentProtection>,
+    reviews: Vec<DeploymentReview>,
+}
+
+/// Load a deployment that is waiting for approval, checking the caller may
+/// review it and has not already.
+async fn under_review(
+    state: &AppState,
+    auth: &AuthContext,
+    id: Uuid,
+) -> Result<UnderReview, ApiError> {
+    let deployment = authorized_deployment(state, auth, id, Permission::DeploymentRead).await?;
+    if deployment.status != "awaiting_approval" {
+        return Err(ApiError::Conflict(format!(
+            "Deployment {} is {}, not awaiting approval",
+            id, deployment.status
+        )));
+    }
+    let environment = state
+        .deployment_repo
+        .get_environment(ResourceId::from_uuid(deployment.environment_id))
+        .await?;
+    let protection = state
+        .deployment_repo
+        .get_environment_protection(ResourceId::from_uuid(environment.id))
+        .await?;
+
+    let role = auth.role_in(state, environment.tenant_id).await?;
+    if !may_review(auth, role, protection.as_ref()) {
+        return Err(ApiError::Forbidden(format!(
+            "Only the designated reviewers of {} may review deployments to it",
+            environment.name
+        )));
+    }
+
+    let reviews = state
+        .deployment_repo
+        .list_deployment_reviews(ResourceId::from_uuid(id))
+        .await?;
+    let reviewed = reviews.iter().any(|r| match auth.user_id {
+        Some(user_id) => r.user_id == Some(user_id),
+        None => auth.api_key_id.is_some() && r.api_key_id == auth.api_key_id,
+    });
+    if reviewed {
+        return Err(ApiError::Conflict(format!(
+            "You have already reviewed deployment {}",
+            id
+        )));
+    }
+
+    Ok(UnderReview {
+        deployment,
+        environment,
+        protection,
+        reviews,
+    })
+}
+
+/// Record the caller's review of a deployment.
+async fn record_review(
+    state: &AppState,
+    auth: &AuthContext,
+    deployment_id: Uuid,
+    approved: bool,
+    comment: Option<String>,
+) -> Result<DeploymentReview, ApiError> {
+    Ok(state
+        .deployment_repo
+        .create_deployment_review(&DeploymentReview {
+            id: Uuid::now_v7(),
+            deployment_id,
+            user_id: auth.user_id,
+            api_key_id: auth.api_key_id,
+            approved,
+            comment: comment.filter(|c| !c.trim().is_empty()),
+            created_at: Utc::now(),
+        })
+        .await?)
+}
+
+/// Approve a deployment waiting for review. Once it has the approvals its
+/// environment requires it starts rolling out, after the environment's
+/// wait timer if it has one.
+#[utoipa::path(
+    post,
+    path = "/deployments/{id}/approve",
+    params(("id" = Uuid, Path, description = "Deployment ID")),
+    request_body(content = ReviewRequest, description = "Optional comment"),
+    responses((status = 200, description = "The approved deployment", body = DeploymentResponse))
+)]
+async fn approve_deployment(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    body: Option<Json<ReviewRequest>>,
+) -> Result<Json<DeploymentResponse>, ApiError> {
+    let req = body.map(|Json(b)| b).unwrap_or_default();
+    Ok(Json(approve(&state, &auth, id, req.comment).await?.into()))
+}
+
+/// Record the caller's approval of a deployment, starting it once it has
+/// the approvals it needs.
+pub(crate) async fn approve(
+    state: &AppState,
+    auth: &AuthContext,
+    id: Uuid,
+    comment: Option<String>,
+) -> Result<Deployment, ApiError> {
+    let UnderReview {
+        mut deployment,
+        environment,
+        protection,
+        reviews,
+    } = under_review(state, auth, id).await?;
+
+    let approved = protection::approvals(&reviews) + 1
+        >= required_approvals(&environment, protection.as_ref(), &deployment);
+    if approved && deployment.freeze_override.is_none() {
+        check_freeze(state, auth, &environment, None).await?;
+    }
+    record_review(state, auth, id, true, comment).await?;
+    if !approved {
+        return Ok(deployment);
+    }
+
+    state
+        .deployment_repo
+        .update_deployment_status(ResourceId::from_uuid(id), "pending")
+        .await?;
+    start_deployment(state, &mut deployment, &environment, None).await?;
+
+    Ok(state
+        .deployment_repo
+        .get_deployment(ResourceId::from_uuid(id))
+        .await?)
+}
+
+/// Reject a deployment waiting for review; it is cancelled without
+/// rolling out.
+#[utoipa::path(
+    post,
+    path = "/deployments/{id}/reject",
+    params(("id" = Uuid, Path, description = "Deployment ID")),
+    request_body(content = ReviewRequest, description = "Optional comment"),
+    responses((status = 200, description = "The cancelled deployment", body = DeploymentResponse))
+)]
+async fn reject_deployment(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    body: Option<Json<ReviewRequest>>,
+) -> Result<Json<DeploymentResponse>, ApiError> {
+    let req = body.map(|Json(b)| b).unwrap_or_default();
+    Ok(Json(reject(&state, &auth, id, req.comment).await?.into()))
+}
+
+/// Record the caller's rejection of a deployment, cancelling it.
+pub(crate) async fn reject(
+    state: &AppState,
+    auth: &AuthContext,
+    id: Uuid,
+    comment: Option<String>,
+) -> Result<Deployment, ApiError> {
+    under_review(state, auth, id).await?;
+    record_review(state, auth, id, false, comment).await?;
+    state
+        .deployment_repo
+        .update_deployment_status(ResourceId::from_uuid(id), "cancelled")
+        .await?;
+    state.event_bus.publish(BroadcastEvent::DeploymentUpdate {
+        deployment_id: id.to_string(),
+        status: "cancelled".to_string(),
+    });
+
+    Ok(state
+        .deployment_repo
+        .get_deployment(ResourceId::from_uuid(id))
+        .await?)
+}
+
+#[utoipa::path(
+    get,
+    path = "/deployments/{id}/reviews",
+    params(("id" = Uuid, Path, description = "Deployment ID")),
+    responses((status = 200, description = "Reviews, oldest first", body = Vec<ReviewResponse>))
+)]
+async fn list_deployment_reviews(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<ReviewResponse>>, ApiError> {
+    authorized_deployment(&state, &auth, id, Permission::DeploymentRead).await?;
+    let reviews = state
+        .deployment_repo
+        .list_deployment_reviews(ResourceId::from_uuid(id))
+        .await?;
+    Ok(Json(reviews.into_iter().map(Into::into).collect()))
+}
+
+/// The tenant's deployments waiting for review, oldest first.
+#[utoipa::path(
+    get,
+    path = "/approvals",
+    responses((status = 200, description = "Deployments awaiting approval", body = Vec<PendingApprovalResponse>))
+)]
+async fn list_pending_approvals(
+    State(state): State<AppState>,
+    auth: AuthContext,
+) -> Result<Json<Vec<PendingApprovalResponse>>, ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::DeploymentRead)
+        .await?;
+    Ok(Json(pending_approvals(&state, &auth, tenant.id).await?))
+}
+
+/// Deployments awaiting approval in a tenant, with their reviews so far.
+async fn pending_approvals(
+    state: &AppState,
+    auth: &AuthContext,
+    tenant_id: Uuid,
+) -> Result<Vec<PendingApprovalResponse>, ApiError> {
+    let repo = &state.deployment_repo;
+    let role = auth.role_in(state, tenant_id).await?;
+    let services = repo.list_services(ResourceId::from_uuid(tenant_id)).await?;
+    let mut environments: HashMap<Uuid, (Environment, Option<EnvironmentProtection>)> =
+        HashMap::new();
+
+    let mut pending = Vec::new();
+    for deployment in repo
+        .list_awaiting_approval(ResourceId::from_uuid(tenant_id))
+        .await?
+    {
+        let environment_id = deployment.environment_id;
+        if let Entry::Vacant(entry) = environments.entry(environment_id) {
+            let id = ResourceId::from_uuid(environment_id);
+            let environment = repo.get_environment(id).await?;
+            let protection = repo.get_environment_protection(id).await?;
+            entry.insert((environment, protection));
+        }
+        let (environment, protection) = &environments[&environment_id];
+        let reviews = repo
+            .list_deployment_reviews(ResourceId::from_uuid(deployment.id))
+            .await?;
+        let reviewed = reviews
+            .iter()
+            .any(|r| auth.user_id.is_some() && r.user_id == auth.user_id);
+        pending.push(PendingApprovalResponse {
+            service_name: services
+                .iter()
+                .find(|s| s.id == deployment.service_id)
+                .map(|s| s.name.clone())
+                .unwrap_or_default(),
+            environment_name: environment.name.clone(),
+            approvals: protection::approvals(&reviews),
+            required_approvals: required_approvals(environment, protection.as_ref(), &deployment),
+            can_review: !reviewed && may_review(auth, role, protection.as_ref()),
+            reviews: reviews.into_iter().map(Into::into).collect(),
+            deployment: deployment.into(),
+        });
+    }
+    Ok(pending)
+}
+
+/// The deployments a release was promoted through, from this one back to
+/// the environment it was first deployed to.
+#[utoipa::path(
+    get,
+    path = "/deployments/{id}/lineage",
+    params(("id" = Uuid, Path, description = "Deployment ID")),
+    responses((status = 200, description = "This deployment and its sources", body = Vec<DeploymentResponse>))
+)]
+async fn get_deployment_lineage(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<DeploymentResponse>>, ApiError> {
+    let deployment = authorized_deployment(&state, &auth, id, Permission::DeploymentRead).await?;
+    let mut lineage = vec![deployment];
+    while let Some(source) = lineage.last().and_then(|d| d.promoted_from) {
+        if lineage.iter().any(|d| d.id == source) {
+            break;
+        }
+        let deployment = state
+            .deployment_repo
+            .get_deployment(ResourceId::from_uuid(source))
+            .await?;
+        lineage.push(deployment);
+    }
+    Ok(Json(lineage.into_iter().map(Into::into).collect()))
+}
+
+/// What changed between a deployment and an earlier one of the service:
+/// the image, environment variables, other spec fields and manifests.
+#[utoipa::path(
+    get,
+    path = "/deployments/{id}/diff",
+    params(("id" = Uuid, Path, description = "Deployment ID"), DeploymentDiffQuery),
+    responses((status = 200, description = "Changes from the earlier deployment", body = DeploymentDiff))
+)]
+async fn diff_deployments(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    Query(query): Query<DeploymentDiffQuery>,
+) -> Result<Json<DeploymentDiff>, ApiError> {
+    let to = authorized_deployment(&state, &auth, id, Permission::DeploymentRead).await?;
+    let from = diff_base(&state, &to, query.from).await?;
+    Ok(Json(state.deployment_diff().diff(&from, &to).await?))
+}
+
+/// The deployment `to` is diffed against: `from` when given, which must be
+/// of the same service, or the service's previous successful deployment to
+/// the same environment.
+pub(crate) async fn diff_base(
+    state: &AppState,
+    to: &Deployment,
+    from: Option<Uuid>,
+) -> Result<Deployment, ApiError> {
+    let repo = &state.deployment_repo;
+    let from = match from {
+        Some(from) => repo.get_deployment(ResourceId::from_uuid(from)).await?,
+        None => repo
+            .get_previous_deployment(
+                ResourceId::from_uuid(to.service_id),
+                ResourceId::from_uuid(to.environment_id),
+                to.created_at,
+            )
+            .await?
+            .ok_or_else(|| {
+                ApiError::NotFound(format!(
+                    "Deployment {} has no earlier successful deployment to compare with",
+                    to.id
+                ))
+            })?,
+    };
+    if from.service_id != to.service_id {
+        return Err(ApiError::BadRequest(
+            "Compare two deployments of the same service".to_string(),
+        ));
+    }
+    if from.id == to.id {
+        return Err(ApiError::BadRequest(
+            "Compare two different deployments".to_string(),
+        ));
+    }
+    Ok(from)
+}
+
+/// Roll out a deployment held by a freeze now, recording why.
+#[utoipa::path(
+    post,
+    path = "/deployments/{id}/override-freeze",
+    params(("id" = Uuid, Path, description = "Deployment ID")),
+    request_body = OverrideFreezeRequest,
+    responses((status = 200, description = "The released deployment", body = DeploymentResponse))
+)]
+async fn override_freeze(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    Json(req): Json<OverrideFreezeRequest>,
+) -> Result<Json<DeploymentResponse>, ApiError> {
+    let deployment = authorized_deployment(&state, &auth, id, Permission::DeploymentDeploy).await?;
+    if deployment.status != "frozen" {
+        return Err(ApiError::Conflict(format!(
+            "Deployment {} is {}, not frozen",
+            id, deployment.status
+        )));
+    }
+    let reason = req.reason.trim();
+    if reason.is_empty() {
+        return Err(ApiError::BadRequest("A reason is required".to_string()));
+    }
+    let environment = state
+        .deployment_repo
+        .get_environment(ResourceId::from_uuid(deployment.environment_id))
+        .await?;
+    let freezes = freeze::active(&state.deployment_repo, &environment, Utc::now()).await?;
+    let freeze_override = freeze_override(&state, &auth, &environment, &freezes, reason).await?;
+
+    let deployment_id = ResourceId::from_uuid(id);
+    state
+        .deployment_repo
+        .set_freeze_override(deployment_id, freeze_override)
+        .await?;
+    state
+        .deployment_repo
+        .update_deployment_status(deployment_id, "pending")
+        .await?;
+    let task = Task::Deployment { deployment_id: id };
+    if !state
+        .job_queue
+        .run_task_now(&task.idempotency_key())
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?
+    {
+        tasks::enqueue(&state, task).await?;
+    }
+
+    let deployment = state.deployment_repo.get_deployment(deployment_id).await?;
+    Ok(Json(deployment.into()))
+}
+
+// ============================================================================
+// Deployment handlers
+// ============================================================================
+
+#[utoipa::path(
+    get,
+    path = "/services",
+    params(ListServicesQuery),
+    responses((status = 200, description = "Services", body = Vec<ServiceResponse>))
+)]
+async fn list_services(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Query(query): Query<ListServicesQuery>,
+) -> Result<Json<Vec<ServiceResponse>>, ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::DeploymentRead)
+        .await?;
+
+    let services = state
+        .deployment_repo
+        .list_services(ResourceId::from_uuid(tenant.id))
+        .await?;
+
+    let response = services
+        .into_iter()
+        .filter(|s| query.name.as_deref().is_none_or(|name| s.name == name))
+        .filter(|s| {
+            query
+                .owner_team
+                .as_deref()
+                .is_none_or(|team| s.owner_team.as_deref() == Some(team))
+        })
+        .map(Into::into)
+        .collect();
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    get,
+    path = "/services/{id}",
+    params(("id" = Uuid, Path, description = "Service ID")),
+    responses((status = 200, description = "The service", body = ServiceResponse))
+)]
+async fn get_service(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ServiceResponse>, ApiError> {
+    let service = authorized_service(&state, &auth, id, Permission::DeploymentRead).await?;
+    Ok(Json(service.into()))
+}
+
+/// Set who owns a service, where its code, runbook and dashboards are, and
+/// its tier.
+#[utoipa::path(
+    put,
+    path = "/services/{id}/catalog",
+    params(("id" = Uuid, Path, description = "Service ID")),
+    request_body = UpdateServiceCatalogRequest,
+    responses((status = 200, description = "The updated service", body = ServiceResponse))
+)]
+async fn update_service_catalog(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    Json(req): Json<UpdateServiceCatalogRequest>,
+) -> Result<(AuditBefore, Json<ServiceResponse>), ApiError> {
+    let service = authorized_service(&state, &auth, id, Permission::DeploymentWrite).await?;
+    let catalog = service_catalog(req)?;
+    let previous = ServiceCatalog {
+        owner_team: service.owner_team,
+        repository_url: service.repository_url,
+        runbook_url: service.runbook_url,
+        dashboard_urls: service.dashboard_urls,
+        tier: service.tier,
+    };
+
+    let updated = state
+        .deployment_repo
+        .set_service_catalog(ResourceId::from_uuid(id), &catalog)
+        .await?;
+    Ok((AuditBefore::of(&previous), Json(updated.into())))
+}
+
+/// Check a catalog entry, dropping blank fields.
+fn service_catalog(req: UpdateServiceCatalogRequest) -> Result<ServiceCatalog, ApiError> {
+    let blank_to_none = |value: Option<String>| {
+        value
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+    let url = |field: &str, value: Option<String>| match blank_to_none(value) {
+        Some(url) if !(url.starts_with("https://") || url.starts_with("http://")) => Err(
+            ApiError::BadRequest(format!("{} must be an http(s) URL", field)),
+        ),
+        url => Ok(url),
+    };
+    if req.tier.is_some_and(|tier| !(1..=4).contains(&tier)) {
+        return Err(ApiError::BadRequest(
+            "tier must be from 1 (most critical) to 4".to_string(),
+        ));
+    }
+    let mut dashboard_urls = Vec::new();
+    for dashboard in req.dashboard_urls {
+        if let Some(dashboard) = url("dashboard_urls", Some(dashboard))? {
+            dashboard_urls.push(dashboard);
+        }
+    }
+    Ok(ServiceCatalog {
+        owner_team: blank_to_none(req.owner_team),
+        repository_url: url("repository_url", req.repository_url)?,
+        runbook_url: url("runbook_url", req.runbook_url)?,
+        dashboard_urls,
+        tier: req.tier,
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/deployments",
+    params(ListDeploymentsQuery),
+    responses((status = 200, description = "Deployments, newest first", body = Vec<DeploymentResponse>))
+)]
+async fn list_deployments(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Query(query): Query<ListDeploymentsQuery>,
+) -> Result<Json<Vec<DeploymentResponse>>, ApiError> {
+    authorized_service(&state, &auth, query.service_id, Permission::DeploymentRead).await?;
+    let deployments = state
+        .deployment_repo
+        .list_service_deployments(
+            ResourceId::from_uuid(query.service_id),
+            query.environment_id.map(ResourceId::from_uuid),
+            query.limit.unwrap_or(20).clamp(1, 100),
+        )
+        .await?;
+    Ok(Json(deployments.into_iter().map(Into::into).collect()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/deployments/{id}",
+    params(("id" = Uuid, Path, description = "Deployment ID")),
+    responses((status = 200, description = "The deployment", body = DeploymentResponse))
+)]
+async fn get_deployment(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<DeploymentResponse>, ApiError> {
+    let deployment = authorized_deployment(&state, &auth, id, Permission::DeploymentRead).await?;
+    Ok(Json(deployment.into()))
+}
+
+/// Deploy a service's current spec to an environment.
+#[utoipa::path(
+    post,
+    path = "/deployments",
+    request_body = CreateDeploymentRequest,
+    responses((status = 200, description = "The queued deployment", body = DeploymentResponse))
+)]
+async fn create_deployment(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Json(req): Json<CreateDeploymentRequest>,
+) -> Result<Json<DeploymentResponse>, ApiError> {
+    Ok(Json(deploy(&state, &auth, req).await?.into()))
+}
+
+/// Dry-run a deployment of a service's current spec, or of a new image,
+/// returning what it would change without recording or applying it.
+#[utoipa::path(
+    post,
+    path = "/preview",
+    request_body = PreviewDeploymentRequest,
+    responses((status = 200, description = "The changes the deployment would make", body = DeploymentPreview))
+)]
+async fn preview_deployment(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Json(req): Json<PreviewDeploymentRequest>,
+) -> Result<Json<DeploymentPreview>, ApiError> {
+    let service =
+        authorized_service(&state, &auth, req.service_id, Permission::DeploymentDeploy).await?;
+    let environment = state
+        .deployment_repo
+        .get_environment(ResourceId::from_uuid(req.environment_id))
+        .await?;
+    if environment.tenant_id != service.tenant_id {
+        return Err(ApiError::BadRequest(
+            "Environment belongs to a different tenant".to_string(),
+        ));
+    }
+    let preview = state
+        .deployment_preview()
+        .preview(&service, &environment, req.image, req.branch)
+        .await?;
+    Ok(Json(preview))
+}
+
+/// Record a deployment of a service's current spec, or of a new image, and
+/// start it or hold it for approval.
+pub(crate) async fn deploy(
+    state: &AppState,
+    auth: &AuthContext,
+    req: CreateDeploymentRequest,
+) -> Result<Deployment, ApiError> {
+    let service =
+        authorized_service(state, auth, req.service_id, Permission::DeploymentDeploy).await?;
+    let environment = state
+        .deployment_repo
+        .get_environment(ResourceId::from_uuid(req.environment_id))
+        .await?;
+    if environment.tenant_id != service.tenant_id {
+        return Err(ApiError::BadRequest(
+            "Environment belongs to a different tenant".to_string(),
+        ));
+    }
+
+    let protection = check_protection(state, &environment, req.branch.as_deref()).await?;
+    let freeze_override =
+        check_freeze(state, auth, &environment, req.override_reason.as_deref()).await?;
+
+    let current = state
+        .deployment_repo
+        .list_service_spec_versions(ResourceId::from_uuid(service.id))
+        .await?
+        .into_iter()
+        .next();
+    let spec = match (current, req.image) {
+        (Some(current), None) => current,
+        (current, image) => {
+            let mut spec = current
+                .map(|c| c.spec)
+                .unwrap_or_else(|| serde_json::json!({}));
+            if let Some(image) = image {
+                spec["image"] = serde_json::Value::String(image);
+            }
+            state
+                .deployment_repo
+                .update_service_spec(ResourceId::from_uuid(service.id), spec, None)
+                .await?
+        }
+    };
+    if spec.spec.get("image").and_then(|i| i.as_str()).is_none()
+        && !matches!(
+            spec.spec
+                .get("source")
+                .and_then(|s| s.get("type"))
+                .and_then(|t| t.as_str()),
+            Some("manifests" | "kustomize")
+        )
+    {
+        return Err(ApiError::BadRequest(format!(
+            "Service {} has no image; pass one explicitly",
+            service.name
+        )));
+    }
+
+    let mut deployment = state
+        .deployment_repo
+        .create_deployment(
+            ResourceId::from_uuid(service.tenant_id),
+            ResourceId::from_uuid(service.id),
+            ResourceId::from_uuid(environment.id),
+            &version_label(&spec),
+            Some(spec.version),
+            spec.spec,
+        )
+        .await?;
+
+    record_branch(state, &mut deployment, req.branch).await?;
+    submit_deployment(
+        state,
+        &mut deployment,
+        &environment,
+        protection.as_ref(),
+        freeze_override,
+    )
+    .await?;
+    Ok(deployment)
+}
+
+// ============================================================================
+// Release notes handlers
+// ============================================================================
+
+/// Stored release notes for a deployment.
+#[utoipa::path(
+    get,
+    path = "/deployments/{id}/release-notes",
+    params(("id" = Uuid, Path, description = "Deployment ID")),
+    responses((status = 200, description = "The release notes", body = ReleaseNotes))
+)]
+async fn get_release_notes(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ReleaseNotes>, ApiError> {
+    let deployment = authorized_deployment(&state, &auth, id, Permission::DeploymentRead).await?;
+    let notes = deployment
+        .release_notes
+        .ok_or_else(|| ApiError::NotFound(format!("No release notes for deployment {}", id)))?;
+    serde_json::from_value(notes)
+        .map_err(|e| ApiError::Internal(e.to_string()))
+        .map(Json)
+}
+
+/// Regenerate release notes for a deployment, optionally publishing them.
+#[utoipa::path(
+    post,
+    path = "/deployments/{id}/release-notes",
+    params(("id" = Uuid, Path, description = "Deployment ID")),
+    request_body = Option<GenerateReleaseNotesRequest>,
+    responses((status = 200, description = "The release notes", body = ReleaseNotes))
+)]
+async fn generate_release_notes(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    body: Option<Json<GenerateReleaseNotesRequest>>,
+) -> Result<Json<ReleaseNotes>, ApiError> {
+    let req = body.map(|Json(b)| b).unwrap_or_default();
+    let deployment = authorized_deployment(&state, &auth, id, Permission::DeploymentWrite).await?;
+    let notes =
+        ReleaseNotesService::new(state.deployment_repo.clone(), state.pipeline_repo.clone())
+            .generate_and_publish(&deployment, req.publish)
+            .await
+            .map_err(|e| ApiError::Internal(e.to_string()))?;
+    Ok(Json(notes))
+}
+
+// ============================================================================
+// Approval context handlers
+// ============================================================================
+
+/// What an approver should know before a deployment rolls out.
+#[utoipa::path(
+    get,
+    path = "/deployments/{id}/approval-context",
+    operation_id = "get_deployment_approval_context",
+    params(("id" = Uuid, Path, description = "Deployment ID")),
+    responses((status = 200, description = "The approval context", body = ApprovalContext))
+)]
+async fn get_approval_context(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ApprovalContext>, ApiError> {
+    let deployment = authorized_deployment(&state, &auth, id, Permission::DeploymentRead).await?;
+    let context = state
+        .approval_context()
+        .for_deployment(&deployment)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    Ok(Json(context))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_spec_versions() {
+        let old = json!({
+            "image": "app:1.0",
+            "replicas": 2,
+            "resources": {"cpu_limit": "500m", "memory_limit": "256Mi"},
+            "port": 8080,
+        });
+        let new = json!({
+            "image": "app:1.1",
+            "replicas": 2,
+            "resources": {"cpu_limit": "1", "memory_limit": "256Mi"},
+            "health_path": "/healthz",
+        });
+
+        let mut changes = Vec::new();
+        diff_json("", &old, &new, &mut changes);
+        let summary: Vec<_> = changes
+            .iter()
+            .map(|c| (c.path.as_str(), c.change))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("health_path", "added"),
+                ("image", "changed"),
+                ("port", "removed"),
+                ("resources.cpu_limit", "changed"),
+            ]
+        );
+        assert_eq!(changes[1].old, Some(json!("app:1.0")));
+        assert_eq!(changes[1].new, Some(json!("app:1.1")));
+
+        // Identical specs have nothing to show
+        let mut changes = Vec::new();
+        diff_json("", &old, &old, &mut changes);
+        assert!(changes.is_empty());
+    }
+
+    fn environment(name: &str, config: serde_json::Value) -> Environment {
+        Environment {
+            id: Uuid::new_v4(),
+            tenant_id: Uuid::new_v4(),
+            target_id: Uuid::new_v4(),
+            name: name.to_string(),
+            health_status: "healthy".to_string(),
+            config,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_promotions_take_the_previous_environments_release() {
+        let dev = environment("dev", json!({}));
+        let staging = environment("staging", json!({}));
+        let production = environment("production", json!({"requires_approval": true}));
+        let service = Service {
+            id: Uuid::new_v4(),
+            tenant_id: dev.tenant_id,
+            pipeline_id: None,
+            name: "api".to_string(),
+            image: None,
+            status: "active".to_string(),
+            config: json!({}),
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+            promotion_chain: vec![dev.id, staging.id],
+            owner_team: None,
+            repository_url: None,
+            runbook_url: None,
+            dashboard_urls: Vec::new(),
+            tier: None,
+        };
+
+        assert_eq!(promotion_source(&service, &staging).unwrap(), dev.id);
+        // The first environment is deployed to directly
+        assert!(matches!(
+            promotion_source(&service, &dev),
+            Err(ApiError::BadRequest(_))
+        ));
+        assert!(matches!(
+            promotion_source(&service, &production),
+            Err(ApiError::BadRequest(_))
+        ));
+
+        assert!(requires_approval(&production));
+        assert!(!requires_approval(&staging));
+    }
+
+    fn catalog_request(
+        tier: Option<i32>,
+        runbook_url: Option<&str>,
+    ) -> UpdateServiceCatalogRequest {
+        UpdateServiceCatalogRequest {
+            owner_team: Some("  payments ".to_string()),
+            repository_url: Some(" ".to_string()),
+            runbook_url: runbook_url.map(str::to_string),
+            dashboard_urls: vec![
+                "https://grafana.example.com/d/api".to_string(),
+                String::new(),
+            ],
+            tier,
+        }
+    }
+
+    #[test]
+    fn test_service_catalog_is_checked() {
+        let catalog = service_catalog(catalog_request(
+            Some(1),
+            Some("https://wiki.example.com/api"),
+        ))
+        .unwrap();
+        assert_eq!(catalog.owner_team.as_deref(), Some("payments"));
+        assert_eq!(catalog.repository_url, None);
+        assert_eq!(
+            catalog.dashboard_urls,
+            ["https://grafana.example.com/d/api"]
+        );
+
+        for (tier, runbook) in [(Some(0), None), (Some(5), None), (None, Some("wiki/api"))] {
+            assert!(matches!(
+                service_catalog(catalog_request(tier, runbook)),
+                Err(ApiError::BadRequest(_))
+            ));
+        }
+    }
 }
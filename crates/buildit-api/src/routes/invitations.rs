@@ -0,0 +1,106 @@
+//! Answering invitations to join an organization, by their token.
+
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    routing::post,
+};
+
+use crate::AppState;
+use crate::auth::{AuthContext, token_hash};
+use crate::error::ApiError;
+use buildit_core::ResourceId;
+use buildit_db::{DbError, OrgInvitation, OrgMembership, OrganizationRepo};
+use utoipa::OpenApi;
+
+#[derive(OpenApi)]
+#[openapi(paths(accept_invitation, decline_invitation))]
+pub struct ApiDoc;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/{token}/accept", post(accept_invitation))
+        .route("/{token}/decline", post(decline_invitation))
+}
+
+/// The pending invitation a token belongs to.
+async fn pending(state: &AppState, token: &str) -> Result<OrgInvitation, ApiError> {
+    let not_found = || ApiError::NotFound("Invitation not found or no longer valid".to_string());
+    let invitation = match state
+        .organization_repo
+        .get_invitation_by_token(&token_hash(token))
+        .await
+    {
+        Ok(invitation) => invitation,
+        Err(DbError::NotFound(_)) => return Err(not_found()),
+        Err(e) => return Err(e.into()),
+    };
+    if invitation.status != "pending" || invitation.expires_at <= chrono::Utc::now() {
+        return Err(not_found());
+    }
+    Ok(invitation)
+}
+
+/// Join the organization as the signed-in user.
+#[utoipa::path(
+    post,
+    path = "/{token}/accept",
+    params(("token" = String, Path, description = "Invitation token from the invitee's link")),
+    responses((status = 200, description = "The new membership", body = OrgMembership))
+)]
+async fn accept_invitation(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(token): Path<String>,
+) -> Result<Json<OrgMembership>, ApiError> {
+    let user_id = auth.user_id.ok_or_else(|| {
+        ApiError::BadRequest("Sign in as a user to accept invitations".to_string())
+    })?;
+    let invitation = pending(&state, &token).await?;
+
+    let membership = state
+        .organization_repo
+        .accept_invitation(
+            ResourceId::from_uuid(invitation.id),
+            ResourceId::from_uuid(user_id),
+        )
+        .await
+        .map_err(|e| match e {
+            DbError::NotFound(_) => {
+                ApiError::NotFound("Invitation not found or no longer valid".to_string())
+            }
+            DbError::Conflict(_) => {
+                ApiError::Conflict("You are already a member of this organization".to_string())
+            }
+            e => e.into(),
+        })?;
+
+    tracing::info!(
+        organization = %invitation.organization_id,
+        user = %user_id,
+        role = %membership.role,
+        "Invitation accepted"
+    );
+    Ok(Json(membership))
+}
+
+/// Decline an invitation.
+#[utoipa::path(
+    post,
+    path = "/{token}/decline",
+    params(("token" = String, Path, description = "Invitation token from the invitee's link")),
+    responses((status = 200, description = "Declined", body = Object, example = json!({"declined": true})))
+)]
+async fn decline_invitation(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let invitation = pending(&state, &token).await?;
+    state
+        .organization_repo
+        .decline_invitation(ResourceId::from_uuid(invitation.id))
+        .await?;
+
+    tracing::info!(organization = %invitation.organization_id, "Invitation declined");
+    Ok(Json(serde_json::json!({"declined": true})))
+}
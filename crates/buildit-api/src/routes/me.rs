@@ -0,0 +1,185 @@
+//! The authenticated caller.
+
+use axum::Json;
+use axum::Router;
+use axum::extract::{Query, State};
+use axum::routing::{get, put};
+use buildit_core::ResourceId;
+use buildit_core::rbac::{Permission, Role};
+use buildit_db::{OrganizationRepo, Tenant, TenantRepo};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::auth::{AuthContext, AuthMethod, TENANT_HEADER};
+use crate::error::ApiError;
+use crate::tenancy::find_tenant;
+
+#[derive(OpenApi)]
+#[openapi(paths(me, permissions, list_tenants, switch_tenant))]
+pub struct ApiDoc;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(me))
+        .route("/permissions", get(permissions))
+        .route("/tenants", get(list_tenants))
+        .route("/tenant", put(switch_tenant))
+}
+
+/// Who the request is authenticated as, and the organization and tenant it
+/// acts in.
+#[utoipa::path(get, path = "", responses((status = 200, description = "The caller", body = AuthContext)))]
+async fn me(auth: AuthContext) -> Json<AuthContext> {
+    Json(auth)
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct PermissionsQuery {
+    tenant_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct PermissionsResponse {
+    organization_id: Option<Uuid>,
+    tenant_id: Option<Uuid>,
+    role: Option<Role>,
+    permissions: Vec<Permission>,
+}
+
+/// The caller's effective role and permissions in a tenant: the one given
+/// by `tenant_id`, else the request's tenant, else its organization.
+#[utoipa::path(
+    get,
+    path = "/permissions",
+    params(PermissionsQuery),
+    responses((status = 200, description = "Role and permissions", body = PermissionsResponse))
+)]
+async fn permissions(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Query(query): Query<PermissionsQuery>,
+) -> Result<Json<PermissionsResponse>, ApiError> {
+    let tenant_id = query.tenant_id.or(auth.tenant_id);
+    let role = match tenant_id {
+        Some(tenant_id) => auth.role_in(&state, tenant_id).await?,
+        None => auth.org_role(&state, auth.organization()?).await?,
+    };
+
+    Ok(Json(PermissionsResponse {
+        organization_id: auth.organization_id,
+        tenant_id,
+        role,
+        permissions: role.map(|r| r.permissions()).unwrap_or_default(),
+    }))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct TenantResponse {
+    id: Uuid,
+    name: String,
+    slug: String,
+    organization_id: Option<Uuid>,
+    /// The request acts in this tenant.
+    current: bool,
+}
+
+impl TenantResponse {
+    fn new(tenant: Tenant, current: Option<Uuid>) -> Self {
+        Self {
+            current: current == Some(tenant.id),
+            id: tenant.id,
+            name: tenant.name,
+            slug: tenant.slug,
+            organization_id: tenant.organization_id,
+        }
+    }
+}
+
+/// Tenants the caller can act in.
+#[utoipa::path(
+    get,
+    path = "/tenants",
+    operation_id = "list_my_tenants",
+    responses((status = 200, description = "Tenants", body = Vec<TenantResponse>))
+)]
+async fn list_tenants(
+    State(state): State<AppState>,
+    auth: AuthContext,
+) -> Result<Json<Vec<TenantResponse>>, ApiError> {
+    let repo = &state.tenant_repo;
+    let tenants = match (auth.method, auth.user_id, auth.tenant_id) {
+        (AuthMethod::Anonymous, _, _) => repo.list().await?,
+        (AuthMethod::ApiKey, _, Some(tenant_id)) if auth.tenant_scoped => {
+            vec![repo.get_by_id(ResourceId::from_uuid(tenant_id)).await?]
+        }
+        (AuthMethod::ApiKey, _, _) => {
+            repo.list_by_organization(ResourceId::from_uuid(auth.organization()?))
+                .await?
+        }
+        (AuthMethod::Session, Some(user_id), _) => {
+            repo.list_for_user(ResourceId::from_uuid(user_id)).await?
+        }
+        (AuthMethod::Session, None, _) => Vec::new(),
+    };
+    Ok(Json(
+        tenants
+            .into_iter()
+            .map(|t| TenantResponse::new(t, auth.tenant_id))
+            .collect(),
+    ))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct SwitchTenantRequest {
+    /// Tenant ID or slug; `null` to switch back to no tenant.
+    tenant: Option<String>,
+}
+
+/// Switch the tenant the caller's session acts in when a request names
+/// none.
+#[utoipa::path(
+    put,
+    path = "/tenant",
+    request_body = SwitchTenantRequest,
+    responses((status = 200, description = "The tenant switched to, if any", body = Option<TenantResponse>))
+)]
+async fn switch_tenant(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Json(req): Json<SwitchTenantRequest>,
+) -> Result<Json<Option<TenantResponse>>, ApiError> {
+    let Some(session_id) = auth.session_id else {
+        return Err(ApiError::BadRequest(format!(
+            "Only sessions switch tenants; send the {} header instead",
+            TENANT_HEADER
+        )));
+    };
+    let tenant = match req.tenant.as_deref().filter(|t| !t.trim().is_empty()) {
+        Some(reference) => {
+            let tenant = find_tenant(&state, reference).await?;
+            if auth.role_in(&state, tenant.id).await?.is_none() {
+                return Err(ApiError::Forbidden(format!(
+                    "No access to tenant {}",
+                    tenant.slug
+                )));
+            }
+            Some(tenant)
+        }
+        None => None,
+    };
+
+    state
+        .organization_repo
+        .set_session_tenant(
+            ResourceId::from_uuid(session_id),
+            tenant.as_ref().map(|t| ResourceId::from_uuid(t.id)),
+        )
+        .await?;
+    Ok(Json(tenant.map(|t| {
+        let id = t.id;
+        TenantResponse::new(t, Some(id))
+    })))
+}
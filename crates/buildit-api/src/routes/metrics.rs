@@ -0,0 +1,32 @@
+//! Prometheus metrics endpoint.
+
+use axum::Router;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+
+use crate::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/metrics", get(metrics))
+}
+
+/// Every metric in the Prometheus text format.
+async fn metrics(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let Some(metrics) = state.metrics.clone() else {
+        return (StatusCode::NOT_FOUND, "metrics are not enabled").into_response();
+    };
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    if !metrics.authorizes(bearer) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics.render(&state).await,
+    )
+        .into_response()
+}
@@ -1,39 +1,100 @@
 //! API routes.
 
+pub mod analytics;
 pub mod applications;
+pub mod artifact_links;
+pub mod assets;
+pub mod audit;
 pub mod auth;
+pub mod badges;
+pub mod debug_sessions;
 pub mod deployment;
 pub mod health;
+pub mod invitations;
+pub mod me;
+pub mod metrics;
+pub mod modules;
+pub mod notifications;
+pub mod openapi;
+pub mod organizations;
 pub mod pipelines;
+pub mod plan_policies;
+pub mod reports;
 pub mod repositories;
+pub mod runners;
+pub mod runs;
+pub mod search;
+pub mod slack;
+pub mod stack_state;
 pub mod stacks;
 pub mod tenants;
 pub mod ui;
+pub mod variable_groups;
+pub mod webhook_subscriptions;
 pub mod webhooks;
 
 use crate::AppState;
+use crate::audit::record;
+use crate::auth::require_auth;
+use crate::sse::sse_handler;
 use crate::ws::ws_handler;
 use axum::Router;
+use axum::middleware;
 use axum::routing::get;
 
 /// Build the main API router.
 pub fn router(state: AppState) -> Router {
     Router::new()
         .merge(ui::router())
-        .nest("/api/v1", api_router())
+        .nest("/api/v1", api_router(state.clone()))
         .nest("/auth", auth::router())
         .nest("/webhooks", webhooks::router())
-        .route("/ws", get(ws_handler))
+        .nest("/reports", reports::router())
+        .nest("/artifacts", artifact_links::router())
+        .nest("/badge", badges::router())
+        .nest("/static", assets::router())
+        .route("/.well-known/terraform.json", get(modules::discovery))
+        .merge(events_router(state.clone()))
         .merge(health::router())
-        .with_state(state)
+        .merge(metrics::router())
+        .with_state(state.clone())
+        .merge(crate::grpc::router(state))
+}
+
+/// Live events over the WebSocket and server-sent events, authenticated by
+/// [`require_auth`]; the handlers filter them to the caller's tenant.
+fn events_router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/ws", get(ws_handler))
+        .route("/events", get(sse_handler))
+        .route_layer(middleware::from_fn_with_state(state, require_auth))
 }
 
-fn api_router() -> Router<AppState> {
+/// `/api/v1`, authenticated by [`require_auth`] and audited by [`record`]
+/// except for the runner endpoints, which check runner tokens themselves,
+/// and the API's OpenAPI document and reference.
+fn api_router(state: AppState) -> Router<AppState> {
     Router::new()
+        .nest("/me", me::router())
+        .nest("/audit-logs", audit::router())
+        .nest("/organizations", organizations::router())
+        .nest("/invitations", invitations::router())
+        .nest("/notifications", notifications::router())
+        .nest("/webhook-subscriptions", webhook_subscriptions::router())
+        .nest("/variable-groups", variable_groups::router())
         .nest("/tenants", tenants::router())
         .nest("/pipelines", pipelines::router())
+        .nest("/runs", runs::router())
+        .nest("/debug-sessions", debug_sessions::router())
+        .nest("/analytics", analytics::router())
+        .nest("/search", search::router())
         .nest("/repositories", repositories::router())
         .nest("/stacks", stacks::router())
+        .nest("/modules", modules::router())
         .nest("/applications", applications::router())
         .nest("/deployment", deployment::router())
+        .route_layer(middleware::from_fn_with_state(state.clone(), record))
+        .route_layer(middleware::from_fn_with_state(state, require_auth))
+        .nest("/runners", runners::router())
+        .merge(openapi::router())
 }
@@ -0,0 +1,227 @@
+//! Terraform module registry.
+//!
+//! Implements the module registry protocol so stacks can source modules as
+//! `buildit.example.com/<namespace>/<name>/<system>`. A module is a
+//! connected repository named `<namespace>/terraform-<system>-<name>`, as on
+//! the public registry, and its versions are the repository's semantic
+//! version tags (`v1.2.0` or `1.2.0`). Downloads point Terraform at the
+//! repository itself, at the tag of the version asked for.
+
+use axum::extract::{Path, State};
+use axum::http::{HeaderName, StatusCode};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use utoipa::{OpenApi, ToSchema};
+
+use crate::AppState;
+use crate::auth::AuthContext;
+use crate::error::ApiError;
+use crate::services::git::GitService;
+use buildit_core::ResourceId;
+use buildit_core::rbac::Permission;
+use buildit_core::repository::Repository;
+use buildit_db::RepositoryRepo;
+
+/// Header telling Terraform where to fetch a module version from.
+static TERRAFORM_GET: HeaderName = HeaderName::from_static("x-terraform-get");
+
+#[derive(OpenApi)]
+#[openapi(paths(list_versions, download_version))]
+pub struct ApiDoc;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/{namespace}/{name}/{system}/versions", get(list_versions))
+        .route(
+            "/{namespace}/{name}/{system}/{version}/download",
+            get(download_version),
+        )
+}
+
+/// Service discovery for Terraform, served at `/.well-known/terraform.json`.
+pub async fn discovery() -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "modules.v1": "/api/v1/modules/" }))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct VersionsResponse {
+    /// Always one entry, the module asked for.
+    modules: Vec<ModuleVersions>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct ModuleVersions {
+    versions: Vec<ModuleVersion>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct ModuleVersion {
+    version: String,
+}
+
+/// Versions of a module, one per semantic version tag of its repository.
+#[utoipa::path(
+    get,
+    path = "/{namespace}/{name}/{system}/versions",
+    params(
+        ("namespace" = String, Path, description = "Repository owner"),
+        ("name" = String, Path, description = "Module name"),
+        ("system" = String, Path, description = "Provider the module is for, e.g. aws"),
+    ),
+    responses((status = 200, description = "The module's versions", body = VersionsResponse))
+)]
+async fn list_versions(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((namespace, name, system)): Path<(String, String, String)>,
+) -> Result<Json<VersionsResponse>, ApiError> {
+    let repo = module_repository(&state, &auth, &namespace, &name, &system).await?;
+    let versions = module_versions(&repo).await?;
+    Ok(Json(VersionsResponse {
+        modules: vec![ModuleVersions {
+            versions: versions
+                .into_keys()
+                .map(|version| ModuleVersion { version })
+                .collect(),
+        }],
+    }))
+}
+
+/// Where to fetch a version of a module from, in the `X-Terraform-Get`
+/// header.
+#[utoipa::path(
+    get,
+    path = "/{namespace}/{name}/{system}/{version}/download",
+    params(
+        ("namespace" = String, Path, description = "Repository owner"),
+        ("name" = String, Path, description = "Module name"),
+        ("system" = String, Path, description = "Provider the module is for, e.g. aws"),
+        ("version" = String, Path, description = "Module version"),
+    ),
+    responses((status = 204, description = "Source address in the X-Terraform-Get header"))
+)]
+async fn download_version(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((namespace, name, system, version)): Path<(String, String, String, String)>,
+) -> Result<(StatusCode, [(HeaderName, String); 1]), ApiError> {
+    let repo = module_repository(&state, &auth, &namespace, &name, &system).await?;
+    let tag = module_versions(&repo)
+        .await?
+        .remove(&version)
+        .ok_or_else(|| {
+            ApiError::NotFound(format!(
+                "Module {}/{}/{} has no version {}",
+                namespace, name, system, version
+            ))
+        })?;
+    Ok((
+        StatusCode::NO_CONTENT,
+        [(
+            TERRAFORM_GET.clone(),
+            format!("git::{}?ref={}", repo.clone_url, urlencoding::encode(&tag)),
+        )],
+    ))
+}
+
+/// The repository of a module in the caller's organization.
+async fn module_repository(
+    state: &AppState,
+    auth: &AuthContext,
+    namespace: &str,
+    name: &str,
+    system: &str,
+) -> Result<Repository, ApiError> {
+    let org_id = auth.organization()?;
+    auth.require_org(state, org_id, Permission::RepositoryRead)
+        .await?;
+    let full_name = format!("{}/terraform-{}-{}", namespace, system, name);
+    state
+        .repository_repo
+        .get_by_full_name(ResourceId::from_uuid(org_id), &full_name)
+        .await?
+        .ok_or_else(|| {
+            ApiError::NotFound(format!(
+                "Module {}/{}/{} not found; it needs a connected repository named {}",
+                namespace, name, system, full_name
+            ))
+        })
+}
+
+/// A module's versions and the tags they come from.
+async fn module_versions(repo: &Repository) -> Result<BTreeMap<String, String>, ApiError> {
+    let tags = GitService::new().list_tags(repo).await.map_err(|e| {
+        ApiError::Internal(format!("Failed to list tags of {}: {}", repo.full_name, e))
+    })?;
+    Ok(tag_versions(tags))
+}
+
+/// The versions named by a repository's tags, each with the tag it comes
+/// from. Tags that are not semantic versions are left out.
+fn tag_versions(tags: Vec<String>) -> BTreeMap<String, String> {
+    let mut versions = BTreeMap::new();
+    for tag in tags {
+        if let Some(version) = module_version(&tag) {
+            // `v1.0.0` wins over `1.0.0`, whichever is listed first
+            versions
+                .entry(version.to_string())
+                .and_modify(|existing: &mut String| {
+                    if tag.starts_with('v') {
+                        *existing = tag.clone();
+                    }
+                })
+                .or_insert(tag);
+        }
+    }
+    versions
+}
+
+/// The semantic version a tag names, without its `v` prefix.
+fn module_version(tag: &str) -> Option<&str> {
+    let version = tag.strip_prefix('v').unwrap_or(tag);
+    let core = version.split(['-', '+']).next()?;
+    let parts: Vec<&str> = core.split('.').collect();
+    let numeric = parts.len() == 3
+        && parts
+            .iter()
+            .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()));
+    numeric.then_some(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_semantic_version_tags_are_module_versions() {
+        assert_eq!(module_version("v1.2.0"), Some("1.2.0"));
+        assert_eq!(module_version("1.2.0"), Some("1.2.0"));
+        assert_eq!(module_version("v2.0.0-rc.1"), Some("2.0.0-rc.1"));
+        assert_eq!(module_version("1.0.0+build.5"), Some("1.0.0+build.5"));
+        assert_eq!(module_version("v1.2"), None);
+        assert_eq!(module_version("1.2.x"), None);
+        assert_eq!(module_version("1..2"), None);
+        assert_eq!(module_version("release"), None);
+    }
+
+    #[test]
+    fn test_v_prefixed_tags_win() {
+        let tags = ["1.0.0", "v1.0.0", "v1.1.0", "latest", "1.2.0"]
+            .map(str::to_string)
+            .to_vec();
+        let versions = tag_versions(tags);
+        assert_eq!(
+            versions.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("1.0.0".to_string(), "v1.0.0".to_string()),
+                ("1.1.0".to_string(), "v1.1.0".to_string()),
+                ("1.2.0".to_string(), "1.2.0".to_string()),
+            ]
+        );
+
+        let reversed = tag_versions(vec!["v1.0.0".to_string(), "1.0.0".to_string()]);
+        assert_eq!(reversed["1.0.0"], "v1.0.0");
+    }
+}
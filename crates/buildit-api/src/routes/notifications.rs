@@ -0,0 +1,307 @@
+//! Notification channel endpoints for the tenant the request acts in.
+
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    routing::{get, post},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::{OpenApi, ToSchema};
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::audit::AuditBefore;
+use crate::auth::AuthContext;
+use crate::error::ApiError;
+use crate::services::notifications::{self, EVENTS, Notification};
+use buildit_core::ResourceId;
+use buildit_core::rbac::Permission;
+use buildit_db::{NotificationChannel, NotificationRepo};
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    list_events,
+    list_channels,
+    get_channel,
+    create_channel,
+    update_channel,
+    delete_channel,
+    test_channel
+))]
+pub struct ApiDoc;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/events", get(list_events))
+        .route("/channels", get(list_channels).post(create_channel))
+        .route(
+            "/channels/{id}",
+            get(get_channel).put(update_channel).delete(delete_channel),
+        )
+        .route("/channels/{id}/test", post(test_channel))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateChannelRequest {
+    pub name: String,
+    /// `slack`, `email` or `webhook`.
+    pub kind: String,
+    pub config: Value,
+    /// The system configuration's default events when left out.
+    #[serde(default)]
+    pub events: Option<Vec<String>>,
+    #[serde(default = "enabled_default")]
+    pub enabled: bool,
+}
+
+fn enabled_default() -> bool {
+    true
+}
+
+/// Changes to a channel; fields left out keep their value.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct UpdateChannelRequest {
+    pub name: Option<String>,
+    pub config: Option<Value>,
+    pub events: Option<Vec<String>>,
+    pub enabled: Option<bool>,
+    /// Replace a webhook channel's signing secret.
+    #[serde(default)]
+    pub rotate_secret: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ChannelResponse {
+    #[serde(flatten)]
+    pub channel: NotificationChannel,
+    /// A webhook channel's signing secret, shown only when it is created
+    /// or rotated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TestResponse {
+    pub delivered: bool,
+    pub error: Option<String>,
+}
+
+/// Events channels can subscribe to.
+#[utoipa::path(
+    get,
+    path = "/events",
+    operation_id = "list_notification_events",
+    responses((status = 200, description = "Event names", body = Vec<String>))
+)]
+async fn list_events() -> Json<&'static [&'static str]> {
+    Json(EVENTS)
+}
+
+/// The tenant's notification channels.
+#[utoipa::path(
+    get,
+    path = "/channels",
+    responses((status = 200, description = "Channels", body = Vec<NotificationChannel>))
+)]
+async fn list_channels(
+    State(state): State<AppState>,
+    auth: AuthContext,
+) -> Result<Json<Vec<NotificationChannel>>, ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::TenantRead)
+        .await?;
+    let channels = state
+        .notification_repo
+        .list_channels(ResourceId::from_uuid(tenant.id))
+        .await?;
+    Ok(Json(channels))
+}
+
+#[utoipa::path(
+    get,
+    path = "/channels/{id}",
+    params(("id" = Uuid, Path, description = "Channel ID")),
+    responses((status = 200, description = "The channel", body = NotificationChannel))
+)]
+async fn get_channel(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<NotificationChannel>, ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::TenantRead)
+        .await?;
+    let channel = state
+        .notification_repo
+        .get_channel(ResourceId::from_uuid(tenant.id), ResourceId::from_uuid(id))
+        .await?;
+    Ok(Json(channel))
+}
+
+/// Add a channel. Webhook channels get a signing secret, returned once.
+#[utoipa::path(
+    post,
+    path = "/channels",
+    request_body = CreateChannelRequest,
+    responses((status = 200, description = "The channel", body = ChannelResponse))
+)]
+async fn create_channel(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Json(req): Json<CreateChannelRequest>,
+) -> Result<Json<ChannelResponse>, ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::TenantManage)
+        .await?;
+
+    let name = req.name.trim();
+    if name.is_empty() {
+        return Err(ApiError::BadRequest("Channel name is required".to_string()));
+    }
+    notifications::validate_config(&req.kind, &req.config).map_err(ApiError::BadRequest)?;
+    let events = validate_events(
+        req.events
+            .unwrap_or_else(|| state.system_config.notifications.default_events.clone()),
+    )?;
+    let secret = (req.kind == "webhook").then(notifications::new_secret);
+
+    let channel = state
+        .notification_repo
+        .create_channel(&NotificationChannel {
+            id: Uuid::now_v7(),
+            tenant_id: tenant.id,
+            name: name.to_string(),
+            kind: req.kind,
+            config: req.config,
+            secret: secret.clone(),
+            events,
+            enabled: req.enabled,
+            created_by: auth.user_id,
+            last_delivery_at: None,
+            last_error: None,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        })
+        .await?;
+    tracing::info!(tenant = %tenant.slug, channel = %channel.name, kind = %channel.kind, "Notification channel created");
+    Ok(Json(ChannelResponse { channel, secret }))
+}
+
+/// Change a channel, or rotate a webhook channel's signing secret.
+#[utoipa::path(
+    put,
+    path = "/channels/{id}",
+    params(("id" = Uuid, Path, description = "Channel ID")),
+    request_body = UpdateChannelRequest,
+    responses((status = 200, description = "The channel", body = ChannelResponse))
+)]
+async fn update_channel(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    Json(req): Json<UpdateChannelRequest>,
+) -> Result<(AuditBefore, Json<ChannelResponse>), ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::TenantManage)
+        .await?;
+    let mut channel = state
+        .notification_repo
+        .get_channel(ResourceId::from_uuid(tenant.id), ResourceId::from_uuid(id))
+        .await?;
+    let before = AuditBefore::of(&channel);
+
+    if let Some(name) = req.name {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(ApiError::BadRequest("Channel name is required".to_string()));
+        }
+        channel.name = name.to_string();
+    }
+    if let Some(config) = req.config {
+        notifications::validate_config(&channel.kind, &config).map_err(ApiError::BadRequest)?;
+        channel.config = config;
+    }
+    if let Some(events) = req.events {
+        channel.events = validate_events(events)?;
+    }
+    if let Some(enabled) = req.enabled {
+        channel.enabled = enabled;
+    }
+    let mut secret = None;
+    if req.rotate_secret {
+        if channel.kind != "webhook" {
+            return Err(ApiError::BadRequest(
+                "Only webhook channels have a signing secret".to_string(),
+            ));
+        }
+        secret = Some(notifications::new_secret());
+        channel.secret = secret.clone();
+    }
+
+    let channel = state.notification_repo.update_channel(&channel).await?;
+    Ok((before, Json(ChannelResponse { channel, secret })))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/channels/{id}",
+    params(("id" = Uuid, Path, description = "Channel ID")),
+    responses((status = 200, description = "Deleted"))
+)]
+async fn delete_channel(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<(), ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::TenantManage)
+        .await?;
+    state
+        .notification_repo
+        .delete_channel(ResourceId::from_uuid(tenant.id), ResourceId::from_uuid(id))
+        .await?;
+    Ok(())
+}
+
+/// Send a test notification to the channel right away, without retries.
+#[utoipa::path(
+    post,
+    path = "/channels/{id}/test",
+    params(("id" = Uuid, Path, description = "Channel ID")),
+    responses((status = 200, description = "Whether it was delivered", body = TestResponse))
+)]
+async fn test_channel(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<TestResponse>, ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::TenantManage)
+        .await?;
+    let channel = state
+        .notification_repo
+        .get_channel(ResourceId::from_uuid(tenant.id), ResourceId::from_uuid(id))
+        .await?;
+    let notification = Notification::test(&state, &channel);
+    let error = notifications::deliver(&state, &channel, &notification)
+        .await
+        .err();
+    Ok(Json(TestResponse {
+        delivered: error.is_none(),
+        error,
+    }))
+}
+
+pub(crate) fn validate_events(mut events: Vec<String>) -> Result<Vec<String>, ApiError> {
+    if let Some(unknown) = events.iter().find(|e| !EVENTS.contains(&e.as_str())) {
+        return Err(ApiError::BadRequest(format!(
+            "Unknown event '{}'; expected one of {}",
+            unknown,
+            EVENTS.join(", ")
+        )));
+    }
+    events.sort();
+    events.dedup();
+    Ok(events)
+}
@@ -0,0 +1,175 @@
+//! OpenAPI document of the REST API, served at `/api/v1/openapi.json` with
+//! a Swagger UI at `/api/v1/docs`.
+//!
+//! Each route module documents its handlers in its own `ApiDoc`; they are
+//! nested here under the paths their routers are nested under.
+
+use askama::Template;
+use axum::{Json, Router, response::Html, routing::get};
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::openapi::{Content, Ref, RefOr, Response, ResponseBuilder};
+use utoipa::{Modify, OpenApi, ToSchema};
+
+use super::{
+    analytics, applications, audit, debug_sessions, deployment, invitations, me, modules,
+    notifications, organizations, pipelines, plan_policies, repositories, runners, runs, search,
+    stacks, tenants, variable_groups, webhook_subscriptions,
+};
+use crate::AppState;
+use crate::error::ErrorBody;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "BuildIt API",
+        description = "REST API of the BuildIt CI/CD server. Requests authenticate with an API key or token as a bearer token, or with a session cookie."
+    ),
+    nest(
+        (path = "/api/v1/me", api = me::ApiDoc, tags = ["me"]),
+        (path = "/api/v1/audit-logs", api = audit::ApiDoc, tags = ["audit"]),
+        (path = "/api/v1/organizations", api = organizations::ApiDoc, tags = ["organizations"]),
+        (path = "/api/v1/invitations", api = invitations::ApiDoc, tags = ["organizations"]),
+        (path = "/api/v1/notifications", api = notifications::ApiDoc, tags = ["notifications"]),
+        (path = "/api/v1/webhook-subscriptions", api = webhook_subscriptions::ApiDoc, tags = ["webhooks"]),
+        (path = "/api/v1/variable-groups", api = variable_groups::ApiDoc, tags = ["variables"]),
+        (path = "/api/v1/tenants", api = tenants::ApiDoc, tags = ["tenants"]),
+        (path = "/api/v1/pipelines", api = pipelines::ApiDoc, tags = ["pipelines"]),
+        (path = "/api/v1/runs", api = runs::ApiDoc, tags = ["pipelines"]),
+        (path = "/api/v1/debug-sessions", api = debug_sessions::ApiDoc, tags = ["pipelines"]),
+        (path = "/api/v1/analytics", api = analytics::ApiDoc, tags = ["analytics"]),
+        (path = "/api/v1/search", api = search::ApiDoc, tags = ["search"]),
+        (path = "/api/v1/repositories", api = repositories::ApiDoc, tags = ["repositories"]),
+        (path = "/api/v1/stacks", api = stacks::ApiDoc, tags = ["stacks"]),
+        (path = "/api/v1/stacks", api = plan_policies::ApiDoc, tags = ["stacks"]),
+        (path = "/api/v1/modules", api = modules::ApiDoc, tags = ["stacks"]),
+        (path = "/api/v1/applications", api = applications::ApiDoc, tags = ["applications"]),
+        (path = "/api/v1/deployment", api = deployment::ApiDoc, tags = ["deployment"]),
+        (path = "/api/v1/runners", api = runners::ApiDoc, tags = ["runners"]),
+    ),
+    components(schemas(ErrorBody)),
+    modifiers(&Security, &ErrorResponses),
+    security(("bearer" = []))
+)]
+pub struct ApiDoc;
+
+/// The bearer token scheme every operation is secured with.
+struct Security;
+
+impl Modify for Security {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+        );
+    }
+}
+
+/// Errors come back as an [`ErrorBody`] with the matching status.
+struct ErrorResponses;
+
+impl Modify for ErrorResponses {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let error: RefOr<Response> = ResponseBuilder::new()
+            .description("Error")
+            .content(
+                "application/json",
+                Content::new(Some(Ref::from_schema_name(ErrorBody::name()))),
+            )
+            .build()
+            .into();
+        for item in openapi.paths.paths.values_mut() {
+            for operation in [
+                &mut item.get,
+                &mut item.put,
+                &mut item.post,
+                &mut item.delete,
+                &mut item.patch,
+            ]
+            .into_iter()
+            .flatten()
+            {
+                operation
+                    .responses
+                    .responses
+                    .entry("default".to_string())
+                    .or_insert_with(|| error.clone());
+            }
+        }
+    }
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/openapi.json", get(spec))
+        .route("/docs", get(docs))
+}
+
+async fn spec() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+#[derive(Template)]
+#[template(path = "pages/api_docs.html")]
+struct ApiDocsTemplate;
+
+/// Swagger UI for the document.
+async fn docs() -> Html<String> {
+    Html(ApiDocsTemplate.render().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+
+    /// Every `$ref` in `value`.
+    fn refs<'a>(value: &'a Value, out: &mut Vec<&'a str>) {
+        match value {
+            Value::Object(map) => {
+                for (key, value) in map {
+                    match (key.as_str(), value) {
+                        ("$ref", Value::String(r)) => out.push(r),
+                        _ => refs(value, out),
+                    }
+                }
+            }
+            Value::Array(items) => items.iter().for_each(|v| refs(v, out)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn test_document_references_only_defined_schemas() {
+        let doc = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let schemas = &doc["components"]["schemas"];
+        let mut found = Vec::new();
+        refs(&doc, &mut found);
+        assert!(!found.is_empty());
+        for r in found {
+            let name = r
+                .strip_prefix("#/components/schemas/")
+                .unwrap_or_else(|| panic!("unexpected reference {}", r));
+            assert!(schemas.get(name).is_some(), "{} is not defined", r);
+        }
+    }
+
+    #[test]
+    fn test_every_operation_documents_errors() {
+        let doc = ApiDoc::openapi();
+        assert!(doc.paths.paths.contains_key("/api/v1/pipelines"));
+        for (path, item) in &doc.paths.paths {
+            for operation in [&item.get, &item.put, &item.post, &item.delete, &item.patch]
+                .into_iter()
+                .flatten()
+            {
+                assert!(
+                    operation.responses.responses.contains_key("default"),
+                    "{} has no error response",
+                    path
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,613 @@
+//! Organization routes: API key issuance and revocation, invitations, and
+//! the pipeline policy.
+
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::HeaderMap,
+    routing::{delete, get, post},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{OpenApi, ToSchema};
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::audit::AuditBefore;
+use crate::auth::{
+    API_KEY_LOOKUP_LEN, API_KEY_PREFIX, AuthContext, INVITATION_TOKEN_PREFIX, is_valid_scope,
+    scopes_role, token_hash,
+};
+use crate::error::ApiError;
+use crate::routes::auth::{public_url, random_token};
+use crate::services::email::{Email, html_escape};
+use buildit_core::ResourceId;
+use buildit_core::policy::PipelinePolicy;
+use buildit_core::rbac::{Permission, Role};
+use buildit_db::{ApiKey, DbError, OrgInvitation, Organization, OrganizationRepo};
+use buildit_scheduler::policy;
+
+/// How long an invitation link stays valid.
+const INVITATION_TTL_DAYS: i64 = 7;
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    list_api_keys,
+    create_api_key,
+    revoke_api_key,
+    list_invitations,
+    create_invitation,
+    resend_invitation,
+    revoke_invitation,
+    get_pipeline_policy,
+    update_pipeline_policy
+))]
+pub struct ApiDoc;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/{id}/api-keys", get(list_api_keys).post(create_api_key))
+        .route("/{id}/api-keys/{key_id}", delete(revoke_api_key))
+        .route(
+            "/{id}/invitations",
+            get(list_invitations).post(create_invitation),
+        )
+        .route(
+            "/{id}/invitations/{invitation_id}",
+            delete(revoke_invitation),
+        )
+        .route(
+            "/{id}/invitations/{invitation_id}/resend",
+            post(resend_invitation),
+        )
+        .route(
+            "/{id}/pipeline-policy",
+            get(get_pipeline_policy).put(update_pipeline_policy),
+        )
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    /// Defaults to `read`.
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    /// Restrict the key to one of the organization's tenants.
+    pub tenant_id: Option<Uuid>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreatedApiKeyResponse {
+    #[serde(flatten)]
+    pub key: ApiKey,
+    /// The secret, shown only in this response.
+    pub token: String,
+}
+
+/// The organization's API keys.
+#[utoipa::path(
+    get,
+    path = "/{id}/api-keys",
+    params(("id" = Uuid, Path, description = "Organization ID")),
+    responses((status = 200, description = "API keys", body = Vec<ApiKey>))
+)]
+async fn list_api_keys(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<ApiKey>>, ApiError> {
+    auth.require_org(&state, id, Permission::ApiKeyRead).await?;
+    let keys = state
+        .organization_repo
+        .list_api_keys(ResourceId::from_uuid(id))
+        .await?;
+    Ok(Json(keys))
+}
+
+/// Issue an API key. Only its hash is stored; the secret is returned once.
+#[utoipa::path(
+    post,
+    path = "/{id}/api-keys",
+    params(("id" = Uuid, Path, description = "Organization ID")),
+    request_body = CreateApiKeyRequest,
+    responses((status = 200, description = "The key and its secret", body = CreatedApiKeyResponse))
+)]
+async fn create_api_key(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    Json(req): Json<CreateApiKeyRequest>,
+) -> Result<Json<CreatedApiKeyResponse>, ApiError> {
+    auth.require_org(&state, id, Permission::ApiKeyWrite)
+        .await?;
+
+    let name = req.name.trim();
+    if name.is_empty() {
+        return Err(ApiError::BadRequest("API key name is required".to_string()));
+    }
+    let scopes = if req.scopes.is_empty() {
+        vec!["read".to_string()]
+    } else {
+        req.scopes
+    };
+    if let Some(scope) = scopes.iter().find(|s| !is_valid_scope(s)) {
+        return Err(ApiError::BadRequest(format!("Invalid scope '{}'", scope)));
+    }
+    if req.expires_at.is_some_and(|at| at <= Utc::now()) {
+        return Err(ApiError::BadRequest(
+            "expires_at must be in the future".to_string(),
+        ));
+    }
+
+    // A key may not act with more than its creator can
+    let role = match req.tenant_id {
+        Some(tenant_id) => {
+            let organization = state
+                .organization_repo
+                .get_tenant_organization(ResourceId::from_uuid(tenant_id))
+                .await?;
+            if organization != Some(id) {
+                return Err(ApiError::BadRequest(format!(
+                    "Tenant {} is not in organization {}",
+                    tenant_id, id
+                )));
+            }
+            auth.role_in(&state, tenant_id).await?
+        }
+        None => auth.org_role(&state, id).await?,
+    };
+    if role.is_none_or(|role| role < scopes_role(&scopes)) {
+        return Err(ApiError::Forbidden(
+            "Cannot issue an API key with more access than your own".to_string(),
+        ));
+    }
+
+    let token = random_token(API_KEY_PREFIX);
+    let key = state
+        .organization_repo
+        .create_api_key(
+            &ApiKey {
+                id: Uuid::new_v4(),
+                organization_id: id,
+                user_id: auth.user_id,
+                tenant_id: req.tenant_id,
+                name: name.to_string(),
+                key_prefix: token[..API_KEY_LOOKUP_LEN].to_string(),
+                scopes,
+                last_used_at: None,
+                expires_at: req.expires_at,
+                revoked_at: None,
+                created_at: Utc::now(),
+            },
+            &token_hash(&token),
+        )
+        .await?;
+
+    tracing::info!(organization = %id, key = %key.key_prefix, "Issued API key");
+    Ok(Json(CreatedApiKeyResponse { key, token }))
+}
+
+/// Revoke an API key.
+#[utoipa::path(
+    delete,
+    path = "/{id}/api-keys/{key_id}",
+    params(("id" = Uuid, Path, description = "Organization ID"), ("key_id" = Uuid, Path, description = "API key ID")),
+    responses((status = 200, description = "Revoked", body = Object, example = json!({"revoked": true})))
+)]
+async fn revoke_api_key(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((id, key_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    auth.require_org(&state, id, Permission::ApiKeyWrite)
+        .await?;
+    state
+        .organization_repo
+        .revoke_api_key(ResourceId::from_uuid(id), ResourceId::from_uuid(key_id))
+        .await?;
+
+    tracing::info!(organization = %id, key = %key_id, "Revoked API key");
+    Ok(Json(serde_json::json!({"revoked": true})))
+}
+
+/// Rules every pipeline of the organization's tenants must follow.
+#[utoipa::path(
+    get,
+    path = "/{id}/pipeline-policy",
+    params(("id" = Uuid, Path, description = "Organization ID")),
+    responses((status = 200, description = "The pipeline policy", body = PipelinePolicy))
+)]
+async fn get_pipeline_policy(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<PipelinePolicy>, ApiError> {
+    auth.require_org(&state, id, Permission::TenantRead).await?;
+    let org = state
+        .organization_repo
+        .get_organization(ResourceId::from_uuid(id))
+        .await?;
+    Ok(Json(org.pipeline_policy()))
+}
+
+/// Replace the pipeline policy. It is set by whoever may create tenants in
+/// the organization, so tenant admins cannot loosen it. Existing pipelines
+/// that break it keep their config, but their runs are refused.
+#[utoipa::path(
+    put,
+    path = "/{id}/pipeline-policy",
+    params(("id" = Uuid, Path, description = "Organization ID")),
+    request_body = PipelinePolicy,
+    responses((status = 200, description = "The pipeline policy", body = PipelinePolicy))
+)]
+async fn update_pipeline_policy(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    Json(pipeline_policy): Json<PipelinePolicy>,
+) -> Result<(AuditBefore, Json<PipelinePolicy>), ApiError> {
+    auth.require_org(&state, id, Permission::TenantCreate)
+        .await?;
+    policy::validate(&pipeline_policy).map_err(ApiError::BadRequest)?;
+    let org_id = ResourceId::from_uuid(id);
+    let before = AuditBefore::of(
+        &state
+            .organization_repo
+            .get_organization(org_id)
+            .await?
+            .pipeline_policy(),
+    );
+    let org = state
+        .organization_repo
+        .update_pipeline_policy(org_id, &pipeline_policy)
+        .await?;
+    tracing::info!(organization = %org.slug, "Pipeline policy updated");
+    Ok((before, Json(org.pipeline_policy())))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateInvitationRequest {
+    pub email: String,
+    /// `owner`, `admin` or `member`; defaults to `member`.
+    pub role: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SentInvitationResponse {
+    #[serde(flatten)]
+    pub invitation: OrgInvitation,
+    /// The invitee's link, to share by hand if the email did not go out.
+    pub url: String,
+    pub email_sent: bool,
+}
+
+/// The organization's pending invitations.
+#[utoipa::path(
+    get,
+    path = "/{id}/invitations",
+    params(("id" = Uuid, Path, description = "Organization ID")),
+    responses((status = 200, description = "Pending invitations", body = Vec<OrgInvitation>))
+)]
+async fn list_invitations(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<OrgInvitation>>, ApiError> {
+    auth.require_org(&state, id, Permission::MemberInvite)
+        .await?;
+    let invitations = state
+        .organization_repo
+        .list_pending_invitations(ResourceId::from_uuid(id))
+        .await?;
+    Ok(Json(invitations))
+}
+
+/// Invite someone by email. They join with the invitation's role once they
+/// accept it, signed in with any account.
+#[utoipa::path(
+    post,
+    path = "/{id}/invitations",
+    params(("id" = Uuid, Path, description = "Organization ID")),
+    request_body = CreateInvitationRequest,
+    responses((status = 200, description = "The invitation and its link", body = SentInvitationResponse))
+)]
+async fn create_invitation(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(req): Json<CreateInvitationRequest>,
+) -> Result<Json<SentInvitationResponse>, ApiError> {
+    auth.require_org(&state, id, Permission::MemberInvite)
+        .await?;
+
+    let email = req.email.trim();
+    if email.is_empty() || !email.contains('@') || email.contains(char::is_whitespace) {
+        return Err(ApiError::BadRequest(format!(
+            "'{}' is not an email address",
+            email
+        )));
+    }
+    let role = req.role.as_deref().unwrap_or("member");
+    let role = match Role::parse(role) {
+        Some(role) if role >= Role::Member => role,
+        _ => {
+            return Err(ApiError::BadRequest(format!(
+                "Invalid role '{}'; use owner, admin or member",
+                role
+            )));
+        }
+    };
+    if auth
+        .org_role(&state, id)
+        .await?
+        .is_none_or(|own| own < role)
+    {
+        return Err(ApiError::Forbidden(
+            "Cannot invite with a higher role than your own".to_string(),
+        ));
+    }
+
+    let repo = &state.organization_repo;
+    let org = repo.get_organization(ResourceId::from_uuid(id)).await?;
+    match repo.get_user_by_email(email).await {
+        Ok(user) => match repo
+            .get_org_membership(ResourceId::from_uuid(id), ResourceId::from_uuid(user.id))
+            .await
+        {
+            Ok(_) => {
+                return Err(ApiError::Conflict(format!("{} is already a member", email)));
+            }
+            Err(DbError::NotFound(_)) => {}
+            Err(e) => return Err(e.into()),
+        },
+        Err(DbError::NotFound(_)) => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let token = random_token(INVITATION_TOKEN_PREFIX);
+    let invitation = repo
+        .create_invitation(
+            &OrgInvitation {
+                id: Uuid::new_v4(),
+                organization_id: id,
+                email: email.to_string(),
+                role: role.as_str().to_string(),
+                invited_by: auth.user_id,
+                status: "pending".to_string(),
+                accepted_by: None,
+                expires_at: Utc::now() + chrono::Duration::days(INVITATION_TTL_DAYS),
+                responded_at: None,
+                created_at: Utc::now(),
+            },
+            &token_hash(&token),
+        )
+        .await?;
+
+    tracing::info!(organization = %id, invitation = %invitation.id, "Invited {}", email);
+    Ok(Json(
+        send_invitation(&state, &auth, &headers, &org, invitation, &token).await,
+    ))
+}
+
+/// Send a pending invitation again, with a new link and expiry.
+#[utoipa::path(
+    post,
+    path = "/{id}/invitations/{invitation_id}/resend",
+    params(("id" = Uuid, Path, description = "Organization ID"), ("invitation_id" = Uuid, Path, description = "Invitation ID")),
+    responses((status = 200, description = "The invitation and its new link", body = SentInvitationResponse))
+)]
+async fn resend_invitation(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    headers: HeaderMap,
+    Path((id, invitation_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<SentInvitationResponse>, ApiError> {
+    auth.require_org(&state, id, Permission::MemberInvite)
+        .await?;
+    let repo = &state.organization_repo;
+    let org = repo.get_organization(ResourceId::from_uuid(id)).await?;
+
+    let token = random_token(INVITATION_TOKEN_PREFIX);
+    let invitation = repo
+        .renew_invitation(
+            ResourceId::from_uuid(id),
+            ResourceId::from_uuid(invitation_id),
+            &token_hash(&token),
+            Utc::now() + chrono::Duration::days(INVITATION_TTL_DAYS),
+        )
+        .await?;
+    Ok(Json(
+        send_invitation(&state, &auth, &headers, &org, invitation, &token).await,
+    ))
+}
+
+/// Revoke a pending invitation.
+#[utoipa::path(
+    delete,
+    path = "/{id}/invitations/{invitation_id}",
+    params(("id" = Uuid, Path, description = "Organization ID"), ("invitation_id" = Uuid, Path, description = "Invitation ID")),
+    responses((status = 200, description = "Revoked", body = Object, example = json!({"revoked": true})))
+)]
+async fn revoke_invitation(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((id, invitation_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    auth.require_org(&state, id, Permission::MemberInvite)
+        .await?;
+    state
+        .organization_repo
+        .revoke_invitation(
+            ResourceId::from_uuid(id),
+            ResourceId::from_uuid(invitation_id),
+        )
+        .await?;
+
+    tracing::info!(organization = %id, invitation = %invitation_id, "Revoked invitation");
+    Ok(Json(serde_json::json!({"revoked": true})))
+}
+
+/// Email the invitee their link. A failed delivery leaves the invitation
+/// in place; the response carries the link to pass on some other way.
+async fn send_invitation(
+    state: &AppState,
+    auth: &AuthContext,
+    headers: &HeaderMap,
+    org: &Organization,
+    invitation: OrgInvitation,
+    token: &str,
+) -> SentInvitationResponse {
+    let url = format!("{}/invitations/{}", public_url(state, headers), token);
+    let inviter = match auth.user_id {
+        Some(user_id) => state
+            .organization_repo
+            .get_user(ResourceId::from_uuid(user_id))
+            .await
+            .map(|u| u.name)
+            .ok(),
+        None => None,
+    }
+    .unwrap_or_else(|| "An administrator".to_string());
+
+    let email = invitation_email(org, &invitation, &inviter, &url);
+    let email_sent = match state.email.send(&email).await {
+        Ok(()) => state.email.delivers(),
+        Err(e) => {
+            tracing::warn!(invitation = %invitation.id, "Failed to email invitation: {}", e);
+            false
+        }
+    };
+    SentInvitationResponse {
+        invitation,
+        url,
+        email_sent,
+    }
+}
+
+fn invitation_email(
+    org: &Organization,
+    invitation: &OrgInvitation,
+    inviter: &str,
+    url: &str,
+) -> Email {
+    let expires = invitation.expires_at.format("%B %-d, %Y");
+    Email {
+        to: invitation.email.clone(),
+        subject: format!("{} invited you to {} on BuildIt", inviter, org.name),
+        text: format!(
+            "{} invited you to join {} on BuildIt as {} {}.\n\n\
+             Accept or decline the invitation here:\n{}\n\n\
+             The link expires on {}.\n",
+            inviter,
+            org.name,
+            article(&invitation.role),
+            invitation.role,
+            url,
+            expires
+        ),
+        html: format!(
+            "<p>{} invited you to join <strong>{}</strong> on BuildIt as {} {}.</p>\
+             <p><a href=\"{}\">Accept or decline the invitation</a></p>\
+             <p>The link expires on {}.</p>",
+            html_escape(inviter),
+            html_escape(&org.name),
+            article(&invitation.role),
+            invitation.role,
+            url,
+            expires
+        ),
+    }
+}
+
+fn article(role: &str) -> &'static str {
+    if role.starts_with(['a', 'e', 'i', 'o', 'u']) {
+        "an"
+    } else {
+        "a"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::AuthMethod;
+
+    #[test]
+    fn test_api_key_scopes() {
+        assert!(is_valid_scope("read"));
+        assert!(is_valid_scope("pipelines:write"));
+        assert!(is_valid_scope("runners:register"));
+        assert!(!is_valid_scope("pipelines:admin"));
+        assert!(!is_valid_scope("Pipelines:read"));
+
+        let scopes = |s: &[&str]| s.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(scopes_role(&scopes(&["read"])), Role::Viewer);
+        assert_eq!(scopes_role(&scopes(&["stacks:write"])), Role::Member);
+        assert_eq!(scopes_role(&scopes(&["read", "admin"])), Role::Admin);
+    }
+
+    /// An API key of the organization `org_id` with `scopes`.
+    fn api_key(org_id: Uuid, scopes: &[&str], tenant_scoped: bool) -> AuthContext {
+        AuthContext {
+            method: AuthMethod::ApiKey,
+            user_id: None,
+            organization_id: Some(org_id),
+            tenant_id: tenant_scoped.then(Uuid::new_v4),
+            api_key_id: Some(Uuid::new_v4()),
+            scopes: Some(scopes.iter().map(|s| s.to_string()).collect()),
+            tenant_scoped,
+            session_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tenant_scoped_key_cannot_issue_keys() {
+        let org_id = Uuid::new_v4();
+        let auth = api_key(org_id, &["admin"], true);
+        let result = create_api_key(
+            State(AppState::without_database()),
+            auth,
+            Path(org_id),
+            Json(CreateApiKeyRequest {
+                name: "deploy".to_string(),
+                scopes: vec!["admin".to_string()],
+                tenant_id: None,
+                expires_at: None,
+            }),
+        )
+        .await;
+        assert!(matches!(result, Err(ApiError::Forbidden(_))));
+    }
+
+    #[tokio::test]
+    async fn test_invitations_cannot_exceed_the_inviters_role() {
+        let org_id = Uuid::new_v4();
+        let invite = |email: &str, role: &str| {
+            create_invitation(
+                State(AppState::without_database()),
+                api_key(org_id, &["admin"], false),
+                HeaderMap::new(),
+                Path(org_id),
+                Json(CreateInvitationRequest {
+                    email: email.to_string(),
+                    role: Some(role.to_string()),
+                }),
+            )
+        };
+
+        assert!(matches!(
+            invite("ada@example.com", "owner").await,
+            Err(ApiError::Forbidden(_))
+        ));
+        assert!(matches!(
+            invite("ada@example.com", "viewer").await,
+            Err(ApiError::BadRequest(_))
+        ));
+        assert!(matches!(
+            invite("not an address", "member").await,
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+}
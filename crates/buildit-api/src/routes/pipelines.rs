@@ -1,477 +1,1875 @@
 //! Pipeline management endpoints.
 
-use axum::extract::{Path, Query, State};
-use axum::routing::get;
+use axum::body::{Body, Bytes};
+use axum::extract::{DefaultBodyLimit, Path, Query, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
 use axum::{Json, Router};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use utoipa::{IntoParams, OpenApi, ToSchema};
 use uuid::Uuid;
 
 use crate::AppState;
+use crate::audit::AuditBefore;
+use crate::auth::AuthContext;
 use crate::error::ApiError;
-use buildit_config::VariableContextBuilder;
+use crate::routes::debug_sessions::DebugSessionResponse;
+use crate::services::approval_context::ApprovalContext;
+use crate::services::reports;
+use crate::services::run_comparison::RunComparison;
+use crate::services::{debug_sessions, log_archive, tasks, test_reports};
+use buildit_config::{
+    ResolvedVariable, SimulationResult, StageGraph, TriggerEvent, build_stage_graph, env,
+    resolve_params, simulate_pipeline,
+};
 use buildit_core::ResourceId;
-use buildit_core::executor::GitCloneSpec;
-use buildit_core::pipeline::Pipeline;
-use buildit_db::{LogRepo, PipelineRepo, RepositoryRepo};
+use buildit_core::artifact::{ArtifactDependency, ArtifactKey, ArtifactKind, ArtifactPreview};
+use buildit_core::child_pipeline::TriggerPipelineSpec;
+use buildit_core::executor::{Platform, PullPolicy, SecurityContext, WaitCondition};
+use buildit_core::pipeline::{
+    ContainerOptions, Ownership, PipelineParam, Stage, StageAction, Trigger,
+};
+use buildit_core::rbac::Permission;
+use buildit_core::release::ReleaseSpec;
+use buildit_core::scan::{ScanSpec, Severity};
+use buildit_core::tenant::{PolicyDrift, QuotaAction};
+use buildit_core::test_report::TestStatus;
+use buildit_db::repo::pipeline::{PipelineRecord, PipelineRunRecord, StageResultRecord};
+use buildit_db::{
+    ArtifactRecord, ArtifactRepo, DbError, FlakyTest, OrganizationRepo, PgOrganizationRepo,
+    PgPipelineRepo, PgTenantRepo, PipelineRepo, ReleaseRecord, ReleaseRepo, ReportFile,
+    ReportRecord, ScanFindingRecord, ScanFindingRepo, ScanSummary, TenantRepo, TestResultRecord,
+    TestResultRepo, TestSummary, VariableGroupRepo,
+};
+use buildit_scheduler::policy;
+use buildit_scheduler::quota::JobResources;
+use buildit_scheduler::rerun::{self, RerunMode};
+use buildit_scheduler::test_report;
+use chrono::Utc;
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    list_pipelines,
+    create_pipeline,
+    search_pipelines,
+    get_pipeline,
+    update_owners,
+    archive_pipeline,
+    unarchive_pipeline,
+    get_policy_drift,
+    get_pipeline_graph,
+    simulate_conditions,
+    list_runs,
+    trigger_run,
+    rerun_run,
+    archive_run,
+    unarchive_run,
+    debug_stage,
+    latest_branch_run,
+    list_run_stages,
+    get_run_logs,
+    list_artifacts,
+    upload_artifact,
+    download_artifact,
+    preview_artifact,
+    pin_artifact,
+    unpin_artifact,
+    list_reports,
+    get_run_tests,
+    list_flaky_tests,
+    list_releases,
+    get_run_findings,
+    get_run_variables,
+    get_approval_context,
+    compare_runs
+))]
+pub struct ApiDoc;
 
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/", get(list_pipelines).post(create_pipeline))
+        .route("/search", get(search_pipelines))
         .route("/{id}", get(get_pipeline))
+        .route("/{id}/owners", put(update_owners))
+        .route("/{id}/policy-drift", get(get_policy_drift))
+        .route("/{id}/archive", post(archive_pipeline))
+        .route("/{id}/unarchive", post(unarchive_pipeline))
+        .route("/{id}/graph", get(get_pipeline_graph))
+        .route("/{id}/simulate", post(simulate_conditions))
         .route("/{id}/runs", get(list_runs).post(trigger_run))
+        .route("/{id}/runs/compare", get(compare_runs))
+        .route("/{id}/branches/{branch}/latest", get(latest_branch_run))
+        .route("/{id}/runs/{run_id}/rerun", post(rerun_run))
+        .route("/{id}/runs/{run_id}/archive", post(archive_run))
+        .route("/{id}/runs/{run_id}/unarchive", post(unarchive_run))
+        .route("/{id}/runs/{run_id}/stages", get(list_run_stages))
+        .route(
+            "/{id}/runs/{run_id}/stages/{stage}/debug",
+            post(debug_stage),
+        )
         .route("/{id}/runs/{run_id}/logs", get(get_run_logs))
+        .route(
+            "/{id}/runs/{run_id}/artifacts",
+            get(list_artifacts)
+                .post(upload_artifact)
+                .layer(DefaultBodyLimit::max(ARTIFACT_UPLOAD_LIMIT)),
+        )
+        .route("/{id}/runs/{run_id}/reports", get(list_reports))
+        .route("/{id}/runs/{run_id}/tests", get(get_run_tests))
+        .route("/{id}/tests/flaky", get(list_flaky_tests))
+        .route("/{id}/releases", get(list_releases))
+        .route("/{id}/runs/{run_id}/findings", get(get_run_findings))
+        .route("/{id}/runs/{run_id}/variables", get(get_run_variables))
+        .route(
+            "/{id}/runs/{run_id}/artifacts/{artifact_id}",
+            get(download_artifact),
+        )
+        .route(
+            "/{id}/runs/{run_id}/artifacts/{artifact_id}/preview",
+            get(preview_artifact),
+        )
+        .route(
+            "/{id}/runs/{run_id}/artifacts/{artifact_id}/pin",
+            post(pin_artifact).delete(unpin_artifact),
+        )
+        .route(
+            "/{id}/runs/{run_id}/approval-context",
+            get(get_approval_context),
+        )
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct ListPipelinesQuery {
-    tenant_id: Uuid,
+    /// Defaults to the request's tenant.
+    tenant_id: Option<Uuid>,
+    /// Only pipelines owned (at pipeline or stage level) by this owner.
+    owner: Option<String>,
+    /// List archived pipelines instead of active ones.
+    #[serde(default)]
+    archived: bool,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 struct PipelineResponse {
     id: String,
     name: String,
     repository: String,
+    labels: Vec<String>,
+    owners: Vec<String>,
+    archived_at: Option<String>,
+}
+
+impl From<PipelineRecord> for PipelineResponse {
+    fn from(p: PipelineRecord) -> Self {
+        Self {
+            id: p.id.to_string(),
+            name: p.name,
+            repository: p.repository,
+            labels: p.labels,
+            owners: p.owner_list,
+            archived_at: p.archived_at.map(|t| t.to_rfc3339()),
+        }
+    }
 }
 
+#[utoipa::path(
+    get,
+    path = "",
+    params(ListPipelinesQuery),
+    responses((status = 200, description = "Pipelines", body = Vec<PipelineResponse>))
+)]
 async fn list_pipelines(
     State(state): State<AppState>,
+    auth: AuthContext,
     Query(query): Query<ListPipelinesQuery>,
 ) -> Result<Json<Vec<PipelineResponse>>, ApiError> {
-    let tenant_id = ResourceId::from_uuid(query.tenant_id);
-    let pipelines = state.pipeline_repo.list_by_tenant(tenant_id).await?;
-    let response: Vec<PipelineResponse> = pipelines
-        .into_iter()
-        .map(|p| PipelineResponse {
-            id: p.id.to_string(),
-            name: p.name,
-            repository: p.repository,
-        })
-        .collect();
+    let tenant_id = auth.tenant_or(&state, query.tenant_id).await?;
+    auth.require(&state, tenant_id, Permission::PipelineRead)
+        .await?;
+    let tenant_id = ResourceId::from_uuid(tenant_id);
+    let pipelines = match (query.archived, query.owner.as_deref()) {
+        (true, owner) => state
+            .pipeline_repo
+            .list_archived(tenant_id)
+            .await?
+            .into_iter()
+            .filter(|p| owner.is_none_or(|o| p.owner_list.iter().any(|x| x == o)))
+            .collect(),
+        (false, Some(owner)) => state.pipeline_repo.list_by_owner(tenant_id, owner).await?,
+        (false, None) => state.pipeline_repo.list_by_tenant(tenant_id).await?,
+    };
+    let response: Vec<PipelineResponse> =
+        pipelines.into_iter().map(PipelineResponse::from).collect();
     Ok(Json(response))
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 struct CreatePipelineRequest {
-    tenant_id: Uuid,
+    /// Defaults to the request's tenant.
+    tenant_id: Option<Uuid>,
     name: String,
     repository: String,
     config: serde_json::Value,
+    #[serde(default)]
+    labels: Vec<String>,
+    /// Pipeline and stage owners; defaults to `ownership` in the config.
+    #[serde(default)]
+    owners: Option<Ownership>,
 }
 
+#[utoipa::path(
+    post,
+    path = "",
+    request_body = CreatePipelineRequest,
+    responses((status = 200, description = "The pipeline", body = PipelineResponse))
+)]
 async fn create_pipeline(
     State(state): State<AppState>,
+    auth: AuthContext,
     Json(req): Json<CreatePipelineRequest>,
 ) -> Result<Json<PipelineResponse>, ApiError> {
-    let tenant_id = ResourceId::from_uuid(req.tenant_id);
+    let tenant_id = auth.tenant_or(&state, req.tenant_id).await?;
+    auth.require(&state, tenant_id, Permission::PipelineWrite)
+        .await?;
+    let tenant_id = ResourceId::from_uuid(tenant_id);
+
+    // Start from the tenant's policy defaults for whatever the config leaves out
+    let mut config = req.config.clone();
+    let applied = state
+        .tenant_repo
+        .get_by_id(tenant_id)
+        .await?
+        .policy()
+        .apply(&mut config);
+    if !applied.is_empty() {
+        tracing::info!(pipeline = %req.name, applied = ?applied, "Applied tenant policy defaults");
+    }
+
+    let stages = StageDefinitions::from_config(&req.config)?;
+    declared_params(&req.config)?;
+    check_pipeline_policy(&state, tenant_id, &stages.stages()).await?;
+
     let pipeline = state
         .pipeline_repo
-        .create(tenant_id, &req.name, &req.repository, req.config.clone())
+        .create(tenant_id, &req.name, &req.repository, config)
         .await?;
 
-    // Extract and create stage definitions from config
     let pipeline_id = ResourceId::from_uuid(pipeline.id);
-    if let Some(stages) = req.config.get("stages").and_then(|s| s.as_array()) {
-        for stage in stages {
-            let name = stage
-                .get("name")
-                .and_then(|n| n.as_str())
-                .unwrap_or("unnamed");
-            let image = stage.get("image").and_then(|i| i.as_str());
-            let commands: Vec<String> = stage
-                .get("commands")
-                .and_then(|c| c.as_array())
-                .map(|arr| {
-                    arr.iter()
-                        .filter_map(|v| v.as_str().map(String::from))
-                        .collect()
-                })
-                .unwrap_or_default();
-            let depends_on: Vec<String> = stage
-                .get("depends_on")
-                .and_then(|d| d.as_array())
-                .map(|arr| {
-                    arr.iter()
-                        .filter_map(|v| v.as_str().map(String::from))
-                        .collect()
-                })
-                .unwrap_or_default();
-            let env = stage.get("env").cloned().unwrap_or(serde_json::json!({}));
-            let timeout = stage
-                .get("timeout_seconds")
-                .and_then(|t| t.as_i64())
-                .map(|t| t as i32);
+    if !req.labels.is_empty() {
+        state
+            .pipeline_repo
+            .update_labels(pipeline_id, &req.labels)
+            .await?;
+    }
 
-            if let Err(e) = state
-                .pipeline_repo
-                .create_stage(
-                    pipeline_id,
-                    name,
-                    image,
-                    &commands,
-                    &depends_on,
-                    env,
-                    timeout,
-                )
-                .await
-            {
-                tracing::error!(error = %e, stage = %name, "Failed to create stage definition");
-            }
-        }
+    let ownership = req.owners.clone().unwrap_or_else(|| {
+        req.config
+            .get("ownership")
+            .cloned()
+            .and_then(|o| serde_json::from_value(o).ok())
+            .unwrap_or_default()
+    });
+    if !ownership.is_empty() {
+        state
+            .pipeline_repo
+            .update_owners(pipeline_id, &ownership)
+            .await?;
     }
 
+    // Extract and create stage definitions from config
+    stages.save(&state.pipeline_repo, pipeline_id).await;
+
     Ok(Json(PipelineResponse {
         id: pipeline.id.to_string(),
         name: pipeline.name,
         repository: pipeline.repository,
+        labels: req.labels,
+        owners: ownership.all_owners(),
+        archived_at: None,
     }))
 }
 
-async fn get_pipeline(
-    State(state): State<AppState>,
-    Path(id): Path<Uuid>,
-) -> Result<Json<PipelineResponse>, ApiError> {
-    let pipeline = state
-        .pipeline_repo
-        .get_by_id(ResourceId::from_uuid(id))
-        .await?;
-    Ok(Json(PipelineResponse {
-        id: pipeline.id.to_string(),
-        name: pipeline.name,
-        repository: pipeline.repository,
-    }))
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SearchPipelinesQuery {
+    /// Defaults to the request's tenant.
+    tenant_id: Option<Uuid>,
+    #[serde(default)]
+    q: String,
+    limit: Option<i64>,
 }
 
-#[derive(Debug, Serialize)]
-struct RunResponse {
+#[derive(Debug, Serialize, ToSchema)]
+struct PipelineSearchResponse {
     id: String,
-    number: i64,
-    status: String,
+    name: String,
+    repository: String,
+    labels: Vec<String>,
+    last_run_status: Option<String>,
+    last_run_at: Option<String>,
+    score: f64,
 }
 
-async fn list_runs(
+#[utoipa::path(
+    get,
+    path = "/search",
+    params(SearchPipelinesQuery),
+    responses((status = 200, description = "Matches, best first", body = Vec<PipelineSearchResponse>))
+)]
+async fn search_pipelines(
     State(state): State<AppState>,
-    Path(id): Path<Uuid>,
-) -> Result<Json<Vec<RunResponse>>, ApiError> {
-    let runs = state
+    auth: AuthContext,
+    Query(query): Query<SearchPipelinesQuery>,
+) -> Result<Json<Vec<PipelineSearchResponse>>, ApiError> {
+    let tenant_id = ResourceId::from_uuid(auth.tenant_or(&state, query.tenant_id).await?);
+    auth.require(&state, *tenant_id.as_uuid(), Permission::PipelineRead)
+        .await?;
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+
+    let results = state
         .pipeline_repo
-        .list_runs(ResourceId::from_uuid(id), 20)
+        .search(tenant_id, query.q.trim(), limit)
         .await?;
-    let response: Vec<RunResponse> = runs
+    let response = results
         .into_iter()
-        .map(|r| RunResponse {
+        .map(|r| PipelineSearchResponse {
             id: r.id.to_string(),
-            number: r.number,
-            status: r.status,
+            name: r.name,
+            repository: r.repository,
+            labels: r.labels,
+            last_run_status: r.last_run_status,
+            last_run_at: r.last_run_at.map(|t| t.to_rfc3339()),
+            score: r.score,
         })
         .collect();
     Ok(Json(response))
 }
 
-#[derive(Debug, Deserialize)]
-struct TriggerRunRequest {
-    branch: Option<String>,
-    sha: Option<String>,
+#[utoipa::path(
+    get,
+    path = "/{id}",
+    params(("id" = Uuid, Path, description = "Pipeline ID")),
+    responses((status = 200, description = "The pipeline", body = PipelineResponse))
+)]
+async fn get_pipeline(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<PipelineResponse>, ApiError> {
+    let pipeline = authorized_pipeline(&state, &auth, id, Permission::PipelineRead).await?;
+    Ok(Json(pipeline.into()))
 }
 
-async fn trigger_run(
+/// Replace a pipeline's owners.
+#[utoipa::path(
+    put,
+    path = "/{id}/owners",
+    params(("id" = Uuid, Path, description = "Pipeline ID")),
+    request_body = Ownership,
+    responses((status = 200, description = "The owners", body = Ownership))
+)]
+async fn update_owners(
     State(state): State<AppState>,
+    auth: AuthContext,
     Path(id): Path<Uuid>,
-    Json(req): Json<TriggerRunRequest>,
-) -> Result<Json<RunResponse>, ApiError> {
-    let trigger_info = serde_json::json!({
-        "kind": "manual"
-    });
-    let git_info = serde_json::json!({
-        "branch": req.branch.clone().unwrap_or_default(),
-        "sha": req.sha.clone().unwrap_or_default(),
-        "short_sha": "",
-        "message": "",
-        "author": ""
-    });
-
-    // Create the run record
-    let run = state
+    Json(ownership): Json<Ownership>,
+) -> Result<(AuditBefore, Json<Ownership>), ApiError> {
+    let pipeline_id = ResourceId::from_uuid(id);
+    let pipeline = authorized_pipeline(&state, &auth, id, Permission::PipelineWrite).await?;
+    if pipeline.is_archived() {
+        return Err(ApiError::Conflict(format!(
+            "pipeline {} is archived",
+            pipeline.name
+        )));
+    }
+    state
         .pipeline_repo
-        .create_run(ResourceId::from_uuid(id), trigger_info, git_info)
+        .update_owners(pipeline_id, &ownership)
         .await?;
+    Ok((AuditBefore::of(&pipeline.ownership()), Json(ownership)))
+}
 
-    // Get the pipeline config
-    let pipeline_record = state
+/// Archive a pipeline: it keeps its runs but can no longer be triggered,
+/// and is purged with them once it has been archived for the retention
+/// window.
+#[utoipa::path(
+    post,
+    path = "/{id}/archive",
+    params(("id" = Uuid, Path, description = "Pipeline ID")),
+    responses((status = 200, description = "The pipeline", body = PipelineResponse))
+)]
+async fn archive_pipeline(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<PipelineResponse>, ApiError> {
+    authorized_pipeline(&state, &auth, id, Permission::PipelineWrite).await?;
+    let pipeline = state
         .pipeline_repo
-        .get_by_id(ResourceId::from_uuid(id))
+        .set_archived(ResourceId::from_uuid(id), true)
         .await?;
+    tracing::info!(pipeline = %pipeline.name, "Pipeline archived");
+    Ok(Json(pipeline.into()))
+}
 
-    // Load stages from pipeline_stages table
-    let stage_records = state
+#[utoipa::path(
+    post,
+    path = "/{id}/unarchive",
+    params(("id" = Uuid, Path, description = "Pipeline ID")),
+    responses((status = 200, description = "The pipeline", body = PipelineResponse))
+)]
+async fn unarchive_pipeline(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<PipelineResponse>, ApiError> {
+    authorized_pipeline(&state, &auth, id, Permission::PipelineWrite).await?;
+    let pipeline = state
         .pipeline_repo
-        .list_stages(ResourceId::from_uuid(id))
+        .set_archived(ResourceId::from_uuid(id), false)
         .await?;
+    tracing::info!(pipeline = %pipeline.name, "Pipeline unarchived");
+    Ok(Json(pipeline.into()))
+}
 
-    // Convert stage records to Stage structs
-    let stages: Vec<buildit_core::pipeline::Stage> = stage_records
-        .into_iter()
-        .map(|s| {
-            let env: HashMap<String, String> = serde_json::from_value(s.env).unwrap_or_default();
-            buildit_core::pipeline::Stage {
-                name: s.name,
-                needs: s.depends_on,
-                when: None,
-                manual: false,
-                action: buildit_core::pipeline::StageAction::Run {
-                    image: s.image.unwrap_or_else(|| "alpine:latest".to_string()),
-                    commands: s.commands,
-                    artifacts: vec![],
-                },
-                env,
-            }
+/// The stage definitions of a pipeline config, checked before anything is
+/// created and then stored in `pipeline_stages`.
+pub(crate) struct StageDefinitions {
+    stages: Vec<serde_json::Value>,
+    platforms: Vec<Platform>,
+    scans: Vec<Option<serde_json::Value>>,
+    waits: Vec<serde_json::Value>,
+    releases: Vec<Option<serde_json::Value>>,
+    triggers: Vec<Option<serde_json::Value>>,
+    needs_artifacts: Vec<serde_json::Value>,
+    security_contexts: Vec<Option<serde_json::Value>>,
+    containers: Vec<serde_json::Value>,
+    pull_policies: Vec<Option<PullPolicy>>,
+}
+
+impl StageDefinitions {
+    pub(crate) fn from_config(config: &serde_json::Value) -> Result<Self, ApiError> {
+        Ok(Self {
+            stages: config
+                .get("stages")
+                .and_then(|s| s.as_array())
+                .cloned()
+                .unwrap_or_default(),
+            platforms: stage_platforms(config)?,
+            scans: stage_scans(config)?,
+            waits: stage_wait_for(config)?,
+            releases: stage_releases(config)?,
+            triggers: stage_trigger_pipelines(config)?,
+            needs_artifacts: stage_needs_artifacts(config)?,
+            security_contexts: stage_security_contexts(config)?,
+            containers: stage_containers(config)?,
+            pull_policies: stage_pull_policies(config)?,
         })
-        .collect();
+    }
 
-    // Parse env and triggers from config JSON
-    let config = &pipeline_record.config;
-    let env: HashMap<String, String> =
-        serde_json::from_value(config.get("env").cloned().unwrap_or_default()).unwrap_or_default();
-    let triggers: Vec<buildit_core::pipeline::Trigger> =
-        serde_json::from_value(config.get("triggers").cloned().unwrap_or_default())
-            .unwrap_or_default();
-
-    // Build Pipeline struct
-    let pipeline = Pipeline {
-        id: ResourceId::from_uuid(pipeline_record.id),
-        name: pipeline_record.name.clone(),
-        tenant_id: ResourceId::from_uuid(pipeline_record.tenant_id),
-        repository: pipeline_record.repository.clone(),
-        triggers,
-        stages,
-        env,
-        caches: vec![],
-    };
+    /// The stages the definitions describe, as a run of the pipeline would
+    /// load them.
+    pub(crate) fn stages(&self) -> Vec<Stage> {
+        if let Ok(stages) =
+            serde_json::from_value::<Vec<Stage>>(serde_json::Value::Array(self.stages.clone()))
+        {
+            return stages;
+        }
+        fn decode<T: serde::de::DeserializeOwned>(value: &Option<serde_json::Value>) -> Option<T> {
+            value.clone().and_then(|v| serde_json::from_value(v).ok())
+        }
+        self.stages
+            .iter()
+            .enumerate()
+            .map(|(i, stage)| {
+                let field = |key: &str| stage.get(key).cloned().unwrap_or_default();
+                let action = match (
+                    decode(&self.scans[i]),
+                    decode(&self.releases[i]),
+                    decode(&self.triggers[i]),
+                ) {
+                    (Some(scan), _, _) => StageAction::Scan(Box::new(scan)),
+                    (None, Some(release), _) => StageAction::Release(Box::new(release)),
+                    (None, None, Some(trigger)) => StageAction::TriggerPipeline(Box::new(trigger)),
+                    (None, None, None) => StageAction::Run {
+                        image: stage
+                            .get("image")
+                            .and_then(|i| i.as_str())
+                            .unwrap_or("alpine:latest")
+                            .to_string(),
+                        commands: serde_json::from_value(field("commands")).unwrap_or_default(),
+                        artifacts: vec![],
+                        test_reports: serde_json::from_value(field("test_reports"))
+                            .unwrap_or_default(),
+                    },
+                };
+                Stage {
+                    name: stage
+                        .get("name")
+                        .and_then(|n| n.as_str())
+                        .unwrap_or("unnamed")
+                        .to_string(),
+                    needs: serde_json::from_value(field("depends_on")).unwrap_or_default(),
+                    when: None,
+                    manual: false,
+                    action,
+                    env: serde_json::from_value(field("env")).unwrap_or_default(),
+                    runs_on: serde_json::from_value(field("runs_on")).unwrap_or_default(),
+                    resources: serde_json::from_value(field("resources")).unwrap_or_default(),
+                    platform: self.platforms[i],
+                    wait_for: serde_json::from_value(self.waits[i].clone()).unwrap_or_default(),
+                    needs_artifacts: serde_json::from_value(self.needs_artifacts[i].clone())
+                        .unwrap_or_default(),
+                    security_context: decode(&self.security_contexts[i]),
+                    container: serde_json::from_value(self.containers[i].clone())
+                        .unwrap_or_default(),
+                    pull_policy: self.pull_policies[i],
+                }
+            })
+            .collect()
+    }
 
-    // Get repository clone URL if pipeline is linked to a repository
-    let git_clone_spec = if let Some(repo_id) = pipeline_record.repository_id {
-        match state
-            .repository_repo
-            .get_by_id(ResourceId::from_uuid(repo_id))
-            .await
+    /// Store the definitions of a pipeline that has none.
+    pub(crate) async fn save(self, pipeline_repo: &PgPipelineRepo, pipeline_id: ResourceId) {
+        for (
+            (
+                (
+                    ((((((stage, platform), scan), wait_for), release), trigger), needs_artifacts),
+                    security_context,
+                ),
+                container,
+            ),
+            pull_policy,
+        ) in self
+            .stages
+            .iter()
+            .zip(&self.platforms)
+            .zip(self.scans)
+            .zip(self.waits)
+            .zip(self.releases)
+            .zip(self.triggers)
+            .zip(self.needs_artifacts)
+            .zip(self.security_contexts)
+            .zip(self.containers)
+            .zip(self.pull_policies)
         {
-            Ok(repo) => {
-                let branch = req.branch.clone();
-                let sha = req.sha.clone();
-                Some(GitCloneSpec {
-                    url: repo.clone_url,
-                    branch,
-                    sha,
-                    depth: Some(1), // Shallow clone for CI
-                    target_dir: "/workspace".to_string(),
-                    access_token: None, // TODO: Get from repository credentials
-                })
-            }
-            Err(e) => {
-                tracing::warn!(error = %e, "Failed to get repository for pipeline, skipping git clone");
-                None
+            let name = stage
+                .get("name")
+                .and_then(|n| n.as_str())
+                .unwrap_or("unnamed");
+            let strings = |key: &str| -> Vec<String> {
+                stage
+                    .get(key)
+                    .and_then(|v| v.as_array())
+                    .map(|arr| {
+                        arr.iter()
+                            .filter_map(|v| v.as_str().map(String::from))
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            };
+            let image = stage.get("image").and_then(|i| i.as_str());
+            let env = stage.get("env").cloned().unwrap_or(serde_json::json!({}));
+            let timeout = stage
+                .get("timeout_seconds")
+                .and_then(|t| t.as_i64())
+                .map(|t| t as i32);
+            let resources = stage
+                .get("resources")
+                .cloned()
+                .unwrap_or(serde_json::json!({}));
+
+            if let Err(e) = pipeline_repo
+                .create_stage(
+                    pipeline_id,
+                    name,
+                    image,
+                    &strings("commands"),
+                    &strings("depends_on"),
+                    env,
+                    timeout,
+                    &strings("runs_on"),
+                    resources,
+                    &platform.to_string(),
+                    &strings("test_reports"),
+                    scan,
+                    wait_for,
+                    release,
+                    trigger,
+                    needs_artifacts,
+                    security_context,
+                    container,
+                    pull_policy.as_ref().map(PullPolicy::as_str),
+                )
+                .await
+            {
+                tracing::error!(error = %e, stage = %name, "Failed to create stage definition");
             }
         }
-    } else {
-        None
+    }
+}
+
+/// Platform of each stage in a pipeline config, refusing ones that name
+/// an unknown OS or architecture.
+fn stage_platforms(config: &serde_json::Value) -> Result<Vec<Platform>, ApiError> {
+    let Some(stages) = config.get("stages").and_then(|s| s.as_array()) else {
+        return Ok(vec![]);
     };
+    stages
+        .iter()
+        .map(
+            |stage| match stage.get("platform").and_then(|p| p.as_str()) {
+                Some(platform) => platform.parse().map_err(|e| {
+                    let name = stage
+                        .get("name")
+                        .and_then(|n| n.as_str())
+                        .unwrap_or("unnamed");
+                    ApiError::BadRequest(format!("Invalid platform of stage '{}': {}", name, e))
+                }),
+                None => Ok(Platform::default()),
+            },
+        )
+        .collect()
+}
 
-    // Execute pipeline in background (if orchestrator is available)
-    let orchestrator = state.orchestrator.clone();
-    let pipeline_repo = state.pipeline_repo.clone();
-    let log_repo = state.log_repo.clone();
-    let broadcaster = state.broadcaster.clone();
-    let run_id = ResourceId::from_uuid(run.id);
-    let run_id_str = run.id.to_string();
+/// When each stage of a pipeline config pulls its image, refusing unknown
+/// policies.
+fn stage_pull_policies(config: &serde_json::Value) -> Result<Vec<Option<PullPolicy>>, ApiError> {
+    let Some(stages) = config.get("stages").and_then(|s| s.as_array()) else {
+        return Ok(vec![]);
+    };
+    stages
+        .iter()
+        .map(
+            |stage| match stage.get("pull_policy").and_then(|p| p.as_str()) {
+                Some(policy) => policy.parse().map(Some).map_err(|e| {
+                    let name = stage
+                        .get("name")
+                        .and_then(|n| n.as_str())
+                        .unwrap_or("unnamed");
+                    ApiError::BadRequest(format!("Invalid pull policy of stage '{}': {}", name, e))
+                }),
+                None => Ok(None),
+            },
+        )
+        .collect()
+}
 
-    if let Some(orchestrator) = orchestrator {
-        let stage_names: Vec<String> = pipeline.stages.iter().map(|s| s.name.clone()).collect();
+/// What each stage of a pipeline config scans, for scan stages, checked
+/// before anything is created.
+fn stage_scans(config: &serde_json::Value) -> Result<Vec<Option<serde_json::Value>>, ApiError> {
+    let Some(stages) = config.get("stages").and_then(|s| s.as_array()) else {
+        return Ok(vec![]);
+    };
+    stages
+        .iter()
+        .map(|stage| match stage.get("scan").filter(|s| !s.is_null()) {
+            Some(scan) => {
+                serde_json::from_value::<ScanSpec>(scan.clone()).map_err(|e| {
+                    let name = stage
+                        .get("name")
+                        .and_then(|n| n.as_str())
+                        .unwrap_or("unnamed");
+                    ApiError::BadRequest(format!("Invalid scan of stage '{}': {}", name, e))
+                })?;
+                Ok(Some(scan.clone()))
+            }
+            None => Ok(None),
+        })
+        .collect()
+}
 
-        tokio::spawn(async move {
-            tracing::info!(run_id = %run_id, "Starting pipeline execution");
+/// What each stage of a pipeline config publishes, for release stages,
+/// checked before anything is created.
+fn stage_releases(config: &serde_json::Value) -> Result<Vec<Option<serde_json::Value>>, ApiError> {
+    let Some(stages) = config.get("stages").and_then(|s| s.as_array()) else {
+        return Ok(vec![]);
+    };
+    stages
+        .iter()
+        .map(
+            |stage| match stage.get("release").filter(|r| !r.is_null()) {
+                Some(release) => {
+                    serde_json::from_value::<ReleaseSpec>(release.clone()).map_err(|e| {
+                        let name = stage
+                            .get("name")
+                            .and_then(|n| n.as_str())
+                            .unwrap_or("unnamed");
+                        ApiError::BadRequest(format!("Invalid release of stage '{}': {}", name, e))
+                    })?;
+                    Ok(Some(release.clone()))
+                }
+                None => Ok(None),
+            },
+        )
+        .collect()
+}
 
-            // Create stage result records for all stages upfront
-            for stage_name in &stage_names {
-                if let Err(e) = pipeline_repo.create_stage_result(run_id, stage_name).await {
-                    tracing::error!(error = %e, stage = %stage_name, "Failed to create stage result");
+/// What each stage of a pipeline config starts, for trigger-pipeline
+/// stages, checked before anything is created.
+fn stage_trigger_pipelines(
+    config: &serde_json::Value,
+) -> Result<Vec<Option<serde_json::Value>>, ApiError> {
+    let Some(stages) = config.get("stages").and_then(|s| s.as_array()) else {
+        return Ok(vec![]);
+    };
+    stages
+        .iter()
+        .map(
+            |stage| match stage.get("trigger_pipeline").filter(|t| !t.is_null()) {
+                Some(trigger) => {
+                    serde_json::from_value::<TriggerPipelineSpec>(trigger.clone()).map_err(
+                        |e| {
+                            let name = stage
+                                .get("name")
+                                .and_then(|n| n.as_str())
+                                .unwrap_or("unnamed");
+                            ApiError::BadRequest(format!(
+                                "Invalid trigger_pipeline of stage '{}': {}",
+                                name, e
+                            ))
+                        },
+                    )?;
+                    Ok(Some(trigger.clone()))
                 }
-            }
+                None => Ok(None),
+            },
+        )
+        .collect()
+}
 
-            // Set run status to running
-            if let Err(e) = pipeline_repo.update_run_status(run_id, "running").await {
-                tracing::error!(error = %e, "Failed to update run status to running");
-                return;
+/// Artifacts of other pipelines each stage of a pipeline config needs,
+/// checked before anything is created.
+fn stage_needs_artifacts(config: &serde_json::Value) -> Result<Vec<serde_json::Value>, ApiError> {
+    let Some(stages) = config.get("stages").and_then(|s| s.as_array()) else {
+        return Ok(vec![]);
+    };
+    stages
+        .iter()
+        .map(|stage| {
+            let name = stage
+                .get("name")
+                .and_then(|n| n.as_str())
+                .unwrap_or("unnamed");
+            let Some(needs) = stage.get("needs_artifacts").filter(|n| !n.is_null()) else {
+                return Ok(serde_json::json!([]));
+            };
+            let dependencies = serde_json::from_value::<Vec<ArtifactDependency>>(needs.clone())
+                .map_err(|e| {
+                    ApiError::BadRequest(format!(
+                        "Invalid needs_artifacts of stage '{}': {}",
+                        name, e
+                    ))
+                })?;
+            for dependency in &dependencies {
+                let path = dependency.destination();
+                if path.starts_with('/') || path.split(['/', '\\']).any(|part| part == "..") {
+                    return Err(ApiError::BadRequest(format!(
+                        "Artifact {} of stage '{}' must go to a path inside the workspace",
+                        dependency.artifact, name
+                    )));
+                }
             }
+            Ok(needs.clone())
+        })
+        .collect()
+}
 
-            // Build environment
-            let mut env = HashMap::new();
-            env.insert("CI".to_string(), "true".to_string());
-            env.insert("BUILDIT".to_string(), "true".to_string());
-
-            // Build variable context for interpolation
-            // Extract git info from JSON
-            let git_branch = run
-                .git_info
-                .get("branch")
-                .and_then(|v| v.as_str())
-                .unwrap_or_default()
-                .to_string();
-            let git_sha = run
-                .git_info
-                .get("sha")
-                .and_then(|v| v.as_str())
-                .unwrap_or_default()
-                .to_string();
-
-            let var_ctx = VariableContextBuilder::new()
-                .with_pipeline(pipeline.id.to_string(), pipeline.name.clone())
-                .with_run(run_id.to_string(), run.number as u32)
-                .with_git_branch(git_branch)
-                .with_git_sha(git_sha)
-                .build();
-
-            // Execute with git clone if repository is linked
-            tracing::info!(run_id = %run_id, "Executing pipeline with {} stages", pipeline.stages.len());
-            let (event_rx, result_handle) =
-                orchestrator.execute_with_git(&pipeline, env, Some(var_ctx), git_clone_spec);
-
-            // Process events and update stage results in database
-            let mut event_rx = event_rx;
-            let repo_clone = pipeline_repo.clone();
-            let log_repo_clone = log_repo.clone();
-            let broadcaster_clone = broadcaster.clone();
-            while let Some(event) = event_rx.recv().await {
-                match event {
-                    buildit_scheduler::PipelineEvent::StageStarted { stage } => {
-                        tracing::info!(run_id = %run_id, stage = %stage, "Stage started");
-                        if let Err(e) = repo_clone
-                            .update_stage_result_started(run_id, &stage, None)
-                            .await
-                        {
-                            tracing::error!(error = %e, "Failed to update stage start");
-                        }
-                        // Broadcast stage started event
-                        broadcaster_clone.send(crate::ws::BroadcastEvent::StageUpdate {
-                            run_id: run_id_str.clone(),
-                            stage_name: stage.clone(),
-                            status: "running".to_string(),
-                            duration: None,
-                        });
-                    }
-                    buildit_scheduler::PipelineEvent::StageCompleted { stage, success } => {
-                        let status = if success { "succeeded" } else { "failed" };
-                        let error_msg = if success { None } else { Some("Stage failed") };
-                        tracing::info!(run_id = %run_id, stage = %stage, status = %status, "Stage completed");
-                        if let Err(e) = repo_clone
-                            .update_stage_result_finished(run_id, &stage, status, error_msg)
-                            .await
-                        {
-                            tracing::error!(error = %e, "Failed to update stage finish");
-                        }
-                        // Broadcast stage completed event
-                        broadcaster_clone.send(crate::ws::BroadcastEvent::StageUpdate {
-                            run_id: run_id_str.clone(),
-                            stage_name: stage.clone(),
-                            status: status.to_string(),
-                            duration: None, // TODO: calculate duration
-                        });
-                    }
-                    buildit_scheduler::PipelineEvent::StageLog { stage, line } => {
-                        // Store log line to database
-                        let stream = match line.stream {
-                            buildit_core::executor::LogStream::Stdout => "stdout",
-                            buildit_core::executor::LogStream::Stderr => "stderr",
-                            buildit_core::executor::LogStream::System => "system",
-                        };
-                        if let Err(e) = log_repo_clone
-                            .append_log(run_id, &stage, stream, &line.content)
-                            .await
-                        {
-                            tracing::error!(error = %e, "Failed to store log line");
-                        }
-                        // Broadcast log line event
-                        broadcaster_clone.send(crate::ws::BroadcastEvent::LogLine {
-                            run_id: run_id_str.clone(),
-                            stage_name: stage.clone(),
-                            content: line.content.clone(),
-                            stream: stream.to_string(),
-                        });
-                    }
-                    buildit_scheduler::PipelineEvent::PipelineCompleted { success } => {
-                        tracing::info!(run_id = %run_id, success = %success, "Pipeline completed");
-                        // Broadcast run completion event
-                        let status = if success { "succeeded" } else { "failed" };
-                        broadcaster_clone.send(crate::ws::BroadcastEvent::RunUpdate {
-                            run_id: run_id_str.clone(),
-                            status: status.to_string(),
-                        });
-                    }
+/// Security context overrides of each stage of a pipeline config, checked
+/// before anything is created. Whether the tenant allows them is checked
+/// with its stage security policy.
+fn stage_security_contexts(
+    config: &serde_json::Value,
+) -> Result<Vec<Option<serde_json::Value>>, ApiError> {
+    let Some(stages) = config.get("stages").and_then(|s| s.as_array()) else {
+        return Ok(vec![]);
+    };
+    stages
+        .iter()
+        .map(
+            |stage| match stage.get("security_context").filter(|c| !c.is_null()) {
+                Some(context) => {
+                    serde_json::from_value::<SecurityContext>(context.clone()).map_err(|e| {
+                        let name = stage
+                            .get("name")
+                            .and_then(|n| n.as_str())
+                            .unwrap_or("unnamed");
+                        ApiError::BadRequest(format!(
+                            "Invalid security_context of stage '{}': {}",
+                            name, e
+                        ))
+                    })?;
+                    Ok(Some(context.clone()))
                 }
-            }
+                None => Ok(None),
+            },
+        )
+        .collect()
+}
+
+/// How each stage of a pipeline config starts its container, checked
+/// before anything is created. Whether the tenant allows it is checked with
+/// its stage security policy.
+fn stage_containers(config: &serde_json::Value) -> Result<Vec<serde_json::Value>, ApiError> {
+    let Some(stages) = config.get("stages").and_then(|s| s.as_array()) else {
+        return Ok(vec![]);
+    };
+    stages
+        .iter()
+        .map(|stage| {
+            let Some(container) = stage.get("container").filter(|c| !c.is_null()) else {
+                return Ok(serde_json::json!({}));
+            };
+            serde_json::from_value::<ContainerOptions>(container.clone()).map_err(|e| {
+                let name = stage
+                    .get("name")
+                    .and_then(|n| n.as_str())
+                    .unwrap_or("unnamed");
+                ApiError::BadRequest(format!("Invalid container of stage '{}': {}", name, e))
+            })?;
+            Ok(container.clone())
+        })
+        .collect()
+}
+
+/// What each stage of a pipeline config waits for before its commands run,
+/// checked before anything is created.
+fn stage_wait_for(config: &serde_json::Value) -> Result<Vec<serde_json::Value>, ApiError> {
+    let Some(stages) = config.get("stages").and_then(|s| s.as_array()) else {
+        return Ok(vec![]);
+    };
+    stages
+        .iter()
+        .map(
+            |stage| match stage.get("wait_for").filter(|w| !w.is_null()) {
+                Some(wait_for) => {
+                    serde_json::from_value::<Vec<WaitCondition>>(wait_for.clone()).map_err(
+                        |e| {
+                            let name = stage
+                                .get("name")
+                                .and_then(|n| n.as_str())
+                                .unwrap_or("unnamed");
+                            ApiError::BadRequest(format!(
+                                "Invalid wait_for of stage '{}': {}",
+                                name, e
+                            ))
+                        },
+                    )?;
+                    Ok(wait_for.clone())
+                }
+                None => Ok(serde_json::json!([])),
+            },
+        )
+        .collect()
+}
+
+/// Parameters a pipeline config declares, refusing malformed ones.
+pub(crate) fn declared_params(config: &serde_json::Value) -> Result<Vec<PipelineParam>, ApiError> {
+    match config.get("params").filter(|p| !p.is_null()) {
+        Some(params) => serde_json::from_value(params.clone())
+            .map_err(|e| ApiError::BadRequest(format!("Invalid params: {}", e))),
+        None => Ok(vec![]),
+    }
+}
 
-            let result = result_handle.await.expect("Pipeline execution task failed");
+/// Load a pipeline's stage definitions.
+///
+/// Prefers the full stage list stored in the pipeline config (which keeps
+/// conditions, matrix and parallel stages) and falls back to the flat
+/// `pipeline_stages` table.
+pub(crate) async fn load_stage_definitions(
+    state: &AppState,
+    pipeline: &PipelineRecord,
+) -> Result<Vec<Stage>, ApiError> {
+    if let Some(stages) = pipeline
+        .config
+        .get("stages")
+        .and_then(|s| serde_json::from_value::<Vec<Stage>>(s.clone()).ok())
+    {
+        return Ok(stages);
+    }
 
-            // Update final status
-            let status = if result.success {
-                tracing::info!(run_id = %run_id, "Pipeline succeeded");
-                "succeeded"
-            } else {
-                tracing::warn!(run_id = %run_id, "Pipeline failed");
-                "failed"
+    let records = state
+        .pipeline_repo
+        .list_stages(ResourceId::from_uuid(pipeline.id))
+        .await?;
+    Ok(records
+        .into_iter()
+        .map(|s| {
+            let wait_for = s.wait_for();
+            let needs_artifacts = s.needs_artifacts();
+            let security_context = s.security_context();
+            let container = s.container();
+            let pull_policy = s.pull_policy();
+            let action = match (s.scan(), s.release(), s.trigger_pipeline()) {
+                (Some(scan), _, _) => StageAction::Scan(Box::new(scan)),
+                (None, Some(release), _) => StageAction::Release(Box::new(release)),
+                (None, None, Some(trigger)) => StageAction::TriggerPipeline(Box::new(trigger)),
+                (None, None, None) => StageAction::Run {
+                    image: s.image.unwrap_or_else(|| "alpine:latest".to_string()),
+                    commands: s.commands,
+                    artifacts: vec![],
+                    test_reports: s.test_reports,
+                },
             };
-            if let Err(e) = pipeline_repo.update_run_status(run_id, status).await {
-                tracing::error!(error = %e, "Failed to update run status to {}", status);
+            Stage {
+                name: s.name,
+                needs: s.depends_on,
+                when: None,
+                manual: false,
+                action,
+                env: serde_json::from_value(s.env).unwrap_or_default(),
+                runs_on: s.runs_on,
+                resources: serde_json::from_value(s.resources).unwrap_or_default(),
+                platform: s.platform.parse().unwrap_or_default(),
+                wait_for,
+                needs_artifacts,
+                security_context,
+                container,
+                pull_policy,
             }
-        });
-    } else {
-        tracing::warn!(run_id = %run_id, "Orchestrator unavailable - run created but not executed");
+        })
+        .collect())
+}
+
+/// Names of the stages, including those nested in parallel and matrix stages.
+pub(crate) fn stage_names(stages: &[Stage]) -> Vec<String> {
+    let mut names = Vec::new();
+    for stage in stages {
+        names.push(stage.name.clone());
+        match &stage.action {
+            StageAction::Parallel { stages } => names.extend(stage_names(stages)),
+            StageAction::Matrix { stage, .. } => {
+                names.extend(stage_names(std::slice::from_ref(stage.as_ref())))
+            }
+            _ => {}
+        }
     }
+    names
+}
 
-    Ok(Json(RunResponse {
-        id: run.id.to_string(),
-        number: run.number,
-        status: "pending".to_string(),
-    }))
+/// How the pipeline deviates from its tenant's policy template.
+#[utoipa::path(
+    get,
+    path = "/{id}/policy-drift",
+    params(("id" = Uuid, Path, description = "Pipeline ID")),
+    responses((status = 200, description = "Deviations from the policy", body = Vec<PolicyDrift>))
+)]
+async fn get_policy_drift(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<PolicyDrift>>, ApiError> {
+    let pipeline = authorized_pipeline(&state, &auth, id, Permission::PipelineRead).await?;
+    let policy = state
+        .tenant_repo
+        .get_by_id(ResourceId::from_uuid(pipeline.tenant_id))
+        .await?
+        .policy();
+    let stages = load_stage_definitions(&state, &pipeline).await?;
+    Ok(Json(policy.drift(&pipeline.config, &stage_names(&stages))))
 }
 
-#[derive(Debug, Deserialize)]
-struct GetLogsQuery {
-    stage: Option<String>,
-    offset: Option<i64>,
-    limit: Option<i64>,
+#[utoipa::path(
+    get,
+    path = "/{id}/graph",
+    params(("id" = Uuid, Path, description = "Pipeline ID")),
+    responses((status = 200, description = "The stage graph", body = StageGraph))
+)]
+async fn get_pipeline_graph(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<StageGraph>, ApiError> {
+    let pipeline = authorized_pipeline(&state, &auth, id, Permission::PipelineRead).await?;
+    let stages = load_stage_definitions(&state, &pipeline).await?;
+    Ok(Json(build_stage_graph(&stages)))
 }
 
-#[derive(Debug, Serialize)]
-struct LogEntry {
-    id: String,
-    stage_name: String,
-    timestamp: String,
-    stream: String,
-    content: String,
+#[derive(Debug, Deserialize, ToSchema)]
+struct SimulateRequest {
+    /// KDL config to test; defaults to the stored pipeline definition.
+    config: Option<String>,
+    /// Trigger events to replay; defaults to the pipeline's recent runs.
+    events: Option<Vec<TriggerEvent>>,
+    limit: Option<i64>,
 }
 
-#[derive(Debug, Serialize)]
-struct LogsResponse {
-    logs: Vec<LogEntry>,
-    has_more: bool,
+#[derive(Debug, Serialize, ToSchema)]
+struct SimulationResponse {
+    /// Run number the event was taken from, for historical events.
+    run_number: Option<i64>,
+    #[serde(flatten)]
+    result: SimulationResult,
 }
 
-async fn get_run_logs(
+/// Report which stages would have run for each trigger event.
+#[utoipa::path(
+    post,
+    path = "/{id}/simulate",
+    params(("id" = Uuid, Path, description = "Pipeline ID")),
+    request_body = SimulateRequest,
+    responses((status = 200, description = "One result per event", body = Vec<SimulationResponse>))
+)]
+async fn simulate_conditions(
     State(state): State<AppState>,
-    Path((_pipeline_id, run_id)): Path<(Uuid, Uuid)>,
-    Query(query): Query<GetLogsQuery>,
-) -> Result<Json<LogsResponse>, ApiError> {
-    let run_id = ResourceId::from_uuid(run_id);
-    let offset = query.offset.unwrap_or(0);
-    let limit = query.limit.unwrap_or(500).min(1000); // Cap at 1000 lines
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    Json(req): Json<SimulateRequest>,
+) -> Result<Json<Vec<SimulationResponse>>, ApiError> {
+    let pipeline = authorized_pipeline(&state, &auth, id, Permission::PipelineRead).await?;
 
-    let logs = state
-        .log_repo
-        .get_logs_paginated(run_id, query.stage.as_deref(), offset, limit + 1)
-        .await?;
+    let (triggers, stages) = match &req.config {
+        Some(kdl) => {
+            let parsed = buildit_config::pipeline::parse_pipeline(kdl)
+                .map_err(|e| ApiError::BadRequest(format!("Invalid pipeline config: {}", e)))?;
+            (parsed.triggers, parsed.stages)
+        }
+        None => {
+            let triggers = pipeline
+                .config
+                .get("triggers")
+                .and_then(|t| serde_json::from_value::<Vec<Trigger>>(t.clone()).ok())
+                .unwrap_or_default();
+            (triggers, load_stage_definitions(&state, &pipeline).await?)
+        }
+    };
 
-    // Check if there are more logs
+    let events: Vec<(Option<i64>, TriggerEvent)> = match req.events {
+        Some(events) => events.into_iter().map(|e| (None, e)).collect(),
+        None => {
+            let limit = req.limit.unwrap_or(20).clamp(1, 100);
+            state
+                .pipeline_repo
+                .list_runs(ResourceId::from_uuid(id), limit)
+                .await?
+                .into_iter()
+                .map(|r| {
+                    let event = trigger_event_from_run(&r.trigger_info, &r.git_info);
+                    (Some(r.number), event)
+                })
+                .collect()
+        }
+    };
+
+    let response = events
+        .into_iter()
+        .map(|(run_number, event)| SimulationResponse {
+            run_number,
+            result: simulate_pipeline(&triggers, &stages, &event),
+        })
+        .collect();
+    Ok(Json(response))
+}
+
+/// Rebuild the trigger event of a past run from its stored trigger/git info.
+fn trigger_event_from_run(
+    trigger_info: &serde_json::Value,
+    git_info: &serde_json::Value,
+) -> TriggerEvent {
+    let text = |v: &serde_json::Value, key: &str| {
+        v.get(key)
+            .and_then(|s| s.as_str())
+            .filter(|s| !s.is_empty())
+            .map(String::from)
+    };
+    let tag = text(git_info, "tag");
+    let mut kind = text(trigger_info, "kind").unwrap_or_else(|| "manual".to_string());
+    if kind == "push" && tag.is_some() {
+        kind = "tag".to_string();
+    }
+
+    TriggerEvent {
+        kind,
+        branch: text(git_info, "branch"),
+        tag,
+        actor: text(trigger_info, "actor"),
+        changed_files: git_info
+            .get("changed_files")
+            .and_then(|f| serde_json::from_value(f.clone()).ok()),
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RunResponse {
+    id: String,
+    number: i64,
+    status: String,
+    created_at: String,
+    started_at: Option<String>,
+    finished_at: Option<String>,
+    /// Seconds from start to finish, or so far while running.
+    duration_secs: Option<f64>,
+    /// Seconds spent queued before starting, or so far while queued.
+    queued_secs: Option<f64>,
+    archived_at: Option<String>,
+}
+
+impl From<PipelineRunRecord> for RunResponse {
+    fn from(r: PipelineRunRecord) -> Self {
+        Self {
+            id: r.id.to_string(),
+            number: r.number,
+            status: r.status,
+            created_at: r.created_at.to_rfc3339(),
+            started_at: r.started_at.map(|t| t.to_rfc3339()),
+            finished_at: r.finished_at.map(|t| t.to_rfc3339()),
+            duration_secs: r.duration_secs,
+            queued_secs: r.queued_secs,
+            archived_at: r.archived_at.map(|t| t.to_rfc3339()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ListRunsQuery {
+    /// List archived runs, most recently archived first, instead of the
+    /// others.
+    #[serde(default)]
+    archived: bool,
+}
+
+#[utoipa::path(
+    get,
+    path = "/{id}/runs",
+    operation_id = "list_pipeline_runs",
+    params(("id" = Uuid, Path, description = "Pipeline ID"), ListRunsQuery),
+    responses((status = 200, description = "Runs, newest first", body = Vec<RunResponse>))
+)]
+async fn list_runs(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    Query(query): Query<ListRunsQuery>,
+) -> Result<Json<Vec<RunResponse>>, ApiError> {
+    authorized_pipeline(&state, &auth, id, Permission::PipelineRead).await?;
+    let pipeline_id = ResourceId::from_uuid(id);
+    let runs = if query.archived {
+        state
+            .pipeline_repo
+            .list_archived_runs(pipeline_id, 20)
+            .await?
+    } else {
+        state.pipeline_repo.list_runs(pipeline_id, 20).await?
+    };
+    Ok(Json(runs.into_iter().map(Into::into).collect()))
+}
+
+/// Archive a finished run: it is kept, but left out of run lists, and
+/// purged for good once it has been archived for the retention window.
+#[utoipa::path(
+    post,
+    path = "/{id}/runs/{run_id}/archive",
+    params(("id" = Uuid, Path, description = "Pipeline ID"), ("run_id" = Uuid, Path, description = "Run ID")),
+    responses((status = 200, description = "The run", body = RunResponse))
+)]
+async fn archive_run(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((id, run_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<RunResponse>, ApiError> {
+    pipeline_run(&state, &auth, id, run_id, Permission::PipelineWrite).await?;
+    let run = state
+        .pipeline_repo
+        .set_run_archived(ResourceId::from_uuid(run_id), true)
+        .await?;
+    tracing::info!(run_id = %run.id, number = run.number, "Run archived");
+    Ok(Json(run.into()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/{id}/runs/{run_id}/unarchive",
+    params(("id" = Uuid, Path, description = "Pipeline ID"), ("run_id" = Uuid, Path, description = "Run ID")),
+    responses((status = 200, description = "The run", body = RunResponse))
+)]
+async fn unarchive_run(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((id, run_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<RunResponse>, ApiError> {
+    pipeline_run(&state, &auth, id, run_id, Permission::PipelineWrite).await?;
+    let run = state
+        .pipeline_repo
+        .set_run_archived(ResourceId::from_uuid(run_id), false)
+        .await?;
+    tracing::info!(run_id = %run.id, number = run.number, "Run unarchived");
+    Ok(Json(run.into()))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct LatestRunQuery {
+    /// Only consider runs with this status, e.g. `succeeded`.
+    status: Option<String>,
+}
+
+/// An image built by a run, identified by digest.
+#[derive(Debug, Serialize, ToSchema)]
+struct ImageResponse {
+    stage_name: String,
+    name: String,
+    digest: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct LatestRunResponse {
+    pipeline_id: String,
+    run_id: String,
+    number: i64,
+    status: String,
+    branch: String,
+    sha: Option<String>,
+    created_at: String,
+    started_at: Option<String>,
+    finished_at: Option<String>,
+    duration_secs: Option<f64>,
+    queued_secs: Option<f64>,
+    artifacts: Vec<ArtifactResponse>,
+    images: Vec<ImageResponse>,
+}
+
+/// Latest run of a branch, so tooling can resolve e.g. the latest green
+/// build of main without paging through runs. Branch names containing `/`
+/// are passed URL-encoded.
+#[utoipa::path(
+    get,
+    path = "/{id}/branches/{branch}/latest",
+    params(("id" = Uuid, Path, description = "Pipeline ID"), ("branch" = String, Path, description = "Branch name, URL-encoded"), LatestRunQuery),
+    responses((status = 200, description = "The latest run", body = LatestRunResponse))
+)]
+async fn latest_branch_run(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((id, branch)): Path<(Uuid, String)>,
+    Query(query): Query<LatestRunQuery>,
+) -> Result<Json<LatestRunResponse>, ApiError> {
+    let pipeline_id = ResourceId::from_uuid(id);
+    authorized_pipeline(&state, &auth, id, Permission::PipelineRead).await?;
+    let run = state
+        .pipeline_repo
+        .latest_branch_run(pipeline_id, &branch, query.status.as_deref())
+        .await?
+        .ok_or_else(|| match &query.status {
+            Some(status) => {
+                ApiError::NotFound(format!("No {} run of branch {} found", status, branch))
+            }
+            None => ApiError::NotFound(format!("No run of branch {} found", branch)),
+        })?;
+
+    let artifacts = state
+        .artifact_repo
+        .list_artifacts(ResourceId::from_uuid(run.id))
+        .await?;
+    let images = artifacts
+        .iter()
+        .filter(|a| a.kind() == ArtifactKind::Image)
+        .map(|a| ImageResponse {
+            stage_name: a.stage_name.clone(),
+            name: a.name.clone(),
+            digest: a.digest.clone(),
+        })
+        .collect();
+
+    Ok(Json(LatestRunResponse {
+        pipeline_id: run.pipeline_id.to_string(),
+        run_id: run.id.to_string(),
+        number: run.number,
+        status: run.status,
+        branch,
+        sha: run
+            .git_info
+            .get("sha")
+            .and_then(|s| s.as_str())
+            .filter(|s| !s.is_empty())
+            .map(str::to_string),
+        created_at: run.created_at.to_rfc3339(),
+        started_at: run.started_at.map(|t| t.to_rfc3339()),
+        finished_at: run.finished_at.map(|t| t.to_rfc3339()),
+        duration_secs: run.duration_secs,
+        queued_secs: run.queued_secs,
+        artifacts: artifacts.into_iter().map(Into::into).collect(),
+        images,
+    }))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct TriggerRunRequest {
+    branch: Option<String>,
+    sha: Option<String>,
+    /// Variables overriding every other value for this run's jobs.
+    #[serde(default)]
+    variables: HashMap<String, String>,
+    /// Values of the parameters the pipeline declares; the rest take their
+    /// defaults.
+    #[serde(default)]
+    params: HashMap<String, String>,
+}
+
+/// Header naming a run request, so that retrying it returns the run it
+/// created instead of queueing another.
+const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// Queue a run. With an `Idempotency-Key` header, a request repeating the
+/// key of an earlier one by the same API key or user returns that
+/// request's run.
+#[tracing::instrument(name = "run.create", skip_all, fields(pipeline_id = %id, run_id))]
+#[utoipa::path(
+    post,
+    path = "/{id}/runs",
+    params(
+        ("id" = Uuid, Path, description = "Pipeline ID"),
+        ("Idempotency-Key" = Option<String>, Header, description = "Key identifying the request; repeating it returns the same run"),
+    ),
+    request_body = TriggerRunRequest,
+    responses((status = 200, description = "The queued run", body = RunResponse))
+)]
+async fn trigger_run(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(req): Json<TriggerRunRequest>,
+) -> Result<Json<RunResponse>, ApiError> {
+    let pipeline = authorized_pipeline(&state, &auth, id, Permission::PipelineTrigger).await?;
+    let pipeline_id = ResourceId::from_uuid(id);
+    let idempotency_key = idempotency_key(&headers, &auth)?;
+    // A retry gets its run even if the quota has been used up since
+    if let Some(key) = &idempotency_key
+        && let Some(run) = state.pipeline_repo.get_run_by_key(pipeline_id, key).await?
+    {
+        return Ok(Json(run.into()));
+    }
+
+    if let Some(name) = req.variables.keys().find(|name| !env::is_valid_name(name)) {
+        return Err(ApiError::BadRequest(format!(
+            "'{}' is not a valid variable name",
+            name
+        )));
+    }
+    let params = resolve_params(&declared_params(&pipeline.config)?, &req.params)
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    check_run_quota(&state, &pipeline).await?;
+    check_run_routable(&state, &pipeline).await?;
+    check_run_policy(&state, &pipeline).await?;
+    let mut trigger_info = serde_json::json!({
+        "kind": "manual"
+    });
+    if !req.variables.is_empty() {
+        trigger_info["variables"] = serde_json::json!(req.variables);
+    }
+    if !params.is_empty() {
+        trigger_info["params"] = serde_json::json!(params);
+    }
+    let git_info = serde_json::json!({
+        "branch": req.branch.clone().unwrap_or_default(),
+        "sha": req.sha.clone().unwrap_or_default(),
+        "short_sha": "",
+        "message": "",
+        "author": ""
+    });
+
+    // Create the run record
+    let run = match &idempotency_key {
+        Some(key) => {
+            let (run, created) = state
+                .pipeline_repo
+                .create_run_once(pipeline_id, key, trigger_info, git_info)
+                .await?;
+            if !created {
+                return Ok(Json(run.into()));
+            }
+            run
+        }
+        None => {
+            state
+                .pipeline_repo
+                .create_run(pipeline_id, trigger_info, git_info)
+                .await?
+        }
+    };
+    tracing::Span::current().record("run_id", tracing::field::display(run.id));
+
+    // Execute in the background on whichever server has an executor
+    tasks::enqueue_run(&state, &run).await?;
+
+    Ok(Json(RunResponse {
+        status: "pending".to_string(),
+        ..run.into()
+    }))
+}
+
+/// The run key of the request's `Idempotency-Key`, scoped to the API key or
+/// user sending it, so that a caller never gets another caller's run.
+fn idempotency_key(headers: &HeaderMap, auth: &AuthContext) -> Result<Option<String>, ApiError> {
+    let Some(value) = headers.get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(None);
+    };
+    let key = value
+        .to_str()
+        .ok()
+        .map(str::trim)
+        .filter(|k| (1..=255).contains(&k.len()) && k.bytes().all(|b| b.is_ascii_graphic()))
+        .ok_or_else(|| {
+            ApiError::BadRequest(format!(
+                "{} must be 1 to 255 visible characters",
+                IDEMPOTENCY_KEY_HEADER
+            ))
+        })?;
+    let caller = match (auth.api_key_id, auth.user_id) {
+        (Some(key_id), _) => format!("key:{}", key_id),
+        (None, Some(user_id)) => format!("user:{}", user_id),
+        (None, None) => "anonymous".to_string(),
+    };
+    Ok(Some(format!("api:{}:{}", caller, key)))
+}
+
+/// Refuse a new run of the pipeline if its tenant is over a quota that
+/// rejects work; one that queues work holds the run instead.
+async fn check_run_quota(state: &AppState, pipeline: &PipelineRecord) -> Result<(), ApiError> {
+    if let Err((exceeded, QuotaAction::Reject)) = state
+        .quota
+        .check(pipeline.tenant_id, JobResources::default())
+        .await
+        .map_err(|e| ApiError::Internal(format!("Failed to check quota: {}", e)))?
+    {
+        return Err(ApiError::TooManyRequests(format!(
+            "Quota exceeded: {}",
+            exceeded
+        )));
+    }
+    Ok(())
+}
+
+/// Refuse a new run of the pipeline if one of its stages needs labels or
+/// a platform (e.g. `windows/amd64`) no executor offers, rather than fail
+/// it once queued. Servers without executors leave the check to the one
+/// that runs it.
+async fn check_run_routable(state: &AppState, pipeline: &PipelineRecord) -> Result<(), ApiError> {
+    let Some(orchestrator) = &state.orchestrator else {
+        return Ok(());
+    };
+    for stage in load_stage_definitions(state, pipeline).await? {
+        if !matches!(stage.action, StageAction::Run { .. }) {
+            continue;
+        }
+        if let Err(e) = orchestrator
+            .executors()
+            .check(&stage.runs_on, &stage.platform)
+            .await
+        {
+            return Err(ApiError::Conflict(format!(
+                "Stage '{}' cannot run: {}",
+                stage.name, e
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Refuse a new run of the pipeline if it breaks its organization's
+/// pipeline policy, which may have changed since the pipeline was stored.
+async fn check_run_policy(state: &AppState, pipeline: &PipelineRecord) -> Result<(), ApiError> {
+    let stages = load_stage_definitions(state, pipeline).await?;
+    check_pipeline_policy(state, ResourceId::from_uuid(pipeline.tenant_id), &stages).await
+}
+
+/// Refuse stages that break the pipeline policy of the tenant's
+/// organization or the tenant's stage security policy, listing every rule
+/// they break.
+pub(crate) async fn check_pipeline_policy(
+    state: &AppState,
+    tenant_id: ResourceId,
+    stages: &[Stage],
+) -> Result<(), ApiError> {
+    let violations = policy_violations(
+        &state.organization_repo,
+        &state.tenant_repo,
+        tenant_id,
+        stages,
+    )
+    .await?;
+    if violations.is_empty() {
+        return Ok(());
+    }
+    Err(ApiError::Forbidden(policy_error(&violations)))
+}
+
+/// Rules the stages break of the tenant's stage security policy and of its
+/// organization's pipeline policy. Tenants outside an organization have no
+/// pipeline policy.
+pub(crate) async fn policy_violations(
+    organization_repo: &PgOrganizationRepo,
+    tenant_repo: &PgTenantRepo,
+    tenant_id: ResourceId,
+    stages: &[Stage],
+) -> Result<Vec<String>, DbError> {
+    let tenant_policy = tenant_repo.get_by_id(tenant_id).await?.policy();
+    let mut violations = policy::check_security(&tenant_policy.stage_security, stages);
+    if let Some(org_id) = organization_repo.get_tenant_organization(tenant_id).await? {
+        let policy = organization_repo
+            .get_organization(ResourceId::from_uuid(org_id))
+            .await?
+            .pipeline_policy();
+        violations.extend(policy::check(&policy, stages));
+    }
+    Ok(violations)
+}
+
+/// Message for a pipeline that breaks its tenant's or organization's
+/// policies.
+pub(crate) fn policy_error(violations: &[String]) -> String {
+    format!("Pipeline violates policy:\n- {}", violations.join("\n- "))
+}
+
+/// Which stages a re-run executes again.
+#[derive(Debug, Default, Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+enum RerunModeRequest {
+    /// Every stage.
+    #[default]
+    All,
+    /// Stages that failed, were skipped or never finished; the others are
+    /// reused from the original run.
+    FailedOnly,
+    /// `stage` and everything downstream of it.
+    FromStage,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct RerunRequest {
+    #[serde(default)]
+    mode: RerunModeRequest,
+    /// The stage to re-run from, for `from-stage`.
+    stage: Option<String>,
+}
+
+/// Run statuses a run can be re-run from.
+const RERUNNABLE: &[&str] = &["succeeded", "failed", "cancelled", "stalled"];
+
+/// Re-run a finished run on the same commit. Stages that are not run again
+/// are `reused`, taking their result from the original run.
+#[tracing::instrument(name = "run.rerun", skip_all, fields(pipeline_id = %id, rerun_of = %run_id, run_id))]
+#[utoipa::path(
+    post,
+    path = "/{id}/runs/{run_id}/rerun",
+    params(("id" = Uuid, Path, description = "Pipeline ID"), ("run_id" = Uuid, Path, description = "Run to re-run")),
+    request_body = RerunRequest,
+    responses((status = 200, description = "The queued run", body = RunResponse))
+)]
+async fn rerun_run(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((id, run_id)): Path<(Uuid, Uuid)>,
+    Json(req): Json<RerunRequest>,
+) -> Result<Json<RunResponse>, ApiError> {
+    let mode = match (req.mode, req.stage) {
+        (RerunModeRequest::All, _) => RerunMode::All,
+        (RerunModeRequest::FailedOnly, _) => RerunMode::FailedOnly,
+        (RerunModeRequest::FromStage, Some(stage)) => RerunMode::FromStage(stage),
+        (RerunModeRequest::FromStage, None) => {
+            return Err(ApiError::BadRequest(
+                "stage is required to re-run from a stage".to_string(),
+            ));
+        }
+    };
+    let run = start_rerun(&state, &auth, id, run_id, mode).await?;
+    tracing::Span::current().record("run_id", tracing::field::display(run.id));
+
+    Ok(Json(RunResponse {
+        status: "pending".to_string(),
+        ..run.into()
+    }))
+}
+
+/// Queue a re-run of a finished run of a pipeline, reusing the stages
+/// `mode` does not run again.
+pub(crate) async fn start_rerun(
+    state: &AppState,
+    auth: &AuthContext,
+    id: Uuid,
+    run_id: Uuid,
+    mode: RerunMode,
+) -> Result<PipelineRunRecord, ApiError> {
+    let original = pipeline_run(state, auth, id, run_id, Permission::PipelineTrigger).await?;
+    if !RERUNNABLE.contains(&original.status.as_str()) {
+        return Err(ApiError::Conflict(format!(
+            "Run {} is {}; only finished runs can be re-run",
+            run_id, original.status
+        )));
+    }
+    let pipeline_id = ResourceId::from_uuid(id);
+    let needs: HashMap<String, Vec<String>> = state
+        .pipeline_repo
+        .list_stages(pipeline_id)
+        .await?
+        .into_iter()
+        .map(|s| (s.name, s.depends_on))
+        .collect();
+    let succeeded: BTreeSet<String> = state
+        .pipeline_repo
+        .list_stage_results(ResourceId::from_uuid(original.id))
+        .await?
+        .into_iter()
+        .filter(|r| matches!(r.status.as_str(), "succeeded" | "reused"))
+        .map(|r| r.stage_name)
+        .collect();
+    let reused = rerun::reused_stages(&needs, &succeeded, &mode)
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+
+    let pipeline = state.pipeline_repo.get_by_id(pipeline_id).await?;
+    check_run_quota(state, &pipeline).await?;
+    check_run_routable(state, &pipeline).await?;
+    check_run_policy(state, &pipeline).await?;
+    let mut trigger_info = serde_json::json!({
+        "kind": "rerun",
+        "rerun_of": original.id,
+        "rerun_of_number": original.number,
+        "mode": match &mode {
+            RerunMode::All => "all",
+            RerunMode::FailedOnly => "failed-only",
+            RerunMode::FromStage(_) => "from-stage",
+        },
+        "stage": match &mode {
+            RerunMode::FromStage(stage) => Some(stage.as_str()),
+            _ => None,
+        },
+    });
+    // A rerun gets the variables and params the run was triggered with
+    for key in ["variables", "params"] {
+        if let Some(value) = original.trigger_info.get(key) {
+            trigger_info[key] = value.clone();
+        }
+    }
+    let run = state
+        .pipeline_repo
+        .create_run(pipeline_id, trigger_info, original.git_info.clone())
+        .await?;
+    if !reused.is_empty() {
+        let reused: Vec<String> = reused.into_iter().collect();
+        state
+            .pipeline_repo
+            .reuse_stage_results(
+                ResourceId::from_uuid(run.id),
+                ResourceId::from_uuid(original.id),
+                &reused,
+            )
+            .await?;
+    }
+
+    tasks::enqueue_run(state, &run).await?;
+    Ok(run)
+}
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+struct DebugStageRequest {
+    /// Minutes until the session's job is removed; 30 by default, at most
+    /// 120.
+    ttl_minutes: Option<i64>,
+}
+
+/// Open a debug session of a failed stage: a copy of its job with the same
+/// image, environment and workspace that idles instead of running the
+/// stage's commands. Follow the session until it is `ready`, then open its
+/// terminal under `/debug-sessions/{id}/terminal`.
+#[tracing::instrument(name = "run.debug", skip_all, fields(pipeline_id = %id, run_id = %run_id, stage = %stage))]
+#[utoipa::path(
+    post,
+    path = "/{id}/runs/{run_id}/stages/{stage}/debug",
+    params(
+        ("id" = Uuid, Path, description = "Pipeline ID"),
+        ("run_id" = Uuid, Path, description = "Run ID"),
+        ("stage" = String, Path, description = "Failed stage to debug")
+    ),
+    request_body = DebugStageRequest,
+    responses((status = 200, description = "The starting session", body = DebugSessionResponse))
+)]
+async fn debug_stage(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((id, run_id, stage)): Path<(Uuid, Uuid, String)>,
+    Json(req): Json<DebugStageRequest>,
+) -> Result<Json<DebugSessionResponse>, ApiError> {
+    let run = pipeline_run(&state, &auth, id, run_id, Permission::PipelineDebug).await?;
+    let ttl = req
+        .ttl_minutes
+        .unwrap_or(debug_sessions::DEFAULT_TTL_MINUTES);
+    if !(1..=debug_sessions::MAX_TTL_MINUTES).contains(&ttl) {
+        return Err(ApiError::BadRequest(format!(
+            "ttl_minutes must be between 1 and {}",
+            debug_sessions::MAX_TTL_MINUTES
+        )));
+    }
+    let result = state
+        .pipeline_repo
+        .list_stage_results(ResourceId::from_uuid(run.id))
+        .await?
+        .into_iter()
+        .find(|r| r.stage_name == stage)
+        .ok_or_else(|| ApiError::NotFound(format!("Stage {} not found in run", stage)))?;
+    if !debug_sessions::DEBUGGABLE.contains(&result.status.as_str()) {
+        return Err(ApiError::Conflict(format!(
+            "Stage {} is {}; only failed stages can be debugged",
+            stage, result.status
+        )));
+    }
+
+    let pipeline = state
+        .pipeline_repo
+        .get_by_id(ResourceId::from_uuid(id))
+        .await?;
+    let session = state
+        .pipeline_repo
+        .create_debug_session(
+            ResourceId::from_uuid(pipeline.tenant_id),
+            ResourceId::from_uuid(run.id),
+            &stage,
+            auth.user_id,
+            Utc::now() + chrono::Duration::minutes(ttl),
+        )
+        .await?;
+    tasks::enqueue(
+        &state,
+        tasks::Task::DebugSession {
+            session_id: session.id,
+        },
+    )
+    .await?;
+    Ok(Json(session.into()))
+}
+
+/// A stage of a run: `pending` until its dependencies are met, `running`
+/// while it waits for and runs its job, then `succeeded`, `failed`,
+/// `skipped` or `stalled`. A re-run's stages taken from the original run
+/// are `reused`.
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct StageResultResponse {
+    stage_name: String,
+    status: String,
+    /// When the stage waited for an executor, and when its job started.
+    queued_at: Option<String>,
+    started_at: Option<String>,
+    finished_at: Option<String>,
+    duration_secs: Option<f64>,
+    queued_secs: Option<f64>,
+    error_message: Option<String>,
+    /// Executor running the stage's job, and its ID there.
+    executor: Option<String>,
+    executor_id: Option<String>,
+    /// Where the job was routed and which executors were passed over.
+    routing: Option<serde_json::Value>,
+    /// Exit code of the stage's job, once it has exited.
+    exit_code: Option<i32>,
+    /// Run a `reused` stage's result was taken from.
+    reused_from: Option<Uuid>,
+    /// Image the stage's job ran, pinned to its digest.
+    image_digest: Option<String>,
+}
+
+impl From<StageResultRecord> for StageResultResponse {
+    fn from(r: StageResultRecord) -> Self {
+        Self {
+            stage_name: r.stage_name,
+            status: r.status,
+            queued_at: r.queued_at.map(|t| t.to_rfc3339()),
+            started_at: r.started_at.map(|t| t.to_rfc3339()),
+            finished_at: r.finished_at.map(|t| t.to_rfc3339()),
+            duration_secs: r.duration_secs,
+            queued_secs: r.queued_secs,
+            error_message: r.error_message,
+            executor: r.executor_name,
+            executor_id: r.executor_id,
+            routing: r.routing,
+            exit_code: r.exit_code,
+            reused_from: r.reused_from,
+            image_digest: r.image_digest,
+        }
+    }
+}
+
+/// Per-stage results of a run, including where each job ran.
+#[utoipa::path(
+    get,
+    path = "/{id}/runs/{run_id}/stages",
+    params(("id" = Uuid, Path, description = "Pipeline ID"), ("run_id" = Uuid, Path, description = "Run ID")),
+    responses((status = 200, description = "Stage results", body = Vec<StageResultResponse>))
+)]
+async fn list_run_stages(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((pipeline_id, run_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<Vec<StageResultResponse>>, ApiError> {
+    let run = pipeline_run(&state, &auth, pipeline_id, run_id, Permission::PipelineRead).await?;
+    let results = state
+        .pipeline_repo
+        .list_stage_results(ResourceId::from_uuid(run.id))
+        .await?;
+    Ok(Json(results.into_iter().map(Into::into).collect()))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct GetLogsQuery {
+    stage: Option<String>,
+    offset: Option<i64>,
+    limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct LogEntry {
+    id: String,
+    stage_name: String,
+    timestamp: String,
+    stream: String,
+    content: String,
+    /// Command of the stage that wrote the line, counted from 1.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    step: Option<i32>,
+    /// `step_start` on a command's echo, `step_end` on its exit code.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frame: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exit_code: Option<i32>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct LogsResponse {
+    logs: Vec<LogEntry>,
+    has_more: bool,
+}
+
+#[utoipa::path(
+    get,
+    path = "/{id}/runs/{run_id}/logs",
+    params(("id" = Uuid, Path, description = "Pipeline ID"), ("run_id" = Uuid, Path, description = "Run ID"), GetLogsQuery),
+    responses((status = 200, description = "A page of log lines", body = LogsResponse))
+)]
+async fn get_run_logs(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((pipeline_id, run_id)): Path<(Uuid, Uuid)>,
+    Query(query): Query<GetLogsQuery>,
+) -> Result<Json<LogsResponse>, ApiError> {
+    let run = pipeline_run(&state, &auth, pipeline_id, run_id, Permission::PipelineRead).await?;
+    let run_id = ResourceId::from_uuid(run.id);
+    let offset = query.offset.unwrap_or(0);
+    let limit = query.limit.unwrap_or(500).min(1000); // Cap at 1000 lines
+
+    let logs = log_archive::read_logs(
+        &state.log_repo,
+        state.log_archive.as_deref(),
+        run_id,
+        query.stage.as_deref(),
+        offset,
+        limit + 1,
+    )
+    .await?;
+
+    // Check if there are more logs
     let has_more = logs.len() > limit as usize;
     let logs: Vec<LogEntry> = logs
         .into_iter()
@@ -482,8 +1880,1057 @@ async fn get_run_logs(
             timestamp: log.timestamp.to_rfc3339(),
             stream: log.stream,
             content: log.content,
+            step: log.step,
+            frame: log.frame,
+            exit_code: log.exit_code,
         })
         .collect();
 
     Ok(Json(LogsResponse { logs, has_more }))
 }
+
+/// Largest artifact accepted in one upload.
+const ARTIFACT_UPLOAD_LIMIT: usize = 512 * 1024 * 1024;
+
+/// Largest artifact rendered inline; bigger ones can only be downloaded.
+const ARTIFACT_PREVIEW_LIMIT: i64 = 10 * 1024 * 1024;
+
+/// Policy for HTML reports: no scripts, no network, an opaque origin.
+const HTML_REPORT_CSP: &str = "sandbox; default-src 'none'; img-src data:; \
+     style-src 'unsafe-inline'; font-src data:";
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct UploadArtifactQuery {
+    stage: String,
+    /// Path of the artifact within the stage's outputs.
+    name: String,
+    /// Overrides the kind detected from the name and contents.
+    kind: Option<String>,
+    /// Publish the upload, a tar or tar.gz of a directory, as an HTML report.
+    #[serde(default)]
+    report: bool,
+    /// Page a report opens at.
+    entry: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct ArtifactResponse {
+    id: String,
+    stage_name: String,
+    name: String,
+    kind: String,
+    content_type: String,
+    size_bytes: i64,
+    digest: String,
+    /// How the run page can render it inline, if at all.
+    preview: Option<ArtifactPreview>,
+    /// Pinned artifacts are never garbage collected.
+    pinned: bool,
+    created_at: String,
+}
+
+impl From<ArtifactRecord> for ArtifactResponse {
+    fn from(record: ArtifactRecord) -> Self {
+        Self {
+            id: record.id.to_string(),
+            preview: record.kind().preview(&record.content_type),
+            stage_name: record.stage_name,
+            name: record.name,
+            kind: record.kind,
+            content_type: record.content_type,
+            size_bytes: record.size_bytes,
+            digest: record.digest,
+            pinned: record.pinned,
+            created_at: record.created_at.to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct ReportResponse {
+    id: String,
+    stage_name: String,
+    name: String,
+    entry: String,
+    file_count: i32,
+    size_bytes: i64,
+    /// Link that opens the report for a signed-in user of its tenant.
+    url: String,
+    created_at: String,
+}
+
+impl ReportResponse {
+    fn new(record: ReportRecord) -> Self {
+        Self {
+            id: record.id.to_string(),
+            url: reports::report_link(record.id),
+            stage_name: record.stage_name,
+            name: record.name,
+            entry: record.entry,
+            file_count: record.file_count,
+            size_bytes: record.size_bytes,
+            created_at: record.created_at.to_rfc3339(),
+        }
+    }
+}
+
+/// Failed and slowest tests listed per run.
+const TEST_LIST_LIMIT: i64 = 20;
+
+/// Runs looked at for flaky tests, unless the query says otherwise.
+const DEFAULT_FLAKY_RUNS: i64 = 50;
+const MAX_FLAKY_RUNS: i64 = 500;
+const MAX_FLAKY_TESTS: i64 = 200;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct FlakyTestsQuery {
+    /// Look at the pipeline's last `runs` runs with test results.
+    runs: Option<i64>,
+    /// Flaky tests to list.
+    limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct RunTestsResponse {
+    summary: TestSummary,
+    /// Failed and errored tests, by stage, suite and name.
+    failed: Vec<TestResultResponse>,
+    /// Slowest tests, slowest first.
+    slowest: Vec<TestResultResponse>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct TestResultResponse {
+    stage_name: String,
+    /// Report the test was read from.
+    report_path: String,
+    suite: String,
+    classname: String,
+    name: String,
+    status: TestStatus,
+    duration_secs: f64,
+    message: Option<String>,
+    details: Option<String>,
+}
+
+impl From<TestResultRecord> for TestResultResponse {
+    fn from(record: TestResultRecord) -> Self {
+        Self {
+            status: record.status(),
+            stage_name: record.stage_name,
+            report_path: record.report_path,
+            suite: record.suite,
+            classname: record.classname,
+            name: record.name,
+            duration_secs: record.duration_secs,
+            message: record.message,
+            details: record.details,
+        }
+    }
+}
+
+/// Vulnerability findings listed per run.
+const FINDING_LIST_LIMIT: i64 = 500;
+
+#[derive(Debug, Serialize, ToSchema)]
+struct RunFindingsResponse {
+    /// Findings of each scan stage by severity.
+    scans: Vec<ScanSummary>,
+    /// Findings, most severe first.
+    findings: Vec<FindingResponse>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct FindingResponse {
+    stage_name: String,
+    /// Image the finding is in.
+    target: String,
+    vulnerability_id: String,
+    package: String,
+    installed_version: String,
+    fixed_version: Option<String>,
+    severity: Severity,
+    title: Option<String>,
+    url: Option<String>,
+}
+
+impl From<ScanFindingRecord> for FindingResponse {
+    fn from(record: ScanFindingRecord) -> Self {
+        Self {
+            severity: record.severity(),
+            stage_name: record.stage_name,
+            target: record.target,
+            vulnerability_id: record.vulnerability_id,
+            package: record.package,
+            installed_version: record.installed_version,
+            fixed_version: record.fixed_version,
+            title: record.title,
+            url: record.url,
+        }
+    }
+}
+
+/// Load a pipeline, checking the caller holds `permission` in its tenant.
+pub(crate) async fn authorized_pipeline(
+    state: &AppState,
+    auth: &AuthContext,
+    id: Uuid,
+    permission: Permission,
+) -> Result<PipelineRecord, ApiError> {
+    let pipeline = state
+        .pipeline_repo
+        .get_by_id(ResourceId::from_uuid(id))
+        .await?;
+    auth.require(state, pipeline.tenant_id, permission).await?;
+    Ok(pipeline)
+}
+
+/// Load a run, making sure it belongs to the pipeline in the path and the
+/// caller holds `permission` in the pipeline's tenant.
+async fn pipeline_run(
+    state: &AppState,
+    auth: &AuthContext,
+    pipeline_id: Uuid,
+    run_id: Uuid,
+    permission: Permission,
+) -> Result<PipelineRunRecord, ApiError> {
+    authorized_pipeline(state, auth, pipeline_id, permission).await?;
+    let run = state
+        .pipeline_repo
+        .get_run(ResourceId::from_uuid(run_id))
+        .await?;
+    if run.pipeline_id != pipeline_id {
+        return Err(ApiError::NotFound(format!("Run {} not found", run_id)));
+    }
+    Ok(run)
+}
+
+/// Store an artifact uploaded by a stage, typing it on the way in.
+#[utoipa::path(
+    post,
+    path = "/{id}/runs/{run_id}/artifacts",
+    params(("id" = Uuid, Path, description = "Pipeline ID"), ("run_id" = Uuid, Path, description = "Run ID"), UploadArtifactQuery),
+    request_body(content = Vec<u8>, content_type = "application/octet-stream"),
+    responses((
+        status = 201,
+        description = "The artifact; a `ReportResponse` when `report` is set",
+        body = ArtifactResponse
+    ))
+)]
+async fn upload_artifact(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((pipeline_id, run_id)): Path<(Uuid, Uuid)>,
+    Query(query): Query<UploadArtifactQuery>,
+    body: Bytes,
+) -> Result<Response, ApiError> {
+    let run = pipeline_run(
+        &state,
+        &auth,
+        pipeline_id,
+        run_id,
+        Permission::ArtifactWrite,
+    )
+    .await?;
+    if query.report {
+        let report = publish_report(&state, &run, query, body).await?;
+        return Ok((StatusCode::CREATED, Json(report)).into_response());
+    }
+    let kind = match query.kind.as_deref() {
+        Some(kind) => kind.parse::<ArtifactKind>().map_err(ApiError::BadRequest)?,
+        None => ArtifactKind::detect(&query.name, &body),
+    };
+    let content_type = kind.content_type(&query.name, &body);
+    if kind == ArtifactKind::Junit {
+        let cases = std::str::from_utf8(&body)
+            .map_err(|e| e.to_string())
+            .and_then(test_report::parse);
+        test_reports::ingest(
+            &state,
+            ResourceId::from_uuid(run.id),
+            ResourceId::from_uuid(run.pipeline_id),
+            &query.stage,
+            &query.name,
+            cases,
+        )
+        .await;
+    }
+
+    let key = ArtifactKey {
+        run_id: ResourceId::from_uuid(run.id),
+        stage: query.stage,
+        name: query.name,
+    };
+    let reference = state.artifact_store.put(&key, body).await?;
+    let record = state
+        .artifact_repo
+        .record_artifact(&reference, kind, content_type)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(ArtifactResponse::from(record))).into_response())
+}
+
+/// Unpack an uploaded report archive and store each of its files.
+async fn publish_report(
+    state: &AppState,
+    run: &PipelineRunRecord,
+    query: UploadArtifactQuery,
+    body: Bytes,
+) -> Result<ReportResponse, ApiError> {
+    let name = query.name.trim_matches('/').to_string();
+    let entry = query.entry.unwrap_or_else(|| "index.html".to_string());
+    let entries = {
+        let entry = entry.clone();
+        tokio::task::spawn_blocking(move || reports::unpack(&body, &entry))
+            .await
+            .map_err(|e| ApiError::Internal(e.to_string()))??
+    };
+
+    let mut files = BTreeMap::new();
+    for file in entries {
+        let key = ArtifactKey {
+            run_id: ResourceId::from_uuid(run.id),
+            stage: query.stage.clone(),
+            name: format!("{}/{}", name, file.path),
+        };
+        let reference = state.artifact_store.put(&key, file.data).await?;
+        files.insert(
+            file.path.clone(),
+            ReportFile {
+                content_type: reports::content_type(&file.path),
+                location: reference.location,
+                size: reference.size,
+                digest: reference.checksum,
+            },
+        );
+    }
+    let record = state
+        .artifact_repo
+        .record_report(
+            ResourceId::from_uuid(run.id),
+            &query.stage,
+            &name,
+            &entry,
+            &files,
+        )
+        .await?;
+
+    Ok(ReportResponse::new(record))
+}
+
+/// Reports published by a run, with links to open them.
+#[utoipa::path(
+    get,
+    path = "/{id}/runs/{run_id}/reports",
+    params(("id" = Uuid, Path, description = "Pipeline ID"), ("run_id" = Uuid, Path, description = "Run ID")),
+    responses((status = 200, description = "Reports", body = Vec<ReportResponse>))
+)]
+async fn list_reports(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((pipeline_id, run_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<Vec<ReportResponse>>, ApiError> {
+    let run = pipeline_run(&state, &auth, pipeline_id, run_id, Permission::PipelineRead).await?;
+    let reports = state
+        .artifact_repo
+        .list_reports(ResourceId::from_uuid(run.id))
+        .await?;
+    Ok(Json(reports.into_iter().map(ReportResponse::new).collect()))
+}
+
+/// Test counts of a run with its failed and slowest tests.
+#[utoipa::path(
+    get,
+    path = "/{id}/runs/{run_id}/tests",
+    params(("id" = Uuid, Path, description = "Pipeline ID"), ("run_id" = Uuid, Path, description = "Run ID")),
+    responses((status = 200, description = "Test results", body = RunTestsResponse))
+)]
+async fn get_run_tests(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((pipeline_id, run_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<RunTestsResponse>, ApiError> {
+    let run = pipeline_run(&state, &auth, pipeline_id, run_id, Permission::PipelineRead).await?;
+    let run_id = ResourceId::from_uuid(run.id);
+    let repo = &state.test_result_repo;
+    Ok(Json(RunTestsResponse {
+        summary: repo.summary(run_id).await?,
+        failed: repo
+            .failures(run_id, TEST_LIST_LIMIT)
+            .await?
+            .into_iter()
+            .map(TestResultResponse::from)
+            .collect(),
+        slowest: repo
+            .slowest(run_id, TEST_LIST_LIMIT)
+            .await?
+            .into_iter()
+            .map(TestResultResponse::from)
+            .collect(),
+    }))
+}
+
+/// Vulnerabilities the run's scan stages found.
+#[utoipa::path(
+    get,
+    path = "/{id}/runs/{run_id}/findings",
+    params(("id" = Uuid, Path, description = "Pipeline ID"), ("run_id" = Uuid, Path, description = "Run ID")),
+    responses((status = 200, description = "Scan findings", body = RunFindingsResponse))
+)]
+async fn get_run_findings(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((pipeline_id, run_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<RunFindingsResponse>, ApiError> {
+    let run = pipeline_run(&state, &auth, pipeline_id, run_id, Permission::PipelineRead).await?;
+    let run_id = ResourceId::from_uuid(run.id);
+    let repo = &state.scan_finding_repo;
+    Ok(Json(RunFindingsResponse {
+        scans: repo.summaries(run_id).await?,
+        findings: repo
+            .list_findings(run_id, FINDING_LIST_LIMIT)
+            .await?
+            .into_iter()
+            .map(FindingResponse::from)
+            .collect(),
+    }))
+}
+
+/// The variables the run's jobs got and where each came from, sorted by
+/// name with stage variables last; empty until the run starts.
+#[utoipa::path(
+    get,
+    path = "/{id}/runs/{run_id}/variables",
+    params(("id" = Uuid, Path, description = "Pipeline ID"), ("run_id" = Uuid, Path, description = "Run ID")),
+    responses((status = 200, description = "Variables", body = Vec<ResolvedVariable>))
+)]
+async fn get_run_variables(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((pipeline_id, run_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<Vec<ResolvedVariable>>, ApiError> {
+    let run = pipeline_run(&state, &auth, pipeline_id, run_id, Permission::PipelineRead).await?;
+    let variables = state
+        .variable_group_repo
+        .get_run_variables(ResourceId::from_uuid(run.id))
+        .await?
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default();
+    Ok(Json(variables))
+}
+
+/// Tests that flip between passing and failing across the pipeline's
+/// recent runs, flakiest first.
+#[utoipa::path(
+    get,
+    path = "/{id}/tests/flaky",
+    params(("id" = Uuid, Path, description = "Pipeline ID"), FlakyTestsQuery),
+    responses((status = 200, description = "Flaky tests", body = Vec<FlakyTest>))
+)]
+async fn list_flaky_tests(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    Query(query): Query<FlakyTestsQuery>,
+) -> Result<Json<Vec<FlakyTest>>, ApiError> {
+    let pipeline = authorized_pipeline(&state, &auth, id, Permission::PipelineRead).await?;
+    let tests = state
+        .test_result_repo
+        .flaky_tests(
+            ResourceId::from_uuid(pipeline.id),
+            query
+                .runs
+                .unwrap_or(DEFAULT_FLAKY_RUNS)
+                .clamp(2, MAX_FLAKY_RUNS),
+            query
+                .limit
+                .unwrap_or(TEST_LIST_LIMIT)
+                .clamp(1, MAX_FLAKY_TESTS),
+        )
+        .await?;
+    Ok(Json(tests))
+}
+
+/// Releases to list unless the request asks for fewer.
+const RELEASE_LIST_LIMIT: i64 = 50;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ReleasesQuery {
+    /// Releases to list.
+    limit: Option<i64>,
+}
+
+/// Releases the pipeline's release stages published, newest first.
+#[utoipa::path(
+    get,
+    path = "/{id}/releases",
+    params(("id" = Uuid, Path, description = "Pipeline ID"), ReleasesQuery),
+    responses((status = 200, description = "Published releases", body = Vec<ReleaseRecord>))
+)]
+async fn list_releases(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    Query(query): Query<ReleasesQuery>,
+) -> Result<Json<Vec<ReleaseRecord>>, ApiError> {
+    let pipeline = authorized_pipeline(&state, &auth, id, Permission::PipelineRead).await?;
+    let releases = state
+        .release_repo
+        .list_releases(
+            ResourceId::from_uuid(pipeline.id),
+            query
+                .limit
+                .unwrap_or(RELEASE_LIST_LIMIT)
+                .clamp(1, RELEASE_LIST_LIMIT * 10),
+        )
+        .await?;
+    Ok(Json(releases))
+}
+
+#[utoipa::path(
+    get,
+    path = "/{id}/runs/{run_id}/artifacts",
+    params(("id" = Uuid, Path, description = "Pipeline ID"), ("run_id" = Uuid, Path, description = "Run ID")),
+    responses((status = 200, description = "Artifacts", body = Vec<ArtifactResponse>))
+)]
+async fn list_artifacts(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((pipeline_id, run_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<Vec<ArtifactResponse>>, ApiError> {
+    let run = pipeline_run(&state, &auth, pipeline_id, run_id, Permission::PipelineRead).await?;
+    let artifacts = state
+        .artifact_repo
+        .list_artifacts(ResourceId::from_uuid(run.id))
+        .await?;
+    Ok(Json(artifacts.into_iter().map(Into::into).collect()))
+}
+
+/// Download an artifact. Always served as an attachment, so nothing
+/// uploaded by a stage is rendered in the BuildIt origin.
+#[utoipa::path(
+    get,
+    path = "/{id}/runs/{run_id}/artifacts/{artifact_id}",
+    params(("id" = Uuid, Path, description = "Pipeline ID"), ("run_id" = Uuid, Path, description = "Run ID"), ("artifact_id" = Uuid, Path, description = "Artifact ID")),
+    responses((status = 200, description = "The artifact, as an attachment", body = Vec<u8>, content_type = "application/octet-stream"))
+)]
+async fn download_artifact(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((pipeline_id, run_id, artifact_id)): Path<(Uuid, Uuid, Uuid)>,
+) -> Result<Response, ApiError> {
+    let run = pipeline_run(&state, &auth, pipeline_id, run_id, Permission::PipelineRead).await?;
+    let artifact = state
+        .artifact_repo
+        .get_artifact(
+            ResourceId::from_uuid(run.id),
+            ResourceId::from_uuid(artifact_id),
+        )
+        .await?;
+    let stream = state.artifact_store.stream(&artifact.reference()).await?;
+
+    let file_name = artifact
+        .name
+        .rsplit('/')
+        .next()
+        .unwrap_or("artifact")
+        .replace(['"', '\\', '\r', '\n'], "_");
+    Ok((
+        [
+            (header::CONTENT_TYPE, artifact.content_type.clone()),
+            (header::CONTENT_LENGTH, artifact.size_bytes.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", file_name),
+            ),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        ],
+        Body::from_stream(stream),
+    )
+        .into_response())
+}
+
+/// Render an artifact of a safe type for inline viewing on the run page.
+#[utoipa::path(
+    get,
+    path = "/{id}/runs/{run_id}/artifacts/{artifact_id}/preview",
+    params(("id" = Uuid, Path, description = "Pipeline ID"), ("run_id" = Uuid, Path, description = "Run ID"), ("artifact_id" = Uuid, Path, description = "Artifact ID")),
+    responses((status = 200, description = "The artifact rendered for inline viewing", content_type = "text/html"))
+)]
+async fn preview_artifact(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((pipeline_id, run_id, artifact_id)): Path<(Uuid, Uuid, Uuid)>,
+) -> Result<Response, ApiError> {
+    let run = pipeline_run(&state, &auth, pipeline_id, run_id, Permission::PipelineRead).await?;
+    let artifact = state
+        .artifact_repo
+        .get_artifact(
+            ResourceId::from_uuid(run.id),
+            ResourceId::from_uuid(artifact_id),
+        )
+        .await?;
+    let preview = artifact
+        .kind()
+        .preview(&artifact.content_type)
+        .ok_or_else(|| {
+            ApiError::BadRequest(format!(
+                "{} artifacts cannot be previewed; download it instead",
+                artifact.kind
+            ))
+        })?;
+    if artifact.size_bytes > ARTIFACT_PREVIEW_LIMIT {
+        return Err(ApiError::BadRequest(format!(
+            "{} is too large to preview; download it instead",
+            artifact.name
+        )));
+    }
+    let data = state.artifact_store.get(&artifact.reference()).await?;
+
+    match preview {
+        ArtifactPreview::SandboxedHtml => Ok((
+            [
+                (header::CONTENT_TYPE, "text/html; charset=utf-8"),
+                (header::CONTENT_SECURITY_POLICY, HTML_REPORT_CSP),
+                (header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+                (header::X_FRAME_OPTIONS, "SAMEORIGIN"),
+            ],
+            data,
+        )
+            .into_response()),
+        ArtifactPreview::Json => {
+            let value: serde_json::Value = serde_json::from_slice(&data).map_err(|e| {
+                ApiError::BadRequest(format!("{} is not valid JSON: {}", artifact.name, e))
+            })?;
+            let pretty = serde_json::to_string_pretty(&value)
+                .map_err(|e| ApiError::Internal(e.to_string()))?;
+            Ok((
+                [
+                    (header::CONTENT_TYPE, "text/plain; charset=utf-8"),
+                    (header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+                ],
+                pretty,
+            )
+                .into_response())
+        }
+    }
+}
+
+/// Pin an artifact, e.g. a release build, so artifact retention never
+/// collects it.
+#[utoipa::path(
+    post,
+    path = "/{id}/runs/{run_id}/artifacts/{artifact_id}/pin",
+    params(("id" = Uuid, Path, description = "Pipeline ID"), ("run_id" = Uuid, Path, description = "Run ID"), ("artifact_id" = Uuid, Path, description = "Artifact ID")),
+    responses((status = 200, description = "The pinned artifact", body = ArtifactResponse))
+)]
+async fn pin_artifact(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((pipeline_id, run_id, artifact_id)): Path<(Uuid, Uuid, Uuid)>,
+) -> Result<Json<ArtifactResponse>, ApiError> {
+    set_artifact_pinned(&state, &auth, pipeline_id, run_id, artifact_id, true).await
+}
+
+/// Unpin an artifact; it is collected once its tenant's retention says so.
+#[utoipa::path(
+    delete,
+    path = "/{id}/runs/{run_id}/artifacts/{artifact_id}/pin",
+    params(("id" = Uuid, Path, description = "Pipeline ID"), ("run_id" = Uuid, Path, description = "Run ID"), ("artifact_id" = Uuid, Path, description = "Artifact ID")),
+    responses((status = 200, description = "The unpinned artifact", body = ArtifactResponse))
+)]
+async fn unpin_artifact(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((pipeline_id, run_id, artifact_id)): Path<(Uuid, Uuid, Uuid)>,
+) -> Result<Json<ArtifactResponse>, ApiError> {
+    set_artifact_pinned(&state, &auth, pipeline_id, run_id, artifact_id, false).await
+}
+
+async fn set_artifact_pinned(
+    state: &AppState,
+    auth: &AuthContext,
+    pipeline_id: Uuid,
+    run_id: Uuid,
+    artifact_id: Uuid,
+    pinned: bool,
+) -> Result<Json<ArtifactResponse>, ApiError> {
+    let run = pipeline_run(state, auth, pipeline_id, run_id, Permission::PipelineWrite).await?;
+    let artifact = state
+        .artifact_repo
+        .set_pinned(
+            ResourceId::from_uuid(run.id),
+            ResourceId::from_uuid(artifact_id),
+            pinned,
+        )
+        .await?;
+    tracing::info!(artifact_id = %artifact.id, pinned, "Artifact pin changed");
+    Ok(Json(artifact.into()))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ApprovalContextQuery {
+    /// Gate stage; defaults to the stage currently waiting for approval.
+    stage: Option<String>,
+}
+
+/// What an approver should know before letting a run past a gate.
+#[utoipa::path(
+    get,
+    path = "/{id}/runs/{run_id}/approval-context",
+    operation_id = "get_run_approval_context",
+    params(("id" = Uuid, Path, description = "Pipeline ID"), ("run_id" = Uuid, Path, description = "Run ID"), ApprovalContextQuery),
+    responses((status = 200, description = "The approval context", body = ApprovalContext))
+)]
+async fn get_approval_context(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((pipeline_id, run_id)): Path<(Uuid, Uuid)>,
+    Query(query): Query<ApprovalContextQuery>,
+) -> Result<Json<ApprovalContext>, ApiError> {
+    let run = pipeline_run(&state, &auth, pipeline_id, run_id, Permission::PipelineRead).await?;
+
+    let stage = match query.stage {
+        Some(stage) => stage,
+        None => state
+            .pipeline_repo
+            .list_stage_results(ResourceId::from_uuid(run.id))
+            .await?
+            .into_iter()
+            .find(|s| s.status == "waiting_approval")
+            .map(|s| s.stage_name)
+            .ok_or_else(|| ApiError::BadRequest("No stage is waiting for approval".to_string()))?,
+    };
+
+    let context = state
+        .approval_context()
+        .for_pipeline_stage(&run, &stage)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    Ok(Json(context))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct CompareRunsQuery {
+    /// Number of the earlier run.
+    from: i64,
+    /// Number of the later run.
+    to: i64,
+}
+
+/// What changed between two runs of a pipeline: the commits built in
+/// between, each stage's outcome and duration, and newly failing tests.
+#[utoipa::path(
+    get,
+    path = "/{id}/runs/compare",
+    params(("id" = Uuid, Path, description = "Pipeline ID"), CompareRunsQuery),
+    responses((status = 200, description = "The comparison", body = RunComparison))
+)]
+async fn compare_runs(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    Query(query): Query<CompareRunsQuery>,
+) -> Result<Json<RunComparison>, ApiError> {
+    let pipeline = authorized_pipeline(&state, &auth, id, Permission::PipelineRead).await?;
+    if query.from == query.to {
+        return Err(ApiError::BadRequest(
+            "Compare two different runs".to_string(),
+        ));
+    }
+    let pipeline_id = ResourceId::from_uuid(pipeline.id);
+    let from = state
+        .pipeline_repo
+        .get_run_by_number(pipeline_id, query.from)
+        .await?;
+    let to = state
+        .pipeline_repo
+        .get_run_by_number(pipeline_id, query.to)
+        .await?;
+    Ok(Json(
+        state
+            .run_comparison()
+            .compare(&pipeline, &from, &to)
+            .await?,
+    ))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::auth::AuthMethod;
+
+    fn key_caller() -> AuthContext {
+        AuthContext {
+            method: AuthMethod::ApiKey,
+            user_id: None,
+            organization_id: Some(Uuid::new_v4()),
+            tenant_id: Some(Uuid::new_v4()),
+            api_key_id: Some(Uuid::new_v4()),
+            scopes: Some(vec!["write".to_string()]),
+            tenant_scoped: false,
+            session_id: None,
+        }
+    }
+
+    /// A caller with authentication disabled, who reaches every pipeline.
+    fn anonymous() -> AuthContext {
+        AuthContext {
+            method: AuthMethod::Anonymous,
+            user_id: None,
+            organization_id: None,
+            tenant_id: None,
+            api_key_id: None,
+            scopes: None,
+            tenant_scoped: false,
+            session_id: None,
+        }
+    }
+
+    fn with_key(key: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(IDEMPOTENCY_KEY_HEADER, key.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_idempotency_keys_are_checked() {
+        let auth = key_caller();
+        assert_eq!(idempotency_key(&HeaderMap::new(), &auth).ok(), Some(None));
+        let key = idempotency_key(&with_key(" deploy-4f2a91c "), &auth).unwrap();
+        assert!(key.unwrap().ends_with(":deploy-4f2a91c"));
+        assert!(idempotency_key(&with_key(&"k".repeat(255)), &auth).is_ok());
+
+        for bad in ["", "   ", "two words", "tab\there"] {
+            assert!(
+                matches!(
+                    idempotency_key(&with_key(bad), &auth),
+                    Err(ApiError::BadRequest(_))
+                ),
+                "{:?}",
+                bad
+            );
+        }
+        assert!(matches!(
+            idempotency_key(&with_key(&"k".repeat(256)), &auth),
+            Err(ApiError::BadRequest(_))
+        ));
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            IDEMPOTENCY_KEY_HEADER,
+            header::HeaderValue::from_bytes("café".as_bytes()).unwrap(),
+        );
+        assert!(matches!(
+            idempotency_key(&headers, &auth),
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_idempotency_keys_are_kept_apart_per_caller() {
+        let headers = with_key("deploy");
+        let key = |auth: &AuthContext| idempotency_key(&headers, auth).unwrap().unwrap();
+        let (tenant, user) = (Uuid::new_v4(), Uuid::new_v4());
+        let (first, second) = (key_caller(), key_caller());
+
+        assert_eq!(key(&first), key(&first));
+        assert_ne!(key(&first), key(&second));
+        assert_eq!(
+            key(&AuthContext::linked_user(user, tenant)),
+            key(&AuthContext::linked_user(user, Uuid::new_v4()))
+        );
+        assert_ne!(
+            key(&AuthContext::linked_user(user, tenant)),
+            key(&AuthContext::linked_user(Uuid::new_v4(), tenant))
+        );
+    }
+
+    /// A pipeline in the tenant of a new organization, which takes it
+    /// along when deleted.
+    pub(crate) async fn new_pipeline(state: &AppState) -> (Uuid, PipelineRecord) {
+        let (org, tenant) = (Uuid::new_v4(), Uuid::new_v4());
+        let slug = Uuid::new_v4().to_string();
+        sqlx::query("INSERT INTO organizations (id, name, slug) VALUES ($1, $2, $2)")
+            .bind(org)
+            .bind(&slug)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO tenants (id, name, slug, organization_id) VALUES ($1, $2, $2, $3)",
+        )
+        .bind(tenant)
+        .bind(&slug)
+        .bind(org)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+        let pipeline = state
+            .pipeline_repo
+            .create(
+                ResourceId::from_uuid(tenant),
+                "build",
+                "https://example.com/repo.git",
+                serde_json::json!({}),
+            )
+            .await
+            .unwrap();
+        (org, pipeline)
+    }
+
+    #[tokio::test]
+    async fn test_create_run_once_returns_the_run_of_a_repeated_key() {
+        let Ok(url) = std::env::var("DATABASE_URL") else {
+            return;
+        };
+        let pool = sqlx::PgPool::connect(&url).await.unwrap();
+        buildit_db::run_migrations(&pool).await.unwrap();
+        let state = AppState::with_pool(pool.clone());
+        let (org, pipeline) = new_pipeline(&state).await;
+        let pipeline_id = ResourceId::from_uuid(pipeline.id);
+        let run_once = |key: String| {
+            let state = state.clone();
+            async move {
+                state
+                    .pipeline_repo
+                    .create_run_once(
+                        pipeline_id,
+                        &key,
+                        serde_json::json!({}),
+                        serde_json::json!({}),
+                    )
+                    .await
+            }
+        };
+
+        let (first, second) = (key_caller(), key_caller());
+        let headers = with_key("deploy");
+        let key = |auth: &AuthContext| idempotency_key(&headers, auth).unwrap().unwrap();
+
+        let (run, created) = run_once(key(&first)).await.unwrap();
+        assert!(created);
+        let (replayed, created) = run_once(key(&first)).await.unwrap();
+        assert!(!created);
+        assert_eq!(replayed.id, run.id);
+        let found = state
+            .pipeline_repo
+            .get_run_by_key(pipeline_id, &key(&first))
+            .await
+            .unwrap();
+        assert_eq!(found.map(|r| r.id), Some(run.id));
+
+        // The same key from another caller is another request.
+        let (other, created) = run_once(key(&second)).await.unwrap();
+        assert!(created);
+        assert_ne!(other.id, run.id);
+        assert_eq!(other.number, run.number + 1);
+
+        // An archived pipeline gets no new runs, but replays still answer.
+        sqlx::query("UPDATE pipelines SET archived_at = NOW() WHERE id = $1")
+            .bind(pipeline.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(matches!(
+            run_once("api:another".to_string()).await,
+            Err(DbError::Conflict(_))
+        ));
+        assert_eq!(run_once(key(&first)).await.unwrap().0.id, run.id);
+
+        sqlx::query("DELETE FROM organizations WHERE id = $1")
+            .bind(org)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    /// Queue a run of the pipeline for `branch` and give it `status`.
+    pub(crate) async fn branch_run(
+        state: &AppState,
+        pipeline: &PipelineRecord,
+        branch: &str,
+        sha: &str,
+        status: &str,
+    ) -> PipelineRunRecord {
+        let run = state
+            .pipeline_repo
+            .create_run(
+                ResourceId::from_uuid(pipeline.id),
+                serde_json::json!({ "kind": "push" }),
+                serde_json::json!({ "branch": branch, "sha": sha }),
+            )
+            .await
+            .unwrap();
+        state
+            .pipeline_repo
+            .update_run_status(ResourceId::from_uuid(run.id), status)
+            .await
+            .unwrap();
+        run
+    }
+
+    #[tokio::test]
+    async fn test_latest_branch_run() {
+        let Ok(url) = std::env::var("DATABASE_URL") else {
+            return;
+        };
+        let pool = sqlx::PgPool::connect(&url).await.unwrap();
+        buildit_db::run_migrations(&pool).await.unwrap();
+        let state = AppState::with_pool(pool.clone());
+        let (org, pipeline) = new_pipeline(&state).await;
+        let (other_org, other) = new_pipeline(&state).await;
+
+        let green = branch_run(&state, &pipeline, "main", "abc123", "succeeded").await;
+        let red = branch_run(&state, &pipeline, "main", "", "failed").await;
+        let feature = branch_run(&state, &pipeline, "feature/login", "def456", "running").await;
+        branch_run(&state, &other, "main", "fff000", "succeeded").await;
+
+        let latest = |branch: &str, status: Option<&str>| {
+            let state = state.clone();
+            let path = Path((pipeline.id, branch.to_string()));
+            let query = Query(LatestRunQuery {
+                status: status.map(str::to_string),
+            });
+            async move {
+                latest_branch_run(State(state), anonymous(), path, query)
+                    .await
+                    .map(|Json(run)| run)
+            }
+        };
+
+        let run = latest("main", None).await.unwrap();
+        assert_eq!(run.run_id, red.id.to_string());
+        assert_eq!(run.status, "failed");
+        assert_eq!(run.branch, "main");
+        assert_eq!(run.sha, None);
+
+        let run = latest("main", Some("succeeded")).await.unwrap();
+        assert_eq!(run.run_id, green.id.to_string());
+        assert_eq!(run.number, green.number);
+        assert_eq!(run.sha.as_deref(), Some("abc123"));
+        assert!(run.artifacts.is_empty() && run.images.is_empty());
+
+        let run = latest("feature/login", None).await.unwrap();
+        assert_eq!(run.run_id, feature.id.to_string());
+
+        assert!(matches!(
+            latest("feature/login", Some("succeeded")).await,
+            Err(ApiError::NotFound(message)) if message.contains("No succeeded run")
+        ));
+        assert!(matches!(
+            latest("release", None).await,
+            Err(ApiError::NotFound(message)) if message == "No run of branch release found"
+        ));
+
+        sqlx::query("DELETE FROM organizations WHERE id = ANY($1)")
+            .bind(vec![org, other_org])
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+}
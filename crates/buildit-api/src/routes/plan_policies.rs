@@ -0,0 +1,234 @@
+//! Plan policy endpoints for the tenant the request acts in.
+//!
+//! Policies are Rego modules evaluated with OPA against the JSON of every
+//! stack plan; see [`crate::services::opa`] for how they are written.
+
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    routing::get,
+};
+use serde::Deserialize;
+use utoipa::{OpenApi, ToSchema};
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::audit::AuditBefore;
+use crate::auth::AuthContext;
+use crate::error::ApiError;
+use crate::services::opa::{OpaError, OpaService};
+use buildit_core::ResourceId;
+use buildit_core::rbac::Permission;
+use buildit_core::stack::{PlanPolicy, PolicyEnforcement};
+use buildit_db::StackRepo;
+
+#[derive(OpenApi)]
+#[openapi(paths(list_policies, get_policy, create_policy, update_policy, delete_policy))]
+pub struct ApiDoc;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/policies", get(list_policies).post(create_policy))
+        .route(
+            "/policies/{policy_id}",
+            get(get_policy).put(update_policy).delete(delete_policy),
+        )
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreatePolicyRequest {
+    pub name: String,
+    pub description: Option<String>,
+    /// Rego module; its `deny` rule holds the violation messages.
+    pub rego: String,
+    /// `hard` (the default) fails runs that violate the policy, `soft`
+    /// only warns approvers.
+    #[serde(default = "enforcement_default")]
+    pub enforcement: PolicyEnforcement,
+    #[serde(default = "enabled_default")]
+    pub enabled: bool,
+}
+
+fn enforcement_default() -> PolicyEnforcement {
+    PolicyEnforcement::Hard
+}
+
+fn enabled_default() -> bool {
+    true
+}
+
+/// Changes to a policy; fields left out keep their value.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct UpdatePolicyRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub rego: Option<String>,
+    pub enforcement: Option<PolicyEnforcement>,
+    pub enabled: Option<bool>,
+}
+
+/// The tenant's plan policies.
+#[utoipa::path(
+    get,
+    path = "/policies",
+    operation_id = "list_plan_policies",
+    responses((status = 200, description = "Policies", body = Vec<PlanPolicy>))
+)]
+async fn list_policies(
+    State(state): State<AppState>,
+    auth: AuthContext,
+) -> Result<Json<Vec<PlanPolicy>>, ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::StackRead)
+        .await?;
+    let policies = state
+        .stack_repo
+        .list_policies(ResourceId::from_uuid(tenant.id))
+        .await?;
+    Ok(Json(policies))
+}
+
+#[utoipa::path(
+    get,
+    path = "/policies/{policy_id}",
+    operation_id = "get_plan_policy",
+    params(("policy_id" = Uuid, Path, description = "Policy ID")),
+    responses((status = 200, description = "The policy", body = PlanPolicy))
+)]
+async fn get_policy(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<PlanPolicy>, ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::StackRead)
+        .await?;
+    let policy = state
+        .stack_repo
+        .get_policy(ResourceId::from_uuid(tenant.id), ResourceId::from_uuid(id))
+        .await?;
+    Ok(Json(policy))
+}
+
+/// Add a policy. Its Rego must compile.
+#[utoipa::path(
+    post,
+    path = "/policies",
+    operation_id = "create_plan_policy",
+    request_body = CreatePolicyRequest,
+    responses((status = 200, description = "The policy", body = PlanPolicy))
+)]
+async fn create_policy(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Json(req): Json<CreatePolicyRequest>,
+) -> Result<Json<PlanPolicy>, ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::TenantManage)
+        .await?;
+
+    let name = policy_name(&req.name)?;
+    check_rego(&req.rego).await?;
+    let policy = state
+        .stack_repo
+        .create_policy(&PlanPolicy {
+            id: Uuid::now_v7(),
+            tenant_id: tenant.id,
+            name,
+            description: req.description,
+            rego: req.rego,
+            enforcement: req.enforcement,
+            enabled: req.enabled,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        })
+        .await?;
+    tracing::info!(tenant = %tenant.slug, policy = %policy.name, enforcement = %policy.enforcement, "Plan policy created");
+    Ok(Json(policy))
+}
+
+#[utoipa::path(
+    put,
+    path = "/policies/{policy_id}",
+    operation_id = "update_plan_policy",
+    params(("policy_id" = Uuid, Path, description = "Policy ID")),
+    request_body = UpdatePolicyRequest,
+    responses((status = 200, description = "The policy", body = PlanPolicy))
+)]
+async fn update_policy(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    Json(req): Json<UpdatePolicyRequest>,
+) -> Result<(AuditBefore, Json<PlanPolicy>), ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::TenantManage)
+        .await?;
+    let mut policy = state
+        .stack_repo
+        .get_policy(ResourceId::from_uuid(tenant.id), ResourceId::from_uuid(id))
+        .await?;
+    let before = AuditBefore::of(&policy);
+
+    if let Some(name) = req.name {
+        policy.name = policy_name(&name)?;
+    }
+    if let Some(description) = req.description {
+        policy.description = Some(description).filter(|d| !d.is_empty());
+    }
+    if let Some(rego) = req.rego {
+        check_rego(&rego).await?;
+        policy.rego = rego;
+    }
+    if let Some(enforcement) = req.enforcement {
+        policy.enforcement = enforcement;
+    }
+    if let Some(enabled) = req.enabled {
+        policy.enabled = enabled;
+    }
+
+    let policy = state.stack_repo.update_policy(&policy).await?;
+    Ok((before, Json(policy)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/policies/{policy_id}",
+    operation_id = "delete_plan_policy",
+    params(("policy_id" = Uuid, Path, description = "Policy ID")),
+    responses((status = 200, description = "Deleted"))
+)]
+async fn delete_policy(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<(AuditBefore, ()), ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::TenantManage)
+        .await?;
+    let policy = state
+        .stack_repo
+        .get_policy(ResourceId::from_uuid(tenant.id), ResourceId::from_uuid(id))
+        .await?;
+    state
+        .stack_repo
+        .delete_policy(ResourceId::from_uuid(tenant.id), ResourceId::from_uuid(id))
+        .await?;
+    Ok((AuditBefore::of(&policy), ()))
+}
+
+fn policy_name(name: &str) -> Result<String, ApiError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(ApiError::BadRequest("Policy name is required".to_string()));
+    }
+    Ok(name.to_string())
+}
+
+/// Reject Rego that OPA does not compile.
+async fn check_rego(rego: &str) -> Result<(), ApiError> {
+    OpaService::new().check(rego).await.map_err(|e| match e {
+        OpaError::InvalidPolicy(message) => ApiError::BadRequest(message),
+        e => ApiError::Internal(e.to_string()),
+    })
+}
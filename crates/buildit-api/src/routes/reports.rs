@@ -0,0 +1,159 @@
+//! Published HTML reports, served as static sites.
+//!
+//! - `GET /reports/{id}` - sign a link for the browser session and open the
+//!   report's entry page
+//! - `GET /reports/{id}/{token}/` - redirect to the report's entry page
+//! - `GET /reports/{id}/{token}/{*path}` - a file of the report
+//!
+//! Sandboxed pages cannot send the session cookie, so their files are served
+//! under a token instead: an expiring signature for the session that opened
+//! the report, which stops working once that session is signed out or
+//! expires. Pages run under
+//! a CSP sandbox without `allow-same-origin`: scripts in a report work, but
+//! they get an opaque origin and cannot reach the BuildIt UI or API.
+
+use axum::Router;
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, header};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::routing::get;
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::auth::cookie_session;
+use crate::error::ApiError;
+use crate::services::reports::report_url;
+use crate::tenancy::{can_reach, reachable_tenants};
+use buildit_core::ResourceId;
+use buildit_db::{ArtifactRepo, DbError, OrganizationRepo, PipelineRepo, ReportRecord, TenantRepo};
+
+/// Sandbox for report pages: scripts and popups, but an opaque origin, no
+/// forms and no requests outside the report.
+const REPORT_CSP: &str = "sandbox allow-scripts allow-popups allow-downloads; \
+     default-src 'self' 'unsafe-inline' 'unsafe-eval' data: blob:; \
+     connect-src 'self'; form-action 'none'; base-uri 'self'; frame-ancestors 'self'";
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/{id}", get(open_report))
+        .route("/{id}/{token}", get(open_entry))
+        .route("/{id}/{token}/", get(open_entry))
+        .route("/{id}/{token}/{*path}", get(report_file))
+}
+
+/// The report, if its token is valid and the session it was signed for is
+/// still signed in.
+async fn authorized_report(
+    state: &AppState,
+    id: Uuid,
+    token: &str,
+) -> Result<ReportRecord, ApiError> {
+    let expired = || {
+        ApiError::Forbidden(
+            "Report link is invalid or has expired; open it again from the run page".to_string(),
+        )
+    };
+    let grant = state
+        .report_signer
+        .verify_report(id, token)
+        .ok_or_else(expired)?;
+    match grant.session_id {
+        Some(session_id) => match state
+            .organization_repo
+            .get_session(ResourceId::from_uuid(session_id))
+            .await
+        {
+            Ok(_) => {}
+            Err(DbError::NotFound(_)) => return Err(expired()),
+            Err(e) => return Err(e.into()),
+        },
+        None if state.auth.disabled => {}
+        None => return Err(expired()),
+    }
+    Ok(state
+        .artifact_repo
+        .get_report(ResourceId::from_uuid(id))
+        .await?)
+}
+
+/// Open a report for the browser session, if it reaches the report's
+/// tenant.
+async fn open_report(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Redirect, ApiError> {
+    let session = cookie_session(&state, &headers).await?;
+    let reachable = reachable_tenants(&state, session.as_ref()).await?;
+    let report = state
+        .artifact_repo
+        .get_report(ResourceId::from_uuid(id))
+        .await?;
+    let run = state
+        .pipeline_repo
+        .get_run(ResourceId::from_uuid(report.pipeline_run_id))
+        .await?;
+    let pipeline = state
+        .pipeline_repo
+        .get_by_id(ResourceId::from_uuid(run.pipeline_id))
+        .await?;
+    let tenant = state
+        .tenant_repo
+        .get_by_id(ResourceId::from_uuid(pipeline.tenant_id))
+        .await?;
+    if !can_reach(reachable.as_deref(), &tenant) {
+        return Err(ApiError::NotFound(format!("Report {} not found", id)));
+    }
+    let token = state
+        .report_signer
+        .sign_report(id, session.map(|session| session.id));
+    Ok(Redirect::to(&report_url(id, &token, &report.entry)))
+}
+
+async fn open_entry(
+    State(state): State<AppState>,
+    Path((id, token)): Path<(Uuid, String)>,
+) -> Result<Redirect, ApiError> {
+    let report = authorized_report(&state, id, &token).await?;
+    Ok(Redirect::to(&report_url(id, &token, &report.entry)))
+}
+
+async fn report_file(
+    State(state): State<AppState>,
+    Path((id, token, path)): Path<(Uuid, String, String)>,
+) -> Result<Response, ApiError> {
+    let report = authorized_report(&state, id, &token).await?;
+    // Directory links open their index page.
+    let (path, file) = match report.file(&path) {
+        Some(file) => (path, file),
+        None => {
+            let index = format!("{}/index.html", path.trim_end_matches('/'));
+            let file = report
+                .file(&index)
+                .ok_or_else(|| ApiError::NotFound(format!("{} is not in the report", path)))?;
+            (index, file)
+        }
+    };
+    let stream = state
+        .artifact_store
+        .stream(&report.reference(&path, &file))
+        .await?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, file.content_type.clone()),
+            (header::CONTENT_LENGTH, file.size.to_string()),
+            (header::CONTENT_SECURITY_POLICY, REPORT_CSP.to_string()),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+            // Keep the token out of requests to other sites.
+            (header::REFERRER_POLICY, "no-referrer".to_string()),
+            (header::CACHE_CONTROL, "private, max-age=300".to_string()),
+            // Sandboxed pages have an opaque origin, so module scripts and
+            // fonts from the report itself are cross-origin requests.
+            (header::ACCESS_CONTROL_ALLOW_ORIGIN, "*".to_string()),
+        ],
+        Body::from_stream(stream),
+    )
+        .into_response())
+}
@@ -4,28 +4,47 @@ use axum::extract::{Path, Query, State};
 use axum::routing::{get, post};
 use axum::{Json, Router};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, OpenApi, ToSchema};
 use uuid::Uuid;
 
 use crate::AppState;
+use crate::audit::AuditBefore;
+use crate::auth::AuthContext;
 use crate::error::ApiError;
 use crate::services::git::GitService;
+use crate::services::repo_sync::{RepoSync, RepoSyncConfig, RepoSyncError};
 use buildit_core::ResourceId;
+use buildit_core::rbac::Permission;
+use buildit_core::repository::Repository;
 use buildit_core::repository::{DetectedConfig, GitProvider};
-use buildit_db::RepositoryRepo;
+use buildit_db::{ApplicationRepo, PipelineRepo, RepositoryRepo, StackRepo};
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    list_repositories,
+    connect_repository,
+    get_repository,
+    delete_repository,
+    sync_repository,
+    list_suggestions
+))]
+pub struct ApiDoc;
 
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/", get(list_repositories).post(connect_repository))
         .route("/{id}", get(get_repository).delete(delete_repository))
         .route("/{id}/sync", post(sync_repository))
+        .route("/{id}/suggestions", get(list_suggestions))
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct ListRepositoriesQuery {
     pub organization_id: Uuid,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct RepositoryResponse {
     pub id: Uuid,
     pub provider: String,
@@ -37,12 +56,23 @@ pub struct RepositoryResponse {
     pub is_private: bool,
     pub detected_config: DetectedConfig,
     pub last_synced_at: Option<String>,
+    /// Why the last scan failed, if it did.
+    pub sync_error: Option<String>,
 }
 
+#[utoipa::path(
+    get,
+    path = "",
+    params(ListRepositoriesQuery),
+    responses((status = 200, description = "The organization's repositories", body = Vec<RepositoryResponse>))
+)]
 async fn list_repositories(
     State(state): State<AppState>,
+    auth: AuthContext,
     Query(query): Query<ListRepositoriesQuery>,
 ) -> Result<Json<Vec<RepositoryResponse>>, ApiError> {
+    auth.require_org(&state, query.organization_id, Permission::RepositoryRead)
+        .await?;
     let repos = state
         .repository_repo
         .list_by_organization(ResourceId::from_uuid(query.organization_id))
@@ -61,13 +91,14 @@ async fn list_repositories(
             is_private: r.is_private,
             detected_config: r.detected_config,
             last_synced_at: r.last_synced_at.map(|t| t.to_rfc3339()),
+            sync_error: r.sync_error,
         })
         .collect();
 
     Ok(Json(response))
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct ConnectRepositoryRequest {
     pub organization_id: Uuid,
     pub provider: String,
@@ -77,16 +108,25 @@ pub struct ConnectRepositoryRequest {
     pub access_token: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ConnectRepositoryResponse {
     pub repository: RepositoryResponse,
     pub detected_config: DetectedConfig,
 }
 
+#[utoipa::path(
+    post,
+    path = "",
+    request_body = ConnectRepositoryRequest,
+    responses((status = 200, description = "The repository and what was found in it", body = ConnectRepositoryResponse))
+)]
 async fn connect_repository(
     State(state): State<AppState>,
+    auth: AuthContext,
     Json(req): Json<ConnectRepositoryRequest>,
 ) -> Result<Json<ConnectRepositoryResponse>, ApiError> {
+    auth.require_org(&state, req.organization_id, Permission::RepositoryWrite)
+        .await?;
     let provider: GitProvider = req
         .provider
         .parse()
@@ -134,11 +174,11 @@ async fn connect_repository(
         .await?;
 
     // Clone and scan the repository
-    let git_service = GitService::new();
-    let detected_config = match git_service
-        .clone_and_scan(&clone_url, req.access_token.as_deref())
-        .await
-    {
+    let mut git_service = GitService::new();
+    if let Some(token) = &req.access_token {
+        git_service = git_service.with_token(provider, token);
+    }
+    let detected_config = match git_service.clone_and_scan(&repo).await {
         Ok(config) => {
             // Update repository with detected config
             state
@@ -170,19 +210,24 @@ async fn connect_repository(
             is_private: repo.is_private,
             detected_config: detected_config.clone(),
             last_synced_at: repo.last_synced_at.map(|t| t.to_rfc3339()),
+            sync_error: repo.sync_error,
         },
         detected_config,
     }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/{id}",
+    params(("id" = Uuid, Path, description = "Repository ID")),
+    responses((status = 200, description = "The repository", body = RepositoryResponse))
+)]
 async fn get_repository(
     State(state): State<AppState>,
+    auth: AuthContext,
     Path(id): Path<Uuid>,
 ) -> Result<Json<RepositoryResponse>, ApiError> {
-    let repo = state
-        .repository_repo
-        .get_by_id(ResourceId::from_uuid(id))
-        .await?;
+    let repo = authorized_repository(&state, &auth, id, Permission::RepositoryRead).await?;
 
     Ok(Json(RepositoryResponse {
         id: repo.id,
@@ -195,46 +240,299 @@ async fn get_repository(
         is_private: repo.is_private,
         detected_config: repo.detected_config,
         last_synced_at: repo.last_synced_at.map(|t| t.to_rfc3339()),
+        sync_error: repo.sync_error,
     }))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/{id}",
+    params(("id" = Uuid, Path, description = "Repository ID")),
+    responses((status = 200, description = "Deleted", body = Object, example = json!({"deleted": true})))
+)]
 async fn delete_repository(
     State(state): State<AppState>,
+    auth: AuthContext,
     Path(id): Path<Uuid>,
-) -> Result<Json<serde_json::Value>, ApiError> {
+) -> Result<(AuditBefore, Json<serde_json::Value>), ApiError> {
+    let repo = authorized_repository(&state, &auth, id, Permission::RepositoryWrite).await?;
     state
         .repository_repo
         .delete(ResourceId::from_uuid(id))
         .await?;
 
-    Ok(Json(serde_json::json!({"deleted": true})))
+    Ok((
+        AuditBefore::of(&repo),
+        Json(serde_json::json!({"deleted": true})),
+    ))
 }
 
+#[utoipa::path(
+    post,
+    path = "/{id}/sync",
+    params(("id" = Uuid, Path, description = "Repository ID")),
+    responses((status = 200, description = "What was found in the repository", body = DetectedConfig))
+)]
 async fn sync_repository(
     State(state): State<AppState>,
+    auth: AuthContext,
     Path(id): Path<Uuid>,
 ) -> Result<Json<DetectedConfig>, ApiError> {
-    let repo = state
-        .repository_repo
-        .get_by_id(ResourceId::from_uuid(id))
+    let repo = authorized_repository(&state, &auth, id, Permission::RepositoryWrite).await?;
+
+    let sync = RepoSync::new(
+        RepoSyncConfig::from_env(),
+        state.repository_repo.clone(),
+        state.pipeline_repo.clone(),
+        state.tenant_repo.clone(),
+        state.organization_repo.clone(),
+    );
+    match sync.sync(&repo).await {
+        Ok(detected_config) => Ok(Json(detected_config)),
+        Err(RepoSyncError::Database(e)) => Err(e.into()),
+        Err(e) => Err(ApiError::Internal(e.to_string())),
+    }
+}
+
+/// Something that can be set up from what was detected in a repository.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SetupSuggestion {
+    /// `pipeline`, `stack` or `application`.
+    pub kind: String,
+    pub name: String,
+    /// Path in the repository it would be set up from.
+    pub path: String,
+    pub reason: String,
+    /// Draft pipeline config, for repositories without one.
+    pub config: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/{id}/suggestions",
+    params(("id" = Uuid, Path, description = "Repository ID")),
+    responses((status = 200, description = "What could be set up from the repository's detected config", body = Vec<SetupSuggestion>))
+)]
+async fn list_suggestions(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<SetupSuggestion>>, ApiError> {
+    let repo = authorized_repository(&state, &auth, id, Permission::RepositoryRead).await?;
+    Ok(Json(setup_suggestions(&state, &repo).await?))
+}
+
+/// Pipelines, stacks and applications the repository's detected config
+/// calls for that are not set up from it yet.
+pub(crate) async fn setup_suggestions(
+    state: &AppState,
+    repo: &Repository,
+) -> Result<Vec<SetupSuggestion>, ApiError> {
+    let repo_id = ResourceId::from_uuid(repo.id);
+    let detected = &repo.detected_config;
+    let mut suggestions = Vec::new();
+
+    let pipelines = state.pipeline_repo.list_by_repository(repo_id).await?;
+    if pipelines.is_empty() {
+        if let Some(path) = &detected.buildit_config {
+            suggestions.push(SetupSuggestion {
+                kind: "pipeline".to_string(),
+                name: repo.name.clone(),
+                path: path.clone(),
+                reason: format!("Pipeline config found at {}", path),
+                config: None,
+            });
+        } else if detected.has_dockerfile() {
+            suggestions.push(SetupSuggestion {
+                kind: "pipeline".to_string(),
+                name: repo.name.clone(),
+                path: ".".to_string(),
+                reason: format!("{} Dockerfile(s) to build", detected.dockerfiles.len()),
+                config: Some(draft_pipeline(repo)),
+            });
+        }
+    }
+
+    let stacks = state.stack_repo.list_stacks_by_repository(repo_id).await?;
+    for dir in &detected.terraform_dirs {
+        if stacks.iter().any(|s| same_dir(&s.path, dir)) {
+            continue;
+        }
+        suggestions.push(SetupSuggestion {
+            kind: "stack".to_string(),
+            name: suggested_name(&repo.name, dir),
+            path: dir.clone(),
+            reason: format!("Terraform configuration in {}", dir),
+            config: None,
+        });
+    }
+
+    let applications = state
+        .application_repo
+        .list_applications_by_repository(repo_id)
         .await?;
+    let manifest_dirs = detected
+        .helm_charts
+        .iter()
+        .map(|dir| (dir, "Helm chart"))
+        .chain(
+            detected
+                .kubernetes_dirs
+                .iter()
+                .map(|dir| (dir, "Kubernetes manifests")),
+        );
+    for (dir, what) in manifest_dirs {
+        if applications.iter().any(|a| same_dir(&a.path, dir))
+            || suggestions
+                .iter()
+                .any(|s| s.kind == "application" && s.path == *dir)
+        {
+            continue;
+        }
+        suggestions.push(SetupSuggestion {
+            kind: "application".to_string(),
+            name: suggested_name(&repo.name, dir),
+            path: dir.clone(),
+            reason: format!("{} in {}", what, dir),
+            config: None,
+        });
+    }
 
-    // Re-clone and scan
-    let git_service = GitService::new();
-    let detected_config = git_service
-        .clone_and_scan(&repo.clone_url, None) // TODO: get token from oauth_connections
-        .await
-        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    Ok(suggestions)
+}
 
-    // Update repository
-    state
+/// Whether two repository paths name the same directory.
+fn same_dir(a: &str, b: &str) -> bool {
+    let normalize = |p: &str| p.trim_start_matches("./").trim_matches('/').to_string();
+    let (a, b) = (normalize(a), normalize(b));
+    a == b || ((a.is_empty() || a == ".") && (b.is_empty() || b == "."))
+}
+
+/// Name for something set up from `dir`, e.g. `api-infra` for `infra`.
+fn suggested_name(repo_name: &str, dir: &str) -> String {
+    match dir
+        .rsplit('/')
+        .next()
+        .filter(|d| !d.is_empty() && *d != ".")
+    {
+        Some(last) => format!("{}-{}", repo_name, last),
+        None => repo_name.to_string(),
+    }
+}
+
+/// A pipeline config building each of the repository's Dockerfiles.
+fn draft_pipeline(repo: &Repository) -> String {
+    let mut config = format!(
+        "// Builds the Dockerfiles found in {}\npipeline {} {{\n    repository {}\n}}\n",
+        repo.full_name,
+        kdl_string(&repo.name),
+        kdl_string(&repo.clone_url)
+    );
+    let single = repo.detected_config.dockerfiles.len() == 1;
+    for dockerfile in &repo.detected_config.dockerfiles {
+        let (dir, file) = match dockerfile.rsplit_once('/') {
+            Some((dir, file)) => (dir, file),
+            None => (".", dockerfile.as_str()),
+        };
+        // `api/Dockerfile.dev` builds as stage `build-api-dev`
+        let variant = file.strip_prefix("Dockerfile").unwrap_or(file);
+        let slug = [dir.trim_start_matches('.'), variant.trim_start_matches('.')]
+            .iter()
+            .filter(|part| !part.is_empty())
+            .map(|part| part.replace('/', "-"))
+            .collect::<Vec<_>>()
+            .join("-");
+        let (stage, image) = if single || slug.is_empty() {
+            ("build".to_string(), repo.name.clone())
+        } else {
+            (format!("build-{}", slug), format!("{}-{}", repo.name, slug))
+        };
+        let command = format!(
+            "docker build -f {} -t {}:${{git.short_sha}} {}",
+            dockerfile, image, dir
+        );
+        config.push_str(&format!(
+            "\nstage {} {{\n    image \"docker:27\"\n    run {}\n}}\n",
+            kdl_string(&stage),
+            kdl_string(&command)
+        ));
+    }
+    config
+}
+
+/// A quoted KDL string.
+fn kdl_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Load a repository, checking the caller holds `permission` in its
+/// organization.
+async fn authorized_repository(
+    state: &AppState,
+    auth: &AuthContext,
+    id: Uuid,
+    permission: Permission,
+) -> Result<Repository, ApiError> {
+    let repo = state
         .repository_repo
-        .update_detected_config(ResourceId::from_uuid(id), &detected_config)
+        .get_by_id(ResourceId::from_uuid(id))
         .await?;
-    state
-        .repository_repo
-        .update_last_synced(ResourceId::from_uuid(id))
+    auth.require_org(state, repo.organization_id, permission)
         .await?;
+    Ok(repo)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
 
-    Ok(Json(detected_config))
+    fn repository(dockerfiles: &[&str]) -> Repository {
+        Repository {
+            id: Uuid::new_v4(),
+            organization_id: Uuid::new_v4(),
+            provider: GitProvider::Github,
+            provider_id: "1".to_string(),
+            owner: "acme".to_string(),
+            name: "shop".to_string(),
+            full_name: "acme/shop".to_string(),
+            clone_url: "https://github.com/acme/shop.git".to_string(),
+            default_branch: "main".to_string(),
+            is_private: false,
+            webhook_id: None,
+            webhook_secret: None,
+            last_synced_at: None,
+            sync_error: None,
+            detected_config: DetectedConfig {
+                dockerfiles: dockerfiles.iter().map(|d| d.to_string()).collect(),
+                ..Default::default()
+            },
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_draft_pipeline_builds_each_dockerfile() {
+        let draft = draft_pipeline(&repository(&["Dockerfile", "api/Dockerfile.dev"]));
+        let pipeline = buildit_config::pipeline::parse_pipeline(&draft).unwrap();
+        assert_eq!(pipeline.name, "shop");
+        let stages: Vec<_> = pipeline.stages.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(stages, ["build", "build-api-dev"]);
+        assert!(draft.contains("docker build -f api/Dockerfile.dev -t shop-api-dev:"));
+
+        let single = draft_pipeline(&repository(&["web/Dockerfile"]));
+        assert!(single.contains("stage \"build\""));
+        assert!(single.contains("-t shop:${git.short_sha} web"));
+    }
+
+    #[test]
+    fn test_suggestions_match_directories_loosely() {
+        assert!(same_dir("./infra/", "infra"));
+        assert!(same_dir(".", ""));
+        assert!(!same_dir("infra", "infra/prod"));
+        assert_eq!(suggested_name("shop", "deploy/k8s"), "shop-k8s");
+        assert_eq!(suggested_name("shop", "."), "shop");
+    }
 }
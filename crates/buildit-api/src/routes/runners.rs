@@ -0,0 +1,352 @@
+//! Self-hosted runner endpoints.
+//!
+//! - `POST /runners` - register a runner; needs an API token with the
+//!   `runners:register` or `admin` scope and returns the runner's own token
+//! - `GET /runners` - runners of the token's organization
+//! - `DELETE /runners/{id}` - remove a runner
+//!
+//! Runners call the rest with their own token as a Bearer token:
+//!
+//! - `POST /runners/{id}/lease?wait=30` - long-poll for a job, `204` if none
+//!   came up within `wait` seconds
+//! - `POST /runners/{id}/jobs/{job_id}/status` - report the job's state
+//! - `POST /runners/{id}/jobs/{job_id}/logs` - append log lines
+//!
+//! Both reports renew the lease and answer whether the job was cancelled.
+//! The same calls are served over gRPC by [`crate::grpc`].
+
+use axum::Json;
+use axum::Router;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::auth::{authenticate_api_key, bearer_or_basic_password, token_hash, unauthorized};
+use crate::error::ApiError;
+use crate::services::remote_executor::RUNNER_ONLINE_SECS;
+use buildit_core::ResourceId;
+use buildit_core::executor::Platform;
+use buildit_core::runner::{
+    JobLease, JobLogBatch, JobReportAck, JobStatusReport, RunnerCredentials, RunnerRegistration,
+};
+use buildit_db::{ApiKey, DbError, RunnerRecord, RunnerRepo};
+
+const REGISTER_SCOPES: &[&str] = &["runners:register", "admin"];
+
+/// Seconds a lease lasts without a report from the runner.
+pub(crate) const LEASE_SECS: i64 = 60;
+
+/// Longest a lease request is held open.
+pub(crate) const MAX_WAIT_SECS: u64 = 60;
+
+const LEASE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    register_runner,
+    list_runners,
+    delete_runner,
+    lease_job,
+    report_status,
+    report_logs
+))]
+pub struct ApiDoc;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list_runners).post(register_runner))
+        .route("/{id}", delete(delete_runner))
+        .route("/{id}/lease", post(lease_job))
+        .route("/{id}/jobs/{job_id}/status", post(report_status))
+        .route("/{id}/jobs/{job_id}/logs", post(report_logs))
+}
+
+fn require_scope(key: &ApiKey) -> Result<(), ApiError> {
+    if key
+        .scopes
+        .iter()
+        .any(|s| REGISTER_SCOPES.contains(&s.as_str()))
+    {
+        Ok(())
+    } else {
+        Err(ApiError::Forbidden(format!(
+            "API token needs one of the scopes: {}",
+            REGISTER_SCOPES.join(", ")
+        )))
+    }
+}
+
+/// Resolve the runner sending the request from its token.
+async fn authenticate_runner(
+    state: &AppState,
+    id: Uuid,
+    headers: &HeaderMap,
+) -> Result<RunnerRecord, Response> {
+    let token = bearer_or_basic_password(headers)
+        .ok_or_else(|| unauthorized("A runner token is required"))?;
+    match state
+        .runner_repo
+        .authenticate_runner(ResourceId::from_uuid(id), &token_hash(&token))
+        .await
+    {
+        Ok(runner) => Ok(runner),
+        Err(DbError::NotFound(_)) => Err(unauthorized("Invalid runner token")),
+        Err(e) => Err(ApiError::from(e).into_response()),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "",
+    request_body = RunnerRegistration,
+    responses((status = 201, description = "The runner's ID and token", body = RunnerCredentials))
+)]
+async fn register_runner(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<RunnerRegistration>,
+) -> Response {
+    let key = match authenticate_api_key(&state, &headers).await {
+        Ok(key) => key,
+        Err(response) => return response,
+    };
+
+    let result = async {
+        require_scope(&key)?;
+        if req.name.trim().is_empty() {
+            return Err(ApiError::BadRequest("Runner name is required".to_string()));
+        }
+
+        let platforms: Vec<String> = if req.platforms.is_empty() {
+            vec![Platform::default().to_string()]
+        } else {
+            req.platforms.iter().map(|p| p.to_string()).collect()
+        };
+        let token = format!("brn_{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let runner = state
+            .runner_repo
+            .register_runner(
+                ResourceId::from_uuid(key.organization_id),
+                req.name.trim(),
+                &req.labels,
+                &platforms,
+                req.version.as_deref(),
+                &token_hash(&token),
+            )
+            .await?;
+        tracing::info!(runner = %runner.name, labels = ?runner.labels, platforms = ?runner.platforms, "Registered runner");
+
+        Ok((
+            StatusCode::CREATED,
+            Json(RunnerCredentials {
+                runner_id: runner.id,
+                token,
+            }),
+        ))
+    };
+    result.await.into_response()
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct RunnerResponse {
+    #[serde(flatten)]
+    runner: RunnerRecord,
+    online: bool,
+}
+
+#[utoipa::path(
+    get,
+    path = "",
+    responses((status = 200, description = "Runners", body = Vec<RunnerResponse>))
+)]
+async fn list_runners(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let key = match authenticate_api_key(&state, &headers).await {
+        Ok(key) => key,
+        Err(response) => return response,
+    };
+
+    let result = async {
+        require_scope(&key)?;
+        let cutoff = chrono::Utc::now() - chrono::Duration::seconds(RUNNER_ONLINE_SECS);
+        let runners: Vec<RunnerResponse> = state
+            .runner_repo
+            .list_runners(ResourceId::from_uuid(key.organization_id))
+            .await?
+            .into_iter()
+            .map(|runner| RunnerResponse {
+                online: runner.last_seen_at.is_some_and(|seen| seen > cutoff),
+                runner,
+            })
+            .collect();
+        Ok::<_, ApiError>(Json(runners))
+    };
+    result.await.into_response()
+}
+
+#[utoipa::path(
+    delete,
+    path = "/{id}",
+    params(("id" = Uuid, Path, description = "Runner ID")),
+    responses((status = 204, description = "Deleted"))
+)]
+async fn delete_runner(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Response {
+    let key = match authenticate_api_key(&state, &headers).await {
+        Ok(key) => key,
+        Err(response) => return response,
+    };
+
+    let result = async {
+        require_scope(&key)?;
+        state
+            .runner_repo
+            .delete_runner(
+                ResourceId::from_uuid(key.organization_id),
+                ResourceId::from_uuid(id),
+            )
+            .await?;
+        Ok::<_, ApiError>(StatusCode::NO_CONTENT)
+    };
+    result.await.into_response()
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct LeaseQuery {
+    /// Seconds to wait for a job before answering `204`.
+    wait: Option<u64>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/{id}/lease",
+    params(("id" = Uuid, Path, description = "Runner ID"), LeaseQuery),
+    responses(
+        (status = 200, description = "A job to run", body = JobLease),
+        (status = 204, description = "No job came up in time")
+    )
+)]
+async fn lease_job(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<LeaseQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let runner = match authenticate_runner(&state, id, &headers).await {
+        Ok(runner) => runner,
+        Err(response) => return response,
+    };
+
+    let wait = Duration::from_secs(query.wait.unwrap_or(30));
+    match wait_for_lease(&state, &runner, wait).await {
+        Ok(Some(lease)) => Json(lease).into_response(),
+        Ok(None) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Lease a job to `runner`, waiting up to `wait` (at most
+/// [`MAX_WAIT_SECS`]) for one to come up.
+pub(crate) async fn wait_for_lease(
+    state: &AppState,
+    runner: &RunnerRecord,
+    wait: Duration,
+) -> Result<Option<JobLease>, ApiError> {
+    let wait = wait.min(Duration::from_secs(MAX_WAIT_SECS));
+    let deadline = tokio::time::Instant::now() + wait;
+    loop {
+        if let Some(job) = state.runner_repo.lease_job(runner, LEASE_SECS).await? {
+            tracing::info!(runner = %runner.name, job = %job.id, "Leased job to runner");
+            return Ok(Some(JobLease {
+                job_id: job.id,
+                spec: job.job_spec()?,
+                lease_seconds: LEASE_SECS as u64,
+            }));
+        }
+        if tokio::time::Instant::now() + LEASE_POLL_INTERVAL > deadline {
+            return Ok(None);
+        }
+        tokio::time::sleep(LEASE_POLL_INTERVAL).await;
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/{id}/jobs/{job_id}/status",
+    params(("id" = Uuid, Path, description = "Runner ID"), ("job_id" = Uuid, Path, description = "Job ID")),
+    request_body = JobStatusReport,
+    responses((status = 200, description = "Whether the job was cancelled", body = JobReportAck))
+)]
+async fn report_status(
+    State(state): State<AppState>,
+    Path((id, job_id)): Path<(Uuid, Uuid)>,
+    headers: HeaderMap,
+    Json(report): Json<JobStatusReport>,
+) -> Response {
+    let runner = match authenticate_runner(&state, id, &headers).await {
+        Ok(runner) => runner,
+        Err(response) => return response,
+    };
+
+    let result = async {
+        let job = state
+            .runner_repo
+            .report_job(
+                ResourceId::from_uuid(job_id),
+                ResourceId::from_uuid(runner.id),
+                report.state,
+                report.exit_code,
+                report.error.as_deref(),
+                LEASE_SECS,
+            )
+            .await?;
+        Ok::<_, ApiError>(Json(JobReportAck {
+            cancel_requested: job.cancel_requested,
+        }))
+    };
+    result.await.into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/{id}/jobs/{job_id}/logs",
+    params(("id" = Uuid, Path, description = "Runner ID"), ("job_id" = Uuid, Path, description = "Job ID")),
+    request_body = JobLogBatch,
+    responses((status = 200, description = "Whether the job was cancelled", body = JobReportAck))
+)]
+async fn report_logs(
+    State(state): State<AppState>,
+    Path((id, job_id)): Path<(Uuid, Uuid)>,
+    headers: HeaderMap,
+    Json(batch): Json<JobLogBatch>,
+) -> Response {
+    let runner = match authenticate_runner(&state, id, &headers).await {
+        Ok(runner) => runner,
+        Err(response) => return response,
+    };
+
+    let result = async {
+        let job = state
+            .runner_repo
+            .append_job_logs(
+                ResourceId::from_uuid(job_id),
+                ResourceId::from_uuid(runner.id),
+                &batch.lines,
+                LEASE_SECS,
+            )
+            .await?;
+        Ok::<_, ApiError>(Json(JobReportAck {
+            cancel_requested: job.cancel_requested,
+        }))
+    };
+    result.await.into_response()
+}
@@ -0,0 +1,182 @@
+//! Pipeline run endpoints addressed by run ID alone, for consumers that
+//! only hold the run.
+
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    routing::get,
+};
+use serde::Serialize;
+use utoipa::{OpenApi, ToSchema};
+use uuid::Uuid;
+
+use super::pipelines::{
+    RunResponse, StageResultResponse, authorized_pipeline, load_stage_definitions,
+};
+use crate::AppState;
+use crate::auth::AuthContext;
+use crate::error::ApiError;
+use crate::services::tasks::Task;
+use buildit_core::ResourceId;
+use buildit_core::rbac::Permission;
+use buildit_db::PipelineRepo;
+use buildit_db::repo::pipeline::{PipelineRecord, PipelineRunRecord};
+use buildit_scheduler::diagnosis::{self, QueueDiagnosis, QueueReason};
+
+#[derive(OpenApi)]
+#[openapi(paths(get_run, get_queue, list_stages))]
+pub struct ApiDoc;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/{id}", get(get_run))
+        .route("/{id}/queue", get(get_queue))
+        .route("/{id}/stages", get(list_stages))
+}
+
+/// Why a queued run has not started.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct QueueResponse {
+    pub run_id: Uuid,
+    pub status: String,
+    /// Seconds spent queued so far.
+    pub queued_secs: Option<f64>,
+    /// Place in line for a free worker, from 1, while waiting for one.
+    pub position: Option<i64>,
+    /// Everything holding the run; empty once it has left the queue.
+    pub reasons: Vec<QueueReasonResponse>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct QueueReasonResponse {
+    /// `waiting_for_worker`, `starting`, `deferred`, `concurrency_limit`,
+    /// `quota_exceeded`, `no_matching_executor` or `not_queued`.
+    pub kind: String,
+    pub message: String,
+    /// The stage no executor can run.
+    pub stage: Option<String>,
+    /// Runs whose jobs fill the tenant's concurrent job quota.
+    pub blocking_runs: Vec<Uuid>,
+    /// When the run is checked again.
+    pub until: Option<String>,
+}
+
+impl From<QueueReason> for QueueReasonResponse {
+    fn from(reason: QueueReason) -> Self {
+        let message = reason.to_string();
+        let kind = reason.kind().to_string();
+        let (stage, blocking_runs, until) = match reason {
+            QueueReason::NoMatchingExecutor { stage, .. } => (Some(stage), vec![], None),
+            QueueReason::ConcurrencyLimit { blocking_runs, .. } => (None, blocking_runs, None),
+            QueueReason::Deferred { until } => (None, vec![], Some(until)),
+            QueueReason::QuotaExceeded { retry_at, .. } => (None, vec![], Some(retry_at)),
+            _ => (None, vec![], None),
+        };
+        Self {
+            kind,
+            message,
+            stage,
+            blocking_runs,
+            until: until.map(|t| t.to_rfc3339()),
+        }
+    }
+}
+
+/// Why a run of the pipeline is still queued. Runs that have started get
+/// an empty diagnosis without looking at the queue.
+pub(crate) async fn queue_diagnosis(
+    state: &AppState,
+    pipeline: &PipelineRecord,
+    run: &PipelineRunRecord,
+) -> Result<QueueDiagnosis, ApiError> {
+    if run.status != "queued" {
+        return Ok(QueueDiagnosis::default());
+    }
+    let stages = load_stage_definitions(state, pipeline).await?;
+    let task_key = Task::PipelineRun { run_id: run.id }.idempotency_key();
+    diagnosis::diagnose(
+        &state.job_queue,
+        &state.quota,
+        state.orchestrator.as_ref().map(|o| o.executors()),
+        pipeline.tenant_id,
+        &task_key,
+        &stages,
+    )
+    .await
+    .map_err(|e| ApiError::Internal(format!("Failed to diagnose queued run: {}", e)))
+}
+
+/// A run by its ID.
+#[utoipa::path(
+    get,
+    path = "/{id}",
+    params(("id" = Uuid, Path, description = "Run ID")),
+    responses((status = 200, description = "The run", body = RunResponse))
+)]
+async fn get_run(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<RunResponse>, ApiError> {
+    let run = state
+        .pipeline_repo
+        .get_run(ResourceId::from_uuid(id))
+        .await?;
+    authorized_pipeline(&state, &auth, run.pipeline_id, Permission::PipelineRead).await?;
+    Ok(Json(run.into()))
+}
+
+/// Why a queued run has not started: its place in line for a worker, and
+/// any tenant quota, concurrency limit or missing executor holding it.
+#[utoipa::path(
+    get,
+    path = "/{id}/queue",
+    params(("id" = Uuid, Path, description = "Run ID")),
+    responses((status = 200, description = "Queue diagnosis", body = QueueResponse))
+)]
+async fn get_queue(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<QueueResponse>, ApiError> {
+    let run = state
+        .pipeline_repo
+        .get_run(ResourceId::from_uuid(id))
+        .await?;
+    let pipeline =
+        authorized_pipeline(&state, &auth, run.pipeline_id, Permission::PipelineRead).await?;
+    let diagnosis = queue_diagnosis(&state, &pipeline, &run).await?;
+    Ok(Json(QueueResponse {
+        run_id: run.id,
+        status: run.status,
+        queued_secs: run.queued_secs,
+        position: diagnosis.position,
+        reasons: diagnosis.reasons.into_iter().map(Into::into).collect(),
+    }))
+}
+
+/// Stages of a run in the order they started, with the executor, job and
+/// exit code of each. Stages are listed as `pending` from the moment the
+/// run is queued.
+#[utoipa::path(
+    get,
+    path = "/{id}/stages",
+    params(("id" = Uuid, Path, description = "Run ID")),
+    responses((status = 200, description = "Stage results", body = Vec<StageResultResponse>))
+)]
+async fn list_stages(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<StageResultResponse>>, ApiError> {
+    let run = state
+        .pipeline_repo
+        .get_run(ResourceId::from_uuid(id))
+        .await?;
+    authorized_pipeline(&state, &auth, run.pipeline_id, Permission::PipelineRead).await?;
+    let results = state
+        .pipeline_repo
+        .list_stage_results(ResourceId::from_uuid(run.id))
+        .await?;
+    Ok(Json(results.into_iter().map(Into::into).collect()))
+}
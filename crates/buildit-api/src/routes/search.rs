@@ -0,0 +1,253 @@
+//! Search across the runs and logs of the tenant the request acts in.
+//!
+//! Besides the explicit filter parameters, `q` takes `branch:`, `author:`,
+//! `sha:` and `status:` qualifiers, e.g. `q=branch:main timeout`; what is
+//! left of `q` is the text searched for.
+
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    routing::get,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::auth::AuthContext;
+use crate::error::ApiError;
+use buildit_core::ResourceId;
+use buildit_core::rbac::Permission;
+use buildit_db::{LogSearchHit, RunSearchFilter, RunSearchHit, SearchRepo};
+
+/// Results returned when the query sets no limit.
+pub const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+
+/// Lines shown around a log match when the query sets none.
+pub const DEFAULT_CONTEXT: i64 = 2;
+const MAX_CONTEXT: i64 = 10;
+
+#[derive(OpenApi)]
+#[openapi(paths(search_runs, search_logs))]
+pub struct ApiDoc;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/runs", get(search_runs))
+        .route("/logs", get(search_logs))
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchQuery {
+    /// Text to search for, with optional qualifiers.
+    pub q: Option<String>,
+    pub pipeline_id: Option<Uuid>,
+    pub status: Option<String>,
+    /// Exact branch name.
+    pub branch: Option<String>,
+    /// Commit SHA prefix.
+    pub sha: Option<String>,
+    /// Part of the commit author.
+    pub author: Option<String>,
+    /// Lines of context around each log match.
+    pub context: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+impl SearchQuery {
+    /// The runs to search and the text to search for. Qualifiers in `q`
+    /// apply where the matching parameter is unset.
+    pub(crate) fn filter(&self, tenant_id: Uuid) -> (RunSearchFilter, String) {
+        let mut filter = RunSearchFilter {
+            tenant_id: ResourceId::from_uuid(tenant_id),
+            pipeline_id: self.pipeline_id,
+            status: non_empty(&self.status),
+            branch: non_empty(&self.branch),
+            sha: non_empty(&self.sha),
+            author: non_empty(&self.author),
+        };
+        let mut words = Vec::new();
+        for word in self.q.as_deref().unwrap_or_default().split_whitespace() {
+            let (field, value) = match word.split_once(':') {
+                Some(("branch", value)) => (&mut filter.branch, value),
+                Some(("author", value)) => (&mut filter.author, value),
+                Some(("sha", value)) => (&mut filter.sha, value),
+                Some(("status", value)) => (&mut filter.status, value),
+                _ => {
+                    words.push(word);
+                    continue;
+                }
+            };
+            if !value.is_empty() {
+                field.get_or_insert_with(|| value.to_string());
+            }
+        }
+        (filter, words.join(" "))
+    }
+
+    pub(crate) fn limit(&self) -> i64 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+
+    pub(crate) fn context(&self) -> i64 {
+        self.context
+            .unwrap_or(DEFAULT_CONTEXT)
+            .clamp(0, MAX_CONTEXT)
+    }
+}
+
+fn non_empty(value: &Option<String>) -> Option<String> {
+    value.clone().filter(|v| !v.is_empty())
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RunSearchResult {
+    #[serde(flatten)]
+    pub run: RunSearchHit,
+    /// The run's page in the web UI.
+    pub url: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LogSearchResult {
+    #[serde(flatten)]
+    pub line: LogSearchHit,
+    /// The line in the web UI's log viewer.
+    pub url: String,
+}
+
+/// Link to a run's page.
+pub(crate) fn run_url(pipeline_id: Uuid, run_id: Uuid) -> String {
+    format!("/pipelines/{}/runs/{}", pipeline_id, run_id)
+}
+
+/// Link to a log line, opening its stage in the run's log viewer.
+pub(crate) fn log_url(line: &LogSearchHit) -> String {
+    format!(
+        "{}?stage={}#L{}",
+        run_url(line.pipeline_id, line.run_id),
+        urlencoding::encode(&line.stage_name),
+        line.line_number
+    )
+}
+
+/// Runs by commit message, SHA, branch, author or number.
+#[utoipa::path(
+    get,
+    path = "/runs",
+    params(SearchQuery),
+    responses((status = 200, description = "Matching runs, newest first", body = Vec<RunSearchResult>))
+)]
+async fn search_runs(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<Vec<RunSearchResult>>, ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::PipelineRead)
+        .await?;
+    let (filter, text) = query.filter(tenant.id);
+
+    let runs = state
+        .search_repo
+        .search_runs(&filter, &text, query.limit())
+        .await?;
+    Ok(Json(
+        runs.into_iter()
+            .map(|run| RunSearchResult {
+                url: run_url(run.pipeline_id, run.run_id),
+                run,
+            })
+            .collect(),
+    ))
+}
+
+/// Log lines containing the text, with the lines around them.
+#[utoipa::path(
+    get,
+    path = "/logs",
+    params(SearchQuery),
+    responses((status = 200, description = "Matching lines, newest runs first", body = Vec<LogSearchResult>))
+)]
+async fn search_logs(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<Vec<LogSearchResult>>, ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::PipelineRead)
+        .await?;
+    let (filter, text) = query.filter(tenant.id);
+    if text.is_empty() {
+        return Err(ApiError::BadRequest(
+            "q must contain text to search the logs for".to_string(),
+        ));
+    }
+
+    let lines = state
+        .search_repo
+        .search_logs(&filter, &text, query.context(), query.limit())
+        .await?;
+    Ok(Json(
+        lines
+            .into_iter()
+            .map(|line| LogSearchResult {
+                url: log_url(&line),
+                line,
+            })
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    #[test]
+    fn test_qualifiers_in_q_become_filters() {
+        let query = SearchQuery {
+            q: Some("branch:main  timeout status:failed author: in build".to_string()),
+            status: Some("succeeded".to_string()),
+            sha: Some(String::new()),
+            ..Default::default()
+        };
+        let tenant_id = Uuid::new_v4();
+        let (filter, text) = query.filter(tenant_id);
+        assert_eq!(filter.tenant_id, ResourceId::from_uuid(tenant_id));
+        assert_eq!(filter.branch.as_deref(), Some("main"));
+        // Explicit parameters win over qualifiers
+        assert_eq!(filter.status.as_deref(), Some("succeeded"));
+        assert_eq!(filter.author, None);
+        assert_eq!(filter.sha, None);
+        assert_eq!(text, "timeout in build");
+    }
+
+    #[test]
+    fn test_log_hits_link_to_their_line() {
+        let line = LogSearchHit {
+            log_id: Uuid::new_v4(),
+            run_id: Uuid::new_v4(),
+            pipeline_id: Uuid::new_v4(),
+            pipeline_name: "api".to_string(),
+            run_number: 7,
+            stage_name: "unit tests".to_string(),
+            stream: "stdout".to_string(),
+            timestamp: Utc::now(),
+            line_number: 42,
+            content: "timeout".to_string(),
+            before: Vec::new(),
+            after: Vec::new(),
+        };
+        assert_eq!(
+            log_url(&line),
+            format!(
+                "/pipelines/{}/runs/{}?stage=unit%20tests#L42",
+                line.pipeline_id, line.run_id
+            )
+        );
+    }
+}
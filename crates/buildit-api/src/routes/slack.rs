@@ -0,0 +1,400 @@
+//! Slack endpoints, under `/webhooks/slack`.
+//!
+//! - `POST /commands` - the `/buildit` slash command: `deploy <service>
+//!   <environment>`, `rerun <run-id>`, `link` and `help`
+//! - `POST /interactions` - the Approve and Reject buttons of approval
+//!   notifications
+//!
+//! Both need the Slack app's signing secret to be configured, see
+//! [`slack`]. A Slack user acts as the BuildIt user they linked, in the
+//! tenant they linked from, with that user's permissions; what they do is
+//! audited as that user.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::post;
+use axum::{Json, Router};
+use buildit_core::ResourceId;
+use buildit_db::{AuditLog, Deployment, DeploymentRepo, OrganizationRepo, PipelineRepo};
+use buildit_scheduler::rerun::RerunMode;
+use chrono::Utc;
+use serde_json::{Value, json};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::auth::AuthContext;
+use crate::error::ApiError;
+use crate::routes::deployment::{self, CreateDeploymentRequest};
+use crate::routes::pipelines;
+use crate::services::slack::{self, APPROVE_ACTION, Command, REJECT_ACTION};
+
+/// How long posting a reply to Slack may take.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Reply to Slack users who have not linked a BuildIt user.
+const NOT_LINKED: &str = "Your Slack account is not linked to BuildIt yet; run `/buildit link`.";
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/commands", post(command))
+        .route("/interactions", post(interaction))
+}
+
+/// Handle a `/buildit` slash command. The reply is the response body.
+async fn command(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<Value>, ApiError> {
+    let secret = verify(&state, &headers, &body)?;
+    let form = form(&body);
+    let field = |name: &str| form.get(name).map(String::as_str).unwrap_or_default();
+    let (team_id, slack_user) = (field("team_id"), field("user_id"));
+
+    let command = match Command::parse(field("text")) {
+        Ok(command) => command,
+        Err(message) => return Ok(Json(slack::ephemeral(&message))),
+    };
+    info!(team = %team_id, user = %slack_user, command = ?command, "Received Slack command");
+
+    let reply = match command {
+        Command::Help => slack::ephemeral(slack::HELP),
+        Command::Link => link_reply(&state, secret, team_id, slack_user),
+        command => match linked_user(&state, team_id, slack_user).await? {
+            None => slack::ephemeral(NOT_LINKED),
+            Some(auth) => match run_command(&state, &auth, slack_user, command).await {
+                Ok(text) => slack::in_channel(&text),
+                Err(e) => slack::ephemeral(&error_text(e)),
+            },
+        },
+    };
+    Ok(Json(reply))
+}
+
+/// Handle a click on a message button. Slack wants an answer within three
+/// seconds, so the click is acknowledged at once and the outcome posted to
+/// the message's `response_url`.
+async fn interaction(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<StatusCode, ApiError> {
+    verify(&state, &headers, &body)?;
+    let payload: Value = form(&body)
+        .get("payload")
+        .and_then(|p| serde_json::from_str(p).ok())
+        .ok_or_else(|| ApiError::BadRequest("Missing interaction payload".to_string()))?;
+    if payload["type"] != "block_actions" {
+        return Ok(StatusCode::OK);
+    }
+
+    let text = |value: &Value| value.as_str().unwrap_or_default().to_string();
+    let team_id = text(&payload["team"]["id"]);
+    let slack_user = text(&payload["user"]["id"]);
+    let response_url = text(&payload["response_url"]);
+    let action = &payload["actions"][0];
+    let (action_id, value) = (text(&action["action_id"]), text(&action["value"]));
+    info!(team = %team_id, user = %slack_user, action = %action_id, "Received Slack action");
+
+    tokio::spawn(async move {
+        let reply = match review(&state, &team_id, &slack_user, &action_id, &value).await {
+            Ok(reply) => reply,
+            Err(e) => json!({
+                "response_type": "ephemeral",
+                "replace_original": false,
+                "text": error_text(e),
+            }),
+        };
+        if let Err(e) = post_reply(&response_url, &reply).await {
+            warn!(error = %e, "Failed to reply to Slack action");
+        }
+    });
+    Ok(StatusCode::OK)
+}
+
+/// The configured signing secret, once the request is verified to come
+/// from Slack.
+fn verify<'a>(state: &'a AppState, headers: &HeaderMap, body: &[u8]) -> Result<&'a str, ApiError> {
+    let secret = state
+        .system_config
+        .notifications
+        .slack_signing_secret
+        .as_deref()
+        .ok_or_else(|| ApiError::NotFound("Slack is not configured".to_string()))?;
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+    };
+    if !slack::verify_signature(
+        secret,
+        header("X-Slack-Request-Timestamp"),
+        body,
+        header("X-Slack-Signature"),
+        Utc::now(),
+    ) {
+        warn!("Invalid Slack request signature");
+        return Err(ApiError::Unauthorized(
+            "Invalid Slack signature".to_string(),
+        ));
+    }
+    Ok(secret)
+}
+
+fn form(body: &[u8]) -> HashMap<String, String> {
+    url::form_urlencoded::parse(body).into_owned().collect()
+}
+
+/// Reply to `/buildit link` with a link to confirm linking in BuildIt.
+fn link_reply(state: &AppState, secret: &str, team_id: &str, slack_user: &str) -> Value {
+    let Some(base) = state.auth.public_url.as_deref() else {
+        return slack::ephemeral(
+            "BuildIt does not know its public URL, so accounts cannot be linked; ask an administrator to set it.",
+        );
+    };
+    let token = slack::link_token(secret, team_id, slack_user, Utc::now());
+    slack::ephemeral(&format!(
+        "<{}/slack/link/{}|Link your Slack account to BuildIt>. The link is valid for 15 minutes.",
+        base.trim_end_matches('/'),
+        token
+    ))
+}
+
+/// The BuildIt user a Slack user acts as, if they linked one.
+async fn linked_user(
+    state: &AppState,
+    team_id: &str,
+    slack_user: &str,
+) -> Result<Option<AuthContext>, ApiError> {
+    Ok(state
+        .organization_repo
+        .get_slack_user(team_id, slack_user)
+        .await?
+        .map(|linked| AuthContext::linked_user(linked.user_id, linked.tenant_id)))
+}
+
+/// Carry out a command as a linked user, describing the outcome.
+async fn run_command(
+    state: &AppState,
+    auth: &AuthContext,
+    slack_user: &str,
+    command: Command,
+) -> Result<String, ApiError> {
+    match command {
+        Command::Deploy {
+            service,
+            environment,
+        } => {
+            let tenant = auth.tenant(state).await?;
+            let repo = &state.deployment_repo;
+            let service = repo
+                .list_services(ResourceId::from_uuid(tenant.id))
+                .await?
+                .into_iter()
+                .find(|s| s.name == service)
+                .ok_or_else(|| ApiError::NotFound(format!("Service {} not found", service)))?;
+            let environment = repo
+                .get_environment_by_name(ResourceId::from_uuid(tenant.id), &environment)
+                .await?
+                .ok_or_else(|| {
+                    ApiError::NotFound(format!("Environment {} not found", environment))
+                })?;
+            let deployment = deployment::deploy(
+                state,
+                auth,
+                CreateDeploymentRequest {
+                    service_id: service.id,
+                    environment_id: environment.id,
+                    image: None,
+                    branch: None,
+                    override_reason: None,
+                },
+            )
+            .await?;
+            audit(
+                state,
+                auth,
+                "deployment.deployments.create",
+                "deployment",
+                deployment.id,
+                slack_user,
+            )
+            .await;
+            Ok(format!(
+                "<@{}> deployed {} {} to {}: {}",
+                slack_user, service.name, deployment.version, environment.name, deployment.status
+            ))
+        }
+        Command::Rerun { run_id } => {
+            let original = state
+                .pipeline_repo
+                .get_run(ResourceId::from_uuid(run_id))
+                .await?;
+            let run =
+                pipelines::start_rerun(state, auth, original.pipeline_id, run_id, RerunMode::All)
+                    .await?;
+            audit(
+                state,
+                auth,
+                "pipelines.runs.rerun",
+                "pipelines",
+                run.pipeline_id,
+                slack_user,
+            )
+            .await;
+            let pipeline = state
+                .pipeline_repo
+                .get_by_id(ResourceId::from_uuid(run.pipeline_id))
+                .await?;
+            Ok(format!(
+                "<@{}> re-ran {} #{} as #{}",
+                slack_user, pipeline.name, original.number, run.number
+            ))
+        }
+        Command::Link | Command::Help => Ok(slack::HELP.to_string()),
+    }
+}
+
+/// Approve or reject a deployment from its notification's buttons. The
+/// buttons are replaced with the outcome once the deployment no longer
+/// waits for approval.
+async fn review(
+    state: &AppState,
+    team_id: &str,
+    slack_user: &str,
+    action_id: &str,
+    value: &str,
+) -> Result<Value, ApiError> {
+    let Some(auth) = linked_user(state, team_id, slack_user).await? else {
+        return Err(ApiError::Unauthorized(NOT_LINKED.to_string()));
+    };
+    let id = Uuid::parse_str(value)
+        .map_err(|_| ApiError::BadRequest(format!("Invalid deployment ID {}", value)))?;
+    let (deployment, action, verb) = match action_id {
+        APPROVE_ACTION => (
+            deployment::approve(state, &auth, id, None).await?,
+            "approve",
+            "approved",
+        ),
+        REJECT_ACTION => (
+            deployment::reject(state, &auth, id, None).await?,
+            "reject",
+            "rejected",
+        ),
+        other => {
+            return Err(ApiError::BadRequest(format!("Unknown action {}", other)));
+        }
+    };
+    audit(
+        state,
+        &auth,
+        &format!("deployment.deployments.{}", action),
+        "deployment",
+        id,
+        slack_user,
+    )
+    .await;
+
+    let text = format!(
+        "<@{}> {} {}",
+        slack_user,
+        verb,
+        describe(state, &deployment).await?
+    );
+    Ok(if deployment.status == "awaiting_approval" {
+        json!({
+            "response_type": "in_channel",
+            "replace_original": false,
+            "text": format!("{}; it needs more approvals", text),
+        })
+    } else {
+        json!({
+            "replace_original": true,
+            "text": format!("{}; it is {}", text, deployment.status),
+        })
+    })
+}
+
+/// `<service> <version> in <environment>`.
+async fn describe(state: &AppState, deployment: &Deployment) -> Result<String, ApiError> {
+    let repo = &state.deployment_repo;
+    let service = repo
+        .get_service(ResourceId::from_uuid(deployment.service_id))
+        .await?;
+    let environment = repo
+        .get_environment(ResourceId::from_uuid(deployment.environment_id))
+        .await?;
+    Ok(format!(
+        "{} {} in {}",
+        service.name, deployment.version, environment.name
+    ))
+}
+
+/// Record what a Slack user did, as the user they are linked to.
+async fn audit(
+    state: &AppState,
+    auth: &AuthContext,
+    action: &str,
+    resource_type: &str,
+    resource_id: Uuid,
+    slack_user: &str,
+) {
+    let entry = AuditLog {
+        id: Uuid::new_v4(),
+        organization_id: None,
+        tenant_id: auth.tenant_id,
+        user_id: auth.user_id,
+        action: action.to_string(),
+        resource_type: Some(resource_type.to_string()),
+        resource_id: Some(resource_id),
+        metadata: json!({ "via": "slack", "slack_user_id": slack_user }),
+        ip_address: None,
+        user_agent: None,
+        created_at: Utc::now(),
+    };
+    if let Err(e) = state.organization_repo.create_audit_log(&entry).await {
+        warn!(action = %entry.action, "Failed to write audit log: {}", e);
+    }
+}
+
+/// What to tell a Slack user about a failure. Internal errors are logged
+/// rather than shown.
+fn error_text(error: ApiError) -> String {
+    match error {
+        ApiError::NotFound(message)
+        | ApiError::BadRequest(message)
+        | ApiError::Unauthorized(message)
+        | ApiError::Forbidden(message)
+        | ApiError::Conflict(message)
+        | ApiError::TooManyRequests(message) => message,
+        ApiError::Internal(message) => {
+            warn!(error = %message, "Slack request failed");
+            "Something went wrong; check the BuildIt server logs.".to_string()
+        }
+    }
+}
+
+async fn post_reply(response_url: &str, reply: &Value) -> Result<(), String> {
+    if !response_url.starts_with("https://") {
+        return Err(format!("unexpected response_url {}", response_url));
+    }
+    let response = reqwest::Client::builder()
+        .timeout(REPLY_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?
+        .post(response_url)
+        .json(reply)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("Slack returned {}", response.status()));
+    }
+    Ok(())
+}
@@ -0,0 +1,220 @@
+//! Terraform HTTP state backend for stacks.
+//!
+//! Implements the protocol of Terraform's `http` backend, so the terraform
+//! CLI and stack runs share one state per stack:
+//!
+//! - `GET /stacks/{id}/state` - current state, `204` if none is stored
+//! - `POST /stacks/{id}/state?ID=<lock id>` - store a new state
+//! - `LOCK /stacks/{id}/state` - acquire the lock, `423` with the holder's
+//!   lock info if it is taken
+//! - `UNLOCK /stacks/{id}/state` - release the lock
+//!
+//! Clients authenticate with an API token, sent either as the Basic auth
+//! password (`TF_HTTP_PASSWORD`) or as a Bearer token. Reading the state
+//! needs `stack.state.read`, locking it `stack.plan` and writing it
+//! `stack.apply`.
+
+use axum::Json;
+use axum::Router;
+use axum::body::Bytes;
+use axum::extract::{Path, Query, State};
+use axum::http::{Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::any;
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::auth::AuthContext;
+use crate::error::ApiError;
+use crate::routes::stacks::authorized_stack;
+use buildit_core::ResourceId;
+use buildit_core::rbac::Permission;
+use buildit_core::stack::Stack;
+use buildit_db::StackRepo;
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/{id}/state", any(state_backend))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StateQuery {
+    /// Lock ID terraform holds while writing state.
+    #[serde(rename = "ID")]
+    pub id: Option<String>,
+}
+
+async fn state_backend(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    Query(query): Query<StateQuery>,
+    method: Method,
+    body: Bytes,
+) -> Response {
+    let result = async {
+        let permission = match method.as_str() {
+            "GET" => Permission::StackStateRead,
+            // Plans lock the state too
+            "LOCK" | "UNLOCK" => Permission::StackPlan,
+            _ => Permission::StackApply,
+        };
+        let stack = authorized_stack(&state, &auth, id, permission).await?;
+        match method.as_str() {
+            "GET" => get_state(&state, &stack).await,
+            "POST" => put_state(&state, &stack, query.id.as_deref(), &body).await,
+            "LOCK" => lock_state(&state, &stack, &auth, &body).await,
+            "UNLOCK" => unlock_state(&state, &stack, &body).await,
+            _ => Ok(StatusCode::METHOD_NOT_ALLOWED.into_response()),
+        }
+    };
+    result.await.into_response()
+}
+
+async fn get_state(state: &AppState, stack: &Stack) -> Result<Response, ApiError> {
+    match state
+        .stack_repo
+        .get_state(ResourceId::from_uuid(stack.id))
+        .await?
+    {
+        // A lock taken before the first write leaves a `null` placeholder.
+        Some(current) if !current.state_json.is_null() => {
+            Ok(Json(current.state_json).into_response())
+        }
+        _ => Ok(StatusCode::NO_CONTENT.into_response()),
+    }
+}
+
+async fn put_state(
+    state: &AppState,
+    stack: &Stack,
+    lock_id: Option<&str>,
+    body: &[u8],
+) -> Result<Response, ApiError> {
+    let stack_id = ResourceId::from_uuid(stack.id);
+    let state_json: serde_json::Value = serde_json::from_slice(body)
+        .map_err(|e| ApiError::BadRequest(format!("Invalid state: {}", e)))?;
+
+    let serial = state_serial(&state_json)?;
+    let lineage = state_json
+        .get("lineage")
+        .and_then(|l| l.as_str())
+        .map(str::to_string);
+    let saved = state
+        .stack_repo
+        .save_state(stack_id, state_json, serial, lineage.as_deref(), lock_id)
+        .await?;
+    if saved.is_none() {
+        let held = state
+            .stack_repo
+            .get_state(stack_id)
+            .await?
+            .and_then(|s| s.lock_id)
+            .unwrap_or_default();
+        return Err(ApiError::Conflict(format!(
+            "State of stack {} is locked by {}",
+            stack.name, held
+        )));
+    }
+
+    Ok(StatusCode::OK.into_response())
+}
+
+async fn lock_state(
+    state: &AppState,
+    stack: &Stack,
+    auth: &AuthContext,
+    body: &[u8],
+) -> Result<Response, ApiError> {
+    let stack_id = ResourceId::from_uuid(stack.id);
+    let info = lock_info(body)?;
+    let lock_id = lock_id(&info)?;
+
+    let acquired = state
+        .stack_repo
+        .lock_state(
+            stack_id,
+            &lock_id,
+            auth.user_id.map(ResourceId::from_uuid),
+            info.clone(),
+        )
+        .await?;
+    if acquired {
+        return Ok(Json(info).into_response());
+    }
+
+    let holder = state
+        .stack_repo
+        .get_state(stack_id)
+        .await?
+        .and_then(|s| s.lock_info)
+        .unwrap_or_else(|| json!({}));
+    Ok((StatusCode::LOCKED, Json(holder)).into_response())
+}
+
+async fn unlock_state(state: &AppState, stack: &Stack, body: &[u8]) -> Result<Response, ApiError> {
+    let stack_id = ResourceId::from_uuid(stack.id);
+    let lock_id = lock_id(&lock_info(body)?)?;
+
+    if state.stack_repo.unlock_state(stack_id, &lock_id).await? {
+        return Ok(StatusCode::OK.into_response());
+    }
+    match state.stack_repo.get_state(stack_id).await? {
+        Some(current) if current.lock_id.is_some() => Ok((
+            StatusCode::CONFLICT,
+            Json(current.lock_info.unwrap_or_else(|| json!({}))),
+        )
+            .into_response()),
+        // Already unlocked.
+        _ => Ok(StatusCode::OK.into_response()),
+    }
+}
+
+/// The state's `serial`, 0 if it has none.
+fn state_serial(state_json: &serde_json::Value) -> Result<i32, ApiError> {
+    let serial = state_json
+        .get("serial")
+        .and_then(|s| s.as_i64())
+        .unwrap_or(0);
+    i32::try_from(serial)
+        .map_err(|_| ApiError::BadRequest(format!("State serial {} is out of range", serial)))
+}
+
+fn lock_info(body: &[u8]) -> Result<serde_json::Value, ApiError> {
+    serde_json::from_slice(body)
+        .map_err(|e| ApiError::BadRequest(format!("Invalid lock info: {}", e)))
+}
+
+fn lock_id(info: &serde_json::Value) -> Result<String, ApiError> {
+    info.get("ID")
+        .and_then(|id| id.as_str())
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+        .ok_or_else(|| ApiError::BadRequest("Lock info has no ID".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_serial() {
+        assert_eq!(state_serial(&json!({"serial": 7})).unwrap(), 7);
+        assert_eq!(state_serial(&json!({})).unwrap(), 0);
+        assert!(matches!(
+            state_serial(&json!({"serial": i64::from(i32::MAX) + 1})),
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_lock_id_is_required() {
+        assert_eq!(lock_id(&json!({"ID": "abc"})).unwrap(), "abc");
+        assert!(matches!(
+            lock_id(&json!({"ID": ""})),
+            Err(ApiError::BadRequest(_))
+        ));
+        assert!(matches!(lock_id(&json!({})), Err(ApiError::BadRequest(_))));
+    }
+}
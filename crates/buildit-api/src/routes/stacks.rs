@@ -4,19 +4,39 @@ use axum::extract::{Path, Query, State};
 use axum::routing::{get, post};
 use axum::{Json, Router};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use utoipa::{IntoParams, OpenApi, ToSchema};
 use uuid::Uuid;
 
 use crate::AppState;
+use crate::audit::AuditBefore;
+use crate::auth::AuthContext;
 use crate::error::ApiError;
-use crate::services::git::GitService;
-use crate::services::terraform::TerraformService;
+use crate::services::approval_context::ApprovalContext;
+use crate::services::tasks::{self, Task};
+use crate::services::terraform::parse_plan_json;
 use buildit_core::ResourceId;
+use buildit_core::rbac::Permission;
 use buildit_core::stack::{
-    CreateStackRequest, PlanSummary, StackRunStatus, StackRunType, StackStatus, StackTriggerType,
-    TriggerStackRunRequest,
+    PolicyResult, ResourceChange, Stack, StackRun, StackRunType, StackStatus, StackTriggerType,
 };
-use buildit_db::{RepositoryRepo, StackRepo};
+use buildit_db::StackRepo;
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    list_stacks,
+    create_stack,
+    get_stack,
+    delete_stack,
+    list_runs,
+    trigger_run,
+    get_run,
+    get_run_plan,
+    get_approval_context,
+    approve_run,
+    list_variables,
+    set_variable
+))]
+pub struct ApiDoc;
 
 pub fn router() -> Router<AppState> {
     Router::new()
@@ -24,16 +44,25 @@ pub fn router() -> Router<AppState> {
         .route("/{id}", get(get_stack).delete(delete_stack))
         .route("/{id}/runs", get(list_runs).post(trigger_run))
         .route("/{id}/runs/{run_id}", get(get_run))
+        .route("/{id}/runs/{run_id}/plan", get(get_run_plan))
         .route("/{id}/runs/{run_id}/approve", post(approve_run))
+        .route(
+            "/{id}/runs/{run_id}/approval-context",
+            get(get_approval_context),
+        )
         .route("/{id}/variables", get(list_variables).post(set_variable))
+        .merge(super::stack_state::router())
+        .merge(super::plan_policies::router())
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct ListStacksQuery {
-    pub tenant_id: Uuid,
+    /// Defaults to the request's tenant.
+    pub tenant_id: Option<Uuid>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct StackResponse {
     pub id: Uuid,
     pub name: String,
@@ -46,13 +75,23 @@ pub struct StackResponse {
     pub last_run_at: Option<String>,
 }
 
+#[utoipa::path(
+    get,
+    path = "",
+    params(ListStacksQuery),
+    responses((status = 200, description = "Stacks", body = Vec<StackResponse>))
+)]
 async fn list_stacks(
     State(state): State<AppState>,
+    auth: AuthContext,
     Query(query): Query<ListStacksQuery>,
 ) -> Result<Json<Vec<StackResponse>>, ApiError> {
+    let tenant_id = auth.tenant_or(&state, query.tenant_id).await?;
+    auth.require(&state, tenant_id, Permission::StackRead)
+        .await?;
     let stacks = state
         .stack_repo
-        .list_stacks_by_tenant(ResourceId::from_uuid(query.tenant_id))
+        .list_stacks_by_tenant(ResourceId::from_uuid(tenant_id))
         .await?;
 
     let response: Vec<StackResponse> = stacks
@@ -73,9 +112,10 @@ async fn list_stacks(
     Ok(Json(response))
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct CreateStackApiRequest {
-    pub tenant_id: Uuid,
+    /// Defaults to the request's tenant.
+    pub tenant_id: Option<Uuid>,
     pub name: String,
     pub description: Option<String>,
     pub repository_id: Option<Uuid>,
@@ -84,14 +124,24 @@ pub struct CreateStackApiRequest {
     pub auto_apply: Option<bool>,
 }
 
+#[utoipa::path(
+    post,
+    path = "",
+    request_body = CreateStackApiRequest,
+    responses((status = 200, description = "The stack", body = StackResponse))
+)]
 async fn create_stack(
     State(state): State<AppState>,
+    auth: AuthContext,
     Json(req): Json<CreateStackApiRequest>,
 ) -> Result<Json<StackResponse>, ApiError> {
+    let tenant_id = auth.tenant_or(&state, req.tenant_id).await?;
+    auth.require(&state, tenant_id, Permission::StackWrite)
+        .await?;
     let stack = state
         .stack_repo
         .create_stack(
-            ResourceId::from_uuid(req.tenant_id),
+            ResourceId::from_uuid(tenant_id),
             &req.name,
             req.description.as_deref(),
             req.repository_id.map(ResourceId::from_uuid),
@@ -101,77 +151,13 @@ async fn create_stack(
         )
         .await?;
 
-    // If linked to a repository, initialize terraform
-    if let Some(repo_id) = req.repository_id {
-        let repo = state
-            .repository_repo
-            .get_by_id(ResourceId::from_uuid(repo_id))
-            .await?;
-
-        // Clone repo and initialize terraform in background
-        let stack_id = stack.id;
-        let stack_repo = state.stack_repo.clone();
-        let path = req.path.clone().unwrap_or_else(|| ".".to_string());
-
-        tokio::spawn(async move {
-            let git_service = GitService::new();
-            let tf_service = TerraformService::new();
-
-            // Clone the repository
-            match git_service.ensure_cloned(&repo.clone_url, None).await {
-                Ok(repo_path) => {
-                    let working_dir = repo_path.join(&path);
-
-                    // Update stack with working directory
-                    if let Err(e) = stack_repo
-                        .update_stack_working_directory(
-                            ResourceId::from_uuid(stack_id),
-                            working_dir.to_str().unwrap_or(""),
-                        )
-                        .await
-                    {
-                        tracing::error!(error = %e, "Failed to update stack working directory");
-                        return;
-                    }
-
-                    // Run terraform init
-                    match tf_service.init(&working_dir, &HashMap::new()).await {
-                        Ok(_) => {
-                            if let Err(e) = stack_repo
-                                .update_stack_status(
-                                    ResourceId::from_uuid(stack_id),
-                                    StackStatus::Ready,
-                                )
-                                .await
-                            {
-                                tracing::error!(error = %e, "Failed to update stack status");
-                            }
-                        }
-                        Err(e) => {
-                            tracing::error!(error = %e, "Terraform init failed");
-                            let _ = stack_repo
-                                .update_stack_status(
-                                    ResourceId::from_uuid(stack_id),
-                                    StackStatus::Error,
-                                )
-                                .await;
-                        }
-                    }
-                }
-                Err(e) => {
-                    tracing::error!(error = %e, "Failed to clone repository");
-                    let _ = stack_repo
-                        .update_stack_status(ResourceId::from_uuid(stack_id), StackStatus::Error)
-                        .await;
-                }
-            }
-        });
-
-        // Update status to initializing
+    // If linked to a repository, clone it and initialize terraform in the background
+    if req.repository_id.is_some() {
         state
             .stack_repo
             .update_stack_status(ResourceId::from_uuid(stack.id), StackStatus::Initializing)
             .await?;
+        tasks::enqueue(&state, Task::StackInit { stack_id: stack.id }).await?;
     }
 
     Ok(Json(StackResponse {
@@ -187,14 +173,18 @@ async fn create_stack(
     }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/{id}",
+    params(("id" = Uuid, Path, description = "Stack ID")),
+    responses((status = 200, description = "The stack", body = StackResponse))
+)]
 async fn get_stack(
     State(state): State<AppState>,
+    auth: AuthContext,
     Path(id): Path<Uuid>,
 ) -> Result<Json<StackResponse>, ApiError> {
-    let stack = state
-        .stack_repo
-        .get_stack(ResourceId::from_uuid(id))
-        .await?;
+    let stack = authorized_stack(&state, &auth, id, Permission::StackRead).await?;
 
     Ok(Json(StackResponse {
         id: stack.id,
@@ -209,19 +199,30 @@ async fn get_stack(
     }))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/{id}",
+    params(("id" = Uuid, Path, description = "Stack ID")),
+    responses((status = 200, description = "Deleted", body = Object, example = json!({"deleted": true})))
+)]
 async fn delete_stack(
     State(state): State<AppState>,
+    auth: AuthContext,
     Path(id): Path<Uuid>,
-) -> Result<Json<serde_json::Value>, ApiError> {
+) -> Result<(AuditBefore, Json<serde_json::Value>), ApiError> {
+    let stack = authorized_stack(&state, &auth, id, Permission::StackWrite).await?;
     state
         .stack_repo
         .delete_stack(ResourceId::from_uuid(id))
         .await?;
 
-    Ok(Json(serde_json::json!({"deleted": true})))
+    Ok((
+        AuditBefore::of(&stack),
+        Json(serde_json::json!({"deleted": true})),
+    ))
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct StackRunResponse {
     pub id: Uuid,
     pub run_type: String,
@@ -235,10 +236,19 @@ pub struct StackRunResponse {
     pub error_message: Option<String>,
 }
 
+#[utoipa::path(
+    get,
+    path = "/{id}/runs",
+    operation_id = "list_stack_runs",
+    params(("id" = Uuid, Path, description = "Stack ID")),
+    responses((status = 200, description = "Runs", body = Vec<StackRunResponse>))
+)]
 async fn list_runs(
     State(state): State<AppState>,
+    auth: AuthContext,
     Path(id): Path<Uuid>,
 ) -> Result<Json<Vec<StackRunResponse>>, ApiError> {
+    authorized_stack(&state, &auth, id, Permission::StackRead).await?;
     let runs = state
         .stack_repo
         .list_runs(ResourceId::from_uuid(id), 20)
@@ -263,13 +273,22 @@ async fn list_runs(
     Ok(Json(response))
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct TriggerRunApiRequest {
     pub run_type: String, // "plan", "apply", "destroy"
 }
 
+#[utoipa::path(
+    post,
+    path = "/{id}/runs",
+    operation_id = "trigger_stack_run",
+    params(("id" = Uuid, Path, description = "Stack ID")),
+    request_body = TriggerRunApiRequest,
+    responses((status = 200, description = "The queued run", body = StackRunResponse))
+)]
 async fn trigger_run(
     State(state): State<AppState>,
+    auth: AuthContext,
     Path(id): Path<Uuid>,
     Json(req): Json<TriggerRunApiRequest>,
 ) -> Result<Json<StackRunResponse>, ApiError> {
@@ -281,10 +300,12 @@ async fn trigger_run(
         _ => return Err(ApiError::BadRequest("Invalid run type".to_string())),
     };
 
-    let stack = state
-        .stack_repo
-        .get_stack(ResourceId::from_uuid(id))
-        .await?;
+    // Plans only read the infrastructure; applying changes it
+    let permission = match run_type {
+        StackRunType::Plan | StackRunType::Refresh => Permission::StackPlan,
+        StackRunType::Apply | StackRunType::Destroy => Permission::StackApply,
+    };
+    authorized_stack(&state, &auth, id, permission).await?;
 
     // Create the run record
     let run = state
@@ -292,201 +313,14 @@ async fn trigger_run(
         .create_run(
             ResourceId::from_uuid(id),
             run_type,
-            None, // TODO: get user from auth
+            auth.user_id.map(ResourceId::from_uuid),
             StackTriggerType::Manual,
             None,
         )
         .await?;
 
     // Execute in background
-    let stack_repo = state.stack_repo.clone();
-    let run_id = run.id;
-
-    tokio::spawn(async move {
-        let tf_service = TerraformService::new();
-
-        // Mark as running
-        if let Err(e) = stack_repo
-            .update_run_started(ResourceId::from_uuid(run_id))
-            .await
-        {
-            tracing::error!(error = %e, "Failed to update run started");
-            return;
-        }
-
-        let working_dir = match &stack.working_directory {
-            Some(dir) => std::path::PathBuf::from(dir),
-            None => {
-                tracing::error!("Stack has no working directory");
-                let _ = stack_repo
-                    .update_run_finished(
-                        ResourceId::from_uuid(run_id),
-                        StackRunStatus::Failed,
-                        Some("Stack has no working directory"),
-                    )
-                    .await;
-                return;
-            }
-        };
-
-        match run_type {
-            StackRunType::Plan => {
-                match tf_service
-                    .plan(&working_dir, &HashMap::new(), None, None)
-                    .await
-                {
-                    Ok(result) => {
-                        let plan_json = result.plan_json.clone();
-                        let _ = stack_repo
-                            .update_run_plan_output(
-                                ResourceId::from_uuid(run_id),
-                                &result.output,
-                                plan_json,
-                                result.summary.to_add.len() as i32,
-                                result.summary.to_change.len() as i32,
-                                result.summary.to_destroy.len() as i32,
-                            )
-                            .await;
-
-                        let status = if result.has_changes && !stack.auto_apply {
-                            StackRunStatus::NeedsApproval
-                        } else if result.has_changes && stack.auto_apply {
-                            // Auto-apply: run apply immediately
-                            if let Some(plan_file) = result.plan_file {
-                                match tf_service.apply(&working_dir, &plan_file, None).await {
-                                    Ok(apply_result) => {
-                                        let _ = stack_repo
-                                            .update_run_apply_output(
-                                                ResourceId::from_uuid(run_id),
-                                                &apply_result.output,
-                                            )
-                                            .await;
-                                        StackRunStatus::Succeeded
-                                    }
-                                    Err(e) => {
-                                        tracing::error!(error = %e, "Apply failed");
-                                        StackRunStatus::Failed
-                                    }
-                                }
-                            } else {
-                                StackRunStatus::Succeeded
-                            }
-                        } else {
-                            StackRunStatus::Succeeded
-                        };
-
-                        let _ = stack_repo
-                            .update_run_finished(ResourceId::from_uuid(run_id), status, None)
-                            .await;
-                    }
-                    Err(e) => {
-                        tracing::error!(error = %e, "Plan failed");
-                        let _ = stack_repo
-                            .update_run_finished(
-                                ResourceId::from_uuid(run_id),
-                                StackRunStatus::Failed,
-                                Some(&e.to_string()),
-                            )
-                            .await;
-                    }
-                }
-            }
-            StackRunType::Apply => {
-                // For apply, we need to run plan first then apply
-                match tf_service
-                    .plan(&working_dir, &HashMap::new(), None, None)
-                    .await
-                {
-                    Ok(result) => {
-                        if let Some(plan_file) = result.plan_file {
-                            match tf_service.apply(&working_dir, &plan_file, None).await {
-                                Ok(apply_result) => {
-                                    let _ = stack_repo
-                                        .update_run_apply_output(
-                                            ResourceId::from_uuid(run_id),
-                                            &apply_result.output,
-                                        )
-                                        .await;
-                                    let _ = stack_repo
-                                        .update_run_finished(
-                                            ResourceId::from_uuid(run_id),
-                                            StackRunStatus::Succeeded,
-                                            None,
-                                        )
-                                        .await;
-                                }
-                                Err(e) => {
-                                    let _ = stack_repo
-                                        .update_run_finished(
-                                            ResourceId::from_uuid(run_id),
-                                            StackRunStatus::Failed,
-                                            Some(&e.to_string()),
-                                        )
-                                        .await;
-                                }
-                            }
-                        } else {
-                            // No changes
-                            let _ = stack_repo
-                                .update_run_finished(
-                                    ResourceId::from_uuid(run_id),
-                                    StackRunStatus::Succeeded,
-                                    None,
-                                )
-                                .await;
-                        }
-                    }
-                    Err(e) => {
-                        let _ = stack_repo
-                            .update_run_finished(
-                                ResourceId::from_uuid(run_id),
-                                StackRunStatus::Failed,
-                                Some(&e.to_string()),
-                            )
-                            .await;
-                    }
-                }
-            }
-            StackRunType::Destroy => {
-                match tf_service
-                    .destroy(&working_dir, &HashMap::new(), None)
-                    .await
-                {
-                    Ok(output) => {
-                        let _ = stack_repo
-                            .update_run_apply_output(ResourceId::from_uuid(run_id), &output)
-                            .await;
-                        let _ = stack_repo
-                            .update_run_finished(
-                                ResourceId::from_uuid(run_id),
-                                StackRunStatus::Succeeded,
-                                None,
-                            )
-                            .await;
-                    }
-                    Err(e) => {
-                        let _ = stack_repo
-                            .update_run_finished(
-                                ResourceId::from_uuid(run_id),
-                                StackRunStatus::Failed,
-                                Some(&e.to_string()),
-                            )
-                            .await;
-                    }
-                }
-            }
-            StackRunType::Refresh => {
-                // TODO: Implement refresh
-                let _ = stack_repo
-                    .update_run_finished(
-                        ResourceId::from_uuid(run_id),
-                        StackRunStatus::Succeeded,
-                        None,
-                    )
-                    .await;
-            }
-        }
-    });
+    tasks::enqueue(&state, Task::StackRun { run_id: run.id }).await?;
 
     Ok(Json(StackRunResponse {
         id: run.id,
@@ -502,14 +336,19 @@ async fn trigger_run(
     }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/{id}/runs/{run_id}",
+    operation_id = "get_stack_run",
+    params(("id" = Uuid, Path, description = "Stack ID"), ("run_id" = Uuid, Path, description = "Run ID")),
+    responses((status = 200, description = "The run", body = StackRunResponse))
+)]
 async fn get_run(
     State(state): State<AppState>,
+    auth: AuthContext,
     Path((stack_id, run_id)): Path<(Uuid, Uuid)>,
 ) -> Result<Json<StackRunResponse>, ApiError> {
-    let run = state
-        .stack_repo
-        .get_run(ResourceId::from_uuid(run_id))
-        .await?;
+    let run = stack_run(&state, &auth, stack_id, run_id, Permission::StackRead).await?;
 
     Ok(Json(StackRunResponse {
         id: run.id,
@@ -525,86 +364,107 @@ async fn get_run(
     }))
 }
 
+#[derive(Debug, Serialize, ToSchema)]
+pub struct StackPlanResponse {
+    pub run_id: Uuid,
+    pub status: String,
+    pub resources_to_add: i32,
+    pub resources_to_change: i32,
+    pub resources_to_destroy: i32,
+    pub changes: Vec<ResourceChange>,
+    /// Outcome of the tenant's plan policies.
+    pub policy_results: Vec<PolicyResult>,
+}
+
+/// Structured, resource-level diff of a run's plan and the policies it was
+/// checked against.
+#[utoipa::path(
+    get,
+    path = "/{id}/runs/{run_id}/plan",
+    operation_id = "get_stack_run_plan",
+    params(("id" = Uuid, Path, description = "Stack ID"), ("run_id" = Uuid, Path, description = "Run ID")),
+    responses((status = 200, description = "The plan", body = StackPlanResponse))
+)]
+async fn get_run_plan(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((stack_id, run_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<StackPlanResponse>, ApiError> {
+    let run = stack_run(&state, &auth, stack_id, run_id, Permission::StackRead).await?;
+
+    // Runs planned before changes were persisted still have their plan JSON.
+    let changes = match (&run.plan_json, run.plan_changes.is_empty()) {
+        (Some(plan_json), true) => parse_plan_json(plan_json),
+        (None, true) if run.plan_output.is_none() => {
+            return Err(ApiError::NotFound(format!("Run {} has no plan", run_id)));
+        }
+        _ => run.plan_changes,
+    };
+
+    Ok(Json(StackPlanResponse {
+        run_id: run.id,
+        status: run.status.to_string(),
+        resources_to_add: run.resources_to_add,
+        resources_to_change: run.resources_to_change,
+        resources_to_destroy: run.resources_to_destroy,
+        changes,
+        policy_results: run.policy_results,
+    }))
+}
+
+/// What an approver should know before applying a run's plan.
+#[utoipa::path(
+    get,
+    path = "/{id}/runs/{run_id}/approval-context",
+    operation_id = "get_stack_run_approval_context",
+    params(("id" = Uuid, Path, description = "Stack ID"), ("run_id" = Uuid, Path, description = "Run ID")),
+    responses((status = 200, description = "The approval context", body = ApprovalContext))
+)]
+async fn get_approval_context(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((stack_id, run_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<ApprovalContext>, ApiError> {
+    let run = stack_run(&state, &auth, stack_id, run_id, Permission::StackRead).await?;
+
+    let context = state
+        .approval_context()
+        .for_stack_run(&run)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    Ok(Json(context))
+}
+
+#[utoipa::path(
+    post,
+    path = "/{id}/runs/{run_id}/approve",
+    operation_id = "approve_stack_run",
+    params(("id" = Uuid, Path, description = "Stack ID"), ("run_id" = Uuid, Path, description = "Run ID")),
+    responses((status = 200, description = "The run, now applying", body = StackRunResponse))
+)]
 async fn approve_run(
     State(state): State<AppState>,
+    auth: AuthContext,
     Path((stack_id, run_id)): Path<(Uuid, Uuid)>,
 ) -> Result<Json<StackRunResponse>, ApiError> {
-    // TODO: Get user from auth
-    let user_id = Uuid::nil(); // Placeholder
+    stack_run(&state, &auth, stack_id, run_id, Permission::StackApply).await?;
 
     // Approve the run
     state
         .stack_repo
         .approve_run(
             ResourceId::from_uuid(run_id),
-            ResourceId::from_uuid(user_id),
+            auth.user_id.map(ResourceId::from_uuid),
         )
         .await?;
 
-    // Get the stack and run
-    let stack = state
-        .stack_repo
-        .get_stack(ResourceId::from_uuid(stack_id))
-        .await?;
     let run = state
         .stack_repo
         .get_run(ResourceId::from_uuid(run_id))
         .await?;
 
     // Execute apply in background
-    let stack_repo = state.stack_repo.clone();
-
-    tokio::spawn(async move {
-        let tf_service = TerraformService::new();
-
-        let working_dir = match &stack.working_directory {
-            Some(dir) => std::path::PathBuf::from(dir),
-            None => return,
-        };
-
-        // Update status to applying
-        let _ = stack_repo
-            .update_run_status(ResourceId::from_uuid(run_id), StackRunStatus::Applying)
-            .await;
-
-        // The plan file should still exist from the original plan
-        let plan_file = working_dir.join("tfplan");
-
-        if plan_file.exists() {
-            match tf_service.apply(&working_dir, &plan_file, None).await {
-                Ok(result) => {
-                    let _ = stack_repo
-                        .update_run_apply_output(ResourceId::from_uuid(run_id), &result.output)
-                        .await;
-                    let _ = stack_repo
-                        .update_run_finished(
-                            ResourceId::from_uuid(run_id),
-                            StackRunStatus::Succeeded,
-                            None,
-                        )
-                        .await;
-                }
-                Err(e) => {
-                    let _ = stack_repo
-                        .update_run_finished(
-                            ResourceId::from_uuid(run_id),
-                            StackRunStatus::Failed,
-                            Some(&e.to_string()),
-                        )
-                        .await;
-                }
-            }
-        } else {
-            // Plan file doesn't exist, need to re-plan
-            let _ = stack_repo
-                .update_run_finished(
-                    ResourceId::from_uuid(run_id),
-                    StackRunStatus::Failed,
-                    Some("Plan file not found - please run a new plan"),
-                )
-                .await;
-        }
-    });
+    tasks::enqueue(&state, Task::StackApply { run_id }).await?;
 
     Ok(Json(StackRunResponse {
         id: run.id,
@@ -620,7 +480,7 @@ async fn approve_run(
     }))
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct StackVariableResponse {
     pub key: String,
     pub value: Option<String>,
@@ -629,10 +489,19 @@ pub struct StackVariableResponse {
     pub description: Option<String>,
 }
 
+#[utoipa::path(
+    get,
+    path = "/{id}/variables",
+    operation_id = "list_stack_variables",
+    params(("id" = Uuid, Path, description = "Stack ID")),
+    responses((status = 200, description = "Variables; sensitive values are hidden", body = Vec<StackVariableResponse>))
+)]
 async fn list_variables(
     State(state): State<AppState>,
+    auth: AuthContext,
     Path(id): Path<Uuid>,
 ) -> Result<Json<Vec<StackVariableResponse>>, ApiError> {
+    authorized_stack(&state, &auth, id, Permission::StackRead).await?;
     let variables = state
         .stack_repo
         .list_variables(ResourceId::from_uuid(id))
@@ -652,7 +521,7 @@ async fn list_variables(
     Ok(Json(response))
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct SetVariableRequest {
     pub key: String,
     pub value: Option<String>,
@@ -661,11 +530,21 @@ pub struct SetVariableRequest {
     pub description: Option<String>,
 }
 
+#[utoipa::path(
+    post,
+    path = "/{id}/variables",
+    operation_id = "set_stack_variable",
+    params(("id" = Uuid, Path, description = "Stack ID")),
+    request_body = SetVariableRequest,
+    responses((status = 200, description = "The variable", body = StackVariableResponse))
+)]
 async fn set_variable(
     State(state): State<AppState>,
+    auth: AuthContext,
     Path(id): Path<Uuid>,
     Json(req): Json<SetVariableRequest>,
 ) -> Result<Json<StackVariableResponse>, ApiError> {
+    authorized_stack(&state, &auth, id, Permission::SecretsWrite).await?;
     let variable = state
         .stack_repo
         .set_variable(
@@ -690,3 +569,38 @@ async fn set_variable(
         description: variable.description,
     }))
 }
+
+/// Load a stack, checking the caller holds `permission` in its tenant.
+pub(crate) async fn authorized_stack(
+    state: &AppState,
+    auth: &AuthContext,
+    id: Uuid,
+    permission: Permission,
+) -> Result<Stack, ApiError> {
+    let stack = state
+        .stack_repo
+        .get_stack(ResourceId::from_uuid(id))
+        .await?;
+    auth.require(state, stack.tenant_id, permission).await?;
+    Ok(stack)
+}
+
+/// Load a run, making sure it belongs to the stack in the path and the
+/// caller holds `permission` in the stack's tenant.
+async fn stack_run(
+    state: &AppState,
+    auth: &AuthContext,
+    stack_id: Uuid,
+    run_id: Uuid,
+    permission: Permission,
+) -> Result<StackRun, ApiError> {
+    authorized_stack(state, auth, stack_id, permission).await?;
+    let run = state
+        .stack_repo
+        .get_run(ResourceId::from_uuid(run_id))
+        .await?;
+    if run.stack_id != stack_id {
+        return Err(ApiError::NotFound(format!("Run {} not found", run_id)));
+    }
+    Ok(run)
+}
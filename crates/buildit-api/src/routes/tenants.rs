@@ -1,53 +1,126 @@
 //! Tenant management endpoints.
 
-use axum::extract::{Path, State};
+use axum::extract::{Path, Query, State};
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
 use axum::routing::get;
 use axum::{Json, Router};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use uuid::Uuid;
 
 use crate::AppState;
+use crate::audit::AuditBefore;
+use crate::auth::AuthContext;
 use crate::error::ApiError;
-use buildit_db::TenantRepo;
+use crate::routes::pipelines::{load_stage_definitions, stage_names};
+use crate::services::tasks;
+use crate::services::tenant_config::{self, ImportSummary, TenantConfig};
+use buildit_core::ResourceId;
+use buildit_core::rbac::Permission;
+use buildit_core::tenant::{
+    ArtifactRetention, LogRetention, PolicyDrift, TenantPolicy, TenantQuota,
+};
+use buildit_db::{PipelineRepo, Tenant, TenantRepo};
+use buildit_scheduler::quota;
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    list_tenants,
+    create_tenant,
+    get_tenant,
+    get_policy,
+    update_policy,
+    policy_drift,
+    get_quota,
+    update_quota,
+    get_log_retention,
+    update_log_retention,
+    get_artifact_retention,
+    update_artifact_retention,
+    get_usage,
+    export_config,
+    import_config
+))]
+pub struct ApiDoc;
 
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/", get(list_tenants).post(create_tenant))
         .route("/{slug}", get(get_tenant))
+        .route("/{slug}/policy", get(get_policy).put(update_policy))
+        .route("/{slug}/policy/drift", get(policy_drift))
+        .route("/{slug}/quota", get(get_quota).put(update_quota))
+        .route(
+            "/{slug}/log-retention",
+            get(get_log_retention).put(update_log_retention),
+        )
+        .route(
+            "/{slug}/artifact-retention",
+            get(get_artifact_retention).put(update_artifact_retention),
+        )
+        .route("/{slug}/usage", get(get_usage))
+        .route("/{slug}/config", get(export_config).put(import_config))
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 struct TenantResponse {
     id: String,
     name: String,
     slug: String,
 }
 
+/// Tenants the caller can read.
+#[utoipa::path(
+    get,
+    path = "",
+    responses((status = 200, description = "Readable tenants", body = Vec<TenantResponse>))
+)]
 async fn list_tenants(
     State(state): State<AppState>,
+    auth: AuthContext,
 ) -> Result<Json<Vec<TenantResponse>>, ApiError> {
-    let tenants = state.tenant_repo.list().await?;
-    let response: Vec<TenantResponse> = tenants
-        .into_iter()
-        .map(|t| TenantResponse {
-            id: t.id.to_string(),
-            name: t.name,
-            slug: t.slug,
-        })
-        .collect();
+    let mut response = Vec::new();
+    for tenant in state.tenant_repo.list().await? {
+        let role = auth.role_in(&state, tenant.id).await?;
+        if role.is_some_and(|r| r.grants(Permission::TenantRead)) {
+            response.push(TenantResponse {
+                id: tenant.id.to_string(),
+                name: tenant.name,
+                slug: tenant.slug,
+            });
+        }
+    }
     Ok(Json(response))
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 struct CreateTenantRequest {
     name: String,
     slug: String,
 }
 
+/// Create a tenant in the caller's organization.
+#[utoipa::path(
+    post,
+    path = "",
+    request_body = CreateTenantRequest,
+    responses((status = 200, description = "The tenant", body = TenantResponse))
+)]
 async fn create_tenant(
     State(state): State<AppState>,
+    auth: AuthContext,
     Json(req): Json<CreateTenantRequest>,
 ) -> Result<Json<TenantResponse>, ApiError> {
-    let tenant = state.tenant_repo.create(&req.name, &req.slug).await?;
+    let org_id = auth.organization()?;
+    auth.require_org(&state, org_id, Permission::TenantCreate)
+        .await?;
+    let tenant = state
+        .tenant_repo
+        .create(&req.name, &req.slug, Some(ResourceId::from_uuid(org_id)))
+        .await?;
     Ok(Json(TenantResponse {
         id: tenant.id.to_string(),
         name: tenant.name,
@@ -55,14 +128,490 @@ async fn create_tenant(
     }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/{slug}",
+    params(("slug" = String, Path, description = "Tenant slug")),
+    responses((status = 200, description = "The tenant", body = TenantResponse))
+)]
 async fn get_tenant(
     State(state): State<AppState>,
+    auth: AuthContext,
     Path(slug): Path<String>,
 ) -> Result<Json<TenantResponse>, ApiError> {
     let tenant = state.tenant_repo.get_by_slug(&slug).await?;
+    auth.require(&state, tenant.id, Permission::TenantRead)
+        .await?;
     Ok(Json(TenantResponse {
         id: tenant.id.to_string(),
         name: tenant.name,
         slug: tenant.slug,
     }))
 }
+
+#[utoipa::path(
+    get,
+    path = "/{slug}/policy",
+    params(("slug" = String, Path, description = "Tenant slug")),
+    responses((status = 200, description = "The policy template", body = TenantPolicy))
+)]
+async fn get_policy(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(slug): Path<String>,
+) -> Result<Json<TenantPolicy>, ApiError> {
+    let tenant = state.tenant_repo.get_by_slug(&slug).await?;
+    auth.require(&state, tenant.id, Permission::TenantRead)
+        .await?;
+    Ok(Json(tenant.policy()))
+}
+
+/// Replace the tenant's policy template. Only pipelines created afterwards
+/// get the new defaults; existing ones show up in the drift report.
+#[utoipa::path(
+    put,
+    path = "/{slug}/policy",
+    params(("slug" = String, Path, description = "Tenant slug")),
+    request_body = TenantPolicy,
+    responses((status = 200, description = "The policy template", body = TenantPolicy))
+)]
+async fn update_policy(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(slug): Path<String>,
+    Json(policy): Json<TenantPolicy>,
+) -> Result<(AuditBefore, Json<TenantPolicy>), ApiError> {
+    if policy.required_stages.iter().any(|s| s.trim().is_empty()) {
+        return Err(ApiError::BadRequest(
+            "required stage names must not be empty".to_string(),
+        ));
+    }
+    if policy
+        .stage_security
+        .allowed_capabilities
+        .iter()
+        .any(|c| c.trim().is_empty())
+    {
+        return Err(ApiError::BadRequest(
+            "allowed capabilities must not be empty".to_string(),
+        ));
+    }
+    let tenant = state.tenant_repo.get_by_slug(&slug).await?;
+    auth.require(&state, tenant.id, Permission::TenantManage)
+        .await?;
+    let before = AuditBefore::of(&tenant.policy());
+    let tenant = state
+        .tenant_repo
+        .update_policy(ResourceId::from_uuid(tenant.id), &policy)
+        .await?;
+    tracing::info!(tenant = %tenant.slug, "Tenant policy updated");
+    Ok((before, Json(tenant.policy())))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct PipelineDriftResponse {
+    pipeline_id: String,
+    name: String,
+    drift: Vec<PolicyDrift>,
+}
+
+/// Active pipelines of the tenant that deviate from its policy template.
+#[utoipa::path(
+    get,
+    path = "/{slug}/policy/drift",
+    params(("slug" = String, Path, description = "Tenant slug")),
+    responses((status = 200, description = "Drifting pipelines", body = Vec<PipelineDriftResponse>))
+)]
+async fn policy_drift(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(slug): Path<String>,
+) -> Result<Json<Vec<PipelineDriftResponse>>, ApiError> {
+    let tenant = state.tenant_repo.get_by_slug(&slug).await?;
+    auth.require(&state, tenant.id, Permission::PipelineRead)
+        .await?;
+    let policy = tenant.policy();
+    if policy.is_empty() {
+        return Ok(Json(vec![]));
+    }
+
+    let pipelines = state
+        .pipeline_repo
+        .list_by_tenant(ResourceId::from_uuid(tenant.id))
+        .await?;
+    let mut response = Vec::new();
+    for pipeline in pipelines {
+        let stages = load_stage_definitions(&state, &pipeline).await?;
+        let drift = policy.drift(&pipeline.config, &stage_names(&stages));
+        if !drift.is_empty() {
+            response.push(PipelineDriftResponse {
+                pipeline_id: pipeline.id.to_string(),
+                name: pipeline.name,
+                drift,
+            });
+        }
+    }
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    get,
+    path = "/{slug}/quota",
+    params(("slug" = String, Path, description = "Tenant slug")),
+    responses((status = 200, description = "The quota", body = TenantQuota))
+)]
+async fn get_quota(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(slug): Path<String>,
+) -> Result<Json<TenantQuota>, ApiError> {
+    let tenant = state.tenant_repo.get_by_slug(&slug).await?;
+    auth.require(&state, tenant.id, Permission::TenantRead)
+        .await?;
+    Ok(Json(tenant.quota()))
+}
+
+/// Replace the tenant's quota. Quotas are set by whoever may create
+/// tenants in its organization, so its own admins cannot raise them.
+/// Jobs already running are not stopped, and runs held for the old quota
+/// are checked against the new one straight away.
+#[utoipa::path(
+    put,
+    path = "/{slug}/quota",
+    params(("slug" = String, Path, description = "Tenant slug")),
+    request_body = TenantQuota,
+    responses((status = 200, description = "The quota", body = TenantQuota))
+)]
+async fn update_quota(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(slug): Path<String>,
+    Json(quota): Json<TenantQuota>,
+) -> Result<(AuditBefore, Json<TenantQuota>), ApiError> {
+    quota::validate(&quota).map_err(ApiError::BadRequest)?;
+    let tenant = state.tenant_repo.get_by_slug(&slug).await?;
+    require_quota_admin(&state, &auth, &tenant).await?;
+    let before = AuditBefore::of(&tenant.quota());
+    let tenant = state
+        .tenant_repo
+        .update_quota(ResourceId::from_uuid(tenant.id), &quota)
+        .await?;
+    tracing::info!(tenant = %tenant.slug, "Tenant quota updated");
+    if let Err(e) = tasks::release_held_runs(&state, ResourceId::from_uuid(tenant.id)).await {
+        tracing::warn!(tenant = %tenant.slug, error = ?e, "Failed to release held runs");
+    }
+    Ok((before, Json(tenant.quota())))
+}
+
+async fn require_quota_admin(
+    state: &AppState,
+    auth: &AuthContext,
+    tenant: &Tenant,
+) -> Result<(), ApiError> {
+    match tenant.organization_id {
+        Some(org_id) => {
+            auth.require_org(state, org_id, Permission::TenantCreate)
+                .await
+        }
+        None => {
+            auth.require(state, tenant.id, Permission::TenantManage)
+                .await
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/{slug}/log-retention",
+    params(("slug" = String, Path, description = "Tenant slug")),
+    responses((status = 200, description = "The log retention", body = LogRetention))
+)]
+async fn get_log_retention(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(slug): Path<String>,
+) -> Result<Json<LogRetention>, ApiError> {
+    let tenant = state.tenant_repo.get_by_slug(&slug).await?;
+    auth.require(&state, tenant.id, Permission::TenantRead)
+        .await?;
+    Ok(Json(tenant.log_retention()))
+}
+
+/// Replace the tenant's log retention. It applies to finished runs on the
+/// log archiver's next pass, including runs that finished before it was
+/// set.
+#[utoipa::path(
+    put,
+    path = "/{slug}/log-retention",
+    params(("slug" = String, Path, description = "Tenant slug")),
+    request_body = LogRetention,
+    responses((status = 200, description = "The log retention", body = LogRetention))
+)]
+async fn update_log_retention(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(slug): Path<String>,
+    Json(retention): Json<LogRetention>,
+) -> Result<(AuditBefore, Json<LogRetention>), ApiError> {
+    retention.validate().map_err(ApiError::BadRequest)?;
+    let tenant = state.tenant_repo.get_by_slug(&slug).await?;
+    auth.require(&state, tenant.id, Permission::TenantManage)
+        .await?;
+    let before = AuditBefore::of(&tenant.log_retention());
+    let tenant = state
+        .tenant_repo
+        .update_log_retention(ResourceId::from_uuid(tenant.id), &retention)
+        .await?;
+    tracing::info!(tenant = %tenant.slug, "Tenant log retention updated");
+    Ok((before, Json(tenant.log_retention())))
+}
+
+#[utoipa::path(
+    get,
+    path = "/{slug}/artifact-retention",
+    params(("slug" = String, Path, description = "Tenant slug")),
+    responses((status = 200, description = "The artifact retention", body = ArtifactRetention))
+)]
+async fn get_artifact_retention(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(slug): Path<String>,
+) -> Result<Json<ArtifactRetention>, ApiError> {
+    let tenant = state.tenant_repo.get_by_slug(&slug).await?;
+    auth.require(&state, tenant.id, Permission::TenantRead)
+        .await?;
+    Ok(Json(tenant.artifact_retention()))
+}
+
+/// Replace the tenant's artifact retention. The artifact garbage collector
+/// applies it on its next pass; pinned artifacts are never collected.
+#[utoipa::path(
+    put,
+    path = "/{slug}/artifact-retention",
+    params(("slug" = String, Path, description = "Tenant slug")),
+    request_body = ArtifactRetention,
+    responses((status = 200, description = "The artifact retention", body = ArtifactRetention))
+)]
+async fn update_artifact_retention(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(slug): Path<String>,
+    Json(retention): Json<ArtifactRetention>,
+) -> Result<(AuditBefore, Json<ArtifactRetention>), ApiError> {
+    retention.validate().map_err(ApiError::BadRequest)?;
+    let tenant = state.tenant_repo.get_by_slug(&slug).await?;
+    auth.require(&state, tenant.id, Permission::TenantManage)
+        .await?;
+    let before = AuditBefore::of(&tenant.artifact_retention());
+    let tenant = state
+        .tenant_repo
+        .update_artifact_retention(ResourceId::from_uuid(tenant.id), &retention)
+        .await?;
+    tracing::info!(tenant = %tenant.slug, "Tenant artifact retention updated");
+    Ok((before, Json(tenant.artifact_retention())))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct UsageQuery {
+    /// Month to report, as `YYYY-MM` (UTC); defaults to the current month.
+    month: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct UsageResponse {
+    quota: TenantQuota,
+    /// Start of the reported month.
+    period_start: DateTime<Utc>,
+    /// Start of the month after it.
+    period_end: DateTime<Utc>,
+    /// Stage jobs running now.
+    running_jobs: i64,
+    /// CPU cores requested by the running jobs.
+    cpu_cores_in_use: f64,
+    /// Memory requested by the running jobs, in GiB.
+    memory_gib_in_use: f64,
+    /// Job minutes used this month, counted against the monthly quota.
+    run_minutes_this_month: f64,
+    /// Totals over the reported month.
+    jobs: i64,
+    run_minutes: f64,
+    cpu_core_minutes: f64,
+    memory_gib_minutes: f64,
+    /// Usage over the reported month per pipeline, most used first.
+    pipelines: Vec<PipelineUsageResponse>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct PipelineUsageResponse {
+    /// `None` for pipelines deleted since.
+    pipeline_id: Option<Uuid>,
+    name: Option<String>,
+    jobs: i64,
+    run_minutes: f64,
+    /// CPU cores requested times minutes run.
+    cpu_core_minutes: f64,
+    /// Memory requested in GiB times minutes run.
+    memory_gib_minutes: f64,
+}
+
+/// The tenant's quota, what it is using now, and its usage over a month
+/// for billing.
+#[utoipa::path(
+    get,
+    path = "/{slug}/usage",
+    params(("slug" = String, Path, description = "Tenant slug"), UsageQuery),
+    responses((status = 200, description = "Usage", body = UsageResponse))
+)]
+async fn get_usage(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(slug): Path<String>,
+    Query(query): Query<UsageQuery>,
+) -> Result<Json<UsageResponse>, ApiError> {
+    let tenant = state.tenant_repo.get_by_slug(&slug).await?;
+    auth.require(&state, tenant.id, Permission::TenantRead)
+        .await?;
+
+    let period_start = match &query.month {
+        Some(month) => NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d")
+            .map(|date| Utc.from_utc_datetime(&date.and_time(Default::default())))
+            .map_err(|_| ApiError::BadRequest(format!("Invalid month '{}'; use YYYY-MM", month)))?,
+        None => quota::month_start(Utc::now()),
+    };
+    let period_end = quota::next_month(period_start);
+
+    let internal = |e: sqlx::Error| ApiError::Internal(format!("Failed to load usage: {}", e));
+    let current = state.quota.current(tenant.id).await.map_err(internal)?;
+    let report = state
+        .quota
+        .report(tenant.id, period_start, period_end)
+        .await
+        .map_err(internal)?;
+    let names: HashMap<Uuid, String> = state
+        .pipeline_repo
+        .list_by_tenant(ResourceId::from_uuid(tenant.id))
+        .await?
+        .into_iter()
+        .map(|p| (p.id, p.name))
+        .collect();
+
+    Ok(Json(UsageResponse {
+        quota: tenant.quota(),
+        period_start,
+        period_end,
+        running_jobs: current.running_jobs,
+        cpu_cores_in_use: current.cpu_millis as f64 / 1000.0,
+        memory_gib_in_use: current.memory_bytes as f64 / (1024.0 * 1024.0 * 1024.0),
+        run_minutes_this_month: current.run_minutes,
+        jobs: report.iter().map(|p| p.jobs).sum(),
+        run_minutes: report.iter().fold(0.0, |sum, p| sum + p.run_minutes),
+        cpu_core_minutes: report.iter().fold(0.0, |sum, p| sum + p.cpu_core_minutes),
+        memory_gib_minutes: report.iter().fold(0.0, |sum, p| sum + p.memory_gib_minutes),
+        pipelines: report
+            .into_iter()
+            .map(|p| PipelineUsageResponse {
+                name: p.pipeline_id.and_then(|id| names.get(&id).cloned()),
+                pipeline_id: p.pipeline_id,
+                jobs: p.jobs,
+                run_minutes: p.run_minutes,
+                cpu_core_minutes: p.cpu_core_minutes,
+                memory_gib_minutes: p.memory_gib_minutes,
+            })
+            .collect(),
+    }))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ExportQuery {
+    /// `yaml` (the default) or `json`.
+    format: Option<String>,
+}
+
+/// The tenant's pipelines, environments, targets, variable groups and
+/// notification channels as one document, without secrets.
+#[utoipa::path(
+    get,
+    path = "/{slug}/config",
+    params(("slug" = String, Path, description = "Tenant slug"), ExportQuery),
+    responses((status = 200, description = "The configuration", body = TenantConfig, content_type = "application/yaml"))
+)]
+async fn export_config(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(slug): Path<String>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, ApiError> {
+    let tenant = state.tenant_repo.get_by_slug(&slug).await?;
+    for permission in [
+        Permission::TenantRead,
+        Permission::PipelineRead,
+        Permission::DeploymentRead,
+    ] {
+        auth.require(&state, tenant.id, permission).await?;
+    }
+    let config = tenant_config::export(&state, &tenant).await?;
+
+    match query.format.as_deref().unwrap_or("yaml") {
+        "yaml" => {
+            let yaml = serde_yaml::to_string(&config)
+                .map_err(|e| ApiError::Internal(format!("Failed to write YAML: {}", e)))?;
+            Ok(([(header::CONTENT_TYPE, "application/yaml")], yaml).into_response())
+        }
+        "json" => Ok(Json(config).into_response()),
+        other => Err(ApiError::BadRequest(format!(
+            "Unknown format '{}'; expected yaml or json",
+            other
+        ))),
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ImportQuery {
+    /// Report what would change without changing it.
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Create and update the tenant's configuration from an exported document,
+/// in YAML or JSON. Nothing is deleted, and secrets have to be set again.
+#[utoipa::path(
+    put,
+    path = "/{slug}/config",
+    params(("slug" = String, Path, description = "Tenant slug"), ImportQuery),
+    request_body(content = TenantConfig, content_type = "application/yaml"),
+    responses((status = 200, description = "What changed", body = ImportSummary))
+)]
+async fn import_config(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(slug): Path<String>,
+    Query(query): Query<ImportQuery>,
+    body: String,
+) -> Result<Json<ImportSummary>, ApiError> {
+    let tenant = state.tenant_repo.get_by_slug(&slug).await?;
+    for permission in [
+        Permission::TenantManage,
+        Permission::PipelineWrite,
+        Permission::DeploymentWrite,
+    ] {
+        auth.require(&state, tenant.id, permission).await?;
+    }
+    // JSON is YAML too
+    let config: TenantConfig = serde_yaml::from_str(&body)
+        .map_err(|e| ApiError::BadRequest(format!("Invalid configuration: {}", e)))?;
+
+    let summary =
+        tenant_config::import(&state, &tenant, auth.user_id, config, query.dry_run).await?;
+    if !query.dry_run {
+        tracing::info!(
+            tenant = %tenant.slug,
+            created = summary.created.len(),
+            updated = summary.updated.len(),
+            "Tenant configuration imported"
+        );
+    }
+    Ok(Json(summary))
+}
@@ -2,17 +2,39 @@
 
 use askama::Template;
 use axum::Router;
-use axum::extract::{Path, State};
-use axum::response::{Html, IntoResponse};
+use axum::extract::{Form, Path, Query, State};
+use axum::http::{HeaderMap, Uri};
+use axum::response::{Html, IntoResponse, Redirect, Response};
 use axum::routing::get;
+use axum_extra::extract::CookieJar;
+use axum_extra::extract::cookie::{Cookie, SameSite};
 use uuid::Uuid;
 
 use crate::AppState;
+use crate::auth::{INVITATION_COOKIE, cookie_session, cookie_user, token_hash};
 use crate::error::ApiError;
+use crate::routes::analytics::{DEFAULT_DAYS, cost_report};
+use crate::routes::auth::normalize_user_code;
+use crate::routes::deployment::{self, required_approvals};
+use crate::routes::repositories::{SetupSuggestion, setup_suggestions};
+use crate::routes::runs;
+use crate::routes::search::{SearchQuery, log_url, run_url};
+use crate::services::approval_context::ApprovalContext;
+use crate::services::deployment_diff::{DiffedDeployment, EnvChange, ImageRef, ManifestLine};
+use crate::services::reports::report_link;
+use crate::services::run_comparison::ComparedRun;
+use crate::services::slack;
+use crate::tenancy::{CurrentTenant, TENANT_COOKIE, can_reach, find_tenant, reachable_tenants};
+use buildit_config::ResolvedVariable;
 use buildit_core::ResourceId;
+use buildit_core::artifact::ArtifactPreview;
+use buildit_core::stack::StackRunStatus;
 use buildit_db::{
-    ApplicationRepo, DeploymentRepo, PipelineRepo, RepositoryRepo, StackRepo, TenantRepo,
+    AnalyticsFilter, AnalyticsRepo, ApplicationRepo, ArtifactRepo, DeploymentRepo,
+    OrganizationRepo, PipelineRepo, PipelineSummaryRecord, RepositoryRepo, ScanFindingRepo,
+    SearchRepo, StackRepo, TenantRunRecord, TestResultRecord, TestResultRepo, VariableGroupRepo,
 };
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 // ============================================================================
 // Template structs
@@ -43,6 +65,157 @@ struct PipelinesTemplate {
     tenant_id: String,
     pipelines: Vec<PipelineView>,
     has_pipelines: bool,
+    owners: Vec<String>,
+    owner_filter: String,
+    show_archived: bool,
+}
+
+#[derive(Template)]
+#[template(path = "pages/insights.html")]
+struct InsightsTemplate {
+    days: i64,
+    total_runs: i64,
+    success_rate: String,
+    p50_duration: String,
+    p95_duration: String,
+    p50_queue_wait: String,
+    p95_queue_wait: String,
+    daily: Vec<DailyRunsView>,
+    pipelines: Vec<PipelineStatsView>,
+    branches: Vec<BranchStatsView>,
+    stages: Vec<StageStatsView>,
+    flaky_stages: Vec<FlakyStageView>,
+}
+
+struct DailyRunsView {
+    day: String,
+    total_runs: i64,
+    failed: i64,
+    /// Bar height, relative to the busiest day.
+    height: i64,
+    /// Share of the bar that failed.
+    failed_height: i64,
+}
+
+struct PipelineStatsView {
+    id: String,
+    name: String,
+    total_runs: i64,
+    failure_rate: String,
+    p50_duration: String,
+    p95_duration: String,
+    p95_queue_wait: String,
+}
+
+struct BranchStatsView {
+    pipeline_name: String,
+    branch: String,
+    total_runs: i64,
+    failure_rate: String,
+    p50_duration: String,
+    p95_duration: String,
+}
+
+struct StageStatsView {
+    pipeline_id: String,
+    pipeline_name: String,
+    stage_name: String,
+    total_runs: i64,
+    failure_rate: String,
+    p50_duration: String,
+    p95_duration: String,
+}
+
+struct FlakyStageView {
+    pipeline_id: String,
+    pipeline_name: String,
+    stage_name: String,
+    total_runs: i64,
+    failed: i64,
+    flips: i64,
+    flip_rate: String,
+    flaky_commits: i64,
+}
+
+#[derive(Template)]
+#[template(path = "pages/costs.html")]
+struct CostsTemplate {
+    days: i64,
+    currency: String,
+    cpu_core_hour: String,
+    memory_gib_hour: String,
+    total_cost: String,
+    projected_monthly: String,
+    jobs: i64,
+    /// Share of jobs costed from what they used rather than requested.
+    measured_share: String,
+    cpu_core_hours: String,
+    memory_gib_hours: String,
+    /// Monthly budget as entered, empty without one.
+    budget: String,
+    /// Projected month as a share of the budget.
+    budget_used: Option<i64>,
+    daily: Vec<DailyCostView>,
+    pipelines: Vec<PipelineCostView>,
+    runs: Vec<RunCostView>,
+}
+
+struct DailyCostView {
+    day: String,
+    cost: String,
+    /// Bar height, relative to the most expensive day.
+    height: i64,
+}
+
+struct PipelineCostView {
+    /// `None` for pipelines deleted since.
+    id: Option<String>,
+    name: String,
+    runs: i64,
+    cpu_core_hours: String,
+    memory_gib_hours: String,
+    cost: String,
+    cost_per_run: String,
+    /// Share of the total cost.
+    share: String,
+}
+
+struct RunCostView {
+    url: Option<String>,
+    pipeline_name: String,
+    started_at: String,
+    run_minutes: String,
+    cost: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct InsightsQuery {
+    days: Option<i64>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct CostsQuery {
+    days: Option<i64>,
+    /// Monthly budget to hold the projection against.
+    budget: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct CompareRunsPageQuery {
+    from: Option<i64>,
+    to: Option<i64>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct DeploymentDiffPageQuery {
+    from: Option<Uuid>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct PipelinesPageQuery {
+    owner: Option<String>,
+    #[serde(default)]
+    archived: bool,
 }
 
 #[derive(Template)]
@@ -73,6 +246,52 @@ struct RunDetailTemplate {
     first_stage_name: String,
     dag_width: i32,
     dag_height: i32,
+    artifacts: Vec<ArtifactView>,
+    reports: Vec<ReportView>,
+    tests: TestsView,
+    scans: Vec<ScanView>,
+    findings: Vec<FindingView>,
+    variables: Vec<VariableView>,
+    /// Parameters the run was triggered with, by name.
+    params: Vec<(String, String)>,
+}
+
+#[derive(Template)]
+#[template(path = "pages/pipelines/compare.html")]
+struct RunComparisonTemplate {
+    pipeline_id: String,
+    pipeline_name: String,
+    from_number: i64,
+    to_number: i64,
+    /// The earlier run, then the later one.
+    runs: Vec<ComparedRunView>,
+    duration_delta: String,
+    compare_url: String,
+    commits: Vec<CommitView>,
+    stages: Vec<StageComparisonView>,
+    newly_failing: Vec<TestView>,
+}
+
+#[derive(Template)]
+#[template(path = "pages/pipelines/debug.html")]
+struct DebugSessionTemplate {
+    pipeline_id: String,
+    pipeline_name: String,
+    run_id: String,
+    run_number: i64,
+    session_id: String,
+    stage_name: String,
+}
+
+#[derive(Template)]
+#[template(path = "pages/pipelines/graph.html")]
+struct PipelineGraphTemplate {
+    pipeline: PipelineView,
+    stages: Vec<StageView>,
+    edges: Vec<DagEdge>,
+    nodes: Vec<GraphNodeView>,
+    dag_width: i32,
+    dag_height: i32,
 }
 
 #[derive(Template)]
@@ -82,6 +301,58 @@ struct EnvironmentsTemplate {
     has_environments: bool,
 }
 
+#[derive(Template)]
+#[template(path = "pages/device.html")]
+struct DeviceTemplate {
+    signed_in: bool,
+    user_code: String,
+    client_name: String,
+    result: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct DevicePageQuery {
+    user_code: Option<String>,
+    result: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "pages/invitation.html")]
+struct InvitationTemplate {
+    token: String,
+    signed_in: bool,
+    /// Whether the invitation can still be answered.
+    valid: bool,
+    organization: String,
+    inviter: String,
+    role: String,
+    email: String,
+    expires: String,
+    result: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct InvitationPageQuery {
+    result: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct AnswerInvitationForm {
+    /// `accept` or `decline`.
+    action: String,
+}
+
+#[derive(Template)]
+#[template(path = "pages/slack_link.html")]
+struct SlackLinkTemplate {
+    token: String,
+    signed_in: bool,
+    /// Whether the link is signed and unexpired.
+    valid: bool,
+    tenant_name: String,
+    result: String,
+}
+
 #[derive(Template)]
 #[template(path = "pages/settings/index.html")]
 struct SettingsTemplate {
@@ -92,7 +363,9 @@ struct SettingsTemplate {
 #[derive(Template)]
 #[template(path = "pages/settings/team.html")]
 struct SettingsTeamTemplate {
+    org_id: String,
     members: Vec<TeamMemberView>,
+    invitations: Vec<InvitationView>,
 }
 
 #[derive(Template)]
@@ -104,9 +377,41 @@ struct SettingsSecretsTemplate {
 #[derive(Template)]
 #[template(path = "pages/settings/tokens.html")]
 struct SettingsTokensTemplate {
+    org_id: String,
     tokens: Vec<TokenView>,
 }
 
+#[derive(Template)]
+#[template(path = "pages/settings/audit.html")]
+struct SettingsAuditTemplate {
+    entries: Vec<AuditLogView>,
+    members: Vec<AuditMemberView>,
+    resource_types: Vec<String>,
+    filter_user_id: String,
+    filter_resource_type: String,
+    filter_resource_id: String,
+    filter_action: String,
+    filter_from: String,
+    filter_to: String,
+}
+
+/// Filters of the audit log page; empty form fields arrive as empty strings.
+#[derive(Debug, Default, serde::Deserialize)]
+struct AuditPageQuery {
+    #[serde(default)]
+    user_id: String,
+    #[serde(default)]
+    resource_type: String,
+    #[serde(default)]
+    resource_id: String,
+    #[serde(default)]
+    action: String,
+    #[serde(default)]
+    from: String,
+    #[serde(default)]
+    to: String,
+}
+
 #[derive(Template)]
 #[template(path = "pages/settings/git.html")]
 struct SettingsGitTemplate {
@@ -122,10 +427,73 @@ struct SettingsGitTemplate {
 #[derive(Template)]
 #[template(path = "pages/settings/notifications.html")]
 struct SettingsNotificationsTemplate {
-    slack_connected: bool,
-    slack_channel: String,
-    has_webhooks: bool,
-    webhook_count: i32,
+    tenant_slug: String,
+    channels: Vec<NotificationChannelView>,
+    events: Vec<&'static str>,
+}
+
+struct NotificationChannelView {
+    id: String,
+    name: String,
+    kind: String,
+    /// Where it sends: the webhook URL or the recipients.
+    target: String,
+    events: Vec<String>,
+    enabled: bool,
+    last_delivery: String,
+    last_error: String,
+}
+
+#[derive(Template)]
+#[template(path = "pages/settings/variables.html")]
+struct SettingsVariablesTemplate {
+    tenant_slug: String,
+    groups: Vec<VariableGroupView>,
+    pipelines: Vec<PipelineSelectView>,
+}
+
+struct VariableGroupView {
+    id: String,
+    name: String,
+    description: String,
+    /// `All pipelines` or the pipeline's name.
+    scope: String,
+    /// One `NAME=value` line per variable, by name.
+    variables: String,
+    rows: usize,
+}
+
+struct PipelineSelectView {
+    id: String,
+    name: String,
+}
+
+#[derive(Template)]
+#[template(path = "pages/settings/webhooks.html")]
+struct SettingsWebhooksTemplate {
+    tenant_slug: String,
+    subscriptions: Vec<WebhookSubscriptionView>,
+    events: Vec<&'static str>,
+}
+
+struct WebhookSubscriptionView {
+    id: String,
+    url: String,
+    description: String,
+    events: Vec<String>,
+    enabled: bool,
+    deliveries: Vec<WebhookDeliveryView>,
+}
+
+struct WebhookDeliveryView {
+    id: String,
+    event: String,
+    status: String,
+    /// The response status, or why the endpoint could not be reached.
+    response: String,
+    attempts: i32,
+    sent: String,
+    redelivery: bool,
 }
 
 #[derive(Template)]
@@ -142,6 +510,28 @@ struct HistoryTemplate {
     has_deployments: bool,
 }
 
+#[derive(Template)]
+#[template(path = "pages/deployments/diff.html")]
+struct DeploymentDiffTemplate {
+    service_name: String,
+    from_id: String,
+    /// The earlier deployment, then the later one.
+    deployments: Vec<DiffedDeploymentView>,
+    /// Other deployments of the service to compare with.
+    candidates: Vec<DiffCandidateView>,
+    image: Option<ImageChangeView>,
+    env: Vec<EnvChange>,
+    spec: Vec<SpecChangeView>,
+    manifest: Option<Vec<ManifestLine>>,
+}
+
+#[derive(Template)]
+#[template(path = "pages/deployments/approvals.html")]
+struct ApprovalsTemplate {
+    approvals: Vec<PendingDeploymentView>,
+    has_approvals: bool,
+}
+
 #[derive(Template)]
 #[template(path = "pages/infrastructure/targets.html")]
 struct TargetsTemplate {
@@ -157,6 +547,46 @@ struct RunsTemplate {
     has_runs: bool,
 }
 
+#[derive(Template)]
+#[template(path = "pages/search.html")]
+struct SearchTemplate {
+    query: String,
+    /// Whether there was anything to search for.
+    searched: bool,
+    runs: Vec<RunHitView>,
+    log_hits: Vec<LogHitView>,
+}
+
+struct RunHitView {
+    url: String,
+    pipeline_name: String,
+    number: i64,
+    status: String,
+    branch: String,
+    short_sha: String,
+    message: String,
+    created_at: String,
+}
+
+struct LogHitView {
+    url: String,
+    pipeline_name: String,
+    run_number: i64,
+    stage_name: String,
+    line_number: i64,
+    before: Vec<LogLineView>,
+    /// The matching line, split around the first match.
+    prefix: String,
+    matched: String,
+    suffix: String,
+    after: Vec<LogLineView>,
+}
+
+struct LogLineView {
+    number: i64,
+    content: String,
+}
+
 #[derive(Template)]
 #[template(path = "pages/repositories/list.html")]
 struct RepositoriesTemplate {
@@ -169,6 +599,9 @@ struct RepositoriesTemplate {
 struct RepositoryDetailTemplate {
     repository: RepositoryView,
     detected: DetectedConfigView,
+    /// Why the last scan failed, if it did.
+    sync_error: String,
+    suggestions: Vec<SetupSuggestion>,
     pipelines: Vec<RepoPipelineView>,
     has_pipelines: bool,
     stacks: Vec<RepoStackView>,
@@ -191,6 +624,8 @@ struct StackDetailTemplate {
     has_runs: bool,
     variables: Vec<StackVariableView>,
     has_variables: bool,
+    has_approval: bool,
+    approval: ApprovalView,
 }
 
 #[derive(Template)]
@@ -272,6 +707,7 @@ struct PipelineView {
     total_runs: i64,
     success_rate: i64,
     avg_duration: String,
+    archived: bool,
 }
 
 struct RunView {
@@ -285,7 +721,119 @@ struct RunView {
     trigger_kind: String,
     created_at: String,
     duration: String,
+    /// Time spent queued before starting.
+    queued: String,
     stages: Vec<RunStageView>,
+    /// Place in line for a worker while the run waits for one.
+    queue_position: Option<i64>,
+    /// Why a queued run has not started.
+    queue_reasons: Vec<String>,
+}
+
+/// One side of a run comparison
+struct ComparedRunView {
+    id: String,
+    number: i64,
+    status: String,
+    branch: String,
+    commit_sha: String,
+    duration: String,
+}
+
+struct CommitView {
+    sha: String,
+    title: String,
+    author: String,
+}
+
+/// A stage's outcome in both runs of a comparison
+struct StageComparisonView {
+    name: String,
+    from_status: String,
+    to_status: String,
+    from_duration: String,
+    to_duration: String,
+    delta: String,
+    changed: bool,
+}
+
+/// A node of the static (pre-run) stage graph
+struct GraphNodeView {
+    id: String,
+    kind: String,
+    needs: String,
+    condition: String,
+    manual: bool,
+}
+
+/// Stage output artifact on the run page
+struct ArtifactView {
+    id: String,
+    stage_name: String,
+    name: String,
+    kind: String,
+    size: String,
+    /// "html", "json", or empty when it can only be downloaded
+    preview: String,
+}
+
+/// Published HTML report on the run page
+struct ReportView {
+    name: String,
+    stage_name: String,
+    file_count: i32,
+    size: String,
+    /// Signed link that opens the report in a new tab
+    url: String,
+}
+
+/// Test results on the run page
+struct TestsView {
+    total: i64,
+    passed: i64,
+    failures: i64,
+    skipped: i64,
+    duration: String,
+    failed: Vec<TestView>,
+    slowest: Vec<TestView>,
+}
+
+struct TestView {
+    name: String,
+    stage_name: String,
+    message: String,
+    duration: String,
+    /// Known to flip between passing and failing
+    flaky: bool,
+}
+
+/// A scan stage's findings by severity on the run page
+struct ScanView {
+    stage_name: String,
+    scanner: String,
+    target: String,
+    critical: i64,
+    high: i64,
+    medium: i64,
+    low: i64,
+}
+
+struct FindingView {
+    vulnerability_id: String,
+    package: String,
+    installed_version: String,
+    fixed_version: String,
+    severity: String,
+    url: String,
+}
+
+/// A variable a run's jobs got and where its value came from.
+struct VariableView {
+    name: String,
+    value: String,
+    source: String,
+    /// Lower layers it overrode, comma-separated.
+    overrides: String,
 }
 
 /// Minimal stage info for run list display
@@ -306,6 +854,7 @@ struct RecentRunView {
     duration: String,
 }
 
+#[allow(dead_code)]
 struct StageView {
     name: String,
     status: String,
@@ -321,6 +870,7 @@ struct StageView {
 }
 
 /// A column in the pipeline visualization (e.g., BUILD, TEST, DEPLOY)
+#[allow(dead_code)]
 struct PipelineColumn {
     /// Column index (0-based)
     index: i32,
@@ -331,6 +881,7 @@ struct PipelineColumn {
 }
 
 /// Edge between two stages for DAG visualization
+#[allow(dead_code)]
 struct DagEdge {
     from_x: i32,
     from_y: i32,
@@ -361,9 +912,16 @@ struct ServiceView {
     status: String,
     environments: Vec<String>,
     last_deploy_ago: String,
+    owner_team: String,
+    /// Tier from 1 (most critical) to 4, 0 when unset
+    tier: i32,
+    repository_url: String,
+    runbook_url: String,
+    dashboard_urls: Vec<String>,
 }
 
 struct DeploymentView {
+    id: String,
     version: String,
     commit_sha: String,
     service_name: String,
@@ -373,10 +931,67 @@ struct DeploymentView {
     duration: String,
 }
 
+/// One side of a deployment diff
+struct DiffedDeploymentView {
+    version: String,
+    environment: String,
+    status: String,
+    commit_sha: String,
+    spec_version: String,
+    deployed_ago: String,
+}
+
+struct DiffCandidateView {
+    id: String,
+    label: String,
+}
+
+/// Image references of both deployments, blank where a spec has none
+struct ImageChangeView {
+    from: String,
+    to: String,
+    repository_changed: bool,
+    tag_changed: bool,
+    digest_changed: bool,
+}
+
+struct SpecChangeView {
+    path: String,
+    change: &'static str,
+    old: String,
+    new: String,
+}
+
+struct PendingDeploymentView {
+    deployment_id: String,
+    service_name: String,
+    environment: String,
+    version: String,
+    branch: String,
+    requested_ago: String,
+    approvals: i32,
+    required_approvals: i32,
+    /// Who may review, e.g. "Alice, admins".
+    reviewers: String,
+    wait_minutes: i32,
+    reviews: Vec<ReviewView>,
+}
+
+struct ReviewView {
+    reviewer: String,
+    approved: bool,
+    comment: String,
+    ago: String,
+}
+
 struct TargetView {
+    id: String,
     name: String,
     target_type: String,
     status: String,
+    /// What the last connection check found
+    status_message: String,
+    checked_ago: String,
     region: String,
     environment_count: i32,
 }
@@ -401,22 +1016,53 @@ struct TeamMemberView {
     initials: String,
 }
 
+struct InvitationView {
+    id: String,
+    email: String,
+    role: String,
+    invited_by: String,
+    sent: String,
+    expires: String,
+    expired: bool,
+}
+
 struct SecretView {
     name: String,
     updated_at: String,
 }
 
 struct TokenView {
+    id: String,
     name: String,
     prefix: String,
     last_used: String,
     expires: String,
 }
 
+struct AuditLogView {
+    time: String,
+    ago: String,
+    actor: String,
+    action: String,
+    resource_type: String,
+    resource_id: String,
+    resource_short: String,
+    status: String,
+    succeeded: bool,
+    ip_address: String,
+    metadata: String,
+}
+
+struct AuditMemberView {
+    id: String,
+    name: String,
+}
+
 struct EnvironmentSelectView {
     name: String,
 }
 
+#[allow(dead_code)]
 struct RepositoryView {
     id: String,
     provider: String,
@@ -479,6 +1125,7 @@ struct StackView {
     repository_name: String,
 }
 
+#[allow(dead_code)]
 struct StackRunView {
     id: String,
     run_type: String,
@@ -495,6 +1142,98 @@ struct StackRunView {
     has_plan_output: bool,
 }
 
+/// Context shown next to a pending approval.
+#[derive(Default)]
+struct ApprovalView {
+    run_id: String,
+    risk_level: String,
+    risk_reasons: Vec<String>,
+    changes: Vec<PlannedChangeView>,
+    has_cost: bool,
+    cost_delta: String,
+    cost_basis: String,
+    affected_services: Vec<String>,
+    has_failure_rate: bool,
+    failure_summary: String,
+    links: Vec<LinkView>,
+    policies: Vec<PolicyResultView>,
+}
+
+struct PlannedChangeView {
+    address: String,
+    action: String,
+}
+
+struct LinkView {
+    label: String,
+    url: String,
+}
+
+struct PolicyResultView {
+    policy: String,
+    enforcement: String,
+    passed: bool,
+    /// Violations, or why the policy could not be evaluated.
+    messages: Vec<String>,
+}
+
+impl ApprovalView {
+    fn new(run_id: Uuid, context: ApprovalContext) -> Self {
+        Self {
+            run_id: run_id.to_string(),
+            risk_level: context.risk.level.to_string(),
+            risk_reasons: context.risk.reasons,
+            changes: context
+                .resource_changes
+                .map(|c| c.resources)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|r| PlannedChangeView {
+                    address: r.address,
+                    action: r.action,
+                })
+                .collect(),
+            has_cost: context.cost.is_some(),
+            cost_delta: context
+                .cost
+                .as_ref()
+                .map(|c| {
+                    let sign = if c.monthly_delta < 0.0 { "-" } else { "+" };
+                    format!("{}${:.2}/month", sign, c.monthly_delta.abs())
+                })
+                .unwrap_or_default(),
+            cost_basis: context.cost.map(|c| c.basis).unwrap_or_default(),
+            affected_services: context.affected_services,
+            has_failure_rate: context.failure_rate.is_some(),
+            failure_summary: context
+                .failure_rate
+                .map(|f| format!("{} of the last {} runs failed", f.failed, f.total))
+                .unwrap_or_default(),
+            links: context
+                .links
+                .into_iter()
+                .map(|l| LinkView {
+                    label: l.label,
+                    url: l.url,
+                })
+                .collect(),
+            policies: context
+                .policy_results
+                .into_iter()
+                .map(|r| PolicyResultView {
+                    passed: !r.failed(),
+                    policy: r.policy,
+                    enforcement: r.enforcement.to_string(),
+                    messages: match r.error {
+                        Some(error) => vec![format!("Could not be evaluated: {}", error)],
+                        None => r.violations,
+                    },
+                })
+                .collect(),
+        }
+    }
+}
+
 struct StackVariableView {
     key: String,
     value: String,
@@ -518,6 +1257,7 @@ struct ApplicationView {
     last_synced_ago: String,
 }
 
+#[allow(dead_code)]
 struct AppSyncView {
     id: String,
     revision_short: String,
@@ -550,14 +1290,26 @@ pub fn router() -> Router<AppState> {
         .route("/pipelines", get(pipelines_page))
         .route("/pipelines/new", get(new_pipeline_page))
         .route("/pipelines/{id}", get(pipeline_detail_page))
+        .route("/pipelines/{id}/graph", get(pipeline_graph_page))
+        .route("/pipelines/{id}/compare", get(run_comparison_page))
         .route("/pipelines/{id}/runs/{run_id}", get(run_detail_page))
+        .route(
+            "/pipelines/{id}/runs/{run_id}/debug/{session_id}",
+            get(debug_session_page),
+        )
         // Runs (alias)
         .route("/runs", get(runs_page))
+        .route("/search", get(search_page))
+        // Analytics
+        .route("/insights", get(insights_page))
+        .route("/insights/costs", get(costs_page))
         // Deployments
         .route("/environments", get(environments_page))
         .route("/environments/new", get(new_environment_page))
         .route("/services", get(services_page))
         .route("/history", get(history_page))
+        .route("/history/{id}/diff", get(deployment_diff_page))
+        .route("/approvals", get(approvals_page))
         // Infrastructure
         .route("/targets", get(targets_page))
         .route("/targets/new", get(new_target_page))
@@ -578,87 +1330,97 @@ pub fn router() -> Router<AppState> {
         .route("/settings/team", get(settings_team_page))
         .route("/settings/secrets", get(settings_secrets_page))
         .route("/settings/tokens", get(settings_tokens_page))
+        .route("/settings/audit", get(settings_audit_page))
         .route("/settings/git", get(settings_git_page))
         .route("/settings/notifications", get(settings_notifications_page))
+        .route("/settings/webhooks", get(settings_webhooks_page))
+        .route("/settings/variables", get(settings_variables_page))
+        // CLI sign-in
+        .route("/device", get(device_page))
+        // Invitations
+        .route(
+            "/invitations/{token}",
+            get(invitation_page).post(answer_invitation),
+        )
+        // Linking Slack accounts
+        .route(
+            "/slack/link/{token}",
+            get(slack_link_page).post(link_slack_account),
+        )
+        // Tenant switching
+        .route("/t/{slug}", get(tenant_root))
+        .route("/t/{slug}/", get(tenant_root))
+        .route("/t/{slug}/{*rest}", get(tenant_page))
 }
 
 // ============================================================================
 // Page handlers
 // ============================================================================
 
-async fn dashboard_page(State(state): State<AppState>) -> Result<impl IntoResponse, ApiError> {
-    // Get default tenant
-    let tenant = state
-        .tenant_repo
-        .get_by_slug("default")
-        .await
-        .map_err(|_| ApiError::Internal("No default tenant".to_string()))?;
-
+async fn dashboard_page(
+    State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+) -> Result<impl IntoResponse, ApiError> {
     let tenant_id = ResourceId::from_uuid(tenant.id);
-    let pipelines = state.pipeline_repo.list_by_tenant(tenant_id).await?;
-    let pipeline_count = pipelines.len() as i64;
-
-    // Gather recent runs from all pipelines
-    let mut recent_runs = Vec::new();
-    let mut total_runs = 0i64;
-    let mut successful_runs = 0i64;
 
-    for p in &pipelines {
-        let runs = state
-            .pipeline_repo
-            .list_runs(ResourceId::from_uuid(p.id), 5)
-            .await
-            .unwrap_or_default();
-
-        for run in runs {
-            total_runs += 1;
-            if run.status == "succeeded" {
-                successful_runs += 1;
-            }
-
-            if recent_runs.len() < 10 {
-                let branch = run
-                    .trigger_info
-                    .get("branch")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("")
-                    .to_string();
-                let commit_message = run
-                    .trigger_info
-                    .get("commit_message")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("")
-                    .to_string();
-                recent_runs.push(RecentRunView {
-                    pipeline_id: p.id.to_string(),
-                    pipeline_name: p.name.clone(),
-                    run_id: run.id.to_string(),
-                    run_number: run.number,
-                    status: run.status,
-                    ago: format_time_ago(run.created_at),
-                    branch,
-                    commit_message,
-                    duration: String::from("--"),
-                });
+    // Most recent runs across all pipelines
+    let recent_runs = state
+        .pipeline_repo
+        .list_tenant_runs(tenant_id, 10)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|TenantRunRecord { run, pipeline_name }| {
+            let branch = run
+                .trigger_info
+                .get("branch")
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string();
+            let commit_message = run
+                .trigger_info
+                .get("commit_message")
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string();
+            RecentRunView {
+                pipeline_id: run.pipeline_id.to_string(),
+                pipeline_name,
+                run_id: run.id.to_string(),
+                run_number: run.number,
+                status: run.status,
+                ago: format_time_ago(run.created_at),
+                branch,
+                commit_message,
+                duration: format_secs(run.duration_secs),
             }
-        }
-    }
-
-    let success_rate = if total_runs > 0 {
-        format!(
-            "{:.1}",
-            (successful_runs as f64 / total_runs as f64) * 100.0
+        })
+        .collect::<Vec<_>>();
+
+    // Runs since midnight (UTC), and the success rate of the analytics window
+    let overview = state
+        .analytics_repo
+        .overview(
+            &AnalyticsFilter {
+                tenant_id,
+                since: chrono::Utc::now() - chrono::Duration::days(DEFAULT_DAYS),
+                pipeline_id: None,
+            },
+            chrono::Utc::now()
+                .date_naive()
+                .and_hms_opt(0, 0, 0)
+                .unwrap_or_default()
+                .and_utc(),
         )
-    } else {
-        "0.0".to_string()
-    };
+        .await?;
+    let success_rate = format!("{:.1}", overview.success_rate.unwrap_or(0.0) * 100.0);
 
     let has_recent_runs = !recent_runs.is_empty();
     let activities: Vec<ActivityView> = Vec::new(); // TODO: Populate from actual activity
     let has_activity = !activities.is_empty();
     let template = DashboardTemplate {
-        pipeline_count,
-        run_count_today: total_runs,
+        pipeline_count: overview.pipeline_count,
+        run_count_today: overview.runs_today,
         success_rate,
         recent_runs,
         has_recent_runs,
@@ -675,13 +1437,10 @@ async fn dashboard_page(State(state): State<AppState>) -> Result<impl IntoRespon
     }
 }
 
-async fn new_pipeline_page(State(state): State<AppState>) -> Result<impl IntoResponse, ApiError> {
-    let tenant = state
-        .tenant_repo
-        .get_by_slug("default")
-        .await
-        .map_err(|_| ApiError::Internal("No default tenant".to_string()))?;
-
+async fn new_pipeline_page(
+    State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+) -> Result<impl IntoResponse, ApiError> {
     let tenant_id = ResourceId::from_uuid(tenant.id);
 
     // Get available targets for deployment step
@@ -689,9 +1448,12 @@ async fn new_pipeline_page(State(state): State<AppState>) -> Result<impl IntoRes
     let available_targets: Vec<TargetView> = target_records
         .into_iter()
         .map(|t| TargetView {
+            id: t.id.to_string(),
             name: t.name,
             target_type: t.target_type,
             status: t.status,
+            status_message: t.status_message.unwrap_or_default(),
+            checked_ago: t.checked_at.map(format_time_ago).unwrap_or_default(),
             region: t.region.unwrap_or_else(|| "-".to_string()),
             environment_count: 0,
         })
@@ -731,58 +1493,64 @@ async fn new_pipeline_page(State(state): State<AppState>) -> Result<impl IntoRes
     Ok(Html(template.render().unwrap()))
 }
 
-async fn pipelines_page(State(state): State<AppState>) -> Result<impl IntoResponse, ApiError> {
-    // Get default tenant
-    let tenant = state
-        .tenant_repo
-        .get_by_slug("default")
-        .await
-        .map_err(|_| ApiError::Internal("No default tenant".to_string()))?;
+/// A row of the pipelines list.
+fn pipeline_view(s: PipelineSummaryRecord) -> PipelineView {
+    PipelineView {
+        id: s.pipeline.id.to_string(),
+        name: s.pipeline.name,
+        repository: s.pipeline.repository,
+        default_branch: String::from("main"),
+        config: String::new(),
+        last_run_id: s.last_run_id.map(|id| id.to_string()).unwrap_or_default(),
+        last_run_number: s.last_run_number.unwrap_or(0),
+        last_run_status: s.last_run_status.unwrap_or_default(),
+        last_run_ago: s.last_run_at.map(format_time_ago).unwrap_or_default(),
+        total_runs: s.total_runs,
+        success_rate: s
+            .success_rate
+            .map_or(0, |rate| (rate * 100.0).round() as i64),
+        avg_duration: format_secs(s.p50_duration_secs),
+        archived: s.pipeline.archived_at.is_some(),
+    }
+}
 
+async fn pipelines_page(
+    State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+    Query(query): Query<PipelinesPageQuery>,
+) -> Result<impl IntoResponse, ApiError> {
     let tenant_id = ResourceId::from_uuid(tenant.id);
-    let pipeline_records = state.pipeline_repo.list_by_tenant(tenant_id).await?;
-
-    let mut pipelines = Vec::new();
-    for p in pipeline_records {
-        let runs = state
-            .pipeline_repo
-            .list_runs(ResourceId::from_uuid(p.id), 1)
-            .await
-            .unwrap_or_default();
-
-        let (last_run_id, last_run_number, last_run_status, last_run_ago) =
-            if let Some(run) = runs.first() {
-                (
-                    run.id.to_string(),
-                    run.number,
-                    run.status.clone(),
-                    format_time_ago(run.created_at),
-                )
-            } else {
-                (String::new(), 0, String::new(), String::new())
-            };
+    // Each pipeline with its last run and stats, in one query
+    let summaries = state
+        .pipeline_repo
+        .list_summaries(
+            tenant_id,
+            query.archived,
+            chrono::Utc::now() - chrono::Duration::days(DEFAULT_DAYS),
+        )
+        .await?;
+    let mut owners: Vec<String> = summaries
+        .iter()
+        .flat_map(|s| s.pipeline.owner_list.iter().cloned())
+        .collect();
+    owners.sort();
+    owners.dedup();
 
-        pipelines.push(PipelineView {
-            id: p.id.to_string(),
-            name: p.name,
-            repository: p.repository,
-            default_branch: String::from("main"),
-            config: String::new(),
-            last_run_id,
-            last_run_number,
-            last_run_status,
-            last_run_ago,
-            total_runs: 0,
-            success_rate: 0,
-            avg_duration: String::from("--"),
-        });
-    }
+    let owner_filter = query.owner.filter(|o| !o.is_empty()).unwrap_or_default();
+    let pipelines = summaries
+        .into_iter()
+        .filter(|s| owner_filter.is_empty() || s.pipeline.owner_list.contains(&owner_filter))
+        .map(pipeline_view)
+        .collect::<Vec<_>>();
 
     let has_pipelines = !pipelines.is_empty();
     let template = PipelinesTemplate {
         tenant_id: tenant.id.to_string(),
         pipelines,
         has_pipelines,
+        owners,
+        owner_filter,
+        show_archived: query.archived,
     };
 
     Ok(Html(template.render().unwrap()))
@@ -790,12 +1558,17 @@ async fn pipelines_page(State(state): State<AppState>) -> Result<impl IntoRespon
 
 async fn pipeline_detail_page(
     State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+    uri: Uri,
     Path(id): Path<Uuid>,
-) -> Result<impl IntoResponse, ApiError> {
+) -> Result<Response, ApiError> {
     let pipeline = state
         .pipeline_repo
         .get_by_id(ResourceId::from_uuid(id))
         .await?;
+    if let Some(switch) = switch_tenant(&state, &tenant, pipeline.tenant_id, &uri).await? {
+        return Ok(switch);
+    }
 
     let run_records = state
         .pipeline_repo
@@ -840,14 +1613,27 @@ async fn pipeline_detail_page(
                     .unwrap_or("manual")
                     .to_string(),
                 created_at: format_time_ago(r.created_at),
-                duration: "1m 23s".to_string(), // TODO: Calculate actual duration
-                stages: Vec::new(),             // Stages not loaded in list view
+                duration: format_secs(r.duration_secs),
+                queued: format_secs(r.queued_secs),
+                stages: Vec::new(), // Stages not loaded in list view
+                queue_position: None,
+                queue_reasons: Vec::new(),
             }
         })
         .collect();
 
     let has_runs = !runs.is_empty();
     let config_str = serde_json::to_string_pretty(&pipeline.config).unwrap_or_default();
+    let stats = state
+        .analytics_repo
+        .pipeline_stats(&AnalyticsFilter {
+            tenant_id: ResourceId::from_uuid(pipeline.tenant_id),
+            since: chrono::Utc::now() - chrono::Duration::days(DEFAULT_DAYS),
+            pipeline_id: Some(pipeline.id),
+        })
+        .await?
+        .into_iter()
+        .next();
     let template = PipelineDetailTemplate {
         pipeline: PipelineView {
             id: pipeline.id.to_string(),
@@ -859,30 +1645,266 @@ async fn pipeline_detail_page(
             last_run_number: 0,
             last_run_status: String::new(),
             last_run_ago: String::new(),
-            total_runs: runs.len() as i64,
-            success_rate: 0,
-            avg_duration: String::from("--"),
+            total_runs: stats.as_ref().map_or(0, |s| s.total_runs),
+            success_rate: stats
+                .as_ref()
+                .and_then(|s| s.failure_rate)
+                .map_or(0, |rate| ((1.0 - rate) * 100.0).round() as i64),
+            avg_duration: format_secs(stats.and_then(|s| s.p50_duration_secs)),
+            archived: pipeline.archived_at.is_some(),
         },
         runs,
         has_runs,
     };
 
-    Ok(Html(template.render().unwrap()))
+    Ok(Html(template.render().unwrap()).into_response())
+}
+
+async fn pipeline_graph_page(
+    State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+    uri: Uri,
+    Path(id): Path<Uuid>,
+) -> Result<Response, ApiError> {
+    let pipeline = state
+        .pipeline_repo
+        .get_by_id(ResourceId::from_uuid(id))
+        .await?;
+    if let Some(switch) = switch_tenant(&state, &tenant, pipeline.tenant_id, &uri).await? {
+        return Ok(switch);
+    }
+    let stage_defs = super::pipelines::load_stage_definitions(&state, &pipeline).await?;
+    let graph = buildit_config::build_stage_graph(&stage_defs);
+
+    // Reuse the run DAG layout; status drives node styling
+    let mut stages: Vec<StageView> = graph
+        .nodes
+        .iter()
+        .map(|n| {
+            let status = if n.manual {
+                "manual"
+            } else if n.condition.is_some() {
+                "conditional"
+            } else {
+                "pending"
+            };
+            StageView {
+                name: n.id.clone(),
+                status: status.to_string(),
+                duration: n.kind.clone(),
+                dependencies: n.needs.clone(),
+                column: n.level as i32,
+                row: 0,
+                x: 0,
+                y: 0,
+            }
+        })
+        .collect();
+    let (edges, dag_width, dag_height) = compute_dag_layout(&mut stages);
+
+    let nodes = graph
+        .nodes
+        .into_iter()
+        .map(|n| GraphNodeView {
+            id: n.id,
+            kind: n.kind,
+            needs: n.needs.join(", "),
+            condition: n.condition.unwrap_or_default(),
+            manual: n.manual,
+        })
+        .collect();
+
+    let template = PipelineGraphTemplate {
+        pipeline: PipelineView {
+            id: pipeline.id.to_string(),
+            name: pipeline.name,
+            repository: pipeline.repository,
+            default_branch: String::from("main"),
+            config: String::new(),
+            last_run_id: String::new(),
+            last_run_number: 0,
+            last_run_status: String::new(),
+            last_run_ago: String::new(),
+            total_runs: 0,
+            success_rate: 0,
+            avg_duration: String::from("--"),
+            archived: pipeline.archived_at.is_some(),
+        },
+        stages,
+        edges,
+        nodes,
+        dag_width,
+        dag_height,
+    };
+
+    Ok(Html(template.render().unwrap()).into_response())
+}
+
+/// Terminal of a debug session; the page follows the session and connects
+/// once its job is ready.
+async fn debug_session_page(
+    State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+    uri: Uri,
+    Path((pipeline_id, run_id, session_id)): Path<(Uuid, Uuid, Uuid)>,
+) -> Result<Response, ApiError> {
+    let pipeline = state
+        .pipeline_repo
+        .get_by_id(ResourceId::from_uuid(pipeline_id))
+        .await?;
+    if let Some(switch) = switch_tenant(&state, &tenant, pipeline.tenant_id, &uri).await? {
+        return Ok(switch);
+    }
+    let run = state
+        .pipeline_repo
+        .get_run(ResourceId::from_uuid(run_id))
+        .await?;
+    let session = state
+        .pipeline_repo
+        .get_debug_session(ResourceId::from_uuid(session_id))
+        .await?;
+    if run.pipeline_id != pipeline.id || session.pipeline_run_id != run.id {
+        return Err(ApiError::NotFound(format!(
+            "Debug session {} not found",
+            session_id
+        )));
+    }
+
+    let template = DebugSessionTemplate {
+        pipeline_id: pipeline.id.to_string(),
+        pipeline_name: pipeline.name,
+        run_id: run.id.to_string(),
+        run_number: run.number,
+        session_id: session.id.to_string(),
+        stage_name: session.stage_name,
+    };
+    Ok(Html(template.render().unwrap()).into_response())
+}
+
+async fn run_comparison_page(
+    State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+    uri: Uri,
+    Path(pipeline_id): Path<Uuid>,
+    Query(query): Query<CompareRunsPageQuery>,
+) -> Result<Response, ApiError> {
+    let pipeline = state
+        .pipeline_repo
+        .get_by_id(ResourceId::from_uuid(pipeline_id))
+        .await?;
+    if let Some(switch) = switch_tenant(&state, &tenant, pipeline.tenant_id, &uri).await? {
+        return Ok(switch);
+    }
+
+    // Without run numbers compare the latest run with the one before it
+    let to = match query.to {
+        Some(to) => to,
+        None => state
+            .pipeline_repo
+            .list_runs(ResourceId::from_uuid(pipeline_id), 1)
+            .await?
+            .first()
+            .map(|r| r.number)
+            .ok_or_else(|| ApiError::NotFound("The pipeline has no runs yet".to_string()))?,
+    };
+    let from = query.from.unwrap_or(to - 1);
+    if from == to {
+        return Err(ApiError::BadRequest(
+            "Compare two different runs".to_string(),
+        ));
+    }
+    let repo = &state.pipeline_repo;
+    let from = repo
+        .get_run_by_number(ResourceId::from_uuid(pipeline_id), from)
+        .await?;
+    let to = repo
+        .get_run_by_number(ResourceId::from_uuid(pipeline_id), to)
+        .await?;
+    let comparison = state
+        .run_comparison()
+        .compare(&pipeline, &from, &to)
+        .await?;
+
+    let run_view = |run: &ComparedRun| ComparedRunView {
+        id: run.id.to_string(),
+        number: run.number,
+        status: run.status.clone(),
+        branch: run.branch.clone().unwrap_or_default(),
+        commit_sha: run
+            .sha
+            .as_deref()
+            .unwrap_or_default()
+            .chars()
+            .take(7)
+            .collect(),
+        duration: format_secs(run.duration_secs),
+    };
+    let template = RunComparisonTemplate {
+        pipeline_id: pipeline.id.to_string(),
+        pipeline_name: pipeline.name,
+        from_number: comparison.from.number,
+        to_number: comparison.to.number,
+        runs: vec![run_view(&comparison.from), run_view(&comparison.to)],
+        duration_delta: format_delta(comparison.duration_delta_secs),
+        compare_url: comparison.compare_url.unwrap_or_default(),
+        commits: comparison
+            .commits
+            .into_iter()
+            .map(|c| CommitView {
+                sha: c.sha.chars().take(7).collect(),
+                title: c.title,
+                author: c.author.unwrap_or_default(),
+            })
+            .collect(),
+        stages: comparison
+            .stages
+            .into_iter()
+            .map(|s| StageComparisonView {
+                from_status: s.from_status.unwrap_or_else(|| "-".to_string()),
+                to_status: s.to_status.unwrap_or_else(|| "-".to_string()),
+                from_duration: format_secs(s.from_duration_secs),
+                to_duration: format_secs(s.to_duration_secs),
+                delta: format_delta(s.duration_delta_secs),
+                changed: s.changed,
+                name: s.name,
+            })
+            .collect(),
+        newly_failing: comparison
+            .newly_failing_tests
+            .into_iter()
+            .map(|t| TestView {
+                name: t.name,
+                stage_name: t.stage_name,
+                message: t.message.unwrap_or_default(),
+                duration: String::new(),
+                flaky: false,
+            })
+            .collect(),
+    };
+    Ok(Html(template.render().unwrap()).into_response())
 }
 
 async fn run_detail_page(
     State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+    uri: Uri,
     Path((pipeline_id, run_id)): Path<(Uuid, Uuid)>,
-) -> Result<impl IntoResponse, ApiError> {
+) -> Result<Response, ApiError> {
     let pipeline = state
         .pipeline_repo
         .get_by_id(ResourceId::from_uuid(pipeline_id))
         .await?;
+    if let Some(switch) = switch_tenant(&state, &tenant, pipeline.tenant_id, &uri).await? {
+        return Ok(switch);
+    }
 
     let run = state
         .pipeline_repo
         .get_run(ResourceId::from_uuid(run_id))
         .await?;
+    if run.pipeline_id != pipeline.id {
+        return Err(ApiError::NotFound(format!("Run {} not found", run_id)));
+    }
 
     let branch = run
         .trigger_info
@@ -916,32 +1938,6 @@ async fn run_detail_page(
         .list_stage_results(ResourceId::from_uuid(run_id))
         .await?;
 
-    // Calculate total run duration from stage results
-    let run_duration = {
-        let earliest_start = stage_results.iter().filter_map(|r| r.started_at).min();
-        let latest_end = stage_results.iter().filter_map(|r| r.finished_at).max();
-        match (earliest_start, latest_end) {
-            (Some(start), Some(end)) => {
-                let secs = (end - start).num_seconds();
-                if secs < 60 {
-                    format!("{}s", secs)
-                } else {
-                    format!("{}m {}s", secs / 60, secs % 60)
-                }
-            }
-            (Some(start), None) => {
-                // Still running
-                let secs = (chrono::Utc::now() - start).num_seconds();
-                if secs < 60 {
-                    format!("{}s", secs)
-                } else {
-                    format!("{}m {}s", secs / 60, secs % 60)
-                }
-            }
-            _ => "-".to_string(),
-        }
-    };
-
     // Build a map of stage name -> result for quick lookup
     let result_map: std::collections::HashMap<String, _> = stage_results
         .into_iter()
@@ -954,26 +1950,7 @@ async fn run_detail_page(
         .map(|def| {
             let result = result_map.get(&def.name);
             let (status, duration) = if let Some(r) = result {
-                let dur = match (r.started_at, r.finished_at) {
-                    (Some(start), Some(end)) => {
-                        let secs = (end - start).num_seconds();
-                        if secs < 60 {
-                            format!("{}s", secs)
-                        } else {
-                            format!("{}m {}s", secs / 60, secs % 60)
-                        }
-                    }
-                    (Some(start), None) => {
-                        let secs = (chrono::Utc::now() - start).num_seconds();
-                        if secs < 60 {
-                            format!("{}s", secs)
-                        } else {
-                            format!("{}m {}s", secs / 60, secs % 60)
-                        }
-                    }
-                    _ => "-".to_string(),
-                };
-                (r.status.clone(), dur)
+                (r.status.clone(), format_secs(r.duration_secs))
             } else {
                 ("pending".to_string(), "-".to_string())
             };
@@ -1002,6 +1979,97 @@ async fn run_detail_page(
             status: s.status.clone(),
         })
         .collect();
+    let artifacts = state
+        .artifact_repo
+        .list_artifacts(ResourceId::from_uuid(run_id))
+        .await?
+        .into_iter()
+        .map(|a| ArtifactView {
+            id: a.id.to_string(),
+            preview: match a.kind().preview(&a.content_type) {
+                Some(ArtifactPreview::SandboxedHtml) => "html".to_string(),
+                Some(ArtifactPreview::Json) => "json".to_string(),
+                None => String::new(),
+            },
+            size: format_size(a.size_bytes),
+            stage_name: a.stage_name,
+            name: a.name,
+            kind: a.kind,
+        })
+        .collect();
+    let reports = state
+        .artifact_repo
+        .list_reports(ResourceId::from_uuid(run_id))
+        .await?
+        .into_iter()
+        .map(|r| ReportView {
+            url: report_link(r.id),
+            size: format_size(r.size_bytes),
+            file_count: r.file_count,
+            stage_name: r.stage_name,
+            name: r.name,
+        })
+        .collect();
+
+    let tests = run_tests_view(&state, pipeline_id, run_id).await?;
+    let queue = runs::queue_diagnosis(&state, &pipeline, &run).await?;
+    let scan_run_id = ResourceId::from_uuid(run_id);
+    let scans = state
+        .scan_finding_repo
+        .summaries(scan_run_id)
+        .await?
+        .into_iter()
+        .map(|s| ScanView {
+            stage_name: s.stage_name,
+            scanner: s.scanner,
+            target: s.target,
+            critical: s.critical,
+            high: s.high,
+            medium: s.medium,
+            low: s.low,
+        })
+        .collect();
+    let findings = state
+        .scan_finding_repo
+        .list_findings(scan_run_id, 50)
+        .await?
+        .into_iter()
+        .map(|f| FindingView {
+            severity: f.severity().to_string(),
+            vulnerability_id: f.vulnerability_id,
+            package: f.package,
+            installed_version: f.installed_version,
+            fixed_version: f.fixed_version.unwrap_or_default(),
+            url: f.url.unwrap_or_default(),
+        })
+        .collect();
+    let variables = state
+        .variable_group_repo
+        .get_run_variables(ResourceId::from_uuid(run_id))
+        .await?
+        .and_then(|v| serde_json::from_value::<Vec<ResolvedVariable>>(v).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|v| VariableView {
+            source: v.source.to_string(),
+            overrides: v
+                .overrides
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            name: v.name,
+            value: v.value,
+        })
+        .collect();
+    let params: Vec<(String, String)> = run
+        .trigger_info
+        .get("params")
+        .and_then(|v| serde_json::from_value::<BTreeMap<String, String>>(v.clone()).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+
     let template = RunDetailTemplate {
         pipeline: PipelineView {
             id: pipeline.id.to_string(),
@@ -1016,6 +2084,7 @@ async fn run_detail_page(
             total_runs: 0,
             success_rate: 0,
             avg_duration: String::from("--"),
+            archived: pipeline.archived_at.is_some(),
         },
         run: RunView {
             id: run.id.to_string(),
@@ -1031,38 +2100,106 @@ async fn run_detail_page(
                 .unwrap_or("manual")
                 .to_string(),
             created_at: format_time_ago(run.created_at),
-            duration: run_duration,
+            duration: format_secs(run.duration_secs),
+            queued: format_secs(run.queued_secs),
             stages: run_stages,
+            queue_position: queue.position,
+            queue_reasons: queue.reasons.iter().map(|r| r.to_string()).collect(),
         },
         stages,
         edges,
         first_stage_name,
         dag_width,
         dag_height,
+        artifacts,
+        reports,
+        tests,
+        scans,
+        findings,
+        variables,
+        params,
     };
 
-    Ok(Html(template.render().unwrap()))
+    Ok(Html(template.render().unwrap()).into_response())
 }
 
-async fn runs_page(State(state): State<AppState>) -> Result<impl IntoResponse, ApiError> {
-    // Get default tenant
-    let tenant = state
-        .tenant_repo
-        .get_by_slug("default")
-        .await
-        .map_err(|_| ApiError::Internal("No default tenant".to_string()))?;
+/// A run's test counts with its failed and slowest tests, flagging the
+/// failures that are known to be flaky.
+async fn run_tests_view(
+    state: &AppState,
+    pipeline_id: Uuid,
+    run_id: Uuid,
+) -> Result<TestsView, ApiError> {
+    let repo = &state.test_result_repo;
+    let run_id = ResourceId::from_uuid(run_id);
+    let summary = repo.summary(run_id).await?;
+    if summary.total == 0 {
+        return Ok(TestsView {
+            total: 0,
+            passed: 0,
+            failures: 0,
+            skipped: 0,
+            duration: String::new(),
+            failed: vec![],
+            slowest: vec![],
+        });
+    }
+    let flaky: HashSet<(String, String)> = repo
+        .flaky_tests(ResourceId::from_uuid(pipeline_id), 50, 200)
+        .await?
+        .into_iter()
+        .map(|t| (t.classname, t.name))
+        .collect();
+    let view = |r: TestResultRecord| TestView {
+        flaky: flaky.contains(&(r.classname.clone(), r.name.clone())),
+        name: if r.classname.is_empty() {
+            r.name
+        } else {
+            format!("{}.{}", r.classname, r.name)
+        },
+        stage_name: r.stage_name,
+        message: r
+            .message
+            .as_deref()
+            .and_then(|m| m.lines().next())
+            .unwrap_or_default()
+            .to_string(),
+        duration: format!("{:.2}s", r.duration_secs),
+    };
+    Ok(TestsView {
+        total: summary.total,
+        passed: summary.passed,
+        failures: summary.failures(),
+        skipped: summary.skipped,
+        duration: format_secs(Some(summary.duration_secs)),
+        failed: repo
+            .failures(run_id, 20)
+            .await?
+            .into_iter()
+            .map(view)
+            .collect(),
+        slowest: repo
+            .slowest(run_id, 5)
+            .await?
+            .into_iter()
+            .map(view)
+            .collect(),
+    })
+}
 
+async fn runs_page(
+    State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+) -> Result<impl IntoResponse, ApiError> {
     let tenant_id = ResourceId::from_uuid(tenant.id);
     let pipeline_records = state.pipeline_repo.list_by_tenant(tenant_id).await?;
 
-    let mut pipelines = Vec::new();
-    let mut all_runs = Vec::new();
-
-    for p in pipeline_records {
-        pipelines.push(PipelineView {
+    let pipelines = pipeline_records
+        .into_iter()
+        .map(|p| PipelineView {
             id: p.id.to_string(),
-            name: p.name.clone(),
-            repository: p.repository.clone(),
+            name: p.name,
+            repository: p.repository,
             default_branch: String::from("main"),
             config: String::new(),
             last_run_id: String::new(),
@@ -1072,69 +2209,378 @@ async fn runs_page(State(state): State<AppState>) -> Result<impl IntoResponse, A
             total_runs: 0,
             success_rate: 0,
             avg_duration: String::from("--"),
-        });
+            archived: p.archived_at.is_some(),
+        })
+        .collect();
 
-        let runs = state
-            .pipeline_repo
-            .list_runs(ResourceId::from_uuid(p.id), 10)
-            .await
-            .unwrap_or_default();
+    // Most recent first, across all pipelines
+    let all_runs: Vec<AllRunView> = state
+        .pipeline_repo
+        .list_tenant_runs(tenant_id, 50)
+        .await?
+        .into_iter()
+        .map(
+            |TenantRunRecord {
+                 run: r,
+                 pipeline_name,
+             }| {
+                let branch = r
+                    .trigger_info
+                    .get("branch")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("main")
+                    .to_string();
+                let commit_sha = r
+                    .trigger_info
+                    .get("commit_sha")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("")
+                    .chars()
+                    .take(7)
+                    .collect();
+                let commit_message = r
+                    .trigger_info
+                    .get("commit_message")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("No message")
+                    .to_string();
 
-        for r in runs {
-            let branch = r
-                .trigger_info
-                .get("branch")
-                .and_then(|v| v.as_str())
-                .unwrap_or("main")
-                .to_string();
-            let commit_sha = r
-                .trigger_info
-                .get("commit_sha")
-                .and_then(|v| v.as_str())
-                .unwrap_or("")
-                .chars()
-                .take(7)
-                .collect();
-            let commit_message = r
-                .trigger_info
-                .get("commit_message")
-                .and_then(|v| v.as_str())
-                .unwrap_or("No message")
-                .to_string();
+                AllRunView {
+                    id: r.id.to_string(),
+                    pipeline_id: r.pipeline_id.to_string(),
+                    pipeline_name,
+                    number: r.number,
+                    status: r.status,
+                    branch,
+                    commit_sha,
+                    commit_message,
+                    created_at: format_time_ago(r.created_at),
+                    duration: format_secs(r.duration_secs),
+                }
+            },
+        )
+        .collect();
 
-            all_runs.push(AllRunView {
-                id: r.id.to_string(),
-                pipeline_id: p.id.to_string(),
-                pipeline_name: p.name.clone(),
+    let has_runs = !all_runs.is_empty();
+    let template = RunsTemplate {
+        pipelines,
+        runs: all_runs,
+        has_runs,
+    };
+
+    Ok(Html(template.render().unwrap()))
+}
+
+async fn search_page(
+    State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+    Query(query): Query<SearchQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let (filter, text) = query.filter(tenant.id);
+    let searched = query.q.as_deref().is_some_and(|q| !q.trim().is_empty());
+
+    let mut runs = Vec::new();
+    let mut log_hits = Vec::new();
+    if searched {
+        runs = state
+            .search_repo
+            .search_runs(&filter, &text, 20)
+            .await?
+            .into_iter()
+            .map(|r| RunHitView {
+                url: run_url(r.pipeline_id, r.run_id),
+                pipeline_name: r.pipeline_name,
                 number: r.number,
                 status: r.status,
-                branch,
-                commit_sha,
-                commit_message,
+                branch: r.branch.unwrap_or_default(),
+                short_sha: r.sha.unwrap_or_default().chars().take(7).collect(),
+                message: r.message.unwrap_or_else(|| "No message".to_string()),
                 created_at: format_time_ago(r.created_at),
-                duration: "1m 23s".to_string(),
-            });
+            })
+            .collect();
+    }
+    if !text.is_empty() {
+        log_hits = state
+            .search_repo
+            .search_logs(&filter, &text, query.context(), query.limit())
+            .await?
+            .into_iter()
+            .map(|l| {
+                let url = log_url(&l);
+                let numbered = |first: i64, lines: Vec<String>| -> Vec<LogLineView> {
+                    lines
+                        .into_iter()
+                        .zip(first..)
+                        .map(|(content, number)| LogLineView { number, content })
+                        .collect()
+                };
+                // ASCII lowercasing keeps byte offsets valid in the original
+                let (prefix, matched, suffix) = match l
+                    .content
+                    .to_ascii_lowercase()
+                    .find(&text.to_ascii_lowercase())
+                {
+                    Some(start) => {
+                        let end = start + text.len();
+                        (
+                            l.content[..start].to_string(),
+                            l.content[start..end].to_string(),
+                            l.content[end..].to_string(),
+                        )
+                    }
+                    None => (l.content.clone(), String::new(), String::new()),
+                };
+                LogHitView {
+                    url,
+                    pipeline_name: l.pipeline_name,
+                    run_number: l.run_number,
+                    stage_name: l.stage_name,
+                    line_number: l.line_number,
+                    before: numbered(l.line_number - l.before.len() as i64, l.before),
+                    prefix,
+                    matched,
+                    suffix,
+                    after: numbered(l.line_number + 1, l.after),
+                }
+            })
+            .collect();
+    }
+
+    let template = SearchTemplate {
+        query: query.q.unwrap_or_default(),
+        searched,
+        runs,
+        log_hits,
+    };
+
+    match template.render() {
+        Ok(html) => Ok(Html(html)),
+        Err(e) => {
+            tracing::error!("Search template render error: {}", e);
+            Err(ApiError::Internal(format!("Template error: {}", e)))
         }
     }
+}
 
-    // Sort by created_at (most recent first) - already sorted from DB
-    let has_runs = !all_runs.is_empty();
-    let template = RunsTemplate {
+async fn insights_page(
+    State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+    Query(query): Query<InsightsQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let days = query.days.unwrap_or(DEFAULT_DAYS).clamp(1, 365);
+    let filter = AnalyticsFilter {
+        tenant_id: ResourceId::from_uuid(tenant.id),
+        since: chrono::Utc::now() - chrono::Duration::days(days),
+        pipeline_id: None,
+    };
+    let repo = &state.analytics_repo;
+    let summary = repo.summary(&filter).await?;
+
+    let daily = repo.daily_runs(&filter).await?;
+    let busiest = daily.iter().map(|d| d.total_runs).max().unwrap_or(0).max(1);
+    let daily = daily
+        .into_iter()
+        .map(|d| DailyRunsView {
+            day: d.day.format("%b %-d").to_string(),
+            total_runs: d.total_runs,
+            failed: d.failed,
+            height: d.total_runs * 100 / busiest,
+            failed_height: d.failed * 100 / d.total_runs.max(1),
+        })
+        .collect();
+
+    let pipelines = repo
+        .pipeline_stats(&filter)
+        .await?
+        .into_iter()
+        .map(|p| PipelineStatsView {
+            id: p.pipeline_id.to_string(),
+            name: p.pipeline_name,
+            total_runs: p.total_runs,
+            failure_rate: format_rate(p.failure_rate),
+            p50_duration: format_secs(p.p50_duration_secs),
+            p95_duration: format_secs(p.p95_duration_secs),
+            p95_queue_wait: format_secs(p.p95_queue_wait_secs),
+        })
+        .collect();
+
+    let branches = repo
+        .branch_stats(&filter)
+        .await?
+        .into_iter()
+        .take(10)
+        .map(|b| BranchStatsView {
+            pipeline_name: b.pipeline_name,
+            branch: b.branch.unwrap_or_else(|| "-".to_string()),
+            total_runs: b.total_runs,
+            failure_rate: format_rate(b.failure_rate),
+            p50_duration: format_secs(b.p50_duration_secs),
+            p95_duration: format_secs(b.p95_duration_secs),
+        })
+        .collect();
+
+    let stages = repo
+        .stage_stats(&filter)
+        .await?
+        .into_iter()
+        .take(10)
+        .map(|s| StageStatsView {
+            pipeline_id: s.pipeline_id.to_string(),
+            pipeline_name: s.pipeline_name,
+            stage_name: s.stage_name,
+            total_runs: s.total_runs,
+            failure_rate: format_rate(s.failure_rate),
+            p50_duration: format_secs(s.p50_duration_secs),
+            p95_duration: format_secs(s.p95_duration_secs),
+        })
+        .collect();
+
+    let flaky_stages = repo
+        .flaky_stages(&filter, 10)
+        .await?
+        .into_iter()
+        .map(|f| FlakyStageView {
+            pipeline_id: f.pipeline_id.to_string(),
+            pipeline_name: f.pipeline_name,
+            stage_name: f.stage_name,
+            total_runs: f.total_runs,
+            failed: f.failed,
+            flips: f.flips,
+            flip_rate: format_rate(f.flip_rate),
+            flaky_commits: f.flaky_commits,
+        })
+        .collect();
+
+    let template = InsightsTemplate {
+        days,
+        total_runs: summary.total_runs,
+        success_rate: format_rate(summary.success_rate),
+        p50_duration: format_secs(summary.p50_duration_secs),
+        p95_duration: format_secs(summary.p95_duration_secs),
+        p50_queue_wait: format_secs(summary.p50_queue_wait_secs),
+        p95_queue_wait: format_secs(summary.p95_queue_wait_secs),
+        daily,
         pipelines,
-        runs: all_runs,
-        has_runs,
+        branches,
+        stages,
+        flaky_stages,
     };
 
-    Ok(Html(template.render().unwrap()))
+    match template.render() {
+        Ok(html) => Ok(Html(html)),
+        Err(e) => {
+            tracing::error!("Insights template render error: {}", e);
+            Err(ApiError::Internal(format!("Template error: {}", e)))
+        }
+    }
 }
 
-async fn environments_page(State(state): State<AppState>) -> Result<impl IntoResponse, ApiError> {
-    let tenant = state
-        .tenant_repo
-        .get_by_slug("default")
-        .await
-        .map_err(|_| ApiError::Internal("No default tenant".to_string()))?;
+async fn costs_page(
+    State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+    Query(query): Query<CostsQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let days = query.days.unwrap_or(DEFAULT_DAYS).clamp(1, 365);
+    let filter = AnalyticsFilter {
+        tenant_id: ResourceId::from_uuid(tenant.id),
+        since: chrono::Utc::now() - chrono::Duration::days(days),
+        pipeline_id: None,
+    };
+    let report = cost_report(&state, &filter, days, 10).await?;
+    let currency = report.currency;
+    let money = |amount: f64| format_money(amount, &currency);
+
+    let priciest = report
+        .daily
+        .iter()
+        .map(|d| d.cost)
+        .fold(0.0, f64::max)
+        .max(f64::MIN_POSITIVE);
+    let daily = report
+        .daily
+        .iter()
+        .map(|d| DailyCostView {
+            day: d.day.format("%b %-d").to_string(),
+            cost: money(d.cost),
+            height: (d.cost * 100.0 / priciest).round() as i64,
+        })
+        .collect();
+
+    let total = report.summary.cost;
+    let pipelines = report
+        .pipelines
+        .iter()
+        .map(|p| PipelineCostView {
+            id: p.pipeline_id.map(|id| id.to_string()),
+            name: p
+                .pipeline_name
+                .clone()
+                .unwrap_or_else(|| "Deleted pipeline".to_string()),
+            runs: p.runs,
+            cpu_core_hours: format!("{:.1}", p.cpu_core_hours),
+            memory_gib_hours: format!("{:.1}", p.memory_gib_hours),
+            cost: money(p.cost),
+            cost_per_run: p.cost_per_run.map_or("--".to_string(), money),
+            share: format_rate((total > 0.0).then(|| p.cost / total)),
+        })
+        .collect();
+
+    let runs = report
+        .runs
+        .iter()
+        .map(|r| RunCostView {
+            url: r
+                .pipeline_id
+                .map(|pipeline_id| format!("/pipelines/{}/runs/{}", pipeline_id, r.run_id)),
+            pipeline_name: r
+                .pipeline_name
+                .clone()
+                .unwrap_or_else(|| "Deleted pipeline".to_string()),
+            started_at: r.started_at.format("%b %-d %H:%M").to_string(),
+            run_minutes: format_secs(Some(r.run_minutes * 60.0)),
+            cost: money(r.cost),
+        })
+        .collect();
+
+    let budget = query
+        .budget
+        .as_deref()
+        .and_then(|b| b.trim().parse::<f64>().ok())
+        .filter(|b| *b > 0.0);
+    let summary = &report.summary;
+    let template = CostsTemplate {
+        days,
+        cpu_core_hour: money(report.rates.cpu_core_hour),
+        memory_gib_hour: money(report.rates.memory_gib_hour),
+        total_cost: money(total),
+        projected_monthly: money(report.projected_monthly),
+        jobs: summary.jobs,
+        measured_share: format_rate(
+            (summary.jobs > 0).then(|| summary.measured_jobs as f64 / summary.jobs as f64),
+        ),
+        cpu_core_hours: format!("{:.1}", summary.cpu_core_hours),
+        memory_gib_hours: format!("{:.1}", summary.memory_gib_hours),
+        budget: budget.map(|b| b.to_string()).unwrap_or_default(),
+        budget_used: budget.map(|b| (report.projected_monthly * 100.0 / b).round() as i64),
+        daily,
+        pipelines,
+        runs,
+        currency,
+    };
+
+    match template.render() {
+        Ok(html) => Ok(Html(html)),
+        Err(e) => {
+            tracing::error!("Costs template render error: {}", e);
+            Err(ApiError::Internal(format!("Template error: {}", e)))
+        }
+    }
+}
 
+async fn environments_page(
+    State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+) -> Result<impl IntoResponse, ApiError> {
     let tenant_id = ResourceId::from_uuid(tenant.id);
     let env_records = state.deployment_repo.list_environments(tenant_id).await?;
 
@@ -1166,13 +2612,8 @@ async fn environments_page(State(state): State<AppState>) -> Result<impl IntoRes
 
 async fn new_environment_page(
     State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
 ) -> Result<impl IntoResponse, ApiError> {
-    let tenant = state
-        .tenant_repo
-        .get_by_slug("default")
-        .await
-        .map_err(|_| ApiError::Internal("No default tenant".to_string()))?;
-
     let target_records = state
         .deployment_repo
         .list_targets(ResourceId::from_uuid(tenant.id))
@@ -1191,13 +2632,10 @@ async fn new_environment_page(
     Ok(Html(template.render().unwrap()))
 }
 
-async fn services_page(State(state): State<AppState>) -> Result<impl IntoResponse, ApiError> {
-    let tenant = state
-        .tenant_repo
-        .get_by_slug("default")
-        .await
-        .map_err(|_| ApiError::Internal("No default tenant".to_string()))?;
-
+async fn services_page(
+    State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+) -> Result<impl IntoResponse, ApiError> {
     let tenant_id = ResourceId::from_uuid(tenant.id);
     let service_records = state.deployment_repo.list_services(tenant_id).await?;
 
@@ -1224,6 +2662,11 @@ async fn services_page(State(state): State<AppState>) -> Result<impl IntoRespons
             last_deploy_ago: last_deploy
                 .map(format_time_ago)
                 .unwrap_or_else(|| "never".to_string()),
+            owner_team: svc.owner_team.unwrap_or_default(),
+            tier: svc.tier.unwrap_or_default(),
+            repository_url: svc.repository_url.unwrap_or_default(),
+            runbook_url: svc.runbook_url.unwrap_or_default(),
+            dashboard_urls: svc.dashboard_urls,
         });
     }
 
@@ -1235,13 +2678,10 @@ async fn services_page(State(state): State<AppState>) -> Result<impl IntoRespons
     Ok(Html(template.render().unwrap()))
 }
 
-async fn history_page(State(state): State<AppState>) -> Result<impl IntoResponse, ApiError> {
-    let tenant = state
-        .tenant_repo
-        .get_by_slug("default")
-        .await
-        .map_err(|_| ApiError::Internal("No default tenant".to_string()))?;
-
+async fn history_page(
+    State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+) -> Result<impl IntoResponse, ApiError> {
     let tenant_id = ResourceId::from_uuid(tenant.id);
     let deploy_records = state
         .deployment_repo
@@ -1264,6 +2704,7 @@ async fn history_page(State(state): State<AppState>) -> Result<impl IntoResponse
             };
 
             DeploymentView {
+                id: d.id.to_string(),
                 version: d.version,
                 commit_sha: d.commit_sha.unwrap_or_default(),
                 service_name: d.service_name,
@@ -1283,13 +2724,199 @@ async fn history_page(State(state): State<AppState>) -> Result<impl IntoResponse
     Ok(Html(template.render().unwrap()))
 }
 
-async fn targets_page(State(state): State<AppState>) -> Result<impl IntoResponse, ApiError> {
-    let tenant = state
-        .tenant_repo
-        .get_by_slug("default")
-        .await
-        .map_err(|_| ApiError::Internal("No default tenant".to_string()))?;
-
+async fn deployment_diff_page(
+    State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+    uri: Uri,
+    Path(id): Path<Uuid>,
+    Query(query): Query<DeploymentDiffPageQuery>,
+) -> Result<Response, ApiError> {
+    let repo = &state.deployment_repo;
+    let to = repo.get_deployment(ResourceId::from_uuid(id)).await?;
+    if let Some(switch) = switch_tenant(&state, &tenant, to.tenant_id, &uri).await? {
+        return Ok(switch);
+    }
+    let from = deployment::diff_base(&state, &to, query.from).await?;
+    let diff = state.deployment_diff().diff(&from, &to).await?;
+    let service = repo
+        .get_service(ResourceId::from_uuid(to.service_id))
+        .await?;
+
+    let mut environments = HashMap::new();
+    for environment in repo
+        .list_environments(ResourceId::from_uuid(to.tenant_id))
+        .await?
+    {
+        environments.insert(environment.id, environment.name);
+    }
+    let candidates = repo
+        .list_service_deployments(ResourceId::from_uuid(service.id), None, 50)
+        .await?
+        .into_iter()
+        .filter(|d| d.id != to.id)
+        .map(|d| DiffCandidateView {
+            id: d.id.to_string(),
+            label: format!(
+                "{} in {} ({}, {})",
+                d.version,
+                environments
+                    .get(&d.environment_id)
+                    .map(String::as_str)
+                    .unwrap_or("unknown environment"),
+                d.status,
+                format_time_ago(d.created_at)
+            ),
+        })
+        .collect();
+
+    let side = |d: &DiffedDeployment| DiffedDeploymentView {
+        version: d.version.clone(),
+        environment: d.environment.clone(),
+        status: d.status.clone(),
+        commit_sha: d
+            .commit_sha
+            .as_deref()
+            .unwrap_or_default()
+            .chars()
+            .take(7)
+            .collect(),
+        spec_version: d
+            .spec_version
+            .map(|v| format!("v{}", v))
+            .unwrap_or_default(),
+        deployed_ago: format_time_ago(d.created_at),
+    };
+    let image_text = |image: &Option<ImageRef>| {
+        image
+            .as_ref()
+            .map(|i| {
+                let mut text = i.repository.clone();
+                if let Some(tag) = &i.tag {
+                    text.push_str(&format!(":{}", tag));
+                }
+                if let Some(digest) = &i.digest {
+                    text.push_str(&format!("@{}", digest));
+                }
+                text
+            })
+            .unwrap_or_default()
+    };
+    let json_text = |value: Option<serde_json::Value>| match value {
+        Some(serde_json::Value::String(s)) => s,
+        Some(value) => value.to_string(),
+        None => String::new(),
+    };
+    let template = DeploymentDiffTemplate {
+        service_name: service.name,
+        from_id: from.id.to_string(),
+        deployments: vec![side(&diff.from), side(&diff.to)],
+        candidates,
+        image: diff.image.map(|i| ImageChangeView {
+            from: image_text(&i.from),
+            to: image_text(&i.to),
+            repository_changed: i.repository_changed,
+            tag_changed: i.tag_changed,
+            digest_changed: i.digest_changed,
+        }),
+        env: diff.env,
+        spec: diff
+            .spec
+            .into_iter()
+            .map(|c| SpecChangeView {
+                path: c.path,
+                change: c.change,
+                old: json_text(c.old),
+                new: json_text(c.new),
+            })
+            .collect(),
+        manifest: diff.manifest,
+    };
+    Ok(Html(template.render().unwrap()).into_response())
+}
+
+async fn approvals_page(
+    State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+) -> Result<impl IntoResponse, ApiError> {
+    let tenant_id = ResourceId::from_uuid(tenant.id);
+    let repo = &state.deployment_repo;
+    let services = repo.list_services(tenant_id).await?;
+    let user_name = async |id: Option<Uuid>| match id {
+        Some(id) => state
+            .organization_repo
+            .get_user(ResourceId::from_uuid(id))
+            .await
+            .map(|u| u.name)
+            .unwrap_or_else(|_| "unknown user".to_string()),
+        None => "API key".to_string(),
+    };
+
+    let mut approvals = Vec::new();
+    for deployment in repo.list_awaiting_approval(tenant_id).await? {
+        let environment_id = ResourceId::from_uuid(deployment.environment_id);
+        let environment = repo.get_environment(environment_id).await?;
+        let protection = repo.get_environment_protection(environment_id).await?;
+
+        let mut reviewers = Vec::new();
+        for reviewer in protection.iter().flat_map(|p| &p.reviewers) {
+            reviewers.push(user_name(Some(*reviewer)).await);
+        }
+        reviewers.extend(
+            protection
+                .iter()
+                .flat_map(|p| &p.reviewer_roles)
+                .map(|role| format!("{}s", role)),
+        );
+        let reviewers = if reviewers.is_empty() {
+            "anyone who can deploy".to_string()
+        } else {
+            reviewers.join(", ")
+        };
+
+        let mut reviews = Vec::new();
+        for review in repo
+            .list_deployment_reviews(ResourceId::from_uuid(deployment.id))
+            .await?
+        {
+            reviews.push(ReviewView {
+                reviewer: user_name(review.user_id).await,
+                approved: review.approved,
+                comment: review.comment.unwrap_or_default(),
+                ago: format_time_ago(review.created_at),
+            });
+        }
+
+        approvals.push(PendingDeploymentView {
+            deployment_id: deployment.id.to_string(),
+            service_name: services
+                .iter()
+                .find(|s| s.id == deployment.service_id)
+                .map(|s| s.name.clone())
+                .unwrap_or_default(),
+            approvals: reviews.iter().filter(|r| r.approved).count() as i32,
+            required_approvals: required_approvals(&environment, protection.as_ref(), &deployment),
+            environment: environment.name,
+            version: deployment.version,
+            branch: deployment.branch.unwrap_or_default(),
+            requested_ago: format_time_ago(deployment.created_at),
+            reviewers,
+            wait_minutes: protection.map_or(0, |p| p.wait_minutes),
+            reviews,
+        });
+    }
+
+    let has_approvals = !approvals.is_empty();
+    let template = ApprovalsTemplate {
+        approvals,
+        has_approvals,
+    };
+    Ok(Html(template.render().unwrap()))
+}
+
+async fn targets_page(
+    State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+) -> Result<impl IntoResponse, ApiError> {
     let tenant_id = ResourceId::from_uuid(tenant.id);
     let target_records = state.deployment_repo.list_targets(tenant_id).await?;
     let env_records = state.deployment_repo.list_environments(tenant_id).await?;
@@ -1301,9 +2928,12 @@ async fn targets_page(State(state): State<AppState>) -> Result<impl IntoResponse
                 env_records.iter().filter(|e| e.target_id == t.id).count() as i32;
 
             TargetView {
+                id: t.id.to_string(),
                 name: t.name,
                 target_type: t.target_type,
                 status: t.status,
+                status_message: t.status_message.unwrap_or_default(),
+                checked_ago: t.checked_at.map(format_time_ago).unwrap_or_default(),
                 region: t.region.unwrap_or_else(|| "-".to_string()),
                 environment_count,
             }
@@ -1323,13 +2953,218 @@ async fn new_target_page() -> Result<impl IntoResponse, ApiError> {
     Ok(Html(template.render().unwrap()))
 }
 
-async fn settings_page(State(state): State<AppState>) -> Result<impl IntoResponse, ApiError> {
-    let tenant = state
-        .tenant_repo
-        .get_by_slug("default")
+/// Verification page of the CLI's device sign-in.
+async fn device_page(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<DevicePageQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    use buildit_db::OrganizationRepo;
+
+    let signed_in = cookie_user(&state, &headers).await?.is_some();
+    let user_code = query
+        .user_code
+        .as_deref()
+        .map(normalize_user_code)
+        .unwrap_or_default();
+    let client_name = if signed_in && !user_code.is_empty() {
+        state
+            .organization_repo
+            .get_pending_device_authorization(&user_code)
+            .await
+            .ok()
+            .and_then(|a| a.client_name)
+            .unwrap_or_default()
+    } else {
+        String::new()
+    };
+
+    let template = DeviceTemplate {
+        signed_in,
+        user_code,
+        client_name,
+        result: query.result.unwrap_or_default(),
+    };
+    Ok(Html(template.render().unwrap()))
+}
+
+/// The page an invitation email links to. Remembers the token in a cookie
+/// so that signing in with GitHub comes back here, creating an account for
+/// an invitee who has none.
+async fn invitation_page(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(token): Path<String>,
+    Query(query): Query<InvitationPageQuery>,
+) -> Result<Response, ApiError> {
+    use buildit_db::{DbError, OrganizationRepo};
+
+    let repo = &state.organization_repo;
+    let signed_in = cookie_user(&state, &headers).await?.is_some();
+    let invitation = match repo.get_invitation_by_token(&token_hash(&token)).await {
+        Ok(invitation)
+            if invitation.status == "pending" && invitation.expires_at > chrono::Utc::now() =>
+        {
+            Some(invitation)
+        }
+        Ok(_) | Err(DbError::NotFound(_)) => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut template = InvitationTemplate {
+        token: token.clone(),
+        signed_in,
+        valid: invitation.is_some(),
+        organization: String::new(),
+        inviter: String::new(),
+        role: String::new(),
+        email: String::new(),
+        expires: String::new(),
+        result: query.result.unwrap_or_default(),
+    };
+    if let Some(invitation) = &invitation {
+        template.organization = repo
+            .get_organization(ResourceId::from_uuid(invitation.organization_id))
+            .await?
+            .name;
+        if let Some(inviter) = invitation.invited_by {
+            template.inviter = repo
+                .get_user(ResourceId::from_uuid(inviter))
+                .await
+                .map(|u| u.name)
+                .unwrap_or_default();
+        }
+        template.role = invitation.role.clone();
+        template.email = invitation.email.clone();
+        template.expires = invitation.expires_at.format("%B %-d, %Y").to_string();
+    }
+
+    let html = Html(template.render().unwrap());
+    if invitation.is_some() && !signed_in {
+        let cookie = Cookie::build((INVITATION_COOKIE, token))
+            .path("/")
+            .http_only(true)
+            .max_age(time::Duration::hours(1))
+            .same_site(SameSite::Lax)
+            .build();
+        return Ok((CookieJar::new().add(cookie), html).into_response());
+    }
+    Ok(html.into_response())
+}
+
+/// Accept or decline an invitation from its page. Declining needs no
+/// account.
+async fn answer_invitation(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(token): Path<String>,
+    Form(form): Form<AnswerInvitationForm>,
+) -> Result<Redirect, ApiError> {
+    use buildit_db::{DbError, OrganizationRepo};
+
+    let repo = &state.organization_repo;
+    let page = |result: &str| {
+        Redirect::to(&format!(
+            "/invitations/{}?result={}",
+            urlencoding::encode(&token),
+            result
+        ))
+    };
+    let invitation = match repo.get_invitation_by_token(&token_hash(&token)).await {
+        Ok(invitation) => invitation,
+        Err(DbError::NotFound(_)) => return Ok(page("invalid")),
+        Err(e) => return Err(e.into()),
+    };
+    let id = ResourceId::from_uuid(invitation.id);
+
+    if form.action != "accept" {
+        return match repo.decline_invitation(id).await {
+            Ok(()) => Ok(page("declined")),
+            Err(DbError::NotFound(_)) => Ok(page("invalid")),
+            Err(e) => Err(e.into()),
+        };
+    }
+    let Some(user_id) = cookie_user(&state, &headers).await? else {
+        return Ok(page(""));
+    };
+    match repo
+        .accept_invitation(id, ResourceId::from_uuid(user_id))
         .await
-        .map_err(|_| ApiError::Internal("No default tenant".to_string()))?;
+    {
+        Ok(_) => Ok(page("accepted")),
+        Err(DbError::NotFound(_)) => Ok(page("invalid")),
+        Err(DbError::Conflict(_)) => Ok(page("member")),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The page `/buildit link` links to, where the signed-in user confirms
+/// that the Slack user may act as them in the current tenant.
+async fn slack_link_page(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    CurrentTenant(tenant): CurrentTenant,
+    Path(token): Path<String>,
+    Query(query): Query<InvitationPageQuery>,
+) -> Result<Html<String>, ApiError> {
+    let template = SlackLinkTemplate {
+        valid: slack_link(&state, &token).is_some(),
+        token,
+        signed_in: cookie_user(&state, &headers).await?.is_some(),
+        tenant_name: tenant.name,
+        result: query.result.unwrap_or_default(),
+    };
+    Ok(Html(template.render().unwrap()))
+}
+
+/// Link the Slack user of a link token to the signed-in user.
+async fn link_slack_account(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    CurrentTenant(tenant): CurrentTenant,
+    Path(token): Path<String>,
+) -> Result<Redirect, ApiError> {
+    use buildit_db::OrganizationRepo;
+
+    let page = |result: &str| {
+        Redirect::to(&format!(
+            "/slack/link/{}?result={}",
+            urlencoding::encode(&token),
+            result
+        ))
+    };
+    let Some((team_id, slack_user_id)) = slack_link(&state, &token) else {
+        return Ok(page("invalid"));
+    };
+    let Some(user_id) = cookie_user(&state, &headers).await? else {
+        return Ok(page(""));
+    };
+    state
+        .organization_repo
+        .link_slack_user(
+            &team_id,
+            &slack_user_id,
+            ResourceId::from_uuid(user_id),
+            ResourceId::from_uuid(tenant.id),
+        )
+        .await?;
+    tracing::info!(team = %team_id, slack_user = %slack_user_id, user = %user_id, tenant = %tenant.slug, "Linked Slack user");
+    Ok(page("linked"))
+}
+
+/// The Slack team and user a link token is for, if it is valid.
+fn slack_link(state: &AppState, token: &str) -> Option<(String, String)> {
+    let secret = state
+        .system_config
+        .notifications
+        .slack_signing_secret
+        .as_deref()?;
+    slack::verify_link_token(secret, token, chrono::Utc::now())
+}
 
+async fn settings_page(
+    CurrentTenant(tenant): CurrentTenant,
+) -> Result<impl IntoResponse, ApiError> {
     let template = SettingsTemplate {
         tenant_name: tenant.name,
         tenant_slug: tenant.slug,
@@ -1338,19 +3173,22 @@ async fn settings_page(State(state): State<AppState>) -> Result<impl IntoRespons
     Ok(Html(template.render().unwrap()))
 }
 
-async fn settings_team_page(State(state): State<AppState>) -> Result<impl IntoResponse, ApiError> {
+async fn settings_team_page(
+    State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+) -> Result<impl IntoResponse, ApiError> {
     use buildit_db::OrganizationRepo;
 
-    // Get the first organization (demo purposes)
-    let orgs = state.organization_repo.list_organizations().await?;
-    let org = orgs
-        .first()
-        .ok_or_else(|| ApiError::Internal("No organization found".to_string()))?;
+    let org = tenant_organization(&state, &tenant).await?;
 
     let members_db = state
         .organization_repo
         .list_org_members(ResourceId::from_uuid(org.id))
         .await?;
+    let members_db_names: std::collections::HashMap<Uuid, String> = members_db
+        .iter()
+        .map(|m| (m.user_id, m.user_name.clone()))
+        .collect();
 
     let members: Vec<TeamMemberView> = members_db
         .into_iter()
@@ -1372,7 +3210,31 @@ async fn settings_team_page(State(state): State<AppState>) -> Result<impl IntoRe
         })
         .collect();
 
-    let template = SettingsTeamTemplate { members };
+    let now = chrono::Utc::now();
+    let invitations = state
+        .organization_repo
+        .list_pending_invitations(ResourceId::from_uuid(org.id))
+        .await?
+        .into_iter()
+        .map(|i| InvitationView {
+            id: i.id.to_string(),
+            invited_by: i
+                .invited_by
+                .and_then(|id| members_db_names.get(&id).cloned())
+                .unwrap_or_default(),
+            email: i.email,
+            role: i.role,
+            sent: format_time_ago(i.created_at),
+            expires: i.expires_at.format("%b %-d").to_string(),
+            expired: i.expires_at <= now,
+        })
+        .collect();
+
+    let template = SettingsTeamTemplate {
+        org_id: org.id.to_string(),
+        members,
+        invitations,
+    };
     Ok(Html(template.render().unwrap()))
 }
 
@@ -1399,13 +3261,11 @@ async fn settings_secrets_page(_state: State<AppState>) -> Result<impl IntoRespo
 
 async fn settings_tokens_page(
     State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
 ) -> Result<impl IntoResponse, ApiError> {
     use buildit_db::OrganizationRepo;
 
-    let orgs = state.organization_repo.list_organizations().await?;
-    let org = orgs
-        .first()
-        .ok_or_else(|| ApiError::Internal("No organization found".to_string()))?;
+    let org = tenant_organization(&state, &tenant).await?;
 
     let api_keys = state
         .organization_repo
@@ -1415,6 +3275,7 @@ async fn settings_tokens_page(
     let tokens: Vec<TokenView> = api_keys
         .into_iter()
         .map(|k| TokenView {
+            id: k.id.to_string(),
             name: k.name,
             prefix: k.key_prefix,
             last_used: k.last_used_at.map(format_time_ago).unwrap_or_default(),
@@ -1422,17 +3283,113 @@ async fn settings_tokens_page(
         })
         .collect();
 
-    let template = SettingsTokensTemplate { tokens };
+    let template = SettingsTokensTemplate {
+        org_id: org.id.to_string(),
+        tokens,
+    };
     Ok(Html(template.render().unwrap()))
 }
 
-async fn settings_git_page(State(state): State<AppState>) -> Result<impl IntoResponse, ApiError> {
-    use buildit_db::OrganizationRepo;
+/// Resource types offered by the audit log filter: the API's areas.
+const AUDIT_RESOURCE_TYPES: &[&str] = &[
+    "applications",
+    "deployment",
+    "organizations",
+    "pipelines",
+    "repositories",
+    "stacks",
+    "tenants",
+];
+
+async fn settings_audit_page(
+    State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+    Query(query): Query<AuditPageQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    use buildit_db::{AuditLogFilter, OrganizationRepo};
+
+    let org = tenant_organization(&state, &tenant).await?;
+
+    let members = state
+        .organization_repo
+        .list_org_members(ResourceId::from_uuid(org.id))
+        .await?;
+    let names: std::collections::HashMap<Uuid, String> = members
+        .iter()
+        .map(|m| (m.user_id, m.user_name.clone()))
+        .collect();
+
+    let day = |s: &str| {
+        chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+            .ok()
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+            .map(|t| t.and_utc())
+    };
+    let filter = AuditLogFilter {
+        organization_id: Some(org.id),
+        user_id: query.user_id.parse().ok(),
+        resource_type: Some(query.resource_type.clone()).filter(|s| !s.is_empty()),
+        resource_id: query.resource_id.trim().parse().ok(),
+        action: Some(query.action.trim().to_string()).filter(|s| !s.is_empty()),
+        from: day(&query.from),
+        // The end date is inclusive
+        to: day(&query.to).map(|t| t + chrono::Duration::days(1)),
+        limit: 200,
+        ..Default::default()
+    };
+    let logs = state.organization_repo.query_audit_logs(&filter).await?;
+
+    let entries = logs
+        .into_iter()
+        .map(|log| {
+            let status = log.metadata.get("status").and_then(|s| s.as_u64());
+            let actor = match log.user_id {
+                Some(id) => names.get(&id).cloned().unwrap_or_else(|| id.to_string()),
+                None if log.metadata.get("api_key_id").is_some() => "API key".to_string(),
+                None => "Anonymous".to_string(),
+            };
+            let resource_id = log.resource_id.map(|id| id.to_string()).unwrap_or_default();
+            AuditLogView {
+                time: log.created_at.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+                ago: format_time_ago(log.created_at),
+                actor,
+                action: log.action,
+                resource_type: log.resource_type.unwrap_or_default(),
+                resource_short: resource_id.chars().take(8).collect(),
+                resource_id,
+                status: status.map(|s| s.to_string()).unwrap_or_default(),
+                succeeded: status.is_some_and(|s| s < 400),
+                ip_address: log.ip_address.unwrap_or_default(),
+                metadata: serde_json::to_string_pretty(&log.metadata).unwrap_or_default(),
+            }
+        })
+        .collect();
+
+    let template = SettingsAuditTemplate {
+        entries,
+        members: members
+            .into_iter()
+            .map(|m| AuditMemberView {
+                id: m.user_id.to_string(),
+                name: m.user_name,
+            })
+            .collect(),
+        resource_types: AUDIT_RESOURCE_TYPES.iter().map(|s| s.to_string()).collect(),
+        filter_user_id: query.user_id,
+        filter_resource_type: query.resource_type,
+        filter_resource_id: query.resource_id,
+        filter_action: query.action,
+        filter_from: query.from,
+        filter_to: query.to,
+    };
+    Ok(Html(template.render().unwrap()))
+}
 
-    let orgs = state.organization_repo.list_organizations().await?;
-    let org = orgs
-        .first()
-        .ok_or_else(|| ApiError::Internal("No organization found".to_string()))?;
+async fn settings_git_page(
+    State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+) -> Result<impl IntoResponse, ApiError> {
+    let org = tenant_organization(&state, &tenant).await?;
 
     // TODO: Load actual OAuth connections from database
     let template = SettingsGitTemplate {
@@ -1449,27 +3406,168 @@ async fn settings_git_page(State(state): State<AppState>) -> Result<impl IntoRes
 }
 
 async fn settings_notifications_page(
-    _state: State<AppState>,
+    State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
 ) -> Result<impl IntoResponse, ApiError> {
-    // TODO: Load actual notification settings from database
+    use buildit_db::NotificationRepo;
+
+    let channels = state
+        .notification_repo
+        .list_channels(ResourceId::from_uuid(tenant.id))
+        .await?
+        .into_iter()
+        .map(|c| {
+            let target = match c.kind.as_str() {
+                "email" => c
+                    .config
+                    .get("recipients")
+                    .and_then(|r| r.as_array())
+                    .map(|r| {
+                        r.iter()
+                            .filter_map(|r| r.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    })
+                    .unwrap_or_default(),
+                // Slack webhook URLs are secrets themselves
+                "slack" => "Slack incoming webhook".to_string(),
+                _ => c
+                    .config
+                    .get("url")
+                    .and_then(|u| u.as_str())
+                    .unwrap_or_default()
+                    .to_string(),
+            };
+            NotificationChannelView {
+                id: c.id.to_string(),
+                name: c.name,
+                kind: c.kind,
+                target,
+                events: c.events,
+                enabled: c.enabled,
+                last_delivery: c.last_delivery_at.map(format_time_ago).unwrap_or_default(),
+                last_error: c.last_error.unwrap_or_default(),
+            }
+        })
+        .collect();
+
     let template = SettingsNotificationsTemplate {
-        slack_connected: false,
-        slack_channel: String::new(),
-        has_webhooks: false,
-        webhook_count: 0,
+        tenant_slug: tenant.slug,
+        channels,
+        events: crate::services::notifications::EVENTS.to_vec(),
     };
+    Ok(Html(template.render().unwrap()))
+}
+
+async fn settings_variables_page(
+    State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+) -> Result<impl IntoResponse, ApiError> {
+    let tenant_id = ResourceId::from_uuid(tenant.id);
+    let pipelines = state.pipeline_repo.list_by_tenant(tenant_id).await?;
+    let pipeline_names: std::collections::HashMap<Uuid, &str> =
+        pipelines.iter().map(|p| (p.id, p.name.as_str())).collect();
 
+    let groups = state
+        .variable_group_repo
+        .list_all_groups(tenant_id)
+        .await?
+        .into_iter()
+        .map(|g| {
+            let mut variables: Vec<_> = g.variables().into_iter().collect();
+            variables.sort();
+            VariableGroupView {
+                id: g.id.to_string(),
+                scope: match g.pipeline_id {
+                    Some(id) => pipeline_names
+                        .get(&id)
+                        .copied()
+                        .unwrap_or("archived pipeline")
+                        .to_string(),
+                    None => "All pipelines".to_string(),
+                },
+                rows: variables.len().clamp(2, 12),
+                variables: variables
+                    .iter()
+                    .map(|(name, value)| format!("{}={}", name, value))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                name: g.name,
+                description: g.description.unwrap_or_default(),
+            }
+        })
+        .collect();
+
+    let template = SettingsVariablesTemplate {
+        tenant_slug: tenant.slug,
+        groups,
+        pipelines: pipelines
+            .iter()
+            .map(|p| PipelineSelectView {
+                id: p.id.to_string(),
+                name: p.name.clone(),
+            })
+            .collect(),
+    };
     Ok(Html(template.render().unwrap()))
 }
 
-async fn repositories_page(State(state): State<AppState>) -> Result<impl IntoResponse, ApiError> {
-    use buildit_db::OrganizationRepo;
+/// Deliveries shown per endpoint on the webhooks page.
+const WEBHOOK_DELIVERIES_SHOWN: i64 = 10;
+
+async fn settings_webhooks_page(
+    State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+) -> Result<impl IntoResponse, ApiError> {
+    use buildit_db::WebhookSubscriptionRepo;
+
+    let repo = &state.webhook_subscription_repo;
+    let mut subscriptions = Vec::new();
+    for s in repo
+        .list_subscriptions(ResourceId::from_uuid(tenant.id))
+        .await?
+    {
+        let deliveries = repo
+            .list_deliveries(ResourceId::from_uuid(s.id), WEBHOOK_DELIVERIES_SHOWN)
+            .await?
+            .into_iter()
+            .map(|d| WebhookDeliveryView {
+                id: d.id.to_string(),
+                event: d.event,
+                response: match (d.response_status, d.error) {
+                    (Some(status), _) => status.to_string(),
+                    (None, Some(error)) => error,
+                    (None, None) => String::new(),
+                },
+                status: d.status,
+                attempts: d.attempts,
+                sent: format_time_ago(d.last_attempt_at.unwrap_or(d.created_at)),
+                redelivery: d.redelivery_of.is_some(),
+            })
+            .collect();
+        subscriptions.push(WebhookSubscriptionView {
+            id: s.id.to_string(),
+            url: s.url,
+            description: s.description.unwrap_or_default(),
+            events: s.events,
+            enabled: s.enabled,
+            deliveries,
+        });
+    }
+
+    let template = SettingsWebhooksTemplate {
+        tenant_slug: tenant.slug,
+        subscriptions,
+        events: crate::services::webhook_subscriptions::EVENTS.to_vec(),
+    };
+    Ok(Html(template.render().unwrap()))
+}
 
-    // Get the first organization (demo purposes)
-    let orgs = state.organization_repo.list_organizations().await?;
-    let org = orgs
-        .first()
-        .ok_or_else(|| ApiError::Internal("No organization found".to_string()))?;
+async fn repositories_page(
+    State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+) -> Result<impl IntoResponse, ApiError> {
+    let org = tenant_organization(&state, &tenant).await?;
 
     let repos = state
         .repository_repo
@@ -1537,23 +3635,32 @@ async fn connect_repository_page() -> Result<impl IntoResponse, ApiError> {
 
 async fn repository_detail_page(
     State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
     Path(id): Path<Uuid>,
 ) -> Result<impl IntoResponse, ApiError> {
+    let org = tenant_organization(&state, &tenant).await?;
     let repo = state
         .repository_repo
         .get_by_id(ResourceId::from_uuid(id))
         .await?;
+    // Repositories belong to organizations, not tenants
+    if repo.organization_id != org.id {
+        return Err(ApiError::NotFound(format!("Repository {} not found", id)));
+    }
 
     let detected = &repo.detected_config;
 
-    // Get pipelines linked to this repo
+    // Get this tenant's pipelines linked to this repo
     let pipeline_records = state
         .pipeline_repo
         .list_by_repository(ResourceId::from_uuid(repo.id))
         .await?;
 
     let mut pipelines = Vec::new();
-    for p in pipeline_records {
+    for p in pipeline_records
+        .into_iter()
+        .filter(|p| p.tenant_id == tenant.id)
+    {
         let runs = state
             .pipeline_repo
             .list_runs(ResourceId::from_uuid(p.id), 1)
@@ -1571,7 +3678,7 @@ async fn repository_detail_page(
         });
     }
 
-    // Get stacks linked to this repo
+    // Get this tenant's stacks linked to this repo
     let stack_records = state
         .stack_repo
         .list_stacks_by_repository(ResourceId::from_uuid(repo.id))
@@ -1579,6 +3686,7 @@ async fn repository_detail_page(
 
     let stacks: Vec<RepoStackView> = stack_records
         .into_iter()
+        .filter(|s| s.tenant_id == tenant.id)
         .map(|s| RepoStackView {
             id: s.id.to_string(),
             name: s.name,
@@ -1587,6 +3695,8 @@ async fn repository_detail_page(
         })
         .collect();
 
+    let suggestions = setup_suggestions(&state, &repo).await?;
+
     let webhook_url = format!("https://api.buildit.dev/webhooks/github/{}", repo.id);
 
     let provider_str = repo.provider.to_string();
@@ -1614,7 +3724,11 @@ async fn repository_detail_page(
     };
 
     let detected_view = DetectedConfigView {
-        buildit_config: detected.buildit_config.clone().unwrap_or_default(),
+        buildit_config: if detected.pipeline_configs.is_empty() {
+            detected.buildit_config.clone().unwrap_or_default()
+        } else {
+            detected.pipeline_configs.join(", ")
+        },
         has_pipeline: detected.has_pipeline(),
         has_terraform: detected.has_terraform(),
         has_kubernetes: detected.has_kubernetes(),
@@ -1630,6 +3744,8 @@ async fn repository_detail_page(
     let template = RepositoryDetailTemplate {
         repository,
         detected: detected_view,
+        sync_error: repo.sync_error.unwrap_or_default(),
+        suggestions,
         pipelines,
         has_pipelines,
         stacks,
@@ -1640,13 +3756,10 @@ async fn repository_detail_page(
     Ok(Html(template.render().unwrap()))
 }
 
-async fn stacks_page(State(state): State<AppState>) -> Result<impl IntoResponse, ApiError> {
-    let tenant = state
-        .tenant_repo
-        .get_by_slug("default")
-        .await
-        .map_err(|_| ApiError::Internal("No default tenant".to_string()))?;
-
+async fn stacks_page(
+    State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+) -> Result<impl IntoResponse, ApiError> {
     let tenant_id = ResourceId::from_uuid(tenant.id);
     let stack_records = state.stack_repo.list_stacks_by_tenant(tenant_id).await?;
 
@@ -1716,14 +3829,12 @@ async fn stacks_page(State(state): State<AppState>) -> Result<impl IntoResponse,
     Ok(Html(template.render().unwrap()))
 }
 
-async fn new_stack_page(State(state): State<AppState>) -> Result<impl IntoResponse, ApiError> {
-    use buildit_db::OrganizationRepo;
-
+async fn new_stack_page(
+    State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+) -> Result<impl IntoResponse, ApiError> {
     // Get repositories
-    let orgs = state.organization_repo.list_organizations().await?;
-    let org = orgs
-        .first()
-        .ok_or_else(|| ApiError::Internal("No organization found".to_string()))?;
+    let org = tenant_organization(&state, &tenant).await?;
 
     let repos = state
         .repository_repo
@@ -1739,12 +3850,6 @@ async fn new_stack_page(State(state): State<AppState>) -> Result<impl IntoRespon
         .collect();
 
     // Get environments
-    let tenant = state
-        .tenant_repo
-        .get_by_slug("default")
-        .await
-        .map_err(|_| ApiError::Internal("No default tenant".to_string()))?;
-
     let env_records = state
         .deployment_repo
         .list_environments(ResourceId::from_uuid(tenant.id))
@@ -1768,12 +3873,17 @@ async fn new_stack_page(State(state): State<AppState>) -> Result<impl IntoRespon
 
 async fn stack_detail_page(
     State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+    uri: Uri,
     Path(id): Path<Uuid>,
-) -> Result<impl IntoResponse, ApiError> {
+) -> Result<Response, ApiError> {
     let s = state
         .stack_repo
         .get_stack(ResourceId::from_uuid(id))
         .await?;
+    if let Some(switch) = switch_tenant(&state, &tenant, s.tenant_id, &uri).await? {
+        return Ok(switch);
+    }
 
     // Get runs
     let run_records = state
@@ -1781,6 +3891,21 @@ async fn stack_detail_page(
         .list_runs(ResourceId::from_uuid(id), 20)
         .await?;
 
+    // Context for the newest run waiting on approval
+    let approval = match run_records
+        .iter()
+        .find(|r| r.status == StackRunStatus::NeedsApproval)
+    {
+        Some(run) => match state.approval_context().for_stack_run(run).await {
+            Ok(context) => Some(ApprovalView::new(run.id, context)),
+            Err(e) => {
+                tracing::warn!(run_id = %run.id, error = %e, "Failed to build approval context");
+                None
+            }
+        },
+        None => None,
+    };
+
     let runs: Vec<StackRunView> = run_records
         .into_iter()
         .map(|r| {
@@ -1898,18 +4023,17 @@ async fn stack_detail_page(
         has_runs,
         variables,
         has_variables,
+        has_approval: approval.is_some(),
+        approval: approval.unwrap_or_default(),
     };
 
-    Ok(Html(template.render().unwrap()))
+    Ok(Html(template.render().unwrap()).into_response())
 }
 
-async fn applications_page(State(state): State<AppState>) -> Result<impl IntoResponse, ApiError> {
-    let tenant = state
-        .tenant_repo
-        .get_by_slug("default")
-        .await
-        .map_err(|_| ApiError::Internal("No default tenant".to_string()))?;
-
+async fn applications_page(
+    State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+) -> Result<impl IntoResponse, ApiError> {
     let tenant_id = ResourceId::from_uuid(tenant.id);
     let app_records = state
         .application_repo
@@ -1950,14 +4074,10 @@ async fn applications_page(State(state): State<AppState>) -> Result<impl IntoRes
 
 async fn new_application_page(
     State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
 ) -> Result<impl IntoResponse, ApiError> {
-    use buildit_db::OrganizationRepo;
-
     // Get repositories
-    let orgs = state.organization_repo.list_organizations().await?;
-    let org = orgs
-        .first()
-        .ok_or_else(|| ApiError::Internal("No organization found".to_string()))?;
+    let org = tenant_organization(&state, &tenant).await?;
 
     let repos = state
         .repository_repo
@@ -1973,12 +4093,6 @@ async fn new_application_page(
         .collect();
 
     // Get environments
-    let tenant = state
-        .tenant_repo
-        .get_by_slug("default")
-        .await
-        .map_err(|_| ApiError::Internal("No default tenant".to_string()))?;
-
     let env_records = state
         .deployment_repo
         .list_environments(ResourceId::from_uuid(tenant.id))
@@ -2002,12 +4116,17 @@ async fn new_application_page(
 
 async fn application_detail_page(
     State(state): State<AppState>,
+    CurrentTenant(tenant): CurrentTenant,
+    uri: Uri,
     Path(id): Path<Uuid>,
-) -> Result<impl IntoResponse, ApiError> {
+) -> Result<Response, ApiError> {
     let a = state
         .application_repo
         .get_application(ResourceId::from_uuid(id))
         .await?;
+    if let Some(switch) = switch_tenant(&state, &tenant, a.tenant_id, &uri).await? {
+        return Ok(switch);
+    }
 
     // Get resources
     let resource_records = state
@@ -2083,7 +4202,7 @@ async fn application_detail_page(
         resource_count,
     };
 
-    Ok(Html(template.render().unwrap()))
+    Ok(Html(template.render().unwrap()).into_response())
 }
 
 // ============================================================================
@@ -2244,6 +4363,117 @@ fn compute_dag_layout(stages: &mut [StageView]) -> (Vec<DagEdge>, i32, i32) {
     (edges, width.max(200), height)
 }
 
+async fn tenant_root(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    uri: Uri,
+    Path(slug): Path<String>,
+) -> Result<Response, ApiError> {
+    enter_tenant(&state, &headers, &slug, "", uri.query()).await
+}
+
+async fn tenant_page(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    uri: Uri,
+    Path((slug, rest)): Path<(String, String)>,
+) -> Result<Response, ApiError> {
+    enter_tenant(&state, &headers, &slug, &rest, uri.query()).await
+}
+
+/// Switch the browser to the tenant `slug`, then show the page at `rest`.
+/// The caller must reach the tenant, and a signed-in user's session
+/// switches too.
+async fn enter_tenant(
+    state: &AppState,
+    headers: &HeaderMap,
+    slug: &str,
+    rest: &str,
+    query: Option<&str>,
+) -> Result<Response, ApiError> {
+    use buildit_db::OrganizationRepo;
+
+    let target = tenant_redirect(rest, query)
+        .ok_or_else(|| ApiError::BadRequest("Invalid page to redirect to".to_string()))?;
+    let tenant = find_tenant(state, slug).await?;
+    let session = cookie_session(state, headers).await?;
+    let reachable = reachable_tenants(state, session.as_ref()).await?;
+    if !can_reach(reachable.as_deref(), &tenant) {
+        return Err(ApiError::Forbidden(format!(
+            "No access to tenant {}",
+            tenant.slug
+        )));
+    }
+    if let Some(session) = session {
+        state
+            .organization_repo
+            .set_session_tenant(
+                ResourceId::from_uuid(session.id),
+                Some(ResourceId::from_uuid(tenant.id)),
+            )
+            .await?;
+    }
+
+    // Readable by the tenant switcher; it holds only the slug
+    let cookie = Cookie::build((TENANT_COOKIE, tenant.slug))
+        .path("/")
+        .same_site(SameSite::Lax)
+        .build();
+    Ok((CookieJar::new().add(cookie), Redirect::to(&target)).into_response())
+}
+
+/// Local page `rest` of a tenant switch redirects to, or `None` for one
+/// that browsers would take as another site, such as `//evil.com` or
+/// `/\evil.com`.
+fn tenant_redirect(rest: &str, query: Option<&str>) -> Option<String> {
+    // Browsers read backslashes as slashes
+    if rest.starts_with("//") || rest.contains('\\') {
+        return None;
+    }
+    let mut target = format!("/{}", rest.trim_start_matches('/'));
+    if let Some(query) = query {
+        target = format!("{}?{}", target, query);
+    }
+    Some(target)
+}
+
+/// Pages of a resource in another tenant than the current one switch to
+/// it first, through `/t/{slug}`, so links into any tenant work.
+async fn switch_tenant(
+    state: &AppState,
+    current: &buildit_db::Tenant,
+    tenant_id: Uuid,
+    uri: &Uri,
+) -> Result<Option<Response>, ApiError> {
+    use buildit_db::TenantRepo;
+
+    if current.id == tenant_id {
+        return Ok(None);
+    }
+    let tenant = state
+        .tenant_repo
+        .get_by_id(ResourceId::from_uuid(tenant_id))
+        .await?;
+    let target = format!("/t/{}{}", tenant.slug, uri.path());
+    Ok(Some(Redirect::to(&target).into_response()))
+}
+
+/// The organization a tenant belongs to.
+async fn tenant_organization(
+    state: &AppState,
+    tenant: &buildit_db::Tenant,
+) -> Result<buildit_db::Organization, ApiError> {
+    use buildit_db::OrganizationRepo;
+
+    let org_id = tenant.organization_id.ok_or_else(|| {
+        ApiError::NotFound(format!("Tenant {} belongs to no organization", tenant.slug))
+    })?;
+    Ok(state
+        .organization_repo
+        .get_organization(ResourceId::from_uuid(org_id))
+        .await?)
+}
+
 fn format_time_ago(time: chrono::DateTime<chrono::Utc>) -> String {
     let now = chrono::Utc::now();
     let duration = now.signed_duration_since(time);
@@ -2259,6 +4489,52 @@ fn format_time_ago(time: chrono::DateTime<chrono::Utc>) -> String {
     }
 }
 
+/// A duration in seconds, e.g. `1m 23s`, or `--` without one.
+/// A duration difference with its sign, e.g. `+1m 5s`.
+fn format_delta(secs: Option<f64>) -> String {
+    match secs {
+        Some(secs) if secs.round() != 0.0 => {
+            let sign = if secs > 0.0 { "+" } else { "-" };
+            format!("{}{}", sign, format_secs(Some(secs.abs())))
+        }
+        Some(_) => "±0s".to_string(),
+        None => "--".to_string(),
+    }
+}
+
+fn format_secs(secs: Option<f64>) -> String {
+    let Some(secs) = secs else {
+        return "--".to_string();
+    };
+    let secs = secs.round() as i64;
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 3600 {
+        format!("{}m {}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h {}m", secs / 3600, secs % 3600 / 60)
+    }
+}
+
+/// A rate as a percentage, e.g. `12.5%`, or `--` without one.
+fn format_rate(rate: Option<f64>) -> String {
+    match rate {
+        Some(rate) => format!("{:.1}%", rate * 100.0),
+        None => "--".to_string(),
+    }
+}
+
+/// An amount of money, with cents kept for small amounts.
+fn format_money(amount: f64, currency: &str) -> String {
+    if amount.abs() >= 100.0 {
+        format!("{:.0} {}", amount, currency)
+    } else if amount.abs() >= 0.01 || amount == 0.0 {
+        format!("{:.2} {}", amount, currency)
+    } else {
+        format!("{:.4} {}", amount, currency)
+    }
+}
+
 fn capitalize_first(s: &str) -> String {
     let mut chars = s.chars();
     match chars.next() {
@@ -2266,3 +4542,93 @@ fn capitalize_first(s: &str) -> String {
         Some(first) => first.to_uppercase().chain(chars).collect(),
     }
 }
+
+fn format_size(bytes: i64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use buildit_db::repo::pipeline::PipelineRecord;
+
+    #[test]
+    fn test_tenant_redirect_stays_on_site() {
+        assert_eq!(
+            tenant_redirect("pipelines", Some("page=2")).as_deref(),
+            Some("/pipelines?page=2")
+        );
+        assert_eq!(tenant_redirect("", None).as_deref(), Some("/"));
+        assert_eq!(tenant_redirect("/runs", None).as_deref(), Some("/runs"));
+        for rest in ["//evil.com", "\\evil.com", "/\\evil.com", "runs\\..\\x"] {
+            assert_eq!(tenant_redirect(rest, None), None, "{}", rest);
+        }
+    }
+
+    #[test]
+    fn test_durations_read_in_the_largest_units() {
+        assert_eq!(format_secs(None), "--");
+        assert_eq!(format_secs(Some(0.4)), "0s");
+        assert_eq!(format_secs(Some(59.6)), "1m 0s");
+        assert_eq!(format_secs(Some(83.0)), "1m 23s");
+        assert_eq!(format_secs(Some(7_380.0)), "2h 3m");
+    }
+
+    #[test]
+    fn test_pipeline_rows_show_their_summary() {
+        let pipeline = PipelineRecord {
+            id: uuid::Uuid::new_v4(),
+            tenant_id: uuid::Uuid::new_v4(),
+            name: "api".to_string(),
+            repository: "https://github.com/acme/api.git".to_string(),
+            repository_id: None,
+            config: serde_json::json!({}),
+            labels: Vec::new(),
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+            owners: serde_json::json!({}),
+            owner_list: Vec::new(),
+            archived_at: None,
+            config_path: None,
+        };
+        let never_run = pipeline_view(PipelineSummaryRecord {
+            pipeline: pipeline.clone(),
+            last_run_id: None,
+            last_run_number: None,
+            last_run_status: None,
+            last_run_at: None,
+            total_runs: 0,
+            success_rate: None,
+            p50_duration_secs: None,
+        });
+        assert_eq!(never_run.last_run_id, "");
+        assert_eq!(never_run.success_rate, 0);
+        assert_eq!(never_run.avg_duration, "--");
+
+        let run_id = uuid::Uuid::new_v4();
+        let busy = pipeline_view(PipelineSummaryRecord {
+            pipeline,
+            last_run_id: Some(run_id),
+            last_run_number: Some(12),
+            last_run_status: Some("failed".to_string()),
+            last_run_at: Some(chrono::Utc::now()),
+            total_runs: 12,
+            success_rate: Some(0.8756),
+            p50_duration_secs: Some(95.0),
+        });
+        assert_eq!(busy.last_run_id, run_id.to_string());
+        assert_eq!(busy.last_run_number, 12);
+        assert_eq!(busy.success_rate, 88);
+        assert_eq!(busy.avg_duration, "1m 35s");
+    }
+}
@@ -0,0 +1,270 @@
+//! Variable group endpoints for the tenant the request acts in.
+//!
+//! Groups without a `pipeline_id` apply to every pipeline of the tenant and
+//! need `tenant.manage` to change; a pipeline's groups need `pipeline.write`
+//! on it.
+
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    routing::get,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::audit::AuditBefore;
+use crate::auth::AuthContext;
+use crate::error::ApiError;
+use buildit_config::env::is_valid_name;
+use buildit_core::ResourceId;
+use buildit_core::rbac::Permission;
+use buildit_db::{PipelineRepo, VariableGroup, VariableGroupRepo};
+
+#[derive(OpenApi)]
+#[openapi(paths(list_groups, get_group, create_group, update_group, delete_group))]
+pub struct ApiDoc;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list_groups).post(create_group))
+        .route(
+            "/{id}",
+            get(get_group).put(update_group).delete(delete_group),
+        )
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ListQuery {
+    /// List the pipeline's groups instead of the tenant's.
+    pub pipeline_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateGroupRequest {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Make the group the pipeline's rather than the whole tenant's.
+    #[serde(default)]
+    pub pipeline_id: Option<Uuid>,
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+}
+
+/// Changes to a group; fields left out keep their value.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct UpdateGroupRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// Replaces all of the group's variables.
+    pub variables: Option<HashMap<String, String>>,
+}
+
+/// The tenant's variable groups, or a pipeline's.
+#[utoipa::path(
+    get,
+    path = "/",
+    params(ListQuery),
+    responses((status = 200, description = "Groups", body = Vec<VariableGroup>))
+)]
+async fn list_groups(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Query(query): Query<ListQuery>,
+) -> Result<Json<Vec<VariableGroup>>, ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    let pipeline_id = query.pipeline_id;
+    authorize(&state, &auth, tenant.id, pipeline_id, false).await?;
+    let groups = state
+        .variable_group_repo
+        .list_groups(
+            ResourceId::from_uuid(tenant.id),
+            pipeline_id.map(ResourceId::from_uuid),
+        )
+        .await?;
+    Ok(Json(groups))
+}
+
+#[utoipa::path(
+    get,
+    path = "/{id}",
+    params(("id" = Uuid, Path, description = "Group ID")),
+    responses((status = 200, description = "The group", body = VariableGroup))
+)]
+async fn get_group(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<VariableGroup>, ApiError> {
+    let group = authorized_group(&state, &auth, id, false).await?;
+    Ok(Json(group))
+}
+
+/// Add a group to the tenant or one of its pipelines.
+#[utoipa::path(
+    post,
+    path = "/",
+    request_body = CreateGroupRequest,
+    responses((status = 200, description = "The group", body = VariableGroup))
+)]
+async fn create_group(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Json(req): Json<CreateGroupRequest>,
+) -> Result<Json<VariableGroup>, ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    authorize(&state, &auth, tenant.id, req.pipeline_id, true).await?;
+
+    let name = validate_name(&req.name)?;
+    validate_variables(req.variables.keys())?;
+    let now = chrono::Utc::now();
+    let group = state
+        .variable_group_repo
+        .create_group(&VariableGroup {
+            id: Uuid::now_v7(),
+            tenant_id: tenant.id,
+            pipeline_id: req.pipeline_id,
+            name,
+            description: req.description.filter(|d| !d.trim().is_empty()),
+            variables: serde_json::to_value(&req.variables).unwrap_or_default(),
+            created_by: auth.user_id,
+            created_at: now,
+            updated_at: now,
+        })
+        .await?;
+    tracing::info!(tenant = %tenant.slug, group = %group.name, pipeline_id = ?group.pipeline_id, "Variable group created");
+    Ok(Json(group))
+}
+
+/// Rename a group, or change its description or variables.
+#[utoipa::path(
+    put,
+    path = "/{id}",
+    params(("id" = Uuid, Path, description = "Group ID")),
+    request_body = UpdateGroupRequest,
+    responses((status = 200, description = "The group", body = VariableGroup))
+)]
+async fn update_group(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    Json(req): Json<UpdateGroupRequest>,
+) -> Result<(AuditBefore, Json<VariableGroup>), ApiError> {
+    let mut group = authorized_group(&state, &auth, id, true).await?;
+    let before = AuditBefore::of(&group);
+
+    if let Some(name) = req.name {
+        group.name = validate_name(&name)?;
+    }
+    if let Some(description) = req.description {
+        group.description = Some(description).filter(|d| !d.trim().is_empty());
+    }
+    if let Some(variables) = req.variables {
+        validate_variables(variables.keys())?;
+        group.variables = serde_json::to_value(&variables).unwrap_or_default();
+    }
+
+    let group = state.variable_group_repo.update_group(&group).await?;
+    Ok((before, Json(group)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/{id}",
+    params(("id" = Uuid, Path, description = "Group ID")),
+    responses((status = 200, description = "Deleted"))
+)]
+async fn delete_group(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<(AuditBefore, ()), ApiError> {
+    let group = authorized_group(&state, &auth, id, true).await?;
+    state
+        .variable_group_repo
+        .delete_group(
+            ResourceId::from_uuid(group.tenant_id),
+            ResourceId::from_uuid(group.id),
+        )
+        .await?;
+    Ok((AuditBefore::of(&group), ()))
+}
+
+/// Check the caller may read, or with `write` change, the groups of the
+/// tenant or of its pipeline `pipeline_id`.
+async fn authorize(
+    state: &AppState,
+    auth: &AuthContext,
+    tenant_id: Uuid,
+    pipeline_id: Option<Uuid>,
+    write: bool,
+) -> Result<(), ApiError> {
+    match pipeline_id {
+        Some(pipeline_id) => {
+            let pipeline = state
+                .pipeline_repo
+                .get_by_id(ResourceId::from_uuid(pipeline_id))
+                .await?;
+            if pipeline.tenant_id != tenant_id {
+                return Err(ApiError::NotFound(format!(
+                    "Pipeline {} not found",
+                    pipeline_id
+                )));
+            }
+            let permission = if write {
+                Permission::PipelineWrite
+            } else {
+                Permission::PipelineRead
+            };
+            auth.require(state, tenant_id, permission).await
+        }
+        None => {
+            let permission = if write {
+                Permission::TenantManage
+            } else {
+                Permission::TenantRead
+            };
+            auth.require(state, tenant_id, permission).await
+        }
+    }
+}
+
+async fn authorized_group(
+    state: &AppState,
+    auth: &AuthContext,
+    id: Uuid,
+    write: bool,
+) -> Result<VariableGroup, ApiError> {
+    let tenant = auth.tenant(state).await?;
+    let group = state
+        .variable_group_repo
+        .get_group(ResourceId::from_uuid(tenant.id), ResourceId::from_uuid(id))
+        .await?;
+    authorize(state, auth, tenant.id, group.pipeline_id, write).await?;
+    Ok(group)
+}
+
+fn validate_name(name: &str) -> Result<String, ApiError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(ApiError::BadRequest(
+            "Variable group name is required".to_string(),
+        ));
+    }
+    Ok(name.to_string())
+}
+
+pub(crate) fn validate_variables<'a>(
+    mut names: impl Iterator<Item = &'a String>,
+) -> Result<(), ApiError> {
+    match names.find(|name| !is_valid_name(name)) {
+        Some(name) => Err(ApiError::BadRequest(format!(
+            "'{}' is not a valid variable name; use letters, digits and underscores, not starting with a digit",
+            name
+        ))),
+        None => Ok(()),
+    }
+}
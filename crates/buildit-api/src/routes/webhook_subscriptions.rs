@@ -0,0 +1,423 @@
+//! Outbound webhook subscription endpoints for the tenant the request acts
+//! in.
+
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    routing::{get, post},
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::audit::AuditBefore;
+use crate::auth::AuthContext;
+use crate::error::ApiError;
+use crate::services::notifications;
+use crate::services::webhook_subscriptions::{self, EVENTS};
+use buildit_core::ResourceId;
+use buildit_core::rbac::Permission;
+use buildit_db::{WebhookDelivery, WebhookSubscription, WebhookSubscriptionRepo};
+
+/// Deliveries listed when the query sets no limit.
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 500;
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    list_events,
+    list_subscriptions,
+    get_subscription,
+    create_subscription,
+    update_subscription,
+    delete_subscription,
+    ping_subscription,
+    list_deliveries,
+    get_delivery,
+    redeliver_delivery
+))]
+pub struct ApiDoc;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/events", get(list_events))
+        .route("/", get(list_subscriptions).post(create_subscription))
+        .route(
+            "/{id}",
+            get(get_subscription)
+                .put(update_subscription)
+                .delete(delete_subscription),
+        )
+        .route("/{id}/ping", post(ping_subscription))
+        .route("/{id}/deliveries", get(list_deliveries))
+        .route("/{id}/deliveries/{delivery_id}", get(get_delivery))
+        .route(
+            "/{id}/deliveries/{delivery_id}/redeliver",
+            post(redeliver_delivery),
+        )
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateSubscriptionRequest {
+    pub url: String,
+    pub description: Option<String>,
+    pub events: Vec<String>,
+    #[serde(default = "enabled_default")]
+    pub enabled: bool,
+}
+
+fn enabled_default() -> bool {
+    true
+}
+
+/// Changes to a subscription; fields left out keep their value.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct UpdateSubscriptionRequest {
+    pub url: Option<String>,
+    pub description: Option<String>,
+    pub events: Option<Vec<String>>,
+    pub enabled: Option<bool>,
+    /// Replace the signing secret.
+    #[serde(default)]
+    pub rotate_secret: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SubscriptionResponse {
+    #[serde(flatten)]
+    pub subscription: WebhookSubscription,
+    /// The signing secret, shown only when it is created or rotated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DeliveriesQuery {
+    limit: Option<i64>,
+}
+
+/// Events subscriptions can receive.
+#[utoipa::path(
+    get,
+    path = "/events",
+    operation_id = "list_webhook_events",
+    responses((status = 200, description = "Event names", body = Vec<String>))
+)]
+async fn list_events() -> Json<&'static [&'static str]> {
+    Json(EVENTS)
+}
+
+#[utoipa::path(
+    get,
+    path = "",
+    responses((status = 200, description = "Subscriptions", body = Vec<WebhookSubscription>))
+)]
+async fn list_subscriptions(
+    State(state): State<AppState>,
+    auth: AuthContext,
+) -> Result<Json<Vec<WebhookSubscription>>, ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::TenantRead)
+        .await?;
+    let subscriptions = state
+        .webhook_subscription_repo
+        .list_subscriptions(ResourceId::from_uuid(tenant.id))
+        .await?;
+    Ok(Json(subscriptions))
+}
+
+#[utoipa::path(
+    get,
+    path = "/{id}",
+    params(("id" = Uuid, Path, description = "Subscription ID")),
+    responses((status = 200, description = "The subscription", body = WebhookSubscription))
+)]
+async fn get_subscription(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<WebhookSubscription>, ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::TenantRead)
+        .await?;
+    let subscription = state
+        .webhook_subscription_repo
+        .get_subscription(ResourceId::from_uuid(tenant.id), ResourceId::from_uuid(id))
+        .await?;
+    Ok(Json(subscription))
+}
+
+/// Register an endpoint. Its signing secret is returned once.
+#[utoipa::path(
+    post,
+    path = "",
+    request_body = CreateSubscriptionRequest,
+    responses((status = 200, description = "The subscription and its signing secret", body = SubscriptionResponse))
+)]
+async fn create_subscription(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Json(req): Json<CreateSubscriptionRequest>,
+) -> Result<Json<SubscriptionResponse>, ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::TenantManage)
+        .await?;
+
+    let url = validate_url(&req.url)?;
+    let events = validate_events(req.events)?;
+    let secret = notifications::new_secret();
+
+    let subscription = state
+        .webhook_subscription_repo
+        .create_subscription(&WebhookSubscription {
+            id: Uuid::now_v7(),
+            tenant_id: tenant.id,
+            url,
+            description: description(req.description),
+            secret: secret.clone(),
+            events,
+            enabled: req.enabled,
+            created_by: auth.user_id,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        })
+        .await?;
+    tracing::info!(tenant = %tenant.slug, subscription = %subscription.id, url = %subscription.url, "Webhook subscription created");
+    Ok(Json(SubscriptionResponse {
+        subscription,
+        secret: Some(secret),
+    }))
+}
+
+#[utoipa::path(
+    put,
+    path = "/{id}",
+    params(("id" = Uuid, Path, description = "Subscription ID")),
+    request_body = UpdateSubscriptionRequest,
+    responses((status = 200, description = "The subscription", body = SubscriptionResponse))
+)]
+async fn update_subscription(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    Json(req): Json<UpdateSubscriptionRequest>,
+) -> Result<(AuditBefore, Json<SubscriptionResponse>), ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::TenantManage)
+        .await?;
+    let mut subscription = state
+        .webhook_subscription_repo
+        .get_subscription(ResourceId::from_uuid(tenant.id), ResourceId::from_uuid(id))
+        .await?;
+    let before = AuditBefore::of(&subscription);
+
+    if let Some(url) = req.url {
+        subscription.url = validate_url(&url)?;
+    }
+    if req.description.is_some() {
+        subscription.description = description(req.description);
+    }
+    if let Some(events) = req.events {
+        subscription.events = validate_events(events)?;
+    }
+    if let Some(enabled) = req.enabled {
+        subscription.enabled = enabled;
+    }
+    let mut secret = None;
+    if req.rotate_secret {
+        subscription.secret = notifications::new_secret();
+        secret = Some(subscription.secret.clone());
+    }
+
+    let subscription = state
+        .webhook_subscription_repo
+        .update_subscription(&subscription)
+        .await?;
+    Ok((
+        before,
+        Json(SubscriptionResponse {
+            subscription,
+            secret,
+        }),
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/{id}",
+    params(("id" = Uuid, Path, description = "Subscription ID")),
+    responses((status = 200, description = "Deleted"))
+)]
+async fn delete_subscription(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<(), ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::TenantManage)
+        .await?;
+    state
+        .webhook_subscription_repo
+        .delete_subscription(ResourceId::from_uuid(tenant.id), ResourceId::from_uuid(id))
+        .await?;
+    Ok(())
+}
+
+/// Queue a `ping` delivery to the endpoint.
+#[utoipa::path(
+    post,
+    path = "/{id}/ping",
+    params(("id" = Uuid, Path, description = "Subscription ID")),
+    responses((status = 200, description = "The queued delivery", body = WebhookDelivery))
+)]
+async fn ping_subscription(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+) -> Result<Json<WebhookDelivery>, ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::TenantManage)
+        .await?;
+    let subscription = state
+        .webhook_subscription_repo
+        .get_subscription(ResourceId::from_uuid(tenant.id), ResourceId::from_uuid(id))
+        .await?;
+    let delivery = webhook_subscriptions::ping(&state, &subscription).await?;
+    Ok(Json(delivery))
+}
+
+/// The subscription's most recent deliveries, newest first.
+#[utoipa::path(
+    get,
+    path = "/{id}/deliveries",
+    params(("id" = Uuid, Path, description = "Subscription ID"), DeliveriesQuery),
+    responses((status = 200, description = "Deliveries", body = Vec<WebhookDelivery>))
+)]
+async fn list_deliveries(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path(id): Path<Uuid>,
+    Query(query): Query<DeliveriesQuery>,
+) -> Result<Json<Vec<WebhookDelivery>>, ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::TenantRead)
+        .await?;
+    let subscription = state
+        .webhook_subscription_repo
+        .get_subscription(ResourceId::from_uuid(tenant.id), ResourceId::from_uuid(id))
+        .await?;
+    let deliveries = state
+        .webhook_subscription_repo
+        .list_deliveries(
+            ResourceId::from_uuid(subscription.id),
+            query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+        )
+        .await?;
+    Ok(Json(deliveries))
+}
+
+#[utoipa::path(
+    get,
+    path = "/{id}/deliveries/{delivery_id}",
+    params(("id" = Uuid, Path, description = "Subscription ID"), ("delivery_id" = Uuid, Path, description = "Delivery ID")),
+    responses((status = 200, description = "The delivery", body = WebhookDelivery))
+)]
+async fn get_delivery(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((id, delivery_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<WebhookDelivery>, ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::TenantRead)
+        .await?;
+    let delivery = subscription_delivery(&state, tenant.id, id, delivery_id).await?;
+    Ok(Json(delivery))
+}
+
+/// Send a delivery's payload again, as a new delivery.
+#[utoipa::path(
+    post,
+    path = "/{id}/deliveries/{delivery_id}/redeliver",
+    params(("id" = Uuid, Path, description = "Subscription ID"), ("delivery_id" = Uuid, Path, description = "Delivery ID")),
+    responses((status = 200, description = "The new delivery", body = WebhookDelivery))
+)]
+async fn redeliver_delivery(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Path((id, delivery_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<WebhookDelivery>, ApiError> {
+    let tenant = auth.tenant(&state).await?;
+    auth.require(&state, tenant.id, Permission::TenantManage)
+        .await?;
+    let subscription = state
+        .webhook_subscription_repo
+        .get_subscription(ResourceId::from_uuid(tenant.id), ResourceId::from_uuid(id))
+        .await?;
+    if !subscription.enabled {
+        return Err(ApiError::BadRequest(
+            "Enable the subscription before redelivering".to_string(),
+        ));
+    }
+    let delivery = subscription_delivery(&state, tenant.id, id, delivery_id).await?;
+    let redelivery = webhook_subscriptions::redeliver(&state, &subscription, &delivery).await?;
+    Ok(Json(redelivery))
+}
+
+/// A delivery of the subscription `id`, which must belong to the tenant.
+async fn subscription_delivery(
+    state: &AppState,
+    tenant_id: Uuid,
+    id: Uuid,
+    delivery_id: Uuid,
+) -> Result<WebhookDelivery, ApiError> {
+    let delivery = state
+        .webhook_subscription_repo
+        .get_delivery(
+            ResourceId::from_uuid(tenant_id),
+            ResourceId::from_uuid(delivery_id),
+        )
+        .await?;
+    if delivery.subscription_id != id {
+        return Err(ApiError::NotFound(format!(
+            "webhook delivery {}",
+            delivery_id
+        )));
+    }
+    Ok(delivery)
+}
+
+fn validate_url(url: &str) -> Result<String, ApiError> {
+    let url = url.trim();
+    if !(url.starts_with("https://") || url.starts_with("http://")) {
+        return Err(ApiError::BadRequest(
+            "Webhook URL must start with http:// or https://".to_string(),
+        ));
+    }
+    Ok(url.to_string())
+}
+
+fn description(description: Option<String>) -> Option<String> {
+    description
+        .map(|d| d.trim().to_string())
+        .filter(|d| !d.is_empty())
+}
+
+fn validate_events(mut events: Vec<String>) -> Result<Vec<String>, ApiError> {
+    if events.is_empty() {
+        return Err(ApiError::BadRequest(
+            "Subscribe to at least one event".to_string(),
+        ));
+    }
+    if let Some(unknown) = events.iter().find(|e| !EVENTS.contains(&e.as_str())) {
+        return Err(ApiError::BadRequest(format!(
+            "Unknown event '{}'; expected one of {}",
+            unknown,
+            EVENTS.join(", ")
+        )));
+    }
+    events.sort();
+    events.dedup();
+    Ok(events)
+}
@@ -1,24 +1,32 @@
-//! Webhook endpoints for Git providers.
+//! Webhook endpoints for Git providers, and Slack's under `/slack`.
 
+use axum::Router;
 use axum::body::Bytes;
 use axum::extract::{Path, State};
 use axum::http::{HeaderMap, StatusCode};
 use axum::routing::post;
-use axum::{Json, Router};
 use hmac::{Hmac, Mac};
 use sha2::Sha256;
-use tracing::{error, info, warn};
+use std::time::Instant;
+use tracing::{Span, error, info, warn};
 
 use crate::AppState;
 use crate::error::ApiError;
+use crate::routes::slack;
+use crate::services::metrics;
+use crate::services::tasks::{self, Task};
+use buildit_config::condition::{TriggerEvent, glob_match, trigger_matches};
 use buildit_core::ResourceId;
+use buildit_core::pipeline::Trigger;
 use buildit_core::repository::{GitProvider, PushEvent};
+use buildit_db::repo::pipeline::PipelineRecord;
 use buildit_db::{PipelineRepo, RepositoryRepo};
 
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/github", post(github_webhook))
         .route("/github/{repo_id}", post(github_webhook_with_id))
+        .nest("/slack", slack::router())
 }
 
 /// Handle GitHub webhook events.
@@ -40,17 +48,25 @@ async fn github_webhook_with_id(
     process_github_webhook(state, headers, body, Some(repo_id)).await
 }
 
+#[tracing::instrument(name = "webhook.receive", skip_all, fields(provider = "github", event))]
 async fn process_github_webhook(
     state: AppState,
     headers: HeaderMap,
     body: Bytes,
     repo_id: Option<uuid::Uuid>,
 ) -> Result<StatusCode, ApiError> {
+    let received = Instant::now();
+
     // Get event type
     let event_type = headers
         .get("X-GitHub-Event")
         .and_then(|v| v.to_str().ok())
         .unwrap_or("unknown");
+    Span::current().record("event", event_type);
+
+    let delivery = headers
+        .get("X-GitHub-Delivery")
+        .and_then(|v| v.to_str().ok());
 
     // Get signature
     let signature = headers
@@ -95,7 +111,7 @@ async fn process_github_webhook(
     // Store the webhook event
     let headers_json = serde_json::json!({
         "event": event_type,
-        "delivery": headers.get("X-GitHub-Delivery").and_then(|v| v.to_str().ok()),
+        "delivery": delivery,
     });
 
     let webhook_event = state
@@ -126,6 +142,25 @@ async fn process_github_webhook(
         }
     }
 
+    // Providers redeliver events they think failed; a delivery that was
+    // already processed is acknowledged without acting on it again. Only
+    // looked up once the signature holds, so unsigned requests cannot probe
+    // for delivery IDs.
+    if let Some(delivery) = delivery
+        && state
+            .repository_repo
+            .webhook_delivery_processed(GitProvider::Github, delivery)
+            .await?
+    {
+        info!(event = %event_type, delivery = %delivery, "Ignoring redelivered webhook");
+        state
+            .repository_repo
+            .mark_webhook_processed(ResourceId::from_uuid(webhook_event.id), None)
+            .await?;
+        metrics::record_webhook("github", event_type, received.elapsed());
+        return Ok(StatusCode::OK);
+    }
+
     // Process the event
     match event_type {
         "push" => {
@@ -150,6 +185,7 @@ async fn process_github_webhook(
         .repository_repo
         .mark_webhook_processed(ResourceId::from_uuid(webhook_event.id), None)
         .await?;
+    metrics::record_webhook("github", event_type, received.elapsed());
 
     Ok(StatusCode::OK)
 }
@@ -175,7 +211,19 @@ async fn handle_push_event(
         "Processing push event"
     );
 
-    // Find pipelines linked to this repository
+    // Services deployed straight from manifests in this repository
+    if state.clusters.available() {
+        tasks::enqueue(
+            state,
+            Task::ManifestPush {
+                repository_id: repo.id,
+                push: Box::new(push_event.clone()),
+            },
+        )
+        .await?;
+    }
+
+    // Find active pipelines linked to this repository; archived ones are never triggered
     let pipelines = state
         .pipeline_repo
         .list_by_repository(ResourceId::from_uuid(repo.id))
@@ -192,15 +240,35 @@ async fn handle_push_event(
         "Found pipelines to trigger"
     );
 
+    // Files touched by the push, kept for condition simulation
+    let mut changed_files: Vec<&String> = push_event
+        .commits
+        .iter()
+        .flat_map(|c| c.added.iter().chain(&c.modified).chain(&c.removed))
+        .collect();
+    changed_files.sort();
+    changed_files.dedup();
+
     // Build git info for the run
     let git_info = serde_json::json!({
         "sha": push_event.after,
         "short_sha": &push_event.after[..7.min(push_event.after.len())],
         "branch": push_event.branch,
+        "tag": push_event.tag,
         "ref": push_event.r#ref,
         "message": push_event.head_commit.as_ref().map(|c| &c.message),
         "author": push_event.head_commit.as_ref().map(|c| &c.author),
         "repository": push_event.repository_full_name,
+        "changed_files": changed_files,
+        "commits": push_event
+            .commits
+            .iter()
+            .map(|c| serde_json::json!({
+                "sha": c.sha,
+                "message": c.message,
+                "author": c.author,
+            }))
+            .collect::<Vec<_>>(),
     });
 
     // Build trigger info
@@ -210,40 +278,40 @@ async fn handle_push_event(
         "ref": push_event.r#ref,
     });
 
+    // The push as a trigger event, for triggers parsed from pipeline configs
+    let event = TriggerEvent {
+        kind: if push_event.tag.is_some() {
+            "tag"
+        } else {
+            "push"
+        }
+        .to_string(),
+        branch: push_event.branch.clone(),
+        tag: push_event.tag.clone(),
+        actor: Some(push_event.pusher.clone()),
+        changed_files: Some(changed_files.iter().map(|f| f.to_string()).collect()),
+    };
+
+    // The same push always maps to the same runs, however often it is
+    // delivered
+    let idempotency_key = format!("push:{}:{}", push_event.r#ref, push_event.after);
+
     // Trigger each pipeline
     for pipeline in pipelines {
-        // Check if pipeline has trigger configuration
-        let config = &pipeline.config;
-        let triggers = config.get("triggers").and_then(|t| t.as_array());
-
         // Check if this push matches any trigger conditions
-        let should_trigger = match triggers {
-            Some(triggers) => {
-                triggers.iter().any(|trigger| {
-                    match trigger.get("type").and_then(|t| t.as_str()) {
-                        Some("push") => {
-                            // Check branch filter if present
-                            if let Some(branches) =
-                                trigger.get("branches").and_then(|b| b.as_array())
-                            {
-                                let branch_patterns: Vec<&str> =
-                                    branches.iter().filter_map(|b| b.as_str()).collect();
-
-                                if let Some(ref branch) = push_event.branch {
-                                    matches_branch_pattern(branch, &branch_patterns)
-                                } else {
-                                    false
-                                }
-                            } else {
-                                // No branch filter means trigger on all branches
-                                true
-                            }
-                        }
-                        _ => false,
-                    }
-                })
-            }
-            None => {
+        let triggers = pipeline.config.get("triggers");
+        let parsed = triggers
+            .and_then(|t| serde_json::from_value::<Vec<Trigger>>(t.clone()).ok())
+            .filter(|t| !t.is_empty());
+        let should_trigger = match (parsed, triggers) {
+            // Triggers of pipelines synced from the repository's config files
+            (Some(parsed), _) => trigger_matches(&parsed, &event),
+            (None, Some(triggers)) => triggers.as_array().is_some_and(|triggers| {
+                triggers
+                    .iter()
+                    .any(|trigger| push_matches(trigger, &push_event, &changed_files))
+            }),
+            (None, None) => {
                 // No triggers configured - default to triggering on all pushes to default branch
                 push_event.branch.as_deref() == Some(&repo.default_branch)
             }
@@ -253,43 +321,125 @@ async fn handle_push_event(
             info!(
                 pipeline = %pipeline.name,
                 branch = ?push_event.branch,
+                tag = ?push_event.tag,
                 "Pipeline trigger conditions not met, skipping"
             );
             continue;
         }
 
-        // Create a pipeline run
-        match state
-            .pipeline_repo
-            .create_run(
-                ResourceId::from_uuid(pipeline.id),
-                trigger_info.clone(),
-                git_info.clone(),
-            )
-            .await
-        {
-            Ok(run) => {
-                info!(
-                    pipeline = %pipeline.name,
-                    run_id = %run.id,
-                    run_number = run.number,
-                    "Created pipeline run from webhook"
-                );
+        trigger_run(
+            state,
+            &pipeline,
+            &idempotency_key,
+            trigger_info.clone(),
+            git_info.clone(),
+        )
+        .await;
+    }
 
-                // TODO: Queue the run for execution via the orchestrator
-                // For now, just mark it as queued (which is the default)
-            }
-            Err(e) => {
+    Ok(())
+}
+
+/// Create a run of a pipeline for a push and queue it, unless the push
+/// already created one.
+#[tracing::instrument(name = "run.create", skip_all, fields(pipeline_id = %pipeline.id, run_id))]
+async fn trigger_run(
+    state: &AppState,
+    pipeline: &PipelineRecord,
+    idempotency_key: &str,
+    trigger_info: serde_json::Value,
+    git_info: serde_json::Value,
+) {
+    match state
+        .pipeline_repo
+        .create_run_once(
+            ResourceId::from_uuid(pipeline.id),
+            idempotency_key,
+            trigger_info,
+            git_info,
+        )
+        .await
+    {
+        Ok((run, false)) => {
+            Span::current().record("run_id", tracing::field::display(run.id));
+            info!(
+                pipeline = %pipeline.name,
+                run_id = %run.id,
+                run_number = run.number,
+                "Pipeline run already exists for this push"
+            );
+        }
+        Ok((run, true)) => {
+            Span::current().record("run_id", tracing::field::display(run.id));
+            info!(
+                pipeline = %pipeline.name,
+                run_id = %run.id,
+                run_number = run.number,
+                "Created pipeline run from webhook"
+            );
+
+            if let Err(e) = tasks::enqueue_run(state, &run).await {
                 error!(
                     pipeline = %pipeline.name,
-                    error = %e,
-                    "Failed to create pipeline run"
+                    run_id = %run.id,
+                    error = ?e,
+                    "Failed to queue pipeline run"
                 );
             }
         }
+        Err(e) => {
+            error!(
+                pipeline = %pipeline.name,
+                error = %e,
+                "Failed to create pipeline run"
+            );
+        }
     }
+}
 
-    Ok(())
+/// Whether a push matches a trigger of a pipeline created through the API.
+fn push_matches(
+    trigger: &serde_json::Value,
+    push_event: &PushEvent,
+    changed_files: &[&String],
+) -> bool {
+    match trigger.get("type").and_then(|t| t.as_str()) {
+        Some("push") => {
+            // Check branch filter if present; no filter means all branches
+            let branch_matches = match trigger.get("branches").and_then(|b| b.as_array()) {
+                Some(branches) => {
+                    let branch_patterns: Vec<&str> =
+                        branches.iter().filter_map(|b| b.as_str()).collect();
+                    push_event
+                        .branch
+                        .as_deref()
+                        .is_some_and(|branch| matches_branch_pattern(branch, &branch_patterns))
+                }
+                None => true,
+            };
+            // Check path filter if present; a push has to touch a matching file
+            let paths_match = trigger
+                .get("paths")
+                .and_then(|p| p.as_array())
+                .is_none_or(|paths| {
+                    paths.iter().filter_map(|p| p.as_str()).any(|pattern| {
+                        changed_files
+                            .iter()
+                            .any(|file| glob_match(pattern, file, true))
+                    })
+                });
+            branch_matches && paths_match
+        }
+        Some("tag") => match &push_event.tag {
+            // No pattern means trigger on all tags
+            Some(tag) => trigger
+                .get("pattern")
+                .and_then(|p| p.as_str())
+                .is_none_or(|pattern| glob_match(pattern, tag, false)),
+            None => false,
+        },
+        _ => false,
+    }
 }
 
 /// Check if a branch name matches any of the given patterns.
@@ -302,10 +452,10 @@ fn matches_branch_pattern(branch: &str, patterns: &[&str]) -> bool {
             if parts.len() == 2 {
                 let (prefix, suffix) = (parts[0], parts[1]);
                 branch.starts_with(prefix) && branch.ends_with(suffix)
-            } else if pattern.starts_with('*') {
-                branch.ends_with(&pattern[1..])
-            } else if pattern.ends_with('*') {
-                branch.starts_with(&pattern[..pattern.len() - 1])
+            } else if let Some(suffix) = pattern.strip_prefix('*') {
+                branch.ends_with(suffix)
+            } else if let Some(prefix) = pattern.strip_suffix('*') {
+                branch.starts_with(prefix)
             } else {
                 // Complex glob - fall back to exact match
                 branch == *pattern
@@ -0,0 +1,361 @@
+//! Application (GitOps) sync engine.
+//!
+//! A sync renders an application's manifests from its repository, either
+//! the plain manifest directory at `path` or a Helm chart, applies them
+//! through the deployer of its environment's cluster, and records the tracked resources and the digest
+//! of what was rendered. Comparing a fresh render against that digest tells
+//! whether git has moved on since the last sync.
+
+use buildit_core::ResourceId;
+use buildit_core::application::{
+    Application, ApplicationSync, ApplicationSyncStatus, HealthStatus, ResourceStatus, SyncStatus,
+};
+use buildit_core::deployer::{
+    DeploymentResources, DeploymentSource, DeploymentSpec, DeploymentStrategy,
+};
+use buildit_db::{
+    ApplicationRepo, DeploymentRepo, PgApplicationRepo, PgDeploymentRepo, PgRepositoryRepo,
+    RepositoryRepo,
+};
+use buildit_deployer::manifests;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+use tracing::{error, info};
+
+use super::clusters::Clusters;
+use super::git::{CheckoutOptions, GitService};
+use super::helm::HelmService;
+
+/// Manifests rendered for an application.
+#[derive(Debug, Clone)]
+pub struct RenderedManifests {
+    /// Multi-document YAML.
+    pub documents: String,
+    /// `sha256:<hex>` of `documents`.
+    pub digest: String,
+    /// Git revision the manifests were rendered from.
+    pub revision: String,
+}
+
+/// `sha256:<hex>` digest of rendered manifests.
+pub fn manifest_digest(documents: &str) -> String {
+    format!(
+        "sha256:{}",
+        hex::encode(Sha256::digest(documents.as_bytes()))
+    )
+}
+
+/// Renders and applies applications.
+pub struct ApplicationSyncService {
+    application_repo: Arc<PgApplicationRepo>,
+    repository_repo: Arc<PgRepositoryRepo>,
+    deployment_repo: Arc<PgDeploymentRepo>,
+    clusters: Arc<Clusters>,
+    git_service: GitService,
+    helm_service: HelmService,
+}
+
+impl ApplicationSyncService {
+    pub fn new(
+        application_repo: Arc<PgApplicationRepo>,
+        repository_repo: Arc<PgRepositoryRepo>,
+        deployment_repo: Arc<PgDeploymentRepo>,
+        clusters: Arc<Clusters>,
+    ) -> Self {
+        Self {
+            application_repo,
+            repository_repo,
+            deployment_repo,
+            clusters,
+            git_service: GitService::new(),
+            helm_service: HelmService::new(),
+        }
+    }
+
+    /// Render an application's manifests from the head of its repository.
+    pub async fn render(&self, app: &Application) -> Result<RenderedManifests, SyncError> {
+        let repository_id = app.repository_id.ok_or_else(|| {
+            SyncError::InvalidApplication(format!("{} has no repository", app.name))
+        })?;
+        let repository = self
+            .repository_repo
+            .get_by_id(ResourceId::from_uuid(repository_id))
+            .await
+            .map_err(|e| SyncError::Database(e.to_string()))?;
+        let checkout = self
+            .git_service
+            .checkout(&repository, &CheckoutOptions::default())
+            .await
+            .map_err(|e| SyncError::Render(e.to_string()))?;
+        let revision = head_revision(&checkout).await?;
+
+        let documents = match &app.helm {
+            Some(helm) => {
+                let environment = match app.environment_id {
+                    Some(id) => Some(
+                        self.deployment_repo
+                            .get_environment(ResourceId::from_uuid(id))
+                            .await
+                            .map_err(|e| SyncError::Database(e.to_string()))?
+                            .name,
+                    ),
+                    None => None,
+                };
+                self.helm_service
+                    .template(
+                        helm,
+                        &app.name,
+                        &app.target_namespace,
+                        &checkout,
+                        &app.path,
+                        environment.as_deref(),
+                    )
+                    .await
+                    .map_err(|e| SyncError::Render(e.to_string()))?
+            }
+            None => {
+                let relative = Path::new(&app.path);
+                if relative.components().any(|c| {
+                    !matches!(
+                        c,
+                        std::path::Component::Normal(_) | std::path::Component::CurDir
+                    )
+                }) {
+                    return Err(SyncError::InvalidApplication(format!(
+                        "path {:?} must stay inside the repository",
+                        app.path
+                    )));
+                }
+                manifests::read_dir(&checkout.join(relative))
+                    .map_err(|e| SyncError::Render(e.to_string()))?
+            }
+        };
+
+        Ok(RenderedManifests {
+            digest: manifest_digest(&documents),
+            documents,
+            revision,
+        })
+    }
+
+    /// Run a sync: render, apply, and record the result.
+    pub async fn run(&self, app: &Application, sync: &ApplicationSync) {
+        let sync_id = ResourceId::from_uuid(sync.id);
+        let app_id = ResourceId::from_uuid(app.id);
+
+        if let Err(e) = self.application_repo.update_sync_started(sync_id).await {
+            error!(sync_id = %sync.id, error = %e, "Failed to mark sync started");
+        }
+        let _ = self
+            .application_repo
+            .update_application_sync_status(app_id, SyncStatus::Syncing, app.health_status, None)
+            .await;
+
+        match self.sync(app, sync).await {
+            Ok((rendered, counts)) => {
+                let _ = self
+                    .application_repo
+                    .update_sync_finished(
+                        sync_id,
+                        ApplicationSyncStatus::Succeeded,
+                        counts.created,
+                        counts.updated,
+                        counts.deleted,
+                        None,
+                    )
+                    .await;
+                let _ = self
+                    .application_repo
+                    .update_application_sync_status(
+                        app_id,
+                        SyncStatus::Synced,
+                        HealthStatus::Progressing,
+                        Some(&rendered.revision),
+                    )
+                    .await;
+                info!(
+                    application = %app.name,
+                    revision = %rendered.revision,
+                    digest = %rendered.digest,
+                    "Application synced"
+                );
+            }
+            Err(e) => {
+                error!(application = %app.name, error = %e, "Application sync failed");
+                let _ = self
+                    .application_repo
+                    .update_sync_finished(
+                        sync_id,
+                        ApplicationSyncStatus::Failed,
+                        0,
+                        0,
+                        0,
+                        Some(&e.to_string()),
+                    )
+                    .await;
+                let _ = self
+                    .application_repo
+                    .update_application_sync_status(
+                        app_id,
+                        SyncStatus::OutOfSync,
+                        app.health_status,
+                        None,
+                    )
+                    .await;
+            }
+        }
+    }
+
+    async fn sync(
+        &self,
+        app: &Application,
+        sync: &ApplicationSync,
+    ) -> Result<(RenderedManifests, ResourceCounts), SyncError> {
+        let deployer = self
+            .clusters
+            .for_environment_id(app.environment_id)
+            .await
+            .map_err(SyncError::Apply)?;
+
+        let rendered = self.render(app).await?;
+
+        let spec = deployment_spec(
+            app,
+            ResourceId::from_uuid(sync.id),
+            rendered.documents.clone(),
+        );
+        deployer
+            .deploy(spec)
+            .await
+            .map_err(|e| SyncError::Apply(e.to_string()))?;
+        self.application_repo
+            .set_rendered_digest(ResourceId::from_uuid(sync.id), &rendered.digest)
+            .await
+            .map_err(|e| SyncError::Database(e.to_string()))?;
+
+        let counts = self.track_resources(app, &rendered.documents).await?;
+        Ok((rendered, counts))
+    }
+
+    /// Record the applied objects as the application's resources.
+    async fn track_resources(
+        &self,
+        app: &Application,
+        documents: &str,
+    ) -> Result<ResourceCounts, SyncError> {
+        let app_id = ResourceId::from_uuid(app.id);
+        let objects =
+            manifests::parse_documents(documents).map_err(|e| SyncError::Render(e.to_string()))?;
+        let existing: HashSet<(String, String, String)> = self
+            .application_repo
+            .list_resources(app_id)
+            .await
+            .map_err(|e| SyncError::Database(e.to_string()))?
+            .into_iter()
+            .map(|r| (r.kind, r.name, r.namespace))
+            .collect();
+
+        let mut counts = ResourceCounts::default();
+        let mut keep = Vec::with_capacity(objects.len());
+        for object in objects {
+            let gvk = manifests::gvk(&object).map_err(|e| SyncError::Render(e.to_string()))?;
+            let name = object.metadata.name.clone().unwrap_or_default();
+            let namespace = object
+                .metadata
+                .namespace
+                .clone()
+                .unwrap_or_else(|| app.target_namespace.clone());
+            let key = (gvk.kind.clone(), name.clone(), namespace.clone());
+            if existing.contains(&key) {
+                counts.updated += 1;
+            } else {
+                counts.created += 1;
+            }
+
+            self.application_repo
+                .upsert_resource(
+                    app_id,
+                    &gvk.group,
+                    &gvk.api_version(),
+                    &gvk.kind,
+                    &name,
+                    &namespace,
+                    ResourceStatus::Synced,
+                    HealthStatus::Unknown,
+                    false,
+                    serde_json::to_value(&object).ok(),
+                    None,
+                    None,
+                )
+                .await
+                .map_err(|e| SyncError::Database(e.to_string()))?;
+            keep.push(key);
+        }
+
+        counts.deleted = self
+            .application_repo
+            .delete_orphaned_resources(app_id, &keep)
+            .await
+            .map_err(|e| SyncError::Database(e.to_string()))? as i32;
+        Ok(counts)
+    }
+}
+
+/// Spec that applies rendered manifests for an application.
+pub fn deployment_spec(app: &Application, id: ResourceId, documents: String) -> DeploymentSpec {
+    DeploymentSpec {
+        id,
+        service: app.name.clone(),
+        environment: app.target_namespace.clone(),
+        image: String::new(),
+        replicas: 1,
+        env: HashMap::new(),
+        strategy: DeploymentStrategy::default(),
+        resources: DeploymentResources::default(),
+        health_check: None,
+        source: DeploymentSource::Rendered {
+            documents,
+            namespace: Some(app.target_namespace.clone()),
+        },
+    }
+}
+
+#[derive(Debug, Default)]
+struct ResourceCounts {
+    created: i32,
+    updated: i32,
+    deleted: i32,
+}
+
+async fn head_revision(checkout: &Path) -> Result<String, SyncError> {
+    let output = tokio::process::Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .current_dir(checkout)
+        .output()
+        .await
+        .map_err(|e| SyncError::Render(e.to_string()))?;
+    if !output.status.success() {
+        return Err(SyncError::Render(
+            String::from_utf8_lossy(&output.stderr).to_string(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Application sync errors.
+#[derive(Debug, thiserror::Error)]
+pub enum SyncError {
+    #[error("Database error: {0}")]
+    Database(String),
+
+    #[error("Invalid application: {0}")]
+    InvalidApplication(String),
+
+    #[error("Render failed: {0}")]
+    Render(String),
+
+    #[error("Apply failed: {0}")]
+    Apply(String),
+}
@@ -0,0 +1,739 @@
+//! Context for approvers.
+//!
+//! Pulls together what someone needs before approving a stack apply, a
+//! deployment or a pipeline gate: planned resource changes, an estimated
+//! monthly cost delta, the services affected, how often recent runs of the
+//! same thing failed, and links to the relevant diffs. A risk level with
+//! its reasons is derived from the same facts. Stack applies also carry the
+//! results of the tenant's plan policies.
+//!
+//! Cost estimates are rough. Deployments are priced from replicas and
+//! CPU/memory requests at `BUILDIT_COST_CPU_HOUR` per core-hour and
+//! `BUILDIT_COST_GIB_HOUR` per GiB-hour. Stack plans are priced from
+//! `BUILDIT_COST_RESOURCE_PRICES`, a JSON object of monthly prices keyed by
+//! Terraform resource type (e.g. `{"aws_instance": 61.0}`); without it
+//! stack applies have no cost estimate.
+
+use buildit_core::ResourceId;
+use buildit_core::repository::GitProvider;
+use buildit_core::stack::{PolicyResult, StackRun, StackRunStatus};
+use buildit_db::repo::pipeline::PipelineRunRecord;
+use buildit_db::{
+    Deployment, DeploymentRepo, Environment, PgDeploymentRepo, PgPipelineRepo, PgRepositoryRepo,
+    PgStackRepo, PipelineRepo, RepositoryRepo, StackRepo,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+use super::release_notes::is_production;
+use super::terraform::parse_plan_json;
+
+/// How many earlier runs/deployments the failure rate looks at.
+const HISTORY_WINDOW: i64 = 20;
+const HOURS_PER_MONTH: f64 = 730.0;
+const DEFAULT_CPU_HOUR: f64 = 0.0316;
+const DEFAULT_GIB_HOUR: f64 = 0.0042;
+
+/// What is being approved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalKind {
+    StackApply,
+    Deployment,
+    PipelineStage,
+}
+
+/// Everything shown alongside an approval prompt.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ApprovalContext {
+    pub kind: ApprovalKind,
+    /// Human-readable description, e.g. `api v1.4.0 → production`.
+    pub subject: String,
+    pub environment: Option<String>,
+    pub resource_changes: Option<ResourceChanges>,
+    pub cost: Option<CostDelta>,
+    pub affected_services: Vec<String>,
+    pub failure_rate: Option<FailureRate>,
+    pub links: Vec<ContextLink>,
+    /// Plan policies the stack run was checked against.
+    pub policy_results: Vec<PolicyResult>,
+    pub risk: Risk,
+}
+
+/// Planned infrastructure changes.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ResourceChanges {
+    pub add: i32,
+    pub change: i32,
+    pub destroy: i32,
+    pub resources: Vec<PlannedChange>,
+}
+
+/// One resource in a plan.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PlannedChange {
+    pub address: String,
+    pub resource_type: String,
+    /// `create`, `update`, `delete` or `replace`.
+    pub action: String,
+}
+
+/// Estimated monthly cost before and after the change.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CostDelta {
+    pub monthly_before: f64,
+    pub monthly_after: f64,
+    pub monthly_delta: f64,
+    /// How the estimate was made.
+    pub basis: String,
+}
+
+/// Failures among recent finished runs of the same thing.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FailureRate {
+    pub total: usize,
+    pub failed: usize,
+    pub rate: f64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ContextLink {
+    pub label: String,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskLevel {
+    Low,
+    Medium,
+    High,
+}
+
+impl std::fmt::Display for RiskLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RiskLevel::Low => write!(f, "low"),
+            RiskLevel::Medium => write!(f, "medium"),
+            RiskLevel::High => write!(f, "high"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Risk {
+    pub level: RiskLevel,
+    pub reasons: Vec<String>,
+}
+
+impl Risk {
+    fn low() -> Self {
+        Self {
+            level: RiskLevel::Low,
+            reasons: Vec::new(),
+        }
+    }
+
+    fn raise(&mut self, level: RiskLevel, reason: String) {
+        self.level = self.level.max(level);
+        self.reasons.push(reason);
+    }
+}
+
+/// Prices used for cost estimates.
+#[derive(Debug, Clone)]
+pub struct CostRates {
+    pub cpu_hour: f64,
+    pub gib_hour: f64,
+    /// Monthly price per Terraform resource type.
+    pub resource_monthly: HashMap<String, f64>,
+}
+
+impl CostRates {
+    pub fn from_env() -> Self {
+        let rate = |name: &str, default: f64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        };
+        Self {
+            cpu_hour: rate("BUILDIT_COST_CPU_HOUR", DEFAULT_CPU_HOUR),
+            gib_hour: rate("BUILDIT_COST_GIB_HOUR", DEFAULT_GIB_HOUR),
+            resource_monthly: std::env::var("BUILDIT_COST_RESOURCE_PRICES")
+                .ok()
+                .and_then(|v| serde_json::from_str(&v).ok())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Monthly cost of a service spec, from replicas and resource requests
+    /// (falling back to limits).
+    fn spec_monthly(&self, spec: &serde_json::Value) -> f64 {
+        let replicas = spec.get("replicas").and_then(|r| r.as_f64()).unwrap_or(1.0);
+        let resources = spec.get("resources");
+        let quantity = |request: &str, limit: &str| {
+            resources
+                .and_then(|r| r.get(request).or_else(|| r.get(limit)))
+                .and_then(|q| q.as_str())
+        };
+        let cores = quantity("cpu_request", "cpu_limit")
+            .and_then(parse_cpu)
+            .unwrap_or(0.0);
+        let gib = quantity("memory_request", "memory_limit")
+            .and_then(parse_memory_gib)
+            .unwrap_or(0.0);
+        replicas * (cores * self.cpu_hour + gib * self.gib_hour) * HOURS_PER_MONTH
+    }
+}
+
+/// CPU quantity in cores: `500m`, `2`, `0.25`.
+fn parse_cpu(quantity: &str) -> Option<f64> {
+    match quantity.strip_suffix('m') {
+        Some(millis) => millis.parse::<f64>().ok().map(|m| m / 1000.0),
+        None => quantity.parse().ok(),
+    }
+}
+
+/// Memory quantity in GiB: `512Mi`, `1Gi`, `1G`, or plain bytes.
+fn parse_memory_gib(quantity: &str) -> Option<f64> {
+    const GIB: f64 = 1024.0 * 1024.0 * 1024.0;
+    let units: [(&str, f64); 8] = [
+        ("Ki", 1024.0),
+        ("Mi", 1024.0 * 1024.0),
+        ("Gi", GIB),
+        ("Ti", GIB * 1024.0),
+        ("K", 1e3),
+        ("M", 1e6),
+        ("G", 1e9),
+        ("T", 1e12),
+    ];
+    for (suffix, bytes) in units {
+        if let Some(value) = quantity.strip_suffix(suffix) {
+            return value.parse::<f64>().ok().map(|v| v * bytes / GIB);
+        }
+    }
+    quantity.parse::<f64>().ok().map(|v| v / GIB)
+}
+
+/// Planned changes from `terraform show -json` output.
+fn planned_changes(plan_json: &serde_json::Value) -> Vec<PlannedChange> {
+    parse_plan_json(plan_json)
+        .into_iter()
+        .map(|change| PlannedChange {
+            address: change.address,
+            resource_type: change.resource_type,
+            action: change.action,
+        })
+        .collect()
+}
+
+fn failure_rate<T>(history: &[T], is_failure: impl Fn(&T) -> bool) -> Option<FailureRate> {
+    if history.is_empty() {
+        return None;
+    }
+    let failed = history.iter().filter(|h| is_failure(h)).count();
+    Some(FailureRate {
+        total: history.len(),
+        failed,
+        rate: failed as f64 / history.len() as f64,
+    })
+}
+
+/// Flag a failure rate worth a second look.
+fn assess_failure_rate(risk: &mut Risk, rate: Option<&FailureRate>, what: &str) {
+    if let Some(rate) = rate
+        && rate.total >= 4
+        && rate.rate >= 0.25
+    {
+        risk.raise(
+            RiskLevel::Medium,
+            format!("{} of the last {} {} failed", rate.failed, rate.total, what),
+        );
+    }
+}
+
+fn compare_link(base_url: &str, from: &str, to: &str) -> Option<ContextLink> {
+    (from != to).then(|| ContextLink {
+        label: format!("Commits {}…{}", short_sha(from), short_sha(to)),
+        url: format!("{}/compare/{}...{}", base_url, from, to),
+    })
+}
+
+fn short_sha(sha: &str) -> &str {
+    &sha[..7.min(sha.len())]
+}
+
+/// Assembles approval context from the deployment, pipeline and stack
+/// repositories.
+pub struct ApprovalContextService {
+    deployment_repo: Arc<PgDeploymentRepo>,
+    pipeline_repo: Arc<PgPipelineRepo>,
+    stack_repo: Arc<PgStackRepo>,
+    repository_repo: Arc<PgRepositoryRepo>,
+    rates: CostRates,
+}
+
+impl ApprovalContextService {
+    pub fn new(
+        deployment_repo: Arc<PgDeploymentRepo>,
+        pipeline_repo: Arc<PgPipelineRepo>,
+        stack_repo: Arc<PgStackRepo>,
+        repository_repo: Arc<PgRepositoryRepo>,
+    ) -> Self {
+        Self {
+            deployment_repo,
+            pipeline_repo,
+            stack_repo,
+            repository_repo,
+            rates: CostRates::from_env(),
+        }
+    }
+
+    /// Context for applying a stack run's plan.
+    pub async fn for_stack_run(
+        &self,
+        run: &StackRun,
+    ) -> Result<ApprovalContext, ApprovalContextError> {
+        let stack = self
+            .stack_repo
+            .get_stack(ResourceId::from_uuid(run.stack_id))
+            .await
+            .map_err(|e| ApprovalContextError::Database(e.to_string()))?;
+        let placements = self
+            .deployment_repo
+            .list_services_for_stack(ResourceId::from_uuid(stack.id))
+            .await
+            .map_err(|e| ApprovalContextError::Database(e.to_string()))?;
+
+        let resources = run
+            .plan_json
+            .as_ref()
+            .map(planned_changes)
+            .unwrap_or_default();
+        let resource_changes = ResourceChanges {
+            add: run.resources_to_add,
+            change: run.resources_to_change,
+            destroy: run.resources_to_destroy,
+            resources,
+        };
+
+        let cost = (!self.rates.resource_monthly.is_empty()
+            && !resource_changes.resources.is_empty())
+        .then(|| {
+            let price = |r: &PlannedChange| {
+                self.rates
+                    .resource_monthly
+                    .get(&r.resource_type)
+                    .copied()
+                    .unwrap_or(0.0)
+            };
+            let added: f64 = resource_changes
+                .resources
+                .iter()
+                .filter(|r| r.action == "create")
+                .map(price)
+                .sum();
+            let removed: f64 = resource_changes
+                .resources
+                .iter()
+                .filter(|r| r.action == "delete")
+                .map(price)
+                .sum();
+            CostDelta {
+                monthly_before: removed,
+                monthly_after: added,
+                monthly_delta: added - removed,
+                basis: "Configured monthly prices of created and destroyed resources".to_string(),
+            }
+        });
+
+        let history: Vec<StackRun> = self
+            .stack_repo
+            .list_runs(ResourceId::from_uuid(stack.id), HISTORY_WINDOW + 1)
+            .await
+            .map_err(|e| ApprovalContextError::Database(e.to_string()))?
+            .into_iter()
+            .filter(|r| {
+                r.id != run.id
+                    && matches!(r.status, StackRunStatus::Succeeded | StackRunStatus::Failed)
+            })
+            .collect();
+        let failure_rate = failure_rate(&history, |r| r.status == StackRunStatus::Failed);
+
+        let mut links = vec![ContextLink {
+            label: "Plan output".to_string(),
+            url: format!("/api/v1/stacks/{}/runs/{}", stack.id, run.id),
+        }];
+        if let (Some(repository_id), Some(sha)) = (stack.repository_id, &run.commit_sha) {
+            let last_applied = history
+                .iter()
+                .filter(|r| r.status == StackRunStatus::Succeeded)
+                .find_map(|r| r.commit_sha.as_deref());
+            if let Some(from) = last_applied
+                && let Some(base) = self.repository_url(repository_id).await
+            {
+                links.extend(compare_link(&base, from, sha));
+            }
+        }
+
+        let mut risk = Risk::low();
+        if resource_changes.destroy > 0 {
+            risk.raise(
+                RiskLevel::High,
+                format!("{} resources will be destroyed", resource_changes.destroy),
+            );
+        }
+        let replaced = resource_changes
+            .resources
+            .iter()
+            .filter(|r| r.action == "replace")
+            .count();
+        if replaced > 0 {
+            risk.raise(
+                RiskLevel::High,
+                format!("{} resources will be replaced", replaced),
+            );
+        }
+        if !placements.is_empty() && resource_changes.destroy + resource_changes.change > 0 {
+            risk.raise(
+                RiskLevel::Medium,
+                format!(
+                    "Changes infrastructure used by {} services",
+                    placements.len()
+                ),
+            );
+        }
+        assess_failure_rate(&mut risk, failure_rate.as_ref(), "runs");
+        if let Some(cost) = &cost {
+            assess_cost(&mut risk, cost);
+        }
+        for result in run.policy_results.iter().filter(|r| r.failed()) {
+            let reason = match &result.error {
+                Some(_) => format!("Policy {} could not be evaluated", result.policy),
+                None => format!(
+                    "Violates policy {} ({} findings)",
+                    result.policy,
+                    result.violations.len()
+                ),
+            };
+            risk.raise(RiskLevel::Medium, reason);
+        }
+
+        let mut environments: Vec<String> = placements
+            .iter()
+            .map(|p| p.environment_name.clone())
+            .collect();
+        environments.dedup();
+        let mut affected_services: Vec<String> = placements
+            .iter()
+            .map(|p| format!("{} ({})", p.service_name, p.environment_name))
+            .collect();
+        affected_services.dedup();
+
+        Ok(ApprovalContext {
+            kind: ApprovalKind::StackApply,
+            subject: format!("Apply {}", stack.name),
+            environment: (!environments.is_empty()).then(|| environments.join(", ")),
+            resource_changes: Some(resource_changes),
+            cost,
+            affected_services,
+            failure_rate,
+            links,
+            policy_results: run.policy_results.clone(),
+            risk,
+        })
+    }
+
+    /// Context for a deployment about to roll out.
+    pub async fn for_deployment(
+        &self,
+        deployment: &Deployment,
+    ) -> Result<ApprovalContext, ApprovalContextError> {
+        let service = self
+            .deployment_repo
+            .get_service(ResourceId::from_uuid(deployment.service_id))
+            .await
+            .map_err(|e| ApprovalContextError::Database(e.to_string()))?;
+        let environment = self
+            .deployment_repo
+            .get_environment(ResourceId::from_uuid(deployment.environment_id))
+            .await
+            .map_err(|e| ApprovalContextError::Database(e.to_string()))?;
+        let previous = self
+            .deployment_repo
+            .get_previous_deployment(
+                ResourceId::from_uuid(deployment.service_id),
+                ResourceId::from_uuid(deployment.environment_id),
+                deployment.created_at,
+            )
+            .await
+            .map_err(|e| ApprovalContextError::Database(e.to_string()))?;
+
+        let before = previous
+            .as_ref()
+            .map(|p| self.rates.spec_monthly(&p.config))
+            .unwrap_or(0.0);
+        let after = self.rates.spec_monthly(&deployment.config);
+        let cost = Some(CostDelta {
+            monthly_before: before,
+            monthly_after: after,
+            monthly_delta: after - before,
+            basis: "Replicas × CPU and memory requests".to_string(),
+        });
+
+        let history: Vec<Deployment> = self
+            .deployment_repo
+            .list_service_deployments(
+                ResourceId::from_uuid(deployment.service_id),
+                Some(ResourceId::from_uuid(deployment.environment_id)),
+                HISTORY_WINDOW + 1,
+            )
+            .await
+            .map_err(|e| ApprovalContextError::Database(e.to_string()))?
+            .into_iter()
+            .filter(|d| {
+                d.id != deployment.id
+                    && matches!(d.status.as_str(), "succeeded" | "failed" | "rolled_back")
+            })
+            .collect();
+        let failure_rate = failure_rate(&history, |d| d.status != "succeeded");
+
+        let mut links = Vec::new();
+        if let (Some(from), Some(to)) = (
+            previous.as_ref().and_then(|p| p.spec_version),
+            deployment.spec_version,
+        ) && from != to
+        {
+            links.push(ContextLink {
+                label: format!("Spec diff v{} → v{}", from, to),
+                url: format!(
+                    "/api/v1/deployment/services/{}/spec/diff?from={}&to={}",
+                    service.id, from, to
+                ),
+            });
+        }
+        links.push(ContextLink {
+            label: "Release notes".to_string(),
+            url: format!(
+                "/api/v1/deployment/deployments/{}/release-notes",
+                deployment.id
+            ),
+        });
+
+        let mut risk = Risk::low();
+        assess_environment(&mut risk, &environment);
+        if previous.is_none() {
+            risk.raise(
+                RiskLevel::Medium,
+                format!(
+                    "First deployment of {} to {}",
+                    service.name, environment.name
+                ),
+            );
+        }
+        assess_failure_rate(&mut risk, failure_rate.as_ref(), "deployments");
+        if let Some(cost) = &cost {
+            assess_cost(&mut risk, cost);
+        }
+
+        Ok(ApprovalContext {
+            kind: ApprovalKind::Deployment,
+            subject: format!(
+                "{} {} → {}",
+                service.name, deployment.version, environment.name
+            ),
+            environment: Some(environment.name),
+            resource_changes: None,
+            cost,
+            affected_services: vec![service.name],
+            failure_rate,
+            links,
+            policy_results: Vec::new(),
+            risk,
+        })
+    }
+
+    /// Context for a manual gate in a pipeline run.
+    pub async fn for_pipeline_stage(
+        &self,
+        run: &PipelineRunRecord,
+        stage: &str,
+    ) -> Result<ApprovalContext, ApprovalContextError> {
+        let pipeline = self
+            .pipeline_repo
+            .get_by_id(ResourceId::from_uuid(run.pipeline_id))
+            .await
+            .map_err(|e| ApprovalContextError::Database(e.to_string()))?;
+        let services = self
+            .deployment_repo
+            .list_services_by_pipeline(ResourceId::from_uuid(pipeline.id))
+            .await
+            .map_err(|e| ApprovalContextError::Database(e.to_string()))?;
+
+        let history: Vec<PipelineRunRecord> = self
+            .pipeline_repo
+            .list_runs(ResourceId::from_uuid(pipeline.id), HISTORY_WINDOW + 1)
+            .await
+            .map_err(|e| ApprovalContextError::Database(e.to_string()))?
+            .into_iter()
+            .filter(|r| {
+                r.id != run.id && matches!(r.status.as_str(), "succeeded" | "failed" | "cancelled")
+            })
+            .collect();
+        let failure_rate = failure_rate(&history, |r| r.status == "failed");
+
+        let mut links = vec![ContextLink {
+            label: format!("Run #{}", run.number),
+            url: format!("/pipelines/{}/runs/{}", pipeline.id, run.id),
+        }];
+        let sha = run.git_info.get("sha").and_then(|s| s.as_str());
+        let repository = run.git_info.get("repository").and_then(|r| r.as_str());
+        let last_good = history
+            .iter()
+            .filter(|r| r.status == "succeeded")
+            .find_map(|r| r.git_info.get("sha").and_then(|s| s.as_str()));
+        if let (Some(repository), Some(from), Some(to)) = (repository, last_good, sha) {
+            links.extend(compare_link(
+                &format!("https://github.com/{}", repository),
+                from,
+                to,
+            ));
+        }
+
+        // Gates are usually named after the environment they guard
+        let environment = stage
+            .split(['-', '_', ' '])
+            .find(|part| matches!(*part, "prod" | "production" | "staging"))
+            .map(String::from);
+
+        let mut risk = Risk::low();
+        if matches!(environment.as_deref(), Some("prod" | "production")) {
+            risk.raise(
+                RiskLevel::Medium,
+                "Gate guards a production stage".to_string(),
+            );
+        }
+        assess_failure_rate(&mut risk, failure_rate.as_ref(), "runs");
+
+        Ok(ApprovalContext {
+            kind: ApprovalKind::PipelineStage,
+            subject: format!("{} #{}: {}", pipeline.name, run.number, stage),
+            environment,
+            resource_changes: None,
+            cost: None,
+            affected_services: services.into_iter().map(|s| s.name).collect(),
+            failure_rate,
+            links,
+            policy_results: Vec::new(),
+            risk,
+        })
+    }
+
+    /// Browser URL of a connected GitHub repository.
+    async fn repository_url(&self, repository_id: uuid::Uuid) -> Option<String> {
+        let repository = self
+            .repository_repo
+            .get_by_id(ResourceId::from_uuid(repository_id))
+            .await
+            .ok()?;
+        (repository.provider == GitProvider::Github)
+            .then(|| format!("https://github.com/{}", repository.full_name))
+    }
+}
+
+fn assess_environment(risk: &mut Risk, environment: &Environment) {
+    if is_production(environment) {
+        risk.raise(
+            RiskLevel::Medium,
+            format!("Targets production environment {}", environment.name),
+        );
+    }
+}
+
+/// Flag cost increases of more than half the current spend.
+fn assess_cost(risk: &mut Risk, cost: &CostDelta) {
+    if cost.monthly_delta > 0.0 && cost.monthly_delta > cost.monthly_before * 0.5 {
+        risk.raise(
+            RiskLevel::Medium,
+            format!("Estimated cost rises by ${:.2}/month", cost.monthly_delta),
+        );
+    }
+}
+
+/// Approval context errors.
+#[derive(Debug, thiserror::Error)]
+pub enum ApprovalContextError {
+    #[error("Database error: {0}")]
+    Database(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_spec_monthly_cost() {
+        let rates = CostRates {
+            cpu_hour: 0.04,
+            gib_hour: 0.01,
+            resource_monthly: HashMap::new(),
+        };
+        let spec = json!({
+            "replicas": 2,
+            "resources": {"cpu_request": "500m", "memory_limit": "512Mi"},
+        });
+        // 2 x (0.5 cores x 0.04 + 0.5 GiB x 0.01) x 730 hours
+        let expected = 2.0 * (0.5 * 0.04 + 0.5 * 0.01) * HOURS_PER_MONTH;
+        assert!((rates.spec_monthly(&spec) - expected).abs() < 1e-9);
+        assert_eq!(parse_cpu("2"), Some(2.0));
+        assert_eq!(parse_memory_gib("1Gi"), Some(1.0));
+    }
+
+    #[test]
+    fn test_risk_is_raised_by_cost_and_failures() {
+        let mut risk = Risk::low();
+        assess_cost(
+            &mut risk,
+            &CostDelta {
+                monthly_before: 100.0,
+                monthly_after: 120.0,
+                monthly_delta: 20.0,
+                basis: String::new(),
+            },
+        );
+        assess_failure_rate(
+            &mut risk,
+            failure_rate(&[true, false, false], |f| *f).as_ref(),
+            "runs",
+        );
+        // A small rise and too little history stay low
+        assert_eq!(risk.level, RiskLevel::Low);
+
+        assess_cost(
+            &mut risk,
+            &CostDelta {
+                monthly_before: 100.0,
+                monthly_after: 180.0,
+                monthly_delta: 80.0,
+                basis: String::new(),
+            },
+        );
+        assess_failure_rate(
+            &mut risk,
+            failure_rate(&[true, false, true, false], |f| *f).as_ref(),
+            "runs",
+        );
+        assert_eq!(risk.level, RiskLevel::Medium);
+        assert_eq!(
+            risk.reasons,
+            vec![
+                "Estimated cost rises by $80.00/month",
+                "2 of the last 4 runs failed"
+            ]
+        );
+    }
+}
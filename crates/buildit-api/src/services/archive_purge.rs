@@ -0,0 +1,224 @@
+//! Purging archived pipelines and runs.
+//!
+//! Archiving a pipeline or a run keeps its history; the [`ArchivePurger`]
+//! deletes it for good once it has been archived for longer than the
+//! retention window. A purged run's artifacts, reports and archived logs
+//! are removed from their stores before its rows, and a pipeline goes once
+//! none of its runs are left. Runs with pinned artifacts are never purged.
+
+use buildit_core::artifact::ArtifactStore;
+use buildit_core::{Error, ResourceId, Result};
+use buildit_db::repo::pipeline::PipelineRunRecord;
+use buildit_db::{ArtifactRepo, LogRepo, PgArtifactRepo, PgLogRepo, PgPipelineRepo, PipelineRepo};
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+use crate::services::log_archive::LogArchiveStore;
+
+/// Runs purged per batch.
+const BATCH_SIZE: i64 = 100;
+
+/// Archive purger settings.
+#[derive(Debug, Clone)]
+pub struct ArchivePurgeConfig {
+    /// How often to look for archives past the retention window.
+    pub interval: Duration,
+    /// How long pipelines and runs stay archived before they are purged.
+    pub retention: Duration,
+}
+
+impl Default for ArchivePurgeConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(3600),
+            retention: Duration::from_secs(30 * 24 * 3600),
+        }
+    }
+}
+
+impl ArchivePurgeConfig {
+    /// Load settings from `BUILDIT_ARCHIVE_*` environment variables.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let env = |key: &str| {
+            std::env::var(key)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|n: &u64| *n > 0)
+        };
+        Self {
+            interval: env("BUILDIT_ARCHIVE_PURGE_INTERVAL_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.interval),
+            retention: env("BUILDIT_ARCHIVE_RETENTION_DAYS")
+                .map(|days| Duration::from_secs(days.saturating_mul(24 * 3600)))
+                .unwrap_or(defaults.retention),
+        }
+    }
+
+    /// Pipelines and runs archived before this, at `now`, are purged.
+    fn cutoff(&self, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+        chrono::Duration::from_std(self.retention)
+            .ok()
+            .and_then(|retention| now.checked_sub_signed(retention))
+            .ok_or_else(|| {
+                Error::InvalidInput(format!(
+                    "archive retention of {} days is too long",
+                    self.retention.as_secs() / (24 * 3600)
+                ))
+            })
+    }
+}
+
+/// Background task that deletes archived pipelines and runs past the
+/// retention window.
+pub struct ArchivePurger {
+    config: ArchivePurgeConfig,
+    pipeline_repo: Arc<PgPipelineRepo>,
+    artifact_repo: Arc<PgArtifactRepo>,
+    log_repo: Arc<PgLogRepo>,
+    artifact_store: Arc<dyn ArtifactStore>,
+    log_archive: Option<Arc<dyn LogArchiveStore>>,
+}
+
+impl ArchivePurger {
+    pub fn new(
+        config: ArchivePurgeConfig,
+        pipeline_repo: Arc<PgPipelineRepo>,
+        artifact_repo: Arc<PgArtifactRepo>,
+        log_repo: Arc<PgLogRepo>,
+        artifact_store: Arc<dyn ArtifactStore>,
+        log_archive: Option<Arc<dyn LogArchiveStore>>,
+    ) -> Self {
+        Self {
+            config,
+            pipeline_repo,
+            artifact_repo,
+            log_repo,
+            artifact_store,
+            log_archive,
+        }
+    }
+
+    /// Run the purger loop forever.
+    pub async fn run(self) {
+        info!(
+            interval_secs = self.config.interval.as_secs(),
+            retention_days = self.config.retention.as_secs() / (24 * 3600),
+            "Archive purger started"
+        );
+
+        let mut ticker = tokio::time::interval(self.config.interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.purge().await {
+                error!(error = %e, "Archive purge failed");
+            }
+        }
+    }
+
+    /// Purge what is past the retention window once. Returns the runs and
+    /// pipelines deleted.
+    pub async fn purge(&self) -> Result<(usize, u64)> {
+        let cutoff = self.config.cutoff(Utc::now())?;
+
+        let mut runs = 0;
+        'batches: loop {
+            let due = self
+                .pipeline_repo
+                .runs_due_for_purge(cutoff, BATCH_SIZE)
+                .await
+                .map_err(db_error)?;
+            if due.is_empty() {
+                break;
+            }
+            for run in &due {
+                if let Err(e) = self.purge_run(run).await {
+                    // Left in the database, it would be picked again at once
+                    warn!(run_id = %run.id, error = %e, "Failed to purge archived run");
+                    break 'batches;
+                }
+                runs += 1;
+            }
+        }
+
+        let pipelines = self
+            .pipeline_repo
+            .purge_archived_pipelines(cutoff)
+            .await
+            .map_err(db_error)?;
+        if runs > 0 || pipelines > 0 {
+            info!(runs, pipelines, "Purged archived runs and pipelines");
+        }
+        Ok((runs, pipelines))
+    }
+
+    /// Delete a run's stored objects, then the run.
+    async fn purge_run(&self, run: &PipelineRunRecord) -> Result<()> {
+        let run_id = ResourceId::from_uuid(run.id);
+        for artifact in self
+            .artifact_repo
+            .list_artifacts(run_id)
+            .await
+            .map_err(db_error)?
+        {
+            self.artifact_store.delete(&artifact.reference()).await?;
+        }
+        for report in self
+            .artifact_repo
+            .list_reports(run_id)
+            .await
+            .map_err(db_error)?
+        {
+            let paths: Vec<String> = report
+                .files
+                .as_object()
+                .map(|files| files.keys().cloned().collect())
+                .unwrap_or_default();
+            for path in paths {
+                if let Some(file) = report.file(&path) {
+                    self.artifact_store
+                        .delete(&report.reference(&path, &file))
+                        .await?;
+                }
+            }
+        }
+        if let Some(archive) = self.log_repo.get_archive(run_id).await.map_err(db_error)? {
+            let store = self.log_archive.as_ref().ok_or_else(|| {
+                Error::Internal("logs are archived but no log archive is configured".to_string())
+            })?;
+            store.delete(&archive.location).await?;
+        }
+        self.pipeline_repo
+            .delete_run(run_id)
+            .await
+            .map_err(db_error)
+    }
+}
+
+fn db_error(e: buildit_db::DbError) -> Error {
+    Error::Internal(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runs_are_purged_once_past_retention() {
+        let now = Utc::now();
+        let config = ArchivePurgeConfig::default();
+        assert_eq!(
+            config.cutoff(now).unwrap(),
+            now - chrono::Duration::days(30)
+        );
+
+        let forever = ArchivePurgeConfig {
+            retention: Duration::from_secs(u64::MAX),
+            ..config
+        };
+        assert!(matches!(forever.cutoff(now), Err(Error::InvalidInput(_))));
+    }
+}
@@ -0,0 +1,104 @@
+//! Resolving the artifacts stages need from other pipelines.
+//!
+//! A stage's `needs-artifact` names a pipeline of the run's tenant, one of
+//! its artifacts and which run to take it from. The job downloads it before
+//! its commands through an expiring link under `/artifacts`, signed like
+//! report links, so it needs no credentials of its own; the link is built
+//! on `BUILDIT_PUBLIC_URL`, which jobs must be able to reach.
+
+use async_trait::async_trait;
+use buildit_core::ResourceId;
+use buildit_core::artifact::{
+    ArtifactDependencyRequest, ArtifactResolver, ArtifactVersion, ResolvedArtifact,
+};
+use buildit_db::{ArtifactRepo, PipelineRepo};
+use tracing::info;
+
+use crate::AppState;
+
+/// Finds other pipelines' artifacts and signs links to them.
+pub struct ArtifactDependencyService {
+    state: AppState,
+}
+
+impl ArtifactDependencyService {
+    pub fn new(state: AppState) -> Self {
+        Self { state }
+    }
+}
+
+/// Path, under the public URL, a job downloads an artifact from.
+pub fn artifact_link(run_id: uuid::Uuid, artifact_id: uuid::Uuid, token: &str) -> String {
+    format!("/artifacts/{}/{}/{}", run_id, artifact_id, token)
+}
+
+#[async_trait]
+impl ArtifactResolver for ArtifactDependencyService {
+    async fn resolve(
+        &self,
+        request: &ArtifactDependencyRequest,
+    ) -> Result<ResolvedArtifact, String> {
+        let dependency = &request.dependency;
+        let base = self
+            .state
+            .auth
+            .public_url
+            .as_deref()
+            .ok_or("set BUILDIT_PUBLIC_URL so jobs can download artifacts from the server")?;
+        let repo = &self.state.pipeline_repo;
+        let parent = repo
+            .get_by_id(request.pipeline_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        let source = repo
+            .list_by_tenant(ResourceId::from_uuid(parent.tenant_id))
+            .await
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .find(|p| p.name == dependency.pipeline)
+            .ok_or_else(|| {
+                format!(
+                    "the tenant has no active pipeline '{}'",
+                    dependency.pipeline
+                )
+            })?;
+        let artifact = self
+            .state
+            .artifact_repo
+            .find_pipeline_artifact(
+                ResourceId::from_uuid(source.id),
+                &dependency.artifact,
+                dependency.version,
+            )
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| match dependency.version {
+                ArtifactVersion::LatestSuccessful => "no successful run uploaded it".to_string(),
+                ArtifactVersion::Latest => "no run uploaded it".to_string(),
+                ArtifactVersion::Run(number) => format!("run #{} didn't upload it", number),
+            })?;
+        let run = repo
+            .get_run(ResourceId::from_uuid(artifact.pipeline_run_id))
+            .await
+            .map_err(|e| e.to_string())?;
+
+        info!(
+            run_id = %request.run_id,
+            stage = %request.stage,
+            pipeline = %source.name,
+            artifact = %artifact.name,
+            source_run = run.number,
+            "Resolved artifact dependency"
+        );
+        let token = self.state.report_signer.sign_artifact(artifact.id);
+        Ok(ResolvedArtifact {
+            url: format!(
+                "{}{}",
+                base.trim_end_matches('/'),
+                artifact_link(run.id, artifact.id, &token)
+            ),
+            run_number: run.number,
+            size: artifact.size_bytes.max(0) as u64,
+        })
+    }
+}
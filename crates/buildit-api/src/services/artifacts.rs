@@ -0,0 +1,296 @@
+//! Filesystem-backed artifact store.
+//!
+//! Artifacts are written to `<root>/<run id>/<stage>/<name>`, where the root
+//! is the artifact store `path` of the system configuration
+//! (`BUILDIT_ARTIFACT_DIR`). Locations handed out are relative to
+//! the root so the directory can move without rewriting metadata.
+
+use async_trait::async_trait;
+use axum::body::Bytes;
+use buildit_config::system::ArtifactStoreConfig;
+use buildit_core::artifact::{
+    ArtifactKey, ArtifactKind, ArtifactManifest, ArtifactRef, ArtifactStore, PruneStats,
+    RetentionPolicy,
+};
+use buildit_core::{Error, ResourceId, Result};
+use futures::StreamExt;
+use futures::stream::BoxStream;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use tokio::io::AsyncReadExt;
+
+/// Read size when streaming an artifact.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Stores artifacts as plain files under a root directory.
+pub struct FilesystemArtifactStore {
+    root: PathBuf,
+}
+
+impl FilesystemArtifactStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Store rooted at the system configuration's artifact store `path`
+    /// (default `/tmp/buildit/artifacts`).
+    pub fn from_config(config: Option<&ArtifactStoreConfig>) -> Self {
+        Self::new(
+            config
+                .and_then(|c| c.path.clone())
+                .unwrap_or_else(|| "/tmp/buildit/artifacts".to_string()),
+        )
+    }
+
+    fn resolve(&self, location: &str) -> Result<PathBuf> {
+        let relative = relative_path(location)?;
+        Ok(self.root.join(relative))
+    }
+}
+
+/// Reject paths that would escape the store root.
+fn relative_path(path: &str) -> Result<&Path> {
+    let relative = Path::new(path);
+    let valid = !path.is_empty()
+        && relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)));
+    if valid {
+        Ok(relative)
+    } else {
+        Err(Error::InvalidInput(format!(
+            "artifact path {:?} must be relative and stay inside the run",
+            path
+        )))
+    }
+}
+
+fn io_error(e: std::io::Error) -> Error {
+    if e.kind() == std::io::ErrorKind::NotFound {
+        Error::NotFound("artifact content is missing from the store".to_string())
+    } else {
+        Error::Internal(format!("artifact store: {}", e))
+    }
+}
+
+#[async_trait]
+impl ArtifactStore for FilesystemArtifactStore {
+    async fn put(&self, key: &ArtifactKey, data: Bytes) -> Result<ArtifactRef> {
+        if key.stage.contains('/') {
+            return Err(Error::InvalidInput(format!(
+                "stage name {:?} cannot contain '/'",
+                key.stage
+            )));
+        }
+        relative_path(&key.stage)?;
+        relative_path(&key.name)?;
+        let location = format!("{}/{}/{}", key.run_id, key.stage, key.name);
+        let path = self.resolve(&location)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(io_error)?;
+        }
+        tokio::fs::write(&path, &data).await.map_err(io_error)?;
+
+        Ok(ArtifactRef {
+            key: key.clone(),
+            location,
+            checksum: format!("sha256:{}", hex::encode(Sha256::digest(&data))),
+            size: data.len() as u64,
+            created_at: chrono::Utc::now(),
+        })
+    }
+
+    async fn get(&self, reference: &ArtifactRef) -> Result<Bytes> {
+        let path = self.resolve(&reference.location)?;
+        let data = tokio::fs::read(path).await.map_err(io_error)?;
+        Ok(Bytes::from(data))
+    }
+
+    async fn stream(
+        &self,
+        reference: &ArtifactRef,
+    ) -> Result<BoxStream<'static, std::result::Result<Bytes, std::io::Error>>> {
+        let path = self.resolve(&reference.location)?;
+        let file = tokio::fs::File::open(path).await.map_err(io_error)?;
+        let chunks = futures::stream::try_unfold(file, |mut file| async move {
+            let mut buf = vec![0; CHUNK_SIZE];
+            let read = file.read(&mut buf).await?;
+            if read == 0 {
+                return Ok(None);
+            }
+            buf.truncate(read);
+            Ok(Some((Bytes::from(buf), file)))
+        });
+        Ok(chunks.boxed())
+    }
+
+    async fn list(&self, run_id: &ResourceId) -> Result<Vec<ArtifactManifest>> {
+        let run_dir = self.root.join(run_id.to_string());
+        let mut manifests = Vec::new();
+        let mut stages = match tokio::fs::read_dir(&run_dir).await {
+            Ok(stages) => stages,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(manifests),
+            Err(e) => return Err(io_error(e)),
+        };
+        while let Some(stage) = stages.next_entry().await.map_err(io_error)? {
+            let stage_name = stage.file_name().to_string_lossy().to_string();
+            let mut pending = vec![stage.path()];
+            while let Some(dir) = pending.pop() {
+                let mut entries = tokio::fs::read_dir(&dir).await.map_err(io_error)?;
+                while let Some(entry) = entries.next_entry().await.map_err(io_error)? {
+                    let metadata = entry.metadata().await.map_err(io_error)?;
+                    if metadata.is_dir() {
+                        pending.push(entry.path());
+                        continue;
+                    }
+                    let path = entry.path();
+                    let name = path
+                        .strip_prefix(stage.path())
+                        .unwrap_or(&path)
+                        .to_string_lossy()
+                        .to_string();
+                    let created_at = metadata
+                        .modified()
+                        .map(chrono::DateTime::<chrono::Utc>::from)
+                        .unwrap_or_else(|_| chrono::Utc::now());
+                    manifests.push(ArtifactManifest {
+                        kind: ArtifactKind::detect(&name, &[]),
+                        reference: ArtifactRef {
+                            location: format!("{}/{}/{}", run_id, stage_name, name),
+                            key: ArtifactKey {
+                                run_id: *run_id,
+                                stage: stage_name.clone(),
+                                name,
+                            },
+                            checksum: String::new(),
+                            size: metadata.len(),
+                            created_at,
+                        },
+                        content_type: None,
+                        metadata: HashMap::new(),
+                    });
+                }
+            }
+        }
+        Ok(manifests)
+    }
+
+    async fn delete(&self, reference: &ArtifactRef) -> Result<()> {
+        let path = self.resolve(&reference.location)?;
+        match tokio::fs::remove_file(path).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(io_error(e)),
+        }
+    }
+
+    /// Removes whole runs older than `max_age`; size and run-count limits
+    /// are not enforced by this store.
+    async fn prune(&self, policy: RetentionPolicy) -> Result<PruneStats> {
+        let mut stats = PruneStats {
+            artifacts_deleted: 0,
+            bytes_freed: 0,
+        };
+        let Some(max_age) = policy.max_age else {
+            return Ok(stats);
+        };
+        let mut runs = match tokio::fs::read_dir(&self.root).await {
+            Ok(runs) => runs,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(stats),
+            Err(e) => return Err(io_error(e)),
+        };
+        while let Some(run) = runs.next_entry().await.map_err(io_error)? {
+            let Ok(run_id) = run.file_name().to_string_lossy().parse::<uuid::Uuid>() else {
+                continue;
+            };
+            let modified = run.metadata().await.and_then(|m| m.modified());
+            let expired = modified
+                .ok()
+                .and_then(|m| m.elapsed().ok())
+                .is_some_and(|age| age > max_age);
+            if !expired {
+                continue;
+            }
+            for manifest in self.list(&ResourceId::from_uuid(run_id)).await? {
+                stats.artifacts_deleted += 1;
+                stats.bytes_freed += manifest.reference.size;
+            }
+            tokio::fs::remove_dir_all(run.path())
+                .await
+                .map_err(io_error)?;
+        }
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use buildit_core::artifact::ArtifactPreview;
+
+    #[test]
+    fn test_artifacts_are_typed_from_name_and_contents() {
+        let junit = br#"<?xml version="1.0"?><testsuites tests="1"></testsuites>"#;
+        let kind = ArtifactKind::detect("reports/results.xml", junit);
+        assert_eq!(kind, ArtifactKind::Junit);
+        assert_eq!(kind.content_type("results.xml", junit), "application/xml");
+
+        let sbom = br#"{"bomFormat": "CycloneDX", "components": []}"#;
+        let kind = ArtifactKind::detect("bom.json", sbom);
+        assert_eq!(kind, ArtifactKind::Sbom);
+        assert_eq!(
+            kind.preview(kind.content_type("bom.json", sbom)),
+            Some(ArtifactPreview::Json)
+        );
+
+        let kind = ArtifactKind::detect("coverage/index.html", b"<html></html>");
+        assert_eq!(kind, ArtifactKind::HtmlReport);
+        assert_eq!(
+            kind.preview("text/html; charset=utf-8"),
+            Some(ArtifactPreview::SandboxedHtml)
+        );
+
+        let kind = ArtifactKind::detect("app", &[0x7f, b'E', b'L', b'F']);
+        assert_eq!(kind, ArtifactKind::Binary);
+        assert_eq!(kind.preview("application/octet-stream"), None);
+    }
+
+    #[tokio::test]
+    async fn test_put_and_get_stay_inside_the_root() {
+        let root = std::env::temp_dir().join(format!("buildit-artifacts-{}", uuid::Uuid::new_v4()));
+        let store = FilesystemArtifactStore::new(&root);
+        let key = ArtifactKey {
+            run_id: ResourceId::new(),
+            stage: "build".to_string(),
+            name: "dist/app.tar.gz".to_string(),
+        };
+
+        let reference = store
+            .put(&key, Bytes::from_static(b"contents"))
+            .await
+            .unwrap();
+        assert_eq!(
+            reference.location,
+            format!("{}/build/dist/app.tar.gz", key.run_id)
+        );
+        assert_eq!(reference.size, 8);
+        assert_eq!(
+            store.get(&reference).await.unwrap(),
+            Bytes::from_static(b"contents")
+        );
+
+        let escaping = ArtifactKey {
+            name: "../../etc/passwd".to_string(),
+            ..key.clone()
+        };
+        assert!(store.put(&escaping, Bytes::new()).await.is_err());
+        let absolute = ArtifactKey {
+            name: "/etc/passwd".to_string(),
+            ..key
+        };
+        assert!(store.put(&absolute, Bytes::new()).await.is_err());
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
@@ -0,0 +1,480 @@
+//! Backups for disaster recovery.
+//!
+//! A backup is a directory holding:
+//!
+//! - `database.dump`: a `pg_dump` custom-format dump;
+//! - `terraform/<stack id>.tfstate`: each stack's Terraform state, usable
+//!   with `terraform state push` should BuildIt itself be down;
+//! - `artifacts/<artifact id>`: with artifacts included, a copy of each
+//!   build artifact's content;
+//! - `manifest.json`: what the backup holds, with SHA-256 checksums.
+//!
+//! Everything is read from one exported database snapshot, so the dump, the
+//! artifact listing and the Terraform states agree with each other even
+//! while servers keep running.
+//!
+//! Restoring checks every checksum first, loads the dump with `pg_restore`
+//! and applies migrations newer than the backup. Copied artifacts are put
+//! back into the artifact store and their records pointed at wherever the
+//! store put them; artifacts that were not copied are looked up in the store
+//! and reported if missing. Background tasks claimed by the servers of the
+//! old installation are released so the new servers pick them up.
+
+use buildit_core::artifact::{ArtifactKey, ArtifactKind, ArtifactStore};
+use buildit_core::{Error, ResourceId};
+use buildit_db::{ArtifactRepo, BackupSnapshot, DbError, PgArtifactRepo, run_migrations};
+use buildit_scheduler::JobQueue;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::process::Command;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Version of the backup layout written by [`create`].
+pub const FORMAT_VERSION: u32 = 1;
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+const DATABASE_FILE: &str = "database.dump";
+const TERRAFORM_DIR: &str = "terraform";
+const ARTIFACTS_DIR: &str = "artifacts";
+
+/// Read size when checksumming files.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BackupError {
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("database: {0}")]
+    Database(#[from] DbError),
+    #[error("artifact store: {0}")]
+    Artifacts(#[from] Error),
+    #[error("{tool} failed: {message}")]
+    Tool { tool: &'static str, message: String },
+    #[error("invalid backup: {0}")]
+    Invalid(String),
+}
+
+impl From<sqlx::Error> for BackupError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Database(e.into())
+    }
+}
+
+impl From<serde_json::Error> for BackupError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Invalid(e.to_string())
+    }
+}
+
+/// What a backup holds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    /// Last migration applied to the database when it was backed up.
+    pub schema_version: Option<i64>,
+    /// The exported snapshot everything was read from.
+    pub snapshot: String,
+    pub database: BackupFile,
+    pub artifacts: Vec<ArtifactEntry>,
+    pub stack_states: Vec<StackStateEntry>,
+}
+
+/// A file of the backup, relative to its directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupFile {
+    pub path: String,
+    pub sha256: String,
+    pub size: u64,
+}
+
+/// A build artifact as recorded when the backup was taken.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtifactEntry {
+    pub id: Uuid,
+    pub run_id: Uuid,
+    pub stage: String,
+    pub name: String,
+    pub kind: String,
+    pub content_type: String,
+    pub size: u64,
+    pub digest: String,
+    /// Where the artifact store had it.
+    pub location: String,
+    /// Copy of its content, when artifacts were included and it was still
+    /// in the store.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub copy: Option<BackupFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StackStateEntry {
+    pub stack_id: Uuid,
+    pub serial: i32,
+    pub lineage: Option<String>,
+    pub file: BackupFile,
+}
+
+/// What restoring a backup did.
+#[derive(Debug, Default)]
+pub struct RestoreReport {
+    /// Copied artifacts written back to the store.
+    pub artifacts_restored: usize,
+    /// Of those, the ones the store put somewhere else, whose records now
+    /// point there.
+    pub artifacts_relocated: usize,
+    /// Artifacts neither copied nor found in the store, as
+    /// `<run id>/<stage>/<name>`.
+    pub artifacts_missing: Vec<String>,
+    /// Background tasks released from the old servers.
+    pub tasks_released: u64,
+}
+
+/// Back up the database, Terraform states and, with `include_artifacts`,
+/// artifact contents into `dir`, which must not hold a backup yet.
+pub async fn create(
+    pool: &PgPool,
+    database_url: &str,
+    artifact_store: &dyn ArtifactStore,
+    dir: &Path,
+    include_artifacts: bool,
+) -> Result<BackupManifest, BackupError> {
+    if tokio::fs::try_exists(dir.join(MANIFEST_FILE)).await? {
+        return Err(BackupError::Invalid(format!(
+            "{} already holds a backup",
+            dir.display()
+        )));
+    }
+    tokio::fs::create_dir_all(dir.join(TERRAFORM_DIR)).await?;
+    if include_artifacts {
+        tokio::fs::create_dir_all(dir.join(ARTIFACTS_DIR)).await?;
+    }
+
+    let mut snapshot = BackupSnapshot::begin(pool).await?;
+    info!(snapshot = %snapshot.id(), "Backing up database");
+    // pg_dump reads the snapshot while this session keeps it alive
+    run(
+        "pg_dump",
+        Command::new("pg_dump")
+            .arg("--format=custom")
+            .arg("--snapshot")
+            .arg(snapshot.id())
+            .arg("--file")
+            .arg(dir.join(DATABASE_FILE))
+            .arg("--dbname")
+            .arg(database_url),
+    )
+    .await?;
+    let schema_version = snapshot.schema_version().await?;
+    let records = snapshot.artifacts().await?;
+    let states = snapshot.stack_states().await?;
+    let snapshot_id = snapshot.id().to_string();
+    snapshot.finish().await?;
+
+    let mut stack_states = Vec::with_capacity(states.len());
+    for state in states {
+        let path = format!("{}/{}.tfstate", TERRAFORM_DIR, state.stack_id);
+        let content = serde_json::to_vec_pretty(&state.state_json)?;
+        tokio::fs::write(dir.join(&path), &content).await?;
+        stack_states.push(StackStateEntry {
+            stack_id: state.stack_id,
+            serial: state.serial,
+            lineage: state.lineage,
+            file: BackupFile {
+                path,
+                sha256: hex::encode(Sha256::digest(&content)),
+                size: content.len() as u64,
+            },
+        });
+    }
+
+    let mut artifacts = Vec::with_capacity(records.len());
+    for record in records {
+        let copy = if include_artifacts {
+            match artifact_store.get(&record.reference()).await {
+                Ok(content) => {
+                    let path = format!("{}/{}", ARTIFACTS_DIR, record.id);
+                    tokio::fs::write(dir.join(&path), &content).await?;
+                    Some(BackupFile {
+                        path,
+                        sha256: hex::encode(Sha256::digest(&content)),
+                        size: content.len() as u64,
+                    })
+                }
+                // Collected since the snapshot, or lost before it
+                Err(Error::NotFound(_)) => {
+                    warn!(artifact_id = %record.id, location = %record.location, "Artifact content is missing; not copied");
+                    None
+                }
+                Err(e) => return Err(e.into()),
+            }
+        } else {
+            None
+        };
+        artifacts.push(ArtifactEntry {
+            id: record.id,
+            run_id: record.pipeline_run_id,
+            stage: record.stage_name,
+            name: record.name,
+            kind: record.kind,
+            content_type: record.content_type,
+            size: record.size_bytes as u64,
+            digest: record.digest,
+            location: record.location,
+            copy,
+        });
+    }
+
+    let manifest = BackupManifest {
+        version: FORMAT_VERSION,
+        created_at: Utc::now(),
+        schema_version,
+        snapshot: snapshot_id,
+        database: checksum(dir, DATABASE_FILE).await?,
+        artifacts,
+        stack_states,
+    };
+    tokio::fs::write(
+        dir.join(MANIFEST_FILE),
+        serde_json::to_vec_pretty(&manifest)?,
+    )
+    .await?;
+    Ok(manifest)
+}
+
+/// Read the manifest of the backup in `dir` and check every file it lists
+/// against its checksum.
+pub async fn verify(dir: &Path) -> Result<BackupManifest, BackupError> {
+    let manifest: BackupManifest =
+        serde_json::from_slice(&tokio::fs::read(dir.join(MANIFEST_FILE)).await?)?;
+    if manifest.version != FORMAT_VERSION {
+        return Err(BackupError::Invalid(format!(
+            "backup format {} is not supported; expected {}",
+            manifest.version, FORMAT_VERSION
+        )));
+    }
+
+    let files = std::iter::once(&manifest.database)
+        .chain(manifest.stack_states.iter().map(|s| &s.file))
+        .chain(manifest.artifacts.iter().filter_map(|a| a.copy.as_ref()));
+    for file in files {
+        let actual = checksum(dir, &file.path).await?;
+        if actual.sha256 != file.sha256 || actual.size != file.size {
+            return Err(BackupError::Invalid(format!(
+                "{} does not match its checksum",
+                file.path
+            )));
+        }
+    }
+    Ok(manifest)
+}
+
+/// Restore the backup in `dir` over the database at `database_url`,
+/// replacing everything in it. No server may be running against it.
+pub async fn restore(
+    dir: &Path,
+    database_url: &str,
+    artifact_store: &dyn ArtifactStore,
+) -> Result<RestoreReport, BackupError> {
+    let manifest = verify(dir).await?;
+
+    info!(created_at = %manifest.created_at, "Restoring database");
+    run(
+        "pg_restore",
+        Command::new("pg_restore")
+            .args([
+                "--clean",
+                "--if-exists",
+                "--no-owner",
+                "--single-transaction",
+            ])
+            .arg("--exit-on-error")
+            .arg("--dbname")
+            .arg(database_url)
+            .arg(dir.join(&manifest.database.path)),
+    )
+    .await?;
+
+    let pool = buildit_db::create_pool(database_url).await?;
+    // A backup of an older release is brought up to this one's schema
+    run_migrations(&pool).await?;
+
+    let mut report = RestoreReport::default();
+    let artifact_repo = PgArtifactRepo::new(pool.clone());
+    let mut stored: HashMap<Uuid, HashSet<String>> = HashMap::new();
+    for entry in &manifest.artifacts {
+        let key = ArtifactKey {
+            run_id: ResourceId::from_uuid(entry.run_id),
+            stage: entry.stage.clone(),
+            name: entry.name.clone(),
+        };
+        match &entry.copy {
+            Some(copy) => {
+                let content = tokio::fs::read(dir.join(&copy.path)).await?;
+                let reference = artifact_store.put(&key, content.into()).await?;
+                if reference.location != entry.location {
+                    let kind: ArtifactKind = entry.kind.parse().unwrap_or_default();
+                    artifact_repo
+                        .record_artifact(&reference, kind, &entry.content_type)
+                        .await?;
+                    report.artifacts_relocated += 1;
+                }
+                report.artifacts_restored += 1;
+            }
+            None => {
+                let locations = match stored.entry(entry.run_id) {
+                    Entry::Occupied(listed) => listed.into_mut(),
+                    Entry::Vacant(unlisted) => unlisted.insert(
+                        artifact_store
+                            .list(&key.run_id)
+                            .await?
+                            .into_iter()
+                            .map(|m| m.reference.location)
+                            .collect(),
+                    ),
+                };
+                if !locations.contains(&entry.location) {
+                    report
+                        .artifacts_missing
+                        .push(format!("{}/{}/{}", entry.run_id, entry.stage, entry.name));
+                }
+            }
+        }
+    }
+
+    // Tasks claimed by the old servers would otherwise wait to go stale
+    report.tasks_released = JobQueue::new(pool.clone())
+        .requeue_worker_tasks("", Duration::ZERO)
+        .await?;
+    pool.close().await;
+    Ok(report)
+}
+
+/// Run an external tool, failing with its error output if it fails.
+async fn run(tool: &'static str, command: &mut Command) -> Result<(), BackupError> {
+    let output = command.output().await.map_err(|e| BackupError::Tool {
+        tool,
+        message: e.to_string(),
+    })?;
+    if !output.status.success() {
+        return Err(BackupError::Tool {
+            tool,
+            message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Checksum and size of a file of the backup.
+async fn checksum(dir: &Path, path: &str) -> Result<BackupFile, BackupError> {
+    let full: PathBuf = dir.join(path);
+    let mut file = tokio::fs::File::open(&full).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; CHUNK_SIZE];
+    let mut size = 0;
+    loop {
+        let read = file.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+        size += read as u64;
+    }
+    Ok(BackupFile {
+        path: path.to_string(),
+        sha256: hex::encode(hasher.finalize()),
+        size,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A backup directory holding just a database dump.
+    async fn write_backup(dir: &Path, dump: &[u8]) -> BackupManifest {
+        tokio::fs::create_dir_all(dir).await.unwrap();
+        tokio::fs::write(dir.join(DATABASE_FILE), dump)
+            .await
+            .unwrap();
+        let manifest = BackupManifest {
+            version: FORMAT_VERSION,
+            created_at: Utc::now(),
+            schema_version: Some(20240101000000),
+            snapshot: "00000003-00000002-1".to_string(),
+            database: checksum(dir, DATABASE_FILE).await.unwrap(),
+            artifacts: Vec::new(),
+            stack_states: Vec::new(),
+        };
+        tokio::fs::write(
+            dir.join(MANIFEST_FILE),
+            serde_json::to_vec(&manifest).unwrap(),
+        )
+        .await
+        .unwrap();
+        manifest
+    }
+
+    #[tokio::test]
+    async fn test_verify_checks_every_file_against_its_checksum() {
+        let dir = std::env::temp_dir().join(format!("buildit-backup-{}", Uuid::new_v4()));
+        let written = write_backup(&dir, b"PGDMP dump").await;
+        assert_eq!(
+            written.database.sha256,
+            hex::encode(Sha256::digest(b"PGDMP dump"))
+        );
+        assert_eq!(written.database.size, 10);
+
+        let verified = verify(&dir).await.unwrap();
+        assert_eq!(verified.database.sha256, written.database.sha256);
+
+        // Same size, different content
+        tokio::fs::write(dir.join(DATABASE_FILE), b"PGDMP dumq")
+            .await
+            .unwrap();
+        assert!(matches!(verify(&dir).await, Err(BackupError::Invalid(_))));
+
+        tokio::fs::remove_file(dir.join(DATABASE_FILE))
+            .await
+            .unwrap();
+        assert!(matches!(verify(&dir).await, Err(BackupError::Io(_))));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_verify_refuses_other_formats() {
+        let dir = std::env::temp_dir().join(format!("buildit-backup-{}", Uuid::new_v4()));
+        let mut manifest = write_backup(&dir, b"PGDMP dump").await;
+        manifest.version = FORMAT_VERSION + 1;
+        tokio::fs::write(
+            dir.join(MANIFEST_FILE),
+            serde_json::to_vec(&manifest).unwrap(),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(verify(&dir).await, Err(BackupError::Invalid(_))));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_create_refuses_a_directory_holding_a_backup() {
+        let dir = std::env::temp_dir().join(format!("buildit-backup-{}", Uuid::new_v4()));
+        write_backup(&dir, b"PGDMP dump").await;
+        let pool = PgPool::connect_lazy("postgres://localhost/buildit").unwrap();
+        let store = crate::services::artifacts::FilesystemArtifactStore::new(dir.join("store"));
+        let result = create(&pool, "postgres://localhost/buildit", &store, &dir, false).await;
+        assert!(matches!(result, Err(BackupError::Invalid(_))));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
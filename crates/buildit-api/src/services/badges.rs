@@ -0,0 +1,109 @@
+//! Status badges of pipelines, as SVG images and shields.io endpoint JSON.
+
+use serde_json::json;
+
+/// Width of a character of the badge font (11px Verdana), on average.
+const CHAR_WIDTH: f64 = 6.5;
+/// Space left and right of each half's text.
+const PADDING: u32 = 6;
+
+/// A two-part badge: a grey label and a coloured message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Badge {
+    pub label: String,
+    pub message: String,
+    /// A shields.io color name.
+    pub color: &'static str,
+}
+
+impl Badge {
+    /// Badge for the status of a pipeline's latest run, if it has one.
+    pub fn for_status(label: &str, status: Option<&str>) -> Self {
+        let (message, color) = match status {
+            Some("succeeded") => ("passing", "brightgreen"),
+            Some("failed") | Some("stalled") => ("failing", "red"),
+            Some("running") => ("running", "blue"),
+            Some("queued") => ("queued", "blue"),
+            Some("cancelled") => ("cancelled", "yellow"),
+            Some(other) => (other, "lightgrey"),
+            None => ("no runs", "lightgrey"),
+        };
+        Self {
+            label: label.to_string(),
+            message: message.to_string(),
+            color,
+        }
+    }
+
+    /// The badge in the shields.io endpoint format.
+    pub fn shields_json(&self) -> serde_json::Value {
+        json!({
+            "schemaVersion": 1,
+            "label": self.label,
+            "message": self.message,
+            "color": self.color,
+        })
+    }
+
+    /// The badge as an SVG image in the shields.io flat style.
+    pub fn svg(&self) -> String {
+        let label_width = text_width(&self.label);
+        let message_width = text_width(&self.message);
+        let width = label_width + message_width;
+        let label_x = label_width as f64 / 2.0;
+        let message_x = label_width as f64 + message_width as f64 / 2.0;
+        let label = escape(&self.label);
+        let message = escape(&self.message);
+        let color = hex_color(self.color);
+        format!(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="20" role="img" aria-label="{label}: {message}"><title>{label}: {message}</title><linearGradient id="s" x2="0" y2="100%"><stop offset="0" stop-color="#bbb" stop-opacity=".1"/><stop offset="1" stop-opacity=".1"/></linearGradient><clipPath id="r"><rect width="{width}" height="20" rx="3" fill="#fff"/></clipPath><g clip-path="url(#r)"><rect width="{label_width}" height="20" fill="#555"/><rect x="{label_width}" width="{message_width}" height="20" fill="{color}"/><rect width="{width}" height="20" fill="url(#s)"/></g><g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11"><text x="{label_x}" y="15" fill="#010101" fill-opacity=".3">{label}</text><text x="{label_x}" y="14">{label}</text><text x="{message_x}" y="15" fill="#010101" fill-opacity=".3">{message}</text><text x="{message_x}" y="14">{message}</text></g></svg>"##
+        )
+    }
+}
+
+/// Width of one half of a badge holding `text`.
+fn text_width(text: &str) -> u32 {
+    (text.chars().count() as f64 * CHAR_WIDTH).ceil() as u32 + 2 * PADDING
+}
+
+fn hex_color(name: &str) -> &'static str {
+    match name {
+        "brightgreen" => "#4c1",
+        "red" => "#e05d44",
+        "blue" => "#007ec6",
+        "yellow" => "#dfb317",
+        _ => "#9f9f9f",
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statuses_map_to_messages_and_colors() {
+        let badge = Badge::for_status("build", Some("stalled"));
+        assert_eq!((badge.message.as_str(), badge.color), ("failing", "red"));
+        let badge = Badge::for_status("build", None);
+        assert_eq!(
+            badge.shields_json(),
+            json!({"schemaVersion": 1, "label": "build", "message": "no runs", "color": "lightgrey"})
+        );
+        let badge = Badge::for_status("build", Some("succeeded"));
+        assert!(badge.svg().contains(r##"fill="#4c1""##));
+    }
+
+    #[test]
+    fn test_svg_escapes_its_text() {
+        let svg = Badge::for_status("<a & b>", Some("\"x\"")).svg();
+        assert!(svg.contains("&lt;a &amp; b&gt;: &quot;x&quot;"));
+        assert!(!svg.contains("<a & b>"));
+    }
+}
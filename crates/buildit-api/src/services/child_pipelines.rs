@@ -0,0 +1,154 @@
+//! Starting the runs of trigger-pipeline stages.
+//!
+//! A child run is a run of another pipeline of the parent's tenant. Its
+//! trigger info names the run and stage that started it, and the chain of
+//! pipelines above it: a pipeline already in the chain would form a cycle
+//! and is refused, as is a chain deeper than [`MAX_CHILD_DEPTH`]. Each stage
+//! of a run starts at most one child run, so a resumed run waits on the
+//! child it already started.
+
+use async_trait::async_trait;
+use buildit_config::params::resolve_params;
+use buildit_core::ResourceId;
+use buildit_core::child_pipeline::{
+    ChildRun, ChildRunRequest, ChildRunStatus, MAX_CHILD_DEPTH, PipelineLauncher,
+};
+use buildit_core::pipeline::PipelineParam;
+use buildit_db::PipelineRepo;
+use std::collections::HashMap;
+use tracing::info;
+use uuid::Uuid;
+
+use super::tasks;
+use crate::AppState;
+
+/// Starts child runs and reports on them.
+pub struct ChildPipelineService {
+    state: AppState,
+}
+
+impl ChildPipelineService {
+    pub fn new(state: AppState) -> Self {
+        Self { state }
+    }
+}
+
+/// Pipelines above a run, from its trigger info.
+fn ancestors(trigger_info: &serde_json::Value) -> Vec<Uuid> {
+    trigger_info
+        .get("chain")
+        .and_then(|c| serde_json::from_value(c.clone()).ok())
+        .unwrap_or_default()
+}
+
+#[async_trait]
+impl PipelineLauncher for ChildPipelineService {
+    async fn launch(&self, request: &ChildRunRequest) -> Result<ChildRun, String> {
+        let repo = &self.state.pipeline_repo;
+        let spec = &request.spec;
+        let parent = repo
+            .get_by_id(request.pipeline_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        let parent_run = repo
+            .get_run(request.run_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        let target = repo
+            .list_by_tenant(ResourceId::from_uuid(parent.tenant_id))
+            .await
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .find(|p| p.name == spec.pipeline)
+            .ok_or_else(|| format!("the tenant has no active pipeline '{}'", spec.pipeline))?;
+
+        let mut chain = ancestors(&parent_run.trigger_info);
+        chain.push(parent.id);
+        if chain.contains(&target.id) {
+            return Err(format!(
+                "{} is already running above this run; triggering it would form a cycle",
+                spec.pipeline
+            ));
+        }
+        if chain.len() >= MAX_CHILD_DEPTH {
+            return Err(format!(
+                "runs triggered by other pipelines can nest at most {} deep",
+                MAX_CHILD_DEPTH
+            ));
+        }
+
+        let declared: Vec<PipelineParam> = target
+            .config
+            .get("params")
+            .and_then(|p| serde_json::from_value(p.clone()).ok())
+            .unwrap_or_default();
+        let given: HashMap<String, String> = spec.params.clone().into_iter().collect();
+        let params = resolve_params(&declared, &given).map_err(|e| e.to_string())?;
+
+        let mut trigger_info = serde_json::json!({
+            "kind": "pipeline",
+            "actor": parent.name,
+            "parent": {
+                "pipeline_id": parent.id,
+                "pipeline": parent.name,
+                "run_id": parent_run.id,
+                "run_number": parent_run.number,
+                "stage": request.stage,
+            },
+            "chain": chain,
+        });
+        if !params.is_empty() {
+            trigger_info["params"] = serde_json::json!(params);
+        }
+        let git_info = serde_json::json!({
+            "branch": spec.branch.clone().unwrap_or_default(),
+            "sha": "",
+            "short_sha": "",
+            "message": format!("Triggered by {} #{}", parent.name, parent_run.number),
+            "author": ""
+        });
+
+        let key = format!("pipeline:{}:{}", parent_run.id, request.stage);
+        let (run, created) = repo
+            .create_run_once(
+                ResourceId::from_uuid(target.id),
+                &key,
+                trigger_info,
+                git_info,
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        if created {
+            tasks::enqueue_run(&self.state, &run)
+                .await
+                .map_err(|e| format!("failed to queue the run: {:?}", e))?;
+            info!(
+                parent_run_id = %parent_run.id,
+                pipeline = %target.name,
+                run_id = %run.id,
+                "Triggered child pipeline run"
+            );
+        }
+
+        Ok(ChildRun {
+            pipeline_id: ResourceId::from_uuid(target.id),
+            run_id: ResourceId::from_uuid(run.id),
+            number: run.number,
+        })
+    }
+
+    async fn status(&self, run: &ChildRun) -> Result<ChildRunStatus, String> {
+        let record = self
+            .state
+            .pipeline_repo
+            .get_run(run.run_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(match record.status.as_str() {
+            "succeeded" => ChildRunStatus::Succeeded,
+            "failed" | "stalled" => ChildRunStatus::Failed,
+            "cancelled" => ChildRunStatus::Cancelled,
+            _ => ChildRunStatus::Pending,
+        })
+    }
+}
@@ -0,0 +1,270 @@
+//! Kubernetes clusters of deployment targets.
+//!
+//! Every environment deploys to a target. A Kubernetes target with stored
+//! credentials is its own cluster, reached with a client built from them;
+//! targets without credentials, and targets of other kinds, go through the
+//! server's own deployer. Clients are cached per target and rebuilt once
+//! the target is changed, so rotated credentials take effect on the next
+//! deployment.
+
+use buildit_core::ResourceId;
+use buildit_core::deployer::Deployer;
+use buildit_core::target::TargetKind;
+use buildit_db::{DeploymentRepo, Environment, PgDeploymentRepo, Target};
+use buildit_deployer::kubernetes::KubernetesDeployer;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+use crate::services::credentials::CredentialCipher;
+use crate::services::targets;
+
+/// Namespace deployments go to when neither the environment nor the
+/// target names one.
+const DEFAULT_NAMESPACE: &str = "default";
+
+/// Namespace a target's deployments go to.
+pub fn namespace(target: &Target) -> &str {
+    target
+        .config
+        .get("namespace")
+        .and_then(|n| n.as_str())
+        .unwrap_or(DEFAULT_NAMESPACE)
+}
+
+/// A client built for a target, valid while the target is unchanged.
+struct CachedCluster {
+    updated_at: DateTime<Utc>,
+    client: kube::Client,
+    deployer: Arc<dyn Deployer>,
+}
+
+/// Resolves the cluster, and the deployer for it, of targets and
+/// environments.
+pub struct Clusters {
+    deployment_repo: Arc<PgDeploymentRepo>,
+    cipher: Option<Arc<CredentialCipher>>,
+    /// The server's own deployer, for targets without credentials.
+    default_deployer: Option<Arc<dyn Deployer>>,
+    cache: Mutex<HashMap<Uuid, CachedCluster>>,
+}
+
+impl Clusters {
+    pub fn new(
+        deployment_repo: Arc<PgDeploymentRepo>,
+        cipher: Option<Arc<CredentialCipher>>,
+        default_deployer: Option<Arc<dyn Deployer>>,
+    ) -> Self {
+        Self {
+            deployment_repo,
+            cipher,
+            default_deployer,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether anything can be deployed: with the server's own deployer,
+    /// or to targets whose stored credentials can be opened.
+    pub fn available(&self) -> bool {
+        self.default_deployer.is_some() || self.cipher.is_some()
+    }
+
+    /// Client for a Kubernetes target's own cluster, or `None` when it has
+    /// no credentials and the server's own cluster is meant.
+    pub async fn client(&self, target: &Target) -> Result<Option<kube::Client>, String> {
+        Ok(self.cluster(target).await?.map(|(client, _)| client))
+    }
+
+    /// Deployer for a target: one for its cluster, or the server's own.
+    pub async fn deployer(&self, target: &Target) -> Result<Arc<dyn Deployer>, String> {
+        match self.cluster(target).await? {
+            Some((_, deployer)) => Ok(deployer),
+            None => self
+                .default_deployer
+                .clone()
+                .ok_or_else(|| format!("no deployer is configured for target {}", target.name)),
+        }
+    }
+
+    /// Deployer for the target of an environment.
+    pub async fn for_environment(
+        &self,
+        environment: &Environment,
+    ) -> Result<Arc<dyn Deployer>, String> {
+        let target = self
+            .deployment_repo
+            .get_target(ResourceId::from_uuid(environment.target_id))
+            .await
+            .map_err(|e| e.to_string())?;
+        self.deployer(&target).await
+    }
+
+    /// Deployer for an environment by ID, or the server's own when there
+    /// is none, e.g. for applications not tied to an environment.
+    pub async fn for_environment_id(
+        &self,
+        environment_id: Option<Uuid>,
+    ) -> Result<Arc<dyn Deployer>, String> {
+        match environment_id {
+            Some(id) => {
+                let environment = self
+                    .deployment_repo
+                    .get_environment(ResourceId::from_uuid(id))
+                    .await
+                    .map_err(|e| e.to_string())?;
+                self.for_environment(&environment).await
+            }
+            None => self
+                .default_deployer
+                .clone()
+                .ok_or_else(|| "no deployer is configured".to_string()),
+        }
+    }
+
+    /// Client and deployer for a target with its own cluster, from the
+    /// cache while the target is unchanged.
+    async fn cluster(
+        &self,
+        target: &Target,
+    ) -> Result<Option<(kube::Client, Arc<dyn Deployer>)>, String> {
+        if target.credentials.is_none() || targets::target_kind(target)? != TargetKind::Kubernetes {
+            return Ok(None);
+        }
+        if let Some(cached) = self.cache.lock().unwrap().get(&target.id)
+            && cached.updated_at == target.updated_at
+        {
+            return Ok(Some((cached.client.clone(), cached.deployer.clone())));
+        }
+
+        let credentials = targets::stored_credentials(self.cipher.as_deref(), target)?;
+        let client = targets::kube_client(credentials.as_ref())
+            .await
+            .map_err(|e| format!("cannot reach cluster of target {}: {}", target.name, e))?;
+        let namespace = namespace(target);
+        let deployer: Arc<dyn Deployer> =
+            Arc::new(KubernetesDeployer::with_client(client.clone(), namespace));
+        tracing::info!(target = %target.name, namespace = %namespace, "Connected to target cluster");
+
+        self.cache.lock().unwrap().insert(
+            target.id,
+            CachedCluster {
+                updated_at: target.updated_at,
+                client: client.clone(),
+                deployer: deployer.clone(),
+            },
+        );
+        Ok(Some((client, deployer)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use buildit_core::target::TargetCredentials;
+    use sqlx::PgPool;
+
+    use super::*;
+
+    fn target(kind: &str, credentials: Option<Vec<u8>>) -> Target {
+        Target {
+            id: Uuid::new_v4(),
+            tenant_id: Uuid::new_v4(),
+            name: "prod-eu".to_string(),
+            target_type: kind.to_string(),
+            status: "connected".to_string(),
+            region: None,
+            config: serde_json::json!({"namespace": "shop"}),
+            credentials,
+            status_message: None,
+            checked_at: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn clusters(
+        cipher: Option<Arc<CredentialCipher>>,
+        default_deployer: Option<Arc<dyn Deployer>>,
+    ) -> Clusters {
+        let pool = PgPool::connect_lazy("postgres://localhost/buildit").unwrap();
+        Clusters::new(
+            Arc::new(PgDeploymentRepo::new(pool)),
+            cipher,
+            default_deployer,
+        )
+    }
+
+    fn sealed_service_account(cipher: &CredentialCipher) -> Vec<u8> {
+        cipher
+            .seal(&TargetCredentials::ServiceAccount {
+                server: "https://10.0.0.1:6443".to_string(),
+                token: "token".to_string(),
+                ca_cert: None,
+            })
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_targets_without_credentials_use_the_servers_deployer() {
+        let config = kube::Config::new("https://127.0.0.1:6443".parse().unwrap());
+        let client = kube::Client::try_from(config).unwrap();
+        let server: Arc<dyn Deployer> =
+            Arc::new(KubernetesDeployer::with_client(client, "default"));
+        let with_server = clusters(None, Some(server.clone()));
+
+        let deployer = with_server
+            .deployer(&target("kubernetes", None))
+            .await
+            .unwrap();
+        assert!(Arc::ptr_eq(&deployer, &server));
+        assert!(
+            with_server
+                .client(&target("kubernetes", None))
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let without = clusters(None, None);
+        assert!(!without.available());
+        assert!(without.deployer(&target("kubernetes", None)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_clusters_of_targets_are_rebuilt_when_they_change() {
+        let cipher = Arc::new(CredentialCipher::new(b"key"));
+        let clusters = clusters(Some(cipher.clone()), None);
+        let mut target = target("kubernetes", Some(sealed_service_account(&cipher)));
+
+        let first = clusters.deployer(&target).await.unwrap();
+        assert!(Arc::ptr_eq(
+            &first,
+            &clusters.deployer(&target).await.unwrap()
+        ));
+        target.updated_at += chrono::Duration::seconds(1);
+        assert!(!Arc::ptr_eq(
+            &first,
+            &clusters.deployer(&target).await.unwrap()
+        ));
+        assert_eq!(namespace(&target), "shop");
+    }
+
+    #[tokio::test]
+    async fn test_stored_credentials_need_the_key() {
+        let sealed = sealed_service_account(&CredentialCipher::new(b"key"));
+        let without_key = clusters(None, None);
+        assert!(
+            without_key
+                .deployer(&target("kubernetes", Some(sealed.clone())))
+                .await
+                .is_err()
+        );
+        let wrong_key = clusters(Some(Arc::new(CredentialCipher::new(b"other"))), None);
+        assert!(
+            wrong_key
+                .deployer(&target("kubernetes", Some(sealed)))
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,126 @@
+//! Encryption of credentials stored in the database.
+//!
+//! Credentials are sealed with AES-256-GCM under a key derived from
+//! `BUILDIT_ENCRYPTION_KEY`, or from the `key-file` of the system
+//! configuration's `local` secret store. Without a key nothing can be
+//! stored encrypted, so requests carrying credentials are refused rather
+//! than storing them in the clear. Changing the key makes stored
+//! credentials unreadable.
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use buildit_config::system::SecretStoreConfig;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+
+/// First byte of sealed data, so the format can change later.
+const VERSION: u8 = 1;
+const NONCE_LEN: usize = 12;
+
+/// Seals and opens credentials.
+pub struct CredentialCipher {
+    cipher: Aes256Gcm,
+}
+
+impl CredentialCipher {
+    pub fn new(secret: &[u8]) -> Self {
+        let key = Sha256::digest(secret);
+        Self {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)),
+        }
+    }
+
+    /// Cipher keyed by `BUILDIT_ENCRYPTION_KEY`, else by the secret
+    /// store's key file, if either is set.
+    pub fn from_config(config: Option<&SecretStoreConfig>) -> Result<Option<Self>, String> {
+        if let Some(key) = std::env::var("BUILDIT_ENCRYPTION_KEY")
+            .ok()
+            .filter(|s| !s.is_empty())
+        {
+            return Ok(Some(Self::new(key.as_bytes())));
+        }
+        let Some(path) = config.and_then(|c| c.key_file.as_deref()) else {
+            return Ok(None);
+        };
+        let key = std::fs::read(path)
+            .map_err(|e| format!("cannot read encryption key file {}: {}", path, e))?;
+        let key = key.trim_ascii();
+        if key.is_empty() {
+            return Err(format!("encryption key file {} is empty", path));
+        }
+        Ok(Some(Self::new(key)))
+    }
+
+    /// Encrypt a value as JSON: the version, a random nonce and the
+    /// ciphertext.
+    pub fn seal<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, String> {
+        let plaintext = serde_json::to_vec(value).map_err(|e| e.to_string())?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext.as_slice())
+            .map_err(|_| "encryption failed".to_string())?;
+        let mut sealed = Vec::with_capacity(1 + NONCE_LEN + ciphertext.len());
+        sealed.push(VERSION);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    /// Decrypt a value sealed with the same key.
+    pub fn open<T: DeserializeOwned>(&self, sealed: &[u8]) -> Result<T, String> {
+        let (version, rest) = sealed
+            .split_first()
+            .ok_or_else(|| "empty credentials".to_string())?;
+        if *version != VERSION || rest.len() < NONCE_LEN {
+            return Err("unsupported credentials format".to_string());
+        }
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "credentials cannot be decrypted; was BUILDIT_ENCRYPTION_KEY changed?")?;
+        serde_json::from_slice(&plaintext).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn credentials() -> BTreeMap<String, String> {
+        BTreeMap::from([("token".to_string(), "fly-abc123".to_string())])
+    }
+
+    #[test]
+    fn test_sealed_credentials_open_with_the_same_key() {
+        let cipher = CredentialCipher::new(b"first key");
+        let sealed = cipher.seal(&credentials()).unwrap();
+        assert_eq!(sealed[0], VERSION);
+        assert!(!String::from_utf8_lossy(&sealed).contains("fly-abc123"));
+        // Each seal uses a new nonce
+        assert_ne!(sealed, cipher.seal(&credentials()).unwrap());
+
+        let opened: BTreeMap<String, String> = cipher.open(&sealed).unwrap();
+        assert_eq!(opened, credentials());
+    }
+
+    #[test]
+    fn test_credentials_do_not_open_with_another_key_or_when_tampered() {
+        let sealed = CredentialCipher::new(b"first key")
+            .seal(&credentials())
+            .unwrap();
+        let other = CredentialCipher::new(b"second key");
+        assert!(other.open::<BTreeMap<String, String>>(&sealed).is_err());
+
+        let cipher = CredentialCipher::new(b"first key");
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(cipher.open::<BTreeMap<String, String>>(&tampered).is_err());
+        assert!(cipher.open::<BTreeMap<String, String>>(&[]).is_err());
+        assert!(cipher.open::<BTreeMap<String, String>>(&[2, 0, 0]).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+//! Debug sessions of failed stages.
+//!
+//! A debug session relaunches a failed stage's job with the same image,
+//! environment and workspace, but idling instead of running the stage's
+//! commands, so a user can open a shell in it and look around. The job is
+//! spawned by a [`Task::DebugSession`](crate::services::tasks::Task) task,
+//! which stays deferred until the session expires and then cancels the job.
+//! Closing a session makes its task due at once.
+
+use buildit_core::ResourceId;
+use buildit_db::{DbError, PipelineRepo};
+use buildit_scheduler::TaskContext;
+use chrono::Utc;
+
+use crate::AppState;
+use crate::services::pipeline_runner;
+
+/// Minutes a session lasts unless asked otherwise.
+pub const DEFAULT_TTL_MINUTES: i64 = 30;
+
+/// Longest a session may last.
+pub const MAX_TTL_MINUTES: i64 = 120;
+
+/// Stage statuses a debug session can be opened for.
+pub const DEBUGGABLE: &[&str] = &["failed", "stalled"];
+
+/// Spawn a session's job, or cancel it once the session is closed or has
+/// expired.
+#[tracing::instrument(name = "debug_session", skip(state, task), fields(session_id = %session_id))]
+pub async fn run(
+    state: &AppState,
+    session_id: ResourceId,
+    task: &TaskContext,
+) -> Result<(), String> {
+    let Some(orchestrator) = state.orchestrator.clone() else {
+        return Err("no executor is configured".to_string());
+    };
+    let pipeline_repo = &state.pipeline_repo;
+    let session = match pipeline_repo.get_debug_session(session_id).await {
+        Ok(session) => session,
+        // Deleted with its run
+        Err(DbError::NotFound(_)) => return Ok(()),
+        Err(e) => return Err(e.to_string()),
+    };
+
+    if !session.is_finished() && Utc::now() >= session.expires_at {
+        tracing::info!(session_id = %session_id, "Debug session expired");
+        pipeline_repo
+            .finish_debug_session(session_id, "expired", None)
+            .await
+            .map_err(|e| e.to_string())?;
+    } else if !session.is_finished() {
+        if session.job_id.is_none() && !spawn(state, &session).await? {
+            return Ok(());
+        }
+        return task.defer_until(session.expires_at).await;
+    }
+
+    // The session is over; its job goes with it
+    if let Some(handle) = session.job_handle()
+        && let Some(executor) = orchestrator.executors().get(&handle.executor_name)
+        && let Err(e) = executor.cancel(&handle).await
+    {
+        tracing::warn!(session_id = %session_id, error = %e, "Failed to cancel debug job");
+    }
+    Ok(())
+}
+
+/// Spawn the idle copy of the session's stage, returning whether it
+/// started. A stage that can no longer be spawned fails the session rather
+/// than the task.
+async fn spawn(state: &AppState, session: &buildit_db::DebugSessionRecord) -> Result<bool, String> {
+    let Some(orchestrator) = state.orchestrator.clone() else {
+        return Err("no executor is configured".to_string());
+    };
+    let pipeline_repo = &state.pipeline_repo;
+    let session_id = ResourceId::from_uuid(session.id);
+    let run = pipeline_repo
+        .get_run(ResourceId::from_uuid(session.pipeline_run_id))
+        .await
+        .map_err(|e| e.to_string())?;
+    let record = pipeline_repo
+        .get_by_id(ResourceId::from_uuid(run.pipeline_id))
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut pipeline = pipeline_runner::load_pipeline(state, &record)
+        .await
+        .map_err(|e| e.to_string())?;
+    let git_clone = pipeline_runner::git_clone_spec(state, &record, &pipeline.checkout, &run).await;
+    let (env, var_ctx, _) = pipeline_runner::run_environment(state, &mut pipeline, &run)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let idle = (session.expires_at - Utc::now())
+        .to_std()
+        .unwrap_or_default();
+    match orchestrator
+        .spawn_debug(
+            &pipeline,
+            &session.stage_name,
+            env,
+            Some(var_ctx),
+            git_clone,
+            idle,
+        )
+        .await
+    {
+        Ok(dispatch) => {
+            tracing::info!(session_id = %session_id, stage = %session.stage_name, executor = %dispatch.routing.executor, "Debug job started");
+            pipeline_repo
+                .record_debug_job(session_id, &dispatch.handle)
+                .await
+                .map(|_| true)
+                .map_err(|e| e.to_string())
+        }
+        Err(e) => {
+            tracing::warn!(session_id = %session_id, error = %e, "Failed to start debug job");
+            pipeline_repo
+                .finish_debug_session(session_id, "failed", Some(&e))
+                .await
+                .map(|_| false)
+                .map_err(|e| e.to_string())
+        }
+    }
+}
@@ -0,0 +1,448 @@
+//! What changed between two deployments of a service.
+//!
+//! A diff compares the specs the two deployments rolled out: the image,
+//! split into repository, tag and digest; the environment variables; the
+//! rest of the spec field by field; and the manifests the Kubernetes
+//! deployer applies for them, line by line. Image-based deployments are
+//! rendered as the Deployment object of one color, so the manifests of two
+//! rollouts line up. Manifest directories are read from the repository at
+//! deploy time and aren't recorded, so only their settings are compared.
+
+use buildit_core::ResourceId;
+use buildit_core::deployer::DeploymentSource;
+use buildit_db::{DbResult, Deployment, DeploymentRepo, PgDeploymentRepo};
+use buildit_deployer::blue_green::{self, Color};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+use super::clusters;
+use super::tasks::ServiceSpecDocument;
+use crate::routes::deployment::{SpecChange, diff_json};
+
+/// Changed lines of a manifest beyond which no line diff is computed.
+const MAX_CHANGED_LINES: usize = 2000;
+
+/// Unchanged lines kept around each change of a manifest diff.
+const CONTEXT_LINES: usize = 3;
+
+/// Differences between two deployments of a service.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DeploymentDiff {
+    pub from: DiffedDeployment,
+    pub to: DiffedDeployment,
+    /// The image change, when the image differs.
+    pub image: Option<ImageChange>,
+    /// Environment variables added, removed or changed, by name.
+    pub env: Vec<EnvChange>,
+    /// Other fields of the spec that differ.
+    pub spec: Vec<SpecChange>,
+    /// Line diff of the rendered manifests, with unchanged stretches
+    /// collapsed, and empty when they're the same; `None` when either side
+    /// can't be rendered or too much of them changed.
+    pub manifest: Option<Vec<ManifestLine>>,
+}
+
+/// One side of a diff.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DiffedDeployment {
+    pub id: uuid::Uuid,
+    pub version: String,
+    pub environment: String,
+    pub status: String,
+    pub commit_sha: Option<String>,
+    pub spec_version: Option<i32>,
+    pub image: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A container image reference taken apart.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct ImageRef {
+    pub repository: String,
+    pub tag: Option<String>,
+    /// `sha256:...` when the image is pinned by digest.
+    pub digest: Option<String>,
+}
+
+impl ImageRef {
+    /// Split `repository[:tag][@digest]`. A colon before the last `/` is a
+    /// registry port, not a tag.
+    pub fn parse(image: &str) -> Self {
+        let (name, digest) = match image.split_once('@') {
+            Some((name, digest)) => (name, Some(digest.to_string())),
+            None => (image, None),
+        };
+        match name.rsplit_once(':') {
+            Some((repository, tag)) if !tag.contains('/') => Self {
+                repository: repository.to_string(),
+                tag: Some(tag.to_string()),
+                digest,
+            },
+            _ => Self {
+                repository: name.to_string(),
+                tag: None,
+                digest,
+            },
+        }
+    }
+}
+
+/// How the image changed; either side is `None` when its spec has no image.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ImageChange {
+    pub from: Option<ImageRef>,
+    pub to: Option<ImageRef>,
+    pub repository_changed: bool,
+    pub tag_changed: bool,
+    pub digest_changed: bool,
+}
+
+/// A changed environment variable.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct EnvChange {
+    pub name: String,
+    /// `added`, `removed` or `changed`.
+    pub change: &'static str,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// A line of a manifest diff.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct ManifestLine {
+    /// `context`, `added` or `removed`, or `skipped` for a stretch of
+    /// unchanged lines, whose text says how many.
+    pub kind: &'static str,
+    pub text: String,
+}
+
+impl ManifestLine {
+    fn new(kind: &'static str, text: &str) -> Self {
+        Self {
+            kind,
+            text: text.to_string(),
+        }
+    }
+}
+
+fn image(config: &serde_json::Value) -> Option<String> {
+    config
+        .get("image")
+        .and_then(|i| i.as_str())
+        .filter(|i| !i.is_empty())
+        .map(str::to_string)
+}
+
+fn env(config: &serde_json::Value) -> BTreeMap<String, String> {
+    config
+        .get("env")
+        .and_then(|e| serde_json::from_value(e.clone()).ok())
+        .unwrap_or_default()
+}
+
+fn compare_images(from: Option<&str>, to: Option<&str>) -> Option<ImageChange> {
+    if from == to {
+        return None;
+    }
+    let from = from.map(ImageRef::parse);
+    let to = to.map(ImageRef::parse);
+    let field =
+        |f: fn(&ImageRef) -> Option<&str>| from.as_ref().and_then(f) != to.as_ref().and_then(f);
+    Some(ImageChange {
+        repository_changed: field(|i| Some(&i.repository)),
+        tag_changed: field(|i| i.tag.as_deref()),
+        digest_changed: field(|i| i.digest.as_deref()),
+        from,
+        to,
+    })
+}
+
+fn compare_env(from: &BTreeMap<String, String>, to: &BTreeMap<String, String>) -> Vec<EnvChange> {
+    let mut names: Vec<&String> = from.keys().chain(to.keys()).collect();
+    names.sort();
+    names.dedup();
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let (old, new) = (from.get(name), to.get(name));
+            let change = match (old, new) {
+                (Some(old), Some(new)) if old == new => return None,
+                (Some(_), Some(_)) => "changed",
+                (Some(_), None) => "removed",
+                (None, _) => "added",
+            };
+            Some(EnvChange {
+                name: name.clone(),
+                change,
+                old: old.cloned(),
+                new: new.cloned(),
+            })
+        })
+        .collect()
+}
+
+/// Spec fields other than those compared on their own.
+fn other_fields(config: &serde_json::Value) -> serde_json::Value {
+    let mut config = config.clone();
+    if let Some(fields) = config.as_object_mut() {
+        fields.remove("image");
+        fields.remove("env");
+    }
+    config
+}
+
+/// Line diff of two texts, keeping [`CONTEXT_LINES`] unchanged lines
+/// around each change and collapsing the rest; `None` when the changed
+/// stretch of either text is longer than [`MAX_CHANGED_LINES`].
+pub(crate) fn diff_lines(old: &str, new: &str) -> Option<Vec<ManifestLine>> {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (old_changed, new_changed) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+    if old_changed.is_empty() && new_changed.is_empty() {
+        return Some(vec![]);
+    }
+    if old_changed.len() > MAX_CHANGED_LINES || new_changed.len() > MAX_CHANGED_LINES {
+        return None;
+    }
+
+    // Longest common subsequence lengths of every pair of suffixes
+    let (m, n) = (old_changed.len(), new_changed.len());
+    let mut lcs = vec![vec![0u32; n + 1]; m + 1];
+    for i in (0..m).rev() {
+        for j in (0..n).rev() {
+            lcs[i][j] = if old_changed[i] == new_changed[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut lines: Vec<ManifestLine> = a[..prefix]
+        .iter()
+        .map(|l| ManifestLine::new("context", l))
+        .collect();
+    let (mut i, mut j) = (0, 0);
+    while i < m || j < n {
+        if i < m && j < n && old_changed[i] == new_changed[j] {
+            lines.push(ManifestLine::new("context", old_changed[i]));
+            i += 1;
+            j += 1;
+        } else if i < m && (j == n || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            // Removed lines come before the lines replacing them
+            lines.push(ManifestLine::new("removed", old_changed[i]));
+            i += 1;
+        } else {
+            lines.push(ManifestLine::new("added", new_changed[j]));
+            j += 1;
+        }
+    }
+    lines.extend(
+        a[a.len() - suffix..]
+            .iter()
+            .map(|l| ManifestLine::new("context", l)),
+    );
+
+    // Keep context lines near a change
+    let changed: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, l)| l.kind != "context")
+        .map(|(n, _)| n)
+        .collect();
+    let near_change = |n: usize| changed.iter().any(|&c| c.abs_diff(n) <= CONTEXT_LINES);
+    let mut collapsed = Vec::new();
+    let mut skipped = 0;
+    for (n, line) in lines.into_iter().enumerate() {
+        if line.kind == "context" && !near_change(n) {
+            skipped += 1;
+            continue;
+        }
+        if skipped > 0 {
+            collapsed.push(ManifestLine::new("skipped", &skipped_text(skipped)));
+            skipped = 0;
+        }
+        collapsed.push(line);
+    }
+    if skipped > 0 {
+        collapsed.push(ManifestLine::new("skipped", &skipped_text(skipped)));
+    }
+    Some(collapsed)
+}
+
+fn skipped_text(lines: usize) -> String {
+    match lines {
+        1 => "1 unchanged line".to_string(),
+        n => format!("{} unchanged lines", n),
+    }
+}
+
+/// Diffs deployments from the deployment repository.
+pub struct DeploymentDiffService {
+    deployment_repo: Arc<PgDeploymentRepo>,
+}
+
+impl DeploymentDiffService {
+    pub fn new(deployment_repo: Arc<PgDeploymentRepo>) -> Self {
+        Self { deployment_repo }
+    }
+
+    /// Diff two deployments of the same service.
+    pub async fn diff(&self, from: &Deployment, to: &Deployment) -> DbResult<DeploymentDiff> {
+        let service = self
+            .deployment_repo
+            .get_service(ResourceId::from_uuid(to.service_id))
+            .await?;
+        let from_manifest = self.render(from, &service.name).await?;
+        let to_manifest = self.render(to, &service.name).await?;
+        let manifest = match (from_manifest, to_manifest) {
+            (Some(old), Some(new)) => diff_lines(&old, &new),
+            _ => None,
+        };
+
+        let mut spec = Vec::new();
+        diff_json(
+            "",
+            &other_fields(&from.config),
+            &other_fields(&to.config),
+            &mut spec,
+        );
+        Ok(DeploymentDiff {
+            from: self.side(from).await?,
+            to: self.side(to).await?,
+            image: compare_images(image(&from.config).as_deref(), image(&to.config).as_deref()),
+            env: compare_env(&env(&from.config), &env(&to.config)),
+            spec,
+            manifest,
+        })
+    }
+
+    async fn side(&self, deployment: &Deployment) -> DbResult<DiffedDeployment> {
+        let environment = self
+            .deployment_repo
+            .get_environment(ResourceId::from_uuid(deployment.environment_id))
+            .await?;
+        Ok(DiffedDeployment {
+            id: deployment.id,
+            version: deployment.version.clone(),
+            environment: environment.name,
+            status: deployment.status.clone(),
+            commit_sha: deployment.commit_sha.clone(),
+            spec_version: deployment.spec_version,
+            image: image(&deployment.config),
+            created_at: deployment.created_at,
+        })
+    }
+
+    /// The manifests the Kubernetes deployer applies for a deployment, as
+    /// YAML, or `None` when they aren't known ahead of the rollout.
+    async fn render(&self, deployment: &Deployment, service: &str) -> DbResult<Option<String>> {
+        let Ok(doc) = serde_json::from_value::<ServiceSpecDocument>(deployment.config.clone())
+        else {
+            return Ok(None);
+        };
+        let environment = self
+            .deployment_repo
+            .get_environment(ResourceId::from_uuid(deployment.environment_id))
+            .await?;
+        let target = self
+            .deployment_repo
+            .get_target(ResourceId::from_uuid(environment.target_id))
+            .await?;
+        let spec = doc.into_spec(
+            ResourceId::from_uuid(deployment.id),
+            service.to_string(),
+            environment.name,
+        );
+        Ok(match &spec.source {
+            DeploymentSource::Image if !spec.image.is_empty() => {
+                let object =
+                    blue_green::build_deployment(&spec, clusters::namespace(&target), Color::Blue);
+                serde_yaml::to_string(&object).ok()
+            }
+            DeploymentSource::Rendered { documents, .. } => Some(documents.clone()),
+            _ => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_image_references() {
+        assert_eq!(
+            ImageRef::parse("localhost:5000/shop/api:1.2@sha256:abc"),
+            ImageRef {
+                repository: "localhost:5000/shop/api".to_string(),
+                tag: Some("1.2".to_string()),
+                digest: Some("sha256:abc".to_string()),
+            }
+        );
+        assert_eq!(ImageRef::parse("localhost:5000/shop/api").tag, None);
+
+        let change = compare_images(Some("shop/api:1.2"), Some("shop/api:1.3")).unwrap();
+        assert!(change.tag_changed);
+        assert!(!change.repository_changed && !change.digest_changed);
+        assert!(compare_images(Some("shop/api:1.2"), Some("shop/api:1.2")).is_none());
+    }
+
+    #[test]
+    fn test_env_changes() {
+        let from = env(&json!({"env": {"A": "1", "B": "2", "C": "3"}}));
+        let to = env(&json!({"env": {"A": "1", "B": "20", "D": "4"}}));
+        let changes: Vec<_> = compare_env(&from, &to)
+            .into_iter()
+            .map(|c| (c.name, c.change))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ("B".to_string(), "changed"),
+                ("C".to_string(), "removed"),
+                ("D".to_string(), "added"),
+            ]
+        );
+        assert_eq!(
+            other_fields(&json!({"image": "x", "env": {}, "replicas": 2})),
+            json!({"replicas": 2})
+        );
+    }
+
+    #[test]
+    fn test_manifest_diff_keeps_context_near_changes() {
+        let old: String = (1..=20).map(|n| format!("line {}\n", n)).collect();
+        let new = old.replace("line 10\n", "line ten\n");
+        let lines = diff_lines(&old, &new).unwrap();
+        let kinds: Vec<_> = lines.iter().map(|l| l.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                "skipped", "context", "context", "context", "removed", "added", "context",
+                "context", "context", "skipped",
+            ]
+        );
+        assert_eq!(lines[0].text, "6 unchanged lines");
+        assert_eq!(lines[4].text, "line 10");
+        assert_eq!(lines[5].text, "line ten");
+        assert_eq!(lines[9].text, "7 unchanged lines");
+
+        assert_eq!(diff_lines(&old, &old), Some(vec![]));
+        let huge: String = (0..=MAX_CHANGED_LINES)
+            .map(|n| format!("{}\n", n))
+            .collect();
+        assert_eq!(diff_lines("", &huge), None);
+    }
+}
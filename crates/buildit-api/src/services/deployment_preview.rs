@@ -0,0 +1,133 @@
+//! What a deployment would change, before making it.
+//!
+//! A preview builds the spec a deployment would roll out (the service's
+//! current spec, with another image if one is given) and has the
+//! environment's deployer dry-run it. On Kubernetes every object goes
+//! through a server-side apply dry run and is compared with the live one,
+//! so defaults and admission webhooks show up as they would on deploy.
+//! Nothing is recorded and nothing in the cluster changes; Secret values
+//! are redacted.
+
+use buildit_core::ResourceId;
+use buildit_core::deployer::ObjectPreview;
+use buildit_db::{DeploymentRepo, Environment, Service};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::AppState;
+use crate::error::ApiError;
+use crate::services::deployment_diff::{ManifestLine, diff_lines};
+use crate::services::manifest_deploy;
+use crate::services::tasks::ServiceSpecDocument;
+
+/// The changes a deployment would make.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DeploymentPreview {
+    pub service: String,
+    pub environment: String,
+    /// Spec version the deployment would roll out.
+    pub spec_version: Option<i32>,
+    pub image: Option<String>,
+    /// Every object the deployment applies, in apply order.
+    pub changes: Vec<ObjectChange>,
+}
+
+/// An object a deployment applies.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ObjectChange {
+    pub kind: String,
+    pub name: String,
+    pub namespace: Option<String>,
+    /// `create`, `update` or `unchanged`.
+    pub action: &'static str,
+    /// Line diff of the object's YAML, all added for a new object; `None`
+    /// when the change is too large to diff.
+    pub diff: Option<Vec<ManifestLine>>,
+}
+
+impl ObjectChange {
+    fn new(object: ObjectPreview) -> Self {
+        let yaml = |value: &serde_json::Value| serde_yaml::to_string(value).unwrap_or_default();
+        let (action, diff) = match &object.live {
+            None => ("create", diff_lines("", &yaml(&object.planned))),
+            Some(live) if *live == object.planned => ("unchanged", Some(vec![])),
+            Some(live) => ("update", diff_lines(&yaml(live), &yaml(&object.planned))),
+        };
+        Self {
+            kind: object.kind,
+            name: object.name,
+            namespace: object.namespace,
+            action,
+            diff,
+        }
+    }
+}
+
+/// Dry-runs deployments against the clusters of their environments.
+pub struct DeploymentPreviewService {
+    state: AppState,
+}
+
+impl DeploymentPreviewService {
+    pub fn new(state: AppState) -> Self {
+        Self { state }
+    }
+
+    /// Preview deploying `service` to `environment`, with `image` instead
+    /// of the spec's own. Manifest sources with a repository are checked
+    /// out at `branch`.
+    pub async fn preview(
+        &self,
+        service: &Service,
+        environment: &Environment,
+        image: Option<String>,
+        branch: Option<String>,
+    ) -> Result<DeploymentPreview, ApiError> {
+        let current = self
+            .state
+            .deployment_repo
+            .list_service_spec_versions(ResourceId::from_uuid(service.id))
+            .await?
+            .into_iter()
+            .next();
+        let spec_version = current.as_ref().map(|c| c.version);
+        let mut config = current
+            .map(|c| c.spec)
+            .unwrap_or_else(|| serde_json::json!({}));
+        if let Some(image) = &image {
+            config["image"] = serde_json::Value::String(image.clone());
+        }
+
+        let doc: ServiceSpecDocument = serde_json::from_value(config.clone())
+            .map_err(|e| ApiError::BadRequest(format!("Invalid service spec: {}", e)))?;
+        let mut spec = doc.into_spec(
+            ResourceId::new(),
+            service.name.clone(),
+            environment.name.clone(),
+        );
+        manifest_deploy::check_out_spec_source(&self.state, &config, &mut spec.source, branch)
+            .await
+            .map_err(ApiError::BadRequest)?;
+
+        let deployer = self
+            .state
+            .clusters
+            .for_environment(environment)
+            .await
+            .map_err(ApiError::BadRequest)?;
+        let changes = deployer
+            .preview(&spec)
+            .await?
+            .into_iter()
+            .map(ObjectChange::new)
+            .collect();
+
+        Ok(DeploymentPreview {
+            service: service.name.clone(),
+            environment: environment.name.clone(),
+            spec_version,
+            image: Some(spec.image).filter(|i| !i.is_empty()),
+            changes,
+        })
+    }
+}
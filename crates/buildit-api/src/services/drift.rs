@@ -0,0 +1,324 @@
+//! Drift detection for GitOps applications.
+//!
+//! Periodically compares the live objects of every synced application, in
+//! the cluster of its environment, against the manifests applied by its last sync. Drifted
+//! resources are stored with a field-level diff and the application is
+//! marked out of sync; applications with `self_heal` get their last synced
+//! manifests re-applied instead. Newly found drift is broadcast as a
+//! [`BroadcastEvent::DriftDetected`].
+
+use buildit_core::ResourceId;
+use buildit_core::application::{
+    Application, ApplicationResource, ApplicationSyncStatus, ResourceStatus, SyncStatus,
+    SyncTriggerType,
+};
+use buildit_core::deployer::Deployer;
+use buildit_db::{ApplicationRepo, PgApplicationRepo};
+use buildit_deployer::manifests;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+use super::app_sync::deployment_spec;
+use super::clusters::Clusters;
+use crate::services::event_bus::EventBus;
+use crate::ws::BroadcastEvent;
+
+/// Drift detector settings.
+#[derive(Debug, Clone)]
+pub struct DriftConfig {
+    /// How often to compare live state with the last sync.
+    pub interval: Duration,
+}
+
+impl Default for DriftConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(180),
+        }
+    }
+}
+
+impl DriftConfig {
+    /// Load settings from `BUILDIT_DRIFT_*` environment variables.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            interval: std::env::var("BUILDIT_DRIFT_INTERVAL_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|s: &u64| *s > 0)
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.interval),
+        }
+    }
+}
+
+/// Background task that detects and optionally heals drift.
+pub struct DriftDetector {
+    config: DriftConfig,
+    application_repo: Arc<PgApplicationRepo>,
+    clusters: Arc<Clusters>,
+    event_bus: Arc<dyn EventBus>,
+}
+
+impl DriftDetector {
+    pub fn new(
+        config: DriftConfig,
+        application_repo: Arc<PgApplicationRepo>,
+        clusters: Arc<Clusters>,
+        event_bus: Arc<dyn EventBus>,
+    ) -> Self {
+        Self {
+            config,
+            application_repo,
+            clusters,
+            event_bus,
+        }
+    }
+
+    /// Run the detector loop forever.
+    pub async fn run(self) {
+        if !self.clusters.available() {
+            info!("Drift detection disabled: no deployer configured");
+            return;
+        }
+        info!(
+            interval_secs = self.config.interval.as_secs(),
+            "Drift detector started"
+        );
+
+        let mut ticker = tokio::time::interval(self.config.interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.check().await {
+                error!(error = %e, "Drift scan failed");
+            }
+        }
+    }
+
+    /// Scan once, returning the applications that had drifted.
+    pub async fn check(&self) -> buildit_db::DbResult<Vec<String>> {
+        let mut drifted = Vec::new();
+        for app in self.application_repo.list_synced_applications().await? {
+            if app.sync_status == SyncStatus::Syncing {
+                continue;
+            }
+            let deployer = match self.clusters.for_environment_id(app.environment_id).await {
+                Ok(deployer) => deployer,
+                Err(e) => {
+                    warn!(application = %app.name, error = %e, "Cannot reach cluster of application");
+                    continue;
+                }
+            };
+            if self.check_application(&deployer, &app).await? {
+                drifted.push(app.name);
+            }
+        }
+        Ok(drifted)
+    }
+
+    async fn check_application(
+        &self,
+        deployer: &Arc<dyn Deployer>,
+        app: &Application,
+    ) -> buildit_db::DbResult<bool> {
+        let app_id = ResourceId::from_uuid(app.id);
+        let resources = self.application_repo.list_resources(app_id).await?;
+        let previously_drifted = resources.iter().any(|r| r.out_of_sync);
+
+        let mut drifted = Vec::new();
+        for resource in &resources {
+            let Some(desired) = &resource.desired_state else {
+                continue;
+            };
+            let mut live = match deployer.live_object(desired, &resource.namespace).await {
+                Ok(live) => live,
+                Err(e) => {
+                    warn!(
+                        application = %app.name,
+                        kind = %resource.kind,
+                        name = %resource.name,
+                        error = %e,
+                        "Failed to read live object"
+                    );
+                    continue;
+                }
+            };
+            if let Some(metadata) = live.as_mut().and_then(|l| l.get_mut("metadata")) {
+                if let Some(metadata) = metadata.as_object_mut() {
+                    metadata.remove("managedFields");
+                }
+            }
+
+            let (status, diff) = match &live {
+                None => (
+                    ResourceStatus::Missing,
+                    Some("missing from cluster".to_string()),
+                ),
+                Some(live) => {
+                    let differences = manifests::drift(desired, live);
+                    if differences.is_empty() {
+                        (ResourceStatus::Synced, None)
+                    } else {
+                        (ResourceStatus::OutOfSync, Some(differences.join("\n")))
+                    }
+                }
+            };
+            let out_of_sync = status != ResourceStatus::Synced;
+
+            self.application_repo
+                .upsert_resource(
+                    app_id,
+                    &resource.api_group,
+                    &resource.api_version,
+                    &resource.kind,
+                    &resource.name,
+                    &resource.namespace,
+                    status,
+                    resource.health_status,
+                    out_of_sync,
+                    resource.desired_state.clone(),
+                    live,
+                    diff.as_deref(),
+                )
+                .await?;
+            if out_of_sync {
+                drifted.push(resource);
+            }
+        }
+
+        if drifted.is_empty() {
+            if previously_drifted && app.sync_status == SyncStatus::OutOfSync {
+                info!(application = %app.name, "Drift resolved");
+                self.application_repo
+                    .update_application_sync_status(
+                        app_id,
+                        SyncStatus::Synced,
+                        app.health_status,
+                        None,
+                    )
+                    .await?;
+            }
+            return Ok(false);
+        }
+
+        warn!(
+            application = %app.name,
+            resources = drifted.len(),
+            self_heal = app.self_heal,
+            "Application has drifted from its last sync"
+        );
+        if !previously_drifted {
+            self.event_bus.publish(BroadcastEvent::DriftDetected {
+                application_id: app.id.to_string(),
+                application_name: app.name.clone(),
+                resources: drifted.len(),
+                self_heal: app.self_heal,
+            });
+        }
+        if app.self_heal {
+            self.heal(deployer, app, &resources, &drifted).await?;
+        } else {
+            self.application_repo
+                .update_application_sync_status(
+                    app_id,
+                    SyncStatus::OutOfSync,
+                    app.health_status,
+                    None,
+                )
+                .await?;
+        }
+        Ok(true)
+    }
+
+    /// Re-apply the manifests of the last sync, recorded as an automatic sync.
+    async fn heal(
+        &self,
+        deployer: &Arc<dyn Deployer>,
+        app: &Application,
+        resources: &[ApplicationResource],
+        drifted: &[&ApplicationResource],
+    ) -> buildit_db::DbResult<()> {
+        let app_id = ResourceId::from_uuid(app.id);
+        let revision = app.synced_revision.as_deref().unwrap_or("HEAD");
+        let sync = self
+            .application_repo
+            .create_sync(app_id, revision, None, SyncTriggerType::Auto)
+            .await?;
+        let sync_id = ResourceId::from_uuid(sync.id);
+        self.application_repo.update_sync_started(sync_id).await?;
+
+        let documents = resources
+            .iter()
+            .filter_map(|r| r.desired_state.as_ref())
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>()
+            .join("\n---\n");
+        let spec = deployment_spec(app, sync_id, documents);
+
+        match deployer.deploy(spec).await {
+            Ok(_) => {
+                info!(application = %app.name, resources = drifted.len(), "Drift healed");
+                self.application_repo
+                    .update_sync_finished(
+                        sync_id,
+                        ApplicationSyncStatus::Succeeded,
+                        0,
+                        drifted.len() as i32,
+                        0,
+                        None,
+                    )
+                    .await?;
+                for resource in drifted {
+                    self.application_repo
+                        .upsert_resource(
+                            app_id,
+                            &resource.api_group,
+                            &resource.api_version,
+                            &resource.kind,
+                            &resource.name,
+                            &resource.namespace,
+                            ResourceStatus::Synced,
+                            resource.health_status,
+                            false,
+                            resource.desired_state.clone(),
+                            None,
+                            None,
+                        )
+                        .await?;
+                }
+                self.application_repo
+                    .update_application_sync_status(
+                        app_id,
+                        SyncStatus::Synced,
+                        app.health_status,
+                        None,
+                    )
+                    .await?;
+            }
+            Err(e) => {
+                error!(application = %app.name, error = %e, "Failed to heal drift");
+                self.application_repo
+                    .update_sync_finished(
+                        sync_id,
+                        ApplicationSyncStatus::Failed,
+                        0,
+                        0,
+                        0,
+                        Some(&e.to_string()),
+                    )
+                    .await?;
+                self.application_repo
+                    .update_application_sync_status(
+                        app_id,
+                        SyncStatus::OutOfSync,
+                        app.health_status,
+                        None,
+                    )
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,387 @@
+//! Event bus carrying run, stage, log and deployment events to WebSocket
+//! and SSE clients and to the notification dispatcher.
+//!
+//! A single server uses the in-process bus. API replicas behind a load
+//! balancer share a Redis stream instead (`event-bus "redis"` in the system
+//! config, or `BUILDIT_EVENT_BUS_URL`), so a client watching a run gets its
+//! logs whichever replica runs it. Each replica delivers the events it
+//! publishes to its own subscribers straight away, appends them to the
+//! stream, and reads the other replicas' events back from it. Consumers
+//! that must see an event once, like notifications, read the stream through
+//! a consumer group, which hands each event to one replica.
+
+use buildit_config::system::EventBusConfig;
+use buildit_core::{Error, Result};
+use futures::StreamExt;
+use futures::stream::BoxStream;
+use redis::AsyncCommands;
+use redis::aio::MultiplexedConnection;
+use redis::streams::{StreamMaxlen, StreamReadOptions, StreamReadReply};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+use tracing::{info, warn};
+
+use crate::ws::BroadcastEvent;
+
+/// Events buffered for slow subscribers before they start losing some.
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// Default key of the Redis stream.
+const DEFAULT_STREAM: &str = "buildit:events";
+
+/// Default number of events the Redis stream keeps, roughly.
+const DEFAULT_MAX_LEN: u64 = 10_000;
+
+/// Events written to, or read from, Redis per round trip.
+const BATCH_SIZE: usize = 100;
+
+/// How long a stream read waits for new events.
+const READ_BLOCK: Duration = Duration::from_secs(5);
+
+/// Pause before reconnecting after a Redis error.
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Publishes events and hands them to subscribers.
+pub trait EventBus: Send + Sync {
+    /// Name of the backend, for logs.
+    fn name(&self) -> &'static str;
+
+    /// Send an event to subscribers on every replica. Never blocks; an
+    /// event that can't be passed on is dropped.
+    fn publish(&self, event: BroadcastEvent);
+
+    /// Every event published from now on, by any replica.
+    fn subscribe(&self) -> broadcast::Receiver<BroadcastEvent>;
+
+    /// Events published from now on, each handed to only one of the
+    /// subscribers of `group` across replicas.
+    fn subscribe_group(&self, group: &str) -> BoxStream<'static, BroadcastEvent>;
+}
+
+/// The bus the system config describes, in-process without one.
+pub fn bus_from_config(config: Option<&EventBusConfig>) -> Result<Arc<dyn EventBus>> {
+    let bus: Arc<dyn EventBus> = match config.map(|c| c.backend.as_str()) {
+        None | Some("memory") => Arc::new(InProcessBus::new()),
+        Some("redis") => Arc::new(RedisStreamBus::new(config.expect("checked above"))?),
+        Some(other) => {
+            return Err(Error::InvalidInput(format!(
+                "unknown event bus backend '{}'",
+                other
+            )));
+        }
+    };
+    info!(backend = bus.name(), "Event bus ready");
+    Ok(bus)
+}
+
+/// Received events of a broadcast subscription, skipping over those a slow
+/// reader missed.
+fn receiver_stream(
+    receiver: broadcast::Receiver<BroadcastEvent>,
+) -> BoxStream<'static, BroadcastEvent> {
+    futures::stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => return Some((event, receiver)),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!(skipped, "Event subscriber fell behind, events were dropped");
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    })
+    .boxed()
+}
+
+/// Bus of a single server process.
+pub struct InProcessBus {
+    tx: broadcast::Sender<BroadcastEvent>,
+}
+
+impl InProcessBus {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self { tx }
+    }
+}
+
+impl Default for InProcessBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus for InProcessBus {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn publish(&self, event: BroadcastEvent) {
+        // Ignore errors if no receivers
+        let _ = self.tx.send(event);
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<BroadcastEvent> {
+        self.tx.subscribe()
+    }
+
+    /// With one process, the group's only subscriber gets every event.
+    fn subscribe_group(&self, _group: &str) -> BoxStream<'static, BroadcastEvent> {
+        receiver_stream(self.tx.subscribe())
+    }
+}
+
+/// Bus shared by API replicas through a Redis stream.
+///
+/// Entries have an `origin` field, the ID of the replica that published
+/// them, and an `event` field with the event as JSON.
+pub struct RedisStreamBus {
+    client: redis::Client,
+    stream: String,
+    /// ID of this replica.
+    origin: String,
+    local: broadcast::Sender<BroadcastEvent>,
+    outgoing: mpsc::Sender<BroadcastEvent>,
+}
+
+impl RedisStreamBus {
+    /// Connect lazily to the stream and start relaying events.
+    pub fn new(config: &EventBusConfig) -> Result<Self> {
+        let url = config
+            .url
+            .as_deref()
+            .ok_or_else(|| Error::InvalidInput("the redis event bus needs a url".to_string()))?;
+        let client = redis::Client::open(url)
+            .map_err(|e| Error::InvalidInput(format!("invalid event bus url: {}", e)))?;
+        let stream = config
+            .stream
+            .clone()
+            .unwrap_or_else(|| DEFAULT_STREAM.to_string());
+        let max_len = config.max_len.unwrap_or(DEFAULT_MAX_LEN) as usize;
+        let origin = uuid::Uuid::new_v4().to_string();
+        let (local, _) = broadcast::channel(CHANNEL_CAPACITY);
+        let (outgoing, pending) = mpsc::channel(CHANNEL_CAPACITY);
+
+        tokio::spawn(write_events(
+            client.clone(),
+            stream.clone(),
+            max_len,
+            origin.clone(),
+            pending,
+        ));
+        tokio::spawn(read_events(
+            client.clone(),
+            stream.clone(),
+            origin.clone(),
+            local.clone(),
+        ));
+
+        Ok(Self {
+            client,
+            stream,
+            origin,
+            local,
+            outgoing,
+        })
+    }
+}
+
+impl EventBus for RedisStreamBus {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    fn publish(&self, event: BroadcastEvent) {
+        let _ = self.local.send(event.clone());
+        if let Err(mpsc::error::TrySendError::Full(_)) = self.outgoing.try_send(event) {
+            warn!("Event bus is backed up, an event was not sent to other replicas");
+        }
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<BroadcastEvent> {
+        self.local.subscribe()
+    }
+
+    fn subscribe_group(&self, group: &str) -> BoxStream<'static, BroadcastEvent> {
+        let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+        tokio::spawn(read_group(
+            self.client.clone(),
+            self.stream.clone(),
+            group.to_string(),
+            self.origin.clone(),
+            tx,
+        ));
+        futures::stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|event| (event, rx))
+        })
+        .boxed()
+    }
+}
+
+async fn connect(client: &redis::Client) -> MultiplexedConnection {
+    loop {
+        match client.get_multiplexed_async_connection().await {
+            Ok(connection) => return connection,
+            Err(e) => {
+                warn!(error = %e, "Failed to connect to the event bus, retrying");
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+        }
+    }
+}
+
+/// Append published events to the stream, a batch at a time, until the
+/// bus is dropped.
+async fn write_events(
+    client: redis::Client,
+    stream: String,
+    max_len: usize,
+    origin: String,
+    mut pending: mpsc::Receiver<BroadcastEvent>,
+) {
+    let mut connection = connect(&client).await;
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    while pending.recv_many(&mut batch, BATCH_SIZE).await > 0 {
+        let mut pipe = redis::pipe();
+        for event in batch.drain(..) {
+            let Ok(json) = serde_json::to_string(&event) else {
+                continue;
+            };
+            pipe.xadd_maxlen(
+                &stream,
+                StreamMaxlen::Approx(max_len),
+                "*",
+                &[("origin", origin.as_str()), ("event", json.as_str())],
+            )
+            .ignore();
+        }
+        if let Err(e) = pipe.exec_async(&mut connection).await {
+            warn!(error = %e, "Failed to publish events to the event bus");
+            tokio::time::sleep(RETRY_DELAY).await;
+            connection = connect(&client).await;
+        }
+    }
+}
+
+/// Events of a stream read, with the ID of the last entry, skipping those
+/// published by `skip_origin`.
+fn parse_entries(
+    reply: StreamReadReply,
+    skip_origin: Option<&str>,
+) -> (Vec<BroadcastEvent>, Option<String>) {
+    let mut events = Vec::new();
+    let mut last_id = None;
+    for entry in reply.keys.into_iter().flat_map(|key| key.ids) {
+        let origin: Option<String> = entry.get("origin");
+        let event: Option<String> = entry.get("event");
+        last_id = Some(entry.id);
+        if skip_origin.is_some() && origin.as_deref() == skip_origin {
+            continue;
+        }
+        match event.map(|json| serde_json::from_str::<BroadcastEvent>(&json)) {
+            Some(Ok(event)) => events.push(event),
+            Some(Err(e)) => warn!(error = %e, "Skipping unreadable event bus entry"),
+            None => warn!("Skipping event bus entry without an event"),
+        }
+    }
+    (events, last_id)
+}
+
+/// Pass other replicas' events on to local subscribers, for the life of
+/// the server.
+async fn read_events(
+    client: redis::Client,
+    stream: String,
+    origin: String,
+    local: broadcast::Sender<BroadcastEvent>,
+) {
+    let options = StreamReadOptions::default()
+        .block(READ_BLOCK.as_millis() as usize)
+        .count(BATCH_SIZE);
+    // Only events from now on; after a reconnect, those since the last one
+    let mut last_id = "$".to_string();
+    let mut connection = connect(&client).await;
+    loop {
+        let reply: StreamReadReply = match connection
+            .xread_options(&[&stream], &[&last_id], &options)
+            .await
+        {
+            Ok(reply) => reply,
+            Err(e) => {
+                warn!(error = %e, "Failed to read from the event bus");
+                tokio::time::sleep(RETRY_DELAY).await;
+                connection = connect(&client).await;
+                continue;
+            }
+        };
+        let (events, last) = parse_entries(reply, Some(&origin));
+        if let Some(last) = last {
+            last_id = last;
+        }
+        for event in events {
+            let _ = local.send(event);
+        }
+    }
+}
+
+/// Hand the events `group` has not seen yet to `tx`, acknowledging them,
+/// until the subscriber goes away.
+async fn read_group(
+    client: redis::Client,
+    stream: String,
+    group: String,
+    consumer: String,
+    tx: mpsc::Sender<BroadcastEvent>,
+) {
+    let options = StreamReadOptions::default()
+        .group(&group, &consumer)
+        .block(READ_BLOCK.as_millis() as usize)
+        .count(BATCH_SIZE);
+    let mut connection = connect(&client).await;
+    loop {
+        // Start the group at the end of the stream if it is new
+        let created: redis::RedisResult<()> = connection
+            .xgroup_create_mkstream(&stream, &group, "$")
+            .await;
+        match created {
+            Ok(()) => break,
+            Err(e) if e.code() == Some("BUSYGROUP") => break,
+            Err(e) => {
+                warn!(error = %e, group = %group, "Failed to create event bus consumer group");
+                tokio::time::sleep(RETRY_DELAY).await;
+                connection = connect(&client).await;
+            }
+        }
+    }
+
+    while !tx.is_closed() {
+        let reply: StreamReadReply =
+            match connection.xread_options(&[&stream], &[">"], &options).await {
+                Ok(reply) => reply,
+                Err(e) => {
+                    warn!(error = %e, group = %group, "Failed to read from the event bus");
+                    tokio::time::sleep(RETRY_DELAY).await;
+                    connection = connect(&client).await;
+                    continue;
+                }
+            };
+        let ids: Vec<String> = reply
+            .keys
+            .iter()
+            .flat_map(|key| key.ids.iter().map(|entry| entry.id.clone()))
+            .collect();
+        let (events, _) = parse_entries(reply, None);
+        for event in events {
+            if tx.send(event).await.is_err() {
+                return;
+            }
+        }
+        if !ids.is_empty() {
+            let acked: redis::RedisResult<()> = connection.xack(&stream, &group, &ids).await;
+            if let Err(e) = acked {
+                warn!(error = %e, group = %group, "Failed to acknowledge event bus entries");
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Deployment freezes.
+//!
+//! An environment is frozen while one of its freeze windows is in effect.
+//! Depending on the window's policy, deployments to it are rejected by the
+//! API or held as `frozen`: their rollout task is deferred until the freeze
+//! ends and checks again then. Someone with `deployment.override_freeze`
+//! can deploy anyway by giving a reason, which is stored on the deployment.
+
+use buildit_core::ResourceId;
+use buildit_db::{DbError, DeploymentRepo, Environment, FreezeWindow, PgDeploymentRepo};
+use buildit_scheduler::freeze::{FreezeError, FreezeSchedule};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::warn;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Policy holding deployments until the freeze ends.
+pub const POLICY_QUEUE: &str = "queue";
+/// Policy refusing deployments outright.
+pub const POLICY_REJECT: &str = "reject";
+
+/// One occurrence of a freeze window.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FreezeOccurrence {
+    pub window_id: Uuid,
+    pub environment_id: Uuid,
+    pub name: String,
+    pub reason: Option<String>,
+    pub policy: String,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+}
+
+impl FreezeOccurrence {
+    fn new(window: &FreezeWindow, (starts_at, ends_at): (DateTime<Utc>, DateTime<Utc>)) -> Self {
+        Self {
+            window_id: window.id,
+            environment_id: window.environment_id,
+            name: window.name.clone(),
+            reason: window.reason.clone(),
+            policy: window.policy.clone(),
+            starts_at,
+            ends_at,
+        }
+    }
+}
+
+/// The schedule a stored window describes.
+pub fn schedule(window: &FreezeWindow) -> Result<FreezeSchedule, FreezeError> {
+    FreezeSchedule::new(
+        window.starts_at,
+        window.ends_at,
+        window.cron.as_deref(),
+        window.duration_minutes,
+    )
+}
+
+/// Occurrences of `windows` overlapping `from..to`, in order of their start.
+pub fn occurrences(
+    windows: &[FreezeWindow],
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Vec<FreezeOccurrence> {
+    let mut found: Vec<FreezeOccurrence> = windows
+        .iter()
+        .filter_map(|window| match schedule(window) {
+            Ok(schedule) => Some((window, schedule)),
+            Err(e) => {
+                warn!(window = %window.id, error = %e, "Ignoring invalid freeze window");
+                None
+            }
+        })
+        .flat_map(|(window, schedule)| {
+            schedule
+                .occurrences(from, to)
+                .into_iter()
+                .map(move |occurrence| FreezeOccurrence::new(window, occurrence))
+        })
+        .collect();
+    found.sort_by_key(|o| o.starts_at);
+    found
+}
+
+/// The freezes in effect for an environment at `at`.
+pub async fn active(
+    repo: &PgDeploymentRepo,
+    environment: &Environment,
+    at: DateTime<Utc>,
+) -> Result<Vec<FreezeOccurrence>, DbError> {
+    let windows = repo
+        .list_freeze_windows(
+            ResourceId::from_uuid(environment.tenant_id),
+            Some(ResourceId::from_uuid(environment.id)),
+        )
+        .await?;
+    Ok(windows
+        .iter()
+        .filter_map(|window| {
+            let schedule = schedule(window).ok()?;
+            schedule
+                .active_at(at)
+                .map(|occurrence| FreezeOccurrence::new(window, occurrence))
+        })
+        .collect())
+}
+
+/// When the last of `freezes` ends.
+pub fn frozen_until(freezes: &[FreezeOccurrence]) -> Option<DateTime<Utc>> {
+    freezes.iter().map(|f| f.ends_at).max()
+}
+
+/// Describe why an environment is frozen, e.g. for an error message.
+pub fn describe(environment: &Environment, freezes: &[FreezeOccurrence]) -> String {
+    let names: Vec<String> = freezes.iter().map(|f| format!("'{}'", f.name)).collect();
+    match frozen_until(freezes) {
+        Some(until) => format!(
+            "{} is frozen by {} until {}",
+            environment.name,
+            names.join(", "),
+            until.to_rfc3339()
+        ),
+        None => format!("{} is not frozen", environment.name),
+    }
+}
@@ -1,15 +1,105 @@
 //! Git service for cloning repositories and detecting configuration files.
-
-use buildit_core::repository::DetectedConfig;
+//!
+//! Checkouts are kept in the work directory between uses and brought up to
+//! date with a shallow fetch, so only what changed is downloaded again.
+//! Private repositories are fetched with the provider's token from
+//! `GITHUB_TOKEN`, `GITLAB_TOKEN` or `BITBUCKET_TOKEN`, sent as a header
+//! rather than stored in the checkout.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use buildit_core::repository::{DetectedConfig, GitProvider, Repository};
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::sync::{Arc, LazyLock, Mutex};
 use tokio::process::Command;
 use tracing::{debug, info, warn};
 
+/// Directory whose `.kdl` files are each a pipeline config, for
+/// repositories with several pipelines.
+const PIPELINE_DIR: &str = ".buildit";
+
+/// Locks serializing git operations on each checkout directory.
+static CHECKOUT_LOCKS: LazyLock<Mutex<HashMap<PathBuf, Arc<tokio::sync::Mutex<()>>>>> =
+    LazyLock::new(Default::default);
+
+/// Access tokens for fetching private repositories, one per provider.
+#[derive(Debug, Clone, Default)]
+pub struct GitCredentials {
+    github: Option<String>,
+    gitlab: Option<String>,
+    bitbucket: Option<String>,
+}
+
+impl GitCredentials {
+    /// Load tokens from `GITHUB_TOKEN`, `GITLAB_TOKEN` and `BITBUCKET_TOKEN`.
+    pub fn from_env() -> Self {
+        let token = |name: &str| std::env::var(name).ok().filter(|t| !t.is_empty());
+        Self {
+            github: token("GITHUB_TOKEN"),
+            gitlab: token("GITLAB_TOKEN"),
+            bitbucket: token("BITBUCKET_TOKEN"),
+        }
+    }
+
+    /// The token for a provider's repositories, if one is configured.
+    pub fn token(&self, provider: GitProvider) -> Option<&str> {
+        match provider {
+            GitProvider::Github => self.github.as_deref(),
+            GitProvider::Gitlab => self.gitlab.as_deref(),
+            GitProvider::Bitbucket => self.bitbucket.as_deref(),
+        }
+    }
+
+    fn set_token(&mut self, provider: GitProvider, token: String) {
+        let slot = match provider {
+            GitProvider::Github => &mut self.github,
+            GitProvider::Gitlab => &mut self.gitlab,
+            GitProvider::Bitbucket => &mut self.bitbucket,
+        };
+        *slot = Some(token);
+    }
+}
+
+/// What to check out of a repository.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CheckoutOptions {
+    /// Branch to check out; the repository's default branch if unset.
+    pub branch: Option<String>,
+    /// Only check out these paths (sparse checkout); all if empty or if
+    /// one is the root. A file comes with the rest of its directory.
+    pub sparse_paths: Vec<String>,
+}
+
+impl CheckoutOptions {
+    /// The sparse paths relative to the root, or none if one is the root.
+    fn sparse_paths(&self) -> Vec<String> {
+        let paths: Vec<String> = self
+            .sparse_paths
+            .iter()
+            .map(|p| {
+                Path::new(p)
+                    .components()
+                    .filter(|c| !matches!(c, std::path::Component::CurDir))
+                    .collect::<PathBuf>()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect();
+        if paths.iter().any(|p| p.is_empty()) {
+            Vec::new()
+        } else {
+            paths
+        }
+    }
+}
+
 /// Service for Git operations.
 pub struct GitService {
     /// Base directory for cloned repositories
     work_dir: PathBuf,
+    credentials: GitCredentials,
 }
 
 impl Default for GitService {
@@ -24,78 +114,192 @@ impl GitService {
             .map(PathBuf::from)
             .unwrap_or_else(|_| std::env::temp_dir().join("buildit-repos"));
 
-        Self { work_dir }
+        Self {
+            work_dir,
+            credentials: GitCredentials::from_env(),
+        }
     }
 
-    /// Clone a repository and scan for configuration files.
-    pub async fn clone_and_scan(
-        &self,
-        clone_url: &str,
-        access_token: Option<&str>,
-    ) -> Result<DetectedConfig, GitError> {
-        // Create work directory if it doesn't exist
-        tokio::fs::create_dir_all(&self.work_dir).await?;
+    /// Fetch `provider`'s repositories with `token` instead of the
+    /// configured one.
+    pub fn with_token(mut self, provider: GitProvider, token: impl Into<String>) -> Self {
+        self.credentials.set_token(provider, token.into());
+        self
+    }
 
-        // Generate a unique directory name for this clone
-        let repo_hash = format!("{:x}", md5::compute(clone_url));
-        let clone_path = self.work_dir.join(&repo_hash);
+    /// Check a repository out and scan it for configuration files.
+    pub async fn clone_and_scan(&self, repo: &Repository) -> Result<DetectedConfig, GitError> {
+        let checkout = self.checkout(repo, &CheckoutOptions::default()).await?;
 
-        // Remove existing clone if present
-        if clone_path.exists() {
-            tokio::fs::remove_dir_all(&clone_path).await?;
-        }
+        // Scan for configuration files
+        let detected_config = self.scan_repository(&checkout).await?;
 
-        // Build clone URL with authentication if provided
-        let auth_url = if let Some(token) = access_token {
-            // Insert token into URL for authentication
-            // https://github.com/owner/repo.git -> https://token@github.com/owner/repo.git
-            if let Some(rest) = clone_url.strip_prefix("https://") {
-                format!("https://{}@{}", token, rest)
-            } else {
-                clone_url.to_string()
-            }
-        } else {
-            clone_url.to_string()
-        };
+        info!(
+            buildit_config = ?detected_config.buildit_config,
+            terraform_dirs = ?detected_config.terraform_dirs,
+            "Configuration files detected"
+        );
+
+        Ok(detected_config)
+    }
 
-        info!(clone_url = %clone_url, path = %clone_path.display(), "Cloning repository");
+    /// Check a repository out into the work directory and return its path.
+    ///
+    /// The head of the branch is fetched shallowly into the checkout kept
+    /// from last time, if there is one. Submodules are initialized and Git
+    /// LFS objects pulled when the repository uses them.
+    pub async fn checkout(
+        &self,
+        repo: &Repository,
+        options: &CheckoutOptions,
+    ) -> Result<PathBuf, GitError> {
+        if let Some(branch) = &options.branch
+            && !valid_ref(branch)
+        {
+            return Err(GitError::InvalidRef(branch.clone()));
+        }
+        let path = self.checkout_path(&repo.clone_url, options);
+        let lock = CHECKOUT_LOCKS
+            .lock()
+            .unwrap()
+            .entry(path.clone())
+            .or_default()
+            .clone();
+        let _guard = lock.lock().await;
+
+        let auth = self.auth_args(repo);
+        let token = self.credentials.token(repo.provider);
+
+        if !path.join(".git").exists() {
+            // Nothing usable there, e.g. a clone that was interrupted
+            if path.exists() {
+                tokio::fs::remove_dir_all(&path).await?;
+            }
+            tokio::fs::create_dir_all(&path).await?;
+            run_git(&path, &[], &["init", "-q"], token).await?;
+            run_git(
+                &path,
+                &[],
+                &["remote", "add", "origin", &repo.clone_url],
+                token,
+            )
+            .await?;
+        }
 
-        // Clone the repository (shallow clone for speed)
-        let output = Command::new("git")
-            .args([
-                "clone",
+        info!(clone_url = %repo.clone_url, path = %path.display(), "Fetching repository");
+        let target = options.branch.as_deref().unwrap_or("HEAD");
+        run_git(
+            &path,
+            &auth,
+            &[
+                "fetch",
+                "-q",
                 "--depth",
                 "1",
-                "--single-branch",
-                &auth_url,
-                clone_path.to_str().unwrap(),
-            ])
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output()
+                "--end-of-options",
+                "origin",
+                target,
+            ],
+            token,
+        )
+        .await?;
+
+        let sparse_paths = options.sparse_paths();
+        if sparse_paths.is_empty() {
+            if path.join(".git/info/sparse-checkout").exists() {
+                run_git(&path, &[], &["sparse-checkout", "disable"], token).await?;
+            }
+        } else {
+            // Files aren't known to be files until they are checked out
+            let mut args = vec!["sparse-checkout", "set", "--skip-checks", "--"];
+            args.extend(sparse_paths.iter().map(String::as_str));
+            run_git(&path, &[], &args, token).await?;
+        }
+        run_git(
+            &path,
+            &auth,
+            &["checkout", "-q", "--force", "--detach", "FETCH_HEAD"],
+            token,
+        )
+        .await?;
+
+        if path.join(".gitmodules").exists() {
+            run_git(
+                &path,
+                &auth,
+                &["submodule", "update", "-q", "--init", "--recursive"],
+                token,
+            )
             .await?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            // Don't log the full error as it may contain the token
-            warn!("Git clone failed");
-            return Err(GitError::CloneFailed(
-                stderr.replace(access_token.unwrap_or(""), "[REDACTED]"),
-            ));
         }
 
-        info!(path = %clone_path.display(), "Repository cloned successfully");
+        let attributes = tokio::fs::read_to_string(path.join(".gitattributes"))
+            .await
+            .unwrap_or_default();
+        if attributes.contains("filter=lfs") {
+            if run_git(&path, &[], &["lfs", "version"], token)
+                .await
+                .is_ok()
+            {
+                run_git(&path, &[], &["lfs", "install", "--local"], token).await?;
+                let include = sparse_paths.join(",");
+                let mut args = vec!["lfs", "pull"];
+                if !include.is_empty() {
+                    args.extend(["-I", include.as_str()]);
+                }
+                run_git(&path, &auth, &args, token).await?;
+            } else {
+                warn!(
+                    repository = %repo.full_name,
+                    "Repository uses Git LFS but git-lfs is not installed; LFS files are left as pointers"
+                );
+            }
+        }
 
-        // Scan for configuration files
-        let detected_config = self.scan_repository(&clone_path).await?;
+        info!(path = %path.display(), "Repository checked out");
+        Ok(path)
+    }
 
-        info!(
-            buildit_config = ?detected_config.buildit_config,
-            terraform_dirs = ?detected_config.terraform_dirs,
-            "Configuration files detected"
-        );
+    /// Names of a repository's tags, read from the remote without checking
+    /// it out.
+    pub async fn list_tags(&self, repo: &Repository) -> Result<Vec<String>, GitError> {
+        tokio::fs::create_dir_all(&self.work_dir).await?;
+        let output = run_git(
+            &self.work_dir,
+            &self.auth_args(repo),
+            &["ls-remote", "--tags", "--refs", &repo.clone_url],
+            self.credentials.token(repo.provider),
+        )
+        .await?;
+        Ok(output
+            .lines()
+            .filter_map(|line| line.split_once('\t')?.1.strip_prefix("refs/tags/"))
+            .map(str::to_string)
+            .collect())
+    }
 
-        Ok(detected_config)
+    /// `git -c` arguments that authenticate requests to the repository's
+    /// host, if there is a token for its provider.
+    fn auth_args(&self, repo: &Repository) -> Vec<String> {
+        let Some(token) = self.credentials.token(repo.provider) else {
+            return Vec::new();
+        };
+        let Some(host) = repo
+            .clone_url
+            .strip_prefix("https://")
+            .and_then(|rest| rest.split('/').next())
+            .filter(|host| !host.is_empty())
+        else {
+            return Vec::new();
+        };
+        let basic = STANDARD.encode(format!("{}:{}", repo.provider.token_username(), token));
+        vec![
+            "-c".to_string(),
+            format!(
+                "http.https://{}/.extraHeader=Authorization: Basic {}",
+                host, basic
+            ),
+        ]
     }
 
     /// Scan a repository directory for configuration files.
@@ -109,10 +313,41 @@ impl GitService {
         // Deduplicate terraform_dirs based on unique directories containing .tf files
         config.terraform_dirs.sort();
         config.terraform_dirs.dedup();
+        config.pipeline_configs.sort();
+        // The shallowest single-file config, for those that know only one
+        config.buildit_config = config
+            .pipeline_configs
+            .iter()
+            .filter(|path| !path.split('/').any(|part| part == PIPELINE_DIR))
+            .min_by_key(|path| path.matches('/').count())
+            .cloned();
 
         Ok(config)
     }
 
+    /// Add the `.kdl` files of a `.buildit` directory as pipeline configs.
+    async fn scan_pipeline_dir(
+        &self,
+        base_path: &Path,
+        dir: &Path,
+        config: &mut DetectedConfig,
+    ) -> Result<(), GitError> {
+        let mut entries = tokio::fs::read_dir(dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "kdl") {
+                let relative_path = path
+                    .strip_prefix(base_path)
+                    .unwrap_or(&path)
+                    .to_string_lossy()
+                    .to_string();
+                debug!(path = %relative_path, "Found BuildIt config");
+                config.pipeline_configs.push(relative_path);
+            }
+        }
+        Ok(())
+    }
+
     /// Recursively scan a directory.
     #[async_recursion::async_recursion]
     async fn scan_directory(
@@ -128,6 +363,12 @@ impl GitService {
             let file_name = entry.file_name();
             let file_name_str = file_name.to_string_lossy();
 
+            // Each pipeline config in a `.buildit` directory is a pipeline
+            if file_name_str == PIPELINE_DIR && path.is_dir() {
+                self.scan_pipeline_dir(base_path, &path, config).await?;
+                continue;
+            }
+
             // Skip hidden directories (like .git)
             if file_name_str.starts_with('.') && path.is_dir() {
                 continue;
@@ -154,7 +395,7 @@ impl GitService {
                 // Check for .buildit.kdl
                 if file_name_str == ".buildit.kdl" || file_name_str == "buildit.kdl" {
                     debug!(path = %relative_path, "Found BuildIt config");
-                    config.buildit_config = Some(relative_path.clone());
+                    config.pipeline_configs.push(relative_path.clone());
                 }
 
                 // Check for Terraform files
@@ -298,37 +539,73 @@ impl GitService {
         self.work_dir.join(repo_hash)
     }
 
-    /// Ensure a repository is cloned and up-to-date, return its path.
-    pub async fn ensure_cloned(
-        &self,
-        clone_url: &str,
-        access_token: Option<&str>,
-    ) -> Result<PathBuf, GitError> {
-        let repo_path = self.get_repo_path(clone_url);
-
-        if repo_path.exists() {
-            // Pull latest changes
-            info!(path = %repo_path.display(), "Pulling latest changes");
-            let output = Command::new("git")
-                .args(["pull", "--ff-only"])
-                .current_dir(&repo_path)
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped())
-                .output()
-                .await?;
-
-            if !output.status.success() {
-                // If pull fails, re-clone
-                warn!("Git pull failed, re-cloning");
-                self.clone_and_scan(clone_url, access_token).await?;
-            }
-        } else {
-            // Clone fresh
-            self.clone_and_scan(clone_url, access_token).await?;
+    /// Where a checkout with `options` is kept. Other branches and sparse
+    /// checkouts get their own, so they don't disturb the full one.
+    fn checkout_path(&self, clone_url: &str, options: &CheckoutOptions) -> PathBuf {
+        if *options == CheckoutOptions::default() {
+            return self.get_repo_path(clone_url);
         }
+        let key = format!(
+            "{}\n{}\n{}",
+            clone_url,
+            options.branch.as_deref().unwrap_or_default(),
+            options.sparse_paths.join("\n")
+        );
+        self.work_dir.join(format!("{:x}", md5::compute(key)))
+    }
+}
 
-        Ok(repo_path)
+/// Whether `name` is a branch or tag name git accepts (see
+/// `git check-ref-format`), and so never one git would read as an option.
+fn valid_ref(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('-')
+        && name != "@"
+        && !name.contains("..")
+        && !name.contains("@{")
+        && !name.ends_with(".lock")
+        && !name.ends_with('.')
+        && !name
+            .chars()
+            .any(|c| c.is_ascii_control() || " ~^:?*[\\".contains(c))
+        && name
+            .split('/')
+            .all(|part| !part.is_empty() && !part.starts_with('.'))
+}
+
+/// Run git with `args` in `dir`, failing with what it printed. `token` is
+/// redacted from the error in case git echoed it.
+async fn run_git(
+    dir: &Path,
+    config: &[String],
+    args: &[&str],
+    token: Option<&str>,
+) -> Result<String, GitError> {
+    debug!(dir = %dir.display(), args = ?args, "Running git");
+    let output = Command::new("git")
+        .args(config)
+        .args(args)
+        .current_dir(dir)
+        // Fail rather than wait for credentials nobody will type
+        .env("GIT_TERMINAL_PROMPT", "0")
+        // LFS objects are pulled in one batch after checkout
+        .env("GIT_LFS_SKIP_SMUDGE", "1")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        let mut stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        // Don't log the full error as it may contain the token
+        warn!(command = args[0], "Git command failed");
+        if let Some(token) = token.filter(|t| !t.is_empty()) {
+            stderr = stderr.replace(token, "[REDACTED]");
+        }
+        return Err(GitError::CloneFailed(stderr));
     }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
 }
 
 /// Git operation errors.
@@ -342,4 +619,113 @@ pub enum GitError {
 
     #[error("Invalid repository URL")]
     InvalidUrl,
+
+    #[error("Invalid branch name: {0}")]
+    InvalidRef(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repository(clone_url: &str) -> Repository {
+        Repository {
+            id: uuid::Uuid::new_v4(),
+            organization_id: uuid::Uuid::new_v4(),
+            provider: GitProvider::Github,
+            provider_id: "1".to_string(),
+            owner: "acme".to_string(),
+            name: "shop".to_string(),
+            full_name: "acme/shop".to_string(),
+            clone_url: clone_url.to_string(),
+            default_branch: "main".to_string(),
+            is_private: false,
+            webhook_id: None,
+            webhook_secret: None,
+            last_synced_at: None,
+            sync_error: None,
+            detected_config: DetectedConfig::default(),
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_valid_ref() {
+        for name in ["main", "release/1.0", "feature/add-cart", "v1.2.3"] {
+            assert!(valid_ref(name), "{:?}", name);
+        }
+        for name in [
+            "",
+            "--upload-pack=touch /tmp/pwned",
+            "-b",
+            "main..dev",
+            "my branch",
+            "main.lock",
+            "release/.hidden",
+            "release//1.0",
+            "release/",
+            "@",
+            "main@{1}",
+            "HEAD:main",
+            "main\n",
+        ] {
+            assert!(!valid_ref(name), "{:?}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_checkout_refuses_branches_git_would_read_as_options() {
+        let root = std::env::temp_dir().join(format!("buildit-git-{}", uuid::Uuid::new_v4()));
+        let origin = root.join("origin");
+        tokio::fs::create_dir_all(&origin).await.unwrap();
+        tokio::fs::write(origin.join("README.md"), "shop")
+            .await
+            .unwrap();
+        let author = [
+            "-c".to_string(),
+            "user.name=BuildIt".to_string(),
+            "-c".to_string(),
+            "user.email=buildit@example.com".to_string(),
+        ];
+        for args in [
+            &["init", "-q", "-b", "release/1.0"][..],
+            &["add", "README.md"],
+            &["commit", "-q", "-m", "Initial commit"],
+        ] {
+            run_git(&origin, &author, args, None).await.unwrap();
+        }
+
+        let service = GitService {
+            work_dir: root.join("work"),
+            credentials: GitCredentials::default(),
+        };
+        let repo = repository(&format!("file://{}", origin.display()));
+        let checkout = service
+            .checkout(
+                &repo,
+                &CheckoutOptions {
+                    branch: Some("release/1.0".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert!(checkout.join("README.md").exists());
+
+        let marker = root.join("pwned");
+        let result = service
+            .checkout(
+                &repo,
+                &CheckoutOptions {
+                    branch: Some(format!("--upload-pack=touch {}", marker.display())),
+                    ..Default::default()
+                },
+            )
+            .await;
+        assert!(matches!(result, Err(GitError::InvalidRef(_))));
+        assert!(!marker.exists());
+
+        let _ = std::fs::remove_dir_all(root);
+    }
 }
@@ -1,7 +1,7 @@
 //! GitHub API client for OAuth and repository operations.
 
+use buildit_config::system::AuthConfig;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 
 /// GitHub OAuth configuration.
 #[derive(Debug, Clone)]
@@ -12,16 +12,16 @@ pub struct GitHubConfig {
 }
 
 impl GitHubConfig {
-    pub fn from_env() -> Option<Self> {
-        let client_id = std::env::var("GITHUB_CLIENT_ID").ok()?;
-        let client_secret = std::env::var("GITHUB_CLIENT_SECRET").ok()?;
-        let redirect_uri = std::env::var("GITHUB_REDIRECT_URI")
-            .unwrap_or_else(|_| "http://localhost:30080/auth/github/callback".to_string());
-
+    /// The OAuth app of the system configuration's `auth`, if there is one.
+    pub fn from_config(config: &AuthConfig) -> Option<Self> {
+        let github = config.github.as_ref()?;
         Some(Self {
-            client_id,
-            client_secret,
-            redirect_uri,
+            client_id: github.client_id.clone(),
+            client_secret: github.client_secret.clone(),
+            redirect_uri: github
+                .redirect_uri
+                .clone()
+                .unwrap_or_else(|| "http://localhost:30080/auth/github/callback".to_string()),
         })
     }
 
@@ -236,6 +236,195 @@ impl GitHubClient {
             .await
             .map_err(|e| GitHubError::Parse(e.to_string()))
     }
+
+    /// Publish a release for `tag`. Returns the release page URL.
+    pub async fn create_release(
+        &self,
+        owner: &str,
+        repo: &str,
+        tag: &str,
+        name: &str,
+        body: &str,
+    ) -> Result<String, GitHubError> {
+        let release = NewRelease {
+            tag,
+            name,
+            body,
+            target_commitish: None,
+            draft: false,
+            prerelease: false,
+        };
+        Ok(self.publish_release(owner, repo, &release).await?.html_url)
+    }
+
+    /// Publish a release, creating its tag on `target_commitish` if the
+    /// repository doesn't have it yet.
+    pub async fn publish_release(
+        &self,
+        owner: &str,
+        repo: &str,
+        release: &NewRelease<'_>,
+    ) -> Result<GitHubRelease, GitHubError> {
+        let url = format!("https://api.github.com/repos/{}/{}/releases", owner, repo);
+
+        let mut payload = serde_json::json!({
+            "tag_name": release.tag,
+            "name": release.name,
+            "body": release.body,
+            "draft": release.draft,
+            "prerelease": release.prerelease,
+        });
+        if let Some(target) = release.target_commitish {
+            payload["target_commitish"] = target.into();
+        }
+
+        let response = self
+            .client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", self.access_token))
+            .header("User-Agent", "BuildIt-CI")
+            .header("Accept", "application/vnd.github+json")
+            .json(&payload)
+            .send()
+            .await
+            .map_err(|e| GitHubError::Request(e.to_string()))?;
+
+        if !response.status().is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(GitHubError::Api(format!(
+                "Failed to create release: {}",
+                text
+            )));
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|e| GitHubError::Parse(e.to_string()))
+    }
+
+    /// Attach a file to a release.
+    pub async fn upload_release_asset(
+        &self,
+        release: &GitHubRelease,
+        name: &str,
+        content_type: &str,
+        content: Vec<u8>,
+    ) -> Result<(), GitHubError> {
+        // `upload_url` ends in a URI template, e.g. `/assets{?name,label}`
+        let url = release
+            .upload_url
+            .split('{')
+            .next()
+            .unwrap_or(&release.upload_url);
+
+        let response = self
+            .client
+            .post(url)
+            .query(&[("name", name)])
+            .header("Authorization", format!("Bearer {}", self.access_token))
+            .header("User-Agent", "BuildIt-CI")
+            .header("Accept", "application/vnd.github+json")
+            .header("Content-Type", content_type)
+            .body(content)
+            .send()
+            .await
+            .map_err(|e| GitHubError::Request(e.to_string()))?;
+
+        if !response.status().is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(GitHubError::Api(format!(
+                "Failed to upload {}: {}",
+                name, text
+            )));
+        }
+        Ok(())
+    }
+
+    /// Numbers of the open pull requests whose head is `sha`.
+    pub async fn pulls_for_commit(
+        &self,
+        owner: &str,
+        repo: &str,
+        sha: &str,
+    ) -> Result<Vec<u64>, GitHubError> {
+        let url = format!(
+            "https://api.github.com/repos/{}/{}/commits/{}/pulls",
+            owner, repo, sha
+        );
+
+        let response = self
+            .client
+            .get(&url)
+            .header("Authorization", format!("Bearer {}", self.access_token))
+            .header("User-Agent", "BuildIt-CI")
+            .header("Accept", "application/vnd.github+json")
+            .send()
+            .await
+            .map_err(|e| GitHubError::Request(e.to_string()))?;
+
+        if !response.status().is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(GitHubError::Api(format!(
+                "Failed to list pull requests: {}",
+                text
+            )));
+        }
+
+        let pulls: Vec<serde_json::Value> = response
+            .json()
+            .await
+            .map_err(|e| GitHubError::Parse(e.to_string()))?;
+        Ok(pulls
+            .iter()
+            .filter(|p| p.get("state").and_then(|s| s.as_str()) == Some("open"))
+            .filter(|p| p.pointer("/head/sha").and_then(|s| s.as_str()) == Some(sha))
+            .filter_map(|p| p.get("number").and_then(|n| n.as_u64()))
+            .collect())
+    }
+
+    /// Comment on an issue or pull request. Returns the comment URL.
+    pub async fn create_issue_comment(
+        &self,
+        owner: &str,
+        repo: &str,
+        number: u64,
+        body: &str,
+    ) -> Result<String, GitHubError> {
+        let url = format!(
+            "https://api.github.com/repos/{}/{}/issues/{}/comments",
+            owner, repo, number
+        );
+
+        let response = self
+            .client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", self.access_token))
+            .header("User-Agent", "BuildIt-CI")
+            .header("Accept", "application/vnd.github+json")
+            .json(&serde_json::json!({ "body": body }))
+            .send()
+            .await
+            .map_err(|e| GitHubError::Request(e.to_string()))?;
+
+        if !response.status().is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(GitHubError::Api(format!(
+                "Failed to comment on #{}: {}",
+                number, text
+            )));
+        }
+
+        let comment: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| GitHubError::Parse(e.to_string()))?;
+        Ok(comment
+            .get("html_url")
+            .and_then(|u| u.as_str())
+            .unwrap_or_default()
+            .to_string())
+    }
 }
 
 /// OAuth token response.
@@ -294,6 +483,27 @@ pub struct WebhookConfig {
     pub content_type: String,
 }
 
+/// A release to publish.
+#[derive(Debug)]
+pub struct NewRelease<'a> {
+    pub tag: &'a str,
+    pub name: &'a str,
+    pub body: &'a str,
+    /// Commit to create the tag on if it doesn't exist.
+    pub target_commitish: Option<&'a str>,
+    pub draft: bool,
+    pub prerelease: bool,
+}
+
+/// A published release.
+#[derive(Debug, Deserialize)]
+pub struct GitHubRelease {
+    pub id: i64,
+    pub html_url: String,
+    /// Where its assets are uploaded, as a URI template.
+    pub upload_url: String,
+}
+
 /// GitHub API errors.
 #[derive(Debug, thiserror::Error)]
 pub enum GitHubError {
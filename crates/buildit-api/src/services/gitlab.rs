@@ -0,0 +1,153 @@
+//! GitLab API client for publishing releases.
+//!
+//! Requests go to the API of the host a repository is cloned from, so
+//! self-managed instances work as well as gitlab.com.
+
+use serde::Deserialize;
+
+/// GitLab API client for one instance.
+pub struct GitLabClient {
+    client: reqwest::Client,
+    /// e.g. `https://gitlab.com/api/v4`
+    api_url: String,
+    access_token: String,
+}
+
+impl GitLabClient {
+    /// Client for the instance serving `clone_url`.
+    pub fn for_clone_url(clone_url: &str, access_token: String) -> Result<Self, GitLabError> {
+        let url = url::Url::parse(clone_url)
+            .map_err(|e| GitLabError::Request(format!("invalid clone URL: {}", e)))?;
+        let host = url
+            .host_str()
+            .ok_or_else(|| GitLabError::Request("clone URL has no host".to_string()))?;
+        let api_url = match url.port() {
+            Some(port) => format!("{}://{}:{}/api/v4", url.scheme(), host, port),
+            None => format!("{}://{}/api/v4", url.scheme(), host),
+        };
+        Ok(Self {
+            client: reqwest::Client::new(),
+            api_url,
+            access_token,
+        })
+    }
+
+    fn project_url(&self, project: &str) -> String {
+        format!("{}/projects/{}", self.api_url, urlencoding::encode(project))
+    }
+
+    /// Store a file in the project's generic package registry. Returns the
+    /// URL it can be downloaded from.
+    pub async fn upload_package_file(
+        &self,
+        project: &str,
+        package: &str,
+        version: &str,
+        file_name: &str,
+        content: Vec<u8>,
+    ) -> Result<String, GitLabError> {
+        let url = format!(
+            "{}/packages/generic/{}/{}/{}",
+            self.project_url(project),
+            urlencoding::encode(package),
+            urlencoding::encode(version),
+            urlencoding::encode(file_name)
+        );
+
+        let response = self
+            .client
+            .put(&url)
+            .header("PRIVATE-TOKEN", &self.access_token)
+            .header("User-Agent", "BuildIt-CI")
+            .body(content)
+            .send()
+            .await
+            .map_err(|e| GitLabError::Request(e.to_string()))?;
+
+        if !response.status().is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(GitLabError::Api(format!(
+                "Failed to upload {}: {}",
+                file_name, text
+            )));
+        }
+        Ok(url)
+    }
+
+    /// Publish a release, creating its tag on `ref_sha` if the project
+    /// doesn't have it yet, with `links` (name, URL) as its assets.
+    /// Returns the release page URL.
+    pub async fn create_release(
+        &self,
+        project: &str,
+        tag: &str,
+        name: &str,
+        description: &str,
+        ref_sha: Option<&str>,
+        links: &[(String, String)],
+    ) -> Result<String, GitLabError> {
+        let mut payload = serde_json::json!({
+            "tag_name": tag,
+            "name": name,
+            "description": description,
+            "assets": {
+                "links": links
+                    .iter()
+                    .map(|(name, url)| serde_json::json!({ "name": name, "url": url, "link_type": "package" }))
+                    .collect::<Vec<_>>(),
+            },
+        });
+        if let Some(sha) = ref_sha {
+            payload["ref"] = sha.into();
+        }
+
+        let response = self
+            .client
+            .post(format!("{}/releases", self.project_url(project)))
+            .header("PRIVATE-TOKEN", &self.access_token)
+            .header("User-Agent", "BuildIt-CI")
+            .json(&payload)
+            .send()
+            .await
+            .map_err(|e| GitLabError::Request(e.to_string()))?;
+
+        if !response.status().is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(GitLabError::Api(format!(
+                "Failed to create release: {}",
+                text
+            )));
+        }
+
+        let release: GitLabRelease = response
+            .json()
+            .await
+            .map_err(|e| GitLabError::Parse(e.to_string()))?;
+        Ok(release.links.url)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GitLabRelease {
+    #[serde(rename = "_links")]
+    links: GitLabReleaseLinks,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitLabReleaseLinks {
+    #[serde(rename = "self")]
+    url: String,
+}
+
+/// GitLab API errors.
+#[derive(Debug, thiserror::Error)]
+pub enum GitLabError {
+    #[error("Request failed: {0}")]
+    Request(String),
+
+    #[error("API error: {0}")]
+    Api(String),
+
+    #[error("Parse error: {0}")]
+    Parse(String),
+}
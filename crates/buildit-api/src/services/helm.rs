@@ -0,0 +1,395 @@
+//! Helm service for rendering charts with `helm template`.
+
+use buildit_core::application::{HelmSource, HelmValues};
+use std::path::{Component, Path, PathBuf};
+use std::process::Stdio;
+use tokio::process::Command;
+use tracing::{error, info};
+
+/// Longest release name Helm accepts.
+const MAX_RELEASE_NAME: usize = 53;
+
+/// Service for Helm operations.
+pub struct HelmService {
+    /// Path to helm binary
+    helm_bin: String,
+}
+
+impl Default for HelmService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HelmService {
+    pub fn new() -> Self {
+        let helm_bin = std::env::var("HELM_BIN").unwrap_or_else(|_| "helm".to_string());
+        Self { helm_bin }
+    }
+
+    /// Render a chart to multi-document YAML.
+    ///
+    /// `checkout` is the application's repository checkout and `path` the
+    /// application path in it; local charts live there and values files are
+    /// resolved against the checkout root. The shared values are applied
+    /// first, then the overrides for `environment`.
+    pub async fn template(
+        &self,
+        source: &HelmSource,
+        release: &str,
+        namespace: &str,
+        checkout: &Path,
+        path: &str,
+        environment: Option<&str>,
+    ) -> Result<String, HelmError> {
+        let release = source.release_name.as_deref().unwrap_or(release);
+        let mut inline_files = Vec::new();
+        let output = async {
+            let args = template_args(
+                source,
+                release,
+                namespace,
+                checkout,
+                path,
+                environment,
+                &mut inline_files,
+            )
+            .await?;
+
+            info!(release = %release, namespace = %namespace, "Running helm template");
+            let output = Command::new(&self.helm_bin)
+                .args(&args)
+                .current_dir(checkout)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .output()
+                .await?;
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+                error!(output = %stderr, "Helm template failed");
+                return Err(HelmError::TemplateFailed(stderr));
+            }
+            Ok(String::from_utf8_lossy(&output.stdout).to_string())
+        }
+        .await;
+
+        for file in inline_files {
+            let _ = tokio::fs::remove_file(file).await;
+        }
+        output
+    }
+}
+
+/// Arguments for `helm template`. Flags come first and the release and
+/// chart after `--`, so neither is ever read as a flag. Values files go in
+/// the order they apply: the shared files, then the shared inline values,
+/// then the environment's files and inline values. Inline values are
+/// written to temporary files, added to `inline_files` for the caller to
+/// remove.
+async fn template_args(
+    source: &HelmSource,
+    release: &str,
+    namespace: &str,
+    checkout: &Path,
+    path: &str,
+    environment: Option<&str>,
+    inline_files: &mut Vec<PathBuf>,
+) -> Result<Vec<String>, HelmError> {
+    check_release_name(release)?;
+    let chart = chart_ref(source, checkout, path)?;
+    let mut args: Vec<String> = vec![
+        "template".to_string(),
+        "--namespace".to_string(),
+        namespace.to_string(),
+    ];
+    if let Some(repo_url) = &source.repo_url {
+        args.extend(["--repo".to_string(), not_a_flag("repo_url", repo_url)?]);
+    }
+    if let Some(version) = &source.version {
+        args.extend(["--version".to_string(), not_a_flag("version", version)?]);
+    }
+
+    let overrides = environment.and_then(|e| source.environments.get(e));
+    for values in std::iter::once(&source.values).chain(overrides) {
+        for file in &values.values_files {
+            args.extend([
+                "--values".to_string(),
+                repo_path(checkout, file)?.display().to_string(),
+            ]);
+        }
+        if let Some(file) = write_inline_values(values).await? {
+            args.extend(["--values".to_string(), file.display().to_string()]);
+            inline_files.push(file);
+        }
+    }
+
+    args.extend(["--".to_string(), release.to_string(), chart]);
+    Ok(args)
+}
+
+/// Check a release name is one Helm accepts: a DNS-1123 name of at most
+/// 53 characters.
+fn check_release_name(release: &str) -> Result<(), HelmError> {
+    let valid = !release.is_empty()
+        && release.len() <= MAX_RELEASE_NAME
+        && release
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'.')
+        && release.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && release.ends_with(|c: char| c.is_ascii_alphanumeric());
+    if valid {
+        Ok(())
+    } else {
+        Err(HelmError::InvalidSource(format!(
+            "release name {:?} must be lowercase letters, digits, '-' and '.', \
+             start and end with a letter or digit and be at most {} characters",
+            release, MAX_RELEASE_NAME
+        )))
+    }
+}
+
+/// Refuse a value helm would take for a flag.
+fn not_a_flag(field: &str, value: &str) -> Result<String, HelmError> {
+    if value.starts_with('-') {
+        return Err(HelmError::InvalidSource(format!(
+            "{} must not start with '-'",
+            field
+        )));
+    }
+    Ok(value.to_string())
+}
+
+/// Chart argument for `helm template`: a chart name or `oci://` reference
+/// for remote charts, otherwise the chart directory in the checkout.
+fn chart_ref(source: &HelmSource, checkout: &Path, path: &str) -> Result<String, HelmError> {
+    match &source.chart {
+        Some(chart) if source.repo_url.is_some() || chart.starts_with("oci://") => {
+            not_a_flag("chart", chart)
+        }
+        Some(_) => Err(HelmError::InvalidSource(
+            "chart names need a repo_url unless they are oci:// references".to_string(),
+        )),
+        None if source.repo_url.is_some() => Err(HelmError::InvalidSource(
+            "repo_url needs a chart name".to_string(),
+        )),
+        None => Ok(repo_path(checkout, path)?.display().to_string()),
+    }
+}
+
+/// Resolve a repository-relative path, refusing anything that escapes the
+/// checkout.
+fn repo_path(checkout: &Path, relative: &str) -> Result<PathBuf, HelmError> {
+    let path = Path::new(relative);
+    if path
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(HelmError::InvalidSource(format!(
+            "{:?} must be a path inside the repository",
+            relative
+        )));
+    }
+    Ok(checkout.join(path))
+}
+
+/// Inline values as a temporary values file (JSON is valid YAML).
+async fn write_inline_values(values: &HelmValues) -> Result<Option<PathBuf>, HelmError> {
+    if values.values.is_null() {
+        return Ok(None);
+    }
+    if !values.values.is_object() {
+        return Err(HelmError::InvalidSource(
+            "inline values must be an object".to_string(),
+        ));
+    }
+    let file = std::env::temp_dir().join(format!("buildit-values-{}.yaml", uuid::Uuid::new_v4()));
+    tokio::fs::write(&file, values.values.to_string()).await?;
+    Ok(Some(file))
+}
+
+/// Helm errors.
+#[derive(Debug, thiserror::Error)]
+pub enum HelmError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid Helm source: {0}")]
+    InvalidSource(String),
+
+    #[error("Helm template failed: {0}")]
+    TemplateFailed(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(repo_url: Option<&str>, chart: Option<&str>) -> HelmSource {
+        HelmSource {
+            repo_url: repo_url.map(str::to_string),
+            chart: chart.map(str::to_string),
+            version: None,
+            release_name: None,
+            values: HelmValues::default(),
+            environments: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_chart_ref() {
+        let checkout = Path::new("/checkout");
+        let repo = source(Some("https://charts.example.com"), Some("nginx"));
+        assert_eq!(chart_ref(&repo, checkout, "deploy").unwrap(), "nginx");
+        let oci = source(None, Some("oci://ghcr.io/acme/charts/api"));
+        assert_eq!(
+            chart_ref(&oci, checkout, "deploy").unwrap(),
+            "oci://ghcr.io/acme/charts/api"
+        );
+        assert_eq!(
+            chart_ref(&source(None, None), checkout, "deploy/chart").unwrap(),
+            "/checkout/deploy/chart"
+        );
+
+        assert!(chart_ref(&source(None, Some("nginx")), checkout, "deploy").is_err());
+        assert!(
+            chart_ref(
+                &source(Some("https://charts.example.com"), None),
+                checkout,
+                "deploy"
+            )
+            .is_err()
+        );
+        assert!(chart_ref(&source(None, None), checkout, "../elsewhere").is_err());
+        let flag = source(
+            Some("https://charts.example.com"),
+            Some("--post-renderer=/usr/bin/id"),
+        );
+        assert!(matches!(
+            chart_ref(&flag, checkout, "deploy"),
+            Err(HelmError::InvalidSource(_))
+        ));
+    }
+
+    #[test]
+    fn test_repo_paths_stay_in_the_checkout() {
+        let checkout = Path::new("/checkout");
+        assert_eq!(
+            repo_path(checkout, "./deploy/values.yaml").unwrap(),
+            Path::new("/checkout/deploy/values.yaml")
+        );
+        assert!(repo_path(checkout, "deploy/../../etc/passwd").is_err());
+        assert!(repo_path(checkout, "/etc/passwd").is_err());
+    }
+
+    #[test]
+    fn test_release_names_are_dns_names() {
+        assert!(check_release_name("web").is_ok());
+        assert!(check_release_name("web-api.v2").is_ok());
+        assert!(check_release_name(&"a".repeat(MAX_RELEASE_NAME)).is_ok());
+
+        for name in [
+            "",
+            "--post-renderer=/usr/bin/id",
+            "-web",
+            "web-",
+            "Web",
+            "web_api",
+            "web api",
+        ] {
+            assert!(check_release_name(name).is_err(), "{:?}", name);
+        }
+        assert!(check_release_name(&"a".repeat(MAX_RELEASE_NAME + 1)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_values_apply_shared_then_environment() {
+        let checkout = Path::new("/checkout");
+        let mut source = source(Some("https://charts.example.com"), Some("nginx"));
+        source.version = Some("1.2.3".to_string());
+        source.values = HelmValues {
+            values_files: vec!["values.yaml".to_string()],
+            values: serde_json::json!({ "replicas": 2 }),
+        };
+        source.environments.insert(
+            "production".to_string(),
+            HelmValues {
+                values_files: vec!["values-production.yaml".to_string()],
+                values: serde_json::json!({ "replicas": 5 }),
+            },
+        );
+
+        let mut inline_files = Vec::new();
+        let args = template_args(
+            &source,
+            "web",
+            "apps",
+            checkout,
+            "deploy",
+            Some("production"),
+            &mut inline_files,
+        )
+        .await
+        .unwrap();
+        assert_eq!(inline_files.len(), 2);
+        let shared = std::fs::read_to_string(&inline_files[0]).unwrap();
+        let production = std::fs::read_to_string(&inline_files[1]).unwrap();
+        for file in &inline_files {
+            std::fs::remove_file(file).unwrap();
+        }
+        assert_eq!(shared, r#"{"replicas":2}"#);
+        assert_eq!(production, r#"{"replicas":5}"#);
+
+        let values: Vec<&str> = args
+            .windows(2)
+            .filter(|pair| pair[0] == "--values")
+            .map(|pair| pair[1].as_str())
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                "/checkout/values.yaml",
+                &*inline_files[0].display().to_string(),
+                "/checkout/values-production.yaml",
+                &*inline_files[1].display().to_string(),
+            ]
+        );
+
+        // The release and chart are the only arguments after `--`
+        assert_eq!(args[0], "template");
+        assert_eq!(&args[args.len() - 3..], ["--", "web", "nginx"]);
+        assert!(args.windows(2).any(|pair| pair == ["--version", "1.2.3"]));
+    }
+
+    #[tokio::test]
+    async fn test_flags_cannot_be_smuggled_in() {
+        let checkout = Path::new("/checkout");
+        let mut version = source(Some("https://charts.example.com"), Some("nginx"));
+        version.version = Some("--post-renderer=/usr/bin/id".to_string());
+        let mut inline_files = Vec::new();
+        let result = template_args(
+            &version,
+            "web",
+            "apps",
+            checkout,
+            "deploy",
+            None,
+            &mut inline_files,
+        )
+        .await;
+        assert!(matches!(result, Err(HelmError::InvalidSource(_))));
+
+        let release = source(Some("https://charts.example.com"), Some("nginx"));
+        let result = template_args(
+            &release,
+            "--post-renderer=/usr/bin/id",
+            "apps",
+            checkout,
+            "deploy",
+            None,
+            &mut inline_files,
+        )
+        .await;
+        assert!(matches!(result, Err(HelmError::InvalidSource(_))));
+        assert!(inline_files.is_empty());
+    }
+}
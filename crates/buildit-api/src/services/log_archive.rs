@@ -0,0 +1,553 @@
+//! Log retention: archiving and deleting old job logs.
+//!
+//! A tenant's [`LogRetention`](buildit_core::tenant::LogRetention) says how
+//! many days after a run finishes its logs are compressed into one gzipped
+//! JSON-lines object in the log archive, and when they are deleted for good.
+//! The [`LogArchiver`] applies it periodically. Once a run's logs have left
+//! the database, [`read_logs`] reads them back from the archive.
+//!
+//! The archive is configured with `log-archive` in the system config: S3 or
+//! an S3-compatible store, signed with the `AWS_ACCESS_KEY_ID`,
+//! `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN` credentials, or a
+//! directory. Without one, logs are only ever deleted.
+
+use async_trait::async_trait;
+use buildit_config::system::LogArchiveConfig;
+use buildit_core::{Error, ResourceId, Result};
+use buildit_db::{LogRecord, LogRepo, PgLogRepo};
+use chrono::Utc;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use hmac::{Hmac, Mac};
+use reqwest::{Method, Url};
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+use crate::services::metrics;
+
+/// Runs archived or deleted per batch.
+const BATCH_SIZE: i64 = 100;
+
+/// Where archived logs are kept.
+#[async_trait]
+pub trait LogArchiveStore: Send + Sync {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<()>;
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>>;
+
+    /// Delete an object; deleting one that is gone succeeds.
+    async fn delete(&self, key: &str) -> Result<()>;
+}
+
+/// The archive the system config describes, if any.
+pub fn store_from_config(config: &LogArchiveConfig) -> Result<Arc<dyn LogArchiveStore>> {
+    let prefix = config.prefix.clone().unwrap_or_default();
+    match config.backend.as_str() {
+        "s3" => Ok(Arc::new(S3LogArchive::new(config)?)),
+        "filesystem" => Ok(Arc::new(FilesystemLogArchive {
+            root: PathBuf::from(config.path.clone().unwrap_or_default()).join(prefix),
+        })),
+        other => Err(Error::InvalidInput(format!(
+            "unknown log archive backend '{}'",
+            other
+        ))),
+    }
+}
+
+/// Archive key of a run's logs.
+fn archive_key(tenant_id: uuid::Uuid, run_id: uuid::Uuid) -> String {
+    format!("{}/{}.jsonl.gz", tenant_id, run_id)
+}
+
+/// Gzipped JSON lines of `logs`.
+fn compress(logs: &[LogRecord]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    for log in logs {
+        serde_json::to_writer(&mut encoder, log)
+            .map_err(|e| Error::Internal(format!("encoding log line: {}", e)))?;
+        encoder
+            .write_all(b"\n")
+            .map_err(|e| Error::Internal(format!("compressing logs: {}", e)))?;
+    }
+    encoder
+        .finish()
+        .map_err(|e| Error::Internal(format!("compressing logs: {}", e)))
+}
+
+fn decompress(data: &[u8]) -> Result<Vec<LogRecord>> {
+    let mut text = String::new();
+    GzDecoder::new(data)
+        .read_to_string(&mut text)
+        .map_err(|e| Error::Internal(format!("decompressing archived logs: {}", e)))?;
+    text.lines()
+        .map(|line| {
+            serde_json::from_str(line)
+                .map_err(|e| Error::Internal(format!("decoding archived log line: {}", e)))
+        })
+        .collect()
+}
+
+/// A page of a run's logs, optionally of one stage, from the database or,
+/// once they were moved there, the log archive.
+pub async fn read_logs(
+    log_repo: &PgLogRepo,
+    store: Option<&dyn LogArchiveStore>,
+    run_id: ResourceId,
+    stage: Option<&str>,
+    offset: i64,
+    limit: i64,
+) -> Result<Vec<LogRecord>> {
+    let Some(archive) = log_repo.get_archive(run_id).await.map_err(db_error)? else {
+        return log_repo
+            .get_logs_paginated(run_id, stage, offset, limit)
+            .await
+            .map_err(db_error);
+    };
+    let store = store.ok_or_else(|| {
+        Error::Internal("logs are archived but no log archive is configured".to_string())
+    })?;
+    let logs = decompress(&store.get(&archive.location).await?)?;
+    Ok(logs
+        .into_iter()
+        .filter(|log| stage.is_none_or(|s| log.stage_name == s))
+        .skip(offset.max(0) as usize)
+        .take(limit.max(0) as usize)
+        .collect())
+}
+
+/// Log archiver settings.
+#[derive(Debug, Clone)]
+pub struct LogArchiverConfig {
+    /// How often to look for logs to archive or delete.
+    pub interval: Duration,
+}
+
+impl Default for LogArchiverConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(3600),
+        }
+    }
+}
+
+impl LogArchiverConfig {
+    /// Load settings from `BUILDIT_LOG_RETENTION_*` environment variables.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            interval: std::env::var("BUILDIT_LOG_RETENTION_INTERVAL_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|s: &u64| *s > 0)
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.interval),
+        }
+    }
+}
+
+/// Background task that applies the tenants' log retention.
+pub struct LogArchiver {
+    config: LogArchiverConfig,
+    log_repo: Arc<PgLogRepo>,
+    store: Option<Arc<dyn LogArchiveStore>>,
+}
+
+impl LogArchiver {
+    pub fn new(
+        config: LogArchiverConfig,
+        log_repo: Arc<PgLogRepo>,
+        store: Option<Arc<dyn LogArchiveStore>>,
+    ) -> Self {
+        Self {
+            config,
+            log_repo,
+            store,
+        }
+    }
+
+    /// Run the archiver loop forever.
+    pub async fn run(self) {
+        info!(
+            interval_secs = self.config.interval.as_secs(),
+            archive = self.store.is_some(),
+            "Log archiver started"
+        );
+
+        let mut ticker = tokio::time::interval(self.config.interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.check().await {
+                error!(error = %e, "Log retention pass failed");
+            }
+        }
+    }
+
+    /// Archive and delete what is due once. Returns the runs whose logs
+    /// were archived and deleted.
+    pub async fn check(&self) -> Result<(usize, usize)> {
+        let archived = match &self.store {
+            Some(store) => self.archive_due(store.as_ref()).await?,
+            None => 0,
+        };
+        let deleted = self.delete_due().await?;
+
+        let (runs, bytes) = self.log_repo.archive_totals().await.map_err(db_error)?;
+        metrics::record_log_archive_size(runs, bytes);
+        if archived + deleted > 0 {
+            info!(archived, deleted, "Applied log retention");
+        }
+        Ok((archived, deleted))
+    }
+
+    async fn archive_due(&self, store: &dyn LogArchiveStore) -> Result<usize> {
+        let mut archived = 0;
+        loop {
+            let due = self
+                .log_repo
+                .runs_due_for_archive(BATCH_SIZE)
+                .await
+                .map_err(db_error)?;
+            if due.is_empty() {
+                return Ok(archived);
+            }
+            for run in &due {
+                let run_id = ResourceId::from_uuid(run.pipeline_run_id);
+                if let Err(e) = self.archive_run(store, run_id, run.tenant_id).await {
+                    // Left in the database, it would be picked again at once
+                    warn!(run_id = %run_id, error = %e, "Failed to archive run logs");
+                    return Ok(archived);
+                }
+                archived += 1;
+            }
+        }
+    }
+
+    async fn archive_run(
+        &self,
+        store: &dyn LogArchiveStore,
+        run_id: ResourceId,
+        tenant_id: uuid::Uuid,
+    ) -> Result<()> {
+        let logs = self
+            .log_repo
+            .get_logs_for_run(run_id)
+            .await
+            .map_err(db_error)?;
+        let data = compress(&logs)?;
+        let bytes = data.len() as i64;
+        let key = archive_key(tenant_id, *run_id.as_uuid());
+        store.put(&key, data).await?;
+        self.log_repo
+            .archive_run(
+                run_id,
+                ResourceId::from_uuid(tenant_id),
+                &key,
+                logs.len() as i64,
+                bytes,
+            )
+            .await
+            .map_err(db_error)?;
+        metrics::record_logs_archived(logs.len() as u64, bytes as u64);
+        Ok(())
+    }
+
+    async fn delete_due(&self) -> Result<usize> {
+        let mut deleted = 0;
+        loop {
+            let due = self
+                .log_repo
+                .runs_due_for_deletion(BATCH_SIZE)
+                .await
+                .map_err(db_error)?;
+            if due.is_empty() {
+                return Ok(deleted);
+            }
+            for run in &due {
+                let run_id = ResourceId::from_uuid(run.pipeline_run_id);
+                if let Some(location) = &run.archive_location {
+                    let Some(store) = &self.store else {
+                        warn!(run_id = %run_id, "Run logs are archived but no log archive is configured");
+                        return Ok(deleted);
+                    };
+                    if let Err(e) = store.delete(location).await {
+                        warn!(run_id = %run_id, error = %e, "Failed to delete archived logs");
+                        return Ok(deleted);
+                    }
+                }
+                let lines = self
+                    .log_repo
+                    .delete_run_logs(run_id)
+                    .await
+                    .map_err(db_error)?;
+                metrics::record_logs_deleted(lines);
+                deleted += 1;
+            }
+        }
+    }
+}
+
+fn db_error(e: buildit_db::DbError) -> Error {
+    Error::Internal(e.to_string())
+}
+
+/// Keeps archived logs as files under a directory.
+pub struct FilesystemLogArchive {
+    root: PathBuf,
+}
+
+impl FilesystemLogArchive {
+    fn resolve(&self, key: &str) -> Result<PathBuf> {
+        let relative = Path::new(key);
+        if !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            return Err(Error::InvalidInput(format!(
+                "invalid archive key {:?}",
+                key
+            )));
+        }
+        Ok(self.root.join(relative))
+    }
+}
+
+fn io_error(e: std::io::Error) -> Error {
+    if e.kind() == std::io::ErrorKind::NotFound {
+        Error::NotFound("archived logs are missing from the log archive".to_string())
+    } else {
+        Error::Internal(format!("log archive: {}", e))
+    }
+}
+
+#[async_trait]
+impl LogArchiveStore for FilesystemLogArchive {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<()> {
+        let path = self.resolve(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(io_error)?;
+        }
+        tokio::fs::write(path, data).await.map_err(io_error)
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        tokio::fs::read(self.resolve(key)?).await.map_err(io_error)
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match tokio::fs::remove_file(self.resolve(key)?).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(io_error(e)),
+        }
+    }
+}
+
+/// Keeps archived logs in an S3 bucket, addressed path-style so
+/// S3-compatible stores work too. Requests are signed with AWS Signature
+/// Version 4.
+pub struct S3LogArchive {
+    client: reqwest::Client,
+    endpoint: Url,
+    bucket: String,
+    region: String,
+    prefix: String,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+impl S3LogArchive {
+    fn new(config: &LogArchiveConfig) -> Result<Self> {
+        let region = config
+            .region
+            .clone()
+            .or_else(|| std::env::var("AWS_REGION").ok())
+            .unwrap_or_else(|| "us-east-1".to_string());
+        let endpoint = config
+            .endpoint
+            .clone()
+            .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", region));
+        let endpoint = Url::parse(&endpoint).map_err(|e| {
+            Error::InvalidInput(format!("invalid log archive endpoint {}: {}", endpoint, e))
+        })?;
+        let credential = |name: &str| {
+            std::env::var(name).map_err(|_| {
+                Error::InvalidInput(format!("{} is required for the S3 log archive", name))
+            })
+        };
+        Ok(Self {
+            client: reqwest::Client::new(),
+            endpoint,
+            bucket: config.bucket.clone().unwrap_or_default(),
+            region,
+            prefix: config.prefix.clone().unwrap_or_default(),
+            access_key: credential("AWS_ACCESS_KEY_ID")?,
+            secret_key: credential("AWS_SECRET_ACCESS_KEY")?,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+
+    async fn send(&self, method: Method, key: &str, body: Vec<u8>) -> Result<reqwest::Response> {
+        let path = format!("/{}/{}{}", self.bucket, self.prefix, key);
+        let path = path
+            .split('/')
+            .map(|segment| urlencoding::encode(segment).into_owned())
+            .collect::<Vec<_>>()
+            .join("/");
+        let mut url = self.endpoint.clone();
+        url.set_path(&path);
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+
+        let now = Utc::now();
+        let payload_hash = hex::encode(Sha256::digest(&body));
+        let mut headers = vec![
+            ("host".to_string(), host),
+            ("x-amz-content-sha256".to_string(), payload_hash.clone()),
+            (
+                "x-amz-date".to_string(),
+                now.format("%Y%m%dT%H%M%SZ").to_string(),
+            ),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token".to_string(), token.clone()));
+        }
+        let authorization = sign_v4(
+            method.as_str(),
+            &path,
+            &headers,
+            &payload_hash,
+            &self.access_key,
+            &self.secret_key,
+            &self.region,
+            "s3",
+            now,
+        );
+
+        let mut request = self
+            .client
+            .request(method, url)
+            .header("authorization", authorization)
+            .body(body);
+        for (name, value) in headers.iter().filter(|(name, _)| name != "host") {
+            request = request.header(name, value);
+        }
+        request
+            .send()
+            .await
+            .map_err(|e| Error::Internal(format!("log archive request failed: {}", e)))
+    }
+}
+
+/// `Authorization` header of a request to an AWS `service` signed with
+/// AWS Signature Version 4. `headers` are the lowercase headers to sign,
+/// including `host` and `x-amz-date`; the request has no query string.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn sign_v4(
+    method: &str,
+    path: &str,
+    headers: &[(String, String)],
+    payload_hash: &str,
+    access_key: &str,
+    secret_key: &str,
+    region: &str,
+    service: &str,
+    now: chrono::DateTime<Utc>,
+) -> String {
+    let mut headers: Vec<_> = headers.iter().collect();
+    headers.sort_by(|a, b| a.0.cmp(&b.0));
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "{}\n{}\n\n{}\n{}\n{}",
+        method, path, canonical_headers, signed_headers, payload_hash
+    );
+
+    let date = now.format("%Y%m%d").to_string();
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        now.format("%Y%m%dT%H%M%SZ"),
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let mut key = format!("AWS4{}", secret_key).into_bytes();
+    for part in [
+        date.as_str(),
+        region,
+        service,
+        "aws4_request",
+        &string_to_sign,
+    ] {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&key).expect("HMAC takes any key size");
+        mac.update(part.as_bytes());
+        key = mac.finalize().into_bytes().to_vec();
+    }
+
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        access_key,
+        scope,
+        signed_headers,
+        hex::encode(key)
+    )
+}
+
+async fn s3_error(key: &str, response: reqwest::Response) -> Error {
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    if status == reqwest::StatusCode::NOT_FOUND {
+        Error::NotFound(format!(
+            "archived logs {} are missing from the log archive",
+            key
+        ))
+    } else {
+        Error::Internal(format!("log archive returned {}: {}", status, body))
+    }
+}
+
+#[async_trait]
+impl LogArchiveStore for S3LogArchive {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<()> {
+        let response = self.send(Method::PUT, key, data).await?;
+        if !response.status().is_success() {
+            return Err(s3_error(key, response).await);
+        }
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        let response = self.send(Method::GET, key, Vec::new()).await?;
+        if !response.status().is_success() {
+            return Err(s3_error(key, response).await);
+        }
+        let data = response
+            .bytes()
+            .await
+            .map_err(|e| Error::Internal(format!("reading archived logs: {}", e)))?;
+        Ok(data.to_vec())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let response = self.send(Method::DELETE, key, Vec::new()).await?;
+        if !response.status().is_success() && response.status() != reqwest::StatusCode::NOT_FOUND {
+            return Err(s3_error(key, response).await);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,370 @@
+//! "Apply this manifest directory on merge" deployments.
+//!
+//! A service can skip pipelines entirely by pointing its config at a
+//! directory of Kubernetes manifests in a connected repository:
+//!
+//! ```json
+//! {
+//!   "source": {
+//!     "type": "manifests",
+//!     "repository_id": "0191...",
+//!     "path": "deploy/k8s",
+//!     "branch": "main",
+//!     "environment_id": "0191...",
+//!     "variables": { "replicas": "3" }
+//!   }
+//! }
+//! ```
+//!
+//! Pushes to the branch (the repository default branch when omitted) apply
+//! the directory to the cluster of the environment's target. `sha`, `short_sha` and
+//! `branch` are available as variables alongside the configured ones.
+//! While the environment is frozen, the push is applied once the freeze
+//! ends, or skipped if the freeze rejects deployments. Pushes are also
+//! skipped when the environment's protection rules refuse the branch, or
+//! require reviews or a wait timer, which only deployments made through the
+//! API go through.
+//!
+//! With `"type": "kustomize"` the directory is a kustomization instead:
+//! each push builds the overlay for the environment (`overlays` maps
+//! environment names to overlay directories, defaulting to
+//! `overlays/<environment>`), sets the service's image and waits for the
+//! rollout. Service specs deployed through the API can use the same source;
+//! their repository is checked out at the deployment's branch and the
+//! spec's image set on the workloads.
+
+use buildit_core::ResourceId;
+use buildit_core::deployer::{
+    DeploymentResources, DeploymentSource, DeploymentSpec, DeploymentStrategy,
+};
+use buildit_core::repository::{PushEvent, Repository};
+use buildit_db::{DeploymentRepo, PgDeploymentRepo, RepositoryRepo, Service};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::services::clusters::Clusters;
+use crate::services::freeze;
+use crate::services::git::{CheckoutOptions, GitService};
+use crate::services::protection;
+
+/// The `source` block of a manifest-deployed service.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ManifestSourceConfig {
+    pub repository_id: Uuid,
+    /// Directory relative to the repository root.
+    pub path: String,
+    /// Branch whose pushes trigger a deploy.
+    pub branch: Option<String>,
+    pub environment_id: Uuid,
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+    /// Overlay directory per environment, for kustomize sources.
+    #[serde(default)]
+    pub overlays: HashMap<String, String>,
+    /// `manifests` or `kustomize`.
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+impl ManifestSourceConfig {
+    pub fn from_service(service: &Service) -> Option<Self> {
+        let source = service.config.get("source")?;
+        if !matches!(source.get("type")?.as_str()?, "manifests" | "kustomize") {
+            return None;
+        }
+        serde_json::from_value(source.clone()).ok()
+    }
+}
+
+/// Sparsely check out `path` of a repository, returning its directory in
+/// the checkout.
+async fn check_out(
+    git_service: &GitService,
+    repo: &Repository,
+    path: &str,
+    branch: Option<String>,
+) -> Result<PathBuf, String> {
+    let relative = Path::new(path);
+    if relative
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(format!(
+            "manifest path {:?} must stay inside the repository",
+            path
+        ));
+    }
+    let checkout = git_service
+        .checkout(
+            repo,
+            &CheckoutOptions {
+                branch,
+                sparse_paths: vec![path.to_string()],
+            },
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(checkout.join(relative))
+}
+
+/// Point a kustomize source of a service spec that names a
+/// `repository_id` at a checkout of that repository, taken at `branch` or
+/// the source's own branch. Other sources are left alone.
+pub async fn check_out_spec_source(
+    state: &AppState,
+    config: &serde_json::Value,
+    source: &mut DeploymentSource,
+    branch: Option<String>,
+) -> Result<(), String> {
+    let DeploymentSource::Kustomize { path, .. } = source else {
+        return Ok(());
+    };
+    let Some(repository_id) = config
+        .pointer("/source/repository_id")
+        .and_then(|id| id.as_str())
+    else {
+        return Ok(());
+    };
+    let repository_id = Uuid::parse_str(repository_id)
+        .map_err(|e| format!("invalid source repository_id: {}", e))?;
+    let repo = state
+        .repository_repo
+        .get_by_id(ResourceId::from_uuid(repository_id))
+        .await
+        .map_err(|e| e.to_string())?;
+    let branch = branch.or_else(|| {
+        config
+            .pointer("/source/branch")
+            .and_then(|b| b.as_str())
+            .map(str::to_string)
+    });
+    let dir = check_out(&GitService::new(), &repo, path, branch).await?;
+    *path = dir.display().to_string();
+    Ok(())
+}
+
+/// Applies manifest-sourced services when their repository is pushed to.
+pub struct ManifestDeployService {
+    deployment_repo: Arc<PgDeploymentRepo>,
+    clusters: Arc<Clusters>,
+    git_service: GitService,
+}
+
+impl ManifestDeployService {
+    pub fn new(deployment_repo: Arc<PgDeploymentRepo>, clusters: Arc<Clusters>) -> Self {
+        Self {
+            deployment_repo,
+            clusters,
+            git_service: GitService::new(),
+        }
+    }
+
+    /// Deploy every manifest service watching this repository and branch.
+    /// Returns when to handle the push again if a freeze held any of them;
+    /// services already deployed at the pushed commit are skipped then.
+    pub async fn handle_push(&self, repo: &Repository, push: &PushEvent) -> Option<DateTime<Utc>> {
+        let services = match self
+            .deployment_repo
+            .list_manifest_services(ResourceId::from_uuid(repo.id))
+            .await
+        {
+            Ok(services) => services,
+            Err(e) => {
+                error!(error = %e, "Failed to list manifest services");
+                return None;
+            }
+        };
+
+        let mut retry_at: Option<DateTime<Utc>> = None;
+        for service in services {
+            let Some(source) = ManifestSourceConfig::from_service(&service) else {
+                continue;
+            };
+            let branch = source.branch.as_deref().unwrap_or(&repo.default_branch);
+            if push.branch.as_deref() != Some(branch) {
+                continue;
+            }
+
+            match self.deploy(repo, push, &service, &source).await {
+                Ok(Some(until)) => retry_at = Some(retry_at.map_or(until, |t| t.min(until))),
+                Ok(None) => {}
+                Err(e) => {
+                    error!(service = %service.name, error = %e, "Manifest deployment failed");
+                }
+            }
+        }
+        retry_at
+    }
+
+    async fn deploy(
+        &self,
+        repo: &Repository,
+        push: &PushEvent,
+        service: &Service,
+        source: &ManifestSourceConfig,
+    ) -> Result<Option<DateTime<Utc>>, String> {
+        let environment = self
+            .deployment_repo
+            .get_environment(ResourceId::from_uuid(source.environment_id))
+            .await
+            .map_err(|e| e.to_string())?;
+        let short_sha = push.after[..7.min(push.after.len())].to_string();
+
+        let latest = self
+            .deployment_repo
+            .list_service_deployments(
+                ResourceId::from_uuid(service.id),
+                Some(ResourceId::from_uuid(environment.id)),
+                1,
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        if latest
+            .first()
+            .is_some_and(|d| d.version == short_sha && d.status != "failed")
+        {
+            info!(service = %service.name, sha = %short_sha, "Manifests already applied");
+            return Ok(None);
+        }
+
+        let protection = self
+            .deployment_repo
+            .get_environment_protection(ResourceId::from_uuid(environment.id))
+            .await
+            .map_err(|e| e.to_string())?;
+        if let Err(reason) = protection::check_branch(
+            &environment.name,
+            protection.as_ref(),
+            push.branch.as_deref(),
+        ) {
+            warn!(service = %service.name, "{}; skipping manifest deployment", reason);
+            return Ok(None);
+        }
+        if protection
+            .as_ref()
+            .is_some_and(|p| p.required_approvals > 0 || p.wait_minutes > 0)
+        {
+            warn!(
+                service = %service.name,
+                "{} requires review before deploying; skipping manifest deployment",
+                environment.name
+            );
+            return Ok(None);
+        }
+
+        let freezes = freeze::active(&self.deployment_repo, &environment, Utc::now())
+            .await
+            .map_err(|e| e.to_string())?;
+        if let Some(until) = freeze::frozen_until(&freezes) {
+            let description = freeze::describe(&environment, &freezes);
+            if freezes.iter().any(|f| f.policy == freeze::POLICY_REJECT) {
+                warn!(service = %service.name, "{}; skipping manifest deployment", description);
+                return Ok(None);
+            }
+            info!(service = %service.name, "{}; holding manifest deployment", description);
+            return Ok(Some(until));
+        }
+
+        let deployment = self
+            .deployment_repo
+            .create_deployment(
+                ResourceId::from_uuid(service.tenant_id),
+                ResourceId::from_uuid(service.id),
+                ResourceId::from_uuid(environment.id),
+                &short_sha,
+                None,
+                serde_json::json!({
+                    "source": service.config.get("source"),
+                    "sha": push.after,
+                }),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        let deployment_id = ResourceId::from_uuid(deployment.id);
+        if let Some(branch) = &push.branch {
+            self.deployment_repo
+                .set_deployment_branch(deployment_id, branch)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+
+        let result = async {
+            let deployer = self.clusters.for_environment(&environment).await?;
+            let dir = check_out(&self.git_service, repo, &source.path, push.branch.clone())
+                .await?
+                .display()
+                .to_string();
+
+            let mut variables = source.variables.clone();
+            variables.insert("sha".to_string(), push.after.clone());
+            variables.insert("short_sha".to_string(), short_sha.clone());
+            if let Some(branch) = &push.branch {
+                variables.insert("branch".to_string(), branch.clone());
+            }
+
+            let spec = DeploymentSpec {
+                id: deployment_id,
+                service: service.name.clone(),
+                environment: environment.name.clone(),
+                image: service.image.clone().unwrap_or_default(),
+                replicas: 1,
+                env: HashMap::new(),
+                strategy: DeploymentStrategy::default(),
+                resources: DeploymentResources::default(),
+                health_check: None,
+                source: if source.kind == "kustomize" {
+                    DeploymentSource::Kustomize {
+                        path: dir,
+                        overlays: source.overlays.clone(),
+                    }
+                } else {
+                    DeploymentSource::Manifests {
+                        path: dir,
+                        variables,
+                    }
+                },
+            };
+
+            let warnings = deployer.validate(&spec).await.map_err(|e| e.to_string())?;
+            if !warnings.is_empty() {
+                let problems: Vec<String> = warnings
+                    .iter()
+                    .map(|w| format!("{}: {}", w.field, w.message))
+                    .collect();
+                return Err(problems.join("; "));
+            }
+
+            self.deployment_repo
+                .update_deployment_status(deployment_id, "running")
+                .await
+                .map_err(|e| e.to_string())?;
+            deployer.deploy(spec).await.map_err(|e| e.to_string())
+        }
+        .await;
+
+        let status = if result.is_ok() {
+            "succeeded"
+        } else {
+            "failed"
+        };
+        self.deployment_repo
+            .update_deployment_status(deployment_id, status)
+            .await
+            .map_err(|e| e.to_string())?;
+        result?;
+
+        info!(
+            service = %service.name,
+            environment = %environment.name,
+            sha = %short_sha,
+            "Applied manifests"
+        );
+        Ok(None)
+    }
+}
@@ -0,0 +1,166 @@
+//! Prometheus metrics.
+//!
+//! The server, scheduler and executors record metrics with the `metrics`
+//! crate; [`Metrics::install`] installs the Prometheus recorder they go to,
+//! and `GET /metrics` renders it. Gauges of state held elsewhere (queue depth,
+//! database pool) are sampled on each scrape.
+//!
+//! Set `BUILDIT_METRICS_TOKEN` to require it as a bearer token on scrapes.
+
+use std::time::Duration;
+
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use tracing::warn;
+
+use crate::AppState;
+
+/// Histogram buckets, in seconds, for every `*_seconds` metric. They cover
+/// webhook handling in milliseconds up to jobs running for an hour.
+const BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0, 300.0, 900.0, 1800.0, 3600.0,
+];
+
+/// The installed recorder.
+pub struct Metrics {
+    handle: PrometheusHandle,
+    token: Option<String>,
+}
+
+impl Metrics {
+    /// Install the process-wide Prometheus recorder. Returns `None` if one
+    /// could not be installed, e.g. because another recorder already was.
+    pub fn install() -> Option<Self> {
+        let handle = PrometheusBuilder::new()
+            .set_buckets_for_metric(Matcher::Suffix("_seconds".to_string()), BUCKETS)
+            .and_then(|builder| builder.install_recorder());
+        let handle = match handle {
+            Ok(handle) => handle,
+            Err(e) => {
+                warn!(error = %e, "Failed to install the metrics recorder");
+                return None;
+            }
+        };
+
+        // Histograms are kept as a window of recent samples; drain it now and
+        // then so idle histograms don't hold on to them.
+        let upkeep = handle.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(Duration::from_secs(5));
+            loop {
+                ticker.tick().await;
+                upkeep.run_upkeep();
+            }
+        });
+
+        let token = std::env::var("BUILDIT_METRICS_TOKEN")
+            .ok()
+            .filter(|t| !t.is_empty());
+        Some(Self { handle, token })
+    }
+
+    /// Whether a scrape with this bearer token may read the metrics.
+    pub fn authorizes(&self, bearer: Option<&str>) -> bool {
+        match &self.token {
+            Some(token) => bearer == Some(token.as_str()),
+            None => true,
+        }
+    }
+
+    /// Sample the gauges and render every metric in the Prometheus text
+    /// format.
+    pub async fn render(&self, state: &AppState) -> String {
+        if let Err(e) = state.job_queue.record_depth().await {
+            warn!(error = %e, "Failed to sample queue depth");
+        }
+
+        let size = state.pool.size() as f64;
+        let idle = state.pool.num_idle() as f64;
+        metrics::gauge!("buildit_db_pool_connections", "state" => "idle").set(idle);
+        metrics::gauge!("buildit_db_pool_connections", "state" => "in_use").set(size - idle);
+        metrics::gauge!("buildit_db_pool_max_connections")
+            .set(state.pool.options().get_max_connections() as f64);
+
+        self.handle.render()
+    }
+}
+
+/// A pipeline run that entered `status`.
+pub fn record_run(status: &str) {
+    metrics::counter!("buildit_pipeline_runs_total", "status" => status.to_string()).increment(1);
+}
+
+/// A received webhook, from receipt until it was handled. Events other
+/// than those handled are counted as `other`.
+pub fn record_webhook(provider: &'static str, event: &str, elapsed: Duration) {
+    let event = match event {
+        "push" | "pull_request" | "ping" => event,
+        _ => "other",
+    };
+    metrics::counter!("buildit_webhooks_received_total", "provider" => provider, "event" => event.to_string())
+        .increment(1);
+    metrics::histogram!("buildit_webhook_processing_seconds", "provider" => provider, "event" => event.to_string())
+        .record(elapsed.as_secs_f64());
+}
+
+/// A run's logs were moved to the log archive.
+pub fn record_logs_archived(lines: u64, compressed_bytes: u64) {
+    metrics::counter!("buildit_logs_archived_runs_total").increment(1);
+    metrics::counter!("buildit_logs_archived_lines_total").increment(lines);
+    metrics::counter!("buildit_logs_archived_bytes_total").increment(compressed_bytes);
+}
+
+/// A run's logs were deleted after their retention period; `lines` is how
+/// many were still in the database.
+pub fn record_logs_deleted(lines: u64) {
+    metrics::counter!("buildit_logs_deleted_runs_total").increment(1);
+    metrics::counter!("buildit_logs_deleted_lines_total").increment(lines);
+}
+
+/// Runs in the log archive and their compressed size.
+pub fn record_log_archive_size(runs: i64, bytes: i64) {
+    metrics::gauge!("buildit_log_archive_runs").set(runs as f64);
+    metrics::gauge!("buildit_log_archive_bytes").set(bytes as f64);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scrapes_need_the_token_when_one_is_set() {
+        let handle = PrometheusBuilder::new().build_recorder().handle();
+        let open = Metrics {
+            handle: handle.clone(),
+            token: None,
+        };
+        assert!(open.authorizes(None));
+        let guarded = Metrics {
+            handle,
+            token: Some("scrape".to_string()),
+        };
+        assert!(guarded.authorizes(Some("scrape")));
+        assert!(!guarded.authorizes(Some("wrong")));
+        assert!(!guarded.authorizes(None));
+    }
+
+    #[test]
+    fn test_unhandled_webhook_events_count_as_other() {
+        let recorder = PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        metrics::with_local_recorder(&recorder, || {
+            record_webhook("github", "push", Duration::from_millis(3));
+            record_webhook("github", "issues", Duration::from_millis(3));
+            record_webhook("github", "release", Duration::from_millis(3));
+        });
+        let rendered = handle.render();
+        assert!(
+            rendered
+                .contains(r#"buildit_webhooks_received_total{provider="github",event="push"} 1"#)
+        );
+        assert!(
+            rendered
+                .contains(r#"buildit_webhooks_received_total{provider="github",event="other"} 2"#)
+        );
+        assert!(!rendered.contains("issues"));
+    }
+}
@@ -1,6 +1,48 @@
 //! Application services.
 
+pub mod app_sync;
+pub mod approval_context;
+pub mod archive_purge;
+pub mod artifact_dependencies;
+pub mod artifacts;
+pub mod backup;
+pub mod badges;
+pub mod child_pipelines;
+pub mod clusters;
+pub mod credentials;
+pub mod debug_sessions;
+pub mod deployment_diff;
+pub mod deployment_preview;
+pub mod drift;
+pub mod email;
+pub mod event_bus;
+pub mod freeze;
 pub mod git;
 pub mod github;
+pub mod gitlab;
+pub mod helm;
+pub mod log_archive;
+pub mod manifest_deploy;
+pub mod metrics;
+pub mod notifications;
+pub mod opa;
+pub mod pipeline_runner;
+pub mod protection;
+pub mod release_notes;
+pub mod releases;
+pub mod remote_executor;
+pub mod repo_sync;
+pub mod reports;
+pub mod run_comparison;
+pub mod scans;
+pub mod slack;
 pub mod stack_runner;
+pub mod stack_tasks;
+pub mod targets;
+pub mod tasks;
+pub mod telemetry;
+pub mod tenant_config;
 pub mod terraform;
+pub mod test_reports;
+pub mod watchdog;
+pub mod webhook_subscriptions;
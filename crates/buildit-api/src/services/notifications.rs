@@ -0,0 +1,789 @@
+//! Notifications of runs, deployments, approvals and drift.
+//!
+//! The [`NotificationDispatcher`] listens to the events on the event bus,
+//! once across all API replicas, and turns the ones channels can subscribe to ([`EVENTS`]) into
+//! a [`Notification`]. Each enabled channel of the tenant that wants it gets
+//! a [`Task::Notification`], so failed deliveries are retried with the job
+//! queue's backoff. The tenant's webhook subscriptions get it too, see
+//! [`webhook_subscriptions`].
+//!
+//! Deployments waiting for approval are also emailed to the designated
+//! reviewers of their environment. Slack messages about them carry Approve
+//! and Reject buttons, see [`slack`].
+//!
+//! Channels are Slack incoming webhooks, email recipients or HTTP
+//! endpoints. Messages come from the event's template, or the channel's own
+//! `template`, with `{{field}}` placeholders. Webhook deliveries carry the
+//! notification as JSON, signed with the channel's secret: the
+//! `X-BuildIt-Signature` header is `sha256=` and the hex HMAC-SHA256 of the
+//! body.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use buildit_core::ResourceId;
+use buildit_db::{
+    ApplicationRepo, DeploymentRepo, NotificationChannel, NotificationRepo, OrganizationRepo,
+    PipelineRepo, Service, StackRepo,
+};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sha2::Sha256;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::error::ApiError;
+use crate::services::email::{Email, html_escape};
+use crate::services::slack;
+use crate::services::tasks::{self, Task};
+use crate::services::webhook_subscriptions;
+use crate::ws::BroadcastEvent;
+
+/// Events a channel can subscribe to.
+pub const EVENTS: &[&str] = &[
+    "run.started",
+    "run.succeeded",
+    "run.failed",
+    "deployment.succeeded",
+    "deployment.failed",
+    "approval.required",
+    "drift.detected",
+];
+
+/// Kinds of channel.
+pub const CHANNEL_KINDS: &[&str] = &["slack", "email", "webhook"];
+
+/// Event of the notification sent by `POST .../channels/{id}/test`.
+pub const TEST_EVENT: &str = "test";
+
+/// Prefix of webhook signing secrets.
+const SECRET_PREFIX: &str = "whsec_";
+
+/// How long a delivery may take.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Event bus group the dispatchers of all replicas share.
+const DISPATCH_GROUP: &str = "notifications";
+
+/// Something that happened in a tenant, to tell its channels about.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    /// One of [`EVENTS`], or [`TEST_EVENT`].
+    pub event: String,
+    pub tenant_id: Uuid,
+    /// The run, deployment, stack run or drift occurrence it is about.
+    pub subject_id: Uuid,
+    /// Values message templates can use, e.g. `pipeline` and `number`.
+    pub fields: BTreeMap<String, String>,
+    /// Page showing the subject, when the server's public URL is known.
+    pub url: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+impl Notification {
+    fn new(
+        state: &AppState,
+        event: &str,
+        tenant_id: Uuid,
+        subject_id: Uuid,
+        path: &str,
+        fields: &[(&str, String)],
+    ) -> Self {
+        Self {
+            event: event.to_string(),
+            tenant_id,
+            subject_id,
+            fields: fields
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.clone()))
+                .collect(),
+            url: state
+                .auth
+                .public_url
+                .as_deref()
+                .map(|base| format!("{}{}", base.trim_end_matches('/'), path)),
+            occurred_at: Utc::now(),
+        }
+    }
+
+    /// A notification checking that a channel works.
+    pub fn test(state: &AppState, channel: &NotificationChannel) -> Self {
+        Self::new(
+            state,
+            TEST_EVENT,
+            channel.tenant_id,
+            Uuid::now_v7(),
+            "/settings/notifications",
+            &[("channel", channel.name.clone())],
+        )
+    }
+
+    /// The message, from `template` or the event's own.
+    pub fn message(&self, template: Option<&str>) -> String {
+        render(
+            template.unwrap_or_else(|| default_template(&self.event)),
+            &self.fields,
+        )
+    }
+}
+
+fn default_template(event: &str) -> &'static str {
+    match event {
+        "run.started" => "{{pipeline}} run #{{number}} started",
+        "run.succeeded" => "{{pipeline}} run #{{number}} succeeded",
+        "run.failed" => "{{pipeline}} run #{{number}} failed",
+        "deployment.succeeded" => "{{service}} {{version}} deployed to {{environment}}",
+        "deployment.failed" => "Deployment of {{service}} {{version}} to {{environment}} failed",
+        "approval.required" => "{{subject}} is waiting for approval",
+        "drift.detected" => "{{application}} drifted from its last sync ({{resources}} resources)",
+        _ => "Test notification for {{channel}} from BuildIt",
+    }
+}
+
+/// Replace `{{name}}` placeholders with the field of that name. Unknown
+/// names become empty.
+fn render(template: &str, fields: &BTreeMap<String, String>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let Some(len) = rest[start + 2..].find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+        let name = rest[start + 2..start + 2 + len].trim();
+        if let Some(value) = fields.get(name) {
+            out.push_str(value);
+        }
+        rest = &rest[start + 2 + len + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Check a channel's config for its kind, returning what is wrong.
+pub fn validate_config(kind: &str, config: &Value) -> Result<(), String> {
+    if !config.is_object() {
+        return Err("config must be an object".to_string());
+    }
+    if config
+        .get("template")
+        .is_some_and(|t| !t.is_null() && !t.is_string())
+    {
+        return Err("template must be a string".to_string());
+    }
+    match kind {
+        "slack" => {
+            let url = config_str(config, "webhook_url").unwrap_or_default();
+            if !url.starts_with("https://") {
+                return Err("slack channels need an https webhook_url".to_string());
+            }
+        }
+        "email" => {
+            let recipients = recipients(config);
+            if recipients.is_empty() {
+                return Err("email channels need at least one recipient".to_string());
+            }
+            if let Some(bad) = recipients.iter().find(|r| !r.contains('@')) {
+                return Err(format!("{} is not an email address", bad));
+            }
+        }
+        "webhook" => {
+            let url = config_str(config, "url").unwrap_or_default();
+            if !(url.starts_with("https://") || url.starts_with("http://")) {
+                return Err("webhook channels need an http(s) url".to_string());
+            }
+        }
+        other => {
+            return Err(format!(
+                "unknown channel kind {}; expected one of {}",
+                other,
+                CHANNEL_KINDS.join(", ")
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn config_str<'a>(config: &'a Value, key: &str) -> Option<&'a str> {
+    config
+        .get(key)
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+}
+
+fn recipients(config: &Value) -> Vec<String> {
+    config
+        .get("recipients")
+        .and_then(Value::as_array)
+        .map(|r| {
+            r.iter()
+                .filter_map(Value::as_str)
+                .map(|r| r.trim().to_string())
+                .filter(|r| !r.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// A new webhook signing secret.
+pub fn new_secret() -> String {
+    format!(
+        "{}{}{}",
+        SECRET_PREFIX,
+        Uuid::new_v4().simple(),
+        Uuid::new_v4().simple()
+    )
+}
+
+/// `sha256=` and the hex HMAC-SHA256 of `body` keyed with `secret`.
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take any size key");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Sends broadcast events to the notification channels and webhook
+/// subscriptions that want them.
+pub struct NotificationDispatcher {
+    state: AppState,
+}
+
+impl NotificationDispatcher {
+    pub fn new(state: AppState) -> Self {
+        Self { state }
+    }
+
+    /// Dispatch events until the event bus goes away. With several API
+    /// replicas, each event is dispatched by one of them.
+    pub async fn run(self) {
+        let mut events = self.state.event_bus.subscribe_group(DISPATCH_GROUP);
+        info!("Notification dispatcher started");
+        while let Some(event) = events.next().await {
+            if let Err(e) = self.dispatch(&event).await {
+                warn!(error = ?e, event = ?event, "Failed to dispatch notification");
+            }
+        }
+    }
+
+    async fn dispatch(&self, event: &BroadcastEvent) -> Result<(), ApiError> {
+        if let Some(notification) = notification(&self.state, event).await? {
+            notify(&self.state, &notification).await?;
+            webhook_subscriptions::publish(&self.state, event, &notification).await?;
+            if let BroadcastEvent::DeploymentUpdate { deployment_id, .. } = event
+                && notification.event == "approval.required"
+            {
+                email_reviewers(&self.state, deployment_id, &notification).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Email a deployment's approval request to the designated reviewers of its
+/// environment. Failed deliveries are logged, not retried.
+async fn email_reviewers(
+    state: &AppState,
+    deployment_id: &str,
+    notification: &Notification,
+) -> Result<(), ApiError> {
+    let repo = &state.deployment_repo;
+    let deployment = repo.get_deployment(parse_id(deployment_id)?).await?;
+    let Some(protection) = repo
+        .get_environment_protection(ResourceId::from_uuid(deployment.environment_id))
+        .await?
+    else {
+        return Ok(());
+    };
+
+    let email = notification_email(notification, &notification.message(None));
+    for reviewer in &protection.reviewers {
+        let user = match state
+            .organization_repo
+            .get_user(ResourceId::from_uuid(*reviewer))
+            .await
+        {
+            Ok(user) => user,
+            Err(e) => {
+                warn!(reviewer = %reviewer, error = %e, "Failed to look up reviewer");
+                continue;
+            }
+        };
+        if let Err(e) = state
+            .email
+            .send(&Email {
+                to: user.email,
+                ..email.clone()
+            })
+            .await
+        {
+            warn!(reviewer = %reviewer, error = %e, "Failed to email reviewer");
+        }
+    }
+    Ok(())
+}
+
+/// Queue delivery of a notification to each channel that receives it.
+/// Returns how many channels that is.
+pub async fn notify(state: &AppState, notification: &Notification) -> Result<usize, ApiError> {
+    let channels = state
+        .notification_repo
+        .channels_for_event(
+            ResourceId::from_uuid(notification.tenant_id),
+            &notification.event,
+        )
+        .await?;
+    for channel in &channels {
+        tasks::enqueue(
+            state,
+            Task::Notification {
+                channel_id: channel.id,
+                notification: Box::new(notification.clone()),
+            },
+        )
+        .await?;
+    }
+    Ok(channels.len())
+}
+
+/// The notification for a broadcast event, if it is one channels can
+/// subscribe to.
+async fn notification(
+    state: &AppState,
+    event: &BroadcastEvent,
+) -> Result<Option<Notification>, ApiError> {
+    let notification = match event {
+        BroadcastEvent::RunUpdate { run_id, status } => {
+            let event = match status.as_str() {
+                "running" => "run.started",
+                "succeeded" => "run.succeeded",
+                "failed" => "run.failed",
+                _ => return Ok(None),
+            };
+            let run = state.pipeline_repo.get_run(parse_id(run_id)?).await?;
+            let pipeline = state
+                .pipeline_repo
+                .get_by_id(ResourceId::from_uuid(run.pipeline_id))
+                .await?;
+            let git = |key: &str| {
+                run.git_info
+                    .get(key)
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string()
+            };
+            let mut fields = vec![
+                ("pipeline", pipeline.name.clone()),
+                ("number", run.number.to_string()),
+                ("status", status.clone()),
+                ("branch", git("branch")),
+                ("sha", git("sha")),
+            ];
+            if event == "run.failed" {
+                let services = state
+                    .deployment_repo
+                    .list_services_by_pipeline(ResourceId::from_uuid(pipeline.id))
+                    .await?;
+                fields.extend(ownership_fields(&services));
+            }
+            Notification::new(
+                state,
+                event,
+                pipeline.tenant_id,
+                run.id,
+                &format!("/pipelines/{}/runs/{}", pipeline.id, run.id),
+                &fields,
+            )
+        }
+        BroadcastEvent::DeploymentUpdate {
+            deployment_id,
+            status,
+        } => {
+            let event = match status.as_str() {
+                "succeeded" => "deployment.succeeded",
+                "failed" => "deployment.failed",
+                "awaiting_promotion" | "awaiting_approval" => "approval.required",
+                _ => return Ok(None),
+            };
+            let repo = &state.deployment_repo;
+            let deployment = repo.get_deployment(parse_id(deployment_id)?).await?;
+            let service = repo
+                .get_service(ResourceId::from_uuid(deployment.service_id))
+                .await?;
+            let environment = repo
+                .get_environment(ResourceId::from_uuid(deployment.environment_id))
+                .await?;
+            let mut fields = vec![
+                (
+                    "subject",
+                    format!(
+                        "{} {} in {}",
+                        service.name, deployment.version, environment.name
+                    ),
+                ),
+                ("service", service.name.clone()),
+                ("environment", environment.name.clone()),
+                ("version", deployment.version.clone()),
+                ("status", status.clone()),
+                ("deployment_id", deployment.id.to_string()),
+            ];
+            fields.extend(ownership_fields(std::slice::from_ref(&service)));
+            Notification::new(
+                state,
+                event,
+                deployment.tenant_id,
+                deployment.id,
+                if event == "approval.required" {
+                    "/approvals"
+                } else {
+                    "/history"
+                },
+                &fields,
+            )
+        }
+        BroadcastEvent::StackRunUpdate {
+            run_id,
+            stack_id,
+            status,
+        } => {
+            if status != "needs_approval" {
+                return Ok(None);
+            }
+            let stack = state.stack_repo.get_stack(parse_id(stack_id)?).await?;
+            Notification::new(
+                state,
+                "approval.required",
+                stack.tenant_id,
+                *parse_id(run_id)?.as_uuid(),
+                &format!("/stacks/{}", stack.id),
+                &[
+                    ("subject", format!("Stack {}", stack.name)),
+                    ("stack", stack.name.clone()),
+                    ("status", status.clone()),
+                ],
+            )
+        }
+        BroadcastEvent::DriftDetected {
+            application_id,
+            application_name,
+            resources,
+            self_heal,
+        } => {
+            let app = state
+                .application_repo
+                .get_application(parse_id(application_id)?)
+                .await?;
+            Notification::new(
+                state,
+                "drift.detected",
+                app.tenant_id,
+                // Each time drift is found is news
+                Uuid::now_v7(),
+                &format!("/applications/{}", app.id),
+                &[
+                    ("application", application_name.clone()),
+                    ("resources", resources.to_string()),
+                    ("self_heal", self_heal.to_string()),
+                ],
+            )
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(notification))
+}
+
+/// Who owns the services a notification is about, from their catalog
+/// entries: the owning teams, the most critical tier and a runbook. Empty
+/// when no service has an owner.
+fn ownership_fields(services: &[Service]) -> Vec<(&'static str, String)> {
+    let mut teams: Vec<&str> = services
+        .iter()
+        .filter_map(|s| s.owner_team.as_deref())
+        .collect();
+    teams.sort();
+    teams.dedup();
+    if teams.is_empty() {
+        return vec![];
+    }
+    let mut fields = vec![("owner_team", teams.join(", "))];
+    if let Some(tier) = services.iter().filter_map(|s| s.tier).min() {
+        fields.push(("tier", tier.to_string()));
+    }
+    if let Some(runbook) = services.iter().find_map(|s| s.runbook_url.clone()) {
+        fields.push(("runbook_url", runbook));
+    }
+    fields
+}
+
+/// The owners of what failed and their runbook, added to failure messages
+/// that don't mention them.
+fn ownership_line(notification: &Notification, message: &str) -> Option<String> {
+    if !notification.event.ends_with(".failed") {
+        return None;
+    }
+    let team = notification.fields.get("owner_team")?;
+    if message.contains(team.as_str()) {
+        return None;
+    }
+    let mut line = format!("Owner: {}", team);
+    if let Some(tier) = notification.fields.get("tier") {
+        line.push_str(&format!(" (tier {})", tier));
+    }
+    if let Some(runbook) = notification.fields.get("runbook_url") {
+        line.push_str(&format!(" · Runbook: {}", runbook));
+    }
+    Some(line)
+}
+
+fn parse_id(id: &str) -> Result<ResourceId, ApiError> {
+    Uuid::parse_str(id)
+        .map(ResourceId::from_uuid)
+        .map_err(|_| ApiError::Internal(format!("Invalid ID in event: {}", id)))
+}
+
+/// Deliver a notification to a channel, recording the outcome on it.
+pub async fn deliver(
+    state: &AppState,
+    channel: &NotificationChannel,
+    notification: &Notification,
+) -> Result<(), String> {
+    let result = send(state, channel, notification).await;
+    if let Err(e) = state
+        .notification_repo
+        .record_delivery(
+            ResourceId::from_uuid(channel.id),
+            result.as_ref().err().map(String::as_str),
+        )
+        .await
+    {
+        warn!(channel = %channel.id, error = %e, "Failed to record notification delivery");
+    }
+    result
+}
+
+async fn send(
+    state: &AppState,
+    channel: &NotificationChannel,
+    notification: &Notification,
+) -> Result<(), String> {
+    // A channel's template is written for its events' fields, which tests lack
+    let template = Some(&channel.config)
+        .filter(|_| notification.event != TEST_EVENT)
+        .and_then(|config| config_str(config, "template"));
+    let message = notification.message(template);
+    let http = || {
+        reqwest::Client::builder()
+            .timeout(DELIVERY_TIMEOUT)
+            .build()
+            .map_err(|e| e.to_string())
+    };
+
+    match channel.kind.as_str() {
+        "slack" => {
+            let url =
+                config_str(&channel.config, "webhook_url").ok_or("channel has no webhook_url")?;
+            let text = slack_escape(&message);
+            let mut text = match &notification.url {
+                Some(link) => format!("<{}|{}>", link, text),
+                None => text,
+            };
+            if let Some(owners) = ownership_line(notification, &message) {
+                text.push_str(&format!("\n{}", slack_escape(&owners)));
+            }
+            let mut body = json!({ "text": text });
+            if let Some(deployment_id) = approvable_deployment(notification) {
+                body["blocks"] = slack::approval_blocks(&text, deployment_id);
+            }
+            let response = http()?
+                .post(url)
+                .json(&body)
+                .send()
+                .await
+                .map_err(|e| e.to_string())?;
+            check(response).await
+        }
+        "email" => {
+            let email = notification_email(notification, &message);
+            for to in recipients(&channel.config) {
+                state
+                    .email
+                    .send(&Email {
+                        to,
+                        ..email.clone()
+                    })
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+            Ok(())
+        }
+        "webhook" => {
+            let url = config_str(&channel.config, "url").ok_or("channel has no url")?;
+            let delivery = Uuid::now_v7();
+            let body = serde_json::to_vec(&json!({
+                "id": delivery,
+                "event": notification.event,
+                "tenant_id": notification.tenant_id,
+                "subject_id": notification.subject_id,
+                "message": message,
+                "data": notification.fields,
+                "url": notification.url,
+                "occurred_at": notification.occurred_at,
+            }))
+            .map_err(|e| e.to_string())?;
+            let mut request = http()?
+                .post(url)
+                .header("Content-Type", "application/json")
+                .header("User-Agent", "BuildIt-Notifications")
+                .header("X-BuildIt-Event", &notification.event)
+                .header("X-BuildIt-Delivery", delivery.to_string());
+            if let Some(secret) = &channel.secret {
+                request = request.header("X-BuildIt-Signature", signature(secret, &body));
+            }
+            let response = request.body(body).send().await.map_err(|e| e.to_string())?;
+            check(response).await
+        }
+        other => Err(format!("unknown channel kind {}", other)),
+    }
+}
+
+async fn check(response: reqwest::Response) -> Result<(), String> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let body = response.text().await.unwrap_or_default();
+    let body: String = body.chars().take(200).collect();
+    Err(format!("endpoint returned {}: {}", status, body))
+}
+
+/// The deployment an approval notification asks to review, which Slack
+/// messages offer buttons for.
+fn approvable_deployment(notification: &Notification) -> Option<Uuid> {
+    if notification.event != "approval.required"
+        || notification.fields.get("status").map(String::as_str) != Some("awaiting_approval")
+    {
+        return None;
+    }
+    notification
+        .fields
+        .get("deployment_id")
+        .and_then(|id| Uuid::parse_str(id).ok())
+}
+
+/// Slack treats `&`, `<` and `>` as markup.
+fn slack_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn notification_email(notification: &Notification, message: &str) -> Email {
+    let mut text = format!("{}\n\n", message);
+    let mut rows = String::new();
+    for (key, value) in notification.fields.iter().filter(|(_, v)| !v.is_empty()) {
+        text.push_str(&format!("{}: {}\n", key, value));
+        rows.push_str(&format!(
+            "<tr><td style=\"color:#71717a;padding-right:12px\">{}</td><td>{}</td></tr>",
+            html_escape(key),
+            html_escape(value)
+        ));
+    }
+    let mut link = String::new();
+    if let Some(url) = &notification.url {
+        text.push_str(&format!("\n{}\n", url));
+        link = format!(
+            "<p><a href=\"{}\">View in BuildIt</a></p>",
+            html_escape(url)
+        );
+    }
+    Email {
+        to: String::new(),
+        subject: format!("[BuildIt] {}", message),
+        html: format!(
+            "<p><strong>{}</strong></p><table>{}</table>{}",
+            html_escape(message),
+            rows,
+            link
+        ),
+        text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notification(event: &str, fields: &[(&str, &str)]) -> Notification {
+        Notification {
+            event: event.to_string(),
+            tenant_id: Uuid::new_v4(),
+            subject_id: Uuid::new_v4(),
+            fields: fields
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            url: None,
+            occurred_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_messages_fill_in_fields() {
+        let failed = notification("run.failed", &[("pipeline", "api"), ("number", "42")]);
+        assert_eq!(failed.message(None), "api run #42 failed");
+        assert_eq!(
+            failed.message(Some("{{ pipeline }} broke{{missing}}")),
+            "api broke"
+        );
+        // An unclosed placeholder is left as written
+        assert_eq!(failed.message(Some("{{pipeline")), "{{pipeline");
+    }
+
+    #[test]
+    fn test_channel_config_is_checked_for_its_kind() {
+        assert!(
+            validate_config(
+                "slack",
+                &json!({"webhook_url": "https://hooks.slack.com/x"})
+            )
+            .is_ok()
+        );
+        assert!(
+            validate_config("slack", &json!({"webhook_url": "http://hooks.slack.com/x"})).is_err()
+        );
+        assert!(validate_config("email", &json!({"recipients": ["ops@example.com"]})).is_ok());
+        assert!(validate_config("email", &json!({"recipients": []})).is_err());
+        assert_eq!(
+            validate_config("email", &json!({"recipients": ["ops"]})),
+            Err("ops is not an email address".to_string())
+        );
+        assert!(validate_config("webhook", &json!({"url": "ftp://example.com"})).is_err());
+        assert!(
+            validate_config(
+                "webhook",
+                &json!({"url": "https://example.com", "template": 1})
+            )
+            .is_err()
+        );
+        assert!(validate_config("pager", &json!({})).is_err());
+        assert!(validate_config("slack", &json!("https://hooks.slack.com/x")).is_err());
+    }
+
+    #[test]
+    fn test_failures_name_the_owning_team() {
+        let failed = notification(
+            "deployment.failed",
+            &[("owner_team", "payments"), ("tier", "1")],
+        );
+        let line = ownership_line(&failed, "Deployment failed").unwrap();
+        assert!(line.starts_with("Owner: payments (tier 1)"));
+        let succeeded = notification("deployment.succeeded", &[("owner_team", "payments")]);
+        assert_eq!(ownership_line(&succeeded, "Deployed"), None);
+    }
+}
@@ -0,0 +1,277 @@
+//! Open Policy Agent service for evaluating Rego policies with `opa eval`.
+//!
+//! A policy is a single Rego module whose `deny` rule collects violation
+//! messages, conftest style:
+//!
+//! ```rego
+//! package terraform.tags
+//!
+//! deny contains msg if {
+//!     some change in input.resource_changes
+//!     not change.change.after.tags.owner
+//!     msg := sprintf("%s has no owner tag", [change.address])
+//! }
+//! ```
+//!
+//! Messages may also be objects with a `msg` field.
+
+use buildit_core::stack::{PlanPolicy, PolicyResult};
+use std::path::PathBuf;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::{info, warn};
+
+/// Service for OPA operations.
+pub struct OpaService {
+    /// Path to opa binary
+    opa_bin: String,
+}
+
+impl Default for OpaService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OpaService {
+    pub fn new() -> Self {
+        let opa_bin = std::env::var("OPA_BIN").unwrap_or_else(|_| "opa".to_string());
+        Self { opa_bin }
+    }
+
+    /// Check that `rego` is a module OPA compiles, with a package to query.
+    pub async fn check(&self, rego: &str) -> Result<(), OpaError> {
+        package_of(rego)?;
+        let module = PolicyFile::write(rego).await?;
+        let output = Command::new(&self.opa_bin)
+            .arg("check")
+            .arg(&module.0)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await;
+        drop(module);
+        let output = output.map_err(|e| OpaError::Unavailable(e.to_string()))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            return Err(OpaError::InvalidPolicy(
+                format!("{}{}", stdout, stderr).trim().to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The messages of `rego`'s `deny` rule for `input`, empty when the
+    /// rule is undefined.
+    pub async fn deny(
+        &self,
+        rego: &str,
+        input: &serde_json::Value,
+    ) -> Result<Vec<String>, OpaError> {
+        let query = format!("data.{}.deny", package_of(rego)?);
+        let module = PolicyFile::write(rego).await?;
+        let output = async {
+            let mut child = Command::new(&self.opa_bin)
+                .args(["eval", "--format", "json", "--stdin-input", "--data"])
+                .arg(&module.0)
+                .arg(&query)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .map_err(|e| OpaError::Unavailable(e.to_string()))?;
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(input.to_string().as_bytes()).await?;
+            }
+            Ok::<_, OpaError>(child.wait_with_output().await?)
+        }
+        .await;
+        drop(module);
+        let output = output?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(OpaError::EvalFailed(
+                format!("{}{}", stdout, stderr).trim().to_string(),
+            ));
+        }
+        let result: serde_json::Value = serde_json::from_str(&stdout)
+            .map_err(|e| OpaError::EvalFailed(format!("Unreadable opa output: {}", e)))?;
+        Ok(deny_messages(&result))
+    }
+
+    /// Evaluate the enabled policies against a plan's JSON. Without a JSON
+    /// plan every policy fails to evaluate.
+    pub async fn check_plan(
+        &self,
+        policies: &[PlanPolicy],
+        plan_json: Option<&serde_json::Value>,
+    ) -> Vec<PolicyResult> {
+        let mut results = Vec::new();
+        for policy in policies.iter().filter(|p| p.enabled) {
+            let outcome = match plan_json {
+                Some(plan_json) => self.deny(&policy.rego, plan_json).await,
+                None => Err(OpaError::EvalFailed(
+                    "Terraform produced no JSON plan to evaluate".to_string(),
+                )),
+            };
+            let (violations, error) = match outcome {
+                Ok(violations) => (violations, None),
+                Err(e) => {
+                    warn!(policy = %policy.name, error = %e, "Plan policy could not be evaluated");
+                    (Vec::new(), Some(e.to_string()))
+                }
+            };
+            info!(
+                policy = %policy.name,
+                violations = violations.len(),
+                "Plan policy evaluated"
+            );
+            results.push(PolicyResult {
+                policy: policy.name.clone(),
+                enforcement: policy.enforcement,
+                violations,
+                error,
+            });
+        }
+        results
+    }
+}
+
+/// The package a module declares, e.g. `terraform.tags`.
+fn package_of(rego: &str) -> Result<String, OpaError> {
+    rego.lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .find_map(|line| line.strip_prefix("package "))
+        .map(|package| package.trim().to_string())
+        .filter(|package| !package.is_empty())
+        .ok_or_else(|| OpaError::InvalidPolicy("Policy declares no package".to_string()))
+}
+
+/// Violation messages from `opa eval --format json` output.
+fn deny_messages(result: &serde_json::Value) -> Vec<String> {
+    let values = result["result"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|r| r["expressions"].as_array().into_iter().flatten())
+        .flat_map(|e| e["value"].as_array().into_iter().flatten());
+    values
+        .map(|value| match value {
+            serde_json::Value::String(msg) => msg.clone(),
+            value => value["msg"]
+                .as_str()
+                .map(str::to_string)
+                .unwrap_or_else(|| value.to_string()),
+        })
+        .collect()
+}
+
+/// A policy written to a temporary `.rego` file, removed on drop.
+struct PolicyFile(PathBuf);
+
+impl PolicyFile {
+    async fn write(rego: &str) -> Result<Self, OpaError> {
+        let path =
+            std::env::temp_dir().join(format!("buildit-policy-{}.rego", uuid::Uuid::new_v4()));
+        tokio::fs::write(&path, rego).await?;
+        Ok(Self(path))
+    }
+}
+
+impl Drop for PolicyFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// OPA errors.
+#[derive(Debug, thiserror::Error)]
+pub enum OpaError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Could not run opa: {0}")]
+    Unavailable(String),
+
+    #[error("Invalid policy: {0}")]
+    InvalidPolicy(String),
+
+    #[error("Policy evaluation failed: {0}")]
+    EvalFailed(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use buildit_core::stack::PolicyEnforcement;
+
+    fn policy(name: &str, enforcement: PolicyEnforcement, enabled: bool) -> PlanPolicy {
+        let now = chrono::Utc::now();
+        PlanPolicy {
+            id: uuid::Uuid::new_v4(),
+            tenant_id: uuid::Uuid::new_v4(),
+            name: name.to_string(),
+            description: None,
+            rego: "package terraform.tags\n".to_string(),
+            enforcement,
+            enabled,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    #[test]
+    fn test_package_is_read_from_the_module() {
+        let rego = "# package commented.out\n\npackage terraform.tags # owners\n\ndeny contains msg if { false }\n";
+        assert_eq!(package_of(rego).unwrap(), "terraform.tags");
+        assert!(matches!(
+            package_of("deny contains msg if { false }"),
+            Err(OpaError::InvalidPolicy(_))
+        ));
+    }
+
+    #[test]
+    fn test_deny_messages_are_strings_or_msg_fields() {
+        let result = serde_json::json!({
+            "result": [{
+                "expressions": [{
+                    "value": [
+                        "aws_s3_bucket.logs has no owner tag",
+                        { "msg": "aws_instance.web is too large", "severity": "high" },
+                        { "code": 7 }
+                    ]
+                }]
+            }]
+        });
+        assert_eq!(
+            deny_messages(&result),
+            vec![
+                "aws_s3_bucket.logs has no owner tag".to_string(),
+                "aws_instance.web is too large".to_string(),
+                r#"{"code":7}"#.to_string(),
+            ]
+        );
+        assert!(deny_messages(&serde_json::json!({})).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_policies_fail_closed_without_a_plan() {
+        let policies = [
+            policy("tags", PolicyEnforcement::Hard, true),
+            policy("sizes", PolicyEnforcement::Soft, true),
+            policy("retired", PolicyEnforcement::Hard, false),
+        ];
+        let results = OpaService::new().check_plan(&policies, None).await;
+
+        // Disabled policies are not evaluated
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| r.failed() && r.error.is_some()));
+        assert!(results[0].blocks());
+        assert!(!results[1].blocks());
+    }
+}
@@ -0,0 +1,639 @@
+//! Executes pipeline runs claimed from the task queue.
+//!
+//! A run is rebuilt from the database on every attempt. Stages that already
+//! succeeded are left out, so a run interrupted by a restart resumes at the
+//! first unfinished stage instead of starting over; so are stages a re-run
+//! reuses from the run it re-runs. Stages whose job was
+//! still running are re-adopted through the executor handle recorded when
+//! the job was dispatched, rather than spawned a second time.
+//!
+//! A run whose tenant is over quota waits in the queue, or fails if the
+//! quota says to reject work; each stage's job is checked again before it
+//! starts.
+
+use buildit_config::{
+    EnvLayers, ResolvedVariable, VariableContext, VariableContextBuilder, VariableSource,
+};
+use buildit_core::ResourceId;
+use buildit_core::executor::{GitCloneSpec, LogFrame, LogStream, ResourceRequirements};
+use buildit_core::pipeline::{
+    CheckoutConfig, Pipeline, PipelineParam, Stage, StageAction, Trigger,
+};
+use buildit_core::tenant::QuotaAction;
+use buildit_db::repo::pipeline::{PipelineRecord, PipelineRunRecord};
+use buildit_db::{LogRepo, PipelineRepo, RepositoryRepo, VariableGroupRepo};
+use buildit_scheduler::quota::JobResources;
+use buildit_scheduler::{AdoptedJob, PipelineEvent, TaskContext};
+use chrono::Utc;
+use std::collections::{HashMap, HashSet};
+
+use crate::AppState;
+use crate::routes::pipelines::policy_violations;
+use crate::services::git::GitCredentials;
+use crate::services::{metrics, scans, test_reports};
+use crate::ws::BroadcastEvent;
+
+/// Run statuses that need no further work.
+const FINISHED: &[&str] = &["succeeded", "failed", "cancelled"];
+
+/// Execute a pipeline run, resuming after any stages that already succeeded.
+#[tracing::instrument(name = "run.execute", skip(state, task), fields(run_id = %run_id, pipeline_id, status))]
+pub async fn execute_run(
+    state: &AppState,
+    run_id: ResourceId,
+    task: &TaskContext,
+) -> Result<(), String> {
+    let Some(orchestrator) = state.orchestrator.clone() else {
+        return Err("no executor is configured".to_string());
+    };
+    let pipeline_repo = &state.pipeline_repo;
+    let run = pipeline_repo
+        .get_run(run_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    if FINISHED.contains(&run.status.as_str()) {
+        tracing::info!(run_id = %run_id, status = %run.status, "Run already finished");
+        return Ok(());
+    }
+    let record = pipeline_repo
+        .get_by_id(ResourceId::from_uuid(run.pipeline_id))
+        .await
+        .map_err(|e| e.to_string())?;
+    tracing::Span::current().record("pipeline_id", tracing::field::display(record.id));
+
+    // Hold a run that has not started while its tenant is over quota,
+    // rather than tying up a worker
+    if run.status == "queued"
+        && let Err((exceeded, action)) = state
+            .quota
+            .check(record.tenant_id, JobResources::default())
+            .await
+            .map_err(|e| format!("failed to check quota: {}", e))?
+    {
+        if action == QuotaAction::Reject {
+            tracing::warn!(run_id = %run_id, reason = %exceeded, "Quota exceeded; failing run");
+            return fail_unstarted_run(state, run_id).await;
+        }
+        tracing::info!(run_id = %run_id, reason = %exceeded, "Quota exceeded; holding run");
+        return task.defer_until(exceeded.retry_at(Utc::now())).await;
+    }
+    let mut pipeline = load_pipeline(state, &record)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // The tenant's and organization's policies may have changed since the
+    // run was triggered, and webhooks and child pipelines trigger unchecked
+    if run.status == "queued" {
+        let violations = policy_violations(
+            &state.organization_repo,
+            &state.tenant_repo,
+            ResourceId::from_uuid(record.tenant_id),
+            &pipeline.stages,
+        )
+        .await
+        .map_err(|e| format!("failed to check pipeline policy: {}", e))?;
+        if !violations.is_empty() {
+            tracing::warn!(run_id = %run_id, violations = ?violations, "Pipeline policy violated; failing run");
+            return fail_unstarted_run(state, run_id).await;
+        }
+    }
+
+    // Stage results are planned with the run; create them for stages added
+    // since, and skip the ones an earlier attempt completed.
+    let results = pipeline_repo
+        .list_stage_results(run_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    let recorded: HashSet<&str> = results.iter().map(|r| r.stage_name.as_str()).collect();
+    let completed: HashSet<String> = results
+        .iter()
+        .filter(|r| matches!(r.status.as_str(), "succeeded" | "reused"))
+        .map(|r| r.stage_name.clone())
+        .collect();
+    for stage in &pipeline.stages {
+        if !recorded.contains(stage.name.as_str()) {
+            if let Err(e) = pipeline_repo.create_stage_result(run_id, &stage.name).await {
+                tracing::error!(error = %e, stage = %stage.name, "Failed to create stage result");
+            }
+        }
+    }
+    // Jobs an interrupted attempt left running on this executor
+    let mut adopted = HashMap::new();
+    for result in &results {
+        if !matches!(result.status.as_str(), "running" | "stalled") {
+            continue;
+        }
+        let Some(handle) = result
+            .job_handle()
+            .filter(|h| orchestrator.executors().get(&h.executor_name).is_some())
+        else {
+            continue;
+        };
+        let logged_lines = state
+            .log_repo
+            .count_logs_for_stage(run_id, &result.stage_name)
+            .await
+            .map_err(|e| e.to_string())?;
+        adopted.insert(
+            result.stage_name.clone(),
+            AdoptedJob {
+                handle,
+                logged_lines: logged_lines as usize,
+            },
+        );
+    }
+    if !completed.is_empty() {
+        tracing::info!(run_id = %run_id, completed = ?completed, "Resuming run after completed stages");
+        pipeline.stages.retain(|s| !completed.contains(&s.name));
+        for stage in &mut pipeline.stages {
+            stage.needs.retain(|need| !completed.contains(need));
+        }
+    }
+
+    pipeline_repo
+        .update_run_status(run_id, "running")
+        .await
+        .map_err(|e| format!("failed to mark run running: {}", e))?;
+    metrics::record_run("running");
+    state.event_bus.publish(BroadcastEvent::RunUpdate {
+        run_id: run_id.to_string(),
+        status: "running".to_string(),
+    });
+
+    let git_clone_spec = git_clone_spec(state, &record, &pipeline.checkout, &run).await;
+    let (env, var_ctx, variables) = run_environment(state, &mut pipeline, &run)
+        .await
+        .map_err(|e| format!("failed to load variables: {}", e))?;
+    if let Err(e) = state
+        .variable_group_repo
+        .set_run_variables(run_id, serde_json::json!(variables))
+        .await
+    {
+        tracing::error!(error = %e, "Failed to record run variables");
+    }
+
+    tracing::info!(run_id = %run_id, "Executing pipeline with {} stages", pipeline.stages.len());
+    let (mut event_rx, result_handle) =
+        orchestrator.resume_with_git(&pipeline, env, Some(var_ctx), git_clone_spec, adopted);
+
+    let run_id_str = run_id.to_string();
+    let event_bus = &state.event_bus;
+    while let Some(event) = event_rx.recv().await {
+        match event {
+            PipelineEvent::StageStarted { stage } => {
+                tracing::info!(run_id = %run_id, stage = %stage, "Stage started");
+                if let Err(e) = pipeline_repo
+                    .update_stage_result_queued(run_id, &stage)
+                    .await
+                {
+                    tracing::error!(error = %e, "Failed to update stage start");
+                }
+                event_bus.publish(BroadcastEvent::StageUpdate {
+                    run_id: run_id_str.clone(),
+                    stage_name: stage.clone(),
+                    status: "running".to_string(),
+                    duration: None,
+                });
+            }
+            PipelineEvent::StageDispatched {
+                stage,
+                handle,
+                routing,
+                image_digest,
+            } => {
+                if routing.rerouted() {
+                    tracing::warn!(run_id = %run_id, stage = %stage, from = %routing.preferred, to = %routing.executor, "Stage rerouted to another executor");
+                }
+                let routing = serde_json::to_value(&routing).unwrap_or_default();
+                if let Err(e) = pipeline_repo
+                    .record_stage_job(run_id, &stage, &handle, routing, image_digest.as_deref())
+                    .await
+                {
+                    tracing::error!(error = %e, stage = %stage, "Failed to record stage job");
+                }
+            }
+            PipelineEvent::StageCompleted {
+                stage,
+                success,
+                exit_code,
+                error,
+            } => {
+                let status = if success { "succeeded" } else { "failed" };
+                tracing::info!(run_id = %run_id, stage = %stage, status = %status, exit_code = ?exit_code, "Stage completed");
+                if let Err(e) = pipeline_repo
+                    .update_stage_result_finished(
+                        run_id,
+                        &stage,
+                        status,
+                        exit_code,
+                        error.as_deref(),
+                    )
+                    .await
+                {
+                    tracing::error!(error = %e, "Failed to update stage finish");
+                }
+                event_bus.publish(BroadcastEvent::StageUpdate {
+                    run_id: run_id_str.clone(),
+                    stage_name: stage.clone(),
+                    status: status.to_string(),
+                    duration: None, // TODO: calculate duration
+                });
+                if !success {
+                    let owners = pipeline.ownership.owners_for_stage(&stage).to_vec();
+                    if !owners.is_empty() {
+                        tracing::warn!(run_id = %run_id, stage = %stage, owners = ?owners, "Stage failed, notifying owners");
+                    }
+                    event_bus.publish(BroadcastEvent::StageFailed {
+                        run_id: run_id_str.clone(),
+                        pipeline_name: pipeline.name.clone(),
+                        stage_name: stage.clone(),
+                        owners,
+                    });
+                }
+            }
+            PipelineEvent::StageSkipped { stage, reason } => {
+                tracing::info!(run_id = %run_id, stage = %stage, reason = %reason, "Stage skipped");
+                if let Err(e) = pipeline_repo
+                    .update_stage_result_finished(run_id, &stage, "skipped", None, Some(&reason))
+                    .await
+                {
+                    tracing::error!(error = %e, "Failed to update stage skip");
+                }
+                event_bus.publish(BroadcastEvent::StageUpdate {
+                    run_id: run_id_str.clone(),
+                    stage_name: stage.clone(),
+                    status: "skipped".to_string(),
+                    duration: None,
+                });
+            }
+            PipelineEvent::StageLog {
+                stage,
+                line,
+                step,
+                frame,
+            } => {
+                let stream = match line.stream {
+                    LogStream::Stdout => "stdout",
+                    LogStream::Stderr => "stderr",
+                    LogStream::System => "system",
+                };
+                if let Err(e) = state
+                    .log_repo
+                    .append_log(run_id, &stage, stream, &line.content, step, frame.as_ref())
+                    .await
+                {
+                    tracing::error!(error = %e, "Failed to store log line");
+                }
+                event_bus.publish(BroadcastEvent::LogLine {
+                    run_id: run_id_str.clone(),
+                    stage_name: stage.clone(),
+                    content: line.content.clone(),
+                    stream: stream.to_string(),
+                    step,
+                    frame: frame.as_ref().map(|f| f.kind().to_string()),
+                    exit_code: match frame {
+                        Some(LogFrame::StepFinished { exit_code, .. }) => Some(exit_code),
+                        _ => None,
+                    },
+                });
+            }
+            PipelineEvent::TestReport { stage, path, cases } => {
+                test_reports::ingest(
+                    state,
+                    run_id,
+                    ResourceId::from_uuid(run.pipeline_id),
+                    &stage,
+                    &path,
+                    cases,
+                )
+                .await;
+            }
+            PipelineEvent::ScanCompleted {
+                stage,
+                scanner,
+                target,
+                findings,
+                sbom,
+            } => {
+                scans::record(state, run_id, &stage, scanner, &target, findings, sbom).await;
+            }
+            PipelineEvent::PipelineCompleted { success } => {
+                tracing::info!(run_id = %run_id, success = %success, "Pipeline completed");
+                let status = if success { "succeeded" } else { "failed" };
+                event_bus.publish(BroadcastEvent::RunUpdate {
+                    run_id: run_id_str.clone(),
+                    status: status.to_string(),
+                });
+            }
+        }
+    }
+
+    let result = result_handle
+        .await
+        .map_err(|e| format!("pipeline execution task failed: {}", e))?;
+    if let Err(e) = state.quota.finish_run(*run_id.as_uuid()).await {
+        tracing::warn!(run_id = %run_id, error = %e, "Failed to finish usage records");
+    }
+    let status = if result.success {
+        tracing::info!(run_id = %run_id, "Pipeline succeeded");
+        "succeeded"
+    } else {
+        tracing::warn!(run_id = %run_id, "Pipeline failed");
+        "failed"
+    };
+    pipeline_repo
+        .update_run_status(run_id, status)
+        .await
+        .map_err(|e| format!("failed to update run status to {}: {}", status, e))?;
+    tracing::Span::current().record("status", status);
+    metrics::record_run(status);
+    test_reports::enqueue_summary(state, &run).await;
+    Ok(())
+}
+
+/// Clone of the run's commit into the job's workspace, if the pipeline is
+/// linked to a repository.
+pub(crate) async fn git_clone_spec(
+    state: &AppState,
+    record: &PipelineRecord,
+    checkout: &CheckoutConfig,
+    run: &PipelineRunRecord,
+) -> Option<GitCloneSpec> {
+    let repo_id = record.repository_id?;
+    let repo = match state
+        .repository_repo
+        .get_by_id(ResourceId::from_uuid(repo_id))
+        .await
+    {
+        Ok(repo) => repo,
+        Err(e) => {
+            tracing::warn!(error = %e, "Failed to get repository for pipeline, skipping git clone");
+            return None;
+        }
+    };
+    let access_token = GitCredentials::from_env()
+        .token(repo.provider)
+        .map(str::to_string);
+    Some(GitCloneSpec {
+        url: repo.clone_url,
+        branch: git_field(&run.git_info, "branch"),
+        sha: git_field(&run.git_info, "sha"),
+        depth: Some(checkout.depth).filter(|d| *d > 0),
+        target_dir: "/workspace".to_string(),
+        username: access_token
+            .as_ref()
+            .map(|_| repo.provider.token_username().to_string()),
+        access_token,
+        submodules: checkout.submodules,
+        lfs: checkout.lfs,
+        sparse_paths: checkout.sparse.clone(),
+        cache: checkout.cache,
+    })
+}
+
+/// Environment and variables the run's jobs see, and where each variable
+/// came from (see [`buildit_config::env`]). Variables the run was
+/// triggered with replace the stages' own, so they win over every layer.
+pub(crate) async fn run_environment(
+    state: &AppState,
+    pipeline: &mut Pipeline,
+    run: &PipelineRunRecord,
+) -> buildit_db::DbResult<(
+    HashMap<String, String>,
+    VariableContext,
+    Vec<ResolvedVariable>,
+)> {
+    let mut system = HashMap::from([
+        ("CI".to_string(), "true".to_string()),
+        ("BUILDIT".to_string(), "true".to_string()),
+    ]);
+    system.extend(state.system_config.jobs.env.clone());
+
+    let mut layers = EnvLayers::new();
+    layers.push(VariableSource::System, system);
+    let groups = state
+        .variable_group_repo
+        .list_all_groups(pipeline.tenant_id)
+        .await?;
+    let (tenant_groups, pipeline_groups): (Vec<_>, Vec<_>) = groups
+        .into_iter()
+        .filter(|g| g.pipeline_id.is_none_or(|id| id == *pipeline.id.as_uuid()))
+        .partition(|g| g.pipeline_id.is_none());
+    for group in tenant_groups {
+        let variables = group.variables();
+        layers.push(VariableSource::TenantGroup { group: group.name }, variables);
+    }
+    for group in pipeline_groups {
+        let variables = group.variables();
+        layers.push(
+            VariableSource::PipelineGroup { group: group.name },
+            variables,
+        );
+    }
+    layers.push(VariableSource::Pipeline, pipeline.env.clone());
+    let overrides: HashMap<String, String> = run
+        .trigger_info
+        .get("variables")
+        .and_then(|v| serde_json::from_value(v.clone()).ok())
+        .unwrap_or_default();
+    layers.push(VariableSource::Trigger, overrides.clone());
+
+    let mut variables = layers.resolve();
+    let env = layers.env();
+    let mut stage_variables = Vec::new();
+    apply_overrides(
+        &mut pipeline.stages,
+        &overrides,
+        &variables,
+        &mut stage_variables,
+    );
+    variables.extend(stage_variables);
+
+    // Runs not triggered by hand have no params; they get the defaults
+    let given: HashMap<String, String> = run
+        .trigger_info
+        .get("params")
+        .and_then(|v| serde_json::from_value(v.clone()).ok())
+        .unwrap_or_default();
+    let params = pipeline.params.iter().filter_map(|param| {
+        let value = given.get(&param.name).or(param.default.as_ref())?;
+        Some((param.name.clone(), value.clone()))
+    });
+
+    let var_ctx = env
+        .iter()
+        .fold(VariableContextBuilder::new(), |builder, (name, value)| {
+            builder.with_env(name, value)
+        });
+    let var_ctx = params
+        .fold(var_ctx, |builder, (name, value)| {
+            builder.with_param(name, value)
+        })
+        .with_pipeline(pipeline.id.to_string(), pipeline.name.clone())
+        .with_run(run.id.to_string(), run.number as u32)
+        .with_git_branch(git_field(&run.git_info, "branch").unwrap_or_default())
+        .with_git_sha(git_field(&run.git_info, "sha").unwrap_or_default());
+    // Left unset for branch runs, so `${git.tag}` has no value
+    let var_ctx = match git_field(&run.git_info, "tag") {
+        Some(tag) => var_ctx.with_git_tag(tag),
+        None => var_ctx,
+    }
+    .build();
+    Ok((env, var_ctx, variables))
+}
+
+/// Replace the stages' variables that `overrides` sets, recording the
+/// others in `recorded` over what they override in `run_variables`.
+fn apply_overrides(
+    stages: &mut [Stage],
+    overrides: &HashMap<String, String>,
+    run_variables: &[ResolvedVariable],
+    recorded: &mut Vec<ResolvedVariable>,
+) {
+    for stage in stages {
+        let mut names: Vec<&String> = stage.env.keys().collect();
+        names.sort();
+        for name in names {
+            if overrides.contains_key(name) {
+                continue;
+            }
+            recorded.push(ResolvedVariable {
+                name: name.clone(),
+                value: stage.env[name].clone(),
+                source: VariableSource::Stage {
+                    stage: stage.name.clone(),
+                },
+                overrides: run_variables
+                    .iter()
+                    .find(|v| v.name == *name)
+                    .map(|v| v.source.clone())
+                    .into_iter()
+                    .collect(),
+            });
+        }
+        for (name, value) in overrides {
+            if let Some(stage_value) = stage.env.get_mut(name) {
+                *stage_value = value.clone();
+            }
+        }
+        match &mut stage.action {
+            StageAction::Parallel { stages } => {
+                apply_overrides(stages, overrides, run_variables, recorded)
+            }
+            StageAction::Matrix { stage, .. } => apply_overrides(
+                std::slice::from_mut(stage.as_mut()),
+                overrides,
+                run_variables,
+                recorded,
+            ),
+            _ => {}
+        }
+    }
+}
+
+/// Fail a run that is refused before any of its stages started.
+async fn fail_unstarted_run(state: &AppState, run_id: ResourceId) -> Result<(), String> {
+    state
+        .pipeline_repo
+        .update_run_status(run_id, "failed")
+        .await
+        .map_err(|e| format!("failed to update run status to failed: {}", e))?;
+    metrics::record_run("failed");
+    state.event_bus.publish(BroadcastEvent::RunUpdate {
+        run_id: run_id.to_string(),
+        status: "failed".to_string(),
+    });
+    Ok(())
+}
+
+/// Build the executable pipeline from its record and stage definitions.
+pub(crate) async fn load_pipeline(
+    state: &AppState,
+    record: &PipelineRecord,
+) -> buildit_db::DbResult<Pipeline> {
+    // Stages that request no resources get the pipeline's, which new
+    // pipelines take from their tenant's policy
+    let config = &record.config;
+    let resources: ResourceRequirements =
+        serde_json::from_value(config.get("resources").cloned().unwrap_or_default())
+            .unwrap_or_default();
+    let stages: Vec<Stage> = state
+        .pipeline_repo
+        .list_stages(ResourceId::from_uuid(record.id))
+        .await?
+        .into_iter()
+        .map(|s| {
+            let wait_for = s.wait_for();
+            let needs_artifacts = s.needs_artifacts();
+            let security_context = s.security_context();
+            let container = s.container();
+            let pull_policy = s.pull_policy();
+            let action = match (s.scan(), s.release(), s.trigger_pipeline()) {
+                (Some(scan), _, _) => StageAction::Scan(Box::new(scan)),
+                (None, Some(release), _) => StageAction::Release(Box::new(release)),
+                (None, None, Some(trigger)) => StageAction::TriggerPipeline(Box::new(trigger)),
+                (None, None, None) => StageAction::Run {
+                    image: s.image.unwrap_or_else(|| "alpine:latest".to_string()),
+                    commands: s.commands,
+                    artifacts: vec![],
+                    test_reports: s.test_reports,
+                },
+            };
+            let env: HashMap<String, String> = serde_json::from_value(s.env).unwrap_or_default();
+            Stage {
+                name: s.name,
+                needs: s.depends_on,
+                when: None,
+                manual: false,
+                action,
+                env,
+                runs_on: s.runs_on,
+                resources: serde_json::from_value(s.resources)
+                    .ok()
+                    .filter(|r| *r != ResourceRequirements::default())
+                    .unwrap_or_else(|| resources.clone()),
+                platform: s.platform.parse().unwrap_or_default(),
+                wait_for,
+                needs_artifacts,
+                security_context,
+                container,
+                pull_policy,
+            }
+        })
+        .collect();
+
+    // Parse env and triggers from config JSON
+    let env: HashMap<String, String> =
+        serde_json::from_value(config.get("env").cloned().unwrap_or_default()).unwrap_or_default();
+    let triggers: Vec<Trigger> =
+        serde_json::from_value(config.get("triggers").cloned().unwrap_or_default())
+            .unwrap_or_default();
+    let checkout: CheckoutConfig =
+        serde_json::from_value(config.get("checkout").cloned().unwrap_or_default())
+            .unwrap_or_default();
+    let params: Vec<PipelineParam> =
+        serde_json::from_value(config.get("params").cloned().unwrap_or_default())
+            .unwrap_or_default();
+
+    Ok(Pipeline {
+        id: ResourceId::from_uuid(record.id),
+        name: record.name.clone(),
+        tenant_id: ResourceId::from_uuid(record.tenant_id),
+        repository: record.repository.clone(),
+        triggers,
+        stages,
+        env,
+        caches: vec![],
+        ownership: record.ownership(),
+        checkout,
+        params,
+    })
+}
+
+fn git_field(git_info: &serde_json::Value, key: &str) -> Option<String> {
+    git_info
+        .get(key)
+        .and_then(|v| v.as_str())
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+}
@@ -0,0 +1,197 @@
+//! Environment protection rules.
+//!
+//! A protected environment only takes deployments from the branches it
+//! allows. Deployments to it wait in `awaiting_approval` until they have as
+//! many approvals as it requires, from its designated reviewers when it
+//! names any, and then for its wait timer before they roll out.
+
+use buildit_config::condition::glob_match;
+use buildit_core::rbac::Role;
+use buildit_db::{Deployment, DeploymentReview, EnvironmentProtection};
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+/// Most approvals a rule can require.
+pub const MAX_APPROVALS: i32 = 10;
+/// Longest wait timer, 30 days.
+pub const MAX_WAIT_MINUTES: i32 = 30 * 24 * 60;
+
+/// Check the branch a deployment comes from against the environment's
+/// branch restrictions, returning why it is refused.
+pub fn check_branch(
+    environment: &str,
+    protection: Option<&EnvironmentProtection>,
+    branch: Option<&str>,
+) -> Result<(), String> {
+    let Some(protection) = protection.filter(|p| !p.branches.is_empty()) else {
+        return Ok(());
+    };
+    let allowed = protection.branches.join(", ");
+    match branch {
+        Some(branch)
+            if protection
+                .branches
+                .iter()
+                .any(|p| glob_match(p, branch, false)) =>
+        {
+            Ok(())
+        }
+        Some(branch) => Err(format!(
+            "Deployments to {} must come from a branch matching {}, not {}",
+            environment, allowed, branch
+        )),
+        None => Err(format!(
+            "Deployments to {} must come from a branch matching {}; give the branch",
+            environment, allowed
+        )),
+    }
+}
+
+/// Whether the rules name who may review, rather than leaving it to anyone
+/// who may deploy.
+pub fn designates_reviewers(protection: &EnvironmentProtection) -> bool {
+    !protection.reviewers.is_empty() || !protection.reviewer_roles.is_empty()
+}
+
+/// Whether a user holding `role` is one of the designated reviewers.
+pub fn is_reviewer(
+    protection: &EnvironmentProtection,
+    user_id: Option<Uuid>,
+    role: Option<Role>,
+) -> bool {
+    user_id.is_some_and(|id| protection.reviewers.contains(&id))
+        || role.is_some_and(|role| {
+            protection
+                .reviewer_roles
+                .iter()
+                .filter_map(|r| Role::parse(r))
+                .any(|required| role >= required)
+        })
+}
+
+/// Approvals among `reviews`.
+pub fn approvals(reviews: &[DeploymentReview]) -> i32 {
+    reviews.iter().filter(|r| r.approved).count() as i32
+}
+
+/// When a deployment's wait timer runs out: `wait_minutes` after its last
+/// approval, or after it was created if it needed none.
+pub fn wait_until(
+    protection: &EnvironmentProtection,
+    deployment: &Deployment,
+    reviews: &[DeploymentReview],
+) -> Option<DateTime<Utc>> {
+    if protection.wait_minutes <= 0 {
+        return None;
+    }
+    let from = reviews
+        .iter()
+        .filter(|r| r.approved)
+        .map(|r| r.created_at)
+        .max()
+        .unwrap_or(deployment.created_at);
+    Some(from + Duration::minutes(protection.wait_minutes as i64))
+        .filter(|until| *until > Utc::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn protection() -> EnvironmentProtection {
+        EnvironmentProtection {
+            environment_id: Uuid::new_v4(),
+            tenant_id: Uuid::new_v4(),
+            reviewers: Vec::new(),
+            reviewer_roles: Vec::new(),
+            required_approvals: 1,
+            wait_minutes: 0,
+            branches: Vec::new(),
+            updated_by: None,
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn review(approved: bool, minutes_ago: i64) -> DeploymentReview {
+        DeploymentReview {
+            id: Uuid::new_v4(),
+            deployment_id: Uuid::new_v4(),
+            user_id: Some(Uuid::new_v4()),
+            api_key_id: None,
+            approved,
+            comment: None,
+            created_at: Utc::now() - Duration::minutes(minutes_ago),
+        }
+    }
+
+    #[test]
+    fn test_only_allowed_branches_deploy() {
+        let mut rules = protection();
+        assert!(check_branch("production", Some(&rules), None).is_ok());
+        rules.branches = vec!["main".to_string(), "release/*".to_string()];
+        assert!(check_branch("production", Some(&rules), Some("main")).is_ok());
+        assert!(check_branch("production", Some(&rules), Some("release/1.2")).is_ok());
+        assert_eq!(
+            check_branch("production", Some(&rules), Some("feature/x")),
+            Err(
+                "Deployments to production must come from a branch matching main, release/*, not feature/x"
+                    .to_string()
+            )
+        );
+        assert!(check_branch("production", Some(&rules), None).is_err());
+        assert!(check_branch("production", None, Some("feature/x")).is_ok());
+    }
+
+    #[test]
+    fn test_designated_reviewers() {
+        let reviewer = Uuid::new_v4();
+        let mut rules = protection();
+        assert!(!designates_reviewers(&rules));
+        rules.reviewers = vec![reviewer];
+        rules.reviewer_roles = vec!["admin".to_string()];
+        assert!(designates_reviewers(&rules));
+
+        assert!(is_reviewer(&rules, Some(reviewer), Some(Role::Viewer)));
+        assert!(is_reviewer(&rules, Some(Uuid::new_v4()), Some(Role::Owner)));
+        assert!(!is_reviewer(
+            &rules,
+            Some(Uuid::new_v4()),
+            Some(Role::Member)
+        ));
+        assert!(!is_reviewer(&rules, None, None));
+    }
+
+    #[test]
+    fn test_wait_timer_runs_from_the_last_approval() {
+        let mut rules = protection();
+        let deployment = Deployment {
+            id: Uuid::new_v4(),
+            tenant_id: rules.tenant_id,
+            service_id: Uuid::new_v4(),
+            environment_id: rules.environment_id,
+            pipeline_run_id: None,
+            version: "1.0.0".to_string(),
+            commit_sha: None,
+            status: "awaiting_approval".to_string(),
+            started_at: None,
+            finished_at: None,
+            config: serde_json::json!({}),
+            created_at: Utc::now() - Duration::minutes(120),
+            spec_version: None,
+            release_notes: None,
+            promoted_from: None,
+            freeze_override: None,
+            branch: None,
+        };
+        let reviews = [review(true, 50), review(true, 10), review(false, 1)];
+        assert_eq!(approvals(&reviews), 2);
+        assert_eq!(wait_until(&rules, &deployment, &reviews), None);
+
+        rules.wait_minutes = 30;
+        let until = wait_until(&rules, &deployment, &reviews).unwrap();
+        assert!(until > Utc::now() + Duration::minutes(19));
+        assert!(until <= Utc::now() + Duration::minutes(20));
+        // Long past for a deployment that needed no approval
+        assert_eq!(wait_until(&rules, &deployment, &[]), None);
+    }
+}
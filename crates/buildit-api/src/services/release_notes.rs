@@ -0,0 +1,394 @@
+//! Release notes for production deployments.
+//!
+//! Notes list the commits built by a service's pipeline since the last
+//! successful deployment of that service to the same environment. They are
+//! stored on the deployment record and can optionally be posted to Slack
+//! and published as a GitHub release.
+//!
+//! Publishing is configured per environment:
+//!
+//! ```json
+//! {
+//!   "production": true,
+//!   "release_notes": {
+//!     "slack_webhook_url": "https://hooks.slack.com/services/...",
+//!     "github_release": true
+//!   }
+//! }
+//! ```
+//!
+//! `BUILDIT_RELEASE_NOTES_SLACK_WEBHOOK` is used when the environment has no
+//! webhook of its own, and GitHub releases need `GITHUB_TOKEN`.
+
+use buildit_core::ResourceId;
+use buildit_db::repo::pipeline::PipelineRunRecord;
+use buildit_db::{
+    Deployment, DeploymentRepo, Environment, PgDeploymentRepo, PgPipelineRepo, PipelineRepo,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+use super::github::GitHubClient;
+
+/// Notes attached to a deployment.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ReleaseNotes {
+    pub service: String,
+    pub environment: String,
+    pub version: String,
+    pub previous_deployment_id: Option<uuid::Uuid>,
+    pub previous_version: Option<String>,
+    pub repository: Option<String>,
+    pub entries: Vec<ReleaseNoteEntry>,
+    pub markdown: String,
+    pub generated_at: DateTime<Utc>,
+    /// Where the notes were posted.
+    #[serde(default)]
+    pub published: Vec<String>,
+}
+
+/// One change in the release.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ReleaseNoteEntry {
+    pub sha: String,
+    pub title: String,
+    pub author: Option<String>,
+    pub pull_request: Option<u64>,
+}
+
+impl ReleaseNoteEntry {
+    /// Build an entry from a commit, preferring the PR title for merges.
+    ///
+    /// Handles GitHub merge commits ("Merge pull request #12 from ...",
+    /// with the PR title in the body) and squash merges ("Title (#12)").
+    pub fn from_commit(sha: &str, message: &str, author: Option<&str>) -> Self {
+        let mut lines = message.lines().map(str::trim);
+        let subject = lines.next().unwrap_or_default();
+
+        let (title, pull_request) = if let Some(rest) = subject.strip_prefix("Merge pull request #")
+        {
+            let number = rest.split_whitespace().next().and_then(|n| n.parse().ok());
+            let title = lines.find(|l| !l.is_empty()).unwrap_or(subject).to_string();
+            (title, number)
+        } else if let Some((title, number)) = subject
+            .strip_suffix(')')
+            .and_then(|s| s.rsplit_once(" (#"))
+            .and_then(|(title, n)| n.parse().ok().map(|n| (title, n)))
+        {
+            (title.to_string(), Some(number))
+        } else {
+            (subject.to_string(), None)
+        };
+
+        Self {
+            sha: sha.to_string(),
+            title,
+            author: author.filter(|a| !a.is_empty()).map(String::from),
+            pull_request,
+        }
+    }
+}
+
+/// Whether deployments to this environment get release notes.
+pub fn is_production(environment: &Environment) -> bool {
+    environment
+        .config
+        .get("production")
+        .and_then(|p| p.as_bool())
+        .unwrap_or(matches!(environment.name.as_str(), "production" | "prod"))
+}
+
+/// Entries for the commits built by `runs`, oldest first and without
+/// duplicates.
+pub(crate) fn collect_entries(runs: &[PipelineRunRecord]) -> Vec<ReleaseNoteEntry> {
+    let mut seen = HashSet::new();
+    let mut entries = Vec::new();
+
+    for run in runs {
+        let git = &run.git_info;
+        let commits: Vec<(&str, &str, Option<&str>)> = match git
+            .get("commits")
+            .and_then(|c| c.as_array())
+        {
+            Some(commits) => commits
+                .iter()
+                .filter_map(|c| {
+                    Some((
+                        c.get("sha")?.as_str()?,
+                        c.get("message")?.as_str()?,
+                        c.get("author").and_then(|a| a.as_str()),
+                    ))
+                })
+                .collect(),
+            // Older runs only recorded the head commit
+            None => git
+                .get("sha")
+                .and_then(|s| s.as_str())
+                .zip(git.get("message").and_then(|m| m.as_str()))
+                .map(|(sha, message)| (sha, message, git.get("author").and_then(|a| a.as_str())))
+                .into_iter()
+                .collect(),
+        };
+
+        for (sha, message, author) in commits {
+            if seen.insert(sha.to_string()) {
+                entries.push(ReleaseNoteEntry::from_commit(sha, message, author));
+            }
+        }
+    }
+
+    entries
+}
+
+fn render_markdown(notes: &ReleaseNotes) -> String {
+    let mut out = format!(
+        "## {} {} → {}\n\n",
+        notes.service, notes.version, notes.environment
+    );
+    if let Some(previous) = &notes.previous_version {
+        out.push_str(&format!("Changes since {}:\n\n", previous));
+    }
+    if notes.entries.is_empty() {
+        out.push_str("No new changes.\n");
+    }
+    for entry in &notes.entries {
+        out.push_str(&entry_markdown(entry));
+    }
+    out
+}
+
+/// An entry as a Markdown list item.
+pub(crate) fn entry_markdown(entry: &ReleaseNoteEntry) -> String {
+    let short_sha = &entry.sha[..7.min(entry.sha.len())];
+    let mut out = format!("- {}", entry.title);
+    if let Some(pr) = entry.pull_request {
+        out.push_str(&format!(" (#{})", pr));
+    }
+    out.push_str(&format!(" `{}`", short_sha));
+    if let Some(author) = &entry.author {
+        out.push_str(&format!(" by {}", author));
+    }
+    out.push('\n');
+    out
+}
+
+/// Generates, stores and publishes release notes.
+pub struct ReleaseNotesService {
+    deployment_repo: Arc<PgDeploymentRepo>,
+    pipeline_repo: Arc<PgPipelineRepo>,
+    client: reqwest::Client,
+}
+
+impl ReleaseNotesService {
+    pub fn new(deployment_repo: Arc<PgDeploymentRepo>, pipeline_repo: Arc<PgPipelineRepo>) -> Self {
+        Self {
+            deployment_repo,
+            pipeline_repo,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Build notes for a deployment from the pipeline runs since the
+    /// previous successful deployment.
+    pub async fn generate(
+        &self,
+        deployment: &Deployment,
+    ) -> Result<ReleaseNotes, ReleaseNotesError> {
+        let service = self
+            .deployment_repo
+            .get_service(ResourceId::from_uuid(deployment.service_id))
+            .await
+            .map_err(|e| ReleaseNotesError::Database(e.to_string()))?;
+        let environment = self
+            .deployment_repo
+            .get_environment(ResourceId::from_uuid(deployment.environment_id))
+            .await
+            .map_err(|e| ReleaseNotesError::Database(e.to_string()))?;
+        let previous = self
+            .deployment_repo
+            .get_previous_deployment(
+                ResourceId::from_uuid(deployment.service_id),
+                ResourceId::from_uuid(deployment.environment_id),
+                deployment.created_at,
+            )
+            .await
+            .map_err(|e| ReleaseNotesError::Database(e.to_string()))?;
+
+        let runs = match service.pipeline_id {
+            Some(pipeline_id) => self
+                .pipeline_repo
+                .list_runs_between(
+                    ResourceId::from_uuid(pipeline_id),
+                    previous.as_ref().map(|p| p.created_at),
+                    deployment.created_at,
+                )
+                .await
+                .map_err(|e| ReleaseNotesError::Database(e.to_string()))?,
+            None => Vec::new(),
+        };
+        let repository = runs
+            .iter()
+            .rev()
+            .find_map(|r| r.git_info.get("repository").and_then(|v| v.as_str()))
+            .map(String::from);
+
+        let mut notes = ReleaseNotes {
+            service: service.name,
+            environment: environment.name,
+            version: deployment.version.clone(),
+            previous_deployment_id: previous.as_ref().map(|p| p.id),
+            previous_version: previous.map(|p| p.version),
+            repository,
+            entries: collect_entries(&runs),
+            markdown: String::new(),
+            generated_at: Utc::now(),
+            published: Vec::new(),
+        };
+        notes.markdown = render_markdown(&notes);
+        Ok(notes)
+    }
+
+    /// Generate notes, post them where the environment asks, and store them
+    /// on the deployment.
+    pub async fn generate_and_publish(
+        &self,
+        deployment: &Deployment,
+        publish: bool,
+    ) -> Result<ReleaseNotes, ReleaseNotesError> {
+        let mut notes = self.generate(deployment).await?;
+
+        if publish {
+            let environment = self
+                .deployment_repo
+                .get_environment(ResourceId::from_uuid(deployment.environment_id))
+                .await
+                .map_err(|e| ReleaseNotesError::Database(e.to_string()))?;
+            let settings = environment.config.get("release_notes");
+
+            let slack_webhook = settings
+                .and_then(|s| s.get("slack_webhook_url"))
+                .and_then(|u| u.as_str())
+                .map(String::from)
+                .or_else(|| std::env::var("BUILDIT_RELEASE_NOTES_SLACK_WEBHOOK").ok());
+            if let Some(url) = slack_webhook {
+                match self.post_to_slack(&url, &notes).await {
+                    Ok(()) => notes.published.push("slack".to_string()),
+                    Err(e) => warn!(error = %e, "Failed to post release notes to Slack"),
+                }
+            }
+
+            let github_release = settings
+                .and_then(|s| s.get("github_release"))
+                .and_then(|g| g.as_bool())
+                .unwrap_or(false);
+            if github_release {
+                match self.create_github_release(&notes).await {
+                    Ok(url) => notes.published.push(url),
+                    Err(e) => warn!(error = %e, "Failed to create GitHub release"),
+                }
+            }
+        }
+
+        let value =
+            serde_json::to_value(&notes).map_err(|e| ReleaseNotesError::Database(e.to_string()))?;
+        self.deployment_repo
+            .set_release_notes(ResourceId::from_uuid(deployment.id), value)
+            .await
+            .map_err(|e| ReleaseNotesError::Database(e.to_string()))?;
+
+        info!(
+            deployment = %deployment.id,
+            entries = notes.entries.len(),
+            "Generated release notes"
+        );
+        Ok(notes)
+    }
+
+    async fn post_to_slack(
+        &self,
+        url: &str,
+        notes: &ReleaseNotes,
+    ) -> Result<(), ReleaseNotesError> {
+        let response = self
+            .client
+            .post(url)
+            .json(&serde_json::json!({ "text": notes.markdown }))
+            .send()
+            .await
+            .map_err(|e| ReleaseNotesError::Delivery(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(ReleaseNotesError::Delivery(format!(
+                "Slack returned {}",
+                response.status()
+            )));
+        }
+        Ok(())
+    }
+
+    async fn create_github_release(
+        &self,
+        notes: &ReleaseNotes,
+    ) -> Result<String, ReleaseNotesError> {
+        let token = std::env::var("GITHUB_TOKEN")
+            .map_err(|_| ReleaseNotesError::Delivery("GITHUB_TOKEN is not set".to_string()))?;
+        let (owner, repo) = notes
+            .repository
+            .as_deref()
+            .and_then(|r| r.split_once('/'))
+            .ok_or_else(|| {
+                ReleaseNotesError::Delivery("No repository recorded for these runs".to_string())
+            })?;
+
+        let name = format!("{} {}", notes.service, notes.version);
+        GitHubClient::new(token)
+            .create_release(owner, repo, &notes.version, &name, &notes.markdown)
+            .await
+            .map_err(|e| ReleaseNotesError::Delivery(e.to_string()))
+    }
+}
+
+/// Release notes errors.
+#[derive(Debug, thiserror::Error)]
+pub enum ReleaseNotesError {
+    #[error("Database error: {0}")]
+    Database(String),
+
+    #[error("Delivery error: {0}")]
+    Delivery(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_from_merge_commit() {
+        let entry = ReleaseNoteEntry::from_commit(
+            "0123456789abcdef",
+            "Merge pull request #42 from acme/retry\n\nRetry failed uploads",
+            Some("ada"),
+        );
+        assert_eq!(entry.title, "Retry failed uploads");
+        assert_eq!(entry.pull_request, Some(42));
+        assert_eq!(
+            entry_markdown(&entry),
+            "- Retry failed uploads (#42) `0123456` by ada\n"
+        );
+    }
+
+    #[test]
+    fn test_entry_from_squash_and_plain_commits() {
+        let squash = ReleaseNoteEntry::from_commit("abc", "Add dark mode (#7)\n\nDetails", None);
+        assert_eq!(squash.title, "Add dark mode");
+        assert_eq!(squash.pull_request, Some(7));
+
+        let plain = ReleaseNoteEntry::from_commit("def", "Fix typo (in docs)", Some(""));
+        assert_eq!(plain.title, "Fix typo (in docs)");
+        assert_eq!(plain.pull_request, None);
+        assert_eq!(plain.author, None);
+    }
+}
@@ -0,0 +1,289 @@
+//! Publishing the releases of release stages.
+//!
+//! A release stage creates a release on the pipeline's repository for its
+//! tag, on GitHub or GitLab with the `GITHUB_TOKEN` or `GITLAB_TOKEN` used
+//! to fetch it. Its body is the stage's notes followed by a changelog of the
+//! commits the pipeline built since its previous release. Run artifacts
+//! matching the stage's `assets` are attached: uploaded to the release on
+//! GitHub, and to the project's generic package registry and linked from
+//! the release on GitLab, which has no draft releases.
+
+use async_trait::async_trait;
+use buildit_config::condition::glob_match;
+use buildit_core::ResourceId;
+use buildit_core::artifact::ArtifactStore;
+use buildit_core::release::{PublishedRelease, ReleasePublisher, ReleaseRequest, ReleaseSpec};
+use buildit_core::repository::{GitProvider, Repository};
+use buildit_db::repo::pipeline::PipelineRunRecord;
+use buildit_db::{
+    ArtifactRecord, ArtifactRepo, PgArtifactRepo, PgPipelineRepo, PgReleaseRepo, PgRepositoryRepo,
+    PipelineRepo, ReleaseRecord, ReleaseRepo, RepositoryRepo,
+};
+use chrono::Utc;
+use std::sync::Arc;
+use tracing::{error, info};
+
+use super::git::GitCredentials;
+use super::github::{GitHubClient, NewRelease};
+use super::gitlab::GitLabClient;
+use super::release_notes::{collect_entries, entry_markdown};
+
+/// Publishes releases to the pipeline's repository.
+pub struct ReleaseService {
+    pipeline_repo: Arc<PgPipelineRepo>,
+    repository_repo: Arc<PgRepositoryRepo>,
+    artifact_repo: Arc<PgArtifactRepo>,
+    release_repo: Arc<PgReleaseRepo>,
+    artifact_store: Arc<dyn ArtifactStore>,
+}
+
+impl ReleaseService {
+    pub fn new(
+        pipeline_repo: Arc<PgPipelineRepo>,
+        repository_repo: Arc<PgRepositoryRepo>,
+        artifact_repo: Arc<PgArtifactRepo>,
+        release_repo: Arc<PgReleaseRepo>,
+        artifact_store: Arc<dyn ArtifactStore>,
+    ) -> Self {
+        Self {
+            pipeline_repo,
+            repository_repo,
+            artifact_repo,
+            release_repo,
+            artifact_store,
+        }
+    }
+
+    /// The runs whose commits go in the release's changelog: those since
+    /// the pipeline's previous release, up to and including this one.
+    async fn changelog_runs(
+        &self,
+        pipeline_id: ResourceId,
+    ) -> Result<Vec<PipelineRunRecord>, String> {
+        let previous = self
+            .release_repo
+            .latest_release(pipeline_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        self.pipeline_repo
+            .list_runs_between(pipeline_id, previous.map(|p| p.created_at), Utc::now())
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    /// The run's artifacts matching the spec's `assets`, with their contents.
+    async fn assets(
+        &self,
+        run_id: ResourceId,
+        spec: &ReleaseSpec,
+    ) -> Result<Vec<(ArtifactRecord, Vec<u8>)>, String> {
+        if spec.assets.is_empty() {
+            return Ok(vec![]);
+        }
+        let artifacts = self
+            .artifact_repo
+            .list_artifacts(run_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        let mut assets: Vec<(ArtifactRecord, Vec<u8>)> = Vec::new();
+        for pattern in &spec.assets {
+            let matched: Vec<ArtifactRecord> = artifacts
+                .iter()
+                .filter(|a| glob_match(pattern, &a.name, false))
+                .filter(|a| !assets.iter().any(|(b, _)| b.id == a.id))
+                .cloned()
+                .collect();
+            if matched.is_empty() {
+                return Err(format!("no artifact of the run matches '{}'", pattern));
+            }
+            for artifact in matched {
+                let content = self
+                    .artifact_store
+                    .get(&artifact.reference())
+                    .await
+                    .map_err(|e| format!("failed to read artifact {}: {}", artifact.name, e))?;
+                assets.push((artifact, content.to_vec()));
+            }
+        }
+        Ok(assets)
+    }
+}
+
+/// Name a file is attached to a release under; providers refuse paths.
+fn asset_name(artifact: &ArtifactRecord) -> &str {
+    artifact.name.rsplit('/').next().unwrap_or(&artifact.name)
+}
+
+/// Body of the release: its notes, then the changelog.
+fn release_body(spec: &ReleaseSpec, runs: &[PipelineRunRecord]) -> (String, usize) {
+    let mut body = spec.notes.clone().unwrap_or_default();
+    if !spec.changelog {
+        return (body, 0);
+    }
+    let entries = collect_entries(runs);
+    if !body.is_empty() {
+        body.push_str("\n\n");
+    }
+    body.push_str("## Changes\n\n");
+    if entries.is_empty() {
+        body.push_str("No new changes.\n");
+    }
+    for entry in &entries {
+        body.push_str(&entry_markdown(entry));
+    }
+    (body, entries.len())
+}
+
+/// Commit the run built, to tag if the tag doesn't exist yet.
+fn run_sha(run: &PipelineRunRecord) -> Option<&str> {
+    run.git_info
+        .get("sha")
+        .and_then(|s| s.as_str())
+        .filter(|s| !s.is_empty())
+}
+
+async fn publish_github(
+    repo: &Repository,
+    token: String,
+    spec: &ReleaseSpec,
+    body: &str,
+    sha: Option<&str>,
+    assets: &[(ArtifactRecord, Vec<u8>)],
+) -> Result<String, String> {
+    let client = GitHubClient::new(token);
+    let release = NewRelease {
+        tag: &spec.tag,
+        name: spec.name(),
+        body,
+        target_commitish: sha,
+        draft: spec.draft,
+        prerelease: spec.prerelease,
+    };
+    let published = client
+        .publish_release(&repo.owner, &repo.name, &release)
+        .await
+        .map_err(|e| e.to_string())?;
+    for (artifact, content) in assets {
+        client
+            .upload_release_asset(
+                &published,
+                asset_name(artifact),
+                &artifact.content_type,
+                content.clone(),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(published.html_url)
+}
+
+async fn publish_gitlab(
+    repo: &Repository,
+    token: String,
+    spec: &ReleaseSpec,
+    body: &str,
+    sha: Option<&str>,
+    assets: &[(ArtifactRecord, Vec<u8>)],
+) -> Result<String, String> {
+    let client = GitLabClient::for_clone_url(&repo.clone_url, token).map_err(|e| e.to_string())?;
+    let mut links = Vec::new();
+    for (artifact, content) in assets {
+        let name = asset_name(artifact);
+        let url = client
+            .upload_package_file(
+                &repo.full_name,
+                &repo.name,
+                &spec.tag,
+                name,
+                content.clone(),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        links.push((name.to_string(), url));
+    }
+    client
+        .create_release(&repo.full_name, &spec.tag, spec.name(), body, sha, &links)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[async_trait]
+impl ReleasePublisher for ReleaseService {
+    async fn publish(&self, request: &ReleaseRequest) -> Result<PublishedRelease, String> {
+        let spec = &request.spec;
+        let pipeline = self
+            .pipeline_repo
+            .get_by_id(request.pipeline_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        let repository_id = pipeline
+            .repository_id
+            .ok_or("the pipeline has no connected repository to release")?;
+        let repo = self
+            .repository_repo
+            .get_by_id(ResourceId::from_uuid(repository_id))
+            .await
+            .map_err(|e| e.to_string())?;
+        let token = GitCredentials::from_env()
+            .token(repo.provider)
+            .map(str::to_string)
+            .ok_or_else(|| {
+                format!(
+                    "no {} token is configured to publish releases with",
+                    repo.provider
+                )
+            })?;
+        let run = self
+            .pipeline_repo
+            .get_run(request.run_id)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let runs = self.changelog_runs(request.pipeline_id).await?;
+        let (body, commits) = release_body(spec, &runs);
+        let assets = self.assets(request.run_id, spec).await?;
+
+        let url = match repo.provider {
+            GitProvider::Github => {
+                publish_github(&repo, token, spec, &body, run_sha(&run), &assets).await?
+            }
+            GitProvider::Gitlab => {
+                publish_gitlab(&repo, token, spec, &body, run_sha(&run), &assets).await?
+            }
+            GitProvider::Bitbucket => {
+                return Err("releases can't be published to Bitbucket".to_string());
+            }
+        };
+        let asset_names: Vec<String> = assets
+            .iter()
+            .map(|(artifact, _)| asset_name(artifact).to_string())
+            .collect();
+        info!(pipeline_id = %request.pipeline_id, tag = %spec.tag, url = %url, "Published release");
+
+        let record = ReleaseRecord {
+            id: uuid::Uuid::now_v7(),
+            pipeline_id: pipeline.id,
+            pipeline_run_id: run.id,
+            stage_name: request.stage.clone(),
+            provider: repo.provider.to_string(),
+            tag: spec.tag.clone(),
+            name: spec.name().to_string(),
+            url: url.clone(),
+            commits: commits as i32,
+            assets: asset_names.clone(),
+            draft: spec.draft,
+            prerelease: spec.prerelease,
+            created_at: Utc::now(),
+        };
+        // The release exists either way; only the next changelog suffers
+        if let Err(e) = self.release_repo.create_release(&record).await {
+            error!(tag = %spec.tag, error = %e, "Failed to record release");
+        }
+
+        Ok(PublishedRelease {
+            url,
+            commits,
+            assets: asset_names,
+        })
+    }
+}
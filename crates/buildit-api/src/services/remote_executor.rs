@@ -0,0 +1,325 @@
+//! Executor that hands jobs to self-hosted runners.
+//!
+//! Jobs are queued in `runner_jobs` and leased by `buildit-runner` agents
+//! through the `/api/v1/runners` endpoints. This executor only talks to the
+//! database: it polls for the status and log lines the runner reports.
+//! Jobs only go to runners of the organization whose tenant queued them.
+
+use async_trait::async_trait;
+use buildit_core::executor::*;
+use buildit_core::{Error, ResourceId, Result};
+use buildit_db::{DbError, PgRunnerRepo, RunnerJobRecord, RunnerRepo};
+use futures::stream::{self, BoxStream, StreamExt};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A runner counts as online if it polled within this many seconds.
+pub const RUNNER_ONLINE_SECS: i64 = 90;
+
+/// How often job status and logs are polled.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Log lines read per poll.
+const LOG_PAGE_SIZE: i64 = 500;
+
+/// Runs jobs on self-hosted runners that have all of its labels.
+pub struct RemoteExecutor {
+    repo: Arc<PgRunnerRepo>,
+    labels: Vec<String>,
+}
+
+impl RemoteExecutor {
+    pub fn new(repo: Arc<PgRunnerRepo>, labels: Vec<String>) -> Self {
+        Self { repo, labels }
+    }
+
+    fn job_id(handle: &JobHandle) -> Result<ResourceId> {
+        handle
+            .executor_id
+            .parse()
+            .map(ResourceId::from_uuid)
+            .map_err(|_| {
+                Error::InvalidInput(format!("Invalid runner job ID: {}", handle.executor_id))
+            })
+    }
+
+    async fn job(&self, handle: &JobHandle) -> Result<RunnerJobRecord> {
+        // Jobs of runners that went away are failed before they are read
+        self.repo.expire_leases().await.map_err(db_error)?;
+        self.repo
+            .get_job(Self::job_id(handle)?)
+            .await
+            .map_err(db_error)
+    }
+}
+
+fn db_error(err: DbError) -> Error {
+    match err {
+        DbError::NotFound(msg) => Error::NotFound(msg),
+        other => Error::Internal(other.to_string()),
+    }
+}
+
+fn job_status(job: &RunnerJobRecord) -> JobStatus {
+    let finished_at = job.finished_at.unwrap_or_else(chrono::Utc::now);
+    match job.status.as_str() {
+        "running" => JobStatus::Running {
+            started_at: job.started_at.unwrap_or(job.created_at),
+        },
+        "succeeded" => JobStatus::Succeeded {
+            started_at: job.started_at.unwrap_or(job.created_at),
+            finished_at,
+        },
+        "failed" => JobStatus::Failed {
+            started_at: job.started_at,
+            finished_at,
+            exit_code: job.exit_code,
+            message: job.error.clone().unwrap_or_else(|| match job.exit_code {
+                Some(code) => format!("Exit code {}", code),
+                None => "Job failed".to_string(),
+            }),
+        },
+        "cancelled" => JobStatus::Cancelled {
+            started_at: job.started_at,
+            cancelled_at: finished_at,
+        },
+        _ => JobStatus::Pending,
+    }
+}
+
+#[async_trait]
+impl Executor for RemoteExecutor {
+    fn name(&self) -> &'static str {
+        "remote"
+    }
+
+    /// Whether the job's organization has a runner online with the
+    /// executor's labels, for the job's platform.
+    async fn can_execute(&self, spec: &JobSpec) -> bool {
+        let Some(tenant_id) = spec.tenant_id else {
+            return false;
+        };
+        let runners = async {
+            if !self
+                .repo
+                .runner_online(tenant_id, &self.labels, RUNNER_ONLINE_SECS)
+                .await?
+            {
+                return Ok(false);
+            }
+            let platforms = self.repo.runner_platforms(tenant_id, &self.labels).await?;
+            Ok::<_, DbError>(
+                platforms
+                    .iter()
+                    .filter_map(|p| p.parse::<Platform>().ok())
+                    .any(|p| p.supports(&spec.platform)),
+            )
+        };
+        match runners.await {
+            Ok(capable) => capable,
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to look up runners");
+                false
+            }
+        }
+    }
+
+    /// Every platform a runner can run. Runners belong to organizations, so
+    /// which of these a job's organization has runners for is checked when
+    /// the job is spawned.
+    async fn platforms(&self) -> Vec<Platform> {
+        [Os::Linux, Os::Windows, Os::Macos]
+            .into_iter()
+            .flat_map(|os| [Arch::Amd64, Arch::Arm64].map(|arch| Platform::new(os, arch)))
+            .collect()
+    }
+
+    async fn spawn(&self, spec: JobSpec) -> Result<JobHandle> {
+        let tenant_id = spec.tenant_id.ok_or_else(|| {
+            Error::InvalidInput("Jobs for self-hosted runners need a tenant".to_string())
+        })?;
+        let job = self
+            .repo
+            .create_job(tenant_id, &spec, &self.labels)
+            .await
+            .map_err(db_error)?;
+        Ok(JobHandle {
+            id: spec.id,
+            executor_id: job.id.to_string(),
+            executor_name: self.name().to_string(),
+        })
+    }
+
+    async fn logs(&self, handle: &JobHandle) -> Result<BoxStream<'static, LogLine>> {
+        let job_id = Self::job_id(handle)?;
+        let repo = self.repo.clone();
+
+        // Follow the reported lines until the job has ended and all its
+        // lines have been read.
+        let pages = stream::unfold(Some(0i64), move |after| {
+            let repo = repo.clone();
+            async move {
+                let mut after = after?;
+                loop {
+                    let finished = repo
+                        .get_job(job_id)
+                        .await
+                        .map(|job| job.finished_at.is_some())
+                        .unwrap_or(true);
+                    let records = repo
+                        .job_logs_after(job_id, after, LOG_PAGE_SIZE)
+                        .await
+                        .unwrap_or_default();
+                    if let Some(last) = records.last() {
+                        after = last.id;
+                        let lines: Vec<LogLine> = records.iter().map(|r| r.log_line()).collect();
+                        return Some((stream::iter(lines), Some(after)));
+                    }
+                    if finished {
+                        return None;
+                    }
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                }
+            }
+        });
+
+        Ok(pages.flatten().boxed())
+    }
+
+    async fn status(&self, handle: &JobHandle) -> Result<JobStatus> {
+        Ok(job_status(&self.job(handle).await?))
+    }
+
+    async fn wait(&self, handle: &JobHandle) -> Result<JobResult> {
+        loop {
+            let job = self.job(handle).await?;
+            let status = job_status(&job);
+            if status.is_terminal() {
+                return Ok(JobResult {
+                    status,
+                    exit_code: job.exit_code,
+                    artifacts: vec![],
+                });
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    async fn cancel(&self, handle: &JobHandle) -> Result<()> {
+        self.repo
+            .request_cancel(Self::job_id(handle)?)
+            .await
+            .map_err(db_error)?;
+        Ok(())
+    }
+
+    async fn exec_interactive(
+        &self,
+        _handle: &JobHandle,
+        _cmd: Vec<String>,
+    ) -> Result<TerminalSession> {
+        Err(Error::InvalidInput(
+            "Interactive sessions are not supported on remote runners".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::PgPool;
+
+    fn spec(tenant_id: Option<ResourceId>) -> JobSpec {
+        JobSpec {
+            id: ResourceId::new(),
+            image: "alpine:latest".to_string(),
+            command: vec!["env".to_string()],
+            entrypoint: None,
+            user: None,
+            working_dir: None,
+            env: [("DEPLOY_TOKEN".to_string(), "secret".to_string())].into(),
+            resources: ResourceRequirements::default(),
+            timeout: None,
+            volumes: vec![],
+            git_clone: None,
+            platform: Default::default(),
+            wait_for: vec![],
+            security_context: None,
+            privileged: false,
+            extra_hosts: vec![],
+            network_mode: None,
+            workspace: None,
+            tenant_id,
+            pull_policy: None,
+        }
+    }
+
+    /// An organization with one tenant, removed with everything of theirs
+    /// when `pool` runs `DELETE FROM organizations`.
+    async fn organization(pool: &PgPool) -> (ResourceId, ResourceId) {
+        let org_id = uuid::Uuid::now_v7();
+        let tenant_id = uuid::Uuid::now_v7();
+        sqlx::query("INSERT INTO organizations (id, name, slug) VALUES ($1, $2, $2)")
+            .bind(org_id)
+            .bind(format!("org-{}", org_id))
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO tenants (id, name, slug, organization_id) VALUES ($1, $2, $2, $3)",
+        )
+        .bind(tenant_id)
+        .bind(format!("tenant-{}", tenant_id))
+        .bind(org_id)
+        .execute(pool)
+        .await
+        .unwrap();
+        (
+            ResourceId::from_uuid(org_id),
+            ResourceId::from_uuid(tenant_id),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_runners_only_lease_jobs_of_their_organization() {
+        let Ok(url) = std::env::var("DATABASE_URL") else {
+            return;
+        };
+        let pool = PgPool::connect(&url).await.unwrap();
+        buildit_db::run_migrations(&pool).await.unwrap();
+        let (org_a, tenant_a) = organization(&pool).await;
+        let (org_b, tenant_b) = organization(&pool).await;
+
+        let repo = Arc::new(PgRunnerRepo::new(pool.clone()));
+        let labels = vec!["macos".to_string()];
+        let platforms = vec!["linux/amd64".to_string()];
+        let runner_a = repo
+            .register_runner(org_a, "a", &labels, &platforms, None, &org_a.to_string())
+            .await
+            .unwrap();
+        let executor = RemoteExecutor::new(repo.clone(), labels.clone());
+
+        // Organization B has no runner of its own to take the job
+        assert!(executor.can_execute(&spec(Some(tenant_a))).await);
+        assert!(!executor.can_execute(&spec(Some(tenant_b))).await);
+        assert!(!executor.can_execute(&spec(None)).await);
+        assert!(executor.spawn(spec(None)).await.is_err());
+
+        let handle = executor.spawn(spec(Some(tenant_b))).await.unwrap();
+        assert!(repo.lease_job(&runner_a, 60).await.unwrap().is_none());
+
+        let runner_b = repo
+            .register_runner(org_b, "b", &labels, &platforms, None, &org_b.to_string())
+            .await
+            .unwrap();
+        let leased = repo.lease_job(&runner_b, 60).await.unwrap().unwrap();
+        assert_eq!(leased.id.to_string(), handle.executor_id);
+        assert_eq!(leased.organization_id, Some(*org_b.as_uuid()));
+
+        sqlx::query("DELETE FROM organizations WHERE id = ANY($1)")
+            .bind(vec![*org_a.as_uuid(), *org_b.as_uuid()])
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,499 @@
+//! Background sync of connected repositories.
+//!
+//! Periodically rescans the repositories not synced within the interval for
+//! pipeline configs, Dockerfiles, Terraform and Kubernetes manifests, and
+//! stores what was found as their detected config. Each pipeline config
+//! found (`buildit.kdl`, or every `.buildit/*.kdl` of repositories with
+//! several pipelines) is synced to a pipeline of the repository, with its
+//! own triggers and path filters; pipelines whose file is gone are
+//! archived. With a `GITHUB_TOKEN`,
+//! GitHub repositories' default branch and visibility are kept up to date,
+//! and the other repositories of owners an organization already connected a
+//! repository of are discovered and connected too.
+
+use buildit_config::pipeline::{FsTemplateResolver, parse_pipeline_with_resolver};
+use buildit_core::ResourceId;
+use buildit_core::pipeline::{Pipeline, StageAction};
+use buildit_core::repository::{DetectedConfig, GitProvider, Repository};
+use buildit_db::repo::pipeline::PipelineRecord;
+use buildit_db::{
+    DbError, PgOrganizationRepo, PgPipelineRepo, PgRepositoryRepo, PgTenantRepo, PipelineRepo,
+    RepositoryRepo, TenantRepo,
+};
+use chrono::Utc;
+use serde_json::{Value, json};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use super::git::{GitError, GitService};
+use super::github::{GitHubClient, GitHubError, GitHubRepo};
+use crate::error::ApiError;
+use crate::routes::pipelines::{StageDefinitions, policy_error, policy_violations};
+
+/// Repositories listed per page when discovering.
+const DISCOVERY_PAGE_SIZE: u32 = 100;
+/// Most pages listed per discovery.
+const DISCOVERY_MAX_PAGES: u32 = 10;
+
+/// Repository sync settings.
+#[derive(Debug, Clone)]
+pub struct RepoSyncConfig {
+    /// How often each repository is rescanned.
+    pub interval: Duration,
+    /// Most repositories scanned per pass.
+    pub batch_size: i64,
+    /// Whether to connect repositories of already connected owners.
+    pub discover: bool,
+    /// Token for listing GitHub repositories.
+    pub github_token: Option<String>,
+}
+
+impl Default for RepoSyncConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(3600),
+            batch_size: 20,
+            discover: true,
+            github_token: None,
+        }
+    }
+}
+
+impl RepoSyncConfig {
+    /// Load settings from `BUILDIT_REPO_SYNC_*` environment variables and
+    /// `GITHUB_TOKEN`.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            interval: std::env::var("BUILDIT_REPO_SYNC_INTERVAL_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|s: &u64| *s > 0)
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.interval),
+            batch_size: std::env::var("BUILDIT_REPO_SYNC_BATCH_SIZE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|n: &i64| *n > 0)
+                .unwrap_or(defaults.batch_size),
+            discover: std::env::var("BUILDIT_REPO_SYNC_DISCOVER")
+                .map(|v| !matches!(v.as_str(), "0" | "false"))
+                .unwrap_or(defaults.discover),
+            github_token: std::env::var("GITHUB_TOKEN").ok().filter(|t| !t.is_empty()),
+        }
+    }
+}
+
+/// Background task that keeps repositories' detected config fresh.
+pub struct RepoSync {
+    config: RepoSyncConfig,
+    repository_repo: Arc<PgRepositoryRepo>,
+    pipeline_repo: Arc<PgPipelineRepo>,
+    tenant_repo: Arc<PgTenantRepo>,
+    organization_repo: Arc<PgOrganizationRepo>,
+    git: GitService,
+}
+
+impl RepoSync {
+    pub fn new(
+        config: RepoSyncConfig,
+        repository_repo: Arc<PgRepositoryRepo>,
+        pipeline_repo: Arc<PgPipelineRepo>,
+        tenant_repo: Arc<PgTenantRepo>,
+        organization_repo: Arc<PgOrganizationRepo>,
+    ) -> Self {
+        Self {
+            config,
+            repository_repo,
+            pipeline_repo,
+            tenant_repo,
+            organization_repo,
+            git: GitService::new(),
+        }
+    }
+
+    /// Run the sync loop forever.
+    pub async fn run(self) {
+        info!(
+            interval_secs = self.config.interval.as_secs(),
+            discover = self.config.discover && self.config.github_token.is_some(),
+            "Repository sync started"
+        );
+
+        let mut ticker = tokio::time::interval(self.config.interval);
+        loop {
+            ticker.tick().await;
+            if let Some(token) = self
+                .config
+                .github_token
+                .as_deref()
+                .filter(|_| self.config.discover)
+            {
+                if let Err(e) = self.discover(token).await {
+                    error!(error = %e, "Repository discovery failed");
+                }
+            }
+            if let Err(e) = self.sync_due().await {
+                error!(error = %e, "Repository sync failed");
+            }
+        }
+    }
+
+    /// Scan the repositories due for a sync, returning how many were scanned.
+    pub async fn sync_due(&self) -> Result<usize, DbError> {
+        let synced_before = Utc::now()
+            - chrono::Duration::from_std(self.config.interval).unwrap_or(chrono::Duration::zero());
+        let due = self
+            .repository_repo
+            .list_due_for_sync(synced_before, self.config.batch_size)
+            .await?;
+        for repo in &due {
+            match self.sync(repo).await {
+                Ok(_) => {}
+                Err(RepoSyncError::Database(e)) => return Err(e),
+                Err(e) => {
+                    warn!(repository = %repo.full_name, error = %e, "Failed to scan repository")
+                }
+            }
+        }
+        Ok(due.len())
+    }
+
+    /// Clone and scan one repository, storing what was found or why the
+    /// scan failed.
+    pub async fn sync(&self, repo: &Repository) -> Result<DetectedConfig, RepoSyncError> {
+        let id = ResourceId::from_uuid(repo.id);
+        match self.git.clone_and_scan(repo).await {
+            Ok(detected) => {
+                self.repository_repo
+                    .update_detected_config(id, &detected)
+                    .await?;
+                self.repository_repo.update_last_synced(id).await?;
+                self.sync_pipelines(repo, &detected).await?;
+                Ok(detected)
+            }
+            Err(e) => {
+                self.repository_repo
+                    .update_sync_error(id, &e.to_string())
+                    .await?;
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Create or update a pipeline for each pipeline config of the
+    /// repository, and archive those whose config is gone. A config that
+    /// can't be read or stored is skipped with a warning, leaving its
+    /// pipeline as it was.
+    async fn sync_pipelines(
+        &self,
+        repo: &Repository,
+        detected: &DetectedConfig,
+    ) -> Result<(), DbError> {
+        let repository_id = ResourceId::from_uuid(repo.id);
+        let existing = self.pipeline_repo.list_by_repository(repository_id).await?;
+        if detected.pipeline_configs.is_empty() && existing.iter().all(|p| p.config_path.is_none())
+        {
+            return Ok(());
+        }
+        let Some(tenant_id) = self.pipeline_tenant(repo, &existing).await? else {
+            warn!(
+                repository = %repo.full_name,
+                "Not syncing pipelines: the organization has no single tenant to create them in"
+            );
+            return Ok(());
+        };
+
+        let checkout = self.git.get_repo_path(&repo.clone_url);
+        for path in &detected.pipeline_configs {
+            let pipeline = match read_pipeline_config(&checkout, path).await {
+                Ok(pipeline) => pipeline,
+                Err(e) => {
+                    warn!(repository = %repo.full_name, path = %path, error = %e, "Skipping pipeline config");
+                    continue;
+                }
+            };
+            match self.sync_pipeline(repo, tenant_id, path, &pipeline).await {
+                Ok(()) => {}
+                Err(RepoSyncError::Database(e)) if !matches!(e, DbError::Duplicate(_)) => {
+                    return Err(e);
+                }
+                Err(e) => {
+                    warn!(repository = %repo.full_name, path = %path, error = %e, "Skipping pipeline config");
+                }
+            }
+        }
+
+        for pipeline in existing {
+            let Some(path) = &pipeline.config_path else {
+                continue;
+            };
+            if !detected.pipeline_configs.contains(path) {
+                self.pipeline_repo
+                    .set_archived(ResourceId::from_uuid(pipeline.id), true)
+                    .await?;
+                info!(pipeline = %pipeline.name, path = %path, "Archived pipeline whose config was removed");
+            }
+        }
+        Ok(())
+    }
+
+    /// Store a parsed pipeline config as the pipeline of its path, replacing
+    /// its stage definitions.
+    async fn sync_pipeline(
+        &self,
+        repo: &Repository,
+        tenant_id: ResourceId,
+        path: &str,
+        pipeline: &Pipeline,
+    ) -> Result<(), RepoSyncError> {
+        let mut config = stored_config(pipeline).map_err(RepoSyncError::Config)?;
+        let stages = StageDefinitions::from_config(&config).map_err(|e| match e {
+            ApiError::BadRequest(msg) => RepoSyncError::Config(msg),
+            e => RepoSyncError::Config(format!("{:?}", e)),
+        })?;
+        // Configs breaking the tenant's or organization's policies are not
+        // stored
+        let violations = policy_violations(
+            &self.organization_repo,
+            &self.tenant_repo,
+            tenant_id,
+            &pipeline.stages,
+        )
+        .await?;
+        if !violations.is_empty() {
+            return Err(RepoSyncError::Config(policy_error(&violations)));
+        }
+        // Start from the tenant's policy defaults, as pipelines created through the API do
+        self.tenant_repo
+            .get_by_id(tenant_id)
+            .await?
+            .policy()
+            .apply(&mut config);
+
+        let (record, created) = self
+            .pipeline_repo
+            .upsert_repository_pipeline(
+                tenant_id,
+                ResourceId::from_uuid(repo.id),
+                path,
+                &pipeline.name,
+                &repo.clone_url,
+                config,
+            )
+            .await?;
+        let pipeline_id = ResourceId::from_uuid(record.id);
+        // The file being back brings its pipeline back
+        if record.archived_at.is_some() {
+            self.pipeline_repo.set_archived(pipeline_id, false).await?;
+        }
+        if !pipeline.ownership.is_empty() {
+            self.pipeline_repo
+                .update_owners(pipeline_id, &pipeline.ownership)
+                .await?;
+        }
+        self.pipeline_repo.delete_stages(pipeline_id).await?;
+        stages.save(&self.pipeline_repo, pipeline_id).await;
+
+        if created {
+            info!(repository = %repo.full_name, pipeline = %record.name, path = %path, "Created pipeline from repository config");
+        }
+        Ok(())
+    }
+
+    /// Tenant new pipelines of the repository are created in: that of its
+    /// existing pipelines, or its organization's only tenant.
+    async fn pipeline_tenant(
+        &self,
+        repo: &Repository,
+        existing: &[PipelineRecord],
+    ) -> Result<Option<ResourceId>, DbError> {
+        if let Some(pipeline) = existing.first() {
+            return Ok(Some(ResourceId::from_uuid(pipeline.tenant_id)));
+        }
+        let tenants = self
+            .tenant_repo
+            .list_by_organization(ResourceId::from_uuid(repo.organization_id))
+            .await?;
+        Ok(match tenants.as_slice() {
+            [tenant] => Some(ResourceId::from_uuid(tenant.id)),
+            _ => None,
+        })
+    }
+
+    /// Connect the GitHub repositories visible to `token` whose owner an
+    /// organization already connected a repository of, and refresh the
+    /// default branch and visibility of connected ones. Returns how many
+    /// repositories were connected.
+    pub async fn discover(&self, token: &str) -> Result<usize, RepoSyncError> {
+        let connected = self
+            .repository_repo
+            .list_by_provider(GitProvider::Github)
+            .await?;
+        if connected.is_empty() {
+            return Ok(0);
+        }
+        let visible = list_visible_repos(&GitHubClient::new(token.to_string())).await?;
+        let visible_by_name: HashMap<String, &GitHubRepo> = visible
+            .iter()
+            .map(|r| (r.full_name.to_lowercase(), r))
+            .collect();
+
+        let mut owners: HashMap<Uuid, HashSet<String>> = HashMap::new();
+        let mut known: HashSet<(Uuid, String)> = HashSet::new();
+        for repo in &connected {
+            let full_name = repo.full_name.to_lowercase();
+            owners
+                .entry(repo.organization_id)
+                .or_default()
+                .insert(repo.owner.to_lowercase());
+            if let Some(remote) = visible_by_name.get(&full_name) {
+                if remote.default_branch != repo.default_branch || remote.private != repo.is_private
+                {
+                    self.repository_repo
+                        .update_provider_info(
+                            ResourceId::from_uuid(repo.id),
+                            &remote.default_branch,
+                            remote.private,
+                        )
+                        .await?;
+                }
+            }
+            known.insert((repo.organization_id, full_name));
+        }
+
+        let mut discovered = 0;
+        for (organization_id, owners) in &owners {
+            for remote in &visible {
+                let full_name = remote.full_name.to_lowercase();
+                if !owners.contains(&remote.owner.login.to_lowercase())
+                    || known.contains(&(*organization_id, full_name))
+                {
+                    continue;
+                }
+                self.repository_repo
+                    .create(
+                        ResourceId::from_uuid(*organization_id),
+                        GitProvider::Github,
+                        &remote.full_name,
+                        &remote.owner.login,
+                        &remote.name,
+                        &remote.clone_url,
+                        &remote.default_branch,
+                        remote.private,
+                    )
+                    .await?;
+                info!(
+                    organization_id = %organization_id,
+                    repository = %remote.full_name,
+                    "Discovered repository"
+                );
+                discovered += 1;
+            }
+        }
+        Ok(discovered)
+    }
+}
+
+/// Parse a pipeline config of the checkout, resolving its includes
+/// relative to the file.
+async fn read_pipeline_config(checkout: &Path, path: &str) -> Result<Pipeline, String> {
+    let file = checkout.join(path);
+    let content = tokio::fs::read_to_string(&file)
+        .await
+        .map_err(|e| e.to_string())?;
+    let dir = file.parent().unwrap_or(checkout);
+    parse_pipeline_with_resolver(&content, &FsTemplateResolver::new(dir)).map_err(|e| e.to_string())
+}
+
+/// A parsed pipeline as the config stored for pipelines, with its stages
+/// flattened as `pipeline_stages` keeps them. Only stages that run
+/// commands, scan, release or trigger a pipeline can be stored that way.
+fn stored_config(pipeline: &Pipeline) -> Result<Value, String> {
+    let stages = pipeline
+        .stages
+        .iter()
+        .map(|stage| {
+            let mut flat = json!({
+                "name": stage.name,
+                "depends_on": stage.needs,
+                "env": stage.env,
+                "runs_on": stage.runs_on,
+                "resources": stage.resources,
+                "platform": stage.platform.to_string(),
+                "wait_for": stage.wait_for,
+                "needs_artifacts": stage.needs_artifacts,
+                "security_context": stage.security_context,
+                "container": stage.container,
+                "pull_policy": stage.pull_policy,
+            });
+            match &stage.action {
+                StageAction::Run {
+                    image,
+                    commands,
+                    test_reports,
+                    ..
+                } => {
+                    flat["image"] = json!(image);
+                    flat["commands"] = json!(commands);
+                    flat["test_reports"] = json!(test_reports);
+                }
+                StageAction::Scan(scan) => flat["scan"] = json!(scan),
+                StageAction::Release(release) => flat["release"] = json!(release),
+                StageAction::TriggerPipeline(trigger) => {
+                    flat["trigger_pipeline"] = json!(trigger)
+                }
+                _ => {
+                    return Err(format!(
+                        "stage '{}' can't be synced: only stages that run commands, scan, release or trigger a pipeline can",
+                        stage.name
+                    ));
+                }
+            }
+            Ok(flat)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(json!({
+        "triggers": pipeline.triggers,
+        "env": pipeline.env,
+        "checkout": pipeline.checkout,
+        "params": pipeline.params,
+        "ownership": pipeline.ownership,
+        "stages": stages,
+    }))
+}
+
+/// Every repository visible to the client, up to the page limit.
+async fn list_visible_repos(client: &GitHubClient) -> Result<Vec<GitHubRepo>, GitHubError> {
+    let mut repos = Vec::new();
+    for page in 1..=DISCOVERY_MAX_PAGES {
+        let batch = client.list_repos(page, DISCOVERY_PAGE_SIZE).await?;
+        let last = batch.len() < DISCOVERY_PAGE_SIZE as usize;
+        repos.extend(batch);
+        if last {
+            break;
+        }
+    }
+    Ok(repos)
+}
+
+/// Repository sync errors.
+#[derive(Debug, thiserror::Error)]
+pub enum RepoSyncError {
+    #[error(transparent)]
+    Git(#[from] GitError),
+
+    #[error("GitHub error: {0}")]
+    GitHub(#[from] GitHubError),
+
+    #[error("Database error: {0}")]
+    Database(#[from] DbError),
+
+    #[error("Invalid pipeline config: {0}")]
+    Config(String),
+}
@@ -0,0 +1,598 @@
+//! Published HTML reports.
+//!
+//! A stage publishes a report by uploading its directory as a tar archive
+//! (optionally gzipped). The archive is unpacked here and each file stored
+//! in the artifact store; the site is then served under `/reports` behind a
+//! signed, expiring link bound to the browser session that opened it.
+
+use axum::body::Bytes;
+use buildit_core::{Error, Result};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::io::Read;
+use std::path::{Component, Path};
+use uuid::Uuid;
+
+/// Largest report accepted once unpacked.
+const MAX_UNPACKED_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Most files a single report may contain.
+const MAX_FILES: usize = 10_000;
+
+/// How long a report link stays valid unless `BUILDIT_REPORT_TTL_SECS` is set.
+const DEFAULT_LINK_TTL_SECS: i64 = 8 * 60 * 60;
+
+const BLOCK: usize = 512;
+
+/// A file unpacked from a report archive.
+#[derive(Debug)]
+pub struct ReportEntry {
+    /// Path inside the report, `/`-separated.
+    pub path: String,
+    pub data: Bytes,
+}
+
+/// Unpack a tar or tar.gz archive into the report's files.
+///
+/// Only regular files are kept; directories, links and device entries are
+/// skipped. When every file sits under one top-level directory and `entry`
+/// is not at the root, that directory is stripped so `tar czf site.tgz site/`
+/// and `tar czf site.tgz -C site .` publish the same report.
+pub fn unpack(archive: &[u8], entry: &str) -> Result<Vec<ReportEntry>> {
+    let tar = if archive.starts_with(&[0x1f, 0x8b]) {
+        let mut data = Vec::new();
+        flate2::read::GzDecoder::new(archive)
+            .take(MAX_UNPACKED_SIZE + 1)
+            .read_to_end(&mut data)
+            .map_err(|e| invalid(format!("cannot decompress report archive: {}", e)))?;
+        if data.len() as u64 > MAX_UNPACKED_SIZE {
+            return Err(too_large());
+        }
+        data
+    } else {
+        archive.to_vec()
+    };
+
+    let mut entries = read_tar(&tar)?;
+    if entries.is_empty() {
+        return Err(invalid("report archive contains no files".to_string()));
+    }
+    strip_shared_root(&mut entries, entry);
+    if !entries.iter().any(|e| e.path == entry) {
+        return Err(invalid(format!(
+            "report archive has no {:?} to open",
+            entry
+        )));
+    }
+    Ok(entries)
+}
+
+fn read_tar(tar: &[u8]) -> Result<Vec<ReportEntry>> {
+    let mut entries = Vec::new();
+    let mut total: u64 = 0;
+    // Name for the next entry from a GNU long name or pax header.
+    let mut long_name: Option<String> = None;
+    let mut offset = 0;
+
+    while offset + BLOCK <= tar.len() {
+        let header = &tar[offset..offset + BLOCK];
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        let size = header_size(&header[124..136])?;
+        let start = offset + BLOCK;
+        let end = start
+            .checked_add(size)
+            .filter(|&end| end <= tar.len())
+            .ok_or_else(|| invalid("report archive is truncated".to_string()))?;
+        let data = &tar[start..end];
+        offset = start + size.div_ceil(BLOCK) * BLOCK;
+
+        match header[156] {
+            b'L' => {
+                long_name = Some(field(data));
+                continue;
+            }
+            b'x' => {
+                if let Some(path) = pax_path(data) {
+                    long_name = Some(path);
+                }
+                continue;
+            }
+            b'0' | 0 => {}
+            // Directories, links, global pax headers and the rest.
+            _ => {
+                long_name = None;
+                continue;
+            }
+        }
+
+        let name = match long_name.take() {
+            Some(name) => name,
+            None if &header[257..263] == b"ustar\0" && header[345] != 0 => {
+                format!("{}/{}", field(&header[345..500]), field(&header[..100]))
+            }
+            None => field(&header[..100]),
+        };
+        let Some(path) = report_path(&name)? else {
+            continue;
+        };
+
+        total += size as u64;
+        if total > MAX_UNPACKED_SIZE {
+            return Err(too_large());
+        }
+        if entries.len() == MAX_FILES {
+            return Err(invalid(format!(
+                "report archive has more than {} files",
+                MAX_FILES
+            )));
+        }
+        entries.push(ReportEntry {
+            path,
+            data: Bytes::copy_from_slice(data),
+        });
+    }
+    Ok(entries)
+}
+
+/// Entry size, in octal or GNU base-256.
+fn header_size(raw: &[u8]) -> Result<usize> {
+    if raw[0] & 0x80 != 0 {
+        let size = raw[1..]
+            .iter()
+            .try_fold(0u64, |acc, &b| acc.checked_mul(256).map(|v| v + b as u64));
+        return size
+            .filter(|&s| s <= MAX_UNPACKED_SIZE)
+            .map(|s| s as usize)
+            .ok_or_else(too_large);
+    }
+    let text = field(raw);
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(text, 8)
+        .ok()
+        .filter(|&s| s <= MAX_UNPACKED_SIZE)
+        .map(|s| s as usize)
+        .ok_or_else(|| invalid(format!("bad entry size {:?} in report archive", text)))
+}
+
+/// A NUL-terminated header field.
+fn field(raw: &[u8]) -> String {
+    let end = raw.iter().position(|&b| b == 0).unwrap_or(raw.len());
+    String::from_utf8_lossy(&raw[..end]).to_string()
+}
+
+/// The `path` record of a pax extended header.
+fn pax_path(data: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(data);
+    text.lines().find_map(|record| {
+        let (_, pair) = record.split_once(' ')?;
+        pair.strip_prefix("path=").map(str::to_string)
+    })
+}
+
+/// Normalise an archive path, rejecting any that would leave the report.
+/// Returns `None` for entries that name the archive root itself.
+fn report_path(name: &str) -> Result<Option<String>> {
+    let mut parts = Vec::new();
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().to_string()),
+            Component::CurDir => {}
+            _ => {
+                return Err(invalid(format!(
+                    "report archive path {:?} must stay inside the report",
+                    name
+                )));
+            }
+        }
+    }
+    if parts.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(parts.join("/")))
+    }
+}
+
+fn strip_shared_root(entries: &mut [ReportEntry], entry: &str) {
+    if entries.iter().any(|e| e.path == entry) {
+        return;
+    }
+    let Some((root, _)) = entries[0].path.split_once('/') else {
+        return;
+    };
+    let prefix = format!("{}/", root);
+    if entries.iter().all(|e| e.path.starts_with(&prefix)) {
+        for e in entries.iter_mut() {
+            e.path = e.path[prefix.len()..].to_string();
+        }
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::InvalidInput(message)
+}
+
+fn too_large() -> Error {
+    invalid(format!(
+        "report is larger than {} MiB unpacked",
+        MAX_UNPACKED_SIZE / (1024 * 1024)
+    ))
+}
+
+/// Content type to serve a report file with.
+pub fn content_type(path: &str) -> String {
+    let mime = mime_guess::from_path(path).first_or_octet_stream();
+    match (mime.type_(), mime.subtype()) {
+        (mime_guess::mime::TEXT, _) | (_, mime_guess::mime::JAVASCRIPT) => {
+            format!("{}; charset=utf-8", mime.essence_str())
+        }
+        _ => mime.essence_str().to_string(),
+    }
+}
+
+/// Link that opens a report for a signed-in user of its tenant.
+pub fn report_link(report_id: Uuid) -> String {
+    format!("/reports/{}", report_id)
+}
+
+/// Path of a report file under an already issued token.
+pub fn report_url(report_id: Uuid, token: &str, path: &str) -> String {
+    let path: Vec<_> = path
+        .split('/')
+        .map(|part| urlencoding::encode(part).into_owned())
+        .collect();
+    format!("/reports/{}/{}/{}", report_id, token, path.join("/"))
+}
+
+/// Signs and checks the expiring links reports and artifacts are served
+/// under.
+///
+/// Report links are bound to the browser session they were opened from;
+/// artifact links are handed to jobs and carry no session. Each kind is
+/// signed for its own purpose, so a token of one never opens the other.
+///
+/// The key comes from `BUILDIT_REPORT_SECRET`, so that every replica
+/// accepts the links of the others and they survive a restart.
+pub struct ReportSigner {
+    key: Vec<u8>,
+    ttl_secs: i64,
+}
+
+/// Who a verified report link was issued to.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ReportGrant {
+    /// The browser session; `None` for links opened with authentication
+    /// disabled.
+    pub session_id: Option<Uuid>,
+}
+
+const REPORT_PURPOSE: &str = "report";
+const ARTIFACT_PURPOSE: &str = "artifact";
+/// Session part of a report token opened without a session.
+const NO_SESSION: &str = "-";
+
+impl ReportSigner {
+    pub fn new(key: impl Into<Vec<u8>>, ttl_secs: i64) -> Self {
+        Self {
+            key: key.into(),
+            ttl_secs,
+        }
+    }
+
+    /// Signer with the key of `BUILDIT_REPORT_SECRET`. Only a single-process
+    /// development server may run without it, on a random key whose links
+    /// stop working when it restarts.
+    pub fn from_env(single_process_dev: bool) -> std::result::Result<Self, String> {
+        let key = match std::env::var("BUILDIT_REPORT_SECRET")
+            .ok()
+            .filter(|s| !s.is_empty())
+        {
+            Some(secret) => secret.into_bytes(),
+            None if single_process_dev => {
+                tracing::warn!(
+                    "BUILDIT_REPORT_SECRET is not set; report and artifact links stop working on restart"
+                );
+                [Uuid::new_v4().into_bytes(), Uuid::new_v4().into_bytes()].concat()
+            }
+            None => {
+                return Err(
+                    "BUILDIT_REPORT_SECRET must be set to sign report and artifact links"
+                        .to_string(),
+                );
+            }
+        };
+        let ttl_secs = std::env::var("BUILDIT_REPORT_TTL_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEFAULT_LINK_TTL_SECS);
+        Ok(Self::new(key, ttl_secs))
+    }
+
+    /// A fresh token for the report, `<expires>.<session>.<signature>`,
+    /// good only while `session_id` is.
+    pub fn sign_report(&self, report_id: Uuid, session_id: Option<Uuid>) -> String {
+        let session = session_id.map_or_else(|| NO_SESSION.to_string(), |id| id.to_string());
+        let expires = self.expires();
+        let signature = self.signature(REPORT_PURPOSE, report_id, &session, expires);
+        format!("{}.{}.{}", expires, session, signature)
+    }
+
+    /// Who the token was issued to, if it was signed for this report and
+    /// has not expired.
+    pub fn verify_report(&self, report_id: Uuid, token: &str) -> Option<ReportGrant> {
+        let mut parts = token.splitn(3, '.');
+        let (expires, session, signature) = (parts.next()?, parts.next()?, parts.next()?);
+        if !self.check(REPORT_PURPOSE, report_id, session, expires, signature) {
+            return None;
+        }
+        let session_id = match session {
+            NO_SESSION => None,
+            id => Some(id.parse().ok()?),
+        };
+        Some(ReportGrant { session_id })
+    }
+
+    /// A fresh token for downloading the artifact, `<expires>.<signature>`.
+    pub fn sign_artifact(&self, artifact_id: Uuid) -> String {
+        let expires = self.expires();
+        let signature = self.signature(ARTIFACT_PURPOSE, artifact_id, "", expires);
+        format!("{}.{}", expires, signature)
+    }
+
+    /// Whether the token was signed for this artifact and has not expired.
+    pub fn verify_artifact(&self, artifact_id: Uuid, token: &str) -> bool {
+        token.split_once('.').is_some_and(|(expires, signature)| {
+            self.check(ARTIFACT_PURPOSE, artifact_id, "", expires, signature)
+        })
+    }
+
+    fn expires(&self) -> i64 {
+        chrono::Utc::now().timestamp() + self.ttl_secs
+    }
+
+    fn signature(&self, purpose: &str, id: Uuid, binding: &str, expires: i64) -> String {
+        hex::encode(
+            self.mac(purpose, id, binding, expires)
+                .finalize()
+                .into_bytes(),
+        )
+    }
+
+    fn check(
+        &self,
+        purpose: &str,
+        id: Uuid,
+        binding: &str,
+        expires: &str,
+        signature: &str,
+    ) -> bool {
+        let Ok(expires) = expires.parse::<i64>() else {
+            return false;
+        };
+        let Ok(signature) = hex::decode(signature) else {
+            return false;
+        };
+        expires >= chrono::Utc::now().timestamp()
+            && self
+                .mac(purpose, id, binding, expires)
+                .verify_slice(&signature)
+                .is_ok()
+    }
+
+    fn mac(&self, purpose: &str, id: Uuid, binding: &str, expires: i64) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC can take any size key");
+        mac.update(format!("{}:{}:{}:{}", purpose, id, binding, expires).as_bytes());
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// A tar header block for an entry of `size` bytes.
+    fn header(name: &str, size: usize, kind: u8) -> Vec<u8> {
+        let mut block = vec![0u8; BLOCK];
+        block[..name.len()].copy_from_slice(name.as_bytes());
+        let size = format!("{:011o}\0", size);
+        block[124..136].copy_from_slice(size.as_bytes());
+        block[156] = kind;
+        block[257..263].copy_from_slice(b"ustar\0");
+        block
+    }
+
+    /// An entry: its header then its data, padded to whole blocks.
+    fn entry(name: &str, kind: u8, data: &[u8]) -> Vec<u8> {
+        let mut out = header(name, data.len(), kind);
+        out.extend_from_slice(data);
+        out.resize(out.len().div_ceil(BLOCK) * BLOCK, 0);
+        out
+    }
+
+    fn archive(entries: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = entries.concat();
+        out.extend_from_slice(&[0u8; 2 * BLOCK]);
+        out
+    }
+
+    fn paths(entries: &[ReportEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.path.as_str()).collect()
+    }
+
+    fn error_of(result: Result<Vec<ReportEntry>>) -> String {
+        match result {
+            Err(Error::InvalidInput(message)) => message,
+            other => panic!("expected an invalid archive, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_unpack_strips_the_shared_root() {
+        let tar = archive(&[
+            entry("site/", b'5', b""),
+            entry("site/index.html", b'0', b"<h1>hi</h1>"),
+            entry("site/css/app.css", b'0', b"body {}"),
+        ]);
+        let entries = unpack(&tar, "index.html").unwrap();
+        assert_eq!(paths(&entries), ["index.html", "css/app.css"]);
+        assert_eq!(&entries[0].data[..], b"<h1>hi</h1>");
+
+        let tar = archive(&[entry("./index.html", b'0', b"root")]);
+        assert_eq!(paths(&unpack(&tar, "index.html").unwrap()), ["index.html"]);
+    }
+
+    #[test]
+    fn test_unpack_reads_gzipped_archives() {
+        let tar = archive(&[entry("index.html", b'0', b"zipped")]);
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(&tar).unwrap();
+        let entries = unpack(&encoder.finish().unwrap(), "index.html").unwrap();
+        assert_eq!(&entries[0].data[..], b"zipped");
+    }
+
+    #[test]
+    fn test_unpack_refuses_paths_leaving_the_report() {
+        for name in ["../etc/passwd", "site/../../escape.html", "/etc/passwd"] {
+            let tar = archive(&[entry(name, b'0', b"x")]);
+            assert!(
+                error_of(unpack(&tar, "index.html")).contains("must stay inside the report"),
+                "{}",
+                name
+            );
+        }
+        // A long name may not smuggle one in either.
+        let tar = archive(&[
+            entry("././@LongLink", b'L', b"../../outside.html\0"),
+            entry("placeholder", b'0', b"x"),
+        ]);
+        assert!(error_of(unpack(&tar, "index.html")).contains("must stay inside the report"));
+    }
+
+    #[test]
+    fn test_unpack_reads_long_names() {
+        let long = format!("{}/index.html", "d".repeat(120));
+        let pax = format!("path={}\n", long);
+        let pax = format!("{} {}", pax.len() + 4, pax);
+        let tar = archive(&[
+            entry("././@LongLink", b'L', format!("{}\0", long).as_bytes()),
+            entry("truncated-gnu", b'0', b"gnu"),
+            entry("PaxHeaders/x", b'x', pax.as_bytes()),
+            entry("truncated-pax", b'0', b"pax"),
+        ]);
+        let entries = read_tar(&tar).unwrap();
+        assert_eq!(paths(&entries), [long.as_str(), long.as_str()]);
+        assert_eq!(&entries[0].data[..], b"gnu");
+        assert_eq!(&entries[1].data[..], b"pax");
+
+        let mut prefixed = entry("index.html", b'0', b"ustar");
+        prefixed[345..349].copy_from_slice(b"site");
+        let entries = read_tar(&archive(&[prefixed])).unwrap();
+        assert_eq!(paths(&entries), ["site/index.html"]);
+    }
+
+    #[test]
+    fn test_unpack_skips_links_and_directories() {
+        let tar = archive(&[
+            entry("docs", b'5', b""),
+            entry("docs/passwd", b'2', b""),
+            entry("docs/hard", b'1', b""),
+            entry("docs/index.html", b'0', b"page"),
+        ]);
+        assert_eq!(paths(&read_tar(&tar).unwrap()), ["docs/index.html"]);
+    }
+
+    #[test]
+    fn test_unpack_refuses_truncated_archives() {
+        let mut tar = header("index.html", 2048, b'0');
+        tar.extend_from_slice(&[b'x'; 100]);
+        assert!(error_of(unpack(&tar, "index.html")).contains("truncated"));
+    }
+
+    #[test]
+    fn test_unpack_limits_sizes() {
+        let mut octal = header("index.html", 0, b'0');
+        let size = format!("{:011o}\0", MAX_UNPACKED_SIZE + 1);
+        octal[124..136].copy_from_slice(size.as_bytes());
+        assert!(error_of(unpack(&archive(&[octal]), "index.html")).contains("bad entry size"));
+
+        let mut base256 = header("index.html", 0, b'0');
+        base256[124..136].fill(0);
+        base256[124] = 0x80;
+        base256[128..136].copy_from_slice(&(MAX_UNPACKED_SIZE + 1).to_be_bytes());
+        assert!(error_of(unpack(&archive(&[base256]), "index.html")).contains("larger than"));
+    }
+
+    #[test]
+    fn test_unpack_limits_file_count() {
+        let files: Vec<_> = (0..=MAX_FILES)
+            .map(|i| entry(&format!("f{}.html", i), b'0', b""))
+            .collect();
+        assert!(error_of(unpack(&archive(&files), "f0.html")).contains("more than"));
+    }
+
+    #[test]
+    fn test_unpack_needs_the_entry_page() {
+        let tar = archive(&[entry("site/other.html", b'0', b"x")]);
+        assert!(error_of(unpack(&tar, "index.html")).contains("no \"index.html\""));
+        assert!(error_of(unpack(&archive(&[]), "index.html")).contains("no files"));
+    }
+
+    #[test]
+    fn test_report_tokens_are_bound_to_their_session() {
+        let signer = ReportSigner::new(b"key".to_vec(), 60);
+        let (report, session) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let token = signer.sign_report(report, Some(session));
+        assert_eq!(
+            signer.verify_report(report, &token),
+            Some(ReportGrant {
+                session_id: Some(session)
+            })
+        );
+        assert_eq!(signer.verify_report(Uuid::new_v4(), &token), None);
+        let other_session = token.replace(&session.to_string(), &Uuid::new_v4().to_string());
+        assert_eq!(signer.verify_report(report, &other_session), None);
+        let anonymous = token.replace(&session.to_string(), NO_SESSION);
+        assert_eq!(signer.verify_report(report, &anonymous), None);
+
+        let token = signer.sign_report(report, None);
+        assert_eq!(
+            signer.verify_report(report, &token),
+            Some(ReportGrant { session_id: None })
+        );
+        let other_key = ReportSigner::new(b"other".to_vec(), 60);
+        assert_eq!(other_key.verify_report(report, &token), None);
+    }
+
+    #[test]
+    fn test_tokens_expire() {
+        let signer = ReportSigner::new(b"key".to_vec(), -1);
+        let id = Uuid::new_v4();
+        assert_eq!(
+            signer.verify_report(id, &signer.sign_report(id, None)),
+            None
+        );
+        assert!(!signer.verify_artifact(id, &signer.sign_artifact(id)));
+    }
+
+    #[test]
+    fn test_report_and_artifact_tokens_do_not_open_each_other() {
+        let signer = ReportSigner::new(b"key".to_vec(), 60);
+        let id = Uuid::new_v4();
+        let artifact = signer.sign_artifact(id);
+        assert!(signer.verify_artifact(id, &artifact));
+        assert!(!signer.verify_artifact(Uuid::new_v4(), &artifact));
+
+        let report = signer.sign_report(id, None);
+        assert!(!signer.verify_artifact(id, &report));
+        let (expires, signature) = artifact.split_once('.').unwrap();
+        let forged = format!("{}.{}.{}", expires, NO_SESSION, signature);
+        assert_eq!(signer.verify_report(id, &forged), None);
+    }
+}
@@ -0,0 +1,311 @@
+//! What changed between two runs of a pipeline.
+//!
+//! A comparison lists the commits built after the earlier run up to the
+//! later one, with a link to their diff on GitHub or GitLab, each stage's
+//! outcome and duration in both runs, and the tests that fail in the later
+//! run but didn't in the earlier one. It is meant for tracking down a
+//! regression between two runs without reading through their logs.
+
+use buildit_core::ResourceId;
+use buildit_core::repository::GitProvider;
+use buildit_core::test_report::TestStatus;
+use buildit_db::repo::pipeline::{PipelineRecord, PipelineRunRecord, StageResultRecord};
+use buildit_db::{
+    DbResult, PgPipelineRepo, PgRepositoryRepo, PgTestResultRepo, PipelineRepo, RepositoryRepo,
+    TestResultRecord, TestResultRepo,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+use super::release_notes::{ReleaseNoteEntry, collect_entries};
+
+/// Newly failing tests listed per comparison.
+const NEWLY_FAILING_LIMIT: i64 = 200;
+
+/// Differences between two runs of a pipeline.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RunComparison {
+    pub from: ComparedRun,
+    pub to: ComparedRun,
+    /// Commits built after `from` up to `to`, oldest first; empty when
+    /// `from` is the later run.
+    pub commits: Vec<ReleaseNoteEntry>,
+    /// Diff of the two runs' commits on the repository's provider.
+    pub compare_url: Option<String>,
+    /// How much longer `to` took than `from`, negative when faster.
+    pub duration_delta_secs: Option<f64>,
+    /// Stages of either run, in the order `to` ran them.
+    pub stages: Vec<StageComparison>,
+    /// Tests failing in `to` that didn't fail in `from`.
+    pub newly_failing_tests: Vec<ComparedTest>,
+}
+
+/// One side of a comparison.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ComparedRun {
+    pub id: uuid::Uuid,
+    pub number: i64,
+    pub status: String,
+    pub branch: Option<String>,
+    pub sha: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub duration_secs: Option<f64>,
+}
+
+impl From<&PipelineRunRecord> for ComparedRun {
+    fn from(run: &PipelineRunRecord) -> Self {
+        Self {
+            id: run.id,
+            number: run.number,
+            status: run.status.clone(),
+            branch: git_field(run, "branch"),
+            sha: git_field(run, "sha"),
+            created_at: run.created_at,
+            duration_secs: run.duration_secs,
+        }
+    }
+}
+
+/// A stage's outcome in both runs; `None` where the run has no result
+/// for it.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct StageComparison {
+    pub name: String,
+    pub from_status: Option<String>,
+    pub to_status: Option<String>,
+    pub from_duration_secs: Option<f64>,
+    pub to_duration_secs: Option<f64>,
+    pub duration_delta_secs: Option<f64>,
+    /// Whether the stage's outcome differs, or only one run has it.
+    pub changed: bool,
+}
+
+/// A test failing in the later run.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ComparedTest {
+    pub stage_name: String,
+    pub suite: String,
+    pub classname: String,
+    pub name: String,
+    pub status: TestStatus,
+    pub message: Option<String>,
+}
+
+impl From<TestResultRecord> for ComparedTest {
+    fn from(record: TestResultRecord) -> Self {
+        Self {
+            status: record.status(),
+            stage_name: record.stage_name,
+            suite: record.suite,
+            classname: record.classname,
+            name: record.name,
+            message: record.message,
+        }
+    }
+}
+
+fn git_field(run: &PipelineRunRecord, key: &str) -> Option<String> {
+    run.git_info
+        .get(key)
+        .and_then(|v| v.as_str())
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+}
+
+fn delta(from: Option<f64>, to: Option<f64>) -> Option<f64> {
+    Some(to? - from?)
+}
+
+/// Stages of both runs side by side, in the order `to` ran them followed
+/// by those only `from` has.
+fn compare_stages(from: &[StageResultRecord], to: &[StageResultRecord]) -> Vec<StageComparison> {
+    let mut names: Vec<&str> = to.iter().map(|s| s.stage_name.as_str()).collect();
+    for stage in from {
+        if !names.contains(&stage.stage_name.as_str()) {
+            names.push(&stage.stage_name);
+        }
+    }
+    names
+        .into_iter()
+        .map(|name| {
+            let before = from.iter().find(|s| s.stage_name == name);
+            let after = to.iter().find(|s| s.stage_name == name);
+            let from_duration_secs = before.and_then(|s| s.duration_secs);
+            let to_duration_secs = after.and_then(|s| s.duration_secs);
+            StageComparison {
+                name: name.to_string(),
+                from_status: before.map(|s| s.status.clone()),
+                to_status: after.map(|s| s.status.clone()),
+                from_duration_secs,
+                to_duration_secs,
+                duration_delta_secs: delta(from_duration_secs, to_duration_secs),
+                changed: before.map(|s| &s.status) != after.map(|s| &s.status),
+            }
+        })
+        .collect()
+}
+
+/// Compares runs from the pipeline, repository and test result
+/// repositories.
+pub struct RunComparisonService {
+    pipeline_repo: Arc<PgPipelineRepo>,
+    repository_repo: Arc<PgRepositoryRepo>,
+    test_result_repo: Arc<PgTestResultRepo>,
+}
+
+impl RunComparisonService {
+    pub fn new(
+        pipeline_repo: Arc<PgPipelineRepo>,
+        repository_repo: Arc<PgRepositoryRepo>,
+        test_result_repo: Arc<PgTestResultRepo>,
+    ) -> Self {
+        Self {
+            pipeline_repo,
+            repository_repo,
+            test_result_repo,
+        }
+    }
+
+    /// Compare two runs of the pipeline.
+    pub async fn compare(
+        &self,
+        pipeline: &PipelineRecord,
+        from: &PipelineRunRecord,
+        to: &PipelineRunRecord,
+    ) -> DbResult<RunComparison> {
+        let from_sha = git_field(from, "sha");
+        let commits = if from.created_at < to.created_at {
+            let runs = self
+                .pipeline_repo
+                .list_runs_between(
+                    ResourceId::from_uuid(pipeline.id),
+                    Some(from.created_at),
+                    to.created_at,
+                )
+                .await?;
+            collect_entries(&runs)
+                .into_iter()
+                .filter(|entry| Some(&entry.sha) != from_sha.as_ref())
+                .collect()
+        } else {
+            vec![]
+        };
+        let compare_url = match (pipeline.repository_id, &from_sha, git_field(to, "sha")) {
+            (Some(repository_id), Some(from_sha), Some(to_sha)) if *from_sha != to_sha => {
+                self.compare_url(repository_id, from_sha, &to_sha).await
+            }
+            _ => None,
+        };
+
+        let from_stages = self
+            .pipeline_repo
+            .list_stage_results(ResourceId::from_uuid(from.id))
+            .await?;
+        let to_stages = self
+            .pipeline_repo
+            .list_stage_results(ResourceId::from_uuid(to.id))
+            .await?;
+        let newly_failing_tests = self
+            .test_result_repo
+            .newly_failing(
+                ResourceId::from_uuid(to.id),
+                ResourceId::from_uuid(from.id),
+                NEWLY_FAILING_LIMIT,
+            )
+            .await?
+            .into_iter()
+            .map(ComparedTest::from)
+            .collect();
+
+        Ok(RunComparison {
+            from: from.into(),
+            to: to.into(),
+            commits,
+            compare_url,
+            duration_delta_secs: delta(from.duration_secs, to.duration_secs),
+            stages: compare_stages(&from_stages, &to_stages),
+            newly_failing_tests,
+        })
+    }
+
+    /// Link to the diff between two commits of the repository, on GitHub
+    /// or GitLab.
+    async fn compare_url(&self, repository_id: uuid::Uuid, from: &str, to: &str) -> Option<String> {
+        let repository = self
+            .repository_repo
+            .get_by_id(ResourceId::from_uuid(repository_id))
+            .await
+            .ok()?;
+        let base = repository
+            .clone_url
+            .strip_prefix("https://")
+            .map(|url| url.trim_end_matches('/').trim_end_matches(".git"))?;
+        match repository.provider {
+            GitProvider::Github => Some(format!("https://{}/compare/{}...{}", base, from, to)),
+            GitProvider::Gitlab => Some(format!("https://{}/-/compare/{}...{}", base, from, to)),
+            GitProvider::Bitbucket => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(stage: &str, status: &str, duration_secs: Option<f64>) -> StageResultRecord {
+        StageResultRecord {
+            id: uuid::Uuid::new_v4(),
+            pipeline_run_id: uuid::Uuid::new_v4(),
+            stage_name: stage.to_string(),
+            status: status.to_string(),
+            job_id: None,
+            deployment_id: None,
+            started_at: None,
+            finished_at: None,
+            error_message: None,
+            stalled_at: None,
+            executor_id: None,
+            executor_name: None,
+            routing: None,
+            queued_at: None,
+            exit_code: None,
+            reused_from: None,
+            image_digest: None,
+            duration_secs,
+            queued_secs: None,
+        }
+    }
+
+    #[test]
+    fn test_stages_are_compared_side_by_side() {
+        let from = [
+            result("build", "succeeded", Some(60.0)),
+            result("test", "succeeded", Some(120.0)),
+            result("lint", "succeeded", Some(5.0)),
+        ];
+        let to = [
+            result("build", "succeeded", Some(75.0)),
+            result("test", "failed", Some(30.0)),
+            result("deploy", "skipped", None),
+        ];
+        let stages = compare_stages(&from, &to);
+        let summary: Vec<_> = stages
+            .iter()
+            .map(|s| (s.name.as_str(), s.changed, s.duration_delta_secs))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("build", false, Some(15.0)),
+                ("test", true, Some(-90.0)),
+                ("deploy", true, None),
+                // Only the earlier run has it
+                ("lint", true, None),
+            ]
+        );
+        assert_eq!(stages[3].to_status, None);
+        assert_eq!(stages[3].from_status.as_deref(), Some("succeeded"));
+    }
+}
@@ -0,0 +1,71 @@
+//! Vulnerability scan results of pipeline runs.
+//!
+//! Scan stages run grype or trivy in a job; the orchestrator reads the
+//! results and the SBOM back out of it and hands them over here. Findings
+//! are stored per stage, and the SBOM is kept as a run artifact.
+
+use axum::body::Bytes;
+use buildit_core::ResourceId;
+use buildit_core::artifact::{ArtifactKey, ArtifactKind};
+use buildit_core::scan::{Finding, Scanner};
+use buildit_db::{ArtifactRepo, ScanFindingRepo};
+
+use crate::AppState;
+
+/// Name of a scan stage's SBOM among the run's artifacts.
+const SBOM_NAME: &str = "sbom.cdx.json";
+
+/// Store a scan stage's findings and SBOM. Results that could not be read
+/// are logged; whether the stage failed was decided by the orchestrator.
+pub async fn record(
+    state: &AppState,
+    run_id: ResourceId,
+    stage: &str,
+    scanner: Scanner,
+    target: &str,
+    findings: Result<Vec<Finding>, String>,
+    sbom: Option<String>,
+) {
+    match findings {
+        Ok(findings) => match state
+            .scan_finding_repo
+            .replace_findings(run_id, stage, scanner, target, &findings)
+            .await
+        {
+            Ok(stored) => {
+                tracing::info!(run_id = %run_id, stage = %stage, target = %target, findings = stored, "Stored scan findings")
+            }
+            Err(e) => {
+                tracing::error!(run_id = %run_id, stage = %stage, error = %e, "Failed to store scan findings")
+            }
+        },
+        Err(e) => {
+            tracing::warn!(run_id = %run_id, stage = %stage, error = %e, "Failed to read scan results")
+        }
+    }
+
+    let Some(sbom) = sbom else {
+        return;
+    };
+    let key = ArtifactKey {
+        run_id,
+        stage: stage.to_string(),
+        name: SBOM_NAME.to_string(),
+    };
+    let stored = match state.artifact_store.put(&key, Bytes::from(sbom)).await {
+        Ok(reference) => state
+            .artifact_repo
+            .record_artifact(
+                &reference,
+                ArtifactKind::Sbom,
+                "application/vnd.cyclonedx+json",
+            )
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    if let Err(e) = stored {
+        tracing::error!(run_id = %run_id, stage = %stage, error = %e, "Failed to store SBOM");
+    }
+}
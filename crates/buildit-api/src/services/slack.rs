@@ -0,0 +1,274 @@
+//! Slack slash commands and message buttons.
+//!
+//! A Slack app's `/buildit` command and the Approve and Reject buttons of
+//! approval notifications call `/webhooks/slack`. Slack signs each request
+//! with the app's signing secret: `X-Slack-Signature` is `v0=` and the hex
+//! HMAC-SHA256 of `v0:{timestamp}:{body}`, with the timestamp in
+//! `X-Slack-Request-Timestamp`. Requests older than five minutes are
+//! refused so captured ones cannot be replayed.
+//!
+//! Slack users act as the BuildIt user they are linked to. `/buildit link`
+//! answers with a signed link to a page where the signed-in user confirms
+//! the link, so nobody can claim someone else's Slack account.
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde_json::{Value, json};
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// How far a request's timestamp may be from now, in seconds.
+const MAX_CLOCK_SKEW_SECS: i64 = 5 * 60;
+
+/// How long a link to connect a Slack account is valid, in seconds.
+const LINK_TTL_SECS: i64 = 15 * 60;
+
+/// `action_id` of the Approve button of approval notifications.
+pub const APPROVE_ACTION: &str = "approve_deployment";
+/// `action_id` of the Reject button of approval notifications.
+pub const REJECT_ACTION: &str = "reject_deployment";
+
+/// A `/buildit` command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Deploy a service's current spec to an environment, both by name.
+    Deploy {
+        service: String,
+        environment: String,
+    },
+    /// Re-run a finished run.
+    Rerun {
+        run_id: Uuid,
+    },
+    /// Link the Slack user to a BuildIt user.
+    Link,
+    Help,
+}
+
+impl Command {
+    /// Parse the text after `/buildit`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        match words.as_slice() {
+            [] | ["help"] => Ok(Command::Help),
+            ["link"] => Ok(Command::Link),
+            ["deploy", service, environment] => Ok(Command::Deploy {
+                service: service.to_string(),
+                environment: environment.to_string(),
+            }),
+            ["deploy", ..] => Err("Usage: /buildit deploy <service> <environment>".to_string()),
+            ["rerun", run] => Uuid::parse_str(run)
+                .map(|run_id| Command::Rerun { run_id })
+                .map_err(|_| format!("{} is not a run ID", run)),
+            ["rerun", ..] => Err("Usage: /buildit rerun <run-id>".to_string()),
+            [other, ..] => Err(format!("Unknown command '{}'. {}", other, HELP)),
+        }
+    }
+}
+
+/// What `/buildit help` answers.
+pub const HELP: &str = "Commands: `/buildit deploy <service> <environment>`, \
+    `/buildit rerun <run-id>`, `/buildit link` to act as your BuildIt account.";
+
+/// Whether `signature` is Slack's for a request with `timestamp` and
+/// `body`, sent within five minutes of `now`.
+pub fn verify_signature(
+    secret: &str,
+    timestamp: &str,
+    body: &[u8],
+    signature: &str,
+    now: DateTime<Utc>,
+) -> bool {
+    let Ok(sent) = timestamp.parse::<i64>() else {
+        return false;
+    };
+    if (now.timestamp() - sent).abs() > MAX_CLOCK_SKEW_SECS {
+        return false;
+    }
+    let Some(Ok(signature)) = signature.strip_prefix("v0=").map(hex::decode) else {
+        return false;
+    };
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take any size key");
+    mac.update(format!("v0:{}:", timestamp).as_bytes());
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// A token for linking the Slack user `slack_user_id` of `team_id`,
+/// `<team>.<user>.<expires>.<signature>`.
+pub fn link_token(secret: &str, team_id: &str, slack_user_id: &str, now: DateTime<Utc>) -> String {
+    let expires = now.timestamp() + LINK_TTL_SECS;
+    let mac = link_mac(secret, team_id, slack_user_id, expires);
+    format!(
+        "{}.{}.{}.{}",
+        team_id,
+        slack_user_id,
+        expires,
+        hex::encode(mac.finalize().into_bytes())
+    )
+}
+
+/// The team and user a link token was signed for, unless it is invalid or
+/// expired.
+pub fn verify_link_token(
+    secret: &str,
+    token: &str,
+    now: DateTime<Utc>,
+) -> Option<(String, String)> {
+    let [team_id, slack_user_id, expires, signature] =
+        <[&str; 4]>::try_from(token.split('.').collect::<Vec<_>>()).ok()?;
+    let expires: i64 = expires.parse().ok()?;
+    let signature = hex::decode(signature).ok()?;
+    if expires < now.timestamp() {
+        return None;
+    }
+    link_mac(secret, team_id, slack_user_id, expires)
+        .verify_slice(&signature)
+        .ok()?;
+    Some((team_id.to_string(), slack_user_id.to_string()))
+}
+
+fn link_mac(secret: &str, team_id: &str, slack_user_id: &str, expires: i64) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take any size key");
+    mac.update(format!("link:{}:{}:{}", team_id, slack_user_id, expires).as_bytes());
+    mac
+}
+
+/// A reply only the user who sent a command sees.
+pub fn ephemeral(text: &str) -> Value {
+    json!({ "response_type": "ephemeral", "text": text })
+}
+
+/// A reply the whole channel sees.
+pub fn in_channel(text: &str) -> Value {
+    json!({ "response_type": "in_channel", "text": text })
+}
+
+/// Blocks of an approval notification of a deployment: its message and
+/// buttons approving or rejecting it.
+pub fn approval_blocks(text: &str, deployment_id: Uuid) -> Value {
+    json!([
+        {
+            "type": "section",
+            "text": { "type": "mrkdwn", "text": text },
+        },
+        {
+            "type": "actions",
+            "elements": [
+                {
+                    "type": "button",
+                    "action_id": APPROVE_ACTION,
+                    "style": "primary",
+                    "text": { "type": "plain_text", "text": "Approve" },
+                    "value": deployment_id.to_string(),
+                },
+                {
+                    "type": "button",
+                    "action_id": REJECT_ACTION,
+                    "style": "danger",
+                    "text": { "type": "plain_text", "text": "Reject" },
+                    "value": deployment_id.to_string(),
+                },
+            ],
+        },
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    const BODY: &[u8] = b"command=%2Fbuildit&text=help";
+    const SIGNATURE: &str = "v0=57e6a3bdb8d6e60f9176eed56d5925e27bea859f2fde1e4b93ea97e7ba60bd9e";
+
+    fn sent_at(offset_secs: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_700_000_000 + offset_secs, 0).unwrap()
+    }
+
+    #[test]
+    fn test_requests_must_carry_slacks_signature() {
+        let secret = "signing-secret";
+        let ts = "1700000000";
+        assert!(verify_signature(secret, ts, BODY, SIGNATURE, sent_at(30)));
+        assert!(!verify_signature(
+            "other-secret",
+            ts,
+            BODY,
+            SIGNATURE,
+            sent_at(30)
+        ));
+        assert!(!verify_signature(
+            secret,
+            ts,
+            b"command=%2Fbuildit&text=link",
+            SIGNATURE,
+            sent_at(30)
+        ));
+        assert!(!verify_signature(
+            secret,
+            "1700000001",
+            BODY,
+            SIGNATURE,
+            sent_at(30)
+        ));
+        assert!(!verify_signature(
+            secret,
+            ts,
+            BODY,
+            &SIGNATURE[3..],
+            sent_at(30)
+        ));
+        // Replays of old requests are refused
+        assert!(!verify_signature(
+            secret,
+            ts,
+            BODY,
+            SIGNATURE,
+            sent_at(MAX_CLOCK_SKEW_SECS + 1)
+        ));
+    }
+
+    #[test]
+    fn test_link_tokens_expire_and_cannot_be_altered() {
+        let token = link_token("secret", "T1", "U1", sent_at(0));
+        assert_eq!(
+            verify_link_token("secret", &token, sent_at(LINK_TTL_SECS)),
+            Some(("T1".to_string(), "U1".to_string()))
+        );
+        assert_eq!(
+            verify_link_token("secret", &token, sent_at(LINK_TTL_SECS + 1)),
+            None
+        );
+        assert_eq!(verify_link_token("other", &token, sent_at(0)), None);
+        let claimed = token.replacen("U1", "U2", 1);
+        assert_eq!(verify_link_token("secret", &claimed, sent_at(0)), None);
+    }
+
+    #[test]
+    fn test_commands() {
+        assert_eq!(Command::parse("  "), Ok(Command::Help));
+        assert_eq!(
+            Command::parse("deploy api production"),
+            Ok(Command::Deploy {
+                service: "api".to_string(),
+                environment: "production".to_string()
+            })
+        );
+        assert!(Command::parse("deploy api").is_err());
+        assert!(Command::parse("rerun 42").is_err());
+        let run_id = Uuid::new_v4();
+        assert_eq!(
+            Command::parse(&format!("rerun {}", run_id)),
+            Ok(Command::Rerun { run_id })
+        );
+        assert!(
+            Command::parse("destroy")
+                .unwrap_err()
+                .starts_with("Unknown command 'destroy'")
+        );
+    }
+}
@@ -44,6 +44,7 @@ impl Default for StackRunnerConfig {
 pub struct StackRunner {
     config: StackRunnerConfig,
     stack_repo: Arc<PgStackRepo>,
+    #[allow(dead_code)]
     git_service: GitService,
 }
 
@@ -94,7 +95,7 @@ impl StackRunner {
 
         // Update final status
         match result {
-            Ok(output) => {
+            Ok(_output) => {
                 info!(run_id = %run.id, "Stack run completed successfully");
                 self.stack_repo
                     .update_run_finished(run_id, StackRunStatus::Succeeded, None)
@@ -123,7 +124,7 @@ impl StackRunner {
         let commands = self.build_plan_commands(stack);
 
         // Create job spec
-        let job_spec = self.build_terraform_job_spec(&format!("plan-{}", run.id), stack, commands);
+        let _job_spec = self.build_terraform_job_spec(&format!("plan-{}", run.id), stack, commands);
 
         // For now, run terraform locally (Phase 2 will add container execution)
         let output = self
@@ -196,7 +197,7 @@ impl StackRunner {
     async fn execute_destroy(
         &self,
         stack: &buildit_core::stack::Stack,
-        run: &buildit_core::stack::StackRun,
+        _run: &buildit_core::stack::StackRun,
     ) -> Result<String, StackRunnerError> {
         // Init first
         let init_output = self
@@ -218,7 +219,7 @@ impl StackRunner {
     async fn execute_refresh(
         &self,
         stack: &buildit_core::stack::Stack,
-        run: &buildit_core::stack::StackRun,
+        _run: &buildit_core::stack::StackRun,
     ) -> Result<String, StackRunnerError> {
         let init_output = self
             .run_terraform_locally(stack, &["init", "-input=false"])
@@ -231,7 +232,7 @@ impl StackRunner {
     }
 
     /// Build terraform plan commands for container execution.
-    fn build_plan_commands(&self, stack: &buildit_core::stack::Stack) -> Vec<String> {
+    fn build_plan_commands(&self, _stack: &buildit_core::stack::Stack) -> Vec<String> {
         vec![
             "terraform init -input=false".to_string(),
             format!("terraform plan -input=false -no-color -out=tfplan -detailed-exitcode || true"),
@@ -268,11 +269,22 @@ impl StackRunner {
             image: self.config.terraform_image.clone(),
             command,
             env,
+            entrypoint: None,
+            user: None,
             working_dir: Some(stack.path.clone()),
             timeout: Some(std::time::Duration::from_secs(3600)), // 1 hour timeout
             resources: ResourceRequirements::default(),
             volumes: vec![],
             git_clone: None,
+            platform: Default::default(),
+            wait_for: vec![],
+            security_context: None,
+            privileged: false,
+            extra_hosts: vec![],
+            network_mode: None,
+            workspace: None,
+            pull_policy: None,
+            tenant_id: Some(ResourceId::from_uuid(stack.tenant_id)),
         }
     }
 
@@ -287,7 +299,7 @@ impl StackRunner {
         let working_dir = stack
             .working_directory
             .as_ref()
-            .ok_or_else(|| StackRunnerError::NoWorkingDirectory)?;
+            .ok_or(StackRunnerError::NoWorkingDirectory)?;
 
         let terraform_bin =
             std::env::var("TERRAFORM_BIN").unwrap_or_else(|_| "terraform".to_string());
@@ -0,0 +1,324 @@
+//! Stack operations run from the task queue: initializing a stack's
+//! workspace, and planning, applying and destroying stack runs.
+//!
+//! Terraform failures finish the run as failed and complete the task;
+//! only errors reaching the database fail the task, so it is retried.
+//! Every plan is checked against the tenant's plan policies before it can
+//! be applied; a failing hard policy fails the run too.
+
+use buildit_core::ResourceId;
+use buildit_core::stack::{Stack, StackRun, StackRunStatus, StackRunType, StackStatus};
+use buildit_db::{RepositoryRepo, StackRepo};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::AppState;
+use crate::services::git::{CheckoutOptions, GitService};
+use crate::services::opa::OpaService;
+use crate::services::terraform::{PlanResult, TerraformService};
+use crate::ws::BroadcastEvent;
+use buildit_scheduler::TaskContext;
+
+/// Progress of a stack run, checkpointed between plan and apply.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StackRunProgress {
+    /// Plan written by an earlier attempt; applied instead of planning again
+    /// while it is still on disk.
+    plan_file: Option<PathBuf>,
+}
+
+/// Clone the stack's repository and run `terraform init`.
+pub async fn init(state: &AppState, stack_id: ResourceId) -> Result<(), String> {
+    let stack = state
+        .stack_repo
+        .get_stack(stack_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    let Some(repo_id) = stack.repository_id else {
+        return Ok(());
+    };
+    let repo = state
+        .repository_repo
+        .get_by_id(ResourceId::from_uuid(repo_id))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let git_service = GitService::new();
+    let tf_service = TerraformService::new().with_state_backend(stack.id);
+    let initialized = async {
+        let repo_path = git_service
+            .checkout(&repo, &CheckoutOptions::default())
+            .await
+            .map_err(|e| format!("Failed to clone repository: {}", e))?;
+        let working_dir = repo_path.join(&stack.path);
+        state
+            .stack_repo
+            .update_stack_working_directory(stack_id, working_dir.to_str().unwrap_or(""))
+            .await
+            .map_err(|e| format!("Failed to update stack working directory: {}", e))?;
+        tf_service
+            .init(&working_dir, &HashMap::new())
+            .await
+            .map_err(|e| format!("Terraform init failed: {}", e))
+    }
+    .await;
+
+    let status = if initialized.is_ok() {
+        StackStatus::Ready
+    } else {
+        StackStatus::Error
+    };
+    state
+        .stack_repo
+        .update_stack_status(stack_id, status)
+        .await
+        .map_err(|e| e.to_string())?;
+    initialized.map(|_| ())
+}
+
+/// Execute a plan, apply, destroy or refresh run.
+pub async fn run(state: &AppState, run_id: ResourceId, task: &TaskContext) -> Result<(), String> {
+    let run = state
+        .stack_repo
+        .get_run(run_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    if !matches!(
+        run.status,
+        StackRunStatus::Pending | StackRunStatus::Running
+    ) {
+        tracing::info!(run_id = %run_id, status = %run.status, "Stack run already handled");
+        return Ok(());
+    }
+    let stack = state
+        .stack_repo
+        .get_stack(ResourceId::from_uuid(run.stack_id))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .stack_repo
+        .update_run_started(run_id)
+        .await
+        .map_err(|e| format!("Failed to update run started: {}", e))?;
+    let Some(working_dir) = stack.working_directory.as_deref().map(PathBuf::from) else {
+        return finish(
+            state,
+            &run,
+            Err("Stack has no working directory".to_string()),
+        )
+        .await;
+    };
+    let tf_service = TerraformService::new().with_state_backend(stack.id);
+
+    let outcome = match run.run_type {
+        StackRunType::Plan | StackRunType::Apply => {
+            let mut progress: StackRunProgress = task.state();
+            let plan_file = match progress.plan_file.take().filter(|f| f.exists()) {
+                Some(plan_file) => Some(plan_file),
+                None => {
+                    let result = match tf_service
+                        .plan(&working_dir, &HashMap::new(), None, None)
+                        .await
+                    {
+                        Ok(result) => result,
+                        Err(e) => {
+                            tracing::error!(error = %e, "Plan failed");
+                            return finish(state, &run, Err(e.to_string())).await;
+                        }
+                    };
+                    record_plan(state, run_id, &result).await?;
+                    if let Some(message) = check_policies(state, &stack, run_id, &result).await? {
+                        return finish(state, &run, Err(message)).await;
+                    }
+                    if run.run_type == StackRunType::Plan && !result.has_changes {
+                        return finish(state, &run, Ok(StackRunStatus::Succeeded)).await;
+                    }
+                    if run.run_type == StackRunType::Plan && !stack.auto_apply {
+                        return finish(state, &run, Ok(StackRunStatus::NeedsApproval)).await;
+                    }
+                    progress.plan_file = result.plan_file.clone();
+                    task.checkpoint(&progress).await?;
+                    result.plan_file
+                }
+            };
+            match plan_file {
+                // Apply runs and auto-applied plans go straight on to apply
+                Some(plan_file) => {
+                    apply(state, &tf_service, run_id, &working_dir, &plan_file).await
+                }
+                // No changes
+                None => Ok(StackRunStatus::Succeeded),
+            }
+        }
+        StackRunType::Destroy => {
+            match tf_service
+                .destroy(&working_dir, &HashMap::new(), None)
+                .await
+            {
+                Ok(output) => {
+                    let _ = state
+                        .stack_repo
+                        .update_run_apply_output(run_id, &output)
+                        .await;
+                    Ok(StackRunStatus::Succeeded)
+                }
+                Err(e) => Err(e.to_string()),
+            }
+        }
+        // TODO: Implement refresh
+        StackRunType::Refresh => Ok(StackRunStatus::Succeeded),
+    };
+    finish(state, &run, outcome).await
+}
+
+/// Apply the saved plan of an approved run.
+pub async fn apply_approved(state: &AppState, run_id: ResourceId) -> Result<(), String> {
+    let run = state
+        .stack_repo
+        .get_run(run_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    if !matches!(
+        run.status,
+        StackRunStatus::Approved | StackRunStatus::Applying
+    ) {
+        tracing::info!(run_id = %run_id, status = %run.status, "Stack run already handled");
+        return Ok(());
+    }
+    let stack = state
+        .stack_repo
+        .get_stack(ResourceId::from_uuid(run.stack_id))
+        .await
+        .map_err(|e| e.to_string())?;
+    let Some(working_dir) = stack.working_directory.as_deref().map(PathBuf::from) else {
+        return finish(
+            state,
+            &run,
+            Err("Stack has no working directory".to_string()),
+        )
+        .await;
+    };
+    let tf_service = TerraformService::new().with_state_backend(stack.id);
+
+    let _ = state
+        .stack_repo
+        .update_run_status(run_id, StackRunStatus::Applying)
+        .await;
+
+    // The plan file should still exist from the original plan
+    let plan_file = working_dir.join("tfplan");
+    let outcome = if plan_file.exists() {
+        apply(state, &tf_service, run_id, &working_dir, &plan_file).await
+    } else {
+        // Plan file doesn't exist, need to re-plan
+        Err("Plan file not found - please run a new plan".to_string())
+    };
+    finish(state, &run, outcome).await
+}
+
+async fn record_plan(
+    state: &AppState,
+    run_id: ResourceId,
+    result: &PlanResult,
+) -> Result<(), String> {
+    state
+        .stack_repo
+        .update_run_plan_output(
+            run_id,
+            &result.output,
+            result.plan_json.clone(),
+            result.summary.to_add.len() as i32,
+            result.summary.to_change.len() as i32,
+            result.summary.to_destroy.len() as i32,
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    state
+        .stack_repo
+        .update_run_plan_changes(run_id, &result.changes)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Evaluate the tenant's plan policies and record their results on the
+/// run. Returns why the run must stop if a hard policy failed.
+async fn check_policies(
+    state: &AppState,
+    stack: &Stack,
+    run_id: ResourceId,
+    result: &PlanResult,
+) -> Result<Option<String>, String> {
+    let policies = state
+        .stack_repo
+        .list_policies(ResourceId::from_uuid(stack.tenant_id))
+        .await
+        .map_err(|e| e.to_string())?;
+    if !policies.iter().any(|p| p.enabled) {
+        return Ok(None);
+    }
+    let results = OpaService::new()
+        .check_plan(&policies, result.plan_json.as_ref())
+        .await;
+    state
+        .stack_repo
+        .update_run_policy_results(run_id, &results)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let failures: Vec<String> = results
+        .iter()
+        .filter(|r| r.blocks())
+        .map(|r| match &r.error {
+            Some(error) => format!("{}: {}", r.policy, error),
+            None => format!("{}: {}", r.policy, r.violations.join("; ")),
+        })
+        .collect();
+    Ok((!failures.is_empty()).then(|| format!("Plan violates policy: {}", failures.join(", "))))
+}
+
+async fn apply(
+    state: &AppState,
+    tf_service: &TerraformService,
+    run_id: ResourceId,
+    working_dir: &Path,
+    plan_file: &Path,
+) -> Result<StackRunStatus, String> {
+    match tf_service.apply(working_dir, plan_file, None).await {
+        Ok(result) => {
+            let _ = state
+                .stack_repo
+                .update_run_apply_output(run_id, &result.output)
+                .await;
+            Ok(StackRunStatus::Succeeded)
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "Apply failed");
+            Err(e.to_string())
+        }
+    }
+}
+
+/// Record how a run ended; a terraform error finishes it as failed.
+async fn finish(
+    state: &AppState,
+    run: &StackRun,
+    outcome: Result<StackRunStatus, String>,
+) -> Result<(), String> {
+    let (status, error) = match &outcome {
+        Ok(status) => (*status, None),
+        Err(message) => (StackRunStatus::Failed, Some(message.as_str())),
+    };
+    state
+        .stack_repo
+        .update_run_finished(ResourceId::from_uuid(run.id), status, error)
+        .await
+        .map_err(|e| format!("Failed to record run status: {}", e))?;
+    state.event_bus.publish(BroadcastEvent::StackRunUpdate {
+        run_id: run.id.to_string(),
+        stack_id: run.stack_id.to_string(),
+        status: status.to_string(),
+    });
+    Ok(())
+}
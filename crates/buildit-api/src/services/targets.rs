@@ -0,0 +1,530 @@
+//! Connection checks of deployment targets.
+//!
+//! A target is checked when it is created or changed, when asked to, and
+//! periodically by the target monitor. A check reaches the target's API
+//! with its credentials (a version ping for Kubernetes and Docker, an
+//! authenticated call for Fly.io and AWS) and, for Kubernetes, asks the
+//! cluster whether the credentials may manage deployments. The outcome is
+//! stored on the target and pushed to the targets page.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use buildit_core::ResourceId;
+use buildit_core::target::{TargetCredentials, TargetKind, TargetStatus};
+use buildit_db::{DeploymentRepo, PgDeploymentRepo, Target};
+use chrono::Utc;
+use k8s_openapi::api::authorization::v1::{
+    ResourceAttributes, SelfSubjectAccessReview, SelfSubjectAccessReviewSpec,
+};
+use kube::api::{Api, PostParams};
+use kube::config::{KubeConfigOptions, Kubeconfig};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+use crate::services::credentials::CredentialCipher;
+use crate::services::event_bus::EventBus;
+use crate::services::log_archive::sign_v4;
+use crate::ws::BroadcastEvent;
+
+/// Longest a single check may take.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// What Kubernetes targets must be allowed in their namespace: verb, API
+/// group and resource.
+const KUBERNETES_PERMISSIONS: &[(&str, &str, &str)] = &[
+    ("create", "apps", "deployments"),
+    ("patch", "apps", "deployments"),
+    ("create", "", "services"),
+    ("patch", "", "services"),
+    ("create", "", "configmaps"),
+];
+
+/// Outcome of a connection check.
+#[derive(Debug, Clone)]
+pub struct TargetCheck {
+    pub status: TargetStatus,
+    /// What was found, e.g. the server version, or why the check failed.
+    pub message: Option<String>,
+}
+
+impl TargetCheck {
+    fn connected(message: impl Into<String>) -> Self {
+        Self {
+            status: TargetStatus::Connected,
+            message: Some(message.into()),
+        }
+    }
+
+    fn degraded(message: impl Into<String>) -> Self {
+        Self {
+            status: TargetStatus::Degraded,
+            message: Some(message.into()),
+        }
+    }
+
+    fn disconnected(message: impl Into<String>) -> Self {
+        Self {
+            status: TargetStatus::Disconnected,
+            message: Some(message.into()),
+        }
+    }
+}
+
+/// Check that a target can be reached with its credentials.
+pub async fn check(
+    kind: TargetKind,
+    region: Option<&str>,
+    config: &serde_json::Value,
+    credentials: Option<&TargetCredentials>,
+) -> TargetCheck {
+    let check = async {
+        match kind {
+            TargetKind::Kubernetes => check_kubernetes(config, credentials).await,
+            TargetKind::Fly => check_fly(config, credentials).await,
+            TargetKind::Aws => check_aws(region, credentials).await,
+            TargetKind::Docker => check_docker(config).await,
+        }
+    };
+    tokio::time::timeout(CHECK_TIMEOUT, check)
+        .await
+        .unwrap_or_else(|_| {
+            TargetCheck::disconnected(format!("no answer within {}s", CHECK_TIMEOUT.as_secs()))
+        })
+}
+
+/// Check a stored target and record the outcome, telling the targets page
+/// when its status changed.
+pub async fn refresh(
+    deployment_repo: &PgDeploymentRepo,
+    cipher: Option<&CredentialCipher>,
+    event_bus: &dyn EventBus,
+    target: Target,
+) -> buildit_db::DbResult<Target> {
+    let outcome = match target_kind(&target) {
+        Ok(kind) => match stored_credentials(cipher, &target) {
+            Ok(credentials) => {
+                check(
+                    kind,
+                    target.region.as_deref(),
+                    &target.config,
+                    credentials.as_ref(),
+                )
+                .await
+            }
+            Err(e) => TargetCheck::disconnected(e),
+        },
+        Err(e) => TargetCheck::disconnected(e),
+    };
+
+    let updated = deployment_repo
+        .update_target_status(
+            ResourceId::from_uuid(target.id),
+            outcome.status.as_str(),
+            outcome.message.as_deref(),
+        )
+        .await?;
+    if updated.status != target.status || updated.status_message != target.status_message {
+        event_bus.publish(BroadcastEvent::TargetStatus {
+            target_id: target.id.to_string(),
+            status: updated.status.clone(),
+            message: updated.status_message.clone(),
+        });
+    }
+    Ok(updated)
+}
+
+/// Kind of a stored target.
+pub fn target_kind(target: &Target) -> Result<TargetKind, String> {
+    target.target_type.parse()
+}
+
+/// Decrypt a target's stored credentials, if it has any.
+pub fn stored_credentials(
+    cipher: Option<&CredentialCipher>,
+    target: &Target,
+) -> Result<Option<TargetCredentials>, String> {
+    let Some(sealed) = target.credentials.as_deref() else {
+        return Ok(None);
+    };
+    let cipher = cipher.ok_or("credentials are stored but BUILDIT_ENCRYPTION_KEY is not set")?;
+    cipher.open(sealed).map(Some)
+}
+
+/// Client for a Kubernetes target's cluster. Without credentials the
+/// server's own (in-cluster or default kubeconfig) are used.
+pub async fn kube_client(credentials: Option<&TargetCredentials>) -> Result<kube::Client, String> {
+    let config = match credentials {
+        None => kube::Config::infer().await.map_err(|e| e.to_string())?,
+        Some(TargetCredentials::Kubeconfig {
+            kubeconfig,
+            context,
+        }) => {
+            let kubeconfig = Kubeconfig::from_yaml(&kubeconfig_yaml(kubeconfig))
+                .map_err(|e| format!("invalid kubeconfig: {}", e))?;
+            let options = KubeConfigOptions {
+                context: context.clone(),
+                ..Default::default()
+            };
+            kube::Config::from_custom_kubeconfig(kubeconfig, &options)
+                .await
+                .map_err(|e| format!("invalid kubeconfig: {}", e))?
+        }
+        Some(TargetCredentials::ServiceAccount {
+            server,
+            token,
+            ca_cert,
+        }) => {
+            let kubeconfig: Kubeconfig = serde_json::from_value(serde_json::json!({
+                "clusters": [{
+                    "name": "target",
+                    "cluster": {
+                        "server": server,
+                        "certificate-authority-data": ca_cert.as_ref().map(|c| BASE64.encode(c)),
+                    },
+                }],
+                "users": [{"name": "target", "user": {"token": token}}],
+                "contexts": [{"name": "target", "context": {"cluster": "target", "user": "target"}}],
+                "current-context": "target",
+            }))
+            .map_err(|e| e.to_string())?;
+            kube::Config::from_custom_kubeconfig(kubeconfig, &KubeConfigOptions::default())
+                .await
+                .map_err(|e| format!("invalid service account credentials: {}", e))?
+        }
+        Some(other) => {
+            return Err(format!(
+                "{} credentials cannot reach a Kubernetes cluster",
+                other.kind()
+            ));
+        }
+    };
+    kube::Client::try_from(config).map_err(|e| e.to_string())
+}
+
+/// A kubeconfig as pasted, or decoded when it was pasted base64-encoded.
+fn kubeconfig_yaml(kubeconfig: &str) -> String {
+    if kubeconfig.contains(':') {
+        return kubeconfig.to_string();
+    }
+    let compact: String = kubeconfig.split_whitespace().collect();
+    BASE64
+        .decode(compact)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .unwrap_or_else(|| kubeconfig.to_string())
+}
+
+async fn check_kubernetes(
+    config: &serde_json::Value,
+    credentials: Option<&TargetCredentials>,
+) -> TargetCheck {
+    let client = match kube_client(credentials).await {
+        Ok(client) => client,
+        Err(e) => return TargetCheck::disconnected(e),
+    };
+    let version = match client.apiserver_version().await {
+        Ok(version) => version,
+        Err(e) => return TargetCheck::disconnected(format!("API server unreachable: {}", e)),
+    };
+
+    let namespace = config
+        .get("namespace")
+        .and_then(|n| n.as_str())
+        .filter(|n| !n.is_empty())
+        .unwrap_or("default");
+    let reviews: Api<SelfSubjectAccessReview> = Api::all(client);
+    let mut missing = Vec::new();
+    for (verb, group, resource) in KUBERNETES_PERMISSIONS {
+        let review = SelfSubjectAccessReview {
+            spec: SelfSubjectAccessReviewSpec {
+                resource_attributes: Some(ResourceAttributes {
+                    namespace: Some(namespace.to_string()),
+                    verb: Some(verb.to_string()),
+                    group: Some(group.to_string()),
+                    resource: Some(resource.to_string()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let allowed = match reviews.create(&PostParams::default(), &review).await {
+            Ok(review) => review.status.is_some_and(|s| s.allowed),
+            Err(e) => {
+                return TargetCheck::degraded(format!("permissions cannot be checked: {}", e));
+            }
+        };
+        if !allowed {
+            let resource = if group.is_empty() {
+                resource.to_string()
+            } else {
+                format!("{}.{}", resource, group)
+            };
+            missing.push(format!("{} {}", verb, resource));
+        }
+    }
+
+    if missing.is_empty() {
+        TargetCheck::connected(format!("Kubernetes {}", version.git_version))
+    } else {
+        TargetCheck::degraded(format!(
+            "not allowed in namespace '{}': {}",
+            namespace,
+            missing.join(", ")
+        ))
+    }
+}
+
+async fn check_fly(
+    config: &serde_json::Value,
+    credentials: Option<&TargetCredentials>,
+) -> TargetCheck {
+    let Some(TargetCredentials::Fly { token }) = credentials else {
+        return TargetCheck::disconnected("no Fly.io token");
+    };
+    let org = config
+        .get("org")
+        .and_then(|o| o.as_str())
+        .filter(|o| !o.is_empty())
+        .unwrap_or("personal");
+
+    let response = reqwest::Client::new()
+        .get("https://api.machines.dev/v1/apps")
+        .query(&[("org_slug", org)])
+        .bearer_auth(token)
+        .send()
+        .await;
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => return TargetCheck::disconnected(format!("Fly.io unreachable: {}", e)),
+    };
+    match response.status().as_u16() {
+        200..=299 => {
+            let apps = response
+                .json::<serde_json::Value>()
+                .await
+                .ok()
+                .and_then(|body| body.get("total_apps").and_then(|n| n.as_u64()))
+                .unwrap_or_default();
+            TargetCheck::connected(format!("{} apps in organization '{}'", apps, org))
+        }
+        401 | 403 => TargetCheck::disconnected("Fly.io refused the token"),
+        404 => TargetCheck::disconnected(format!("no Fly.io organization '{}'", org)),
+        status => TargetCheck::disconnected(format!("Fly.io answered {}", status)),
+    }
+}
+
+/// Checks AWS credentials with STS `GetCallerIdentity`, which any valid
+/// credentials may call.
+async fn check_aws(region: Option<&str>, credentials: Option<&TargetCredentials>) -> TargetCheck {
+    let Some(TargetCredentials::Aws {
+        access_key_id,
+        secret_access_key,
+        session_token,
+    }) = credentials
+    else {
+        return TargetCheck::disconnected("no AWS access key");
+    };
+    let region = region.filter(|r| !r.is_empty()).unwrap_or("us-east-1");
+    let host = format!("sts.{}.amazonaws.com", region);
+    let body = "Action=GetCallerIdentity&Version=2011-06-15";
+    let content_type = "application/x-www-form-urlencoded; charset=utf-8";
+
+    let now = Utc::now();
+    let mut headers = vec![
+        ("content-type".to_string(), content_type.to_string()),
+        ("host".to_string(), host.clone()),
+        (
+            "x-amz-date".to_string(),
+            now.format("%Y%m%dT%H%M%SZ").to_string(),
+        ),
+    ];
+    if let Some(token) = session_token {
+        headers.push(("x-amz-security-token".to_string(), token.clone()));
+    }
+    let authorization = sign_v4(
+        "POST",
+        "/",
+        &headers,
+        &hex::encode(Sha256::digest(body.as_bytes())),
+        access_key_id,
+        secret_access_key,
+        region,
+        "sts",
+        now,
+    );
+
+    let mut request = reqwest::Client::new()
+        .post(format!("https://{}/", host))
+        .header("authorization", authorization)
+        .body(body);
+    for (name, value) in headers.iter().filter(|(name, _)| name != "host") {
+        request = request.header(name, value);
+    }
+    let response = match request.send().await {
+        Ok(response) => response,
+        Err(e) => return TargetCheck::disconnected(format!("AWS unreachable: {}", e)),
+    };
+    let status = response.status();
+    let text = response.text().await.unwrap_or_default();
+    if status.is_success() {
+        match xml_element(&text, "Arn") {
+            Some(arn) => TargetCheck::connected(format!("authenticated as {}", arn)),
+            None => TargetCheck::connected("authenticated"),
+        }
+    } else {
+        TargetCheck::disconnected(format!(
+            "AWS refused the credentials: {}",
+            xml_element(&text, "Message").unwrap_or_else(|| status.to_string())
+        ))
+    }
+}
+
+/// Text of the first `<name>` element of an XML response.
+fn xml_element(xml: &str, name: &str) -> Option<String> {
+    let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", name))?;
+    Some(xml[start..end].trim().to_string())
+}
+
+async fn check_docker(config: &serde_json::Value) -> TargetCheck {
+    use bollard::{API_DEFAULT_VERSION, Docker};
+
+    let host = config
+        .get("host")
+        .and_then(|h| h.as_str())
+        .filter(|h| !h.is_empty());
+    let timeout = CHECK_TIMEOUT.as_secs();
+    let docker = match host {
+        None => Docker::connect_with_local_defaults(),
+        Some(host) => match host.strip_prefix("unix://") {
+            Some(socket) => Docker::connect_with_unix(socket, timeout, API_DEFAULT_VERSION),
+            None => Docker::connect_with_http(
+                &host.replacen("tcp://", "http://", 1),
+                timeout,
+                API_DEFAULT_VERSION,
+            ),
+        },
+    };
+    let docker = match docker {
+        Ok(docker) => docker,
+        Err(e) => return TargetCheck::disconnected(e.to_string()),
+    };
+    match docker.version().await {
+        Ok(version) => {
+            TargetCheck::connected(format!("Docker {}", version.version.unwrap_or_default()))
+        }
+        Err(e) => TargetCheck::disconnected(format!("Docker daemon unreachable: {}", e)),
+    }
+}
+
+/// Target monitor settings.
+#[derive(Debug, Clone)]
+pub struct TargetMonitorConfig {
+    /// How often targets are checked.
+    pub interval: Duration,
+    /// Targets checked per round, least recently checked first.
+    pub batch_size: i64,
+}
+
+impl Default for TargetMonitorConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(300),
+            batch_size: 100,
+        }
+    }
+}
+
+impl TargetMonitorConfig {
+    /// Load settings from `BUILDIT_TARGET_CHECK_*` environment variables.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            interval: std::env::var("BUILDIT_TARGET_CHECK_INTERVAL_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.interval),
+            batch_size: std::env::var("BUILDIT_TARGET_CHECK_BATCH")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(defaults.batch_size),
+        }
+    }
+}
+
+/// Background task that keeps the targets' connection status current.
+pub struct TargetMonitor {
+    config: TargetMonitorConfig,
+    deployment_repo: Arc<PgDeploymentRepo>,
+    cipher: Option<Arc<CredentialCipher>>,
+    event_bus: Arc<dyn EventBus>,
+}
+
+impl TargetMonitor {
+    pub fn new(
+        config: TargetMonitorConfig,
+        deployment_repo: Arc<PgDeploymentRepo>,
+        cipher: Option<Arc<CredentialCipher>>,
+        event_bus: Arc<dyn EventBus>,
+    ) -> Self {
+        Self {
+            config,
+            deployment_repo,
+            cipher,
+            event_bus,
+        }
+    }
+
+    /// Run the monitor loop forever.
+    pub async fn run(self) {
+        info!(
+            interval_secs = self.config.interval.as_secs(),
+            "Target monitor started"
+        );
+
+        let mut ticker = tokio::time::interval(self.config.interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.check().await {
+                error!(error = %e, "Target check round failed");
+            }
+        }
+    }
+
+    /// Check the least recently checked targets once, returning how many
+    /// are not connected.
+    pub async fn check(&self) -> buildit_db::DbResult<usize> {
+        let targets = self
+            .deployment_repo
+            .list_targets_to_check(self.config.batch_size)
+            .await?;
+        let mut failing = 0;
+        for target in targets {
+            let (id, name) = (target.id, target.name.clone());
+            let target = refresh(
+                &self.deployment_repo,
+                self.cipher.as_deref(),
+                self.event_bus.as_ref(),
+                target,
+            )
+            .await?;
+            if target.status != TargetStatus::Connected.as_str() {
+                failing += 1;
+                warn!(
+                    target_id = %id,
+                    target = %name,
+                    status = %target.status,
+                    message = target.status_message.as_deref().unwrap_or_default(),
+                    "Deployment target not connected"
+                );
+            }
+        }
+        Ok(failing)
+    }
+}
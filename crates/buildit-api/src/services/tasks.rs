@@ -0,0 +1,576 @@
+//! Background tasks of the API server.
+//!
+//! Request handlers never run long work themselves: they enqueue a [`Task`]
+//! on the job queue and return. A task worker in any server process claims
+//! it, so a restart in the middle of a pipeline run or a terraform apply
+//! delays the work instead of losing it. Payloads only carry IDs; handlers
+//! reload everything else and skip what is already done, which makes
+//! retries and duplicate deliveries safe.
+
+use async_trait::async_trait;
+use buildit_core::ResourceId;
+use buildit_core::application::ApplicationSyncStatus;
+use buildit_core::deployer::{
+    DeploymentResources, DeploymentSource, DeploymentSpec, DeploymentStrategy, HealthCheck,
+};
+use buildit_core::repository::PushEvent;
+use buildit_db::repo::pipeline::PipelineRunRecord;
+use buildit_db::{
+    ApplicationRepo, DbError, DeploymentRepo, NotificationRepo, PipelineRepo, RepositoryRepo,
+};
+use buildit_scheduler::{QueuedTask, TaskContext, TaskHandler};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::error::ApiError;
+use crate::services::freeze;
+use crate::services::manifest_deploy::{self, ManifestDeployService};
+use crate::services::notifications::{self, Notification};
+use crate::services::protection;
+use crate::services::release_notes::ReleaseNotesService;
+use crate::services::{
+    debug_sessions, metrics, pipeline_runner, stack_tasks, telemetry, test_reports,
+    webhook_subscriptions,
+};
+use crate::ws::BroadcastEvent;
+
+/// Deployment statuses that need no further rollout work.
+const DEPLOYMENT_FINISHED: &[&str] = &[
+    "succeeded",
+    "failed",
+    "cancelled",
+    "awaiting_promotion",
+    "rolled_back",
+];
+
+/// Attempts at a task before it fails, unless the task sets its own.
+const DEFAULT_MAX_ATTEMPTS: i32 = 3;
+
+/// Work the API server does in the background.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Task {
+    /// Execute a pipeline run.
+    PipelineRun { run_id: Uuid },
+    /// Start a debug session's job and cancel it when the session ends.
+    DebugSession { session_id: Uuid },
+    /// Clone a stack's repository and run `terraform init`.
+    StackInit { stack_id: Uuid },
+    /// Plan, apply, destroy or refresh a stack run.
+    StackRun { run_id: Uuid },
+    /// Apply the plan of an approved stack run.
+    StackApply { run_id: Uuid },
+    /// Roll out a deployment.
+    Deployment { deployment_id: Uuid },
+    /// Generate and publish release notes for a production deployment.
+    ReleaseNotes { deployment_id: Uuid },
+    /// Render and apply an application sync.
+    ApplicationSync { sync_id: Uuid },
+    /// Deploy services whose manifests changed in a push.
+    ManifestPush {
+        repository_id: Uuid,
+        push: Box<PushEvent>,
+    },
+    /// Deliver a notification to one of its tenant's channels.
+    Notification {
+        channel_id: Uuid,
+        notification: Box<Notification>,
+    },
+    /// Post an event to a webhook subscription.
+    WebhookDelivery { tenant_id: Uuid, delivery_id: Uuid },
+    /// Comment a finished run's test summary on its commit's pull requests.
+    TestSummaryComment { run_id: Uuid },
+}
+
+impl Task {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Task::PipelineRun { .. } => "pipeline_run",
+            Task::DebugSession { .. } => "debug_session",
+            Task::StackInit { .. } => "stack_init",
+            Task::StackRun { .. } => "stack_run",
+            Task::StackApply { .. } => "stack_apply",
+            Task::Deployment { .. } => "deployment",
+            Task::ReleaseNotes { .. } => "release_notes",
+            Task::ApplicationSync { .. } => "application_sync",
+            Task::ManifestPush { .. } => "manifest_push",
+            Task::Notification { .. } => "notification",
+            Task::WebhookDelivery { .. } => "webhook_delivery",
+            Task::TestSummaryComment { .. } => "test_summary_comment",
+        }
+    }
+
+    /// How many times the task is tried before it fails.
+    pub fn max_attempts(&self) -> i32 {
+        match self {
+            Task::WebhookDelivery { .. } => webhook_subscriptions::MAX_ATTEMPTS,
+            _ => DEFAULT_MAX_ATTEMPTS,
+        }
+    }
+
+    /// Key that identifies the work, so enqueueing it twice runs it once.
+    pub fn idempotency_key(&self) -> String {
+        let subject = match self {
+            Task::PipelineRun { run_id }
+            | Task::StackRun { run_id }
+            | Task::StackApply { run_id }
+            | Task::TestSummaryComment { run_id } => run_id.to_string(),
+            Task::DebugSession { session_id } => session_id.to_string(),
+            Task::StackInit { stack_id } => stack_id.to_string(),
+            Task::Deployment { deployment_id } | Task::ReleaseNotes { deployment_id } => {
+                deployment_id.to_string()
+            }
+            Task::ApplicationSync { sync_id } => sync_id.to_string(),
+            Task::ManifestPush {
+                repository_id,
+                push,
+            } => format!("{}:{}", repository_id, push.after),
+            Task::Notification {
+                channel_id,
+                notification,
+            } => format!(
+                "{}:{}:{}",
+                channel_id, notification.event, notification.subject_id
+            ),
+            Task::WebhookDelivery { delivery_id, .. } => delivery_id.to_string(),
+        };
+        format!("{}:{}", self.kind(), subject)
+    }
+}
+
+/// Queue a task for any worker to pick up. Tasks are operator actions and
+/// get the priority of a manually triggered run.
+pub async fn enqueue(state: &AppState, task: Task) -> Result<QueuedTask, ApiError> {
+    let priority = state.job_queue.config().priority("manual");
+    enqueue_for(state, task, priority, None).await
+}
+
+/// Queue a pipeline run with the priority of what triggered it, on behalf of
+/// the pipeline's tenant so it gets a fair share of the workers.
+pub async fn enqueue_run(
+    state: &AppState,
+    run: &PipelineRunRecord,
+) -> Result<QueuedTask, ApiError> {
+    let pipeline = state
+        .pipeline_repo
+        .get_by_id(ResourceId::from_uuid(run.pipeline_id))
+        .await?;
+    let trigger = run
+        .trigger_info
+        .get("kind")
+        .and_then(|k| k.as_str())
+        .unwrap_or("manual");
+    let priority = state.job_queue.config().priority(trigger);
+    let queued = enqueue_for(
+        state,
+        Task::PipelineRun { run_id: run.id },
+        priority,
+        Some(ResourceId::from_uuid(pipeline.tenant_id)),
+    )
+    .await?;
+    metrics::record_run("pending");
+    Ok(queued)
+}
+
+/// Check the tenant's runs held for quota again now, e.g. after its quota
+/// was raised.
+pub async fn release_held_runs(state: &AppState, tenant_id: ResourceId) -> Result<u64, ApiError> {
+    state
+        .job_queue
+        .run_tenant_tasks_now(tenant_id, "pipeline_run")
+        .await
+        .map_err(|e| ApiError::Internal(format!("Failed to release held runs: {}", e)))
+}
+
+async fn enqueue_for(
+    state: &AppState,
+    task: Task,
+    priority: i32,
+    tenant_id: Option<ResourceId>,
+) -> Result<QueuedTask, ApiError> {
+    let mut payload = serde_json::to_value(&task).map_err(|e| ApiError::Internal(e.to_string()))?;
+    telemetry::inject(&mut payload);
+    let queued = state
+        .job_queue
+        .enqueue_task(
+            task.kind(),
+            payload,
+            &task.idempotency_key(),
+            priority,
+            tenant_id,
+            task.max_attempts(),
+        )
+        .await
+        .map_err(|e| ApiError::Internal(format!("Failed to queue {}: {}", task.kind(), e)))?;
+    tracing::info!(task_id = %queued.id, kind = %queued.kind, "Queued task");
+    Ok(queued)
+}
+
+/// Runs the API server's tasks in a task worker.
+pub struct AppTaskHandler {
+    state: AppState,
+}
+
+impl AppTaskHandler {
+    pub fn new(state: AppState) -> Self {
+        Self { state }
+    }
+
+    /// Run a task, in the span [`TaskHandler::handle`] opened for it.
+    async fn run(&self, task: TaskContext) -> Result<(), String> {
+        let state = &self.state;
+        match task.payload::<Task>()? {
+            Task::PipelineRun { run_id } => {
+                pipeline_runner::execute_run(state, ResourceId::from_uuid(run_id), &task).await
+            }
+            Task::DebugSession { session_id } => {
+                debug_sessions::run(state, ResourceId::from_uuid(session_id), &task).await
+            }
+            Task::StackInit { stack_id } => {
+                stack_tasks::init(state, ResourceId::from_uuid(stack_id)).await
+            }
+            Task::StackRun { run_id } => {
+                stack_tasks::run(state, ResourceId::from_uuid(run_id), &task).await
+            }
+            Task::StackApply { run_id } => {
+                stack_tasks::apply_approved(state, ResourceId::from_uuid(run_id)).await
+            }
+            Task::Deployment { deployment_id } => {
+                roll_out(state, ResourceId::from_uuid(deployment_id), &task).await
+            }
+            Task::ReleaseNotes { deployment_id } => {
+                let deployment = state
+                    .deployment_repo
+                    .get_deployment(ResourceId::from_uuid(deployment_id))
+                    .await
+                    .map_err(|e| e.to_string())?;
+                ReleaseNotesService::new(state.deployment_repo.clone(), state.pipeline_repo.clone())
+                    .generate_and_publish(&deployment, true)
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| format!("Failed to generate release notes: {}", e))
+            }
+            Task::ApplicationSync { sync_id } => sync_application(state, sync_id).await,
+            Task::ManifestPush {
+                repository_id,
+                push,
+            } => {
+                let repo = state
+                    .repository_repo
+                    .get_by_id(ResourceId::from_uuid(repository_id))
+                    .await
+                    .map_err(|e| e.to_string())?;
+                let retry_at = ManifestDeployService::new(
+                    state.deployment_repo.clone(),
+                    state.clusters.clone(),
+                )
+                .handle_push(&repo, &push)
+                .await;
+                match retry_at {
+                    Some(at) => task.defer_until(at).await,
+                    None => Ok(()),
+                }
+            }
+            Task::Notification {
+                channel_id,
+                notification,
+            } => {
+                let channel = match state
+                    .notification_repo
+                    .get_channel(
+                        ResourceId::from_uuid(notification.tenant_id),
+                        ResourceId::from_uuid(channel_id),
+                    )
+                    .await
+                {
+                    Ok(channel) => channel,
+                    // Deleted since
+                    Err(DbError::NotFound(_)) => return Ok(()),
+                    Err(e) => return Err(e.to_string()),
+                };
+                if !channel.enabled {
+                    return Ok(());
+                }
+                notifications::deliver(state, &channel, &notification).await
+            }
+            Task::WebhookDelivery {
+                tenant_id,
+                delivery_id,
+            } => {
+                let last_attempt = task.task.attempts >= task.task.max_attempts;
+                webhook_subscriptions::deliver(
+                    state,
+                    ResourceId::from_uuid(tenant_id),
+                    ResourceId::from_uuid(delivery_id),
+                    last_attempt,
+                )
+                .await
+            }
+            Task::TestSummaryComment { run_id } => {
+                test_reports::comment_summary(state, ResourceId::from_uuid(run_id)).await
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl TaskHandler for AppTaskHandler {
+    /// Pipeline runs and debug sessions need an executor and rollouts a deployer; without them
+    /// those tasks are left for other servers.
+    fn kinds(&self) -> Vec<String> {
+        let mut kinds = vec![
+            "stack_init",
+            "stack_run",
+            "stack_apply",
+            "release_notes",
+            "application_sync",
+            "notification",
+            "webhook_delivery",
+            "test_summary_comment",
+        ];
+        if self.state.orchestrator.is_some() {
+            kinds.extend(["pipeline_run", "debug_session"]);
+        }
+        if self.state.clusters.available() {
+            kinds.extend(["deployment", "manifest_push"]);
+        }
+        kinds.into_iter().map(str::to_string).collect()
+    }
+
+    async fn handle(&self, task: TaskContext) -> Result<(), String> {
+        let span = tracing::info_span!(
+            "task",
+            kind = %task.task.kind,
+            task_id = %task.task.id,
+            attempt = task.task.attempts,
+        );
+        telemetry::set_parent(&span, &task.task.payload);
+        self.run(task).instrument(span).await
+    }
+}
+
+/// Fields of a service spec the deployer understands.
+#[derive(Debug, Deserialize)]
+pub(crate) struct ServiceSpecDocument {
+    #[serde(default)]
+    image: String,
+    #[serde(default = "default_replicas")]
+    replicas: u32,
+    #[serde(default)]
+    env: HashMap<String, String>,
+    #[serde(default)]
+    strategy: DeploymentStrategy,
+    #[serde(default)]
+    resources: DeploymentResources,
+    health_check: Option<HealthCheck>,
+    #[serde(default)]
+    source: DeploymentSource,
+}
+
+fn default_replicas() -> u32 {
+    1
+}
+
+impl ServiceSpecDocument {
+    /// The spec to hand the deployer for a deployment of `service` to
+    /// `environment`.
+    pub(crate) fn into_spec(
+        self,
+        id: ResourceId,
+        service: String,
+        environment: String,
+    ) -> DeploymentSpec {
+        DeploymentSpec {
+            id,
+            service,
+            environment,
+            image: self.image,
+            replicas: self.replicas,
+            env: self.env,
+            strategy: self.strategy,
+            resources: self.resources,
+            health_check: self.health_check,
+            source: self.source,
+        }
+    }
+}
+
+/// Roll out a recorded deployment to the cluster of its environment.
+///
+/// While its environment is frozen the deployment is held as `frozen` and
+/// the task deferred until the freeze ends, or cancelled if a window
+/// rejects deployments, unless the deployment overrides the freeze.
+async fn roll_out(state: &AppState, id: ResourceId, task: &TaskContext) -> Result<(), String> {
+    let repo = &state.deployment_repo;
+    let deployment = repo.get_deployment(id).await.map_err(|e| e.to_string())?;
+    if DEPLOYMENT_FINISHED.contains(&deployment.status.as_str()) {
+        tracing::info!(deployment = %id, status = %deployment.status, "Deployment already finished");
+        return Ok(());
+    }
+    let service = repo
+        .get_service(ResourceId::from_uuid(deployment.service_id))
+        .await
+        .map_err(|e| e.to_string())?;
+    let environment = repo
+        .get_environment(ResourceId::from_uuid(deployment.environment_id))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if deployment.freeze_override.is_none() {
+        let freezes = freeze::active(repo, &environment, Utc::now())
+            .await
+            .map_err(|e| e.to_string())?;
+        if let Some(until) = freeze::frozen_until(&freezes) {
+            let description = freeze::describe(&environment, &freezes);
+            if freezes.iter().any(|f| f.policy == freeze::POLICY_REJECT) {
+                tracing::warn!(deployment = %id, "{}; cancelling deployment", description);
+                return finish_deployment(state, id, "cancelled").await;
+            }
+            tracing::info!(deployment = %id, "{}; holding deployment", description);
+            if deployment.status != "frozen" {
+                finish_deployment(state, id, "frozen").await?;
+            }
+            return task.defer_until(until).await;
+        }
+    }
+
+    if let Some(protection) = repo
+        .get_environment_protection(ResourceId::from_uuid(environment.id))
+        .await
+        .map_err(|e| e.to_string())?
+    {
+        let reviews = repo
+            .list_deployment_reviews(id)
+            .await
+            .map_err(|e| e.to_string())?;
+        if let Some(until) = protection::wait_until(&protection, &deployment, &reviews) {
+            tracing::info!(deployment = %id, %until, "Waiting before rolling out to {}", environment.name);
+            if deployment.status != "waiting" {
+                finish_deployment(state, id, "waiting").await?;
+            }
+            return task.defer_until(until).await;
+        }
+    }
+
+    let doc: ServiceSpecDocument = match serde_json::from_value(deployment.config.clone()) {
+        Ok(doc) => doc,
+        Err(e) => {
+            tracing::error!(deployment = %id, error = %e, "Invalid service spec");
+            return finish_deployment(state, id, "failed").await;
+        }
+    };
+    let awaits_promotion = matches!(
+        doc.strategy,
+        DeploymentStrategy::BlueGreen {
+            auto_promote: false,
+            ..
+        }
+    );
+    let deployer = match state.clusters.for_environment(&environment).await {
+        Ok(deployer) => deployer,
+        Err(e) => {
+            tracing::error!(deployment = %id, error = %e, "No deployer for environment");
+            return finish_deployment(state, id, "failed").await;
+        }
+    };
+    let mut spec = doc.into_spec(id, service.name, environment.name);
+    if let Err(e) = manifest_deploy::check_out_spec_source(
+        state,
+        &deployment.config,
+        &mut spec.source,
+        deployment.branch,
+    )
+    .await
+    {
+        tracing::error!(deployment = %id, error = %e, "Failed to check out deployment source");
+        return finish_deployment(state, id, "failed").await;
+    }
+
+    if let Err(e) = repo.update_deployment_status(id, "running").await {
+        tracing::error!(deployment = %id, error = %e, "Failed to mark deployment running");
+    }
+    let status = match deployer.deploy(spec).await {
+        Ok(_) if awaits_promotion => "awaiting_promotion",
+        Ok(_) => "succeeded",
+        Err(e) => {
+            tracing::error!(deployment = %id, error = %e, "Deployment failed");
+            "failed"
+        }
+    };
+    finish_deployment(state, id, status).await
+}
+
+/// Record how a rollout ended and tell listeners.
+async fn finish_deployment(state: &AppState, id: ResourceId, status: &str) -> Result<(), String> {
+    state
+        .deployment_repo
+        .update_deployment_status(id, status)
+        .await
+        .map_err(|e| format!("Failed to record deployment status: {}", e))?;
+    state.event_bus.publish(BroadcastEvent::DeploymentUpdate {
+        deployment_id: id.to_string(),
+        status: status.to_string(),
+    });
+    Ok(())
+}
+
+/// Render and apply a sync that has not finished yet.
+async fn sync_application(state: &AppState, sync_id: Uuid) -> Result<(), String> {
+    let sync = state
+        .application_repo
+        .get_sync(ResourceId::from_uuid(sync_id))
+        .await
+        .map_err(|e| e.to_string())?;
+    if matches!(
+        sync.status,
+        ApplicationSyncStatus::Succeeded | ApplicationSyncStatus::Failed
+    ) {
+        return Ok(());
+    }
+    let app = state
+        .application_repo
+        .get_application(ResourceId::from_uuid(sync.application_id))
+        .await
+        .map_err(|e| e.to_string())?;
+    state.application_sync().run(&app, &sync).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enqueueing_the_same_work_twice_shares_a_key() {
+        let run_id = Uuid::new_v4();
+        let run = Task::PipelineRun { run_id };
+        assert_eq!(run.idempotency_key(), format!("pipeline_run:{}", run_id));
+        assert_eq!(
+            run.idempotency_key(),
+            Task::PipelineRun { run_id }.idempotency_key()
+        );
+        // Different work on the same subject runs separately
+        assert_ne!(
+            Task::StackRun { run_id }.idempotency_key(),
+            Task::StackApply { run_id }.idempotency_key()
+        );
+    }
+
+    #[test]
+    fn test_task_payload_names_its_kind() {
+        let task = Task::Deployment {
+            deployment_id: Uuid::new_v4(),
+        };
+        let payload = serde_json::to_value(&task).unwrap();
+        assert_eq!(payload["kind"], task.kind());
+        assert!(matches!(
+            serde_json::from_value(payload).unwrap(),
+            Task::Deployment { .. }
+        ));
+        assert_eq!(task.max_attempts(), DEFAULT_MAX_ATTEMPTS);
+    }
+}
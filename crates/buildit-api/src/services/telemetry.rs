@@ -0,0 +1,162 @@
+//! Logging and OpenTelemetry tracing.
+//!
+//! Spans are exported over OTLP/HTTP when `OTEL_EXPORTER_OTLP_ENDPOINT` (or
+//! `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`) is set, under the service name in
+//! `OTEL_SERVICE_NAME` (default `buildit`). A run's trace follows it from the
+//! webhook or request that created it through the task queue to each stage's
+//! job: the trace context is carried in the payload of the tasks queued
+//! meanwhile ([`inject`]) and picked up by the worker running them
+//! ([`set_parent`]).
+
+use std::collections::HashMap;
+
+use opentelemetry::KeyValue;
+use opentelemetry::global;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{Resource, runtime};
+use serde_json::Value;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// Payload key of the trace context of a queued task.
+const TRACE_CONTEXT_KEY: &str = "trace_context";
+
+/// Exports spans until it is shut down.
+pub struct Telemetry {
+    provider: Option<TracerProvider>,
+}
+
+impl Telemetry {
+    /// Flush the spans not exported yet.
+    pub fn shutdown(self) {
+        if let Some(provider) = self.provider {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Failed to shut down trace export: {}", e);
+            }
+        }
+    }
+}
+
+/// Install the global subscriber: logs to stdout, filtered by `RUST_LOG`
+/// (default `info`), and spans to the OTLP endpoint if one is configured.
+pub fn init() -> Telemetry {
+    let provider = match otlp_endpoint_configured().then(tracer_provider) {
+        Some(Ok(provider)) => Some(provider),
+        Some(Err(e)) => {
+            eprintln!("Failed to set up trace export: {}", e);
+            None
+        }
+        None => None,
+    };
+    let otel = provider.as_ref().map(|provider| {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        tracing_opentelemetry::layer().with_tracer(provider.tracer("buildit"))
+    });
+
+    tracing_subscriber::registry()
+        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .with(tracing_subscriber::fmt::layer())
+        .with(otel)
+        .init();
+    if provider.is_some() {
+        tracing::info!("Exporting traces over OTLP");
+    }
+    Telemetry { provider }
+}
+
+fn otlp_endpoint_configured() -> bool {
+    [
+        "OTEL_EXPORTER_OTLP_ENDPOINT",
+        "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+    ]
+    .iter()
+    .any(|var| std::env::var(var).is_ok_and(|v| !v.is_empty()))
+}
+
+fn tracer_provider() -> Result<TracerProvider, opentelemetry::trace::TraceError> {
+    let exporter = SpanExporter::builder().with_http().build()?;
+    let service_name = std::env::var("OTEL_SERVICE_NAME")
+        .ok()
+        .filter(|n| !n.is_empty())
+        .unwrap_or_else(|| "buildit".to_string());
+    Ok(TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new("service.name", service_name)]))
+        .build())
+}
+
+/// Add the current span's trace context to a task payload, so the span of
+/// the task continues its trace. Does nothing unless traces are exported.
+pub fn inject(payload: &mut Value) {
+    let mut carrier = HashMap::new();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&Span::current().context(), &mut carrier)
+    });
+    if carrier.is_empty() {
+        return;
+    }
+    if let Value::Object(payload) = payload {
+        payload.insert(
+            TRACE_CONTEXT_KEY.to_string(),
+            serde_json::to_value(carrier).unwrap_or_default(),
+        );
+    }
+}
+
+/// Make `span` a child of the span that queued a task, if its payload
+/// carries a trace context.
+pub fn set_parent(span: &Span, payload: &Value) {
+    let Some(carrier) = payload
+        .get(TRACE_CONTEXT_KEY)
+        .and_then(|c| serde_json::from_value::<HashMap<String, String>>(c.clone()).ok())
+    else {
+        return;
+    };
+    let context = global::get_text_map_propagator(|propagator| propagator.extract(&carrier));
+    span.set_parent(context);
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::trace::{TraceContextExt, TraceId};
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_tasks_continue_the_trace_that_queued_them() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let provider = TracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let mut payload = json!({"kind": "pipeline_run"});
+            let request = tracing::info_span!("request");
+            let trace_id = {
+                let _entered = request.enter();
+                inject(&mut payload);
+                Span::current().context().span().span_context().trace_id()
+            };
+            assert_ne!(trace_id, TraceId::INVALID);
+            assert!(payload[TRACE_CONTEXT_KEY]["traceparent"].is_string());
+
+            let task = tracing::info_span!("task");
+            set_parent(&task, &payload);
+            assert_eq!(task.context().span().span_context().trace_id(), trace_id);
+        });
+    }
+
+    #[test]
+    fn test_payloads_are_untouched_without_a_trace() {
+        let mut payload = json!({"kind": "pipeline_run"});
+        inject(&mut payload);
+        assert_eq!(payload, json!({"kind": "pipeline_run"}));
+    }
+}
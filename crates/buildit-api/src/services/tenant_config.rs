@@ -0,0 +1,738 @@
+//! Tenant configuration as code.
+//!
+//! A [`TenantConfig`] holds a tenant's pipelines, deployment targets,
+//! environments, variable groups and notification channels, referring to
+//! each other by name rather than ID so the document can be kept in git and
+//! imported into another installation. Secrets are left out: target
+//! credentials and webhook signing secrets stay with the installation that
+//! has them.
+//!
+//! Importing creates what is missing and updates what differs, matching by
+//! name; nothing is deleted. The whole document is checked before the first
+//! change is made.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::error::ApiError;
+use crate::routes::deployment::target_config;
+use crate::routes::notifications::validate_events;
+use crate::routes::pipelines::{
+    StageDefinitions, declared_params, policy_error, policy_violations,
+};
+use crate::routes::variable_groups::validate_variables;
+use crate::services::notifications;
+use buildit_core::ResourceId;
+use buildit_core::pipeline::Ownership;
+use buildit_core::target::TargetKind;
+use buildit_db::{
+    DeploymentRepo, NotificationChannel, NotificationRepo, PipelineRepo, Tenant, VariableGroup,
+    VariableGroupRepo,
+};
+
+/// Version of the document format written by [`export`].
+pub const VERSION: u32 = 1;
+
+/// A tenant's configuration.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TenantConfig {
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pipelines: Vec<PipelineConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<TargetConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub environments: Vec<EnvironmentConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variable_groups: Vec<VariableGroupConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notification_channels: Vec<ChannelConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PipelineConfig {
+    pub name: String,
+    pub repository: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+    #[serde(default, skip_serializing_if = "Ownership::is_empty")]
+    pub owners: Ownership,
+    /// The parsed pipeline definition, as stored.
+    pub config: serde_json::Value,
+}
+
+/// A deployment target, without its credentials.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TargetConfig {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub target_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(default)]
+    pub config: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct EnvironmentConfig {
+    pub name: String,
+    /// Name of the environment's target.
+    pub target: String,
+    #[serde(default)]
+    pub config: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct VariableGroupConfig {
+    pub name: String,
+    /// Name of the pipeline the group applies to; all of the tenant's when
+    /// unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pipeline: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub variables: BTreeMap<String, String>,
+}
+
+/// A notification channel, without a webhook channel's signing secret.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ChannelConfig {
+    pub name: String,
+    pub kind: String,
+    pub config: serde_json::Value,
+    #[serde(default)]
+    pub events: Vec<String>,
+    #[serde(default = "enabled_default")]
+    pub enabled: bool,
+}
+
+fn enabled_default() -> bool {
+    true
+}
+
+/// What an import changed, or with `dry_run` would change. Entries read
+/// like `pipeline build` or `target prod-cluster`.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct ImportSummary {
+    pub dry_run: bool,
+    pub created: Vec<String>,
+    pub updated: Vec<String>,
+    pub unchanged: Vec<String>,
+    /// Things left to do by hand, such as setting a new target's
+    /// credentials.
+    pub warnings: Vec<String>,
+}
+
+/// The tenant's configuration. Archived pipelines and their variable
+/// groups are left out.
+pub async fn export(state: &AppState, tenant: &Tenant) -> Result<TenantConfig, ApiError> {
+    let tenant_id = ResourceId::from_uuid(tenant.id);
+
+    let pipelines = state.pipeline_repo.list_by_tenant(tenant_id).await?;
+    let pipeline_names: HashMap<Uuid, String> =
+        pipelines.iter().map(|p| (p.id, p.name.clone())).collect();
+
+    let variable_groups = state
+        .variable_group_repo
+        .list_all_groups(tenant_id)
+        .await?
+        .into_iter()
+        .filter_map(|group| {
+            let pipeline = match group.pipeline_id {
+                Some(id) => Some(pipeline_names.get(&id)?.clone()),
+                None => None,
+            };
+            Some(VariableGroupConfig {
+                variables: group.variables().into_iter().collect(),
+                name: group.name,
+                pipeline,
+                description: group.description,
+            })
+        })
+        .collect();
+
+    Ok(TenantConfig {
+        version: VERSION,
+        pipelines: pipelines
+            .into_iter()
+            .map(|p| PipelineConfig {
+                owners: p.ownership(),
+                name: p.name,
+                repository: p.repository,
+                labels: p.labels,
+                config: p.config,
+            })
+            .collect(),
+        targets: state
+            .deployment_repo
+            .list_targets(tenant_id)
+            .await?
+            .into_iter()
+            .map(|t| TargetConfig {
+                name: t.name,
+                target_type: t.target_type,
+                region: t.region,
+                config: t.config,
+            })
+            .collect(),
+        environments: state
+            .deployment_repo
+            .list_environments(tenant_id)
+            .await?
+            .into_iter()
+            .map(|e| EnvironmentConfig {
+                name: e.name,
+                target: e.target_name,
+                config: e.config,
+            })
+            .collect(),
+        variable_groups,
+        notification_channels: state
+            .notification_repo
+            .list_channels(tenant_id)
+            .await?
+            .into_iter()
+            .map(|c| ChannelConfig {
+                name: c.name,
+                kind: c.kind,
+                config: c.config,
+                events: c.events,
+                enabled: c.enabled,
+            })
+            .collect(),
+    })
+}
+
+/// Bring the tenant in line with `config`. With `dry_run` nothing is
+/// changed and the summary tells what would be.
+pub async fn import(
+    state: &AppState,
+    tenant: &Tenant,
+    user_id: Option<Uuid>,
+    config: TenantConfig,
+    dry_run: bool,
+) -> Result<ImportSummary, ApiError> {
+    let tenant_id = ResourceId::from_uuid(tenant.id);
+    let mut summary = ImportSummary {
+        dry_run,
+        ..Default::default()
+    };
+
+    let targets = state.deployment_repo.list_targets(tenant_id).await?;
+    let environments = state.deployment_repo.list_environments(tenant_id).await?;
+    let mut pipelines = state.pipeline_repo.list_by_tenant(tenant_id).await?;
+    pipelines.extend(state.pipeline_repo.list_archived(tenant_id).await?);
+    let groups = state.variable_group_repo.list_all_groups(tenant_id).await?;
+    let channels = state.notification_repo.list_channels(tenant_id).await?;
+
+    validate(&config, &targets, &pipelines, &channels)?;
+    for pipeline in &config.pipelines {
+        let stages = StageDefinitions::from_config(&pipeline.config)?.stages();
+        let violations = policy_violations(
+            &state.organization_repo,
+            &state.tenant_repo,
+            tenant_id,
+            &stages,
+        )
+        .await?;
+        if !violations.is_empty() {
+            return Err(ApiError::Forbidden(format!(
+                "Pipeline {}: {}",
+                pipeline.name,
+                policy_error(&violations)
+            )));
+        }
+    }
+
+    // Targets come first: environments refer to them
+    let mut target_ids: HashMap<String, Uuid> =
+        targets.iter().map(|t| (t.name.clone(), t.id)).collect();
+    for target in &config.targets {
+        let entry = format!("target {}", target.name);
+        let settings = target_config(target.config.clone())?;
+        match targets.iter().find(|t| t.name == target.name) {
+            Some(existing) if existing.region == target.region && existing.config == settings => {
+                summary.unchanged.push(entry);
+            }
+            Some(existing) => {
+                if !dry_run {
+                    // Stored credentials are kept
+                    state
+                        .deployment_repo
+                        .update_target(
+                            ResourceId::from_uuid(existing.id),
+                            &existing.name,
+                            target.region.as_deref(),
+                            settings,
+                            None,
+                        )
+                        .await?;
+                }
+                summary.updated.push(entry);
+            }
+            None => {
+                let kind: TargetKind = target.target_type.parse().map_err(ApiError::BadRequest)?;
+                if !dry_run {
+                    let created = state
+                        .deployment_repo
+                        .create_target(
+                            tenant_id,
+                            &target.name,
+                            kind.as_str(),
+                            target.region.as_deref(),
+                            settings,
+                            None,
+                        )
+                        .await?;
+                    target_ids.insert(created.name, created.id);
+                }
+                if matches!(kind, TargetKind::Fly | TargetKind::Aws) {
+                    summary.warnings.push(format!(
+                        "target {} needs credentials before it can be deployed to",
+                        target.name
+                    ));
+                }
+                summary.created.push(entry);
+            }
+        }
+    }
+
+    for environment in &config.environments {
+        let entry = format!("environment {}", environment.name);
+        let settings = object_or_empty(&environment.config);
+        let existing = environments.iter().find(|e| e.name == environment.name);
+        if let Some(existing) = existing
+            && existing.target_name == environment.target
+            && existing.config == settings
+        {
+            summary.unchanged.push(entry);
+            continue;
+        }
+        if !dry_run {
+            let target_id = ResourceId::from_uuid(target_ids[&environment.target]);
+            match existing {
+                Some(existing) => {
+                    state
+                        .deployment_repo
+                        .update_environment(ResourceId::from_uuid(existing.id), target_id, settings)
+                        .await?;
+                }
+                None => {
+                    state
+                        .deployment_repo
+                        .create_environment(tenant_id, target_id, &environment.name, settings)
+                        .await?;
+                }
+            }
+        }
+        match existing {
+            Some(_) => summary.updated.push(entry),
+            None => summary.created.push(entry),
+        }
+    }
+
+    // Pipelines come before variable groups, which may belong to them
+    let policy = tenant.policy();
+    let existing_names: HashMap<Uuid, String> =
+        pipelines.iter().map(|p| (p.id, p.name.clone())).collect();
+    let mut pipeline_ids: HashMap<String, Uuid> =
+        pipelines.iter().map(|p| (p.name.clone(), p.id)).collect();
+    for pipeline in &config.pipelines {
+        let entry = format!("pipeline {}", pipeline.name);
+        // Start from the tenant's policy defaults, as pipelines created through the API do
+        let mut stored = pipeline.config.clone();
+        policy.apply(&mut stored);
+        let existing = pipelines.iter().find(|p| p.name == pipeline.name);
+
+        if let Some(existing) = existing {
+            if let Some(path) = &existing.config_path {
+                summary.warnings.push(format!(
+                    "pipeline {} is synced from {} in its repository and was left as is",
+                    pipeline.name, path
+                ));
+                summary.unchanged.push(entry);
+                continue;
+            }
+            if existing.repository != pipeline.repository {
+                summary.warnings.push(format!(
+                    "pipeline {} builds {}, not {}; its repository was left as is",
+                    pipeline.name, existing.repository, pipeline.repository
+                ));
+            }
+            if existing.config == stored
+                && existing.labels == pipeline.labels
+                && existing.ownership() == pipeline.owners
+            {
+                summary.unchanged.push(entry);
+                continue;
+            }
+        }
+
+        if !dry_run {
+            let stages = StageDefinitions::from_config(&pipeline.config)?;
+            let pipeline_id = match existing {
+                Some(existing) => {
+                    let id = ResourceId::from_uuid(existing.id);
+                    state.pipeline_repo.update_config(id, stored).await?;
+                    state.pipeline_repo.delete_stages(id).await?;
+                    id
+                }
+                None => {
+                    let created = state
+                        .pipeline_repo
+                        .create(tenant_id, &pipeline.name, &pipeline.repository, stored)
+                        .await?;
+                    pipeline_ids.insert(created.name, created.id);
+                    ResourceId::from_uuid(created.id)
+                }
+            };
+            state
+                .pipeline_repo
+                .update_labels(pipeline_id, &pipeline.labels)
+                .await?;
+            state
+                .pipeline_repo
+                .update_owners(pipeline_id, &pipeline.owners)
+                .await?;
+            stages.save(&state.pipeline_repo, pipeline_id).await;
+        }
+        match existing {
+            Some(_) => summary.updated.push(entry),
+            None => summary.created.push(entry),
+        }
+    }
+
+    for group in &config.variable_groups {
+        let entry = match &group.pipeline {
+            Some(pipeline) => format!("variable group {} of pipeline {}", group.name, pipeline),
+            None => format!("variable group {}", group.name),
+        };
+        let description = group.description.clone().filter(|d| !d.trim().is_empty());
+        let variables = serde_json::to_value(&group.variables).unwrap_or_default();
+        let existing = groups.iter().find(|g| {
+            g.name == group.name
+                && g.pipeline_id.and_then(|id| existing_names.get(&id)) == group.pipeline.as_ref()
+        });
+
+        match existing {
+            Some(existing)
+                if existing.description == description
+                    && existing.variables().into_iter().collect::<BTreeMap<_, _>>()
+                        == group.variables =>
+            {
+                summary.unchanged.push(entry);
+            }
+            Some(existing) => {
+                if !dry_run {
+                    let mut updated = existing.clone();
+                    updated.description = description;
+                    updated.variables = variables;
+                    state.variable_group_repo.update_group(&updated).await?;
+                }
+                summary.updated.push(entry);
+            }
+            None => {
+                if !dry_run {
+                    let now = chrono::Utc::now();
+                    state
+                        .variable_group_repo
+                        .create_group(&VariableGroup {
+                            id: Uuid::now_v7(),
+                            tenant_id: tenant.id,
+                            pipeline_id: group.pipeline.as_ref().map(|name| pipeline_ids[name]),
+                            name: group.name.clone(),
+                            description,
+                            variables,
+                            created_by: user_id,
+                            created_at: now,
+                            updated_at: now,
+                        })
+                        .await?;
+                }
+                summary.created.push(entry);
+            }
+        }
+    }
+
+    for channel in &config.notification_channels {
+        let entry = format!("notification channel {}", channel.name);
+        let events = validate_events(channel.events.clone())?;
+        match channels.iter().find(|c| c.name == channel.name) {
+            Some(existing)
+                if existing.config == channel.config
+                    && existing.events == events
+                    && existing.enabled == channel.enabled =>
+            {
+                summary.unchanged.push(entry);
+            }
+            Some(existing) => {
+                if !dry_run {
+                    let mut updated = existing.clone();
+                    updated.config = channel.config.clone();
+                    updated.events = events;
+                    updated.enabled = channel.enabled;
+                    state.notification_repo.update_channel(&updated).await?;
+                }
+                summary.updated.push(entry);
+            }
+            None => {
+                let secret = (channel.kind == "webhook").then(notifications::new_secret);
+                if secret.is_some() {
+                    summary.warnings.push(format!(
+                        "notification channel {} has a new signing secret; rotate it to see the secret",
+                        channel.name
+                    ));
+                }
+                if !dry_run {
+                    state
+                        .notification_repo
+                        .create_channel(&NotificationChannel {
+                            id: Uuid::now_v7(),
+                            tenant_id: tenant.id,
+                            name: channel.name.clone(),
+                            kind: channel.kind.clone(),
+                            config: channel.config.clone(),
+                            secret,
+                            events,
+                            enabled: channel.enabled,
+                            created_by: user_id,
+                            last_delivery_at: None,
+                            last_error: None,
+                            created_at: chrono::Utc::now(),
+                            updated_at: chrono::Utc::now(),
+                        })
+                        .await?;
+                }
+                summary.created.push(entry);
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Check the whole document against itself and what the tenant has, so an
+/// import fails before changing anything rather than halfway.
+fn validate(
+    config: &TenantConfig,
+    targets: &[buildit_db::Target],
+    pipelines: &[buildit_db::repo::pipeline::PipelineRecord],
+    channels: &[NotificationChannel],
+) -> Result<(), ApiError> {
+    if config.version != VERSION {
+        return Err(ApiError::BadRequest(format!(
+            "Unsupported configuration version {}; expected {}",
+            config.version, VERSION
+        )));
+    }
+
+    unique_names("target", config.targets.iter().map(|t| t.name.as_str()))?;
+    for target in &config.targets {
+        let kind: TargetKind = target.target_type.parse().map_err(ApiError::BadRequest)?;
+        if let Some(existing) = targets.iter().find(|t| t.name == target.name)
+            && existing.target_type != kind.as_str()
+        {
+            return Err(ApiError::BadRequest(format!(
+                "Target {} is a {} target, not {}",
+                target.name, existing.target_type, kind
+            )));
+        }
+        target_config(target.config.clone())?;
+    }
+
+    unique_names(
+        "environment",
+        config.environments.iter().map(|e| e.name.as_str()),
+    )?;
+    for environment in &config.environments {
+        let known = config.targets.iter().any(|t| t.name == environment.target)
+            || targets.iter().any(|t| t.name == environment.target);
+        if !known {
+            return Err(ApiError::BadRequest(format!(
+                "Environment {} refers to unknown target {}",
+                environment.name, environment.target
+            )));
+        }
+    }
+
+    unique_names("pipeline", config.pipelines.iter().map(|p| p.name.as_str()))?;
+    for pipeline in &config.pipelines {
+        StageDefinitions::from_config(&pipeline.config).map_err(|e| match e {
+            ApiError::BadRequest(msg) => {
+                ApiError::BadRequest(format!("Pipeline {}: {}", pipeline.name, msg))
+            }
+            e => e,
+        })?;
+        declared_params(&pipeline.config)?;
+    }
+
+    let mut groups = HashSet::new();
+    for group in &config.variable_groups {
+        if group.name.trim().is_empty() {
+            return Err(ApiError::BadRequest(
+                "Variable group name is required".to_string(),
+            ));
+        }
+        if !groups.insert((group.pipeline.as_deref(), group.name.as_str())) {
+            return Err(ApiError::BadRequest(format!(
+                "Two variable groups are named {}",
+                group.name
+            )));
+        }
+        if let Some(pipeline) = &group.pipeline {
+            let known = config.pipelines.iter().any(|p| &p.name == pipeline)
+                || pipelines.iter().any(|p| &p.name == pipeline);
+            if !known {
+                return Err(ApiError::BadRequest(format!(
+                    "Variable group {} refers to unknown pipeline {}",
+                    group.name, pipeline
+                )));
+            }
+        }
+        validate_variables(group.variables.keys())?;
+    }
+
+    unique_names(
+        "notification channel",
+        config.notification_channels.iter().map(|c| c.name.as_str()),
+    )?;
+    for channel in &config.notification_channels {
+        if let Some(existing) = channels.iter().find(|c| c.name == channel.name)
+            && existing.kind != channel.kind
+        {
+            return Err(ApiError::BadRequest(format!(
+                "Notification channel {} is a {} channel, not {}",
+                channel.name, existing.kind, channel.kind
+            )));
+        }
+        notifications::validate_config(&channel.kind, &channel.config)
+            .map_err(ApiError::BadRequest)?;
+        validate_events(channel.events.clone())?;
+    }
+    Ok(())
+}
+
+/// Fail if a section names something twice, or leaves a name empty.
+fn unique_names<'a>(kind: &str, names: impl Iterator<Item = &'a str>) -> Result<(), ApiError> {
+    let mut seen = HashSet::new();
+    for name in names {
+        if name.trim().is_empty() {
+            return Err(ApiError::BadRequest(format!("Every {} needs a name", kind)));
+        }
+        if !seen.insert(name) {
+            return Err(ApiError::BadRequest(format!(
+                "Two {}s are named {}",
+                kind, name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// An environment's settings, an empty object when left out.
+fn object_or_empty(config: &serde_json::Value) -> serde_json::Value {
+    match config {
+        serde_json::Value::Null => serde_json::json!({}),
+        config => config.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(yaml: &str) -> TenantConfig {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    const CONFIG: &str = "
+version: 1
+targets:
+  - name: prod-cluster
+    type: kubernetes
+environments:
+  - name: production
+    target: prod-cluster
+";
+
+    #[test]
+    fn test_imports_are_checked_before_anything_changes() {
+        assert!(validate(&parse(CONFIG), &[], &[], &[]).is_ok());
+
+        let newer = parse(&CONFIG.replace("version: 1", "version: 2"));
+        assert!(matches!(
+            validate(&newer, &[], &[], &[]),
+            Err(ApiError::BadRequest(_))
+        ));
+
+        let unknown_target = parse(&CONFIG.replace("target: prod-cluster", "target: staging"));
+        assert!(matches!(
+            validate(&unknown_target, &[], &[], &[]),
+            Err(ApiError::BadRequest(_))
+        ));
+
+        let unknown_pipeline = parse(&format!(
+            "{}variable_groups:\n  - name: secrets\n    pipeline: missing\n",
+            CONFIG
+        ));
+        assert!(matches!(
+            validate(&unknown_pipeline, &[], &[], &[]),
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_existing_targets_keep_their_type() {
+        let now = chrono::Utc::now();
+        let existing = buildit_db::Target {
+            id: Uuid::new_v4(),
+            tenant_id: Uuid::new_v4(),
+            name: "prod-cluster".to_string(),
+            target_type: "fly".to_string(),
+            status: "connected".to_string(),
+            region: None,
+            config: serde_json::json!({}),
+            credentials: None,
+            status_message: None,
+            checked_at: None,
+            created_at: now,
+            updated_at: now,
+        };
+        assert!(matches!(
+            validate(&parse(CONFIG), std::slice::from_ref(&existing), &[], &[]),
+            Err(ApiError::BadRequest(_))
+        ));
+
+        // An environment may point at a target the tenant already has.
+        let only_environment = parse(
+            "
+version: 1
+environments:
+  - name: production
+    target: prod-cluster
+",
+        );
+        assert!(validate(&only_environment, &[existing], &[], &[]).is_ok());
+    }
+
+    #[test]
+    fn test_names_are_required_and_unique() {
+        assert!(unique_names("target", ["a", "b"].into_iter()).is_ok());
+        assert!(matches!(
+            unique_names("target", ["a", "a"].into_iter()),
+            Err(ApiError::BadRequest(_))
+        ));
+        assert!(matches!(
+            unique_names("target", ["a", " "].into_iter()),
+            Err(ApiError::BadRequest(_))
+        ));
+        assert_eq!(
+            object_or_empty(&serde_json::Value::Null),
+            serde_json::json!({})
+        );
+    }
+}
@@ -1,18 +1,20 @@
 //! Terraform service for running plan/apply operations.
 
-use buildit_core::stack::{PlanSummary, ResourceChange, StackRunStatus};
+use buildit_core::stack::{AttributeChange, PlanSummary, ResourceChange};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::process::Command;
 use tokio::sync::mpsc;
-use tracing::{debug, error, info, warn};
+use tracing::{error, info, warn};
 
 /// Service for Terraform operations.
 pub struct TerraformService {
     /// Path to terraform binary
     terraform_bin: String,
+    /// Extra environment for every terraform invocation
+    env: HashMap<String, String>,
 }
 
 impl Default for TerraformService {
@@ -25,7 +27,39 @@ impl TerraformService {
     pub fn new() -> Self {
         let terraform_bin =
             std::env::var("TERRAFORM_BIN").unwrap_or_else(|_| "terraform".to_string());
-        Self { terraform_bin }
+        Self {
+            terraform_bin,
+            env: HashMap::new(),
+        }
+    }
+
+    /// Point a stack's `backend "http"` block at BuildIt's state backend.
+    ///
+    /// Uses `BUILDIT_STATE_BACKEND_URL` (the API's external base URL) and
+    /// `BUILDIT_STATE_BACKEND_TOKEN` (an API token with `stacks:write`).
+    /// Configurations with another backend ignore these variables.
+    pub fn with_state_backend(mut self, stack_id: uuid::Uuid) -> Self {
+        let Ok(base_url) = std::env::var("BUILDIT_STATE_BACKEND_URL") else {
+            return self;
+        };
+        let address = format!(
+            "{}/api/v1/stacks/{}/state",
+            base_url.trim_end_matches('/'),
+            stack_id
+        );
+        for key in [
+            "TF_HTTP_ADDRESS",
+            "TF_HTTP_LOCK_ADDRESS",
+            "TF_HTTP_UNLOCK_ADDRESS",
+        ] {
+            self.env.insert(key.to_string(), address.clone());
+        }
+        if let Ok(token) = std::env::var("BUILDIT_STATE_BACKEND_TOKEN") {
+            self.env
+                .insert("TF_HTTP_USERNAME".to_string(), "buildit".to_string());
+            self.env.insert("TF_HTTP_PASSWORD".to_string(), token);
+        }
+        self
     }
 
     /// Initialize a Terraform working directory.
@@ -48,6 +82,7 @@ impl TerraformService {
         }
 
         let output = Command::new(&self.terraform_bin)
+            .envs(&self.env)
             .args(&args)
             .current_dir(working_dir)
             .stdout(Stdio::piped())
@@ -99,6 +134,7 @@ impl TerraformService {
         }
 
         let mut child = Command::new(&self.terraform_bin)
+            .envs(&self.env)
             .args(&args)
             .current_dir(working_dir)
             .stdout(Stdio::piped())
@@ -163,8 +199,20 @@ impl TerraformService {
             return Err(TerraformError::PlanFailed(output));
         }
 
-        // Parse plan output for resource counts
-        let summary = self.parse_plan_output(&output);
+        // Get JSON plan for detailed view
+        let plan_json = if plan_file.exists() {
+            self.show_plan_json(&plan_file).await.ok()
+        } else {
+            None
+        };
+
+        // Prefer the structured plan; fall back to scraping the text output
+        let changes = plan_json.as_ref().map(parse_plan_json).unwrap_or_default();
+        let summary = if plan_json.is_some() {
+            PlanSummary::from_changes(&changes)
+        } else {
+            self.parse_plan_output(&output)
+        };
 
         info!(
             has_changes = %has_changes,
@@ -174,18 +222,12 @@ impl TerraformService {
             "Terraform plan completed"
         );
 
-        // Get JSON plan for detailed view
-        let plan_json = if plan_file.exists() {
-            self.show_plan_json(&plan_file).await.ok()
-        } else {
-            None
-        };
-
         Ok(PlanResult {
             has_changes,
             output,
             plan_file: if has_changes { Some(plan_file) } else { None },
             summary,
+            changes,
             plan_json,
         })
     }
@@ -208,6 +250,7 @@ impl TerraformService {
         ];
 
         let mut child = Command::new(&self.terraform_bin)
+            .envs(&self.env)
             .args(&args)
             .current_dir(working_dir)
             .stdout(Stdio::piped())
@@ -293,6 +336,7 @@ impl TerraformService {
         }
 
         let mut child = Command::new(&self.terraform_bin)
+            .envs(&self.env)
             .args(&args)
             .current_dir(working_dir)
             .stdout(Stdio::piped())
@@ -326,6 +370,7 @@ impl TerraformService {
         working_dir: &Path,
     ) -> Result<HashMap<String, serde_json::Value>, TerraformError> {
         let output = Command::new(&self.terraform_bin)
+            .envs(&self.env)
             .args(["output", "-json"])
             .current_dir(working_dir)
             .stdout(Stdio::piped())
@@ -348,6 +393,7 @@ impl TerraformService {
     /// Show plan as JSON.
     async fn show_plan_json(&self, plan_file: &Path) -> Result<serde_json::Value, TerraformError> {
         let output = Command::new(&self.terraform_bin)
+            .envs(&self.env)
             .args(["show", "-json", plan_file.to_str().unwrap()])
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
@@ -434,7 +480,241 @@ impl TerraformService {
             action: action.to_string(),
             before: None,
             after: None,
+            attributes: Vec::new(),
+        })
+    }
+}
+
+/// Placeholder for redacted sensitive values.
+const SENSITIVE: &str = "(sensitive)";
+
+/// Convert `terraform show -json` output into resource-level changes.
+///
+/// No-op and read (data source) changes are dropped. Sensitive values are
+/// redacted in both the resource bodies and the attribute diff, and
+/// attributes only known after apply are flagged rather than shown as null.
+pub fn parse_plan_json(plan_json: &serde_json::Value) -> Vec<ResourceChange> {
+    plan_json
+        .get("resource_changes")
+        .and_then(|c| c.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|rc| {
+            let change = rc.get("change")?;
+            let actions: Vec<&str> = change
+                .get("actions")?
+                .as_array()?
+                .iter()
+                .filter_map(|a| a.as_str())
+                .collect();
+            let action = match actions.as_slice() {
+                ["create"] => "create",
+                ["update"] => "update",
+                ["delete"] => "delete",
+                ["delete", "create"] | ["create", "delete"] => "replace",
+                _ => return None,
+            };
+
+            let before = change.get("before").filter(|v| !v.is_null());
+            let after = change.get("after").filter(|v| !v.is_null());
+            let markers = Markers {
+                before_sensitive: change.get("before_sensitive"),
+                after_sensitive: change.get("after_sensitive"),
+                after_unknown: change.get("after_unknown"),
+            };
+            let mut attributes = Vec::new();
+            diff_attributes("", before, after, &markers, &mut attributes);
+
+            Some(ResourceChange {
+                address: rc.get("address")?.as_str()?.to_string(),
+                resource_type: rc
+                    .get("type")
+                    .and_then(|t| t.as_str())
+                    .unwrap_or_default()
+                    .to_string(),
+                name: rc
+                    .get("name")
+                    .and_then(|n| n.as_str())
+                    .unwrap_or_default()
+                    .to_string(),
+                action: action.to_string(),
+                before: before.map(|b| redact(b, markers.before_sensitive)),
+                after: after.map(|a| redact(a, markers.after_sensitive)),
+                attributes,
+            })
         })
+        .collect()
+}
+
+/// Sensitivity and unknown markers, mirroring the shape of the values.
+struct Markers<'a> {
+    before_sensitive: Option<&'a serde_json::Value>,
+    after_sensitive: Option<&'a serde_json::Value>,
+    after_unknown: Option<&'a serde_json::Value>,
+}
+
+impl<'a> Markers<'a> {
+    fn child(&self, step: Step<'_>) -> Markers<'a> {
+        Markers {
+            before_sensitive: step.of(self.before_sensitive),
+            after_sensitive: step.of(self.after_sensitive),
+            after_unknown: step.of(self.after_unknown),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Step<'s> {
+    Key(&'s str),
+    Index(usize),
+}
+
+impl Step<'_> {
+    fn of<'a>(&self, value: Option<&'a serde_json::Value>) -> Option<&'a serde_json::Value> {
+        match self {
+            Step::Key(key) => value?.get(key),
+            Step::Index(index) => value?.get(index),
+        }
+    }
+
+    fn path(&self, parent: &str) -> String {
+        match self {
+            Step::Key(key) if parent.is_empty() => key.to_string(),
+            Step::Key(key) => format!("{}.{}", parent, key),
+            Step::Index(index) => format!("{}[{}]", parent, index),
+        }
+    }
+}
+
+fn is_marked(marker: Option<&serde_json::Value>) -> bool {
+    marker.and_then(|m| m.as_bool()).unwrap_or(false)
+}
+
+fn diff_attributes(
+    path: &str,
+    before: Option<&serde_json::Value>,
+    after: Option<&serde_json::Value>,
+    markers: &Markers<'_>,
+    out: &mut Vec<AttributeChange>,
+) {
+    use serde_json::Value;
+
+    let before = before.filter(|v| !v.is_null());
+    let after = after.filter(|v| !v.is_null());
+    let before_sensitive = is_marked(markers.before_sensitive);
+    let after_sensitive = is_marked(markers.after_sensitive);
+
+    if is_marked(markers.after_unknown) {
+        out.push(AttributeChange {
+            path: path.to_string(),
+            before: before.map(|b| redact(b, markers.before_sensitive)),
+            after: None,
+            unknown: true,
+            sensitive: before_sensitive || after_sensitive,
+        });
+        return;
+    }
+    if before_sensitive || after_sensitive {
+        if before != after {
+            out.push(AttributeChange {
+                path: path.to_string(),
+                before: before.map(|_| Value::from(SENSITIVE)),
+                after: after.map(|_| Value::from(SENSITIVE)),
+                unknown: false,
+                sensitive: true,
+            });
+        }
+        return;
+    }
+
+    match (before, after) {
+        (Some(Value::Object(b)), Some(Value::Object(a))) => {
+            let mut keys: Vec<&String> = b.keys().chain(a.keys()).collect();
+            if let Some(Value::Object(unknown)) = markers.after_unknown {
+                keys.extend(unknown.keys());
+            }
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let step = Step::Key(key);
+                diff_attributes(
+                    &step.path(path),
+                    b.get(key),
+                    a.get(key),
+                    &markers.child(step),
+                    out,
+                );
+            }
+        }
+        (Some(Value::Array(b)), Some(Value::Array(a))) => {
+            let mut len = b.len().max(a.len());
+            if let Some(Value::Array(unknown)) = markers.after_unknown {
+                len = len.max(unknown.len());
+            }
+            for index in 0..len {
+                let step = Step::Index(index);
+                diff_attributes(
+                    &step.path(path),
+                    b.get(index),
+                    a.get(index),
+                    &markers.child(step),
+                    out,
+                );
+            }
+        }
+        // A whole resource created or destroyed: list its leaf attributes.
+        (None, Some(Value::Object(_) | Value::Array(_)))
+        | (Some(Value::Object(_) | Value::Array(_)), None)
+            if path.is_empty() =>
+        {
+            let empty = match before.or(after) {
+                Some(Value::Array(_)) => Value::Array(Vec::new()),
+                _ => Value::Object(serde_json::Map::new()),
+            };
+            diff_attributes(
+                path,
+                Some(before.unwrap_or(&empty)),
+                Some(after.unwrap_or(&empty)),
+                markers,
+                out,
+            );
+        }
+        // Nested blocks may still hold sensitive values below this path
+        _ if before != after => {
+            let redacted_before = before.map(|b| redact(b, markers.before_sensitive));
+            let redacted_after = after.map(|a| redact(a, markers.after_sensitive));
+            let sensitive = redacted_before.as_ref() != before || redacted_after.as_ref() != after;
+            out.push(AttributeChange {
+                path: path.to_string(),
+                before: redacted_before,
+                after: redacted_after,
+                unknown: false,
+                sensitive,
+            })
+        }
+        _ => {}
+    }
+}
+
+/// Replace values marked sensitive with a placeholder.
+fn redact(value: &serde_json::Value, sensitive: Option<&serde_json::Value>) -> serde_json::Value {
+    use serde_json::Value;
+
+    match (value, sensitive) {
+        (_, Some(Value::Bool(true))) => Value::from(SENSITIVE),
+        (Value::Object(map), Some(Value::Object(marks))) => Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), redact(v, marks.get(k))))
+                .collect(),
+        ),
+        (Value::Array(items), Some(Value::Array(marks))) => Value::Array(
+            items
+                .iter()
+                .enumerate()
+                .map(|(i, v)| redact(v, marks.get(i)))
+                .collect(),
+        ),
+        _ => value.clone(),
     }
 }
 
@@ -445,6 +725,8 @@ pub struct PlanResult {
     pub output: String,
     pub plan_file: Option<PathBuf>,
     pub summary: PlanSummary,
+    /// Resource-level changes parsed from `plan_json`.
+    pub changes: Vec<ResourceChange>,
     pub plan_json: Option<serde_json::Value>,
 }
 
@@ -489,3 +771,132 @@ pub enum TerraformError {
     #[error("Failed to parse terraform output: {0}")]
     ParseFailed(String),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn plan(change: serde_json::Value) -> Vec<ResourceChange> {
+        parse_plan_json(&json!({
+            "resource_changes": [{
+                "address": "aws_db_instance.main",
+                "type": "aws_db_instance",
+                "name": "main",
+                "change": change,
+            }]
+        }))
+    }
+
+    fn attribute<'a>(change: &'a ResourceChange, path: &str) -> &'a AttributeChange {
+        change
+            .attributes
+            .iter()
+            .find(|a| a.path == path)
+            .unwrap_or_else(|| panic!("no change to {}", path))
+    }
+
+    #[test]
+    fn test_sensitive_nested_block_is_redacted() {
+        let changes = plan(json!({
+            "actions": ["update"],
+            "before": {"name": "db", "settings": null},
+            "after": {"name": "db", "settings": {"password": "hunter2", "size": 20}},
+            "before_sensitive": {},
+            "after_sensitive": {"settings": {"password": true}},
+            "after_unknown": {},
+        }));
+
+        let settings = attribute(&changes[0], "settings");
+        assert_eq!(
+            settings.after,
+            Some(json!({"password": SENSITIVE, "size": 20}))
+        );
+        assert!(settings.sensitive);
+        let stored = serde_json::to_string(&changes).unwrap();
+        assert!(!stored.contains("hunter2"), "{}", stored);
+    }
+
+    #[test]
+    fn test_sensitive_leaf_is_redacted() {
+        let changes = plan(json!({
+            "actions": ["update"],
+            "before": {"name": "db", "password": "old-secret"},
+            "after": {"name": "db", "password": "new-secret"},
+            "before_sensitive": {"password": true},
+            "after_sensitive": {"password": true},
+        }));
+
+        assert_eq!(changes[0].action, "update");
+        assert_eq!(changes[0].attributes.len(), 1);
+        let password = attribute(&changes[0], "password");
+        assert_eq!(password.before, Some(json!(SENSITIVE)));
+        assert_eq!(password.after, Some(json!(SENSITIVE)));
+        assert!(password.sensitive);
+        assert_eq!(
+            changes[0].after,
+            Some(json!({"name": "db", "password": SENSITIVE}))
+        );
+    }
+
+    #[test]
+    fn test_unknown_after_apply() {
+        let changes = plan(json!({
+            "actions": ["create"],
+            "before": null,
+            "after": {"name": "db"},
+            "after_unknown": {"id": true, "endpoint": true},
+        }));
+
+        assert_eq!(changes[0].action, "create");
+        assert_eq!(changes[0].before, None);
+        let id = attribute(&changes[0], "id");
+        assert!(id.unknown);
+        assert_eq!(id.after, None);
+        assert!(!attribute(&changes[0], "name").unknown);
+        assert!(attribute(&changes[0], "endpoint").unknown);
+    }
+
+    #[test]
+    fn test_replace_and_delete_actions() {
+        let parsed = parse_plan_json(&json!({
+            "resource_changes": [
+                {
+                    "address": "aws_instance.web",
+                    "type": "aws_instance",
+                    "name": "web",
+                    "change": {
+                        "actions": ["delete", "create"],
+                        "before": {"ami": "ami-1"},
+                        "after": {"ami": "ami-2"},
+                    },
+                },
+                {
+                    "address": "aws_s3_bucket.logs",
+                    "type": "aws_s3_bucket",
+                    "name": "logs",
+                    "change": {
+                        "actions": ["delete"],
+                        "before": {"bucket": "logs"},
+                        "after": null,
+                    },
+                },
+                {
+                    "address": "aws_vpc.main",
+                    "type": "aws_vpc",
+                    "name": "main",
+                    "change": {"actions": ["no-op"], "before": {}, "after": {}},
+                },
+            ]
+        }));
+
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].action, "replace");
+        assert_eq!(attribute(&parsed[0], "ami").after, Some(json!("ami-2")));
+        assert_eq!(parsed[1].action, "delete");
+        assert_eq!(parsed[1].after, None);
+        let bucket = attribute(&parsed[1], "bucket");
+        assert_eq!(bucket.before, Some(json!("logs")));
+        assert_eq!(bucket.after, None);
+    }
+}
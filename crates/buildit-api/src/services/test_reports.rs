@@ -0,0 +1,191 @@
+//! Test results of pipeline runs.
+//!
+//! Stages name their JUnit or xUnit.net reports with `test-reports`; the
+//! orchestrator reads them out of the job after its commands and hands the
+//! parsed cases over here to be stored. Reports uploaded as `junit`
+//! artifacts are stored the same way.
+//!
+//! When a run with test results finishes on a GitHub repository's commit,
+//! a summary is posted on the commit's open pull requests. That needs
+//! `GITHUB_TOKEN`.
+
+use buildit_core::ResourceId;
+use buildit_core::test_report::TestCase;
+use buildit_db::repo::pipeline::PipelineRunRecord;
+use buildit_db::{PipelineRepo, TestResultRecord, TestResultRepo, TestSummary};
+
+use crate::AppState;
+use crate::services::github::GitHubClient;
+use crate::services::tasks::{self, Task};
+
+/// Failed tests listed in a pull request comment.
+const COMMENT_FAILURES: i64 = 10;
+
+/// Longest failure message quoted in a pull request comment.
+const COMMENT_MESSAGE_CHARS: usize = 200;
+
+/// Store the cases of a stage's report. A report that could not be parsed
+/// is logged and otherwise ignored; it does not fail the stage.
+pub async fn ingest(
+    state: &AppState,
+    run_id: ResourceId,
+    pipeline_id: ResourceId,
+    stage: &str,
+    path: &str,
+    cases: Result<Vec<TestCase>, String>,
+) {
+    let cases = match cases {
+        Ok(cases) => cases,
+        Err(e) => {
+            tracing::warn!(run_id = %run_id, stage = %stage, path = %path, error = %e, "Failed to parse test report");
+            return;
+        }
+    };
+    match state
+        .test_result_repo
+        .replace_report(run_id, pipeline_id, stage, path, &cases)
+        .await
+    {
+        Ok(stored) => {
+            tracing::info!(run_id = %run_id, stage = %stage, path = %path, tests = stored, "Stored test report")
+        }
+        Err(e) => {
+            tracing::error!(run_id = %run_id, stage = %stage, path = %path, error = %e, "Failed to store test report")
+        }
+    }
+}
+
+/// Queue a pull request comment summing up the finished run's tests, if it
+/// has any and ran on a GitHub repository's commit.
+pub async fn enqueue_summary(state: &AppState, run: &PipelineRunRecord) {
+    if std::env::var("GITHUB_TOKEN").is_err() || commit(run).is_none() {
+        return;
+    }
+    let summary = match state
+        .test_result_repo
+        .summary(ResourceId::from_uuid(run.id))
+        .await
+    {
+        Ok(summary) => summary,
+        Err(e) => {
+            tracing::warn!(run_id = %run.id, error = %e, "Failed to load test summary");
+            return;
+        }
+    };
+    if summary.total == 0 {
+        return;
+    }
+    if let Err(e) = tasks::enqueue(state, Task::TestSummaryComment { run_id: run.id }).await {
+        tracing::warn!(run_id = %run.id, error = ?e, "Failed to queue test summary comment");
+    }
+}
+
+/// Post the run's test summary on the open pull requests of its commit.
+pub async fn comment_summary(state: &AppState, run_id: ResourceId) -> Result<(), String> {
+    let run = state
+        .pipeline_repo
+        .get_run(run_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    let Some((owner, repo, sha)) = commit(&run) else {
+        return Ok(());
+    };
+    let pipeline = state
+        .pipeline_repo
+        .get_by_id(ResourceId::from_uuid(run.pipeline_id))
+        .await
+        .map_err(|e| e.to_string())?;
+    let summary = state
+        .test_result_repo
+        .summary(run_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    let failures = state
+        .test_result_repo
+        .failures(run_id, COMMENT_FAILURES)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let token = std::env::var("GITHUB_TOKEN").map_err(|_| "GITHUB_TOKEN is not set")?;
+    let github = GitHubClient::new(token);
+    let pulls = github
+        .pulls_for_commit(&owner, &repo, &sha)
+        .await
+        .map_err(|e| e.to_string())?;
+    let body = summary_markdown(&pipeline.name, run.number, &summary, &failures);
+    for number in pulls {
+        github
+            .create_issue_comment(&owner, &repo, number, &body)
+            .await
+            .map_err(|e| e.to_string())?;
+        tracing::info!(run_id = %run_id, pull = number, "Posted test summary");
+    }
+    Ok(())
+}
+
+/// Markdown summary of a run's tests, listing the first failures.
+fn summary_markdown(
+    pipeline: &str,
+    run_number: i64,
+    summary: &TestSummary,
+    failures: &[TestResultRecord],
+) -> String {
+    let icon = if summary.failures() == 0 {
+        "✅"
+    } else {
+        "❌"
+    };
+    let mut body = format!(
+        "### {} {} #{}: {} tests, {} failed\n\n\
+         | Passed | Failed | Errored | Skipped | Time |\n\
+         | --- | --- | --- | --- | --- |\n\
+         | {} | {} | {} | {} | {:.1}s |\n",
+        icon,
+        pipeline,
+        run_number,
+        summary.total,
+        summary.failures(),
+        summary.passed,
+        summary.failed,
+        summary.errored,
+        summary.skipped,
+        summary.duration_secs,
+    );
+    if !failures.is_empty() {
+        body.push_str("\n**Failed tests**\n\n");
+        for failure in failures {
+            let name = if failure.classname.is_empty() {
+                failure.name.clone()
+            } else {
+                format!("{}.{}", failure.classname, failure.name)
+            };
+            body.push_str(&format!("- `{}` ({})", name, failure.stage_name));
+            if let Some(message) = failure.message.as_deref().and_then(|m| m.lines().next()) {
+                let message: String = message.chars().take(COMMENT_MESSAGE_CHARS).collect();
+                body.push_str(&format!(": {}", message));
+            }
+            body.push('\n');
+        }
+        let more = summary.failures() - failures.len() as i64;
+        if more > 0 {
+            body.push_str(&format!("- and {} more\n", more));
+        }
+    }
+    body
+}
+
+/// Owner, repository and commit SHA of a run on a GitHub repository.
+fn commit(run: &PipelineRunRecord) -> Option<(String, String, String)> {
+    let field = |key: &str| {
+        run.git_info
+            .get(key)
+            .and_then(|v| v.as_str())
+            .filter(|v| !v.is_empty())
+    };
+    let (owner, repo) = field("repository")?.split_once('/')?;
+    Some((
+        owner.to_string(),
+        repo.to_string(),
+        field("sha")?.to_string(),
+    ))
+}
@@ -0,0 +1,353 @@
+//! Watchdog for stuck pipeline runs.
+//!
+//! Periodically looks for stages that have been running longer than a
+//! multiple of their historical p95 duration, flags them, notifies
+//! subscribers and optionally cancels the run with a `stalled` status.
+
+use buildit_core::ResourceId;
+use buildit_core::pipeline::Ownership;
+use buildit_db::{PgPipelineRepo, PipelineRepo, RunningStageRecord, StageResultRecord};
+use buildit_scheduler::{ExecutorRegistry, PipelineOrchestrator};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+use crate::services::event_bus::EventBus;
+use crate::services::metrics;
+use crate::ws::BroadcastEvent;
+
+/// Watchdog settings.
+#[derive(Debug, Clone)]
+pub struct WatchdogConfig {
+    /// How often to scan for stuck stages.
+    pub interval: Duration,
+    /// Multiple of the historical p95 after which a stage counts as stuck.
+    pub multiplier: f64,
+    /// Never flag stages that have run for less than this.
+    pub min_runtime: Duration,
+    /// Threshold for stages without any successful history.
+    pub default_timeout: Duration,
+    /// Cancel stuck runs instead of only flagging them.
+    pub auto_cancel: bool,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+            multiplier: 3.0,
+            min_runtime: Duration::from_secs(300),
+            default_timeout: Duration::from_secs(3600),
+            auto_cancel: false,
+        }
+    }
+}
+
+impl WatchdogConfig {
+    /// Load settings from `BUILDIT_WATCHDOG_*` environment variables.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let secs = |name: &str, default: Duration| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(default)
+        };
+
+        Self {
+            interval: secs("BUILDIT_WATCHDOG_INTERVAL_SECS", defaults.interval),
+            multiplier: std::env::var("BUILDIT_WATCHDOG_MULTIPLIER")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|m: &f64| *m > 0.0)
+                .unwrap_or(defaults.multiplier),
+            min_runtime: secs("BUILDIT_WATCHDOG_MIN_SECS", defaults.min_runtime),
+            default_timeout: secs("BUILDIT_WATCHDOG_DEFAULT_SECS", defaults.default_timeout),
+            auto_cancel: std::env::var("BUILDIT_WATCHDOG_AUTO_CANCEL")
+                .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+                .unwrap_or(defaults.auto_cancel),
+        }
+    }
+
+    /// Seconds after which a stage with the given historical p95 counts as
+    /// stuck.
+    pub fn threshold_secs(&self, p95_seconds: Option<f64>) -> f64 {
+        let threshold = p95_seconds
+            .map(|p95| p95 * self.multiplier)
+            .unwrap_or(self.default_timeout.as_secs_f64());
+        threshold.max(self.min_runtime.as_secs_f64())
+    }
+}
+
+/// Background task that detects and remediates stuck runs.
+pub struct RunWatchdog {
+    config: WatchdogConfig,
+    pipeline_repo: Arc<PgPipelineRepo>,
+    event_bus: Arc<dyn EventBus>,
+    /// Executors to cancel stuck jobs on; none without an executor.
+    orchestrator: Option<Arc<PipelineOrchestrator>>,
+}
+
+impl RunWatchdog {
+    pub fn new(
+        config: WatchdogConfig,
+        pipeline_repo: Arc<PgPipelineRepo>,
+        event_bus: Arc<dyn EventBus>,
+        orchestrator: Option<Arc<PipelineOrchestrator>>,
+    ) -> Self {
+        Self {
+            config,
+            pipeline_repo,
+            event_bus,
+            orchestrator,
+        }
+    }
+
+    /// Run the watchdog loop forever.
+    pub async fn run(self) {
+        info!(
+            interval_secs = self.config.interval.as_secs(),
+            multiplier = self.config.multiplier,
+            auto_cancel = self.config.auto_cancel,
+            "Run watchdog started"
+        );
+
+        let mut ticker = tokio::time::interval(self.config.interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.check().await {
+                error!(error = %e, "Run watchdog scan failed");
+            }
+        }
+    }
+
+    /// Scan once, returning the stages that were flagged.
+    pub async fn check(&self) -> buildit_db::DbResult<Vec<RunningStageRecord>> {
+        let stuck: Vec<_> = self
+            .pipeline_repo
+            .find_running_stages()
+            .await?
+            .into_iter()
+            .filter(|stage| stage.elapsed_seconds > self.config.threshold_secs(stage.p95_seconds))
+            .collect();
+
+        for stage in &stuck {
+            let threshold_seconds = self.config.threshold_secs(stage.p95_seconds);
+            let run_id = ResourceId::from_uuid(stage.pipeline_run_id);
+            let owners = serde_json::from_value::<Ownership>(stage.owners.clone())
+                .map(|o| o.owners_for_stage(&stage.stage_name).to_vec())
+                .unwrap_or_default();
+            warn!(
+                pipeline = %stage.pipeline_name,
+                run = stage.run_number,
+                stage = %stage.stage_name,
+                elapsed_secs = stage.elapsed_seconds as u64,
+                threshold_secs = threshold_seconds as u64,
+                owners = ?owners,
+                "Stage appears stuck"
+            );
+
+            self.pipeline_repo
+                .mark_stage_stalled(run_id, &stage.stage_name)
+                .await?;
+
+            if self.config.auto_cancel {
+                let message = format!(
+                    "Cancelled by watchdog after {}s (threshold {}s)",
+                    stage.elapsed_seconds as u64, threshold_seconds as u64
+                );
+                // Stop the run's jobs before reporting it stalled, so they
+                // don't hold on to their executor.
+                if let Some(orchestrator) = &self.orchestrator {
+                    let results = self.pipeline_repo.list_stage_results(run_id).await?;
+                    cancel_jobs(orchestrator.executors(), &results).await;
+                }
+                self.pipeline_repo
+                    .update_stage_result_finished(
+                        run_id,
+                        &stage.stage_name,
+                        "stalled",
+                        None,
+                        Some(&message),
+                    )
+                    .await?;
+                self.pipeline_repo
+                    .update_run_status(run_id, "stalled")
+                    .await?;
+                metrics::record_run("stalled");
+
+                self.event_bus.publish(BroadcastEvent::StageUpdate {
+                    run_id: run_id.to_string(),
+                    stage_name: stage.stage_name.clone(),
+                    status: "stalled".to_string(),
+                    duration: None,
+                });
+                self.event_bus.publish(BroadcastEvent::RunUpdate {
+                    run_id: run_id.to_string(),
+                    status: "stalled".to_string(),
+                });
+            }
+
+            self.event_bus.publish(BroadcastEvent::StageStuck {
+                run_id: run_id.to_string(),
+                pipeline_name: stage.pipeline_name.clone(),
+                stage_name: stage.stage_name.clone(),
+                elapsed_seconds: stage.elapsed_seconds as u64,
+                threshold_seconds: threshold_seconds as u64,
+                cancelled: self.config.auto_cancel,
+                owners,
+            });
+        }
+
+        Ok(stuck)
+    }
+}
+
+/// Cancel the jobs of the run's running stages, returning how many were
+/// cancelled. Failures are logged; the run is marked stalled regardless.
+async fn cancel_jobs(executors: &ExecutorRegistry, results: &[StageResultRecord]) -> usize {
+    let mut cancelled = 0;
+    for result in results.iter().filter(|r| r.status == "running") {
+        let Some(handle) = result.job_handle() else {
+            continue;
+        };
+        let Some(executor) = executors.get(&handle.executor_name) else {
+            warn!(
+                stage = %result.stage_name,
+                executor = %handle.executor_name,
+                "Cannot cancel stuck job on unknown executor"
+            );
+            continue;
+        };
+        match executor.cancel(&handle).await {
+            Ok(()) => cancelled += 1,
+            Err(e) => warn!(
+                stage = %result.stage_name,
+                error = %e,
+                "Failed to cancel stuck job"
+            ),
+        }
+    }
+    cancelled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use buildit_core::executor::{
+        Executor, JobHandle, JobResult, JobSpec, JobStatus, LogLine, TerminalSession,
+    };
+    use futures::stream::BoxStream;
+    use std::sync::Mutex;
+
+    /// Executor that records the jobs it was asked to cancel.
+    #[derive(Default)]
+    struct RecordingExecutor {
+        cancelled: Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Executor for RecordingExecutor {
+        fn name(&self) -> &'static str {
+            "recording"
+        }
+
+        async fn can_execute(&self, _spec: &JobSpec) -> bool {
+            true
+        }
+
+        async fn spawn(&self, _spec: JobSpec) -> buildit_core::Result<JobHandle> {
+            unimplemented!()
+        }
+
+        async fn logs(
+            &self,
+            _handle: &JobHandle,
+        ) -> buildit_core::Result<BoxStream<'static, LogLine>> {
+            unimplemented!()
+        }
+
+        async fn status(&self, _handle: &JobHandle) -> buildit_core::Result<JobStatus> {
+            unimplemented!()
+        }
+
+        async fn wait(&self, _handle: &JobHandle) -> buildit_core::Result<JobResult> {
+            unimplemented!()
+        }
+
+        async fn cancel(&self, handle: &JobHandle) -> buildit_core::Result<()> {
+            self.cancelled
+                .lock()
+                .unwrap()
+                .push(handle.executor_id.clone());
+            Ok(())
+        }
+
+        async fn exec_interactive(
+            &self,
+            _handle: &JobHandle,
+            _cmd: Vec<String>,
+        ) -> buildit_core::Result<TerminalSession> {
+            unimplemented!()
+        }
+    }
+
+    fn result(stage: &str, status: &str, executor: Option<&str>) -> StageResultRecord {
+        StageResultRecord {
+            id: uuid::Uuid::new_v4(),
+            pipeline_run_id: uuid::Uuid::new_v4(),
+            stage_name: stage.to_string(),
+            status: status.to_string(),
+            job_id: executor.map(|_| uuid::Uuid::new_v4()),
+            deployment_id: None,
+            started_at: None,
+            finished_at: None,
+            error_message: None,
+            stalled_at: None,
+            executor_id: executor.map(|_| format!("{stage}-container")),
+            executor_name: executor.map(str::to_string),
+            routing: None,
+            queued_at: None,
+            exit_code: None,
+            reused_from: None,
+            image_digest: None,
+            duration_secs: None,
+            queued_secs: None,
+        }
+    }
+
+    #[test]
+    fn test_stuck_threshold() {
+        let config = WatchdogConfig {
+            multiplier: 3.0,
+            min_runtime: Duration::from_secs(300),
+            default_timeout: Duration::from_secs(3600),
+            ..WatchdogConfig::default()
+        };
+
+        // Three times the p95 of earlier runs
+        assert_eq!(config.threshold_secs(Some(200.0)), 600.0);
+        // Never below the minimum runtime
+        assert_eq!(config.threshold_secs(Some(10.0)), 300.0);
+        // Stages without history use the default
+        assert_eq!(config.threshold_secs(None), 3600.0);
+    }
+
+    #[tokio::test]
+    async fn test_auto_cancel_stops_running_jobs() {
+        let executor = Arc::new(RecordingExecutor::default());
+        let registry = ExecutorRegistry::new(vec![executor.clone()]);
+        let results = vec![
+            result("build", "succeeded", Some("recording")),
+            result("test", "running", Some("recording")),
+            // Not dispatched yet
+            result("lint", "running", None),
+            // Executor no longer configured
+            result("e2e", "running", Some("gone")),
+        ];
+
+        assert_eq!(cancel_jobs(&registry, &results).await, 1);
+        assert_eq!(*executor.cancelled.lock().unwrap(), vec!["test-container"]);
+    }
+}
@@ -0,0 +1,366 @@
+//! Outbound webhook subscriptions.
+//!
+//! Tenants register HTTP endpoints for some of their events ([`EVENTS`]).
+//! The notification dispatcher hands each notification to [`publish`], which
+//! records a delivery for every subscription that wants the event and queues
+//! a [`Task::WebhookDelivery`] for it. Failed attempts are retried with the
+//! job queue's exponential backoff, up to [`MAX_ATTEMPTS`] times. Each
+//! delivery keeps the response of its last attempt, and [`redeliver`] sends
+//! any delivery again.
+//!
+//! Deliveries are signed like webhook notification channels: the
+//! `X-BuildIt-Signature` header is `sha256=` and the hex HMAC-SHA256 of the
+//! body, keyed with the subscription's secret.
+
+use std::time::{Duration, Instant};
+
+use buildit_core::ResourceId;
+use buildit_db::{
+    DbError, DeliveryAttempt, WebhookDelivery, WebhookSubscription, WebhookSubscriptionRepo,
+};
+use chrono::Utc;
+use serde_json::{Value, json};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::error::ApiError;
+use crate::services::notifications::{Notification, signature};
+use crate::services::tasks::{self, Task};
+use crate::ws::BroadcastEvent;
+
+/// Events a subscription can receive.
+pub const EVENTS: &[&str] = &[
+    "run.started",
+    "run.completed",
+    "deployment.succeeded",
+    "deployment.failed",
+    "deployment.awaiting_promotion",
+    "deployment.awaiting_approval",
+    "stack.needs_approval",
+    "drift.detected",
+];
+
+/// Event of the delivery sent by `POST .../{id}/ping`.
+pub const PING_EVENT: &str = "ping";
+
+/// Attempts at a delivery before it fails. With the job queue's backoff the
+/// last one is about an hour after the first.
+pub const MAX_ATTEMPTS: i32 = 8;
+
+/// How long an endpoint may take to answer.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How much of an endpoint's response is kept.
+const RESPONSE_BODY_LIMIT: usize = 2000;
+
+/// The subscription event for a notification of a broadcast event, if
+/// subscriptions can receive it.
+fn event_for(notification: &Notification, source: &BroadcastEvent) -> Option<&'static str> {
+    let event = match notification.event.as_str() {
+        "run.started" => "run.started",
+        "run.succeeded" | "run.failed" => "run.completed",
+        "deployment.succeeded" => "deployment.succeeded",
+        "deployment.failed" => "deployment.failed",
+        "approval.required" => match source {
+            BroadcastEvent::StackRunUpdate { .. } => "stack.needs_approval",
+            BroadcastEvent::DeploymentUpdate { status, .. } if status == "awaiting_approval" => {
+                "deployment.awaiting_approval"
+            }
+            _ => "deployment.awaiting_promotion",
+        },
+        "drift.detected" => "drift.detected",
+        _ => return None,
+    };
+    Some(event)
+}
+
+/// The JSON body posted for an event.
+fn payload(event: &str, notification: &Notification) -> Value {
+    json!({
+        "id": Uuid::now_v7(),
+        "event": event,
+        "tenant_id": notification.tenant_id,
+        "subject_id": notification.subject_id,
+        "data": notification.fields,
+        "url": notification.url,
+        "occurred_at": notification.occurred_at,
+    })
+}
+
+/// Queue a delivery of a notification to each subscription of its tenant
+/// that receives it. Returns how many subscriptions that is.
+pub async fn publish(
+    state: &AppState,
+    source: &BroadcastEvent,
+    notification: &Notification,
+) -> Result<usize, ApiError> {
+    let Some(event) = event_for(notification, source) else {
+        return Ok(0);
+    };
+    let subscriptions = state
+        .webhook_subscription_repo
+        .subscriptions_for_event(ResourceId::from_uuid(notification.tenant_id), event)
+        .await?;
+    if subscriptions.is_empty() {
+        return Ok(0);
+    }
+    let payload = payload(event, notification);
+    for subscription in &subscriptions {
+        queue_delivery(state, subscription, event, payload.clone(), None).await?;
+    }
+    Ok(subscriptions.len())
+}
+
+/// Queue a `ping` delivery, to check that a subscription's endpoint works.
+pub async fn ping(
+    state: &AppState,
+    subscription: &WebhookSubscription,
+) -> Result<WebhookDelivery, ApiError> {
+    let payload = json!({
+        "id": Uuid::now_v7(),
+        "event": PING_EVENT,
+        "tenant_id": subscription.tenant_id,
+        "subject_id": subscription.id,
+        "data": { "events": subscription.events },
+        "url": null,
+        "occurred_at": Utc::now(),
+    });
+    queue_delivery(state, subscription, PING_EVENT, payload, None).await
+}
+
+/// Queue a new delivery of the same payload as `delivery`.
+pub async fn redeliver(
+    state: &AppState,
+    subscription: &WebhookSubscription,
+    delivery: &WebhookDelivery,
+) -> Result<WebhookDelivery, ApiError> {
+    queue_delivery(
+        state,
+        subscription,
+        &delivery.event,
+        delivery.payload.clone(),
+        Some(delivery.id),
+    )
+    .await
+}
+
+async fn queue_delivery(
+    state: &AppState,
+    subscription: &WebhookSubscription,
+    event: &str,
+    payload: Value,
+    redelivery_of: Option<Uuid>,
+) -> Result<WebhookDelivery, ApiError> {
+    let delivery = state
+        .webhook_subscription_repo
+        .create_delivery(&WebhookDelivery {
+            id: Uuid::now_v7(),
+            subscription_id: subscription.id,
+            tenant_id: subscription.tenant_id,
+            event: event.to_string(),
+            payload,
+            status: "pending".to_string(),
+            attempts: 0,
+            response_status: None,
+            response_body: None,
+            error: None,
+            duration_ms: None,
+            redelivery_of,
+            created_at: Utc::now(),
+            last_attempt_at: None,
+        })
+        .await?;
+    tasks::enqueue(
+        state,
+        Task::WebhookDelivery {
+            tenant_id: delivery.tenant_id,
+            delivery_id: delivery.id,
+        },
+    )
+    .await?;
+    Ok(delivery)
+}
+
+/// Make an attempt at a pending delivery and record its outcome. A failed
+/// attempt leaves the delivery pending for the job queue to retry, unless
+/// it is the `last_attempt`.
+pub async fn deliver(
+    state: &AppState,
+    tenant_id: ResourceId,
+    id: ResourceId,
+    last_attempt: bool,
+) -> Result<(), String> {
+    let repo = &state.webhook_subscription_repo;
+    let delivery = match repo.get_delivery(tenant_id, id).await {
+        Ok(delivery) => delivery,
+        // Its subscription was deleted since
+        Err(DbError::NotFound(_)) => return Ok(()),
+        Err(e) => return Err(e.to_string()),
+    };
+    if delivery.status != "pending" {
+        return Ok(());
+    }
+    let subscription = match repo
+        .get_subscription(tenant_id, ResourceId::from_uuid(delivery.subscription_id))
+        .await
+    {
+        Ok(subscription) => subscription,
+        Err(DbError::NotFound(_)) => return Ok(()),
+        Err(e) => return Err(e.to_string()),
+    };
+    if !subscription.enabled {
+        record(
+            state,
+            id,
+            DeliveryAttempt {
+                status: "failed".to_string(),
+                error: Some("subscription is disabled".to_string()),
+                ..Default::default()
+            },
+        )
+        .await;
+        return Ok(());
+    }
+
+    let started = Instant::now();
+    let result = send(&subscription, &delivery).await;
+    let duration_ms = started.elapsed().as_millis().min(i32::MAX as u128) as i32;
+    let (response_status, response_body, error) = match result {
+        Ok((status, body)) if (200..300).contains(&status) => (Some(status), Some(body), None),
+        Ok((status, body)) => {
+            let error = format!("endpoint returned {}", status);
+            (Some(status), Some(body), Some(error))
+        }
+        Err(e) => (None, None, Some(e)),
+    };
+    let status = match (&error, last_attempt) {
+        (None, _) => "succeeded",
+        (Some(_), true) => "failed",
+        (Some(_), false) => "pending",
+    };
+    record(
+        state,
+        id,
+        DeliveryAttempt {
+            status: status.to_string(),
+            response_status,
+            response_body,
+            error: error.clone(),
+            duration_ms,
+        },
+    )
+    .await;
+    match error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+async fn record(state: &AppState, id: ResourceId, attempt: DeliveryAttempt) {
+    if let Err(e) = state
+        .webhook_subscription_repo
+        .record_attempt(id, &attempt)
+        .await
+    {
+        warn!(delivery = %id, error = %e, "Failed to record webhook delivery attempt");
+    }
+}
+
+/// Post a delivery's payload, returning the response status and body.
+async fn send(
+    subscription: &WebhookSubscription,
+    delivery: &WebhookDelivery,
+) -> Result<(i32, String), String> {
+    let body = serde_json::to_vec(&delivery.payload).map_err(|e| e.to_string())?;
+    let response = reqwest::Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?
+        .post(&subscription.url)
+        .header("Content-Type", "application/json")
+        .header("User-Agent", "BuildIt-Webhooks")
+        .header("X-BuildIt-Event", &delivery.event)
+        .header("X-BuildIt-Delivery", delivery.id.to_string())
+        .header(
+            "X-BuildIt-Signature",
+            signature(&subscription.secret, &body),
+        )
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = response.status().as_u16() as i32;
+    let body = response.text().await.unwrap_or_default();
+    Ok((status, body.chars().take(RESPONSE_BODY_LIMIT).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    #[test]
+    fn test_signatures_only_match_the_same_secret_and_body() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            signature("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        let body = br#"{"event":"run.completed"}"#;
+        let signed = signature("whsec_a", body);
+        assert_eq!(signed, signature("whsec_a", body));
+        assert_ne!(signed, signature("whsec_b", body));
+        assert_ne!(signed, signature("whsec_a", br#"{"event":"run.started"}"#));
+    }
+
+    #[test]
+    fn test_notifications_map_to_subscription_events() {
+        let notification = |event: &str| Notification {
+            event: event.to_string(),
+            tenant_id: Uuid::new_v4(),
+            subject_id: Uuid::new_v4(),
+            fields: BTreeMap::new(),
+            url: None,
+            occurred_at: Utc::now(),
+        };
+        let run = BroadcastEvent::RunUpdate {
+            run_id: Uuid::new_v4().to_string(),
+            status: "failed".to_string(),
+        };
+        let deployment = |status: &str| BroadcastEvent::DeploymentUpdate {
+            deployment_id: Uuid::new_v4().to_string(),
+            status: status.to_string(),
+        };
+        let stack_run = BroadcastEvent::StackRunUpdate {
+            run_id: Uuid::new_v4().to_string(),
+            stack_id: Uuid::new_v4().to_string(),
+            status: "needs_approval".to_string(),
+        };
+
+        assert_eq!(
+            event_for(&notification("run.failed"), &run),
+            Some("run.completed")
+        );
+        assert_eq!(
+            event_for(&notification("approval.required"), &stack_run),
+            Some("stack.needs_approval")
+        );
+        assert_eq!(
+            event_for(
+                &notification("approval.required"),
+                &deployment("awaiting_approval")
+            ),
+            Some("deployment.awaiting_approval")
+        );
+        assert_eq!(
+            event_for(
+                &notification("approval.required"),
+                &deployment("awaiting_promotion")
+            ),
+            Some("deployment.awaiting_promotion")
+        );
+        assert_eq!(event_for(&notification("test"), &run), None);
+    }
+}
@@ -0,0 +1,76 @@
+//! Server-sent events for live UI pages.
+//!
+//! - `GET /events?channels=run:<id>,deployment:*` - broadcast events of the channels
+//!
+//! Channels are those of the WebSocket (`run:<id>`, `deployment:<id>`, ...),
+//! the wildcard of a kind such as `run:*`, or `*` for all of them, which is
+//! for admins. Only events of the caller's tenant are sent. Each event
+//! is named after its `type`, so a page element can refresh itself with
+//! `hx-trigger="sse:run_update"`. Log lines are only sent over the WebSocket.
+
+use axum::extract::{Query, State};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures::Stream;
+use serde::Deserialize;
+use std::convert::Infallible;
+use tokio::sync::broadcast;
+
+use crate::AppState;
+use crate::auth::AuthContext;
+use crate::error::ApiError;
+use crate::ws::{BroadcastEvent, EventAccess, subscribed};
+
+#[derive(Debug, Deserialize)]
+pub struct EventsQuery {
+    /// Comma-separated channels to receive events of.
+    #[serde(default)]
+    channels: String,
+}
+
+/// Stream the broadcast events of the requested channels.
+pub async fn sse_handler(
+    State(state): State<AppState>,
+    auth: AuthContext,
+    Query(query): Query<EventsQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let channels: Vec<String> = query
+        .channels
+        .split(',')
+        .map(str::trim)
+        .filter(|c| !c.is_empty())
+        .map(String::from)
+        .collect();
+    let rx = state.event_bus.subscribe();
+    let access = EventAccess::new(state, auth);
+    for channel in &channels {
+        access.check_subscription(channel).await?;
+    }
+
+    let stream = futures::stream::unfold(
+        (rx, channels, access),
+        |(mut rx, channels, mut access)| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(BroadcastEvent::LogLine { .. }) => continue,
+                    Ok(event) => {
+                        let channel = event.channel();
+                        if !channels.iter().any(|c| subscribed(c, &channel))
+                            || !access.allows(&event).await
+                        {
+                            continue;
+                        }
+                        let sse = Event::default()
+                            .event(event.kind())
+                            .json_data(&event)
+                            .unwrap_or_default();
+                        return Some((Ok(sse), (rx, channels, access)));
+                    }
+                    // A page that missed events catches up on the next one
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        },
+    );
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
@@ -1,20 +1,57 @@
 //! Application state.
 
+use buildit_db::DeploymentRepo;
+use buildit_db::PgAnalyticsRepo;
 use buildit_db::PgApplicationRepo;
+use buildit_db::PgArtifactRepo;
 use buildit_db::PgDeploymentRepo;
 use buildit_db::PgLogRepo;
+use buildit_db::PgNotificationRepo;
 use buildit_db::PgOrganizationRepo;
 use buildit_db::PgPipelineRepo;
+use buildit_db::PgReleaseRepo;
 use buildit_db::PgRepositoryRepo;
+use buildit_db::PgRunnerRepo;
+use buildit_db::PgScanFindingRepo;
+use buildit_db::PgSearchRepo;
 use buildit_db::PgStackRepo;
 use buildit_db::PgTenantRepo;
+use buildit_db::PgTestResultRepo;
+use buildit_db::PgVariableGroupRepo;
+use buildit_db::PgWebhookSubscriptionRepo;
 
-use crate::ws::Broadcaster;
-use buildit_executor::{KubernetesExecutor, LocalDockerExecutor};
-use buildit_scheduler::PipelineOrchestrator;
+use crate::auth::AuthConfig;
+use crate::services::app_sync::ApplicationSyncService;
+use crate::services::approval_context::ApprovalContextService;
+use crate::services::artifact_dependencies::ArtifactDependencyService;
+use crate::services::artifacts::FilesystemArtifactStore;
+use crate::services::child_pipelines::ChildPipelineService;
+use crate::services::clusters::Clusters;
+use crate::services::credentials::CredentialCipher;
+use crate::services::deployment_diff::DeploymentDiffService;
+use crate::services::deployment_preview::DeploymentPreviewService;
+use crate::services::email::{EmailSender, sender_from_config};
+use crate::services::event_bus::{self, EventBus};
+use crate::services::log_archive::{self, LogArchiveStore};
+use crate::services::metrics::Metrics;
+use crate::services::notifications;
+use crate::services::releases::ReleaseService;
+use crate::services::remote_executor::RemoteExecutor;
+use crate::services::reports::ReportSigner;
+use crate::services::run_comparison::RunComparisonService;
+use buildit_config::system::{ExecutorConfig, JobsConfig, SystemConfig};
+use buildit_core::ResourceId;
+use buildit_core::artifact::ArtifactStore;
+use buildit_core::deployer::Deployer;
+use buildit_deployer::kubernetes::KubernetesDeployer;
+use buildit_executor::{
+    Executor, KubernetesExecutor, LocalDockerExecutor, PodmanExecutor, SshExecutor, SshTarget,
+};
+use buildit_scheduler::{ExecutorRegistry, JobQueue, PipelineOrchestrator, QuotaTracker};
 use sqlx::PgPool;
 use std::sync::Arc;
 use tracing::{info, warn};
+use uuid::Uuid;
 
 /// Executor type to use for pipeline execution.
 #[derive(Debug, Clone, Default)]
@@ -24,21 +61,20 @@ pub enum ExecutorType {
     /// Use local Docker containers
     #[default]
     Docker,
+    /// Use Podman containers, e.g. rootless without a Docker daemon
+    Podman,
+    /// Hand jobs to self-hosted runners
+    Remote,
+    /// Run jobs on a machine over SSH
+    Ssh,
 }
 
 impl ExecutorType {
-    /// Determine executor type from environment.
-    /// Uses BUILDIT_EXECUTOR env var, or auto-detects based on environment.
-    pub fn from_env() -> Self {
-        // Check explicit configuration first
-        if let Ok(executor) = std::env::var("BUILDIT_EXECUTOR") {
-            match executor.to_lowercase().as_str() {
-                "kubernetes" | "k8s" => return Self::Kubernetes,
-                "docker" | "local" => return Self::Docker,
-                other => {
-                    warn!("Unknown executor type '{}', using auto-detection", other);
-                }
-            }
+    /// The executor of the system configuration's `jobs`, or, when it names
+    /// none, Kubernetes inside a cluster and Docker elsewhere.
+    pub fn from_config(jobs: &JobsConfig) -> Self {
+        if let Some(executor_type) = jobs.executor.as_deref().and_then(Self::parse) {
+            return executor_type;
         }
 
         // Auto-detect: if we're running in K8s (KUBERNETES_SERVICE_HOST is set), use K8s executor
@@ -50,6 +86,44 @@ impl ExecutorType {
             Self::Docker
         }
     }
+
+    fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "kubernetes" | "k8s" => Some(Self::Kubernetes),
+            "docker" | "local" => Some(Self::Docker),
+            "podman" => Some(Self::Podman),
+            "remote" | "runner" => Some(Self::Remote),
+            "ssh" => Some(Self::Ssh),
+            _ => None,
+        }
+    }
+
+    /// Executors to fail over to when the primary one is unhealthy, in
+    /// order of preference.
+    pub fn fallbacks(jobs: &JobsConfig) -> Vec<Self> {
+        jobs.fallbacks
+            .iter()
+            .filter_map(|name| Self::parse(name))
+            .collect()
+    }
+}
+
+/// Check what the system configuration refers to that only the server
+/// knows, such as notification event names.
+fn validate_system_config(config: &SystemConfig) -> Result<(), String> {
+    if let Some(unknown) = config
+        .notifications
+        .default_events
+        .iter()
+        .find(|e| !notifications::EVENTS.contains(&e.as_str()))
+    {
+        return Err(format!(
+            "unknown notification event '{}' (expected one of {})",
+            unknown,
+            notifications::EVENTS.join(", ")
+        ));
+    }
+    Ok(())
 }
 
 /// Shared application state.
@@ -58,78 +132,472 @@ pub struct AppState {
     pub pool: PgPool,
     pub tenant_repo: Arc<PgTenantRepo>,
     pub pipeline_repo: Arc<PgPipelineRepo>,
+    pub analytics_repo: Arc<PgAnalyticsRepo>,
+    pub search_repo: Arc<PgSearchRepo>,
     pub deployment_repo: Arc<PgDeploymentRepo>,
     pub organization_repo: Arc<PgOrganizationRepo>,
     pub repository_repo: Arc<PgRepositoryRepo>,
     pub stack_repo: Arc<PgStackRepo>,
     pub application_repo: Arc<PgApplicationRepo>,
     pub log_repo: Arc<PgLogRepo>,
-    pub broadcaster: Arc<Broadcaster>,
+    pub runner_repo: Arc<PgRunnerRepo>,
+    pub notification_repo: Arc<PgNotificationRepo>,
+    pub webhook_subscription_repo: Arc<PgWebhookSubscriptionRepo>,
+    pub artifact_repo: Arc<PgArtifactRepo>,
+    pub test_result_repo: Arc<PgTestResultRepo>,
+    pub scan_finding_repo: Arc<PgScanFindingRepo>,
+    pub variable_group_repo: Arc<PgVariableGroupRepo>,
+    pub release_repo: Arc<PgReleaseRepo>,
+    pub artifact_store: Arc<dyn ArtifactStore>,
+    /// Where logs past their tenant's retention are archived, if anywhere.
+    pub log_archive: Option<Arc<dyn LogArchiveStore>>,
+    pub report_signer: Arc<ReportSigner>,
+    /// Seals stored credentials; unset without `BUILDIT_ENCRYPTION_KEY`.
+    pub credential_cipher: Option<Arc<CredentialCipher>>,
+    pub email: Arc<dyn EmailSender>,
+    pub event_bus: Arc<dyn EventBus>,
+    pub job_queue: Arc<JobQueue>,
+    pub quota: Arc<QuotaTracker>,
+    pub system_config: Arc<SystemConfig>,
+    pub auth: AuthConfig,
     pub orchestrator: Option<Arc<PipelineOrchestrator>>,
+    /// The server's own deployer, for targets without credentials.
+    pub deployer: Option<Arc<dyn Deployer>>,
+    /// Resolves the cluster each environment deploys to.
+    pub clusters: Arc<Clusters>,
+    pub metrics: Option<Arc<Metrics>>,
 }
 
 impl AppState {
-    pub fn new(pool: PgPool) -> Self {
+    /// State for a loaded system configuration; fails when the
+    /// configuration cannot be used, e.g. its key file is unreadable.
+    pub fn new(pool: PgPool, system_config: SystemConfig) -> Result<Self, String> {
+        // Only one in-process server without sign-in may sign links with a
+        // throwaway key.
+        let single_process_dev = system_config.auth.disabled == Some(true)
+            && system_config
+                .event_bus
+                .as_ref()
+                .is_none_or(|bus| bus.backend == "memory");
+        let report_signer = ReportSigner::from_env(single_process_dev)?;
+        Self::with_report_signer(pool, system_config, report_signer)
+    }
+
+    fn with_report_signer(
+        pool: PgPool,
+        system_config: SystemConfig,
+        report_signer: ReportSigner,
+    ) -> Result<Self, String> {
+        validate_system_config(&system_config)?;
         let tenant_repo = Arc::new(PgTenantRepo::new(pool.clone()));
         let pipeline_repo = Arc::new(PgPipelineRepo::new(pool.clone()));
+        let analytics_repo = Arc::new(PgAnalyticsRepo::new(pool.clone()));
+        let search_repo = Arc::new(PgSearchRepo::new(pool.clone()));
         let deployment_repo = Arc::new(PgDeploymentRepo::new(pool.clone()));
         let organization_repo = Arc::new(PgOrganizationRepo::new(pool.clone()));
         let repository_repo = Arc::new(PgRepositoryRepo::new(pool.clone()));
         let stack_repo = Arc::new(PgStackRepo::new(pool.clone()));
         let application_repo = Arc::new(PgApplicationRepo::new(pool.clone()));
         let log_repo = Arc::new(PgLogRepo::new(pool.clone()));
-        let broadcaster = Arc::new(Broadcaster::new());
+        let runner_repo = Arc::new(PgRunnerRepo::new(pool.clone()));
+        let notification_repo = Arc::new(PgNotificationRepo::new(pool.clone()));
+        let webhook_subscription_repo = Arc::new(PgWebhookSubscriptionRepo::new(pool.clone()));
+        let artifact_repo = Arc::new(PgArtifactRepo::new(pool.clone()));
+        let test_result_repo = Arc::new(PgTestResultRepo::new(pool.clone()));
+        let scan_finding_repo = Arc::new(PgScanFindingRepo::new(pool.clone()));
+        let variable_group_repo = Arc::new(PgVariableGroupRepo::new(pool.clone()));
+        let release_repo = Arc::new(PgReleaseRepo::new(pool.clone()));
+        let artifact_store: Arc<dyn ArtifactStore> = Arc::new(
+            FilesystemArtifactStore::from_config(system_config.artifact_store.as_ref()),
+        );
+        let report_signer = Arc::new(report_signer);
+        let credential_cipher =
+            CredentialCipher::from_config(system_config.secret_store.as_ref())?.map(Arc::new);
+        let email = sender_from_config(&system_config.notifications)?;
+        let event_bus = event_bus::bus_from_config(system_config.event_bus.as_ref())
+            .map_err(|e| e.to_string())?;
+        let system_config = Arc::new(system_config);
+        let log_archive = system_config.log_archive.as_ref().and_then(|config| {
+            log_archive::store_from_config(config)
+                .inspect_err(
+                    |e| warn!(error = %e, "Invalid log archive, logs will not be archived"),
+                )
+                .ok()
+        });
+        let auth = AuthConfig::from_config(&system_config.auth);
+        let job_queue = Arc::new(JobQueue::with_config(
+            pool.clone(),
+            system_config.scheduler.clone(),
+        ));
+        let quota = Arc::new(QuotaTracker::new(pool.clone()));
 
-        // Orchestrator is initialized async via init_executor()
+        // Orchestrator and deployer are initialized async via init_executor()/init_deployer()
         let orchestrator = None;
+        let deployer = None;
+        let clusters = Arc::new(Clusters::new(
+            deployment_repo.clone(),
+            credential_cipher.clone(),
+            None,
+        ));
+        let metrics = None;
 
-        Self {
+        Ok(Self {
             pool,
             tenant_repo,
             pipeline_repo,
+            analytics_repo,
+            search_repo,
             deployment_repo,
             organization_repo,
             repository_repo,
             stack_repo,
             application_repo,
             log_repo,
-            broadcaster,
+            runner_repo,
+            notification_repo,
+            webhook_subscription_repo,
+            artifact_repo,
+            test_result_repo,
+            scan_finding_repo,
+            variable_group_repo,
+            release_repo,
+            artifact_store,
+            log_archive,
+            report_signer,
+            credential_cipher,
+            email,
+            event_bus,
+            job_queue,
+            quota,
+            system_config,
+            auth,
             orchestrator,
+            deployer,
+            clusters,
+            metrics,
+        })
+    }
+
+    /// Install the Prometheus recorder served at `/metrics`.
+    pub fn init_metrics(&mut self) {
+        self.metrics = Metrics::install().map(Arc::new);
+    }
+
+    /// Service that assembles context for approval screens.
+    pub fn approval_context(&self) -> ApprovalContextService {
+        ApprovalContextService::new(
+            self.deployment_repo.clone(),
+            self.pipeline_repo.clone(),
+            self.stack_repo.clone(),
+            self.repository_repo.clone(),
+        )
+    }
+
+    /// Service that compares two runs of a pipeline.
+    pub fn run_comparison(&self) -> RunComparisonService {
+        RunComparisonService::new(
+            self.pipeline_repo.clone(),
+            self.repository_repo.clone(),
+            self.test_result_repo.clone(),
+        )
+    }
+
+    /// Service that diffs two deployments of a service.
+    pub fn deployment_diff(&self) -> DeploymentDiffService {
+        DeploymentDiffService::new(self.deployment_repo.clone())
+    }
+
+    /// Service that dry-runs deployments.
+    pub fn deployment_preview(&self) -> DeploymentPreviewService {
+        DeploymentPreviewService::new(self.clone())
+    }
+
+    /// Service that renders and applies GitOps applications.
+    pub fn application_sync(&self) -> ApplicationSyncService {
+        ApplicationSyncService::new(
+            self.application_repo.clone(),
+            self.repository_repo.clone(),
+            self.deployment_repo.clone(),
+            self.clusters.clone(),
+        )
+    }
+
+    /// Service that publishes the releases of release stages.
+    pub fn releases(&self) -> ReleaseService {
+        ReleaseService::new(
+            self.pipeline_repo.clone(),
+            self.repository_repo.clone(),
+            self.artifact_repo.clone(),
+            self.release_repo.clone(),
+            self.artifact_store.clone(),
+        )
+    }
+
+    /// Service that resolves the artifacts stages need from other pipelines.
+    pub fn artifact_dependencies(&self) -> ArtifactDependencyService {
+        ArtifactDependencyService::new(self.clone())
+    }
+
+    /// Service that starts the runs of trigger-pipeline stages.
+    pub fn child_pipelines(&self) -> ChildPipelineService {
+        ChildPipelineService::new(self.clone())
+    }
+
+    /// Initialize the Kubernetes deployer when running in (or configured for) a cluster.
+    pub async fn init_deployer(&mut self) {
+        let configured = std::env::var("BUILDIT_DEPLOYER")
+            .map(|d| matches!(d.to_lowercase().as_str(), "kubernetes" | "k8s"))
+            .unwrap_or_else(|_| std::env::var("KUBERNETES_SERVICE_HOST").is_ok());
+        if !configured {
+            return;
+        }
+
+        let namespace =
+            std::env::var("BUILDIT_DEPLOY_NAMESPACE").unwrap_or_else(|_| "default".to_string());
+        match KubernetesDeployer::new(&namespace).await {
+            Ok(deployer) => {
+                info!(namespace = %namespace, "Kubernetes deployer initialized");
+                self.deployer = Some(Arc::new(deployer));
+                self.clusters = Arc::new(Clusters::new(
+                    self.deployment_repo.clone(),
+                    self.credential_cipher.clone(),
+                    self.deployer.clone(),
+                ));
+            }
+            Err(e) => {
+                warn!(
+                    "Kubernetes deployer unavailable: {}. Deployments disabled.",
+                    e
+                );
+            }
         }
     }
 
-    /// Initialize the executor asynchronously (required for Kubernetes executor).
+    /// Initialize the executor asynchronously (required for Kubernetes executor),
+    /// along with any fallback executors jobs are rerouted to when it is down.
+    ///
+    /// Executors declared in the system configuration replace both: each is
+    /// registered under its name and labels, in the order declared.
     pub async fn init_executor(&mut self, executor_type: ExecutorType) {
-        let namespace =
-            std::env::var("BUILDIT_JOB_NAMESPACE").unwrap_or_else(|_| "buildit".to_string());
-
-        match executor_type {
-            ExecutorType::Kubernetes => match KubernetesExecutor::new(&namespace).await {
-                Ok(executor) => {
-                    info!(namespace = %namespace, "Kubernetes executor initialized");
-                    self.orchestrator =
-                        Some(Arc::new(PipelineOrchestrator::new(Arc::new(executor))));
-                }
-                Err(e) => {
-                    warn!(
-                        "Kubernetes executor unavailable: {}. Pipeline execution disabled.",
-                        e
-                    );
+        if !self.system_config.executors.is_empty() {
+            self.init_executor_pool().await;
+            return;
+        }
+
+        let mut executor_types = vec![executor_type];
+        executor_types.extend(ExecutorType::fallbacks(&self.system_config.jobs));
+
+        let mut executors: Vec<Arc<dyn Executor>> = Vec::new();
+        for executor_type in executor_types {
+            let Some(executor) = self.create_executor(&executor_type, None).await else {
+                continue;
+            };
+            if executors.iter().any(|e| e.name() == executor.name()) {
+                continue;
+            }
+            executors.push(executor);
+        }
+        if executors.is_empty() {
+            warn!("No executor available. Pipeline execution disabled.");
+            return;
+        }
+        if executors.len() > 1 {
+            let names: Vec<_> = executors.iter().map(|e| e.name()).collect();
+            info!(executors = ?names, "Jobs fail over between executors");
+        }
+
+        self.orchestrator = Some(Arc::new(
+            PipelineOrchestrator::with_registry(ExecutorRegistry::new(executors))
+                .with_quota(self.quota.clone())
+                .with_releases(Arc::new(self.releases()))
+                .with_child_pipelines(Arc::new(self.child_pipelines()))
+                .with_artifact_resolver(Arc::new(self.artifact_dependencies()))
+                .with_shared_workspace(self.system_config.jobs.shared_workspace),
+        ));
+    }
+
+    /// Register the executors of the system configuration.
+    async fn init_executor_pool(&mut self) {
+        let mut registry = ExecutorRegistry::new(vec![]);
+        let system_config = self.system_config.clone();
+        for config in &system_config.executors {
+            let Some(executor_type) = ExecutorType::parse(&config.executor_type) else {
+                warn!(executor = %config.name, "Unknown executor type '{}', ignoring", config.executor_type);
+                continue;
+            };
+            if registry.get(&config.name).is_some() {
+                warn!(executor = %config.name, "Duplicate executor name, ignoring");
+                continue;
+            }
+            let Some(executor) = self.create_executor(&executor_type, Some(config)).await else {
+                continue;
+            };
+            info!(executor = %config.name, labels = ?config.labels, "Registered executor");
+            registry.register(config.name.clone(), config.labels.clone(), executor);
+        }
+        if registry.names().is_empty() {
+            warn!("No executor available. Pipeline execution disabled.");
+            return;
+        }
+
+        self.orchestrator = Some(Arc::new(
+            PipelineOrchestrator::with_registry(registry)
+                .with_quota(self.quota.clone())
+                .with_releases(Arc::new(self.releases()))
+                .with_child_pipelines(Arc::new(self.child_pipelines()))
+                .with_artifact_resolver(Arc::new(self.artifact_dependencies()))
+                .with_shared_workspace(self.system_config.jobs.shared_workspace),
+        ));
+    }
+
+    /// Client for the cluster of the target a Kubernetes executor is
+    /// declared with.
+    async fn target_client(&self, target_id: &str) -> Result<kube::Client, String> {
+        let id =
+            Uuid::parse_str(target_id).map_err(|_| format!("invalid target ID '{}'", target_id))?;
+        let target = self
+            .deployment_repo
+            .get_target(ResourceId::from_uuid(id))
+            .await
+            .map_err(|e| e.to_string())?;
+        self.clusters
+            .client(&target)
+            .await?
+            .ok_or_else(|| format!("target {} has no cluster credentials", target.name))
+    }
+
+    /// Create an executor, with its settings from the system configuration
+    /// if it is declared there.
+    async fn create_executor(
+        &self,
+        executor_type: &ExecutorType,
+        config: Option<&ExecutorConfig>,
+    ) -> Option<Arc<dyn Executor>> {
+        let labels = config.map(|c| c.labels.clone()).unwrap_or_default();
+        let platforms = config
+            .map(|c| c.platforms.clone())
+            .filter(|p| !p.is_empty());
+        let jobs = &self.system_config.jobs;
+        let namespace = config
+            .and_then(|c| c.namespace.clone())
+            .or_else(|| jobs.namespace.clone())
+            .unwrap_or_else(|| "buildit".to_string());
+
+        let executor: Arc<dyn Executor> = match executor_type {
+            ExecutorType::Kubernetes => {
+                let target = config.and_then(|c| c.target.as_deref());
+                let executor = match target {
+                    Some(target) => self
+                        .target_client(target)
+                        .await
+                        .map(|client| KubernetesExecutor::with_client(client, &namespace)),
+                    None => KubernetesExecutor::new(&namespace)
+                        .await
+                        .map_err(|e| e.to_string()),
+                };
+                match executor {
+                    Ok(mut executor) => {
+                        if let Some(image) = &jobs.git_image {
+                            executor = executor.with_git_image(image);
+                        }
+                        if let Some(claim) = &jobs.git_cache_claim {
+                            executor = executor.with_git_cache(claim);
+                        }
+                        if let Some(platforms) = platforms {
+                            executor = executor.with_platforms(platforms);
+                        }
+                        if let Some(context) = &jobs.security_context {
+                            executor = executor.with_security_context(context.clone());
+                        }
+                        info!(namespace = %namespace, target = ?target, "Kubernetes executor initialized");
+                        Arc::new(executor)
+                    }
+                    Err(e) => {
+                        warn!("Kubernetes executor unavailable: {}", e);
+                        return None;
+                    }
                 }
-            },
+            }
             ExecutorType::Docker => match LocalDockerExecutor::new() {
-                Ok(executor) => {
+                Ok(mut executor) => {
+                    if let Some(image) = &jobs.git_image {
+                        executor = executor.with_git_image(image);
+                    }
                     info!("Docker executor initialized");
-                    self.orchestrator =
-                        Some(Arc::new(PipelineOrchestrator::new(Arc::new(executor))));
+                    Arc::new(executor)
                 }
                 Err(e) => {
-                    warn!(
-                        "Docker executor unavailable: {}. Pipeline execution disabled.",
-                        e
-                    );
+                    warn!("Docker executor unavailable: {}", e);
+                    return None;
                 }
             },
-        }
+            ExecutorType::Podman => {
+                let socket = config
+                    .and_then(|c| c.socket.clone())
+                    .or_else(|| std::env::var("BUILDIT_PODMAN_SOCKET").ok());
+                match PodmanExecutor::new(socket.as_deref()) {
+                    Ok(mut executor) => {
+                        if let Some(image) = &jobs.git_image {
+                            executor = executor.with_git_image(image);
+                        }
+                        info!(socket = %executor.socket(), "Podman executor initialized");
+                        Arc::new(executor)
+                    }
+                    Err(e) => {
+                        warn!("Podman executor unavailable: {}", e);
+                        return None;
+                    }
+                }
+            }
+            ExecutorType::Remote => {
+                info!(labels = ?labels, "Remote executor initialized");
+                Arc::new(RemoteExecutor::new(self.runner_repo.clone(), labels))
+            }
+            ExecutorType::Ssh => {
+                let Some(ssh) = config.and_then(|c| c.ssh.as_ref()) else {
+                    warn!("SSH executor needs a host in the system configuration");
+                    return None;
+                };
+                let mut target = SshTarget::new(&ssh.host);
+                target.user = ssh.user.clone();
+                target.port = ssh.port;
+                target.identity_file = ssh.identity_file.clone();
+                target.platforms = platforms;
+                if let Some(workdir) = &ssh.workdir {
+                    target.work_root = workdir.clone();
+                }
+                info!(host = %ssh.host, "SSH executor initialized");
+                Arc::new(SshExecutor::new(target))
+            }
+        };
+
+        #[cfg(feature = "chaos")]
+        let executor: Arc<dyn Executor> = match buildit_executor::ChaosConfig::from_env() {
+            Some(config) => {
+                warn!(
+                    seed = config.seed,
+                    "Chaos executor enabled; jobs will fail on purpose"
+                );
+                Arc::new(buildit_executor::ChaosExecutor::new(executor, config))
+            }
+            None => executor,
+        };
+
+        Some(executor)
+    }
+}
+
+#[cfg(test)]
+impl AppState {
+    /// State with default configuration whose database is never connected
+    /// to, for tests of paths that decide without it.
+    pub(crate) fn without_database() -> Self {
+        Self::with_pool(PgPool::connect_lazy("postgres://localhost/buildit").unwrap())
+    }
+
+    /// State with default configuration on `pool`.
+    pub(crate) fn with_pool(pool: PgPool) -> Self {
+        let report_signer = ReportSigner::new(b"test".to_vec(), 60);
+        Self::with_report_signer(pool, SystemConfig::default(), report_signer).unwrap()
     }
 }
@@ -0,0 +1,223 @@
+//! Which tenant a request acts in.
+//!
+//! API requests name a tenant with the `X-BuildIt-Tenant` header, by ID or
+//! slug, or by the host they are sent to: with `BUILDIT_TENANT_DOMAIN` set
+//! to `buildit.example.com`, `acme.buildit.example.com` is tenant `acme`.
+//! Otherwise a session acts in the tenant it last switched to
+//! (`PUT /api/v1/me/tenant`), and [`AuthContext::tenant`] falls back to the
+//! only tenant the caller reaches.
+//!
+//! UI pages take a [`CurrentTenant`], resolved the same way from the
+//! session cookie; `/t/{slug}/...` switches the browser to another tenant.
+//!
+//! [`AuthContext::tenant`]: crate::auth::AuthContext::tenant
+
+use axum::extract::FromRequestParts;
+use axum::http::HeaderMap;
+use axum::http::request::Parts;
+use axum_extra::extract::CookieJar;
+use buildit_core::ResourceId;
+use buildit_db::{DbError, Session, Tenant, TenantRepo};
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::auth::{TENANT_HEADER, cookie_session};
+use crate::error::ApiError;
+
+/// Cookie remembering the tenant a browser switched to without signing in.
+pub const TENANT_COOKIE: &str = "buildit_tenant";
+
+/// Slug of the tenant the request's host names: its subdomain of `domain`.
+pub fn host_tenant(headers: &HeaderMap, domain: &str) -> Option<String> {
+    let host = headers
+        .get(axum::http::header::HOST)
+        .and_then(|v| v.to_str().ok())?;
+    let host = host.split(':').next().unwrap_or(host).to_lowercase();
+    let domain = domain.trim_matches('.').to_lowercase();
+    host.strip_suffix(&domain)
+        .and_then(|sub| sub.strip_suffix('.'))
+        .filter(|sub| !sub.is_empty() && !sub.contains('.'))
+        .map(str::to_string)
+}
+
+/// The tenant the request names by header or host, if any.
+pub(crate) async fn requested_tenant(
+    state: &AppState,
+    headers: &HeaderMap,
+) -> Result<Option<Uuid>, ApiError> {
+    if let Some(value) = headers.get(TENANT_HEADER) {
+        let value = value
+            .to_str()
+            .map_err(|_| ApiError::BadRequest(format!("Invalid {} header", TENANT_HEADER)))?;
+        return find_tenant(state, value).await.map(|t| Some(t.id));
+    }
+    match state
+        .auth
+        .tenant_domain
+        .as_deref()
+        .and_then(|domain| host_tenant(headers, domain))
+    {
+        Some(slug) => find_tenant(state, &slug).await.map(|t| Some(t.id)),
+        None => Ok(None),
+    }
+}
+
+/// Look up a tenant by ID or slug.
+pub(crate) async fn find_tenant(state: &AppState, reference: &str) -> Result<Tenant, ApiError> {
+    let reference = reference.trim();
+    let tenant = match Uuid::parse_str(reference) {
+        Ok(id) => state.tenant_repo.get_by_id(ResourceId::from_uuid(id)).await,
+        Err(_) => state.tenant_repo.get_by_slug(reference).await,
+    };
+    tenant.map_err(|e| match e {
+        DbError::NotFound(_) => ApiError::NotFound(format!("Tenant {} not found", reference)),
+        e => e.into(),
+    })
+}
+
+/// Tenants a browser session reaches; `None` for anyone when
+/// authentication is disabled and there is no session. Visitors without a
+/// session are refused otherwise.
+pub(crate) async fn reachable_tenants(
+    state: &AppState,
+    session: Option<&Session>,
+) -> Result<Option<Vec<Tenant>>, ApiError> {
+    match session {
+        Some(session) => Ok(Some(
+            state
+                .tenant_repo
+                .list_for_user(ResourceId::from_uuid(session.user_id))
+                .await?,
+        )),
+        None if state.auth.disabled => Ok(None),
+        None => Err(ApiError::Unauthorized(
+            "Authentication required".to_string(),
+        )),
+    }
+}
+
+/// Whether `tenant` is among the `reachable` ones; all are without
+/// authentication.
+pub(crate) fn can_reach(reachable: Option<&[Tenant]>, tenant: &Tenant) -> bool {
+    reachable.is_none_or(|tenants| tenants.iter().any(|t| t.id == tenant.id))
+}
+
+/// The tenant a UI page shows: the one the host names, else the one the
+/// session or browser switched to, else the first the user reaches. With
+/// authentication disabled and no session, that is the `default` tenant or
+/// the first there is.
+pub struct CurrentTenant(pub Tenant);
+
+impl FromRequestParts<AppState> for CurrentTenant {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let session = cookie_session(state, &parts.headers).await?;
+        let reachable = reachable_tenants(state, session.as_ref()).await?;
+        let allowed = |tenant: &Tenant| can_reach(reachable.as_deref(), tenant);
+
+        if let Some(slug) = state
+            .auth
+            .tenant_domain
+            .as_deref()
+            .and_then(|domain| host_tenant(&parts.headers, domain))
+        {
+            let tenant = find_tenant(state, &slug).await?;
+            if !allowed(&tenant) {
+                return Err(ApiError::Forbidden(format!(
+                    "No access to tenant {}",
+                    tenant.slug
+                )));
+            }
+            return Ok(Self(tenant));
+        }
+
+        // Switched to earlier; ignored if it has since gone out of reach
+        let jar = CookieJar::from_headers(&parts.headers);
+        let selected = session
+            .as_ref()
+            .and_then(|s| s.tenant_id.map(|id| id.to_string()))
+            .or_else(|| jar.get(TENANT_COOKIE).map(|c| c.value().to_string()));
+        if let Some(selected) = selected {
+            match find_tenant(state, &selected).await {
+                Ok(tenant) if allowed(&tenant) => return Ok(Self(tenant)),
+                Ok(_) | Err(ApiError::NotFound(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        if let Some(tenants) = reachable {
+            return tenants.into_iter().next().map(Self).ok_or_else(|| {
+                ApiError::Forbidden("You are not a member of any tenant".to_string())
+            });
+        }
+        match state.tenant_repo.get_by_slug("default").await {
+            Ok(tenant) => Ok(Self(tenant)),
+            Err(DbError::NotFound(_)) => state
+                .tenant_repo
+                .list()
+                .await?
+                .into_iter()
+                .next()
+                .map(Self)
+                .ok_or_else(|| ApiError::NotFound("No tenant exists yet".to_string())),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn tenant(slug: &str) -> Tenant {
+        Tenant {
+            id: Uuid::now_v7(),
+            name: slug.to_string(),
+            slug: slug.to_string(),
+            organization_id: None,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+            policy: serde_json::json!({}),
+            quota: serde_json::json!({}),
+            log_retention: serde_json::json!({}),
+            artifact_retention: serde_json::json!({}),
+        }
+    }
+
+    #[test]
+    fn test_host_tenant() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            axum::http::header::HOST,
+            HeaderValue::from_static("Acme.buildit.example.com:8080"),
+        );
+        assert_eq!(
+            host_tenant(&headers, "buildit.example.com").as_deref(),
+            Some("acme")
+        );
+        assert_eq!(host_tenant(&headers, "example.org"), None);
+
+        headers.insert(
+            axum::http::header::HOST,
+            HeaderValue::from_static("a.b.buildit.example.com"),
+        );
+        assert_eq!(host_tenant(&headers, "buildit.example.com"), None);
+    }
+
+    #[test]
+    fn test_only_reachable_tenants_are_allowed() {
+        let acme = tenant("acme");
+        let globex = tenant("globex");
+        let reachable = vec![acme.clone()];
+        assert!(can_reach(Some(&reachable), &acme));
+        assert!(!can_reach(Some(&reachable), &globex));
+        assert!(!can_reach(Some(&[]), &acme));
+        // Only when authentication is disabled
+        assert!(can_reach(None, &globex));
+    }
+}
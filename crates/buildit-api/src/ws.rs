@@ -3,17 +3,23 @@
 use axum::extract::State;
 use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
 use axum::response::Response;
+use buildit_core::ResourceId;
+use buildit_core::rbac::Role;
+use buildit_db::{ApplicationRepo, DbError, DeploymentRepo, PipelineRepo, StackRepo};
 use futures::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
 use tokio::sync::broadcast;
 use tracing::{info, warn};
+use uuid::Uuid;
 
 use crate::AppState;
+use crate::auth::AuthContext;
+use crate::error::ApiError;
 
-/// Broadcast event sent to WebSocket clients.
-#[derive(Debug, Clone, Serialize)]
+/// Event sent to WebSocket and SSE clients, through the
+/// [`EventBus`](crate::services::event_bus::EventBus).
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum BroadcastEvent {
     RunUpdate {
@@ -26,56 +32,234 @@ pub enum BroadcastEvent {
         status: String,
         duration: Option<String>,
     },
+    /// A line of a stage's log; `step` is the command that wrote it, and
+    /// `frame` (`step_start` or `step_end`, with `exit_code`) marks the
+    /// lines framing a command.
     LogLine {
         run_id: String,
         stage_name: String,
         content: String,
         stream: String,
+        step: Option<u32>,
+        frame: Option<String>,
+        exit_code: Option<i32>,
     },
+    /// A stage ran far longer than usual; sent by the run watchdog.
+    StageStuck {
+        run_id: String,
+        pipeline_name: String,
+        stage_name: String,
+        elapsed_seconds: u64,
+        threshold_seconds: u64,
+        cancelled: bool,
+        owners: Vec<String>,
+    },
+    /// A stage failed; `owners` are the people responsible for it.
+    StageFailed {
+        run_id: String,
+        pipeline_name: String,
+        stage_name: String,
+        owners: Vec<String>,
+    },
+    /// A deployment's rollout changed status.
+    DeploymentUpdate {
+        deployment_id: String,
+        status: String,
+    },
+    /// A stack run changed status, e.g. its plan needs approval.
+    StackRunUpdate {
+        run_id: String,
+        stack_id: String,
+        status: String,
+    },
+    /// An application's live state no longer matches its last sync; sent
+    /// by the drift detector when the drift is first seen.
+    DriftDetected {
+        application_id: String,
+        application_name: String,
+        resources: usize,
+        self_heal: bool,
+    },
+    /// A deployment target's connection status changed.
+    TargetStatus {
+        target_id: String,
+        status: String,
+        message: Option<String>,
+    },
+}
+
+impl BroadcastEvent {
+    /// Channel clients subscribe to for the event, e.g. `run:<id>`.
+    pub fn channel(&self) -> String {
+        match self {
+            BroadcastEvent::RunUpdate { run_id, .. }
+            | BroadcastEvent::StageUpdate { run_id, .. }
+            | BroadcastEvent::LogLine { run_id, .. }
+            | BroadcastEvent::StageStuck { run_id, .. }
+            | BroadcastEvent::StageFailed { run_id, .. } => format!("run:{}", run_id),
+            BroadcastEvent::DeploymentUpdate { deployment_id, .. } => {
+                format!("deployment:{}", deployment_id)
+            }
+            BroadcastEvent::StackRunUpdate { stack_id, .. } => format!("stack:{}", stack_id),
+            BroadcastEvent::DriftDetected { application_id, .. } => {
+                format!("application:{}", application_id)
+            }
+            BroadcastEvent::TargetStatus { target_id, .. } => format!("target:{}", target_id),
+        }
+    }
+
+    /// The event's `type`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            BroadcastEvent::RunUpdate { .. } => "run_update",
+            BroadcastEvent::StageUpdate { .. } => "stage_update",
+            BroadcastEvent::LogLine { .. } => "log_line",
+            BroadcastEvent::StageStuck { .. } => "stage_stuck",
+            BroadcastEvent::StageFailed { .. } => "stage_failed",
+            BroadcastEvent::DeploymentUpdate { .. } => "deployment_update",
+            BroadcastEvent::StackRunUpdate { .. } => "stack_run_update",
+            BroadcastEvent::DriftDetected { .. } => "drift_detected",
+            BroadcastEvent::TargetStatus { .. } => "target_status",
+        }
+    }
 }
 
-/// Broadcaster for WebSocket events.
-#[derive(Clone)]
-pub struct Broadcaster {
-    tx: broadcast::Sender<BroadcastEvent>,
+/// Whether a subscription covers a channel: the channel itself, the
+/// wildcard of its kind (`run:*`), or `*`.
+pub fn subscribed(subscription: &str, channel: &str) -> bool {
+    subscription == "*"
+        || subscription == channel
+        || subscription
+            .strip_suffix('*')
+            .is_some_and(|prefix| prefix.ends_with(':') && channel.starts_with(prefix))
 }
 
-impl Broadcaster {
-    pub fn new() -> Self {
-        let (tx, _) = broadcast::channel(1024);
-        Self { tx }
+/// Whether a caller with `role` in its tenant may subscribe to
+/// `subscription`: `*` spans every kind of event and is for admins.
+pub fn may_subscribe(subscription: &str, role: Option<Role>) -> bool {
+    subscription != "*" || role.is_some_and(|r| r >= Role::Admin)
+}
+
+/// Kind and ID of the resource a channel is about, e.g. `run` and the run's
+/// ID for `run:<id>`.
+fn channel_resource(channel: &str) -> Option<(&str, ResourceId)> {
+    let (kind, id) = channel.split_once(':')?;
+    let id = Uuid::parse_str(id).ok()?;
+    Some((kind, ResourceId::from_uuid(id)))
+}
+
+/// The events a client may receive: those of its tenant, or of the tenants
+/// it reaches if it has not selected one. The tenant of each channel is
+/// looked up once per connection.
+pub struct EventAccess {
+    state: AppState,
+    auth: AuthContext,
+    channels: HashMap<String, bool>,
+}
+
+impl EventAccess {
+    pub fn new(state: AppState, auth: AuthContext) -> Self {
+        Self {
+            state,
+            auth,
+            channels: HashMap::new(),
+        }
     }
 
-    /// Send an event to all connected WebSocket clients.
-    pub fn send(&self, event: BroadcastEvent) {
-        // Ignore errors if no receivers
-        let _ = self.tx.send(event);
+    /// Fail with `403` unless the client may subscribe to `subscription`.
+    pub async fn check_subscription(&self, subscription: &str) -> Result<(), ApiError> {
+        if subscription != "*" {
+            return Ok(());
+        }
+        let tenant = self.auth.tenant(&self.state).await?;
+        let role = self.auth.role_in(&self.state, tenant.id).await?;
+        if may_subscribe(subscription, role) {
+            Ok(())
+        } else {
+            Err(ApiError::Forbidden(
+                "Only admins may subscribe to all events".to_string(),
+            ))
+        }
     }
 
-    /// Subscribe to receive events.
-    pub fn subscribe(&self) -> broadcast::Receiver<BroadcastEvent> {
-        self.tx.subscribe()
+    /// Whether the client may receive `event`.
+    pub async fn allows(&mut self, event: &BroadcastEvent) -> bool {
+        let channel = event.channel();
+        if let Some(allowed) = self.channels.get(&channel) {
+            return *allowed;
+        }
+        let allowed = match self.channel_tenant(&channel).await {
+            Ok(Some(tenant_id)) => match self.auth.tenant_id {
+                Some(selected) => selected == tenant_id,
+                None => matches!(self.auth.role_in(&self.state, tenant_id).await, Ok(Some(_))),
+            },
+            Ok(None) => false,
+            Err(e) => {
+                warn!(channel = %channel, error = ?e, "Failed to look up the tenant of an event");
+                // Looked up again with the next event
+                return false;
+            }
+        };
+        self.channels.insert(channel, allowed);
+        allowed
     }
-}
 
-impl Default for Broadcaster {
-    fn default() -> Self {
-        Self::new()
+    /// Tenant of the resource a channel is about, `None` if it is gone.
+    async fn channel_tenant(&self, channel: &str) -> Result<Option<Uuid>, ApiError> {
+        let Some((kind, id)) = channel_resource(channel) else {
+            return Ok(None);
+        };
+        let state = &self.state;
+        let tenant_id = match kind {
+            "run" => match state.pipeline_repo.get_run(id).await {
+                Ok(run) => state
+                    .pipeline_repo
+                    .get_by_id(ResourceId::from_uuid(run.pipeline_id))
+                    .await
+                    .map(|p| p.tenant_id),
+                Err(e) => Err(e),
+            },
+            "deployment" => state
+                .deployment_repo
+                .get_deployment(id)
+                .await
+                .map(|d| d.tenant_id),
+            "stack" => state.stack_repo.get_stack(id).await.map(|s| s.tenant_id),
+            "application" => state
+                .application_repo
+                .get_application(id)
+                .await
+                .map(|a| a.tenant_id),
+            "target" => state
+                .deployment_repo
+                .get_target(id)
+                .await
+                .map(|t| t.tenant_id),
+            _ => return Ok(None),
+        };
+        match tenant_id {
+            Ok(tenant_id) => Ok(Some(tenant_id)),
+            Err(DbError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
     }
 }
 
 /// WebSocket upgrade handler.
-pub async fn ws_handler(ws: WebSocketUpgrade, State(state): State<AppState>) -> Response {
-    let broadcaster = state.broadcaster.clone();
-    ws.on_upgrade(move |socket| handle_socket(socket, broadcaster))
+pub async fn ws_handler(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    auth: AuthContext,
+) -> Response {
+    ws.on_upgrade(move |socket| handle_socket(socket, EventAccess::new(state, auth)))
 }
 
-async fn handle_socket(socket: WebSocket, broadcaster: Arc<Broadcaster>) {
+async fn handle_socket(socket: WebSocket, mut access: EventAccess) {
     info!("WebSocket connection established");
 
     let (mut sender, mut receiver) = socket.split();
     let mut subscriptions: HashSet<String> = HashSet::new();
-    let mut broadcast_rx = broadcaster.subscribe();
+    let mut broadcast_rx = access.state.event_bus.subscribe();
 
     loop {
         tokio::select! {
@@ -86,9 +270,17 @@ async fn handle_socket(socket: WebSocket, broadcaster: Arc<Broadcaster>) {
                         if let Ok(cmd) = serde_json::from_str::<WsCommand>(&text) {
                             match cmd {
                                 WsCommand::Subscribe { channel } => {
-                                    info!(channel = %channel, "Client subscribed");
-                                    subscriptions.insert(channel.clone());
-                                    let response = WsResponse::Subscribed { channel };
+                                    let response = match access.check_subscription(&channel).await {
+                                        Ok(()) => {
+                                            info!(channel = %channel, "Client subscribed");
+                                            subscriptions.insert(channel.clone());
+                                            WsResponse::Subscribed { channel }
+                                        }
+                                        Err(e) => {
+                                            warn!(channel = %channel, error = ?e, "Subscription rejected");
+                                            WsResponse::Rejected { channel }
+                                        }
+                                    };
                                     if let Ok(json) = serde_json::to_string(&response) {
                                         let _ = sender.send(Message::Text(json.into())).await;
                                     }
@@ -117,13 +309,10 @@ async fn handle_socket(socket: WebSocket, broadcaster: Arc<Broadcaster>) {
                 match event {
                     Ok(event) => {
                         // Check if client is subscribed to this event's channel
-                        let channel = match &event {
-                            BroadcastEvent::RunUpdate { run_id, .. } => format!("run:{}", run_id),
-                            BroadcastEvent::StageUpdate { run_id, .. } => format!("run:{}", run_id),
-                            BroadcastEvent::LogLine { run_id, .. } => format!("run:{}", run_id),
-                        };
-
-                        if subscriptions.contains(&channel) || subscriptions.contains("*") {
+                        let channel = event.channel();
+                        if subscriptions.iter().any(|s| subscribed(s, &channel))
+                            && access.allows(&event).await
+                        {
                             if let Ok(json) = serde_json::to_string(&event) {
                                 if sender.send(Message::Text(json.into())).await.is_err() {
                                     break;
@@ -154,5 +343,37 @@ enum WsCommand {
 #[derive(Debug, Serialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 enum WsResponse {
-    Subscribed { channel: String },
+    Subscribed {
+        channel: String,
+    },
+    /// The client may not subscribe to the channel.
+    Rejected {
+        channel: String,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_admins_subscribe_to_everything() {
+        assert!(may_subscribe("*", Some(Role::Admin)));
+        assert!(may_subscribe("*", Some(Role::Owner)));
+        assert!(!may_subscribe("*", Some(Role::Member)));
+        assert!(!may_subscribe("*", None));
+        // Kind wildcards are filtered to the tenant instead
+        assert!(may_subscribe("run:*", Some(Role::Viewer)));
+    }
+
+    #[test]
+    fn test_channel_resource() {
+        let id = Uuid::new_v4();
+        let channel = format!("run:{id}");
+        let (kind, resource) = channel_resource(&channel).unwrap();
+        assert_eq!(kind, "run");
+        assert_eq!(*resource.as_uuid(), id);
+        assert!(channel_resource("run:*").is_none());
+        assert!(channel_resource("run").is_none());
+    }
 }
@@ -0,0 +1,281 @@
+// Theme toggle functionality
+const themeToggle = document.getElementById("theme-toggle");
+themeToggle.addEventListener("click", function () {
+    const isDark = document.documentElement.classList.toggle("dark");
+    localStorage.setItem("theme", isDark ? "dark" : "light");
+});
+
+// Command palette
+let commandPaletteOpen = false;
+let shortcutsModalOpen = false;
+let selectedCommandIndex = 0;
+let keyBuffer = "";
+let keyBufferTimeout;
+
+function openCommandPalette() {
+    document.getElementById("command-palette").classList.remove("hidden");
+    document.getElementById("command-input").focus();
+    document.getElementById("command-input").value = "";
+    filterCommands("");
+    selectedCommandIndex = 0;
+    updateSelectedCommand();
+    commandPaletteOpen = true;
+}
+
+function closeCommandPalette() {
+    document.getElementById("command-palette").classList.add("hidden");
+    commandPaletteOpen = false;
+}
+
+function openShortcutsModal() {
+    document.getElementById("shortcuts-modal").classList.remove("hidden");
+    shortcutsModalOpen = true;
+}
+
+function closeShortcutsModal() {
+    document.getElementById("shortcuts-modal").classList.add("hidden");
+    shortcutsModalOpen = false;
+}
+
+// Pipeline quick-switcher: query the search API as the user types
+let pipelineSearchTimeout;
+let pipelineSearchSeq = 0;
+
+function searchPipelines(query) {
+    clearTimeout(pipelineSearchTimeout);
+    pipelineSearchTimeout = setTimeout(async () => {
+        const seq = ++pipelineSearchSeq;
+        try {
+            const res = await fetch(
+                "/api/v1/pipelines/search?limit=8&q=" + encodeURIComponent(query.trim()),
+            );
+            if (!res.ok || seq !== pipelineSearchSeq) return;
+            renderPipelineResults(await res.json(), query.trim());
+        } catch (e) {
+            renderPipelineResults([], query.trim());
+        }
+    }, 150);
+}
+
+function renderPipelineResults(pipelines, query) {
+    const container = document.getElementById("pipeline-results");
+    container.replaceChildren();
+    if (pipelines.length === 0 && !query) return;
+
+    if (pipelines.length > 0) {
+        const header = document.createElement("div");
+        header.className = "px-2 py-1.5 text-xs font-semibold text-zinc-400 uppercase tracking-wider";
+        header.textContent = "Pipelines";
+        container.appendChild(header);
+
+        pipelines.forEach((p) => {
+            const item = document.createElement("button");
+            item.className =
+                "command-item pipeline-result w-full flex items-center gap-3 px-3 py-2.5 rounded-lg text-left hover:bg-zinc-100 dark:hover:bg-zinc-800 transition-colors";
+            item.dataset.command = "goto-pipeline:" + p.id;
+
+            const name = document.createElement("span");
+            name.className = "flex-1 text-sm text-zinc-900 dark:text-zinc-100";
+            name.textContent = p.name;
+            const repo = document.createElement("span");
+            repo.className = "text-xs text-zinc-500 truncate max-w-[50%]";
+            repo.textContent = p.repository;
+
+            item.append(name, repo);
+            item.addEventListener("click", () => executeCommand(item.dataset.command));
+            container.appendChild(item);
+        });
+    }
+
+    // Full search across run metadata and logs
+    if (query) {
+        const item = document.createElement("button");
+        item.className =
+            "command-item pipeline-result w-full flex items-center gap-3 px-3 py-2.5 rounded-lg text-left hover:bg-zinc-100 dark:hover:bg-zinc-800 transition-colors";
+        item.dataset.command = "search:" + query;
+
+        const label = document.createElement("span");
+        label.className = "flex-1 text-sm text-zinc-900 dark:text-zinc-100 truncate";
+        label.textContent = "Search runs and logs for \u201c" + query + "\u201d";
+
+        item.append(label);
+        item.addEventListener("click", () => executeCommand(item.dataset.command));
+        container.appendChild(item);
+    }
+
+    selectedCommandIndex = 0;
+    updateSelectedCommand();
+}
+
+function filterCommands(query) {
+    searchPipelines(query);
+    const items = document.querySelectorAll(".command-item:not(.pipeline-result)");
+    const q = query.toLowerCase();
+    let visibleCount = 0;
+
+    items.forEach((item, index) => {
+        const text = item.textContent.toLowerCase();
+        const keywords = (item.dataset.keywords || "").toLowerCase();
+        const matches = text.includes(q) || keywords.includes(q);
+        item.style.display = matches ? "" : "none";
+        if (matches) visibleCount++;
+    });
+
+    selectedCommandIndex = 0;
+    updateSelectedCommand();
+}
+
+function updateSelectedCommand() {
+    const items = document.querySelectorAll(".command-item");
+    const visibleItems = Array.from(items).filter((item) => item.style.display !== "none");
+
+    items.forEach((item) => item.classList.remove("bg-zinc-100", "dark:bg-zinc-800"));
+
+    if (visibleItems[selectedCommandIndex]) {
+        visibleItems[selectedCommandIndex].classList.add("bg-zinc-100", "dark:bg-zinc-800");
+        visibleItems[selectedCommandIndex].scrollIntoView({ block: "nearest" });
+    }
+}
+
+function handleCommandKeydown(event) {
+    const items = document.querySelectorAll(".command-item");
+    const visibleItems = Array.from(items).filter((item) => item.style.display !== "none");
+
+    if (event.key === "ArrowDown") {
+        event.preventDefault();
+        selectedCommandIndex = Math.min(selectedCommandIndex + 1, visibleItems.length - 1);
+        updateSelectedCommand();
+    } else if (event.key === "ArrowUp") {
+        event.preventDefault();
+        selectedCommandIndex = Math.max(selectedCommandIndex - 1, 0);
+        updateSelectedCommand();
+    } else if (event.key === "Enter") {
+        event.preventDefault();
+        if (visibleItems[selectedCommandIndex]) {
+            executeCommand(visibleItems[selectedCommandIndex].dataset.command);
+        }
+    } else if (event.key === "Escape") {
+        closeCommandPalette();
+    }
+}
+
+function executeCommand(command) {
+    closeCommandPalette();
+
+    if (command.startsWith("goto-pipeline:")) {
+        window.location.href = "/pipelines/" + command.slice("goto-pipeline:".length);
+        return;
+    }
+    if (command.startsWith("search:")) {
+        window.location.href = "/search?q=" + encodeURIComponent(command.slice("search:".length));
+        return;
+    }
+
+    switch (command) {
+        case "goto-dashboard":
+            window.location.href = "/";
+            break;
+        case "goto-pipelines":
+            window.location.href = "/pipelines";
+            break;
+        case "goto-runs":
+            window.location.href = "/runs";
+            break;
+        case "goto-environments":
+            window.location.href = "/environments";
+            break;
+        case "goto-targets":
+            window.location.href = "/targets";
+            break;
+        case "goto-settings":
+            window.location.href = "/settings";
+            break;
+        case "new-pipeline":
+            window.location.href = "/pipelines/new";
+            break;
+        case "toggle-theme":
+            themeToggle.click();
+            break;
+        case "show-shortcuts":
+            openShortcutsModal();
+            break;
+    }
+}
+
+// Click handlers for command items
+document.querySelectorAll(".command-item").forEach((item) => {
+    item.addEventListener("click", () => executeCommand(item.dataset.command));
+});
+
+// Global keyboard shortcuts
+document.addEventListener("keydown", function (event) {
+    // Don't capture if typing in an input
+    if (event.target.tagName === "INPUT" || event.target.tagName === "TEXTAREA") {
+        if (event.key === "Escape") {
+            event.target.blur();
+        }
+        return;
+    }
+
+    // Command palette: Cmd+K or Ctrl+K
+    if ((event.metaKey || event.ctrlKey) && event.key === "k") {
+        event.preventDefault();
+        if (commandPaletteOpen) {
+            closeCommandPalette();
+        } else {
+            openCommandPalette();
+        }
+        return;
+    }
+
+    // Close modals on Escape
+    if (event.key === "Escape") {
+        if (commandPaletteOpen) closeCommandPalette();
+        if (shortcutsModalOpen) closeShortcutsModal();
+        return;
+    }
+
+    // Show shortcuts: ?
+    if (event.key === "?" && !event.metaKey && !event.ctrlKey) {
+        event.preventDefault();
+        openShortcutsModal();
+        return;
+    }
+
+    // Two-key shortcuts using buffer
+    clearTimeout(keyBufferTimeout);
+
+    keyBuffer += event.key.toLowerCase();
+    keyBufferTimeout = setTimeout(() => {
+        keyBuffer = "";
+    }, 500);
+
+    // G + key navigation
+    if (keyBuffer === "gd") {
+        window.location.href = "/";
+        keyBuffer = "";
+    } else if (keyBuffer === "gp") {
+        window.location.href = "/pipelines";
+        keyBuffer = "";
+    } else if (keyBuffer === "gr") {
+        window.location.href = "/runs";
+        keyBuffer = "";
+    } else if (keyBuffer === "ge") {
+        window.location.href = "/environments";
+        keyBuffer = "";
+    } else if (keyBuffer === "gt") {
+        window.location.href = "/targets";
+        keyBuffer = "";
+    } else if (keyBuffer === "gs") {
+        window.location.href = "/settings";
+        keyBuffer = "";
+    } else if (keyBuffer === "np") {
+        window.location.href = "/pipelines/new";
+        keyBuffer = "";
+    }
+
+    // Keep only last 2 chars in buffer
+    if (keyBuffer.length > 2) {
+        keyBuffer = keyBuffer.slice(-2);
+    }
+});
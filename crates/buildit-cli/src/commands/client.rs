@@ -0,0 +1,42 @@
+//! The API client commands use.
+
+use buildit_client::{Client, Credentials};
+
+use super::credentials;
+
+/// A client for `api_url`. Requests carry the API token from
+/// `BUILDIT_API_TOKEN` if set, else the credentials saved by `buildit
+/// login`, and name the tenant in `BUILDIT_TENANT` if set.
+pub fn connect(api_url: &str) -> Client {
+    let client = Client::new(api_url);
+    let client = match env("BUILDIT_TENANT") {
+        Some(tenant) => client.with_tenant(tenant),
+        None => client,
+    };
+
+    if let Some(token) = env("BUILDIT_API_TOKEN") {
+        return client.with_token(token);
+    }
+    match credentials::load(api_url) {
+        // Refreshed tokens are saved back
+        Ok(Some(stored)) => {
+            let api_url = api_url.to_string();
+            client
+                .with_credentials(stored)
+                .on_refresh(move |refreshed: &Credentials| {
+                    if let Err(e) = credentials::save(&api_url, refreshed) {
+                        tracing::warn!("Could not save refreshed credentials: {:#}", e);
+                    }
+                })
+        }
+        Ok(None) => client,
+        Err(e) => {
+            tracing::warn!("Ignoring stored credentials: {:#}", e);
+            client
+        }
+    }
+}
+
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|v| !v.is_empty())
+}
@@ -0,0 +1,94 @@
+//! Stored API credentials.
+//!
+//! `buildit login` saves a token per API server in
+//! `$XDG_CONFIG_HOME/buildit/credentials.json` (`~/.config/buildit` by
+//! default, or `$BUILDIT_CONFIG_DIR`), readable only by the user.
+
+use anyhow::{Context, Result};
+use buildit_client::Credentials;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CredentialsFile {
+    #[serde(default)]
+    servers: BTreeMap<String, Credentials>,
+}
+
+fn path() -> Result<PathBuf> {
+    let dir = match std::env::var_os("BUILDIT_CONFIG_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(config) => PathBuf::from(config).join("buildit"),
+            None => PathBuf::from(std::env::var_os("HOME").context("HOME is not set")?)
+                .join(".config")
+                .join("buildit"),
+        },
+    };
+    Ok(dir.join("credentials.json"))
+}
+
+/// Credentials are keyed by server URL without a trailing slash.
+fn server_key(api_url: &str) -> String {
+    api_url.trim_end_matches('/').to_string()
+}
+
+fn read() -> Result<CredentialsFile> {
+    let path = path()?;
+    match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content)
+            .with_context(|| format!("Invalid credentials file {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(CredentialsFile::default()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+fn write(file: &CredentialsFile) -> Result<()> {
+    let path = path()?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+
+    // Write a private temporary file, then move it into place
+    let tmp = path.with_extension("json.tmp");
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut out = options
+        .open(&tmp)
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    out.write_all(serde_json::to_string_pretty(file)?.as_bytes())?;
+    out.sync_all()?;
+    std::fs::rename(&tmp, &path).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
+/// Stored credentials for `api_url`, if any.
+pub fn load(api_url: &str) -> Result<Option<Credentials>> {
+    Ok(read()?.servers.remove(&server_key(api_url)))
+}
+
+/// Store credentials for `api_url`, replacing any it had.
+pub fn save(api_url: &str, credentials: &Credentials) -> Result<()> {
+    let mut file = read()?;
+    file.servers
+        .insert(server_key(api_url), credentials.clone());
+    write(&file)
+}
+
+/// Forget the credentials for `api_url`, returning them.
+pub fn remove(api_url: &str) -> Result<Option<Credentials>> {
+    let mut file = read()?;
+    let removed = file.servers.remove(&server_key(api_url));
+    if removed.is_some() {
+        write(&file)?;
+    }
+    Ok(removed)
+}
@@ -0,0 +1,105 @@
+//! Debug command: a shell in a copy of a failed stage's job.
+
+use super::client::connect;
+use anyhow::{Context, Result, bail};
+use futures::{SinkExt, StreamExt};
+use std::io::Write;
+use std::process::Command;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio_tungstenite::tungstenite::Message;
+use uuid::Uuid;
+
+/// How often to check whether the session's job is ready.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub async fn debug(
+    api_url: &str,
+    run_id: &str,
+    stage: &str,
+    pipeline: &str,
+    ttl_minutes: Option<i64>,
+) -> Result<()> {
+    let run_id: Uuid = run_id.parse().context("Invalid run ID")?;
+    let client = connect(api_url);
+    let pipeline = super::pipelines::resolve(&client, pipeline).await?;
+    let mut session = client
+        .debug_stage(pipeline.id, run_id, stage, ttl_minutes)
+        .await?;
+    println!(
+        "Starting a copy of {} ({}); it is removed at {}",
+        stage,
+        session.id,
+        session.expires_at.format("%H:%M:%S")
+    );
+    while session.status == "starting" {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        session = client.debug_session(session.id).await?;
+    }
+    if session.status != "ready" {
+        bail!(
+            "Debug session {}: {}",
+            session.status,
+            session.error_message.unwrap_or_default()
+        );
+    }
+
+    let terminal = client.debug_terminal(session.id).await?;
+    let result = {
+        let _raw = RawMode::enable();
+        pipe(terminal).await
+    };
+    client.close_debug_session(session.id).await?;
+    println!("\nClosed debug session {}", session.id);
+    result
+}
+
+/// Copy stdin to the terminal and its output to stdout until either ends.
+async fn pipe(terminal: buildit_client::debug::Terminal) -> Result<()> {
+    let (mut sender, mut receiver) = terminal.split();
+    let mut stdin = tokio::io::stdin();
+    let mut stdout = std::io::stdout();
+    let mut buf = [0u8; 1024];
+    loop {
+        tokio::select! {
+            read = stdin.read(&mut buf) => {
+                let n = read.context("Failed to read stdin")?;
+                if n == 0 {
+                    break;
+                }
+                sender.send(Message::binary(buf[..n].to_vec())).await?;
+            }
+            message = receiver.next() => match message {
+                Some(Ok(Message::Binary(bytes))) => {
+                    stdout.write_all(&bytes)?;
+                    stdout.flush()?;
+                }
+                Some(Ok(Message::Text(text))) => {
+                    stdout.write_all(text.as_bytes())?;
+                    stdout.flush()?;
+                }
+                Some(Ok(Message::Close(_))) | None => break,
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e).context("Terminal connection failed"),
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Puts the local terminal in raw mode, so keys reach the remote shell as
+/// typed, until dropped.
+struct RawMode;
+
+impl RawMode {
+    fn enable() -> Option<Self> {
+        let status = Command::new("stty").args(["raw", "-echo"]).status();
+        status.is_ok_and(|s| s.success()).then_some(RawMode)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = Command::new("stty").arg("sane").status();
+    }
+}
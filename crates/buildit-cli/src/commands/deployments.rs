@@ -0,0 +1,329 @@
+//! Deployment commands.
+
+use super::client::connect;
+use anyhow::{Context, Result, bail};
+use buildit_client::Client;
+use buildit_client::deployments::{CreateDeployment, Deployment, ListDeployments};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How often to poll a deployment while waiting for it to finish.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Stop waiting if nothing picks a deployment up within this long.
+const PENDING_TIMEOUT: Duration = Duration::from_secs(60);
+
+async fn resolve_service(client: &Client, name: &str) -> Result<Uuid> {
+    match client.list_services(Some(name)).await?.into_iter().next() {
+        Some(service) => Ok(service.id),
+        None => bail!("Service '{}' not found", name),
+    }
+}
+
+async fn resolve_environment(client: &Client, name: &str) -> Result<Uuid> {
+    let environments = client.list_environments().await?;
+    match environments.into_iter().find(|e| e.name == name) {
+        Some(environment) => Ok(environment.id),
+        None => bail!("Environment '{}' not found", name),
+    }
+}
+
+/// Poll a deployment, printing status changes, until it settles.
+async fn wait_for(client: &Client, deployment: Deployment) -> Result<()> {
+    let mut last_status = deployment.status.clone();
+    println!("  {}", last_status);
+    let mut deployment = deployment;
+    let started = std::time::Instant::now();
+
+    while !deployment.is_terminal() {
+        if deployment.status == "pending" && started.elapsed() > PENDING_TIMEOUT {
+            println!(
+                "Deployment {} is still pending; the server may not have a deployer configured",
+                deployment.id
+            );
+            return Ok(());
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+        deployment = client.get_deployment(deployment.id).await?;
+        if deployment.status != last_status {
+            println!("  {}", deployment.status);
+            last_status = deployment.status.clone();
+        }
+    }
+
+    match deployment.status.as_str() {
+        "failed" | "cancelled" => bail!("Deployment {} {}", deployment.id, deployment.status),
+        "awaiting_promotion" => {
+            println!(
+                "Deployment {} is ready; run `buildit promote {}` to switch traffic",
+                deployment.id, deployment.id
+            );
+            Ok(())
+        }
+        "awaiting_approval" => {
+            println!(
+                "Deployment {} is waiting for approval; run `buildit approve {}` to roll it out",
+                deployment.id, deployment.id
+            );
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Deploy a service to an environment and follow it until it finishes.
+pub async fn deploy(
+    api_url: &str,
+    service: &str,
+    environment: &str,
+    image: Option<String>,
+    branch: Option<String>,
+    wait: bool,
+) -> Result<()> {
+    let client = connect(api_url);
+    let service_id = resolve_service(&client, service).await?;
+    let environment_id = resolve_environment(&client, environment).await?;
+
+    let deployment = client
+        .create_deployment(&CreateDeployment {
+            service_id,
+            environment_id,
+            image,
+            branch,
+        })
+        .await?;
+    println!(
+        "Deploying {} {} to {} ({})",
+        service, deployment.version, environment, deployment.id
+    );
+
+    if wait {
+        wait_for(&client, deployment).await?;
+    }
+    Ok(())
+}
+
+/// Print what deploying a service to an environment would change.
+pub async fn preview(
+    api_url: &str,
+    service: &str,
+    environment: &str,
+    image: Option<String>,
+    branch: Option<String>,
+) -> Result<()> {
+    let client = connect(api_url);
+    let service_id = resolve_service(&client, service).await?;
+    let environment_id = resolve_environment(&client, environment).await?;
+
+    let preview = client
+        .preview_deployment(&CreateDeployment {
+            service_id,
+            environment_id,
+            image,
+            branch,
+        })
+        .await?;
+    println!(
+        "Deploying {}{} to {} would apply:",
+        service,
+        preview
+            .image
+            .as_deref()
+            .map(|image| format!(" ({})", image))
+            .unwrap_or_default(),
+        environment
+    );
+
+    for change in &preview.changes {
+        let namespace = change
+            .namespace
+            .as_deref()
+            .map(|ns| format!(" in {}", ns))
+            .unwrap_or_default();
+        println!(
+            "\n  {} {}/{}{}",
+            change.action, change.kind, change.name, namespace
+        );
+        if change.action == "unchanged" {
+            continue;
+        }
+        match &change.diff {
+            Some(lines) => {
+                for line in lines {
+                    let prefix = match line.kind.as_str() {
+                        "added" => "+",
+                        "removed" => "-",
+                        "skipped" => "…",
+                        _ => " ",
+                    };
+                    println!("    {} {}", prefix, line.text);
+                }
+            }
+            None => println!("    (too large to show)"),
+        }
+    }
+
+    let changed = preview
+        .changes
+        .iter()
+        .filter(|c| c.action != "unchanged")
+        .count();
+    println!(
+        "\n{} of {} objects would change; nothing was deployed",
+        changed,
+        preview.changes.len()
+    );
+    Ok(())
+}
+
+/// Roll back a deployment, given its ID or a service and environment.
+///
+/// Without `to` traffic flips back to the previous version; with it, the
+/// last good deployment of that version is deployed again.
+pub async fn rollback(
+    api_url: &str,
+    target: &str,
+    environment: Option<String>,
+    to: Option<String>,
+    wait: bool,
+) -> Result<()> {
+    let client = connect(api_url);
+
+    let deployment_id = if let Ok(id) = target.parse::<Uuid>() {
+        id
+    } else {
+        let Some(environment) = environment else {
+            bail!("--environment is required when rolling back by service name");
+        };
+        let service_id = resolve_service(&client, target).await?;
+        let environment_id = resolve_environment(&client, &environment).await?;
+        let latest = client
+            .list_deployments(&ListDeployments {
+                service_id,
+                environment_id: Some(environment_id),
+                limit: Some(1),
+            })
+            .await?;
+        match latest.into_iter().next() {
+            Some(d) => d.id,
+            None => bail!("{} has never been deployed to {}", target, environment),
+        }
+    };
+
+    let deployment = client
+        .rollback_deployment(deployment_id, to.as_deref())
+        .await?;
+
+    match &to {
+        Some(version) => println!(
+            "Rolling back {} to {} ({})",
+            deployment_id, version, deployment.id
+        ),
+        None => println!("Rolled back {}", deployment_id),
+    }
+
+    if wait {
+        wait_for(&client, deployment).await?;
+    }
+    Ok(())
+}
+
+/// Promote a service into an environment, or without `to`, switch traffic
+/// to a blue/green deployment that is awaiting promotion.
+///
+/// A promotion deploys the release the previous environment in the
+/// service's promotion chain last deployed successfully.
+pub async fn promote(api_url: &str, target: &str, to: Option<String>, wait: bool) -> Result<()> {
+    let client = connect(api_url);
+
+    let Some(environment) = to else {
+        let id: Uuid = target
+            .parse()
+            .context("Invalid deployment ID; pass --to to promote a service")?;
+        client.promote_deployment(id).await?;
+        println!("Promoted deployment {}", target);
+        return Ok(());
+    };
+
+    let service_id = resolve_service(&client, target).await?;
+    let environment_id = resolve_environment(&client, &environment).await?;
+    let deployment = client.promote_service(service_id, environment_id).await?;
+    match deployment.promoted_from {
+        Some(source) => println!(
+            "Promoting {} {} to {} from deployment {} ({})",
+            target, deployment.version, environment, source, deployment.id
+        ),
+        None => println!(
+            "Promoting {} {} to {} ({})",
+            target, deployment.version, environment, deployment.id
+        ),
+    }
+
+    if wait {
+        wait_for(&client, deployment).await?;
+    }
+    Ok(())
+}
+
+/// Approve a deployment that is waiting for approval, or reject it.
+pub async fn approve(
+    api_url: &str,
+    deployment: &str,
+    reject: bool,
+    comment: Option<String>,
+    wait: bool,
+) -> Result<()> {
+    let id: Uuid = deployment.parse().context("Invalid deployment ID")?;
+    let client = connect(api_url);
+
+    if reject {
+        client.reject_deployment(id, comment.as_deref()).await?;
+        println!("Rejected deployment {}", deployment);
+        return Ok(());
+    }
+
+    let deployment = client.approve_deployment(id, comment.as_deref()).await?;
+    if deployment.status == "awaiting_approval" {
+        println!(
+            "Approved deployment {}; it still needs more approvals",
+            deployment.id
+        );
+        return Ok(());
+    }
+    println!("Approved deployment {}", deployment.id);
+    if wait {
+        wait_for(&client, deployment).await?;
+    }
+    Ok(())
+}
+
+/// List deployments waiting for approval.
+pub async fn approvals(api_url: &str) -> Result<()> {
+    let client = connect(api_url);
+    let pending = client.list_pending_approvals().await?;
+    if pending.is_empty() {
+        println!("No deployments are waiting for approval");
+        return Ok(());
+    }
+
+    println!(
+        "{:<38} {:<20} {:<14} {:<14} {:<10}",
+        "DEPLOYMENT", "SERVICE", "VERSION", "ENVIRONMENT", "APPROVALS"
+    );
+    for approval in pending {
+        println!(
+            "{:<38} {:<20} {:<14} {:<14} {}/{}{}",
+            approval.deployment.id,
+            approval.service_name,
+            approval.deployment.version,
+            approval.environment_name,
+            approval.approvals,
+            approval.required_approvals,
+            if approval.can_review {
+                ""
+            } else {
+                " (not yours to review)"
+            }
+        );
+    }
+    Ok(())
+}
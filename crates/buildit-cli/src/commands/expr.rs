@@ -0,0 +1,67 @@
+//! Expression commands, for trying out interpolations locally.
+
+use anyhow::{Result, bail};
+use buildit_config::{VariableContext, expr};
+use std::collections::HashMap;
+
+use super::variables::parse_assignments;
+
+/// Values to evaluate an expression with, on top of the current repository's
+/// git context.
+pub struct EvalContext {
+    pub branch: Option<String>,
+    pub tag: Option<String>,
+    pub sha: Option<String>,
+    /// `NAME=VALUE` environment variables.
+    pub variables: Vec<String>,
+    /// `STAGE.KEY=VALUE` outputs of stages that succeeded.
+    pub outputs: Vec<String>,
+}
+
+impl EvalContext {
+    fn build(&self) -> Result<VariableContext> {
+        let mut ctx = VariableContext::from_git_repo(".");
+        if let Some(branch) = &self.branch {
+            ctx.git.branch = branch.clone();
+            ctx.git.ref_name = branch.clone();
+        }
+        if let Some(tag) = &self.tag {
+            ctx.git.tag = Some(tag.clone());
+            ctx.git.ref_name = tag.clone();
+        }
+        if let Some(sha) = &self.sha {
+            ctx.git.short_sha = sha.chars().take(7).collect();
+            ctx.git.sha = sha.clone();
+        }
+        ctx.env.extend(parse_assignments(&self.variables)?);
+
+        let mut outputs: HashMap<String, HashMap<String, String>> = HashMap::new();
+        for (name, value) in parse_assignments(&self.outputs)? {
+            let Some((stage, key)) = name.split_once('.') else {
+                bail!("Expected STAGE.KEY=VALUE, got '{}={}'", name, value);
+            };
+            outputs
+                .entry(stage.to_string())
+                .or_default()
+                .insert(key.to_string(), value);
+        }
+        for (stage, outputs) in outputs {
+            ctx.record_stage(&stage, "succeeded", outputs);
+        }
+        Ok(ctx)
+    }
+}
+
+/// Print the value of an expression, or of every `${...}` in a template.
+pub fn eval(expression: &str, context: &EvalContext) -> Result<()> {
+    let ctx = context.build()?;
+    if expression.contains("${") {
+        println!("{}", ctx.interpolate(expression));
+        return Ok(());
+    }
+    match expr::evaluate(expression, &ctx)? {
+        Some(value) => println!("{}", value),
+        None => bail!("'{}' has no value", expression),
+    }
+    Ok(())
+}
@@ -0,0 +1,117 @@
+//! `buildit login` and `buildit logout`.
+//!
+//! Without `--token`, login uses the device authorization flow: the API
+//! hands out a short user code, which the user approves on the web UI's
+//! `/device` page while the CLI polls for its tokens.
+
+use anyhow::{Context, Result, bail};
+use buildit_client::{Client, Credentials, DevicePoll, TokenResponse};
+use std::time::Duration;
+
+use super::credentials;
+
+/// Access tokens from device sign-ins start with this; revoking one on
+/// logout ends the session server-side.
+const ACCESS_TOKEN_PREFIX: &str = "bs_";
+
+/// Extra wait after the server asks us to slow down.
+const SLOW_DOWN_STEP: Duration = Duration::from_secs(5);
+
+/// Sign in to `api_url` and store the credentials.
+pub async fn login(api_url: &str, token: Option<String>, open_browser: bool) -> Result<()> {
+    let base_url = api_url.trim_end_matches('/');
+
+    let credentials = match token {
+        Some(token) => {
+            Client::new(base_url)
+                .with_token(token.clone())
+                .verify()
+                .await
+                .context("Token rejected")?;
+            Credentials {
+                access_token: token,
+                refresh_token: None,
+                expires_at: None,
+            }
+        }
+        None => device_login(&Client::new(base_url), open_browser)
+            .await?
+            .into(),
+    };
+
+    credentials::save(base_url, &credentials)?;
+    println!("Logged in to {}", base_url);
+    Ok(())
+}
+
+/// Forget the credentials for `api_url`, ending the CLI session.
+pub async fn logout(api_url: &str) -> Result<()> {
+    let base_url = api_url.trim_end_matches('/');
+    let Some(credentials) = credentials::remove(base_url)? else {
+        println!("Not logged in to {}", base_url);
+        return Ok(());
+    };
+
+    if credentials.access_token.starts_with(ACCESS_TOKEN_PREFIX) {
+        let client = Client::new(base_url).with_credentials(credentials);
+        if let Err(e) = client.revoke_session().await {
+            tracing::warn!("Could not revoke the session: {}", e);
+        }
+    }
+    println!("Logged out of {}", base_url);
+    Ok(())
+}
+
+/// Run the device authorization flow, returning the issued tokens.
+async fn device_login(client: &Client, open_browser: bool) -> Result<TokenResponse> {
+    let client_name = std::env::var("HOSTNAME")
+        .map(|host| format!("buildit CLI on {}", host))
+        .unwrap_or_else(|_| "buildit CLI".to_string());
+    let code = client
+        .start_device_login(&client_name)
+        .await
+        .context("Failed to start login")?;
+
+    println!("To sign in, open {}", code.verification_uri);
+    println!("and enter the code: {}", code.user_code);
+    if open_browser && open_url(&code.verification_uri_complete) {
+        println!("(Opened your browser.)");
+    }
+    println!("Waiting for approval...");
+
+    let mut interval = Duration::from_secs(code.interval.max(1));
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(code.expires_in);
+    loop {
+        tokio::time::sleep(interval).await;
+        if tokio::time::Instant::now() >= deadline {
+            bail!("The login code expired; run `buildit login` again");
+        }
+
+        match client
+            .poll_device_login(&code.device_code)
+            .await
+            .context("Login failed")?
+        {
+            DevicePoll::Pending => {}
+            DevicePoll::SlowDown => interval += SLOW_DOWN_STEP,
+            DevicePoll::Denied => bail!("Login was denied"),
+            DevicePoll::Expired => bail!("The login code expired; run `buildit login` again"),
+            DevicePoll::Approved(tokens) => return Ok(tokens),
+        }
+    }
+}
+
+/// Open `url` in the user's browser, returning whether that worked.
+fn open_url(url: &str) -> bool {
+    let opener = if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    std::process::Command::new(opener)
+        .arg(url)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
@@ -1,44 +1,28 @@
 //! CLI command implementations.
 
+pub mod client;
+pub mod credentials;
+pub mod debug;
+pub mod deployments;
+pub mod expr;
+pub mod login;
 pub mod pipelines;
 pub mod run;
 pub mod runs;
+pub mod tenants;
+pub mod tokens;
+pub mod variables;
 
 use anyhow::Result;
+use buildit_config::pipeline::FsTemplateResolver;
+use std::path::Path;
 
 pub use run::run_local;
 
-pub async fn login(_api_url: &str, _token: Option<String>) -> Result<()> {
-    // TODO: Implement login
-    println!("Login not yet implemented");
-    Ok(())
-}
-
-pub async fn deploy(
-    _api_url: &str,
-    service: &str,
-    environment: &str,
-    image: Option<String>,
-) -> Result<()> {
-    // TODO: Implement deploy
-    println!(
-        "Deploying {} to {} (image: {:?})",
-        service, environment, image
-    );
-    println!("Deploy not yet implemented");
-    Ok(())
-}
-
-pub async fn rollback(_api_url: &str, target: &str) -> Result<()> {
-    // TODO: Implement rollback
-    println!("Rolling back {}", target);
-    println!("Rollback not yet implemented");
-    Ok(())
-}
-
 pub fn validate(path: &str) -> Result<()> {
     let content = std::fs::read_to_string(path)?;
-    match buildit_config::pipeline::parse_pipeline(&content) {
+    match buildit_config::pipeline::parse_pipeline_with_resolver(&content, &template_resolver(path))
+    {
         Ok(pipeline) => {
             println!("Configuration is valid");
             println!("Pipeline: {}", pipeline.name);
@@ -59,3 +43,12 @@ pub fn validate(path: &str) -> Result<()> {
         }
     }
 }
+
+/// Resolver for `include` directives, relative to the config file's directory.
+pub(crate) fn template_resolver(config_path: &str) -> FsTemplateResolver {
+    let dir = Path::new(config_path)
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    FsTemplateResolver::new(dir)
+}
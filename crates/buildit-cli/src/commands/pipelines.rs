@@ -1,17 +1,117 @@
 //! Pipeline commands.
 
-use anyhow::Result;
+use super::client::connect;
+use anyhow::{Context, Result, bail};
+use buildit_client::Client;
+use buildit_client::pipelines::{ListPipelines, Pipeline};
+use uuid::Uuid;
 
-pub async fn list(_api_url: &str, tenant: Option<String>) -> Result<()> {
-    // TODO: Implement API call
-    println!("Listing pipelines (tenant: {:?})", tenant);
-    println!("Not yet implemented");
+fn parse_tenant(tenant: Option<String>) -> Result<Option<Uuid>> {
+    tenant
+        .map(|t| t.parse())
+        .transpose()
+        .context("Invalid tenant ID")
+}
+
+/// A pipeline by ID or name.
+pub(crate) async fn resolve(client: &Client, pipeline: &str) -> Result<Pipeline> {
+    match client.find_pipeline(pipeline).await? {
+        Some(pipeline) => Ok(pipeline),
+        None => bail!("Pipeline '{}' not found", pipeline),
+    }
+}
+
+pub async fn list(api_url: &str, tenant: Option<String>) -> Result<()> {
+    let pipelines = connect(api_url)
+        .list_pipelines(&ListPipelines {
+            tenant_id: parse_tenant(tenant)?,
+            ..Default::default()
+        })
+        .await?;
+
+    if pipelines.is_empty() {
+        println!("No pipelines");
+        return Ok(());
+    }
+    for p in pipelines {
+        println!("{}  {:<30} {}", p.id, p.name, p.repository);
+    }
     Ok(())
 }
 
-pub async fn trigger(_api_url: &str, pipeline: &str, branch: Option<String>) -> Result<()> {
-    // TODO: Implement API call
-    println!("Triggering pipeline {} (branch: {:?})", pipeline, branch);
-    println!("Not yet implemented");
+pub async fn trigger(
+    api_url: &str,
+    pipeline: &str,
+    branch: Option<String>,
+    variables: &[String],
+    params: &[String],
+) -> Result<()> {
+    let variables = super::variables::parse_assignments(variables)?;
+    let params = super::variables::parse_assignments(params)?;
+    let client = connect(api_url);
+    let pipeline = resolve(&client, pipeline).await?;
+    let run = client
+        .trigger_run(pipeline.id, branch.as_deref(), None, &variables, &params)
+        .await?;
+
+    println!(
+        "Triggered {} run #{} ({})",
+        pipeline.name, run.number, run.id
+    );
+    Ok(())
+}
+
+pub async fn releases(api_url: &str, pipeline: &str, limit: u32) -> Result<()> {
+    let client = connect(api_url);
+    let pipeline = resolve(&client, pipeline).await?;
+    let releases = client.list_releases(pipeline.id, limit).await?;
+
+    if releases.is_empty() {
+        println!("No releases of {}", pipeline.name);
+        return Ok(());
+    }
+    for r in releases {
+        let kind = match (r.draft, r.prerelease) {
+            (true, _) => " (draft)",
+            (false, true) => " (prerelease)",
+            (false, false) => "",
+        };
+        println!(
+            "{}  {:<20} {:>4} commits  {}{}",
+            r.created_at.format("%Y-%m-%d %H:%M"),
+            r.tag,
+            r.commits,
+            r.url,
+            kind
+        );
+    }
+    Ok(())
+}
+
+pub async fn search(api_url: &str, query: &str, tenant: Option<String>, limit: u32) -> Result<()> {
+    let results = connect(api_url)
+        .search_pipelines(query, parse_tenant(tenant)?, limit)
+        .await?;
+
+    if results.is_empty() {
+        println!("No pipelines match '{}'", query);
+        return Ok(());
+    }
+
+    for r in results {
+        let labels = if r.labels.is_empty() {
+            String::new()
+        } else {
+            format!(" [{}]", r.labels.join(", "))
+        };
+        println!(
+            "{}  {:<30} {:<10} {}{}",
+            r.id,
+            r.name,
+            r.last_run_status.as_deref().unwrap_or("-"),
+            r.repository,
+            labels
+        );
+    }
     Ok(())
 }
@@ -1,22 +1,32 @@
 //! Local pipeline execution command.
 
 use anyhow::{Context, Result};
-use buildit_config::VariableContext;
-use buildit_config::pipeline::parse_pipeline;
+use buildit_config::pipeline::parse_pipeline_with_resolver;
+use buildit_config::{VariableContext, resolve_params};
+use buildit_core::executor::LogFrame;
 use buildit_executor::LocalDockerExecutor;
 use buildit_scheduler::{PipelineEvent, PipelineOrchestrator};
 use std::collections::HashMap;
 use std::sync::Arc;
 
 /// Run a pipeline locally using Docker.
-pub async fn run_local(config_path: &str, stages: Option<Vec<String>>) -> Result<()> {
+pub async fn run_local(
+    config_path: &str,
+    stages: Option<Vec<String>>,
+    params: &[String],
+) -> Result<()> {
     // Read and parse the pipeline config
     let content = std::fs::read_to_string(config_path)
         .with_context(|| format!("Failed to read config file: {}", config_path))?;
 
-    let pipeline = parse_pipeline(&content)
+    let pipeline = parse_pipeline_with_resolver(&content, &super::template_resolver(config_path))
         .with_context(|| format!("Failed to parse pipeline config: {}", config_path))?;
 
+    let params = resolve_params(
+        &pipeline.params,
+        &super::variables::parse_assignments(params)?,
+    )?;
+
     println!("Running pipeline: {}", pipeline.name);
     println!("Stages: {}", pipeline.stages.len());
 
@@ -62,7 +72,8 @@ pub async fn run_local(config_path: &str, stages: Option<Vec<String>>) -> Result
     env.insert("BUILDIT".to_string(), "true".to_string());
 
     // Build variable context from current git repo
-    let var_ctx = VariableContext::from_git_repo(working_dir.to_str().unwrap_or("."));
+    let mut var_ctx = VariableContext::from_git_repo(working_dir.to_str().unwrap_or("."));
+    var_ctx.params.extend(params);
 
     // Execute the pipeline
     println!("\n--- Starting pipeline execution ---\n");
@@ -75,7 +86,61 @@ pub async fn run_local(config_path: &str, stages: Option<Vec<String>>) -> Result
             PipelineEvent::StageStarted { stage } => {
                 println!("▶ Stage '{}' started", stage);
             }
-            PipelineEvent::StageLog { stage, line } => {
+            PipelineEvent::StageDispatched { .. } => {}
+            PipelineEvent::TestReport {
+                stage,
+                path,
+                cases: Ok(cases),
+            } => {
+                let failed = cases.iter().filter(|c| c.status.is_failure()).count();
+                println!(
+                    "  [{}] {}: {} tests, {} failed",
+                    stage,
+                    path,
+                    cases.len(),
+                    failed
+                );
+            }
+            PipelineEvent::TestReport {
+                stage,
+                path,
+                cases: Err(e),
+            } => {
+                println!("  [{}] {}: unreadable test report: {}", stage, path, e);
+            }
+            PipelineEvent::ScanCompleted {
+                stage,
+                scanner,
+                target,
+                findings: Ok(findings),
+                ..
+            } => {
+                println!(
+                    "  [{}] {} found {} vulnerabilities in {}",
+                    stage,
+                    scanner,
+                    findings.len(),
+                    target
+                );
+            }
+            PipelineEvent::ScanCompleted {
+                stage,
+                findings: Err(e),
+                ..
+            } => {
+                println!("  [{}] unreadable scan results: {}", stage, e);
+            }
+            PipelineEvent::StageLog {
+                stage,
+                line,
+                frame: Some(frame),
+                ..
+            } => match frame {
+                LogFrame::StepStarted { .. } => println!("  [{}] ▸ {}", stage, line.content),
+                LogFrame::StepFinished { exit_code: 0, .. } => {}
+                LogFrame::StepFinished { .. } => println!("  [{}] ✗ {}", stage, line.content),
+            },
+            PipelineEvent::StageLog { stage, line, .. } => {
                 let stream_marker = match line.stream {
                     buildit_core::executor::LogStream::Stdout => " ",
                     buildit_core::executor::LogStream::Stderr => "!",
@@ -83,13 +148,23 @@ pub async fn run_local(config_path: &str, stages: Option<Vec<String>>) -> Result
                 };
                 println!("  [{}]{} {}", stage, stream_marker, line.content);
             }
-            PipelineEvent::StageCompleted { stage, success } => {
+            PipelineEvent::StageCompleted {
+                stage,
+                success,
+                exit_code,
+                ..
+            } => {
                 if success {
                     println!("✓ Stage '{}' completed successfully\n", stage);
+                } else if let Some(code) = exit_code {
+                    println!("✗ Stage '{}' failed with exit code {}\n", stage, code);
                 } else {
                     println!("✗ Stage '{}' failed\n", stage);
                 }
             }
+            PipelineEvent::StageSkipped { stage, reason } => {
+                println!("⊘ Stage '{}' skipped: {}\n", stage, reason);
+            }
             PipelineEvent::PipelineCompleted { success } => {
                 if success {
                     println!("--- Pipeline completed successfully ---");
@@ -1,18 +1,88 @@
 //! Run commands.
 
-use anyhow::Result;
+use super::client::connect;
+use anyhow::{Context, Result, bail};
+use uuid::Uuid;
 
-pub async fn list(_api_url: &str, pipeline: Option<String>, limit: u32) -> Result<()> {
-    // TODO: Implement API call
-    println!("Listing runs (pipeline: {:?}, limit: {})", pipeline, limit);
-    println!("Not yet implemented");
+pub async fn list(api_url: &str, pipeline: Option<String>, limit: u32) -> Result<()> {
+    let Some(pipeline) = pipeline else {
+        bail!("--pipeline is required");
+    };
+    let client = connect(api_url);
+    let pipeline = super::pipelines::resolve(&client, &pipeline).await?;
+    let runs = client.list_runs(pipeline.id).await?;
+
+    if runs.is_empty() {
+        println!("No runs of {}", pipeline.name);
+        return Ok(());
+    }
+    for run in runs.into_iter().take(limit as usize) {
+        let duration = run
+            .duration_secs
+            .map(|secs| format!("{:.0}s", secs))
+            .unwrap_or_else(|| "-".to_string());
+        println!(
+            "#{:<6} {:<10} {:<8} {}  {}",
+            run.number,
+            run.status,
+            duration,
+            run.created_at.format("%Y-%m-%d %H:%M"),
+            run.id
+        );
+    }
     Ok(())
 }
 
-pub async fn show(_api_url: &str, id: &str) -> Result<()> {
-    // TODO: Implement API call
-    println!("Showing run {}", id);
-    println!("Not yet implemented");
+pub async fn show(api_url: &str, id: &str) -> Result<()> {
+    let run_id: Uuid = id.parse().context("Invalid run ID")?;
+    let client = connect(api_url);
+    let run = client.run(run_id).await?;
+
+    println!("Run #{} ({})", run.number, run.id);
+    println!("Status:   {}", run.status);
+    println!(
+        "Created:  {}",
+        run.created_at.format("%Y-%m-%d %H:%M:%S UTC")
+    );
+    if let Some(secs) = run.queued_secs {
+        println!("Queued:   {:.0}s", secs);
+    }
+    if let Some(secs) = run.duration_secs {
+        println!("Duration: {:.0}s", secs);
+    }
+
+    if run.status == "queued" {
+        let queue = client.run_queue(run_id).await?;
+        println!();
+        match queue.position {
+            Some(position) => println!("Waiting in queue, position {}", position),
+            None => println!("Waiting in queue"),
+        }
+        for reason in &queue.reasons {
+            println!("  - {}", reason.message);
+            for blocking in &reason.blocking_runs {
+                println!("      held by run {}", blocking);
+            }
+        }
+    }
+
+    let stages = client.stages(run_id).await?;
+    if !stages.is_empty() {
+        println!();
+        for stage in stages {
+            let duration = stage
+                .duration_secs
+                .map(|secs| format!("{:.0}s", secs))
+                .unwrap_or_else(|| "-".to_string());
+            println!(
+                "  {:<24} {:<10} {:<8} {}",
+                stage.stage_name,
+                stage.status,
+                duration,
+                stage.executor.unwrap_or_default()
+            );
+        }
+    }
     Ok(())
 }
 
@@ -29,3 +99,39 @@ pub async fn cancel(_api_url: &str, id: &str) -> Result<()> {
     println!("Not yet implemented");
     Ok(())
 }
+
+pub async fn rerun(
+    api_url: &str,
+    id: &str,
+    pipeline: &str,
+    failed: bool,
+    from: Option<String>,
+) -> Result<()> {
+    let run_id: Uuid = id.parse().context("Invalid run ID")?;
+    let mode = match (&from, failed) {
+        (Some(_), _) => "from-stage",
+        (None, true) => "failed-only",
+        (None, false) => "all",
+    };
+    let client = connect(api_url);
+    let pipeline = super::pipelines::resolve(&client, pipeline).await?;
+    let run = client
+        .rerun(pipeline.id, run_id, mode, from.as_deref())
+        .await?;
+
+    println!(
+        "Re-running {} as run #{} ({})",
+        pipeline.name, run.number, run.id
+    );
+    let reused: Vec<String> = client
+        .stages(run.id)
+        .await?
+        .into_iter()
+        .filter(|s| s.status == "reused")
+        .map(|s| s.stage_name)
+        .collect();
+    if !reused.is_empty() {
+        println!("Reusing {}", reused.join(", "));
+    }
+    Ok(())
+}
@@ -0,0 +1,98 @@
+//! Tenant commands.
+
+use super::client::connect;
+use anyhow::{Context, Result, bail};
+use buildit_client::Client;
+
+pub async fn list(api_url: &str) -> Result<()> {
+    let tenants = connect(api_url).list_tenants().await?;
+
+    if tenants.is_empty() {
+        println!("No tenants");
+        return Ok(());
+    }
+    for tenant in tenants {
+        let marker = if tenant.current { "*" } else { " " };
+        println!(
+            "{} {:<20} {:<30} {}",
+            marker, tenant.slug, tenant.name, tenant.id
+        );
+    }
+    Ok(())
+}
+
+/// Switch the signed-in session to a tenant, by slug or ID.
+pub async fn switch(api_url: &str, tenant: &str) -> Result<()> {
+    let switched = connect(api_url).switch_tenant(Some(tenant)).await?;
+
+    match switched {
+        Some(tenant) => println!("Switched to tenant {} ({})", tenant.name, tenant.slug),
+        None => println!("Cleared the selected tenant"),
+    }
+    Ok(())
+}
+
+/// Slug of `tenant`, or of the tenant requests act in by default.
+async fn tenant_slug(client: &Client, tenant: Option<String>) -> Result<String> {
+    if let Some(tenant) = tenant {
+        return Ok(tenant);
+    }
+    match client.list_tenants().await?.into_iter().find(|t| t.current) {
+        Some(tenant) => Ok(tenant.slug),
+        None => bail!("No current tenant; pass --tenant or run `buildit tenants switch`"),
+    }
+}
+
+/// Write the tenant's configuration as YAML to `output`, or stdout.
+pub async fn export(api_url: &str, tenant: Option<String>, output: Option<String>) -> Result<()> {
+    let client = connect(api_url);
+    let slug = tenant_slug(&client, tenant).await?;
+    let document = client.export_tenant_config(&slug).await?;
+
+    match output {
+        Some(path) => {
+            std::fs::write(&path, document).with_context(|| format!("Failed to write {}", path))?;
+            println!("Exported tenant {} to {}", slug, path);
+        }
+        None => print!("{}", document),
+    }
+    Ok(())
+}
+
+/// Create and update the tenant's configuration from an exported file.
+pub async fn import(
+    api_url: &str,
+    path: &str,
+    tenant: Option<String>,
+    dry_run: bool,
+) -> Result<()> {
+    let document =
+        std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+    let client = connect(api_url);
+    let slug = tenant_slug(&client, tenant).await?;
+    let summary = client
+        .import_tenant_config(&slug, document, dry_run)
+        .await?;
+
+    let (created, updated) = if summary.dry_run {
+        ("Would create", "Would update")
+    } else {
+        ("Created", "Updated")
+    };
+    for entry in &summary.created {
+        println!("{} {}", created, entry);
+    }
+    for entry in &summary.updated {
+        println!("{} {}", updated, entry);
+    }
+    for warning in &summary.warnings {
+        println!("Warning: {}", warning);
+    }
+    println!(
+        "{} created, {} updated, {} unchanged",
+        summary.created.len(),
+        summary.updated.len(),
+        summary.unchanged.len()
+    );
+    Ok(())
+}
@@ -0,0 +1,115 @@
+//! API token commands.
+
+use super::client::connect;
+use anyhow::{Context, Result, bail};
+use buildit_client::Client;
+use buildit_client::tokens::CreateApiKey;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// The organization to manage tokens in: the given one, else the one the
+/// caller acts in.
+async fn organization(client: &Client, org: Option<String>) -> Result<Uuid> {
+    if let Some(org) = org {
+        return org.parse().context("Invalid organization ID");
+    }
+    match client.me().await?.organization_id {
+        Some(org) => Ok(org),
+        None => bail!("You belong to several organizations; pass --org"),
+    }
+}
+
+pub async fn create(
+    api_url: &str,
+    org: Option<String>,
+    name: &str,
+    scopes: Vec<String>,
+    tenant: Option<String>,
+    expires_in_days: Option<i64>,
+) -> Result<()> {
+    let client = connect(api_url);
+    let org = organization(&client, org).await?;
+    let tenant_id = tenant
+        .map(|t| t.parse())
+        .transpose()
+        .context("Invalid tenant ID")?;
+    let expires_at = expires_in_days.map(|days| Utc::now() + chrono::Duration::days(days));
+
+    let created = client
+        .create_api_key(
+            org,
+            &CreateApiKey {
+                name: name.to_string(),
+                scopes,
+                tenant_id,
+                expires_at,
+            },
+        )
+        .await?;
+
+    let key = created.key;
+    println!(
+        "Created token {} ({}) with scopes: {}",
+        key.name,
+        key.id,
+        key.scopes.join(", ")
+    );
+    if let Some(tenant) = &key.tenant_id {
+        println!("Restricted to tenant {}", tenant);
+    }
+    if let Some(expires_at) = key.expires_at {
+        println!("Expires {}", expires_at.format("%Y-%m-%d %H:%M UTC"));
+    }
+    println!();
+    println!("{}", created.token);
+    println!();
+    println!("Copy it now; it will not be shown again.");
+    Ok(())
+}
+
+pub async fn list(api_url: &str, org: Option<String>) -> Result<()> {
+    let client = connect(api_url);
+    let org = organization(&client, org).await?;
+    let keys = client.list_api_keys(org).await?;
+
+    if keys.is_empty() {
+        println!("No API tokens");
+        return Ok(());
+    }
+    for key in keys {
+        println!(
+            "{}  {:<24} {}...  {:<28} last used {:<16} expires {}",
+            key.id,
+            key.name,
+            key.key_prefix,
+            key.scopes.join(","),
+            format_time(key.last_used_at),
+            format_time(key.expires_at),
+        );
+    }
+    Ok(())
+}
+
+/// Revoke a token by ID or by its prefix.
+pub async fn revoke(api_url: &str, org: Option<String>, token: &str) -> Result<()> {
+    let client = connect(api_url);
+    let org = organization(&client, org).await?;
+
+    let keys = client.list_api_keys(org).await?;
+    let prefix = token.trim_end_matches("...");
+    let Some(key) = keys
+        .into_iter()
+        .find(|k| k.id.to_string() == token || k.key_prefix == prefix)
+    else {
+        bail!("No token '{}'", token);
+    };
+
+    client.revoke_api_key(org, key.id).await?;
+    println!("Revoked token {} ({})", key.name, key.key_prefix);
+    Ok(())
+}
+
+fn format_time(time: Option<DateTime<Utc>>) -> String {
+    time.map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| "never".to_string())
+}
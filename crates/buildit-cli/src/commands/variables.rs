@@ -0,0 +1,173 @@
+//! Variable group commands.
+
+use super::client::connect;
+use anyhow::{Context, Result, bail};
+use buildit_client::Client;
+use buildit_client::variables::{CreateVariableGroup, UpdateVariableGroup, VariableGroup};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Parse `NAME=VALUE` arguments.
+pub(crate) fn parse_assignments(assignments: &[String]) -> Result<HashMap<String, String>> {
+    assignments
+        .iter()
+        .map(|assignment| match assignment.split_once('=') {
+            Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+            _ => bail!("Expected NAME=VALUE, got '{}'", assignment),
+        })
+        .collect()
+}
+
+async fn pipeline_id(client: &Client, pipeline: Option<&str>) -> Result<Option<Uuid>> {
+    match pipeline {
+        Some(pipeline) => Ok(Some(super::pipelines::resolve(client, pipeline).await?.id)),
+        None => Ok(None),
+    }
+}
+
+/// A group of the tenant, or of `pipeline`, by ID or name.
+async fn resolve(client: &Client, group: &str, pipeline: Option<&str>) -> Result<VariableGroup> {
+    let groups = client
+        .list_variable_groups(pipeline_id(client, pipeline).await?)
+        .await?;
+    let id: Option<Uuid> = group.parse().ok();
+    match groups
+        .into_iter()
+        .find(|g| Some(g.id) == id || g.name == group)
+    {
+        Some(group) => Ok(group),
+        None => bail!("Variable group '{}' not found", group),
+    }
+}
+
+pub async fn list(api_url: &str, pipeline: Option<String>) -> Result<()> {
+    let client = connect(api_url);
+    let groups = client
+        .list_variable_groups(pipeline_id(&client, pipeline.as_deref()).await?)
+        .await?;
+
+    if groups.is_empty() {
+        println!("No variable groups");
+        return Ok(());
+    }
+    for group in groups {
+        println!(
+            "{:<24} {}  {}",
+            group.name,
+            group.id,
+            group.description.unwrap_or_default()
+        );
+        let mut variables: Vec<_> = group.variables.into_iter().collect();
+        variables.sort();
+        for (name, value) in variables {
+            println!("  {}={}", name, value);
+        }
+    }
+    Ok(())
+}
+
+pub async fn create(
+    api_url: &str,
+    name: &str,
+    pipeline: Option<String>,
+    description: Option<String>,
+    variables: &[String],
+) -> Result<()> {
+    let client = connect(api_url);
+    let group = client
+        .create_variable_group(&CreateVariableGroup {
+            name: name.to_string(),
+            description,
+            pipeline_id: pipeline_id(&client, pipeline.as_deref()).await?,
+            variables: parse_assignments(variables)?,
+        })
+        .await?;
+
+    println!("Created variable group {} ({})", group.name, group.id);
+    Ok(())
+}
+
+/// Set variables of a group, keeping its others.
+pub async fn set(
+    api_url: &str,
+    group: &str,
+    pipeline: Option<String>,
+    assignments: &[String],
+) -> Result<()> {
+    let client = connect(api_url);
+    let group = resolve(&client, group, pipeline.as_deref()).await?;
+    let mut variables = group.variables;
+    variables.extend(parse_assignments(assignments)?);
+    save(&client, group.id, variables).await
+}
+
+/// Remove variables from a group.
+pub async fn unset(
+    api_url: &str,
+    group: &str,
+    pipeline: Option<String>,
+    names: &[String],
+) -> Result<()> {
+    let client = connect(api_url);
+    let group = resolve(&client, group, pipeline.as_deref()).await?;
+    let mut variables = group.variables;
+    for name in names {
+        if variables.remove(name).is_none() {
+            bail!("{} has no variable {}", group.name, name);
+        }
+    }
+    save(&client, group.id, variables).await
+}
+
+async fn save(client: &Client, id: Uuid, variables: HashMap<String, String>) -> Result<()> {
+    let group = client
+        .update_variable_group(
+            id,
+            &UpdateVariableGroup {
+                variables: Some(variables),
+                ..Default::default()
+            },
+        )
+        .await?;
+    println!(
+        "Updated variable group {} ({} variables)",
+        group.name,
+        group.variables.len()
+    );
+    Ok(())
+}
+
+pub async fn delete(api_url: &str, group: &str, pipeline: Option<String>) -> Result<()> {
+    let client = connect(api_url);
+    let group = resolve(&client, group, pipeline.as_deref()).await?;
+    client.delete_variable_group(group.id).await?;
+    println!("Deleted variable group {}", group.name);
+    Ok(())
+}
+
+/// Print the variables a run's jobs got and where each came from.
+pub async fn run_variables(api_url: &str, id: &str, pipeline: &str) -> Result<()> {
+    let run_id: Uuid = id.parse().context("Invalid run ID")?;
+    let client = connect(api_url);
+    let pipeline = super::pipelines::resolve(&client, pipeline).await?;
+    let variables = client.run_variables(pipeline.id, run_id).await?;
+
+    if variables.is_empty() {
+        println!("No variables recorded; the run has not started");
+        return Ok(());
+    }
+    for variable in variables {
+        let overrides = if variable.overrides.is_empty() {
+            String::new()
+        } else {
+            let overridden: Vec<String> =
+                variable.overrides.iter().map(|s| s.to_string()).collect();
+            format!(" (overrides {})", overridden.join(", "))
+        };
+        println!(
+            "{}={}  from {}{}",
+            variable.name, variable.value, variable.source, overrides
+        );
+    }
+    Ok(())
+}
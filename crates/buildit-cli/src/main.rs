@@ -32,13 +32,23 @@ enum Commands {
         /// Only run specific stages
         #[arg(long)]
         stage: Option<Vec<String>>,
+
+        /// Value of a parameter the pipeline declares, as NAME=VALUE;
+        /// repeatable
+        #[arg(long = "param", value_name = "NAME=VALUE")]
+        params: Vec<String>,
     },
-    /// Authenticate with the API server
+    /// Sign in to the API server
     Login {
-        /// Authentication token
+        /// Store this API token instead of signing in through the browser
         #[arg(long)]
         token: Option<String>,
+        /// Print the sign-in link without opening a browser
+        #[arg(long)]
+        no_browser: bool,
     },
+    /// Sign out of the API server, forgetting the stored token
+    Logout,
     /// List pipelines
     Pipelines {
         #[command(subcommand)]
@@ -49,20 +59,123 @@ enum Commands {
         #[command(subcommand)]
         command: RunCommands,
     },
+    /// Open a shell in a copy of a failed stage's job, with the stage's
+    /// image, environment and workspace
+    Debug {
+        /// Run ID
+        run: String,
+        /// Failed stage to debug
+        stage: String,
+        /// Pipeline name or ID
+        #[arg(long)]
+        pipeline: String,
+        /// Minutes until the copy is removed (at most 120)
+        #[arg(long)]
+        ttl: Option<i64>,
+    },
     /// Deploy a service
     Deploy {
         /// Service name
         service: String,
         /// Target environment
         environment: String,
-        /// Image to deploy
+        /// Image to deploy instead of the service's current image
         #[arg(long)]
         image: Option<String>,
+        /// Branch the release comes from, for environments restricted to
+        /// some branches
+        #[arg(long)]
+        branch: Option<String>,
+        /// Return once the deployment is queued instead of waiting for it
+        #[arg(long)]
+        no_wait: bool,
+        /// Show what the deployment would change without deploying
+        #[arg(long)]
+        dry_run: bool,
     },
+    /// Promote a service to the next environment in its promotion chain, or
+    /// switch traffic to a blue/green deployment
+    Promote {
+        /// Service name, or a blue/green deployment ID without --to
+        target: String,
+        /// Environment to promote the service into
+        #[arg(long)]
+        to: Option<String>,
+        /// Return once the promotion is queued instead of waiting for it
+        #[arg(long)]
+        no_wait: bool,
+    },
+    /// Approve a deployment that is waiting for approval
+    Approve {
+        /// Deployment ID
+        deployment: String,
+        /// Reject the deployment instead
+        #[arg(long)]
+        reject: bool,
+        /// Comment to record with the review
+        #[arg(long, short)]
+        comment: Option<String>,
+        /// Return once the deployment is queued instead of waiting for it
+        #[arg(long)]
+        no_wait: bool,
+    },
+    /// List deployments waiting for approval
+    Approvals,
     /// Rollback a deployment
     Rollback {
         /// Deployment ID or service name
         target: String,
+        /// Environment, when rolling back by service name
+        #[arg(long, short)]
+        environment: Option<String>,
+        /// Redeploy this version instead of the previous one
+        #[arg(long)]
+        to: Option<String>,
+        /// Return once the rollback is queued instead of waiting for it
+        #[arg(long)]
+        no_wait: bool,
+    },
+    /// List tenants and switch between them
+    Tenants {
+        #[command(subcommand)]
+        command: TenantCommands,
+    },
+    /// Write the tenant's pipelines, environments, targets, variable groups
+    /// and notification channels to a YAML file, without secrets
+    Export {
+        /// File to write; stdout when left out
+        #[arg(long, short)]
+        output: Option<String>,
+        /// Tenant slug (defaults to the current tenant)
+        #[arg(long)]
+        tenant: Option<String>,
+    },
+    /// Create and update the tenant's configuration from an exported file;
+    /// nothing is deleted
+    Import {
+        /// Exported YAML or JSON file
+        path: String,
+        /// Tenant slug (defaults to the current tenant)
+        #[arg(long)]
+        tenant: Option<String>,
+        /// Show what would change without changing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Manage API tokens
+    Tokens {
+        #[command(subcommand)]
+        command: TokenCommands,
+    },
+    /// Manage variable groups, non-secret variables jobs get
+    Variables {
+        #[command(subcommand)]
+        command: VariableCommands,
+    },
+    /// Evaluate interpolation expressions
+    Expr {
+        #[command(subcommand)]
+        command: ExprCommands,
     },
     /// Validate a pipeline configuration
     Validate {
@@ -80,6 +193,17 @@ enum PipelineCommands {
         #[arg(long)]
         tenant: Option<String>,
     },
+    /// Search pipelines by name, repository or label
+    Search {
+        /// Search text
+        query: String,
+        /// Tenant ID (defaults to the current tenant)
+        #[arg(long)]
+        tenant: Option<String>,
+        /// Maximum number of results
+        #[arg(long, default_value = "20")]
+        limit: u32,
+    },
     /// Trigger a pipeline run
     Trigger {
         /// Pipeline name or ID
@@ -87,6 +211,146 @@ enum PipelineCommands {
         /// Branch to build
         #[arg(long)]
         branch: Option<String>,
+        /// Variable for every job, as NAME=VALUE, overriding variable
+        /// groups and the configuration; repeatable
+        #[arg(long = "var", value_name = "NAME=VALUE")]
+        variables: Vec<String>,
+        /// Value of a parameter the pipeline declares, as NAME=VALUE;
+        /// repeatable
+        #[arg(long = "param", value_name = "NAME=VALUE")]
+        params: Vec<String>,
+    },
+    /// List the releases a pipeline's release stages published
+    Releases {
+        /// Pipeline name or ID
+        pipeline: String,
+        /// Maximum number of releases
+        #[arg(long, default_value = "20")]
+        limit: u32,
+    },
+}
+
+#[derive(Subcommand)]
+enum ExprCommands {
+    /// Evaluate an expression, such as "lower(git.branch)", or a template
+    /// with ${...} interpolations, against the current repository
+    Eval {
+        /// Expression or template
+        expression: String,
+        /// Branch, instead of the checked out one
+        #[arg(long)]
+        branch: Option<String>,
+        /// Tag, instead of the one at HEAD
+        #[arg(long)]
+        tag: Option<String>,
+        /// Commit SHA, instead of HEAD's
+        #[arg(long)]
+        sha: Option<String>,
+        /// Environment variable as NAME=VALUE; repeatable
+        #[arg(long = "var", value_name = "NAME=VALUE")]
+        variables: Vec<String>,
+        /// Output of a stage that succeeded, as STAGE.KEY=VALUE; repeatable
+        #[arg(long = "output", value_name = "STAGE.KEY=VALUE")]
+        outputs: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum TenantCommands {
+    /// List the tenants you can act in
+    List,
+    /// Act in another tenant from now on
+    Switch {
+        /// Tenant slug or ID
+        tenant: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum TokenCommands {
+    /// Issue an API token; its secret is shown once
+    Create {
+        /// Token name
+        name: String,
+        /// Scope, repeatable: read, write, admin, or e.g. pipelines:write
+        #[arg(long = "scope", default_value = "read")]
+        scopes: Vec<String>,
+        /// Restrict the token to one tenant
+        #[arg(long)]
+        tenant: Option<String>,
+        /// Expire the token after this many days
+        #[arg(long)]
+        expires_in_days: Option<i64>,
+        /// Organization ID (defaults to yours)
+        #[arg(long)]
+        org: Option<String>,
+    },
+    /// List the organization's API tokens
+    List {
+        /// Organization ID (defaults to yours)
+        #[arg(long)]
+        org: Option<String>,
+    },
+    /// Revoke an API token
+    Revoke {
+        /// Token ID or prefix
+        token: String,
+        /// Organization ID (defaults to yours)
+        #[arg(long)]
+        org: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum VariableCommands {
+    /// List the tenant's variable groups, or a pipeline's
+    List {
+        /// Pipeline name or ID
+        #[arg(long)]
+        pipeline: Option<String>,
+    },
+    /// Create a variable group
+    Create {
+        /// Group name
+        name: String,
+        /// Pipeline name or ID, for a group of one pipeline only
+        #[arg(long)]
+        pipeline: Option<String>,
+        #[arg(long)]
+        description: Option<String>,
+        /// Variable as NAME=VALUE; repeatable
+        #[arg(long = "var", value_name = "NAME=VALUE")]
+        variables: Vec<String>,
+    },
+    /// Set variables of a group
+    Set {
+        /// Group name or ID
+        group: String,
+        /// Variables as NAME=VALUE
+        #[arg(required = true, value_name = "NAME=VALUE")]
+        variables: Vec<String>,
+        /// Pipeline name or ID, for a pipeline's group
+        #[arg(long)]
+        pipeline: Option<String>,
+    },
+    /// Remove variables from a group
+    Unset {
+        /// Group name or ID
+        group: String,
+        /// Variable names
+        #[arg(required = true)]
+        names: Vec<String>,
+        /// Pipeline name or ID, for a pipeline's group
+        #[arg(long)]
+        pipeline: Option<String>,
+    },
+    /// Delete a variable group
+    Delete {
+        /// Group name or ID
+        group: String,
+        /// Pipeline name or ID, for a pipeline's group
+        #[arg(long)]
+        pipeline: Option<String>,
     },
 }
 
@@ -119,6 +383,28 @@ enum RunCommands {
         /// Run ID
         id: String,
     },
+    /// Re-run a finished run on the same commit
+    Rerun {
+        /// Run ID
+        id: String,
+        /// Pipeline name or ID
+        #[arg(long)]
+        pipeline: String,
+        /// Only re-run stages that did not succeed, reusing the others
+        #[arg(long, conflicts_with = "from")]
+        failed: bool,
+        /// Re-run this stage and every stage after it
+        #[arg(long, value_name = "STAGE")]
+        from: Option<String>,
+    },
+    /// Show the variables a run's jobs got and where each came from
+    Variables {
+        /// Run ID
+        id: String,
+        /// Pipeline name or ID
+        #[arg(long)]
+        pipeline: String,
+    },
 }
 
 #[tokio::main]
@@ -139,18 +425,41 @@ async fn main() -> anyhow::Result<()> {
         .init();
 
     match cli.command {
-        Commands::Run { config, stage } => {
-            commands::run_local(&config, stage).await?;
+        Commands::Run {
+            config,
+            stage,
+            params,
+        } => {
+            commands::run_local(&config, stage, &params).await?;
+        }
+        Commands::Login { token, no_browser } => {
+            commands::login::login(&cli.api_url, token, !no_browser).await?;
         }
-        Commands::Login { token } => {
-            commands::login(&cli.api_url, token).await?;
+        Commands::Logout => {
+            commands::login::logout(&cli.api_url).await?;
         }
         Commands::Pipelines { command } => match command {
             PipelineCommands::List { tenant } => {
                 commands::pipelines::list(&cli.api_url, tenant).await?;
             }
-            PipelineCommands::Trigger { pipeline, branch } => {
-                commands::pipelines::trigger(&cli.api_url, &pipeline, branch).await?;
+            PipelineCommands::Search {
+                query,
+                tenant,
+                limit,
+            } => {
+                commands::pipelines::search(&cli.api_url, &query, tenant, limit).await?;
+            }
+            PipelineCommands::Trigger {
+                pipeline,
+                branch,
+                variables,
+                params,
+            } => {
+                commands::pipelines::trigger(&cli.api_url, &pipeline, branch, &variables, &params)
+                    .await?;
+            }
+            PipelineCommands::Releases { pipeline, limit } => {
+                commands::pipelines::releases(&cli.api_url, &pipeline, limit).await?;
             }
         },
         Commands::Runs { command } => match command {
@@ -166,17 +475,163 @@ async fn main() -> anyhow::Result<()> {
             RunCommands::Cancel { id } => {
                 commands::runs::cancel(&cli.api_url, &id).await?;
             }
+            RunCommands::Rerun {
+                id,
+                pipeline,
+                failed,
+                from,
+            } => {
+                commands::runs::rerun(&cli.api_url, &id, &pipeline, failed, from).await?;
+            }
+            RunCommands::Variables { id, pipeline } => {
+                commands::variables::run_variables(&cli.api_url, &id, &pipeline).await?;
+            }
         },
+        Commands::Debug {
+            run,
+            stage,
+            pipeline,
+            ttl,
+        } => {
+            commands::debug::debug(&cli.api_url, &run, &stage, &pipeline, ttl).await?;
+        }
         Commands::Deploy {
             service,
             environment,
             image,
+            branch,
+            no_wait,
+            dry_run,
+        } => {
+            if dry_run {
+                commands::deployments::preview(&cli.api_url, &service, &environment, image, branch)
+                    .await?;
+            } else {
+                commands::deployments::deploy(
+                    &cli.api_url,
+                    &service,
+                    &environment,
+                    image,
+                    branch,
+                    !no_wait,
+                )
+                .await?;
+            }
+        }
+        Commands::Promote {
+            target,
+            to,
+            no_wait,
+        } => {
+            commands::deployments::promote(&cli.api_url, &target, to, !no_wait).await?;
+        }
+        Commands::Approve {
+            deployment,
+            reject,
+            comment,
+            no_wait,
+        } => {
+            commands::deployments::approve(&cli.api_url, &deployment, reject, comment, !no_wait)
+                .await?;
+        }
+        Commands::Approvals => {
+            commands::deployments::approvals(&cli.api_url).await?;
+        }
+        Commands::Rollback {
+            target,
+            environment,
+            to,
+            no_wait,
         } => {
-            commands::deploy(&cli.api_url, &service, &environment, image).await?;
+            commands::deployments::rollback(&cli.api_url, &target, environment, to, !no_wait)
+                .await?;
         }
-        Commands::Rollback { target } => {
-            commands::rollback(&cli.api_url, &target).await?;
+        Commands::Tenants { command } => match command {
+            TenantCommands::List => {
+                commands::tenants::list(&cli.api_url).await?;
+            }
+            TenantCommands::Switch { tenant } => {
+                commands::tenants::switch(&cli.api_url, &tenant).await?;
+            }
+        },
+        Commands::Export { output, tenant } => {
+            commands::tenants::export(&cli.api_url, tenant, output).await?;
+        }
+        Commands::Import {
+            path,
+            tenant,
+            dry_run,
+        } => {
+            commands::tenants::import(&cli.api_url, &path, tenant, dry_run).await?;
         }
+        Commands::Tokens { command } => match command {
+            TokenCommands::Create {
+                name,
+                scopes,
+                tenant,
+                expires_in_days,
+                org,
+            } => {
+                commands::tokens::create(&cli.api_url, org, &name, scopes, tenant, expires_in_days)
+                    .await?;
+            }
+            TokenCommands::List { org } => {
+                commands::tokens::list(&cli.api_url, org).await?;
+            }
+            TokenCommands::Revoke { token, org } => {
+                commands::tokens::revoke(&cli.api_url, org, &token).await?;
+            }
+        },
+        Commands::Variables { command } => match command {
+            VariableCommands::List { pipeline } => {
+                commands::variables::list(&cli.api_url, pipeline).await?;
+            }
+            VariableCommands::Create {
+                name,
+                pipeline,
+                description,
+                variables,
+            } => {
+                commands::variables::create(&cli.api_url, &name, pipeline, description, &variables)
+                    .await?;
+            }
+            VariableCommands::Set {
+                group,
+                variables,
+                pipeline,
+            } => {
+                commands::variables::set(&cli.api_url, &group, pipeline, &variables).await?;
+            }
+            VariableCommands::Unset {
+                group,
+                names,
+                pipeline,
+            } => {
+                commands::variables::unset(&cli.api_url, &group, pipeline, &names).await?;
+            }
+            VariableCommands::Delete { group, pipeline } => {
+                commands::variables::delete(&cli.api_url, &group, pipeline).await?;
+            }
+        },
+        Commands::Expr { command } => match command {
+            ExprCommands::Eval {
+                expression,
+                branch,
+                tag,
+                sha,
+                variables,
+                outputs,
+            } => {
+                let context = commands::expr::EvalContext {
+                    branch,
+                    tag,
+                    sha,
+                    variables,
+                    outputs,
+                };
+                commands::expr::eval(&expression, &context)?;
+            }
+        },
         Commands::Validate { path } => {
             commands::validate(&path)?;
         }
@@ -0,0 +1,158 @@
+//! GitOps applications and their syncs.
+
+use buildit_core::application::HelmSource;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::Client;
+use crate::error::Result;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Application {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub path: String,
+    pub target_namespace: String,
+    pub sync_policy: String,
+    pub sync_status: String,
+    pub health_status: String,
+    pub synced_revision: Option<String>,
+    pub last_synced_at: Option<DateTime<Utc>>,
+    pub repository_id: Option<Uuid>,
+    pub environment_id: Option<Uuid>,
+    /// `helm` or `directory`.
+    pub source_type: String,
+    pub helm: Option<HelmSource>,
+    pub rendered_digest: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CreateApplication {
+    /// Defaults to the request's tenant.
+    pub tenant_id: Option<Uuid>,
+    pub name: String,
+    pub description: Option<String>,
+    pub repository_id: Option<Uuid>,
+    pub environment_id: Option<Uuid>,
+    pub path: String,
+    pub target_namespace: String,
+    pub sync_policy: Option<String>,
+    /// Render this Helm chart instead of the manifests at `path`.
+    pub helm: Option<HelmSource>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Sync {
+    pub id: Uuid,
+    pub application_id: Uuid,
+    pub revision: String,
+    pub status: String,
+    pub trigger_type: String,
+    pub resources_created: i32,
+    pub resources_updated: i32,
+    pub resources_deleted: i32,
+    pub error_message: Option<String>,
+    pub rendered_digest: Option<String>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A Kubernetes resource an application manages.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Resource {
+    pub id: Uuid,
+    pub api_version: String,
+    pub kind: String,
+    pub name: String,
+    pub namespace: String,
+    pub status: String,
+    pub health_status: String,
+    pub out_of_sync: bool,
+}
+
+/// Drift of an application from its synced revision.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Diff {
+    pub application_id: Uuid,
+    pub sync_status: String,
+    pub synced_revision: Option<String>,
+    pub rendered_digest: Option<String>,
+    /// Drifted resources only.
+    pub resources: Vec<ResourceDiff>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResourceDiff {
+    pub api_version: String,
+    pub kind: String,
+    pub name: String,
+    pub namespace: String,
+    pub status: String,
+    pub diff: Option<String>,
+    pub checked_at: DateTime<Utc>,
+}
+
+impl Client {
+    /// Applications of a tenant, defaulting to the request's.
+    pub async fn list_applications(&self, tenant_id: Option<Uuid>) -> Result<Vec<Application>> {
+        #[derive(Serialize)]
+        struct Query {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            tenant_id: Option<Uuid>,
+        }
+        self.get("/applications", &Query { tenant_id }).await
+    }
+
+    pub async fn get_application(&self, id: Uuid) -> Result<Application> {
+        self.get(&format!("/applications/{}", id), &()).await
+    }
+
+    pub async fn create_application(&self, application: &CreateApplication) -> Result<Application> {
+        self.post("/applications", application).await
+    }
+
+    pub async fn delete_application(&self, id: Uuid) -> Result<()> {
+        self.delete(&format!("/applications/{}", id)).await
+    }
+
+    /// Set the Helm chart an application renders; `None` switches it back
+    /// to plain manifests.
+    pub async fn update_application_helm(
+        &self,
+        id: Uuid,
+        helm: Option<&HelmSource>,
+    ) -> Result<Application> {
+        self.put(
+            &format!("/applications/{}/helm", id),
+            &serde_json::json!({ "helm": helm }),
+        )
+        .await
+    }
+
+    pub async fn list_syncs(&self, application_id: Uuid) -> Result<Vec<Sync>> {
+        self.get(&format!("/applications/{}/syncs", application_id), &())
+            .await
+    }
+
+    /// Queue a sync of an application, to `revision` if given.
+    pub async fn trigger_sync(&self, application_id: Uuid, revision: Option<&str>) -> Result<Sync> {
+        self.post(
+            &format!("/applications/{}/syncs", application_id),
+            &serde_json::json!({ "revision": revision }),
+        )
+        .await
+    }
+
+    pub async fn list_application_resources(&self, application_id: Uuid) -> Result<Vec<Resource>> {
+        self.get(&format!("/applications/{}/resources", application_id), &())
+            .await
+    }
+
+    pub async fn get_application_diff(&self, application_id: Uuid) -> Result<Diff> {
+        self.get(&format!("/applications/{}/diff", application_id), &())
+            .await
+    }
+}
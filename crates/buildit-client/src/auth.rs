@@ -0,0 +1,153 @@
+//! Credentials, the device sign-in flow and token refresh.
+//!
+//! These endpoints live under `/auth` rather than `/api/v1` and are sent
+//! without the client's own credentials, except where noted.
+
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::Client;
+use crate::error::{ClientError, ErrorBody, Result};
+
+/// Refresh access tokens this long before they expire.
+const REFRESH_MARGIN_SECS: i64 = 60;
+
+/// Credentials for one API server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Credentials {
+    pub access_token: String,
+    /// Set for device sign-ins; API keys have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl Credentials {
+    /// Whether the access token should be refreshed before use.
+    pub fn needs_refresh(&self) -> bool {
+        self.refresh_token.is_some()
+            && self
+                .expires_at
+                .is_some_and(|at| at - chrono::Duration::seconds(REFRESH_MARGIN_SECS) <= Utc::now())
+    }
+}
+
+/// Tokens issued by the device sign-in and token refresh endpoints.
+#[derive(Debug, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub expires_in: i64,
+}
+
+impl From<TokenResponse> for Credentials {
+    fn from(tokens: TokenResponse) -> Self {
+        Credentials {
+            access_token: tokens.access_token,
+            refresh_token: Some(tokens.refresh_token),
+            expires_at: Some(Utc::now() + chrono::Duration::seconds(tokens.expires_in)),
+        }
+    }
+}
+
+/// A pending device sign-in, to be approved on the web UI.
+#[derive(Debug, Deserialize)]
+pub struct DeviceCode {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    pub verification_uri_complete: String,
+    pub expires_in: u64,
+    pub interval: u64,
+}
+
+/// The state of a device sign-in.
+#[derive(Debug)]
+pub enum DevicePoll {
+    /// Not approved yet; poll again after the interval.
+    Pending,
+    /// Polling too fast; poll again after a longer interval.
+    SlowDown,
+    Denied,
+    Expired,
+    Approved(TokenResponse),
+}
+
+impl Client {
+    /// Start a device sign-in for a client described as `client_name`.
+    pub async fn start_device_login(&self, client_name: &str) -> Result<DeviceCode> {
+        self.send(
+            self.http
+                .post(format!("{}/auth/device/code", self.base_url))
+                .json(&serde_json::json!({ "client_name": client_name })),
+        )
+        .await
+    }
+
+    /// Check whether the device sign-in for `device_code` was approved.
+    pub async fn poll_device_login(&self, device_code: &str) -> Result<DevicePoll> {
+        let response = self
+            .http
+            .post(format!("{}/auth/device/token", self.base_url))
+            .json(&serde_json::json!({ "device_code": device_code }))
+            .send()
+            .await
+            .map_err(|source| ClientError::Unreachable {
+                url: self.base_url.clone(),
+                source,
+            })?;
+        let status = response.status();
+        if status.is_success() {
+            let url = response.url().to_string();
+            return response
+                .json()
+                .await
+                .map(DevicePoll::Approved)
+                .map_err(|source| ClientError::InvalidResponse { url, source });
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        let error = serde_json::from_str::<ErrorBody>(&body).map(|b| b.error);
+        match error.as_deref() {
+            Ok("authorization_pending") => Ok(DevicePoll::Pending),
+            Ok("slow_down") => Ok(DevicePoll::SlowDown),
+            Ok("access_denied") => Ok(DevicePoll::Denied),
+            Ok("expired_token") => Ok(DevicePoll::Expired),
+            _ => Err(ClientError::from_response(status, &body)),
+        }
+    }
+
+    /// End the session the client's credentials belong to.
+    pub async fn revoke_session(&self) -> Result<()> {
+        let credentials = self.credentials.lock().await.clone();
+        let Some(credentials) = credentials else {
+            return Ok(());
+        };
+        let request = self
+            .http
+            .post(format!("{}/auth/token/revoke", self.base_url))
+            .bearer_auth(&credentials.access_token)
+            .json(&serde_json::json!({ "refresh_token": credentials.refresh_token }));
+        self.execute(request).await?;
+        Ok(())
+    }
+
+    /// Check the client's credentials are accepted.
+    pub async fn verify(&self) -> Result<()> {
+        self.me().await.map(|_| ())
+    }
+}
+
+/// Exchange a refresh token for new tokens.
+pub(crate) async fn refresh(client: &Client, refresh_token: &str) -> Result<TokenResponse> {
+    let request = client
+        .http
+        .post(format!("{}/auth/token/refresh", client.base_url))
+        .json(&serde_json::json!({ "refresh_token": refresh_token }));
+    match client.send(request).await {
+        Err(e) if e.status() == Some(StatusCode::UNAUTHORIZED) => Err(ClientError::SessionExpired),
+        result => result,
+    }
+}
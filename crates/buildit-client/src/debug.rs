@@ -0,0 +1,136 @@
+//! Debug sessions: a shell in an idle copy of a failed stage's job.
+
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, tungstenite};
+use uuid::Uuid;
+
+use crate::error::{ClientError, Result};
+use crate::{Client, TENANT_HEADER, segment};
+
+/// A terminal's WebSocket: binary frames in are its input, binary frames
+/// out its output.
+pub type Terminal = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// A debug session. It is `starting` until its job runs, then `ready`,
+/// and ends `closed`, `expired` or `failed`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DebugSession {
+    pub id: Uuid,
+    pub run_id: Uuid,
+    pub stage_name: String,
+    pub status: String,
+    pub executor: Option<String>,
+    pub error_message: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+impl DebugSession {
+    /// Whether the session has ended and its job is gone.
+    pub fn is_finished(&self) -> bool {
+        self.finished_at.is_some()
+    }
+}
+
+impl Client {
+    /// Start a debug session of a failed stage, lasting `ttl_minutes` or
+    /// the server's default.
+    pub async fn debug_stage(
+        &self,
+        pipeline_id: Uuid,
+        run_id: Uuid,
+        stage: &str,
+        ttl_minutes: Option<i64>,
+    ) -> Result<DebugSession> {
+        self.post(
+            &format!(
+                "/pipelines/{}/runs/{}/stages/{}/debug",
+                pipeline_id,
+                run_id,
+                segment(stage)
+            ),
+            &serde_json::json!({ "ttl_minutes": ttl_minutes }),
+        )
+        .await
+    }
+
+    pub async fn debug_session(&self, id: Uuid) -> Result<DebugSession> {
+        self.get(&format!("/debug-sessions/{}", id), &()).await
+    }
+
+    /// Close a debug session, removing its job.
+    pub async fn close_debug_session(&self, id: Uuid) -> Result<()> {
+        self.delete(&format!("/debug-sessions/{}", id)).await
+    }
+
+    /// Open a shell in a `ready` debug session.
+    pub async fn debug_terminal(&self, id: Uuid) -> Result<Terminal> {
+        let url = websocket_url(&format!(
+            "{}/api/v1/debug-sessions/{}/terminal",
+            self.base_url, id
+        ));
+        let terminal_error = |source| ClientError::Terminal {
+            url: url.clone(),
+            source: Box::new(source),
+        };
+        let mut request = url.as_str().into_client_request().map_err(terminal_error)?;
+        let headers = request.headers_mut();
+        if let Some(tenant) = self.tenant.as_deref().and_then(|t| t.parse().ok()) {
+            headers.insert(TENANT_HEADER, tenant);
+        }
+        if let Some(token) = self.access_token().await?
+            && let Ok(value) = HeaderValue::from_str(&format!("Bearer {}", token))
+        {
+            headers.insert("authorization", value);
+        }
+        match tokio_tungstenite::connect_async(request).await {
+            Ok((terminal, _)) => Ok(terminal),
+            // Refused before the upgrade, with the API's error body
+            Err(tungstenite::Error::Http(response)) => {
+                let status = StatusCode::from_u16(response.status().as_u16())
+                    .unwrap_or(StatusCode::BAD_GATEWAY);
+                let body = response
+                    .body()
+                    .as_deref()
+                    .map(String::from_utf8_lossy)
+                    .unwrap_or_default();
+                Err(ClientError::from_response(status, &body))
+            }
+            Err(e) => Err(terminal_error(e)),
+        }
+    }
+}
+
+/// The WebSocket URL of an HTTP URL.
+fn websocket_url(url: &str) -> String {
+    if let Some(rest) = url.strip_prefix("https://") {
+        format!("wss://{}", rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        format!("ws://{}", rest)
+    } else {
+        url.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_websocket_url() {
+        assert_eq!(
+            websocket_url("https://ci.example.com/api/v1/x"),
+            "wss://ci.example.com/api/v1/x"
+        );
+        assert_eq!(
+            websocket_url("http://localhost:3000"),
+            "ws://localhost:3000"
+        );
+    }
+}
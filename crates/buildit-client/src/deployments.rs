@@ -0,0 +1,320 @@
+//! Services, environments and deployments.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::Client;
+use crate::error::Result;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Service {
+    pub id: Uuid,
+    pub name: String,
+    pub image: Option<String>,
+    pub status: String,
+    /// Team that owns the service.
+    #[serde(default)]
+    pub owner_team: Option<String>,
+    #[serde(default)]
+    pub repository_url: Option<String>,
+    #[serde(default)]
+    pub runbook_url: Option<String>,
+    #[serde(default)]
+    pub dashboard_urls: Vec<String>,
+    /// How critical the service is, from 1 (most) to 4 (least).
+    #[serde(default)]
+    pub tier: Option<i32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Environment {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub target_id: Uuid,
+    pub target_name: String,
+    pub target_type: String,
+    pub health_status: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Deployment {
+    pub id: Uuid,
+    pub service_id: Uuid,
+    pub environment_id: Uuid,
+    pub version: String,
+    pub spec_version: Option<i32>,
+    pub status: String,
+    /// The deployment in the previous environment this was promoted from.
+    #[serde(default)]
+    pub promoted_from: Option<Uuid>,
+    /// The branch the deployment comes from.
+    #[serde(default)]
+    pub branch: Option<String>,
+}
+
+impl Deployment {
+    /// Whether the deployment has settled; a blue/green deployment
+    /// awaiting promotion or a deployment awaiting approval waits on a
+    /// person, not the deployer.
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self.status.as_str(),
+            "succeeded"
+                | "failed"
+                | "cancelled"
+                | "rolled_back"
+                | "awaiting_promotion"
+                | "awaiting_approval"
+        )
+    }
+}
+
+/// An environment in a service's promotion chain.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PromotionStage {
+    pub environment_id: Uuid,
+    pub environment_name: String,
+    pub requires_approval: bool,
+    /// The last successful deployment in this environment.
+    pub current: Option<Deployment>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CreateDeployment {
+    pub service_id: Uuid,
+    pub environment_id: Uuid,
+    /// Deploy this image instead of the one in the service's current spec.
+    pub image: Option<String>,
+    /// The branch the release comes from, for environments that only take
+    /// deployments from some branches.
+    pub branch: Option<String>,
+}
+
+/// What a deployment would change, from a dry run.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeploymentPreview {
+    pub service: String,
+    pub environment: String,
+    pub spec_version: Option<i32>,
+    pub image: Option<String>,
+    pub changes: Vec<ObjectChange>,
+}
+
+/// An object a previewed deployment applies.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ObjectChange {
+    pub kind: String,
+    pub name: String,
+    pub namespace: Option<String>,
+    /// `create`, `update` or `unchanged`.
+    pub action: String,
+    /// Line diff of the object; `None` when too large to diff.
+    pub diff: Option<Vec<DiffLine>>,
+}
+
+/// A line of a diff.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DiffLine {
+    /// `context`, `added`, `removed` or `skipped`.
+    pub kind: String,
+    pub text: String,
+}
+
+/// An environment's protection rules.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Protection {
+    /// Users who may review deployments.
+    #[serde(default)]
+    pub reviewers: Vec<Uuid>,
+    /// Roles whose holders, or holders of a higher role, may review.
+    #[serde(default)]
+    pub reviewer_roles: Vec<String>,
+    pub required_approvals: i32,
+    /// Minutes to wait after approval before rolling out.
+    #[serde(default)]
+    pub wait_minutes: i32,
+    /// Glob patterns of the branches deployments must come from.
+    #[serde(default)]
+    pub branches: Vec<String>,
+}
+
+/// A review of a deployment.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Review {
+    pub id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub api_key_id: Option<Uuid>,
+    pub approved: bool,
+    pub comment: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A deployment waiting for review.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PendingApproval {
+    pub deployment: Deployment,
+    pub service_name: String,
+    pub environment_name: String,
+    pub approvals: i32,
+    pub required_approvals: i32,
+    pub reviews: Vec<Review>,
+    /// Whether the caller may review it.
+    pub can_review: bool,
+}
+
+/// Filters for [`Client::list_deployments`].
+#[derive(Debug, Clone, Serialize)]
+pub struct ListDeployments {
+    pub service_id: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub environment_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<i64>,
+}
+
+impl Client {
+    /// Services, optionally only the one named `name`.
+    pub async fn list_services(&self, name: Option<&str>) -> Result<Vec<Service>> {
+        #[derive(Serialize)]
+        struct Query<'a> {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            name: Option<&'a str>,
+        }
+        self.get("/deployment/services", &Query { name }).await
+    }
+
+    pub async fn list_environments(&self) -> Result<Vec<Environment>> {
+        self.get("/deployment/environments", &()).await
+    }
+
+    pub async fn create_deployment(&self, deployment: &CreateDeployment) -> Result<Deployment> {
+        self.post("/deployment/deployments", deployment).await
+    }
+
+    /// Dry-run a deployment: what it would change, without making it.
+    pub async fn preview_deployment(
+        &self,
+        deployment: &CreateDeployment,
+    ) -> Result<DeploymentPreview> {
+        self.post("/deployment/preview", deployment).await
+    }
+
+    pub async fn get_deployment(&self, id: Uuid) -> Result<Deployment> {
+        self.get(&format!("/deployment/deployments/{}", id), &())
+            .await
+    }
+
+    /// Deployments of a service, newest first.
+    pub async fn list_deployments(&self, filter: &ListDeployments) -> Result<Vec<Deployment>> {
+        self.get("/deployment/deployments", filter).await
+    }
+
+    /// Roll a deployment back: flip traffic back to the previous version,
+    /// or with `to`, deploy the last good deployment of that version again.
+    pub async fn rollback_deployment(&self, id: Uuid, to: Option<&str>) -> Result<Deployment> {
+        self.post(
+            &format!("/deployment/deployments/{}/rollback", id),
+            &serde_json::json!({ "to": to }),
+        )
+        .await
+    }
+
+    /// The environments a service is promoted through, in order.
+    pub async fn get_promotion_chain(&self, service_id: Uuid) -> Result<Vec<PromotionStage>> {
+        self.get(
+            &format!("/deployment/services/{}/promotion-chain", service_id),
+            &(),
+        )
+        .await
+    }
+
+    /// Set the environments a service is promoted through, in order.
+    pub async fn set_promotion_chain(
+        &self,
+        service_id: Uuid,
+        environments: &[Uuid],
+    ) -> Result<Vec<PromotionStage>> {
+        self.put(
+            &format!("/deployment/services/{}/promotion-chain", service_id),
+            &serde_json::json!({ "environments": environments }),
+        )
+        .await
+    }
+
+    /// Deploy what the previous environment in the service's promotion
+    /// chain last deployed successfully to `environment_id`.
+    pub async fn promote_service(
+        &self,
+        service_id: Uuid,
+        environment_id: Uuid,
+    ) -> Result<Deployment> {
+        self.post(
+            &format!("/deployment/services/{}/promote", service_id),
+            &serde_json::json!({ "environment_id": environment_id }),
+        )
+        .await
+    }
+
+    /// Approve a deployment waiting for approval. It starts rolling out
+    /// once it has all the approvals its environment requires.
+    pub async fn approve_deployment(&self, id: Uuid, comment: Option<&str>) -> Result<Deployment> {
+        self.post(
+            &format!("/deployment/deployments/{}/approve", id),
+            &serde_json::json!({ "comment": comment }),
+        )
+        .await
+    }
+
+    /// Reject a deployment waiting for approval.
+    pub async fn reject_deployment(&self, id: Uuid, comment: Option<&str>) -> Result<Deployment> {
+        self.post(
+            &format!("/deployment/deployments/{}/reject", id),
+            &serde_json::json!({ "comment": comment }),
+        )
+        .await
+    }
+
+    /// Deployments in the current tenant waiting for approval.
+    pub async fn list_pending_approvals(&self) -> Result<Vec<PendingApproval>> {
+        self.get("/deployment/approvals", &()).await
+    }
+
+    pub async fn get_environment_protection(&self, environment_id: Uuid) -> Result<Protection> {
+        self.get(
+            &format!("/deployment/environments/{}/protection", environment_id),
+            &(),
+        )
+        .await
+    }
+
+    pub async fn set_environment_protection(
+        &self,
+        environment_id: Uuid,
+        protection: &Protection,
+    ) -> Result<Protection> {
+        self.put(
+            &format!("/deployment/environments/{}/protection", environment_id),
+            protection,
+        )
+        .await
+    }
+
+    /// A deployment followed by the deployments it was promoted from.
+    pub async fn get_deployment_lineage(&self, id: Uuid) -> Result<Vec<Deployment>> {
+        self.get(&format!("/deployment/deployments/{}/lineage", id), &())
+            .await
+    }
+
+    /// Switch traffic to a blue/green deployment awaiting promotion.
+    pub async fn promote_deployment(&self, id: Uuid) -> Result<()> {
+        let _: serde_json::Value = self
+            .post(
+                &format!("/deployment/deployments/{}/promote", id),
+                &serde_json::json!({}),
+            )
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+//! Errors returned by the client.
+
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    #[error("Failed to reach API at {url}")]
+    Unreachable {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    /// The server answered with an error status.
+    #[error("API request failed ({status}): {message}")]
+    Api { status: StatusCode, message: String },
+
+    #[error("Invalid response from {url}")]
+    InvalidResponse {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    #[error("Failed to open a terminal at {url}")]
+    Terminal {
+        url: String,
+        #[source]
+        source: Box<tokio_tungstenite::tungstenite::Error>,
+    },
+
+    /// The refresh token was rejected; the user has to sign in again.
+    #[error("Your session has expired; run `buildit login` again")]
+    SessionExpired,
+}
+
+/// Error body the API sends with error statuses.
+#[derive(Debug, Deserialize)]
+pub(crate) struct ErrorBody {
+    pub error: String,
+}
+
+impl ClientError {
+    /// An [`ClientError::Api`] for an error response, taking the message
+    /// from its JSON body where there is one.
+    pub(crate) fn from_response(status: StatusCode, body: &str) -> Self {
+        let message = serde_json::from_str::<ErrorBody>(body)
+            .map(|b| b.error)
+            .unwrap_or_else(|_| body.to_string());
+        ClientError::Api { status, message }
+    }
+
+    /// The status the server answered with, if it answered.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            ClientError::Api { status, .. } => Some(*status),
+            ClientError::SessionExpired => Some(StatusCode::UNAUTHORIZED),
+            _ => None,
+        }
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.status() == Some(StatusCode::NOT_FOUND)
+    }
+}
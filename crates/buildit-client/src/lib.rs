@@ -0,0 +1,235 @@
+//! Typed async client for the BuildIt API.
+//!
+//! ```no_run
+//! # async fn example() -> buildit_client::Result<()> {
+//! let client = buildit_client::Client::new("http://localhost:3000")
+//!     .with_token("bk_...")
+//!     .with_tenant("acme");
+//! for pipeline in client.list_pipelines(&Default::default()).await? {
+//!     println!("{} {}", pipeline.id, pipeline.name);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Each module adds the calls of one area of the API to [`Client`], along
+//! with the types they send and receive. Listings the API pages through
+//! can be read as a stream with [`pagination::paginate`].
+
+pub mod applications;
+pub mod auth;
+pub mod debug;
+pub mod deployments;
+pub mod error;
+pub mod pagination;
+pub mod pipelines;
+pub mod runs;
+pub mod stacks;
+pub mod tenants;
+pub mod tokens;
+pub mod variables;
+
+pub use auth::{Credentials, DeviceCode, DevicePoll, TokenResponse};
+pub use error::{ClientError, Result};
+pub use pagination::Page;
+
+use reqwest::{Method, RequestBuilder, Response};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use tokio::sync::Mutex;
+
+/// Header naming the tenant a request acts in.
+pub(crate) const TENANT_HEADER: &str = "x-buildit-tenant";
+
+type RefreshHook = Box<dyn Fn(&Credentials) + Send + Sync>;
+
+/// A client for one BuildIt server.
+pub struct Client {
+    base_url: String,
+    http: reqwest::Client,
+    credentials: Mutex<Option<Credentials>>,
+    /// Tenant to act in, by ID or slug; the server picks one if unset.
+    tenant: Option<String>,
+    on_refresh: Option<RefreshHook>,
+}
+
+impl Client {
+    /// A client for the server at `base_url`, sending no credentials.
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            http: reqwest::Client::new(),
+            credentials: Mutex::new(None),
+            tenant: None,
+            on_refresh: None,
+        }
+    }
+
+    /// Authenticate with an API key or access token as a Bearer token.
+    pub fn with_token(self, token: impl Into<String>) -> Self {
+        self.with_credentials(Credentials {
+            access_token: token.into(),
+            refresh_token: None,
+            expires_at: None,
+        })
+    }
+
+    /// Authenticate with credentials from a device sign-in; the access
+    /// token is refreshed shortly before it expires.
+    pub fn with_credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = Mutex::new(Some(credentials));
+        self
+    }
+
+    /// Act in a tenant, by ID or slug.
+    pub fn with_tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenant = Some(tenant.into());
+        self
+    }
+
+    /// Call `hook` with the new credentials whenever they are refreshed,
+    /// e.g. to store them.
+    pub fn on_refresh(mut self, hook: impl Fn(&Credentials) + Send + Sync + 'static) -> Self {
+        self.on_refresh = Some(Box::new(hook));
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// A request to `/api/v1{path}` carrying the tenant and credentials.
+    async fn request(&self, method: Method, path: &str) -> Result<RequestBuilder> {
+        let url = format!("{}/api/v1{}", self.base_url, path);
+        let mut request = self.http.request(method, url);
+        if let Some(tenant) = &self.tenant {
+            request = request.header(TENANT_HEADER, tenant);
+        }
+        Ok(match self.access_token().await? {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        })
+    }
+
+    /// The access token to send, refreshed first if it is about to expire.
+    pub(crate) async fn access_token(&self) -> Result<Option<String>> {
+        let mut credentials = self.credentials.lock().await;
+        let Some(current) = credentials.as_mut() else {
+            return Ok(None);
+        };
+        if let Some(refresh_token) = current
+            .refresh_token
+            .as_deref()
+            .filter(|_| current.needs_refresh())
+        {
+            let refreshed: Credentials = auth::refresh(self, refresh_token).await?.into();
+            if let Some(hook) = &self.on_refresh {
+                hook(&refreshed);
+            }
+            *current = refreshed;
+        }
+        Ok(Some(current.access_token.clone()))
+    }
+
+    /// Send a request, failing on an error status.
+    async fn execute(&self, request: RequestBuilder) -> Result<Response> {
+        let response = request
+            .send()
+            .await
+            .map_err(|source| ClientError::Unreachable {
+                url: self.base_url.clone(),
+                source,
+            })?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let body = response.text().await.unwrap_or_default();
+        Err(ClientError::from_response(status, &body))
+    }
+
+    /// Send a request and decode its JSON response.
+    async fn send<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T> {
+        let response = self.execute(request).await?;
+        let url = response.url().to_string();
+        response
+            .json()
+            .await
+            .map_err(|source| ClientError::InvalidResponse { url, source })
+    }
+
+    /// GET `/api/v1{path}` and decode the JSON response.
+    pub(crate) async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &(impl Serialize + ?Sized),
+    ) -> Result<T> {
+        let request = self.request(Method::GET, path).await?.query(query);
+        self.send(request).await
+    }
+
+    /// GET `/api/v1{path}` and return the response body as text.
+    pub(crate) async fn get_text(
+        &self,
+        path: &str,
+        query: &(impl Serialize + ?Sized),
+    ) -> Result<String> {
+        let request = self.request(Method::GET, path).await?.query(query);
+        let response = self.execute(request).await?;
+        let url = response.url().to_string();
+        response
+            .text()
+            .await
+            .map_err(|source| ClientError::InvalidResponse { url, source })
+    }
+
+    /// POST a JSON body to `/api/v1{path}` and decode the JSON response.
+    pub(crate) async fn post<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &(impl Serialize + ?Sized),
+    ) -> Result<T> {
+        let request = self.request(Method::POST, path).await?.json(body);
+        self.send(request).await
+    }
+
+    /// PUT a JSON body to `/api/v1{path}` and decode the JSON response.
+    pub(crate) async fn put<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &(impl Serialize + ?Sized),
+    ) -> Result<T> {
+        let request = self.request(Method::PUT, path).await?.json(body);
+        self.send(request).await
+    }
+
+    /// PUT a text body of `content_type` to `/api/v1{path}` and decode the
+    /// JSON response.
+    pub(crate) async fn put_text<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &(impl Serialize + ?Sized),
+        content_type: &str,
+        body: String,
+    ) -> Result<T> {
+        let request = self
+            .request(Method::PUT, path)
+            .await?
+            .query(query)
+            .header(reqwest::header::CONTENT_TYPE, content_type)
+            .body(body);
+        self.send(request).await
+    }
+
+    /// DELETE `/api/v1{path}`, ignoring the response body.
+    pub(crate) async fn delete(&self, path: &str) -> Result<()> {
+        let request = self.request(Method::DELETE, path).await?;
+        self.execute(request).await?;
+        Ok(())
+    }
+}
+
+/// Encode a path segment taken from user input, such as a branch name.
+pub(crate) fn segment(value: &str) -> String {
+    urlencoding::encode(value).into_owned()
+}
@@ -0,0 +1,89 @@
+//! Reading paged listings as streams.
+
+use futures::{Stream, TryStreamExt, stream};
+use std::future::Future;
+
+use crate::error::{ClientError, Result};
+
+/// One page of a listing.
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Offset of the next page; `None` on the last page.
+    pub next_offset: Option<i64>,
+}
+
+/// Stream every item of a listing, fetching the page at each offset with
+/// `fetch` as the stream is read, starting from offset 0.
+pub fn paginate<T, F, Fut>(mut fetch: F) -> impl Stream<Item = Result<T>>
+where
+    F: FnMut(i64) -> Fut,
+    Fut: Future<Output = Result<Page<T>>>,
+{
+    stream::try_unfold(Some(0), move |offset| {
+        let page = offset.map(&mut fetch);
+        async move {
+            let Some(page) = page else {
+                return Ok(None);
+            };
+            let page = page.await?;
+            let items = stream::iter(page.items.into_iter().map(Ok::<T, ClientError>));
+            Ok(Some((items, page.next_offset)))
+        }
+    })
+    .try_flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+
+    /// Pages of `size` items over `0..total`.
+    fn pages(total: i64, size: i64) -> impl FnMut(i64) -> std::future::Ready<Result<Page<i64>>> {
+        move |offset| {
+            let end = (offset + size).min(total);
+            std::future::ready(Ok(Page {
+                items: (offset..end).collect(),
+                next_offset: (end < total).then_some(end),
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_paginate_reads_every_page() {
+        let items: Vec<i64> = paginate(pages(7, 3)).try_collect().await.unwrap();
+        assert_eq!(items, (0..7).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_paginate_empty_listing() {
+        let items: Vec<i64> = paginate(pages(0, 3)).try_collect().await.unwrap();
+        assert!(items.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_paginate_stops_at_error() {
+        let mut fetched = Vec::new();
+        let stream = paginate(|offset| {
+            fetched.push(offset);
+            std::future::ready(if offset == 0 {
+                Ok(Page {
+                    items: vec![1, 2],
+                    next_offset: Some(2),
+                })
+            } else {
+                Err(ClientError::Api {
+                    status: StatusCode::INTERNAL_SERVER_ERROR,
+                    message: "boom".to_string(),
+                })
+            })
+        });
+        let result: Result<Vec<i64>> = stream.try_collect().await;
+        assert_eq!(
+            result.unwrap_err().status(),
+            Some(StatusCode::INTERNAL_SERVER_ERROR)
+        );
+        assert_eq!(fetched, vec![0, 2]);
+    }
+}
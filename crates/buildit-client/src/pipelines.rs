@@ -0,0 +1,123 @@
+//! Pipelines.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::Client;
+use crate::error::Result;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Pipeline {
+    pub id: Uuid,
+    pub name: String,
+    pub repository: String,
+    pub labels: Vec<String>,
+    pub owners: Vec<String>,
+    pub archived_at: Option<DateTime<Utc>>,
+}
+
+/// Filters for [`Client::list_pipelines`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ListPipelines {
+    /// Defaults to the request's tenant.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<Uuid>,
+    /// Only pipelines owned by this owner.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    /// List archived pipelines instead of active ones.
+    pub archived: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PipelineSearchResult {
+    pub id: Uuid,
+    pub name: String,
+    pub repository: String,
+    pub labels: Vec<String>,
+    pub last_run_status: Option<String>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub score: f64,
+}
+
+/// A release published by a pipeline's release stage.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Release {
+    pub id: Uuid,
+    pub pipeline_run_id: Uuid,
+    pub stage_name: String,
+    pub provider: String,
+    pub tag: String,
+    pub name: String,
+    pub url: String,
+    pub commits: i32,
+    pub assets: Vec<String>,
+    pub draft: bool,
+    pub prerelease: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Client {
+    pub async fn list_pipelines(&self, filter: &ListPipelines) -> Result<Vec<Pipeline>> {
+        self.get("/pipelines", filter).await
+    }
+
+    pub async fn get_pipeline(&self, id: Uuid) -> Result<Pipeline> {
+        self.get(&format!("/pipelines/{}", id), &()).await
+    }
+
+    /// Search pipelines by name, repository or label, best match first.
+    pub async fn search_pipelines(
+        &self,
+        query: &str,
+        tenant_id: Option<Uuid>,
+        limit: u32,
+    ) -> Result<Vec<PipelineSearchResult>> {
+        #[derive(Serialize)]
+        struct Query<'a> {
+            q: &'a str,
+            limit: u32,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            tenant_id: Option<Uuid>,
+        }
+        self.get(
+            "/pipelines/search",
+            &Query {
+                q: query,
+                limit,
+                tenant_id,
+            },
+        )
+        .await
+    }
+
+    /// Releases the pipeline published, newest first.
+    pub async fn list_releases(&self, pipeline_id: Uuid, limit: u32) -> Result<Vec<Release>> {
+        #[derive(Serialize)]
+        struct Query {
+            limit: u32,
+        }
+        self.get(
+            &format!("/pipelines/{}/releases", pipeline_id),
+            &Query { limit },
+        )
+        .await
+    }
+
+    /// A pipeline by ID, or else by exact name among the listed ones.
+    pub async fn find_pipeline(&self, id_or_name: &str) -> Result<Option<Pipeline>> {
+        if let Ok(id) = id_or_name.parse::<Uuid>() {
+            return match self.get_pipeline(id).await {
+                Ok(pipeline) => Ok(Some(pipeline)),
+                Err(e) if e.is_not_found() => Ok(None),
+                Err(e) => Err(e),
+            };
+        }
+        Ok(self
+            .list_pipelines(&ListPipelines::default())
+            .await?
+            .into_iter()
+            .find(|p| p.name == id_or_name))
+    }
+}
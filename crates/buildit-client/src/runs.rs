@@ -0,0 +1,282 @@
+//! Pipeline runs, their stages and logs.
+
+use chrono::{DateTime, Utc};
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::error::Result;
+use crate::pagination::{Page, paginate};
+use crate::{Client, segment};
+
+/// Log lines fetched per request when streaming logs; the API's maximum.
+const LOG_PAGE_SIZE: i64 = 1000;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Run {
+    pub id: Uuid,
+    pub number: i64,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub duration_secs: Option<f64>,
+    /// Time spent waiting for an executor.
+    pub queued_secs: Option<f64>,
+}
+
+impl Run {
+    /// Whether the run has stopped and its status is final.
+    pub fn is_finished(&self) -> bool {
+        self.finished_at.is_some()
+    }
+}
+
+/// Why a queued run has not started.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Queue {
+    pub run_id: Uuid,
+    pub status: String,
+    pub queued_secs: Option<f64>,
+    /// Place in line for a free worker, from 1, while waiting for one.
+    pub position: Option<i64>,
+    /// Empty once the run has left the queue.
+    pub reasons: Vec<QueueReason>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct QueueReason {
+    /// e.g. `waiting_for_worker`, `concurrency_limit` or
+    /// `no_matching_executor`.
+    pub kind: String,
+    pub message: String,
+    pub stage: Option<String>,
+    /// Runs whose jobs fill the tenant's concurrent job quota.
+    pub blocking_runs: Vec<Uuid>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// The latest run of a branch, with what it produced.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BranchRun {
+    pub pipeline_id: Uuid,
+    pub run_id: Uuid,
+    pub number: i64,
+    pub status: String,
+    pub branch: String,
+    pub sha: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub duration_secs: Option<f64>,
+    pub queued_secs: Option<f64>,
+    pub artifacts: Vec<Artifact>,
+    pub images: Vec<Image>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Artifact {
+    pub id: Uuid,
+    pub stage_name: String,
+    pub name: String,
+    pub kind: String,
+    pub content_type: String,
+    pub size_bytes: i64,
+    pub digest: String,
+    #[serde(default)]
+    pub pinned: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An image built by a run, identified by digest.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Image {
+    pub stage_name: String,
+    pub name: String,
+    pub digest: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StageResult {
+    pub stage_name: String,
+    pub status: String,
+    pub queued_at: Option<DateTime<Utc>>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub duration_secs: Option<f64>,
+    pub queued_secs: Option<f64>,
+    pub error_message: Option<String>,
+    pub executor: Option<String>,
+    pub executor_id: Option<String>,
+    /// Exit code of the stage's job, once it has exited.
+    pub exit_code: Option<i32>,
+    /// Run a `reused` stage's result was taken from.
+    pub reused_from: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogLine {
+    pub id: Uuid,
+    pub stage_name: String,
+    pub timestamp: DateTime<Utc>,
+    /// `stdout`, `stderr` or `system`.
+    pub stream: String,
+    pub content: String,
+    /// Command of the stage that wrote the line, counted from 1.
+    #[serde(default)]
+    pub step: Option<i32>,
+    /// `step_start` on a command's echo, `step_end` on its exit code.
+    #[serde(default)]
+    pub frame: Option<String>,
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LogsResponse {
+    logs: Vec<LogLine>,
+    has_more: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct LogsQuery<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stage: Option<&'a str>,
+    offset: i64,
+    limit: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct LatestQuery<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<&'a str>,
+}
+
+impl Client {
+    /// The pipeline's 20 latest runs, newest first.
+    pub async fn list_runs(&self, pipeline_id: Uuid) -> Result<Vec<Run>> {
+        self.get(&format!("/pipelines/{}/runs", pipeline_id), &())
+            .await
+    }
+
+    /// Queue a run of the pipeline. `variables` override those of the
+    /// pipeline's variable groups and configuration in every job; `params`
+    /// set the parameters the pipeline declares.
+    pub async fn trigger_run(
+        &self,
+        pipeline_id: Uuid,
+        branch: Option<&str>,
+        sha: Option<&str>,
+        variables: &HashMap<String, String>,
+        params: &HashMap<String, String>,
+    ) -> Result<Run> {
+        self.post(
+            &format!("/pipelines/{}/runs", pipeline_id),
+            &serde_json::json!({
+                "branch": branch,
+                "sha": sha,
+                "variables": variables,
+                "params": params,
+            }),
+        )
+        .await
+    }
+
+    /// Queue a re-run of a finished run on the same commit. `mode` is
+    /// `all`, `failed-only` or `from-stage`, which re-runs `stage` and
+    /// everything after it.
+    pub async fn rerun(
+        &self,
+        pipeline_id: Uuid,
+        run_id: Uuid,
+        mode: &str,
+        stage: Option<&str>,
+    ) -> Result<Run> {
+        self.post(
+            &format!("/pipelines/{}/runs/{}/rerun", pipeline_id, run_id),
+            &serde_json::json!({ "mode": mode, "stage": stage }),
+        )
+        .await
+    }
+
+    /// The latest run of a branch, optionally only among runs with
+    /// `status`, e.g. the latest green build of `main`.
+    pub async fn latest_run(
+        &self,
+        pipeline_id: Uuid,
+        branch: &str,
+        status: Option<&str>,
+    ) -> Result<BranchRun> {
+        self.get(
+            &format!(
+                "/pipelines/{}/branches/{}/latest",
+                pipeline_id,
+                segment(branch)
+            ),
+            &LatestQuery { status },
+        )
+        .await
+    }
+
+    pub async fn run_stages(&self, pipeline_id: Uuid, run_id: Uuid) -> Result<Vec<StageResult>> {
+        self.get(
+            &format!("/pipelines/{}/runs/{}/stages", pipeline_id, run_id),
+            &(),
+        )
+        .await
+    }
+
+    /// A run, when only its ID is known.
+    pub async fn run(&self, run_id: Uuid) -> Result<Run> {
+        self.get(&format!("/runs/{}", run_id), &()).await
+    }
+
+    /// Why a queued run has not started.
+    pub async fn run_queue(&self, run_id: Uuid) -> Result<Queue> {
+        self.get(&format!("/runs/{}/queue", run_id), &()).await
+    }
+
+    /// The run's stages, when only the run ID is known.
+    pub async fn stages(&self, run_id: Uuid) -> Result<Vec<StageResult>> {
+        self.get(&format!("/runs/{}/stages", run_id), &()).await
+    }
+
+    /// A page of the run's log lines, optionally of one stage.
+    pub async fn run_logs(
+        &self,
+        pipeline_id: Uuid,
+        run_id: Uuid,
+        stage: Option<&str>,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Page<LogLine>> {
+        let response: LogsResponse = self
+            .get(
+                &format!("/pipelines/{}/runs/{}/logs", pipeline_id, run_id),
+                &LogsQuery {
+                    stage,
+                    offset,
+                    limit,
+                },
+            )
+            .await?;
+        let next_offset = response
+            .has_more
+            .then(|| offset + response.logs.len() as i64);
+        Ok(Page {
+            items: response.logs,
+            next_offset,
+        })
+    }
+
+    /// Every log line of the run logged so far, optionally of one stage.
+    pub fn run_log_stream<'a>(
+        &'a self,
+        pipeline_id: Uuid,
+        run_id: Uuid,
+        stage: Option<&'a str>,
+    ) -> impl Stream<Item = Result<LogLine>> + 'a {
+        paginate(move |offset| self.run_logs(pipeline_id, run_id, stage, offset, LOG_PAGE_SIZE))
+    }
+}
@@ -0,0 +1,164 @@
+//! Terraform stacks, their runs and variables.
+
+use buildit_core::stack::{PolicyResult, ResourceChange};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::Client;
+use crate::error::Result;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Stack {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub repository_id: Option<Uuid>,
+    pub path: String,
+    pub terraform_version: String,
+    pub auto_apply: bool,
+    pub status: String,
+    pub last_run_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CreateStack {
+    /// Defaults to the request's tenant.
+    pub tenant_id: Option<Uuid>,
+    pub name: String,
+    pub description: Option<String>,
+    pub repository_id: Option<Uuid>,
+    pub path: Option<String>,
+    pub terraform_version: Option<String>,
+    pub auto_apply: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StackRunType {
+    Plan,
+    Apply,
+    Destroy,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StackRun {
+    pub id: Uuid,
+    pub run_type: String,
+    pub status: String,
+    pub trigger_type: String,
+    pub resources_to_add: i32,
+    pub resources_to_change: i32,
+    pub resources_to_destroy: i32,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub error_message: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StackPlan {
+    pub run_id: Uuid,
+    pub status: String,
+    pub resources_to_add: i32,
+    pub resources_to_change: i32,
+    pub resources_to_destroy: i32,
+    pub changes: Vec<ResourceChange>,
+    #[serde(default)]
+    pub policy_results: Vec<PolicyResult>,
+}
+
+/// A stack variable; values of sensitive ones are not returned.
+#[derive(Debug, Clone, Deserialize)]
+pub struct StackVariable {
+    pub key: String,
+    pub value: Option<String>,
+    pub is_sensitive: bool,
+    /// The value is an HCL expression rather than a string.
+    pub is_hcl: bool,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SetStackVariable {
+    pub key: String,
+    pub value: Option<String>,
+    pub is_sensitive: Option<bool>,
+    pub is_hcl: Option<bool>,
+    pub description: Option<String>,
+}
+
+impl Client {
+    /// Stacks of a tenant, defaulting to the request's.
+    pub async fn list_stacks(&self, tenant_id: Option<Uuid>) -> Result<Vec<Stack>> {
+        #[derive(Serialize)]
+        struct Query {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            tenant_id: Option<Uuid>,
+        }
+        self.get("/stacks", &Query { tenant_id }).await
+    }
+
+    pub async fn get_stack(&self, id: Uuid) -> Result<Stack> {
+        self.get(&format!("/stacks/{}", id), &()).await
+    }
+
+    pub async fn create_stack(&self, stack: &CreateStack) -> Result<Stack> {
+        self.post("/stacks", stack).await
+    }
+
+    pub async fn delete_stack(&self, id: Uuid) -> Result<()> {
+        self.delete(&format!("/stacks/{}", id)).await
+    }
+
+    pub async fn list_stack_runs(&self, stack_id: Uuid) -> Result<Vec<StackRun>> {
+        self.get(&format!("/stacks/{}/runs", stack_id), &()).await
+    }
+
+    pub async fn trigger_stack_run(
+        &self,
+        stack_id: Uuid,
+        run_type: StackRunType,
+    ) -> Result<StackRun> {
+        self.post(
+            &format!("/stacks/{}/runs", stack_id),
+            &serde_json::json!({ "run_type": run_type }),
+        )
+        .await
+    }
+
+    pub async fn get_stack_run(&self, stack_id: Uuid, run_id: Uuid) -> Result<StackRun> {
+        self.get(&format!("/stacks/{}/runs/{}", stack_id, run_id), &())
+            .await
+    }
+
+    /// The resource changes a run planned.
+    pub async fn get_stack_plan(&self, stack_id: Uuid, run_id: Uuid) -> Result<StackPlan> {
+        self.get(&format!("/stacks/{}/runs/{}/plan", stack_id, run_id), &())
+            .await
+    }
+
+    /// Approve a planned run so it is applied.
+    pub async fn approve_stack_run(&self, stack_id: Uuid, run_id: Uuid) -> Result<StackRun> {
+        self.post(
+            &format!("/stacks/{}/runs/{}/approve", stack_id, run_id),
+            &serde_json::json!({}),
+        )
+        .await
+    }
+
+    pub async fn list_stack_variables(&self, stack_id: Uuid) -> Result<Vec<StackVariable>> {
+        self.get(&format!("/stacks/{}/variables", stack_id), &())
+            .await
+    }
+
+    /// Create or update a stack variable; needs the `secrets.write`
+    /// permission.
+    pub async fn set_stack_variable(
+        &self,
+        stack_id: Uuid,
+        variable: &SetStackVariable,
+    ) -> Result<StackVariable> {
+        self.post(&format!("/stacks/{}/variables", stack_id), variable)
+            .await
+    }
+}
@@ -0,0 +1,92 @@
+//! The caller, the tenants they can act in, and tenant configuration.
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::Result;
+use crate::{Client, segment};
+
+/// The authenticated caller.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Me {
+    /// `session`, `api_key` or `anonymous`.
+    pub method: String,
+    pub user_id: Option<Uuid>,
+    /// Unset when the caller belongs to several organizations and the
+    /// request named no tenant.
+    pub organization_id: Option<Uuid>,
+    pub tenant_id: Option<Uuid>,
+    pub api_key_id: Option<Uuid>,
+    /// Scopes of the API key; unset for sessions.
+    pub scopes: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Tenant {
+    pub id: Uuid,
+    pub name: String,
+    pub slug: String,
+    pub organization_id: Option<Uuid>,
+    /// The request acted in this tenant.
+    pub current: bool,
+}
+
+/// What importing a tenant configuration changed, or would change.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImportSummary {
+    pub dry_run: bool,
+    /// Entries such as `pipeline build` or `target prod-cluster`.
+    pub created: Vec<String>,
+    pub updated: Vec<String>,
+    pub unchanged: Vec<String>,
+    /// Things left to do by hand, such as setting a new target's
+    /// credentials.
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ImportQuery {
+    dry_run: bool,
+}
+
+impl Client {
+    pub async fn me(&self) -> Result<Me> {
+        self.get("/me", &()).await
+    }
+
+    /// Tenants the caller can act in.
+    pub async fn list_tenants(&self) -> Result<Vec<Tenant>> {
+        self.get("/me/tenants", &()).await
+    }
+
+    /// Switch the signed-in session to a tenant, by ID or slug, or back to
+    /// none; returns the tenant switched to.
+    pub async fn switch_tenant(&self, tenant: Option<&str>) -> Result<Option<Tenant>> {
+        self.put("/me/tenant", &serde_json::json!({ "tenant": tenant }))
+            .await
+    }
+
+    /// A tenant's pipelines, environments, targets, variable groups and
+    /// notification channels as a YAML document, without secrets.
+    pub async fn export_tenant_config(&self, slug: &str) -> Result<String> {
+        self.get_text(&format!("/tenants/{}/config", segment(slug)), &())
+            .await
+    }
+
+    /// Create and update a tenant's configuration from an exported YAML or
+    /// JSON document; with `dry_run` only report what would change.
+    pub async fn import_tenant_config(
+        &self,
+        slug: &str,
+        document: String,
+        dry_run: bool,
+    ) -> Result<ImportSummary> {
+        self.put_text(
+            &format!("/tenants/{}/config", segment(slug)),
+            &ImportQuery { dry_run },
+            "application/yaml",
+            document,
+        )
+        .await
+    }
+}
@@ -0,0 +1,62 @@
+//! Organization API keys.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::Client;
+use crate::error::Result;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub name: String,
+    /// Start of the secret, to tell keys apart.
+    pub key_prefix: String,
+    pub scopes: Vec<String>,
+    /// The key only reaches this tenant.
+    pub tenant_id: Option<Uuid>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CreateApiKey {
+    pub name: String,
+    /// Defaults to `read`.
+    pub scopes: Vec<String>,
+    pub tenant_id: Option<Uuid>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreatedApiKey {
+    #[serde(flatten)]
+    pub key: ApiKey,
+    /// The secret, returned only on creation.
+    pub token: String,
+}
+
+impl Client {
+    pub async fn list_api_keys(&self, organization_id: Uuid) -> Result<Vec<ApiKey>> {
+        self.get(&format!("/organizations/{}/api-keys", organization_id), &())
+            .await
+    }
+
+    pub async fn create_api_key(
+        &self,
+        organization_id: Uuid,
+        key: &CreateApiKey,
+    ) -> Result<CreatedApiKey> {
+        self.post(&format!("/organizations/{}/api-keys", organization_id), key)
+            .await
+    }
+
+    pub async fn revoke_api_key(&self, organization_id: Uuid, key_id: Uuid) -> Result<()> {
+        self.delete(&format!(
+            "/organizations/{}/api-keys/{}",
+            organization_id, key_id
+        ))
+        .await
+    }
+}
@@ -0,0 +1,124 @@
+//! Variable groups and the variables runs' jobs get.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use uuid::Uuid;
+
+use crate::Client;
+use crate::error::Result;
+
+/// Non-secret variables of a tenant, or of one of its pipelines.
+#[derive(Debug, Clone, Deserialize)]
+pub struct VariableGroup {
+    pub id: Uuid,
+    /// The pipeline the group applies to; all of the tenant's when unset.
+    pub pipeline_id: Option<Uuid>,
+    pub name: String,
+    pub description: Option<String>,
+    pub variables: HashMap<String, String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CreateVariableGroup {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Make the group the pipeline's rather than the whole tenant's.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pipeline_id: Option<Uuid>,
+    pub variables: HashMap<String, String>,
+}
+
+/// Changes to a group; fields left unset keep their value.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UpdateVariableGroup {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Replaces all of the group's variables.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variables: Option<HashMap<String, String>>,
+}
+
+/// Where a run's variable got its value.
+#[derive(Debug, Clone, Deserialize)]
+pub struct VariableSource {
+    /// `system`, `tenant_group`, `pipeline_group`, `pipeline`, `stage` or
+    /// `trigger`.
+    pub kind: String,
+    pub group: Option<String>,
+    pub stage: Option<String>,
+}
+
+impl fmt::Display for VariableSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.kind.as_str(), &self.group, &self.stage) {
+            ("tenant_group", Some(group), _) => write!(f, "tenant group '{}'", group),
+            ("pipeline_group", Some(group), _) => write!(f, "pipeline group '{}'", group),
+            ("pipeline", _, _) => f.write_str("pipeline env"),
+            ("stage", _, Some(stage)) => write!(f, "stage '{}'", stage),
+            (kind, _, _) => f.write_str(kind),
+        }
+    }
+}
+
+/// A variable a run's jobs got.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RunVariable {
+    pub name: String,
+    pub value: String,
+    pub source: VariableSource,
+    /// Lower layers that also set the variable, lowest first.
+    #[serde(default)]
+    pub overrides: Vec<VariableSource>,
+}
+
+#[derive(Debug, Serialize)]
+struct GroupsQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pipeline_id: Option<Uuid>,
+}
+
+impl Client {
+    /// The tenant's variable groups, or with `pipeline_id` the pipeline's.
+    pub async fn list_variable_groups(
+        &self,
+        pipeline_id: Option<Uuid>,
+    ) -> Result<Vec<VariableGroup>> {
+        self.get("/variable-groups", &GroupsQuery { pipeline_id })
+            .await
+    }
+
+    pub async fn create_variable_group(
+        &self,
+        group: &CreateVariableGroup,
+    ) -> Result<VariableGroup> {
+        self.post("/variable-groups", group).await
+    }
+
+    pub async fn update_variable_group(
+        &self,
+        id: Uuid,
+        update: &UpdateVariableGroup,
+    ) -> Result<VariableGroup> {
+        self.put(&format!("/variable-groups/{}", id), update).await
+    }
+
+    pub async fn delete_variable_group(&self, id: Uuid) -> Result<()> {
+        self.delete(&format!("/variable-groups/{}", id)).await
+    }
+
+    /// The variables a run's jobs got and where each came from, once the
+    /// run has started.
+    pub async fn run_variables(&self, pipeline_id: Uuid, run_id: Uuid) -> Result<Vec<RunVariable>> {
+        self.get(
+            &format!("/pipelines/{}/runs/{}/variables", pipeline_id, run_id),
+            &(),
+        )
+        .await
+    }
+}
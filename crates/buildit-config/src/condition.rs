@@ -0,0 +1,620 @@
+//! Stage condition evaluation and trigger simulation.
+//!
+//! `when` expressions support:
+//! - Variables: `branch`, `tag`, `event`, `actor`, `ref` (also written as
+//!   `{branch}` or `${git.branch}`)
+//! - String literals in single or double quotes
+//! - Comparisons: `==`, `!=`, `=~` (glob match)
+//! - `changed('src/**', ...)` - true if any changed file matches a glob
+//! - Boolean operators `&&`, `||`, `!` and parentheses
+//!
+//! [`simulate_pipeline`] replays a trigger event against a pipeline's
+//! triggers and stage conditions without running anything.
+
+use buildit_core::pipeline::{Stage, Trigger};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+use crate::error::{ConfigError, ConfigResult};
+
+/// A trigger event to evaluate conditions against.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct TriggerEvent {
+    /// Event kind: push, pull_request, tag, schedule, manual, webhook.
+    pub kind: String,
+    #[serde(default)]
+    pub branch: Option<String>,
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(default)]
+    pub actor: Option<String>,
+    /// Files changed by the event; `None` when not known.
+    #[serde(default)]
+    pub changed_files: Option<Vec<String>>,
+}
+
+/// Evaluate a `when` expression against a trigger event.
+pub fn evaluate_condition(expression: &str, event: &TriggerEvent) -> ConfigResult<bool> {
+    Ok(Evaluator::new(expression, event)?.run()?.0)
+}
+
+/// Whether a trigger event would start a pipeline with the given triggers.
+///
+/// A pipeline without triggers accepts every event.
+pub fn trigger_matches(triggers: &[Trigger], event: &TriggerEvent) -> bool {
+    if triggers.is_empty() {
+        return true;
+    }
+    triggers.iter().any(|trigger| match trigger {
+        Trigger::Push { branches, paths } => {
+            event.kind == "push"
+                && event
+                    .branch
+                    .as_deref()
+                    .is_some_and(|b| branches.iter().any(|p| glob_match(p, b, false)))
+                && paths
+                    .as_ref()
+                    .is_none_or(|globs| changed_matches(globs, event).unwrap_or(true))
+        }
+        Trigger::PullRequest { branches } => {
+            event.kind == "pull_request"
+                && branches.as_ref().is_none_or(|patterns| {
+                    event
+                        .branch
+                        .as_deref()
+                        .is_some_and(|b| patterns.iter().any(|p| glob_match(p, b, false)))
+                })
+        }
+        Trigger::Tag { pattern } => {
+            event.kind == "tag"
+                && event
+                    .tag
+                    .as_deref()
+                    .is_some_and(|t| pattern.as_deref().is_none_or(|p| glob_match(p, t, false)))
+        }
+        Trigger::Schedule { .. } => event.kind == "schedule",
+        Trigger::Manual => event.kind == "manual",
+        Trigger::Webhook { .. } => event.kind == "webhook",
+    })
+}
+
+/// Simulated outcome of a stage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SimulatedStatus {
+    Run,
+    /// Would run after manual approval.
+    Manual,
+    Skipped,
+    /// The `when` expression could not be evaluated.
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct StageSimulation {
+    pub name: String,
+    pub status: SimulatedStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub condition: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Result of replaying one trigger event.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SimulationResult {
+    pub event: TriggerEvent,
+    pub triggered: bool,
+    pub stages: Vec<StageSimulation>,
+    /// Assumptions made during evaluation (e.g. unknown changed files).
+    pub notes: Vec<String>,
+}
+
+/// Replay a trigger event against a pipeline's triggers and stages.
+///
+/// Stages whose dependencies are skipped are skipped as well.
+pub fn simulate_pipeline(
+    triggers: &[Trigger],
+    stages: &[Stage],
+    event: &TriggerEvent,
+) -> SimulationResult {
+    let mut notes = Vec::new();
+    let triggered = trigger_matches(triggers, event);
+    if !triggered {
+        return SimulationResult {
+            event: event.clone(),
+            triggered,
+            stages: stages
+                .iter()
+                .map(|s| StageSimulation {
+                    name: s.name.clone(),
+                    status: SimulatedStatus::Skipped,
+                    condition: s.when.as_ref().map(|c| c.expression.clone()),
+                    reason: Some("pipeline not triggered".to_string()),
+                })
+                .collect(),
+            notes,
+        };
+    }
+
+    let has_path_filter = triggers
+        .iter()
+        .any(|t| matches!(t, Trigger::Push { paths: Some(_), .. }));
+    let mut assumed_paths = has_path_filter && event.changed_files.is_none();
+
+    let mut statuses: HashMap<&str, SimulatedStatus> = HashMap::new();
+    let mut results = Vec::with_capacity(stages.len());
+    for stage in topological_order(stages) {
+        let condition = stage.when.as_ref().map(|c| c.expression.clone());
+        let blocked = stage.needs.iter().find(|dep| {
+            matches!(
+                statuses.get(dep.as_str()),
+                Some(SimulatedStatus::Skipped | SimulatedStatus::Error)
+            )
+        });
+
+        let (status, reason) = if let Some(dep) = blocked {
+            (
+                SimulatedStatus::Skipped,
+                Some(format!("dependency '{}' skipped", dep)),
+            )
+        } else {
+            let evaluated = match &condition {
+                Some(expr) => Evaluator::new(expr, event).and_then(|e| e.run()),
+                None => Ok((true, false)),
+            };
+            match evaluated {
+                Ok((true, assumed)) => {
+                    assumed_paths |= assumed;
+                    if stage.manual {
+                        (SimulatedStatus::Manual, None)
+                    } else {
+                        (SimulatedStatus::Run, None)
+                    }
+                }
+                Ok((false, _)) => (
+                    SimulatedStatus::Skipped,
+                    Some("condition evaluated to false".to_string()),
+                ),
+                Err(e) => (SimulatedStatus::Error, Some(e.to_string())),
+            }
+        };
+
+        statuses.insert(stage.name.as_str(), status);
+        results.push(StageSimulation {
+            name: stage.name.clone(),
+            status,
+            condition,
+            reason,
+        });
+    }
+
+    if assumed_paths {
+        notes.push("changed files unknown; path filters assumed to match".to_string());
+    }
+
+    SimulationResult {
+        event: event.clone(),
+        triggered,
+        stages: results,
+        notes,
+    }
+}
+
+/// Order stages so dependencies come first, keeping definition order
+/// otherwise. Stages in a cycle are appended as-is.
+fn topological_order(stages: &[Stage]) -> Vec<&Stage> {
+    let mut ordered: Vec<&Stage> = Vec::with_capacity(stages.len());
+    let mut remaining: Vec<&Stage> = stages.iter().collect();
+    while !remaining.is_empty() {
+        let before = remaining.len();
+        remaining.retain(|stage| {
+            let ready = stage.needs.iter().all(|dep| {
+                ordered.iter().any(|s| &s.name == dep) || !stages.iter().any(|s| &s.name == dep)
+            });
+            if ready {
+                ordered.push(stage);
+            }
+            !ready
+        });
+        if remaining.len() == before {
+            ordered.append(&mut remaining);
+        }
+    }
+    ordered
+}
+
+/// Match `text` against a glob. `**` matches anything; `*` and `?` stop at
+/// `/` in path mode and match any character otherwise.
+pub fn glob_match(pattern: &str, text: &str, path_mode: bool) -> bool {
+    fn go(p: &[char], t: &[char], path_mode: bool) -> bool {
+        match p.first() {
+            None => t.is_empty(),
+            Some('*') if p.get(1) == Some(&'*') => {
+                let rest = if p.get(2) == Some(&'/') {
+                    &p[3..]
+                } else {
+                    &p[2..]
+                };
+                (0..=t.len()).any(|i| go(rest, &t[i..], path_mode))
+            }
+            Some('*') => (0..=t.len())
+                .take_while(|&i| i == 0 || !(path_mode && t[i - 1] == '/'))
+                .any(|i| go(&p[1..], &t[i..], path_mode)),
+            Some('?') => {
+                t.first().is_some_and(|&c| !(path_mode && c == '/'))
+                    && go(&p[1..], &t[1..], path_mode)
+            }
+            Some(c) => t.first() == Some(c) && go(&p[1..], &t[1..], path_mode),
+        }
+    }
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    go(&p, &t, path_mode)
+}
+
+/// `None` when the event's changed files are unknown.
+fn changed_matches(globs: &[String], event: &TriggerEvent) -> Option<bool> {
+    let files = event.changed_files.as_ref()?;
+    Some(
+        files
+            .iter()
+            .any(|f| globs.iter().any(|g| glob_match(g, f, true))),
+    )
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    LParen,
+    RParen,
+    Comma,
+    Eq,
+    Ne,
+    Match,
+    And,
+    Or,
+    Not,
+}
+
+fn tokenize(expr: &str) -> ConfigResult<Vec<Token>> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    let invalid = |message: String| ConfigError::InvalidValue {
+        field: "when".to_string(),
+        message,
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        let two: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+        match c {
+            _ if c.is_whitespace() => i += 1,
+            '(' => {
+                tokens.push(Token::LParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                i += 1;
+            }
+            ',' => {
+                tokens.push(Token::Comma);
+                i += 1;
+            }
+            '\'' | '"' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&ch| ch == c)
+                    .ok_or_else(|| invalid("unterminated string".to_string()))?;
+                tokens.push(Token::Str(chars[i + 1..i + 1 + end].iter().collect()));
+                i += end + 2;
+            }
+            '$' | '{' => {
+                // `{branch}` / `${git.branch}` variable forms
+                let start = if c == '$' { i + 2 } else { i + 1 };
+                let end = chars[i..]
+                    .iter()
+                    .position(|&ch| ch == '}')
+                    .ok_or_else(|| invalid("unterminated variable".to_string()))?;
+                tokens.push(Token::Ident(chars[start..i + end].iter().collect()));
+                i += end + 1;
+            }
+            _ if two == "==" => {
+                tokens.push(Token::Eq);
+                i += 2;
+            }
+            _ if two == "!=" => {
+                tokens.push(Token::Ne);
+                i += 2;
+            }
+            _ if two == "=~" => {
+                tokens.push(Token::Match);
+                i += 2;
+            }
+            _ if two == "&&" => {
+                tokens.push(Token::And);
+                i += 2;
+            }
+            _ if two == "||" => {
+                tokens.push(Token::Or);
+                i += 2;
+            }
+            '!' => {
+                tokens.push(Token::Not);
+                i += 1;
+            }
+            _ if c.is_alphanumeric() || c == '_' => {
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|ch| ch.is_alphanumeric() || **ch == '_' || **ch == '.')
+                    .count();
+                tokens.push(Token::Ident(chars[i..i + len].iter().collect()));
+                i += len;
+            }
+            _ => return Err(invalid(format!("unexpected character '{}'", c))),
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive-descent evaluator over the token stream.
+struct Evaluator<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    event: &'a TriggerEvent,
+    /// Set when `changed()` was evaluated without known changed files.
+    assumed_paths: bool,
+}
+
+impl<'a> Evaluator<'a> {
+    fn new(expression: &str, event: &'a TriggerEvent) -> ConfigResult<Self> {
+        Ok(Self {
+            tokens: tokenize(expression)?,
+            pos: 0,
+            event,
+            assumed_paths: false,
+        })
+    }
+
+    /// Returns the result and whether changed files had to be assumed.
+    fn run(mut self) -> ConfigResult<(bool, bool)> {
+        let value = self.or()?;
+        if let Some(token) = self.peek() {
+            return Err(self.error(format!("unexpected token {:?}", token)));
+        }
+        Ok((value, self.assumed_paths))
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error(&self, message: String) -> ConfigError {
+        ConfigError::InvalidValue {
+            field: "when".to_string(),
+            message,
+        }
+    }
+
+    fn or(&mut self) -> ConfigResult<bool> {
+        let mut value = self.and()?;
+        while self.eat(&Token::Or) {
+            let rhs = self.and()?;
+            value = value || rhs;
+        }
+        Ok(value)
+    }
+
+    fn and(&mut self) -> ConfigResult<bool> {
+        let mut value = self.unary()?;
+        while self.eat(&Token::And) {
+            let rhs = self.unary()?;
+            value = value && rhs;
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> ConfigResult<bool> {
+        if self.eat(&Token::Not) {
+            return Ok(!self.unary()?);
+        }
+        if self.eat(&Token::LParen) {
+            let value = self.or()?;
+            if !self.eat(&Token::RParen) {
+                return Err(self.error("expected ')'".to_string()));
+            }
+            return Ok(value);
+        }
+        if let Some(Token::Ident(name)) = self.peek()
+            && name == "changed"
+            && self.tokens.get(self.pos + 1) == Some(&Token::LParen)
+        {
+            self.pos += 2;
+            return self.changed();
+        }
+
+        let lhs = self.operand()?;
+        let op = match self.peek() {
+            Some(Token::Eq | Token::Ne | Token::Match) => self.next(),
+            _ => None,
+        };
+        let Some(op) = op else {
+            return Ok(lhs.is_some_and(|v| !v.is_empty() && v != "false"));
+        };
+        let rhs = self.operand()?;
+        Ok(match op {
+            Token::Eq => lhs == rhs,
+            Token::Ne => lhs != rhs,
+            _ => match (lhs, rhs) {
+                (Some(value), Some(pattern)) => glob_match(&pattern, &value, false),
+                _ => false,
+            },
+        })
+    }
+
+    fn changed(&mut self) -> ConfigResult<bool> {
+        let mut globs = Vec::new();
+        loop {
+            match self.next() {
+                Some(Token::Str(glob)) => globs.push(glob),
+                _ => return Err(self.error("changed() expects string globs".to_string())),
+            }
+            if self.eat(&Token::RParen) {
+                break;
+            }
+            if !self.eat(&Token::Comma) {
+                return Err(self.error("expected ',' or ')'".to_string()));
+            }
+        }
+        let event = self.event;
+        Ok(changed_matches(&globs, event).unwrap_or_else(|| {
+            self.assumed_paths = true;
+            true
+        }))
+    }
+
+    fn operand(&mut self) -> ConfigResult<Option<String>> {
+        match self.next() {
+            Some(Token::Str(s)) => Ok(Some(s)),
+            Some(Token::Ident(name)) => self.variable(&name),
+            other => Err(self.error(format!("expected a value, found {:?}", other))),
+        }
+    }
+
+    fn variable(&self, name: &str) -> ConfigResult<Option<String>> {
+        let event = self.event;
+        Ok(match name {
+            "branch" | "git.branch" => event.branch.clone(),
+            "tag" | "git.tag" => event.tag.clone(),
+            "ref" | "git.ref" => event.tag.clone().or_else(|| event.branch.clone()),
+            "event" | "trigger" | "run.trigger" => Some(event.kind.clone()),
+            "actor" => event.actor.clone(),
+            "true" => Some("true".to_string()),
+            "false" => Some("false".to_string()),
+            _ => return Err(self.error(format!("unknown variable '{}'", name))),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use buildit_core::pipeline::{StageAction, StageCondition};
+
+    fn push(branch: &str, files: Option<&[&str]>) -> TriggerEvent {
+        TriggerEvent {
+            kind: "push".to_string(),
+            branch: Some(branch.to_string()),
+            changed_files: files.map(|f| f.iter().map(|s| s.to_string()).collect()),
+            ..Default::default()
+        }
+    }
+
+    fn stage(name: &str, needs: &[&str], when: Option<&str>) -> Stage {
+        Stage {
+            name: name.to_string(),
+            needs: needs.iter().map(|s| s.to_string()).collect(),
+            when: when.map(|e| StageCondition {
+                expression: e.to_string(),
+            }),
+            manual: false,
+            action: StageAction::Run {
+                image: "alpine".to_string(),
+                commands: vec![],
+                artifacts: vec![],
+                test_reports: vec![],
+            },
+            env: HashMap::new(),
+            runs_on: vec![],
+            resources: Default::default(),
+            platform: Default::default(),
+            wait_for: vec![],
+            needs_artifacts: vec![],
+            security_context: None,
+            container: Default::default(),
+            pull_policy: None,
+        }
+    }
+
+    #[test]
+    fn test_evaluate_condition() {
+        let event = push("release/1.2", Some(&["docs/index.md"]));
+        assert!(evaluate_condition("branch =~ 'release/*'", &event).unwrap());
+        assert!(evaluate_condition("{branch} != 'main' && event == 'push'", &event).unwrap());
+        assert!(!evaluate_condition("${git.branch} == 'main' || tag", &event).unwrap());
+        assert!(evaluate_condition("changed('docs/**')", &event).unwrap());
+        assert!(!evaluate_condition("changed('src/*.rs', 'Cargo.toml')", &event).unwrap());
+        assert!(evaluate_condition("!(branch == 'main')", &event).unwrap());
+        assert!(evaluate_condition("branch ==", &event).is_err());
+        assert!(evaluate_condition("bogus == 'x'", &event).is_err());
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("src/**/*.rs", "src/a/b/lib.rs", true));
+        assert!(glob_match("src/**/*.rs", "src/lib.rs", true));
+        assert!(!glob_match("src/*.rs", "src/a/lib.rs", true));
+        assert!(glob_match("*", "feature/login", false));
+    }
+
+    #[test]
+    fn test_simulate_pipeline() {
+        let triggers = vec![Trigger::Push {
+            branches: vec!["main".to_string(), "feature/*".to_string()],
+            paths: Some(vec!["src/**".to_string()]),
+        }];
+        let stages = vec![
+            stage("build", &[], None),
+            stage("deploy", &["build"], Some("branch == 'main'")),
+            stage("smoke", &["deploy"], None),
+        ];
+
+        let result = simulate_pipeline(&triggers, &stages, &push("feature/x", Some(&["src/a.rs"])));
+        assert!(result.triggered);
+        let statuses: Vec<_> = result.stages.iter().map(|s| s.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                SimulatedStatus::Run,
+                SimulatedStatus::Skipped,
+                SimulatedStatus::Skipped
+            ]
+        );
+        assert_eq!(
+            result.stages[2].reason.as_deref(),
+            Some("dependency 'deploy' skipped")
+        );
+
+        let result = simulate_pipeline(&triggers, &stages, &push("main", Some(&["README.md"])));
+        assert!(!result.triggered);
+
+        let result = simulate_pipeline(&triggers, &stages, &push("main", None));
+        assert!(result.triggered);
+        assert_eq!(result.notes.len(), 1);
+        assert!(
+            result
+                .stages
+                .iter()
+                .all(|s| s.status == SimulatedStatus::Run)
+        );
+    }
+}
@@ -0,0 +1,181 @@
+//! Layering of the environment variables jobs get.
+//!
+//! A job's variables come from, lowest precedence first:
+//!
+//! 1. the system: `CI`, `BUILDIT` and the system configuration's `jobs env`
+//! 2. the tenant's variable groups, in name order
+//! 3. the pipeline's variable groups, in name order, then its `env`
+//! 4. the stage's `env`
+//! 5. the variables the run was triggered with
+//!
+//! Each variable remembers where its value came from and which layers it
+//! overrode, so a run can show why a job saw the value it did.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::LazyLock;
+use utoipa::ToSchema;
+
+static NAME: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").unwrap());
+
+/// Whether `name` can be an environment variable's name.
+pub fn is_valid_name(name: &str) -> bool {
+    NAME.is_match(name)
+}
+
+/// Where a variable's value came from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum VariableSource {
+    System,
+    TenantGroup {
+        group: String,
+    },
+    PipelineGroup {
+        group: String,
+    },
+    /// The pipeline configuration's `env`.
+    Pipeline,
+    Stage {
+        stage: String,
+    },
+    Trigger,
+}
+
+impl std::fmt::Display for VariableSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VariableSource::System => f.write_str("system"),
+            VariableSource::TenantGroup { group } => write!(f, "tenant group '{}'", group),
+            VariableSource::PipelineGroup { group } => write!(f, "pipeline group '{}'", group),
+            VariableSource::Pipeline => f.write_str("pipeline env"),
+            VariableSource::Stage { stage } => write!(f, "stage '{}'", stage),
+            VariableSource::Trigger => f.write_str("trigger"),
+        }
+    }
+}
+
+/// A variable's value and where it came from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ResolvedVariable {
+    pub name: String,
+    pub value: String,
+    pub source: VariableSource,
+    /// Lower layers that also set the variable, lowest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overrides: Vec<VariableSource>,
+}
+
+/// Variables of a run, layered from lowest to highest precedence.
+#[derive(Debug, Clone, Default)]
+pub struct EnvLayers {
+    layers: Vec<(VariableSource, HashMap<String, String>)>,
+}
+
+impl EnvLayers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a layer over the ones added before.
+    pub fn push(&mut self, source: VariableSource, variables: HashMap<String, String>) {
+        if !variables.is_empty() {
+            self.layers.push((source, variables));
+        }
+    }
+
+    /// Every variable with its value from the highest layer setting it, by
+    /// name.
+    pub fn resolve(&self) -> Vec<ResolvedVariable> {
+        let mut resolved: BTreeMap<&str, ResolvedVariable> = BTreeMap::new();
+        for (source, variables) in &self.layers {
+            for (name, value) in variables {
+                match resolved.get_mut(name.as_str()) {
+                    Some(variable) => {
+                        let lower = std::mem::replace(&mut variable.source, source.clone());
+                        variable.overrides.push(lower);
+                        variable.value = value.clone();
+                    }
+                    None => {
+                        resolved.insert(
+                            name,
+                            ResolvedVariable {
+                                name: name.clone(),
+                                value: value.clone(),
+                                source: source.clone(),
+                                overrides: vec![],
+                            },
+                        );
+                    }
+                }
+            }
+        }
+        resolved.into_values().collect()
+    }
+
+    /// The variables' values.
+    pub fn env(&self) -> HashMap<String, String> {
+        self.resolve()
+            .into_iter()
+            .map(|variable| (variable.name, variable.value))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_higher_layers_win() {
+        let mut layers = EnvLayers::new();
+        layers.push(
+            VariableSource::System,
+            vars(&[("CI", "true"), ("REGION", "us")]),
+        );
+        layers.push(
+            VariableSource::TenantGroup {
+                group: "defaults".to_string(),
+            },
+            vars(&[("REGION", "eu"), ("LOG_LEVEL", "info")]),
+        );
+        layers.push(VariableSource::Pipeline, HashMap::new());
+        layers.push(VariableSource::Trigger, vars(&[("LOG_LEVEL", "debug")]));
+
+        let resolved = layers.resolve();
+        assert_eq!(
+            resolved.iter().map(|v| v.name.as_str()).collect::<Vec<_>>(),
+            ["CI", "LOG_LEVEL", "REGION"]
+        );
+        assert_eq!(resolved[0].source, VariableSource::System);
+        assert_eq!(resolved[1].value, "debug");
+        assert_eq!(resolved[1].source, VariableSource::Trigger);
+        assert_eq!(
+            resolved[1].overrides,
+            [VariableSource::TenantGroup {
+                group: "defaults".to_string()
+            }]
+        );
+        assert_eq!(resolved[2].value, "eu");
+        assert_eq!(resolved[2].overrides, [VariableSource::System]);
+        assert_eq!(layers.env()["REGION"], "eu");
+    }
+
+    #[test]
+    fn test_valid_names() {
+        for name in ["CI", "_private", "node_env2"] {
+            assert!(is_valid_name(name), "{}", name);
+        }
+        for name in ["", "2FAST", "MY-VAR", "A B"] {
+            assert!(!is_valid_name(name), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,536 @@
+//! Expressions inside `${...}` interpolations.
+//!
+//! Besides plain references such as `${git.sha}`, an interpolation can be:
+//! - a default for an unset or empty variable: `${REGION:-eu-west-1}`
+//! - a function call: `${lower(git.branch)}`, `${replace(git.branch, '/', '-')}`
+//! - a comparison or ternary: `${git.branch == 'main' ? 'prod' : 'staging'}`
+//!
+//! References are the [`VariableContext`] paths, including the results of
+//! stages that already ran: `stages.<name>.status` and
+//! `stages.<name>.outputs.<key>`. Bare names are custom variables, then
+//! environment variables. Comparisons are `==`, `!=` and `=~` (glob match),
+//! combined with `&&`, `||`, `!` and parentheses.
+//!
+//! Functions: `trim(s)`, `lower(s)`, `upper(s)`, `replace(s, from, to)`,
+//! `default(s, fallback)`, `starts_with(s, prefix)`, `ends_with(s, suffix)`,
+//! `contains(s, text)`, `truncate(s, n)` and `slug(s)`, which lowercases
+//! `s` and turns everything but letters and digits into `-`.
+//!
+//! An interpolation that names an unknown bare variable or is not a valid
+//! expression, such as the shell's `${#args[@]}`, is left as written for the
+//! job's shell; `$${...}` is always left for the shell, as `${...}`.
+
+use crate::condition::glob_match;
+use crate::error::{ConfigError, ConfigResult};
+use crate::variables::VariableContext;
+
+/// Namespaces of [`VariableContext::resolve`]; their paths are unset rather
+/// than unknown when they have no value.
+const NAMESPACES: &[&str] = &[
+    "git", "pipeline", "run", "stage", "env", "secrets", "stages", "params",
+];
+
+/// Evaluate an expression, the text between `${` and `}`. Returns `None`
+/// when it is a reference to a variable without a value.
+pub fn evaluate(expression: &str, ctx: &VariableContext) -> ConfigResult<Option<String>> {
+    if let Some((name, default)) = split_default(expression) {
+        let value = lookup(name, ctx).unwrap_or(Value::Unset);
+        return Ok(Some(if value.is_empty() {
+            interpolate(default, ctx)
+        } else {
+            value.text()
+        }));
+    }
+
+    let mut evaluator = Evaluator {
+        tokens: tokenize(expression)?,
+        pos: 0,
+        ctx,
+    };
+    let value = evaluator.ternary()?;
+    if let Some(token) = evaluator.peek() {
+        return Err(invalid(format!("unexpected {:?}", token)));
+    }
+    Ok(match value {
+        Value::Unset => None,
+        value => Some(value.text()),
+    })
+}
+
+/// Replace every `${...}` in `input` with its value.
+pub(crate) fn interpolate(input: &str, ctx: &VariableContext) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            out.push_str(&rest[..start - 1]);
+            out.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        out.push_str(&rest[..start]);
+        let Some(len) = body_len(&rest[start + 2..]) else {
+            break;
+        };
+        let end = start + 2 + len + 1;
+        match evaluate(&rest[start + 2..end - 1], ctx) {
+            Ok(Some(value)) => out.push_str(&value),
+            _ => out.push_str(&rest[start..end]),
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The bodies of the `${...}` interpolations in `input`.
+pub(crate) fn bodies(input: &str) -> Vec<&str> {
+    let mut found = Vec::new();
+    let mut rest = input;
+    while let Some(start) = rest.find("${") {
+        let escaped = rest[..start].ends_with('$');
+        rest = &rest[start + 2..];
+        if escaped {
+            continue;
+        }
+        let Some(len) = body_len(rest) else {
+            break;
+        };
+        found.push(&rest[..len]);
+        rest = &rest[len + 1..];
+    }
+    found
+}
+
+/// Length of the body before the `}` closing an interpolation, skipping
+/// nested braces and quoted strings.
+fn body_len(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '{') => depth += 1,
+            (None, '}') if depth == 0 => return Some(i),
+            (None, '}') => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// `NAME:-default`, split into the name and the default.
+fn split_default(expression: &str) -> Option<(&str, &str)> {
+    let (name, default) = expression.split_once(":-")?;
+    let name = name.trim();
+    let valid =
+        name.starts_with(|c: char| c.is_alphabetic() || c == '_') && name.chars().all(is_path_char);
+    valid.then_some((name, default))
+}
+
+fn is_path_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '.' | '-')
+}
+
+/// The value of a reference.
+fn lookup(path: &str, ctx: &VariableContext) -> ConfigResult<Value> {
+    if let Some(value) = ctx.resolve(path) {
+        return Ok(Value::Str(value));
+    }
+    match path.split_once('.') {
+        Some((namespace, _)) if NAMESPACES.contains(&namespace) => Ok(Value::Unset),
+        _ => Err(ConfigError::InvalidReference(path.to_string())),
+    }
+}
+
+fn invalid(message: String) -> ConfigError {
+    ConfigError::InvalidValue {
+        field: "expression".to_string(),
+        message,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    /// A reference to a variable without a value.
+    Unset,
+    Str(String),
+    Bool(bool),
+}
+
+impl Value {
+    fn text(&self) -> String {
+        match self {
+            Value::Unset => String::new(),
+            Value::Str(s) => s.clone(),
+            Value::Bool(b) => b.to_string(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            Value::Unset => true,
+            Value::Str(s) => s.is_empty(),
+            Value::Bool(_) => false,
+        }
+    }
+
+    fn truthy(&self) -> bool {
+        match self {
+            Value::Unset => false,
+            Value::Str(s) => !s.is_empty() && s != "false",
+            Value::Bool(b) => *b,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    LParen,
+    RParen,
+    Comma,
+    Eq,
+    Ne,
+    Match,
+    And,
+    Or,
+    Not,
+    Question,
+    Colon,
+}
+
+fn tokenize(expr: &str) -> ConfigResult<Vec<Token>> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let two: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+        let (token, len) = match c {
+            _ if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => (Token::LParen, 1),
+            ')' => (Token::RParen, 1),
+            ',' => (Token::Comma, 1),
+            '?' => (Token::Question, 1),
+            ':' => (Token::Colon, 1),
+            '\'' | '"' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&ch| ch == c)
+                    .ok_or_else(|| invalid("unterminated string".to_string()))?;
+                (
+                    Token::Str(chars[i + 1..i + 1 + end].iter().collect()),
+                    end + 2,
+                )
+            }
+            _ if two == "==" => (Token::Eq, 2),
+            _ if two == "!=" => (Token::Ne, 2),
+            _ if two == "=~" => (Token::Match, 2),
+            _ if two == "&&" => (Token::And, 2),
+            _ if two == "||" => (Token::Or, 2),
+            '!' => (Token::Not, 1),
+            _ if c.is_ascii_digit() => {
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|ch| ch.is_ascii_digit())
+                    .count();
+                (Token::Str(chars[i..i + len].iter().collect()), len)
+            }
+            _ if c.is_alphabetic() || c == '_' => {
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|ch| is_path_char(**ch))
+                    .count();
+                (Token::Ident(chars[i..i + len].iter().collect()), len)
+            }
+            _ => return Err(invalid(format!("unexpected character '{}'", c))),
+        };
+        tokens.push(token);
+        i += len;
+    }
+    Ok(tokens)
+}
+
+/// Recursive-descent evaluator over the token stream.
+struct Evaluator<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    ctx: &'a VariableContext,
+}
+
+impl Evaluator<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: Token) -> ConfigResult<()> {
+        if self.eat(&token) {
+            Ok(())
+        } else {
+            Err(invalid(format!("expected {:?}", token)))
+        }
+    }
+
+    fn ternary(&mut self) -> ConfigResult<Value> {
+        let condition = self.or()?;
+        if !self.eat(&Token::Question) {
+            return Ok(condition);
+        }
+        let then = self.ternary()?;
+        self.expect(Token::Colon)?;
+        let otherwise = self.ternary()?;
+        Ok(if condition.truthy() { then } else { otherwise })
+    }
+
+    fn or(&mut self) -> ConfigResult<Value> {
+        let mut value = self.and()?;
+        while self.eat(&Token::Or) {
+            let rhs = self.and()?;
+            value = Value::Bool(value.truthy() || rhs.truthy());
+        }
+        Ok(value)
+    }
+
+    fn and(&mut self) -> ConfigResult<Value> {
+        let mut value = self.comparison()?;
+        while self.eat(&Token::And) {
+            let rhs = self.comparison()?;
+            value = Value::Bool(value.truthy() && rhs.truthy());
+        }
+        Ok(value)
+    }
+
+    fn comparison(&mut self) -> ConfigResult<Value> {
+        let lhs = self.unary()?;
+        let op = match self.peek() {
+            Some(Token::Eq | Token::Ne | Token::Match) => self.next(),
+            _ => None,
+        };
+        let Some(op) = op else {
+            return Ok(lhs);
+        };
+        let rhs = self.unary()?;
+        let (lhs, rhs) = (lhs.text(), rhs.text());
+        Ok(Value::Bool(match op {
+            Token::Eq => lhs == rhs,
+            Token::Ne => lhs != rhs,
+            _ => glob_match(&rhs, &lhs, false),
+        }))
+    }
+
+    fn unary(&mut self) -> ConfigResult<Value> {
+        if self.eat(&Token::Not) {
+            return Ok(Value::Bool(!self.unary()?.truthy()));
+        }
+        match self.next() {
+            Some(Token::LParen) => {
+                let value = self.ternary()?;
+                self.expect(Token::RParen)?;
+                Ok(value)
+            }
+            Some(Token::Str(s)) => Ok(Value::Str(s)),
+            Some(Token::Ident(name)) if self.eat(&Token::LParen) => {
+                let mut args = Vec::new();
+                if !self.eat(&Token::RParen) {
+                    loop {
+                        args.push(self.ternary()?);
+                        if self.eat(&Token::RParen) {
+                            break;
+                        }
+                        self.expect(Token::Comma)?;
+                    }
+                }
+                call(&name, &args)
+            }
+            Some(Token::Ident(name)) => match name.as_str() {
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                _ => lookup(&name, self.ctx),
+            },
+            Some(token) => Err(invalid(format!("unexpected {:?}", token))),
+            None => Err(invalid("unexpected end of expression".to_string())),
+        }
+    }
+}
+
+/// Apply a function to its arguments.
+fn call(name: &str, args: &[Value]) -> ConfigResult<Value> {
+    let arity = match name {
+        "trim" | "lower" | "upper" | "slug" => 1,
+        "default" | "starts_with" | "ends_with" | "contains" | "truncate" => 2,
+        "replace" => 3,
+        _ => return Err(invalid(format!("unknown function '{}'", name))),
+    };
+    if args.len() != arity {
+        return Err(invalid(format!(
+            "{}() takes {} argument{}, got {}",
+            name,
+            arity,
+            if arity == 1 { "" } else { "s" },
+            args.len()
+        )));
+    }
+
+    let s = args[0].text();
+    let arg = |i: usize| args[i].text();
+    Ok(match name {
+        "trim" => Value::Str(s.trim().to_string()),
+        "lower" => Value::Str(s.to_lowercase()),
+        "upper" => Value::Str(s.to_uppercase()),
+        "slug" => Value::Str(slug(&s)),
+        "replace" => Value::Str(s.replace(&arg(1), &arg(2))),
+        "default" if args[0].is_empty() => args[1].clone(),
+        "default" => args[0].clone(),
+        "starts_with" => Value::Bool(s.starts_with(&arg(1))),
+        "ends_with" => Value::Bool(s.ends_with(&arg(1))),
+        "contains" => Value::Bool(s.contains(&arg(1))),
+        _ => {
+            let n: usize = arg(1)
+                .parse()
+                .map_err(|_| invalid(format!("truncate() length '{}' is not a number", arg(1))))?;
+            Value::Str(s.chars().take(n).collect())
+        }
+    })
+}
+
+/// `s` lowercased, with runs of anything but letters and digits turned into
+/// single dashes, e.g. for image tags from branch names.
+fn slug(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c.is_alphanumeric() {
+            out.extend(c.to_lowercase());
+        } else if !out.is_empty() && !out.ends_with('-') {
+            out.push('-');
+        }
+    }
+    out.trim_end_matches('-').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variables::VariableContextBuilder;
+    use std::collections::HashMap;
+
+    fn ctx() -> VariableContext {
+        let mut ctx = VariableContextBuilder::new()
+            .with_git_sha("abc1234567890")
+            .with_git_branch("feature/Login-Page")
+            .with_env("REGION", "eu-west-1")
+            .with_env("EMPTY", "")
+            .with_param("dry_run", "false")
+            .build();
+        ctx.record_stage(
+            "build",
+            "succeeded",
+            HashMap::from([("version".to_string(), "1.4.2".to_string())]),
+        );
+        ctx
+    }
+
+    fn eval(expression: &str) -> Option<String> {
+        evaluate(expression, &ctx()).unwrap()
+    }
+
+    #[test]
+    fn test_defaults() {
+        assert_eq!(ctx().interpolate("${REGION:-us-east-1}"), "eu-west-1");
+        assert_eq!(ctx().interpolate("${ZONE:-a}/${EMPTY:-b}"), "a/b");
+        assert_eq!(
+            ctx().interpolate("${env.ZONE:-${git.short_sha}}"),
+            "abc1234"
+        );
+    }
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(eval("lower(git.branch)").unwrap(), "feature/login-page");
+        assert_eq!(
+            eval("replace(git.branch, '/', '-')").unwrap(),
+            "feature-Login-Page"
+        );
+        assert_eq!(eval("slug(git.branch)").unwrap(), "feature-login-page");
+        assert_eq!(eval("upper(trim('  eu '))").unwrap(), "EU");
+        assert_eq!(eval("truncate(git.sha, 4)").unwrap(), "abc1");
+        assert_eq!(eval("default(git.tag, 'untagged')").unwrap(), "untagged");
+        assert!(evaluate("lower()", &ctx()).is_err());
+        assert!(evaluate("shout(git.sha)", &ctx()).is_err());
+    }
+
+    #[test]
+    fn test_ternaries() {
+        assert_eq!(
+            eval("git.branch == 'main' ? 'prod' : 'staging'").unwrap(),
+            "staging"
+        );
+        assert_eq!(
+            eval("git.branch =~ 'feature/*' && !git.tag ? 'preview' : 'none'").unwrap(),
+            "preview"
+        );
+        assert_eq!(
+            eval("git.tag ? git.tag : truncate(git.sha, 7)").unwrap(),
+            "abc1234"
+        );
+    }
+
+    #[test]
+    fn test_stage_results() {
+        assert_eq!(
+            ctx().interpolate("v${stages.build.outputs.version} (${stages.build.status})"),
+            "v1.4.2 (succeeded)"
+        );
+        assert_eq!(eval("stages.test.status"), None);
+    }
+
+    #[test]
+    fn test_params() {
+        assert_eq!(
+            ctx().interpolate("deploy${params.dry_run ? ' --dry-run' : ''}"),
+            "deploy"
+        );
+        assert_eq!(eval("params.version"), None);
+    }
+
+    #[test]
+    fn test_shell_syntax_left_alone() {
+        let ctx = ctx();
+        for text in [
+            "${HOME}",
+            "${#args[@]}",
+            "${!ref}",
+            "${file%.tar.gz}",
+            "${unknown.var}",
+            "${unterminated",
+        ] {
+            assert_eq!(ctx.interpolate(text), text);
+        }
+        assert_eq!(
+            ctx.interpolate("$${REGION} ${REGION}"),
+            "${REGION} eu-west-1"
+        );
+    }
+}
@@ -0,0 +1,324 @@
+//! Static stage graph for a pipeline definition.
+//!
+//! Builds the DAG a pipeline would execute without running anything:
+//! matrix stages are expanded into one node per combination, parallel
+//! stages into one node per child, and conditional/manual stages are
+//! flagged so the UI can mark them.
+
+use buildit_core::pipeline::{Stage, StageAction};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use utoipa::ToSchema;
+
+/// The expanded stage graph of a pipeline.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct StageGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+/// A single executable unit in the graph.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct GraphNode {
+    /// Unique node id, e.g. `test [os=linux, rust=1.80]` or `lint/fmt`.
+    pub id: String,
+    /// Name of the stage this node was expanded from.
+    pub stage: String,
+    /// Action kind: run, image_build, deploy.
+    pub kind: String,
+    /// Node ids this node depends on.
+    pub needs: Vec<String>,
+    /// `when` expression, if the stage is conditional.
+    pub condition: Option<String>,
+    /// Whether the stage waits for manual approval.
+    pub manual: bool,
+    /// Matrix values for this combination (empty for non-matrix stages).
+    pub matrix: BTreeMap<String, String>,
+    /// Longest-path depth from a root node.
+    pub level: usize,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct GraphEdge {
+    pub from: String,
+    pub to: String,
+}
+
+/// Build the static graph for a list of stages.
+///
+/// References to unknown stages are ignored; config validation reports them.
+pub fn build_stage_graph(stages: &[Stage]) -> StageGraph {
+    let mut nodes = Vec::new();
+    // Stage name -> node ids it expanded into, for resolving `needs`
+    let mut expansions: HashMap<String, Vec<String>> = HashMap::new();
+
+    for stage in stages {
+        let ids = expand_stage(stage, None, &stage.needs, &mut nodes);
+        expansions.insert(stage.name.clone(), ids);
+    }
+
+    // Resolve stage-level needs to node ids
+    for node in &mut nodes {
+        node.needs = node
+            .needs
+            .iter()
+            .flat_map(|dep| {
+                expansions
+                    .get(dep)
+                    .cloned()
+                    .unwrap_or_else(|| vec![dep.clone()])
+            })
+            .collect();
+    }
+
+    let index: HashMap<String, usize> = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (n.id.clone(), i))
+        .collect();
+    let mut levels = vec![None; nodes.len()];
+    for i in 0..nodes.len() {
+        compute_level(i, &nodes, &index, &mut levels, &mut Vec::new());
+    }
+    for (node, level) in nodes.iter_mut().zip(levels) {
+        node.level = level.unwrap_or(0);
+    }
+
+    let edges = nodes
+        .iter()
+        .flat_map(|n| {
+            n.needs
+                .iter()
+                .filter(|dep| index.contains_key(*dep))
+                .map(|dep| GraphEdge {
+                    from: dep.clone(),
+                    to: n.id.clone(),
+                })
+        })
+        .collect();
+
+    StageGraph { nodes, edges }
+}
+
+/// Expand a stage into graph nodes, returning the ids it produced.
+///
+/// `parent` is set for children of a parallel stage, whose sibling
+/// references are namespaced under the parent.
+fn expand_stage(
+    stage: &Stage,
+    parent: Option<&str>,
+    needs: &[String],
+    nodes: &mut Vec<GraphNode>,
+) -> Vec<String> {
+    let id = match parent {
+        Some(p) => format!("{}/{}", p, stage.name),
+        None => stage.name.clone(),
+    };
+    let condition = stage.when.as_ref().map(|c| c.expression.clone());
+
+    match &stage.action {
+        StageAction::Parallel { stages: children } => {
+            let sibling_names: Vec<&str> = children.iter().map(|c| c.name.as_str()).collect();
+            children
+                .iter()
+                .flat_map(|child| {
+                    let child_needs: Vec<String> = needs
+                        .iter()
+                        .cloned()
+                        .chain(child.needs.iter().map(|n| {
+                            if sibling_names.contains(&n.as_str()) {
+                                format!("{}/{}", id, n)
+                            } else {
+                                n.clone()
+                            }
+                        }))
+                        .collect();
+                    let mut child = child.clone();
+                    if child.when.is_none() {
+                        child.when = stage.when.clone();
+                    }
+                    child.manual |= stage.manual;
+                    expand_stage(&child, Some(&id), &child_needs, nodes)
+                })
+                .collect()
+        }
+        StageAction::Matrix {
+            variables,
+            stage: inner,
+        } => matrix_combinations(variables)
+            .into_iter()
+            .map(|combo| {
+                let label = combo
+                    .iter()
+                    .map(|(k, v)| format!("{}={}", k, v))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let node_id = format!("{} [{}]", id, label);
+                nodes.push(GraphNode {
+                    id: node_id.clone(),
+                    stage: stage.name.clone(),
+                    kind: action_kind(&inner.action).to_string(),
+                    needs: needs.to_vec(),
+                    condition: condition
+                        .clone()
+                        .or_else(|| inner.when.as_ref().map(|c| c.expression.clone())),
+                    manual: stage.manual || inner.manual,
+                    matrix: combo,
+                    level: 0,
+                });
+                node_id
+            })
+            .collect(),
+        action => {
+            nodes.push(GraphNode {
+                id: id.clone(),
+                stage: stage.name.clone(),
+                kind: action_kind(action).to_string(),
+                needs: needs.to_vec(),
+                condition,
+                manual: stage.manual,
+                matrix: BTreeMap::new(),
+                level: 0,
+            });
+            vec![id]
+        }
+    }
+}
+
+fn action_kind(action: &StageAction) -> &'static str {
+    match action {
+        StageAction::Run { .. } => "run",
+        StageAction::ImageBuild { .. } => "image_build",
+        StageAction::Deploy(_) => "deploy",
+        StageAction::Scan(_) => "scan",
+        StageAction::Release(_) => "release",
+        StageAction::TriggerPipeline(_) => "trigger_pipeline",
+        StageAction::Parallel { .. } => "parallel",
+        StageAction::Matrix { .. } => "matrix",
+    }
+}
+
+/// Cartesian product of matrix variables, with keys in sorted order.
+fn matrix_combinations(variables: &HashMap<String, Vec<String>>) -> Vec<BTreeMap<String, String>> {
+    let sorted: BTreeMap<_, _> = variables.iter().collect();
+    let mut combos = vec![BTreeMap::new()];
+    for (key, values) in sorted {
+        combos = combos
+            .into_iter()
+            .flat_map(|combo| {
+                values.iter().map(move |v| {
+                    let mut next = combo.clone();
+                    next.insert(key.clone(), v.clone());
+                    next
+                })
+            })
+            .collect();
+    }
+    combos
+}
+
+fn compute_level(
+    idx: usize,
+    nodes: &[GraphNode],
+    index: &HashMap<String, usize>,
+    levels: &mut Vec<Option<usize>>,
+    visiting: &mut Vec<usize>,
+) -> usize {
+    if let Some(level) = levels[idx] {
+        return level;
+    }
+    if visiting.contains(&idx) {
+        return 0; // cycle; config validation reports these
+    }
+    visiting.push(idx);
+
+    let level = nodes[idx]
+        .needs
+        .iter()
+        .filter_map(|dep| index.get(dep))
+        .map(|&d| compute_level(d, nodes, index, levels, visiting) + 1)
+        .max()
+        .unwrap_or(0);
+
+    visiting.pop();
+    levels[idx] = Some(level);
+    level
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use buildit_core::pipeline::StageCondition;
+
+    fn run_stage(name: &str, needs: &[&str]) -> Stage {
+        Stage {
+            name: name.to_string(),
+            needs: needs.iter().map(|s| s.to_string()).collect(),
+            when: None,
+            manual: false,
+            action: StageAction::Run {
+                image: "alpine".to_string(),
+                commands: vec![],
+                artifacts: vec![],
+                test_reports: vec![],
+            },
+            env: HashMap::new(),
+            runs_on: vec![],
+            resources: Default::default(),
+            platform: Default::default(),
+            wait_for: vec![],
+            needs_artifacts: vec![],
+            security_context: None,
+            container: Default::default(),
+            pull_policy: None,
+        }
+    }
+
+    #[test]
+    fn test_matrix_expansion_and_fan_in() {
+        let mut variables = HashMap::new();
+        variables.insert("os".to_string(), vec!["linux".into(), "macos".into()]);
+        variables.insert("rust".to_string(), vec!["1.75".into(), "1.80".into()]);
+
+        let mut test = run_stage("test", &["build"]);
+        test.action = StageAction::Matrix {
+            variables,
+            stage: Box::new(run_stage("test", &[])),
+        };
+
+        let stages = vec![
+            run_stage("build", &[]),
+            test,
+            run_stage("publish", &["test"]),
+        ];
+        let graph = build_stage_graph(&stages);
+
+        assert_eq!(graph.nodes.len(), 6);
+        assert!(
+            graph
+                .nodes
+                .iter()
+                .any(|n| n.id == "test [os=linux, rust=1.75]")
+        );
+
+        let publish = graph.nodes.iter().find(|n| n.id == "publish").unwrap();
+        assert_eq!(publish.needs.len(), 4);
+        assert_eq!(publish.level, 2);
+        assert_eq!(graph.edges.len(), 8);
+    }
+
+    #[test]
+    fn test_conditional_and_manual_flags() {
+        let mut deploy = run_stage("deploy", &["build"]);
+        deploy.when = Some(StageCondition {
+            expression: "branch == 'main'".to_string(),
+        });
+        deploy.manual = true;
+
+        let graph = build_stage_graph(&[run_stage("build", &[]), deploy]);
+        let node = graph.nodes.iter().find(|n| n.id == "deploy").unwrap();
+        assert_eq!(node.condition.as_deref(), Some("branch == 'main'"));
+        assert!(node.manual);
+        assert_eq!(node.level, 1);
+    }
+}
@@ -2,15 +2,32 @@
 //!
 //! This crate handles parsing of:
 //! - Pipeline definitions (buildit.kdl)
+//! - Run parameters
+//! - Stage conditions and trigger simulation
+//! - Static stage graphs for pipeline definitions
 //! - System configuration
-//! - Variable interpolation
+//! - Variable interpolation and its expressions
+//! - Layering of job environment variables
 
+pub mod condition;
+pub mod env;
 pub mod error;
+pub mod expr;
+pub mod graph;
+pub mod params;
 pub mod pipeline;
 pub mod system;
 pub mod variables;
 
+pub use condition::{
+    SimulatedStatus, SimulationResult, StageSimulation, TriggerEvent, evaluate_condition,
+    simulate_pipeline,
+};
+pub use env::{EnvLayers, ResolvedVariable, VariableSource};
 pub use error::{ConfigError, ConfigResult};
+pub use graph::{GraphEdge, GraphNode, StageGraph, build_stage_graph};
+pub use params::resolve_params;
 pub use variables::{
-    GitContext, PipelineContext, RunContext, StageContext, VariableContext, VariableContextBuilder,
+    GitContext, OUTPUT_MARKER, PipelineContext, RunContext, StageContext, StageResultContext,
+    VariableContext, VariableContextBuilder,
 };
@@ -0,0 +1,140 @@
+//! Values of the parameters a run is triggered with.
+//!
+//! A pipeline declares its parameters in a `params` block:
+//!
+//! ```kdl
+//! params {
+//!     environment type="choice" default="staging" {
+//!         options "staging" "production"
+//!     }
+//!     dry_run type="bool" default=#true
+//!     version description="Release to deploy"
+//! }
+//! ```
+//!
+//! A run sets any of them; the rest take their default, and one without a
+//! default must be set. Stages read them as `${params.NAME}`.
+
+use buildit_core::pipeline::{ParamKind, PipelineParam};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{ConfigError, ConfigResult};
+
+/// `value` as a value of `param`: a `bool`'s is `true` or `false`, a
+/// `choice`'s one of its options.
+pub fn check_value(param: &PipelineParam, value: &str) -> ConfigResult<String> {
+    let invalid = |message: String| ConfigError::InvalidValue {
+        field: format!("param '{}'", param.name),
+        message,
+    };
+    match &param.kind {
+        ParamKind::String => Ok(value.to_string()),
+        ParamKind::Choice { options } if options.iter().any(|o| o == value) => {
+            Ok(value.to_string())
+        }
+        ParamKind::Choice { options } => Err(invalid(format!(
+            "'{}' is not one of {}",
+            value,
+            options.join(", ")
+        ))),
+        ParamKind::Bool => match value.to_ascii_lowercase().as_str() {
+            "true" => Ok("true".to_string()),
+            "false" => Ok("false".to_string()),
+            _ => Err(invalid(format!("'{}' is not true or false", value))),
+        },
+    }
+}
+
+/// The value of every declared parameter for a run that sets `given`.
+pub fn resolve_params(
+    declared: &[PipelineParam],
+    given: &HashMap<String, String>,
+) -> ConfigResult<BTreeMap<String, String>> {
+    let mut unknown: Vec<&String> = given
+        .keys()
+        .filter(|name| !declared.iter().any(|p| &p.name == *name))
+        .collect();
+    unknown.sort();
+    if let Some(name) = unknown.first() {
+        return Err(ConfigError::InvalidReference(format!(
+            "the pipeline has no param '{}'",
+            name
+        )));
+    }
+
+    declared
+        .iter()
+        .map(|param| {
+            let value = match (given.get(&param.name), &param.default) {
+                (Some(value), _) => check_value(param, value)?,
+                (None, Some(default)) => default.clone(),
+                (None, None) => {
+                    return Err(ConfigError::MissingField(format!("param '{}'", param.name)));
+                }
+            };
+            Ok((param.name.clone(), value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn declared() -> Vec<PipelineParam> {
+        vec![
+            PipelineParam {
+                name: "environment".to_string(),
+                kind: ParamKind::Choice {
+                    options: vec!["staging".to_string(), "production".to_string()],
+                },
+                default: Some("staging".to_string()),
+                description: None,
+            },
+            PipelineParam {
+                name: "dry_run".to_string(),
+                kind: ParamKind::Bool,
+                default: Some("true".to_string()),
+                description: None,
+            },
+            PipelineParam {
+                name: "version".to_string(),
+                kind: ParamKind::String,
+                default: None,
+                description: None,
+            },
+        ]
+    }
+
+    fn given(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_defaults_fill_unset_params() {
+        let values = resolve_params(
+            &declared(),
+            &given(&[("version", "1.2.0"), ("dry_run", "False")]),
+        )
+        .unwrap();
+        assert_eq!(values["environment"], "staging");
+        assert_eq!(values["dry_run"], "false");
+        assert_eq!(values["version"], "1.2.0");
+    }
+
+    #[test]
+    fn test_invalid_params_are_refused() {
+        let cases = [
+            given(&[]),
+            given(&[("version", "1"), ("environment", "qa")]),
+            given(&[("version", "1"), ("dry_run", "maybe")]),
+            given(&[("version", "1"), ("region", "eu")]),
+        ];
+        for case in cases {
+            assert!(resolve_params(&declared(), &case).is_err(), "{:?}", case);
+        }
+    }
+}
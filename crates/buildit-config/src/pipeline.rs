@@ -1,20 +1,74 @@
 //! Pipeline configuration parsing.
+//!
+//! Besides plain `stage`/`cache`/`env` definitions, a pipeline can pull in
+//! shared configuration:
+//!
+//! ```kdl
+//! include "shared/rust.kdl"
+//!
+//! template "rust-ci" {
+//!     param "toolchain" default="1.75"
+//!     stage "test" {
+//!         image "rust:${param.toolchain}"
+//!         run "cargo test"
+//!     }
+//! }
+//!
+//! extends "rust-ci" toolchain="1.80"
+//! ```
+//!
+//! `include` loads another document through a [`TemplateResolver`] (a
+//! directory on disk or a server-side [`TemplateLibrary`]); its templates
+//! become available and its other nodes are merged in. `extends` expands a
+//! template's body with `${param.NAME}` substituted. A stage or cache defined
+//! later with the same name replaces the earlier one, so a pipeline can
+//! override individual stages it inherited.
 
+use crate::env::is_valid_name;
+use crate::params::check_value;
 use crate::{ConfigError, ConfigResult};
 use buildit_core::ResourceId;
-use buildit_core::pipeline::{CacheConfig, Pipeline, Stage, StageAction, StageCondition, Trigger};
-use kdl::{KdlDocument, KdlNode};
-use std::collections::HashMap;
+use buildit_core::artifact::{ArtifactDependency, ArtifactVersion};
+use buildit_core::child_pipeline::TriggerPipelineSpec;
+use buildit_core::executor::{
+    Os, Platform, Probe, ResourceRequirements, SecurityContext, WaitCondition,
+};
+use buildit_core::pipeline::{
+    CacheConfig, CheckoutConfig, ContainerOptions, OwnerRule, Ownership, ParamKind, Pipeline,
+    PipelineParam, Stage, StageAction, StageCondition, Trigger,
+};
+use buildit_core::release::ReleaseSpec;
+use buildit_core::scan::{ScanSpec, Scanner, Severity};
+use kdl::{KdlDocument, KdlNode, KdlValue};
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::LazyLock;
 
 /// Parse a pipeline configuration from KDL text.
+///
+/// `include` directives are rejected; use [`parse_pipeline_with_resolver`]
+/// to allow them.
 pub fn parse_pipeline(kdl: &str) -> ConfigResult<Pipeline> {
+    parse_pipeline_with_resolver(kdl, &NoIncludes)
+}
+
+/// Parse a pipeline configuration, resolving `include` directives with `resolver`.
+pub fn parse_pipeline_with_resolver(
+    kdl: &str,
+    resolver: &dyn TemplateResolver,
+) -> ConfigResult<Pipeline> {
     let doc: KdlDocument = kdl.parse()?;
+    let doc = expand_templates(&doc, resolver)?;
 
     let mut name = String::new();
     let mut triggers = Vec::new();
     let mut stages = Vec::new();
     let mut caches = Vec::new();
     let mut env = HashMap::new();
+    let mut ownership = Ownership::default();
+    let mut checkout = CheckoutConfig::default();
+    let mut params = Vec::new();
 
     for node in doc.nodes() {
         match node.name().value() {
@@ -31,6 +85,15 @@ pub fn parse_pipeline(kdl: &str) -> ConfigResult<Pipeline> {
             "cache" => {
                 caches.push(parse_cache(node)?);
             }
+            "owners" => {
+                ownership = parse_owners(node);
+            }
+            "checkout" => {
+                checkout = parse_checkout(node)?;
+            }
+            "params" => {
+                params = parse_params(node)?;
+            }
             "env" => {
                 if let Some(children) = node.children() {
                     for child in children.nodes() {
@@ -76,9 +139,158 @@ pub fn parse_pipeline(kdl: &str) -> ConfigResult<Pipeline> {
         stages,
         env,
         caches,
+        ownership,
+        checkout,
+        params,
+    })
+}
+
+/// Parse an `owners` node: arguments own the whole pipeline, children map
+/// stage name patterns to owners (last match wins).
+///
+/// ```kdl
+/// owners "team:platform" {
+///     "deploy-*" "@sre"
+///     lint "@alice"
+/// }
+/// ```
+fn parse_owners(node: &KdlNode) -> Ownership {
+    let owners = get_all_string_args(node);
+    let stages = node
+        .children()
+        .map(|children| {
+            children
+                .nodes()
+                .iter()
+                .map(|rule| OwnerRule {
+                    pattern: rule.name().value().to_string(),
+                    owners: get_all_string_args(rule),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ownership { owners, stages }
+}
+
+/// Parse a `checkout` node: how much of the repository each stage gets.
+///
+/// ```kdl
+/// checkout depth=0 submodules=#true lfs=#true cache=#true {
+///     sparse "services/api" "libs/common"
+/// }
+/// ```
+fn parse_checkout(node: &KdlNode) -> ConfigResult<CheckoutConfig> {
+    let defaults = CheckoutConfig::default();
+    let depth = match node.get("depth") {
+        Some(depth) => depth
+            .as_integer()
+            .and_then(|d| u32::try_from(d).ok())
+            .ok_or_else(|| ConfigError::InvalidValue {
+                field: "checkout depth".to_string(),
+                message: "expected a number of commits, or 0 for all of them".to_string(),
+            })?,
+        None => defaults.depth,
+    };
+
+    let mut sparse = Vec::new();
+    if let Some(children) = node.children() {
+        for child in children.nodes() {
+            if child.name().value() == "sparse" {
+                sparse.extend(get_all_string_args(child));
+            }
+        }
+    }
+    for path in &sparse {
+        if !Path::new(path)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(ConfigError::InvalidValue {
+                field: "checkout sparse".to_string(),
+                message: format!("'{}' must be a relative path inside the repository", path),
+            });
+        }
+    }
+
+    Ok(CheckoutConfig {
+        depth,
+        submodules: get_bool_prop(node, "submodules").unwrap_or(defaults.submodules),
+        lfs: get_bool_prop(node, "lfs").unwrap_or(defaults.lfs),
+        sparse,
+        cache: get_bool_prop(node, "cache").unwrap_or(defaults.cache),
     })
 }
 
+/// Parse a `params` node: the parameters a run can be triggered with.
+///
+/// ```kdl
+/// params {
+///     environment type="choice" default="staging" {
+///         options "staging" "production"
+///     }
+///     dry_run type="bool" default=#false
+///     version description="Release to deploy"
+/// }
+/// ```
+fn parse_params(node: &KdlNode) -> ConfigResult<Vec<PipelineParam>> {
+    let mut params: Vec<PipelineParam> = Vec::new();
+    for child in node.children().map(|c| c.nodes()).unwrap_or_default() {
+        let name = child.name().value().to_string();
+        let field = format!("param '{}'", name);
+        if !is_valid_name(&name) {
+            return Err(ConfigError::InvalidValue {
+                field,
+                message: "use letters, digits and underscores, not starting with a digit"
+                    .to_string(),
+            });
+        }
+        if params.iter().any(|p| p.name == name) {
+            return Err(ConfigError::InvalidValue {
+                field,
+                message: "declared more than once".to_string(),
+            });
+        }
+
+        let kind = match get_string_prop(child, "type").as_deref() {
+            None | Some("string") => ParamKind::String,
+            Some("bool") => ParamKind::Bool,
+            Some("choice") => {
+                let options: Vec<String> = child
+                    .children()
+                    .and_then(|c| c.get("options"))
+                    .map(get_all_string_args)
+                    .unwrap_or_default();
+                if options.is_empty() {
+                    return Err(ConfigError::MissingField(format!("options of {}", field)));
+                }
+                ParamKind::Choice { options }
+            }
+            Some(other) => {
+                return Err(ConfigError::InvalidValue {
+                    field,
+                    message: format!("unknown type '{}'; use string, choice or bool", other),
+                });
+            }
+        };
+        let mut param = PipelineParam {
+            name,
+            kind,
+            default: None,
+            description: get_string_prop(child, "description"),
+        };
+        if let Some(default) = child.get("default") {
+            let default = match default {
+                KdlValue::Bool(b) => b.to_string(),
+                other => kdl_value_to_string(other),
+            };
+            param.default = Some(check_value(&param, &default)?);
+        }
+        params.push(param);
+    }
+    Ok(params)
+}
+
 fn parse_trigger(node: &KdlNode) -> ConfigResult<Trigger> {
     let trigger_type = get_first_string_arg(node).unwrap_or_default();
 
@@ -135,7 +347,19 @@ fn parse_stage(node: &KdlNode) -> ConfigResult<Stage> {
     let mut image = String::new();
     let mut commands = Vec::new();
     let mut artifacts = Vec::new();
+    let mut test_reports = Vec::new();
     let mut env = HashMap::new();
+    let mut runs_on = Vec::new();
+    let mut resources = ResourceRequirements::default();
+    let mut platform = Platform::default();
+    let mut scan = None;
+    let mut release = None;
+    let mut trigger = None;
+    let mut wait_for = Vec::new();
+    let mut needs_artifacts = Vec::new();
+    let mut security_context = None;
+    let mut container = ContainerOptions::default();
+    let mut pull_policy = None;
 
     if let Some(children) = node.children() {
         for child in children.nodes() {
@@ -153,6 +377,85 @@ fn parse_stage(node: &KdlNode) -> ConfigResult<Stage> {
                         artifacts.push(art);
                     }
                 }
+                "test-reports" => {
+                    test_reports.extend(get_all_string_args(child));
+                }
+                "scan" => {
+                    scan = Some(parse_scan(child, &name)?);
+                }
+                "release" => {
+                    release = Some(parse_release(child));
+                }
+                "trigger-pipeline" => {
+                    trigger = Some(parse_trigger_pipeline(child, &name)?);
+                }
+                "wait-for" => {
+                    wait_for.push(parse_wait_for(child, &name)?);
+                }
+                "needs-artifact" | "needs_artifact" => {
+                    needs_artifacts.push(parse_artifact_dependency(child, &name)?);
+                }
+                "security-context" => {
+                    security_context = Some(parse_security_context(
+                        child,
+                        &format!("security-context of stage '{}'", name),
+                    )?);
+                }
+                "user" => {
+                    container.user = Some(get_first_string_arg(child).unwrap_or_default())
+                        .filter(|u| !u.is_empty());
+                }
+                "entrypoint" => {
+                    // `entrypoint ""` runs the commands without one
+                    container.entrypoint = Some(
+                        get_all_string_args(child)
+                            .into_iter()
+                            .filter(|arg| !arg.is_empty())
+                            .collect(),
+                    );
+                }
+                "privileged" => {
+                    container.privileged = child
+                        .entries()
+                        .iter()
+                        .find(|e| e.name().is_none())
+                        .is_none_or(|e| e.value().as_bool() == Some(true));
+                }
+                "extra-host" => {
+                    for entry in get_all_string_args(child) {
+                        container.extra_hosts.push(parse_extra_host(&entry, &name)?);
+                    }
+                }
+                "network" => {
+                    container.network_mode = Some(get_first_string_arg(child).unwrap_or_default())
+                        .filter(|n| !n.is_empty());
+                }
+                "pull-policy" => {
+                    let value = get_first_string_arg(child).unwrap_or_default();
+                    pull_policy =
+                        Some(value.parse().map_err(|message| ConfigError::InvalidValue {
+                            field: format!("pull-policy of stage '{}'", name),
+                            message,
+                        })?);
+                }
+                "runs-on" => {
+                    runs_on.extend(get_all_string_args(child));
+                }
+                "platform" => {
+                    let value = get_first_string_arg(child).unwrap_or_default();
+                    platform = value.parse().map_err(|message| ConfigError::InvalidValue {
+                        field: format!("platform of stage '{}'", name),
+                        message,
+                    })?;
+                }
+                "resources" => {
+                    resources = ResourceRequirements {
+                        cpu_request: get_string_prop(child, "cpu"),
+                        memory_request: get_string_prop(child, "memory"),
+                        cpu_limit: get_string_prop(child, "cpu-limit"),
+                        memory_limit: get_string_prop(child, "memory-limit"),
+                    };
+                }
                 "env" => {
                     if let Some(grandchildren) = child.children() {
                         for gc in grandchildren.nodes() {
@@ -168,24 +471,372 @@ fn parse_stage(node: &KdlNode) -> ConfigResult<Stage> {
         }
     }
 
-    if image.is_empty() {
-        return Err(ConfigError::MissingField(format!(
-            "image for stage '{}'",
-            name
-        )));
+    // Conditions are probed with POSIX shell tools
+    if !wait_for.is_empty() && platform.os == Os::Windows {
+        return Err(ConfigError::InvalidValue {
+            field: format!("wait-for of stage '{}'", name),
+            message: "not supported on Windows".to_string(),
+        });
     }
 
+    // A scan stage runs its scanner's image unless it names one; release
+    // and trigger-pipeline stages run no job
+    if [scan.is_some(), release.is_some(), trigger.is_some()]
+        .iter()
+        .filter(|set| **set)
+        .count()
+        > 1
+    {
+        return Err(ConfigError::InvalidValue {
+            field: format!("stage '{}'", name),
+            message: "a stage can only do one of scan, release and trigger-pipeline".to_string(),
+        });
+    }
+    // Artifacts are fetched by the stage's own commands
+    if !needs_artifacts.is_empty()
+        && (scan.is_some() || release.is_some() || trigger.is_some() || commands.is_empty())
+    {
+        return Err(ConfigError::InvalidValue {
+            field: format!("needs-artifact of stage '{}'", name),
+            message: "only stages that run commands can fetch artifacts".to_string(),
+        });
+    }
+    let action = match (scan, release, trigger) {
+        (Some(mut scan), _, _) => {
+            scan.image = Some(image).filter(|i| !i.is_empty());
+            StageAction::Scan(Box::new(scan))
+        }
+        (None, Some(_), _) if !commands.is_empty() => {
+            return Err(ConfigError::InvalidValue {
+                field: format!("release of stage '{}'", name),
+                message: "a release stage runs no commands; build its assets in an earlier stage"
+                    .to_string(),
+            });
+        }
+        (None, Some(release), _) => StageAction::Release(Box::new(release)),
+        (None, None, Some(_)) if !commands.is_empty() => {
+            return Err(ConfigError::InvalidValue {
+                field: format!("trigger-pipeline of stage '{}'", name),
+                message: "a trigger-pipeline stage runs no commands".to_string(),
+            });
+        }
+        (None, None, Some(trigger)) => StageAction::TriggerPipeline(Box::new(trigger)),
+        (None, None, None) if image.is_empty() => {
+            return Err(ConfigError::MissingField(format!(
+                "image for stage '{}'",
+                name
+            )));
+        }
+        (None, None, None) => StageAction::Run {
+            image,
+            commands,
+            artifacts,
+            test_reports,
+        },
+    };
+
     Ok(Stage {
         name,
         needs,
         when,
         manual,
-        action: StageAction::Run {
-            image,
-            commands,
-            artifacts,
-        },
+        action,
         env,
+        runs_on,
+        resources,
+        platform,
+        wait_for,
+        needs_artifacts,
+        security_context,
+        container,
+        pull_policy,
+    })
+}
+
+/// Check an `extra-host` entry of a stage: `host:ip`, where the IP may be
+/// `host-gateway` for the Docker host.
+fn parse_extra_host(entry: &str, stage: &str) -> ConfigResult<String> {
+    let valid = entry.split_once(':').is_some_and(|(host, ip)| {
+        !host.is_empty() && (ip == "host-gateway" || ip.parse::<std::net::IpAddr>().is_ok())
+    });
+    if !valid {
+        return Err(ConfigError::InvalidValue {
+            field: format!("extra-host of stage '{}'", stage),
+            message: format!("'{}' is not a host:ip entry", entry),
+        });
+    }
+    Ok(entry.to_string())
+}
+
+/// Seconds a `wait-for` condition is probed for unless it sets `timeout`.
+const DEFAULT_WAIT_TIMEOUT_SECS: u32 = 60;
+
+/// Parse a `wait-for` condition of a stage, one of:
+///
+/// ```kdl
+/// wait-for tcp="postgres:5432" timeout=30
+/// wait-for http="http://api:8080/health"
+/// wait-for shell="pg_isready -h postgres"
+/// ```
+fn parse_artifact_dependency(node: &KdlNode, stage: &str) -> ConfigResult<ArtifactDependency> {
+    let invalid = |message: String| ConfigError::InvalidValue {
+        field: format!("needs-artifact of stage '{}'", stage),
+        message,
+    };
+    let pipeline = get_string_prop(node, "from-pipeline")
+        .ok_or_else(|| invalid("from-pipeline is required".to_string()))?;
+    let artifact = get_string_prop(node, "artifact")
+        .ok_or_else(|| invalid("artifact is required".to_string()))?;
+    // A run number may be written bare
+    let version = match node.get("version") {
+        Some(version) => match (version.as_string(), version.as_integer()) {
+            (Some(version), _) => version.parse().map_err(invalid)?,
+            (None, Some(number)) => format!("{}", number).parse().map_err(invalid)?,
+            _ => {
+                return Err(invalid(
+                    "version must be a string or run number".to_string(),
+                ));
+            }
+        },
+        None => ArtifactVersion::default(),
+    };
+    let path = get_string_prop(node, "path");
+    if let Some(path) = &path
+        && !Path::new(path)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(invalid(format!(
+            "path '{}' must be relative to the workspace",
+            path
+        )));
+    }
+    Ok(ArtifactDependency {
+        pipeline,
+        artifact,
+        version,
+        path,
+    })
+}
+
+fn parse_wait_for(node: &KdlNode, stage: &str) -> ConfigResult<WaitCondition> {
+    let invalid = |message: String| ConfigError::InvalidValue {
+        field: format!("wait-for of stage '{}'", stage),
+        message,
+    };
+    let mut probes = Vec::new();
+    if let Some(address) = get_string_prop(node, "tcp") {
+        let (host, port) = address
+            .rsplit_once(':')
+            .and_then(|(host, port)| Some((host, port.parse::<u16>().ok()?)))
+            .filter(|(host, _)| !host.is_empty())
+            .ok_or_else(|| invalid(format!("'{}' is not a host:port address", address)))?;
+        probes.push(Probe::Tcp {
+            host: host.to_string(),
+            port,
+        });
+    }
+    if let Some(url) = get_string_prop(node, "http") {
+        probes.push(Probe::Http { url });
+    }
+    if let Some(command) = get_string_prop(node, "shell") {
+        probes.push(Probe::Shell { command });
+    }
+    let probe = match <[Probe; 1]>::try_from(probes) {
+        Ok([probe]) => probe,
+        Err(_) => {
+            return Err(invalid(
+                "expected exactly one of tcp, http or shell".to_string(),
+            ));
+        }
+    };
+    let timeout_secs = match node.get("timeout") {
+        Some(timeout) => timeout
+            .as_integer()
+            .and_then(|t| u32::try_from(t).ok())
+            .filter(|t| *t > 0)
+            .ok_or_else(|| invalid("timeout must be a positive number of seconds".to_string()))?,
+        None => DEFAULT_WAIT_TIMEOUT_SECS,
+    };
+    Ok(WaitCondition {
+        probe,
+        timeout_secs,
+    })
+}
+
+/// Parse a job security context, from a stage or the system config's
+/// `jobs` defaults:
+///
+/// ```kdl
+/// security-context {
+///     run-as-non-root #true
+///     run-as-user 1000
+///     run-as-group 1000
+///     read-only-root-filesystem #true
+///     allow-privilege-escalation #false
+///     drop-capabilities "ALL"
+///     add-capabilities "NET_BIND_SERVICE"
+///     seccomp-profile "RuntimeDefault"
+/// }
+/// ```
+pub(crate) fn parse_security_context(node: &KdlNode, field: &str) -> ConfigResult<SecurityContext> {
+    let invalid = |message: String| ConfigError::InvalidValue {
+        field: field.to_string(),
+        message,
+    };
+    let first_arg = |node: &KdlNode| {
+        node.entries()
+            .iter()
+            .find(|e| e.name().is_none())
+            .map(|e| e.value().clone())
+    };
+    let mut context = SecurityContext::default();
+    for child in node.children().iter().flat_map(|c| c.nodes()) {
+        let setting = child.name().value();
+        let flag = || {
+            first_arg(child)
+                .and_then(|v| v.as_bool())
+                .ok_or_else(|| invalid(format!("{} takes #true or #false", setting)))
+        };
+        let id = || {
+            first_arg(child)
+                .and_then(|v| v.as_integer())
+                .and_then(|i| i64::try_from(i).ok())
+                .filter(|i| *i >= 0)
+                .ok_or_else(|| invalid(format!("{} takes a user or group ID", setting)))
+        };
+        match setting {
+            "run-as-non-root" => context.run_as_non_root = Some(flag()?),
+            "run-as-user" => context.run_as_user = Some(id()?),
+            "run-as-group" => context.run_as_group = Some(id()?),
+            "read-only-root-filesystem" => context.read_only_root_filesystem = Some(flag()?),
+            "allow-privilege-escalation" => context.allow_privilege_escalation = Some(flag()?),
+            "drop-capabilities" => context.drop_capabilities.extend(get_all_string_args(child)),
+            "add-capabilities" => context.add_capabilities.extend(get_all_string_args(child)),
+            "seccomp-profile" => {
+                let profile = get_first_string_arg(child).unwrap_or_default();
+                let valid = profile == "RuntimeDefault"
+                    || profile == "Unconfined"
+                    || profile
+                        .strip_prefix("Localhost/")
+                        .is_some_and(|path| !path.is_empty());
+                if !valid {
+                    return Err(invalid(format!(
+                        "unknown seccomp profile '{}' (expected RuntimeDefault, Unconfined or Localhost/<path>)",
+                        profile
+                    )));
+                }
+                context.seccomp_profile = Some(profile);
+            }
+            other => return Err(invalid(format!("unknown setting '{}'", other))),
+        }
+    }
+    if context.run_as_non_root == Some(true) && context.run_as_user == Some(0) {
+        return Err(invalid(
+            "run-as-non-root cannot be set with run-as-user 0".to_string(),
+        ));
+    }
+    Ok(context)
+}
+
+fn parse_scan(node: &KdlNode, stage: &str) -> ConfigResult<ScanSpec> {
+    let target = get_first_string_arg(node)
+        .ok_or_else(|| ConfigError::MissingField(format!("image to scan in stage '{}'", stage)))?;
+    let invalid = |field: &str, message: String| ConfigError::InvalidValue {
+        field: format!("{} of stage '{}'", field, stage),
+        message,
+    };
+    let scanner = match get_string_prop(node, "scanner") {
+        Some(scanner) => scanner.parse().map_err(|e| invalid("scanner", e))?,
+        None => Scanner::default(),
+    };
+    let fail_on = get_string_prop(node, "fail-on")
+        .map(|severity| severity.parse::<Severity>())
+        .transpose()
+        .map_err(|e| invalid("fail-on", e))?;
+
+    let mut ignore = Vec::new();
+    if let Some(children) = node.children() {
+        for child in children.nodes() {
+            if child.name().value() == "ignore" {
+                ignore.extend(get_all_string_args(child));
+            }
+        }
+    }
+
+    Ok(ScanSpec {
+        target,
+        scanner,
+        image: None,
+        fail_on,
+        ignore,
+        ignore_unfixed: get_bool_prop(node, "ignore-unfixed").unwrap_or(false),
+        sbom: get_bool_prop(node, "sbom").unwrap_or(false),
+    })
+}
+
+/// Parse a `release` node of a stage; the tag defaults to `${git.tag}`.
+///
+/// ```kdl
+/// release "${git.tag}" name="BuildIt ${git.tag}" prerelease=#false {
+///     notes "Highlights of this release"
+///     assets "buildit-*.tar.gz" "checksums.txt"
+/// }
+/// ```
+fn parse_release(node: &KdlNode) -> ReleaseSpec {
+    let mut notes = None;
+    let mut assets = Vec::new();
+    if let Some(children) = node.children() {
+        for child in children.nodes() {
+            match child.name().value() {
+                "notes" => notes = get_first_string_arg(child),
+                "assets" => assets.extend(get_all_string_args(child)),
+                _ => {}
+            }
+        }
+    }
+
+    ReleaseSpec {
+        tag: get_first_string_arg(node).unwrap_or_else(|| "${git.tag}".to_string()),
+        name: get_string_prop(node, "name"),
+        notes,
+        changelog: get_bool_prop(node, "changelog").unwrap_or(true),
+        assets,
+        draft: get_bool_prop(node, "draft").unwrap_or(false),
+        prerelease: get_bool_prop(node, "prerelease").unwrap_or(false),
+    }
+}
+
+fn parse_trigger_pipeline(node: &KdlNode, stage: &str) -> ConfigResult<TriggerPipelineSpec> {
+    let pipeline = get_first_string_arg(node).ok_or_else(|| {
+        ConfigError::MissingField(format!("pipeline of trigger-pipeline of stage '{}'", stage))
+    })?;
+    let mut params = BTreeMap::new();
+    if let Some(children) = node.children() {
+        for child in children.nodes() {
+            if child.name().value() == "param" {
+                let args = get_all_string_args(child);
+                match args.as_slice() {
+                    [name, value] => {
+                        params.insert(name.clone(), value.clone());
+                    }
+                    _ => {
+                        return Err(ConfigError::InvalidValue {
+                            field: format!("param of trigger-pipeline of stage '{}'", stage),
+                            message: "expected a name and a value".to_string(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(TriggerPipelineSpec {
+        pipeline,
+        branch: get_string_prop(node, "branch"),
+        params,
+        wait: get_bool_prop(node, "wait").unwrap_or(true),
+        propagate: get_bool_prop(node, "propagate").unwrap_or(true),
     })
 }
 
@@ -224,6 +875,319 @@ fn parse_cache(node: &KdlNode) -> ConfigResult<CacheConfig> {
     })
 }
 
+// ============================================================================
+// Templates and includes
+// ============================================================================
+
+/// Source of documents referenced by `include` directives.
+pub trait TemplateResolver {
+    /// Load the KDL text for an include reference.
+    fn resolve(&self, reference: &str) -> ConfigResult<String>;
+}
+
+/// Resolver that rejects every include.
+pub struct NoIncludes;
+
+impl TemplateResolver for NoIncludes {
+    fn resolve(&self, reference: &str) -> ConfigResult<String> {
+        Err(ConfigError::InvalidReference(format!(
+            "include '{}' is not allowed here",
+            reference
+        )))
+    }
+}
+
+/// Resolves includes as paths relative to a root directory.
+///
+/// References may not escape the root (absolute paths and `..` are rejected).
+pub struct FsTemplateResolver {
+    root: PathBuf,
+}
+
+impl FsTemplateResolver {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl TemplateResolver for FsTemplateResolver {
+    fn resolve(&self, reference: &str) -> ConfigResult<String> {
+        let path = Path::new(reference);
+        if !path
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(ConfigError::InvalidReference(format!(
+                "include '{}' must be a relative path inside the repository",
+                reference
+            )));
+        }
+        Ok(std::fs::read_to_string(self.root.join(path))?)
+    }
+}
+
+/// In-memory template library, e.g. templates managed server-side.
+#[derive(Debug, Clone, Default)]
+pub struct TemplateLibrary {
+    documents: HashMap<String, String>,
+}
+
+impl TemplateLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a document under a reference name.
+    pub fn insert(&mut self, reference: impl Into<String>, kdl: impl Into<String>) {
+        self.documents.insert(reference.into(), kdl.into());
+    }
+}
+
+impl TemplateResolver for TemplateLibrary {
+    fn resolve(&self, reference: &str) -> ConfigResult<String> {
+        self.documents.get(reference).cloned().ok_or_else(|| {
+            ConfigError::InvalidReference(format!("unknown include '{}'", reference))
+        })
+    }
+}
+
+static PARAM_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\$\{param\.([a-zA-Z_][a-zA-Z0-9_-]*)\}").unwrap());
+
+#[derive(Debug, Clone)]
+struct Template {
+    /// Parameter names with their optional default values.
+    params: Vec<(String, Option<String>)>,
+    body: Vec<KdlNode>,
+}
+
+struct TemplateExpander<'a> {
+    resolver: &'a dyn TemplateResolver,
+    templates: HashMap<String, Template>,
+    /// Includes currently being expanded, for cycle detection.
+    include_stack: Vec<String>,
+    /// Includes already expanded; including one again is a no-op.
+    included: HashSet<String>,
+    /// Templates currently being expanded, for cycle detection.
+    template_stack: Vec<String>,
+}
+
+/// Expand `include`, `template` and `extends` nodes into a flat document.
+fn expand_templates(
+    doc: &KdlDocument,
+    resolver: &dyn TemplateResolver,
+) -> ConfigResult<KdlDocument> {
+    let mut expander = TemplateExpander {
+        resolver,
+        templates: HashMap::new(),
+        include_stack: Vec::new(),
+        included: HashSet::new(),
+        template_stack: Vec::new(),
+    };
+    let nodes = expander.expand(doc.nodes())?;
+
+    let mut out = KdlDocument::new();
+    *out.nodes_mut() = apply_overrides(nodes);
+    Ok(out)
+}
+
+impl TemplateExpander<'_> {
+    fn expand(&mut self, nodes: &[KdlNode]) -> ConfigResult<Vec<KdlNode>> {
+        // Register this document's templates first so `extends` may precede them
+        for node in nodes.iter().filter(|n| n.name().value() == "template") {
+            self.register_template(node)?;
+        }
+
+        let mut out = Vec::new();
+        for node in nodes {
+            match node.name().value() {
+                "template" => {}
+                "include" => {
+                    let reference = get_first_string_arg(node)
+                        .ok_or_else(|| ConfigError::MissingField("include path".to_string()))?;
+                    out.extend(self.include(&reference)?);
+                }
+                "extends" => {
+                    out.extend(self.instantiate(node)?);
+                }
+                _ => out.push(node.clone()),
+            }
+        }
+        Ok(out)
+    }
+
+    fn include(&mut self, reference: &str) -> ConfigResult<Vec<KdlNode>> {
+        if self.include_stack.iter().any(|r| r == reference) {
+            let mut chain = self.include_stack.clone();
+            chain.push(reference.to_string());
+            return Err(ConfigError::CycleDetected(format!(
+                "include {}",
+                chain.join(" -> ")
+            )));
+        }
+        if !self.included.insert(reference.to_string()) {
+            return Ok(Vec::new());
+        }
+
+        let doc: KdlDocument = self.resolver.resolve(reference)?.parse()?;
+        self.include_stack.push(reference.to_string());
+        let nodes = self.expand(doc.nodes());
+        self.include_stack.pop();
+        nodes
+    }
+
+    fn register_template(&mut self, node: &KdlNode) -> ConfigResult<()> {
+        let name = get_first_string_arg(node)
+            .ok_or_else(|| ConfigError::MissingField("template name".to_string()))?;
+
+        let mut params = Vec::new();
+        let mut body = Vec::new();
+        if let Some(children) = node.children() {
+            for child in children.nodes() {
+                if child.name().value() == "param" {
+                    let param = get_first_string_arg(child).ok_or_else(|| {
+                        ConfigError::MissingField(format!("param name in template '{}'", name))
+                    })?;
+                    params.push((param, get_string_prop(child, "default")));
+                } else {
+                    body.push(child.clone());
+                }
+            }
+        }
+
+        if self.templates.contains_key(&name) {
+            return Err(ConfigError::Duplicate(format!("template '{}'", name)));
+        }
+        self.templates.insert(name, Template { params, body });
+        Ok(())
+    }
+
+    fn instantiate(&mut self, node: &KdlNode) -> ConfigResult<Vec<KdlNode>> {
+        let name = get_first_string_arg(node)
+            .ok_or_else(|| ConfigError::MissingField("extends template name".to_string()))?;
+        let template =
+            self.templates.get(&name).cloned().ok_or_else(|| {
+                ConfigError::InvalidReference(format!("unknown template '{}'", name))
+            })?;
+
+        if self.template_stack.contains(&name) {
+            let mut chain = self.template_stack.clone();
+            chain.push(name);
+            return Err(ConfigError::CycleDetected(format!(
+                "template {}",
+                chain.join(" -> ")
+            )));
+        }
+
+        // Collect parameter values: explicit properties override defaults
+        let mut values = HashMap::new();
+        for entry in node.entries() {
+            let Some(key) = entry.name() else { continue };
+            let key = key.value();
+            if !template.params.iter().any(|(p, _)| p == key) {
+                return Err(ConfigError::InvalidValue {
+                    field: format!("extends \"{}\"", name),
+                    message: format!("unknown param '{}'", key),
+                });
+            }
+            values.insert(key.to_string(), kdl_value_to_string(entry.value()));
+        }
+        for (param, default) in &template.params {
+            if !values.contains_key(param) {
+                let value = default.clone().ok_or_else(|| {
+                    ConfigError::MissingField(format!("param '{}' for template '{}'", param, name))
+                })?;
+                values.insert(param.clone(), value);
+            }
+        }
+
+        let mut body = template.body;
+        for node in &mut body {
+            substitute_params(node, &values, &name)?;
+        }
+
+        self.template_stack.push(name);
+        let expanded = self.expand(&body);
+        self.template_stack.pop();
+        expanded
+    }
+}
+
+fn kdl_value_to_string(value: &KdlValue) -> String {
+    match value {
+        KdlValue::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Replace `${param.NAME}` in every string value of a node and its children.
+fn substitute_params(
+    node: &mut KdlNode,
+    values: &HashMap<String, String>,
+    template: &str,
+) -> ConfigResult<()> {
+    for entry in node.entries_mut() {
+        let KdlValue::String(s) = entry.value() else {
+            continue;
+        };
+        if !PARAM_REGEX.is_match(s) {
+            continue;
+        }
+        if let Some(caps) = PARAM_REGEX
+            .captures_iter(s)
+            .find(|caps| !values.contains_key(&caps[1]))
+        {
+            return Err(ConfigError::InvalidReference(format!(
+                "template '{}' uses undeclared param '{}'",
+                template, &caps[1]
+            )));
+        }
+        let replaced = PARAM_REGEX
+            .replace_all(s, |caps: &regex::Captures| values[&caps[1]].clone())
+            .to_string();
+        entry.set_value(replaced);
+        entry.clear_format();
+    }
+
+    if let Some(children) = node.children_mut() {
+        for child in children.nodes_mut() {
+            substitute_params(child, values, template)?;
+        }
+    }
+    Ok(())
+}
+
+/// Let later `stage`/`cache` definitions replace earlier ones with the same name.
+///
+/// The replacement keeps the position of the first definition so stage order
+/// stays stable when a pipeline overrides an inherited stage.
+fn apply_overrides(nodes: Vec<KdlNode>) -> Vec<KdlNode> {
+    let key = |node: &KdlNode| match node.name().value() {
+        kind @ ("stage" | "cache") => get_first_string_arg(node).map(|n| (kind.to_string(), n)),
+        _ => None,
+    };
+
+    let mut latest: HashMap<(String, String), KdlNode> = HashMap::new();
+    for node in &nodes {
+        if let Some(k) = key(node) {
+            latest.insert(k, node.clone());
+        }
+    }
+
+    let mut out = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        match key(&node) {
+            Some(k) => {
+                if let Some(winner) = latest.remove(&k) {
+                    out.push(winner);
+                }
+            }
+            None => out.push(node),
+        }
+    }
+    out
+}
+
 // Helper functions for extracting values from KDL nodes
 
 fn get_first_string_arg(node: &KdlNode) -> Option<String> {
@@ -332,6 +1296,7 @@ fn dfs_detect_cycle<'a>(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use buildit_core::executor::{Arch, Os, PullPolicy};
 
     #[test]
     fn test_parse_simple_pipeline() {
@@ -351,63 +1316,807 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_pipeline_with_dependencies() {
+    fn test_parse_stage_runs_on() {
         let kdl = r#"
-            pipeline "multi-stage"
+            pipeline "train"
 
-            stage "test" {
-                image "rust:1.75"
-                run "cargo test"
-            }
-
-            stage "build" needs="test" {
-                image "rust:1.75"
-                run "cargo build --release"
+            stage "fit" {
+                image "pytorch/pytorch"
+                runs-on "gpu" "cuda"
+                run "python train.py"
             }
         "#;
 
         let pipeline = parse_pipeline(kdl).unwrap();
-        assert_eq!(pipeline.stages.len(), 2);
-        assert_eq!(pipeline.stages[1].needs, vec!["test"]);
+        assert_eq!(pipeline.stages[0].runs_on, ["gpu", "cuda"]);
     }
 
     #[test]
-    fn test_detect_missing_dependency() {
+    fn test_parse_stage_test_reports() {
         let kdl = r#"
-            pipeline "bad-deps"
+            pipeline "ci"
 
-            stage "build" needs="nonexistent" {
-                image "rust:1.75"
-                run "cargo build"
+            stage "test" {
+                image "rust:1.80"
+                run "cargo nextest run --profile ci"
+                test-reports "target/nextest/ci/junit.xml" "reports/*.xml"
             }
         "#;
 
-        let result = parse_pipeline(kdl);
-        assert!(result.is_err());
-        assert!(matches!(
-            result.unwrap_err(),
-            ConfigError::InvalidReference(_)
-        ));
+        let pipeline = parse_pipeline(kdl).unwrap();
+        match &pipeline.stages[0].action {
+            StageAction::Run { test_reports, .. } => {
+                assert_eq!(
+                    test_reports,
+                    &["target/nextest/ci/junit.xml", "reports/*.xml"]
+                )
+            }
+            _ => panic!("expected run stage"),
+        }
     }
 
     #[test]
-    fn test_detect_cycle() {
+    fn test_parse_scan_stage() {
         let kdl = r#"
-            pipeline "cyclic"
+            pipeline "ci"
 
-            stage "a" needs="b" {
-                image "alpine"
-                run "echo a"
+            stage "build" {
+                image "docker:cli"
+                run "docker build -t registry.example.com/api:${git.sha} ."
             }
 
-            stage "b" needs="a" {
-                image "alpine"
-                run "echo b"
+            stage "scan" needs="build" {
+                scan "registry.example.com/api:${git.sha}" scanner="trivy" fail-on="HIGH" sbom=#true {
+                    ignore "CVE-2024-0001" "GHSA-abcd"
+                }
             }
         "#;
 
-        let result = parse_pipeline(kdl);
-        assert!(result.is_err());
-        assert!(matches!(result.unwrap_err(), ConfigError::CycleDetected(_)));
+        let pipeline = parse_pipeline(kdl).unwrap();
+        match &pipeline.stages[1].action {
+            StageAction::Scan(scan) => {
+                assert_eq!(scan.target, "registry.example.com/api:${git.sha}");
+                assert_eq!(scan.scanner, Scanner::Trivy);
+                assert_eq!(scan.image(), "aquasec/trivy:latest");
+                assert_eq!(scan.fail_on, Some(Severity::High));
+                assert_eq!(scan.ignore, ["CVE-2024-0001", "GHSA-abcd"]);
+                assert!(scan.sbom);
+                assert!(!scan.ignore_unfixed);
+            }
+            _ => panic!("expected scan stage"),
+        }
+
+        let bad = r#"
+            pipeline "ci"
+            stage "scan" {
+                scan "api:latest" fail-on="severe"
+            }
+        "#;
+        assert!(parse_pipeline(bad).is_err());
+    }
+
+    #[test]
+    fn test_parse_release_stage() {
+        let kdl = r#"
+            pipeline "cli"
+            on "tag" pattern="v*"
+
+            stage "build" {
+                image "rust:1.80"
+                run "cargo build --release"
+            }
+
+            stage "publish" needs="build" {
+                release name="CLI ${git.tag}" prerelease=#true {
+                    notes "Built by BuildIt"
+                    assets "buildit-*.tar.gz" "checksums.txt"
+                }
+            }
+        "#;
+
+        let pipeline = parse_pipeline(kdl).unwrap();
+        match &pipeline.stages[1].action {
+            StageAction::Release(release) => {
+                assert_eq!(release.tag, "${git.tag}");
+                assert_eq!(release.name(), "CLI ${git.tag}");
+                assert_eq!(release.notes.as_deref(), Some("Built by BuildIt"));
+                assert_eq!(release.assets, ["buildit-*.tar.gz", "checksums.txt"]);
+                assert!(release.changelog);
+                assert!(release.prerelease);
+                assert!(!release.draft);
+            }
+            _ => panic!("expected release stage"),
+        }
+
+        let with_commands = r#"
+            pipeline "cli"
+            stage "publish" {
+                image "alpine"
+                run "make dist"
+                release "v1.0.0"
+            }
+        "#;
+        assert!(parse_pipeline(with_commands).is_err());
+    }
+
+    #[test]
+    fn test_parse_trigger_pipeline_stage() {
+        let kdl = r#"
+            pipeline "api"
+
+            stage "build" {
+                image "rust:1.80"
+                run "cargo build --release"
+            }
+
+            stage "deploy" needs="build" {
+                trigger-pipeline "deploy" branch="main" propagate=#false {
+                    param "service" "api"
+                    param "version" "${git.sha}"
+                }
+            }
+        "#;
+
+        let pipeline = parse_pipeline(kdl).unwrap();
+        match &pipeline.stages[1].action {
+            StageAction::TriggerPipeline(trigger) => {
+                assert_eq!(trigger.pipeline, "deploy");
+                assert_eq!(trigger.branch.as_deref(), Some("main"));
+                assert_eq!(trigger.params["service"], "api");
+                assert_eq!(trigger.params["version"], "${git.sha}");
+                assert!(trigger.wait);
+                assert!(!trigger.propagate);
+            }
+            _ => panic!("expected trigger-pipeline stage"),
+        }
+
+        let with_release = r#"
+            pipeline "api"
+            stage "deploy" {
+                trigger-pipeline "deploy"
+                release "v1.0.0"
+            }
+        "#;
+        assert!(parse_pipeline(with_release).is_err());
+
+        let without_value = r#"
+            pipeline "api"
+            stage "deploy" {
+                trigger-pipeline "deploy" {
+                    param "service"
+                }
+            }
+        "#;
+        assert!(parse_pipeline(without_value).is_err());
+    }
+
+    #[test]
+    fn test_parse_needs_artifact() {
+        let kdl = r#"
+            pipeline "app"
+
+            stage "link" {
+                image "gcc:14"
+                needs-artifact from-pipeline="libfoo" artifact="dist/libfoo.so" path="lib/"
+                needs_artifact from-pipeline="schemas" artifact="schema.json" version=42
+                run "make"
+            }
+        "#;
+
+        let pipeline = parse_pipeline(kdl).unwrap();
+        let deps = &pipeline.stages[0].needs_artifacts;
+        assert_eq!(deps.len(), 2);
+        assert_eq!(deps[0].pipeline, "libfoo");
+        assert_eq!(deps[0].version, ArtifactVersion::LatestSuccessful);
+        assert_eq!(deps[0].destination(), "lib/libfoo.so");
+        assert_eq!(deps[1].version, ArtifactVersion::Run(42));
+        assert_eq!(deps[1].destination(), "schema.json");
+
+        let escaping = r#"
+            pipeline "app"
+            stage "link" {
+                image "gcc:14"
+                needs-artifact from-pipeline="libfoo" artifact="libfoo.so" path="../libfoo.so"
+                run "make"
+            }
+        "#;
+        assert!(parse_pipeline(escaping).is_err());
+
+        let unknown_version = r#"
+            pipeline "app"
+            stage "link" {
+                image "gcc:14"
+                needs-artifact from-pipeline="libfoo" artifact="libfoo.so" version="newest"
+                run "make"
+            }
+        "#;
+        assert!(parse_pipeline(unknown_version).is_err());
+    }
+
+    #[test]
+    fn test_parse_stage_resources() {
+        let kdl = r#"
+            pipeline "train"
+
+            stage "fit" {
+                image "pytorch/pytorch"
+                resources cpu="4" memory="16Gi" memory-limit="24Gi"
+                run "python train.py"
+            }
+        "#;
+
+        let pipeline = parse_pipeline(kdl).unwrap();
+        let resources = &pipeline.stages[0].resources;
+        assert_eq!(resources.cpu_request.as_deref(), Some("4"));
+        assert_eq!(resources.memory_request.as_deref(), Some("16Gi"));
+        assert_eq!(resources.cpu_limit, None);
+        assert_eq!(resources.memory_limit.as_deref(), Some("24Gi"));
+    }
+
+    #[test]
+    fn test_parse_stage_platform() {
+        let kdl = r#"
+            pipeline "release"
+
+            stage "windows" {
+                image "mcr.microsoft.com/windows/servercore:ltsc2022"
+                platform "windows/amd64"
+            }
+
+            stage "sign" {
+                image "xcode"
+                platform "macos"
+            }
+
+            stage "test" {
+                image "rust:1.85"
+            }
+        "#;
+
+        let pipeline = parse_pipeline(kdl).unwrap();
+        assert_eq!(
+            pipeline.stages[0].platform,
+            Platform::new(Os::Windows, Arch::Amd64)
+        );
+        assert_eq!(pipeline.stages[1].platform.os, Os::Macos);
+        assert_eq!(pipeline.stages[1].platform.arch, None);
+        assert_eq!(pipeline.stages[2].platform, Platform::default());
+
+        let kdl = r#"
+            pipeline "release"
+
+            stage "build" {
+                image "alpine"
+                platform "linux/sparc"
+            }
+        "#;
+        let err = parse_pipeline(kdl).unwrap_err();
+        assert!(err.to_string().contains("unknown architecture"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_stage_security_context() {
+        let kdl = r#"
+            pipeline "ci"
+
+            stage "build" {
+                image "rust:1.85"
+                security-context {
+                    run-as-non-root #true
+                    run-as-user 1000
+                    read-only-root-filesystem #true
+                    allow-privilege-escalation #false
+                    drop-capabilities "ALL"
+                    add-capabilities "NET_BIND_SERVICE"
+                    seccomp-profile "Localhost/profiles/build.json"
+                }
+                run "cargo build"
+            }
+
+            stage "test" {
+                image "rust:1.85"
+            }
+        "#;
+
+        let pipeline = parse_pipeline(kdl).unwrap();
+        assert_eq!(
+            pipeline.stages[0].security_context,
+            Some(SecurityContext {
+                run_as_non_root: Some(true),
+                run_as_user: Some(1000),
+                run_as_group: None,
+                read_only_root_filesystem: Some(true),
+                allow_privilege_escalation: Some(false),
+                drop_capabilities: vec!["ALL".to_string()],
+                add_capabilities: vec!["NET_BIND_SERVICE".to_string()],
+                seccomp_profile: Some("Localhost/profiles/build.json".to_string()),
+            })
+        );
+        assert_eq!(pipeline.stages[1].security_context, None);
+
+        for (setting, message) in [
+            ("run-as-user \"root\"", "user or group ID"),
+            ("run-as-non-root 1", "#true or #false"),
+            ("seccomp-profile \"Localhost/\"", "unknown seccomp profile"),
+            ("privileged #true", "unknown setting 'privileged'"),
+            ("run-as-non-root #true\n run-as-user 0", "cannot be set"),
+        ] {
+            let kdl = format!(
+                "pipeline \"ci\"\nstage \"test\" {{\n image \"alpine\"\n security-context {{\n {}\n }}\n}}",
+                setting
+            );
+            let err = parse_pipeline(&kdl).unwrap_err();
+            assert!(err.to_string().contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_parse_stage_container_options() {
+        let kdl = r#"
+            pipeline "ci"
+
+            stage "legacy" {
+                image "legacy-builder:1"
+                user "root"
+                entrypoint "/usr/bin/tini" "--"
+                privileged #true
+                extra-host "registry.internal:10.0.0.5" "docker.host:host-gateway"
+                extra-host "v6.internal:fd00::1"
+                network "host"
+                run "make"
+            }
+
+            stage "plain" {
+                image "alpine"
+                entrypoint ""
+                privileged #false
+            }
+        "#;
+
+        let pipeline = parse_pipeline(kdl).unwrap();
+        assert_eq!(
+            pipeline.stages[0].container,
+            ContainerOptions {
+                user: Some("root".to_string()),
+                entrypoint: Some(vec!["/usr/bin/tini".to_string(), "--".to_string()]),
+                privileged: true,
+                extra_hosts: vec![
+                    "registry.internal:10.0.0.5".to_string(),
+                    "docker.host:host-gateway".to_string(),
+                    "v6.internal:fd00::1".to_string(),
+                ],
+                network_mode: Some("host".to_string()),
+            }
+        );
+        let plain = &pipeline.stages[1].container;
+        assert_eq!(plain.entrypoint, Some(vec![]));
+        assert!(!plain.privileged);
+
+        for entry in ["registry.internal", ":10.0.0.5", "registry:not-an-ip"] {
+            let kdl = format!(
+                "pipeline \"ci\"\nstage \"test\" {{\n image \"alpine\"\n extra-host \"{}\"\n}}",
+                entry
+            );
+            let err = parse_pipeline(&kdl).unwrap_err();
+            assert!(err.to_string().contains("host:ip"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_parse_stage_pull_policy() {
+        let kdl = r#"
+            pipeline "ci"
+
+            stage "build" {
+                image "rust:1.75"
+                pull-policy "if-not-present"
+            }
+
+            stage "test" {
+                image "rust:1.75"
+            }
+        "#;
+
+        let pipeline = parse_pipeline(kdl).unwrap();
+        assert_eq!(
+            pipeline.stages[0].pull_policy,
+            Some(PullPolicy::IfNotPresent)
+        );
+        assert_eq!(pipeline.stages[1].pull_policy, None);
+
+        let kdl =
+            "pipeline \"ci\"\nstage \"test\" {\n image \"alpine\"\n pull-policy \"sometimes\"\n}";
+        let err = parse_pipeline(kdl).unwrap_err();
+        assert!(err.to_string().contains("unknown pull policy"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_stage_wait_for() {
+        let kdl = r#"
+            pipeline "ci"
+
+            stage "test" {
+                image "rust:1.85"
+                wait-for tcp="postgres:5432" timeout=30
+                wait-for http="http://api:8080/health"
+                wait-for shell="pg_isready -h postgres"
+                run "cargo test"
+            }
+        "#;
+
+        let pipeline = parse_pipeline(kdl).unwrap();
+        let wait_for = &pipeline.stages[0].wait_for;
+        assert_eq!(wait_for.len(), 3);
+        assert_eq!(
+            wait_for[0],
+            WaitCondition {
+                probe: Probe::Tcp {
+                    host: "postgres".to_string(),
+                    port: 5432
+                },
+                timeout_secs: 30,
+            }
+        );
+        assert_eq!(
+            wait_for[1].probe,
+            Probe::Http {
+                url: "http://api:8080/health".to_string()
+            }
+        );
+        assert_eq!(wait_for[1].timeout_secs, DEFAULT_WAIT_TIMEOUT_SECS);
+        assert_eq!(
+            wait_for[2].probe,
+            Probe::Shell {
+                command: "pg_isready -h postgres".to_string()
+            }
+        );
+
+        for (condition, message) in [
+            (r#"tcp="postgres""#, "host:port"),
+            (r#"tcp="db:5432" http="http://db""#, "exactly one"),
+            (r#"shell="true" timeout=0"#, "positive"),
+        ] {
+            let kdl = format!(
+                "pipeline \"ci\"\nstage \"test\" {{\n image \"alpine\"\n wait-for {}\n}}",
+                condition
+            );
+            let err = parse_pipeline(&kdl).unwrap_err();
+            assert!(err.to_string().contains(message), "{}", err);
+        }
+
+        let kdl = r#"
+            pipeline "ci"
+
+            stage "test" {
+                image "mcr.microsoft.com/windows/servercore:ltsc2022"
+                platform "windows"
+                wait-for tcp="db:1433"
+            }
+        "#;
+        let err = parse_pipeline(kdl).unwrap_err();
+        assert!(
+            err.to_string().contains("not supported on Windows"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_parse_pipeline_with_dependencies() {
+        let kdl = r#"
+            pipeline "multi-stage"
+
+            stage "test" {
+                image "rust:1.75"
+                run "cargo test"
+            }
+
+            stage "build" needs="test" {
+                image "rust:1.75"
+                run "cargo build --release"
+            }
+        "#;
+
+        let pipeline = parse_pipeline(kdl).unwrap();
+        assert_eq!(pipeline.stages.len(), 2);
+        assert_eq!(pipeline.stages[1].needs, vec!["test"]);
+    }
+
+    #[test]
+    fn test_parse_owners() {
+        let kdl = r#"
+            pipeline "owned"
+
+            owners "team:platform" "@alice" {
+                "deploy-*" "@sre"
+                "deploy-docs" "@docs"
+            }
+
+            stage "build" {
+                image "rust:1.75"
+                run "cargo build"
+            }
+        "#;
+
+        let pipeline = parse_pipeline(kdl).unwrap();
+        let ownership = &pipeline.ownership;
+        assert_eq!(ownership.owners, vec!["team:platform", "@alice"]);
+        assert_eq!(
+            ownership.owners_for_stage("build"),
+            ["team:platform", "@alice"]
+        );
+        assert_eq!(ownership.owners_for_stage("deploy-prod"), ["@sre"]);
+        // Last matching rule wins
+        assert_eq!(ownership.owners_for_stage("deploy-docs"), ["@docs"]);
+        assert_eq!(
+            ownership.all_owners(),
+            vec!["@alice", "@docs", "@sre", "team:platform"]
+        );
+    }
+
+    #[test]
+    fn test_parse_checkout() {
+        let kdl = r#"
+            pipeline "monorepo"
+
+            checkout depth=0 submodules=#true lfs=#true {
+                sparse "services/api" "libs"
+            }
+
+            stage "build" {
+                image "rust:1.75"
+                run "cargo build"
+            }
+        "#;
+
+        let checkout = parse_pipeline(kdl).unwrap().checkout;
+        assert_eq!(checkout.depth, 0);
+        assert!(checkout.submodules);
+        assert!(checkout.lfs);
+        assert!(!checkout.cache);
+        assert_eq!(checkout.sparse, vec!["services/api", "libs"]);
+
+        let kdl = r#"
+            pipeline "plain"
+            stage "build" {
+                image "rust:1.75"
+            }
+        "#;
+        assert_eq!(
+            parse_pipeline(kdl).unwrap().checkout,
+            CheckoutConfig::default()
+        );
+    }
+
+    #[test]
+    fn test_checkout_rejects_paths_outside_the_repository() {
+        let kdl = r#"
+            pipeline "escape"
+            checkout {
+                sparse "../other"
+            }
+            stage "build" {
+                image "rust:1.75"
+            }
+        "#;
+        assert!(matches!(
+            parse_pipeline(kdl),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_params() {
+        let kdl = r#"
+            pipeline "deploy"
+            params {
+                environment type="choice" default="staging" {
+                    options "staging" "production"
+                }
+                dry_run type="bool" default=#false
+                version description="Release to deploy"
+            }
+            stage "deploy" {
+                image "alpine"
+                run "./deploy.sh ${params.environment}"
+            }
+        "#;
+        let params = parse_pipeline(kdl).unwrap().params;
+        assert_eq!(params.len(), 3);
+        assert_eq!(
+            params[0].kind,
+            ParamKind::Choice {
+                options: vec!["staging".to_string(), "production".to_string()]
+            }
+        );
+        assert_eq!(params[1].kind, ParamKind::Bool);
+        assert_eq!(params[1].default.as_deref(), Some("false"));
+        assert_eq!(params[2].kind, ParamKind::String);
+        assert_eq!(params[2].default, None);
+        assert_eq!(params[2].description.as_deref(), Some("Release to deploy"));
+
+        let bad_default = r#"
+            pipeline "deploy"
+            params {
+                environment type="choice" default="qa" {
+                    options "staging" "production"
+                }
+            }
+        "#;
+        assert!(matches!(
+            parse_pipeline(bad_default),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn test_detect_missing_dependency() {
+        let kdl = r#"
+            pipeline "bad-deps"
+
+            stage "build" needs="nonexistent" {
+                image "rust:1.75"
+                run "cargo build"
+            }
+        "#;
+
+        let result = parse_pipeline(kdl);
+        assert!(result.is_err());
+        assert!(matches!(
+            result.unwrap_err(),
+            ConfigError::InvalidReference(_)
+        ));
+    }
+
+    #[test]
+    fn test_detect_cycle() {
+        let kdl = r#"
+            pipeline "cyclic"
+
+            stage "a" needs="b" {
+                image "alpine"
+                run "echo a"
+            }
+
+            stage "b" needs="a" {
+                image "alpine"
+                run "echo b"
+            }
+        "#;
+
+        let result = parse_pipeline(kdl);
+        assert!(result.is_err());
+        assert!(matches!(result.unwrap_err(), ConfigError::CycleDetected(_)));
+    }
+
+    #[test]
+    fn test_extends_template_with_params() {
+        let kdl = r#"
+            pipeline "templated"
+
+            template "rust-ci" {
+                param "toolchain" default="1.75"
+                param "package"
+
+                stage "test" {
+                    image "rust:${param.toolchain}"
+                    run "cargo test -p ${param.package}"
+                }
+            }
+
+            extends "rust-ci" package="buildit-core"
+        "#;
+
+        let pipeline = parse_pipeline(kdl).unwrap();
+        assert_eq!(pipeline.stages.len(), 1);
+        match &pipeline.stages[0].action {
+            StageAction::Run {
+                image, commands, ..
+            } => {
+                assert_eq!(image, "rust:1.75");
+                assert_eq!(commands, &vec!["cargo test -p buildit-core".to_string()]);
+            }
+            _ => panic!("expected run stage"),
+        }
+    }
+
+    #[test]
+    fn test_local_stage_overrides_template_stage() {
+        let kdl = r#"
+            pipeline "override"
+
+            template "base" {
+                stage "lint" {
+                    image "alpine"
+                    run "echo lint"
+                }
+                stage "test" needs="lint" {
+                    image "alpine"
+                    run "echo test"
+                }
+            }
+
+            extends "base"
+
+            stage "lint" {
+                image "rust:1.80"
+                run "cargo clippy"
+            }
+        "#;
+
+        let pipeline = parse_pipeline(kdl).unwrap();
+        let names: Vec<_> = pipeline.stages.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["lint", "test"]);
+        match &pipeline.stages[0].action {
+            StageAction::Run { image, .. } => assert_eq!(image, "rust:1.80"),
+            _ => panic!("expected run stage"),
+        }
+    }
+
+    #[test]
+    fn test_include_from_library() {
+        let mut library = TemplateLibrary::new();
+        library.insert(
+            "shared/common.kdl",
+            r#"
+            env {
+                CI "true"
+            }
+
+            template "lint" {
+                stage "fmt" {
+                    image "rust:1.80"
+                    run "cargo fmt --check"
+                }
+            }
+            "#,
+        );
+
+        let kdl = r#"
+            pipeline "included"
+            include "shared/common.kdl"
+            extends "lint"
+        "#;
+
+        let pipeline = parse_pipeline_with_resolver(kdl, &library).unwrap();
+        assert_eq!(pipeline.env.get("CI"), Some(&"true".to_string()));
+        assert_eq!(pipeline.stages[0].name, "fmt");
+    }
+
+    #[test]
+    fn test_detect_include_cycle() {
+        let mut library = TemplateLibrary::new();
+        library.insert("a.kdl", r#"include "b.kdl""#);
+        library.insert("b.kdl", r#"include "a.kdl""#);
+
+        let kdl = r#"
+            pipeline "cyclic"
+            include "a.kdl"
+        "#;
+
+        let err = parse_pipeline_with_resolver(kdl, &library).unwrap_err();
+        assert!(matches!(err, ConfigError::CycleDetected(_)));
+    }
+
+    #[test]
+    fn test_detect_template_cycle() {
+        let kdl = r#"
+            pipeline "cyclic"
+
+            template "a" {
+                extends "b"
+            }
+
+            template "b" {
+                extends "a"
+            }
+
+            extends "a"
+        "#;
+
+        assert!(matches!(
+            parse_pipeline(kdl).unwrap_err(),
+            ConfigError::CycleDetected(_)
+        ));
     }
 }
@@ -14,16 +14,24 @@
 //! - `${run.id}` - Run ID
 //! - `${run.number}` - Run number
 //! - `${stage.name}` - Current stage name
+//! - `${stages.NAME.status}` - Status of a stage that already ran
+//! - `${stages.NAME.outputs.KEY}` - Output a stage that already ran set
+//! - `${params.NAME}` - Parameter the run was triggered with
 //! - `${env.VAR_NAME}` - Environment variable
 //! - `${secrets.SECRET_NAME}` - Secret value
 //! - `${timestamp}` - Unix timestamp
 //! - `${date}` - ISO date (YYYY-MM-DD)
 //! - `${datetime}` - ISO datetime
+//!
+//! Interpolations can also hold defaults, function calls and ternaries; see
+//! [`crate::expr`].
 
 use regex::Regex;
 use std::collections::HashMap;
 use std::sync::LazyLock;
 
+use crate::expr;
+
 /// Variable context containing all available variables for interpolation.
 #[derive(Debug, Clone, Default)]
 pub struct VariableContext {
@@ -41,6 +49,10 @@ pub struct VariableContext {
     pub secrets: HashMap<String, String>,
     /// Custom variables defined by user
     pub custom: HashMap<String, String>,
+    /// Results of the stages that already ran, by name
+    pub stages: HashMap<String, StageResultContext>,
+    /// Parameters the run was triggered with
+    pub params: HashMap<String, String>,
 }
 
 /// Git context for variable interpolation.
@@ -79,10 +91,22 @@ pub struct StageContext {
     pub index: usize,
 }
 
-// Regex for matching ${...} variables
-static VAR_REGEX: LazyLock<Regex> = LazyLock::new(|| {
-    Regex::new(r"\$\{([a-zA-Z_][a-zA-Z0-9_]*(?:\.[a-zA-Z_][a-zA-Z0-9_]*)?)\}").unwrap()
-});
+/// Result of a stage that already ran, for the stages after it.
+#[derive(Debug, Clone, Default)]
+pub struct StageResultContext {
+    /// `succeeded`, `failed` or `skipped`.
+    pub status: String,
+    /// Values the stage's job set by printing `OUTPUT_MARKER` lines.
+    pub outputs: HashMap<String, String>,
+}
+
+/// Prefix of the log lines a job sets an output with,
+/// `##[buildit:output] KEY=VALUE`.
+pub const OUTPUT_MARKER: &str = "##[buildit:output] ";
+
+// Matches secret references within an interpolation
+static SECRET_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\bsecrets\.[a-zA-Z_][a-zA-Z0-9_]*").unwrap());
 
 impl VariableContext {
     /// Create a new empty variable context.
@@ -227,6 +251,13 @@ impl VariableContext {
             ["stage", "name"] => Some(self.stage.name.clone()),
             ["stage", "index"] => Some(self.stage.index.to_string()),
 
+            ["stages", name, "status"] => self.stages.get(*name).map(|s| s.status.clone()),
+            ["stages", name, "outputs", key] => self
+                .stages
+                .get(*name)
+                .and_then(|s| s.outputs.get(*key).cloned()),
+
+            ["params", name] => self.params.get(*name).cloned(),
             ["env", name] => self.env.get(*name).cloned(),
             ["secrets", name] => self.secrets.get(*name).cloned(),
 
@@ -234,23 +265,38 @@ impl VariableContext {
             ["date"] => Some(chrono::Utc::now().format("%Y-%m-%d").to_string()),
             ["datetime"] => Some(chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string()),
 
-            // Single-part names check custom variables
-            [name] => self.custom.get(*name).cloned(),
+            // Single-part names check custom, then environment variables
+            [name] => self
+                .custom
+                .get(*name)
+                .or_else(|| self.env.get(*name))
+                .cloned(),
 
             _ => None,
         }
     }
 
     /// Interpolate all variables in a string.
-    /// Variables are specified as `${var_name}` or `${namespace.var_name}`.
+    /// Variables are specified as `${var_name}` or `${namespace.var_name}`,
+    /// or as expressions such as `${lower(git.branch)}`.
     pub fn interpolate(&self, input: &str) -> String {
-        VAR_REGEX
-            .replace_all(input, |caps: &regex::Captures| {
-                let var_name = &caps[1];
-                self.resolve(var_name)
-                    .unwrap_or_else(|| format!("${{{}}}", var_name))
-            })
-            .to_string()
+        expr::interpolate(input, self)
+    }
+
+    /// Record a stage's result for the stages after it.
+    pub fn record_stage(
+        &mut self,
+        name: &str,
+        status: impl Into<String>,
+        outputs: HashMap<String, String>,
+    ) {
+        self.stages.insert(
+            name.to_string(),
+            StageResultContext {
+                status: status.into(),
+                outputs,
+            },
+        );
     }
 
     /// Interpolate variables in a list of strings.
@@ -267,16 +313,10 @@ impl VariableContext {
 
     /// Get a list of all secret variable names used in a string (for masking).
     pub fn find_secrets_in_string(&self, input: &str) -> Vec<String> {
-        VAR_REGEX
-            .captures_iter(input)
-            .filter_map(|caps| {
-                let var_name = &caps[1];
-                if var_name.starts_with("secrets.") {
-                    Some(var_name.to_string())
-                } else {
-                    None
-                }
-            })
+        expr::bodies(input)
+            .into_iter()
+            .flat_map(|body| SECRET_REGEX.find_iter(body))
+            .map(|m| m.as_str().to_string())
             .collect()
     }
 
@@ -349,6 +389,11 @@ impl VariableContextBuilder {
         self
     }
 
+    pub fn with_param(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.ctx.params.insert(name.into(), value.into());
+        self
+    }
+
     pub fn with_custom(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
         self.ctx.custom.insert(key.into(), value.into());
         self
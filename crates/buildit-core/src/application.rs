@@ -5,24 +5,21 @@
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::ToSchema;
 use uuid::Uuid;
 
 /// Sync policy for an application
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum SyncPolicy {
     /// Manual sync required
+    #[default]
     Manual,
     /// Auto-sync when git changes detected
     Auto,
 }
 
-impl Default for SyncPolicy {
-    fn default() -> Self {
-        SyncPolicy::Manual
-    }
-}
-
 impl std::fmt::Display for SyncPolicy {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -33,7 +30,7 @@ impl std::fmt::Display for SyncPolicy {
 }
 
 /// Application sync status
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum SyncStatus {
     /// Application is synced with git
@@ -43,15 +40,10 @@ pub enum SyncStatus {
     /// Sync is in progress
     Syncing,
     /// Unknown status (not yet checked)
+    #[default]
     Unknown,
 }
 
-impl Default for SyncStatus {
-    fn default() -> Self {
-        SyncStatus::Unknown
-    }
-}
-
 impl std::fmt::Display for SyncStatus {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -64,7 +56,7 @@ impl std::fmt::Display for SyncStatus {
 }
 
 /// Application health status
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum HealthStatus {
     /// All resources are healthy
@@ -78,15 +70,10 @@ pub enum HealthStatus {
     /// Some resources are missing
     Missing,
     /// Unknown health status
+    #[default]
     Unknown,
 }
 
-impl Default for HealthStatus {
-    fn default() -> Self {
-        HealthStatus::Unknown
-    }
-}
-
 impl std::fmt::Display for HealthStatus {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -100,6 +87,40 @@ impl std::fmt::Display for HealthStatus {
     }
 }
 
+/// Helm chart an application is rendered from.
+///
+/// Without `repo_url` the chart is read from the application's `path` in its
+/// git repository; with it, `chart` names a chart in that chart repository.
+/// `oci://` references in `chart` are pulled directly.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct HelmSource {
+    /// Chart repository URL
+    pub repo_url: Option<String>,
+    /// Chart name in `repo_url`, or an `oci://` reference
+    pub chart: Option<String>,
+    /// Chart version (latest if unset)
+    pub version: Option<String>,
+    /// Release name (defaults to the application name)
+    pub release_name: Option<String>,
+    /// Values applied to every environment
+    #[serde(flatten)]
+    pub values: HelmValues,
+    /// Overrides keyed by environment name, applied after the shared values
+    #[serde(default)]
+    pub environments: HashMap<String, HelmValues>,
+}
+
+/// Values passed to `helm template`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct HelmValues {
+    /// Values files, relative to the repository root
+    #[serde(default)]
+    pub values_files: Vec<String>,
+    /// Inline values, applied after the files
+    #[serde(default)]
+    pub values: serde_json::Value,
+}
+
 /// A GitOps Application that deploys Kubernetes manifests
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Application {
@@ -129,6 +150,10 @@ pub struct Application {
     pub synced_revision: Option<String>,
     /// Last sync timestamp
     pub last_synced_at: Option<DateTime<Utc>>,
+    /// Helm chart to render instead of plain manifests
+    pub helm: Option<HelmSource>,
+    /// Digest of the manifests applied by the last successful sync
+    pub rendered_digest: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -152,6 +177,8 @@ pub struct ApplicationSync {
     pub resources_deleted: i32,
     /// Error message if failed
     pub error_message: Option<String>,
+    /// Digest of the rendered manifests
+    pub rendered_digest: Option<String>,
     /// Started timestamp
     pub started_at: Option<DateTime<Utc>>,
     /// Finished timestamp
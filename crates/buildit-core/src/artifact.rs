@@ -5,6 +5,7 @@ use bytes::Bytes;
 use chrono::{DateTime, Utc};
 use futures::stream::BoxStream;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use crate::{ResourceId, Result};
 
@@ -34,10 +35,153 @@ pub struct ArtifactRef {
     pub created_at: DateTime<Utc>,
 }
 
+/// What an artifact contains, decided when it is uploaded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ArtifactKind {
+    /// JUnit XML test results.
+    Junit,
+    /// Coverage report (lcov, Cobertura, JSON summaries).
+    Coverage,
+    /// Software bill of materials (CycloneDX, SPDX).
+    Sbom,
+    /// Container image tarball (OCI layout or `docker save`).
+    Image,
+    /// Self-contained HTML report.
+    HtmlReport,
+    /// Anything else.
+    #[default]
+    Binary,
+}
+
+/// How an artifact can be rendered inline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum ArtifactPreview {
+    /// Served as HTML under a sandboxing Content-Security-Policy.
+    SandboxedHtml,
+    /// Pretty-printed JSON.
+    Json,
+}
+
+/// How many leading bytes are inspected when detecting a kind.
+const SNIFF_LEN: usize = 64 * 1024;
+
+impl ArtifactKind {
+    pub const ALL: [ArtifactKind; 6] = [
+        ArtifactKind::Junit,
+        ArtifactKind::Coverage,
+        ArtifactKind::Sbom,
+        ArtifactKind::Image,
+        ArtifactKind::HtmlReport,
+        ArtifactKind::Binary,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ArtifactKind::Junit => "junit",
+            ArtifactKind::Coverage => "coverage",
+            ArtifactKind::Sbom => "sbom",
+            ArtifactKind::Image => "image",
+            ArtifactKind::HtmlReport => "html-report",
+            ArtifactKind::Binary => "binary",
+        }
+    }
+
+    /// Guess the kind from the file name and the start of its contents.
+    pub fn detect(name: &str, data: &[u8]) -> Self {
+        let name = name.to_ascii_lowercase();
+        let file = name.rsplit('/').next().unwrap_or(&name);
+        let head = String::from_utf8_lossy(&data[..data.len().min(SNIFF_LEN)]);
+
+        if file.ends_with(".html") || file.ends_with(".htm") {
+            ArtifactKind::HtmlReport
+        } else if head.contains("<testsuites") || head.contains("<testsuite") {
+            ArtifactKind::Junit
+        } else if head.contains("\"bomFormat\"")
+            || head.contains("\"spdxVersion\"")
+            || file.contains("sbom")
+            || file.ends_with(".spdx")
+            || file.ends_with(".spdx.json")
+            || file.ends_with(".cdx.json")
+        {
+            ArtifactKind::Sbom
+        } else if file.ends_with(".lcov")
+            || file == "lcov.info"
+            || head.starts_with("TN:")
+            || head.starts_with("SF:")
+            || head.contains("<coverage")
+            || file.contains("coverage")
+        {
+            ArtifactKind::Coverage
+        } else if (file.ends_with(".tar") || file.ends_with(".oci"))
+            && (head.contains("oci-layout") || head.contains("manifest.json"))
+        {
+            ArtifactKind::Image
+        } else {
+            ArtifactKind::Binary
+        }
+    }
+
+    /// Content type to store and serve the artifact with.
+    pub fn content_type(&self, name: &str, data: &[u8]) -> &'static str {
+        let name = name.to_ascii_lowercase();
+        if *self == ArtifactKind::HtmlReport {
+            return "text/html; charset=utf-8";
+        }
+        if *self == ArtifactKind::Image {
+            return "application/x-tar";
+        }
+        if name.ends_with(".json") {
+            return "application/json";
+        }
+        if name.ends_with(".xml") || *self == ArtifactKind::Junit {
+            return "application/xml";
+        }
+        if name.ends_with(".txt") || name.ends_with(".info") || name.ends_with(".lcov") {
+            return "text/plain; charset=utf-8";
+        }
+        if *self != ArtifactKind::Binary
+            && serde_json::from_slice::<serde_json::Value>(data).is_ok()
+        {
+            return "application/json";
+        }
+        "application/octet-stream"
+    }
+
+    /// Inline rendering that is safe for this artifact, if any.
+    pub fn preview(&self, content_type: &str) -> Option<ArtifactPreview> {
+        match self {
+            ArtifactKind::HtmlReport => Some(ArtifactPreview::SandboxedHtml),
+            _ if content_type.starts_with("application/json") => Some(ArtifactPreview::Json),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ArtifactKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for ArtifactKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        ArtifactKind::ALL
+            .into_iter()
+            .find(|k| k.as_str() == s)
+            .ok_or_else(|| format!("unknown artifact kind: {}", s))
+    }
+}
+
 /// Metadata about an artifact.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ArtifactManifest {
     pub reference: ArtifactRef,
+    /// What the artifact contains.
+    pub kind: ArtifactKind,
     /// MIME type if known.
     pub content_type: Option<String>,
     /// Custom metadata.
@@ -86,3 +230,122 @@ pub trait ArtifactStore: Send + Sync {
     /// Prune artifacts according to a policy.
     async fn prune(&self, policy: RetentionPolicy) -> Result<PruneStats>;
 }
+
+/// Which run of another pipeline an artifact dependency is taken from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ArtifactVersion {
+    /// The pipeline's latest run that succeeded.
+    #[default]
+    LatestSuccessful,
+    /// The pipeline's latest run with the artifact, whatever its outcome.
+    Latest,
+    /// The run with this number.
+    Run(i64),
+}
+
+impl std::fmt::Display for ArtifactVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArtifactVersion::LatestSuccessful => f.write_str("latest-successful"),
+            ArtifactVersion::Latest => f.write_str("latest"),
+            ArtifactVersion::Run(number) => write!(f, "{}", number),
+        }
+    }
+}
+
+impl std::str::FromStr for ArtifactVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim() {
+            "latest-successful" => Ok(ArtifactVersion::LatestSuccessful),
+            "latest" => Ok(ArtifactVersion::Latest),
+            other => other
+                .trim_start_matches('#')
+                .parse()
+                .ok()
+                .filter(|n: &i64| *n > 0)
+                .map(ArtifactVersion::Run)
+                .ok_or_else(|| {
+                    format!(
+                        "unknown artifact version '{}': expected latest-successful, latest or a run number",
+                        s
+                    )
+                }),
+        }
+    }
+}
+
+impl TryFrom<String> for ArtifactVersion {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<ArtifactVersion> for String {
+    fn from(version: ArtifactVersion) -> Self {
+        version.to_string()
+    }
+}
+
+/// An artifact of another pipeline a stage needs in its workspace, fetched
+/// before its commands run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArtifactDependency {
+    /// Name of the pipeline, in the same tenant, that produced it.
+    pub pipeline: String,
+    /// Name the artifact was uploaded under.
+    pub artifact: String,
+    #[serde(default)]
+    pub version: ArtifactVersion,
+    /// Where to put it, relative to the workspace; the artifact's file name
+    /// when unset, and inside the directory when ending in `/`.
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+impl ArtifactDependency {
+    /// Path in the workspace the artifact is written to.
+    pub fn destination(&self) -> String {
+        let file = self.artifact.rsplit('/').next().unwrap_or(&self.artifact);
+        match self.path.as_deref().filter(|p| !p.is_empty()) {
+            Some(dir) if dir.ends_with('/') => format!("{}{}", dir, file),
+            Some(path) => path.to_string(),
+            None => file.to_string(),
+        }
+    }
+}
+
+/// An artifact dependency of a stage of a run to resolve.
+#[derive(Debug, Clone)]
+pub struct ArtifactDependencyRequest {
+    pub pipeline_id: ResourceId,
+    pub run_id: ResourceId,
+    pub stage: String,
+    pub dependency: ArtifactDependency,
+}
+
+/// Where a job downloads a resolved artifact dependency from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedArtifact {
+    /// Expiring link the job downloads the artifact with, without other
+    /// credentials.
+    pub url: String,
+    /// Number of the run that produced it.
+    pub run_number: i64,
+    pub size: u64,
+}
+
+/// Finds the artifacts stages need from other pipelines' runs.
+#[async_trait]
+pub trait ArtifactResolver: Send + Sync {
+    /// Resolve a dependency, failing the stage with the error if no run of
+    /// the pipeline it names matches.
+    async fn resolve(
+        &self,
+        request: &ArtifactDependencyRequest,
+    ) -> std::result::Result<ResolvedArtifact, String>;
+}
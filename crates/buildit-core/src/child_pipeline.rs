@@ -0,0 +1,93 @@
+//! Runs of other pipelines started by a stage.
+//!
+//! A `trigger-pipeline` stage starts a run of another pipeline of the
+//! tenant, e.g. a shared deploy pipeline invoked by several build
+//! pipelines. It runs no job: the orchestrator hands the stage to a
+//! [`PipelineLauncher`], which the API server provides, and by default
+//! waits for the child run and takes on its outcome.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+use crate::ResourceId;
+
+/// Most pipelines deep a chain of triggered runs may go, counting the
+/// run that started it.
+pub const MAX_CHILD_DEPTH: usize = 5;
+
+/// What a trigger-pipeline stage starts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TriggerPipelineSpec {
+    /// Name of the pipeline to run, in the same tenant.
+    pub pipeline: String,
+    /// Branch the child run builds; its repository's default when unset.
+    #[serde(default)]
+    pub branch: Option<String>,
+    /// Parameters the child run is triggered with.
+    #[serde(default)]
+    pub params: BTreeMap<String, String>,
+    /// Wait for the child run to finish before the stage completes.
+    #[serde(default = "default_true")]
+    pub wait: bool,
+    /// Fail the stage when the child run it waited for doesn't succeed.
+    #[serde(default = "default_true")]
+    pub propagate: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// A trigger-pipeline stage of a run to start, its spec already
+/// interpolated.
+#[derive(Debug, Clone)]
+pub struct ChildRunRequest {
+    pub pipeline_id: ResourceId,
+    pub run_id: ResourceId,
+    pub stage: String,
+    pub spec: TriggerPipelineSpec,
+}
+
+/// A run started for a trigger-pipeline stage.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChildRun {
+    pub pipeline_id: ResourceId,
+    pub run_id: ResourceId,
+    pub number: i64,
+}
+
+/// Where a child run is at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChildRunStatus {
+    /// Queued, running or waiting for approval.
+    Pending,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl ChildRunStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChildRunStatus::Pending => "pending",
+            ChildRunStatus::Succeeded => "succeeded",
+            ChildRunStatus::Failed => "failed",
+            ChildRunStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// Starts and watches the runs of trigger-pipeline stages.
+#[async_trait]
+pub trait PipelineLauncher: Send + Sync {
+    /// Start the child run, failing the stage with the error if it can't
+    /// be, e.g. because it would form a cycle or go too deep. Starting the
+    /// same stage of the same run again returns the run already started.
+    async fn launch(&self, request: &ChildRunRequest) -> Result<ChildRun, String>;
+
+    /// Where a child run is at.
+    async fn status(&self, run: &ChildRun) -> Result<ChildRunStatus, String>;
+}
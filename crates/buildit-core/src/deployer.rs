@@ -32,6 +32,49 @@ pub struct DeploymentSpec {
     pub resources: DeploymentResources,
     /// Health check configuration.
     pub health_check: Option<HealthCheck>,
+    /// Where the workload definition comes from.
+    #[serde(default)]
+    pub source: DeploymentSource,
+}
+
+/// Source of the objects a deployment applies.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DeploymentSource {
+    /// Objects generated from `image` and the rest of the spec.
+    #[default]
+    Image,
+    /// Raw manifests from a directory of a repository checkout. `image`,
+    /// `replicas`, `strategy` and friends are ignored.
+    Manifests {
+        /// Directory containing `.yaml`, `.yml` or `.json` manifests.
+        path: String,
+        /// Values substituted for `${name}` in the manifests.
+        #[serde(default)]
+        variables: HashMap<String, String>,
+    },
+    /// A kustomization from a directory of a repository checkout, built
+    /// with the overlay for the deployment's environment. Containers
+    /// running `image`'s repository are switched to `image`, and the
+    /// deploy waits for every workload to finish rolling out.
+    Kustomize {
+        /// Directory containing the base `kustomization.yaml`, or the
+        /// root of the overlays.
+        path: String,
+        /// Overlay directory per environment, relative to `path`.
+        /// Environments without one use `overlays/<environment>` when it
+        /// exists, and `path` itself otherwise.
+        #[serde(default)]
+        overlays: HashMap<String, String>,
+    },
+    /// Manifests that were already rendered (e.g. by `helm template`),
+    /// applied as-is without variable substitution.
+    Rendered {
+        /// Multi-document YAML.
+        documents: String,
+        /// Namespace for namespaced objects that don't set one.
+        namespace: Option<String>,
+    },
 }
 
 /// Deployment strategy.
@@ -45,7 +88,15 @@ pub enum DeploymentStrategy {
     /// Gradually shift traffic to new version.
     Canary { steps: Vec<CanaryStep> },
     /// Deploy new version alongside old, then switch.
-    BlueGreen,
+    BlueGreen {
+        /// Checks run against the idle color before traffic is switched.
+        #[serde(default)]
+        smoke_checks: Vec<SmokeCheck>,
+        /// Switch traffic as soon as smoke checks pass, instead of
+        /// waiting for an explicit promotion.
+        #[serde(default)]
+        auto_promote: bool,
+    },
     /// Recreate all instances (downtime).
     Recreate,
 }
@@ -70,6 +121,26 @@ pub struct CanaryStep {
     pub manual_approval: bool,
 }
 
+/// An HTTP check run against a new version before it receives traffic.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmokeCheck {
+    pub path: String,
+    pub port: u16,
+    /// Expected HTTP status code.
+    #[serde(default = "default_smoke_status")]
+    pub expected_status: u16,
+    #[serde(default = "default_smoke_timeout")]
+    pub timeout_seconds: u32,
+}
+
+fn default_smoke_status() -> u16 {
+    200
+}
+
+fn default_smoke_timeout() -> u32 {
+    10
+}
+
 /// Resource configuration for a deployment.
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct DeploymentResources {
@@ -161,6 +232,7 @@ pub enum DeploymentEventKind {
     HealthCheckFailed,
     Paused,
     Resumed,
+    Promoted,
     Completed,
     Failed,
     RollbackStarted,
@@ -194,6 +266,18 @@ pub struct ValidationWarning {
     pub message: String,
 }
 
+/// An object a deployment would apply, as the cluster has it now and as
+/// a server-side dry run says it would be afterwards.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObjectPreview {
+    pub kind: String,
+    pub name: String,
+    pub namespace: Option<String>,
+    /// The object in the cluster, or `None` if the deployment creates it.
+    pub live: Option<serde_json::Value>,
+    pub planned: serde_json::Value,
+}
+
 /// Trait for deployers.
 #[async_trait]
 pub trait Deployer: Send + Sync {
@@ -209,6 +293,10 @@ pub trait Deployer: Send + Sync {
     /// Start a deployment.
     async fn deploy(&self, spec: DeploymentSpec) -> Result<DeploymentHandle>;
 
+    /// Dry-run a deployment, returning every object it would apply
+    /// without changing anything.
+    async fn preview(&self, spec: &DeploymentSpec) -> Result<Vec<ObjectPreview>>;
+
     /// Get current deployment state.
     async fn state(&self, handle: &DeploymentHandle) -> Result<DeploymentState>;
 
@@ -228,6 +316,9 @@ pub trait Deployer: Send + Sync {
     /// Scale a deployment.
     async fn scale(&self, handle: &DeploymentHandle, replicas: u32) -> Result<()>;
 
+    /// Switch traffic to a version waiting for promotion (blue/green).
+    async fn promote(&self, handle: &DeploymentHandle) -> Result<()>;
+
     /// Pause a deployment (canary).
     async fn pause(&self, handle: &DeploymentHandle) -> Result<()>;
 
@@ -237,6 +328,14 @@ pub trait Deployer: Send + Sync {
     /// Destroy/delete a deployment.
     async fn destroy(&self, handle: &DeploymentHandle) -> Result<()>;
 
+    /// Fetch the live version of a manifest object, or `None` if it doesn't
+    /// exist. Objects without a namespace are looked up in `namespace`.
+    async fn live_object(
+        &self,
+        object: &serde_json::Value,
+        namespace: &str,
+    ) -> Result<Option<serde_json::Value>>;
+
     /// Stream logs from a deployment.
     async fn logs(
         &self,
@@ -9,18 +9,29 @@ use futures::stream::BoxStream;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::time::Duration;
+use utoipa::ToSchema;
 
 use crate::{ResourceId, Result};
 
 /// Specification for a job to execute.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct JobSpec {
     /// Unique identifier for this job.
     pub id: ResourceId,
     /// Container image to run.
     pub image: String,
+    /// When the executor pulls `image`; its own default if unset.
+    #[serde(default)]
+    pub pull_policy: Option<PullPolicy>,
     /// Command to execute.
     pub command: Vec<String>,
+    /// Replaces the image's entrypoint; empty runs `command` without one.
+    #[serde(default)]
+    pub entrypoint: Option<Vec<String>>,
+    /// User the container's processes run as (a name or `uid[:gid]`); the
+    /// image's user if unset.
+    #[serde(default)]
+    pub user: Option<String>,
     /// Working directory inside the container.
     pub working_dir: Option<String>,
     /// Environment variables.
@@ -28,15 +39,228 @@ pub struct JobSpec {
     /// Resource limits.
     pub resources: ResourceRequirements,
     /// Maximum execution time.
+    #[schema(value_type = Option<Object>)]
     pub timeout: Option<Duration>,
     /// Volumes to mount.
     pub volumes: Vec<VolumeMount>,
     /// Git repository to clone before running commands.
     pub git_clone: Option<GitCloneSpec>,
+    /// Operating system and architecture the job needs.
+    #[serde(default)]
+    #[schema(value_type = String, example = "windows/amd64")]
+    pub platform: Platform,
+    /// Conditions checked inside the job, in order, before its command
+    /// runs; the job fails if one is not met in time.
+    #[serde(default)]
+    pub wait_for: Vec<WaitCondition>,
+    /// Overrides of the executor's default security context.
+    #[serde(default)]
+    pub security_context: Option<SecurityContext>,
+    /// Run the container with every capability and access to the host's
+    /// devices.
+    #[serde(default)]
+    pub privileged: bool,
+    /// Extra `/etc/hosts` entries, as `host:ip`.
+    #[serde(default)]
+    pub extra_hosts: Vec<String>,
+    /// Network the container joins (`host`, `none` or a network name); the
+    /// executor's default if unset.
+    #[serde(default)]
+    pub network_mode: Option<String>,
+    /// Workspace shared by the jobs of a run, mounted at `/workspace`: the
+    /// repository is cloned into it once and each job sees what the ones
+    /// before it left. Executors that cannot share one give each job its
+    /// own.
+    #[serde(default)]
+    pub workspace: Option<String>,
+    /// Tenant whose run the job belongs to. Executors shared between
+    /// organizations, like self-hosted runners, only hand a job to runners
+    /// of its tenant's organization.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<ResourceId>,
+}
+
+/// Operating system of a job or executor.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Os {
+    #[default]
+    Linux,
+    Windows,
+    Macos,
+}
+
+impl Os {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Os::Linux => "linux",
+            Os::Windows => "windows",
+            Os::Macos => "macos",
+        }
+    }
+}
+
+impl std::str::FromStr for Os {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "linux" => Ok(Os::Linux),
+            "windows" => Ok(Os::Windows),
+            "macos" | "darwin" | "osx" => Ok(Os::Macos),
+            other => Err(format!(
+                "unknown operating system '{}' (expected linux, windows or macos)",
+                other
+            )),
+        }
+    }
+}
+
+/// When an executor pulls a job's image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum PullPolicy {
+    /// Pull before every job, picking up a moved tag.
+    Always,
+    /// Pull only when the executor does not have the image.
+    IfNotPresent,
+    /// Never pull; jobs fail when the executor does not have the image.
+    Never,
+}
+
+impl PullPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PullPolicy::Always => "always",
+            PullPolicy::IfNotPresent => "if-not-present",
+            PullPolicy::Never => "never",
+        }
+    }
+}
+
+impl std::str::FromStr for PullPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "always" => Ok(PullPolicy::Always),
+            "if-not-present" => Ok(PullPolicy::IfNotPresent),
+            "never" => Ok(PullPolicy::Never),
+            other => Err(format!(
+                "unknown pull policy '{}' (expected always, if-not-present or never)",
+                other
+            )),
+        }
+    }
+}
+
+/// CPU architecture of a job or executor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Arch {
+    Amd64,
+    Arm64,
+}
+
+impl Arch {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Arch::Amd64 => "amd64",
+            Arch::Arm64 => "arm64",
+        }
+    }
+}
+
+impl std::str::FromStr for Arch {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "amd64" | "x86_64" | "x64" => Ok(Arch::Amd64),
+            "arm64" | "aarch64" => Ok(Arch::Arm64),
+            other => Err(format!(
+                "unknown architecture '{}' (expected amd64 or arm64)",
+                other
+            )),
+        }
+    }
+}
+
+/// An operating system and, optionally, a CPU architecture, written
+/// `os/arch` (e.g. `windows/amd64`, `macos/arm64`) or just `os`.
+///
+/// A job without an architecture runs on any architecture of its OS. Jobs
+/// default to Linux.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Platform {
+    pub os: Os,
+    pub arch: Option<Arch>,
+}
+
+impl Platform {
+    pub fn new(os: Os, arch: Arch) -> Self {
+        Self {
+            os,
+            arch: Some(arch),
+        }
+    }
+
+    /// Platform of the machine this process runs on.
+    pub fn host() -> Self {
+        Self {
+            os: std::env::consts::OS.parse().unwrap_or_default(),
+            arch: std::env::consts::ARCH.parse().ok(),
+        }
+    }
+
+    /// Whether an executor of this platform can run a job that needs
+    /// `required`.
+    pub fn supports(&self, required: &Platform) -> bool {
+        self.os == required.os && (required.arch.is_none() || self.arch == required.arch)
+    }
+}
+
+impl std::fmt::Display for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.arch {
+            Some(arch) => write!(f, "{}/{}", self.os.as_str(), arch.as_str()),
+            None => f.write_str(self.os.as_str()),
+        }
+    }
+}
+
+impl std::str::FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (os, arch) = match s.split_once('/') {
+            Some((os, arch)) => (os, Some(arch.parse()?)),
+            None => (s, None),
+        };
+        Ok(Self {
+            os: os.parse()?,
+            arch,
+        })
+    }
+}
+
+impl TryFrom<String> for Platform {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Platform> for String {
+    fn from(platform: Platform) -> Self {
+        platform.to_string()
+    }
 }
 
 /// Specification for cloning a git repository.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct GitCloneSpec {
     /// Repository URL to clone.
     pub url: String,
@@ -50,10 +274,57 @@ pub struct GitCloneSpec {
     pub depth: Option<u32>,
     /// Access token for private repos.
     pub access_token: Option<String>,
+    /// Username sent with the access token; the token alone if unset.
+    #[serde(default)]
+    pub username: Option<String>,
+    /// Initialize submodules, recursively.
+    #[serde(default)]
+    pub submodules: bool,
+    /// Fetch Git LFS objects.
+    #[serde(default)]
+    pub lfs: bool,
+    /// Only check out these directories (sparse checkout); all if empty.
+    #[serde(default)]
+    pub sparse_paths: Vec<String>,
+    /// Borrow objects from a mirror of the repository cached by the
+    /// executor, where it has somewhere to keep one.
+    #[serde(default)]
+    pub cache: bool,
+}
+
+/// Something a job waits for before running its command, e.g. a database
+/// the tests connect to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct WaitCondition {
+    pub probe: Probe,
+    /// Seconds to keep probing before failing the job.
+    pub timeout_secs: u32,
+}
+
+/// How a [`WaitCondition`] is checked. Probes are retried every second.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Probe {
+    /// A TCP connection to `host:port` is accepted.
+    Tcp { host: String, port: u16 },
+    /// A `GET` of `url` answers `200 OK`.
+    Http { url: String },
+    /// A shell command exits with status 0.
+    Shell { command: String },
+}
+
+impl std::fmt::Display for Probe {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Probe::Tcp { host, port } => write!(f, "tcp {}:{}", host, port),
+            Probe::Http { url } => write!(f, "http {}", url),
+            Probe::Shell { command } => write!(f, "shell `{}`", command),
+        }
+    }
 }
 
 /// Resource requirements for a job.
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct ResourceRequirements {
     /// CPU limit (e.g., "1000m" for 1 core).
     pub cpu_limit: Option<String>,
@@ -65,8 +336,77 @@ pub struct ResourceRequirements {
     pub memory_request: Option<String>,
 }
 
+/// Security settings for a job's container. Unset fields leave the
+/// executor's (or the image's) own behaviour.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct SecurityContext {
+    /// Refuse to start the container as root.
+    #[serde(default)]
+    pub run_as_non_root: Option<bool>,
+    /// User ID to run the container's processes as.
+    #[serde(default)]
+    pub run_as_user: Option<i64>,
+    /// Group ID to run the container's processes as.
+    #[serde(default)]
+    pub run_as_group: Option<i64>,
+    /// Mount the container's root filesystem read-only.
+    #[serde(default)]
+    pub read_only_root_filesystem: Option<bool>,
+    /// Whether processes may gain more privileges than their parent
+    /// (e.g. through setuid binaries).
+    #[serde(default)]
+    pub allow_privilege_escalation: Option<bool>,
+    /// Linux capabilities to drop, e.g. `ALL`.
+    #[serde(default)]
+    pub drop_capabilities: Vec<String>,
+    /// Linux capabilities to add, e.g. `NET_BIND_SERVICE`.
+    #[serde(default)]
+    pub add_capabilities: Vec<String>,
+    /// Seccomp profile: `RuntimeDefault`, `Unconfined` or
+    /// `Localhost/<profile path>`.
+    #[serde(default)]
+    pub seccomp_profile: Option<String>,
+}
+
+impl SecurityContext {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// This context with `over` laid on top: the fields `over` sets win,
+    /// and capabilities it drops or adds come on top of these.
+    pub fn merged(&self, over: &SecurityContext) -> SecurityContext {
+        fn union(base: &[String], extra: &[String]) -> Vec<String> {
+            let mut all = base.to_vec();
+            for capability in extra {
+                if !all.contains(capability) {
+                    all.push(capability.clone());
+                }
+            }
+            all
+        }
+        SecurityContext {
+            run_as_non_root: over.run_as_non_root.or(self.run_as_non_root),
+            run_as_user: over.run_as_user.or(self.run_as_user),
+            run_as_group: over.run_as_group.or(self.run_as_group),
+            read_only_root_filesystem: over
+                .read_only_root_filesystem
+                .or(self.read_only_root_filesystem),
+            allow_privilege_escalation: over
+                .allow_privilege_escalation
+                .or(self.allow_privilege_escalation),
+            drop_capabilities: union(&self.drop_capabilities, &over.drop_capabilities),
+            add_capabilities: union(&self.add_capabilities, &over.add_capabilities),
+            seccomp_profile: over
+                .seccomp_profile
+                .clone()
+                .or_else(|| self.seccomp_profile.clone()),
+        }
+    }
+}
+
 /// A volume mount specification.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct VolumeMount {
     /// Name of the volume.
     pub name: String,
@@ -133,6 +473,15 @@ pub struct JobResult {
     pub artifacts: Vec<ArtifactRef>,
 }
 
+/// CPU and memory a running job uses at one moment.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    /// CPU in use, in millicores.
+    pub cpu_millis: u64,
+    /// Memory in use, in bytes.
+    pub memory_bytes: u64,
+}
+
 /// Reference to an artifact.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ArtifactRef {
@@ -142,20 +491,93 @@ pub struct ArtifactRef {
 }
 
 /// A line of log output.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct LogLine {
     pub timestamp: DateTime<Utc>,
     pub stream: LogStream,
     pub content: String,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
 pub enum LogStream {
     Stdout,
     Stderr,
     System,
 }
 
+/// Prefix of the marker lines a job's script writes around each command.
+const LOG_FRAME_PREFIX: &str = "##[buildit:";
+
+/// Framing written into a job's log around each command of a stage, so the
+/// log can be shown as one collapsible section per command.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "frame", rename_all = "snake_case")]
+pub enum LogFrame {
+    /// Command `step` (counted from 1) is about to run.
+    StepStarted { step: u32, command: String },
+    /// Command `step` exited with `exit_code`.
+    StepFinished { step: u32, exit_code: i32 },
+}
+
+impl LogFrame {
+    pub fn step(&self) -> u32 {
+        match self {
+            LogFrame::StepStarted { step, .. } | LogFrame::StepFinished { step, .. } => *step,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LogFrame::StepStarted { .. } => "step_start",
+            LogFrame::StepFinished { .. } => "step_end",
+        }
+    }
+
+    /// The line a job writes to its log for this frame, e.g.
+    /// `##[buildit:step-start 1] cargo test`.
+    pub fn marker(&self) -> String {
+        match self {
+            LogFrame::StepStarted { step, command } => {
+                format!("{}step-start {}] {}", LOG_FRAME_PREFIX, step, command)
+            }
+            LogFrame::StepFinished { step, exit_code } => {
+                format!("{}step-end {}] {}", LOG_FRAME_PREFIX, step, exit_code)
+            }
+        }
+    }
+
+    /// The frame a log line marks, if it is a marker.
+    pub fn parse(line: &str) -> Option<Self> {
+        let rest = line.trim_end().strip_prefix(LOG_FRAME_PREFIX)?;
+        let (kind, rest) = rest.split_once(' ')?;
+        let (step, value) = match rest.split_once("] ") {
+            Some(parts) => parts,
+            None => (rest.strip_suffix(']')?, ""),
+        };
+        let step = step.parse().ok()?;
+        match kind {
+            "step-start" => Some(LogFrame::StepStarted {
+                step,
+                command: value.to_string(),
+            }),
+            "step-end" => Some(LogFrame::StepFinished {
+                step,
+                exit_code: value.parse().ok()?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for LogFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogFrame::StepStarted { command, .. } => write!(f, "$ {}", command),
+            LogFrame::StepFinished { exit_code, .. } => write!(f, "Exited with code {}", exit_code),
+        }
+    }
+}
+
 /// An interactive terminal session.
 pub struct TerminalSession {
     pub stdin: Box<dyn futures::Sink<Bytes, Error = std::io::Error> + Send + Unpin>,
@@ -171,6 +593,18 @@ pub trait Executor: Send + Sync {
     /// Check if this executor can handle the given job spec.
     async fn can_execute(&self, spec: &JobSpec) -> bool;
 
+    /// Check that the executor's backend (Docker daemon, cluster API) is
+    /// reachable, so jobs can be routed away from one that is down.
+    async fn health(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Platforms this executor runs jobs on, so jobs for another OS or
+    /// architecture are routed elsewhere. Defaults to the host's.
+    async fn platforms(&self) -> Vec<Platform> {
+        vec![Platform::host()]
+    }
+
     /// Spawn a new job.
     async fn spawn(&self, spec: JobSpec) -> Result<JobHandle>;
 
@@ -183,9 +617,32 @@ pub trait Executor: Send + Sync {
     /// Wait for a job to complete.
     async fn wait(&self, handle: &JobHandle) -> Result<JobResult>;
 
+    /// CPU and memory the job uses now, or `None` if the executor cannot
+    /// tell. Sampled while the job runs to record what it used.
+    async fn usage(&self, _handle: &JobHandle) -> Result<Option<ResourceUsage>> {
+        Ok(None)
+    }
+
     /// Cancel a running job.
     async fn cancel(&self, handle: &JobHandle) -> Result<()>;
 
+    /// Reference pinning `image` to the digest it currently resolves to
+    /// (`name@sha256:...`), pulling it first as `pull_policy` says, or
+    /// `None` if the executor cannot tell, e.g. for an image built locally.
+    async fn resolve_image(
+        &self,
+        _image: &str,
+        _pull_policy: Option<PullPolicy>,
+    ) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Remove a workspace the jobs of a finished run shared. Removing one
+    /// that does not exist succeeds.
+    async fn remove_workspace(&self, _workspace: &str) -> Result<()> {
+        Ok(())
+    }
+
     /// Open an interactive terminal session to a running job.
     async fn exec_interactive(
         &self,
@@ -2,11 +2,12 @@
 
 use derive_more::Display;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
 /// A unique identifier for any resource in the system.
 /// Uses UUIDv7 for time-ordered, sortable IDs.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Display)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Display, ToSchema)]
 #[display("{_0}")]
 pub struct ResourceId(Uuid);
 
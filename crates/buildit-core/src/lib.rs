@@ -4,21 +4,38 @@
 //! - Resource identifiers and common types
 //! - Executor trait and job types
 //! - Deployer trait and deployment types
+//! - Deployment targets and their credentials
 //! - Pipeline and stage definitions
 //! - Repository and stack types
+//! - Release publishing
 //! - Application types (GitOps)
+//! - Tenant policy templates
+//! - Organization pipeline policies
+//! - Test results
+//! - Vulnerability scanning
+//! - Self-hosted runner protocol
+//! - Roles and permissions
 //! - Storage abstractions (artifacts, secrets)
 
 pub mod application;
 pub mod artifact;
+pub mod child_pipeline;
 pub mod deployer;
 pub mod error;
 pub mod executor;
 pub mod id;
 pub mod pipeline;
+pub mod policy;
+pub mod rbac;
+pub mod release;
 pub mod repository;
+pub mod runner;
+pub mod scan;
 pub mod secret;
 pub mod stack;
+pub mod target;
+pub mod tenant;
+pub mod test_report;
 
 pub use error::{Error, Result};
 pub use id::ResourceId;
@@ -3,9 +3,15 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use utoipa::ToSchema;
 
 use crate::ResourceId;
+use crate::artifact::ArtifactDependency;
+use crate::child_pipeline::TriggerPipelineSpec;
 use crate::deployer::DeploymentSpec;
+use crate::executor::{Platform, PullPolicy, ResourceRequirements, SecurityContext, WaitCondition};
+use crate::release::ReleaseSpec;
+use crate::scan::ScanSpec;
 
 /// A CI/CD pipeline definition.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,6 +32,140 @@ pub struct Pipeline {
     pub env: HashMap<String, String>,
     /// Cache configurations.
     pub caches: Vec<CacheConfig>,
+    /// Pipeline and stage owners.
+    #[serde(default)]
+    pub ownership: Ownership,
+    /// How the repository is checked out for each stage.
+    #[serde(default)]
+    pub checkout: CheckoutConfig,
+    /// Parameters a run can be triggered with.
+    #[serde(default)]
+    pub params: Vec<PipelineParam>,
+}
+
+/// A parameter declared by a pipeline, given a value when a run is
+/// triggered and read by stages as `${params.NAME}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PipelineParam {
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: ParamKind,
+    /// Value when the run doesn't set one; a parameter without a default
+    /// must be set. A `bool`'s is `true` or `false`.
+    #[serde(default)]
+    pub default: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// The values a parameter accepts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ParamKind {
+    String,
+    /// One of `options`.
+    Choice {
+        options: Vec<String>,
+    },
+    /// `true` or `false`.
+    Bool,
+}
+
+/// How a pipeline's repository is checked out.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct CheckoutConfig {
+    /// Commits of history to fetch; 0 fetches all of it.
+    pub depth: u32,
+    /// Initialize submodules, recursively.
+    pub submodules: bool,
+    /// Fetch Git LFS objects.
+    pub lfs: bool,
+    /// Only check out these directories (sparse checkout); all if empty.
+    pub sparse: Vec<String>,
+    /// Borrow objects from a mirror of the repository the executor keeps,
+    /// so each run only downloads what changed since the last one.
+    pub cache: bool,
+}
+
+impl Default for CheckoutConfig {
+    fn default() -> Self {
+        Self {
+            depth: 1,
+            submodules: false,
+            lfs: false,
+            sparse: Vec::new(),
+            cache: false,
+        }
+    }
+}
+
+/// Who owns a pipeline and its stages.
+///
+/// Owners are free-form handles such as `@alice` or `team:platform`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Ownership {
+    /// Owners of the pipeline as a whole.
+    #[serde(default)]
+    pub owners: Vec<String>,
+    /// Stage ownership rules. As with CODEOWNERS, the last matching rule wins.
+    #[serde(default)]
+    pub stages: Vec<OwnerRule>,
+}
+
+/// Owners for stages whose name matches `pattern` (`*` wildcards).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct OwnerRule {
+    pub pattern: String,
+    pub owners: Vec<String>,
+}
+
+impl Ownership {
+    pub fn is_empty(&self) -> bool {
+        self.owners.is_empty() && self.stages.is_empty()
+    }
+
+    /// Owners responsible for a stage, falling back to the pipeline owners.
+    pub fn owners_for_stage(&self, stage: &str) -> &[String] {
+        self.stages
+            .iter()
+            .rev()
+            .find(|rule| wildcard_match(&rule.pattern, stage))
+            .map(|rule| rule.owners.as_slice())
+            .unwrap_or(&self.owners)
+    }
+
+    /// Every owner mentioned anywhere, sorted and deduplicated.
+    pub fn all_owners(&self) -> Vec<String> {
+        let mut all: Vec<String> = self
+            .owners
+            .iter()
+            .chain(self.stages.iter().flat_map(|r| r.owners.iter()))
+            .cloned()
+            .collect();
+        all.sort();
+        all.dedup();
+        all
+    }
+}
+
+/// Match `text` against a pattern where `*` matches any run of characters.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let Some((first, rest)) = pattern.split_once('*') else {
+        return pattern == text;
+    };
+    let Some(mut remaining) = text.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = rest.split('*').collect();
+    let last = parts.pop().unwrap_or_default();
+    for part in parts {
+        match remaining.find(part) {
+            Some(idx) => remaining = &remaining[idx + part.len()..],
+            None => return false,
+        }
+    }
+    remaining.len() >= last.len() && remaining.ends_with(last)
 }
 
 /// What triggers a pipeline run.
@@ -63,6 +203,66 @@ pub struct Stage {
     pub action: StageAction,
     /// Stage-specific environment variables.
     pub env: HashMap<String, String>,
+    /// Labels an executor must have to run this stage (e.g. `gpu`).
+    #[serde(default)]
+    pub runs_on: Vec<String>,
+    /// CPU and memory the stage's job requests, counted against its
+    /// tenant's quota.
+    #[serde(default)]
+    pub resources: ResourceRequirements,
+    /// Operating system and architecture the stage's job runs on.
+    #[serde(default)]
+    pub platform: Platform,
+    /// Services the stage's commands need, waited for inside its job.
+    #[serde(default)]
+    pub wait_for: Vec<WaitCondition>,
+    /// Artifacts of other pipelines fetched into the workspace before the
+    /// stage's commands run.
+    #[serde(default)]
+    pub needs_artifacts: Vec<ArtifactDependency>,
+    /// Overrides of the executor's default security context for the
+    /// stage's job, checked against its tenant's stage security policy.
+    #[serde(default)]
+    pub security_context: Option<SecurityContext>,
+    /// How the stage's container is started, where it differs from the
+    /// image's defaults.
+    #[serde(default)]
+    pub container: ContainerOptions,
+    /// When the stage's image is pulled; the executor's default if unset.
+    #[serde(default)]
+    pub pull_policy: Option<PullPolicy>,
+}
+
+/// How a stage's container is started. Loosening options are checked
+/// against the tenant's stage security policy.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ContainerOptions {
+    /// User the commands run as (a name or `uid[:gid]`).
+    #[serde(default)]
+    pub user: Option<String>,
+    /// Replaces the image's entrypoint, which gets the stage's commands as
+    /// arguments; empty runs them without one.
+    #[serde(default)]
+    pub entrypoint: Option<Vec<String>>,
+    /// Run the container privileged.
+    #[serde(default)]
+    pub privileged: bool,
+    /// Extra `/etc/hosts` entries, as `host:ip`.
+    #[serde(default)]
+    pub extra_hosts: Vec<String>,
+    /// Network the container joins (`host`, `none` or a network name).
+    #[serde(default)]
+    pub network_mode: Option<String>,
+}
+
+impl ContainerOptions {
+    /// Whether `user` names root.
+    pub fn runs_as_root(&self) -> bool {
+        self.user
+            .as_deref()
+            .map(|user| user.split(':').next().unwrap_or_default())
+            .is_some_and(|user| user == "root" || user == "0")
+    }
 }
 
 /// Condition for stage execution.
@@ -80,6 +280,10 @@ pub enum StageAction {
         image: String,
         commands: Vec<String>,
         artifacts: Vec<String>,
+        /// JUnit XML reports the commands write, as `sh` globs relative
+        /// to the workspace; read after the commands, even failed ones.
+        #[serde(default)]
+        test_reports: Vec<String>,
     },
     /// Build and push a container image.
     ImageBuild {
@@ -90,6 +294,12 @@ pub enum StageAction {
     },
     /// Deploy to a target.
     Deploy(Box<DeploymentSpec>),
+    /// Scan an image for vulnerabilities.
+    Scan(Box<ScanSpec>),
+    /// Publish a release to the repository's Git provider.
+    Release(Box<ReleaseSpec>),
+    /// Start a run of another pipeline.
+    TriggerPipeline(Box<TriggerPipelineSpec>),
     /// Run stages in parallel.
     Parallel { stages: Vec<Stage> },
     /// Matrix build (multiple configurations).
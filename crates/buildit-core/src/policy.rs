@@ -0,0 +1,42 @@
+//! Organization-wide pipeline policies.
+//!
+//! Unlike a tenant's policy template, which only fills in defaults, an
+//! organization's pipeline policy is a set of rules every pipeline of its
+//! tenants must follow: a security scan stage, images from allowed
+//! registries only, and stage resource requests under a cap. Pipelines
+//! breaking a rule are refused when their config is stored, and runs of
+//! them are refused when triggered and failed when dispatched.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Rules the pipelines of an organization must follow. Unset rules allow
+/// anything.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PipelinePolicy {
+    /// Every pipeline must have a security scan stage.
+    #[serde(default)]
+    pub require_scan: bool,
+    /// Images `run` stages may use. An entry ending in `*` or `/` allows
+    /// every image starting with it (e.g. `ghcr.io/acme/*`), others allow
+    /// that image at any tag (e.g. `rust`). Docker Hub images match with or
+    /// without their `docker.io/library/` prefix.
+    #[serde(default)]
+    pub allowed_images: Vec<String>,
+    /// Most CPU a stage may request (e.g. `2` or `2000m`).
+    #[serde(default)]
+    pub max_cpu_request: Option<String>,
+    /// Most memory a stage may request (e.g. `4Gi`).
+    #[serde(default)]
+    pub max_memory_request: Option<String>,
+}
+
+impl PipelinePolicy {
+    /// Whether no rule is set.
+    pub fn is_empty(&self) -> bool {
+        !self.require_scan
+            && self.allowed_images.is_empty()
+            && self.max_cpu_request.is_none()
+            && self.max_memory_request.is_none()
+    }
+}
@@ -0,0 +1,234 @@
+//! Role-based access control.
+//!
+//! Users hold a role in each organization (`owner`, `admin`, `member`) and
+//! optionally in individual tenants (`admin`, `member`, `viewer`). Their
+//! role in a tenant is the higher of the two, and grants a fixed set of
+//! [`Permission`]s there.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Something a caller may be allowed to do in a tenant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+pub enum Permission {
+    #[serde(rename = "tenant.read")]
+    TenantRead,
+    #[serde(rename = "tenant.create")]
+    TenantCreate,
+    /// Change the tenant's settings, e.g. its policy.
+    #[serde(rename = "tenant.manage")]
+    TenantManage,
+    #[serde(rename = "pipeline.read")]
+    PipelineRead,
+    /// Create pipelines, change their owners, archive them.
+    #[serde(rename = "pipeline.write")]
+    PipelineWrite,
+    #[serde(rename = "pipeline.trigger")]
+    PipelineTrigger,
+    /// Open a shell in a copy of a failed stage's job, which sees the
+    /// stage's environment.
+    #[serde(rename = "pipeline.debug")]
+    PipelineDebug,
+    /// Upload artifacts and reports to runs.
+    #[serde(rename = "artifact.write")]
+    ArtifactWrite,
+    #[serde(rename = "repository.read")]
+    RepositoryRead,
+    /// Connect, sync and remove repositories.
+    #[serde(rename = "repository.write")]
+    RepositoryWrite,
+    #[serde(rename = "stack.read")]
+    StackRead,
+    /// Create and delete stacks.
+    #[serde(rename = "stack.write")]
+    StackWrite,
+    /// Run plans, and lock the stack's state for them.
+    #[serde(rename = "stack.plan")]
+    StackPlan,
+    /// Approve applies and write the stack's state.
+    #[serde(rename = "stack.apply")]
+    StackApply,
+    /// Read the stack's Terraform state, which may hold secrets.
+    #[serde(rename = "stack.state.read")]
+    StackStateRead,
+    /// Set stack variables, which may be secrets.
+    #[serde(rename = "secrets.write")]
+    SecretsWrite,
+    #[serde(rename = "application.read")]
+    ApplicationRead,
+    /// Create, change and delete GitOps applications.
+    #[serde(rename = "application.write")]
+    ApplicationWrite,
+    #[serde(rename = "application.sync")]
+    ApplicationSync,
+    #[serde(rename = "deployment.read")]
+    DeploymentRead,
+    /// Manage environments, targets and service specs.
+    #[serde(rename = "deployment.write")]
+    DeploymentWrite,
+    /// Deploy, promote and roll back.
+    #[serde(rename = "deployment.deploy")]
+    DeploymentDeploy,
+    /// Deploy to an environment during a freeze window.
+    #[serde(rename = "deployment.override_freeze")]
+    DeploymentOverrideFreeze,
+    /// Change environments' protection rules.
+    #[serde(rename = "deployment.protect")]
+    DeploymentProtect,
+    /// List the organization's API keys.
+    #[serde(rename = "api_key.read")]
+    ApiKeyRead,
+    /// Issue and revoke API keys.
+    #[serde(rename = "api_key.write")]
+    ApiKeyWrite,
+    /// Read the organization's audit log.
+    #[serde(rename = "audit.read")]
+    AuditRead,
+    /// Invite people to the organization and withdraw invitations.
+    #[serde(rename = "member.invite")]
+    MemberInvite,
+}
+
+impl Permission {
+    pub const ALL: &'static [Permission] = &[
+        Permission::TenantRead,
+        Permission::TenantCreate,
+        Permission::TenantManage,
+        Permission::PipelineRead,
+        Permission::PipelineWrite,
+        Permission::PipelineTrigger,
+        Permission::PipelineDebug,
+        Permission::ArtifactWrite,
+        Permission::RepositoryRead,
+        Permission::RepositoryWrite,
+        Permission::StackRead,
+        Permission::StackWrite,
+        Permission::StackPlan,
+        Permission::StackApply,
+        Permission::StackStateRead,
+        Permission::SecretsWrite,
+        Permission::ApplicationRead,
+        Permission::ApplicationWrite,
+        Permission::ApplicationSync,
+        Permission::DeploymentRead,
+        Permission::DeploymentWrite,
+        Permission::DeploymentDeploy,
+        Permission::DeploymentOverrideFreeze,
+        Permission::DeploymentProtect,
+        Permission::ApiKeyRead,
+        Permission::ApiKeyWrite,
+        Permission::AuditRead,
+        Permission::MemberInvite,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::TenantRead => "tenant.read",
+            Permission::TenantCreate => "tenant.create",
+            Permission::TenantManage => "tenant.manage",
+            Permission::PipelineRead => "pipeline.read",
+            Permission::PipelineWrite => "pipeline.write",
+            Permission::PipelineTrigger => "pipeline.trigger",
+            Permission::PipelineDebug => "pipeline.debug",
+            Permission::ArtifactWrite => "artifact.write",
+            Permission::RepositoryRead => "repository.read",
+            Permission::RepositoryWrite => "repository.write",
+            Permission::StackRead => "stack.read",
+            Permission::StackWrite => "stack.write",
+            Permission::StackPlan => "stack.plan",
+            Permission::StackApply => "stack.apply",
+            Permission::StackStateRead => "stack.state.read",
+            Permission::SecretsWrite => "secrets.write",
+            Permission::ApplicationRead => "application.read",
+            Permission::ApplicationWrite => "application.write",
+            Permission::ApplicationSync => "application.sync",
+            Permission::DeploymentRead => "deployment.read",
+            Permission::DeploymentWrite => "deployment.write",
+            Permission::DeploymentDeploy => "deployment.deploy",
+            Permission::DeploymentOverrideFreeze => "deployment.override_freeze",
+            Permission::DeploymentProtect => "deployment.protect",
+            Permission::ApiKeyRead => "api_key.read",
+            Permission::ApiKeyWrite => "api_key.write",
+            Permission::AuditRead => "audit.read",
+            Permission::MemberInvite => "member.invite",
+        }
+    }
+}
+
+impl std::fmt::Display for Permission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A role, from least to most privileged.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Viewer,
+    Member,
+    Admin,
+    Owner,
+}
+
+impl Role {
+    /// Parse a membership role. Unknown roles get no access.
+    pub fn parse(role: &str) -> Option<Self> {
+        match role.trim().to_lowercase().as_str() {
+            "viewer" => Some(Role::Viewer),
+            "member" => Some(Role::Member),
+            "admin" => Some(Role::Admin),
+            "owner" => Some(Role::Owner),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Member => "member",
+            Role::Admin => "admin",
+            Role::Owner => "owner",
+        }
+    }
+
+    /// Whether the role grants `permission`.
+    pub fn grants(&self, permission: Permission) -> bool {
+        use Permission::*;
+        match self {
+            Role::Owner | Role::Admin => true,
+            Role::Member => !matches!(
+                permission,
+                TenantCreate
+                    | TenantManage
+                    | StackApply
+                    | SecretsWrite
+                    | DeploymentOverrideFreeze
+                    | DeploymentProtect
+                    | ApiKeyWrite
+                    | AuditRead
+                    | MemberInvite
+            ),
+            Role::Viewer => matches!(
+                permission,
+                TenantRead
+                    | PipelineRead
+                    | RepositoryRead
+                    | StackRead
+                    | ApplicationRead
+                    | DeploymentRead
+            ),
+        }
+    }
+
+    /// Every permission the role grants.
+    pub fn permissions(&self) -> Vec<Permission> {
+        Permission::ALL
+            .iter()
+            .copied()
+            .filter(|p| self.grants(*p))
+            .collect()
+    }
+}
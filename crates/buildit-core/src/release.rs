@@ -0,0 +1,79 @@
+//! Publishing releases to the repository's Git provider.
+//!
+//! A `release` stage creates a GitHub or GitLab release for a tag, with a
+//! changelog of the commits built since the pipeline's previous release and
+//! the run's artifacts attached. It runs no job: the orchestrator hands the
+//! stage to a [`ReleasePublisher`], which the API server provides.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::ResourceId;
+
+/// What a release stage publishes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ReleaseSpec {
+    /// Tag to release, e.g. `${git.tag}`; created on the run's commit if the
+    /// provider doesn't have it yet.
+    #[serde(default = "default_tag")]
+    pub tag: String,
+    /// Title of the release; the tag when unset.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Text shown above the changelog.
+    #[serde(default)]
+    pub notes: Option<String>,
+    /// List the commits built since the pipeline's previous release.
+    #[serde(default = "default_changelog")]
+    pub changelog: bool,
+    /// Names of the run's artifacts to attach, `*` matching any characters.
+    #[serde(default)]
+    pub assets: Vec<String>,
+    #[serde(default)]
+    pub draft: bool,
+    #[serde(default)]
+    pub prerelease: bool,
+}
+
+fn default_tag() -> String {
+    "${git.tag}".to_string()
+}
+
+fn default_changelog() -> bool {
+    true
+}
+
+impl ReleaseSpec {
+    /// Title of the release.
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.tag)
+    }
+}
+
+/// A release stage of a run to publish, its spec already interpolated.
+#[derive(Debug, Clone)]
+pub struct ReleaseRequest {
+    pub pipeline_id: ResourceId,
+    pub run_id: ResourceId,
+    pub stage: String,
+    pub spec: ReleaseSpec,
+}
+
+/// A release that was published.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PublishedRelease {
+    /// Page of the release on the provider.
+    pub url: String,
+    /// Commits listed in its changelog.
+    pub commits: usize,
+    /// Names of the artifacts attached to it.
+    pub assets: Vec<String>,
+}
+
+/// Publishes the releases of release stages.
+#[async_trait]
+pub trait ReleasePublisher: Send + Sync {
+    /// Create the release, failing the stage with the error if it can't be.
+    async fn publish(&self, request: &ReleaseRequest) -> Result<PublishedRelease, String>;
+}
@@ -2,6 +2,7 @@
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
 /// Git provider type
@@ -13,6 +14,17 @@ pub enum GitProvider {
     Bitbucket,
 }
 
+impl GitProvider {
+    /// Username to send with an access token over HTTPS.
+    pub fn token_username(&self) -> &'static str {
+        match self {
+            GitProvider::Github => "x-access-token",
+            GitProvider::Gitlab => "oauth2",
+            GitProvider::Bitbucket => "x-token-auth",
+        }
+    }
+}
+
 impl std::fmt::Display for GitProvider {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -37,10 +49,15 @@ impl std::str::FromStr for GitProvider {
 }
 
 /// Detected configuration files in a repository
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
 pub struct DetectedConfig {
     /// Path to .buildit.kdl if found
     pub buildit_config: Option<String>,
+    /// Paths to every pipeline config: `.buildit.kdl` or `buildit.kdl`
+    /// files and the `.kdl` files in `.buildit` directories, each its own
+    /// pipeline
+    #[serde(default)]
+    pub pipeline_configs: Vec<String>,
     /// Paths to Terraform files (.tf)
     pub terraform_files: Vec<String>,
     /// Paths to Terraform directories (containing .tf files)
@@ -70,7 +87,7 @@ impl DetectedConfig {
 
     /// Check if a BuildIt pipeline config was found
     pub fn has_pipeline(&self) -> bool {
-        self.buildit_config.is_some()
+        self.buildit_config.is_some() || !self.pipeline_configs.is_empty()
     }
 
     /// Check if any Dockerfile was found
@@ -85,7 +102,10 @@ impl DetectedConfig {
             items.push(format!("{} Terraform stack(s)", self.terraform_dirs.len()));
         }
         if self.has_pipeline() {
-            items.push("1 Pipeline".to_string());
+            items.push(format!(
+                "{} Pipeline(s)",
+                self.pipeline_configs.len().max(1)
+            ));
         }
         if self.has_kubernetes() {
             let count = self.kubernetes_dirs.len() + self.helm_charts.len();
@@ -114,6 +134,8 @@ pub struct Repository {
     pub webhook_id: Option<String>,
     pub webhook_secret: Option<String>,
     pub last_synced_at: Option<DateTime<Utc>>,
+    /// Why the last scan failed, if it did.
+    pub sync_error: Option<String>,
     pub detected_config: DetectedConfig,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
@@ -202,7 +224,7 @@ impl PushEvent {
             .and_then(|c| c.as_array())
             .map(|arr| {
                 arr.iter()
-                    .filter_map(|c| CommitInfo::from_github_commit(c))
+                    .filter_map(CommitInfo::from_github_commit)
                     .collect()
             })
             .unwrap_or_default();
@@ -0,0 +1,88 @@
+//! Protocol between the API server and self-hosted runners.
+//!
+//! A runner registers once with an API token and gets a runner token back.
+//! It then long-polls for job leases, runs each job locally and reports its
+//! status and log lines. Every report renews the lease and tells the runner
+//! whether the job was cancelled; a job whose lease runs out is failed.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::executor::{JobSpec, LogLine, Platform};
+
+/// Sent by a runner to register itself.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RunnerRegistration {
+    pub name: String,
+    /// Capability labels; a runner only gets jobs whose labels it all has.
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Platforms the runner runs jobs on; Linux if none are given.
+    #[serde(default)]
+    #[schema(value_type = Vec<String>)]
+    pub platforms: Vec<Platform>,
+    pub version: Option<String>,
+}
+
+/// Credentials of a registered runner.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RunnerCredentials {
+    pub runner_id: uuid::Uuid,
+    /// Bearer token for the runner endpoints. Only returned once.
+    pub token: String,
+}
+
+/// A job leased to a runner.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct JobLease {
+    pub job_id: uuid::Uuid,
+    pub spec: JobSpec,
+    /// The lease lapses unless the runner reports within this many seconds.
+    pub lease_seconds: u64,
+}
+
+/// State of a job as reported by its runner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RunnerJobState {
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl RunnerJobState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RunnerJobState::Running => "running",
+            RunnerJobState::Succeeded => "succeeded",
+            RunnerJobState::Failed => "failed",
+            RunnerJobState::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        !matches!(self, RunnerJobState::Running)
+    }
+}
+
+/// Status report for a leased job.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct JobStatusReport {
+    pub state: RunnerJobState,
+    pub exit_code: Option<i32>,
+    pub error: Option<String>,
+}
+
+/// Log lines of a leased job, in order.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct JobLogBatch {
+    pub lines: Vec<LogLine>,
+}
+
+/// Reply to a status or log report.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct JobReportAck {
+    /// The job was cancelled; the runner should stop it.
+    pub cancel_requested: bool,
+}
@@ -0,0 +1,175 @@
+//! Vulnerability scanning of container images.
+//!
+//! A `scan` stage runs a scanner against an image in a job, optionally
+//! writing a CycloneDX SBOM of it, and fails when it finds vulnerabilities
+//! at or above the stage's threshold.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Tool a scan stage runs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Scanner {
+    /// Anchore's grype.
+    #[default]
+    Grype,
+    /// Aqua Security's trivy.
+    Trivy,
+}
+
+impl Scanner {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scanner::Grype => "grype",
+            Scanner::Trivy => "trivy",
+        }
+    }
+
+    /// Image the scanner runs in unless the stage names another. It needs
+    /// `sh` and `awk`, hence grype's debug variant.
+    pub fn default_image(&self) -> &'static str {
+        match self {
+            Scanner::Grype => "anchore/grype:debug",
+            Scanner::Trivy => "aquasec/trivy:latest",
+        }
+    }
+}
+
+impl std::fmt::Display for Scanner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Scanner {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "grype" => Ok(Scanner::Grype),
+            "trivy" => Ok(Scanner::Trivy),
+            other => Err(format!(
+                "unknown scanner '{}', expected grype or trivy",
+                other
+            )),
+        }
+    }
+}
+
+/// Severity of a vulnerability, least severe first.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Not rated by the scanner's data sources.
+    Unknown,
+    Negligible,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    pub const ALL: [Severity; 6] = [
+        Severity::Unknown,
+        Severity::Negligible,
+        Severity::Low,
+        Severity::Medium,
+        Severity::High,
+        Severity::Critical,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Unknown => "unknown",
+            Severity::Negligible => "negligible",
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Parses severities in any case, as scanners write them (`HIGH`, `High`).
+impl std::str::FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Severity::ALL
+            .into_iter()
+            .find(|v| v.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown severity: {}", s))
+    }
+}
+
+/// A vulnerability found in a package of the scanned image.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Finding {
+    /// e.g. `CVE-2024-3094` or `GHSA-...`.
+    pub vulnerability_id: String,
+    pub package: String,
+    pub installed_version: String,
+    /// Version the vulnerability is fixed in, if there is one.
+    pub fixed_version: Option<String>,
+    pub severity: Severity,
+    pub title: Option<String>,
+    /// Advisory describing the vulnerability.
+    pub url: Option<String>,
+}
+
+/// What a scan stage scans and when it fails.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ScanSpec {
+    /// Image to scan, e.g. `registry.example.com/api:${git.sha}`.
+    pub target: String,
+    #[serde(default)]
+    pub scanner: Scanner,
+    /// Image to run the scanner in, instead of the scanner's own.
+    #[serde(default)]
+    pub image: Option<String>,
+    /// Fail the stage on findings of this severity or worse. Unset, the
+    /// stage only reports.
+    #[serde(default)]
+    pub fail_on: Option<Severity>,
+    /// Vulnerability IDs that never fail the stage.
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    /// Whether findings without a fixed version never fail the stage.
+    #[serde(default)]
+    pub ignore_unfixed: bool,
+    /// Write a CycloneDX SBOM of the image as a run artifact.
+    #[serde(default)]
+    pub sbom: bool,
+}
+
+impl ScanSpec {
+    /// Image the scanner runs in.
+    pub fn image(&self) -> &str {
+        self.image
+            .as_deref()
+            .unwrap_or_else(|| self.scanner.default_image())
+    }
+
+    /// Findings that fail the stage.
+    pub fn violations<'a>(&self, findings: &'a [Finding]) -> Vec<&'a Finding> {
+        let Some(threshold) = self.fail_on else {
+            return vec![];
+        };
+        findings
+            .iter()
+            .filter(|f| f.severity >= threshold)
+            .filter(|f| !self.ignore.contains(&f.vulnerability_id))
+            .filter(|f| !self.ignore_unfixed || f.fixed_version.is_some())
+            .collect()
+    }
+}
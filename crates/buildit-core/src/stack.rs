@@ -2,6 +2,7 @@
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
 /// Stack status
@@ -143,6 +144,10 @@ pub struct StackRun {
     pub commit_sha: Option<String>,
     pub plan_output: Option<String>,
     pub plan_json: Option<serde_json::Value>,
+    /// Resource-level changes parsed from `plan_json`.
+    pub plan_changes: Vec<ResourceChange>,
+    /// Outcome of the tenant's plan policies, one per policy evaluated.
+    pub policy_results: Vec<PolicyResult>,
     pub apply_output: Option<String>,
     pub resources_to_add: i32,
     pub resources_to_change: i32,
@@ -178,15 +183,127 @@ pub struct PlanSummary {
     pub to_destroy: Vec<ResourceChange>,
 }
 
+impl PlanSummary {
+    /// Group resource changes the way Terraform counts them; a replacement
+    /// counts as both an addition and a destruction.
+    pub fn from_changes(changes: &[ResourceChange]) -> Self {
+        let mut summary = PlanSummary::default();
+        for change in changes {
+            match change.action.as_str() {
+                "create" => summary.to_add.push(change.clone()),
+                "update" => summary.to_change.push(change.clone()),
+                "delete" => summary.to_destroy.push(change.clone()),
+                "replace" => {
+                    summary.to_add.push(change.clone());
+                    summary.to_destroy.push(change.clone());
+                }
+                _ => {}
+            }
+        }
+        summary
+    }
+}
+
 /// A resource change in a Terraform plan
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ResourceChange {
     pub address: String,
     pub resource_type: String,
     pub name: String,
+    /// `create`, `update`, `delete` or `replace`.
     pub action: String,
     pub before: Option<serde_json::Value>,
     pub after: Option<serde_json::Value>,
+    /// Attributes whose value changes, empty when only parsed from text output.
+    #[serde(default)]
+    pub attributes: Vec<AttributeChange>,
+}
+
+/// A single attribute difference within a resource change
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct AttributeChange {
+    /// Attribute path, e.g. `tags.Name` or `ingress[0].cidr_blocks[1]`.
+    pub path: String,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    /// Value is only known after apply.
+    #[serde(default)]
+    pub unknown: bool,
+    /// Value is sensitive and has been redacted.
+    #[serde(default)]
+    pub sensitive: bool,
+}
+
+/// How a plan policy's violations are enforced
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyEnforcement {
+    /// Violations fail the run before anything is applied.
+    Hard,
+    /// Violations are recorded and shown to approvers.
+    Soft,
+}
+
+impl std::fmt::Display for PolicyEnforcement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PolicyEnforcement::Hard => write!(f, "hard"),
+            PolicyEnforcement::Soft => write!(f, "soft"),
+        }
+    }
+}
+
+impl std::str::FromStr for PolicyEnforcement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hard" => Ok(PolicyEnforcement::Hard),
+            "soft" => Ok(PolicyEnforcement::Soft),
+            other => Err(format!(
+                "Unknown enforcement '{}'; expected hard or soft",
+                other
+            )),
+        }
+    }
+}
+
+/// A tenant's Rego policy, evaluated against the JSON of every stack plan
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PlanPolicy {
+    pub id: Uuid,
+    pub tenant_id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    /// Rego module; its `deny` rule holds the violation messages.
+    pub rego: String,
+    pub enforcement: PolicyEnforcement,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Outcome of one plan policy on a run
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PolicyResult {
+    pub policy: String,
+    pub enforcement: PolicyEnforcement,
+    /// Messages of the policy's `deny` rule.
+    pub violations: Vec<String>,
+    /// Why the policy could not be evaluated, if it could not.
+    pub error: Option<String>,
+}
+
+impl PolicyResult {
+    /// Whether the policy found violations or could not be evaluated.
+    pub fn failed(&self) -> bool {
+        !self.violations.is_empty() || self.error.is_some()
+    }
+
+    /// Whether the run must stop here; hard policies fail closed.
+    pub fn blocks(&self) -> bool {
+        self.enforcement == PolicyEnforcement::Hard && self.failed()
+    }
 }
 
 /// Request to create a stack
@@ -0,0 +1,143 @@
+//! Deployment targets and the credentials to reach them.
+//!
+//! Credentials are kept apart from a target's plain configuration: they
+//! are stored encrypted and never returned by the API.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Kind of infrastructure a target deploys to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetKind {
+    Kubernetes,
+    /// A Fly.io organization.
+    Fly,
+    /// An AWS account.
+    Aws,
+    /// A Docker daemon.
+    Docker,
+}
+
+impl TargetKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TargetKind::Kubernetes => "kubernetes",
+            TargetKind::Fly => "fly",
+            TargetKind::Aws => "aws",
+            TargetKind::Docker => "docker",
+        }
+    }
+}
+
+impl std::fmt::Display for TargetKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for TargetKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "kubernetes" => Ok(TargetKind::Kubernetes),
+            "fly" => Ok(TargetKind::Fly),
+            "aws" => Ok(TargetKind::Aws),
+            "docker" => Ok(TargetKind::Docker),
+            other => Err(format!(
+                "unknown target type '{}', expected kubernetes, fly, aws or docker",
+                other
+            )),
+        }
+    }
+}
+
+/// Whether a target could be reached the last time it was checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetStatus {
+    /// Not checked yet.
+    Unknown,
+    Connected,
+    /// Reachable, but the credentials lack permissions deployments need.
+    Degraded,
+    /// Unreachable, or the credentials were refused.
+    Disconnected,
+}
+
+impl TargetStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TargetStatus::Unknown => "unknown",
+            TargetStatus::Connected => "connected",
+            TargetStatus::Degraded => "degraded",
+            TargetStatus::Disconnected => "disconnected",
+        }
+    }
+}
+
+impl std::fmt::Display for TargetStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Credentials of a target.
+#[derive(Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TargetCredentials {
+    /// A kubeconfig, using its current context unless `context` names one.
+    Kubeconfig {
+        kubeconfig: String,
+        #[serde(default)]
+        context: Option<String>,
+    },
+    /// A service account token for a cluster's API server.
+    ServiceAccount {
+        /// e.g. `https://10.0.0.1:6443`.
+        server: String,
+        token: String,
+        /// PEM certificate of the cluster's CA; the system roots otherwise.
+        #[serde(default)]
+        ca_cert: Option<String>,
+    },
+    /// A Fly.io API token.
+    Fly { token: String },
+    /// An AWS access key.
+    Aws {
+        access_key_id: String,
+        secret_access_key: String,
+        #[serde(default)]
+        session_token: Option<String>,
+    },
+}
+
+impl TargetCredentials {
+    /// Kind of target the credentials are for.
+    pub fn kind(&self) -> TargetKind {
+        match self {
+            TargetCredentials::Kubeconfig { .. } | TargetCredentials::ServiceAccount { .. } => {
+                TargetKind::Kubernetes
+            }
+            TargetCredentials::Fly { .. } => TargetKind::Fly,
+            TargetCredentials::Aws { .. } => TargetKind::Aws,
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            TargetCredentials::Kubeconfig { .. } => "kubeconfig",
+            TargetCredentials::ServiceAccount { .. } => "service_account",
+            TargetCredentials::Fly { .. } => "fly",
+            TargetCredentials::Aws { .. } => "aws",
+        }
+    }
+}
+
+/// Shows only the kind of credentials, so they never end up in logs.
+impl std::fmt::Debug for TargetCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "TargetCredentials::{}(..)", self.type_name())
+    }
+}
@@ -0,0 +1,289 @@
+//! Tenant-level policy templates and quotas.
+//!
+//! A tenant's policy holds defaults that are copied into the config of every
+//! pipeline created for it, plus stages every pipeline must have. Pipelines
+//! can change their copy afterwards; [`TenantPolicy::drift`] reports where
+//! they no longer match the template.
+//!
+//! A tenant's stage security policy says how far a stage may loosen the
+//! executor's default security context for its job.
+//!
+//! A tenant's quota limits the stage jobs it runs at once, the CPU and
+//! memory they request together, and its job minutes per month.
+//!
+//! A tenant's log retention says when its job logs move to the log archive
+//! and when they are deleted.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::executor::ResourceRequirements;
+
+/// Pipeline config keys the policy fills in.
+const RETENTION_KEY: &str = "retention";
+const NOTIFICATIONS_KEY: &str = "notifications";
+const RESOURCES_KEY: &str = "resources";
+
+/// Defaults and requirements a tenant applies to its pipelines.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TenantPolicy {
+    /// How long run history is kept.
+    #[serde(default)]
+    pub retention: Option<RunRetention>,
+    /// Who hears about run outcomes.
+    #[serde(default)]
+    pub notifications: Vec<NotificationRule>,
+    /// Resource limits for stage jobs.
+    #[serde(default)]
+    pub resources: Option<ResourceRequirements>,
+    /// Stages every pipeline must define (e.g. a security scan).
+    #[serde(default)]
+    pub required_stages: Vec<String>,
+    /// Security context overrides stages may make.
+    #[serde(default)]
+    pub stage_security: StageSecurityPolicy,
+}
+
+/// Which security context overrides and container options a tenant's
+/// stages may use. Overrides that only tighten the executor's defaults are
+/// always allowed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct StageSecurityPolicy {
+    /// Stages may run as root (`run_as_non_root` false, `run_as_user` 0 or
+    /// user `root`).
+    #[serde(default)]
+    pub allow_root: bool,
+    /// Stages may allow privilege escalation.
+    #[serde(default)]
+    pub allow_privilege_escalation: bool,
+    /// Stages may turn a read-only root filesystem writable.
+    #[serde(default)]
+    pub allow_writable_root_filesystem: bool,
+    /// Stages may run without seccomp (`Unconfined`).
+    #[serde(default)]
+    pub allow_unconfined_seccomp: bool,
+    /// Capabilities stages may add; `*` allows any.
+    #[serde(default)]
+    pub allowed_capabilities: Vec<String>,
+    /// Stages may run privileged containers.
+    #[serde(default)]
+    pub allow_privileged: bool,
+    /// Networks stages may join besides `bridge` and `none`, e.g. `host`;
+    /// `*` allows any.
+    #[serde(default)]
+    pub allowed_network_modes: Vec<String>,
+}
+
+/// How much run history a pipeline keeps.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct RunRetention {
+    /// Keep at most this many runs.
+    pub max_runs: Option<u32>,
+    /// Delete runs older than this many days.
+    pub max_age_days: Option<u32>,
+}
+
+/// Send a notification to `target` when a run ends in one of `on`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct NotificationRule {
+    /// Run statuses to notify on, e.g. `failed`.
+    pub on: Vec<String>,
+    /// Where to send it, e.g. `slack:#builds` or `email:team@example.com`.
+    pub target: String,
+}
+
+/// Limits on a tenant's stage jobs. Unset limits are unlimited.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TenantQuota {
+    /// Stage jobs running at once.
+    #[serde(default)]
+    pub max_concurrent_jobs: Option<u32>,
+    /// CPU requested by running jobs together (e.g. `8` or `8000m`).
+    #[serde(default)]
+    pub max_cpu: Option<String>,
+    /// Memory requested by running jobs together (e.g. `16Gi`).
+    #[serde(default)]
+    pub max_memory: Option<String>,
+    /// Job minutes per calendar month (UTC).
+    #[serde(default)]
+    pub max_run_minutes_per_month: Option<u32>,
+    /// What happens to work that would exceed the quota.
+    #[serde(default)]
+    pub on_exceeded: QuotaAction,
+}
+
+/// What happens to work that would exceed a tenant's quota.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum QuotaAction {
+    /// Hold it until the tenant is back under quota.
+    #[default]
+    Queue,
+    /// Refuse it: triggering a run fails, and so do runs already queued.
+    Reject,
+}
+
+impl TenantQuota {
+    /// Whether no limit is set.
+    pub fn is_unlimited(&self) -> bool {
+        self.max_concurrent_jobs.is_none()
+            && self.max_cpu.is_none()
+            && self.max_memory.is_none()
+            && self.max_run_minutes_per_month.is_none()
+    }
+}
+
+/// How long a tenant's job logs are kept. Unset periods keep logs forever.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct LogRetention {
+    /// Days after a run finishes before its logs are compressed and moved
+    /// to the log archive.
+    #[serde(default)]
+    pub archive_after_days: Option<u32>,
+    /// Days after a run finishes before its logs are deleted, archived or
+    /// not.
+    #[serde(default)]
+    pub delete_after_days: Option<u32>,
+}
+
+impl LogRetention {
+    /// Check that logs are not deleted before they would be archived.
+    pub fn validate(&self) -> Result<(), String> {
+        match (self.archive_after_days, self.delete_after_days) {
+            (Some(archive), Some(delete)) if delete < archive => Err(format!(
+                "delete_after_days ({}) cannot be shorter than archive_after_days ({})",
+                delete, archive
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// How long a tenant's run artifacts and reports are kept. Each rule that is
+/// set collects on its own; pinned artifacts are never collected.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ArtifactRetention {
+    /// Keep the artifacts of each pipeline's N most recent runs that have
+    /// any; older runs' artifacts are collected.
+    #[serde(default)]
+    pub keep_last_runs: Option<u32>,
+    /// Days after upload before an artifact is collected.
+    #[serde(default)]
+    pub max_age_days: Option<u32>,
+    /// Most bytes the tenant's artifacts may take up; the oldest are
+    /// collected first. Pinned artifacts count towards it.
+    #[serde(default)]
+    pub max_total_bytes: Option<u64>,
+}
+
+impl ArtifactRetention {
+    /// Whether no rule is set, so artifacts are kept forever.
+    pub fn is_unlimited(&self) -> bool {
+        self.keep_last_runs.is_none()
+            && self.max_age_days.is_none()
+            && self.max_total_bytes.is_none()
+    }
+
+    /// Check that no rule would collect every artifact.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.keep_last_runs == Some(0) {
+            return Err("keep_last_runs must be at least 1".to_string());
+        }
+        if self.max_age_days == Some(0) {
+            return Err("max_age_days must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// A pipeline setting that differs from its tenant's template.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PolicyDrift {
+    /// Setting that drifted (`retention`, `notifications`, `resources` or
+    /// `required_stages`).
+    pub field: String,
+    /// Value the template prescribes.
+    pub expected: Value,
+    /// Value the pipeline has; `None` if it is missing.
+    pub actual: Option<Value>,
+}
+
+impl TenantPolicy {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The template's defaults, keyed like the pipeline config.
+    fn defaults(&self) -> Vec<(&'static str, Value)> {
+        let mut defaults = Vec::new();
+        if let Some(retention) = &self.retention {
+            defaults.push((RETENTION_KEY, to_value(retention)));
+        }
+        if !self.notifications.is_empty() {
+            defaults.push((NOTIFICATIONS_KEY, to_value(&self.notifications)));
+        }
+        if let Some(resources) = &self.resources {
+            defaults.push((RESOURCES_KEY, to_value(resources)));
+        }
+        defaults
+    }
+
+    /// Fill in the defaults a new pipeline's config does not set itself.
+    /// Returns the keys that were filled in.
+    pub fn apply(&self, config: &mut Value) -> Vec<&'static str> {
+        if !config.is_object() {
+            *config = Value::Object(Default::default());
+        }
+        let Some(object) = config.as_object_mut() else {
+            return vec![];
+        };
+        let mut applied = Vec::new();
+        for (key, value) in self.defaults() {
+            if object.get(key).is_none_or(Value::is_null) {
+                object.insert(key.to_string(), value);
+                applied.push(key);
+            }
+        }
+        applied
+    }
+
+    /// Where a pipeline's config and stages deviate from the template.
+    pub fn drift(&self, config: &Value, stage_names: &[String]) -> Vec<PolicyDrift> {
+        let mut drift: Vec<PolicyDrift> = self
+            .defaults()
+            .into_iter()
+            .filter_map(|(key, expected)| {
+                let actual = config.get(key).filter(|v| !v.is_null());
+                (actual != Some(&expected)).then(|| PolicyDrift {
+                    field: key.to_string(),
+                    expected,
+                    actual: actual.cloned(),
+                })
+            })
+            .collect();
+
+        let missing: Vec<&String> = self
+            .required_stages
+            .iter()
+            .filter(|s| !stage_names.contains(s))
+            .collect();
+        if !missing.is_empty() {
+            let present: Vec<&String> = self
+                .required_stages
+                .iter()
+                .filter(|s| stage_names.contains(s))
+                .collect();
+            drift.push(PolicyDrift {
+                field: "required_stages".to_string(),
+                expected: to_value(&self.required_stages),
+                actual: Some(to_value(&present)),
+            });
+        }
+        drift
+    }
+}
+
+fn to_value<T: Serialize>(value: &T) -> Value {
+    serde_json::to_value(value).unwrap_or(Value::Null)
+}
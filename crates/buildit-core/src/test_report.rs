@@ -0,0 +1,72 @@
+//! Test results read from the reports a stage produces.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Outcome of one test case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TestStatus {
+    Passed,
+    /// An assertion failed.
+    Failed,
+    /// The test could not run to completion, e.g. it panicked or timed out.
+    Errored,
+    Skipped,
+}
+
+impl TestStatus {
+    pub const ALL: [TestStatus; 4] = [
+        TestStatus::Passed,
+        TestStatus::Failed,
+        TestStatus::Errored,
+        TestStatus::Skipped,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TestStatus::Passed => "passed",
+            TestStatus::Failed => "failed",
+            TestStatus::Errored => "errored",
+            TestStatus::Skipped => "skipped",
+        }
+    }
+
+    /// Whether the test failed or errored.
+    pub fn is_failure(&self) -> bool {
+        matches!(self, TestStatus::Failed | TestStatus::Errored)
+    }
+}
+
+impl std::fmt::Display for TestStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for TestStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        TestStatus::ALL
+            .into_iter()
+            .find(|t| t.as_str() == s)
+            .ok_or_else(|| format!("unknown test status: {}", s))
+    }
+}
+
+/// One test case of a report.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TestCase {
+    /// Test suite the case belongs to.
+    pub suite: String,
+    /// Class, module or file of the test, if the report names one.
+    pub classname: String,
+    pub name: String,
+    pub status: TestStatus,
+    pub duration_secs: f64,
+    /// Failure, error or skip message.
+    pub message: Option<String>,
+    /// Failure details, e.g. a stack trace.
+    pub details: Option<String>,
+}
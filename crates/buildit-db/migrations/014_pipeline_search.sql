@@ -0,0 +1,11 @@
+-- Pipeline search: labels plus trigram indexes for fuzzy name/repository matching
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+ALTER TABLE pipelines ADD COLUMN IF NOT EXISTS labels TEXT[] NOT NULL DEFAULT '{}';
+
+CREATE INDEX IF NOT EXISTS idx_pipelines_name_trgm ON pipelines USING GIN (name gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS idx_pipelines_repository_trgm ON pipelines USING GIN (repository gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS idx_pipelines_labels ON pipelines USING GIN (labels);
+
+-- Latest run per pipeline is used to rank recently active pipelines higher
+CREATE INDEX IF NOT EXISTS idx_pipeline_runs_pipeline_created ON pipeline_runs(pipeline_id, created_at DESC);
@@ -0,0 +1,20 @@
+-- Versioned deployment specs for services
+CREATE TABLE service_spec_versions (
+    id UUID PRIMARY KEY,
+    service_id UUID NOT NULL REFERENCES services(id) ON DELETE CASCADE,
+    version INTEGER NOT NULL,
+    spec JSONB NOT NULL,
+    created_by VARCHAR(255),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE(service_id, version)
+);
+
+CREATE INDEX idx_service_spec_versions_service ON service_spec_versions(service_id, version DESC);
+
+-- Which spec version a deployment rolled out
+ALTER TABLE deployments ADD COLUMN spec_version INTEGER;
+
+-- Seed version 1 from each service's current configuration
+INSERT INTO service_spec_versions (id, service_id, version, spec)
+SELECT gen_random_uuid(), id, 1, jsonb_strip_nulls(config || jsonb_build_object('image', image))
+FROM services;
@@ -0,0 +1,5 @@
+-- Stuck stage detection: when the watchdog flagged a running stage
+ALTER TABLE stage_results ADD COLUMN stalled_at TIMESTAMPTZ;
+
+CREATE INDEX idx_stage_results_running ON stage_results(started_at) WHERE status = 'running';
+CREATE INDEX idx_stage_results_name_status ON stage_results(stage_name, status);
@@ -0,0 +1,6 @@
+-- Pipeline and stage ownership
+ALTER TABLE pipelines ADD COLUMN owners JSONB NOT NULL DEFAULT '{}';
+-- Flattened list of every owner in `owners`, for "owned by" filtering
+ALTER TABLE pipelines ADD COLUMN owner_list TEXT[] NOT NULL DEFAULT '{}';
+
+CREATE INDEX idx_pipelines_owner_list ON pipelines USING GIN (owner_list);
@@ -0,0 +1,4 @@
+-- Generated release notes attached to a deployment
+ALTER TABLE deployments ADD COLUMN release_notes JSONB;
+
+CREATE INDEX idx_deployments_service_env ON deployments(service_id, environment_id, created_at DESC);
@@ -0,0 +1,5 @@
+-- Helm chart sources for applications, and digests of rendered manifests
+ALTER TABLE applications ADD COLUMN helm JSONB;
+ALTER TABLE applications ADD COLUMN rendered_digest VARCHAR(71);
+
+ALTER TABLE application_syncs ADD COLUMN rendered_digest VARCHAR(71);
@@ -0,0 +1,4 @@
+-- Archived pipelines keep their history but can no longer be triggered
+ALTER TABLE pipelines ADD COLUMN archived_at TIMESTAMPTZ;
+
+CREATE INDEX idx_pipelines_active ON pipelines(tenant_id) WHERE archived_at IS NULL;
@@ -0,0 +1,16 @@
+-- Artifacts uploaded by pipeline stages, typed at upload time
+CREATE TABLE run_artifacts (
+    id UUID PRIMARY KEY,
+    pipeline_run_id UUID NOT NULL REFERENCES pipeline_runs(id) ON DELETE CASCADE,
+    stage_name VARCHAR(255) NOT NULL,
+    name VARCHAR(1024) NOT NULL,
+    kind VARCHAR(32) NOT NULL, -- 'junit', 'coverage', 'sbom', 'image', 'html-report', 'binary'
+    content_type VARCHAR(255) NOT NULL,
+    size_bytes BIGINT NOT NULL,
+    digest VARCHAR(100) NOT NULL, -- 'sha256:<hex>'
+    location TEXT NOT NULL, -- artifact store location
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (pipeline_run_id, stage_name, name)
+);
+
+CREATE INDEX idx_run_artifacts_run ON run_artifacts(pipeline_run_id);
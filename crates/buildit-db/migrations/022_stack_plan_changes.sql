@@ -0,0 +1,2 @@
+-- Resource-level changes parsed from a run's plan JSON
+ALTER TABLE stack_runs ADD COLUMN plan_changes JSONB;
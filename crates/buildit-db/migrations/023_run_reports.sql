@@ -0,0 +1,15 @@
+-- HTML reports published from a run, served as a static site
+CREATE TABLE run_reports (
+    id UUID PRIMARY KEY,
+    pipeline_run_id UUID NOT NULL REFERENCES pipeline_runs(id) ON DELETE CASCADE,
+    stage_name VARCHAR(255) NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    entry VARCHAR(1024) NOT NULL DEFAULT 'index.html', -- page opened from the run page
+    files JSONB NOT NULL DEFAULT '{}', -- path -> {location, size, digest}
+    file_count INTEGER NOT NULL DEFAULT 0,
+    size_bytes BIGINT NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (pipeline_run_id, stage_name, name)
+);
+
+CREATE INDEX idx_run_reports_run ON run_reports(pipeline_run_id);
@@ -0,0 +1,3 @@
+-- Latest run of a branch, for integrations resolving "latest green build of main"
+CREATE INDEX IF NOT EXISTS idx_pipeline_runs_branch
+    ON pipeline_runs(pipeline_id, (git_info->>'branch'), number DESC);
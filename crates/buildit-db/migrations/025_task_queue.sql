@@ -0,0 +1,23 @@
+-- Background work of the control plane (pipeline runs, stack operations,
+-- deployments, syncs), claimed with SKIP LOCKED by any server's task worker
+CREATE TABLE task_queue (
+    id UUID PRIMARY KEY,
+    kind VARCHAR(100) NOT NULL,
+    payload JSONB NOT NULL DEFAULT '{}',
+    idempotency_key VARCHAR(255) NOT NULL UNIQUE, -- enqueueing the same work twice is a no-op
+    state JSONB NOT NULL DEFAULT '{}', -- checkpoint the handler resumes from after a crash
+    status VARCHAR(50) NOT NULL DEFAULT 'pending',
+    priority INT NOT NULL DEFAULT 0,
+    attempts INT NOT NULL DEFAULT 0,
+    max_attempts INT NOT NULL DEFAULT 3,
+    run_after TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    claimed_by VARCHAR(255),
+    claimed_at TIMESTAMPTZ,
+    heartbeat_at TIMESTAMPTZ,
+    error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    finished_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_task_queue_pending ON task_queue(priority DESC, created_at ASC) WHERE status = 'pending';
+CREATE INDEX idx_task_queue_claimed ON task_queue(heartbeat_at) WHERE status = 'claimed';
@@ -0,0 +1,5 @@
+-- Executor handle of the job running each stage, so a restarted server can
+-- re-attach to jobs that kept running instead of starting them again.
+ALTER TABLE stage_results
+    ADD COLUMN executor_id TEXT,
+    ADD COLUMN executor_name TEXT;
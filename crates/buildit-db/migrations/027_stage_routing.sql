@@ -0,0 +1,3 @@
+-- Which executor each stage's job was routed to, and why, including any
+-- executors it was moved off because they were unhealthy.
+ALTER TABLE stage_results ADD COLUMN routing JSONB;
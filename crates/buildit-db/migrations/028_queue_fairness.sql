@@ -0,0 +1,13 @@
+-- Tenant owning each queued item, so claims can share workers fairly
+-- between tenants instead of strictly by priority and age
+ALTER TABLE job_queue ADD COLUMN tenant_id UUID REFERENCES tenants(id) ON DELETE CASCADE;
+ALTER TABLE task_queue ADD COLUMN tenant_id UUID REFERENCES tenants(id) ON DELETE CASCADE;
+
+UPDATE job_queue q
+SET tenant_id = p.tenant_id
+FROM pipeline_runs r
+JOIN pipelines p ON p.id = r.pipeline_id
+WHERE r.id = q.pipeline_run_id;
+
+CREATE INDEX idx_job_queue_tenant_claimed ON job_queue(tenant_id) WHERE status = 'claimed';
+CREATE INDEX idx_task_queue_tenant_claimed ON task_queue(tenant_id) WHERE status = 'claimed';
@@ -0,0 +1,3 @@
+-- Policy template applied to a tenant's new pipelines: default retention,
+-- notification rules and resource limits, plus required stages
+ALTER TABLE tenants ADD COLUMN policy JSONB NOT NULL DEFAULT '{}';
@@ -0,0 +1,2 @@
+-- Executor labels a stage must run on (`runs-on "gpu"`)
+ALTER TABLE pipeline_stages ADD COLUMN runs_on TEXT[] NOT NULL DEFAULT '{}';
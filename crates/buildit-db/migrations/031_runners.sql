@@ -0,0 +1,40 @@
+-- Self-hosted runners and the jobs leased to them.
+CREATE TABLE runners (
+    id UUID PRIMARY KEY,
+    organization_id UUID NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    labels TEXT[] NOT NULL DEFAULT '{}',
+    version VARCHAR(64),
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    last_seen_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE runner_jobs (
+    id UUID PRIMARY KEY,
+    spec JSONB NOT NULL,
+    -- A runner can only lease the job if it has all of these labels.
+    labels TEXT[] NOT NULL DEFAULT '{}',
+    status VARCHAR(50) NOT NULL DEFAULT 'pending', -- pending, leased, running, succeeded, failed, cancelled
+    runner_id UUID REFERENCES runners(id) ON DELETE SET NULL,
+    lease_expires_at TIMESTAMPTZ,
+    cancel_requested BOOLEAN NOT NULL DEFAULT FALSE,
+    exit_code INTEGER,
+    error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    started_at TIMESTAMPTZ,
+    finished_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_runner_jobs_pending ON runner_jobs(created_at) WHERE status = 'pending';
+CREATE INDEX idx_runner_jobs_leased ON runner_jobs(lease_expires_at) WHERE status IN ('leased', 'running');
+
+CREATE TABLE runner_job_logs (
+    id BIGSERIAL PRIMARY KEY,
+    job_id UUID NOT NULL REFERENCES runner_jobs(id) ON DELETE CASCADE,
+    stream VARCHAR(10) NOT NULL,
+    content TEXT NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_runner_job_logs_job ON runner_job_logs(job_id, id);
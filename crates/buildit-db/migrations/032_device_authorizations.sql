@@ -0,0 +1,19 @@
+-- Device authorization grants (RFC 8628), for signing in the CLI.
+CREATE TABLE device_authorizations (
+    id UUID PRIMARY KEY,
+    device_code_hash VARCHAR(64) NOT NULL UNIQUE,
+    user_code VARCHAR(16) NOT NULL UNIQUE,
+    client_name VARCHAR(255),
+    status VARCHAR(20) NOT NULL DEFAULT 'pending', -- pending, approved, denied, redeemed
+    user_id UUID REFERENCES users(id) ON DELETE CASCADE,
+    last_polled_at TIMESTAMPTZ,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_device_authorizations_expires ON device_authorizations(expires_at);
+
+-- Sessions issued to the CLI keep a short-lived access token and a
+-- refresh token to rotate it with.
+ALTER TABLE sessions ADD COLUMN refresh_token_hash VARCHAR(64) UNIQUE;
+ALTER TABLE sessions ADD COLUMN refresh_expires_at TIMESTAMPTZ;
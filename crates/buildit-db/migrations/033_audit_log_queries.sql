@@ -0,0 +1,3 @@
+-- Audit log queries filter an organization's entries by resource and time.
+CREATE INDEX idx_audit_logs_resource ON audit_logs(resource_type, resource_id);
+CREATE INDEX idx_audit_logs_org_created ON audit_logs(organization_id, created_at DESC);
@@ -0,0 +1,2 @@
+-- The tenant a session last switched to, used when a request names none.
+ALTER TABLE sessions ADD COLUMN tenant_id UUID REFERENCES tenants(id) ON DELETE SET NULL;
@@ -0,0 +1,20 @@
+-- Invitations to join an organization, accepted through an emailed link.
+CREATE TABLE org_invitations (
+    id UUID PRIMARY KEY,
+    organization_id UUID NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+    email VARCHAR(255) NOT NULL,
+    role VARCHAR(50) NOT NULL DEFAULT 'member',
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    invited_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending', -- 'pending', 'accepted', 'declined', 'revoked'
+    accepted_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    responded_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_org_invitations_org ON org_invitations(organization_id, status);
+
+-- One pending invitation per address and organization
+CREATE UNIQUE INDEX idx_org_invitations_pending
+    ON org_invitations(organization_id, LOWER(email)) WHERE status = 'pending';
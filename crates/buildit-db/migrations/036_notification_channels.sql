@@ -0,0 +1,23 @@
+-- Where a tenant's run, deployment, approval and drift notifications go.
+CREATE TABLE notification_channels (
+    id UUID PRIMARY KEY,
+    tenant_id UUID NOT NULL REFERENCES tenants(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    kind VARCHAR(20) NOT NULL, -- 'slack', 'email', 'webhook'
+    -- slack: {"webhook_url"}, email: {"recipients"}, webhook: {"url"};
+    -- any kind may set a "template" for the message
+    config JSONB NOT NULL DEFAULT '{}',
+    -- HMAC-SHA256 key signing webhook deliveries
+    secret TEXT,
+    -- Events sent to the channel, e.g. 'run.failed'
+    events TEXT[] NOT NULL DEFAULT '{}',
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    last_delivery_at TIMESTAMPTZ,
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE(tenant_id, name)
+);
+
+CREATE INDEX idx_notification_channels_tenant ON notification_channels(tenant_id) WHERE enabled;
@@ -0,0 +1,15 @@
+-- Pipeline analytics: runs now record when they started and finished.
+-- Backfill finished runs from their stages.
+UPDATE pipeline_runs r
+SET started_at = s.started_at,
+    finished_at = CASE WHEN r.status IN ('succeeded', 'failed', 'cancelled', 'stalled')
+                       THEN s.finished_at END
+FROM (
+    SELECT pipeline_run_id, MIN(started_at) AS started_at, MAX(finished_at) AS finished_at
+    FROM stage_results
+    GROUP BY pipeline_run_id
+) s
+WHERE s.pipeline_run_id = r.id AND r.started_at IS NULL;
+
+-- Rollups scan a window of recent runs
+CREATE INDEX IF NOT EXISTS idx_pipeline_runs_created ON pipeline_runs(created_at);
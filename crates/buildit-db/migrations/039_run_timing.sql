@@ -0,0 +1,9 @@
+-- Runs and stages record when they were queued: a run when it is created
+-- for the task queue, a stage when its dependencies are met and it waits for
+-- an executor. The wait ends at started_at, which for a stage is now when
+-- its job was dispatched.
+ALTER TABLE pipeline_runs ADD COLUMN IF NOT EXISTS queued_at TIMESTAMPTZ;
+ALTER TABLE stage_results ADD COLUMN IF NOT EXISTS queued_at TIMESTAMPTZ;
+
+UPDATE pipeline_runs SET queued_at = created_at WHERE queued_at IS NULL;
+UPDATE stage_results SET queued_at = started_at WHERE queued_at IS NULL;
@@ -0,0 +1,16 @@
+-- Search across runs and their logs: trigram indexes for substring matches on
+-- commit metadata and log lines, plus the stage order used for line numbers
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX IF NOT EXISTS idx_pipeline_runs_message_trgm
+    ON pipeline_runs USING GIN ((git_info->>'message') gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS idx_pipeline_runs_author_trgm
+    ON pipeline_runs USING GIN ((git_info->>'author') gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS idx_pipeline_runs_sha
+    ON pipeline_runs ((git_info->>'sha') text_pattern_ops);
+
+CREATE INDEX IF NOT EXISTS idx_logs_content_trgm ON logs USING GIN (content gin_trgm_ops);
+
+-- Lines of a stage are ordered by timestamp, then ID for lines logged together
+DROP INDEX IF EXISTS idx_logs_run_stage;
+CREATE INDEX idx_logs_run_stage ON logs(pipeline_run_id, stage_name, timestamp, id);
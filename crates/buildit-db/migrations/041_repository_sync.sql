@@ -0,0 +1,6 @@
+-- Repositories are rescanned in the background. A failed scan keeps the
+-- config found by the last successful one and records why it failed.
+ALTER TABLE repositories ADD COLUMN IF NOT EXISTS sync_error TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_repositories_last_synced
+    ON repositories(last_synced_at NULLS FIRST);
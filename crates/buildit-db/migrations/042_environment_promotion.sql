@@ -0,0 +1,11 @@
+-- Services promote releases through an ordered chain of environments
+-- (e.g. dev -> staging -> production). A promotion copies the exact
+-- version and spec of the last successful deployment in the previous
+-- environment and records which deployment it was promoted from.
+ALTER TABLE services ADD COLUMN IF NOT EXISTS promotion_chain UUID[] NOT NULL DEFAULT '{}';
+
+ALTER TABLE deployments ADD COLUMN IF NOT EXISTS promoted_from UUID
+    REFERENCES deployments(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_deployments_promoted_from
+    ON deployments(promoted_from) WHERE promoted_from IS NOT NULL;
@@ -0,0 +1,31 @@
+-- Deployment freeze windows. A window freezes an environment for a fixed
+-- range (starts_at/ends_at) or for duration_minutes each time a cron
+-- expression matches. Deployments during a freeze are held as 'frozen'
+-- until it ends, or rejected, depending on the window's policy.
+CREATE TABLE IF NOT EXISTS freeze_windows (
+    id UUID PRIMARY KEY,
+    tenant_id UUID NOT NULL REFERENCES tenants(id) ON DELETE CASCADE,
+    environment_id UUID NOT NULL REFERENCES environments(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    reason TEXT,
+    starts_at TIMESTAMPTZ,
+    ends_at TIMESTAMPTZ,
+    cron VARCHAR(255),
+    duration_minutes INTEGER,
+    policy VARCHAR(20) NOT NULL DEFAULT 'queue', -- 'queue' or 'reject'
+    created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK (
+        (starts_at IS NOT NULL AND ends_at IS NOT NULL AND cron IS NULL AND duration_minutes IS NULL)
+        OR (starts_at IS NULL AND ends_at IS NULL AND cron IS NOT NULL AND duration_minutes IS NOT NULL)
+    )
+);
+
+CREATE INDEX IF NOT EXISTS idx_freeze_windows_environment ON freeze_windows(environment_id);
+CREATE INDEX IF NOT EXISTS idx_freeze_windows_tenant ON freeze_windows(tenant_id);
+
+-- Who deployed through a freeze and why
+ALTER TABLE deployments ADD COLUMN IF NOT EXISTS freeze_override JSONB;
+
+CREATE INDEX IF NOT EXISTS idx_deployments_frozen
+    ON deployments(created_at) WHERE status = 'frozen';
@@ -0,0 +1,35 @@
+-- Environment protection rules, checked before a deployment rolls out:
+-- reviews by designated users or roles, a wait timer after approval, and
+-- the branches deployments must come from.
+CREATE TABLE IF NOT EXISTS environment_protection (
+    environment_id UUID PRIMARY KEY REFERENCES environments(id) ON DELETE CASCADE,
+    tenant_id UUID NOT NULL REFERENCES tenants(id) ON DELETE CASCADE,
+    reviewers UUID[] NOT NULL DEFAULT '{}',
+    reviewer_roles TEXT[] NOT NULL DEFAULT '{}',
+    required_approvals INTEGER NOT NULL DEFAULT 0,
+    wait_minutes INTEGER NOT NULL DEFAULT 0,
+    branches TEXT[] NOT NULL DEFAULT '{}', -- glob patterns; empty allows any
+    updated_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_environment_protection_tenant ON environment_protection(tenant_id);
+
+-- Approvals and rejections of deployments awaiting review
+CREATE TABLE IF NOT EXISTS deployment_reviews (
+    id UUID PRIMARY KEY,
+    deployment_id UUID NOT NULL REFERENCES deployments(id) ON DELETE CASCADE,
+    user_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    api_key_id UUID,
+    approved BOOLEAN NOT NULL,
+    comment TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_deployment_reviews_deployment ON deployment_reviews(deployment_id);
+
+-- The branch a deployment comes from, for branch restrictions
+ALTER TABLE deployments ADD COLUMN IF NOT EXISTS branch VARCHAR(255);
+
+CREATE INDEX IF NOT EXISTS idx_deployments_awaiting_approval
+    ON deployments(tenant_id, created_at) WHERE status = 'awaiting_approval';
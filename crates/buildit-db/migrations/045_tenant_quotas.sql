@@ -0,0 +1,23 @@
+-- Resource quotas per tenant, the CPU and memory each stage requests, and
+-- a usage row per stage job, which quotas are enforced and billed from
+ALTER TABLE tenants ADD COLUMN quota JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE pipeline_stages ADD COLUMN resources JSONB NOT NULL DEFAULT '{}';
+
+CREATE TABLE usage_records (
+    id UUID PRIMARY KEY,
+    tenant_id UUID NOT NULL REFERENCES tenants(id) ON DELETE CASCADE,
+    -- Kept when run history is pruned, so past months can still be billed
+    pipeline_id UUID REFERENCES pipelines(id) ON DELETE SET NULL,
+    pipeline_run_id UUID REFERENCES pipeline_runs(id) ON DELETE SET NULL,
+    stage_name VARCHAR(255) NOT NULL,
+    cpu_millis BIGINT NOT NULL DEFAULT 0,
+    memory_bytes BIGINT NOT NULL DEFAULT 0,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    finished_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_usage_records_tenant_started ON usage_records(tenant_id, started_at);
+CREATE INDEX idx_usage_records_running ON usage_records(tenant_id) WHERE finished_at IS NULL;
+-- A stage re-adopted after a restart keeps the row it started with
+CREATE UNIQUE INDEX idx_usage_records_open_stage ON usage_records(pipeline_run_id, stage_name)
+    WHERE finished_at IS NULL;
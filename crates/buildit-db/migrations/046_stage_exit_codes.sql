@@ -0,0 +1,2 @@
+-- Exit code of each stage's job, recorded when the stage finishes.
+ALTER TABLE stage_results ADD COLUMN exit_code INTEGER;
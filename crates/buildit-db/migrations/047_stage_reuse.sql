@@ -0,0 +1,4 @@
+-- Stages a re-run takes from an earlier run instead of executing them are
+-- recorded with status 'reused' and the run they came from.
+ALTER TABLE stage_results
+    ADD COLUMN reused_from UUID REFERENCES pipeline_runs(id) ON DELETE SET NULL;
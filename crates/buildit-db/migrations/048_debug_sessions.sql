@@ -0,0 +1,21 @@
+-- Debug sessions: a copy of a failed stage's job that idles instead of
+-- running the stage's commands, for a user to open a shell in. Each expires
+-- on its own; the job is removed when it expires or is closed.
+CREATE TABLE IF NOT EXISTS debug_sessions (
+    id UUID PRIMARY KEY,
+    tenant_id UUID NOT NULL REFERENCES tenants(id) ON DELETE CASCADE,
+    pipeline_run_id UUID NOT NULL REFERENCES pipeline_runs(id) ON DELETE CASCADE,
+    stage_name VARCHAR(255) NOT NULL,
+    created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    -- starting, ready, closed, expired or failed
+    status VARCHAR(20) NOT NULL DEFAULT 'starting',
+    job_id UUID,
+    executor_id TEXT,
+    executor_name TEXT,
+    error_message TEXT,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    finished_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_debug_sessions_run ON debug_sessions(pipeline_run_id);
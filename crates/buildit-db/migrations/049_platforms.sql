@@ -0,0 +1,5 @@
+-- Operating system and architecture a stage runs on (`platform "windows/amd64"`)
+ALTER TABLE pipeline_stages ADD COLUMN platform TEXT NOT NULL DEFAULT 'linux';
+
+-- Platforms a runner runs jobs on, e.g. `macos/arm64`
+ALTER TABLE runners ADD COLUMN platforms TEXT[] NOT NULL DEFAULT '{linux}';
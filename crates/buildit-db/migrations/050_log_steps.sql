@@ -0,0 +1,6 @@
+-- Step framing of job logs: the command of a stage each line was written
+-- by, and the lines marking a command's start (`step_start`, content is the
+-- command echo) and end (`step_end`, with its exit code)
+ALTER TABLE logs ADD COLUMN step INTEGER;
+ALTER TABLE logs ADD COLUMN frame VARCHAR(16);
+ALTER TABLE logs ADD COLUMN exit_code INTEGER;
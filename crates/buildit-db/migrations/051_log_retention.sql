@@ -0,0 +1,17 @@
+-- Log retention per tenant: when a run's logs move to the log archive and
+-- when they are deleted
+ALTER TABLE tenants ADD COLUMN log_retention JSONB NOT NULL DEFAULT '{}';
+
+-- A run's logs after they were compressed and moved to the log archive;
+-- its rows in `logs` are removed at the same time
+CREATE TABLE log_archives (
+    pipeline_run_id UUID PRIMARY KEY REFERENCES pipeline_runs(id) ON DELETE CASCADE,
+    tenant_id UUID NOT NULL REFERENCES tenants(id) ON DELETE CASCADE,
+    location TEXT NOT NULL,
+    lines BIGINT NOT NULL,
+    compressed_bytes BIGINT NOT NULL,
+    archived_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_log_archives_tenant ON log_archives(tenant_id);
+CREATE INDEX idx_pipeline_runs_finished ON pipeline_runs(finished_at);
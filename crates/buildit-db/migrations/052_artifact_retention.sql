@@ -0,0 +1,6 @@
+-- Artifact retention per tenant: which run artifacts and reports the
+-- artifact garbage collector removes
+ALTER TABLE tenants ADD COLUMN artifact_retention JSONB NOT NULL DEFAULT '{}';
+
+-- Pinned artifacts, e.g. release builds, are never collected
+ALTER TABLE run_artifacts ADD COLUMN pinned BOOLEAN NOT NULL DEFAULT FALSE;
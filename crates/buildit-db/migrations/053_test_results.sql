@@ -0,0 +1,22 @@
+-- JUnit XML reports a stage writes (`test-reports "target/junit/*.xml"`)
+ALTER TABLE pipeline_stages ADD COLUMN test_reports TEXT[] NOT NULL DEFAULT '{}';
+
+-- Test cases read from a run's test reports
+CREATE TABLE test_results (
+    id UUID PRIMARY KEY,
+    pipeline_run_id UUID NOT NULL REFERENCES pipeline_runs(id) ON DELETE CASCADE,
+    pipeline_id UUID NOT NULL REFERENCES pipelines(id) ON DELETE CASCADE,
+    stage_name VARCHAR(255) NOT NULL,
+    report_path TEXT NOT NULL,
+    suite TEXT NOT NULL,
+    classname TEXT NOT NULL,
+    name TEXT NOT NULL,
+    status VARCHAR(16) NOT NULL, -- 'passed', 'failed', 'errored', 'skipped'
+    duration_secs DOUBLE PRECISION NOT NULL DEFAULT 0,
+    message TEXT,
+    details TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_test_results_run ON test_results(pipeline_run_id);
+CREATE INDEX idx_test_results_pipeline_test ON test_results(pipeline_id, classname, name);
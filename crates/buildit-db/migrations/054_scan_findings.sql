@@ -0,0 +1,21 @@
+-- What a scan stage scans and when it fails (`scan "app:${git.sha}" fail-on="high"`)
+ALTER TABLE pipeline_stages ADD COLUMN scan JSONB;
+
+-- Vulnerabilities scan stages found in a run's images
+CREATE TABLE scan_findings (
+    id UUID PRIMARY KEY,
+    pipeline_run_id UUID NOT NULL REFERENCES pipeline_runs(id) ON DELETE CASCADE,
+    stage_name VARCHAR(255) NOT NULL,
+    scanner VARCHAR(16) NOT NULL, -- 'grype', 'trivy'
+    target TEXT NOT NULL,
+    vulnerability_id TEXT NOT NULL,
+    package TEXT NOT NULL,
+    installed_version TEXT NOT NULL,
+    fixed_version TEXT,
+    severity VARCHAR(16) NOT NULL, -- 'unknown', 'negligible', 'low', 'medium', 'high', 'critical'
+    title TEXT,
+    url TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_scan_findings_run ON scan_findings(pipeline_run_id);
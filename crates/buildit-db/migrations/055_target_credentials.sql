@@ -0,0 +1,9 @@
+-- Encrypted credentials of deployment targets and the outcome of the last
+-- connection check
+ALTER TABLE targets
+    ADD COLUMN credentials BYTEA,
+    ADD COLUMN status_message TEXT,
+    ADD COLUMN checked_at TIMESTAMPTZ,
+    ALTER COLUMN status SET DEFAULT 'unknown';
+
+CREATE INDEX idx_targets_checked ON targets(checked_at NULLS FIRST);
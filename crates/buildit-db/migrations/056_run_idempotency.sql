@@ -0,0 +1,12 @@
+-- Runs created for the same trigger (a webhook redelivery, a retried API
+-- request) share an idempotency key, so only the first creates a run
+ALTER TABLE pipeline_runs ADD COLUMN idempotency_key TEXT;
+
+CREATE UNIQUE INDEX idx_pipeline_runs_idempotency
+    ON pipeline_runs(pipeline_id, idempotency_key)
+    WHERE idempotency_key IS NOT NULL;
+
+-- Deliveries are looked up by provider delivery ID to skip redeliveries
+CREATE INDEX idx_webhook_events_delivery
+    ON webhook_events(provider, (headers->>'delivery'))
+    WHERE processed;
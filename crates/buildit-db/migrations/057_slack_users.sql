@@ -0,0 +1,12 @@
+-- Slack users linked to BuildIt users, whose slash commands and button
+-- clicks act as them in a tenant
+CREATE TABLE slack_users (
+    team_id VARCHAR(32) NOT NULL,
+    slack_user_id VARCHAR(32) NOT NULL,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    tenant_id UUID NOT NULL REFERENCES tenants(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (team_id, slack_user_id)
+);
+
+CREATE INDEX idx_slack_users_user ON slack_users(user_id);
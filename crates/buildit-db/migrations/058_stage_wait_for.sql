@@ -0,0 +1,3 @@
+-- Conditions a stage's job waits for before its commands run
+-- (`wait-for tcp="postgres:5432"`), as JSON
+ALTER TABLE pipeline_stages ADD COLUMN wait_for JSONB NOT NULL DEFAULT '[]';
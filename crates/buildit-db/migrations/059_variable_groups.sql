@@ -0,0 +1,21 @@
+-- Named sets of non-secret variables jobs get. A tenant's groups apply to
+-- all of its pipelines, a pipeline's (pipeline_id set) to that pipeline.
+CREATE TABLE variable_groups (
+    id UUID PRIMARY KEY,
+    tenant_id UUID NOT NULL REFERENCES tenants(id) ON DELETE CASCADE,
+    pipeline_id UUID REFERENCES pipelines(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    description TEXT,
+    variables JSONB NOT NULL DEFAULT '{}',
+    created_by UUID,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX idx_variable_groups_tenant_name
+    ON variable_groups(tenant_id, name) WHERE pipeline_id IS NULL;
+CREATE UNIQUE INDEX idx_variable_groups_pipeline_name
+    ON variable_groups(pipeline_id, name) WHERE pipeline_id IS NOT NULL;
+
+-- Variables a run's jobs got and where each came from, once it started
+ALTER TABLE pipeline_runs ADD COLUMN variables JSONB;
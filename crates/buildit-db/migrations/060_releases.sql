@@ -0,0 +1,21 @@
+-- What a release stage publishes (`release "${git.tag}" { assets "dist/*" }`)
+ALTER TABLE pipeline_stages ADD COLUMN release JSONB;
+
+-- Releases release stages published to the repository's Git provider
+CREATE TABLE releases (
+    id UUID PRIMARY KEY,
+    pipeline_id UUID NOT NULL REFERENCES pipelines(id) ON DELETE CASCADE,
+    pipeline_run_id UUID NOT NULL REFERENCES pipeline_runs(id) ON DELETE CASCADE,
+    stage_name VARCHAR(255) NOT NULL,
+    provider VARCHAR(16) NOT NULL, -- 'github', 'gitlab'
+    tag TEXT NOT NULL,
+    name TEXT NOT NULL,
+    url TEXT NOT NULL,
+    commits INTEGER NOT NULL DEFAULT 0,
+    assets TEXT[] NOT NULL DEFAULT '{}',
+    draft BOOLEAN NOT NULL DEFAULT FALSE,
+    prerelease BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_releases_pipeline ON releases(pipeline_id, created_at DESC);
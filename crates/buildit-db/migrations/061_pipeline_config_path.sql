@@ -0,0 +1,6 @@
+-- File in the pipeline's repository it is synced from (`.buildit/api.kdl`);
+-- unset for pipelines created through the API
+ALTER TABLE pipelines ADD COLUMN config_path TEXT;
+
+CREATE UNIQUE INDEX idx_pipelines_repository_config_path
+    ON pipelines(repository_id, config_path) WHERE config_path IS NOT NULL;
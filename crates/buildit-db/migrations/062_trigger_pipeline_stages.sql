@@ -0,0 +1,2 @@
+-- What a trigger-pipeline stage starts (`trigger-pipeline "deploy" { param "version" "1.2" }`)
+ALTER TABLE pipeline_stages ADD COLUMN trigger_pipeline JSONB;
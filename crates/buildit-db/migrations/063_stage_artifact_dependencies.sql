@@ -0,0 +1,3 @@
+-- Artifacts of other pipelines a stage fetches into its workspace
+-- (`needs-artifact from-pipeline="libfoo" artifact="libfoo.so"`)
+ALTER TABLE pipeline_stages ADD COLUMN needs_artifacts JSONB NOT NULL DEFAULT '[]';
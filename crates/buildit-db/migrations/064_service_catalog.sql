@@ -0,0 +1,8 @@
+-- Who owns a service and where to look when it breaks: the owning team,
+-- its source repository, runbook and dashboards, and how critical it is
+-- (tier 1 the most, tier 4 the least)
+ALTER TABLE services ADD COLUMN owner_team TEXT;
+ALTER TABLE services ADD COLUMN repository_url TEXT;
+ALTER TABLE services ADD COLUMN runbook_url TEXT;
+ALTER TABLE services ADD COLUMN dashboard_urls TEXT[] NOT NULL DEFAULT '{}';
+ALTER TABLE services ADD COLUMN tier INTEGER CHECK (tier BETWEEN 1 AND 4);
@@ -0,0 +1,7 @@
+-- Archived runs keep their history but are left out of run lists. Archived
+-- runs, and the runs of archived pipelines, are purged for good once the
+-- retention window has passed, followed by the pipelines themselves
+ALTER TABLE pipeline_runs ADD COLUMN archived_at TIMESTAMPTZ;
+
+CREATE INDEX idx_pipeline_runs_archived ON pipeline_runs(archived_at) WHERE archived_at IS NOT NULL;
+CREATE INDEX idx_pipelines_archived ON pipelines(archived_at) WHERE archived_at IS NOT NULL;
@@ -0,0 +1,17 @@
+-- Rego policies a tenant evaluates against the JSON of every stack plan.
+-- Hard policies fail the run on a violation, soft ones only warn approvers
+CREATE TABLE plan_policies (
+    id UUID PRIMARY KEY,
+    tenant_id UUID NOT NULL REFERENCES tenants(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    description TEXT,
+    rego TEXT NOT NULL,
+    enforcement VARCHAR(10) NOT NULL DEFAULT 'hard', -- 'hard', 'soft'
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (tenant_id, name)
+);
+
+-- Outcome of each policy, as evaluated when the run was planned
+ALTER TABLE stack_runs ADD COLUMN policy_results JSONB;
@@ -0,0 +1,2 @@
+-- Rules every pipeline of the organization's tenants must follow
+ALTER TABLE organizations ADD COLUMN pipeline_policy JSONB NOT NULL DEFAULT '{}';
@@ -0,0 +1,5 @@
+-- What a job measurably used, from its executor's CPU and memory readings.
+-- NULL when the executor could not tell; costs then fall back to requests.
+ALTER TABLE usage_records
+    ADD COLUMN cpu_seconds DOUBLE PRECISION,
+    ADD COLUMN memory_gib_seconds DOUBLE PRECISION;
@@ -0,0 +1,11 @@
+-- Highest and average CPU and memory readings of a job, which stage
+-- resource requests are right-sized from. NULL when the executor could not
+-- tell.
+ALTER TABLE usage_records
+    ADD COLUMN peak_cpu_millis BIGINT,
+    ADD COLUMN peak_memory_bytes BIGINT,
+    ADD COLUMN avg_cpu_millis BIGINT,
+    ADD COLUMN avg_memory_bytes BIGINT;
+
+CREATE INDEX idx_usage_records_measured_stage ON usage_records(pipeline_id, stage_name, started_at)
+    WHERE peak_cpu_millis IS NOT NULL;
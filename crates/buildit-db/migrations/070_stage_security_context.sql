@@ -0,0 +1,2 @@
+-- Overrides of the executor's default security context for a stage's job.
+ALTER TABLE pipeline_stages ADD COLUMN security_context JSONB;
@@ -0,0 +1,3 @@
+-- How a stage's container is started where it differs from the image's
+-- defaults: user, entrypoint, privileged, extra hosts and network.
+ALTER TABLE pipeline_stages ADD COLUMN container JSONB NOT NULL DEFAULT '{}';
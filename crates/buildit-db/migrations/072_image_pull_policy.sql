@@ -0,0 +1,7 @@
+-- When a stage's image is pulled (always, if-not-present or never); the
+-- executor's default when unset.
+ALTER TABLE pipeline_stages ADD COLUMN pull_policy TEXT;
+
+-- Image a stage's job ran, pinned to the digest its tag resolved to when
+-- the run was planned.
+ALTER TABLE stage_results ADD COLUMN image_digest TEXT;
@@ -0,0 +1,14 @@
+-- Tenant and organization a runner job belongs to; runners only lease jobs
+-- of their own organization.
+ALTER TABLE runner_jobs ADD COLUMN tenant_id UUID REFERENCES tenants(id) ON DELETE CASCADE;
+ALTER TABLE runner_jobs ADD COLUMN organization_id UUID REFERENCES organizations(id) ON DELETE CASCADE;
+
+-- Jobs queued without an owner could go to any organization's runner
+UPDATE runner_jobs
+SET status = 'cancelled',
+    error = 'Cancelled: queued before runner jobs were kept to their organization',
+    finished_at = NOW()
+WHERE status = 'pending';
+
+DROP INDEX idx_runner_jobs_pending;
+CREATE INDEX idx_runner_jobs_pending ON runner_jobs(organization_id, created_at) WHERE status = 'pending';
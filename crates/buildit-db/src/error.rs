@@ -10,6 +10,9 @@ pub enum DbError {
     #[error("duplicate: {0}")]
     Duplicate(String),
 
+    #[error("conflict: {0}")]
+    Conflict(String),
+
     #[error("invalid data: {0}")]
     InvalidData(String),
 
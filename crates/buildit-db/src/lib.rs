@@ -2,6 +2,8 @@
 //!
 //! Provides repository traits and implementations using Clorinde-generated queries.
 
+#![allow(clippy::too_many_arguments)]
+
 pub mod error;
 pub mod repo;
 
@@ -16,10 +18,18 @@ use sqlx::PgPool;
 use sqlx::postgres::PgPoolOptions;
 use tokio_postgres::NoTls;
 
+/// Connections a pool keeps open unless configured otherwise.
+pub const DEFAULT_MAX_CONNECTIONS: u32 = 10;
+
 /// Create a new SQLx database connection pool (for migrations).
 pub async fn create_pool(database_url: &str) -> DbResult<PgPool> {
+    create_pool_sized(database_url, DEFAULT_MAX_CONNECTIONS).await
+}
+
+/// Create a connection pool keeping at most `max_connections` open.
+pub async fn create_pool_sized(database_url: &str, max_connections: u32) -> DbResult<PgPool> {
     let pool = PgPoolOptions::new()
-        .max_connections(10)
+        .max_connections(max_connections)
         .connect(database_url)
         .await?;
     Ok(pool)
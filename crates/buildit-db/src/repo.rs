@@ -1,25 +1,61 @@
 //! Repository traits and implementations.
 
+pub mod analytics;
 pub mod application;
+pub mod artifacts;
+pub mod backup;
 pub mod deployment;
 pub mod logs;
+pub mod notification;
 pub mod organization;
 pub mod pipeline;
+pub mod release;
 pub mod repository;
+pub mod runner;
+pub mod scan_findings;
+pub mod search;
 pub mod stack;
 pub mod tenant;
+pub mod test_results;
+pub mod variable_group;
+pub mod webhook_subscription;
 
+pub use analytics::{
+    AnalyticsFilter, AnalyticsRepo, BranchStats, CostRates, CostSummary, DailyCost, DailyRuns,
+    FlakyStage, PgAnalyticsRepo, PipelineCost, PipelineStats, RunCost, RunSummary, StageStats,
+    StageUsage, TenantOverview,
+};
 pub use application::{ApplicationRepo, PgApplicationRepo};
+pub use artifacts::{ArtifactRecord, ArtifactRepo, PgArtifactRepo, ReportFile, ReportRecord};
+pub use backup::BackupSnapshot;
 pub use deployment::{
-    Deployment, DeploymentRepo, DeploymentWithDetails, Environment, EnvironmentWithTarget,
-    PgDeploymentRepo, Service, Target,
+    Deployment, DeploymentRepo, DeploymentReview, DeploymentWithDetails, Environment,
+    EnvironmentProtection, EnvironmentWithTarget, FreezeWindow, PgDeploymentRepo, Service,
+    ServiceCatalog, ServicePlacement, ServiceSpecVersion, Target,
 };
-pub use logs::{LogRecord, LogRepo, PgLogRepo};
+pub use logs::{LogArchiveRecord, LogRecord, LogRepo, PgLogRepo, RunLogsDue};
+pub use notification::{NotificationChannel, NotificationRepo, PgNotificationRepo};
 pub use organization::{
-    ApiKey, AuditLog, OAuthConnection, OrgMembership, OrgMembershipWithUser, Organization,
-    OrganizationRepo, PgOrganizationRepo, Session, TenantMembership, User, UserPublic,
+    ApiKey, AuditLog, AuditLogFilter, DeviceAuthorization, OAuthConnection, OrgInvitation,
+    OrgMembership, OrgMembershipWithUser, Organization, OrganizationRepo, PgOrganizationRepo,
+    Session, SlackUser, TenantMembership, User, UserPublic,
+};
+pub use pipeline::{
+    DebugSessionRecord, PgPipelineRepo, PipelineRepo, PipelineSearchRecord, PipelineStageRecord,
+    PipelineSummaryRecord, RunningStageRecord, StageResultRecord, TenantRunRecord,
 };
-pub use pipeline::{PgPipelineRepo, PipelineRepo, PipelineStageRecord, StageResultRecord};
+pub use release::{PgReleaseRepo, ReleaseRecord, ReleaseRepo};
 pub use repository::{PgRepositoryRepo, RepositoryRepo};
+pub use runner::{PgRunnerRepo, RunnerJobLogRecord, RunnerJobRecord, RunnerRecord, RunnerRepo};
+pub use scan_findings::{PgScanFindingRepo, ScanFindingRecord, ScanFindingRepo, ScanSummary};
+pub use search::{LogSearchHit, PgSearchRepo, RunSearchFilter, RunSearchHit, SearchRepo};
 pub use stack::{PgStackRepo, StackRepo};
-pub use tenant::{PgTenantRepo, TenantRepo};
+pub use tenant::{PgTenantRepo, Tenant, TenantRepo};
+pub use test_results::{
+    FlakyTest, PgTestResultRepo, TestResultRecord, TestResultRepo, TestSummary,
+};
+pub use variable_group::{PgVariableGroupRepo, VariableGroup, VariableGroupRepo};
+pub use webhook_subscription::{
+    DeliveryAttempt, PgWebhookSubscriptionRepo, WebhookDelivery, WebhookSubscription,
+    WebhookSubscriptionRepo,
+};
@@ -0,0 +1,661 @@
+//! Pipeline analytics repository.
+//!
+//! SQL rollups over a tenant's recent runs: success rates, run and stage
+//! durations, queue wait and flaky stages. Rates are over completed runs
+//! (`succeeded`, `failed` or `stalled`); cancelled runs only count towards
+//! totals. Durations and queue wait are in seconds.
+//!
+//! Costs are estimated from the usage rows of the jobs started in the
+//! window: the CPU and memory a job measurably used, or what it requested
+//! for as long as it ran where its executor could not tell, priced at the
+//! configured [`CostRates`]. The same rows give each stage's measured peak
+//! and average CPU and memory, which its resource requests are right-sized
+//! from.
+
+use async_trait::async_trait;
+use buildit_core::ResourceId;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+use crate::DbResult;
+
+/// The runs analytics are computed over.
+#[derive(Debug, Clone)]
+pub struct AnalyticsFilter {
+    pub tenant_id: ResourceId,
+    /// Runs created from then on.
+    pub since: DateTime<Utc>,
+    /// Only the runs of this pipeline.
+    pub pipeline_id: Option<uuid::Uuid>,
+}
+
+/// Totals over all runs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct RunSummary {
+    pub total_runs: i64,
+    pub succeeded: i64,
+    pub failed: i64,
+    pub success_rate: Option<f64>,
+    pub p50_duration_secs: Option<f64>,
+    pub p95_duration_secs: Option<f64>,
+    /// Time from a run being created to it starting.
+    pub p50_queue_wait_secs: Option<f64>,
+    pub p95_queue_wait_secs: Option<f64>,
+}
+
+/// The headline numbers of a tenant's dashboard.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct TenantOverview {
+    /// Active (non-archived) pipelines.
+    pub pipeline_count: i64,
+    /// Runs created since the start of the day.
+    pub runs_today: i64,
+    /// Success rate of the runs created since the start of the window.
+    pub success_rate: Option<f64>,
+}
+
+/// Runs created on a day (UTC).
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct DailyRuns {
+    pub day: DateTime<Utc>,
+    pub total_runs: i64,
+    pub succeeded: i64,
+    pub failed: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct PipelineStats {
+    pub pipeline_id: uuid::Uuid,
+    pub pipeline_name: String,
+    pub total_runs: i64,
+    pub succeeded: i64,
+    pub failed: i64,
+    pub failure_rate: Option<f64>,
+    pub p50_duration_secs: Option<f64>,
+    pub p95_duration_secs: Option<f64>,
+    pub p50_queue_wait_secs: Option<f64>,
+    pub p95_queue_wait_secs: Option<f64>,
+    pub last_run_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct BranchStats {
+    pub pipeline_id: uuid::Uuid,
+    pub pipeline_name: String,
+    /// `None` for runs not of a branch, e.g. manual runs without one.
+    pub branch: Option<String>,
+    pub total_runs: i64,
+    pub succeeded: i64,
+    pub failed: i64,
+    pub failure_rate: Option<f64>,
+    pub p50_duration_secs: Option<f64>,
+    pub p95_duration_secs: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct StageStats {
+    pub pipeline_id: uuid::Uuid,
+    pub pipeline_name: String,
+    pub stage_name: String,
+    pub total_runs: i64,
+    pub failed: i64,
+    pub failure_rate: Option<f64>,
+    pub p50_duration_secs: Option<f64>,
+    pub p95_duration_secs: Option<f64>,
+}
+
+/// A stage whose outcome changes without its pipeline changing much.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct FlakyStage {
+    pub pipeline_id: uuid::Uuid,
+    pub pipeline_name: String,
+    pub stage_name: String,
+    pub total_runs: i64,
+    pub failed: i64,
+    /// Runs whose outcome differs from the previous run's on the same
+    /// branch.
+    pub flips: i64,
+    pub flip_rate: Option<f64>,
+    /// Commits the stage both passed and failed on.
+    pub flaky_commits: i64,
+}
+
+/// What compute is priced at, in the configured currency.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+pub struct CostRates {
+    pub cpu_core_hour: f64,
+    pub memory_gib_hour: f64,
+}
+
+/// Compute used and its estimated cost over all jobs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct CostSummary {
+    pub jobs: i64,
+    /// Jobs whose executor reported what they used; the others are costed
+    /// at what they requested.
+    pub measured_jobs: i64,
+    pub run_minutes: f64,
+    pub cpu_core_hours: f64,
+    pub memory_gib_hours: f64,
+    pub cost: f64,
+}
+
+/// Compute used by the jobs started on a day (UTC).
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct DailyCost {
+    pub day: DateTime<Utc>,
+    pub jobs: i64,
+    pub cpu_core_hours: f64,
+    pub memory_gib_hours: f64,
+    pub cost: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct PipelineCost {
+    /// `None` for jobs of pipelines deleted since.
+    pub pipeline_id: Option<uuid::Uuid>,
+    pub pipeline_name: Option<String>,
+    pub runs: i64,
+    pub jobs: i64,
+    pub run_minutes: f64,
+    pub cpu_core_hours: f64,
+    pub memory_gib_hours: f64,
+    pub cost: f64,
+    pub cost_per_run: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct RunCost {
+    pub run_id: uuid::Uuid,
+    pub pipeline_id: Option<uuid::Uuid>,
+    pub pipeline_name: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub jobs: i64,
+    pub run_minutes: f64,
+    pub cpu_core_hours: f64,
+    pub memory_gib_hours: f64,
+    pub cost: f64,
+}
+
+/// What a stage's jobs measurably used, against what they asked for.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct StageUsage {
+    pub pipeline_id: uuid::Uuid,
+    pub pipeline_name: String,
+    pub stage_name: String,
+    /// Jobs whose executor reported what they used.
+    pub jobs: i64,
+    /// CPU the latest job requested (its limit without a request), in
+    /// millicores; 0 if it set neither.
+    pub cpu_request_millis: i64,
+    /// Memory the latest job requested, as for CPU, in bytes.
+    pub memory_request_bytes: i64,
+    /// 95th percentile of the jobs' average CPU, in millicores.
+    pub p95_avg_cpu_millis: f64,
+    /// 95th percentile of the jobs' peak CPU, in millicores.
+    pub p95_peak_cpu_millis: f64,
+    /// 95th percentile of the jobs' peak memory, in bytes.
+    pub p95_peak_memory_bytes: f64,
+    /// Highest memory any job reached, in bytes.
+    pub max_peak_memory_bytes: i64,
+}
+
+#[async_trait]
+pub trait AnalyticsRepo: Send + Sync {
+    async fn summary(&self, filter: &AnalyticsFilter) -> DbResult<RunSummary>;
+
+    /// Pipeline count, runs since `today` and the success rate of the
+    /// runs of `filter`, in one query. `filter.pipeline_id` is ignored.
+    async fn overview(
+        &self,
+        filter: &AnalyticsFilter,
+        today: DateTime<Utc>,
+    ) -> DbResult<TenantOverview>;
+
+    /// Runs per day, oldest first. Days without runs are left out.
+    async fn daily_runs(&self, filter: &AnalyticsFilter) -> DbResult<Vec<DailyRuns>>;
+
+    /// Stats of each pipeline with runs, most runs first.
+    async fn pipeline_stats(&self, filter: &AnalyticsFilter) -> DbResult<Vec<PipelineStats>>;
+
+    /// Stats of each branch of each pipeline, most runs first.
+    async fn branch_stats(&self, filter: &AnalyticsFilter) -> DbResult<Vec<BranchStats>>;
+
+    /// Stats of each stage of each pipeline, slowest first.
+    async fn stage_stats(&self, filter: &AnalyticsFilter) -> DbResult<Vec<StageStats>>;
+
+    /// Stages that flipped between passing and failing, flakiest first.
+    async fn flaky_stages(&self, filter: &AnalyticsFilter, limit: i64)
+    -> DbResult<Vec<FlakyStage>>;
+
+    /// Compute used by the jobs started in the window and what it cost.
+    async fn cost_summary(
+        &self,
+        filter: &AnalyticsFilter,
+        rates: CostRates,
+    ) -> DbResult<CostSummary>;
+
+    /// Cost per day, oldest first. Days without jobs are left out.
+    async fn daily_costs(
+        &self,
+        filter: &AnalyticsFilter,
+        rates: CostRates,
+    ) -> DbResult<Vec<DailyCost>>;
+
+    /// Cost of each pipeline with jobs, most expensive first.
+    async fn pipeline_costs(
+        &self,
+        filter: &AnalyticsFilter,
+        rates: CostRates,
+    ) -> DbResult<Vec<PipelineCost>>;
+
+    /// The most expensive runs, most expensive first.
+    async fn run_costs(
+        &self,
+        filter: &AnalyticsFilter,
+        rates: CostRates,
+        limit: i64,
+    ) -> DbResult<Vec<RunCost>>;
+
+    /// Measured usage of each stage with measured jobs started in the
+    /// window, by pipeline and stage name.
+    async fn stage_usage(&self, filter: &AnalyticsFilter) -> DbResult<Vec<StageUsage>>;
+}
+
+/// PostgreSQL implementation of AnalyticsRepo.
+pub struct PgAnalyticsRepo {
+    pool: PgPool,
+}
+
+/// The filtered runs, with their duration and queue wait; `$1` is the
+/// tenant, `$2` the start of the window and `$3` the pipeline, if any.
+const RUNS: &str = r#"
+    runs AS (
+        SELECT r.id, r.pipeline_id, p.name AS pipeline_name, r.status, r.created_at,
+               NULLIF(r.git_info->>'branch', '') AS branch,
+               NULLIF(r.git_info->>'sha', '') AS sha,
+               r.status IN ('succeeded', 'failed', 'stalled') AS completed,
+               r.status IN ('failed', 'stalled') AS failure,
+               EXTRACT(EPOCH FROM r.finished_at - r.started_at)::float8 AS duration,
+               GREATEST(EXTRACT(EPOCH FROM r.started_at - r.queued_at)::float8, 0) AS queue_wait
+        FROM pipeline_runs r
+        JOIN pipelines p ON p.id = r.pipeline_id
+        WHERE p.tenant_id = $1 AND r.created_at >= $2
+          AND ($3::uuid IS NULL OR r.pipeline_id = $3)
+    )
+"#;
+
+/// The completed stages of the filtered runs, after [`RUNS`].
+const STAGES: &str = r#"
+    stages AS (
+        SELECT runs.pipeline_id, runs.pipeline_name, runs.branch, runs.sha, runs.created_at,
+               s.stage_name, s.status <> 'succeeded' AS failure,
+               EXTRACT(EPOCH FROM s.finished_at - s.started_at)::float8 AS duration
+        FROM stage_results s
+        JOIN runs ON runs.id = s.pipeline_run_id
+        WHERE s.status IN ('succeeded', 'failed', 'stalled')
+    )
+"#;
+
+/// Run totals, failure rate and duration percentiles over `runs`.
+const RUN_ROLLUP: &str = r#"
+    COUNT(*) AS total_runs,
+    COUNT(*) FILTER (WHERE status = 'succeeded') AS succeeded,
+    COUNT(*) FILTER (WHERE failure) AS failed,
+    COUNT(*) FILTER (WHERE failure)::float8
+        / NULLIF(COUNT(*) FILTER (WHERE completed), 0) AS failure_rate,
+    percentile_cont(0.5) WITHIN GROUP (ORDER BY duration) FILTER (WHERE completed) AS p50_duration_secs,
+    percentile_cont(0.95) WITHIN GROUP (ORDER BY duration) FILTER (WHERE completed) AS p95_duration_secs
+"#;
+
+/// The jobs started in the window, with the compute they used and its
+/// cost; `$1` to `$3` filter as in [`RUNS`], `$4` and `$5` are the CPU and
+/// memory rates. Jobs still running count until now.
+const JOBS: &str = r#"
+    jobs AS (
+        SELECT u.pipeline_id, p.name AS pipeline_name, u.pipeline_run_id, u.started_at,
+               u.cpu_seconds IS NOT NULL AS measured, d.secs,
+               h.cpu_core_hours, h.memory_gib_hours,
+               $4 * h.cpu_core_hours + $5 * h.memory_gib_hours AS cost
+        FROM usage_records u
+        LEFT JOIN pipelines p ON p.id = u.pipeline_id
+        CROSS JOIN LATERAL (
+            SELECT GREATEST(EXTRACT(EPOCH FROM COALESCE(u.finished_at, NOW()) - u.started_at)::float8, 0) AS secs
+        ) d
+        CROSS JOIN LATERAL (
+            SELECT COALESCE(u.cpu_seconds, d.secs * u.cpu_millis / 1000.0) / 3600 AS cpu_core_hours,
+                   COALESCE(u.memory_gib_seconds, d.secs * u.memory_bytes / 1073741824.0) / 3600 AS memory_gib_hours
+        ) h
+        WHERE u.tenant_id = $1 AND u.started_at >= $2
+          AND ($3::uuid IS NULL OR u.pipeline_id = $3)
+    )
+"#;
+
+/// Compute totals and cost over `jobs`.
+const JOB_ROLLUP: &str = r#"
+    COUNT(*) AS jobs,
+    COALESCE(SUM(secs), 0) / 60 AS run_minutes,
+    COALESCE(SUM(cpu_core_hours), 0) AS cpu_core_hours,
+    COALESCE(SUM(memory_gib_hours), 0) AS memory_gib_hours,
+    COALESCE(SUM(cost), 0) AS cost
+"#;
+
+type QueryAs<'q, T> = sqlx::query::QueryAs<'q, sqlx::Postgres, T, sqlx::postgres::PgArguments>;
+
+impl PgAnalyticsRepo {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// A query of the filtered runs, with the filter bound.
+    fn query_as<'q, T>(&self, sql: &'q str, filter: &'q AnalyticsFilter) -> QueryAs<'q, T>
+    where
+        T: for<'r> sqlx::FromRow<'r, sqlx::postgres::PgRow>,
+    {
+        sqlx::query_as::<_, T>(sql)
+            .bind(filter.tenant_id.as_uuid())
+            .bind(filter.since)
+            .bind(filter.pipeline_id)
+    }
+
+    /// A query of the filtered jobs, with the filter and rates bound.
+    fn cost_query_as<'q, T>(
+        &self,
+        sql: &'q str,
+        filter: &'q AnalyticsFilter,
+        rates: CostRates,
+    ) -> QueryAs<'q, T>
+    where
+        T: for<'r> sqlx::FromRow<'r, sqlx::postgres::PgRow>,
+    {
+        self.query_as(sql, filter)
+            .bind(rates.cpu_core_hour)
+            .bind(rates.memory_gib_hour)
+    }
+}
+
+#[async_trait]
+impl AnalyticsRepo for PgAnalyticsRepo {
+    async fn summary(&self, filter: &AnalyticsFilter) -> DbResult<RunSummary> {
+        let sql = format!(
+            r#"
+            WITH {RUNS}
+            SELECT COUNT(*) AS total_runs,
+                   COUNT(*) FILTER (WHERE status = 'succeeded') AS succeeded,
+                   COUNT(*) FILTER (WHERE failure) AS failed,
+                   COUNT(*) FILTER (WHERE status = 'succeeded')::float8
+                       / NULLIF(COUNT(*) FILTER (WHERE completed), 0) AS success_rate,
+                   percentile_cont(0.5) WITHIN GROUP (ORDER BY duration) FILTER (WHERE completed) AS p50_duration_secs,
+                   percentile_cont(0.95) WITHIN GROUP (ORDER BY duration) FILTER (WHERE completed) AS p95_duration_secs,
+                   percentile_cont(0.5) WITHIN GROUP (ORDER BY queue_wait) AS p50_queue_wait_secs,
+                   percentile_cont(0.95) WITHIN GROUP (ORDER BY queue_wait) AS p95_queue_wait_secs
+            FROM runs
+            "#
+        );
+        let summary = self.query_as(&sql, filter).fetch_one(&self.pool).await?;
+        Ok(summary)
+    }
+
+    async fn overview(
+        &self,
+        filter: &AnalyticsFilter,
+        today: DateTime<Utc>,
+    ) -> DbResult<TenantOverview> {
+        let overview = sqlx::query_as::<_, TenantOverview>(
+            r#"
+            SELECT (SELECT COUNT(*) FROM pipelines
+                    WHERE tenant_id = $1 AND archived_at IS NULL) AS pipeline_count,
+                   COUNT(*) FILTER (WHERE r.created_at >= $3) AS runs_today,
+                   COUNT(*) FILTER (WHERE r.created_at >= $2 AND r.status = 'succeeded')::float8
+                       / NULLIF(COUNT(*) FILTER (
+                           WHERE r.created_at >= $2 AND r.status IN ('succeeded', 'failed', 'stalled')
+                       ), 0) AS success_rate
+            FROM pipeline_runs r
+            JOIN pipelines p ON p.id = r.pipeline_id
+            WHERE p.tenant_id = $1 AND r.created_at >= LEAST($2, $3)
+            "#,
+        )
+        .bind(filter.tenant_id.as_uuid())
+        .bind(filter.since)
+        .bind(today)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(overview)
+    }
+
+    async fn daily_runs(&self, filter: &AnalyticsFilter) -> DbResult<Vec<DailyRuns>> {
+        let sql = format!(
+            r#"
+            WITH {RUNS}
+            SELECT date_trunc('day', created_at, 'UTC') AS day,
+                   COUNT(*) AS total_runs,
+                   COUNT(*) FILTER (WHERE status = 'succeeded') AS succeeded,
+                   COUNT(*) FILTER (WHERE failure) AS failed
+            FROM runs
+            GROUP BY 1
+            ORDER BY 1
+            "#
+        );
+        let days = self.query_as(&sql, filter).fetch_all(&self.pool).await?;
+        Ok(days)
+    }
+
+    async fn pipeline_stats(&self, filter: &AnalyticsFilter) -> DbResult<Vec<PipelineStats>> {
+        let sql = format!(
+            r#"
+            WITH {RUNS}
+            SELECT pipeline_id, pipeline_name, {RUN_ROLLUP},
+                   percentile_cont(0.5) WITHIN GROUP (ORDER BY queue_wait) AS p50_queue_wait_secs,
+                   percentile_cont(0.95) WITHIN GROUP (ORDER BY queue_wait) AS p95_queue_wait_secs,
+                   MAX(created_at) AS last_run_at
+            FROM runs
+            GROUP BY pipeline_id, pipeline_name
+            ORDER BY total_runs DESC, pipeline_name
+            "#
+        );
+        let stats = self.query_as(&sql, filter).fetch_all(&self.pool).await?;
+        Ok(stats)
+    }
+
+    async fn branch_stats(&self, filter: &AnalyticsFilter) -> DbResult<Vec<BranchStats>> {
+        let sql = format!(
+            r#"
+            WITH {RUNS}
+            SELECT pipeline_id, pipeline_name, branch, {RUN_ROLLUP}
+            FROM runs
+            GROUP BY pipeline_id, pipeline_name, branch
+            ORDER BY total_runs DESC, pipeline_name, branch
+            "#
+        );
+        let stats = self.query_as(&sql, filter).fetch_all(&self.pool).await?;
+        Ok(stats)
+    }
+
+    async fn stage_stats(&self, filter: &AnalyticsFilter) -> DbResult<Vec<StageStats>> {
+        let sql = format!(
+            r#"
+            WITH {RUNS}, {STAGES}
+            SELECT pipeline_id, pipeline_name, stage_name,
+                   COUNT(*) AS total_runs,
+                   COUNT(*) FILTER (WHERE failure) AS failed,
+                   COUNT(*) FILTER (WHERE failure)::float8 / COUNT(*) AS failure_rate,
+                   percentile_cont(0.5) WITHIN GROUP (ORDER BY duration) AS p50_duration_secs,
+                   percentile_cont(0.95) WITHIN GROUP (ORDER BY duration) AS p95_duration_secs
+            FROM stages
+            GROUP BY pipeline_id, pipeline_name, stage_name
+            ORDER BY p95_duration_secs DESC NULLS LAST, pipeline_name, stage_name
+            "#
+        );
+        let stats = self.query_as(&sql, filter).fetch_all(&self.pool).await?;
+        Ok(stats)
+    }
+
+    async fn flaky_stages(
+        &self,
+        filter: &AnalyticsFilter,
+        limit: i64,
+    ) -> DbResult<Vec<FlakyStage>> {
+        let sql = format!(
+            r#"
+            WITH {RUNS}, {STAGES},
+            outcomes AS (
+                SELECT *, LAG(failure) OVER (
+                    PARTITION BY pipeline_id, stage_name, branch ORDER BY created_at
+                ) AS previous_failure
+                FROM stages
+            ),
+            commits AS (
+                SELECT pipeline_id, stage_name, COUNT(*) AS flaky_commits
+                FROM (
+                    SELECT pipeline_id, stage_name, sha
+                    FROM stages
+                    WHERE sha IS NOT NULL
+                    GROUP BY pipeline_id, stage_name, sha
+                    HAVING bool_or(failure) AND NOT bool_and(failure)
+                ) flaky
+                GROUP BY pipeline_id, stage_name
+            ),
+            flips AS (
+                SELECT pipeline_id, pipeline_name, stage_name,
+                       COUNT(*) AS total_runs,
+                       COUNT(*) FILTER (WHERE failure) AS failed,
+                       COUNT(*) FILTER (WHERE failure <> previous_failure) AS flips,
+                       COUNT(*) FILTER (WHERE failure <> previous_failure)::float8
+                           / NULLIF(COUNT(previous_failure), 0) AS flip_rate
+                FROM outcomes
+                GROUP BY pipeline_id, pipeline_name, stage_name
+            )
+            SELECT f.*, COALESCE(c.flaky_commits, 0) AS flaky_commits
+            FROM flips f
+            LEFT JOIN commits c USING (pipeline_id, stage_name)
+            WHERE f.flips > 0 OR c.flaky_commits > 0
+            ORDER BY flaky_commits DESC, f.flip_rate DESC NULLS LAST, f.pipeline_name, f.stage_name
+            LIMIT $4
+            "#
+        );
+        let stages = self
+            .query_as(&sql, filter)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(stages)
+    }
+
+    async fn cost_summary(
+        &self,
+        filter: &AnalyticsFilter,
+        rates: CostRates,
+    ) -> DbResult<CostSummary> {
+        let sql = format!(
+            r#"
+            WITH {JOBS}
+            SELECT {JOB_ROLLUP}, COUNT(*) FILTER (WHERE measured) AS measured_jobs
+            FROM jobs
+            "#
+        );
+        let summary = self
+            .cost_query_as(&sql, filter, rates)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(summary)
+    }
+
+    async fn daily_costs(
+        &self,
+        filter: &AnalyticsFilter,
+        rates: CostRates,
+    ) -> DbResult<Vec<DailyCost>> {
+        let sql = format!(
+            r#"
+            WITH {JOBS}
+            SELECT date_trunc('day', started_at, 'UTC') AS day, {JOB_ROLLUP}
+            FROM jobs
+            GROUP BY 1
+            ORDER BY 1
+            "#
+        );
+        let days = self
+            .cost_query_as(&sql, filter, rates)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(days)
+    }
+
+    async fn pipeline_costs(
+        &self,
+        filter: &AnalyticsFilter,
+        rates: CostRates,
+    ) -> DbResult<Vec<PipelineCost>> {
+        let sql = format!(
+            r#"
+            WITH {JOBS}
+            SELECT pipeline_id, pipeline_name, {JOB_ROLLUP},
+                   COUNT(DISTINCT pipeline_run_id) AS runs,
+                   SUM(cost) / NULLIF(COUNT(DISTINCT pipeline_run_id), 0) AS cost_per_run
+            FROM jobs
+            GROUP BY pipeline_id, pipeline_name
+            ORDER BY cost DESC, pipeline_name
+            "#
+        );
+        let pipelines = self
+            .cost_query_as(&sql, filter, rates)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(pipelines)
+    }
+
+    async fn run_costs(
+        &self,
+        filter: &AnalyticsFilter,
+        rates: CostRates,
+        limit: i64,
+    ) -> DbResult<Vec<RunCost>> {
+        let sql = format!(
+            r#"
+            WITH {JOBS}
+            SELECT pipeline_run_id AS run_id, pipeline_id, pipeline_name,
+                   MIN(started_at) AS started_at, {JOB_ROLLUP}
+            FROM jobs
+            WHERE pipeline_run_id IS NOT NULL
+            GROUP BY pipeline_run_id, pipeline_id, pipeline_name
+            ORDER BY cost DESC
+            LIMIT $6
+            "#
+        );
+        let runs = self
+            .cost_query_as(&sql, filter, rates)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(runs)
+    }
+
+    async fn stage_usage(&self, filter: &AnalyticsFilter) -> DbResult<Vec<StageUsage>> {
+        let usage = self
+            .query_as(
+                r#"
+                SELECT u.pipeline_id, p.name AS pipeline_name, u.stage_name,
+                       COUNT(*) AS jobs,
+                       (array_agg(u.cpu_millis ORDER BY u.started_at DESC))[1] AS cpu_request_millis,
+                       (array_agg(u.memory_bytes ORDER BY u.started_at DESC))[1] AS memory_request_bytes,
+                       percentile_cont(0.95) WITHIN GROUP (ORDER BY u.avg_cpu_millis) AS p95_avg_cpu_millis,
+                       percentile_cont(0.95) WITHIN GROUP (ORDER BY u.peak_cpu_millis) AS p95_peak_cpu_millis,
+                       percentile_cont(0.95) WITHIN GROUP (ORDER BY u.peak_memory_bytes) AS p95_peak_memory_bytes,
+                       MAX(u.peak_memory_bytes) AS max_peak_memory_bytes
+                FROM usage_records u
+                JOIN pipelines p ON p.id = u.pipeline_id
+                WHERE u.tenant_id = $1 AND u.started_at >= $2
+                  AND ($3::uuid IS NULL OR u.pipeline_id = $3)
+                  AND u.peak_cpu_millis IS NOT NULL
+                GROUP BY u.pipeline_id, p.name, u.stage_name
+                ORDER BY p.name, u.stage_name
+                "#,
+                filter,
+            )
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(usage)
+    }
+}
@@ -4,7 +4,7 @@ use async_trait::async_trait;
 use buildit_core::ResourceId;
 use buildit_core::application::{
     Application, ApplicationResource, ApplicationSync, ApplicationSyncStatus, HealthStatus,
-    ResourceStatus, SyncPolicy, SyncStatus, SyncTriggerType,
+    HelmSource, ResourceStatus, SyncPolicy, SyncStatus, SyncTriggerType,
 };
 use chrono::{DateTime, Utc};
 use sqlx::PgPool;
@@ -33,6 +33,8 @@ pub struct ApplicationRow {
     pub last_synced_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub helm: Option<serde_json::Value>,
+    pub rendered_digest: Option<String>,
 }
 
 impl TryFrom<ApplicationRow> for Application {
@@ -60,6 +62,12 @@ impl TryFrom<ApplicationRow> for Application {
             _ => HealthStatus::Unknown,
         };
 
+        let helm = row
+            .helm
+            .map(serde_json::from_value::<HelmSource>)
+            .transpose()
+            .map_err(|e| DbError::InvalidData(format!("application {} helm: {}", row.id, e)))?;
+
         Ok(Application {
             id: row.id,
             tenant_id: row.tenant_id,
@@ -77,6 +85,8 @@ impl TryFrom<ApplicationRow> for Application {
             health_status,
             synced_revision: row.synced_revision,
             last_synced_at: row.last_synced_at,
+            helm,
+            rendered_digest: row.rendered_digest,
             created_at: row.created_at,
             updated_at: row.updated_at,
         })
@@ -99,6 +109,7 @@ pub struct ApplicationSyncRow {
     pub started_at: Option<DateTime<Utc>>,
     pub finished_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
+    pub rendered_digest: Option<String>,
 }
 
 impl TryFrom<ApplicationSyncRow> for ApplicationSync {
@@ -130,6 +141,7 @@ impl TryFrom<ApplicationSyncRow> for ApplicationSync {
             resources_updated: row.resources_updated,
             resources_deleted: row.resources_deleted,
             error_message: row.error_message,
+            rendered_digest: row.rendered_digest,
             started_at: row.started_at,
             finished_at: row.finished_at,
             created_at: row.created_at,
@@ -211,6 +223,7 @@ pub trait ApplicationRepo: Send + Sync {
         path: &str,
         target_namespace: &str,
         sync_policy: SyncPolicy,
+        helm: Option<&HelmSource>,
     ) -> DbResult<Application>;
 
     async fn get_application(&self, id: ResourceId) -> DbResult<Application>;
@@ -222,6 +235,8 @@ pub trait ApplicationRepo: Send + Sync {
         &self,
         repository_id: ResourceId,
     ) -> DbResult<Vec<Application>>;
+    /// Applications that have been synced at least once, across tenants.
+    async fn list_synced_applications(&self) -> DbResult<Vec<Application>>;
     async fn update_application_sync_status(
         &self,
         id: ResourceId,
@@ -229,6 +244,12 @@ pub trait ApplicationRepo: Send + Sync {
         health_status: HealthStatus,
         synced_revision: Option<&str>,
     ) -> DbResult<()>;
+    /// Set or clear the Helm chart an application renders.
+    async fn update_application_helm(
+        &self,
+        id: ResourceId,
+        helm: Option<&HelmSource>,
+    ) -> DbResult<Application>;
     async fn delete_application(&self, id: ResourceId) -> DbResult<()>;
 
     // Application syncs
@@ -255,6 +276,9 @@ pub trait ApplicationRepo: Send + Sync {
         resources_deleted: i32,
         error_message: Option<&str>,
     ) -> DbResult<()>;
+    /// Record the digest of a sync's rendered manifests on the sync and,
+    /// as the last applied digest, on its application.
+    async fn set_rendered_digest(&self, sync_id: ResourceId, digest: &str) -> DbResult<()>;
 
     // Application resources
     async fn upsert_resource(
@@ -306,14 +330,19 @@ impl ApplicationRepo for PgApplicationRepo {
         path: &str,
         target_namespace: &str,
         sync_policy: SyncPolicy,
+        helm: Option<&HelmSource>,
     ) -> DbResult<Application> {
+        let helm = helm
+            .map(serde_json::to_value)
+            .transpose()
+            .map_err(|e| DbError::InvalidData(e.to_string()))?;
         let row = sqlx::query_as::<_, ApplicationRow>(
             r#"
             INSERT INTO applications (
                 id, tenant_id, repository_id, environment_id, name, description,
-                path, target_namespace, sync_policy, created_at, updated_at
+                path, target_namespace, sync_policy, helm, created_at, updated_at
             )
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, NOW(), NOW())
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, NOW(), NOW())
             RETURNING *
             "#,
         )
@@ -326,6 +355,7 @@ impl ApplicationRepo for PgApplicationRepo {
         .bind(path)
         .bind(target_namespace)
         .bind(sync_policy.to_string())
+        .bind(helm)
         .fetch_one(&self.pool)
         .await?;
 
@@ -370,6 +400,16 @@ impl ApplicationRepo for PgApplicationRepo {
         rows.into_iter().map(|r| r.try_into()).collect()
     }
 
+    async fn list_synced_applications(&self) -> DbResult<Vec<Application>> {
+        let rows = sqlx::query_as::<_, ApplicationRow>(
+            "SELECT * FROM applications WHERE rendered_digest IS NOT NULL ORDER BY name",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(|r| r.try_into()).collect()
+    }
+
     async fn update_application_sync_status(
         &self,
         id: ResourceId,
@@ -398,6 +438,27 @@ impl ApplicationRepo for PgApplicationRepo {
         Ok(())
     }
 
+    async fn update_application_helm(
+        &self,
+        id: ResourceId,
+        helm: Option<&HelmSource>,
+    ) -> DbResult<Application> {
+        let helm = helm
+            .map(serde_json::to_value)
+            .transpose()
+            .map_err(|e| DbError::InvalidData(e.to_string()))?;
+        let row = sqlx::query_as::<_, ApplicationRow>(
+            "UPDATE applications SET helm = $2, updated_at = NOW() WHERE id = $1 RETURNING *",
+        )
+        .bind(id.as_uuid())
+        .bind(helm)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("application {}", id)))?;
+
+        row.try_into()
+    }
+
     async fn delete_application(&self, id: ResourceId) -> DbResult<()> {
         sqlx::query("DELETE FROM applications WHERE id = $1")
             .bind(id.as_uuid())
@@ -506,6 +567,29 @@ impl ApplicationRepo for PgApplicationRepo {
         Ok(())
     }
 
+    async fn set_rendered_digest(&self, sync_id: ResourceId, digest: &str) -> DbResult<()> {
+        let result = sqlx::query(
+            r#"
+            WITH sync AS (
+                UPDATE application_syncs SET rendered_digest = $2
+                WHERE id = $1
+                RETURNING application_id
+            )
+            UPDATE applications SET rendered_digest = $2, updated_at = NOW()
+            WHERE id = (SELECT application_id FROM sync)
+            "#,
+        )
+        .bind(sync_id.as_uuid())
+        .bind(digest)
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!("application sync {}", sync_id)));
+        }
+
+        Ok(())
+    }
+
     async fn upsert_resource(
         &self,
         application_id: ResourceId,
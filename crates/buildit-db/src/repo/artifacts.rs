@@ -0,0 +1,330 @@
+//! Artifact repository for metadata about stage output artifacts.
+//!
+//! The bytes live in an artifact store; this table records what each
+//! artifact is so the UI can list and preview it without reading the store.
+//! Published HTML reports are stored file by file and indexed in
+//! `run_reports`.
+
+use async_trait::async_trait;
+use buildit_core::ResourceId;
+use buildit_core::artifact::{ArtifactKey, ArtifactKind, ArtifactRef, ArtifactVersion};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+
+use crate::{DbError, DbResult};
+
+/// An artifact record from the database.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ArtifactRecord {
+    pub id: uuid::Uuid,
+    pub pipeline_run_id: uuid::Uuid,
+    pub stage_name: String,
+    pub name: String,
+    pub kind: String,
+    pub content_type: String,
+    pub size_bytes: i64,
+    pub digest: String,
+    pub location: String,
+    pub created_at: DateTime<Utc>,
+    /// Pinned artifacts are never garbage collected.
+    pub pinned: bool,
+}
+
+impl ArtifactRecord {
+    pub fn kind(&self) -> ArtifactKind {
+        self.kind.parse().unwrap_or_default()
+    }
+
+    /// Reference for reading the artifact back from its store.
+    pub fn reference(&self) -> ArtifactRef {
+        ArtifactRef {
+            key: ArtifactKey {
+                run_id: ResourceId::from_uuid(self.pipeline_run_id),
+                stage: self.stage_name.clone(),
+                name: self.name.clone(),
+            },
+            location: self.location.clone(),
+            checksum: self.digest.clone(),
+            size: self.size_bytes as u64,
+            created_at: self.created_at,
+        }
+    }
+}
+
+/// One file of a published report.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportFile {
+    pub location: String,
+    pub size: u64,
+    pub digest: String,
+    pub content_type: String,
+}
+
+/// A published HTML report from the database.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ReportRecord {
+    pub id: uuid::Uuid,
+    pub pipeline_run_id: uuid::Uuid,
+    pub stage_name: String,
+    pub name: String,
+    pub entry: String,
+    pub files: serde_json::Value,
+    pub file_count: i32,
+    pub size_bytes: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ReportRecord {
+    /// Look up a file of the report by its path inside the report.
+    pub fn file(&self, path: &str) -> Option<ReportFile> {
+        self.files
+            .get(path)
+            .and_then(|f| serde_json::from_value(f.clone()).ok())
+    }
+
+    /// Reference for reading one of the report's files from the store.
+    pub fn reference(&self, path: &str, file: &ReportFile) -> ArtifactRef {
+        ArtifactRef {
+            key: ArtifactKey {
+                run_id: ResourceId::from_uuid(self.pipeline_run_id),
+                stage: self.stage_name.clone(),
+                name: format!("{}/{}", self.name, path),
+            },
+            location: file.location.clone(),
+            checksum: file.digest.clone(),
+            size: file.size,
+            created_at: self.created_at,
+        }
+    }
+}
+
+#[async_trait]
+pub trait ArtifactRepo: Send + Sync {
+    /// Record a stored artifact, replacing an earlier upload with the same name.
+    async fn record_artifact(
+        &self,
+        reference: &ArtifactRef,
+        kind: ArtifactKind,
+        content_type: &str,
+    ) -> DbResult<ArtifactRecord>;
+
+    /// All artifacts of a run, by stage then name.
+    async fn list_artifacts(&self, run_id: ResourceId) -> DbResult<Vec<ArtifactRecord>>;
+
+    /// Get an artifact of a run.
+    async fn get_artifact(&self, run_id: ResourceId, id: ResourceId) -> DbResult<ArtifactRecord>;
+
+    /// The artifact named `name` of the pipeline's run `version` picks:
+    /// its latest successful or latest run with one, or a numbered run.
+    async fn find_pipeline_artifact(
+        &self,
+        pipeline_id: ResourceId,
+        name: &str,
+        version: ArtifactVersion,
+    ) -> DbResult<Option<ArtifactRecord>>;
+
+    /// Pin or unpin an artifact of a run.
+    async fn set_pinned(
+        &self,
+        run_id: ResourceId,
+        id: ResourceId,
+        pinned: bool,
+    ) -> DbResult<ArtifactRecord>;
+
+    /// Record a published report, replacing an earlier one with the same name.
+    async fn record_report(
+        &self,
+        run_id: ResourceId,
+        stage: &str,
+        name: &str,
+        entry: &str,
+        files: &BTreeMap<String, ReportFile>,
+    ) -> DbResult<ReportRecord>;
+
+    /// All reports of a run, by stage then name.
+    async fn list_reports(&self, run_id: ResourceId) -> DbResult<Vec<ReportRecord>>;
+
+    /// Get a report by ID.
+    async fn get_report(&self, id: ResourceId) -> DbResult<ReportRecord>;
+}
+
+/// PostgreSQL implementation of ArtifactRepo.
+pub struct PgArtifactRepo {
+    pool: PgPool,
+}
+
+impl PgArtifactRepo {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ArtifactRepo for PgArtifactRepo {
+    async fn record_artifact(
+        &self,
+        reference: &ArtifactRef,
+        kind: ArtifactKind,
+        content_type: &str,
+    ) -> DbResult<ArtifactRecord> {
+        let record = sqlx::query_as::<_, ArtifactRecord>(
+            r#"
+            INSERT INTO run_artifacts (
+                id, pipeline_run_id, stage_name, name, kind, content_type,
+                size_bytes, digest, location, created_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            ON CONFLICT (pipeline_run_id, stage_name, name) DO UPDATE SET
+                kind = EXCLUDED.kind,
+                content_type = EXCLUDED.content_type,
+                size_bytes = EXCLUDED.size_bytes,
+                digest = EXCLUDED.digest,
+                location = EXCLUDED.location,
+                created_at = EXCLUDED.created_at
+            RETURNING *
+            "#,
+        )
+        .bind(uuid::Uuid::now_v7())
+        .bind(reference.key.run_id.as_uuid())
+        .bind(&reference.key.stage)
+        .bind(&reference.key.name)
+        .bind(kind.as_str())
+        .bind(content_type)
+        .bind(reference.size as i64)
+        .bind(&reference.checksum)
+        .bind(&reference.location)
+        .bind(reference.created_at)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn list_artifacts(&self, run_id: ResourceId) -> DbResult<Vec<ArtifactRecord>> {
+        let records = sqlx::query_as::<_, ArtifactRecord>(
+            "SELECT * FROM run_artifacts WHERE pipeline_run_id = $1 ORDER BY stage_name, name",
+        )
+        .bind(run_id.as_uuid())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn get_artifact(&self, run_id: ResourceId, id: ResourceId) -> DbResult<ArtifactRecord> {
+        sqlx::query_as::<_, ArtifactRecord>(
+            "SELECT * FROM run_artifacts WHERE pipeline_run_id = $1 AND id = $2",
+        )
+        .bind(run_id.as_uuid())
+        .bind(id.as_uuid())
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("artifact {}", id)))
+    }
+
+    async fn find_pipeline_artifact(
+        &self,
+        pipeline_id: ResourceId,
+        name: &str,
+        version: ArtifactVersion,
+    ) -> DbResult<Option<ArtifactRecord>> {
+        let (status, number) = match version {
+            ArtifactVersion::LatestSuccessful => (Some("succeeded"), None),
+            ArtifactVersion::Latest => (None, None),
+            ArtifactVersion::Run(number) => (None, Some(number)),
+        };
+        let record = sqlx::query_as::<_, ArtifactRecord>(
+            r#"
+            SELECT a.* FROM run_artifacts a
+            JOIN pipeline_runs r ON r.id = a.pipeline_run_id
+            WHERE r.pipeline_id = $1 AND a.name = $2
+              AND ($3::TEXT IS NULL OR r.status = $3)
+              AND ($4::BIGINT IS NULL OR r.number = $4)
+            ORDER BY r.number DESC, a.created_at DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(pipeline_id.as_uuid())
+        .bind(name)
+        .bind(status)
+        .bind(number)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn set_pinned(
+        &self,
+        run_id: ResourceId,
+        id: ResourceId,
+        pinned: bool,
+    ) -> DbResult<ArtifactRecord> {
+        sqlx::query_as::<_, ArtifactRecord>(
+            "UPDATE run_artifacts SET pinned = $3 WHERE pipeline_run_id = $1 AND id = $2 RETURNING *",
+        )
+        .bind(run_id.as_uuid())
+        .bind(id.as_uuid())
+        .bind(pinned)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("artifact {}", id)))
+    }
+
+    async fn record_report(
+        &self,
+        run_id: ResourceId,
+        stage: &str,
+        name: &str,
+        entry: &str,
+        files: &BTreeMap<String, ReportFile>,
+    ) -> DbResult<ReportRecord> {
+        let size_bytes: u64 = files.values().map(|f| f.size).sum();
+        let files_json =
+            serde_json::to_value(files).map_err(|e| DbError::InvalidData(e.to_string()))?;
+        let record = sqlx::query_as::<_, ReportRecord>(
+            r#"
+            INSERT INTO run_reports (
+                id, pipeline_run_id, stage_name, name, entry, files,
+                file_count, size_bytes, created_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, NOW())
+            ON CONFLICT (pipeline_run_id, stage_name, name) DO UPDATE SET
+                entry = EXCLUDED.entry,
+                files = EXCLUDED.files,
+                file_count = EXCLUDED.file_count,
+                size_bytes = EXCLUDED.size_bytes,
+                created_at = EXCLUDED.created_at
+            RETURNING *
+            "#,
+        )
+        .bind(uuid::Uuid::now_v7())
+        .bind(run_id.as_uuid())
+        .bind(stage)
+        .bind(name)
+        .bind(entry)
+        .bind(files_json)
+        .bind(files.len() as i32)
+        .bind(size_bytes as i64)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn list_reports(&self, run_id: ResourceId) -> DbResult<Vec<ReportRecord>> {
+        let records = sqlx::query_as::<_, ReportRecord>(
+            "SELECT * FROM run_reports WHERE pipeline_run_id = $1 ORDER BY stage_name, name",
+        )
+        .bind(run_id.as_uuid())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn get_report(&self, id: ResourceId) -> DbResult<ReportRecord> {
+        sqlx::query_as::<_, ReportRecord>("SELECT * FROM run_reports WHERE id = $1")
+            .bind(id.as_uuid())
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| DbError::NotFound(format!("report {}", id)))
+    }
+}
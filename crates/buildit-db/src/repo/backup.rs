@@ -0,0 +1,71 @@
+//! Consistent reads for backups.
+//!
+//! A [`BackupSnapshot`] is a read-only, repeatable-read transaction whose
+//! snapshot is exported, so `pg_dump --snapshot` and the reads made here see
+//! the database at the same instant.
+
+use sqlx::{PgPool, Postgres, Transaction};
+
+use crate::DbResult;
+use crate::repo::artifacts::ArtifactRecord;
+use crate::repo::stack::StackStateRow;
+
+/// A database snapshot held open while a backup is taken.
+pub struct BackupSnapshot {
+    tx: Transaction<'static, Postgres>,
+    id: String,
+}
+
+impl BackupSnapshot {
+    /// Open a snapshot. It stays usable by other sessions until
+    /// [`finish`](Self::finish) or drop.
+    pub async fn begin(pool: &PgPool) -> DbResult<Self> {
+        let mut tx = pool.begin().await?;
+        sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+            .execute(&mut *tx)
+            .await?;
+        let (id,): (String,) = sqlx::query_as("SELECT pg_export_snapshot()")
+            .fetch_one(&mut *tx)
+            .await?;
+        Ok(Self { tx, id })
+    }
+
+    /// ID to hand to `pg_dump --snapshot`.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Version of the last migration applied.
+    pub async fn schema_version(&mut self) -> DbResult<Option<i64>> {
+        let (version,): (Option<i64>,) =
+            sqlx::query_as("SELECT MAX(version) FROM _sqlx_migrations WHERE success")
+                .fetch_one(&mut *self.tx)
+                .await?;
+        Ok(version)
+    }
+
+    /// Every run's artifacts, oldest run first.
+    pub async fn artifacts(&mut self) -> DbResult<Vec<ArtifactRecord>> {
+        let records = sqlx::query_as::<_, ArtifactRecord>(
+            "SELECT * FROM run_artifacts ORDER BY pipeline_run_id, stage_name, name",
+        )
+        .fetch_all(&mut *self.tx)
+        .await?;
+        Ok(records)
+    }
+
+    /// The Terraform state of every stack that has one.
+    pub async fn stack_states(&mut self) -> DbResult<Vec<StackStateRow>> {
+        let rows =
+            sqlx::query_as::<_, StackStateRow>("SELECT * FROM stack_state ORDER BY stack_id")
+                .fetch_all(&mut *self.tx)
+                .await?;
+        Ok(rows)
+    }
+
+    /// Release the snapshot.
+    pub async fn finish(self) -> DbResult<()> {
+        self.tx.commit().await?;
+        Ok(())
+    }
+}
@@ -18,6 +18,12 @@ pub struct Target {
     pub status: String,
     pub region: Option<String>,
     pub config: serde_json::Value,
+    /// Encrypted credentials, never serialized.
+    #[serde(skip)]
+    pub credentials: Option<Vec<u8>>,
+    /// Why the last connection check failed or was degraded.
+    pub status_message: Option<String>,
+    pub checked_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -62,6 +68,25 @@ pub struct Service {
     pub config: serde_json::Value,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// Environments a release is promoted through, in order.
+    pub promotion_chain: Vec<uuid::Uuid>,
+    /// Team that owns the service and is paged when it fails.
+    pub owner_team: Option<String>,
+    pub repository_url: Option<String>,
+    pub runbook_url: Option<String>,
+    pub dashboard_urls: Vec<String>,
+    /// How critical the service is, from 1 (most) to 4 (least).
+    pub tier: Option<i32>,
+}
+
+/// Catalog entry of a service: who owns it and where to find out more.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServiceCatalog {
+    pub owner_team: Option<String>,
+    pub repository_url: Option<String>,
+    pub runbook_url: Option<String>,
+    pub dashboard_urls: Vec<String>,
+    pub tier: Option<i32>,
 }
 
 /// Service with environment info.
@@ -75,6 +100,15 @@ pub struct ServiceWithEnvs {
     pub last_deployed_at: Option<DateTime<Utc>>,
 }
 
+/// A service deployed to an environment, with both names joined.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ServicePlacement {
+    pub service_id: uuid::Uuid,
+    pub service_name: String,
+    pub environment_id: uuid::Uuid,
+    pub environment_name: String,
+}
+
 /// A deployment record.
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Deployment {
@@ -90,6 +124,77 @@ pub struct Deployment {
     pub finished_at: Option<DateTime<Utc>>,
     pub config: serde_json::Value,
     pub created_at: DateTime<Utc>,
+    pub spec_version: Option<i32>,
+    pub release_notes: Option<serde_json::Value>,
+    /// The deployment in the previous environment this one was promoted from.
+    pub promoted_from: Option<uuid::Uuid>,
+    /// Who deployed through a freeze window, and why.
+    pub freeze_override: Option<serde_json::Value>,
+    /// The branch the deployment comes from, when known.
+    pub branch: Option<String>,
+}
+
+/// A period during which deployments to an environment are held or
+/// rejected: a fixed range, or a cron expression and a duration.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct FreezeWindow {
+    pub id: uuid::Uuid,
+    pub tenant_id: uuid::Uuid,
+    pub environment_id: uuid::Uuid,
+    pub name: String,
+    pub reason: Option<String>,
+    pub starts_at: Option<DateTime<Utc>>,
+    pub ends_at: Option<DateTime<Utc>>,
+    pub cron: Option<String>,
+    pub duration_minutes: Option<i32>,
+    /// `queue` to hold deployments until the window ends, `reject` to
+    /// refuse them.
+    pub policy: String,
+    pub created_by: Option<uuid::Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Rules deployments to an environment must pass before rolling out.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct EnvironmentProtection {
+    pub environment_id: uuid::Uuid,
+    pub tenant_id: uuid::Uuid,
+    /// Users who may review deployments.
+    pub reviewers: Vec<uuid::Uuid>,
+    /// Roles whose holders, or holders of a higher role, may review.
+    pub reviewer_roles: Vec<String>,
+    /// Approvals a deployment needs before it rolls out; 0 for none.
+    pub required_approvals: i32,
+    /// Minutes to wait after approval before rolling out.
+    pub wait_minutes: i32,
+    /// Glob patterns of the branches deployments must come from; empty
+    /// allows any.
+    pub branches: Vec<String>,
+    pub updated_by: Option<uuid::Uuid>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// An approval or rejection of a deployment awaiting review.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct DeploymentReview {
+    pub id: uuid::Uuid,
+    pub deployment_id: uuid::Uuid,
+    pub user_id: Option<uuid::Uuid>,
+    pub api_key_id: Option<uuid::Uuid>,
+    pub approved: bool,
+    pub comment: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A recorded version of a service's deployment spec.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ServiceSpecVersion {
+    pub id: uuid::Uuid,
+    pub service_id: uuid::Uuid,
+    pub version: i32,
+    pub spec: serde_json::Value,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
 }
 
 /// Deployment with service and environment names joined.
@@ -118,9 +223,29 @@ pub trait DeploymentRepo: Send + Sync {
         target_type: &str,
         region: Option<&str>,
         config: serde_json::Value,
+        credentials: Option<Vec<u8>>,
     ) -> DbResult<Target>;
     async fn update_target_config(&self, id: ResourceId, config: serde_json::Value)
     -> DbResult<()>;
+    /// Rename a target or change its region, config or credentials; `None`
+    /// credentials keep the stored ones.
+    async fn update_target(
+        &self,
+        id: ResourceId,
+        name: &str,
+        region: Option<&str>,
+        config: serde_json::Value,
+        credentials: Option<Vec<u8>>,
+    ) -> DbResult<Target>;
+    /// Record the outcome of a connection check.
+    async fn update_target_status(
+        &self,
+        id: ResourceId,
+        status: &str,
+        message: Option<&str>,
+    ) -> DbResult<Target>;
+    /// Targets of all tenants, least recently checked first.
+    async fn list_targets_to_check(&self, limit: i64) -> DbResult<Vec<Target>>;
     async fn delete_target(&self, id: ResourceId) -> DbResult<()>;
 
     // Environments
@@ -141,6 +266,13 @@ pub trait DeploymentRepo: Send + Sync {
         name: &str,
         config: serde_json::Value,
     ) -> DbResult<Environment>;
+    /// Move an environment to another target or replace its config.
+    async fn update_environment(
+        &self,
+        id: ResourceId,
+        target_id: ResourceId,
+        config: serde_json::Value,
+    ) -> DbResult<Environment>;
     async fn update_environment_from_stack(
         &self,
         id: ResourceId,
@@ -150,14 +282,75 @@ pub trait DeploymentRepo: Send + Sync {
     async fn count_services_in_environment(&self, env_id: ResourceId) -> DbResult<i64>;
     async fn delete_environment(&self, id: ResourceId) -> DbResult<()>;
 
+    // Freeze windows
+    /// A tenant's freeze windows, optionally only one environment's.
+    async fn list_freeze_windows(
+        &self,
+        tenant_id: ResourceId,
+        environment_id: Option<ResourceId>,
+    ) -> DbResult<Vec<FreezeWindow>>;
+    async fn get_freeze_window(&self, id: ResourceId) -> DbResult<FreezeWindow>;
+    async fn create_freeze_window(&self, window: &FreezeWindow) -> DbResult<FreezeWindow>;
+    async fn delete_freeze_window(&self, id: ResourceId) -> DbResult<()>;
+
+    // Protection rules
+    async fn get_environment_protection(
+        &self,
+        environment_id: ResourceId,
+    ) -> DbResult<Option<EnvironmentProtection>>;
+    /// Create or replace an environment's protection rules.
+    async fn set_environment_protection(
+        &self,
+        protection: &EnvironmentProtection,
+    ) -> DbResult<EnvironmentProtection>;
+    async fn delete_environment_protection(&self, environment_id: ResourceId) -> DbResult<()>;
+
     // Services
     async fn list_services(&self, tenant_id: ResourceId) -> DbResult<Vec<Service>>;
+    /// Services deployed from raw manifests in the given repository.
+    async fn list_manifest_services(&self, repository_id: ResourceId) -> DbResult<Vec<Service>>;
     async fn get_service(&self, id: ResourceId) -> DbResult<Service>;
+    /// Services built by a pipeline.
+    async fn list_services_by_pipeline(&self, pipeline_id: ResourceId) -> DbResult<Vec<Service>>;
+    /// Services running in environments provisioned by a stack.
+    async fn list_services_for_stack(
+        &self,
+        stack_id: ResourceId,
+    ) -> DbResult<Vec<ServicePlacement>>;
     async fn get_service_environments(&self, service_id: ResourceId) -> DbResult<Vec<String>>;
     async fn get_service_last_deploy(
         &self,
         service_id: ResourceId,
     ) -> DbResult<Option<DateTime<Utc>>>;
+    async fn set_promotion_chain(
+        &self,
+        service_id: ResourceId,
+        chain: &[uuid::Uuid],
+    ) -> DbResult<Service>;
+    async fn set_service_catalog(
+        &self,
+        service_id: ResourceId,
+        catalog: &ServiceCatalog,
+    ) -> DbResult<Service>;
+
+    // Service spec versions
+    /// Record a new spec version and make it the service's current spec.
+    /// Returns the latest version unchanged if the spec is identical.
+    async fn update_service_spec(
+        &self,
+        service_id: ResourceId,
+        spec: serde_json::Value,
+        created_by: Option<&str>,
+    ) -> DbResult<ServiceSpecVersion>;
+    async fn list_service_spec_versions(
+        &self,
+        service_id: ResourceId,
+    ) -> DbResult<Vec<ServiceSpecVersion>>;
+    async fn get_service_spec_version(
+        &self,
+        service_id: ResourceId,
+        version: i32,
+    ) -> DbResult<ServiceSpecVersion>;
 
     // Deployments
     async fn list_deployments(
@@ -166,6 +359,57 @@ pub trait DeploymentRepo: Send + Sync {
         limit: i64,
     ) -> DbResult<Vec<DeploymentWithDetails>>;
     async fn get_deployment(&self, id: ResourceId) -> DbResult<Deployment>;
+    /// Most recent deployments of a service, optionally for one environment.
+    async fn list_service_deployments(
+        &self,
+        service_id: ResourceId,
+        environment_id: Option<ResourceId>,
+        limit: i64,
+    ) -> DbResult<Vec<Deployment>>;
+    async fn create_deployment(
+        &self,
+        tenant_id: ResourceId,
+        service_id: ResourceId,
+        environment_id: ResourceId,
+        version: &str,
+        spec_version: Option<i32>,
+        config: serde_json::Value,
+    ) -> DbResult<Deployment>;
+    /// Deploy exactly what `source` deployed to another environment,
+    /// recording where it was promoted from.
+    async fn create_promotion(
+        &self,
+        source: &Deployment,
+        environment_id: ResourceId,
+        status: &str,
+    ) -> DbResult<Deployment>;
+    async fn update_deployment_status(&self, id: ResourceId, status: &str) -> DbResult<()>;
+    async fn set_deployment_branch(&self, id: ResourceId, branch: &str) -> DbResult<()>;
+    /// A tenant's deployments waiting for review, oldest first.
+    async fn list_awaiting_approval(&self, tenant_id: ResourceId) -> DbResult<Vec<Deployment>>;
+    async fn list_deployment_reviews(
+        &self,
+        deployment_id: ResourceId,
+    ) -> DbResult<Vec<DeploymentReview>>;
+    async fn create_deployment_review(
+        &self,
+        review: &DeploymentReview,
+    ) -> DbResult<DeploymentReview>;
+    /// Record who deployed through a freeze window, and why.
+    async fn set_freeze_override(
+        &self,
+        id: ResourceId,
+        freeze_override: serde_json::Value,
+    ) -> DbResult<()>;
+    /// Latest successful deployment of a service to an environment created
+    /// before `before`.
+    async fn get_previous_deployment(
+        &self,
+        service_id: ResourceId,
+        environment_id: ResourceId,
+        before: DateTime<Utc>,
+    ) -> DbResult<Option<Deployment>>;
+    async fn set_release_notes(&self, id: ResourceId, notes: serde_json::Value) -> DbResult<()>;
 }
 
 /// PostgreSQL implementation of DeploymentRepo.
@@ -206,11 +450,12 @@ impl DeploymentRepo for PgDeploymentRepo {
         target_type: &str,
         region: Option<&str>,
         config: serde_json::Value,
+        credentials: Option<Vec<u8>>,
     ) -> DbResult<Target> {
         let target = sqlx::query_as::<_, Target>(
             r#"
-            INSERT INTO targets (id, tenant_id, name, target_type, status, region, config, created_at, updated_at)
-            VALUES ($1, $2, $3, $4, 'connected', $5, $6, NOW(), NOW())
+            INSERT INTO targets (id, tenant_id, name, target_type, status, region, config, credentials, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, 'unknown', $5, $6, $7, NOW(), NOW())
             RETURNING *
             "#,
         )
@@ -220,6 +465,7 @@ impl DeploymentRepo for PgDeploymentRepo {
         .bind(target_type)
         .bind(region)
         .bind(config)
+        .bind(credentials)
         .fetch_one(&self.pool)
         .await?;
         Ok(target)
@@ -238,6 +484,67 @@ impl DeploymentRepo for PgDeploymentRepo {
         Ok(())
     }
 
+    async fn update_target(
+        &self,
+        id: ResourceId,
+        name: &str,
+        region: Option<&str>,
+        config: serde_json::Value,
+        credentials: Option<Vec<u8>>,
+    ) -> DbResult<Target> {
+        let target = sqlx::query_as::<_, Target>(
+            r#"
+            UPDATE targets
+            SET name = $2, region = $3, config = $4,
+                credentials = COALESCE($5, credentials), updated_at = NOW()
+            WHERE id = $1
+            RETURNING *
+            "#,
+        )
+        .bind(id.as_uuid())
+        .bind(name)
+        .bind(region)
+        .bind(config)
+        .bind(credentials)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("target {}", id)))?;
+        Ok(target)
+    }
+
+    async fn update_target_status(
+        &self,
+        id: ResourceId,
+        status: &str,
+        message: Option<&str>,
+    ) -> DbResult<Target> {
+        let target = sqlx::query_as::<_, Target>(
+            r#"
+            UPDATE targets
+            SET status = $2, status_message = $3, checked_at = NOW()
+            WHERE id = $1
+            RETURNING *
+            "#,
+        )
+        .bind(id.as_uuid())
+        .bind(status)
+        .bind(message)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("target {}", id)))?;
+        Ok(target)
+    }
+
+    async fn list_targets_to_check(&self, limit: i64) -> DbResult<Vec<Target>> {
+        let targets = sqlx::query_as::<_, Target>(
+            "SELECT * FROM targets ORDER BY checked_at NULLS FIRST LIMIT $1",
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(targets)
+    }
+
     async fn delete_target(&self, id: ResourceId) -> DbResult<()> {
         sqlx::query("DELETE FROM targets WHERE id = $1")
             .bind(id.as_uuid())
@@ -313,6 +620,29 @@ impl DeploymentRepo for PgDeploymentRepo {
         Ok(env)
     }
 
+    async fn update_environment(
+        &self,
+        id: ResourceId,
+        target_id: ResourceId,
+        config: serde_json::Value,
+    ) -> DbResult<Environment> {
+        let env = sqlx::query_as::<_, Environment>(
+            r#"
+            UPDATE environments
+            SET target_id = $2, config = $3, updated_at = NOW()
+            WHERE id = $1
+            RETURNING *
+            "#,
+        )
+        .bind(id.as_uuid())
+        .bind(target_id.as_uuid())
+        .bind(config)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("environment {}", id)))?;
+        Ok(env)
+    }
+
     async fn update_environment_from_stack(
         &self,
         id: ResourceId,
@@ -347,6 +677,125 @@ impl DeploymentRepo for PgDeploymentRepo {
         Ok(())
     }
 
+    async fn list_freeze_windows(
+        &self,
+        tenant_id: ResourceId,
+        environment_id: Option<ResourceId>,
+    ) -> DbResult<Vec<FreezeWindow>> {
+        let windows = sqlx::query_as::<_, FreezeWindow>(
+            r#"
+            SELECT * FROM freeze_windows
+            WHERE tenant_id = $1 AND ($2::uuid IS NULL OR environment_id = $2)
+            ORDER BY created_at
+            "#,
+        )
+        .bind(tenant_id.as_uuid())
+        .bind(environment_id.map(|id| *id.as_uuid()))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(windows)
+    }
+
+    async fn get_freeze_window(&self, id: ResourceId) -> DbResult<FreezeWindow> {
+        let window =
+            sqlx::query_as::<_, FreezeWindow>("SELECT * FROM freeze_windows WHERE id = $1")
+                .bind(id.as_uuid())
+                .fetch_optional(&self.pool)
+                .await?
+                .ok_or_else(|| DbError::NotFound(format!("freeze window {}", id)))?;
+        Ok(window)
+    }
+
+    async fn create_freeze_window(&self, window: &FreezeWindow) -> DbResult<FreezeWindow> {
+        let window = sqlx::query_as::<_, FreezeWindow>(
+            r#"
+            INSERT INTO freeze_windows
+                (id, tenant_id, environment_id, name, reason, starts_at, ends_at, cron,
+                 duration_minutes, policy, created_by, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            RETURNING *
+            "#,
+        )
+        .bind(window.id)
+        .bind(window.tenant_id)
+        .bind(window.environment_id)
+        .bind(&window.name)
+        .bind(&window.reason)
+        .bind(window.starts_at)
+        .bind(window.ends_at)
+        .bind(&window.cron)
+        .bind(window.duration_minutes)
+        .bind(&window.policy)
+        .bind(window.created_by)
+        .bind(window.created_at)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(window)
+    }
+
+    async fn delete_freeze_window(&self, id: ResourceId) -> DbResult<()> {
+        sqlx::query("DELETE FROM freeze_windows WHERE id = $1")
+            .bind(id.as_uuid())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_environment_protection(
+        &self,
+        environment_id: ResourceId,
+    ) -> DbResult<Option<EnvironmentProtection>> {
+        let protection = sqlx::query_as::<_, EnvironmentProtection>(
+            "SELECT * FROM environment_protection WHERE environment_id = $1",
+        )
+        .bind(environment_id.as_uuid())
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(protection)
+    }
+
+    async fn set_environment_protection(
+        &self,
+        protection: &EnvironmentProtection,
+    ) -> DbResult<EnvironmentProtection> {
+        let protection = sqlx::query_as::<_, EnvironmentProtection>(
+            r#"
+            INSERT INTO environment_protection
+                (environment_id, tenant_id, reviewers, reviewer_roles, required_approvals,
+                 wait_minutes, branches, updated_by, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, NOW())
+            ON CONFLICT (environment_id) DO UPDATE SET
+                reviewers = EXCLUDED.reviewers,
+                reviewer_roles = EXCLUDED.reviewer_roles,
+                required_approvals = EXCLUDED.required_approvals,
+                wait_minutes = EXCLUDED.wait_minutes,
+                branches = EXCLUDED.branches,
+                updated_by = EXCLUDED.updated_by,
+                updated_at = NOW()
+            RETURNING *
+            "#,
+        )
+        .bind(protection.environment_id)
+        .bind(protection.tenant_id)
+        .bind(&protection.reviewers)
+        .bind(&protection.reviewer_roles)
+        .bind(protection.required_approvals)
+        .bind(protection.wait_minutes)
+        .bind(&protection.branches)
+        .bind(protection.updated_by)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(protection)
+    }
+
+    async fn delete_environment_protection(&self, environment_id: ResourceId) -> DbResult<()> {
+        sqlx::query("DELETE FROM environment_protection WHERE environment_id = $1")
+            .bind(environment_id.as_uuid())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
     async fn list_services(&self, tenant_id: ResourceId) -> DbResult<Vec<Service>> {
         let services = sqlx::query_as::<_, Service>(
             "SELECT * FROM services WHERE tenant_id = $1 ORDER BY name",
@@ -357,6 +806,21 @@ impl DeploymentRepo for PgDeploymentRepo {
         Ok(services)
     }
 
+    async fn list_manifest_services(&self, repository_id: ResourceId) -> DbResult<Vec<Service>> {
+        let services = sqlx::query_as::<_, Service>(
+            r#"
+            SELECT * FROM services
+            WHERE config->'source'->>'type' IN ('manifests', 'kustomize')
+              AND config->'source'->>'repository_id' = $1::text
+            ORDER BY name
+            "#,
+        )
+        .bind(repository_id.as_uuid())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(services)
+    }
+
     async fn get_service(&self, id: ResourceId) -> DbResult<Service> {
         let service = sqlx::query_as::<_, Service>("SELECT * FROM services WHERE id = $1")
             .bind(id.as_uuid())
@@ -366,6 +830,37 @@ impl DeploymentRepo for PgDeploymentRepo {
         Ok(service)
     }
 
+    async fn list_services_by_pipeline(&self, pipeline_id: ResourceId) -> DbResult<Vec<Service>> {
+        let services = sqlx::query_as::<_, Service>(
+            "SELECT * FROM services WHERE pipeline_id = $1 ORDER BY name",
+        )
+        .bind(pipeline_id.as_uuid())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(services)
+    }
+
+    async fn list_services_for_stack(
+        &self,
+        stack_id: ResourceId,
+    ) -> DbResult<Vec<ServicePlacement>> {
+        let placements = sqlx::query_as::<_, ServicePlacement>(
+            r#"
+            SELECT s.id AS service_id, s.name AS service_name,
+                   e.id AS environment_id, e.name AS environment_name
+            FROM service_environments se
+            JOIN services s ON se.service_id = s.id
+            JOIN environments e ON se.environment_id = e.id
+            WHERE e.stack_id = $1
+            ORDER BY e.name, s.name
+            "#,
+        )
+        .bind(stack_id.as_uuid())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(placements)
+    }
+
     async fn get_service_environments(&self, service_id: ResourceId) -> DbResult<Vec<String>> {
         let envs: Vec<(String,)> = sqlx::query_as(
             r#"
@@ -399,6 +894,139 @@ impl DeploymentRepo for PgDeploymentRepo {
         Ok(result.map(|(dt,)| dt))
     }
 
+    async fn set_promotion_chain(
+        &self,
+        service_id: ResourceId,
+        chain: &[uuid::Uuid],
+    ) -> DbResult<Service> {
+        let service = sqlx::query_as::<_, Service>(
+            "UPDATE services SET promotion_chain = $2, updated_at = NOW() WHERE id = $1 RETURNING *",
+        )
+        .bind(service_id.as_uuid())
+        .bind(chain)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("service {}", service_id)))?;
+        Ok(service)
+    }
+
+    async fn set_service_catalog(
+        &self,
+        service_id: ResourceId,
+        catalog: &ServiceCatalog,
+    ) -> DbResult<Service> {
+        let service = sqlx::query_as::<_, Service>(
+            r#"
+            UPDATE services
+            SET owner_team = $2, repository_url = $3, runbook_url = $4, dashboard_urls = $5,
+                tier = $6, updated_at = NOW()
+            WHERE id = $1
+            RETURNING *
+            "#,
+        )
+        .bind(service_id.as_uuid())
+        .bind(&catalog.owner_team)
+        .bind(&catalog.repository_url)
+        .bind(&catalog.runbook_url)
+        .bind(&catalog.dashboard_urls)
+        .bind(catalog.tier)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("service {}", service_id)))?;
+        Ok(service)
+    }
+
+    async fn update_service_spec(
+        &self,
+        service_id: ResourceId,
+        spec: serde_json::Value,
+        created_by: Option<&str>,
+    ) -> DbResult<ServiceSpecVersion> {
+        let mut tx = self.pool.begin().await?;
+
+        // Lock the service so concurrent updates get sequential versions
+        sqlx::query("SELECT id FROM services WHERE id = $1 FOR UPDATE")
+            .bind(service_id.as_uuid())
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| DbError::NotFound(format!("service {}", service_id)))?;
+
+        let latest = sqlx::query_as::<_, ServiceSpecVersion>(
+            "SELECT * FROM service_spec_versions WHERE service_id = $1 ORDER BY version DESC LIMIT 1",
+        )
+        .bind(service_id.as_uuid())
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if let Some(latest) = latest.as_ref().filter(|v| v.spec == spec) {
+            return Ok(latest.clone());
+        }
+
+        let version = sqlx::query_as::<_, ServiceSpecVersion>(
+            r#"
+            INSERT INTO service_spec_versions (id, service_id, version, spec, created_by, created_at)
+            VALUES ($1, $2, $3, $4, $5, NOW())
+            RETURNING *
+            "#,
+        )
+        .bind(uuid::Uuid::now_v7())
+        .bind(service_id.as_uuid())
+        .bind(latest.map(|v| v.version + 1).unwrap_or(1))
+        .bind(&spec)
+        .bind(created_by)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            UPDATE services
+            SET image = COALESCE($2->>'image', image), config = $2, updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(service_id.as_uuid())
+        .bind(&spec)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(version)
+    }
+
+    async fn list_service_spec_versions(
+        &self,
+        service_id: ResourceId,
+    ) -> DbResult<Vec<ServiceSpecVersion>> {
+        let versions = sqlx::query_as::<_, ServiceSpecVersion>(
+            "SELECT * FROM service_spec_versions WHERE service_id = $1 ORDER BY version DESC",
+        )
+        .bind(service_id.as_uuid())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(versions)
+    }
+
+    async fn get_service_spec_version(
+        &self,
+        service_id: ResourceId,
+        version: i32,
+    ) -> DbResult<ServiceSpecVersion> {
+        let spec = sqlx::query_as::<_, ServiceSpecVersion>(
+            "SELECT * FROM service_spec_versions WHERE service_id = $1 AND version = $2",
+        )
+        .bind(service_id.as_uuid())
+        .bind(version)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| {
+            DbError::NotFound(format!(
+                "spec version {} of service {}",
+                version, service_id
+            ))
+        })?;
+        Ok(spec)
+    }
+
     async fn list_deployments(
         &self,
         tenant_id: ResourceId,
@@ -431,4 +1059,211 @@ impl DeploymentRepo for PgDeploymentRepo {
             .ok_or_else(|| DbError::NotFound(format!("deployment {}", id)))?;
         Ok(deployment)
     }
+
+    async fn list_service_deployments(
+        &self,
+        service_id: ResourceId,
+        environment_id: Option<ResourceId>,
+        limit: i64,
+    ) -> DbResult<Vec<Deployment>> {
+        let deployments = sqlx::query_as::<_, Deployment>(
+            r#"
+            SELECT * FROM deployments
+            WHERE service_id = $1 AND ($2::uuid IS NULL OR environment_id = $2)
+            ORDER BY created_at DESC
+            LIMIT $3
+            "#,
+        )
+        .bind(service_id.as_uuid())
+        .bind(environment_id.map(|id| *id.as_uuid()))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(deployments)
+    }
+
+    async fn create_deployment(
+        &self,
+        tenant_id: ResourceId,
+        service_id: ResourceId,
+        environment_id: ResourceId,
+        version: &str,
+        spec_version: Option<i32>,
+        config: serde_json::Value,
+    ) -> DbResult<Deployment> {
+        let deployment = sqlx::query_as::<_, Deployment>(
+            r#"
+            INSERT INTO deployments (id, tenant_id, service_id, environment_id, version, status, spec_version, config, created_at)
+            VALUES ($1, $2, $3, $4, $5, 'pending', $6, $7, NOW())
+            RETURNING *
+            "#,
+        )
+        .bind(uuid::Uuid::now_v7())
+        .bind(tenant_id.as_uuid())
+        .bind(service_id.as_uuid())
+        .bind(environment_id.as_uuid())
+        .bind(version)
+        .bind(spec_version)
+        .bind(config)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(deployment)
+    }
+
+    async fn create_promotion(
+        &self,
+        source: &Deployment,
+        environment_id: ResourceId,
+        status: &str,
+    ) -> DbResult<Deployment> {
+        let deployment = sqlx::query_as::<_, Deployment>(
+            r#"
+            INSERT INTO deployments (id, tenant_id, service_id, environment_id, pipeline_run_id,
+                                     version, commit_sha, status, spec_version, config,
+                                     promoted_from, branch, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, NOW())
+            RETURNING *
+            "#,
+        )
+        .bind(uuid::Uuid::now_v7())
+        .bind(source.tenant_id)
+        .bind(source.service_id)
+        .bind(environment_id.as_uuid())
+        .bind(source.pipeline_run_id)
+        .bind(&source.version)
+        .bind(&source.commit_sha)
+        .bind(status)
+        .bind(source.spec_version)
+        .bind(&source.config)
+        .bind(source.id)
+        .bind(&source.branch)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(deployment)
+    }
+
+    async fn update_deployment_status(&self, id: ResourceId, status: &str) -> DbResult<()> {
+        sqlx::query(
+            r#"
+            UPDATE deployments
+            SET status = $2,
+                finished_at = CASE WHEN $2 IN ('succeeded', 'failed', 'cancelled', 'rolled_back')
+                                   THEN NOW() ELSE finished_at END
+            WHERE id = $1
+            "#,
+        )
+        .bind(id.as_uuid())
+        .bind(status)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn set_deployment_branch(&self, id: ResourceId, branch: &str) -> DbResult<()> {
+        sqlx::query("UPDATE deployments SET branch = $2 WHERE id = $1")
+            .bind(id.as_uuid())
+            .bind(branch)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn list_awaiting_approval(&self, tenant_id: ResourceId) -> DbResult<Vec<Deployment>> {
+        let deployments = sqlx::query_as::<_, Deployment>(
+            r#"
+            SELECT * FROM deployments
+            WHERE tenant_id = $1 AND status = 'awaiting_approval'
+            ORDER BY created_at
+            "#,
+        )
+        .bind(tenant_id.as_uuid())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(deployments)
+    }
+
+    async fn list_deployment_reviews(
+        &self,
+        deployment_id: ResourceId,
+    ) -> DbResult<Vec<DeploymentReview>> {
+        let reviews = sqlx::query_as::<_, DeploymentReview>(
+            "SELECT * FROM deployment_reviews WHERE deployment_id = $1 ORDER BY created_at",
+        )
+        .bind(deployment_id.as_uuid())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(reviews)
+    }
+
+    async fn create_deployment_review(
+        &self,
+        review: &DeploymentReview,
+    ) -> DbResult<DeploymentReview> {
+        let review = sqlx::query_as::<_, DeploymentReview>(
+            r#"
+            INSERT INTO deployment_reviews
+                (id, deployment_id, user_id, api_key_id, approved, comment, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING *
+            "#,
+        )
+        .bind(review.id)
+        .bind(review.deployment_id)
+        .bind(review.user_id)
+        .bind(review.api_key_id)
+        .bind(review.approved)
+        .bind(&review.comment)
+        .bind(review.created_at)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(review)
+    }
+
+    async fn set_freeze_override(
+        &self,
+        id: ResourceId,
+        freeze_override: serde_json::Value,
+    ) -> DbResult<()> {
+        sqlx::query("UPDATE deployments SET freeze_override = $2 WHERE id = $1")
+            .bind(id.as_uuid())
+            .bind(freeze_override)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_previous_deployment(
+        &self,
+        service_id: ResourceId,
+        environment_id: ResourceId,
+        before: DateTime<Utc>,
+    ) -> DbResult<Option<Deployment>> {
+        let deployment = sqlx::query_as::<_, Deployment>(
+            r#"
+            SELECT * FROM deployments
+            WHERE service_id = $1 AND environment_id = $2
+              AND status = 'succeeded' AND created_at < $3
+            ORDER BY created_at DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(service_id.as_uuid())
+        .bind(environment_id.as_uuid())
+        .bind(before)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(deployment)
+    }
+
+    async fn set_release_notes(&self, id: ResourceId, notes: serde_json::Value) -> DbResult<()> {
+        let result = sqlx::query("UPDATE deployments SET release_notes = $2 WHERE id = $1")
+            .bind(id.as_uuid())
+            .bind(notes)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!("deployment {}", id)));
+        }
+        Ok(())
+    }
 }
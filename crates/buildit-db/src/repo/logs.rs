@@ -2,6 +2,7 @@
 
 use async_trait::async_trait;
 use buildit_core::ResourceId;
+use buildit_core::executor::LogFrame;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
@@ -17,17 +18,48 @@ pub struct LogRecord {
     pub timestamp: DateTime<Utc>,
     pub stream: String,
     pub content: String,
+    /// Command of the stage the line was written by, counted from 1.
+    pub step: Option<i32>,
+    /// `step_start` or `step_end` on the lines framing a command.
+    pub frame: Option<String>,
+    /// Exit code of the command, on its `step_end` line.
+    pub exit_code: Option<i32>,
+}
+
+/// A run's logs in the log archive.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct LogArchiveRecord {
+    pub pipeline_run_id: uuid::Uuid,
+    pub tenant_id: uuid::Uuid,
+    /// Key of the compressed logs in the archive.
+    pub location: String,
+    pub lines: i64,
+    pub compressed_bytes: i64,
+    pub archived_at: DateTime<Utc>,
+}
+
+/// A finished run whose logs its tenant's retention says to archive or
+/// delete.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct RunLogsDue {
+    pub pipeline_run_id: uuid::Uuid,
+    pub tenant_id: uuid::Uuid,
+    /// Where the run's logs are archived, if they are.
+    pub archive_location: Option<String>,
 }
 
 #[async_trait]
 pub trait LogRepo: Send + Sync {
-    /// Append a log line for a stage.
+    /// Append a log line for a stage, with the step it belongs to and the
+    /// frame it marks.
     async fn append_log(
         &self,
         run_id: ResourceId,
         stage_name: &str,
         stream: &str,
         content: &str,
+        step: Option<u32>,
+        frame: Option<&LogFrame>,
     ) -> DbResult<()>;
 
     /// Append multiple log lines at once (batch insert).
@@ -56,6 +88,39 @@ pub trait LogRepo: Send + Sync {
         offset: i64,
         limit: i64,
     ) -> DbResult<Vec<LogRecord>>;
+
+    /// Count the log lines stored for a stage.
+    async fn count_logs_for_stage(&self, run_id: ResourceId, stage_name: &str) -> DbResult<i64>;
+
+    /// Finished runs with logs in the database that are older than their
+    /// tenant's `archive_after_days` but not yet due for deletion, oldest
+    /// first.
+    async fn runs_due_for_archive(&self, limit: i64) -> DbResult<Vec<RunLogsDue>>;
+
+    /// Finished runs with logs, in the database or archived, that are older
+    /// than their tenant's `delete_after_days`, oldest first.
+    async fn runs_due_for_deletion(&self, limit: i64) -> DbResult<Vec<RunLogsDue>>;
+
+    /// Record that a run's logs were archived at `location`, and remove
+    /// them from the database.
+    async fn archive_run(
+        &self,
+        run_id: ResourceId,
+        tenant_id: ResourceId,
+        location: &str,
+        lines: i64,
+        compressed_bytes: i64,
+    ) -> DbResult<()>;
+
+    /// Where a run's logs are archived, if they are.
+    async fn get_archive(&self, run_id: ResourceId) -> DbResult<Option<LogArchiveRecord>>;
+
+    /// Delete a run's logs and its archive record. Returns the log lines
+    /// deleted from the database.
+    async fn delete_run_logs(&self, run_id: ResourceId) -> DbResult<u64>;
+
+    /// Runs archived and their compressed size in bytes, over all tenants.
+    async fn archive_totals(&self) -> DbResult<(i64, i64)>;
 }
 
 /// PostgreSQL implementation of LogRepo.
@@ -77,11 +142,17 @@ impl LogRepo for PgLogRepo {
         stage_name: &str,
         stream: &str,
         content: &str,
+        step: Option<u32>,
+        frame: Option<&LogFrame>,
     ) -> DbResult<()> {
+        let exit_code = match frame {
+            Some(LogFrame::StepFinished { exit_code, .. }) => Some(*exit_code),
+            _ => None,
+        };
         sqlx::query(
             r#"
-            INSERT INTO logs (id, pipeline_run_id, stage_name, stream, content, timestamp)
-            VALUES ($1, $2, $3, $4, $5, NOW())
+            INSERT INTO logs (id, pipeline_run_id, stage_name, stream, content, step, frame, exit_code, timestamp)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, NOW())
             "#,
         )
         .bind(uuid::Uuid::now_v7())
@@ -89,6 +160,9 @@ impl LogRepo for PgLogRepo {
         .bind(stage_name)
         .bind(stream)
         .bind(content)
+        .bind(step.map(|s| s as i32))
+        .bind(frame.map(LogFrame::kind))
+        .bind(exit_code)
         .execute(&self.pool)
         .await?;
         Ok(())
@@ -126,10 +200,10 @@ impl LogRepo for PgLogRepo {
     async fn get_logs_for_run(&self, run_id: ResourceId) -> DbResult<Vec<LogRecord>> {
         let records = sqlx::query_as::<_, LogRecord>(
             r#"
-            SELECT id, pipeline_run_id, stage_name, timestamp, stream, content
+            SELECT id, pipeline_run_id, stage_name, timestamp, stream, content, step, frame, exit_code
             FROM logs
             WHERE pipeline_run_id = $1
-            ORDER BY timestamp ASC
+            ORDER BY timestamp ASC, id ASC
             "#,
         )
         .bind(run_id.as_uuid())
@@ -145,10 +219,10 @@ impl LogRepo for PgLogRepo {
     ) -> DbResult<Vec<LogRecord>> {
         let records = sqlx::query_as::<_, LogRecord>(
             r#"
-            SELECT id, pipeline_run_id, stage_name, timestamp, stream, content
+            SELECT id, pipeline_run_id, stage_name, timestamp, stream, content, step, frame, exit_code
             FROM logs
             WHERE pipeline_run_id = $1 AND stage_name = $2
-            ORDER BY timestamp ASC
+            ORDER BY timestamp ASC, id ASC
             "#,
         )
         .bind(run_id.as_uuid())
@@ -168,10 +242,10 @@ impl LogRepo for PgLogRepo {
         let records = if let Some(stage) = stage_name {
             sqlx::query_as::<_, LogRecord>(
                 r#"
-                SELECT id, pipeline_run_id, stage_name, timestamp, stream, content
+                SELECT id, pipeline_run_id, stage_name, timestamp, stream, content, step, frame, exit_code
                 FROM logs
                 WHERE pipeline_run_id = $1 AND stage_name = $2
-                ORDER BY timestamp ASC
+                ORDER BY timestamp ASC, id ASC
                 OFFSET $3 LIMIT $4
                 "#,
             )
@@ -184,10 +258,10 @@ impl LogRepo for PgLogRepo {
         } else {
             sqlx::query_as::<_, LogRecord>(
                 r#"
-                SELECT id, pipeline_run_id, stage_name, timestamp, stream, content
+                SELECT id, pipeline_run_id, stage_name, timestamp, stream, content, step, frame, exit_code
                 FROM logs
                 WHERE pipeline_run_id = $1
-                ORDER BY timestamp ASC
+                ORDER BY timestamp ASC, id ASC
                 OFFSET $2 LIMIT $3
                 "#,
             )
@@ -199,4 +273,124 @@ impl LogRepo for PgLogRepo {
         };
         Ok(records)
     }
+
+    async fn count_logs_for_stage(&self, run_id: ResourceId, stage_name: &str) -> DbResult<i64> {
+        let count: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM logs WHERE pipeline_run_id = $1 AND stage_name = $2",
+        )
+        .bind(run_id.as_uuid())
+        .bind(stage_name)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(count)
+    }
+
+    async fn runs_due_for_archive(&self, limit: i64) -> DbResult<Vec<RunLogsDue>> {
+        let runs = sqlx::query_as::<_, RunLogsDue>(
+            r#"
+            SELECT r.id AS pipeline_run_id, t.id AS tenant_id, NULL::TEXT AS archive_location
+            FROM pipeline_runs r
+            JOIN pipelines p ON p.id = r.pipeline_id
+            JOIN tenants t ON t.id = p.tenant_id
+            WHERE t.log_retention->>'archive_after_days' IS NOT NULL
+              AND r.finished_at < NOW() - make_interval(days => (t.log_retention->>'archive_after_days')::INT)
+              AND (t.log_retention->>'delete_after_days' IS NULL
+                   OR r.finished_at >= NOW() - make_interval(days => (t.log_retention->>'delete_after_days')::INT))
+              AND NOT EXISTS (SELECT 1 FROM log_archives a WHERE a.pipeline_run_id = r.id)
+              AND EXISTS (SELECT 1 FROM logs l WHERE l.pipeline_run_id = r.id)
+            ORDER BY r.finished_at
+            LIMIT $1
+            "#,
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(runs)
+    }
+
+    async fn runs_due_for_deletion(&self, limit: i64) -> DbResult<Vec<RunLogsDue>> {
+        let runs = sqlx::query_as::<_, RunLogsDue>(
+            r#"
+            SELECT r.id AS pipeline_run_id, t.id AS tenant_id, a.location AS archive_location
+            FROM pipeline_runs r
+            JOIN pipelines p ON p.id = r.pipeline_id
+            JOIN tenants t ON t.id = p.tenant_id
+            LEFT JOIN log_archives a ON a.pipeline_run_id = r.id
+            WHERE t.log_retention->>'delete_after_days' IS NOT NULL
+              AND r.finished_at < NOW() - make_interval(days => (t.log_retention->>'delete_after_days')::INT)
+              AND (a.pipeline_run_id IS NOT NULL
+                   OR EXISTS (SELECT 1 FROM logs l WHERE l.pipeline_run_id = r.id))
+            ORDER BY r.finished_at
+            LIMIT $1
+            "#,
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(runs)
+    }
+
+    async fn archive_run(
+        &self,
+        run_id: ResourceId,
+        tenant_id: ResourceId,
+        location: &str,
+        lines: i64,
+        compressed_bytes: i64,
+    ) -> DbResult<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            r#"
+            INSERT INTO log_archives (pipeline_run_id, tenant_id, location, lines, compressed_bytes)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(run_id.as_uuid())
+        .bind(tenant_id.as_uuid())
+        .bind(location)
+        .bind(lines)
+        .bind(compressed_bytes)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM logs WHERE pipeline_run_id = $1")
+            .bind(run_id.as_uuid())
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_archive(&self, run_id: ResourceId) -> DbResult<Option<LogArchiveRecord>> {
+        let archive = sqlx::query_as::<_, LogArchiveRecord>(
+            "SELECT * FROM log_archives WHERE pipeline_run_id = $1",
+        )
+        .bind(run_id.as_uuid())
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(archive)
+    }
+
+    async fn delete_run_logs(&self, run_id: ResourceId) -> DbResult<u64> {
+        let mut tx = self.pool.begin().await?;
+        let deleted = sqlx::query("DELETE FROM logs WHERE pipeline_run_id = $1")
+            .bind(run_id.as_uuid())
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        sqlx::query("DELETE FROM log_archives WHERE pipeline_run_id = $1")
+            .bind(run_id.as_uuid())
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(deleted)
+    }
+
+    async fn archive_totals(&self) -> DbResult<(i64, i64)> {
+        let totals: (i64, i64) = sqlx::query_as(
+            "SELECT COUNT(*), COALESCE(SUM(compressed_bytes), 0)::BIGINT FROM log_archives",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(totals)
+    }
 }
@@ -0,0 +1,202 @@
+//! Notification channel repository.
+//!
+//! A channel is a Slack webhook, a list of email recipients or an HTTP
+//! endpoint a tenant sends some of its events to.
+
+use async_trait::async_trait;
+use buildit_core::ResourceId;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+use crate::{DbError, DbResult};
+
+/// Where a tenant's notifications for some events go.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct NotificationChannel {
+    pub id: uuid::Uuid,
+    pub tenant_id: uuid::Uuid,
+    pub name: String,
+    /// `slack`, `email` or `webhook`.
+    pub kind: String,
+    pub config: serde_json::Value,
+    /// Key signing webhook deliveries.
+    #[serde(skip_serializing)]
+    pub secret: Option<String>,
+    pub events: Vec<String>,
+    pub enabled: bool,
+    pub created_by: Option<uuid::Uuid>,
+    pub last_delivery_at: Option<DateTime<Utc>>,
+    /// Error of the last delivery, if it failed.
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[async_trait]
+pub trait NotificationRepo: Send + Sync {
+    /// Fails with `Duplicate` if the tenant has a channel of that name.
+    async fn create_channel(&self, channel: &NotificationChannel) -> DbResult<NotificationChannel>;
+
+    async fn list_channels(&self, tenant_id: ResourceId) -> DbResult<Vec<NotificationChannel>>;
+
+    async fn get_channel(
+        &self,
+        tenant_id: ResourceId,
+        id: ResourceId,
+    ) -> DbResult<NotificationChannel>;
+
+    /// Save a channel's name, config, secret, events and whether it is
+    /// enabled.
+    async fn update_channel(&self, channel: &NotificationChannel) -> DbResult<NotificationChannel>;
+
+    async fn delete_channel(&self, tenant_id: ResourceId, id: ResourceId) -> DbResult<()>;
+
+    /// Enabled channels of the tenant that receive `event`.
+    async fn channels_for_event(
+        &self,
+        tenant_id: ResourceId,
+        event: &str,
+    ) -> DbResult<Vec<NotificationChannel>>;
+
+    /// Record a delivery attempt and its error, if it failed.
+    async fn record_delivery(&self, id: ResourceId, error: Option<&str>) -> DbResult<()>;
+}
+
+/// PostgreSQL implementation of NotificationRepo.
+pub struct PgNotificationRepo {
+    pool: PgPool,
+}
+
+impl PgNotificationRepo {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+fn duplicate_name(e: sqlx::Error, name: &str) -> DbError {
+    match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+            DbError::Duplicate(format!("notification channel {} already exists", name))
+        }
+        e => e.into(),
+    }
+}
+
+#[async_trait]
+impl NotificationRepo for PgNotificationRepo {
+    async fn create_channel(&self, channel: &NotificationChannel) -> DbResult<NotificationChannel> {
+        sqlx::query_as::<_, NotificationChannel>(
+            r#"
+            INSERT INTO notification_channels
+                (id, tenant_id, name, kind, config, secret, events, enabled, created_by)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            RETURNING *
+            "#,
+        )
+        .bind(channel.id)
+        .bind(channel.tenant_id)
+        .bind(&channel.name)
+        .bind(&channel.kind)
+        .bind(&channel.config)
+        .bind(&channel.secret)
+        .bind(&channel.events)
+        .bind(channel.enabled)
+        .bind(channel.created_by)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| duplicate_name(e, &channel.name))
+    }
+
+    async fn list_channels(&self, tenant_id: ResourceId) -> DbResult<Vec<NotificationChannel>> {
+        let channels = sqlx::query_as::<_, NotificationChannel>(
+            "SELECT * FROM notification_channels WHERE tenant_id = $1 ORDER BY name",
+        )
+        .bind(tenant_id.as_uuid())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(channels)
+    }
+
+    async fn get_channel(
+        &self,
+        tenant_id: ResourceId,
+        id: ResourceId,
+    ) -> DbResult<NotificationChannel> {
+        sqlx::query_as::<_, NotificationChannel>(
+            "SELECT * FROM notification_channels WHERE id = $1 AND tenant_id = $2",
+        )
+        .bind(id.as_uuid())
+        .bind(tenant_id.as_uuid())
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("notification channel {}", id)))
+    }
+
+    async fn update_channel(&self, channel: &NotificationChannel) -> DbResult<NotificationChannel> {
+        sqlx::query_as::<_, NotificationChannel>(
+            r#"
+            UPDATE notification_channels
+            SET name = $3, config = $4, secret = $5, events = $6, enabled = $7,
+                updated_at = NOW()
+            WHERE id = $1 AND tenant_id = $2
+            RETURNING *
+            "#,
+        )
+        .bind(channel.id)
+        .bind(channel.tenant_id)
+        .bind(&channel.name)
+        .bind(&channel.config)
+        .bind(&channel.secret)
+        .bind(&channel.events)
+        .bind(channel.enabled)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| duplicate_name(e, &channel.name))?
+        .ok_or_else(|| DbError::NotFound(format!("notification channel {}", channel.id)))
+    }
+
+    async fn delete_channel(&self, tenant_id: ResourceId, id: ResourceId) -> DbResult<()> {
+        let result =
+            sqlx::query("DELETE FROM notification_channels WHERE id = $1 AND tenant_id = $2")
+                .bind(id.as_uuid())
+                .bind(tenant_id.as_uuid())
+                .execute(&self.pool)
+                .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!("notification channel {}", id)));
+        }
+        Ok(())
+    }
+
+    async fn channels_for_event(
+        &self,
+        tenant_id: ResourceId,
+        event: &str,
+    ) -> DbResult<Vec<NotificationChannel>> {
+        let channels = sqlx::query_as::<_, NotificationChannel>(
+            r#"
+            SELECT * FROM notification_channels
+            WHERE tenant_id = $1 AND enabled AND $2 = ANY(events)
+            ORDER BY name
+            "#,
+        )
+        .bind(tenant_id.as_uuid())
+        .bind(event)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(channels)
+    }
+
+    async fn record_delivery(&self, id: ResourceId, error: Option<&str>) -> DbResult<()> {
+        sqlx::query(
+            "UPDATE notification_channels SET last_delivery_at = NOW(), last_error = $2 WHERE id = $1",
+        )
+        .bind(id.as_uuid())
+        .bind(error)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
@@ -2,9 +2,11 @@
 
 use async_trait::async_trait;
 use buildit_core::ResourceId;
+use buildit_core::policy::PipelinePolicy;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
+use utoipa::ToSchema;
 
 use crate::{DbError, DbResult};
 
@@ -18,6 +20,14 @@ pub struct Organization {
     pub settings: serde_json::Value,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub pipeline_policy: serde_json::Value,
+}
+
+impl Organization {
+    /// Decoded pipeline policy; no rules if none is set.
+    pub fn pipeline_policy(&self) -> PipelinePolicy {
+        serde_json::from_value(self.pipeline_policy.clone()).unwrap_or_default()
+    }
 }
 
 /// A user.
@@ -62,7 +72,7 @@ pub struct OAuthConnection {
 }
 
 /// Organization membership.
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
 pub struct OrgMembership {
     pub id: uuid::Uuid,
     pub organization_id: uuid::Uuid,
@@ -89,6 +99,23 @@ pub struct OrgMembershipWithUser {
     pub user_avatar_url: Option<String>,
 }
 
+/// An invitation to join an organization (without its token, only the
+/// invitee's link carries that).
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct OrgInvitation {
+    pub id: uuid::Uuid,
+    pub organization_id: uuid::Uuid,
+    pub email: String,
+    pub role: String,
+    pub invited_by: Option<uuid::Uuid>,
+    /// `pending`, `accepted`, `declined` or `revoked`.
+    pub status: String,
+    pub accepted_by: Option<uuid::Uuid>,
+    pub expires_at: DateTime<Utc>,
+    pub responded_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
 /// Tenant membership.
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct TenantMembership {
@@ -101,7 +128,7 @@ pub struct TenantMembership {
 }
 
 /// API key (without the actual key, just metadata).
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
 pub struct ApiKey {
     pub id: uuid::Uuid,
     pub organization_id: uuid::Uuid,
@@ -126,10 +153,40 @@ pub struct Session {
     pub user_agent: Option<String>,
     pub expires_at: DateTime<Utc>,
     pub created_at: DateTime<Utc>,
+    /// Token the access token can be rotated with, for CLI sessions.
+    pub refresh_token_hash: Option<String>,
+    pub refresh_expires_at: Option<DateTime<Utc>>,
+    /// Tenant the session switched to.
+    pub tenant_id: Option<uuid::Uuid>,
 }
 
-/// Audit log entry.
+/// A device authorization grant, signing in a CLI once a user approves its
+/// user code in the browser.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct DeviceAuthorization {
+    pub id: uuid::Uuid,
+    pub device_code_hash: String,
+    pub user_code: String,
+    pub client_name: Option<String>,
+    pub status: String,
+    pub user_id: Option<uuid::Uuid>,
+    pub last_polled_at: Option<DateTime<Utc>>,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A Slack user linked to a BuildIt user, acting as them in a tenant.
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct SlackUser {
+    pub team_id: String,
+    pub slack_user_id: String,
+    pub user_id: uuid::Uuid,
+    pub tenant_id: uuid::Uuid,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Audit log entry.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
 pub struct AuditLog {
     pub id: uuid::Uuid,
     pub organization_id: Option<uuid::Uuid>,
@@ -144,6 +201,21 @@ pub struct AuditLog {
     pub created_at: DateTime<Utc>,
 }
 
+/// Which audit log entries to return. Unset fields match every entry;
+/// `action` matches by prefix, so `stacks` finds every stack action.
+#[derive(Debug, Clone, Default)]
+pub struct AuditLogFilter {
+    pub organization_id: Option<uuid::Uuid>,
+    pub tenant_id: Option<uuid::Uuid>,
+    pub user_id: Option<uuid::Uuid>,
+    pub resource_type: Option<String>,
+    pub resource_id: Option<uuid::Uuid>,
+    pub action: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub limit: i64,
+}
+
 #[async_trait]
 pub trait OrganizationRepo: Send + Sync {
     // Organizations
@@ -152,6 +224,11 @@ pub trait OrganizationRepo: Send + Sync {
     async fn get_organization_by_slug(&self, slug: &str) -> DbResult<Organization>;
     async fn create_organization(&self, org: &Organization) -> DbResult<Organization>;
     async fn update_organization(&self, org: &Organization) -> DbResult<Organization>;
+    async fn update_pipeline_policy(
+        &self,
+        id: ResourceId,
+        policy: &PipelinePolicy,
+    ) -> DbResult<Organization>;
 
     // Users
     async fn list_users(&self) -> DbResult<Vec<UserPublic>>;
@@ -195,6 +272,38 @@ pub trait OrganizationRepo: Send + Sync {
     ) -> DbResult<()>;
     async fn remove_org_member(&self, org_id: ResourceId, user_id: ResourceId) -> DbResult<()>;
 
+    // Invitations
+    /// Fails with `Duplicate` if the address already has a pending
+    /// invitation to the organization.
+    async fn create_invitation(
+        &self,
+        invitation: &OrgInvitation,
+        token_hash: &str,
+    ) -> DbResult<OrgInvitation>;
+    /// The organization's pending invitations, expired ones included.
+    async fn list_pending_invitations(&self, org_id: ResourceId) -> DbResult<Vec<OrgInvitation>>;
+    async fn get_invitation_by_token(&self, token_hash: &str) -> DbResult<OrgInvitation>;
+    /// Give a pending invitation a new token and expiry, invalidating the
+    /// old link.
+    async fn renew_invitation(
+        &self,
+        org_id: ResourceId,
+        id: ResourceId,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> DbResult<OrgInvitation>;
+    /// Withdraw a pending invitation. Fails if there is no such invitation.
+    async fn revoke_invitation(&self, org_id: ResourceId, id: ResourceId) -> DbResult<()>;
+    /// Decline a pending, unexpired invitation.
+    async fn decline_invitation(&self, id: ResourceId) -> DbResult<()>;
+    /// Accept a pending, unexpired invitation, making the user a member
+    /// with its role. Fails with `Conflict` if they already are one.
+    async fn accept_invitation(
+        &self,
+        id: ResourceId,
+        user_id: ResourceId,
+    ) -> DbResult<OrgMembership>;
+
     // Tenant memberships
     async fn list_tenant_members(&self, tenant_id: ResourceId) -> DbResult<Vec<TenantMembership>>;
     async fn get_tenant_membership(
@@ -203,19 +312,83 @@ pub trait OrganizationRepo: Send + Sync {
         user_id: ResourceId,
     ) -> DbResult<TenantMembership>;
     async fn list_user_tenants(&self, user_id: ResourceId) -> DbResult<Vec<uuid::Uuid>>;
+    async fn get_tenant_organization(&self, tenant_id: ResourceId) -> DbResult<Option<uuid::Uuid>>;
 
     // API keys
     async fn list_api_keys(&self, org_id: ResourceId) -> DbResult<Vec<ApiKey>>;
     async fn get_api_key_by_prefix(&self, prefix: &str) -> DbResult<ApiKey>;
     async fn validate_api_key(&self, prefix: &str, key_hash: &str) -> DbResult<ApiKey>;
     async fn update_api_key_last_used(&self, id: ResourceId) -> DbResult<()>;
+    async fn create_api_key(&self, key: &ApiKey, key_hash: &str) -> DbResult<ApiKey>;
+    /// Revoke one of the organization's keys. Fails if there is no such
+    /// unrevoked key.
+    async fn revoke_api_key(&self, org_id: ResourceId, id: ResourceId) -> DbResult<()>;
 
     // Sessions
     async fn create_session(&self, session: &Session) -> DbResult<Session>;
+    /// An unexpired session by ID.
+    async fn get_session(&self, id: ResourceId) -> DbResult<Session>;
     async fn get_session_by_token(&self, token_hash: &str) -> DbResult<Session>;
+    async fn get_session_by_refresh_token(&self, refresh_token_hash: &str) -> DbResult<Session>;
+    /// Replace a session's tokens, invalidating the old ones.
+    async fn rotate_session(
+        &self,
+        id: ResourceId,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+        refresh_token_hash: &str,
+        refresh_expires_at: DateTime<Utc>,
+    ) -> DbResult<Session>;
+    /// Switch the tenant a session acts in by default.
+    async fn set_session_tenant(
+        &self,
+        id: ResourceId,
+        tenant_id: Option<ResourceId>,
+    ) -> DbResult<()>;
     async fn delete_session(&self, id: ResourceId) -> DbResult<()>;
     async fn delete_expired_sessions(&self) -> DbResult<u64>;
 
+    // Device authorizations
+    async fn create_device_authorization(
+        &self,
+        authorization: &DeviceAuthorization,
+    ) -> DbResult<DeviceAuthorization>;
+    async fn get_device_authorization(
+        &self,
+        device_code_hash: &str,
+    ) -> DbResult<DeviceAuthorization>;
+    /// A pending, unexpired authorization by its user code.
+    async fn get_pending_device_authorization(
+        &self,
+        user_code: &str,
+    ) -> DbResult<DeviceAuthorization>;
+    /// Record a poll, returning the previous poll time.
+    async fn poll_device_authorization(&self, id: ResourceId) -> DbResult<Option<DateTime<Utc>>>;
+    /// Approve or deny a pending authorization.
+    async fn decide_device_authorization(
+        &self,
+        id: ResourceId,
+        user_id: ResourceId,
+        approved: bool,
+    ) -> DbResult<()>;
+    /// Mark an approved authorization redeemed. Fails if it already was.
+    async fn redeem_device_authorization(&self, id: ResourceId) -> DbResult<DeviceAuthorization>;
+
+    // Slack users
+    async fn get_slack_user(
+        &self,
+        team_id: &str,
+        slack_user_id: &str,
+    ) -> DbResult<Option<SlackUser>>;
+    /// Link a Slack user, replacing any earlier link of theirs.
+    async fn link_slack_user(
+        &self,
+        team_id: &str,
+        slack_user_id: &str,
+        user_id: ResourceId,
+        tenant_id: ResourceId,
+    ) -> DbResult<SlackUser>;
+
     // Audit logs
     async fn create_audit_log(&self, log: &AuditLog) -> DbResult<AuditLog>;
     async fn list_audit_logs(
@@ -224,8 +397,14 @@ pub trait OrganizationRepo: Send + Sync {
         tenant_id: Option<ResourceId>,
         limit: i64,
     ) -> DbResult<Vec<AuditLog>>;
+    /// Entries matching `filter`, newest first.
+    async fn query_audit_logs(&self, filter: &AuditLogFilter) -> DbResult<Vec<AuditLog>>;
 }
 
+/// Columns of [`OrgInvitation`]; the token hash is never read back.
+const INVITATION_COLUMNS: &str = "id, organization_id, email, role, invited_by, status, \
+    accepted_by, expires_at, responded_at, created_at";
+
 /// PostgreSQL implementation of OrganizationRepo.
 pub struct PgOrganizationRepo {
     pool: PgPool,
@@ -304,6 +483,22 @@ impl OrganizationRepo for PgOrganizationRepo {
         Ok(updated)
     }
 
+    async fn update_pipeline_policy(
+        &self,
+        id: ResourceId,
+        policy: &PipelinePolicy,
+    ) -> DbResult<Organization> {
+        let org = sqlx::query_as::<_, Organization>(
+            "UPDATE organizations SET pipeline_policy = $2, updated_at = NOW() WHERE id = $1 RETURNING *",
+        )
+        .bind(id.as_uuid())
+        .bind(serde_json::to_value(policy).map_err(|e| DbError::InvalidData(e.to_string()))?)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("organization {}", id)))?;
+        Ok(org)
+    }
+
     // Users
     async fn list_users(&self) -> DbResult<Vec<UserPublic>> {
         let users = sqlx::query_as::<_, UserPublic>(
@@ -526,6 +721,165 @@ impl OrganizationRepo for PgOrganizationRepo {
         Ok(())
     }
 
+    // Invitations
+    async fn create_invitation(
+        &self,
+        invitation: &OrgInvitation,
+        token_hash: &str,
+    ) -> DbResult<OrgInvitation> {
+        let created = sqlx::query_as::<_, OrgInvitation>(&format!(
+            r#"
+            INSERT INTO org_invitations (id, organization_id, email, role, token_hash, invited_by, status, expires_at, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, 'pending', $7, NOW())
+            RETURNING {}
+            "#,
+            INVITATION_COLUMNS
+        ))
+        .bind(invitation.id)
+        .bind(invitation.organization_id)
+        .bind(&invitation.email)
+        .bind(&invitation.role)
+        .bind(token_hash)
+        .bind(invitation.invited_by)
+        .bind(invitation.expires_at)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(ref db) if db.is_unique_violation() => DbError::Duplicate(
+                format!("{} already has a pending invitation", invitation.email),
+            ),
+            e => e.into(),
+        })?;
+        Ok(created)
+    }
+
+    async fn list_pending_invitations(&self, org_id: ResourceId) -> DbResult<Vec<OrgInvitation>> {
+        let invitations = sqlx::query_as::<_, OrgInvitation>(&format!(
+            r#"
+            SELECT {} FROM org_invitations
+            WHERE organization_id = $1 AND status = 'pending'
+            ORDER BY created_at DESC
+            "#,
+            INVITATION_COLUMNS
+        ))
+        .bind(org_id.as_uuid())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(invitations)
+    }
+
+    async fn get_invitation_by_token(&self, token_hash: &str) -> DbResult<OrgInvitation> {
+        sqlx::query_as::<_, OrgInvitation>(&format!(
+            "SELECT {} FROM org_invitations WHERE token_hash = $1",
+            INVITATION_COLUMNS
+        ))
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound("invitation".to_string()))
+    }
+
+    async fn renew_invitation(
+        &self,
+        org_id: ResourceId,
+        id: ResourceId,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> DbResult<OrgInvitation> {
+        sqlx::query_as::<_, OrgInvitation>(&format!(
+            r#"
+            UPDATE org_invitations SET token_hash = $3, expires_at = $4
+            WHERE id = $1 AND organization_id = $2 AND status = 'pending'
+            RETURNING {}
+            "#,
+            INVITATION_COLUMNS
+        ))
+        .bind(id.as_uuid())
+        .bind(org_id.as_uuid())
+        .bind(token_hash)
+        .bind(expires_at)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("invitation {}", id)))
+    }
+
+    async fn revoke_invitation(&self, org_id: ResourceId, id: ResourceId) -> DbResult<()> {
+        let result = sqlx::query(
+            r#"
+            UPDATE org_invitations SET status = 'revoked', responded_at = NOW()
+            WHERE id = $1 AND organization_id = $2 AND status = 'pending'
+            "#,
+        )
+        .bind(id.as_uuid())
+        .bind(org_id.as_uuid())
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!("invitation {}", id)));
+        }
+        Ok(())
+    }
+
+    async fn decline_invitation(&self, id: ResourceId) -> DbResult<()> {
+        let result = sqlx::query(
+            r#"
+            UPDATE org_invitations SET status = 'declined', responded_at = NOW()
+            WHERE id = $1 AND status = 'pending' AND expires_at > NOW()
+            "#,
+        )
+        .bind(id.as_uuid())
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!("invitation {}", id)));
+        }
+        Ok(())
+    }
+
+    async fn accept_invitation(
+        &self,
+        id: ResourceId,
+        user_id: ResourceId,
+    ) -> DbResult<OrgMembership> {
+        let mut tx = self.pool.begin().await?;
+        let invitation = sqlx::query_as::<_, OrgInvitation>(&format!(
+            r#"
+            UPDATE org_invitations SET status = 'accepted', accepted_by = $2, responded_at = NOW()
+            WHERE id = $1 AND status = 'pending' AND expires_at > NOW()
+            RETURNING {}
+            "#,
+            INVITATION_COLUMNS
+        ))
+        .bind(id.as_uuid())
+        .bind(user_id.as_uuid())
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("invitation {}", id)))?;
+
+        let membership = sqlx::query_as::<_, OrgMembership>(
+            r#"
+            INSERT INTO org_memberships (id, organization_id, user_id, role, invited_by, invited_at, accepted_at, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, NOW(), NOW(), NOW())
+            ON CONFLICT (organization_id, user_id) DO NOTHING
+            RETURNING *
+            "#,
+        )
+        .bind(uuid::Uuid::new_v4())
+        .bind(invitation.organization_id)
+        .bind(user_id.as_uuid())
+        .bind(&invitation.role)
+        .bind(invitation.invited_by)
+        .bind(invitation.created_at)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| {
+            DbError::Conflict("already a member of the organization".to_string())
+        })?;
+
+        tx.commit().await?;
+        Ok(membership)
+    }
+
     // Tenant memberships
     async fn list_tenant_members(&self, tenant_id: ResourceId) -> DbResult<Vec<TenantMembership>> {
         let members = sqlx::query_as::<_, TenantMembership>(
@@ -567,6 +921,15 @@ impl OrganizationRepo for PgOrganizationRepo {
         Ok(tenants.into_iter().map(|(id,)| id).collect())
     }
 
+    async fn get_tenant_organization(&self, tenant_id: ResourceId) -> DbResult<Option<uuid::Uuid>> {
+        let row: Option<(Option<uuid::Uuid>,)> =
+            sqlx::query_as("SELECT organization_id FROM tenants WHERE id = $1")
+                .bind(tenant_id.as_uuid())
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(row.and_then(|(id,)| id))
+    }
+
     // API keys
     async fn list_api_keys(&self, org_id: ResourceId) -> DbResult<Vec<ApiKey>> {
         let keys = sqlx::query_as::<_, ApiKey>(
@@ -613,12 +976,52 @@ impl OrganizationRepo for PgOrganizationRepo {
         Ok(())
     }
 
+    async fn create_api_key(&self, key: &ApiKey, key_hash: &str) -> DbResult<ApiKey> {
+        let created = sqlx::query_as::<_, ApiKey>(
+            r#"
+            INSERT INTO api_keys (id, organization_id, user_id, tenant_id, name, key_prefix, key_hash, scopes, expires_at, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            RETURNING *
+            "#,
+        )
+        .bind(key.id)
+        .bind(key.organization_id)
+        .bind(key.user_id)
+        .bind(key.tenant_id)
+        .bind(&key.name)
+        .bind(&key.key_prefix)
+        .bind(key_hash)
+        .bind(&key.scopes)
+        .bind(key.expires_at)
+        .bind(key.created_at)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(created)
+    }
+
+    async fn revoke_api_key(&self, org_id: ResourceId, id: ResourceId) -> DbResult<()> {
+        let result = sqlx::query(
+            r#"
+            UPDATE api_keys SET revoked_at = NOW()
+            WHERE id = $1 AND organization_id = $2 AND revoked_at IS NULL
+            "#,
+        )
+        .bind(id.as_uuid())
+        .bind(org_id.as_uuid())
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!("api key {}", id)));
+        }
+        Ok(())
+    }
+
     // Sessions
     async fn create_session(&self, session: &Session) -> DbResult<Session> {
         let created = sqlx::query_as::<_, Session>(
             r#"
-            INSERT INTO sessions (id, user_id, token_hash, ip_address, user_agent, expires_at, created_at)
-            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            INSERT INTO sessions (id, user_id, token_hash, ip_address, user_agent, expires_at, created_at, refresh_token_hash, refresh_expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
             RETURNING *
             "#
         )
@@ -629,11 +1032,24 @@ impl OrganizationRepo for PgOrganizationRepo {
         .bind(&session.user_agent)
         .bind(session.expires_at)
         .bind(session.created_at)
+        .bind(&session.refresh_token_hash)
+        .bind(session.refresh_expires_at)
         .fetch_one(&self.pool)
         .await?;
         Ok(created)
     }
 
+    async fn get_session(&self, id: ResourceId) -> DbResult<Session> {
+        let session = sqlx::query_as::<_, Session>(
+            "SELECT * FROM sessions WHERE id = $1 AND expires_at > NOW()",
+        )
+        .bind(id.as_uuid())
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound("session not found or expired".to_string()))?;
+        Ok(session)
+    }
+
     async fn get_session_by_token(&self, token_hash: &str) -> DbResult<Session> {
         let session = sqlx::query_as::<_, Session>(
             "SELECT * FROM sessions WHERE token_hash = $1 AND expires_at > NOW()",
@@ -645,6 +1061,60 @@ impl OrganizationRepo for PgOrganizationRepo {
         Ok(session)
     }
 
+    async fn get_session_by_refresh_token(&self, refresh_token_hash: &str) -> DbResult<Session> {
+        let session = sqlx::query_as::<_, Session>(
+            "SELECT * FROM sessions WHERE refresh_token_hash = $1 AND refresh_expires_at > NOW()",
+        )
+        .bind(refresh_token_hash)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound("refresh token not found or expired".to_string()))?;
+        Ok(session)
+    }
+
+    async fn rotate_session(
+        &self,
+        id: ResourceId,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+        refresh_token_hash: &str,
+        refresh_expires_at: DateTime<Utc>,
+    ) -> DbResult<Session> {
+        let session = sqlx::query_as::<_, Session>(
+            r#"
+            UPDATE sessions
+            SET token_hash = $2, expires_at = $3, refresh_token_hash = $4, refresh_expires_at = $5
+            WHERE id = $1
+            RETURNING *
+            "#,
+        )
+        .bind(id.as_uuid())
+        .bind(token_hash)
+        .bind(expires_at)
+        .bind(refresh_token_hash)
+        .bind(refresh_expires_at)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("session {}", id)))?;
+        Ok(session)
+    }
+
+    async fn set_session_tenant(
+        &self,
+        id: ResourceId,
+        tenant_id: Option<ResourceId>,
+    ) -> DbResult<()> {
+        let result = sqlx::query("UPDATE sessions SET tenant_id = $2 WHERE id = $1")
+            .bind(id.as_uuid())
+            .bind(tenant_id.map(|t| *t.as_uuid()))
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!("session {}", id)));
+        }
+        Ok(())
+    }
+
     async fn delete_session(&self, id: ResourceId) -> DbResult<()> {
         sqlx::query("DELETE FROM sessions WHERE id = $1")
             .bind(id.as_uuid())
@@ -654,12 +1124,125 @@ impl OrganizationRepo for PgOrganizationRepo {
     }
 
     async fn delete_expired_sessions(&self) -> DbResult<u64> {
-        let result = sqlx::query("DELETE FROM sessions WHERE expires_at < NOW()")
-            .execute(&self.pool)
-            .await?;
+        let result = sqlx::query(
+            "DELETE FROM sessions WHERE GREATEST(expires_at, refresh_expires_at) < NOW()",
+        )
+        .execute(&self.pool)
+        .await?;
         Ok(result.rows_affected())
     }
 
+    // Device authorizations
+    async fn create_device_authorization(
+        &self,
+        authorization: &DeviceAuthorization,
+    ) -> DbResult<DeviceAuthorization> {
+        let created = sqlx::query_as::<_, DeviceAuthorization>(
+            r#"
+            INSERT INTO device_authorizations (id, device_code_hash, user_code, client_name, status, expires_at, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING *
+            "#,
+        )
+        .bind(authorization.id)
+        .bind(&authorization.device_code_hash)
+        .bind(&authorization.user_code)
+        .bind(&authorization.client_name)
+        .bind(&authorization.status)
+        .bind(authorization.expires_at)
+        .bind(authorization.created_at)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(created)
+    }
+
+    async fn get_device_authorization(
+        &self,
+        device_code_hash: &str,
+    ) -> DbResult<DeviceAuthorization> {
+        let authorization = sqlx::query_as::<_, DeviceAuthorization>(
+            "SELECT * FROM device_authorizations WHERE device_code_hash = $1",
+        )
+        .bind(device_code_hash)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound("device authorization".to_string()))?;
+        Ok(authorization)
+    }
+
+    async fn get_pending_device_authorization(
+        &self,
+        user_code: &str,
+    ) -> DbResult<DeviceAuthorization> {
+        let authorization = sqlx::query_as::<_, DeviceAuthorization>(
+            r#"
+            SELECT * FROM device_authorizations
+            WHERE user_code = $1 AND status = 'pending' AND expires_at > NOW()
+            "#,
+        )
+        .bind(user_code)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("device code {}", user_code)))?;
+        Ok(authorization)
+    }
+
+    async fn poll_device_authorization(&self, id: ResourceId) -> DbResult<Option<DateTime<Utc>>> {
+        let previous: Option<Option<DateTime<Utc>>> = sqlx::query_scalar(
+            r#"
+            UPDATE device_authorizations d
+            SET last_polled_at = NOW()
+            FROM (SELECT id, last_polled_at FROM device_authorizations WHERE id = $1 FOR UPDATE) p
+            WHERE d.id = p.id
+            RETURNING p.last_polled_at
+            "#,
+        )
+        .bind(id.as_uuid())
+        .fetch_optional(&self.pool)
+        .await?;
+        previous.ok_or_else(|| DbError::NotFound(format!("device authorization {}", id)))
+    }
+
+    async fn decide_device_authorization(
+        &self,
+        id: ResourceId,
+        user_id: ResourceId,
+        approved: bool,
+    ) -> DbResult<()> {
+        let result = sqlx::query(
+            r#"
+            UPDATE device_authorizations
+            SET status = CASE WHEN $3 THEN 'approved' ELSE 'denied' END, user_id = $2
+            WHERE id = $1 AND status = 'pending' AND expires_at > NOW()
+            "#,
+        )
+        .bind(id.as_uuid())
+        .bind(user_id.as_uuid())
+        .bind(approved)
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!("device authorization {}", id)));
+        }
+        Ok(())
+    }
+
+    async fn redeem_device_authorization(&self, id: ResourceId) -> DbResult<DeviceAuthorization> {
+        let authorization = sqlx::query_as::<_, DeviceAuthorization>(
+            r#"
+            UPDATE device_authorizations
+            SET status = 'redeemed'
+            WHERE id = $1 AND status = 'approved'
+            RETURNING *
+            "#,
+        )
+        .bind(id.as_uuid())
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("approved device authorization {}", id)))?;
+        Ok(authorization)
+    }
+
     // Audit logs
     async fn create_audit_log(&self, log: &AuditLog) -> DbResult<AuditLog> {
         let created = sqlx::query_as::<_, AuditLog>(
@@ -731,4 +1314,74 @@ impl OrganizationRepo for PgOrganizationRepo {
         };
         Ok(logs)
     }
+
+    async fn query_audit_logs(&self, filter: &AuditLogFilter) -> DbResult<Vec<AuditLog>> {
+        let logs = sqlx::query_as::<_, AuditLog>(
+            r#"
+            SELECT * FROM audit_logs
+            WHERE ($1::uuid IS NULL OR organization_id = $1)
+              AND ($2::uuid IS NULL OR tenant_id = $2)
+              AND ($3::uuid IS NULL OR user_id = $3)
+              AND ($4::text IS NULL OR resource_type = $4)
+              AND ($5::uuid IS NULL OR resource_id = $5)
+              AND ($6::text IS NULL OR action LIKE $6 || '%')
+              AND ($7::timestamptz IS NULL OR created_at >= $7)
+              AND ($8::timestamptz IS NULL OR created_at < $8)
+            ORDER BY created_at DESC
+            LIMIT $9
+            "#,
+        )
+        .bind(filter.organization_id)
+        .bind(filter.tenant_id)
+        .bind(filter.user_id)
+        .bind(&filter.resource_type)
+        .bind(filter.resource_id)
+        .bind(&filter.action)
+        .bind(filter.from)
+        .bind(filter.to)
+        .bind(filter.limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(logs)
+    }
+
+    async fn get_slack_user(
+        &self,
+        team_id: &str,
+        slack_user_id: &str,
+    ) -> DbResult<Option<SlackUser>> {
+        let user = sqlx::query_as::<_, SlackUser>(
+            "SELECT * FROM slack_users WHERE team_id = $1 AND slack_user_id = $2",
+        )
+        .bind(team_id)
+        .bind(slack_user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(user)
+    }
+
+    async fn link_slack_user(
+        &self,
+        team_id: &str,
+        slack_user_id: &str,
+        user_id: ResourceId,
+        tenant_id: ResourceId,
+    ) -> DbResult<SlackUser> {
+        let user = sqlx::query_as::<_, SlackUser>(
+            r#"
+            INSERT INTO slack_users (team_id, slack_user_id, user_id, tenant_id)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (team_id, slack_user_id) DO UPDATE
+            SET user_id = EXCLUDED.user_id, tenant_id = EXCLUDED.tenant_id, created_at = NOW()
+            RETURNING *
+            "#,
+        )
+        .bind(team_id)
+        .bind(slack_user_id)
+        .bind(user_id.as_uuid())
+        .bind(tenant_id.as_uuid())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(user)
+    }
 }
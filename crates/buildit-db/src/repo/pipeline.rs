@@ -2,6 +2,12 @@
 
 use async_trait::async_trait;
 use buildit_core::ResourceId;
+use buildit_core::artifact::ArtifactDependency;
+use buildit_core::child_pipeline::TriggerPipelineSpec;
+use buildit_core::executor::{JobHandle, PullPolicy, SecurityContext, WaitCondition};
+use buildit_core::pipeline::{ContainerOptions, Ownership};
+use buildit_core::release::ReleaseSpec;
+use buildit_core::scan::ScanSpec;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
@@ -17,8 +23,56 @@ pub struct PipelineRecord {
     pub repository: String,
     pub repository_id: Option<uuid::Uuid>,
     pub config: serde_json::Value,
+    pub labels: Vec<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub owners: serde_json::Value,
+    pub owner_list: Vec<String>,
+    /// Set while the pipeline is archived: read-only and never triggered.
+    pub archived_at: Option<DateTime<Utc>>,
+    /// File in the repository the pipeline is synced from, if any.
+    pub config_path: Option<String>,
+}
+
+impl PipelineRecord {
+    pub fn is_archived(&self) -> bool {
+        self.archived_at.is_some()
+    }
+
+    /// Decoded ownership; empty if none is recorded.
+    pub fn ownership(&self) -> Ownership {
+        serde_json::from_value(self.owners.clone()).unwrap_or_default()
+    }
+}
+
+/// A pipeline with its last run and the stats of its recent runs, for
+/// pipeline lists. Rates and durations are over completed runs (`succeeded`,
+/// `failed` or `stalled`), as in analytics.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct PipelineSummaryRecord {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub pipeline: PipelineRecord,
+    pub last_run_id: Option<uuid::Uuid>,
+    pub last_run_number: Option<i64>,
+    pub last_run_status: Option<String>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    /// Runs created since the start of the window.
+    pub total_runs: i64,
+    pub success_rate: Option<f64>,
+    pub p50_duration_secs: Option<f64>,
+}
+
+/// A pipeline search hit, ranked by match quality and recent activity.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct PipelineSearchRecord {
+    pub id: uuid::Uuid,
+    pub name: String,
+    pub repository: String,
+    pub labels: Vec<String>,
+    pub last_run_status: Option<String>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub score: f64,
 }
 
 /// A pipeline run record.
@@ -33,6 +87,26 @@ pub struct PipelineRunRecord {
     pub created_at: DateTime<Utc>,
     pub started_at: Option<DateTime<Utc>>,
     pub finished_at: Option<DateTime<Utc>>,
+    /// When the run was queued for a worker.
+    pub queued_at: Option<DateTime<Utc>>,
+    /// Seconds from start to finish, or so far while running.
+    pub duration_secs: Option<f64>,
+    /// Seconds spent queued before starting, or so far while queued.
+    pub queued_secs: Option<f64>,
+    /// Identifies the trigger the run was created for; a second request
+    /// with the same key gets this run instead of a new one.
+    pub idempotency_key: Option<String>,
+    /// Set while the run is archived: kept, but left out of run lists.
+    pub archived_at: Option<DateTime<Utc>>,
+}
+
+/// A run with the name of its pipeline, for tenant-wide run lists.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TenantRunRecord {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub run: PipelineRunRecord,
+    pub pipeline_name: String,
 }
 
 /// A pipeline stage definition (template).
@@ -47,6 +121,80 @@ pub struct PipelineStageRecord {
     pub env: serde_json::Value,
     pub timeout_seconds: Option<i32>,
     pub created_at: DateTime<Utc>,
+    /// Executor labels the stage must run on.
+    pub runs_on: Vec<String>,
+    /// CPU and memory the stage's job requests.
+    pub resources: serde_json::Value,
+    /// Platform the stage runs on, e.g. `windows/amd64`.
+    pub platform: String,
+    /// JUnit XML reports the stage writes, as globs.
+    pub test_reports: Vec<String>,
+    /// What the stage scans, if it is a scan stage.
+    pub scan: Option<serde_json::Value>,
+    /// Conditions the stage's job waits for before its commands run.
+    pub wait_for: serde_json::Value,
+    /// What the stage publishes, if it is a release stage.
+    pub release: Option<serde_json::Value>,
+    /// What the stage starts, if it is a trigger-pipeline stage.
+    pub trigger_pipeline: Option<serde_json::Value>,
+    /// Artifacts of other pipelines the stage fetches into its workspace.
+    pub needs_artifacts: serde_json::Value,
+    /// Overrides of the executor's default security context.
+    pub security_context: Option<serde_json::Value>,
+    /// How the stage's container is started.
+    pub container: serde_json::Value,
+    /// When the stage's image is pulled, e.g. `if-not-present`.
+    pub pull_policy: Option<String>,
+}
+
+impl PipelineStageRecord {
+    /// What the stage scans, if it is a scan stage.
+    pub fn scan(&self) -> Option<ScanSpec> {
+        self.scan
+            .clone()
+            .and_then(|scan| serde_json::from_value(scan).ok())
+    }
+
+    /// What the stage publishes, if it is a release stage.
+    pub fn release(&self) -> Option<ReleaseSpec> {
+        self.release
+            .clone()
+            .and_then(|release| serde_json::from_value(release).ok())
+    }
+
+    /// What the stage starts, if it is a trigger-pipeline stage.
+    pub fn trigger_pipeline(&self) -> Option<TriggerPipelineSpec> {
+        self.trigger_pipeline
+            .clone()
+            .and_then(|trigger| serde_json::from_value(trigger).ok())
+    }
+
+    /// Conditions the stage's job waits for before its commands run.
+    pub fn wait_for(&self) -> Vec<WaitCondition> {
+        serde_json::from_value(self.wait_for.clone()).unwrap_or_default()
+    }
+
+    /// Artifacts of other pipelines the stage fetches into its workspace.
+    pub fn needs_artifacts(&self) -> Vec<ArtifactDependency> {
+        serde_json::from_value(self.needs_artifacts.clone()).unwrap_or_default()
+    }
+
+    /// Overrides of the executor's default security context.
+    pub fn security_context(&self) -> Option<SecurityContext> {
+        self.security_context
+            .clone()
+            .and_then(|context| serde_json::from_value(context).ok())
+    }
+
+    /// How the stage's container is started.
+    pub fn container(&self) -> ContainerOptions {
+        serde_json::from_value(self.container.clone()).unwrap_or_default()
+    }
+
+    /// When the stage's image is pulled.
+    pub fn pull_policy(&self) -> Option<PullPolicy> {
+        self.pull_policy.as_deref().and_then(|p| p.parse().ok())
+    }
 }
 
 /// A stage result record (run instance of a stage).
@@ -58,9 +206,92 @@ pub struct StageResultRecord {
     pub status: String,
     pub job_id: Option<uuid::Uuid>,
     pub deployment_id: Option<uuid::Uuid>,
+    /// When the stage's job was dispatched to an executor.
     pub started_at: Option<DateTime<Utc>>,
     pub finished_at: Option<DateTime<Utc>>,
     pub error_message: Option<String>,
+    pub stalled_at: Option<DateTime<Utc>>,
+    /// Executor-specific ID of the stage's job (e.g. container ID).
+    pub executor_id: Option<String>,
+    /// Name of the executor running the stage's job.
+    pub executor_name: Option<String>,
+    /// How the stage's job was routed to its executor.
+    pub routing: Option<serde_json::Value>,
+    /// When the stage's dependencies were met and it waited for an executor.
+    pub queued_at: Option<DateTime<Utc>>,
+    /// Exit code of the stage's job, once it has exited.
+    pub exit_code: Option<i32>,
+    /// Run a `reused` stage's result was taken from.
+    pub reused_from: Option<uuid::Uuid>,
+    /// Image the stage's job ran, pinned to its digest
+    /// (`name@sha256:...`), when its tag could be resolved.
+    pub image_digest: Option<String>,
+    /// Seconds from start to finish, or so far while running.
+    pub duration_secs: Option<f64>,
+    /// Seconds spent waiting for an executor, or so far while waiting.
+    pub queued_secs: Option<f64>,
+}
+
+impl StageResultRecord {
+    /// Handle to the job running this stage, if one was dispatched.
+    pub fn job_handle(&self) -> Option<JobHandle> {
+        Some(JobHandle {
+            id: ResourceId::from_uuid(self.job_id?),
+            executor_id: self.executor_id.clone()?,
+            executor_name: self.executor_name.clone()?,
+        })
+    }
+}
+
+/// A debug session: an idle copy of a failed stage's job to open a shell in.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct DebugSessionRecord {
+    pub id: uuid::Uuid,
+    pub tenant_id: uuid::Uuid,
+    pub pipeline_run_id: uuid::Uuid,
+    pub stage_name: String,
+    pub created_by: Option<uuid::Uuid>,
+    /// `starting`, `ready`, `closed`, `expired` or `failed`.
+    pub status: String,
+    pub job_id: Option<uuid::Uuid>,
+    pub executor_id: Option<String>,
+    pub executor_name: Option<String>,
+    pub error_message: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+impl DebugSessionRecord {
+    /// Handle to the session's job, once it was spawned.
+    pub fn job_handle(&self) -> Option<JobHandle> {
+        Some(JobHandle {
+            id: ResourceId::from_uuid(self.job_id?),
+            executor_id: self.executor_id.clone()?,
+            executor_name: self.executor_name.clone()?,
+        })
+    }
+
+    /// Whether the session is over and its job gone or going.
+    pub fn is_finished(&self) -> bool {
+        self.finished_at.is_some()
+    }
+}
+
+/// A running stage, checked by the watchdog against its stuck threshold.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct RunningStageRecord {
+    pub pipeline_run_id: uuid::Uuid,
+    pub run_number: i64,
+    pub pipeline_id: uuid::Uuid,
+    pub pipeline_name: String,
+    pub stage_name: String,
+    pub started_at: DateTime<Utc>,
+    pub elapsed_seconds: f64,
+    /// p95 duration of recent successful runs of this stage, if any.
+    pub p95_seconds: Option<f64>,
+    /// Ownership of the pipeline, see [`PipelineRecord::ownership`].
+    pub owners: serde_json::Value,
 }
 
 #[async_trait]
@@ -73,27 +304,135 @@ pub trait PipelineRepo: Send + Sync {
         config: serde_json::Value,
     ) -> DbResult<PipelineRecord>;
     async fn get_by_id(&self, id: ResourceId) -> DbResult<PipelineRecord>;
+    /// Active (non-archived) pipelines of a tenant.
     async fn list_by_tenant(&self, tenant_id: ResourceId) -> DbResult<Vec<PipelineRecord>>;
+    async fn list_archived(&self, tenant_id: ResourceId) -> DbResult<Vec<PipelineRecord>>;
+    /// Active (non-archived) pipelines built from a repository.
     async fn list_by_repository(&self, repository_id: ResourceId) -> DbResult<Vec<PipelineRecord>>;
+    /// Create the pipeline synced from a file in a repository, or update
+    /// its name and config if it exists, archived or not. The flag tells
+    /// whether it was created; a name another of the tenant's pipelines
+    /// has fails with `Duplicate`.
+    async fn upsert_repository_pipeline(
+        &self,
+        tenant_id: ResourceId,
+        repository_id: ResourceId,
+        config_path: &str,
+        name: &str,
+        repository: &str,
+        config: serde_json::Value,
+    ) -> DbResult<(PipelineRecord, bool)>;
     async fn update_config(
         &self,
         id: ResourceId,
         config: serde_json::Value,
     ) -> DbResult<PipelineRecord>;
+    async fn update_labels(&self, id: ResourceId, labels: &[String]) -> DbResult<()>;
+    async fn update_owners(&self, id: ResourceId, ownership: &Ownership) -> DbResult<()>;
+    /// Active or archived pipelines of a tenant with their last run and the
+    /// stats of their runs created from `since` on, in one query.
+    async fn list_summaries(
+        &self,
+        tenant_id: ResourceId,
+        archived: bool,
+        since: DateTime<Utc>,
+    ) -> DbResult<Vec<PipelineSummaryRecord>>;
+    /// Pipelines where `owner` appears as a pipeline or stage owner.
+    async fn list_by_owner(
+        &self,
+        tenant_id: ResourceId,
+        owner: &str,
+    ) -> DbResult<Vec<PipelineRecord>>;
+    /// Archive or unarchive a pipeline. Archiving cancels queued runs.
+    async fn set_archived(&self, id: ResourceId, archived: bool) -> DbResult<PipelineRecord>;
     async fn delete(&self, id: ResourceId) -> DbResult<()>;
+    /// Fuzzy search over name, repository and labels.
+    ///
+    /// An empty query returns the most recently active pipelines.
+    async fn search(
+        &self,
+        tenant_id: ResourceId,
+        query: &str,
+        limit: i64,
+    ) -> DbResult<Vec<PipelineSearchRecord>>;
 
+    /// Queue a run with a pending result for each of the pipeline's stages.
     async fn create_run(
         &self,
         pipeline_id: ResourceId,
         trigger_info: serde_json::Value,
         git_info: serde_json::Value,
     ) -> DbResult<PipelineRunRecord>;
+    /// Queue a run unless the pipeline already has one with the same
+    /// idempotency key, which is returned instead. The flag tells whether
+    /// the run was created.
+    async fn create_run_once(
+        &self,
+        pipeline_id: ResourceId,
+        idempotency_key: &str,
+        trigger_info: serde_json::Value,
+        git_info: serde_json::Value,
+    ) -> DbResult<(PipelineRunRecord, bool)>;
+    /// The pipeline's run created with an idempotency key.
+    async fn get_run_by_key(
+        &self,
+        pipeline_id: ResourceId,
+        idempotency_key: &str,
+    ) -> DbResult<Option<PipelineRunRecord>>;
     async fn get_run(&self, id: ResourceId) -> DbResult<PipelineRunRecord>;
+    /// The pipeline's run with this number.
+    async fn get_run_by_number(
+        &self,
+        pipeline_id: ResourceId,
+        number: i64,
+    ) -> DbResult<PipelineRunRecord>;
+    /// Archive or unarchive a run. Only finished runs can be archived.
+    async fn set_run_archived(&self, id: ResourceId, archived: bool)
+    -> DbResult<PipelineRunRecord>;
+    /// Archived runs of a pipeline, most recently archived first.
+    async fn list_archived_runs(
+        &self,
+        pipeline_id: ResourceId,
+        limit: i64,
+    ) -> DbResult<Vec<PipelineRunRecord>>;
+    /// Finished runs archived before `cutoff`, or of pipelines archived
+    /// before it, oldest first. Runs with pinned artifacts are kept.
+    async fn runs_due_for_purge(
+        &self,
+        cutoff: DateTime<Utc>,
+        limit: i64,
+    ) -> DbResult<Vec<PipelineRunRecord>>;
+    /// Delete a run and everything recorded about it.
+    async fn delete_run(&self, id: ResourceId) -> DbResult<()>;
+    /// Delete the pipelines archived before `cutoff` that have no runs
+    /// left. Returns how many were deleted.
+    async fn purge_archived_pipelines(&self, cutoff: DateTime<Utc>) -> DbResult<u64>;
+    /// Most recent unarchived runs of the tenant's active pipelines.
+    async fn list_tenant_runs(
+        &self,
+        tenant_id: ResourceId,
+        limit: i64,
+    ) -> DbResult<Vec<TenantRunRecord>>;
     async fn list_runs(
         &self,
         pipeline_id: ResourceId,
         limit: i64,
     ) -> DbResult<Vec<PipelineRunRecord>>;
+    /// Runs created after `since` (exclusive) up to `until` (inclusive),
+    /// oldest first.
+    async fn list_runs_between(
+        &self,
+        pipeline_id: ResourceId,
+        since: Option<DateTime<Utc>>,
+        until: DateTime<Utc>,
+    ) -> DbResult<Vec<PipelineRunRecord>>;
+    /// Most recent run of a branch, optionally only one with `status`.
+    async fn latest_branch_run(
+        &self,
+        pipeline_id: ResourceId,
+        branch: &str,
+        status: Option<&str>,
+    ) -> DbResult<Option<PipelineRunRecord>>;
     async fn update_run_status(&self, id: ResourceId, status: &str) -> DbResult<()>;
 
     // Stage definition methods
@@ -107,6 +446,18 @@ pub trait PipelineRepo: Send + Sync {
         depends_on: &[String],
         env: serde_json::Value,
         timeout_seconds: Option<i32>,
+        runs_on: &[String],
+        resources: serde_json::Value,
+        platform: &str,
+        test_reports: &[String],
+        scan: Option<serde_json::Value>,
+        wait_for: serde_json::Value,
+        release: Option<serde_json::Value>,
+        trigger_pipeline: Option<serde_json::Value>,
+        needs_artifacts: serde_json::Value,
+        security_context: Option<serde_json::Value>,
+        container: serde_json::Value,
+        pull_policy: Option<&str>,
     ) -> DbResult<PipelineStageRecord>;
     async fn delete_stages(&self, pipeline_id: ResourceId) -> DbResult<()>;
 
@@ -117,19 +468,72 @@ pub trait PipelineRepo: Send + Sync {
         run_id: ResourceId,
         stage_name: &str,
     ) -> DbResult<StageResultRecord>;
-    async fn update_stage_result_started(
+    /// Mark a stage running once its dependencies are met; it is queued
+    /// until its job is dispatched.
+    async fn update_stage_result_queued(
         &self,
         run_id: ResourceId,
         stage_name: &str,
-        job_id: Option<ResourceId>,
     ) -> DbResult<()>;
+    /// Record how a stage ended: `succeeded`, `failed`, `skipped` or
+    /// `stalled`, with its job's exit code if it exited.
     async fn update_stage_result_finished(
         &self,
         run_id: ResourceId,
         stage_name: &str,
         status: &str,
+        exit_code: Option<i32>,
         error_message: Option<&str>,
     ) -> DbResult<()>;
+    /// Mark stages of a re-run as `reused` from the run it re-runs, with
+    /// their exit codes and images there, so they are not executed again.
+    async fn reuse_stage_results(
+        &self,
+        run_id: ResourceId,
+        from_run_id: ResourceId,
+        stage_names: &[String],
+    ) -> DbResult<()>;
+    /// Record the job dispatched for a stage, so it can be re-attached to,
+    /// how it was routed and the pinned image it runs. The stage starts
+    /// with it.
+    async fn record_stage_job(
+        &self,
+        run_id: ResourceId,
+        stage_name: &str,
+        handle: &JobHandle,
+        routing: serde_json::Value,
+        image_digest: Option<&str>,
+    ) -> DbResult<()>;
+
+    // Stuck run detection
+    /// Running stages not yet flagged as stalled, with the p95 duration of
+    /// their recent successful runs.
+    async fn find_running_stages(&self) -> DbResult<Vec<RunningStageRecord>>;
+    async fn mark_stage_stalled(&self, run_id: ResourceId, stage_name: &str) -> DbResult<()>;
+
+    // Debug sessions
+    async fn create_debug_session(
+        &self,
+        tenant_id: ResourceId,
+        run_id: ResourceId,
+        stage_name: &str,
+        created_by: Option<uuid::Uuid>,
+        expires_at: DateTime<Utc>,
+    ) -> DbResult<DebugSessionRecord>;
+    async fn get_debug_session(&self, id: ResourceId) -> DbResult<DebugSessionRecord>;
+    /// Debug sessions of a run, newest first.
+    async fn list_debug_sessions(&self, run_id: ResourceId) -> DbResult<Vec<DebugSessionRecord>>;
+    /// Record the session's job; the session is ready unless it finished
+    /// in the meantime.
+    async fn record_debug_job(&self, id: ResourceId, handle: &JobHandle) -> DbResult<()>;
+    /// End a session as `closed`, `expired` or `failed`. A session that
+    /// already ended keeps its status. Returns whether this ended it.
+    async fn finish_debug_session(
+        &self,
+        id: ResourceId,
+        status: &str,
+        error_message: Option<&str>,
+    ) -> DbResult<bool>;
 }
 
 /// PostgreSQL implementation of PipelineRepo.
@@ -141,8 +545,84 @@ impl PgPipelineRepo {
     pub fn new(pool: PgPool) -> Self {
         Self { pool }
     }
+
+    /// Insert a queued run with a pending result for each stage. Nothing
+    /// is inserted, and `None` returned, when the pipeline is missing or
+    /// archived, or already has a run with the idempotency key.
+    async fn insert_run(
+        &self,
+        pipeline_id: ResourceId,
+        idempotency_key: Option<&str>,
+        trigger_info: serde_json::Value,
+        git_info: serde_json::Value,
+    ) -> DbResult<Option<PipelineRunRecord>> {
+        let record = sqlx::query_as::<_, PipelineRunRecord>(&format!(
+            r#"
+            WITH run AS (
+                INSERT INTO pipeline_runs (id, pipeline_id, number, status, trigger_info, git_info, idempotency_key, created_at, queued_at)
+                SELECT $1, p.id, (SELECT COALESCE(MAX(number), 0) + 1 FROM pipeline_runs WHERE pipeline_id = $2), 'queued', $3, $4, $5, NOW(), NOW()
+                FROM pipelines p
+                WHERE p.id = $2 AND p.archived_at IS NULL
+                ON CONFLICT (pipeline_id, idempotency_key) WHERE idempotency_key IS NOT NULL DO NOTHING
+                RETURNING *
+            ),
+            planned AS (
+                INSERT INTO stage_results (id, pipeline_run_id, stage_name, status)
+                SELECT gen_random_uuid(), run.id, s.name, 'pending'
+                FROM run
+                JOIN pipeline_stages s ON s.pipeline_id = run.pipeline_id
+            )
+            SELECT {RUN_COLUMNS} FROM run AS pipeline_runs
+            "#
+        ))
+        .bind(uuid::Uuid::now_v7())
+        .bind(pipeline_id.as_uuid())
+        .bind(trigger_info)
+        .bind(git_info)
+        .bind(idempotency_key)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(record)
+    }
+
+    /// Why no run could be created: the pipeline is missing or archived.
+    async fn not_runnable(&self, pipeline_id: ResourceId) -> DbError {
+        match self.get_by_id(pipeline_id).await {
+            Ok(_) => DbError::Conflict(format!("pipeline {} is archived", pipeline_id)),
+            Err(e) => e,
+        }
+    }
 }
 
+/// Columns of [`PipelineRunRecord`]: a run with its duration and queue wait.
+const RUN_COLUMNS: &str = r#"
+    pipeline_runs.*,
+    EXTRACT(EPOCH FROM COALESCE(
+        pipeline_runs.finished_at,
+        CASE WHEN pipeline_runs.status = 'running' THEN NOW() END
+    ) - pipeline_runs.started_at)::float8 AS duration_secs,
+    EXTRACT(EPOCH FROM COALESCE(
+        pipeline_runs.started_at,
+        pipeline_runs.finished_at,
+        CASE WHEN pipeline_runs.status = 'queued' THEN NOW() END
+    ) - pipeline_runs.queued_at)::float8 AS queued_secs
+"#;
+
+/// Columns of [`StageResultRecord`]: a stage with its duration and queue
+/// wait.
+const STAGE_RESULT_COLUMNS: &str = r#"
+    stage_results.*,
+    EXTRACT(EPOCH FROM COALESCE(
+        stage_results.finished_at,
+        CASE WHEN stage_results.status = 'running' THEN NOW() END
+    ) - stage_results.started_at)::float8 AS duration_secs,
+    EXTRACT(EPOCH FROM COALESCE(
+        stage_results.started_at,
+        stage_results.finished_at,
+        CASE WHEN stage_results.status = 'running' THEN NOW() END
+    ) - stage_results.queued_at)::float8 AS queued_secs
+"#;
+
 #[async_trait]
 impl PipelineRepo for PgPipelineRepo {
     async fn create(
@@ -180,7 +660,17 @@ impl PipelineRepo for PgPipelineRepo {
 
     async fn list_by_tenant(&self, tenant_id: ResourceId) -> DbResult<Vec<PipelineRecord>> {
         let records = sqlx::query_as::<_, PipelineRecord>(
-            "SELECT * FROM pipelines WHERE tenant_id = $1 ORDER BY name",
+            "SELECT * FROM pipelines WHERE tenant_id = $1 AND archived_at IS NULL ORDER BY name",
+        )
+        .bind(tenant_id.as_uuid())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn list_archived(&self, tenant_id: ResourceId) -> DbResult<Vec<PipelineRecord>> {
+        let records = sqlx::query_as::<_, PipelineRecord>(
+            "SELECT * FROM pipelines WHERE tenant_id = $1 AND archived_at IS NOT NULL ORDER BY name",
         )
         .bind(tenant_id.as_uuid())
         .fetch_all(&self.pool)
@@ -190,7 +680,7 @@ impl PipelineRepo for PgPipelineRepo {
 
     async fn list_by_repository(&self, repository_id: ResourceId) -> DbResult<Vec<PipelineRecord>> {
         let records = sqlx::query_as::<_, PipelineRecord>(
-            "SELECT * FROM pipelines WHERE repository_id = $1 ORDER BY name",
+            "SELECT * FROM pipelines WHERE repository_id = $1 AND archived_at IS NULL ORDER BY name",
         )
         .bind(repository_id.as_uuid())
         .fetch_all(&self.pool)
@@ -198,6 +688,61 @@ impl PipelineRepo for PgPipelineRepo {
         Ok(records)
     }
 
+    async fn upsert_repository_pipeline(
+        &self,
+        tenant_id: ResourceId,
+        repository_id: ResourceId,
+        config_path: &str,
+        name: &str,
+        repository: &str,
+        config: serde_json::Value,
+    ) -> DbResult<(PipelineRecord, bool)> {
+        let duplicate = |e: sqlx::Error| match e {
+            sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+                DbError::Duplicate(format!("pipeline {} already exists", name))
+            }
+            e => e.into(),
+        };
+        let updated = sqlx::query_as::<_, PipelineRecord>(
+            r#"
+            UPDATE pipelines SET name = $3, repository = $4, config = $5, updated_at = NOW()
+            WHERE repository_id = $1 AND config_path = $2
+            RETURNING *
+            "#,
+        )
+        .bind(repository_id.as_uuid())
+        .bind(config_path)
+        .bind(name)
+        .bind(repository)
+        .bind(&config)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(duplicate)?;
+        if let Some(record) = updated {
+            return Ok((record, false));
+        }
+
+        let record = sqlx::query_as::<_, PipelineRecord>(
+            r#"
+            INSERT INTO pipelines
+                (id, tenant_id, name, repository, repository_id, config_path, config, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, NOW(), NOW())
+            RETURNING *
+            "#,
+        )
+        .bind(uuid::Uuid::now_v7())
+        .bind(tenant_id.as_uuid())
+        .bind(name)
+        .bind(repository)
+        .bind(repository_id.as_uuid())
+        .bind(config_path)
+        .bind(&config)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(duplicate)?;
+        Ok((record, true))
+    }
+
     async fn update_config(
         &self,
         id: ResourceId,
@@ -218,6 +763,120 @@ impl PipelineRepo for PgPipelineRepo {
         Ok(record)
     }
 
+    async fn update_labels(&self, id: ResourceId, labels: &[String]) -> DbResult<()> {
+        sqlx::query("UPDATE pipelines SET labels = $2, updated_at = NOW() WHERE id = $1")
+            .bind(id.as_uuid())
+            .bind(labels)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn update_owners(&self, id: ResourceId, ownership: &Ownership) -> DbResult<()> {
+        sqlx::query(
+            "UPDATE pipelines SET owners = $2, owner_list = $3, updated_at = NOW() WHERE id = $1",
+        )
+        .bind(id.as_uuid())
+        .bind(serde_json::to_value(ownership).map_err(|e| DbError::InvalidData(e.to_string()))?)
+        .bind(ownership.all_owners())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn list_summaries(
+        &self,
+        tenant_id: ResourceId,
+        archived: bool,
+        since: DateTime<Utc>,
+    ) -> DbResult<Vec<PipelineSummaryRecord>> {
+        let records = sqlx::query_as::<_, PipelineSummaryRecord>(
+            r#"
+            SELECT p.*,
+                   last_run.id AS last_run_id,
+                   last_run.number AS last_run_number,
+                   last_run.status AS last_run_status,
+                   last_run.created_at AS last_run_at,
+                   stats.total_runs, stats.success_rate, stats.p50_duration_secs
+            FROM pipelines p
+            LEFT JOIN LATERAL (
+                SELECT id, number, status, created_at FROM pipeline_runs
+                WHERE pipeline_id = p.id
+                ORDER BY number DESC
+                LIMIT 1
+            ) last_run ON TRUE
+            CROSS JOIN LATERAL (
+                SELECT COUNT(*) AS total_runs,
+                       COUNT(*) FILTER (WHERE status = 'succeeded')::float8
+                           / NULLIF(COUNT(*) FILTER (WHERE status IN ('succeeded', 'failed', 'stalled')), 0)
+                           AS success_rate,
+                       percentile_cont(0.5) WITHIN GROUP (
+                           ORDER BY EXTRACT(EPOCH FROM finished_at - started_at)::float8
+                       ) FILTER (WHERE status IN ('succeeded', 'failed', 'stalled')) AS p50_duration_secs
+                FROM pipeline_runs
+                WHERE pipeline_id = p.id AND created_at >= $3
+            ) stats
+            WHERE p.tenant_id = $1 AND (p.archived_at IS NOT NULL) = $2
+            ORDER BY p.name
+            "#,
+        )
+        .bind(tenant_id.as_uuid())
+        .bind(archived)
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn list_by_owner(
+        &self,
+        tenant_id: ResourceId,
+        owner: &str,
+    ) -> DbResult<Vec<PipelineRecord>> {
+        let records = sqlx::query_as::<_, PipelineRecord>(
+            r#"
+            SELECT * FROM pipelines
+            WHERE tenant_id = $1 AND $2 = ANY(owner_list) AND archived_at IS NULL
+            ORDER BY name
+            "#,
+        )
+        .bind(tenant_id.as_uuid())
+        .bind(owner)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn set_archived(&self, id: ResourceId, archived: bool) -> DbResult<PipelineRecord> {
+        let record = sqlx::query_as::<_, PipelineRecord>(
+            r#"
+            UPDATE pipelines SET
+                archived_at = CASE WHEN $2 THEN COALESCE(archived_at, NOW()) END,
+                updated_at = NOW()
+            WHERE id = $1
+            RETURNING *
+            "#,
+        )
+        .bind(id.as_uuid())
+        .bind(archived)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("pipeline {}", id)))?;
+
+        if archived {
+            sqlx::query(
+                r#"
+                UPDATE pipeline_runs SET status = 'cancelled', finished_at = NOW()
+                WHERE pipeline_id = $1 AND status = 'queued'
+                "#,
+            )
+            .bind(id.as_uuid())
+            .execute(&self.pool)
+            .await?;
+        }
+        Ok(record)
+    }
+
     async fn delete(&self, id: ResourceId) -> DbResult<()> {
         sqlx::query("DELETE FROM pipelines WHERE id = $1")
             .bind(id.as_uuid())
@@ -226,46 +885,164 @@ impl PipelineRepo for PgPipelineRepo {
         Ok(())
     }
 
+    async fn search(
+        &self,
+        tenant_id: ResourceId,
+        query: &str,
+        limit: i64,
+    ) -> DbResult<Vec<PipelineSearchRecord>> {
+        // Score = best trigram similarity, boosted for prefix and exact label
+        // matches, plus a recency bonus that decays over days since the last run.
+        let records = sqlx::query_as::<_, PipelineSearchRecord>(
+            r#"
+            SELECT p.id, p.name, p.repository, p.labels,
+                   lr.status AS last_run_status, lr.created_at AS last_run_at,
+                   (GREATEST(similarity(p.name, $2), similarity(p.repository, $2) * 0.8)
+                    + CASE WHEN $2 <> '' AND p.name ILIKE $2 || '%' THEN 0.5 ELSE 0 END
+                    + CASE WHEN $2 = ANY(p.labels) THEN 0.4 ELSE 0 END
+                    + COALESCE(0.3 / (1 + EXTRACT(EPOCH FROM NOW() - lr.created_at) / 86400), 0)
+                   )::FLOAT8 AS score
+            FROM pipelines p
+            LEFT JOIN LATERAL (
+                SELECT r.status, r.created_at FROM pipeline_runs r
+                WHERE r.pipeline_id = p.id
+                ORDER BY r.created_at DESC
+                LIMIT 1
+            ) lr ON TRUE
+            WHERE p.tenant_id = $1
+              AND p.archived_at IS NULL
+              AND ($2 = ''
+                   OR p.name ILIKE '%' || $2 || '%'
+                   OR p.repository ILIKE '%' || $2 || '%'
+                   OR p.name % $2
+                   OR p.repository % $2
+                   OR $2 = ANY(p.labels))
+            ORDER BY score DESC, p.name
+            LIMIT $3
+            "#,
+        )
+        .bind(tenant_id.as_uuid())
+        .bind(query)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
     async fn create_run(
         &self,
         pipeline_id: ResourceId,
         trigger_info: serde_json::Value,
         git_info: serde_json::Value,
     ) -> DbResult<PipelineRunRecord> {
-        let record = sqlx::query_as::<_, PipelineRunRecord>(
-            r#"
-            INSERT INTO pipeline_runs (id, pipeline_id, number, status, trigger_info, git_info, created_at)
-            VALUES ($1, $2, (SELECT COALESCE(MAX(number), 0) + 1 FROM pipeline_runs WHERE pipeline_id = $2), 'queued', $3, $4, NOW())
-            RETURNING *
-            "#,
-        )
-        .bind(uuid::Uuid::now_v7())
+        match self
+            .insert_run(pipeline_id, None, trigger_info, git_info)
+            .await?
+        {
+            Some(record) => Ok(record),
+            None => Err(self.not_runnable(pipeline_id).await),
+        }
+    }
+
+    async fn create_run_once(
+        &self,
+        pipeline_id: ResourceId,
+        idempotency_key: &str,
+        trigger_info: serde_json::Value,
+        git_info: serde_json::Value,
+    ) -> DbResult<(PipelineRunRecord, bool)> {
+        if let Some(existing) = self.get_run_by_key(pipeline_id, idempotency_key).await? {
+            return Ok((existing, false));
+        }
+        if let Some(record) = self
+            .insert_run(pipeline_id, Some(idempotency_key), trigger_info, git_info)
+            .await?
+        {
+            return Ok((record, true));
+        }
+        // Nothing inserted: created concurrently, or the pipeline is gone
+        match self.get_run_by_key(pipeline_id, idempotency_key).await? {
+            Some(existing) => Ok((existing, false)),
+            None => Err(self.not_runnable(pipeline_id).await),
+        }
+    }
+
+    async fn get_run_by_key(
+        &self,
+        pipeline_id: ResourceId,
+        idempotency_key: &str,
+    ) -> DbResult<Option<PipelineRunRecord>> {
+        let record = sqlx::query_as::<_, PipelineRunRecord>(&format!(
+            "SELECT {RUN_COLUMNS} FROM pipeline_runs WHERE pipeline_id = $1 AND idempotency_key = $2"
+        ))
         .bind(pipeline_id.as_uuid())
-        .bind(trigger_info)
-        .bind(git_info)
-        .fetch_one(&self.pool)
+        .bind(idempotency_key)
+        .fetch_optional(&self.pool)
         .await?;
         Ok(record)
     }
 
     async fn get_run(&self, id: ResourceId) -> DbResult<PipelineRunRecord> {
-        let record =
-            sqlx::query_as::<_, PipelineRunRecord>("SELECT * FROM pipeline_runs WHERE id = $1")
-                .bind(id.as_uuid())
-                .fetch_optional(&self.pool)
-                .await?
-                .ok_or_else(|| DbError::NotFound(format!("pipeline run {}", id)))?;
+        let record = sqlx::query_as::<_, PipelineRunRecord>(&format!(
+            "SELECT {RUN_COLUMNS} FROM pipeline_runs WHERE id = $1"
+        ))
+        .bind(id.as_uuid())
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("pipeline run {}", id)))?;
         Ok(record)
     }
 
+    async fn get_run_by_number(
+        &self,
+        pipeline_id: ResourceId,
+        number: i64,
+    ) -> DbResult<PipelineRunRecord> {
+        let record = sqlx::query_as::<_, PipelineRunRecord>(&format!(
+            "SELECT {RUN_COLUMNS} FROM pipeline_runs WHERE pipeline_id = $1 AND number = $2"
+        ))
+        .bind(pipeline_id.as_uuid())
+        .bind(number)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("run #{} of pipeline {}", number, pipeline_id)))?;
+        Ok(record)
+    }
+
+    async fn list_tenant_runs(
+        &self,
+        tenant_id: ResourceId,
+        limit: i64,
+    ) -> DbResult<Vec<TenantRunRecord>> {
+        let records = sqlx::query_as::<_, TenantRunRecord>(&format!(
+            r#"
+            SELECT {RUN_COLUMNS}, p.name AS pipeline_name
+            FROM pipeline_runs
+            JOIN pipelines p ON p.id = pipeline_runs.pipeline_id
+            WHERE p.tenant_id = $1 AND p.archived_at IS NULL AND pipeline_runs.archived_at IS NULL
+            ORDER BY pipeline_runs.created_at DESC
+            LIMIT $2
+            "#
+        ))
+        .bind(tenant_id.as_uuid())
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
     async fn list_runs(
         &self,
         pipeline_id: ResourceId,
         limit: i64,
     ) -> DbResult<Vec<PipelineRunRecord>> {
-        let records = sqlx::query_as::<_, PipelineRunRecord>(
-            "SELECT * FROM pipeline_runs WHERE pipeline_id = $1 ORDER BY number DESC LIMIT $2",
-        )
+        let records = sqlx::query_as::<_, PipelineRunRecord>(&format!(
+            r#"
+            SELECT {RUN_COLUMNS} FROM pipeline_runs
+            WHERE pipeline_id = $1 AND archived_at IS NULL
+            ORDER BY number DESC LIMIT $2
+            "#
+        ))
         .bind(pipeline_id.as_uuid())
         .bind(limit)
         .fetch_all(&self.pool)
@@ -273,15 +1050,170 @@ impl PipelineRepo for PgPipelineRepo {
         Ok(records)
     }
 
-    async fn update_run_status(&self, id: ResourceId, status: &str) -> DbResult<()> {
-        sqlx::query("UPDATE pipeline_runs SET status = $2 WHERE id = $1")
+    async fn set_run_archived(
+        &self,
+        id: ResourceId,
+        archived: bool,
+    ) -> DbResult<PipelineRunRecord> {
+        let record = sqlx::query_as::<_, PipelineRunRecord>(&format!(
+            r#"
+            UPDATE pipeline_runs SET
+                archived_at = CASE WHEN $2 THEN COALESCE(archived_at, NOW()) END
+            WHERE id = $1 AND (NOT $2 OR finished_at IS NOT NULL)
+            RETURNING {RUN_COLUMNS}
+            "#
+        ))
+        .bind(id.as_uuid())
+        .bind(archived)
+        .fetch_optional(&self.pool)
+        .await?;
+        match record {
+            Some(record) => Ok(record),
+            None => {
+                self.get_run(id).await?;
+                Err(DbError::Conflict(format!(
+                    "run {} has not finished and cannot be archived",
+                    id
+                )))
+            }
+        }
+    }
+
+    async fn list_archived_runs(
+        &self,
+        pipeline_id: ResourceId,
+        limit: i64,
+    ) -> DbResult<Vec<PipelineRunRecord>> {
+        let records = sqlx::query_as::<_, PipelineRunRecord>(&format!(
+            r#"
+            SELECT {RUN_COLUMNS} FROM pipeline_runs
+            WHERE pipeline_id = $1 AND archived_at IS NOT NULL
+            ORDER BY archived_at DESC, number DESC LIMIT $2
+            "#
+        ))
+        .bind(pipeline_id.as_uuid())
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn runs_due_for_purge(
+        &self,
+        cutoff: DateTime<Utc>,
+        limit: i64,
+    ) -> DbResult<Vec<PipelineRunRecord>> {
+        let records = sqlx::query_as::<_, PipelineRunRecord>(&format!(
+            r#"
+            SELECT {RUN_COLUMNS} FROM pipeline_runs
+            JOIN pipelines p ON p.id = pipeline_runs.pipeline_id
+            WHERE (pipeline_runs.archived_at < $1 OR p.archived_at < $1)
+              AND pipeline_runs.finished_at IS NOT NULL
+              AND NOT EXISTS (
+                  SELECT 1 FROM run_artifacts a
+                  WHERE a.pipeline_run_id = pipeline_runs.id AND a.pinned
+              )
+            ORDER BY LEAST(pipeline_runs.archived_at, p.archived_at)
+            LIMIT $2
+            "#
+        ))
+        .bind(cutoff)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn delete_run(&self, id: ResourceId) -> DbResult<()> {
+        sqlx::query("DELETE FROM pipeline_runs WHERE id = $1")
             .bind(id.as_uuid())
-            .bind(status)
             .execute(&self.pool)
             .await?;
         Ok(())
     }
 
+    async fn purge_archived_pipelines(&self, cutoff: DateTime<Utc>) -> DbResult<u64> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM pipelines p
+            WHERE p.archived_at < $1
+              AND NOT EXISTS (SELECT 1 FROM pipeline_runs r WHERE r.pipeline_id = p.id)
+            "#,
+        )
+        .bind(cutoff)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn list_runs_between(
+        &self,
+        pipeline_id: ResourceId,
+        since: Option<DateTime<Utc>>,
+        until: DateTime<Utc>,
+    ) -> DbResult<Vec<PipelineRunRecord>> {
+        let records = sqlx::query_as::<_, PipelineRunRecord>(&format!(
+            r#"
+            SELECT {RUN_COLUMNS} FROM pipeline_runs
+            WHERE pipeline_id = $1
+              AND ($2::timestamptz IS NULL OR created_at > $2)
+              AND created_at <= $3
+            ORDER BY created_at ASC
+            "#
+        ))
+        .bind(pipeline_id.as_uuid())
+        .bind(since)
+        .bind(until)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn latest_branch_run(
+        &self,
+        pipeline_id: ResourceId,
+        branch: &str,
+        status: Option<&str>,
+    ) -> DbResult<Option<PipelineRunRecord>> {
+        let record = sqlx::query_as::<_, PipelineRunRecord>(&format!(
+            r#"
+            SELECT {RUN_COLUMNS} FROM pipeline_runs
+            WHERE pipeline_id = $1
+              AND git_info->>'branch' = $2
+              AND ($3::text IS NULL OR status = $3)
+            ORDER BY number DESC
+            LIMIT 1
+            "#
+        ))
+        .bind(pipeline_id.as_uuid())
+        .bind(branch)
+        .bind(status)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn update_run_status(&self, id: ResourceId, status: &str) -> DbResult<()> {
+        // A stalled run was cancelled by the watchdog; keep that status. A
+        // resumed run keeps the time it first started.
+        sqlx::query(
+            r#"
+            UPDATE pipeline_runs
+            SET status = $2,
+                started_at = CASE WHEN $2 = 'running' THEN COALESCE(started_at, NOW())
+                                  ELSE started_at END,
+                finished_at = CASE WHEN $2 IN ('succeeded', 'failed', 'cancelled', 'stalled')
+                                   THEN NOW() ELSE finished_at END
+            WHERE id = $1 AND status <> 'stalled'
+            "#,
+        )
+        .bind(id.as_uuid())
+        .bind(status)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
     async fn list_stages(&self, pipeline_id: ResourceId) -> DbResult<Vec<PipelineStageRecord>> {
         let records = sqlx::query_as::<_, PipelineStageRecord>(
             "SELECT * FROM pipeline_stages WHERE pipeline_id = $1 ORDER BY created_at",
@@ -301,11 +1233,23 @@ impl PipelineRepo for PgPipelineRepo {
         depends_on: &[String],
         env: serde_json::Value,
         timeout_seconds: Option<i32>,
+        runs_on: &[String],
+        resources: serde_json::Value,
+        platform: &str,
+        test_reports: &[String],
+        scan: Option<serde_json::Value>,
+        wait_for: serde_json::Value,
+        release: Option<serde_json::Value>,
+        trigger_pipeline: Option<serde_json::Value>,
+        needs_artifacts: serde_json::Value,
+        security_context: Option<serde_json::Value>,
+        container: serde_json::Value,
+        pull_policy: Option<&str>,
     ) -> DbResult<PipelineStageRecord> {
         let record = sqlx::query_as::<_, PipelineStageRecord>(
             r#"
-            INSERT INTO pipeline_stages (id, pipeline_id, name, image, commands, depends_on, env, timeout_seconds, created_at)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, NOW())
+            INSERT INTO pipeline_stages (id, pipeline_id, name, image, commands, depends_on, env, timeout_seconds, runs_on, resources, platform, test_reports, scan, wait_for, release, trigger_pipeline, needs_artifacts, security_context, container, pull_policy, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, NOW())
             RETURNING *
             "#,
         )
@@ -317,6 +1261,18 @@ impl PipelineRepo for PgPipelineRepo {
         .bind(depends_on)
         .bind(env)
         .bind(timeout_seconds)
+        .bind(runs_on)
+        .bind(resources)
+        .bind(platform)
+        .bind(test_reports)
+        .bind(scan)
+        .bind(wait_for)
+        .bind(release)
+        .bind(trigger_pipeline)
+        .bind(needs_artifacts)
+        .bind(security_context)
+        .bind(container)
+        .bind(pull_policy)
         .fetch_one(&self.pool)
         .await?;
         Ok(record)
@@ -331,9 +1287,9 @@ impl PipelineRepo for PgPipelineRepo {
     }
 
     async fn list_stage_results(&self, run_id: ResourceId) -> DbResult<Vec<StageResultRecord>> {
-        let records = sqlx::query_as::<_, StageResultRecord>(
-            "SELECT * FROM stage_results WHERE pipeline_run_id = $1 ORDER BY started_at NULLS LAST",
-        )
+        let records = sqlx::query_as::<_, StageResultRecord>(&format!(
+            "SELECT {STAGE_RESULT_COLUMNS} FROM stage_results WHERE pipeline_run_id = $1 ORDER BY COALESCE(started_at, queued_at) NULLS LAST"
+        ))
         .bind(run_id.as_uuid())
         .fetch_all(&self.pool)
         .await?;
@@ -345,13 +1301,13 @@ impl PipelineRepo for PgPipelineRepo {
         run_id: ResourceId,
         stage_name: &str,
     ) -> DbResult<StageResultRecord> {
-        let record = sqlx::query_as::<_, StageResultRecord>(
+        let record = sqlx::query_as::<_, StageResultRecord>(&format!(
             r#"
             INSERT INTO stage_results (id, pipeline_run_id, stage_name, status)
             VALUES ($1, $2, $3, 'pending')
-            RETURNING *
-            "#,
-        )
+            RETURNING {STAGE_RESULT_COLUMNS}
+            "#
+        ))
         .bind(uuid::Uuid::now_v7())
         .bind(run_id.as_uuid())
         .bind(stage_name)
@@ -360,22 +1316,21 @@ impl PipelineRepo for PgPipelineRepo {
         Ok(record)
     }
 
-    async fn update_stage_result_started(
+    async fn update_stage_result_queued(
         &self,
         run_id: ResourceId,
         stage_name: &str,
-        job_id: Option<ResourceId>,
     ) -> DbResult<()> {
         sqlx::query(
             r#"
             UPDATE stage_results
-            SET status = 'running', started_at = NOW(), job_id = $3
+            SET status = 'running', queued_at = NOW(), started_at = NULL, finished_at = NULL,
+                exit_code = NULL, error_message = NULL
             WHERE pipeline_run_id = $1 AND stage_name = $2
             "#,
         )
         .bind(run_id.as_uuid())
         .bind(stage_name)
-        .bind(job_id.map(|j| *j.as_uuid()))
         .execute(&self.pool)
         .await?;
         Ok(())
@@ -386,21 +1341,210 @@ impl PipelineRepo for PgPipelineRepo {
         run_id: ResourceId,
         stage_name: &str,
         status: &str,
+        exit_code: Option<i32>,
         error_message: Option<&str>,
     ) -> DbResult<()> {
         sqlx::query(
             r#"
             UPDATE stage_results
-            SET status = $3, finished_at = NOW(), error_message = $4
-            WHERE pipeline_run_id = $1 AND stage_name = $2
+            SET status = $3, finished_at = NOW(), exit_code = $4, error_message = $5
+            WHERE pipeline_run_id = $1 AND stage_name = $2 AND status <> 'stalled'
             "#,
         )
         .bind(run_id.as_uuid())
         .bind(stage_name)
         .bind(status)
+        .bind(exit_code)
         .bind(error_message)
         .execute(&self.pool)
         .await?;
         Ok(())
     }
+
+    async fn reuse_stage_results(
+        &self,
+        run_id: ResourceId,
+        from_run_id: ResourceId,
+        stage_names: &[String],
+    ) -> DbResult<()> {
+        sqlx::query(
+            r#"
+            UPDATE stage_results sr
+            SET status = 'reused', reused_from = $2, exit_code = prev.exit_code,
+                image_digest = prev.image_digest
+            FROM stage_results prev
+            WHERE sr.pipeline_run_id = $1 AND sr.stage_name = ANY($3)
+              AND prev.pipeline_run_id = $2 AND prev.stage_name = sr.stage_name
+            "#,
+        )
+        .bind(run_id.as_uuid())
+        .bind(from_run_id.as_uuid())
+        .bind(stage_names)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn record_stage_job(
+        &self,
+        run_id: ResourceId,
+        stage_name: &str,
+        handle: &JobHandle,
+        routing: serde_json::Value,
+        image_digest: Option<&str>,
+    ) -> DbResult<()> {
+        sqlx::query(
+            r#"
+            UPDATE stage_results
+            SET job_id = $3, executor_id = $4, executor_name = $5, routing = $6,
+                image_digest = $7, started_at = NOW()
+            WHERE pipeline_run_id = $1 AND stage_name = $2
+            "#,
+        )
+        .bind(run_id.as_uuid())
+        .bind(stage_name)
+        .bind(handle.id.as_uuid())
+        .bind(&handle.executor_id)
+        .bind(&handle.executor_name)
+        .bind(routing)
+        .bind(image_digest)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn find_running_stages(&self) -> DbResult<Vec<RunningStageRecord>> {
+        let stages = sqlx::query_as::<_, RunningStageRecord>(
+            r#"
+            WITH running AS (
+                SELECT sr.pipeline_run_id, pr.number AS run_number, pr.pipeline_id,
+                       p.name AS pipeline_name, p.owners, sr.stage_name,
+                       COALESCE(sr.started_at, sr.queued_at) AS started_at,
+                       EXTRACT(EPOCH FROM NOW() - COALESCE(sr.started_at, sr.queued_at))::float8
+                           AS elapsed_seconds
+                FROM stage_results sr
+                JOIN pipeline_runs pr ON pr.id = sr.pipeline_run_id
+                JOIN pipelines p ON p.id = pr.pipeline_id
+                WHERE sr.status = 'running'
+                  AND COALESCE(sr.started_at, sr.queued_at) IS NOT NULL
+                  AND sr.stalled_at IS NULL
+            )
+            SELECT r.*, h.p95_seconds
+            FROM running r
+            LEFT JOIN LATERAL (
+                SELECT percentile_cont(0.95) WITHIN GROUP (ORDER BY d)::float8 AS p95_seconds
+                FROM (
+                    SELECT EXTRACT(EPOCH FROM s.finished_at - s.started_at) AS d
+                    FROM stage_results s
+                    JOIN pipeline_runs hr ON hr.id = s.pipeline_run_id
+                    WHERE hr.pipeline_id = r.pipeline_id
+                      AND s.stage_name = r.stage_name
+                      AND s.status = 'succeeded'
+                      AND s.started_at IS NOT NULL
+                      AND s.finished_at IS NOT NULL
+                    ORDER BY s.finished_at DESC
+                    LIMIT 50
+                ) recent
+            ) h ON TRUE
+            ORDER BY r.started_at
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(stages)
+    }
+
+    async fn mark_stage_stalled(&self, run_id: ResourceId, stage_name: &str) -> DbResult<()> {
+        sqlx::query(
+            "UPDATE stage_results SET stalled_at = NOW() WHERE pipeline_run_id = $1 AND stage_name = $2",
+        )
+        .bind(run_id.as_uuid())
+        .bind(stage_name)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn create_debug_session(
+        &self,
+        tenant_id: ResourceId,
+        run_id: ResourceId,
+        stage_name: &str,
+        created_by: Option<uuid::Uuid>,
+        expires_at: DateTime<Utc>,
+    ) -> DbResult<DebugSessionRecord> {
+        let record = sqlx::query_as::<_, DebugSessionRecord>(
+            r#"
+            INSERT INTO debug_sessions (id, tenant_id, pipeline_run_id, stage_name, created_by, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING *
+            "#,
+        )
+        .bind(uuid::Uuid::now_v7())
+        .bind(tenant_id.as_uuid())
+        .bind(run_id.as_uuid())
+        .bind(stage_name)
+        .bind(created_by)
+        .bind(expires_at)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn get_debug_session(&self, id: ResourceId) -> DbResult<DebugSessionRecord> {
+        sqlx::query_as::<_, DebugSessionRecord>("SELECT * FROM debug_sessions WHERE id = $1")
+            .bind(id.as_uuid())
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| DbError::NotFound(format!("debug session {}", id)))
+    }
+
+    async fn list_debug_sessions(&self, run_id: ResourceId) -> DbResult<Vec<DebugSessionRecord>> {
+        let records = sqlx::query_as::<_, DebugSessionRecord>(
+            "SELECT * FROM debug_sessions WHERE pipeline_run_id = $1 ORDER BY created_at DESC",
+        )
+        .bind(run_id.as_uuid())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn record_debug_job(&self, id: ResourceId, handle: &JobHandle) -> DbResult<()> {
+        sqlx::query(
+            r#"
+            UPDATE debug_sessions
+            SET job_id = $2, executor_id = $3, executor_name = $4,
+                status = CASE WHEN finished_at IS NULL THEN 'ready' ELSE status END
+            WHERE id = $1
+            "#,
+        )
+        .bind(id.as_uuid())
+        .bind(handle.id.as_uuid())
+        .bind(&handle.executor_id)
+        .bind(&handle.executor_name)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn finish_debug_session(
+        &self,
+        id: ResourceId,
+        status: &str,
+        error_message: Option<&str>,
+    ) -> DbResult<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE debug_sessions
+            SET status = $2, error_message = $3, finished_at = NOW()
+            WHERE id = $1 AND finished_at IS NULL
+            "#,
+        )
+        .bind(id.as_uuid())
+        .bind(status)
+        .bind(error_message)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
 }
@@ -0,0 +1,130 @@
+//! Release repository.
+//!
+//! Each release a release stage published is a row, so the next release's
+//! changelog can start where the previous one's ended.
+
+use async_trait::async_trait;
+use buildit_core::ResourceId;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+use crate::DbResult;
+
+/// A release published by a release stage, from the database.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct ReleaseRecord {
+    pub id: uuid::Uuid,
+    pub pipeline_id: uuid::Uuid,
+    pub pipeline_run_id: uuid::Uuid,
+    pub stage_name: String,
+    /// Git provider the release was published to, `github` or `gitlab`.
+    pub provider: String,
+    pub tag: String,
+    pub name: String,
+    /// Page of the release on the provider.
+    pub url: String,
+    /// Commits listed in its changelog.
+    pub commits: i32,
+    /// Names of the artifacts attached to it.
+    pub assets: Vec<String>,
+    pub draft: bool,
+    pub prerelease: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[async_trait]
+pub trait ReleaseRepo: Send + Sync {
+    async fn create_release(&self, release: &ReleaseRecord) -> DbResult<ReleaseRecord>;
+
+    /// The pipeline's most recently published release.
+    async fn latest_release(&self, pipeline_id: ResourceId) -> DbResult<Option<ReleaseRecord>>;
+
+    /// The pipeline's releases, newest first.
+    async fn list_releases(
+        &self,
+        pipeline_id: ResourceId,
+        limit: i64,
+    ) -> DbResult<Vec<ReleaseRecord>>;
+
+    /// Releases a run published, by stage.
+    async fn list_run_releases(&self, run_id: ResourceId) -> DbResult<Vec<ReleaseRecord>>;
+}
+
+/// PostgreSQL implementation of ReleaseRepo.
+pub struct PgReleaseRepo {
+    pool: PgPool,
+}
+
+impl PgReleaseRepo {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ReleaseRepo for PgReleaseRepo {
+    async fn create_release(&self, release: &ReleaseRecord) -> DbResult<ReleaseRecord> {
+        let record = sqlx::query_as::<_, ReleaseRecord>(
+            r#"
+            INSERT INTO releases (
+                id, pipeline_id, pipeline_run_id, stage_name, provider, tag, name, url,
+                commits, assets, draft, prerelease
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            RETURNING *
+            "#,
+        )
+        .bind(release.id)
+        .bind(release.pipeline_id)
+        .bind(release.pipeline_run_id)
+        .bind(&release.stage_name)
+        .bind(&release.provider)
+        .bind(&release.tag)
+        .bind(&release.name)
+        .bind(&release.url)
+        .bind(release.commits)
+        .bind(&release.assets)
+        .bind(release.draft)
+        .bind(release.prerelease)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn latest_release(&self, pipeline_id: ResourceId) -> DbResult<Option<ReleaseRecord>> {
+        let record = sqlx::query_as::<_, ReleaseRecord>(
+            "SELECT * FROM releases WHERE pipeline_id = $1 ORDER BY created_at DESC LIMIT 1",
+        )
+        .bind(pipeline_id.as_uuid())
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn list_releases(
+        &self,
+        pipeline_id: ResourceId,
+        limit: i64,
+    ) -> DbResult<Vec<ReleaseRecord>> {
+        let records = sqlx::query_as::<_, ReleaseRecord>(
+            "SELECT * FROM releases WHERE pipeline_id = $1 ORDER BY created_at DESC LIMIT $2",
+        )
+        .bind(pipeline_id.as_uuid())
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn list_run_releases(&self, run_id: ResourceId) -> DbResult<Vec<ReleaseRecord>> {
+        let records = sqlx::query_as::<_, ReleaseRecord>(
+            "SELECT * FROM releases WHERE pipeline_run_id = $1 ORDER BY stage_name",
+        )
+        .bind(run_id.as_uuid())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+}
@@ -25,6 +25,7 @@ pub struct RepositoryRow {
     pub webhook_id: Option<String>,
     pub webhook_secret: Option<String>,
     pub last_synced_at: Option<DateTime<Utc>>,
+    pub sync_error: Option<String>,
     pub detected_config: serde_json::Value,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
@@ -55,6 +56,7 @@ impl TryFrom<RepositoryRow> for Repository {
             webhook_id: row.webhook_id,
             webhook_secret: row.webhook_secret,
             last_synced_at: row.last_synced_at,
+            sync_error: row.sync_error,
             detected_config,
             created_at: row.created_at,
             updated_at: row.updated_at,
@@ -140,6 +142,17 @@ pub trait RepositoryRepo: Send + Sync {
     /// List repositories for an organization.
     async fn list_by_organization(&self, organization_id: ResourceId) -> DbResult<Vec<Repository>>;
 
+    /// List repositories of a provider, across organizations.
+    async fn list_by_provider(&self, provider: GitProvider) -> DbResult<Vec<Repository>>;
+
+    /// List repositories never synced or last synced before `synced_before`,
+    /// least recently synced first.
+    async fn list_due_for_sync(
+        &self,
+        synced_before: DateTime<Utc>,
+        limit: i64,
+    ) -> DbResult<Vec<Repository>>;
+
     /// Update detected config.
     async fn update_detected_config(
         &self,
@@ -155,9 +168,21 @@ pub trait RepositoryRepo: Send + Sync {
         webhook_secret: &str,
     ) -> DbResult<()>;
 
-    /// Update last synced timestamp.
+    /// Update last synced timestamp, clearing any sync error.
     async fn update_last_synced(&self, id: ResourceId) -> DbResult<()>;
 
+    /// Record a failed sync. The last synced timestamp is bumped too, so
+    /// the repository is retried on the next sync rather than right away.
+    async fn update_sync_error(&self, id: ResourceId, error: &str) -> DbResult<()>;
+
+    /// Update the default branch and visibility reported by the provider.
+    async fn update_provider_info(
+        &self,
+        id: ResourceId,
+        default_branch: &str,
+        is_private: bool,
+    ) -> DbResult<()>;
+
     /// Delete a repository.
     async fn delete(&self, id: ResourceId) -> DbResult<()>;
 
@@ -172,6 +197,14 @@ pub trait RepositoryRepo: Send + Sync {
         signature: Option<&str>,
     ) -> DbResult<WebhookEvent>;
 
+    /// Whether a delivery with this provider delivery ID was already
+    /// processed, i.e. the provider is redelivering it.
+    async fn webhook_delivery_processed(
+        &self,
+        provider: GitProvider,
+        delivery_id: &str,
+    ) -> DbResult<bool>;
+
     /// Mark a webhook event as processed.
     async fn mark_webhook_processed(
         &self,
@@ -287,6 +320,38 @@ impl RepositoryRepo for PgRepositoryRepo {
         rows.into_iter().map(|r| r.try_into()).collect()
     }
 
+    async fn list_by_provider(&self, provider: GitProvider) -> DbResult<Vec<Repository>> {
+        let rows = sqlx::query_as::<_, RepositoryRow>(
+            "SELECT * FROM repositories WHERE provider = $1 ORDER BY organization_id, full_name",
+        )
+        .bind(provider.to_string())
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(|r| r.try_into()).collect()
+    }
+
+    async fn list_due_for_sync(
+        &self,
+        synced_before: DateTime<Utc>,
+        limit: i64,
+    ) -> DbResult<Vec<Repository>> {
+        let rows = sqlx::query_as::<_, RepositoryRow>(
+            r#"
+            SELECT * FROM repositories
+            WHERE last_synced_at IS NULL OR last_synced_at < $1
+            ORDER BY last_synced_at NULLS FIRST
+            LIMIT $2
+            "#,
+        )
+        .bind(synced_before)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(|r| r.try_into()).collect()
+    }
+
     async fn update_detected_config(
         &self,
         id: ResourceId,
@@ -326,7 +391,7 @@ impl RepositoryRepo for PgRepositoryRepo {
 
     async fn update_last_synced(&self, id: ResourceId) -> DbResult<()> {
         sqlx::query(
-            "UPDATE repositories SET last_synced_at = NOW(), updated_at = NOW() WHERE id = $1",
+            "UPDATE repositories SET last_synced_at = NOW(), sync_error = NULL, updated_at = NOW() WHERE id = $1",
         )
         .bind(id.as_uuid())
         .execute(&self.pool)
@@ -335,6 +400,36 @@ impl RepositoryRepo for PgRepositoryRepo {
         Ok(())
     }
 
+    async fn update_sync_error(&self, id: ResourceId, error: &str) -> DbResult<()> {
+        sqlx::query(
+            "UPDATE repositories SET last_synced_at = NOW(), sync_error = $2, updated_at = NOW() WHERE id = $1",
+        )
+        .bind(id.as_uuid())
+        .bind(error)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn update_provider_info(
+        &self,
+        id: ResourceId,
+        default_branch: &str,
+        is_private: bool,
+    ) -> DbResult<()> {
+        sqlx::query(
+            "UPDATE repositories SET default_branch = $2, is_private = $3, updated_at = NOW() WHERE id = $1",
+        )
+        .bind(id.as_uuid())
+        .bind(default_branch)
+        .bind(is_private)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
     async fn delete(&self, id: ResourceId) -> DbResult<()> {
         sqlx::query("DELETE FROM repositories WHERE id = $1")
             .bind(id.as_uuid())
@@ -375,6 +470,26 @@ impl RepositoryRepo for PgRepositoryRepo {
         row.try_into()
     }
 
+    async fn webhook_delivery_processed(
+        &self,
+        provider: GitProvider,
+        delivery_id: &str,
+    ) -> DbResult<bool> {
+        let processed: bool = sqlx::query_scalar(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM webhook_events
+                WHERE provider = $1 AND headers->>'delivery' = $2 AND processed
+            )
+            "#,
+        )
+        .bind(provider.to_string())
+        .bind(delivery_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(processed)
+    }
+
     async fn mark_webhook_processed(
         &self,
         id: ResourceId,
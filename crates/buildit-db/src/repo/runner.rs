@@ -0,0 +1,518 @@
+//! Runner repository for self-hosted runners and the jobs leased to them.
+//!
+//! Remote executors queue jobs in `runner_jobs`; runners lease them with
+//! `FOR UPDATE SKIP LOCKED`, so each job goes to exactly one runner, and only
+//! to a runner of the organization whose tenant queued it. A lease has to be
+//! renewed by the runner's reports or the job is failed.
+
+use async_trait::async_trait;
+use buildit_core::ResourceId;
+use buildit_core::executor::{JobSpec, LogLine, LogStream};
+use buildit_core::runner::RunnerJobState;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+use crate::{DbError, DbResult};
+
+/// A registered runner.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct RunnerRecord {
+    pub id: uuid::Uuid,
+    pub organization_id: uuid::Uuid,
+    pub name: String,
+    pub labels: Vec<String>,
+    pub version: Option<String>,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub last_seen_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    /// Platforms the runner runs jobs on, e.g. `macos/arm64`.
+    pub platforms: Vec<String>,
+}
+
+/// A job queued for, or leased to, a runner.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct RunnerJobRecord {
+    pub id: uuid::Uuid,
+    pub spec: serde_json::Value,
+    pub labels: Vec<String>,
+    pub status: String,
+    pub runner_id: Option<uuid::Uuid>,
+    pub lease_expires_at: Option<DateTime<Utc>>,
+    pub cancel_requested: bool,
+    pub exit_code: Option<i32>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Tenant whose run queued the job.
+    pub tenant_id: Option<uuid::Uuid>,
+    /// Organization whose runners may lease the job.
+    pub organization_id: Option<uuid::Uuid>,
+}
+
+impl RunnerJobRecord {
+    pub fn job_spec(&self) -> DbResult<JobSpec> {
+        serde_json::from_value(self.spec.clone()).map_err(|e| DbError::InvalidData(e.to_string()))
+    }
+}
+
+/// A log line reported for a runner job.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct RunnerJobLogRecord {
+    pub id: i64,
+    pub job_id: uuid::Uuid,
+    pub stream: String,
+    pub content: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl RunnerJobLogRecord {
+    pub fn log_line(&self) -> LogLine {
+        let stream = match self.stream.as_str() {
+            "stderr" => LogStream::Stderr,
+            "system" => LogStream::System,
+            _ => LogStream::Stdout,
+        };
+        LogLine {
+            timestamp: self.timestamp,
+            stream,
+            content: self.content.clone(),
+        }
+    }
+}
+
+#[async_trait]
+pub trait RunnerRepo: Send + Sync {
+    /// Register a runner with the hash of its token.
+    async fn register_runner(
+        &self,
+        organization_id: ResourceId,
+        name: &str,
+        labels: &[String],
+        platforms: &[String],
+        version: Option<&str>,
+        token_hash: &str,
+    ) -> DbResult<RunnerRecord>;
+
+    /// Look up a runner by ID and token hash, marking it as seen.
+    async fn authenticate_runner(&self, id: ResourceId, token_hash: &str)
+    -> DbResult<RunnerRecord>;
+
+    /// Runners registered by an organization.
+    async fn list_runners(&self, organization_id: ResourceId) -> DbResult<Vec<RunnerRecord>>;
+
+    /// Remove a runner. Jobs it holds are failed when their lease runs out.
+    async fn delete_runner(&self, organization_id: ResourceId, id: ResourceId) -> DbResult<()>;
+
+    /// Whether a runner of the tenant's organization with all the given
+    /// labels was seen within `within_secs`.
+    async fn runner_online(
+        &self,
+        tenant_id: ResourceId,
+        labels: &[String],
+        within_secs: i64,
+    ) -> DbResult<bool>;
+
+    /// Platforms of the tenant's organization's runners that have all the
+    /// given labels.
+    async fn runner_platforms(
+        &self,
+        tenant_id: ResourceId,
+        labels: &[String],
+    ) -> DbResult<Vec<String>>;
+
+    /// Queue a tenant's job for any runner of its organization that has all
+    /// the job's labels.
+    async fn create_job(
+        &self,
+        tenant_id: ResourceId,
+        spec: &JobSpec,
+        labels: &[String],
+    ) -> DbResult<RunnerJobRecord>;
+
+    async fn get_job(&self, id: ResourceId) -> DbResult<RunnerJobRecord>;
+
+    /// Lease the oldest pending job the runner can take, if any: one of its
+    /// organization whose labels it all has, for a platform it runs.
+    async fn lease_job(
+        &self,
+        runner: &RunnerRecord,
+        lease_secs: i64,
+    ) -> DbResult<Option<RunnerJobRecord>>;
+
+    /// Record a runner's status report and renew its lease. Fails with
+    /// `Conflict` if the runner no longer holds the job.
+    async fn report_job(
+        &self,
+        id: ResourceId,
+        runner_id: ResourceId,
+        state: RunnerJobState,
+        exit_code: Option<i32>,
+        error: Option<&str>,
+        lease_secs: i64,
+    ) -> DbResult<RunnerJobRecord>;
+
+    /// Store log lines reported for a job and renew its lease.
+    async fn append_job_logs(
+        &self,
+        id: ResourceId,
+        runner_id: ResourceId,
+        lines: &[LogLine],
+        lease_secs: i64,
+    ) -> DbResult<RunnerJobRecord>;
+
+    /// Log lines of a job after the given log ID, oldest first.
+    async fn job_logs_after(
+        &self,
+        id: ResourceId,
+        after: i64,
+        limit: i64,
+    ) -> DbResult<Vec<RunnerJobLogRecord>>;
+
+    /// Ask for a job to be cancelled. A job no runner has leased yet is
+    /// cancelled right away.
+    async fn request_cancel(&self, id: ResourceId) -> DbResult<RunnerJobRecord>;
+
+    /// Fail jobs whose runner stopped renewing the lease. Returns their IDs.
+    async fn expire_leases(&self) -> DbResult<Vec<uuid::Uuid>>;
+}
+
+/// PostgreSQL implementation of RunnerRepo.
+pub struct PgRunnerRepo {
+    pool: PgPool,
+}
+
+impl PgRunnerRepo {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+fn stream_name(stream: LogStream) -> &'static str {
+    match stream {
+        LogStream::Stdout => "stdout",
+        LogStream::Stderr => "stderr",
+        LogStream::System => "system",
+    }
+}
+
+#[async_trait]
+impl RunnerRepo for PgRunnerRepo {
+    async fn register_runner(
+        &self,
+        organization_id: ResourceId,
+        name: &str,
+        labels: &[String],
+        platforms: &[String],
+        version: Option<&str>,
+        token_hash: &str,
+    ) -> DbResult<RunnerRecord> {
+        let record = sqlx::query_as::<_, RunnerRecord>(
+            r#"
+            INSERT INTO runners (id, organization_id, name, labels, platforms, version, token_hash, last_seen_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, NOW())
+            RETURNING *
+            "#,
+        )
+        .bind(uuid::Uuid::now_v7())
+        .bind(organization_id.as_uuid())
+        .bind(name)
+        .bind(labels)
+        .bind(platforms)
+        .bind(version)
+        .bind(token_hash)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn authenticate_runner(
+        &self,
+        id: ResourceId,
+        token_hash: &str,
+    ) -> DbResult<RunnerRecord> {
+        sqlx::query_as::<_, RunnerRecord>(
+            r#"
+            UPDATE runners SET last_seen_at = NOW()
+            WHERE id = $1 AND token_hash = $2
+            RETURNING *
+            "#,
+        )
+        .bind(id.as_uuid())
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("runner {}", id)))
+    }
+
+    async fn list_runners(&self, organization_id: ResourceId) -> DbResult<Vec<RunnerRecord>> {
+        let records = sqlx::query_as::<_, RunnerRecord>(
+            "SELECT * FROM runners WHERE organization_id = $1 ORDER BY name, created_at",
+        )
+        .bind(organization_id.as_uuid())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn delete_runner(&self, organization_id: ResourceId, id: ResourceId) -> DbResult<()> {
+        let result = sqlx::query("DELETE FROM runners WHERE id = $1 AND organization_id = $2")
+            .bind(id.as_uuid())
+            .bind(organization_id.as_uuid())
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!("runner {}", id)));
+        }
+        Ok(())
+    }
+
+    async fn runner_online(
+        &self,
+        tenant_id: ResourceId,
+        labels: &[String],
+        within_secs: i64,
+    ) -> DbResult<bool> {
+        let online: bool = sqlx::query_scalar(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM runners r
+                JOIN tenants t ON t.organization_id = r.organization_id
+                WHERE t.id = $1
+                  AND r.labels @> $2
+                  AND r.last_seen_at > NOW() - make_interval(secs => $3)
+            )
+            "#,
+        )
+        .bind(tenant_id.as_uuid())
+        .bind(labels)
+        .bind(within_secs as f64)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(online)
+    }
+
+    async fn runner_platforms(
+        &self,
+        tenant_id: ResourceId,
+        labels: &[String],
+    ) -> DbResult<Vec<String>> {
+        let platforms = sqlx::query_scalar(
+            r#"
+            SELECT DISTINCT platform
+            FROM runners r
+            JOIN tenants t ON t.organization_id = r.organization_id,
+                 unnest(r.platforms) AS platform
+            WHERE t.id = $1 AND r.labels @> $2
+            ORDER BY platform
+            "#,
+        )
+        .bind(tenant_id.as_uuid())
+        .bind(labels)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(platforms)
+    }
+
+    async fn create_job(
+        &self,
+        tenant_id: ResourceId,
+        spec: &JobSpec,
+        labels: &[String],
+    ) -> DbResult<RunnerJobRecord> {
+        let spec_json =
+            serde_json::to_value(spec).map_err(|e| DbError::InvalidData(e.to_string()))?;
+        sqlx::query_as::<_, RunnerJobRecord>(
+            r#"
+            INSERT INTO runner_jobs (id, spec, labels, tenant_id, organization_id)
+            SELECT $1, $2, $3, id, organization_id
+            FROM tenants
+            WHERE id = $4 AND organization_id IS NOT NULL
+            RETURNING *
+            "#,
+        )
+        .bind(uuid::Uuid::now_v7())
+        .bind(spec_json)
+        .bind(labels)
+        .bind(tenant_id.as_uuid())
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("organization of tenant {}", tenant_id)))
+    }
+
+    async fn get_job(&self, id: ResourceId) -> DbResult<RunnerJobRecord> {
+        sqlx::query_as::<_, RunnerJobRecord>("SELECT * FROM runner_jobs WHERE id = $1")
+            .bind(id.as_uuid())
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| DbError::NotFound(format!("runner job {}", id)))
+    }
+
+    async fn lease_job(
+        &self,
+        runner: &RunnerRecord,
+        lease_secs: i64,
+    ) -> DbResult<Option<RunnerJobRecord>> {
+        let record = sqlx::query_as::<_, RunnerJobRecord>(
+            r#"
+            UPDATE runner_jobs
+            SET status = 'leased',
+                runner_id = $1,
+                lease_expires_at = NOW() + make_interval(secs => $3)
+            WHERE id = (
+                SELECT id FROM runner_jobs
+                WHERE status = 'pending' AND organization_id = $5 AND labels <@ $2
+                  -- `linux/amd64` runners take `linux` and `linux/amd64` jobs
+                  AND EXISTS (
+                      SELECT 1 FROM unnest($4::text[]) AS runner(platform)
+                      WHERE COALESCE(spec->>'platform', 'linux')
+                            IN (runner.platform, split_part(runner.platform, '/', 1))
+                  )
+                ORDER BY created_at
+                LIMIT 1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING *
+            "#,
+        )
+        .bind(runner.id)
+        .bind(&runner.labels)
+        .bind(lease_secs as f64)
+        .bind(&runner.platforms)
+        .bind(runner.organization_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn report_job(
+        &self,
+        id: ResourceId,
+        runner_id: ResourceId,
+        state: RunnerJobState,
+        exit_code: Option<i32>,
+        error: Option<&str>,
+        lease_secs: i64,
+    ) -> DbResult<RunnerJobRecord> {
+        sqlx::query_as::<_, RunnerJobRecord>(
+            r#"
+            UPDATE runner_jobs
+            SET status = $3,
+                exit_code = COALESCE($4, exit_code),
+                error = COALESCE($5, error),
+                lease_expires_at = NOW() + make_interval(secs => $6),
+                started_at = COALESCE(started_at, NOW()),
+                finished_at = CASE WHEN $3 = 'running' THEN NULL ELSE NOW() END
+            WHERE id = $1 AND runner_id = $2 AND status IN ('leased', 'running')
+            RETURNING *
+            "#,
+        )
+        .bind(id.as_uuid())
+        .bind(runner_id.as_uuid())
+        .bind(state.as_str())
+        .bind(exit_code)
+        .bind(error)
+        .bind(lease_secs as f64)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::Conflict(format!("runner job {} is not leased to runner", id)))
+    }
+
+    async fn append_job_logs(
+        &self,
+        id: ResourceId,
+        runner_id: ResourceId,
+        lines: &[LogLine],
+        lease_secs: i64,
+    ) -> DbResult<RunnerJobRecord> {
+        let mut tx = self.pool.begin().await?;
+        let record = sqlx::query_as::<_, RunnerJobRecord>(
+            r#"
+            UPDATE runner_jobs
+            SET lease_expires_at = NOW() + make_interval(secs => $3)
+            WHERE id = $1 AND runner_id = $2 AND status IN ('leased', 'running')
+            RETURNING *
+            "#,
+        )
+        .bind(id.as_uuid())
+        .bind(runner_id.as_uuid())
+        .bind(lease_secs as f64)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| DbError::Conflict(format!("runner job {} is not leased to runner", id)))?;
+
+        if !lines.is_empty() {
+            let mut query_builder = sqlx::QueryBuilder::new(
+                "INSERT INTO runner_job_logs (job_id, stream, content, timestamp) ",
+            );
+            query_builder.push_values(lines.iter(), |mut b, line| {
+                b.push_bind(id.as_uuid())
+                    .push_bind(stream_name(line.stream))
+                    .push_bind(&line.content)
+                    .push_bind(line.timestamp);
+            });
+            query_builder.build().execute(&mut *tx).await?;
+        }
+
+        tx.commit().await?;
+        Ok(record)
+    }
+
+    async fn job_logs_after(
+        &self,
+        id: ResourceId,
+        after: i64,
+        limit: i64,
+    ) -> DbResult<Vec<RunnerJobLogRecord>> {
+        let records = sqlx::query_as::<_, RunnerJobLogRecord>(
+            r#"
+            SELECT * FROM runner_job_logs
+            WHERE job_id = $1 AND id > $2
+            ORDER BY id
+            LIMIT $3
+            "#,
+        )
+        .bind(id.as_uuid())
+        .bind(after)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn request_cancel(&self, id: ResourceId) -> DbResult<RunnerJobRecord> {
+        sqlx::query_as::<_, RunnerJobRecord>(
+            r#"
+            UPDATE runner_jobs
+            SET cancel_requested = TRUE,
+                status = CASE WHEN status = 'pending' THEN 'cancelled' ELSE status END,
+                finished_at = CASE WHEN status = 'pending' THEN NOW() ELSE finished_at END
+            WHERE id = $1
+            RETURNING *
+            "#,
+        )
+        .bind(id.as_uuid())
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("runner job {}", id)))
+    }
+
+    async fn expire_leases(&self) -> DbResult<Vec<uuid::Uuid>> {
+        let ids = sqlx::query_scalar(
+            r#"
+            UPDATE runner_jobs
+            SET status = 'failed',
+                error = 'Runner stopped reporting before the job finished',
+                finished_at = NOW()
+            WHERE status IN ('leased', 'running') AND lease_expires_at < NOW()
+            RETURNING id
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(ids)
+    }
+}
@@ -0,0 +1,200 @@
+//! Scan finding repository.
+//!
+//! Each vulnerability a scan stage found is a row. A stage scanned again,
+//! e.g. when a re-adopted job's log is replayed, replaces its earlier rows.
+
+use async_trait::async_trait;
+use buildit_core::ResourceId;
+use buildit_core::scan::{Finding, Scanner, Severity};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+use crate::DbResult;
+
+/// Severities, most severe first, for ordering findings.
+const SEVERITY_ORDER: &str =
+    "array_position(ARRAY['critical', 'high', 'medium', 'low', 'negligible', 'unknown'], severity)";
+
+/// A vulnerability found by a scan stage, from the database.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ScanFindingRecord {
+    pub id: uuid::Uuid,
+    pub pipeline_run_id: uuid::Uuid,
+    pub stage_name: String,
+    pub scanner: String,
+    pub target: String,
+    pub vulnerability_id: String,
+    pub package: String,
+    pub installed_version: String,
+    pub fixed_version: Option<String>,
+    pub severity: String,
+    pub title: Option<String>,
+    pub url: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ScanFindingRecord {
+    pub fn severity(&self) -> Severity {
+        self.severity.parse().unwrap_or(Severity::Unknown)
+    }
+}
+
+/// Findings of a scan stage by severity.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct ScanSummary {
+    pub stage_name: String,
+    pub scanner: String,
+    /// Image that was scanned.
+    pub target: String,
+    pub critical: i64,
+    pub high: i64,
+    pub medium: i64,
+    pub low: i64,
+    pub negligible: i64,
+    pub unknown: i64,
+}
+
+#[async_trait]
+pub trait ScanFindingRepo: Send + Sync {
+    /// Store a stage's findings, replacing the ones stored for it before.
+    async fn replace_findings(
+        &self,
+        run_id: ResourceId,
+        stage: &str,
+        scanner: Scanner,
+        target: &str,
+        findings: &[Finding],
+    ) -> DbResult<u64>;
+
+    /// Findings of a run, most severe first.
+    async fn list_findings(
+        &self,
+        run_id: ResourceId,
+        limit: i64,
+    ) -> DbResult<Vec<ScanFindingRecord>>;
+
+    /// Findings of each of a run's scan stages by severity.
+    async fn summaries(&self, run_id: ResourceId) -> DbResult<Vec<ScanSummary>>;
+}
+
+/// PostgreSQL implementation of ScanFindingRepo.
+pub struct PgScanFindingRepo {
+    pool: PgPool,
+}
+
+impl PgScanFindingRepo {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ScanFindingRepo for PgScanFindingRepo {
+    async fn replace_findings(
+        &self,
+        run_id: ResourceId,
+        stage: &str,
+        scanner: Scanner,
+        target: &str,
+        findings: &[Finding],
+    ) -> DbResult<u64> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM scan_findings WHERE pipeline_run_id = $1 AND stage_name = $2")
+            .bind(run_id.as_uuid())
+            .bind(stage)
+            .execute(&mut *tx)
+            .await?;
+
+        let ids: Vec<uuid::Uuid> = findings.iter().map(|_| uuid::Uuid::now_v7()).collect();
+        let vulnerabilities: Vec<&str> = findings
+            .iter()
+            .map(|f| f.vulnerability_id.as_str())
+            .collect();
+        let packages: Vec<&str> = findings.iter().map(|f| f.package.as_str()).collect();
+        let versions: Vec<&str> = findings
+            .iter()
+            .map(|f| f.installed_version.as_str())
+            .collect();
+        let fixed: Vec<Option<&str>> = findings
+            .iter()
+            .map(|f| f.fixed_version.as_deref())
+            .collect();
+        let severities: Vec<&str> = findings.iter().map(|f| f.severity.as_str()).collect();
+        let titles: Vec<Option<&str>> = findings.iter().map(|f| f.title.as_deref()).collect();
+        let urls: Vec<Option<&str>> = findings.iter().map(|f| f.url.as_deref()).collect();
+        let result = sqlx::query(
+            r#"
+            INSERT INTO scan_findings (
+                id, pipeline_run_id, stage_name, scanner, target, vulnerability_id,
+                package, installed_version, fixed_version, severity, title, url
+            )
+            SELECT id, $1, $2, $3, $4, vulnerability_id, package, installed_version,
+                   fixed_version, severity, title, url
+            FROM UNNEST($5::uuid[], $6::text[], $7::text[], $8::text[], $9::text[],
+                        $10::text[], $11::text[], $12::text[])
+                AS t(id, vulnerability_id, package, installed_version, fixed_version,
+                     severity, title, url)
+            "#,
+        )
+        .bind(run_id.as_uuid())
+        .bind(stage)
+        .bind(scanner.as_str())
+        .bind(target)
+        .bind(&ids)
+        .bind(&vulnerabilities)
+        .bind(&packages)
+        .bind(&versions)
+        .bind(&fixed)
+        .bind(&severities)
+        .bind(&titles)
+        .bind(&urls)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn list_findings(
+        &self,
+        run_id: ResourceId,
+        limit: i64,
+    ) -> DbResult<Vec<ScanFindingRecord>> {
+        let records = sqlx::query_as::<_, ScanFindingRecord>(&format!(
+            r#"
+            SELECT * FROM scan_findings
+            WHERE pipeline_run_id = $1
+            ORDER BY {SEVERITY_ORDER}, vulnerability_id, package
+            LIMIT $2
+            "#
+        ))
+        .bind(run_id.as_uuid())
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn summaries(&self, run_id: ResourceId) -> DbResult<Vec<ScanSummary>> {
+        let summaries = sqlx::query_as::<_, ScanSummary>(
+            r#"
+            SELECT stage_name, scanner, target,
+                   COUNT(*) FILTER (WHERE severity = 'critical') AS critical,
+                   COUNT(*) FILTER (WHERE severity = 'high') AS high,
+                   COUNT(*) FILTER (WHERE severity = 'medium') AS medium,
+                   COUNT(*) FILTER (WHERE severity = 'low') AS low,
+                   COUNT(*) FILTER (WHERE severity = 'negligible') AS negligible,
+                   COUNT(*) FILTER (WHERE severity = 'unknown') AS unknown
+            FROM scan_findings
+            WHERE pipeline_run_id = $1
+            GROUP BY stage_name, scanner, target
+            ORDER BY stage_name
+            "#,
+        )
+        .bind(run_id.as_uuid())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(summaries)
+    }
+}
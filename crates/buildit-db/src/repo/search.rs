@@ -0,0 +1,231 @@
+//! Search across a tenant's runs and their logs.
+//!
+//! Runs are matched on their commit metadata (SHA, branch, author and
+//! message, from `git_info`), log lines by substring. Matching is
+//! case-insensitive and backed by trigram indexes.
+
+use async_trait::async_trait;
+use buildit_core::ResourceId;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+use crate::DbResult;
+
+/// The runs searched.
+#[derive(Debug, Clone)]
+pub struct RunSearchFilter {
+    pub tenant_id: ResourceId,
+    pub pipeline_id: Option<uuid::Uuid>,
+    pub status: Option<String>,
+    /// Exact branch name.
+    pub branch: Option<String>,
+    /// Commit SHA prefix.
+    pub sha: Option<String>,
+    /// Part of the commit author.
+    pub author: Option<String>,
+}
+
+/// A run whose commit metadata matched.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct RunSearchHit {
+    pub run_id: uuid::Uuid,
+    pub pipeline_id: uuid::Uuid,
+    pub pipeline_name: String,
+    pub number: i64,
+    pub status: String,
+    pub branch: Option<String>,
+    pub sha: Option<String>,
+    pub author: Option<String>,
+    pub message: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A matching log line, with the lines around it.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct LogSearchHit {
+    pub log_id: uuid::Uuid,
+    pub run_id: uuid::Uuid,
+    pub pipeline_id: uuid::Uuid,
+    pub pipeline_name: String,
+    pub run_number: i64,
+    pub stage_name: String,
+    pub stream: String,
+    pub timestamp: DateTime<Utc>,
+    /// 1-based position of the line in its stage's log.
+    pub line_number: i64,
+    pub content: String,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+#[async_trait]
+pub trait SearchRepo: Send + Sync {
+    /// Runs matching the filter whose commit message, branch or author
+    /// contains `text`, whose SHA starts with it or whose number it is,
+    /// newest first. An empty `text` matches every run of the filter.
+    async fn search_runs(
+        &self,
+        filter: &RunSearchFilter,
+        text: &str,
+        limit: i64,
+    ) -> DbResult<Vec<RunSearchHit>>;
+
+    /// Log lines containing `text` in the runs matching the filter, newest
+    /// runs first and in log order within a run, each with up to `context`
+    /// lines of its stage before and after it.
+    async fn search_logs(
+        &self,
+        filter: &RunSearchFilter,
+        text: &str,
+        context: i64,
+        limit: i64,
+    ) -> DbResult<Vec<LogSearchHit>>;
+}
+
+/// PostgreSQL implementation of SearchRepo.
+pub struct PgSearchRepo {
+    pool: PgPool,
+}
+
+/// The filtered runs; `$1` is the tenant, `$2` to `$6` the pipeline,
+/// status, branch, SHA prefix and author pattern, each `NULL` if unset.
+const RUNS: &str = r#"
+    runs AS (
+        SELECT r.id, r.pipeline_id, p.name AS pipeline_name, r.number, r.status, r.created_at,
+               NULLIF(r.git_info->>'branch', '') AS branch,
+               NULLIF(r.git_info->>'sha', '') AS sha,
+               NULLIF(r.git_info->>'author', '') AS author,
+               NULLIF(r.git_info->>'message', '') AS message
+        FROM pipeline_runs r
+        JOIN pipelines p ON p.id = r.pipeline_id
+        WHERE p.tenant_id = $1
+          AND ($2::uuid IS NULL OR r.pipeline_id = $2)
+          AND ($3::text IS NULL OR r.status = $3)
+          AND ($4::text IS NULL OR r.git_info->>'branch' = $4)
+          AND ($5::text IS NULL OR r.git_info->>'sha' LIKE lower($5) || '%')
+          AND ($6::text IS NULL OR r.git_info->>'author' ILIKE '%' || $6 || '%')
+    )
+"#;
+
+/// Escape `LIKE` wildcards so `text` matches literally.
+fn like_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+type QueryAs<'q, T> = sqlx::query::QueryAs<'q, sqlx::Postgres, T, sqlx::postgres::PgArguments>;
+
+impl PgSearchRepo {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// A query of the filtered runs, with the filter bound.
+    fn query_as<'q, T>(&self, sql: &'q str, filter: &'q RunSearchFilter) -> QueryAs<'q, T>
+    where
+        T: for<'r> sqlx::FromRow<'r, sqlx::postgres::PgRow>,
+    {
+        sqlx::query_as::<_, T>(sql)
+            .bind(filter.tenant_id.as_uuid())
+            .bind(filter.pipeline_id)
+            .bind(filter.status.as_deref())
+            .bind(filter.branch.as_deref())
+            .bind(filter.sha.as_deref().map(like_escape))
+            .bind(filter.author.as_deref().map(like_escape))
+    }
+}
+
+#[async_trait]
+impl SearchRepo for PgSearchRepo {
+    async fn search_runs(
+        &self,
+        filter: &RunSearchFilter,
+        text: &str,
+        limit: i64,
+    ) -> DbResult<Vec<RunSearchHit>> {
+        let sql = format!(
+            r#"
+            WITH {RUNS}
+            SELECT id AS run_id, pipeline_id, pipeline_name, number, status,
+                   branch, sha, author, message, created_at
+            FROM runs
+            WHERE $7 = ''
+               OR message ILIKE '%' || $8 || '%'
+               OR branch ILIKE '%' || $8 || '%'
+               OR author ILIKE '%' || $8 || '%'
+               OR sha LIKE lower($8) || '%'
+               OR number::text = ltrim($7, '#')
+            ORDER BY created_at DESC
+            LIMIT $9
+            "#
+        );
+        let runs = self
+            .query_as(&sql, filter)
+            .bind(text)
+            .bind(like_escape(text))
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(runs)
+    }
+
+    async fn search_logs(
+        &self,
+        filter: &RunSearchFilter,
+        text: &str,
+        context: i64,
+        limit: i64,
+    ) -> DbResult<Vec<LogSearchHit>> {
+        let sql = format!(
+            r#"
+            WITH {RUNS},
+            matches AS (
+                SELECT l.id, l.pipeline_run_id, l.stage_name, l.stream, l.timestamp, l.content,
+                       runs.pipeline_id, runs.pipeline_name, runs.number, runs.created_at
+                FROM logs l
+                JOIN runs ON runs.id = l.pipeline_run_id
+                WHERE l.content ILIKE '%' || $7 || '%'
+                ORDER BY runs.created_at DESC, l.timestamp, l.id
+                LIMIT $9
+            )
+            SELECT m.id AS log_id, m.pipeline_run_id AS run_id, m.pipeline_id, m.pipeline_name,
+                   m.number AS run_number, m.stage_name, m.stream, m.timestamp, m.content,
+                   1 + (
+                       SELECT COUNT(*) FROM logs b
+                       WHERE b.pipeline_run_id = m.pipeline_run_id AND b.stage_name = m.stage_name
+                         AND (b.timestamp, b.id) < (m.timestamp, m.id)
+                   ) AS line_number,
+                   ARRAY(
+                       SELECT content FROM (
+                           SELECT b.content, b.timestamp, b.id FROM logs b
+                           WHERE b.pipeline_run_id = m.pipeline_run_id AND b.stage_name = m.stage_name
+                             AND (b.timestamp, b.id) < (m.timestamp, m.id)
+                           ORDER BY b.timestamp DESC, b.id DESC
+                           LIMIT $8
+                       ) preceding
+                       ORDER BY timestamp, id
+                   ) AS before,
+                   ARRAY(
+                       SELECT a.content FROM logs a
+                       WHERE a.pipeline_run_id = m.pipeline_run_id AND a.stage_name = m.stage_name
+                         AND (a.timestamp, a.id) > (m.timestamp, m.id)
+                       ORDER BY a.timestamp, a.id
+                       LIMIT $8
+                   ) AS after
+            FROM matches m
+            ORDER BY m.created_at DESC, m.timestamp, m.id
+            "#
+        );
+        let lines = self
+            .query_as(&sql, filter)
+            .bind(like_escape(text))
+            .bind(context)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(lines)
+    }
+}
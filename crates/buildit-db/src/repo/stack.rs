@@ -3,8 +3,8 @@
 use async_trait::async_trait;
 use buildit_core::ResourceId;
 use buildit_core::stack::{
-    Stack, StackRun, StackRunStatus, StackRunType, StackState, StackStatus, StackTriggerType,
-    StackVariable,
+    PlanPolicy, PolicyResult, ResourceChange, Stack, StackRun, StackRunStatus, StackRunType,
+    StackState, StackStatus, StackTriggerType, StackVariable,
 };
 use chrono::{DateTime, Utc};
 use sqlx::PgPool;
@@ -108,6 +108,8 @@ pub struct StackRunRow {
     pub commit_sha: Option<String>,
     pub plan_output: Option<String>,
     pub plan_json: Option<serde_json::Value>,
+    pub plan_changes: Option<serde_json::Value>,
+    pub policy_results: Option<serde_json::Value>,
     pub apply_output: Option<String>,
     pub resources_to_add: Option<i32>,
     pub resources_to_change: Option<i32>,
@@ -167,6 +169,18 @@ impl TryFrom<StackRunRow> for StackRun {
             commit_sha: row.commit_sha,
             plan_output: row.plan_output,
             plan_json: row.plan_json,
+            plan_changes: row
+                .plan_changes
+                .map(serde_json::from_value)
+                .transpose()
+                .map_err(|e| DbError::InvalidData(format!("plan changes: {}", e)))?
+                .unwrap_or_default(),
+            policy_results: row
+                .policy_results
+                .map(serde_json::from_value)
+                .transpose()
+                .map_err(|e| DbError::InvalidData(format!("policy results: {}", e)))?
+                .unwrap_or_default(),
             apply_output: row.apply_output,
             resources_to_add: row.resources_to_add.unwrap_or(0),
             resources_to_change: row.resources_to_change.unwrap_or(0),
@@ -213,6 +227,38 @@ impl From<StackStateRow> for StackState {
     }
 }
 
+/// Database row for plan policies.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct PlanPolicyRow {
+    pub id: Uuid,
+    pub tenant_id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub rego: String,
+    pub enforcement: String,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl TryFrom<PlanPolicyRow> for PlanPolicy {
+    type Error = DbError;
+
+    fn try_from(row: PlanPolicyRow) -> Result<Self, Self::Error> {
+        Ok(PlanPolicy {
+            id: row.id,
+            tenant_id: row.tenant_id,
+            name: row.name,
+            description: row.description,
+            rego: row.rego,
+            enforcement: row.enforcement.parse().map_err(DbError::InvalidData)?,
+            enabled: row.enabled,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        })
+    }
+}
+
 #[async_trait]
 pub trait StackRepo: Send + Sync {
     // Stack CRUD
@@ -270,6 +316,11 @@ pub trait StackRepo: Send + Sync {
         to_change: i32,
         to_destroy: i32,
     ) -> DbResult<()>;
+    async fn update_run_plan_changes(
+        &self,
+        id: ResourceId,
+        changes: &[ResourceChange],
+    ) -> DbResult<()>;
     async fn update_run_apply_output(&self, id: ResourceId, output: &str) -> DbResult<()>;
     async fn update_run_finished(
         &self,
@@ -277,22 +328,40 @@ pub trait StackRepo: Send + Sync {
         status: StackRunStatus,
         error_message: Option<&str>,
     ) -> DbResult<()>;
-    async fn approve_run(&self, id: ResourceId, user_id: ResourceId) -> DbResult<()>;
+    async fn update_run_policy_results(
+        &self,
+        id: ResourceId,
+        results: &[PolicyResult],
+    ) -> DbResult<()>;
+    async fn approve_run(&self, id: ResourceId, user_id: Option<ResourceId>) -> DbResult<()>;
+
+    // Plan policies
+    /// Fails with `Duplicate` if the tenant has a policy of that name.
+    async fn create_policy(&self, policy: &PlanPolicy) -> DbResult<PlanPolicy>;
+    async fn list_policies(&self, tenant_id: ResourceId) -> DbResult<Vec<PlanPolicy>>;
+    async fn get_policy(&self, tenant_id: ResourceId, id: ResourceId) -> DbResult<PlanPolicy>;
+    /// Save a policy's name, description, Rego, enforcement and whether it
+    /// is enabled.
+    async fn update_policy(&self, policy: &PlanPolicy) -> DbResult<PlanPolicy>;
+    async fn delete_policy(&self, tenant_id: ResourceId, id: ResourceId) -> DbResult<()>;
 
     // Stack state
     async fn get_state(&self, stack_id: ResourceId) -> DbResult<Option<StackState>>;
+    /// Store the stack's state unless it is locked under a lock ID other
+    /// than `lock_id`, returning `None` if it is.
     async fn save_state(
         &self,
         stack_id: ResourceId,
         state_json: serde_json::Value,
         serial: i32,
         lineage: Option<&str>,
-    ) -> DbResult<StackState>;
+        lock_id: Option<&str>,
+    ) -> DbResult<Option<StackState>>;
     async fn lock_state(
         &self,
         stack_id: ResourceId,
         lock_id: &str,
-        user_id: ResourceId,
+        user_id: Option<ResourceId>,
         lock_info: serde_json::Value,
     ) -> DbResult<bool>;
     async fn unlock_state(&self, stack_id: ResourceId, lock_id: &str) -> DbResult<bool>;
@@ -309,6 +378,15 @@ impl PgStackRepo {
     }
 }
 
+fn duplicate_policy(e: sqlx::Error, name: &str) -> DbError {
+    match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+            DbError::Duplicate(format!("plan policy {} already exists", name))
+        }
+        e => e.into(),
+    }
+}
+
 #[async_trait]
 impl StackRepo for PgStackRepo {
     async fn create_stack(
@@ -571,6 +649,22 @@ impl StackRepo for PgStackRepo {
         Ok(())
     }
 
+    async fn update_run_plan_changes(
+        &self,
+        id: ResourceId,
+        changes: &[ResourceChange],
+    ) -> DbResult<()> {
+        let changes = serde_json::to_value(changes)
+            .map_err(|e| DbError::InvalidData(format!("plan changes: {}", e)))?;
+        sqlx::query("UPDATE stack_runs SET plan_changes = $2 WHERE id = $1")
+            .bind(id.as_uuid())
+            .bind(changes)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
     async fn update_run_apply_output(&self, id: ResourceId, output: &str) -> DbResult<()> {
         sqlx::query("UPDATE stack_runs SET apply_output = $2 WHERE id = $1")
             .bind(id.as_uuid())
@@ -607,18 +701,119 @@ impl StackRepo for PgStackRepo {
         Ok(())
     }
 
-    async fn approve_run(&self, id: ResourceId, user_id: ResourceId) -> DbResult<()> {
+    async fn update_run_policy_results(
+        &self,
+        id: ResourceId,
+        results: &[PolicyResult],
+    ) -> DbResult<()> {
+        let results = serde_json::to_value(results)
+            .map_err(|e| DbError::InvalidData(format!("policy results: {}", e)))?;
+        sqlx::query("UPDATE stack_runs SET policy_results = $2 WHERE id = $1")
+            .bind(id.as_uuid())
+            .bind(results)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn approve_run(&self, id: ResourceId, user_id: Option<ResourceId>) -> DbResult<()> {
         sqlx::query(
             "UPDATE stack_runs SET status = 'approved', approved_by = $2, approved_at = NOW() WHERE id = $1",
         )
         .bind(id.as_uuid())
-        .bind(user_id.as_uuid())
+        .bind(user_id.map(|u| *u.as_uuid()))
         .execute(&self.pool)
         .await?;
 
         Ok(())
     }
 
+    async fn create_policy(&self, policy: &PlanPolicy) -> DbResult<PlanPolicy> {
+        let row = sqlx::query_as::<_, PlanPolicyRow>(
+            r#"
+            INSERT INTO plan_policies
+                (id, tenant_id, name, description, rego, enforcement, enabled)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING *
+            "#,
+        )
+        .bind(policy.id)
+        .bind(policy.tenant_id)
+        .bind(&policy.name)
+        .bind(&policy.description)
+        .bind(&policy.rego)
+        .bind(policy.enforcement.to_string())
+        .bind(policy.enabled)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| duplicate_policy(e, &policy.name))?;
+
+        row.try_into()
+    }
+
+    async fn list_policies(&self, tenant_id: ResourceId) -> DbResult<Vec<PlanPolicy>> {
+        let rows = sqlx::query_as::<_, PlanPolicyRow>(
+            "SELECT * FROM plan_policies WHERE tenant_id = $1 ORDER BY name",
+        )
+        .bind(tenant_id.as_uuid())
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(|r| r.try_into()).collect()
+    }
+
+    async fn get_policy(&self, tenant_id: ResourceId, id: ResourceId) -> DbResult<PlanPolicy> {
+        let row = sqlx::query_as::<_, PlanPolicyRow>(
+            "SELECT * FROM plan_policies WHERE id = $1 AND tenant_id = $2",
+        )
+        .bind(id.as_uuid())
+        .bind(tenant_id.as_uuid())
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("plan policy {}", id)))?;
+
+        row.try_into()
+    }
+
+    async fn update_policy(&self, policy: &PlanPolicy) -> DbResult<PlanPolicy> {
+        let row = sqlx::query_as::<_, PlanPolicyRow>(
+            r#"
+            UPDATE plan_policies
+            SET name = $3, description = $4, rego = $5, enforcement = $6, enabled = $7,
+                updated_at = NOW()
+            WHERE id = $1 AND tenant_id = $2
+            RETURNING *
+            "#,
+        )
+        .bind(policy.id)
+        .bind(policy.tenant_id)
+        .bind(&policy.name)
+        .bind(&policy.description)
+        .bind(&policy.rego)
+        .bind(policy.enforcement.to_string())
+        .bind(policy.enabled)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| duplicate_policy(e, &policy.name))?
+        .ok_or_else(|| DbError::NotFound(format!("plan policy {}", policy.id)))?;
+
+        row.try_into()
+    }
+
+    async fn delete_policy(&self, tenant_id: ResourceId, id: ResourceId) -> DbResult<()> {
+        let result = sqlx::query("DELETE FROM plan_policies WHERE id = $1 AND tenant_id = $2")
+            .bind(id.as_uuid())
+            .bind(tenant_id.as_uuid())
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!("plan policy {}", id)));
+        }
+
+        Ok(())
+    }
+
     async fn get_state(&self, stack_id: ResourceId) -> DbResult<Option<StackState>> {
         let row =
             sqlx::query_as::<_, StackStateRow>("SELECT * FROM stack_state WHERE stack_id = $1")
@@ -635,7 +830,10 @@ impl StackRepo for PgStackRepo {
         state_json: serde_json::Value,
         serial: i32,
         lineage: Option<&str>,
-    ) -> DbResult<StackState> {
+        lock_id: Option<&str>,
+    ) -> DbResult<Option<StackState>> {
+        // The lock is checked in the same statement, so it cannot be taken
+        // between the check and the write.
         let row = sqlx::query_as::<_, StackStateRow>(
             r#"
             INSERT INTO stack_state (id, stack_id, state_json, serial, lineage, updated_at)
@@ -645,6 +843,7 @@ impl StackRepo for PgStackRepo {
                 serial = EXCLUDED.serial,
                 lineage = COALESCE(EXCLUDED.lineage, stack_state.lineage),
                 updated_at = NOW()
+            WHERE stack_state.lock_id IS NULL OR stack_state.lock_id = $6
             RETURNING *
             "#,
         )
@@ -653,33 +852,40 @@ impl StackRepo for PgStackRepo {
         .bind(state_json)
         .bind(serial)
         .bind(lineage)
-        .fetch_one(&self.pool)
+        .bind(lock_id)
+        .fetch_optional(&self.pool)
         .await?;
 
-        Ok(row.into())
+        Ok(row.map(Into::into))
     }
 
     async fn lock_state(
         &self,
         stack_id: ResourceId,
         lock_id: &str,
-        user_id: ResourceId,
+        user_id: Option<ResourceId>,
         lock_info: serde_json::Value,
     ) -> DbResult<bool> {
-        // Try to acquire lock only if not already locked
+        // Acquire the lock only if it is free. A stack that has never stored
+        // state gets an empty placeholder row so its first run can be locked.
         let result = sqlx::query(
             r#"
-            UPDATE stack_state SET
-                lock_id = $2,
-                locked_by = $3,
+            INSERT INTO stack_state (
+                id, stack_id, state_json, serial, lock_id, locked_by, locked_at, lock_info, updated_at
+            )
+            VALUES ($1, $2, 'null'::jsonb, 0, $3, $4, NOW(), $5, NOW())
+            ON CONFLICT (stack_id) DO UPDATE SET
+                lock_id = EXCLUDED.lock_id,
+                locked_by = EXCLUDED.locked_by,
                 locked_at = NOW(),
-                lock_info = $4
-            WHERE stack_id = $1 AND lock_id IS NULL
+                lock_info = EXCLUDED.lock_info
+            WHERE stack_state.lock_id IS NULL
             "#,
         )
+        .bind(Uuid::now_v7())
         .bind(stack_id.as_uuid())
         .bind(lock_id)
-        .bind(user_id.as_uuid())
+        .bind(user_id.map(|id| *id.as_uuid()))
         .bind(lock_info)
         .execute(&self.pool)
         .await?;
@@ -2,6 +2,7 @@
 
 use async_trait::async_trait;
 use buildit_core::ResourceId;
+use buildit_core::tenant::{ArtifactRetention, LogRetention, TenantPolicy, TenantQuota};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
@@ -14,16 +15,64 @@ pub struct Tenant {
     pub id: uuid::Uuid,
     pub name: String,
     pub slug: String,
+    pub organization_id: Option<uuid::Uuid>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub policy: serde_json::Value,
+    pub quota: serde_json::Value,
+    pub log_retention: serde_json::Value,
+    pub artifact_retention: serde_json::Value,
+}
+
+impl Tenant {
+    /// Decoded policy template; empty if none is set.
+    pub fn policy(&self) -> TenantPolicy {
+        serde_json::from_value(self.policy.clone()).unwrap_or_default()
+    }
+
+    /// Decoded quota; unlimited if none is set.
+    pub fn quota(&self) -> TenantQuota {
+        serde_json::from_value(self.quota.clone()).unwrap_or_default()
+    }
+
+    /// Decoded log retention; logs are kept forever if none is set.
+    pub fn log_retention(&self) -> LogRetention {
+        serde_json::from_value(self.log_retention.clone()).unwrap_or_default()
+    }
+
+    /// Decoded artifact retention; artifacts are kept forever if none is set.
+    pub fn artifact_retention(&self) -> ArtifactRetention {
+        serde_json::from_value(self.artifact_retention.clone()).unwrap_or_default()
+    }
 }
 
 #[async_trait]
 pub trait TenantRepo: Send + Sync {
-    async fn create(&self, name: &str, slug: &str) -> DbResult<Tenant>;
+    async fn create(
+        &self,
+        name: &str,
+        slug: &str,
+        organization_id: Option<ResourceId>,
+    ) -> DbResult<Tenant>;
     async fn get_by_id(&self, id: ResourceId) -> DbResult<Tenant>;
     async fn get_by_slug(&self, slug: &str) -> DbResult<Tenant>;
     async fn list(&self) -> DbResult<Vec<Tenant>>;
+    async fn list_by_organization(&self, organization_id: ResourceId) -> DbResult<Vec<Tenant>>;
+    /// Tenants a user reaches: those they are a member of, and every tenant
+    /// of their organizations.
+    async fn list_for_user(&self, user_id: ResourceId) -> DbResult<Vec<Tenant>>;
+    async fn update_policy(&self, id: ResourceId, policy: &TenantPolicy) -> DbResult<Tenant>;
+    async fn update_quota(&self, id: ResourceId, quota: &TenantQuota) -> DbResult<Tenant>;
+    async fn update_log_retention(
+        &self,
+        id: ResourceId,
+        retention: &LogRetention,
+    ) -> DbResult<Tenant>;
+    async fn update_artifact_retention(
+        &self,
+        id: ResourceId,
+        retention: &ArtifactRetention,
+    ) -> DbResult<Tenant>;
     async fn delete(&self, id: ResourceId) -> DbResult<()>;
 }
 
@@ -40,17 +89,23 @@ impl PgTenantRepo {
 
 #[async_trait]
 impl TenantRepo for PgTenantRepo {
-    async fn create(&self, name: &str, slug: &str) -> DbResult<Tenant> {
+    async fn create(
+        &self,
+        name: &str,
+        slug: &str,
+        organization_id: Option<ResourceId>,
+    ) -> DbResult<Tenant> {
         let tenant = sqlx::query_as::<_, Tenant>(
             r#"
-            INSERT INTO tenants (id, name, slug, created_at, updated_at)
-            VALUES ($1, $2, $3, NOW(), NOW())
+            INSERT INTO tenants (id, name, slug, organization_id, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, NOW(), NOW())
             RETURNING *
             "#,
         )
         .bind(uuid::Uuid::now_v7())
         .bind(name)
         .bind(slug)
+        .bind(organization_id.map(|id| *id.as_uuid()))
         .fetch_one(&self.pool)
         .await?;
         Ok(tenant)
@@ -81,6 +136,87 @@ impl TenantRepo for PgTenantRepo {
         Ok(tenants)
     }
 
+    async fn list_by_organization(&self, organization_id: ResourceId) -> DbResult<Vec<Tenant>> {
+        let tenants = sqlx::query_as::<_, Tenant>(
+            "SELECT * FROM tenants WHERE organization_id = $1 ORDER BY name",
+        )
+        .bind(organization_id.as_uuid())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(tenants)
+    }
+
+    async fn list_for_user(&self, user_id: ResourceId) -> DbResult<Vec<Tenant>> {
+        let tenants = sqlx::query_as::<_, Tenant>(
+            r#"
+            SELECT * FROM tenants
+            WHERE id IN (SELECT tenant_id FROM tenant_memberships WHERE user_id = $1)
+               OR organization_id IN (SELECT organization_id FROM org_memberships WHERE user_id = $1)
+            ORDER BY name
+            "#,
+        )
+        .bind(user_id.as_uuid())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(tenants)
+    }
+
+    async fn update_policy(&self, id: ResourceId, policy: &TenantPolicy) -> DbResult<Tenant> {
+        let tenant = sqlx::query_as::<_, Tenant>(
+            "UPDATE tenants SET policy = $2, updated_at = NOW() WHERE id = $1 RETURNING *",
+        )
+        .bind(id.as_uuid())
+        .bind(serde_json::to_value(policy).map_err(|e| DbError::InvalidData(e.to_string()))?)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("tenant {}", id)))?;
+        Ok(tenant)
+    }
+
+    async fn update_quota(&self, id: ResourceId, quota: &TenantQuota) -> DbResult<Tenant> {
+        let tenant = sqlx::query_as::<_, Tenant>(
+            "UPDATE tenants SET quota = $2, updated_at = NOW() WHERE id = $1 RETURNING *",
+        )
+        .bind(id.as_uuid())
+        .bind(serde_json::to_value(quota).map_err(|e| DbError::InvalidData(e.to_string()))?)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("tenant {}", id)))?;
+        Ok(tenant)
+    }
+
+    async fn update_log_retention(
+        &self,
+        id: ResourceId,
+        retention: &LogRetention,
+    ) -> DbResult<Tenant> {
+        let tenant = sqlx::query_as::<_, Tenant>(
+            "UPDATE tenants SET log_retention = $2, updated_at = NOW() WHERE id = $1 RETURNING *",
+        )
+        .bind(id.as_uuid())
+        .bind(serde_json::to_value(retention).map_err(|e| DbError::InvalidData(e.to_string()))?)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("tenant {}", id)))?;
+        Ok(tenant)
+    }
+
+    async fn update_artifact_retention(
+        &self,
+        id: ResourceId,
+        retention: &ArtifactRetention,
+    ) -> DbResult<Tenant> {
+        let tenant = sqlx::query_as::<_, Tenant>(
+            "UPDATE tenants SET artifact_retention = $2, updated_at = NOW() WHERE id = $1 RETURNING *",
+        )
+        .bind(id.as_uuid())
+        .bind(serde_json::to_value(retention).map_err(|e| DbError::InvalidData(e.to_string()))?)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("tenant {}", id)))?;
+        Ok(tenant)
+    }
+
     async fn delete(&self, id: ResourceId) -> DbResult<()> {
         sqlx::query("DELETE FROM tenants WHERE id = $1")
             .bind(id.as_uuid())
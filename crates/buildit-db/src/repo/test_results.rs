@@ -0,0 +1,337 @@
+//! Test result repository.
+//!
+//! Each test case of a run's test reports is a row, so failures, slow
+//! tests and flaky tests can be queried across runs. A report read again,
+//! e.g. when a re-adopted job's log is replayed, replaces its earlier rows.
+
+use async_trait::async_trait;
+use buildit_core::ResourceId;
+use buildit_core::test_report::{TestCase, TestStatus};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+use crate::DbResult;
+
+/// A test case result from the database.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TestResultRecord {
+    pub id: uuid::Uuid,
+    pub pipeline_run_id: uuid::Uuid,
+    pub pipeline_id: uuid::Uuid,
+    pub stage_name: String,
+    pub report_path: String,
+    pub suite: String,
+    pub classname: String,
+    pub name: String,
+    pub status: String,
+    pub duration_secs: f64,
+    pub message: Option<String>,
+    pub details: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl TestResultRecord {
+    pub fn status(&self) -> TestStatus {
+        self.status.parse().unwrap_or(TestStatus::Errored)
+    }
+}
+
+/// Test counts of a run.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct TestSummary {
+    pub total: i64,
+    pub passed: i64,
+    pub failed: i64,
+    pub errored: i64,
+    pub skipped: i64,
+    /// Time all tests took, added up.
+    pub duration_secs: f64,
+}
+
+impl TestSummary {
+    /// Tests that failed or errored.
+    pub fn failures(&self) -> i64 {
+        self.failed + self.errored
+    }
+}
+
+/// A test that flipped between passing and failing across a pipeline's
+/// runs.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct FlakyTest {
+    pub classname: String,
+    pub name: String,
+    pub total_runs: i64,
+    pub failed: i64,
+    /// Runs whose outcome differs from the previous run's on the same
+    /// branch.
+    pub flips: i64,
+    pub flip_rate: Option<f64>,
+    /// Commits the test both passed and failed on.
+    pub flaky_commits: i64,
+    pub last_failed_at: Option<DateTime<Utc>>,
+}
+
+#[async_trait]
+pub trait TestResultRepo: Send + Sync {
+    /// Store the cases of a stage's report, replacing the ones read from it
+    /// before.
+    async fn replace_report(
+        &self,
+        run_id: ResourceId,
+        pipeline_id: ResourceId,
+        stage: &str,
+        report_path: &str,
+        cases: &[TestCase],
+    ) -> DbResult<u64>;
+
+    /// Test counts of a run.
+    async fn summary(&self, run_id: ResourceId) -> DbResult<TestSummary>;
+
+    /// Failed and errored tests of a run, by stage, suite and name.
+    async fn failures(&self, run_id: ResourceId, limit: i64) -> DbResult<Vec<TestResultRecord>>;
+
+    /// Slowest tests of a run, slowest first.
+    async fn slowest(&self, run_id: ResourceId, limit: i64) -> DbResult<Vec<TestResultRecord>>;
+
+    /// Failed and errored tests of a run that didn't fail in `baseline_id`,
+    /// including those it didn't run, by stage, suite and name.
+    async fn newly_failing(
+        &self,
+        run_id: ResourceId,
+        baseline_id: ResourceId,
+        limit: i64,
+    ) -> DbResult<Vec<TestResultRecord>>;
+
+    /// Tests of the pipeline's last `runs` runs with test results that
+    /// keep flipping between passing and failing (at least three times on
+    /// a branch) or both passed and failed on one commit, flakiest first.
+    async fn flaky_tests(
+        &self,
+        pipeline_id: ResourceId,
+        runs: i64,
+        limit: i64,
+    ) -> DbResult<Vec<FlakyTest>>;
+}
+
+/// PostgreSQL implementation of TestResultRepo.
+pub struct PgTestResultRepo {
+    pool: PgPool,
+}
+
+impl PgTestResultRepo {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl TestResultRepo for PgTestResultRepo {
+    async fn replace_report(
+        &self,
+        run_id: ResourceId,
+        pipeline_id: ResourceId,
+        stage: &str,
+        report_path: &str,
+        cases: &[TestCase],
+    ) -> DbResult<u64> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "DELETE FROM test_results WHERE pipeline_run_id = $1 AND stage_name = $2 AND report_path = $3",
+        )
+        .bind(run_id.as_uuid())
+        .bind(stage)
+        .bind(report_path)
+        .execute(&mut *tx)
+        .await?;
+
+        let ids: Vec<uuid::Uuid> = cases.iter().map(|_| uuid::Uuid::now_v7()).collect();
+        let suites: Vec<&str> = cases.iter().map(|c| c.suite.as_str()).collect();
+        let classnames: Vec<&str> = cases.iter().map(|c| c.classname.as_str()).collect();
+        let names: Vec<&str> = cases.iter().map(|c| c.name.as_str()).collect();
+        let statuses: Vec<&str> = cases.iter().map(|c| c.status.as_str()).collect();
+        let durations: Vec<f64> = cases.iter().map(|c| c.duration_secs).collect();
+        let messages: Vec<Option<&str>> = cases.iter().map(|c| c.message.as_deref()).collect();
+        let details: Vec<Option<&str>> = cases.iter().map(|c| c.details.as_deref()).collect();
+        let result = sqlx::query(
+            r#"
+            INSERT INTO test_results (
+                id, pipeline_run_id, pipeline_id, stage_name, report_path,
+                suite, classname, name, status, duration_secs, message, details
+            )
+            SELECT id, $1, $2, $3, $4, suite, classname, name, status, duration_secs, message, details
+            FROM UNNEST($5::uuid[], $6::text[], $7::text[], $8::text[], $9::text[],
+                        $10::float8[], $11::text[], $12::text[])
+                AS t(id, suite, classname, name, status, duration_secs, message, details)
+            "#,
+        )
+        .bind(run_id.as_uuid())
+        .bind(pipeline_id.as_uuid())
+        .bind(stage)
+        .bind(report_path)
+        .bind(&ids)
+        .bind(&suites)
+        .bind(&classnames)
+        .bind(&names)
+        .bind(&statuses)
+        .bind(&durations)
+        .bind(&messages)
+        .bind(&details)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn summary(&self, run_id: ResourceId) -> DbResult<TestSummary> {
+        let summary = sqlx::query_as::<_, TestSummary>(
+            r#"
+            SELECT COUNT(*) AS total,
+                   COUNT(*) FILTER (WHERE status = 'passed') AS passed,
+                   COUNT(*) FILTER (WHERE status = 'failed') AS failed,
+                   COUNT(*) FILTER (WHERE status = 'errored') AS errored,
+                   COUNT(*) FILTER (WHERE status = 'skipped') AS skipped,
+                   COALESCE(SUM(duration_secs), 0)::float8 AS duration_secs
+            FROM test_results
+            WHERE pipeline_run_id = $1
+            "#,
+        )
+        .bind(run_id.as_uuid())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(summary)
+    }
+
+    async fn failures(&self, run_id: ResourceId, limit: i64) -> DbResult<Vec<TestResultRecord>> {
+        let records = sqlx::query_as::<_, TestResultRecord>(
+            r#"
+            SELECT * FROM test_results
+            WHERE pipeline_run_id = $1 AND status IN ('failed', 'errored')
+            ORDER BY stage_name, suite, classname, name
+            LIMIT $2
+            "#,
+        )
+        .bind(run_id.as_uuid())
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn slowest(&self, run_id: ResourceId, limit: i64) -> DbResult<Vec<TestResultRecord>> {
+        let records = sqlx::query_as::<_, TestResultRecord>(
+            r#"
+            SELECT * FROM test_results
+            WHERE pipeline_run_id = $1 AND status <> 'skipped'
+            ORDER BY duration_secs DESC, classname, name
+            LIMIT $2
+            "#,
+        )
+        .bind(run_id.as_uuid())
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn newly_failing(
+        &self,
+        run_id: ResourceId,
+        baseline_id: ResourceId,
+        limit: i64,
+    ) -> DbResult<Vec<TestResultRecord>> {
+        let records = sqlx::query_as::<_, TestResultRecord>(
+            r#"
+            SELECT * FROM test_results t
+            WHERE t.pipeline_run_id = $1 AND t.status IN ('failed', 'errored')
+              AND NOT EXISTS (
+                SELECT 1 FROM test_results b
+                WHERE b.pipeline_run_id = $2
+                  AND b.classname = t.classname AND b.name = t.name
+                  AND b.status IN ('failed', 'errored')
+              )
+            ORDER BY t.stage_name, t.suite, t.classname, t.name
+            LIMIT $3
+            "#,
+        )
+        .bind(run_id.as_uuid())
+        .bind(baseline_id.as_uuid())
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn flaky_tests(
+        &self,
+        pipeline_id: ResourceId,
+        runs: i64,
+        limit: i64,
+    ) -> DbResult<Vec<FlakyTest>> {
+        let tests = sqlx::query_as::<_, FlakyTest>(
+            r#"
+            WITH runs AS (
+                SELECT r.id, r.created_at,
+                       NULLIF(r.git_info->>'branch', '') AS branch,
+                       NULLIF(r.git_info->>'sha', '') AS sha
+                FROM pipeline_runs r
+                WHERE r.pipeline_id = $1
+                  AND EXISTS (SELECT 1 FROM test_results t WHERE t.pipeline_run_id = r.id)
+                ORDER BY r.created_at DESC
+                LIMIT $2
+            ),
+            outcomes AS (
+                SELECT t.classname, t.name, runs.branch, runs.sha, runs.created_at,
+                       bool_or(t.status IN ('failed', 'errored')) AS failure
+                FROM test_results t
+                JOIN runs ON runs.id = t.pipeline_run_id
+                WHERE t.status <> 'skipped'
+                GROUP BY t.classname, t.name, runs.id, runs.branch, runs.sha, runs.created_at
+            ),
+            ordered AS (
+                SELECT *, LAG(failure) OVER (
+                    PARTITION BY classname, name, branch ORDER BY created_at
+                ) AS previous_failure
+                FROM outcomes
+            ),
+            commits AS (
+                SELECT classname, name, COUNT(*) AS flaky_commits
+                FROM (
+                    SELECT classname, name, sha
+                    FROM outcomes
+                    WHERE sha IS NOT NULL
+                    GROUP BY classname, name, sha
+                    HAVING bool_or(failure) AND NOT bool_and(failure)
+                ) flaky
+                GROUP BY classname, name
+            ),
+            flips AS (
+                SELECT classname, name,
+                       COUNT(*) AS total_runs,
+                       COUNT(*) FILTER (WHERE failure) AS failed,
+                       COUNT(*) FILTER (WHERE failure <> previous_failure) AS flips,
+                       COUNT(*) FILTER (WHERE failure <> previous_failure)::float8
+                           / NULLIF(COUNT(previous_failure), 0) AS flip_rate,
+                       MAX(created_at) FILTER (WHERE failure) AS last_failed_at
+                FROM ordered
+                GROUP BY classname, name
+            )
+            SELECT f.classname, f.name, f.total_runs, f.failed, f.flips, f.flip_rate,
+                   COALESCE(c.flaky_commits, 0) AS flaky_commits, f.last_failed_at
+            FROM flips f
+            LEFT JOIN commits c USING (classname, name)
+            WHERE f.flips >= 3 OR c.flaky_commits > 0
+            ORDER BY flaky_commits DESC, f.flip_rate DESC NULLS LAST, f.classname, f.name
+            LIMIT $3
+            "#,
+        )
+        .bind(pipeline_id.as_uuid())
+        .bind(runs)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(tests)
+    }
+}
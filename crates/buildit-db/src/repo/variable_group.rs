@@ -0,0 +1,210 @@
+//! Variable group repository.
+//!
+//! A variable group is a named set of non-secret variables, such as a
+//! region or log level, that jobs get as environment variables. A tenant's
+//! groups apply to all of its pipelines; a pipeline's only to it.
+
+use async_trait::async_trait;
+use buildit_core::ResourceId;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+use crate::{DbError, DbResult};
+
+/// Variables of a tenant, or of one of its pipelines.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct VariableGroup {
+    pub id: uuid::Uuid,
+    pub tenant_id: uuid::Uuid,
+    /// The pipeline the group applies to; all of the tenant's when unset.
+    pub pipeline_id: Option<uuid::Uuid>,
+    pub name: String,
+    pub description: Option<String>,
+    /// Variable names and values.
+    #[schema(value_type = HashMap<String, String>)]
+    pub variables: serde_json::Value,
+    pub created_by: Option<uuid::Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl VariableGroup {
+    /// The group's variables.
+    pub fn variables(&self) -> HashMap<String, String> {
+        serde_json::from_value(self.variables.clone()).unwrap_or_default()
+    }
+}
+
+#[async_trait]
+pub trait VariableGroupRepo: Send + Sync {
+    /// Fails with `Duplicate` if the scope has a group of that name.
+    async fn create_group(&self, group: &VariableGroup) -> DbResult<VariableGroup>;
+
+    /// The tenant's groups, or with `pipeline_id` the pipeline's, by name.
+    async fn list_groups(
+        &self,
+        tenant_id: ResourceId,
+        pipeline_id: Option<ResourceId>,
+    ) -> DbResult<Vec<VariableGroup>>;
+
+    /// The tenant's groups and those of all its pipelines, by name.
+    async fn list_all_groups(&self, tenant_id: ResourceId) -> DbResult<Vec<VariableGroup>>;
+
+    async fn get_group(&self, tenant_id: ResourceId, id: ResourceId) -> DbResult<VariableGroup>;
+
+    /// Save a group's name, description and variables.
+    async fn update_group(&self, group: &VariableGroup) -> DbResult<VariableGroup>;
+
+    async fn delete_group(&self, tenant_id: ResourceId, id: ResourceId) -> DbResult<()>;
+
+    /// Record the variables a run's jobs get, once it starts.
+    async fn set_run_variables(
+        &self,
+        run_id: ResourceId,
+        variables: serde_json::Value,
+    ) -> DbResult<()>;
+
+    /// The variables recorded for a run, if it has started.
+    async fn get_run_variables(&self, run_id: ResourceId) -> DbResult<Option<serde_json::Value>>;
+}
+
+/// PostgreSQL implementation of VariableGroupRepo.
+pub struct PgVariableGroupRepo {
+    pool: PgPool,
+}
+
+impl PgVariableGroupRepo {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+fn duplicate_name(e: sqlx::Error, name: &str) -> DbError {
+    match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+            DbError::Duplicate(format!("variable group {} already exists", name))
+        }
+        e => e.into(),
+    }
+}
+
+#[async_trait]
+impl VariableGroupRepo for PgVariableGroupRepo {
+    async fn create_group(&self, group: &VariableGroup) -> DbResult<VariableGroup> {
+        sqlx::query_as::<_, VariableGroup>(
+            r#"
+            INSERT INTO variable_groups
+                (id, tenant_id, pipeline_id, name, description, variables, created_by)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING *
+            "#,
+        )
+        .bind(group.id)
+        .bind(group.tenant_id)
+        .bind(group.pipeline_id)
+        .bind(&group.name)
+        .bind(&group.description)
+        .bind(&group.variables)
+        .bind(group.created_by)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| duplicate_name(e, &group.name))
+    }
+
+    async fn list_groups(
+        &self,
+        tenant_id: ResourceId,
+        pipeline_id: Option<ResourceId>,
+    ) -> DbResult<Vec<VariableGroup>> {
+        let groups = sqlx::query_as::<_, VariableGroup>(
+            r#"
+            SELECT * FROM variable_groups
+            WHERE tenant_id = $1 AND pipeline_id IS NOT DISTINCT FROM $2
+            ORDER BY name
+            "#,
+        )
+        .bind(tenant_id.as_uuid())
+        .bind(pipeline_id.map(|id| *id.as_uuid()))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(groups)
+    }
+
+    async fn list_all_groups(&self, tenant_id: ResourceId) -> DbResult<Vec<VariableGroup>> {
+        let groups = sqlx::query_as::<_, VariableGroup>(
+            "SELECT * FROM variable_groups WHERE tenant_id = $1 ORDER BY name, pipeline_id NULLS FIRST",
+        )
+        .bind(tenant_id.as_uuid())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(groups)
+    }
+
+    async fn get_group(&self, tenant_id: ResourceId, id: ResourceId) -> DbResult<VariableGroup> {
+        sqlx::query_as::<_, VariableGroup>(
+            "SELECT * FROM variable_groups WHERE id = $1 AND tenant_id = $2",
+        )
+        .bind(id.as_uuid())
+        .bind(tenant_id.as_uuid())
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("variable group {}", id)))
+    }
+
+    async fn update_group(&self, group: &VariableGroup) -> DbResult<VariableGroup> {
+        sqlx::query_as::<_, VariableGroup>(
+            r#"
+            UPDATE variable_groups
+            SET name = $3, description = $4, variables = $5, updated_at = NOW()
+            WHERE id = $1 AND tenant_id = $2
+            RETURNING *
+            "#,
+        )
+        .bind(group.id)
+        .bind(group.tenant_id)
+        .bind(&group.name)
+        .bind(&group.description)
+        .bind(&group.variables)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| duplicate_name(e, &group.name))?
+        .ok_or_else(|| DbError::NotFound(format!("variable group {}", group.id)))
+    }
+
+    async fn delete_group(&self, tenant_id: ResourceId, id: ResourceId) -> DbResult<()> {
+        let result = sqlx::query("DELETE FROM variable_groups WHERE id = $1 AND tenant_id = $2")
+            .bind(id.as_uuid())
+            .bind(tenant_id.as_uuid())
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!("variable group {}", id)));
+        }
+        Ok(())
+    }
+
+    async fn set_run_variables(
+        &self,
+        run_id: ResourceId,
+        variables: serde_json::Value,
+    ) -> DbResult<()> {
+        sqlx::query("UPDATE pipeline_runs SET variables = $2 WHERE id = $1")
+            .bind(run_id.as_uuid())
+            .bind(variables)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_run_variables(&self, run_id: ResourceId) -> DbResult<Option<serde_json::Value>> {
+        let variables: Option<Option<serde_json::Value>> =
+            sqlx::query_scalar("SELECT variables FROM pipeline_runs WHERE id = $1")
+                .bind(run_id.as_uuid())
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(variables.flatten())
+    }
+}
@@ -0,0 +1,325 @@
+//! Webhook subscription repository.
+//!
+//! A subscription is an HTTP endpoint a tenant registers for some of its
+//! events. Every event sent to it is kept as a delivery, with the response
+//! of its last attempt, so failed deliveries can be inspected and sent again.
+
+use async_trait::async_trait;
+use buildit_core::ResourceId;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+use crate::{DbError, DbResult};
+
+/// An endpoint receiving some of a tenant's events.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct WebhookSubscription {
+    pub id: uuid::Uuid,
+    pub tenant_id: uuid::Uuid,
+    pub url: String,
+    pub description: Option<String>,
+    /// Key signing deliveries.
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub events: Vec<String>,
+    pub enabled: bool,
+    pub created_by: Option<uuid::Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// An event sent, or being sent, to a subscription.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct WebhookDelivery {
+    pub id: uuid::Uuid,
+    pub subscription_id: uuid::Uuid,
+    pub tenant_id: uuid::Uuid,
+    pub event: String,
+    /// The JSON body posted to the endpoint.
+    pub payload: serde_json::Value,
+    /// `pending` while attempts remain, then `succeeded` or `failed`.
+    pub status: String,
+    pub attempts: i32,
+    /// HTTP status of the last attempt, if the endpoint answered.
+    pub response_status: Option<i32>,
+    pub response_body: Option<String>,
+    /// Why the last attempt failed.
+    pub error: Option<String>,
+    pub duration_ms: Option<i32>,
+    /// The delivery this one sends again.
+    pub redelivery_of: Option<uuid::Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub last_attempt_at: Option<DateTime<Utc>>,
+}
+
+/// Outcome of one attempt at a delivery.
+#[derive(Debug, Clone, Default)]
+pub struct DeliveryAttempt {
+    pub status: String,
+    pub response_status: Option<i32>,
+    pub response_body: Option<String>,
+    pub error: Option<String>,
+    pub duration_ms: i32,
+}
+
+#[async_trait]
+pub trait WebhookSubscriptionRepo: Send + Sync {
+    async fn create_subscription(
+        &self,
+        subscription: &WebhookSubscription,
+    ) -> DbResult<WebhookSubscription>;
+
+    async fn list_subscriptions(&self, tenant_id: ResourceId)
+    -> DbResult<Vec<WebhookSubscription>>;
+
+    async fn get_subscription(
+        &self,
+        tenant_id: ResourceId,
+        id: ResourceId,
+    ) -> DbResult<WebhookSubscription>;
+
+    /// Save a subscription's URL, description, secret, events and whether
+    /// it is enabled.
+    async fn update_subscription(
+        &self,
+        subscription: &WebhookSubscription,
+    ) -> DbResult<WebhookSubscription>;
+
+    /// Delete a subscription and its deliveries.
+    async fn delete_subscription(&self, tenant_id: ResourceId, id: ResourceId) -> DbResult<()>;
+
+    /// Enabled subscriptions of the tenant that receive `event`.
+    async fn subscriptions_for_event(
+        &self,
+        tenant_id: ResourceId,
+        event: &str,
+    ) -> DbResult<Vec<WebhookSubscription>>;
+
+    async fn create_delivery(&self, delivery: &WebhookDelivery) -> DbResult<WebhookDelivery>;
+
+    async fn get_delivery(
+        &self,
+        tenant_id: ResourceId,
+        id: ResourceId,
+    ) -> DbResult<WebhookDelivery>;
+
+    /// A subscription's most recent deliveries, newest first.
+    async fn list_deliveries(
+        &self,
+        subscription_id: ResourceId,
+        limit: i64,
+    ) -> DbResult<Vec<WebhookDelivery>>;
+
+    /// Record an attempt at a delivery and the status it leaves it in.
+    async fn record_attempt(
+        &self,
+        id: ResourceId,
+        attempt: &DeliveryAttempt,
+    ) -> DbResult<WebhookDelivery>;
+}
+
+/// PostgreSQL implementation of WebhookSubscriptionRepo.
+pub struct PgWebhookSubscriptionRepo {
+    pool: PgPool,
+}
+
+impl PgWebhookSubscriptionRepo {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl WebhookSubscriptionRepo for PgWebhookSubscriptionRepo {
+    async fn create_subscription(
+        &self,
+        subscription: &WebhookSubscription,
+    ) -> DbResult<WebhookSubscription> {
+        let subscription = sqlx::query_as::<_, WebhookSubscription>(
+            r#"
+            INSERT INTO webhook_subscriptions
+                (id, tenant_id, url, description, secret, events, enabled, created_by)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING *
+            "#,
+        )
+        .bind(subscription.id)
+        .bind(subscription.tenant_id)
+        .bind(&subscription.url)
+        .bind(&subscription.description)
+        .bind(&subscription.secret)
+        .bind(&subscription.events)
+        .bind(subscription.enabled)
+        .bind(subscription.created_by)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(subscription)
+    }
+
+    async fn list_subscriptions(
+        &self,
+        tenant_id: ResourceId,
+    ) -> DbResult<Vec<WebhookSubscription>> {
+        let subscriptions = sqlx::query_as::<_, WebhookSubscription>(
+            "SELECT * FROM webhook_subscriptions WHERE tenant_id = $1 ORDER BY created_at",
+        )
+        .bind(tenant_id.as_uuid())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(subscriptions)
+    }
+
+    async fn get_subscription(
+        &self,
+        tenant_id: ResourceId,
+        id: ResourceId,
+    ) -> DbResult<WebhookSubscription> {
+        sqlx::query_as::<_, WebhookSubscription>(
+            "SELECT * FROM webhook_subscriptions WHERE id = $1 AND tenant_id = $2",
+        )
+        .bind(id.as_uuid())
+        .bind(tenant_id.as_uuid())
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("webhook subscription {}", id)))
+    }
+
+    async fn update_subscription(
+        &self,
+        subscription: &WebhookSubscription,
+    ) -> DbResult<WebhookSubscription> {
+        sqlx::query_as::<_, WebhookSubscription>(
+            r#"
+            UPDATE webhook_subscriptions
+            SET url = $3, description = $4, secret = $5, events = $6, enabled = $7,
+                updated_at = NOW()
+            WHERE id = $1 AND tenant_id = $2
+            RETURNING *
+            "#,
+        )
+        .bind(subscription.id)
+        .bind(subscription.tenant_id)
+        .bind(&subscription.url)
+        .bind(&subscription.description)
+        .bind(&subscription.secret)
+        .bind(&subscription.events)
+        .bind(subscription.enabled)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("webhook subscription {}", subscription.id)))
+    }
+
+    async fn delete_subscription(&self, tenant_id: ResourceId, id: ResourceId) -> DbResult<()> {
+        let result =
+            sqlx::query("DELETE FROM webhook_subscriptions WHERE id = $1 AND tenant_id = $2")
+                .bind(id.as_uuid())
+                .bind(tenant_id.as_uuid())
+                .execute(&self.pool)
+                .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!("webhook subscription {}", id)));
+        }
+        Ok(())
+    }
+
+    async fn subscriptions_for_event(
+        &self,
+        tenant_id: ResourceId,
+        event: &str,
+    ) -> DbResult<Vec<WebhookSubscription>> {
+        let subscriptions = sqlx::query_as::<_, WebhookSubscription>(
+            r#"
+            SELECT * FROM webhook_subscriptions
+            WHERE tenant_id = $1 AND enabled AND $2 = ANY(events)
+            ORDER BY created_at
+            "#,
+        )
+        .bind(tenant_id.as_uuid())
+        .bind(event)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(subscriptions)
+    }
+
+    async fn create_delivery(&self, delivery: &WebhookDelivery) -> DbResult<WebhookDelivery> {
+        let delivery = sqlx::query_as::<_, WebhookDelivery>(
+            r#"
+            INSERT INTO webhook_deliveries
+                (id, subscription_id, tenant_id, event, payload, redelivery_of)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING *
+            "#,
+        )
+        .bind(delivery.id)
+        .bind(delivery.subscription_id)
+        .bind(delivery.tenant_id)
+        .bind(&delivery.event)
+        .bind(&delivery.payload)
+        .bind(delivery.redelivery_of)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(delivery)
+    }
+
+    async fn get_delivery(
+        &self,
+        tenant_id: ResourceId,
+        id: ResourceId,
+    ) -> DbResult<WebhookDelivery> {
+        sqlx::query_as::<_, WebhookDelivery>(
+            "SELECT * FROM webhook_deliveries WHERE id = $1 AND tenant_id = $2",
+        )
+        .bind(id.as_uuid())
+        .bind(tenant_id.as_uuid())
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("webhook delivery {}", id)))
+    }
+
+    async fn list_deliveries(
+        &self,
+        subscription_id: ResourceId,
+        limit: i64,
+    ) -> DbResult<Vec<WebhookDelivery>> {
+        let deliveries = sqlx::query_as::<_, WebhookDelivery>(
+            r#"
+            SELECT * FROM webhook_deliveries
+            WHERE subscription_id = $1
+            ORDER BY created_at DESC
+            LIMIT $2
+            "#,
+        )
+        .bind(subscription_id.as_uuid())
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(deliveries)
+    }
+
+    async fn record_attempt(
+        &self,
+        id: ResourceId,
+        attempt: &DeliveryAttempt,
+    ) -> DbResult<WebhookDelivery> {
+        sqlx::query_as::<_, WebhookDelivery>(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = $2, attempts = attempts + 1, response_status = $3,
+                response_body = $4, error = $5, duration_ms = $6, last_attempt_at = NOW()
+            WHERE id = $1
+            RETURNING *
+            "#,
+        )
+        .bind(id.as_uuid())
+        .bind(&attempt.status)
+        .bind(attempt.response_status)
+        .bind(&attempt.response_body)
+        .bind(&attempt.error)
+        .bind(attempt.duration_ms)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("webhook delivery {}", id)))
+    }
+}
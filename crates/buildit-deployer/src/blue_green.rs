@@ -0,0 +1,307 @@
+//! Blue/green manifests for the Kubernetes deployer.
+//!
+//! Each service gets two Deployments (`<service>-blue`, `<service>-green`)
+//! behind a Service whose selector points at the active color. New versions
+//! go to the idle color and are reachable through `<service>-preview` for
+//! smoke checks; promotion flips the main Service selector, and rollback
+//! flips it back.
+
+use buildit_core::deployer::{DeploymentResources, DeploymentSpec, HealthCheck};
+use k8s_openapi::api::apps::v1::{Deployment, DeploymentSpec as K8sDeploymentSpec};
+use k8s_openapi::api::core::v1::{
+    Container, ContainerPort, EnvVar, HTTPGetAction, PodSpec, PodTemplateSpec, Probe,
+    ResourceRequirements, Service, ServicePort, ServiceSpec,
+};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{LabelSelector, ObjectMeta};
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use std::collections::BTreeMap;
+
+pub const NAME_LABEL: &str = "app.kubernetes.io/name";
+pub const COLOR_LABEL: &str = "buildit.dev/color";
+pub const ACTIVE_ANNOTATION: &str = "buildit.dev/active-color";
+pub const PREVIOUS_ANNOTATION: &str = "buildit.dev/previous-color";
+pub const PENDING_ANNOTATION: &str = "buildit.dev/pending-color";
+
+const DEFAULT_PORT: u16 = 8080;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Blue,
+    Green,
+}
+
+impl Color {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Color::Blue => "blue",
+            Color::Green => "green",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "blue" => Some(Color::Blue),
+            "green" => Some(Color::Green),
+            _ => None,
+        }
+    }
+
+    pub fn other(self) -> Self {
+        match self {
+            Color::Blue => Color::Green,
+            Color::Green => Color::Blue,
+        }
+    }
+}
+
+/// Color to deploy a new version to, given the currently active one.
+pub fn target_color(active: Option<Color>) -> Color {
+    active.map(Color::other).unwrap_or(Color::Blue)
+}
+
+/// Read a color annotation from the main Service.
+pub fn annotation_color(service: &Service, key: &str) -> Option<Color> {
+    service
+        .metadata
+        .annotations
+        .as_ref()
+        .and_then(|a| a.get(key))
+        .and_then(|c| Color::parse(c))
+}
+
+pub fn deployment_name(service: &str, color: Color) -> String {
+    format!("{}-{}", service, color.as_str())
+}
+
+pub fn preview_service_name(service: &str) -> String {
+    format!("{}-preview", service)
+}
+
+/// Container port for a spec: the health check port, or 8080.
+pub fn container_port(spec: &DeploymentSpec) -> u16 {
+    spec.health_check
+        .as_ref()
+        .map(|h| h.port)
+        .unwrap_or(DEFAULT_PORT)
+}
+
+fn labels(service: &str, color: Color) -> BTreeMap<String, String> {
+    BTreeMap::from([
+        (NAME_LABEL.to_string(), service.to_string()),
+        (COLOR_LABEL.to_string(), color.as_str().to_string()),
+        (
+            "app.kubernetes.io/managed-by".to_string(),
+            "buildit".to_string(),
+        ),
+    ])
+}
+
+/// Selector matching one color of a service.
+pub fn color_selector(service: &str, color: Color) -> BTreeMap<String, String> {
+    BTreeMap::from([
+        (NAME_LABEL.to_string(), service.to_string()),
+        (COLOR_LABEL.to_string(), color.as_str().to_string()),
+    ])
+}
+
+/// Deployment for one color of a service.
+pub fn build_deployment(spec: &DeploymentSpec, namespace: &str, color: Color) -> Deployment {
+    let port = container_port(spec);
+    let mut env: Vec<EnvVar> = spec
+        .env
+        .iter()
+        .map(|(name, value)| EnvVar {
+            name: name.clone(),
+            value: Some(value.clone()),
+            ..Default::default()
+        })
+        .collect();
+    env.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Deployment {
+        metadata: ObjectMeta {
+            name: Some(deployment_name(&spec.service, color)),
+            namespace: Some(namespace.to_string()),
+            labels: Some(labels(&spec.service, color)),
+            ..Default::default()
+        },
+        spec: Some(K8sDeploymentSpec {
+            replicas: Some(spec.replicas as i32),
+            selector: LabelSelector {
+                match_labels: Some(color_selector(&spec.service, color)),
+                ..Default::default()
+            },
+            template: PodTemplateSpec {
+                metadata: Some(ObjectMeta {
+                    labels: Some(labels(&spec.service, color)),
+                    ..Default::default()
+                }),
+                spec: Some(PodSpec {
+                    containers: vec![Container {
+                        name: spec.service.clone(),
+                        image: Some(spec.image.clone()),
+                        env: Some(env),
+                        ports: Some(vec![ContainerPort {
+                            container_port: port as i32,
+                            ..Default::default()
+                        }]),
+                        resources: Some(resource_requirements(&spec.resources)),
+                        readiness_probe: spec.health_check.as_ref().map(readiness_probe),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }),
+            },
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// Service routing to `color`. Used for both the main and preview services.
+pub fn build_service(
+    name: &str,
+    namespace: &str,
+    service: &str,
+    color: Color,
+    port: u16,
+) -> Service {
+    Service {
+        metadata: ObjectMeta {
+            name: Some(name.to_string()),
+            namespace: Some(namespace.to_string()),
+            labels: Some(BTreeMap::from([(
+                NAME_LABEL.to_string(),
+                service.to_string(),
+            )])),
+            ..Default::default()
+        },
+        spec: Some(ServiceSpec {
+            selector: Some(color_selector(service, color)),
+            ports: Some(vec![ServicePort {
+                name: Some("http".to_string()),
+                port: 80,
+                target_port: Some(IntOrString::Int(port as i32)),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// Merge patch that points the main Service at `color`.
+pub fn switch_patch(service: &str, color: Color, previous: Option<Color>) -> serde_json::Value {
+    serde_json::json!({
+        "metadata": {
+            "annotations": {
+                ACTIVE_ANNOTATION: color.as_str(),
+                PREVIOUS_ANNOTATION: previous.map(Color::as_str),
+                PENDING_ANNOTATION: null,
+            }
+        },
+        "spec": {
+            "selector": color_selector(service, color),
+        }
+    })
+}
+
+fn resource_requirements(resources: &DeploymentResources) -> ResourceRequirements {
+    let quantities = |cpu: &Option<String>, memory: &Option<String>| {
+        let mut map = BTreeMap::new();
+        if let Some(cpu) = cpu {
+            map.insert("cpu".to_string(), Quantity(cpu.clone()));
+        }
+        if let Some(memory) = memory {
+            map.insert("memory".to_string(), Quantity(memory.clone()));
+        }
+        (!map.is_empty()).then_some(map)
+    };
+
+    ResourceRequirements {
+        limits: quantities(&resources.cpu_limit, &resources.memory_limit),
+        requests: quantities(&resources.cpu_request, &resources.memory_request),
+        ..Default::default()
+    }
+}
+
+fn readiness_probe(check: &HealthCheck) -> Probe {
+    Probe {
+        http_get: Some(HTTPGetAction {
+            path: Some(check.path.clone()),
+            port: IntOrString::Int(check.port as i32),
+            ..Default::default()
+        }),
+        period_seconds: Some(check.interval_seconds as i32),
+        timeout_seconds: Some(check.timeout_seconds as i32),
+        success_threshold: Some(check.healthy_threshold as i32),
+        failure_threshold: Some(check.unhealthy_threshold as i32),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use buildit_core::ResourceId;
+    use buildit_core::deployer::DeploymentStrategy;
+    use std::collections::HashMap;
+
+    fn spec() -> DeploymentSpec {
+        DeploymentSpec {
+            id: ResourceId::new(),
+            service: "api".to_string(),
+            environment: "prod".to_string(),
+            image: "ghcr.io/acme/api:v2".to_string(),
+            replicas: 3,
+            env: HashMap::from([("RUST_LOG".to_string(), "info".to_string())]),
+            strategy: DeploymentStrategy::BlueGreen {
+                smoke_checks: vec![],
+                auto_promote: false,
+            },
+            resources: DeploymentResources {
+                cpu_limit: Some("500m".to_string()),
+                ..Default::default()
+            },
+            health_check: None,
+            source: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_target_color_alternates() {
+        assert_eq!(target_color(None), Color::Blue);
+        assert_eq!(target_color(Some(Color::Blue)), Color::Green);
+        assert_eq!(target_color(Some(Color::Green)), Color::Blue);
+    }
+
+    #[test]
+    fn test_build_deployment_for_color() {
+        let deployment = build_deployment(&spec(), "prod", Color::Green);
+        assert_eq!(deployment.metadata.name.as_deref(), Some("api-green"));
+
+        let k8s_spec = deployment.spec.unwrap();
+        assert_eq!(k8s_spec.replicas, Some(3));
+        let selector = k8s_spec.selector.match_labels.unwrap();
+        assert_eq!(selector.get(COLOR_LABEL).map(String::as_str), Some("green"));
+
+        let container = &k8s_spec.template.spec.unwrap().containers[0];
+        assert_eq!(container.image.as_deref(), Some("ghcr.io/acme/api:v2"));
+        assert_eq!(container.ports.as_ref().unwrap()[0].container_port, 8080);
+        assert!(container.resources.as_ref().unwrap().limits.is_some());
+        assert!(container.resources.as_ref().unwrap().requests.is_none());
+    }
+
+    #[test]
+    fn test_switch_patch_records_previous_color() {
+        let patch = switch_patch("api", Color::Green, Some(Color::Blue));
+        assert_eq!(patch["spec"]["selector"][COLOR_LABEL], "green");
+        assert_eq!(patch["metadata"]["annotations"][ACTIVE_ANNOTATION], "green");
+        assert_eq!(
+            patch["metadata"]["annotations"][PREVIOUS_ANNOTATION],
+            "blue"
+        );
+        assert!(patch["metadata"]["annotations"][PENDING_ANNOTATION].is_null());
+    }
+}
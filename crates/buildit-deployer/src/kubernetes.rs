@@ -1,18 +1,33 @@
 //! Kubernetes deployer implementation.
 
 use async_trait::async_trait;
-use buildit_core::Result;
 use buildit_core::deployer::*;
 use buildit_core::executor::{LogLine, TerminalSession};
+use buildit_core::{Error, Result};
+use chrono::Utc;
 use futures::stream::BoxStream;
+use k8s_openapi::api::apps::v1::Deployment;
+use k8s_openapi::api::core::v1::Service;
 use kube::Client;
+use kube::api::{Api, DynamicObject, Patch, PatchParams};
+use kube::discovery::{self, Scope};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+use tracing::info;
+
+use crate::blue_green::{self, Color};
+use crate::kustomize;
+use crate::manifests::{self, Manifest};
+
+/// How long to wait for a new color to become ready.
+const ROLLOUT_TIMEOUT: Duration = Duration::from_secs(600);
 
 /// Kubernetes-based deployer.
 pub struct KubernetesDeployer {
-    #[allow(dead_code)]
     client: Client,
-    #[allow(dead_code)]
     namespace: String,
+    http: reqwest::Client,
 }
 
 impl KubernetesDeployer {
@@ -20,18 +35,476 @@ impl KubernetesDeployer {
         let client = Client::try_default()
             .await
             .map_err(|e| buildit_core::Error::Internal(e.to_string()))?;
-        Ok(Self {
-            client,
-            namespace: namespace.into(),
-        })
+        Ok(Self::with_client(client, namespace))
     }
 
     pub fn with_client(client: Client, namespace: impl Into<String>) -> Self {
         Self {
             client,
             namespace: namespace.into(),
+            http: reqwest::Client::new(),
         }
     }
+
+    /// Split a handle's `deployer_id` (`namespace/service` or `service`).
+    fn target<'a>(&'a self, handle: &'a DeploymentHandle) -> (&'a str, &'a str) {
+        handle
+            .deployer_id
+            .split_once('/')
+            .unwrap_or((&self.namespace, &handle.deployer_id))
+    }
+
+    fn deployments(&self, namespace: &str) -> Api<Deployment> {
+        Api::namespaced(self.client.clone(), namespace)
+    }
+
+    fn services(&self, namespace: &str) -> Api<Service> {
+        Api::namespaced(self.client.clone(), namespace)
+    }
+
+    async fn apply<K>(&self, api: &Api<K>, name: &str, object: &K) -> Result<()>
+    where
+        K: kube::Resource
+            + Clone
+            + serde::Serialize
+            + serde::de::DeserializeOwned
+            + std::fmt::Debug,
+    {
+        api.patch(
+            name,
+            &PatchParams::apply("buildit").force(),
+            &Patch::Apply(object),
+        )
+        .await
+        .map_err(|e| Error::DeploymentFailed(format!("failed to apply {}: {}", name, e)))?;
+        Ok(())
+    }
+
+    /// Poll until all replicas of a deployment are updated and ready.
+    async fn wait_ready(&self, namespace: &str, name: &str, replicas: u32) -> Result<()> {
+        let api = self.deployments(namespace);
+        let deadline = tokio::time::Instant::now() + ROLLOUT_TIMEOUT;
+        loop {
+            let deployment = api
+                .get(name)
+                .await
+                .map_err(|e| Error::DeploymentFailed(e.to_string()))?;
+            let status = deployment.status.unwrap_or_default();
+            if status.updated_replicas.unwrap_or(0) >= replicas as i32
+                && status.ready_replicas.unwrap_or(0) >= replicas as i32
+            {
+                return Ok(());
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(Error::Timeout(format!(
+                    "deployment {} not ready after {}s",
+                    name,
+                    ROLLOUT_TIMEOUT.as_secs()
+                )));
+            }
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        }
+    }
+
+    /// Run smoke checks against the preview service of a blue/green deploy.
+    async fn run_smoke_checks(
+        &self,
+        namespace: &str,
+        service: &str,
+        checks: &[SmokeCheck],
+    ) -> Result<()> {
+        let host = format!(
+            "{}.{}.svc",
+            blue_green::preview_service_name(service),
+            namespace
+        );
+        for check in checks {
+            let url = format!("http://{}:80{}", host, check.path);
+            let response = self
+                .http
+                .get(&url)
+                .timeout(Duration::from_secs(check.timeout_seconds as u64))
+                .send()
+                .await
+                .map_err(|e| {
+                    Error::DeploymentFailed(format!("smoke check {} failed: {}", url, e))
+                })?;
+            if response.status().as_u16() != check.expected_status {
+                return Err(Error::DeploymentFailed(format!(
+                    "smoke check {} returned {}, expected {}",
+                    url,
+                    response.status().as_u16(),
+                    check.expected_status
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Point the main Service at `color`, remembering the old color.
+    async fn switch(&self, namespace: &str, service: &str, color: Color) -> Result<()> {
+        let api = self.services(namespace);
+        let current = api
+            .get(service)
+            .await
+            .map_err(|e| Error::NotFound(format!("service {}: {}", service, e)))?;
+        let previous = blue_green::annotation_color(&current, blue_green::ACTIVE_ANNOTATION)
+            .filter(|c| *c != color);
+
+        api.patch(
+            service,
+            &PatchParams::default(),
+            &Patch::Merge(blue_green::switch_patch(service, color, previous)),
+        )
+        .await
+        .map_err(|e| Error::DeploymentFailed(e.to_string()))?;
+
+        info!(service = %service, color = color.as_str(), "Switched traffic");
+        Ok(())
+    }
+
+    async fn deploy_blue_green(
+        &self,
+        spec: &DeploymentSpec,
+        smoke_checks: &[SmokeCheck],
+        auto_promote: bool,
+    ) -> Result<()> {
+        let ns = self.namespace.as_str();
+        let service = spec.service.as_str();
+        let port = blue_green::container_port(spec);
+
+        let existing = self.services(ns).get_opt(service).await.map_err(|e| {
+            Error::DeploymentFailed(format!("failed to read service {}: {}", service, e))
+        })?;
+        let active = existing
+            .as_ref()
+            .and_then(|s| blue_green::annotation_color(s, blue_green::ACTIVE_ANNOTATION));
+        let target = blue_green::target_color(active);
+        let name = blue_green::deployment_name(service, target);
+
+        info!(service = %service, color = target.as_str(), "Deploying idle color");
+        self.apply(
+            &self.deployments(ns),
+            &name,
+            &blue_green::build_deployment(spec, ns, target),
+        )
+        .await?;
+
+        let preview = blue_green::preview_service_name(service);
+        self.apply(
+            &self.services(ns),
+            &preview,
+            &blue_green::build_service(&preview, ns, service, target, port),
+        )
+        .await?;
+
+        self.wait_ready(ns, &name, spec.replicas).await?;
+        self.run_smoke_checks(ns, service, smoke_checks).await?;
+
+        if existing.is_none() {
+            // First deploy: nothing to switch away from
+            let main = blue_green::build_service(service, ns, service, target, port);
+            self.apply(&self.services(ns), service, &main).await?;
+            return self.switch(ns, service, target).await;
+        }
+
+        if auto_promote {
+            self.switch(ns, service, target).await
+        } else {
+            self.services(ns)
+                .patch(
+                    service,
+                    &PatchParams::default(),
+                    &Patch::Merge(serde_json::json!({
+                        "metadata": {
+                            "annotations": { blue_green::PENDING_ANNOTATION: target.as_str() }
+                        }
+                    })),
+                )
+                .await
+                .map_err(|e| Error::DeploymentFailed(e.to_string()))?;
+            info!(service = %service, color = target.as_str(), "Awaiting promotion");
+            Ok(())
+        }
+    }
+
+    /// Load the manifests for a spec, or `None` for image-based specs.
+    ///
+    /// Manifest directories get `service`, `environment` and `namespace` as
+    /// variables unless the spec overrides them; kustomizations are built
+    /// for the spec's environment with its image set; rendered manifests
+    /// are used as-is.
+    async fn load_manifests(&self, spec: &DeploymentSpec) -> Result<Option<Vec<Manifest>>> {
+        match &spec.source {
+            DeploymentSource::Image => Ok(None),
+            DeploymentSource::Manifests { path, variables } => {
+                let mut vars = HashMap::from([
+                    ("service".to_string(), spec.service.clone()),
+                    ("environment".to_string(), spec.environment.clone()),
+                    ("namespace".to_string(), self.namespace.clone()),
+                ]);
+                vars.extend(variables.iter().map(|(k, v)| (k.clone(), v.clone())));
+                manifests::load_dir(Path::new(path), &vars).map(Some)
+            }
+            DeploymentSource::Kustomize { path, overlays } => {
+                let dir = kustomize::overlay_dir(Path::new(path), overlays, &spec.environment)?;
+                let mut objects = manifests::parse_documents(&kustomize::build(&dir).await?)?;
+                if objects.is_empty() {
+                    return Err(Error::InvalidInput(format!(
+                        "kustomization {} contains no objects",
+                        dir.display()
+                    )));
+                }
+                if !spec.image.is_empty() {
+                    let changed = kustomize::set_image(&mut objects, &spec.image);
+                    info!(service = %spec.service, image = %spec.image, containers = changed, "Set kustomization image");
+                }
+                let file = dir
+                    .strip_prefix(path)
+                    .ok()
+                    .filter(|relative| !relative.as_os_str().is_empty())
+                    .map(|relative| relative.display().to_string())
+                    .unwrap_or_else(|| "kustomization".to_string());
+                Ok(Some(
+                    objects
+                        .into_iter()
+                        .map(|object| Manifest {
+                            file: file.clone(),
+                            object,
+                        })
+                        .collect(),
+                ))
+            }
+            DeploymentSource::Rendered { documents, .. } => {
+                let manifests: Vec<Manifest> = manifests::parse_documents(documents)?
+                    .into_iter()
+                    .map(|object| Manifest {
+                        file: "rendered".to_string(),
+                        object,
+                    })
+                    .collect();
+                if manifests.is_empty() {
+                    return Err(Error::InvalidInput(
+                        "rendered manifests contain no objects".to_string(),
+                    ));
+                }
+                Ok(Some(manifests))
+            }
+        }
+    }
+
+    /// Namespace for objects of this spec that don't set their own.
+    fn default_namespace<'a>(&'a self, spec: &'a DeploymentSpec) -> &'a str {
+        match &spec.source {
+            DeploymentSource::Rendered {
+                namespace: Some(namespace),
+                ..
+            } => namespace,
+            _ => &self.namespace,
+        }
+    }
+
+    /// API handle for a manifest object, resolved through discovery.
+    async fn dynamic_api(
+        &self,
+        manifest: &Manifest,
+        default_namespace: &str,
+    ) -> Result<Api<DynamicObject>> {
+        let gvk = manifests::gvk(&manifest.object)?;
+        let (resource, caps) = discovery::pinned_kind(&self.client, &gvk)
+            .await
+            .map_err(|e| {
+                Error::InvalidInput(format!(
+                    "{}: unknown kind {}/{}: {}",
+                    manifest.file,
+                    gvk.api_version(),
+                    gvk.kind,
+                    e
+                ))
+            })?;
+        Ok(match caps.scope {
+            Scope::Cluster => Api::all_with(self.client.clone(), &resource),
+            Scope::Namespaced => Api::namespaced_with(
+                self.client.clone(),
+                manifest
+                    .object
+                    .metadata
+                    .namespace
+                    .as_deref()
+                    .unwrap_or(default_namespace),
+                &resource,
+            ),
+        })
+    }
+
+    /// Apply raw manifests. Every object must pass the schema checks and a
+    /// server-side dry run before any of them is applied for real.
+    ///
+    /// Returns each applied object with the API it was applied through.
+    async fn deploy_manifests(
+        &self,
+        spec: &DeploymentSpec,
+        mut manifests: Vec<Manifest>,
+    ) -> Result<Vec<(Api<DynamicObject>, Manifest)>> {
+        let namespace = self.default_namespace(spec);
+        check_schemas(&manifests)?;
+
+        let mut planned = Vec::with_capacity(manifests.len());
+        for manifest in &mut manifests {
+            manifests::add_ownership_labels(&mut manifest.object, &spec.service);
+            let api = self.dynamic_api(manifest, namespace).await?;
+            let name = manifest.object.metadata.name.clone().unwrap_or_default();
+            api.patch(
+                &name,
+                &PatchParams::apply("buildit").force().dry_run(),
+                &Patch::Apply(&manifest.object),
+            )
+            .await
+            .map_err(|e| {
+                Error::InvalidInput(format!(
+                    "{} ({}): rejected by server-side dry run: {}",
+                    manifest.file,
+                    manifest.display_name(),
+                    e
+                ))
+            })?;
+            planned.push((api, name));
+        }
+
+        for ((api, name), manifest) in planned.iter().zip(&manifests) {
+            api.patch(
+                name,
+                &PatchParams::apply("buildit").force(),
+                &Patch::Apply(&manifest.object),
+            )
+            .await
+            .map_err(|e| {
+                Error::DeploymentFailed(format!(
+                    "failed to apply {}: {}",
+                    manifest.display_name(),
+                    e
+                ))
+            })?;
+            info!(service = %spec.service, object = %manifest.display_name(), "Applied manifest");
+        }
+
+        Ok(planned
+            .into_iter()
+            .map(|(api, _)| api)
+            .zip(manifests)
+            .collect())
+    }
+
+    /// Objects a deploy of `spec` would apply: its manifests, or for
+    /// image-based blue/green specs the idle color's Deployment and the
+    /// preview Service (and the main Service on a first deploy).
+    async fn planned_manifests(&self, spec: &DeploymentSpec) -> Result<Vec<Manifest>> {
+        if let Some(mut manifests) = self.load_manifests(spec).await? {
+            check_schemas(&manifests)?;
+            for manifest in &mut manifests {
+                manifests::add_ownership_labels(&mut manifest.object, &spec.service);
+            }
+            return Ok(manifests);
+        }
+        if !matches!(spec.strategy, DeploymentStrategy::BlueGreen { .. }) {
+            return Err(Error::InvalidInput(format!(
+                "strategy {:?} is not implemented by the kubernetes deployer",
+                spec.strategy
+            )));
+        }
+
+        let ns = self.namespace.as_str();
+        let service = spec.service.as_str();
+        let port = blue_green::container_port(spec);
+        let existing =
+            self.services(ns).get_opt(service).await.map_err(|e| {
+                Error::Internal(format!("failed to read service {}: {}", service, e))
+            })?;
+        let target = blue_green::target_color(
+            existing
+                .as_ref()
+                .and_then(|s| blue_green::annotation_color(s, blue_green::ACTIVE_ANNOTATION)),
+        );
+        let preview = blue_green::preview_service_name(service);
+
+        let mut objects = vec![
+            to_dynamic(&blue_green::build_deployment(spec, ns, target))?,
+            to_dynamic(&blue_green::build_service(
+                &preview, ns, service, target, port,
+            ))?,
+        ];
+        if existing.is_none() {
+            objects.push(to_dynamic(&blue_green::build_service(
+                service, ns, service, target, port,
+            ))?);
+        }
+        Ok(objects
+            .into_iter()
+            .map(|object| Manifest {
+                file: "generated".to_string(),
+                object,
+            })
+            .collect())
+    }
+
+    /// Poll applied workloads until each has rolled out, like
+    /// `kubectl rollout status`.
+    async fn wait_rolled_out(&self, applied: &[(Api<DynamicObject>, Manifest)]) -> Result<()> {
+        let deadline = tokio::time::Instant::now() + ROLLOUT_TIMEOUT;
+        for (api, manifest) in applied {
+            if kustomize::rolled_out(&manifest.object).is_none() {
+                continue;
+            }
+            let name = manifest.object.metadata.name.as_deref().unwrap_or_default();
+            loop {
+                let live = api.get(name).await.map_err(|e| {
+                    Error::DeploymentFailed(format!("{}: {}", manifest.display_name(), e))
+                })?;
+                if kustomize::rolled_out(&live) != Some(false) {
+                    info!(object = %manifest.display_name(), "Rolled out");
+                    break;
+                }
+                if tokio::time::Instant::now() >= deadline {
+                    return Err(Error::Timeout(format!(
+                        "{} not rolled out after {}s",
+                        manifest.display_name(),
+                        ROLLOUT_TIMEOUT.as_secs()
+                    )));
+                }
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Fail with every schema problem of the manifests, if any.
+fn check_schemas(manifests: &[Manifest]) -> Result<()> {
+    let problems: Vec<String> = manifests
+        .iter()
+        .flat_map(|m| {
+            manifests::check_schema(&m.object)
+                .into_iter()
+                .map(move |p| format!("{} ({}): {}", m.file, m.display_name(), p))
+        })
+        .collect();
+    if !problems.is_empty() {
+        return Err(Error::InvalidInput(problems.join("; ")));
+    }
+    Ok(())
+}
+
+fn to_dynamic<K: serde::Serialize>(object: &K) -> Result<DynamicObject> {
+    serde_json::to_value(object)
+        .and_then(serde_json::from_value)
+        .map_err(|e| Error::Internal(e.to_string()))
+}
+
+/// An object as JSON, without the fields the API server maintains.
+fn comparable(object: &DynamicObject) -> Result<serde_json::Value> {
+    let mut value = serde_json::to_value(object).map_err(|e| Error::Internal(e.to_string()))?;
+    manifests::strip_server_fields(&mut value);
+    Ok(value)
 }
 
 #[async_trait]
@@ -47,22 +520,169 @@ impl Deployer for KubernetesDeployer {
                 max_unavailable: 0,
             },
             DeploymentStrategy::Recreate,
+            DeploymentStrategy::BlueGreen {
+                smoke_checks: vec![],
+                auto_promote: false,
+            },
         ]
     }
 
-    async fn validate(&self, _spec: &DeploymentSpec) -> Result<Vec<ValidationWarning>> {
-        // TODO: Validate the deployment spec
-        Ok(vec![])
+    async fn validate(&self, spec: &DeploymentSpec) -> Result<Vec<ValidationWarning>> {
+        // TODO: Validate image-based specs
+        let Some(manifests) = self.load_manifests(spec).await? else {
+            return Ok(vec![]);
+        };
+
+        let warnings = manifests
+            .iter()
+            .flat_map(|m| {
+                manifests::check_schema(&m.object)
+                    .into_iter()
+                    .map(move |message| ValidationWarning {
+                        field: format!("{} ({})", m.file, m.display_name()),
+                        message,
+                    })
+            })
+            .collect();
+        Ok(warnings)
+    }
+
+    async fn preview(&self, spec: &DeploymentSpec) -> Result<Vec<ObjectPreview>> {
+        let namespace = self.default_namespace(spec);
+        let mut previews = Vec::new();
+        for manifest in self.planned_manifests(spec).await? {
+            let api = self.dynamic_api(&manifest, namespace).await?;
+            let name = manifest.object.metadata.name.clone().unwrap_or_default();
+            let live = api.get_opt(&name).await.map_err(|e| {
+                Error::Internal(format!("failed to read {}: {}", manifest.display_name(), e))
+            })?;
+            let planned = api
+                .patch(
+                    &name,
+                    &PatchParams::apply("buildit").force().dry_run(),
+                    &Patch::Apply(&manifest.object),
+                )
+                .await
+                .map_err(|e| {
+                    Error::InvalidInput(format!(
+                        "{} ({}): rejected by server-side dry run: {}",
+                        manifest.file,
+                        manifest.display_name(),
+                        e
+                    ))
+                })?;
+
+            let mut live = live.as_ref().map(comparable).transpose()?;
+            let mut planned_value = comparable(&planned)?;
+            manifests::redact_secret(live.as_mut(), &mut planned_value);
+            previews.push(ObjectPreview {
+                kind: manifest
+                    .object
+                    .types
+                    .as_ref()
+                    .map(|t| t.kind.clone())
+                    .unwrap_or_default(),
+                name,
+                namespace: planned.metadata.namespace.clone(),
+                live,
+                planned: planned_value,
+            });
+        }
+        Ok(previews)
     }
 
-    async fn deploy(&self, _spec: DeploymentSpec) -> Result<DeploymentHandle> {
-        // TODO: Create/update Kubernetes Deployment
-        todo!("implement kubernetes deployment")
+    async fn deploy(&self, spec: DeploymentSpec) -> Result<DeploymentHandle> {
+        if let Some(manifests) = self.load_manifests(&spec).await? {
+            let applied = self.deploy_manifests(&spec, manifests).await?;
+            if matches!(spec.source, DeploymentSource::Kustomize { .. }) {
+                self.wait_rolled_out(&applied).await?;
+            }
+            return Ok(DeploymentHandle {
+                id: spec.id,
+                deployer_id: format!("{}/{}", self.default_namespace(&spec), spec.service),
+                deployer_name: self.name().to_string(),
+            });
+        }
+
+        match &spec.strategy {
+            DeploymentStrategy::BlueGreen {
+                smoke_checks,
+                auto_promote,
+            } => {
+                self.deploy_blue_green(&spec, smoke_checks, *auto_promote)
+                    .await?
+            }
+            // TODO: Rolling update, recreate and canary deployments
+            other => {
+                return Err(Error::InvalidInput(format!(
+                    "strategy {:?} is not implemented by the kubernetes deployer",
+                    other
+                )));
+            }
+        }
+
+        Ok(DeploymentHandle {
+            id: spec.id,
+            deployer_id: format!("{}/{}", self.namespace, spec.service),
+            deployer_name: self.name().to_string(),
+        })
     }
 
-    async fn state(&self, _handle: &DeploymentHandle) -> Result<DeploymentState> {
-        // TODO: Get deployment state
-        todo!("implement state retrieval")
+    async fn state(&self, handle: &DeploymentHandle) -> Result<DeploymentState> {
+        let (ns, service) = self.target(handle);
+        let svc = self
+            .services(ns)
+            .get(service)
+            .await
+            .map_err(|e| Error::NotFound(format!("service {}: {}", service, e)))?;
+        let active = blue_green::annotation_color(&svc, blue_green::ACTIVE_ANNOTATION)
+            .ok_or_else(|| Error::NotFound(format!("no active color for {}", service)))?;
+        let pending = blue_green::annotation_color(&svc, blue_green::PENDING_ANNOTATION);
+
+        let deployment = self
+            .deployments(ns)
+            .get(&blue_green::deployment_name(service, active))
+            .await
+            .map_err(|e| Error::NotFound(e.to_string()))?;
+        let desired = deployment
+            .spec
+            .as_ref()
+            .and_then(|s| s.replicas)
+            .unwrap_or(1) as u32;
+        let status = deployment.status.unwrap_or_default();
+        let ready = status.ready_replicas.unwrap_or(0) as u32;
+        let available = status.available_replicas.unwrap_or(0) as u32;
+        let current_image = deployment
+            .spec
+            .and_then(|s| s.template.spec)
+            .and_then(|p| p.containers.into_iter().next())
+            .and_then(|c| c.image)
+            .unwrap_or_default();
+
+        let deployment_status = if let Some(color) = pending {
+            DeploymentStatus::Paused {
+                reason: format!("{} awaiting promotion", color.as_str()),
+            }
+        } else if ready >= desired {
+            DeploymentStatus::Healthy
+        } else {
+            DeploymentStatus::InProgress {
+                progress_percent: (ready * 100 / desired.max(1)) as u8,
+            }
+        };
+
+        Ok(DeploymentState {
+            status: deployment_status,
+            replicas: ReplicaStatus {
+                desired,
+                ready,
+                available,
+                unavailable: desired.saturating_sub(available),
+            },
+            current_image,
+            traffic_distribution: None,
+            last_updated: Utc::now(),
+        })
     }
 
     async fn events(
@@ -75,11 +695,44 @@ impl Deployer for KubernetesDeployer {
 
     async fn rollback(
         &self,
-        _handle: &DeploymentHandle,
-        _target: RollbackTarget,
+        handle: &DeploymentHandle,
+        target: RollbackTarget,
     ) -> Result<DeploymentHandle> {
-        // TODO: Rollback deployment
-        todo!("implement rollback")
+        let (ns, service) = self.target(handle);
+        if !matches!(target, RollbackTarget::Previous) {
+            // TODO: Roll back to a specific revision or image
+            return Err(Error::InvalidInput(
+                "only rollback to the previous version is supported".to_string(),
+            ));
+        }
+
+        let svc = self
+            .services(ns)
+            .get(service)
+            .await
+            .map_err(|e| Error::NotFound(format!("service {}: {}", service, e)))?;
+        let previous = blue_green::annotation_color(&svc, blue_green::PREVIOUS_ANNOTATION)
+            .ok_or_else(|| {
+                Error::Conflict(format!("no previous version recorded for {}", service))
+            })?;
+
+        self.switch(ns, service, previous).await?;
+        Ok(handle.clone())
+    }
+
+    async fn promote(&self, handle: &DeploymentHandle) -> Result<()> {
+        let (ns, service) = self.target(handle);
+        let svc = self
+            .services(ns)
+            .get(service)
+            .await
+            .map_err(|e| Error::NotFound(format!("service {}: {}", service, e)))?;
+        let pending = blue_green::annotation_color(&svc, blue_green::PENDING_ANNOTATION)
+            .ok_or_else(|| {
+                Error::Conflict(format!("no version of {} is awaiting promotion", service))
+            })?;
+
+        self.switch(ns, service, pending).await
     }
 
     async fn scale(&self, _handle: &DeploymentHandle, _replicas: u32) -> Result<()> {
@@ -102,6 +755,30 @@ impl Deployer for KubernetesDeployer {
         todo!("implement destroy")
     }
 
+    async fn live_object(
+        &self,
+        object: &serde_json::Value,
+        namespace: &str,
+    ) -> Result<Option<serde_json::Value>> {
+        let manifest = Manifest {
+            file: "live".to_string(),
+            object: serde_json::from_value(object.clone())
+                .map_err(|e| Error::InvalidInput(format!("invalid object: {}", e)))?,
+        };
+        let name = manifest
+            .object
+            .metadata
+            .name
+            .clone()
+            .ok_or_else(|| Error::InvalidInput("object has no name".to_string()))?;
+        let api = self.dynamic_api(&manifest, namespace).await?;
+        let live = api.get_opt(&name).await.map_err(|e| {
+            Error::Internal(format!("failed to read {}: {}", manifest.display_name(), e))
+        })?;
+        live.map(|o| serde_json::to_value(o).map_err(|e| Error::Internal(e.to_string())))
+            .transpose()
+    }
+
     async fn logs(
         &self,
         _handle: &DeploymentHandle,
@@ -0,0 +1,350 @@
+//! Kustomize sources for the Kubernetes deployer.
+//!
+//! Picks the overlay for an environment, builds it with `kustomize build`
+//! (or `kubectl kustomize` when the standalone binary isn't installed),
+//! points the workloads at the image being deployed, and tells whether an
+//! applied workload has finished rolling out.
+
+use buildit_core::{Error, Result};
+use kube::api::DynamicObject;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use tokio::process::Command;
+
+/// Directory to build for `environment`: its configured overlay,
+/// `overlays/<environment>` when that exists, or `path` itself.
+pub fn overlay_dir(
+    path: &Path,
+    overlays: &HashMap<String, String>,
+    environment: &str,
+) -> Result<PathBuf> {
+    if let Some(overlay) = overlays.get(environment) {
+        let relative = Path::new(overlay);
+        if !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(Error::InvalidInput(format!(
+                "overlay for {} must be a relative path inside {}",
+                environment,
+                path.display()
+            )));
+        }
+        let dir = path.join(relative);
+        if !dir.is_dir() {
+            return Err(Error::InvalidInput(format!(
+                "overlay directory {} does not exist",
+                dir.display()
+            )));
+        }
+        return Ok(dir);
+    }
+
+    let default = path.join("overlays").join(environment);
+    if default.is_dir() {
+        return Ok(default);
+    }
+    if !path.is_dir() {
+        return Err(Error::InvalidInput(format!(
+            "kustomization directory {} does not exist",
+            path.display()
+        )));
+    }
+    Ok(path.to_path_buf())
+}
+
+/// Render a kustomization directory to multi-document YAML.
+pub async fn build(dir: &Path) -> Result<String> {
+    let output = match Command::new("kustomize")
+        .arg("build")
+        .arg(dir)
+        .output()
+        .await
+    {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Command::new("kubectl")
+            .arg("kustomize")
+            .arg(dir)
+            .output()
+            .await
+            .map_err(|e| {
+                Error::Internal(format!("neither kustomize nor kubectl could be run: {}", e))
+            })?,
+        other => other.map_err(|e| Error::Internal(format!("failed to run kustomize: {}", e)))?,
+    };
+    if !output.status.success() {
+        return Err(Error::InvalidInput(format!(
+            "kustomize build {} failed: {}",
+            dir.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    String::from_utf8(output.stdout)
+        .map_err(|e| Error::InvalidInput(format!("kustomize output is not UTF-8: {}", e)))
+}
+
+/// Repository part of an image reference, without tag or digest.
+fn repository(image: &str) -> &str {
+    let image = image.split_once('@').map_or(image, |(name, _)| name);
+    match image.rsplit_once(':') {
+        Some((name, tag)) if !tag.contains('/') => name,
+        _ => image,
+    }
+}
+
+/// Pod spec of a workload object, if it has one.
+fn pod_spec(object: &mut DynamicObject) -> Option<&mut Value> {
+    let kind = object.types.as_ref()?.kind.as_str();
+    let spec = object.data.get_mut("spec")?;
+    match kind {
+        "Pod" => Some(spec),
+        "CronJob" => spec.pointer_mut("/jobTemplate/spec/template/spec"),
+        "Deployment" | "StatefulSet" | "DaemonSet" | "ReplicaSet" | "Job" => {
+            spec.pointer_mut("/template/spec")
+        }
+        _ => None,
+    }
+}
+
+/// Switch every container and init container whose image is from the
+/// same repository as `image` to `image`. Returns how many were changed.
+pub fn set_image(objects: &mut [DynamicObject], image: &str) -> usize {
+    let wanted = repository(image);
+    let mut changed = 0;
+    for object in objects {
+        let Some(spec) = pod_spec(object) else {
+            continue;
+        };
+        for field in ["containers", "initContainers"] {
+            let Some(containers) = spec.get_mut(field).and_then(|c| c.as_array_mut()) else {
+                continue;
+            };
+            for container in containers {
+                let Some(current) = container.get("image").and_then(|i| i.as_str()) else {
+                    continue;
+                };
+                if repository(current) == wanted && current != image {
+                    container["image"] = Value::String(image.to_string());
+                    changed += 1;
+                }
+            }
+        }
+    }
+    changed
+}
+
+/// Whether a workload read back from the cluster has finished rolling out,
+/// or `None` for kinds without a rollout.
+pub fn rolled_out(object: &DynamicObject) -> Option<bool> {
+    let kind = object.types.as_ref()?.kind.as_str();
+    let status = object.data.get("status");
+    let number = |field: &str| {
+        status
+            .and_then(|s| s.get(field))
+            .and_then(|v| v.as_i64())
+            .unwrap_or(0)
+    };
+    let observed = object
+        .metadata
+        .generation
+        .is_none_or(|generation| number("observedGeneration") >= generation);
+    let desired = object
+        .data
+        .pointer("/spec/replicas")
+        .and_then(|v| v.as_i64())
+        .unwrap_or(1);
+
+    let done = match kind {
+        "Deployment" => {
+            number("updatedReplicas") >= desired
+                && number("availableReplicas") >= desired
+                && number("replicas") <= number("updatedReplicas")
+        }
+        "StatefulSet" => {
+            let revision = |field: &str| status.and_then(|s| s.get(field)).and_then(|v| v.as_str());
+            number("updatedReplicas") >= desired
+                && number("readyReplicas") >= desired
+                && revision("currentRevision") == revision("updateRevision")
+        }
+        "DaemonSet" => {
+            let scheduled = number("desiredNumberScheduled");
+            number("updatedNumberScheduled") >= scheduled && number("numberAvailable") >= scheduled
+        }
+        _ => return None,
+    };
+    Some(observed && done)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifests::parse_documents;
+
+    const WORKLOADS: &str = r#"
+apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: api
+spec:
+  template:
+    spec:
+      initContainers:
+        - name: migrate
+          image: ghcr.io/acme/api:v1
+      containers:
+        - name: api
+          image: ghcr.io/acme/api:v1
+        - name: proxy
+          image: envoyproxy/envoy:v1.30
+---
+apiVersion: batch/v1
+kind: CronJob
+metadata:
+  name: cleanup
+spec:
+  jobTemplate:
+    spec:
+      template:
+        spec:
+          containers:
+            - name: cleanup
+              image: ghcr.io/acme/api@sha256:abc
+---
+apiVersion: v1
+kind: ConfigMap
+metadata:
+  name: settings
+data:
+  image: ghcr.io/acme/api:v1
+"#;
+
+    #[test]
+    fn test_repository() {
+        assert_eq!(repository("ghcr.io/acme/api:v2"), "ghcr.io/acme/api");
+        assert_eq!(repository("localhost:5000/api"), "localhost:5000/api");
+        assert_eq!(repository("localhost:5000/api:v2"), "localhost:5000/api");
+        assert_eq!(
+            repository("ghcr.io/acme/api:v2@sha256:abc"),
+            "ghcr.io/acme/api"
+        );
+        assert_eq!(repository("nginx"), "nginx");
+    }
+
+    #[test]
+    fn test_set_image() {
+        let mut objects = parse_documents(WORKLOADS).unwrap();
+        assert_eq!(set_image(&mut objects, "ghcr.io/acme/api:v2"), 3);
+
+        let deployment = &objects[0].data;
+        assert_eq!(
+            deployment["spec"]["template"]["spec"]["containers"][0]["image"],
+            "ghcr.io/acme/api:v2"
+        );
+        assert_eq!(
+            deployment["spec"]["template"]["spec"]["initContainers"][0]["image"],
+            "ghcr.io/acme/api:v2"
+        );
+        assert_eq!(
+            deployment["spec"]["template"]["spec"]["containers"][1]["image"],
+            "envoyproxy/envoy:v1.30"
+        );
+        assert_eq!(
+            objects[1].data["spec"]["jobTemplate"]["spec"]["template"]["spec"]["containers"][0]["image"],
+            "ghcr.io/acme/api:v2"
+        );
+        // Only pod templates are touched.
+        assert_eq!(objects[2].data["data"]["image"], "ghcr.io/acme/api:v1");
+
+        // Already up to date.
+        assert_eq!(set_image(&mut objects, "ghcr.io/acme/api:v2"), 0);
+    }
+
+    #[test]
+    fn test_overlay_dir() {
+        let root = std::env::temp_dir().join(format!("buildit-kustomize-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("overlays/staging")).unwrap();
+        std::fs::create_dir_all(root.join("envs/prod")).unwrap();
+        let overlays = HashMap::from([("production".to_string(), "envs/prod".to_string())]);
+
+        assert_eq!(
+            overlay_dir(&root, &overlays, "production").unwrap(),
+            root.join("envs/prod")
+        );
+        assert_eq!(
+            overlay_dir(&root, &overlays, "staging").unwrap(),
+            root.join("overlays/staging")
+        );
+        assert_eq!(overlay_dir(&root, &overlays, "dev").unwrap(), root);
+
+        let escaping = HashMap::from([("production".to_string(), "../other".to_string())]);
+        assert!(overlay_dir(&root, &escaping, "production").is_err());
+        let missing = HashMap::from([("production".to_string(), "envs/missing".to_string())]);
+        assert!(overlay_dir(&root, &missing, "production").is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    fn live(yaml: &str) -> DynamicObject {
+        parse_documents(yaml).unwrap().remove(0)
+    }
+
+    #[test]
+    fn test_rolled_out() {
+        let rolling = live(
+            r#"
+apiVersion: apps/v1
+kind: Deployment
+metadata: {name: api, generation: 4}
+spec: {replicas: 3}
+status: {observedGeneration: 4, replicas: 4, updatedReplicas: 3, availableReplicas: 3}
+"#,
+        );
+        assert_eq!(rolled_out(&rolling), Some(false));
+
+        let stale = live(
+            r#"
+apiVersion: apps/v1
+kind: Deployment
+metadata: {name: api, generation: 5}
+spec: {replicas: 3}
+status: {observedGeneration: 4, replicas: 3, updatedReplicas: 3, availableReplicas: 3}
+"#,
+        );
+        assert_eq!(rolled_out(&stale), Some(false));
+
+        let done = live(
+            r#"
+apiVersion: apps/v1
+kind: Deployment
+metadata: {name: api, generation: 4}
+spec: {replicas: 3}
+status: {observedGeneration: 4, replicas: 3, updatedReplicas: 3, availableReplicas: 3}
+"#,
+        );
+        assert_eq!(rolled_out(&done), Some(true));
+
+        let statefulset = live(
+            r#"
+apiVersion: apps/v1
+kind: StatefulSet
+metadata: {name: db, generation: 2}
+spec: {replicas: 2}
+status: {observedGeneration: 2, updatedReplicas: 2, readyReplicas: 2, currentRevision: db-1, updateRevision: db-2}
+"#,
+        );
+        assert_eq!(rolled_out(&statefulset), Some(false));
+
+        let daemonset = live(
+            r#"
+apiVersion: apps/v1
+kind: DaemonSet
+metadata: {name: agent, generation: 1}
+status: {observedGeneration: 1, desiredNumberScheduled: 5, updatedNumberScheduled: 5, numberAvailable: 5}
+"#,
+        );
+        assert_eq!(rolled_out(&daemonset), Some(true));
+
+        let service = live("apiVersion: v1\nkind: Service\nmetadata: {name: api}\n");
+        assert_eq!(rolled_out(&service), None);
+    }
+}
@@ -6,9 +6,13 @@
 //! - Cloud Run (future)
 //! - Lambda (future)
 
+pub mod blue_green;
 pub mod kubernetes;
+pub mod kustomize;
+pub mod manifests;
 
 pub use buildit_core::deployer::{
-    Deployer, DeploymentHandle, DeploymentSpec, DeploymentState, DeploymentStatus,
-    DeploymentStrategy, LogOptions, RollbackTarget, ValidationWarning,
+    Deployer, DeploymentHandle, DeploymentSource, DeploymentSpec, DeploymentState,
+    DeploymentStatus, DeploymentStrategy, LogOptions, ObjectPreview, RollbackTarget, SmokeCheck,
+    ValidationWarning,
 };
@@ -0,0 +1,569 @@
+//! Raw manifest sources for the Kubernetes deployer.
+//!
+//! Loads every `.yaml`, `.yml` and `.json` file under a directory (sorted by
+//! path), substitutes `${name}` variables, splits multi-document files and
+//! `List` objects, and checks each object has the shape the API server needs
+//! before anything is sent to the cluster.
+
+use buildit_core::{Error, Result};
+use kube::api::DynamicObject;
+use kube::core::GroupVersionKind;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+pub const SERVICE_LABEL: &str = "buildit.dev/service";
+const MANAGED_BY_LABEL: &str = "app.kubernetes.io/managed-by";
+
+/// One object loaded from a manifest file.
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    /// File the object came from, relative to the manifest directory.
+    pub file: String,
+    pub object: DynamicObject,
+}
+
+impl Manifest {
+    /// `Kind/name` for log and error messages.
+    pub fn display_name(&self) -> String {
+        let kind = self
+            .object
+            .types
+            .as_ref()
+            .map(|t| t.kind.as_str())
+            .unwrap_or("?");
+        format!(
+            "{}/{}",
+            kind,
+            self.object.metadata.name.as_deref().unwrap_or("?")
+        )
+    }
+}
+
+/// Load, interpolate and parse all manifests under `dir`.
+pub fn load_dir(dir: &Path, variables: &HashMap<String, String>) -> Result<Vec<Manifest>> {
+    if !dir.is_dir() {
+        return Err(Error::InvalidInput(format!(
+            "manifest directory {} does not exist",
+            dir.display()
+        )));
+    }
+
+    let mut files = Vec::new();
+    collect_files(dir, &mut files)?;
+    files.sort();
+
+    let mut manifests = Vec::new();
+    for file in files {
+        let relative = file
+            .strip_prefix(dir)
+            .unwrap_or(&file)
+            .display()
+            .to_string();
+        let text = std::fs::read_to_string(&file)
+            .map_err(|e| Error::InvalidInput(format!("{}: {}", relative, e)))?;
+        let text = interpolate(&text, variables)
+            .map_err(|e| Error::InvalidInput(format!("{}: {}", relative, e)))?;
+        for object in parse_documents(&text)
+            .map_err(|e| Error::InvalidInput(format!("{}: {}", relative, e)))?
+        {
+            manifests.push(Manifest {
+                file: relative.clone(),
+                object,
+            });
+        }
+    }
+
+    if manifests.is_empty() {
+        return Err(Error::InvalidInput(format!(
+            "no manifests found in {}",
+            dir.display()
+        )));
+    }
+    Ok(manifests)
+}
+
+/// Concatenate every manifest file under `dir` into one multi-document
+/// string, without variable substitution.
+pub fn read_dir(dir: &Path) -> Result<String> {
+    if !dir.is_dir() {
+        return Err(Error::InvalidInput(format!(
+            "manifest directory {} does not exist",
+            dir.display()
+        )));
+    }
+
+    let mut files = Vec::new();
+    collect_files(dir, &mut files)?;
+    files.sort();
+
+    let mut documents = String::new();
+    for file in files {
+        let text = std::fs::read_to_string(&file)
+            .map_err(|e| Error::InvalidInput(format!("{}: {}", file.display(), e)))?;
+        documents.push_str("---\n");
+        documents.push_str(&text);
+        if !text.ends_with('\n') {
+            documents.push('\n');
+        }
+    }
+    Ok(documents)
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|e| Error::InvalidInput(format!("{}: {}", dir.display(), e)))?;
+    for entry in entries {
+        let path = entry.map_err(|e| Error::Internal(e.to_string()))?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else if matches!(
+            path.extension().and_then(|e| e.to_str()),
+            Some("yaml" | "yml" | "json")
+        ) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Replace `${name}` with its value. `$${` produces a literal `${`.
+///
+/// Unknown variables are an error rather than an empty string, so a typo
+/// can't silently produce an invalid object.
+pub fn interpolate(text: &str, variables: &HashMap<String, String>) -> Result<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut missing = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        if let Some(after) = tail.strip_prefix("$${") {
+            out.push_str("${");
+            rest = after;
+        } else if let Some(after) = tail.strip_prefix("${") {
+            let Some(end) = after.find('}') else {
+                return Err(Error::InvalidInput("unterminated ${...}".to_string()));
+            };
+            let name = after[..end].trim();
+            match variables.get(name) {
+                Some(value) => out.push_str(value),
+                None => missing.push(name.to_string()),
+            }
+            rest = &after[end + 1..];
+        } else {
+            out.push('$');
+            rest = &tail[1..];
+        }
+    }
+    out.push_str(rest);
+
+    if !missing.is_empty() {
+        missing.sort();
+        missing.dedup();
+        return Err(Error::InvalidInput(format!(
+            "undefined variables: {}",
+            missing.join(", ")
+        )));
+    }
+    Ok(out)
+}
+
+/// Parse a (possibly multi-document) YAML or JSON string into objects,
+/// expanding `kind: List`.
+pub fn parse_documents(text: &str) -> Result<Vec<DynamicObject>> {
+    use serde::Deserialize;
+
+    let mut objects = Vec::new();
+    for document in serde_yaml::Deserializer::from_str(text) {
+        let value = serde_json::Value::deserialize(document)
+            .map_err(|e| Error::InvalidInput(format!("invalid YAML: {}", e)))?;
+        if value.is_null() {
+            continue;
+        }
+
+        let items = match value.get("kind").and_then(|k| k.as_str()) {
+            Some(kind) if kind.ends_with("List") && value.get("items").is_some() => value
+                .get("items")
+                .and_then(|i| i.as_array())
+                .cloned()
+                .unwrap_or_default(),
+            _ => vec![value],
+        };
+        for item in items {
+            let object = serde_json::from_value(item)
+                .map_err(|e| Error::InvalidInput(format!("not a Kubernetes object: {}", e)))?;
+            objects.push(object);
+        }
+    }
+    Ok(objects)
+}
+
+/// Problems that would make the API server reject the object outright.
+pub fn check_schema(object: &DynamicObject) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    match &object.types {
+        Some(types) => {
+            if types.api_version.is_empty() {
+                problems.push("apiVersion is required".to_string());
+            }
+            if types.kind.is_empty() {
+                problems.push("kind is required".to_string());
+            }
+        }
+        None => problems.push("apiVersion and kind are required".to_string()),
+    }
+
+    match object.metadata.name.as_deref() {
+        None | Some("") => problems.push("metadata.name is required".to_string()),
+        Some(name) if !is_dns_subdomain(name) => problems.push(format!(
+            "metadata.name {:?} is not a valid DNS subdomain",
+            name
+        )),
+        Some(_) => {}
+    }
+    if let Some(namespace) = object.metadata.namespace.as_deref() {
+        if !is_dns_label(namespace) {
+            problems.push(format!(
+                "metadata.namespace {:?} is not a valid DNS label",
+                namespace
+            ));
+        }
+    }
+    for (key, value) in object.metadata.labels.iter().flatten() {
+        if value.len() > 63 {
+            problems.push(format!("label {} is longer than 63 characters", key));
+        }
+    }
+
+    problems
+}
+
+/// Group/version/kind of an object.
+pub fn gvk(object: &DynamicObject) -> Result<GroupVersionKind> {
+    let types = object
+        .types
+        .as_ref()
+        .ok_or_else(|| Error::InvalidInput("object has no apiVersion/kind".to_string()))?;
+    let (group, version) = types
+        .api_version
+        .split_once('/')
+        .unwrap_or(("", &types.api_version));
+    Ok(GroupVersionKind::gvk(group, version, &types.kind))
+}
+
+/// Fields where a live object differs from the desired one.
+///
+/// Only fields set in `desired` are compared, so defaults and status filled
+/// in by the cluster don't count as drift. Of the metadata, only labels and
+/// annotations are compared. Each entry reads `path: expected X, found Y`.
+pub fn drift(desired: &serde_json::Value, live: &serde_json::Value) -> Vec<String> {
+    let mut differences = Vec::new();
+    let (Some(desired), Some(live)) = (desired.as_object(), live.as_object()) else {
+        return differences;
+    };
+
+    for (key, want) in desired {
+        match key.as_str() {
+            "apiVersion" | "kind" | "status" => {}
+            "metadata" => {
+                for field in ["labels", "annotations"] {
+                    if let Some(want) = want.get(field) {
+                        let have = live
+                            .get("metadata")
+                            .and_then(|m| m.get(field))
+                            .unwrap_or(&serde_json::Value::Null);
+                        diff_value(&format!("metadata.{}", field), want, have, &mut differences);
+                    }
+                }
+            }
+            _ => diff_value(
+                key,
+                want,
+                live.get(key).unwrap_or(&serde_json::Value::Null),
+                &mut differences,
+            ),
+        }
+    }
+    differences
+}
+
+fn diff_value(
+    path: &str,
+    want: &serde_json::Value,
+    have: &serde_json::Value,
+    differences: &mut Vec<String>,
+) {
+    use serde_json::Value;
+
+    match (want, have) {
+        (Value::Object(want), Value::Object(have)) => {
+            for (key, value) in want {
+                diff_value(
+                    &format!("{}.{}", path, key),
+                    value,
+                    have.get(key).unwrap_or(&Value::Null),
+                    differences,
+                );
+            }
+        }
+        (Value::Array(want), Value::Array(have)) if want.len() == have.len() => {
+            for (i, (w, h)) in want.iter().zip(have).enumerate() {
+                diff_value(&format!("{}[{}]", path, i), w, h, differences);
+            }
+        }
+        (Value::Number(w), Value::Number(h)) if w.as_f64() == h.as_f64() => {}
+        (Value::Null, _) => {}
+        _ if want == have => {}
+        (_, Value::Null) => differences.push(format!("{}: expected {}, missing", path, want)),
+        _ => differences.push(format!("{}: expected {}, found {}", path, want, have)),
+    }
+}
+
+/// Drop the fields the API server maintains (status, managed fields,
+/// resource version and the like), so an object can be compared with
+/// another version of itself.
+pub fn strip_server_fields(object: &mut serde_json::Value) {
+    let Some(fields) = object.as_object_mut() else {
+        return;
+    };
+    fields.remove("status");
+    if let Some(metadata) = fields.get_mut("metadata").and_then(|m| m.as_object_mut()) {
+        for field in [
+            "managedFields",
+            "resourceVersion",
+            "generation",
+            "creationTimestamp",
+            "uid",
+            "selfLink",
+        ] {
+            metadata.remove(field);
+        }
+    }
+}
+
+/// Replace the values of a Secret with placeholders saying whether they
+/// change, so a preview shows which keys change without their contents.
+pub fn redact_secret(live: Option<&mut serde_json::Value>, planned: &mut serde_json::Value) {
+    use serde_json::Value;
+
+    if planned.get("kind").and_then(|k| k.as_str()) != Some("Secret") {
+        return;
+    }
+    let live_data = live
+        .as_ref()
+        .and_then(|l| l.get("data"))
+        .and_then(|d| d.as_object())
+        .cloned()
+        .unwrap_or_default();
+    for field in ["data", "stringData"] {
+        if let Some(values) = planned.get_mut(field).and_then(|d| d.as_object_mut()) {
+            for (key, value) in values.iter_mut() {
+                let unchanged = field == "data" && live_data.get(key) == Some(value);
+                *value = Value::String(if unchanged {
+                    "(redacted)".to_string()
+                } else {
+                    "(redacted, changed)".to_string()
+                });
+            }
+        }
+    }
+    if let Some(values) = live
+        .and_then(|l| l.get_mut("data"))
+        .and_then(|d| d.as_object_mut())
+    {
+        for value in values.values_mut() {
+            *value = Value::String("(redacted)".to_string());
+        }
+    }
+}
+
+/// Label an object as managed by BuildIt for `service`.
+pub fn add_ownership_labels(object: &mut DynamicObject, service: &str) {
+    let labels = object.metadata.labels.get_or_insert_with(Default::default);
+    labels.insert(SERVICE_LABEL.to_string(), service.to_string());
+    labels.insert(MANAGED_BY_LABEL.to_string(), "buildit".to_string());
+}
+
+fn is_dns_label(s: &str) -> bool {
+    !s.is_empty()
+        && s.len() <= 63
+        && s.bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+        && !s.starts_with('-')
+        && !s.ends_with('-')
+}
+
+fn is_dns_subdomain(s: &str) -> bool {
+    s.len() <= 253 && s.split('.').all(is_dns_label)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interpolate() {
+        let vars = HashMap::from([
+            ("image".to_string(), "ghcr.io/acme/api:v2".to_string()),
+            ("replicas".to_string(), "3".to_string()),
+        ]);
+        let out = interpolate(
+            "image: ${image}\nreplicas: ${ replicas }\ncost: $5 $${HOME}",
+            &vars,
+        )
+        .unwrap();
+        assert_eq!(
+            out,
+            "image: ghcr.io/acme/api:v2\nreplicas: 3\ncost: $5 ${HOME}"
+        );
+
+        let err = interpolate("${image} ${tag} ${tag}", &vars).unwrap_err();
+        assert!(err.to_string().contains("undefined variables: tag"));
+    }
+
+    #[test]
+    fn test_drift_compares_only_desired_fields() {
+        let desired = serde_json::json!({
+            "apiVersion": "apps/v1",
+            "kind": "Deployment",
+            "metadata": { "name": "api", "labels": { "app": "api" } },
+            "spec": {
+                "replicas": 3,
+                "template": { "spec": { "containers": [{ "name": "api", "image": "api:v2" }] } }
+            }
+        });
+        let mut live = desired.clone();
+        live["metadata"]["uid"] = serde_json::json!("1234");
+        live["metadata"]["labels"]["managed-by"] = serde_json::json!("buildit");
+        live["spec"]["strategy"] = serde_json::json!({ "type": "RollingUpdate" });
+        live["status"] = serde_json::json!({ "readyReplicas": 3 });
+        assert!(drift(&desired, &live).is_empty());
+
+        live["spec"]["replicas"] = serde_json::json!(5);
+        live["spec"]["template"]["spec"]["containers"][0]["image"] =
+            serde_json::json!("api:hotfix");
+        live["metadata"]["labels"]
+            .as_object_mut()
+            .unwrap()
+            .remove("app");
+        assert_eq!(
+            drift(&desired, &live),
+            vec![
+                r#"metadata.labels.app: expected "api", missing"#.to_string(),
+                "spec.replicas: expected 3, found 5".to_string(),
+                r#"spec.template.spec.containers[0].image: expected "api:v2", found "api:hotfix""#
+                    .to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_documents_splits_and_expands_lists() {
+        let text = r#"
+apiVersion: v1
+kind: ConfigMap
+metadata:
+  name: settings
+data:
+  key: value
+---
+---
+apiVersion: v1
+kind: List
+items:
+  - apiVersion: v1
+    kind: Service
+    metadata:
+      name: api
+  - apiVersion: apps/v1
+    kind: Deployment
+    metadata:
+      name: api
+"#;
+        let objects = parse_documents(text).unwrap();
+        assert_eq!(objects.len(), 3);
+        assert_eq!(objects[0].data["data"]["key"], "value");
+
+        let gvk = gvk(&objects[2]).unwrap();
+        assert_eq!(
+            (gvk.group.as_str(), gvk.version.as_str(), gvk.kind.as_str()),
+            ("apps", "v1", "Deployment")
+        );
+        let gvk = super::gvk(&objects[1]).unwrap();
+        assert_eq!((gvk.group.as_str(), gvk.version.as_str()), ("", "v1"));
+    }
+
+    #[test]
+    fn test_check_schema() {
+        let objects = parse_documents(
+            r#"
+apiVersion: v1
+kind: ConfigMap
+metadata:
+  name: Bad_Name
+  namespace: prod
+---
+apiVersion: v1
+kind: Secret
+metadata:
+  namespace: prod
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            check_schema(&objects[0]),
+            vec![r#"metadata.name "Bad_Name" is not a valid DNS subdomain"#]
+        );
+        assert_eq!(check_schema(&objects[1]), vec!["metadata.name is required"]);
+    }
+
+    #[test]
+    fn test_strip_server_fields() {
+        let mut object = serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": {
+                "name": "settings",
+                "uid": "1234",
+                "resourceVersion": "42",
+                "managedFields": [{"manager": "buildit"}],
+                "labels": {"app": "api"}
+            },
+            "data": {"level": "info"},
+            "status": {}
+        });
+        strip_server_fields(&mut object);
+        assert_eq!(
+            object,
+            serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "ConfigMap",
+                "metadata": {"name": "settings", "labels": {"app": "api"}},
+                "data": {"level": "info"}
+            })
+        );
+    }
+
+    #[test]
+    fn test_redact_secret() {
+        let mut live = serde_json::json!({
+            "kind": "Secret",
+            "data": {"user": "YWRtaW4=", "password": "b2xk"}
+        });
+        let mut planned = serde_json::json!({
+            "kind": "Secret",
+            "data": {"user": "YWRtaW4=", "password": "bmV3", "token": "dA=="}
+        });
+        redact_secret(Some(&mut live), &mut planned);
+        assert_eq!(live["data"]["password"], "(redacted)");
+        assert_eq!(planned["data"]["user"], "(redacted)");
+        assert_eq!(planned["data"]["password"], "(redacted, changed)");
+        assert_eq!(planned["data"]["token"], "(redacted, changed)");
+
+        let mut config = serde_json::json!({"kind": "ConfigMap", "data": {"a": "b"}});
+        redact_secret(None, &mut config);
+        assert_eq!(config["data"]["a"], "b");
+    }
+}
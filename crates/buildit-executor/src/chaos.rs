@@ -0,0 +1,392 @@
+//! Fault-injection executor.
+//!
+//! Wraps another executor and injects failures driven by a seeded PRNG, so a
+//! given seed always produces the same sequence of faults:
+//! - delays before spawning a job
+//! - log streams cut off after a random number of lines
+//! - errors from `status` calls
+//! - jobs cancelled shortly after they start
+//!
+//! Used in BuildIt's own CI, and by operators to check that retries and
+//! alerting behave when executors misbehave. Only built with the `chaos`
+//! feature.
+
+use async_trait::async_trait;
+use buildit_core::executor::{
+    Executor, JobHandle, JobResult, JobSpec, JobStatus, LogLine, Platform, PullPolicy,
+    ResourceUsage, TerminalSession,
+};
+use buildit_core::{Error, Result};
+use futures::StreamExt;
+use futures::stream::BoxStream;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::warn;
+
+/// Which faults to inject and how often.
+#[derive(Debug, Clone)]
+pub struct ChaosConfig {
+    /// PRNG seed; the same seed replays the same faults.
+    pub seed: u64,
+    /// Probability that a spawn is delayed.
+    pub spawn_delay_rate: f64,
+    /// Longest injected spawn delay.
+    pub max_spawn_delay: Duration,
+    /// Probability that a log stream is cut off early.
+    pub log_drop_rate: f64,
+    /// Probability that a status call fails.
+    pub status_failure_rate: f64,
+    /// Probability that a spawned job is killed.
+    pub kill_rate: f64,
+    /// Killed jobs are cancelled at a random point within this window.
+    pub max_kill_after: Duration,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            spawn_delay_rate: 0.0,
+            max_spawn_delay: Duration::from_secs(10),
+            log_drop_rate: 0.0,
+            status_failure_rate: 0.0,
+            kill_rate: 0.0,
+            max_kill_after: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ChaosConfig {
+    /// Load settings from `BUILDIT_CHAOS_*` environment variables.
+    ///
+    /// Returns `None` unless `BUILDIT_CHAOS_SEED` is set.
+    pub fn from_env() -> Option<Self> {
+        let seed = std::env::var("BUILDIT_CHAOS_SEED").ok()?.parse().ok()?;
+        let defaults = Self::default();
+        let rate = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<f64>().ok())
+                .map(|r| r.clamp(0.0, 1.0))
+                .unwrap_or(0.0)
+        };
+        let millis = |name: &str, default: Duration| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(default)
+        };
+
+        Some(Self {
+            seed,
+            spawn_delay_rate: rate("BUILDIT_CHAOS_SPAWN_DELAY_RATE"),
+            max_spawn_delay: millis("BUILDIT_CHAOS_MAX_SPAWN_DELAY_MS", defaults.max_spawn_delay),
+            log_drop_rate: rate("BUILDIT_CHAOS_LOG_DROP_RATE"),
+            status_failure_rate: rate("BUILDIT_CHAOS_STATUS_FAILURE_RATE"),
+            kill_rate: rate("BUILDIT_CHAOS_KILL_RATE"),
+            max_kill_after: millis("BUILDIT_CHAOS_MAX_KILL_AFTER_MS", defaults.max_kill_after),
+        })
+    }
+}
+
+/// SplitMix64: small, fast and good enough for picking faults.
+#[derive(Debug)]
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn chance(&mut self, rate: f64) -> bool {
+        rate > 0.0 && self.next_f64() < rate
+    }
+
+    fn duration_up_to(&mut self, max: Duration) -> Duration {
+        max.mul_f64(self.next_f64())
+    }
+}
+
+/// Executor wrapper that injects faults into another executor.
+pub struct ChaosExecutor {
+    inner: Arc<dyn Executor>,
+    config: ChaosConfig,
+    rng: Mutex<Rng>,
+}
+
+impl ChaosExecutor {
+    pub fn new(inner: Arc<dyn Executor>, config: ChaosConfig) -> Self {
+        let rng = Mutex::new(Rng(config.seed));
+        Self { inner, config, rng }
+    }
+
+    fn with_rng<T>(&self, f: impl FnOnce(&mut Rng) -> T) -> T {
+        let mut rng = self.rng.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut rng)
+    }
+}
+
+#[async_trait]
+impl Executor for ChaosExecutor {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    async fn can_execute(&self, spec: &JobSpec) -> bool {
+        self.inner.can_execute(spec).await
+    }
+
+    async fn platforms(&self) -> Vec<Platform> {
+        self.inner.platforms().await
+    }
+
+    async fn health(&self) -> Result<()> {
+        self.inner.health().await
+    }
+
+    async fn spawn(&self, spec: JobSpec) -> Result<JobHandle> {
+        let (delay, kill_after) = self.with_rng(|rng| {
+            let delay = rng
+                .chance(self.config.spawn_delay_rate)
+                .then(|| rng.duration_up_to(self.config.max_spawn_delay));
+            let kill_after = rng
+                .chance(self.config.kill_rate)
+                .then(|| rng.duration_up_to(self.config.max_kill_after));
+            (delay, kill_after)
+        });
+
+        if let Some(delay) = delay {
+            warn!(job_id = %spec.id, delay_ms = delay.as_millis() as u64, "chaos: delaying spawn");
+            tokio::time::sleep(delay).await;
+        }
+
+        let handle = self.inner.spawn(spec).await?;
+
+        if let Some(kill_after) = kill_after {
+            let inner = self.inner.clone();
+            let victim = handle.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(kill_after).await;
+                warn!(job_id = %victim.id, "chaos: killing job");
+                if let Err(e) = inner.cancel(&victim).await {
+                    warn!(job_id = %victim.id, error = %e, "chaos: failed to kill job");
+                }
+            });
+        }
+
+        Ok(handle)
+    }
+
+    async fn logs(&self, handle: &JobHandle) -> Result<BoxStream<'static, LogLine>> {
+        let stream = self.inner.logs(handle).await?;
+        let keep = self.with_rng(|rng| {
+            rng.chance(self.config.log_drop_rate)
+                .then(|| (rng.next_u64() % 100) as usize)
+        });
+
+        match keep {
+            Some(lines) => {
+                warn!(job_id = %handle.id, after_lines = lines, "chaos: dropping log stream");
+                Ok(stream.take(lines).boxed())
+            }
+            None => Ok(stream),
+        }
+    }
+
+    async fn status(&self, handle: &JobHandle) -> Result<JobStatus> {
+        if self.with_rng(|rng| rng.chance(self.config.status_failure_rate)) {
+            warn!(job_id = %handle.id, "chaos: failing status call");
+            return Err(Error::Internal(
+                "chaos: injected status failure".to_string(),
+            ));
+        }
+        self.inner.status(handle).await
+    }
+
+    async fn wait(&self, handle: &JobHandle) -> Result<JobResult> {
+        self.inner.wait(handle).await
+    }
+
+    async fn usage(&self, handle: &JobHandle) -> Result<Option<ResourceUsage>> {
+        self.inner.usage(handle).await
+    }
+
+    async fn cancel(&self, handle: &JobHandle) -> Result<()> {
+        self.inner.cancel(handle).await
+    }
+
+    async fn resolve_image(
+        &self,
+        image: &str,
+        pull_policy: Option<PullPolicy>,
+    ) -> Result<Option<String>> {
+        self.inner.resolve_image(image, pull_policy).await
+    }
+
+    async fn remove_workspace(&self, workspace: &str) -> Result<()> {
+        self.inner.remove_workspace(workspace).await
+    }
+
+    async fn exec_interactive(
+        &self,
+        handle: &JobHandle,
+        cmd: Vec<String>,
+    ) -> Result<TerminalSession> {
+        self.inner.exec_interactive(handle, cmd).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use buildit_core::ResourceId;
+    use buildit_core::executor::{LogStream, ResourceRequirements};
+    use chrono::Utc;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Executor that succeeds at everything and counts cancellations.
+    #[derive(Default)]
+    struct FakeExecutor {
+        cancelled: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Executor for FakeExecutor {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        async fn can_execute(&self, _spec: &JobSpec) -> bool {
+            true
+        }
+
+        async fn spawn(&self, spec: JobSpec) -> Result<JobHandle> {
+            Ok(JobHandle {
+                id: spec.id,
+                executor_id: "fake".to_string(),
+                executor_name: "fake".to_string(),
+            })
+        }
+
+        async fn logs(&self, _handle: &JobHandle) -> Result<BoxStream<'static, LogLine>> {
+            let lines = (0..200).map(|i| LogLine {
+                timestamp: Utc::now(),
+                stream: LogStream::Stdout,
+                content: format!("line {}", i),
+            });
+            Ok(futures::stream::iter(lines).boxed())
+        }
+
+        async fn status(&self, _handle: &JobHandle) -> Result<JobStatus> {
+            Ok(JobStatus::Pending)
+        }
+
+        async fn wait(&self, _handle: &JobHandle) -> Result<JobResult> {
+            Err(Error::Internal("not implemented".to_string()))
+        }
+
+        async fn cancel(&self, _handle: &JobHandle) -> Result<()> {
+            self.cancelled.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        async fn exec_interactive(
+            &self,
+            _handle: &JobHandle,
+            _cmd: Vec<String>,
+        ) -> Result<TerminalSession> {
+            Err(Error::Internal("not implemented".to_string()))
+        }
+    }
+
+    fn spec() -> JobSpec {
+        JobSpec {
+            id: ResourceId::new(),
+            image: "alpine".to_string(),
+            command: vec![],
+            entrypoint: None,
+            user: None,
+            working_dir: None,
+            env: HashMap::new(),
+            resources: ResourceRequirements::default(),
+            timeout: None,
+            volumes: vec![],
+            git_clone: None,
+            platform: Default::default(),
+            wait_for: vec![],
+            security_context: None,
+            privileged: false,
+            extra_hosts: vec![],
+            network_mode: None,
+            workspace: None,
+            tenant_id: None,
+            pull_policy: None,
+        }
+    }
+
+    async fn status_failures(seed: u64) -> Vec<bool> {
+        let executor = ChaosExecutor::new(
+            Arc::new(FakeExecutor::default()),
+            ChaosConfig {
+                seed,
+                status_failure_rate: 0.5,
+                ..Default::default()
+            },
+        );
+        let handle = executor.spawn(spec()).await.unwrap();
+        let mut failures = Vec::new();
+        for _ in 0..32 {
+            failures.push(executor.status(&handle).await.is_err());
+        }
+        failures
+    }
+
+    #[tokio::test]
+    async fn test_same_seed_replays_same_faults() {
+        let first = status_failures(42).await;
+        assert_eq!(first, status_failures(42).await);
+        assert_ne!(first, status_failures(7).await);
+        assert!(first.contains(&true) && first.contains(&false));
+    }
+
+    #[tokio::test]
+    async fn test_log_drop_truncates_stream() {
+        let executor = ChaosExecutor::new(
+            Arc::new(FakeExecutor::default()),
+            ChaosConfig {
+                log_drop_rate: 1.0,
+                ..Default::default()
+            },
+        );
+        let handle = executor.spawn(spec()).await.unwrap();
+        let lines: Vec<LogLine> = executor.logs(&handle).await.unwrap().collect().await;
+        assert!(lines.len() < 100);
+    }
+
+    #[tokio::test]
+    async fn test_kill_cancels_job() {
+        let inner = Arc::new(FakeExecutor::default());
+        let executor = ChaosExecutor::new(
+            inner.clone(),
+            ChaosConfig {
+                kill_rate: 1.0,
+                max_kill_after: Duration::from_millis(10),
+                ..Default::default()
+            },
+        );
+        executor.spawn(spec()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(inner.cancelled.load(Ordering::SeqCst), 1);
+    }
+}
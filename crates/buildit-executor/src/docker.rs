@@ -1,23 +1,56 @@
 //! Local Docker executor implementation.
+//!
+//! Images are pulled before each job unless its pull policy says
+//! otherwise, and are resolved to the digest of the local copy when a run
+//! is planned.
+//!
+//! Jobs that share a workspace get a named volume for it, mounted at
+//! `/workspace` in each of them. The first job to start clones the
+//! repository into the volume in a container of its own, as the Kubernetes
+//! executor's init container does, and the jobs after it find it cloned.
 
 use async_trait::async_trait;
 use bollard::Docker;
 use bollard::container::{
-    Config, CreateContainerOptions, LogOutput, LogsOptions, RemoveContainerOptions,
-    StartContainerOptions, WaitContainerOptions,
+    Config, CreateContainerOptions, LogOutput, LogsOptions, MemoryStatsStats,
+    RemoveContainerOptions, StartContainerOptions, Stats, StatsOptions, WaitContainerOptions,
 };
+use bollard::exec::{CreateExecOptions, StartExecOptions, StartExecResults};
 use bollard::image::CreateImageOptions;
 use bollard::models::HostConfig;
+use bollard::volume::RemoveVolumeOptions;
 use buildit_core::executor::*;
 use buildit_core::{Error, Result};
 use chrono::Utc;
 use futures::StreamExt;
 use futures::stream::BoxStream;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncWriteExt;
 use tracing::{debug, info, warn};
 
+use crate::git;
+use crate::metrics::record_error;
+use crate::wait;
+
+/// Named volume repository mirrors are kept in, shared by all jobs.
+const GIT_CACHE_VOLUME: &str = "buildit-git-cache";
+/// Where the mirror volume is mounted in job containers.
+const GIT_CACHE_PATH: &str = "/var/cache/buildit/git";
+/// Where a shared workspace volume is mounted in job containers.
+const WORKSPACE_PATH: &str = "/workspace";
+/// Lines of a failed clone's output kept in its error.
+const CLONE_LOG_LINES: &str = "20";
+
 /// Local Docker executor for development and small deployments.
 pub struct LocalDockerExecutor {
     docker: Docker,
+    /// Image of the container that clones repositories into shared
+    /// workspaces
+    git_image: String,
+    /// Held while a shared workspace is being cloned into, so jobs of a run
+    /// that start together clone it once
+    workspace_locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
 }
 
 impl LocalDockerExecutor {
@@ -25,17 +58,226 @@ impl LocalDockerExecutor {
     pub fn new() -> Result<Self> {
         let docker =
             Docker::connect_with_local_defaults().map_err(|e| Error::Internal(e.to_string()))?;
-        Ok(Self { docker })
+        Ok(Self::with_client(docker))
     }
 
     /// Create with a custom Docker client.
     pub fn with_client(docker: Docker) -> Self {
-        Self { docker }
+        Self {
+            docker,
+            git_image: git::DEFAULT_IMAGE.to_string(),
+            workspace_locks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Clone repositories into shared workspaces with `image`, which needs
+    /// `git-lfs` for LFS.
+    pub fn with_git_image(mut self, image: impl Into<String>) -> Self {
+        self.git_image = image.into();
+        self
     }
 
     fn container_name(job_id: &buildit_core::ResourceId) -> String {
         format!("buildit-job-{}", job_id)
     }
+
+    /// Pull `image`; a failed pull leaves the image the daemon already has.
+    async fn pull(&self, image: &str) {
+        info!(image = %image, "Pulling image");
+        let create_image_options = CreateImageOptions {
+            from_image: image.to_string(),
+            ..Default::default()
+        };
+
+        let mut pull_stream = self
+            .docker
+            .create_image(Some(create_image_options), None, None);
+        while let Some(result) = pull_stream.next().await {
+            match result {
+                Ok(info) => {
+                    if let Some(status) = info.status {
+                        debug!(status = %status, "Pull progress");
+                    }
+                }
+                Err(e) => {
+                    record_error("docker", "pull");
+                    warn!(error = %e, "Pull warning");
+                }
+            }
+        }
+    }
+
+    /// Make `image` available as `policy` says, pulling it before every
+    /// job by default. A digest never moves, so an image pinned to one is
+    /// only pulled when it is missing.
+    async fn ensure_image(&self, image: &str, policy: Option<PullPolicy>) -> Result<()> {
+        let policy = match policy.unwrap_or(PullPolicy::Always) {
+            PullPolicy::Always if image.contains('@') => PullPolicy::IfNotPresent,
+            policy => policy,
+        };
+        match policy {
+            PullPolicy::Always => self.pull(image).await,
+            PullPolicy::IfNotPresent => {
+                if self.docker.inspect_image(image).await.is_err() {
+                    self.pull(image).await;
+                }
+            }
+            PullPolicy::Never => {
+                if self.docker.inspect_image(image).await.is_err() {
+                    return Err(execution_failed(
+                        "pull",
+                        format!(
+                            "Image {} is not present and its pull policy is never",
+                            image
+                        ),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn workspace_lock(&self, volume: &str) -> Arc<tokio::sync::Mutex<()>> {
+        let mut locks = self.workspace_locks.lock().unwrap();
+        locks.entry(volume.to_string()).or_default().clone()
+    }
+
+    /// Clone a job's repository into its shared workspace volume, unless an
+    /// earlier job of the run already did, in a container of its own.
+    async fn clone_into_workspace(
+        &self,
+        job_container: &str,
+        git_clone: &GitCloneSpec,
+        volume: &str,
+    ) -> Result<()> {
+        let lock = self.workspace_lock(volume);
+        let _cloning = lock.lock().await;
+
+        self.pull(&self.git_image).await;
+        let mut binds = vec![format!("{}:{}:rw", volume, WORKSPACE_PATH)];
+        if git_clone.cache {
+            binds.push(format!("{}:{}:rw", GIT_CACHE_VOLUME, GIT_CACHE_PATH));
+        }
+        let script = git::clone_once_script(git_clone, &git_clone.target_dir, Some(GIT_CACHE_PATH));
+        let config = Config {
+            image: Some(self.git_image.clone()),
+            entrypoint: Some(vec!["sh".to_string(), "-c".to_string()]),
+            cmd: Some(vec![script]),
+            host_config: Some(HostConfig {
+                binds: Some(binds),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let name = format!("{}-clone", job_container);
+        let create_options = CreateContainerOptions {
+            name: name.clone(),
+            platform: None,
+        };
+        self.docker
+            .create_container(Some(create_options), config)
+            .await
+            .map_err(|e| {
+                execution_failed("clone", format!("Failed to create clone container: {}", e))
+            })?;
+        let result = self.run_clone(&name).await;
+        let options = RemoveContainerOptions {
+            force: true,
+            ..Default::default()
+        };
+        if let Err(e) = self.docker.remove_container(&name, Some(options)).await {
+            warn!(container = %name, error = %e, "Failed to remove clone container");
+        }
+        result
+    }
+
+    /// Run a created clone container to completion, failing with the end
+    /// of its output if the clone failed.
+    async fn run_clone(&self, name: &str) -> Result<()> {
+        self.docker
+            .start_container(name, None::<StartContainerOptions<String>>)
+            .await
+            .map_err(|e| {
+                execution_failed("clone", format!("Failed to start clone container: {}", e))
+            })?;
+        let options = WaitContainerOptions {
+            condition: "not-running",
+        };
+        let exit_code = match self.docker.wait_container(name, Some(options)).next().await {
+            Some(Ok(response)) => response.status_code,
+            Some(Err(bollard::errors::Error::DockerContainerWaitError { code, .. })) => code,
+            Some(Err(e)) => {
+                return Err(execution_failed(
+                    "clone",
+                    format!("Failed to wait for clone container: {}", e),
+                ));
+            }
+            None => 0,
+        };
+        if exit_code == 0 {
+            return Ok(());
+        }
+
+        let options = LogsOptions::<String> {
+            stdout: true,
+            stderr: true,
+            tail: CLONE_LOG_LINES.to_string(),
+            ..Default::default()
+        };
+        let output: Vec<String> = self
+            .docker
+            .logs(name, Some(options))
+            .filter_map(|line| async move { line.ok().map(|line| line.to_string()) })
+            .collect()
+            .await;
+        Err(execution_failed(
+            "clone",
+            format!(
+                "Failed to clone the repository into the workspace (exit code {}): {}",
+                exit_code,
+                output.concat().trim()
+            ),
+        ))
+    }
+}
+
+/// `image` pinned to the digest among `repo_digests` (as `docker image
+/// inspect` lists them) of its repository; `None` if none is of it, as a
+/// digest of another repository may not exist in this one.
+fn pinned_reference(image: &str, repo_digests: &[String]) -> Option<String> {
+    let name_end = image.rfind('/').map_or(0, |slash| slash + 1);
+    let repository = match image[name_end..].find(':') {
+        Some(colon) => &image[..name_end + colon],
+        None => image,
+    };
+    // Docker lists Docker Hub images by their short names
+    let short = repository
+        .strip_prefix("docker.io/")
+        .map(|r| r.strip_prefix("library/").unwrap_or(r))
+        .unwrap_or(repository);
+    let digest = repo_digests
+        .iter()
+        .filter_map(|d| d.split_once('@'))
+        .find(|(repo, _)| *repo == repository || *repo == short)
+        .map(|(_, digest)| digest)?;
+    Some(format!("{}@{}", repository, digest))
+}
+
+/// Name of the volume that holds the shared workspace `workspace`.
+fn workspace_volume(workspace: &str) -> String {
+    format!("buildit-workspace-{}", workspace)
+}
+
+/// Whether `dir` is inside the shared workspace's mount.
+fn in_workspace(dir: &str) -> bool {
+    dir.strip_prefix(WORKSPACE_PATH)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// A failed Docker call, counted in the executor error metrics.
+fn execution_failed(operation: &'static str, message: String) -> Error {
+    record_error("docker", operation);
+    Error::ExecutionFailed(message)
 }
 
 impl Default for LocalDockerExecutor {
@@ -55,31 +297,46 @@ impl Executor for LocalDockerExecutor {
         self.docker.ping().await.is_ok()
     }
 
+    /// The daemon's OS and architecture: `windows` for a daemon in Windows
+    /// container mode, `linux` otherwise, including Docker Desktop on macOS.
+    async fn platforms(&self) -> Vec<Platform> {
+        match self.docker.info().await {
+            Ok(info) => vec![Platform {
+                os: info
+                    .os_type
+                    .and_then(|os| os.parse().ok())
+                    .unwrap_or_default(),
+                arch: info.architecture.and_then(|arch| arch.parse().ok()),
+            }],
+            // Unreachable daemons are skipped by their health check
+            Err(_) => vec![Platform::host()],
+        }
+    }
+
+    async fn health(&self) -> Result<()> {
+        self.docker
+            .ping()
+            .await
+            .map(|_| ())
+            .map_err(|e| execution_failed("health", format!("Docker daemon unreachable: {}", e)))
+    }
+
     async fn spawn(&self, spec: JobSpec) -> Result<JobHandle> {
         let container_name = Self::container_name(&spec.id);
 
-        // Pull the image first
-        info!(image = %spec.image, "Pulling image");
-        let create_image_options = CreateImageOptions {
-            from_image: spec.image.clone(),
-            ..Default::default()
-        };
+        // Pull the image first, as its policy says
+        self.ensure_image(&spec.image, spec.pull_policy).await?;
 
-        let mut pull_stream = self
-            .docker
-            .create_image(Some(create_image_options), None, None);
-        while let Some(result) = pull_stream.next().await {
-            match result {
-                Ok(info) => {
-                    if let Some(status) = info.status {
-                        debug!(status = %status, "Pull progress");
-                    }
-                }
-                Err(e) => {
-                    warn!(error = %e, "Pull warning");
-                }
+        // A shared workspace is cloned into once, before its first job
+        let workspace = spec.workspace.as_deref().map(workspace_volume);
+        let cloned = match (&workspace, &spec.git_clone) {
+            (Some(volume), Some(git_clone)) if in_workspace(&git_clone.target_dir) => {
+                self.clone_into_workspace(&container_name, git_clone, volume)
+                    .await?;
+                true
             }
-        }
+            _ => false,
+        };
 
         // Build environment variables
         let env: Vec<String> = spec
@@ -89,41 +346,9 @@ impl Executor for LocalDockerExecutor {
             .collect();
 
         // Build the command, prepending git clone if needed
-        let cmd = if let Some(ref git_clone) = spec.git_clone {
-            // Build git clone command
-            let clone_url = if let Some(ref token) = git_clone.access_token {
-                if git_clone.url.starts_with("https://") {
-                    git_clone
-                        .url
-                        .replacen("https://", &format!("https://{}@", token), 1)
-                } else {
-                    git_clone.url.clone()
-                }
-            } else {
-                git_clone.url.clone()
-            };
-
-            let depth_arg = git_clone
-                .depth
-                .map(|d| format!("--depth {}", d))
-                .unwrap_or_default();
-
-            let branch_arg = git_clone
-                .branch
-                .as_ref()
-                .map(|b| format!("-b {}", b))
-                .unwrap_or_default();
-
-            let checkout_cmd = git_clone
-                .sha
-                .as_ref()
-                .map(|sha| format!(" && git checkout {}", sha))
-                .unwrap_or_default();
-
-            let clone_script = format!(
-                "git clone {} {} {} {}{}",
-                depth_arg, branch_arg, clone_url, &git_clone.target_dir, checkout_cmd
-            );
+        let cmd = if let Some(git_clone) = spec.git_clone.as_ref().filter(|_| !cloned) {
+            let clone_script =
+                git::clone_script(git_clone, &git_clone.target_dir, Some(GIT_CACHE_PATH));
 
             // Combine clone with original commands
             let user_cmds = spec.command.join(" && ");
@@ -142,6 +367,7 @@ impl Executor for LocalDockerExecutor {
         } else {
             Some(spec.command.clone())
         };
+        let cmd = cmd.map(|argv| wait::wrap(&spec.wait_for, argv));
 
         // Determine working directory
         let working_dir = spec
@@ -151,29 +377,37 @@ impl Executor for LocalDockerExecutor {
             .or(spec.working_dir.clone());
 
         // Build volume binds from spec.volumes
-        let binds: Option<Vec<String>> = if spec.volumes.is_empty() {
-            None
-        } else {
-            Some(
-                spec.volumes
-                    .iter()
-                    .map(|v| {
-                        let mode = if v.read_only { "ro" } else { "rw" };
-                        format!("{}:{}:{}", v.name, v.mount_path, mode)
-                    })
-                    .collect(),
-            )
-        };
-
-        let host_config = HostConfig {
-            binds,
-            ..Default::default()
-        };
+        let mut binds: Vec<String> = spec
+            .volumes
+            .iter()
+            .map(|v| {
+                let mode = if v.read_only { "ro" } else { "rw" };
+                format!("{}:{}:{}", v.name, v.mount_path, mode)
+            })
+            .collect();
+        if spec.git_clone.as_ref().is_some_and(|gc| gc.cache) && !cloned {
+            binds.push(format!("{}:{}:rw", GIT_CACHE_VOLUME, GIT_CACHE_PATH));
+        }
+        if let Some(volume) = &workspace {
+            binds.push(format!("{}:{}:rw", volume, WORKSPACE_PATH));
+        }
+        let host_config = host_config(&spec, binds);
 
         // Create container config
+        // Docker resets the entrypoint when given a single empty string
+        let entrypoint = spec.entrypoint.as_ref().map(|entrypoint| {
+            if entrypoint.is_empty() {
+                vec![String::new()]
+            } else {
+                entrypoint.clone()
+            }
+        });
+
         let config = Config {
             image: Some(spec.image.clone()),
+            entrypoint,
             cmd,
+            user: spec.user.clone(),
             env: Some(env),
             working_dir,
             attach_stdout: Some(true),
@@ -194,14 +428,14 @@ impl Executor for LocalDockerExecutor {
             .docker
             .create_container(Some(create_options), config)
             .await
-            .map_err(|e| Error::ExecutionFailed(format!("Failed to create container: {}", e)))?;
+            .map_err(|e| execution_failed("spawn", format!("Failed to create container: {}", e)))?;
 
         // Start the container
         info!(container = %container_name, "Starting container");
         self.docker
             .start_container(&container_name, None::<StartContainerOptions<String>>)
             .await
-            .map_err(|e| Error::ExecutionFailed(format!("Failed to start container: {}", e)))?;
+            .map_err(|e| execution_failed("spawn", format!("Failed to start container: {}", e)))?;
 
         Ok(JobHandle {
             id: spec.id,
@@ -251,6 +485,7 @@ impl Executor for LocalDockerExecutor {
                     })
                 }
                 Err(e) => {
+                    record_error("docker", "logs");
                     warn!(error = %e, "Log stream error");
                     None
                 }
@@ -342,6 +577,7 @@ impl Executor for LocalDockerExecutor {
             match result {
                 Ok(response) => Some(response.status_code as i32),
                 Err(e) => {
+                    record_error("docker", "wait");
                     warn!(error = %e, "Wait error");
                     None
                 }
@@ -359,6 +595,67 @@ impl Executor for LocalDockerExecutor {
         })
     }
 
+    /// From `docker stats`: CPU over the daemon's last sampling cycle, and
+    /// memory without the page cache, as `docker stats` shows them.
+    async fn usage(&self, handle: &JobHandle) -> Result<Option<ResourceUsage>> {
+        let container_name = Self::container_name(&handle.id);
+        let options = StatsOptions {
+            stream: false,
+            one_shot: false,
+        };
+        let stats = self
+            .docker
+            .stats(&container_name, Some(options))
+            .next()
+            .await
+            .transpose()
+            .map_err(|e| execution_failed("stats", format!("Failed to read stats: {}", e)))?;
+        Ok(stats.as_ref().map(stats_usage))
+    }
+
+    async fn resolve_image(
+        &self,
+        image: &str,
+        pull_policy: Option<PullPolicy>,
+    ) -> Result<Option<String>> {
+        if image.contains('@') {
+            return Ok(Some(image.to_string()));
+        }
+        self.ensure_image(image, pull_policy).await?;
+        match self.docker.inspect_image(image).await {
+            Ok(inspect) => Ok(pinned_reference(
+                image,
+                &inspect.repo_digests.unwrap_or_default(),
+            )),
+            Err(bollard::errors::Error::DockerResponseServerError {
+                status_code: 404, ..
+            }) => Ok(None),
+            Err(e) => Err(execution_failed(
+                "resolve_image",
+                format!("Failed to inspect image {}: {}", image, e),
+            )),
+        }
+    }
+
+    async fn remove_workspace(&self, workspace: &str) -> Result<()> {
+        let volume = workspace_volume(workspace);
+        self.workspace_locks.lock().unwrap().remove(&volume);
+        match self
+            .docker
+            .remove_volume(&volume, Some(RemoveVolumeOptions { force: true }))
+            .await
+        {
+            Ok(()) => Ok(()),
+            Err(bollard::errors::Error::DockerResponseServerError {
+                status_code: 404, ..
+            }) => Ok(()),
+            Err(e) => Err(execution_failed(
+                "remove_workspace",
+                format!("Failed to remove workspace volume {}: {}", volume, e),
+            )),
+        }
+    }
+
     async fn cancel(&self, handle: &JobHandle) -> Result<()> {
         let container_name = Self::container_name(&handle.id);
 
@@ -366,7 +663,7 @@ impl Executor for LocalDockerExecutor {
         self.docker
             .stop_container(&container_name, None)
             .await
-            .map_err(|e| Error::ExecutionFailed(format!("Failed to stop container: {}", e)))?;
+            .map_err(|e| execution_failed("cancel", format!("Failed to stop container: {}", e)))?;
 
         // Remove the container
         let options = RemoveContainerOptions {
@@ -377,21 +674,67 @@ impl Executor for LocalDockerExecutor {
         self.docker
             .remove_container(&container_name, Some(options))
             .await
-            .map_err(|e| Error::ExecutionFailed(format!("Failed to remove container: {}", e)))?;
+            .map_err(|e| {
+                execution_failed("cancel", format!("Failed to remove container: {}", e))
+            })?;
 
         Ok(())
     }
 
     async fn exec_interactive(
         &self,
-        _handle: &JobHandle,
-        _cmd: Vec<String>,
+        handle: &JobHandle,
+        cmd: Vec<String>,
     ) -> Result<TerminalSession> {
-        // TODO: Implement interactive exec for Docker
-        // This is complex and not needed for MVP
-        Err(Error::Internal(
-            "Interactive exec not yet implemented for Docker".to_string(),
-        ))
+        let container_name = Self::container_name(&handle.id);
+        let exec = self
+            .docker
+            .create_exec(
+                &container_name,
+                CreateExecOptions {
+                    cmd: Some(cmd),
+                    attach_stdin: Some(true),
+                    attach_stdout: Some(true),
+                    attach_stderr: Some(true),
+                    tty: Some(true),
+                    ..Default::default()
+                },
+            )
+            .await
+            .map_err(|e| execution_failed("exec", format!("Failed to create exec: {}", e)))?;
+        let started = self
+            .docker
+            .start_exec(
+                &exec.id,
+                Some(StartExecOptions {
+                    detach: false,
+                    tty: true,
+                    output_capacity: None,
+                }),
+            )
+            .await
+            .map_err(|e| execution_failed("exec", format!("Failed to start exec: {}", e)))?;
+        let StartExecResults::Attached { output, input } = started else {
+            return Err(execution_failed(
+                "exec",
+                "Exec started detached".to_string(),
+            ));
+        };
+
+        let stdin = futures::sink::unfold(input, |mut input, bytes: bytes::Bytes| async move {
+            input.write_all(&bytes).await?;
+            input.flush().await?;
+            Ok::<_, std::io::Error>(input)
+        });
+        let stdout = output.map(|chunk| {
+            chunk
+                .map(LogOutput::into_bytes)
+                .map_err(std::io::Error::other)
+        });
+        Ok(TerminalSession {
+            stdin: Box::new(Box::pin(stdin)),
+            stdout: Box::pin(stdout),
+        })
     }
 }
 
@@ -412,6 +755,48 @@ pub async fn cleanup_container(docker: &Docker, job_id: &buildit_core::ResourceI
     Ok(())
 }
 
+/// What a container uses according to its stats.
+/// How the job's container is run on the host: its volumes, privileges,
+/// `/etc/hosts` entries and network.
+fn host_config(spec: &JobSpec, binds: Vec<String>) -> HostConfig {
+    HostConfig {
+        binds: (!binds.is_empty()).then_some(binds),
+        privileged: spec.privileged.then_some(true),
+        extra_hosts: (!spec.extra_hosts.is_empty()).then(|| spec.extra_hosts.clone()),
+        network_mode: spec.network_mode.clone(),
+        ..Default::default()
+    }
+}
+
+fn stats_usage(stats: &Stats) -> ResourceUsage {
+    let cpu = &stats.cpu_stats;
+    let precpu = &stats.precpu_stats;
+    let cpu_delta = cpu
+        .cpu_usage
+        .total_usage
+        .saturating_sub(precpu.cpu_usage.total_usage);
+    let system_delta = cpu
+        .system_cpu_usage
+        .unwrap_or(0)
+        .saturating_sub(precpu.system_cpu_usage.unwrap_or(0));
+    let cpus = cpu.online_cpus.unwrap_or(1);
+    let cpu_millis = match system_delta {
+        0 => 0,
+        _ => (cpu_delta as f64 / system_delta as f64 * cpus as f64 * 1000.0).round() as u64,
+    };
+
+    let memory = &stats.memory_stats;
+    let cache = match memory.stats {
+        Some(MemoryStatsStats::V1(v1)) => v1.total_inactive_file,
+        Some(MemoryStatsStats::V2(v2)) => v2.inactive_file,
+        None => 0,
+    };
+    ResourceUsage {
+        cpu_millis,
+        memory_bytes: memory.usage.unwrap_or(0).saturating_sub(cache),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -423,6 +808,8 @@ mod tests {
             id: buildit_core::ResourceId::new(),
             image: "alpine:latest".to_string(),
             command: vec!["echo".to_string(), "hello".to_string()],
+            entrypoint: None,
+            user: None,
             working_dir: Some("/workspace".to_string()),
             env: {
                 let mut env = HashMap::new();
@@ -432,9 +819,111 @@ mod tests {
             resources: ResourceRequirements::default(),
             timeout: None,
             volumes: vec![],
+            git_clone: None,
+            platform: Default::default(),
+            wait_for: vec![],
+            security_context: None,
+            privileged: false,
+            extra_hosts: vec![],
+            network_mode: None,
+            workspace: None,
+            tenant_id: None,
+            pull_policy: None,
         }
     }
 
+    #[test]
+    fn test_stats_usage() {
+        let stats: Stats = serde_json::from_value(serde_json::json!({
+            "read": "2026-10-16T12:00:01Z",
+            "preread": "2026-10-16T12:00:00Z",
+            "num_procs": 0,
+            "pids_stats": {},
+            "memory_stats": {"usage": 300_000_000u64},
+            "blkio_stats": {},
+            "cpu_stats": {
+                "cpu_usage": {"total_usage": 1_500_000_000u64, "usage_in_usermode": 0, "usage_in_kernelmode": 0},
+                "system_cpu_usage": 8_000_000_000u64,
+                "online_cpus": 4,
+                "throttling_data": {"periods": 0, "throttled_periods": 0, "throttled_time": 0}
+            },
+            "precpu_stats": {
+                "cpu_usage": {"total_usage": 1_000_000_000u64, "usage_in_usermode": 0, "usage_in_kernelmode": 0},
+                "system_cpu_usage": 4_000_000_000u64,
+                "online_cpus": 4,
+                "throttling_data": {"periods": 0, "throttled_periods": 0, "throttled_time": 0}
+            },
+            "storage_stats": {}
+        }))
+        .unwrap();
+
+        // Half a second of CPU over four seconds of the host's four CPUs
+        assert_eq!(
+            stats_usage(&stats),
+            ResourceUsage {
+                cpu_millis: 500,
+                memory_bytes: 300_000_000,
+            }
+        );
+    }
+
+    #[test]
+    fn test_pinned_reference() {
+        let digests = vec![
+            "registry.example.com/rust@sha256:aaa".to_string(),
+            "rust@sha256:bbb".to_string(),
+        ];
+        assert_eq!(
+            pinned_reference("rust:1.75", &digests).as_deref(),
+            Some("rust@sha256:bbb")
+        );
+        assert_eq!(
+            pinned_reference("docker.io/library/rust:1.75", &digests).as_deref(),
+            Some("docker.io/library/rust@sha256:bbb")
+        );
+        assert_eq!(
+            pinned_reference("registry.example.com/rust:1.75", &digests).as_deref(),
+            Some("registry.example.com/rust@sha256:aaa")
+        );
+        // A digest of another repository does not pin this one
+        assert_eq!(
+            pinned_reference("localhost:5000/tools/rust", &digests),
+            None
+        );
+        // Images built locally have no digest
+        assert_eq!(pinned_reference("app:dev", &[]), None);
+    }
+
+    #[test]
+    fn test_workspace_paths() {
+        assert_eq!(workspace_volume("0191b3c4"), "buildit-workspace-0191b3c4");
+        assert!(in_workspace("/workspace"));
+        assert!(in_workspace("/workspace/app"));
+        assert!(!in_workspace("/workspaces"));
+        assert!(!in_workspace("/src"));
+    }
+
+    #[test]
+    fn test_host_config() {
+        let config = host_config(&make_test_spec(), vec![]);
+        assert_eq!(config.binds, None);
+        assert_eq!(config.privileged, None);
+        assert_eq!(config.extra_hosts, None);
+        assert_eq!(config.network_mode, None);
+
+        let spec = JobSpec {
+            privileged: true,
+            extra_hosts: vec!["registry.internal:10.0.0.5".to_string()],
+            network_mode: Some("host".to_string()),
+            ..make_test_spec()
+        };
+        let config = host_config(&spec, vec!["cache:/cache:rw".to_string()]);
+        assert_eq!(config.binds.unwrap(), ["cache:/cache:rw"]);
+        assert_eq!(config.privileged, Some(true));
+        assert_eq!(config.extra_hosts.unwrap(), ["registry.internal:10.0.0.5"]);
+        assert_eq!(config.network_mode.as_deref(), Some("host"));
+    }
+
     #[test]
     fn test_container_name_generation() {
         let id = buildit_core::ResourceId::new();
@@ -477,11 +966,23 @@ mod tests {
             id: buildit_core::ResourceId::new(),
             image: "alpine:latest".to_string(),
             command: vec![],
+            entrypoint: None,
+            user: None,
             working_dir: None,
             env: HashMap::new(),
             resources: ResourceRequirements::default(),
             timeout: None,
             volumes: vec![],
+            git_clone: None,
+            platform: Default::default(),
+            wait_for: vec![],
+            security_context: None,
+            privileged: false,
+            extra_hosts: vec![],
+            network_mode: None,
+            workspace: None,
+            tenant_id: None,
+            pull_policy: None,
         };
 
         assert!(spec.command.is_empty());
@@ -492,7 +993,7 @@ mod tests {
     fn test_job_handle_structure() {
         let id = buildit_core::ResourceId::new();
         let handle = JobHandle {
-            id: id.clone(),
+            id,
             executor_id: "container-abc123".to_string(),
             executor_name: "docker".to_string(),
         };
@@ -608,11 +1109,23 @@ mod integration_tests {
             id: buildit_core::ResourceId::new(),
             image: "alpine:latest".to_string(),
             command: vec!["echo".to_string(), "test".to_string()],
+            entrypoint: None,
+            user: None,
             working_dir: None,
             env: HashMap::new(),
             resources: ResourceRequirements::default(),
             timeout: None,
             volumes: vec![],
+            git_clone: None,
+            platform: Default::default(),
+            wait_for: vec![],
+            security_context: None,
+            privileged: false,
+            extra_hosts: vec![],
+            network_mode: None,
+            workspace: None,
+            tenant_id: None,
+            pull_policy: None,
         };
 
         let can_execute = executor.can_execute(&spec).await;
@@ -636,6 +1149,8 @@ mod integration_tests {
                 "-c".to_string(),
                 "echo 'Hello from Docker!'".to_string(),
             ],
+            entrypoint: None,
+            user: None,
             working_dir: None,
             env: {
                 let mut env = HashMap::new();
@@ -645,6 +1160,16 @@ mod integration_tests {
             resources: ResourceRequirements::default(),
             timeout: None,
             volumes: vec![],
+            git_clone: None,
+            platform: Default::default(),
+            wait_for: vec![],
+            security_context: None,
+            privileged: false,
+            extra_hosts: vec![],
+            network_mode: None,
+            workspace: None,
+            tenant_id: None,
+            pull_policy: None,
         };
 
         // Spawn the job
@@ -682,11 +1207,23 @@ mod integration_tests {
                 "-c".to_string(),
                 "exit 42".to_string(),
             ],
+            entrypoint: None,
+            user: None,
             working_dir: None,
             env: HashMap::new(),
             resources: ResourceRequirements::default(),
             timeout: None,
             volumes: vec![],
+            git_clone: None,
+            platform: Default::default(),
+            wait_for: vec![],
+            security_context: None,
+            privileged: false,
+            extra_hosts: vec![],
+            network_mode: None,
+            workspace: None,
+            tenant_id: None,
+            pull_policy: None,
         };
 
         let handle = executor.spawn(spec).await.expect("Should spawn container");
@@ -721,11 +1258,23 @@ mod integration_tests {
                 "-c".to_string(),
                 "sleep 300".to_string(),
             ],
+            entrypoint: None,
+            user: None,
             working_dir: None,
             env: HashMap::new(),
             resources: ResourceRequirements::default(),
             timeout: None,
             volumes: vec![],
+            git_clone: None,
+            platform: Default::default(),
+            wait_for: vec![],
+            security_context: None,
+            privileged: false,
+            extra_hosts: vec![],
+            network_mode: None,
+            workspace: None,
+            tenant_id: None,
+            pull_policy: None,
         };
 
         let handle = executor.spawn(spec).await.expect("Should spawn container");
@@ -765,11 +1314,23 @@ mod integration_tests {
                 "-c".to_string(),
                 "echo 'line1'; echo 'line2'; echo 'line3'".to_string(),
             ],
+            entrypoint: None,
+            user: None,
             working_dir: None,
             env: HashMap::new(),
             resources: ResourceRequirements::default(),
             timeout: None,
             volumes: vec![],
+            git_clone: None,
+            platform: Default::default(),
+            wait_for: vec![],
+            security_context: None,
+            privileged: false,
+            extra_hosts: vec![],
+            network_mode: None,
+            workspace: None,
+            tenant_id: None,
+            pull_policy: None,
         };
 
         let handle = executor.spawn(spec).await.expect("Should spawn container");
@@ -818,6 +1379,8 @@ mod integration_tests {
                 "-c".to_string(),
                 "echo $MY_VAR".to_string(),
             ],
+            entrypoint: None,
+            user: None,
             working_dir: None,
             env: {
                 let mut env = HashMap::new();
@@ -827,6 +1390,16 @@ mod integration_tests {
             resources: ResourceRequirements::default(),
             timeout: None,
             volumes: vec![],
+            git_clone: None,
+            platform: Default::default(),
+            wait_for: vec![],
+            security_context: None,
+            privileged: false,
+            extra_hosts: vec![],
+            network_mode: None,
+            workspace: None,
+            tenant_id: None,
+            pull_policy: None,
         };
 
         let handle = executor.spawn(spec).await.expect("Should spawn container");
@@ -10,17 +10,106 @@ use futures::StreamExt;
 use futures::stream::BoxStream;
 use k8s_openapi::api::batch::v1::{Job, JobSpec as K8sJobSpec};
 use k8s_openapi::api::core::v1::{
-    Container, EnvVar, PodSpec, PodTemplateSpec, ResourceRequirements as K8sResourceRequirements,
+    Capabilities, Container, EnvVar, PodSpec, PodTemplateSpec,
+    ResourceRequirements as K8sResourceRequirements, SeccompProfile,
+    SecurityContext as K8sSecurityContext, Toleration,
 };
 use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
 use kube::Client;
-use kube::api::{Api, DeleteParams, LogParams, PostParams};
+use kube::api::{Api, ApiResource, DeleteParams, DynamicObject, LogParams, PostParams};
 use kube::runtime::watcher::{Config as WatcherConfig, Event as WatcherEvent, watcher};
 use std::collections::BTreeMap;
 use tokio::time::{Duration, sleep};
 use tracing::{debug, info, warn};
 
+use crate::git;
+use crate::metrics::record_error;
+use crate::wait;
+
+/// A failed Kubernetes API call, counted in the executor error metrics.
+fn execution_failed(operation: &'static str, message: String) -> Error {
+    record_error("kubernetes", operation);
+    Error::ExecutionFailed(message)
+}
+
+/// Where the mirror cache is mounted in the clone init container.
+const GIT_CACHE_PATH: &str = "/var/cache/buildit/git";
+
+/// Security context job pods get unless configured otherwise: the
+/// container runtime's default seccomp profile, which admission policies at
+/// the Pod Security Standards' baseline level and above ask for.
+fn default_security_context() -> SecurityContext {
+    SecurityContext {
+        seccomp_profile: Some("RuntimeDefault".to_string()),
+        ..Default::default()
+    }
+}
+
+/// A job security context as a Kubernetes container security context, or
+/// `None` if it sets nothing.
+fn container_security_context(context: &SecurityContext) -> Option<K8sSecurityContext> {
+    if context.is_empty() {
+        return None;
+    }
+    let list = |names: &[String]| (!names.is_empty()).then(|| names.to_vec());
+    let capabilities = (!context.drop_capabilities.is_empty()
+        || !context.add_capabilities.is_empty())
+    .then(|| Capabilities {
+        add: list(&context.add_capabilities),
+        drop: list(&context.drop_capabilities),
+    });
+    let seccomp_profile =
+        context
+            .seccomp_profile
+            .as_ref()
+            .map(|profile| match profile.strip_prefix("Localhost/") {
+                Some(path) => SeccompProfile {
+                    type_: "Localhost".to_string(),
+                    localhost_profile: Some(path.to_string()),
+                },
+                None => SeccompProfile {
+                    type_: profile.clone(),
+                    localhost_profile: None,
+                },
+            });
+    Some(K8sSecurityContext {
+        run_as_non_root: context.run_as_non_root,
+        run_as_user: context.run_as_user,
+        run_as_group: context.run_as_group,
+        read_only_root_filesystem: context.read_only_root_filesystem,
+        allow_privilege_escalation: context.allow_privilege_escalation,
+        capabilities,
+        seccomp_profile,
+        ..Default::default()
+    })
+}
+
+/// Where a job's pod may be scheduled for its platform: on nodes with the
+/// well-known `kubernetes.io/os` and `kubernetes.io/arch` labels, and, for
+/// Windows, tolerating the `node.kubernetes.io/os=windows:NoSchedule` taint
+/// Windows node pools usually carry.
+fn node_placement(platform: &Platform) -> (BTreeMap<String, String>, Option<Vec<Toleration>>) {
+    let mut node_selector = BTreeMap::new();
+    node_selector.insert(
+        "kubernetes.io/os".to_string(),
+        platform.os.as_str().to_string(),
+    );
+    if let Some(arch) = platform.arch {
+        node_selector.insert("kubernetes.io/arch".to_string(), arch.as_str().to_string());
+    }
+    let tolerations = (platform.os == Os::Windows).then(|| {
+        vec![Toleration {
+            key: Some("node.kubernetes.io/os".to_string()),
+            operator: Some("Equal".to_string()),
+            value: Some("windows".to_string()),
+            effect: Some("NoSchedule".to_string()),
+            ..Default::default()
+        }]
+    });
+    (node_selector, tolerations)
+}
+
 /// Kubernetes-based job executor.
 ///
 /// Runs each job as a Kubernetes Job resource with a single pod.
@@ -30,6 +119,14 @@ pub struct KubernetesExecutor {
     namespace: String,
     /// Labels to apply to all jobs created by this executor
     labels: BTreeMap<String, String>,
+    /// Image of the init container that clones the repository
+    git_image: String,
+    /// PersistentVolumeClaim repository mirrors are kept in, if any
+    git_cache_claim: Option<String>,
+    /// Platforms the cluster has nodes for
+    platforms: Vec<Platform>,
+    /// Security context of Linux job containers, before stage overrides
+    security_context: SecurityContext,
 }
 
 impl KubernetesExecutor {
@@ -53,6 +150,10 @@ impl KubernetesExecutor {
             client,
             namespace: namespace.into(),
             labels,
+            git_image: git::DEFAULT_IMAGE.to_string(),
+            git_cache_claim: None,
+            platforms: vec![Platform::new(Os::Linux, Arch::Amd64)],
+            security_context: default_security_context(),
         })
     }
 
@@ -72,9 +173,43 @@ impl KubernetesExecutor {
             client,
             namespace: namespace.into(),
             labels,
+            git_image: git::DEFAULT_IMAGE.to_string(),
+            git_cache_claim: None,
+            platforms: vec![Platform::new(Os::Linux, Arch::Amd64)],
+            security_context: default_security_context(),
         }
     }
 
+    /// Clone repositories with `image`, which needs `git-lfs` for LFS.
+    pub fn with_git_image(mut self, image: impl Into<String>) -> Self {
+        self.git_image = image.into();
+        self
+    }
+
+    /// Keep repository mirrors in the PersistentVolumeClaim `claim`, for
+    /// jobs that ask for a cached clone. It must be mountable by every job
+    /// pod at once (ReadWriteMany).
+    pub fn with_git_cache(mut self, claim: impl Into<String>) -> Self {
+        self.git_cache_claim = Some(claim.into());
+        self
+    }
+
+    /// Run jobs of these platforms, e.g. `windows/amd64` for a cluster
+    /// with a Windows node pool; `linux/amd64` by default. Pods are pinned
+    /// to nodes of their job's platform.
+    pub fn with_platforms(mut self, platforms: Vec<Platform>) -> Self {
+        self.platforms = platforms;
+        self
+    }
+
+    /// Run Linux job containers with `context` laid over the default, which
+    /// only sets the `RuntimeDefault` seccomp profile. A job's own security
+    /// context is laid over this in turn.
+    pub fn with_security_context(mut self, context: SecurityContext) -> Self {
+        self.security_context = default_security_context().merged(&context);
+        self
+    }
+
     /// Generate a unique job name from the job ID.
     fn job_name(job_id: &ResourceId) -> String {
         // K8s names must be lowercase, alphanumeric, and max 63 chars
@@ -94,11 +229,23 @@ impl KubernetesExecutor {
     /// Build a Kubernetes Job from our JobSpec.
     fn build_k8s_job(&self, spec: &JobSpec) -> Job {
         use k8s_openapi::api::core::v1::{
-            EmptyDirVolumeSource, Volume, VolumeMount as K8sVolumeMount,
+            EmptyDirVolumeSource, PersistentVolumeClaimVolumeSource, Volume,
+            VolumeMount as K8sVolumeMount,
         };
 
         let job_name = Self::job_name(&spec.id);
 
+        // Windows containers have no Linux security context
+        let security_context = if spec.platform.os == Os::Windows {
+            None
+        } else {
+            let context = match &spec.security_context {
+                Some(over) => self.security_context.merged(over),
+                None => self.security_context.clone(),
+            };
+            container_security_context(&context)
+        };
+
         // Build environment variables
         let env_vars: Vec<EnvVar> = spec
             .env
@@ -148,62 +295,47 @@ impl KubernetesExecutor {
         // Check if we need to clone a git repo
         let (init_containers, volumes, container_volume_mounts, working_dir) =
             if let Some(ref git_clone) = spec.git_clone {
-                // Build git clone command
-                let mut clone_cmd = vec!["sh".to_string(), "-c".to_string()];
-
-                // Build the clone URL with auth if token provided
-                let clone_url = if let Some(ref token) = git_clone.access_token {
-                    if git_clone.url.starts_with("https://") {
-                        git_clone
-                            .url
-                            .replacen("https://", &format!("https://{}@", token), 1)
-                    } else {
-                        git_clone.url.clone()
-                    }
-                } else {
-                    git_clone.url.clone()
-                };
-
-                let depth_arg = git_clone
-                    .depth
-                    .map(|d| format!("--depth {}", d))
-                    .unwrap_or_default();
-
-                let branch_arg = git_clone
-                    .branch
-                    .as_ref()
-                    .map(|b| format!("-b {}", b))
-                    .unwrap_or_default();
-
-                // Clone command, optionally checkout specific SHA
-                let checkout_cmd = git_clone
-                    .sha
-                    .as_ref()
-                    .map(|sha| format!(" && git checkout {}", sha))
-                    .unwrap_or_default();
-
-                let script = format!(
-                    "git clone {} {} {} {}{}",
-                    depth_arg, branch_arg, clone_url, &git_clone.target_dir, checkout_cmd
+                let cache = self.git_cache_claim.as_ref().filter(|_| git_clone.cache);
+                let script = git::clone_script(
+                    git_clone,
+                    &git_clone.target_dir,
+                    cache.map(|_| GIT_CACHE_PATH),
                 );
-                clone_cmd.push(script);
+                let clone_cmd = vec!["sh".to_string(), "-c".to_string(), script];
 
-                let init_container = Container {
-                    name: "git-clone".to_string(),
-                    image: Some("alpine/git:latest".to_string()),
-                    command: Some(clone_cmd),
-                    volume_mounts: Some(vec![K8sVolumeMount {
-                        name: "workspace".to_string(),
-                        mount_path: "/workspace".to_string(),
-                        ..Default::default()
-                    }]),
+                let mut clone_mounts = vec![K8sVolumeMount {
+                    name: "workspace".to_string(),
+                    mount_path: "/workspace".to_string(),
                     ..Default::default()
-                };
-
-                let volume = Volume {
+                }];
+                let mut volumes = vec![Volume {
                     name: "workspace".to_string(),
                     empty_dir: Some(EmptyDirVolumeSource::default()),
                     ..Default::default()
+                }];
+                if let Some(claim) = cache {
+                    clone_mounts.push(K8sVolumeMount {
+                        name: "git-cache".to_string(),
+                        mount_path: GIT_CACHE_PATH.to_string(),
+                        ..Default::default()
+                    });
+                    volumes.push(Volume {
+                        name: "git-cache".to_string(),
+                        persistent_volume_claim: Some(PersistentVolumeClaimVolumeSource {
+                            claim_name: claim.clone(),
+                            read_only: Some(false),
+                        }),
+                        ..Default::default()
+                    });
+                }
+
+                let init_container = Container {
+                    name: "git-clone".to_string(),
+                    image: Some(self.git_image.clone()),
+                    command: Some(clone_cmd),
+                    volume_mounts: Some(clone_mounts),
+                    security_context: security_context.clone(),
+                    ..Default::default()
                 };
 
                 let volume_mount = K8sVolumeMount {
@@ -214,7 +346,7 @@ impl KubernetesExecutor {
 
                 (
                     Some(vec![init_container]),
-                    Some(vec![volume]),
+                    Some(volumes),
                     Some(vec![volume_mount]),
                     Some(git_clone.target_dir.clone()),
                 )
@@ -222,15 +354,42 @@ impl KubernetesExecutor {
                 (None, None, None, spec.working_dir.clone())
             };
 
+        // A container's command already replaces the image's entrypoint; a
+        // non-empty entrypoint override becomes the command, with the
+        // job's command as its arguments
+        let command = (!spec.command.is_empty()).then(|| spec.command.clone());
+        let (command, args) = match &spec.entrypoint {
+            Some(entrypoint) if !entrypoint.is_empty() => (Some(entrypoint.clone()), command),
+            _ => (command, None),
+        };
+        // Waiting runs the container's whole command line once ready
+        let (command, args) = match command {
+            Some(command) if !spec.wait_for.is_empty() => {
+                let argv = command
+                    .into_iter()
+                    .chain(args.unwrap_or_default())
+                    .collect();
+                (Some(wait::wrap(&spec.wait_for, argv)), None)
+            }
+            command => (command, args),
+        };
+
+        // Only the job's own container runs privileged, not the clone
+        let job_security_context = if spec.privileged {
+            Some(K8sSecurityContext {
+                privileged: Some(true),
+                ..security_context.unwrap_or_default()
+            })
+        } else {
+            security_context
+        };
+
         // Build the main container
         let container = Container {
             name: "job".to_string(),
             image: Some(spec.image.clone()),
-            command: if spec.command.is_empty() {
-                None
-            } else {
-                Some(spec.command.clone())
-            },
+            command,
+            args,
             working_dir,
             env: if env_vars.is_empty() {
                 None
@@ -239,13 +398,22 @@ impl KubernetesExecutor {
             },
             resources,
             volume_mounts: container_volume_mounts,
-            image_pull_policy: Some("IfNotPresent".to_string()),
+            image_pull_policy: Some(
+                match spec.pull_policy.unwrap_or(PullPolicy::IfNotPresent) {
+                    PullPolicy::Always => "Always",
+                    PullPolicy::IfNotPresent => "IfNotPresent",
+                    PullPolicy::Never => "Never",
+                }
+                .to_string(),
+            ),
+            security_context: job_security_context,
             ..Default::default()
         };
 
         // Build labels for the job and pod
         let mut job_labels = self.labels.clone();
         job_labels.insert("buildit.io/job-id".to_string(), spec.id.to_string());
+        let (node_selector, tolerations) = node_placement(&spec.platform);
 
         // Build the Job
         Job {
@@ -268,6 +436,8 @@ impl KubernetesExecutor {
                         containers: vec![container],
                         volumes,
                         restart_policy: Some("Never".to_string()),
+                        node_selector: Some(node_selector),
+                        tolerations,
                         ..Default::default()
                     }),
                 },
@@ -285,7 +455,10 @@ impl KubernetesExecutor {
         let pods = pods_api
             .list(&kube::api::ListParams::default().labels(&label_selector))
             .await
-            .map_err(|e| Error::Internal(format!("Failed to list pods: {}", e)))?;
+            .map_err(|e| {
+                record_error("kubernetes", "list_pods");
+                Error::Internal(format!("Failed to list pods: {}", e))
+            })?;
 
         Ok(pods.items.first().and_then(|p| p.metadata.name.clone()))
     }
@@ -296,7 +469,8 @@ impl KubernetesExecutor {
 
         loop {
             if start.elapsed() > timeout {
-                return Err(Error::ExecutionFailed(
+                return Err(execution_failed(
+                    "logs",
                     "Timeout waiting for pod to be created".to_string(),
                 ));
             }
@@ -327,6 +501,18 @@ impl Executor for KubernetesExecutor {
         }
     }
 
+    async fn platforms(&self) -> Vec<Platform> {
+        self.platforms.clone()
+    }
+
+    async fn health(&self) -> Result<()> {
+        self.client
+            .apiserver_version()
+            .await
+            .map(|_| ())
+            .map_err(|e| execution_failed("health", format!("Kubernetes API unreachable: {}", e)))
+    }
+
     async fn spawn(&self, spec: JobSpec) -> Result<JobHandle> {
         let jobs_api = self.jobs_api();
         let job_name = Self::job_name(&spec.id);
@@ -345,7 +531,7 @@ impl Executor for KubernetesExecutor {
         let created = jobs_api
             .create(&PostParams::default(), &k8s_job)
             .await
-            .map_err(|e| Error::ExecutionFailed(format!("Failed to create K8s Job: {}", e)))?;
+            .map_err(|e| execution_failed("spawn", format!("Failed to create K8s Job: {}", e)))?;
 
         let uid = created
             .metadata
@@ -480,6 +666,7 @@ impl Executor for KubernetesExecutor {
                             debug!(error = %e, "Log polling ended - pod completed");
                             break;
                         } else {
+                            record_error("kubernetes", "logs");
                             consecutive_errors += 1;
                             if consecutive_errors >= 10 {
                                 debug!(error = %e, "Log polling ended after errors");
@@ -583,12 +770,13 @@ impl Executor for KubernetesExecutor {
                     }
                 }
                 Ok(WatcherEvent::Delete(_)) => {
-                    return Err(Error::ExecutionFailed("Job was deleted".to_string()));
+                    return Err(execution_failed("wait", "Job was deleted".to_string()));
                 }
                 Ok(WatcherEvent::Init | WatcherEvent::InitApply(_) | WatcherEvent::InitDone) => {
                     // Initial state events, ignore
                 }
                 Err(e) => {
+                    record_error("kubernetes", "wait");
                     warn!(error = %e, "Watcher error, retrying");
                     // Continue watching despite errors
                 }
@@ -622,6 +810,32 @@ impl Executor for KubernetesExecutor {
         })
     }
 
+    async fn usage(&self, handle: &JobHandle) -> Result<Option<ResourceUsage>> {
+        let Some(pod_name) = self.find_job_pod(&handle.id).await? else {
+            return Ok(None);
+        };
+
+        // Pod metrics come from metrics-server, which has none for a pod
+        // until its first scrape and may not be installed at all
+        let metrics_api: Api<DynamicObject> = Api::namespaced_with(
+            self.client.clone(),
+            &self.namespace,
+            &pod_metrics_resource(),
+        );
+        let metrics = match metrics_api.get(&pod_name).await {
+            Ok(metrics) => metrics,
+            Err(kube::Error::Api(e)) if e.code == 404 => return Ok(None),
+            Err(e) => {
+                return Err(execution_failed(
+                    "usage",
+                    format!("Failed to get pod metrics: {}", e),
+                ));
+            }
+        };
+
+        Ok(Some(pod_metrics_usage(&metrics.data)))
+    }
+
     async fn cancel(&self, handle: &JobHandle) -> Result<()> {
         let jobs_api = self.jobs_api();
         let job_name = Self::job_name(&handle.id);
@@ -637,7 +851,7 @@ impl Executor for KubernetesExecutor {
         jobs_api
             .delete(&job_name, &delete_params)
             .await
-            .map_err(|e| Error::ExecutionFailed(format!("Failed to delete job: {}", e)))?;
+            .map_err(|e| execution_failed("cancel", format!("Failed to delete job: {}", e)))?;
 
         info!(job_name = %job_name, "Kubernetes Job cancelled");
 
@@ -663,6 +877,62 @@ impl Executor for KubernetesExecutor {
     }
 }
 
+/// The metrics.k8s.io `PodMetrics` resource served by metrics-server.
+fn pod_metrics_resource() -> ApiResource {
+    ApiResource {
+        group: "metrics.k8s.io".to_string(),
+        version: "v1beta1".to_string(),
+        api_version: "metrics.k8s.io/v1beta1".to_string(),
+        kind: "PodMetrics".to_string(),
+        plural: "pods".to_string(),
+    }
+}
+
+/// What a pod uses now, summed over its containers.
+fn pod_metrics_usage(metrics: &serde_json::Value) -> ResourceUsage {
+    let containers = metrics["containers"].as_array().into_iter().flatten();
+    containers.fold(ResourceUsage::default(), |total, container| {
+        let usage = &container["usage"];
+        ResourceUsage {
+            cpu_millis: total.cpu_millis + usage["cpu"].as_str().and_then(cpu_millis).unwrap_or(0),
+            memory_bytes: total.memory_bytes
+                + usage["memory"].as_str().and_then(memory_bytes).unwrap_or(0),
+        }
+    })
+}
+
+/// Millicores in a CPU quantity such as `250m`, `1` or `1234567n`.
+fn cpu_millis(quantity: &str) -> Option<u64> {
+    let (number, per_core) = match quantity.as_bytes().last()? {
+        b'n' => (&quantity[..quantity.len() - 1], 1e9),
+        b'u' => (&quantity[..quantity.len() - 1], 1e6),
+        b'm' => (&quantity[..quantity.len() - 1], 1e3),
+        _ => (quantity, 1.0),
+    };
+    let value: f64 = number.parse().ok()?;
+    (value >= 0.0).then(|| (value * 1000.0 / per_core).round() as u64)
+}
+
+/// Bytes in a memory quantity such as `128Mi`, `1G` or `4096`.
+fn memory_bytes(quantity: &str) -> Option<u64> {
+    const SUFFIXES: [(&str, f64); 8] = [
+        ("Ki", 1024.0),
+        ("Mi", 1024.0 * 1024.0),
+        ("Gi", 1024.0 * 1024.0 * 1024.0),
+        ("Ti", 1024.0 * 1024.0 * 1024.0 * 1024.0),
+        ("k", 1e3),
+        ("M", 1e6),
+        ("G", 1e9),
+        ("T", 1e12),
+    ];
+    let (number, scale) = SUFFIXES
+        .iter()
+        .find_map(|(suffix, scale)| quantity.strip_suffix(suffix).map(|n| (n, *scale)))
+        .unwrap_or((quantity, 1.0));
+    let value: f64 = number.parse().ok()?;
+    (value >= 0.0).then(|| (value * scale).round() as u64)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -674,6 +944,8 @@ mod tests {
             id: ResourceId::new(),
             image: "alpine:latest".to_string(),
             command: vec!["echo".to_string(), "hello".to_string()],
+            entrypoint: None,
+            user: None,
             working_dir: Some("/workspace".to_string()),
             env: {
                 let mut env = HashMap::new();
@@ -689,6 +961,16 @@ mod tests {
             },
             timeout: None,
             volumes: vec![],
+            git_clone: None,
+            platform: Default::default(),
+            wait_for: vec![],
+            security_context: None,
+            privileged: false,
+            extra_hosts: vec![],
+            network_mode: None,
+            workspace: None,
+            tenant_id: None,
+            pull_policy: None,
         }
     }
 
@@ -705,6 +987,58 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_node_placement_follows_platform() {
+        let (selector, tolerations) = node_placement(&Platform::default());
+        assert_eq!(selector.len(), 1);
+        assert_eq!(selector["kubernetes.io/os"], "linux");
+        assert!(tolerations.is_none());
+
+        let (selector, tolerations) = node_placement(&Platform::new(Os::Windows, Arch::Amd64));
+        assert_eq!(selector["kubernetes.io/os"], "windows");
+        assert_eq!(selector["kubernetes.io/arch"], "amd64");
+        let tolerations = tolerations.unwrap();
+        assert_eq!(tolerations[0].key.as_deref(), Some("node.kubernetes.io/os"));
+        assert_eq!(tolerations[0].value.as_deref(), Some("windows"));
+        assert_eq!(tolerations[0].effect.as_deref(), Some("NoSchedule"));
+    }
+
+    #[test]
+    fn test_container_security_context() {
+        assert!(container_security_context(&SecurityContext::default()).is_none());
+
+        let executor_default = default_security_context().merged(&SecurityContext {
+            run_as_non_root: Some(true),
+            drop_capabilities: vec!["ALL".to_string()],
+            ..Default::default()
+        });
+        // A stage's override wins where it is set and adds to the drops
+        let context = executor_default.merged(&SecurityContext {
+            run_as_user: Some(1000),
+            drop_capabilities: vec!["ALL".to_string(), "NET_RAW".to_string()],
+            add_capabilities: vec!["NET_BIND_SERVICE".to_string()],
+            seccomp_profile: Some("Localhost/profiles/build.json".to_string()),
+            ..Default::default()
+        });
+        let k8s = container_security_context(&context).unwrap();
+        assert_eq!(k8s.run_as_non_root, Some(true));
+        assert_eq!(k8s.run_as_user, Some(1000));
+        assert_eq!(k8s.read_only_root_filesystem, None);
+        let capabilities = k8s.capabilities.unwrap();
+        assert_eq!(capabilities.drop.unwrap(), ["ALL", "NET_RAW"]);
+        assert_eq!(capabilities.add.unwrap(), ["NET_BIND_SERVICE"]);
+        let seccomp = k8s.seccomp_profile.unwrap();
+        assert_eq!(seccomp.type_, "Localhost");
+        assert_eq!(
+            seccomp.localhost_profile.as_deref(),
+            Some("profiles/build.json")
+        );
+
+        let k8s = container_security_context(&default_security_context()).unwrap();
+        assert_eq!(k8s.seccomp_profile.unwrap().type_, "RuntimeDefault");
+        assert!(k8s.capabilities.is_none());
+    }
+
     #[test]
     fn test_job_name_is_deterministic() {
         let id = ResourceId::new();
@@ -740,11 +1074,23 @@ mod tests {
             id: ResourceId::new(),
             image: "alpine:latest".to_string(),
             command: vec![],
+            entrypoint: None,
+            user: None,
             working_dir: None,
             env: HashMap::new(),
             resources: ResourceRequirements::default(),
             timeout: None,
             volumes: vec![],
+            git_clone: None,
+            platform: Default::default(),
+            wait_for: vec![],
+            security_context: None,
+            privileged: false,
+            extra_hosts: vec![],
+            network_mode: None,
+            workspace: None,
+            tenant_id: None,
+            pull_policy: None,
         };
 
         assert!(spec.command.is_empty());
@@ -812,7 +1158,7 @@ mod tests {
     fn test_job_handle_creation() {
         let id = ResourceId::new();
         let handle = JobHandle {
-            id: id.clone(),
+            id,
             executor_id: "test-uid-12345".to_string(),
             executor_name: "kubernetes".to_string(),
         };
@@ -889,11 +1235,23 @@ mod integration_tests {
             id: ResourceId::new(),
             image: "alpine:latest".to_string(),
             command: vec!["echo".to_string(), "test".to_string()],
+            entrypoint: None,
+            user: None,
             working_dir: None,
             env: HashMap::new(),
             resources: ResourceRequirements::default(),
             timeout: None,
             volumes: vec![],
+            git_clone: None,
+            platform: Default::default(),
+            wait_for: vec![],
+            security_context: None,
+            privileged: false,
+            extra_hosts: vec![],
+            network_mode: None,
+            workspace: None,
+            tenant_id: None,
+            pull_policy: None,
         };
 
         let can_execute = executor.can_execute(&spec).await;
@@ -915,6 +1273,8 @@ mod integration_tests {
                 "-c".to_string(),
                 "echo 'Hello from K8s!'".to_string(),
             ],
+            entrypoint: None,
+            user: None,
             working_dir: None,
             env: {
                 let mut env = HashMap::new();
@@ -929,6 +1289,16 @@ mod integration_tests {
             },
             timeout: None,
             volumes: vec![],
+            git_clone: None,
+            platform: Default::default(),
+            wait_for: vec![],
+            security_context: None,
+            privileged: false,
+            extra_hosts: vec![],
+            network_mode: None,
+            workspace: None,
+            tenant_id: None,
+            pull_policy: None,
         };
 
         // Spawn the job
@@ -962,11 +1332,23 @@ mod integration_tests {
                 "-c".to_string(),
                 "exit 1".to_string(),
             ],
+            entrypoint: None,
+            user: None,
             working_dir: None,
             env: HashMap::new(),
             resources: ResourceRequirements::default(),
             timeout: None,
             volumes: vec![],
+            git_clone: None,
+            platform: Default::default(),
+            wait_for: vec![],
+            security_context: None,
+            privileged: false,
+            extra_hosts: vec![],
+            network_mode: None,
+            workspace: None,
+            tenant_id: None,
+            pull_policy: None,
         };
 
         let handle = executor.spawn(spec).await.expect("Should spawn job");
@@ -995,11 +1377,23 @@ mod integration_tests {
                 "-c".to_string(),
                 "sleep 300".to_string(), // Long running job
             ],
+            entrypoint: None,
+            user: None,
             working_dir: None,
             env: HashMap::new(),
             resources: ResourceRequirements::default(),
             timeout: None,
             volumes: vec![],
+            git_clone: None,
+            platform: Default::default(),
+            wait_for: vec![],
+            security_context: None,
+            privileged: false,
+            extra_hosts: vec![],
+            network_mode: None,
+            workspace: None,
+            tenant_id: None,
+            pull_policy: None,
         };
 
         let handle = executor.spawn(spec).await.expect("Should spawn job");
@@ -1029,11 +1423,23 @@ mod integration_tests {
                 "-c".to_string(),
                 "echo 'line1'; echo 'line2'; echo 'line3'".to_string(),
             ],
+            entrypoint: None,
+            user: None,
             working_dir: None,
             env: HashMap::new(),
             resources: ResourceRequirements::default(),
             timeout: None,
             volumes: vec![],
+            git_clone: None,
+            platform: Default::default(),
+            wait_for: vec![],
+            security_context: None,
+            privileged: false,
+            extra_hosts: vec![],
+            network_mode: None,
+            workspace: None,
+            tenant_id: None,
+            pull_policy: None,
         };
 
         let handle = executor.spawn(spec).await.expect("Should spawn job");
@@ -1061,4 +1467,38 @@ mod integration_tests {
         // Cleanup
         let _ = executor.cancel(&handle).await;
     }
+
+    #[test]
+    fn test_quantities() {
+        assert_eq!(cpu_millis("250m"), Some(250));
+        assert_eq!(cpu_millis("2"), Some(2000));
+        assert_eq!(cpu_millis("1500000n"), Some(2));
+        assert_eq!(cpu_millis("750000u"), Some(750));
+        assert_eq!(cpu_millis("lots"), None);
+        assert_eq!(memory_bytes("128Mi"), Some(128 * 1024 * 1024));
+        assert_eq!(memory_bytes("1G"), Some(1_000_000_000));
+        assert_eq!(memory_bytes("4096"), Some(4096));
+        assert_eq!(memory_bytes("2.5Ki"), Some(2560));
+    }
+
+    #[test]
+    fn test_pod_metrics_usage() {
+        let metrics = serde_json::json!({
+            "containers": [
+                {"name": "build", "usage": {"cpu": "250000000n", "memory": "1024Ki"}},
+                {"name": "sidecar", "usage": {"cpu": "5m", "memory": "1Mi"}}
+            ]
+        });
+        assert_eq!(
+            pod_metrics_usage(&metrics),
+            ResourceUsage {
+                cpu_millis: 255,
+                memory_bytes: 2 * 1024 * 1024,
+            }
+        );
+        assert_eq!(
+            pod_metrics_usage(&serde_json::json!({})),
+            ResourceUsage::default()
+        );
+    }
 }
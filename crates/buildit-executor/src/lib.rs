@@ -3,12 +3,26 @@
 //! Provides executor implementations for running CI jobs:
 //! - Kubernetes (production)
 //! - Local Docker (development)
+//! - Podman, through its Docker-compatible socket
+//! - SSH to a physical machine
+//! - Fault injection around either (`chaos` feature)
 
+#[cfg(feature = "chaos")]
+pub mod chaos;
 pub mod docker;
+mod git;
 pub mod kubernetes;
+mod metrics;
+pub mod podman;
+pub mod ssh;
+mod wait;
 
 pub use buildit_core::executor::{
     Executor, JobHandle, JobResult, JobSpec, JobStatus, LogLine, LogStream, TerminalSession,
 };
+#[cfg(feature = "chaos")]
+pub use chaos::{ChaosConfig, ChaosExecutor};
 pub use docker::LocalDockerExecutor;
 pub use kubernetes::KubernetesExecutor;
+pub use podman::PodmanExecutor;
+pub use ssh::{SshExecutor, SshTarget};
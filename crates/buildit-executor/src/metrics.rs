@@ -0,0 +1,14 @@
+//! Executor metrics, recorded with the `metrics` crate for whichever
+//! exporter the process installs.
+
+/// Count a failed call to an executor's backend in
+/// `buildit_executor_errors_total`, by executor and operation (`spawn`,
+/// `wait`, ...).
+pub(crate) fn record_error(executor: &'static str, operation: &'static str) {
+    metrics::counter!(
+        "buildit_executor_errors_total",
+        "executor" => executor,
+        "operation" => operation
+    )
+    .increment(1);
+}
@@ -0,0 +1,221 @@
+//! Podman executor.
+//!
+//! Talks to Podman's Docker-compatible API socket, so it shares the
+//! container handling of [`LocalDockerExecutor`] and needs no daemon running
+//! as root: with rootless Podman the socket belongs to the build user
+//! (`systemctl --user enable --now podman.socket`).
+//!
+//! Podman may refuse short image names it cannot resolve unambiguously, so
+//! names without a registry are qualified with `docker.io`, as Docker would
+//! resolve them.
+
+use async_trait::async_trait;
+use bollard::{API_DEFAULT_VERSION, Docker};
+use buildit_core::executor::*;
+use buildit_core::{Error, Result};
+use futures::stream::BoxStream;
+use std::path::Path;
+
+use crate::docker::LocalDockerExecutor;
+
+/// Socket of the system-wide (rootful) Podman service.
+const ROOTFUL_SOCKET: &str = "/run/podman/podman.sock";
+
+/// Seconds to wait for an API response from Podman.
+const API_TIMEOUT_SECS: u64 = 120;
+
+/// Git image for shared workspaces, fully qualified since Podman may not
+/// resolve short names.
+const GIT_IMAGE: &str = "docker.io/alpine/git:latest";
+
+/// Executor running jobs as Podman containers.
+pub struct PodmanExecutor {
+    inner: LocalDockerExecutor,
+    socket: String,
+}
+
+impl PodmanExecutor {
+    /// Connect to the Podman socket at `socket`, or find it: `CONTAINER_HOST`,
+    /// then the user's rootless socket, then the rootful one.
+    pub fn new(socket: Option<&str>) -> Result<Self> {
+        let socket = socket.map(str::to_string).unwrap_or_else(|| {
+            resolve_socket(
+                std::env::var("CONTAINER_HOST").ok().as_deref(),
+                std::env::var("XDG_RUNTIME_DIR").ok().as_deref(),
+            )
+        });
+        let docker = Docker::connect_with_unix(&socket, API_TIMEOUT_SECS, API_DEFAULT_VERSION)
+            .map_err(|e| Error::Internal(format!("Podman socket {}: {}", socket, e)))?;
+        Ok(Self {
+            inner: LocalDockerExecutor::with_client(docker).with_git_image(GIT_IMAGE),
+            socket,
+        })
+    }
+
+    /// Clone repositories into shared workspaces with `image`.
+    pub fn with_git_image(mut self, image: impl Into<String>) -> Self {
+        self.inner = self.inner.with_git_image(image);
+        self
+    }
+
+    /// Path of the socket this executor talks to.
+    pub fn socket(&self) -> &str {
+        &self.socket
+    }
+
+    fn handle(&self, handle: JobHandle) -> JobHandle {
+        JobHandle {
+            executor_name: self.name().to_string(),
+            ..handle
+        }
+    }
+}
+
+/// Pick the Podman socket to use.
+fn resolve_socket(container_host: Option<&str>, runtime_dir: Option<&str>) -> String {
+    if let Some(path) = container_host.and_then(|h| h.strip_prefix("unix://")) {
+        return path.to_string();
+    }
+    if let Some(dir) = runtime_dir {
+        let rootless = format!("{}/podman/podman.sock", dir.trim_end_matches('/'));
+        if Path::new(&rootless).exists() {
+            return rootless;
+        }
+    }
+    ROOTFUL_SOCKET.to_string()
+}
+
+/// Qualify a short image name with the registry Docker would pull it from.
+pub fn qualify_image(image: &str) -> String {
+    match image.split_once('/') {
+        Some((registry, _))
+            if registry.contains('.') || registry.contains(':') || registry == "localhost" =>
+        {
+            image.to_string()
+        }
+        Some(_) => format!("docker.io/{}", image),
+        None => format!("docker.io/library/{}", image),
+    }
+}
+
+#[async_trait]
+impl Executor for PodmanExecutor {
+    fn name(&self) -> &'static str {
+        "podman"
+    }
+
+    async fn can_execute(&self, spec: &JobSpec) -> bool {
+        self.inner.can_execute(spec).await
+    }
+
+    async fn platforms(&self) -> Vec<Platform> {
+        self.inner.platforms().await
+    }
+
+    async fn health(&self) -> Result<()> {
+        self.inner.health().await.map_err(|_| {
+            Error::ExecutionFailed(format!("Podman socket {} unreachable", self.socket))
+        })
+    }
+
+    async fn spawn(&self, mut spec: JobSpec) -> Result<JobHandle> {
+        spec.image = qualify_image(&spec.image);
+        let handle = self.inner.spawn(spec).await?;
+        Ok(self.handle(handle))
+    }
+
+    async fn logs(&self, handle: &JobHandle) -> Result<BoxStream<'static, LogLine>> {
+        self.inner.logs(handle).await
+    }
+
+    async fn status(&self, handle: &JobHandle) -> Result<JobStatus> {
+        self.inner.status(handle).await
+    }
+
+    async fn wait(&self, handle: &JobHandle) -> Result<JobResult> {
+        self.inner.wait(handle).await
+    }
+
+    async fn usage(&self, handle: &JobHandle) -> Result<Option<ResourceUsage>> {
+        self.inner.usage(handle).await
+    }
+
+    async fn cancel(&self, handle: &JobHandle) -> Result<()> {
+        self.inner.cancel(handle).await
+    }
+
+    async fn resolve_image(
+        &self,
+        image: &str,
+        pull_policy: Option<PullPolicy>,
+    ) -> Result<Option<String>> {
+        self.inner.resolve_image(image, pull_policy).await
+    }
+
+    async fn remove_workspace(&self, workspace: &str) -> Result<()> {
+        self.inner.remove_workspace(workspace).await
+    }
+
+    async fn exec_interactive(
+        &self,
+        handle: &JobHandle,
+        cmd: Vec<String>,
+    ) -> Result<TerminalSession> {
+        self.inner.exec_interactive(handle, cmd).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_qualify_image() {
+        assert_eq!(
+            qualify_image("alpine:3.20"),
+            "docker.io/library/alpine:3.20"
+        );
+        assert_eq!(qualify_image("rust"), "docker.io/library/rust");
+        assert_eq!(
+            qualify_image("hashicorp/terraform:1.9"),
+            "docker.io/hashicorp/terraform:1.9"
+        );
+        assert_eq!(
+            qualify_image("ghcr.io/acme/builder:latest"),
+            "ghcr.io/acme/builder:latest"
+        );
+        assert_eq!(qualify_image("localhost/builder"), "localhost/builder");
+        assert_eq!(
+            qualify_image("registry:5000/builder"),
+            "registry:5000/builder"
+        );
+    }
+
+    #[test]
+    fn test_resolve_socket() {
+        assert_eq!(
+            resolve_socket(Some("unix:///run/user/1000/podman/podman.sock"), None),
+            "/run/user/1000/podman/podman.sock"
+        );
+        // A runtime dir without a Podman socket falls back to the rootful one
+        let dir = std::env::temp_dir().join(format!("buildit-podman-{}", uuid::Uuid::new_v4()));
+        let dir_str = dir.to_string_lossy().to_string();
+        assert_eq!(resolve_socket(None, Some(&dir_str)), ROOTFUL_SOCKET);
+
+        std::fs::create_dir_all(dir.join("podman")).unwrap();
+        std::fs::write(dir.join("podman/podman.sock"), "").unwrap();
+        assert_eq!(
+            resolve_socket(None, Some(&dir_str)),
+            format!("{}/podman/podman.sock", dir_str)
+        );
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_podman_health() {
+        let executor = PodmanExecutor::new(None).expect("Podman socket should exist");
+        assert_eq!(executor.name(), "podman");
+        executor.health().await.expect("Podman should answer");
+    }
+}
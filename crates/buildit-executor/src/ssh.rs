@@ -0,0 +1,748 @@
+//! SSH executor for builds that need a physical machine.
+//!
+//! Each job runs as a shell script fed to `sh -s` on the target host over
+//! the system `ssh` client. The script works in its own directory under the
+//! target's work root, records its process group so the job can be
+//! signalled on cancel, and removes the directory when it exits. The
+//! job's `image` and resource limits do not apply and are ignored.
+//!
+//! A [local](SshTarget::local) target runs the same scripts on this machine
+//! without ssh, for runners that build on their own host (e.g. macOS).
+
+use async_trait::async_trait;
+use buildit_core::executor::*;
+use buildit_core::{Error, ResourceId, Result};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use futures::stream::{self, BoxStream};
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::git;
+use crate::wait;
+
+/// Seconds `ssh` may take to connect.
+const CONNECT_TIMEOUT_SECS: u32 = 10;
+
+/// The machine jobs run on.
+#[derive(Debug, Clone)]
+pub struct SshTarget {
+    pub host: String,
+    pub user: Option<String>,
+    pub port: Option<u16>,
+    /// Private key to authenticate with; the ssh agent/config otherwise.
+    pub identity_file: Option<String>,
+    /// Directory job workspaces are created in.
+    pub work_root: String,
+    /// Run jobs on this machine instead of over ssh.
+    pub local: bool,
+    /// Platforms of the target; asked from the target when unset.
+    pub platforms: Option<Vec<Platform>>,
+}
+
+impl SshTarget {
+    pub fn new(host: impl Into<String>) -> Self {
+        Self {
+            host: host.into(),
+            user: None,
+            port: None,
+            identity_file: None,
+            work_root: "/tmp/buildit".to_string(),
+            local: false,
+            platforms: None,
+        }
+    }
+
+    /// This machine, with job workspaces under `work_root`.
+    pub fn local(work_root: impl Into<String>) -> Self {
+        Self {
+            work_root: work_root.into(),
+            local: true,
+            platforms: Some(vec![Platform::host()]),
+            ..Self::new("localhost")
+        }
+    }
+
+    /// Command that runs `command` on the target.
+    fn command(&self, command: &str) -> Command {
+        if self.local {
+            let mut local = Command::new("sh");
+            local.args(["-c", command]);
+            local
+        } else {
+            let mut ssh = Command::new("ssh");
+            ssh.args(self.ssh_args(command));
+            ssh
+        }
+    }
+
+    /// Command that runs a job script read from stdin. The shell leads its
+    /// own process group, as under sshd, so the job can be signalled.
+    fn script_command(&self) -> Command {
+        if self.local {
+            let mut local = Command::new("sh");
+            local.arg("-s").process_group(0);
+            local
+        } else {
+            self.command("sh -s")
+        }
+    }
+
+    /// `ssh` arguments that run `remote_command` on the target.
+    fn ssh_args(&self, remote_command: &str) -> Vec<String> {
+        let mut args = vec![
+            "-o".to_string(),
+            "BatchMode=yes".to_string(),
+            "-o".to_string(),
+            format!("ConnectTimeout={}", CONNECT_TIMEOUT_SECS),
+        ];
+        if let Some(port) = self.port {
+            args.extend(["-p".to_string(), port.to_string()]);
+        }
+        if let Some(identity) = &self.identity_file {
+            args.extend(["-i".to_string(), identity.clone()]);
+        }
+        args.push(match &self.user {
+            Some(user) => format!("{}@{}", user, self.host),
+            None => self.host.clone(),
+        });
+        args.push("--".to_string());
+        args.push(remote_command.to_string());
+        args
+    }
+
+    fn workdir(&self, job_id: &ResourceId) -> String {
+        format!(
+            "{}/buildit-job-{}",
+            self.work_root.trim_end_matches('/'),
+            job_id
+        )
+    }
+
+    /// Directory repository mirrors are kept in.
+    fn git_cache_dir(&self) -> String {
+        format!("{}/git-cache", self.work_root.trim_end_matches('/'))
+    }
+
+    /// Run a short command on the target.
+    async fn run_remote(&self, command: &str) -> Result<()> {
+        self.output(command).await.map(|_| ())
+    }
+
+    /// Run a short command on the target, returning its output.
+    async fn output(&self, command: &str) -> Result<String> {
+        let output = self
+            .command(command)
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .map_err(|e| Error::ExecutionFailed(format!("Failed to run ssh: {}", e)))?;
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        } else {
+            Err(Error::ExecutionFailed(format!(
+                "ssh {} failed: {}",
+                self.host,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )))
+        }
+    }
+
+    /// Send SIGTERM to the process group of the job in `workdir`. The job
+    /// script then exits and removes the workspace.
+    async fn signal(&self, workdir: &str) -> Result<()> {
+        let pid_file = shell_quote(&format!("{}.pid", workdir));
+        self.run_remote(&format!(
+            // No `--`: dash's kill takes it for a PID
+            "test -f {pid} && kill -TERM -$(cat {pid})",
+            pid = pid_file
+        ))
+        .await
+    }
+}
+
+/// State of a job started by this executor.
+struct SshJob {
+    workdir: String,
+    lines: Mutex<Vec<LogLine>>,
+    /// Number of log lines so far, and whether the output has ended.
+    output: watch::Sender<(usize, bool)>,
+    status: watch::Sender<JobStatus>,
+    cancelled: Mutex<bool>,
+}
+
+impl SshJob {
+    fn push_line(&self, line: LogLine) {
+        let count = {
+            let mut lines = self.lines.lock().unwrap();
+            lines.push(line);
+            lines.len()
+        };
+        self.output.send_modify(|(n, _)| *n = count);
+    }
+}
+
+/// Executor that runs jobs on a remote machine over SSH.
+pub struct SshExecutor {
+    target: SshTarget,
+    jobs: Arc<Mutex<HashMap<ResourceId, Arc<SshJob>>>>,
+    /// Platform reported by the target, once asked.
+    platform: tokio::sync::OnceCell<Platform>,
+}
+
+impl SshExecutor {
+    pub fn new(target: SshTarget) -> Self {
+        Self {
+            target,
+            jobs: Arc::new(Mutex::new(HashMap::new())),
+            platform: tokio::sync::OnceCell::new(),
+        }
+    }
+
+    fn job(&self, handle: &JobHandle) -> Result<Arc<SshJob>> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .get(&handle.id)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("SSH job {} not found", handle.id)))
+    }
+}
+
+/// Platform from `uname -s -m` output, e.g. `Darwin arm64`.
+fn uname_platform(uname: &str) -> Option<Platform> {
+    let mut fields = uname.split_whitespace();
+    let os = fields.next()?.parse().ok()?;
+    let arch = fields.next().and_then(|arch| arch.parse().ok());
+    Some(Platform { os, arch })
+}
+
+/// Quote a string for a POSIX shell.
+pub(crate) fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+fn is_env_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The script that runs a job in `workdir`, with repository mirrors kept
+/// in `git_cache`.
+///
+/// sshd starts the script as a session leader, so its PID, written to
+/// `<workdir>.pid`, is also the process group of everything the job starts;
+/// cancelling signals that group. The `EXIT` trap removes the workspace
+/// however the script ends, and the script exits with the job's status.
+pub(crate) fn job_script(spec: &JobSpec, workdir: &str, git_cache: &str) -> String {
+    let mut job = Vec::new();
+    if let Some(git_clone) = &spec.git_clone {
+        job.push(git::clone_script(git_clone, ".", Some(git_cache)));
+    }
+    // Container paths such as /workspace mean the workspace itself here
+    if let Some(dir) = spec.working_dir.as_deref().filter(|d| !d.starts_with('/')) {
+        job.push(format!("cd {}", shell_quote(dir)));
+    }
+    if !spec.wait_for.is_empty() {
+        job.push(wait::wait_script(&spec.wait_for));
+    }
+    if !spec.command.is_empty() {
+        let argv: Vec<String> = spec.command.iter().map(|a| shell_quote(a)).collect();
+        job.push(argv.join(" "));
+    }
+    let job = if job.is_empty() {
+        "true".to_string()
+    } else {
+        job.join(" && ")
+    };
+
+    let mut env: Vec<(&String, &String)> = spec.env.iter().collect();
+    env.sort();
+    let exports: Vec<String> = env
+        .into_iter()
+        .filter(|(name, _)| is_env_name(name))
+        .map(|(name, value)| format!("export {}={}\n", name, shell_quote(value)))
+        .collect();
+
+    let workdir = shell_quote(workdir);
+    format!(
+        "workdir={workdir}\n\
+         mkdir -p \"$workdir\" && cd \"$workdir\" || exit 1\n\
+         trap 'cd /; rm -rf \"$workdir\" \"$workdir.pid\"' EXIT\n\
+         trap 'exit 143' TERM\n\
+         echo $$ > \"$workdir.pid\"\n\
+         {exports}\
+         sh -c {job} &\n\
+         wait $!\n\
+         exit $?\n",
+        workdir = workdir,
+        exports = exports.concat(),
+        job = shell_quote(&job),
+    )
+}
+
+/// Forward lines of `reader` to the job's log.
+async fn read_lines<R: AsyncRead + Unpin>(reader: R, stream: LogStream, job: Arc<SshJob>) {
+    let mut lines = BufReader::new(reader).lines();
+    loop {
+        match lines.next_line().await {
+            Ok(Some(content)) => job.push_line(LogLine {
+                timestamp: Utc::now(),
+                stream,
+                content,
+            }),
+            Ok(None) => break,
+            Err(e) => {
+                warn!(error = %e, "SSH output error");
+                break;
+            }
+        }
+    }
+}
+
+fn finished_status(
+    started_at: DateTime<Utc>,
+    exit_code: Option<i32>,
+    cancelled: bool,
+    timed_out: bool,
+) -> JobStatus {
+    let finished_at = Utc::now();
+    if cancelled {
+        return JobStatus::Cancelled {
+            started_at: Some(started_at),
+            cancelled_at: finished_at,
+        };
+    }
+    match exit_code {
+        Some(0) if !timed_out => JobStatus::Succeeded {
+            started_at,
+            finished_at,
+        },
+        _ => JobStatus::Failed {
+            started_at: Some(started_at),
+            finished_at,
+            exit_code,
+            message: if timed_out {
+                "Job timed out".to_string()
+            } else {
+                match exit_code {
+                    // ssh itself exits with 255 when it cannot connect
+                    Some(255) => "SSH connection failed".to_string(),
+                    Some(code) => format!("Exit code {}", code),
+                    None => "Job was killed".to_string(),
+                }
+            },
+        },
+    }
+}
+
+#[async_trait]
+impl Executor for SshExecutor {
+    fn name(&self) -> &'static str {
+        "ssh"
+    }
+
+    async fn can_execute(&self, _spec: &JobSpec) -> bool {
+        true
+    }
+
+    /// The configured platforms, or the one `uname` reports on the target.
+    /// A target that cannot be reached yet is taken to be Linux; its health
+    /// check keeps jobs off it.
+    async fn platforms(&self) -> Vec<Platform> {
+        if let Some(platforms) = &self.target.platforms {
+            return platforms.clone();
+        }
+        let platform = self
+            .platform
+            .get_or_try_init(|| async {
+                let uname = self.target.output("uname -s -m").await?;
+                uname_platform(&uname).ok_or_else(|| {
+                    Error::ExecutionFailed(format!("Unknown platform: {}", uname.trim()))
+                })
+            })
+            .await;
+        match platform {
+            Ok(platform) => vec![*platform],
+            Err(e) => {
+                warn!(host = %self.target.host, error = %e, "Failed to detect SSH target platform");
+                vec![Platform::default()]
+            }
+        }
+    }
+
+    async fn health(&self) -> Result<()> {
+        self.target.run_remote("true").await
+    }
+
+    async fn spawn(&self, spec: JobSpec) -> Result<JobHandle> {
+        let workdir = self.target.workdir(&spec.id);
+        let script = job_script(&spec, &workdir, &self.target.git_cache_dir());
+
+        info!(host = %self.target.host, workdir = %workdir, "Starting SSH job");
+        let mut child = self
+            .target
+            .script_command()
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| Error::ExecutionFailed(format!("Failed to run ssh: {}", e)))?;
+
+        // The script goes in on stdin, so it needs no quoting for ssh
+        let mut stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| Error::Internal("ssh has no stdin".to_string()))?;
+        stdin
+            .write_all(script.as_bytes())
+            .await
+            .map_err(|e| Error::ExecutionFailed(format!("Failed to send job script: {}", e)))?;
+        drop(stdin);
+
+        let started_at = Utc::now();
+        let job = Arc::new(SshJob {
+            workdir: workdir.clone(),
+            lines: Mutex::new(Vec::new()),
+            output: watch::channel((0, false)).0,
+            status: watch::channel(JobStatus::Running { started_at }).0,
+            cancelled: Mutex::new(false),
+        });
+        self.jobs.lock().unwrap().insert(spec.id, job.clone());
+
+        let stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+        let timeout = spec.timeout;
+        let target = self.target.clone();
+        tokio::spawn(async move {
+            let readers = futures::future::join(
+                async {
+                    if let Some(stdout) = stdout {
+                        read_lines(stdout, LogStream::Stdout, job.clone()).await;
+                    }
+                },
+                async {
+                    if let Some(stderr) = stderr {
+                        read_lines(stderr, LogStream::Stderr, job.clone()).await;
+                    }
+                },
+            );
+
+            let run = async {
+                readers.await;
+                child.wait().await
+            };
+            let (exit, timed_out) = match timeout {
+                Some(limit) => match tokio::time::timeout(limit, run).await {
+                    Ok(exit) => (exit, false),
+                    Err(_) => {
+                        warn!(workdir = %job.workdir, "SSH job timed out, stopping it");
+                        let _ = target.signal(&job.workdir).await;
+                        // The job is gone from the target; give up on ssh too
+                        (Err(std::io::Error::other("timed out")), true)
+                    }
+                },
+                None => (run.await, false),
+            };
+
+            let exit_code = exit.ok().and_then(|status| status.code());
+            let cancelled = *job.cancelled.lock().unwrap();
+            job.output.send_modify(|(_, closed)| *closed = true);
+            job.status
+                .send_replace(finished_status(started_at, exit_code, cancelled, timed_out));
+        });
+
+        Ok(JobHandle {
+            id: spec.id,
+            executor_id: format!("{}:{}", self.target.host, workdir),
+            executor_name: self.name().to_string(),
+        })
+    }
+
+    async fn logs(&self, handle: &JobHandle) -> Result<BoxStream<'static, LogLine>> {
+        let job = self.job(handle)?;
+        let output = job.output.subscribe();
+
+        // Replay the lines so far, then follow new ones until the output ends
+        let lines = stream::unfold((0usize, output), move |(next, mut output)| {
+            let job = job.clone();
+            async move {
+                loop {
+                    let (count, closed) = *output.borrow_and_update();
+                    if next < count {
+                        let lines: Vec<LogLine> = job.lines.lock().unwrap()[next..count].to_vec();
+                        return Some((stream::iter(lines), (count, output)));
+                    }
+                    if closed || output.changed().await.is_err() {
+                        return None;
+                    }
+                }
+            }
+        });
+
+        Ok(lines.flatten().boxed())
+    }
+
+    async fn status(&self, handle: &JobHandle) -> Result<JobStatus> {
+        Ok(self.job(handle)?.status.borrow().clone())
+    }
+
+    async fn wait(&self, handle: &JobHandle) -> Result<JobResult> {
+        let job = self.job(handle)?;
+        let mut status = job.status.subscribe();
+        let status = status
+            .wait_for(JobStatus::is_terminal)
+            .await
+            .map_err(|_| Error::Internal("SSH job monitor stopped".to_string()))?
+            .clone();
+        self.jobs.lock().unwrap().remove(&handle.id);
+
+        let exit_code = match &status {
+            JobStatus::Succeeded { .. } => Some(0),
+            JobStatus::Failed { exit_code, .. } => *exit_code,
+            _ => None,
+        };
+        Ok(JobResult {
+            status,
+            exit_code,
+            artifacts: vec![],
+        })
+    }
+
+    async fn cancel(&self, handle: &JobHandle) -> Result<()> {
+        let job = self.job(handle)?;
+        *job.cancelled.lock().unwrap() = true;
+        info!(host = %self.target.host, workdir = %job.workdir, "Cancelling SSH job");
+        self.target.signal(&job.workdir).await
+    }
+
+    async fn exec_interactive(
+        &self,
+        _handle: &JobHandle,
+        _cmd: Vec<String>,
+    ) -> Result<TerminalSession> {
+        Err(Error::Internal(
+            "Interactive sessions are not supported by the SSH executor".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use buildit_core::executor::{Probe, ResourceRequirements, WaitCondition};
+
+    fn make_spec(command: &[&str]) -> JobSpec {
+        JobSpec {
+            id: ResourceId::new(),
+            image: "ignored".to_string(),
+            command: command.iter().map(|s| s.to_string()).collect(),
+            entrypoint: None,
+            user: None,
+            working_dir: None,
+            env: HashMap::from([
+                ("GREETING".to_string(), "it's here".to_string()),
+                ("not valid".to_string(), "x".to_string()),
+            ]),
+            resources: ResourceRequirements::default(),
+            timeout: None,
+            volumes: vec![],
+            git_clone: None,
+            platform: Default::default(),
+            wait_for: vec![],
+            security_context: None,
+            privileged: false,
+            extra_hosts: vec![],
+            network_mode: None,
+            workspace: None,
+            tenant_id: None,
+            pull_policy: None,
+        }
+    }
+
+    /// Start a job script with the local shell in its own process group,
+    /// as sshd would on the target.
+    async fn start_script(script: &str) -> tokio::process::Child {
+        let mut child = Command::new("sh")
+            .arg("-s")
+            .process_group(0)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdin = child.stdin.take().unwrap();
+        stdin.write_all(script.as_bytes()).await.unwrap();
+        drop(stdin);
+        child
+    }
+
+    async fn run_script(script: &str) -> std::process::Output {
+        start_script(script).await.wait_with_output().await.unwrap()
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("plain"), "'plain'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    }
+
+    #[test]
+    fn test_ssh_args() {
+        let target = SshTarget {
+            user: Some("ci".to_string()),
+            port: Some(2222),
+            identity_file: Some("/keys/ci".to_string()),
+            ..SshTarget::new("lab-1")
+        };
+        let args = target.ssh_args("true");
+        assert!(args.windows(2).any(|w| w == ["-p", "2222"]));
+        assert!(args.windows(2).any(|w| w == ["-i", "/keys/ci"]));
+        assert_eq!(&args[args.len() - 3..], ["ci@lab-1", "--", "true"]);
+    }
+
+    #[tokio::test]
+    async fn test_job_script_runs_in_workspace_and_cleans_up() {
+        let root = std::env::temp_dir().join(format!("buildit-ssh-{}", ResourceId::new()));
+        let workdir = root.join("job").to_string_lossy().to_string();
+        let spec = make_spec(&["/bin/sh", "-c", "echo \"$GREETING\"; pwd; exit 3"]);
+
+        let output = run_script(&job_script(&spec, &workdir, "/nonexistent")).await;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+
+        assert_eq!(output.status.code(), Some(3));
+        assert!(stdout.contains("it's here"));
+        assert!(stdout.contains(&workdir));
+        assert!(!std::path::Path::new(&workdir).exists());
+        assert!(!std::path::Path::new(&format!("{}.pid", workdir)).exists());
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[tokio::test]
+    async fn test_job_script_succeeds() {
+        let root = std::env::temp_dir().join(format!("buildit-ssh-{}", ResourceId::new()));
+        let workdir = root.join("job").to_string_lossy().to_string();
+        let spec = make_spec(&["true"]);
+
+        let output = run_script(&job_script(&spec, &workdir, "/nonexistent")).await;
+        assert!(output.status.success());
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[tokio::test]
+    async fn test_job_script_waits_for_conditions() {
+        let root = std::env::temp_dir().join(format!("buildit-ssh-{}", ResourceId::new()));
+        let workdir = root.join("job").to_string_lossy().to_string();
+        let mut spec = make_spec(&["echo", "started"]);
+        spec.wait_for = vec![WaitCondition {
+            probe: Probe::Shell {
+                command: "[ \"$GREETING\" = \"it's here\" ]".to_string(),
+            },
+            timeout_secs: 5,
+        }];
+
+        let output = run_script(&job_script(&spec, &workdir, "/nonexistent")).await;
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).ends_with("started\n"));
+
+        spec.wait_for[0].probe = Probe::Shell {
+            command: "false".to_string(),
+        };
+        spec.wait_for[0].timeout_secs = 1;
+        let output = run_script(&job_script(&spec, &workdir, "/nonexistent")).await;
+        assert_eq!(output.status.code(), Some(1));
+        assert!(!String::from_utf8_lossy(&output.stdout).contains("started"));
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[tokio::test]
+    async fn test_job_script_stops_on_signal() {
+        let root = std::env::temp_dir().join(format!("buildit-ssh-{}", ResourceId::new()));
+        let workdir = root.join("job").to_string_lossy().to_string();
+        let spec = make_spec(&["/bin/sh", "-c", "sleep 30"]);
+
+        let child = start_script(&job_script(&spec, &workdir, "/nonexistent")).await;
+        let pid_file = format!("{}.pid", workdir);
+        let mut pid = String::new();
+        for _ in 0..50 {
+            pid = std::fs::read_to_string(&pid_file).unwrap_or_default();
+            if !pid.trim().is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        let kill = Command::new("kill")
+            .args(["-TERM", "--", &format!("-{}", pid.trim())])
+            .status()
+            .await
+            .unwrap();
+        assert!(kill.success());
+
+        let output =
+            tokio::time::timeout(std::time::Duration::from_secs(10), child.wait_with_output())
+                .await
+                .expect("job should stop")
+                .unwrap();
+        assert_eq!(output.status.code(), Some(143));
+        assert!(!std::path::Path::new(&workdir).exists());
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_uname_platform() {
+        assert_eq!(
+            uname_platform("Darwin arm64\n"),
+            Some(Platform::new(Os::Macos, Arch::Arm64))
+        );
+        assert_eq!(
+            uname_platform("Linux x86_64"),
+            Some(Platform::new(Os::Linux, Arch::Amd64))
+        );
+        assert_eq!(uname_platform("SunOS sun4v"), None);
+    }
+
+    #[tokio::test]
+    async fn test_local_target_runs_and_cancels_jobs() {
+        let root = std::env::temp_dir().join(format!("buildit-local-{}", ResourceId::new()));
+        let executor = SshExecutor::new(SshTarget::local(root.to_string_lossy()));
+        assert_eq!(executor.platforms().await, [Platform::host()]);
+
+        let handle = executor
+            .spawn(make_spec(&["/bin/sh", "-c", "echo \"$GREETING\""]))
+            .await
+            .unwrap();
+        let result = executor.wait(&handle).await.unwrap();
+        assert!(matches!(result.status, JobStatus::Succeeded { .. }));
+
+        let handle = executor
+            .spawn(make_spec(&["/bin/sh", "-c", "sleep 30"]))
+            .await
+            .unwrap();
+        let workdir = executor.job(&handle).unwrap().workdir.clone();
+        for _ in 0..50 {
+            let pid = std::fs::read_to_string(format!("{}.pid", workdir)).unwrap_or_default();
+            if !pid.trim().is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        executor.cancel(&handle).await.unwrap();
+        let result =
+            tokio::time::timeout(std::time::Duration::from_secs(10), executor.wait(&handle))
+                .await
+                .expect("job should stop")
+                .unwrap();
+        assert!(matches!(result.status, JobStatus::Cancelled { .. }));
+        assert!(!std::path::Path::new(&workdir).exists());
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
@@ -0,0 +1,157 @@
+//! The shell commands that wait for a job's conditions.
+//!
+//! Conditions are checked inside the job, where its services' hostnames
+//! resolve, so every executor runs the same script before the job's
+//! command. Each condition is probed once a second until it holds or its
+//! timeout passes, in which case the job fails before its command starts.
+//! Probes only rely on `sh` and `date`: TCP uses `nc`, or bash's
+//! `/dev/tcp` when the image has no `nc`, and HTTP uses `curl` or `wget`.
+
+use buildit_core::executor::{Probe, WaitCondition};
+
+use crate::ssh::shell_quote;
+
+/// A command succeeding once `probe` holds, printing nothing.
+fn probe_command(probe: &Probe) -> String {
+    match probe {
+        Probe::Tcp { host, port } => format!(
+            "{{ nc -z -w 2 {host} {port} || bash -c {dev_tcp}; }} >/dev/null 2>&1",
+            host = shell_quote(host),
+            port = port,
+            dev_tcp = shell_quote(&format!("exec 3<>/dev/tcp/{}/{}", host, port)),
+        ),
+        Probe::Http { url } => {
+            let url = shell_quote(url);
+            format!(
+                "if command -v curl >/dev/null 2>&1; \
+                 then [ \"$(curl -s -o /dev/null -w '%{{http_code}}' --max-time 5 {url})\" = 200 ]; \
+                 else wget -q -O /dev/null -T 5 {url} >/dev/null 2>&1; fi",
+                url = url,
+            )
+        }
+        Probe::Shell { command } => format!("sh -c {} >/dev/null 2>&1", shell_quote(command)),
+    }
+}
+
+/// A subshell waiting for `condition`, exiting 1 if it times out.
+fn condition_script(condition: &WaitCondition) -> String {
+    let timeout = condition.timeout_secs;
+    let waiting = shell_quote(&format!(
+        "Waiting up to {}s for {}",
+        timeout, condition.probe
+    ));
+    let timed_out = shell_quote(&format!(
+        "Timed out after {}s waiting for {}",
+        timeout, condition.probe
+    ));
+    let ready = shell_quote(&format!("Ready: {}", condition.probe));
+    format!(
+        "(echo {waiting}; deadline=$(($(date +%s) + {timeout})); \
+         until {probe}; do \
+         if [ \"$(date +%s)\" -ge \"$deadline\" ]; then echo {timed_out} >&2; exit 1; fi; \
+         sleep 1; done; echo {ready})",
+        waiting = waiting,
+        timeout = timeout,
+        probe = probe_command(&condition.probe),
+        timed_out = timed_out,
+        ready = ready,
+    )
+}
+
+/// A command waiting for each of `conditions` in turn, failing on the
+/// first that times out.
+pub(crate) fn wait_script(conditions: &[WaitCondition]) -> String {
+    conditions
+        .iter()
+        .map(condition_script)
+        .collect::<Vec<_>>()
+        .join(" && ")
+}
+
+/// `argv`, run once `conditions` hold.
+pub(crate) fn wrap(conditions: &[WaitCondition], argv: Vec<String>) -> Vec<String> {
+    if conditions.is_empty() {
+        return argv;
+    }
+    let mut wrapped = vec![
+        "sh".to_string(),
+        "-c".to_string(),
+        format!("{} && exec \"$@\"", wait_script(conditions)),
+        "sh".to_string(),
+    ];
+    wrapped.extend(argv);
+    wrapped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::process::{Command, Output};
+
+    fn shell(command: &str, timeout_secs: u32) -> WaitCondition {
+        WaitCondition {
+            probe: Probe::Shell {
+                command: command.to_string(),
+            },
+            timeout_secs,
+        }
+    }
+
+    fn run(argv: &[String]) -> Output {
+        Command::new(&argv[0]).args(&argv[1..]).output().unwrap()
+    }
+
+    #[test]
+    fn test_wrap_without_conditions() {
+        let argv = vec!["cargo".to_string(), "test".to_string()];
+        assert_eq!(wrap(&[], argv.clone()), argv);
+    }
+
+    #[test]
+    fn test_command_runs_once_conditions_hold() {
+        let dir = std::env::temp_dir().join(format!("buildit-wait-{}", std::process::id()));
+        let flag = dir.join("ready");
+        std::fs::create_dir_all(&dir).unwrap();
+        let _ = std::fs::remove_file(&flag);
+
+        // The probe only succeeds on its second try
+        let probe = format!(
+            "[ -e {flag} ] || {{ touch {flag}; exit 1; }}",
+            flag = shell_quote(flag.to_str().unwrap())
+        );
+        let argv = vec!["echo".to_string(), "it's running".to_string()];
+        let output = run(&wrap(&[shell("true", 5), shell(&probe, 5)], argv));
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{:?}", output);
+        assert!(stdout.contains("Ready: shell `true`"), "{}", stdout);
+        assert!(stdout.ends_with("it's running\n"), "{}", stdout);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_timeout_fails_before_the_command() {
+        let argv = vec!["echo".to_string(), "started".to_string()];
+        let output = run(&wrap(&[shell("false", 1)], argv));
+        assert!(!output.status.success());
+        assert!(!String::from_utf8_lossy(&output.stdout).contains("started"));
+        assert!(
+            String::from_utf8_lossy(&output.stderr)
+                .contains("Timed out after 1s waiting for shell `false`")
+        );
+    }
+
+    #[test]
+    fn test_tcp_probe() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let condition = WaitCondition {
+            probe: Probe::Tcp {
+                host: "127.0.0.1".to_string(),
+                port: listener.local_addr().unwrap().port(),
+            },
+            timeout_secs: 5,
+        };
+        let output = run(&wrap(&[condition], vec!["true".to_string()]));
+        assert!(output.status.success(), "{:?}", output);
+    }
+}
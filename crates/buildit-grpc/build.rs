@@ -0,0 +1,17 @@
+//! Build script to generate the gRPC messages and services from `proto/`.
+//!
+//! Uses the `protoc` bundled with `protoc-bin-vendored`, so building needs
+//! no protobuf installation.
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=proto/");
+
+    let mut config = prost_build::Config::new();
+    config.protoc_executable(protoc_bin_vendored::protoc_bin_path()?);
+    tonic_prost_build::configure().compile_with_config(
+        config,
+        &["proto/runner.proto"],
+        &["proto"],
+    )?;
+    Ok(())
+}
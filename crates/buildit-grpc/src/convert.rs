@@ -0,0 +1,241 @@
+//! Conversions between the gRPC messages and `buildit-core` types.
+
+use buildit_core::executor;
+use buildit_core::runner::{self as core, RunnerJobState};
+use chrono::{DateTime, Utc};
+use tonic::Status;
+
+use crate::runner::{JobLease, JobState, LogLine, LogStream, ReportAck, ReportStatusRequest};
+
+/// Parse an ID sent as a string field.
+pub fn parse_id(id: &str) -> Result<uuid::Uuid, Status> {
+    id.parse()
+        .map_err(|_| Status::invalid_argument(format!("Invalid ID: {}", id)))
+}
+
+impl TryFrom<&core::JobLease> for JobLease {
+    type Error = Status;
+
+    fn try_from(lease: &core::JobLease) -> Result<Self, Status> {
+        let spec_json = serde_json::to_vec(&lease.spec)
+            .map_err(|e| Status::internal(format!("Failed to encode job spec: {}", e)))?;
+        Ok(Self {
+            job_id: lease.job_id.to_string(),
+            spec_json,
+            lease_seconds: lease.lease_seconds,
+        })
+    }
+}
+
+impl TryFrom<JobLease> for core::JobLease {
+    type Error = Status;
+
+    fn try_from(lease: JobLease) -> Result<Self, Status> {
+        let spec = serde_json::from_slice(&lease.spec_json)
+            .map_err(|e| Status::invalid_argument(format!("Invalid job spec: {}", e)))?;
+        Ok(Self {
+            job_id: parse_id(&lease.job_id)?,
+            spec,
+            lease_seconds: lease.lease_seconds,
+        })
+    }
+}
+
+impl From<RunnerJobState> for JobState {
+    fn from(state: RunnerJobState) -> Self {
+        match state {
+            RunnerJobState::Running => JobState::Running,
+            RunnerJobState::Succeeded => JobState::Succeeded,
+            RunnerJobState::Failed => JobState::Failed,
+            RunnerJobState::Cancelled => JobState::Cancelled,
+        }
+    }
+}
+
+impl ReportStatusRequest {
+    pub fn new(job_id: uuid::Uuid, report: &core::JobStatusReport) -> Self {
+        let mut request = Self {
+            job_id: job_id.to_string(),
+            exit_code: report.exit_code,
+            error: report.error.clone(),
+            ..Default::default()
+        };
+        request.set_state(report.state.into());
+        request
+    }
+
+    /// The report, without the job ID.
+    pub fn report(&self) -> Result<core::JobStatusReport, Status> {
+        let state = match self.state() {
+            JobState::Running => RunnerJobState::Running,
+            JobState::Succeeded => RunnerJobState::Succeeded,
+            JobState::Failed => RunnerJobState::Failed,
+            JobState::Cancelled => RunnerJobState::Cancelled,
+            JobState::Unspecified => {
+                return Err(Status::invalid_argument("Job state is required"));
+            }
+        };
+        Ok(core::JobStatusReport {
+            state,
+            exit_code: self.exit_code,
+            error: self.error.clone(),
+        })
+    }
+}
+
+impl ReportAck {
+    pub fn new(job_id: uuid::Uuid, cancel_requested: bool) -> Self {
+        Self {
+            job_id: job_id.to_string(),
+            cancel_requested,
+        }
+    }
+}
+
+impl From<ReportAck> for core::JobReportAck {
+    fn from(ack: ReportAck) -> Self {
+        Self {
+            cancel_requested: ack.cancel_requested,
+        }
+    }
+}
+
+impl From<&executor::LogLine> for LogLine {
+    fn from(line: &executor::LogLine) -> Self {
+        let stream = match line.stream {
+            executor::LogStream::Stdout => LogStream::Stdout,
+            executor::LogStream::Stderr => LogStream::Stderr,
+            executor::LogStream::System => LogStream::System,
+        };
+        Self {
+            timestamp_micros: line.timestamp.timestamp_micros(),
+            stream: stream.into(),
+            content: line.content.clone(),
+        }
+    }
+}
+
+/// Lines without a stream count as stdout, and lines with a timestamp out
+/// of range as written now.
+impl From<LogLine> for executor::LogLine {
+    fn from(line: LogLine) -> Self {
+        let stream = match line.stream() {
+            LogStream::Stderr => executor::LogStream::Stderr,
+            LogStream::System => executor::LogStream::System,
+            LogStream::Stdout | LogStream::Unspecified => executor::LogStream::Stdout,
+        };
+        Self {
+            timestamp: DateTime::from_timestamp_micros(line.timestamp_micros)
+                .unwrap_or_else(Utc::now),
+            stream,
+            content: line.content,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use buildit_core::executor::{JobSpec, ResourceRequirements};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_job_lease_roundtrip() {
+        let lease = core::JobLease {
+            job_id: uuid::Uuid::new_v4(),
+            spec: JobSpec {
+                id: buildit_core::ResourceId::new(),
+                image: "rust:1.85".to_string(),
+                command: vec!["cargo".to_string(), "test".to_string()],
+                entrypoint: None,
+                user: None,
+                working_dir: None,
+                env: HashMap::from([("CI".to_string(), "true".to_string())]),
+                resources: ResourceRequirements::default(),
+                timeout: None,
+                volumes: vec![],
+                git_clone: None,
+                platform: Default::default(),
+                wait_for: vec![],
+                security_context: None,
+                privileged: false,
+                extra_hosts: vec![],
+                network_mode: None,
+                workspace: None,
+                tenant_id: None,
+                pull_policy: None,
+            },
+            lease_seconds: 60,
+        };
+        let message = JobLease::try_from(&lease).unwrap();
+        assert_eq!(message.job_id, lease.job_id.to_string());
+
+        let decoded = core::JobLease::try_from(message).unwrap();
+        assert_eq!(decoded.job_id, lease.job_id);
+        assert_eq!(decoded.spec.image, "rust:1.85");
+        assert_eq!(decoded.spec.command, ["cargo", "test"]);
+        assert_eq!(decoded.spec.env["CI"], "true");
+        assert_eq!(decoded.lease_seconds, 60);
+    }
+
+    #[test]
+    fn test_invalid_job_lease() {
+        let lease = JobLease {
+            job_id: "not-an-id".to_string(),
+            spec_json: b"{}".to_vec(),
+            lease_seconds: 60,
+        };
+        assert!(core::JobLease::try_from(lease).is_err());
+    }
+
+    #[test]
+    fn test_status_report_roundtrip() {
+        let job_id = uuid::Uuid::new_v4();
+        let request = ReportStatusRequest::new(
+            job_id,
+            &core::JobStatusReport {
+                state: RunnerJobState::Failed,
+                exit_code: Some(2),
+                error: Some("Exit code 2".to_string()),
+            },
+        );
+        assert_eq!(parse_id(&request.job_id).unwrap(), job_id);
+
+        let report = request.report().unwrap();
+        assert_eq!(report.state, RunnerJobState::Failed);
+        assert_eq!(report.exit_code, Some(2));
+        assert_eq!(report.error.as_deref(), Some("Exit code 2"));
+
+        let missing = ReportStatusRequest {
+            job_id: job_id.to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            missing.report().unwrap_err().code(),
+            tonic::Code::InvalidArgument
+        );
+    }
+
+    #[test]
+    fn test_log_line_roundtrip() {
+        let line = executor::LogLine {
+            timestamp: DateTime::from_timestamp_micros(1_700_000_000_123_456).unwrap(),
+            stream: executor::LogStream::Stderr,
+            content: "warning: unused variable".to_string(),
+        };
+        let decoded = executor::LogLine::from(LogLine::from(&line));
+        assert_eq!(decoded.timestamp, line.timestamp);
+        assert!(matches!(decoded.stream, executor::LogStream::Stderr));
+        assert_eq!(decoded.content, line.content);
+
+        let unspecified = LogLine {
+            timestamp_micros: 0,
+            stream: 0,
+            content: "hello".to_string(),
+        };
+        assert!(matches!(
+            executor::LogLine::from(unspecified).stream,
+            executor::LogStream::Stdout
+        ));
+    }
+}
@@ -0,0 +1,18 @@
+//! gRPC protocol of BuildIt.
+//!
+//! Messages and services generated from the definitions in `proto/`, and
+//! conversions between them and the `buildit-core` types the REST API
+//! uses, so both APIs share one model.
+
+mod convert;
+
+pub use convert::parse_id;
+
+pub mod runner {
+    //! `buildit.runner.v1`: job leasing and reporting for runners.
+
+    tonic::include_proto!("buildit.runner.v1");
+}
+
+/// Metadata key of the runner ID on every `Runner` call.
+pub const RUNNER_ID_METADATA: &str = "x-buildit-runner-id";
@@ -0,0 +1,325 @@
+//! Lease loop: take jobs from the server and run them with local Docker.
+
+use anyhow::Result;
+use buildit_core::executor::{Executor, JobResult, JobStatus, LogLine};
+use buildit_core::runner::{JobLease, JobStatusReport, RunnerJobState};
+use futures::StreamExt;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+use crate::client::{RunnerClient, RunnerRemoved};
+
+/// How long one lease request is held open by the server.
+const LEASE_WAIT: Duration = Duration::from_secs(30);
+
+/// Pause after a failed lease request.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// How often buffered log lines are sent.
+const LOG_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Report at least this often, to keep the lease.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Most log lines sent in one request.
+const MAX_LOG_BATCH: usize = 500;
+
+/// Prefix of the variables jobs read artifact links from.
+const ARTIFACT_URL_PREFIX: &str = "BUILDIT_ARTIFACT_URL_";
+
+pub struct Agent {
+    client: Arc<RunnerClient>,
+    executor: Arc<dyn Executor>,
+}
+
+impl Agent {
+    pub fn new(client: RunnerClient, executor: Arc<dyn Executor>) -> Self {
+        Self {
+            client: Arc::new(client),
+            executor,
+        }
+    }
+
+    /// Run `concurrency` lease loops until one fails for good.
+    pub async fn run(self, concurrency: usize) -> Result<()> {
+        let agent = Arc::new(self);
+        let mut workers = tokio::task::JoinSet::new();
+        for slot in 0..concurrency.max(1) {
+            let agent = agent.clone();
+            workers.spawn(async move { agent.lease_loop(slot).await });
+        }
+        match workers.join_next().await {
+            Some(result) => result?,
+            None => Ok(()),
+        }
+    }
+
+    async fn lease_loop(&self, slot: usize) -> Result<()> {
+        loop {
+            match self.client.lease(LEASE_WAIT).await {
+                Ok(Some(lease)) => {
+                    info!(slot, job = %lease.job_id, image = %lease.spec.image, "Leased job");
+                    self.run_job(lease).await;
+                }
+                Ok(None) => {}
+                Err(e) if e.is::<RunnerRemoved>() => return Err(e),
+                Err(e) => {
+                    warn!(slot, error = %e, "Lease request failed, retrying");
+                    tokio::time::sleep(RETRY_DELAY).await;
+                }
+            }
+        }
+    }
+
+    async fn run_job(&self, lease: JobLease) {
+        let job_id = lease.job_id;
+        let mut spec = lease.spec;
+        // Mounts name paths on the server, which do not exist here
+        spec.volumes.clear();
+        // The links may have expired while the job was queued
+        if spec
+            .env
+            .keys()
+            .any(|name| name.starts_with(ARTIFACT_URL_PREFIX))
+        {
+            match self.client.artifact_urls(job_id).await {
+                Ok(urls) => spec.env.extend(urls),
+                Err(e) => warn!(job = %job_id, error = %e, "Failed to refresh artifact links"),
+            }
+        }
+
+        let handle = match self.executor.spawn(spec).await {
+            Ok(handle) => handle,
+            Err(e) => {
+                error!(job = %job_id, error = %e, "Failed to start job");
+                self.report(
+                    job_id,
+                    JobStatusReport {
+                        state: RunnerJobState::Failed,
+                        exit_code: None,
+                        error: Some(format!("Failed to start job: {}", e)),
+                    },
+                )
+                .await;
+                return;
+            }
+        };
+
+        let mut cancelled = self
+            .report(
+                job_id,
+                JobStatusReport {
+                    state: RunnerJobState::Running,
+                    exit_code: None,
+                    error: None,
+                },
+            )
+            .await;
+
+        if !cancelled {
+            cancelled = self.stream_logs(job_id, &handle).await;
+        }
+        if cancelled {
+            info!(job = %job_id, "Job was cancelled, stopping it");
+            if let Err(e) = self.executor.cancel(&handle).await {
+                warn!(job = %job_id, error = %e, "Failed to stop job");
+            }
+        }
+
+        let report = match self.executor.wait(&handle).await {
+            Ok(result) => final_report(&result, cancelled),
+            Err(_) if cancelled => cancelled_report(),
+            Err(e) => JobStatusReport {
+                state: RunnerJobState::Failed,
+                exit_code: None,
+                error: Some(format!("Failed to wait for job: {}", e)),
+            },
+        };
+        info!(job = %job_id, state = report.state.as_str(), "Job finished");
+        self.report(job_id, report).await;
+    }
+
+    /// Forward the job's logs until it ends or is cancelled. Returns whether
+    /// it was cancelled.
+    async fn stream_logs(
+        &self,
+        job_id: uuid::Uuid,
+        handle: &buildit_core::executor::JobHandle,
+    ) -> bool {
+        let mut logs = match self.executor.logs(handle).await {
+            Ok(logs) => logs,
+            Err(e) => {
+                warn!(job = %job_id, error = %e, "Failed to follow logs");
+                futures::stream::empty().boxed()
+            }
+        };
+
+        let mut buffer: Vec<LogLine> = Vec::new();
+        let mut flush = tokio::time::interval(LOG_FLUSH_INTERVAL);
+        let mut last_report = tokio::time::Instant::now();
+        loop {
+            tokio::select! {
+                line = logs.next() => match line {
+                    Some(line) => {
+                        buffer.push(line);
+                        if buffer.len() < MAX_LOG_BATCH {
+                            continue;
+                        }
+                    }
+                    None => break,
+                },
+                _ = flush.tick() => {
+                    if buffer.is_empty() && last_report.elapsed() < HEARTBEAT_INTERVAL {
+                        continue;
+                    }
+                }
+            }
+
+            last_report = tokio::time::Instant::now();
+            let cancelled = if buffer.is_empty() {
+                self.report(
+                    job_id,
+                    JobStatusReport {
+                        state: RunnerJobState::Running,
+                        exit_code: None,
+                        error: None,
+                    },
+                )
+                .await
+            } else {
+                self.send_logs(job_id, std::mem::take(&mut buffer)).await
+            };
+            if cancelled {
+                return true;
+            }
+        }
+
+        self.send_logs(job_id, buffer).await
+    }
+
+    async fn send_logs(&self, job_id: uuid::Uuid, lines: Vec<LogLine>) -> bool {
+        if lines.is_empty() {
+            return false;
+        }
+        match self.client.send_logs(job_id, lines).await {
+            Ok(ack) => ack.cancel_requested,
+            Err(e) => {
+                warn!(job = %job_id, error = %e, "Failed to send logs");
+                false
+            }
+        }
+    }
+
+    /// Report the job's state. Returns whether it was cancelled.
+    async fn report(&self, job_id: uuid::Uuid, report: JobStatusReport) -> bool {
+        match self.client.report_status(job_id, &report).await {
+            Ok(ack) => ack.cancel_requested,
+            Err(e) => {
+                warn!(job = %job_id, error = %e, "Failed to report job status");
+                false
+            }
+        }
+    }
+}
+
+fn cancelled_report() -> JobStatusReport {
+    JobStatusReport {
+        state: RunnerJobState::Cancelled,
+        exit_code: None,
+        error: None,
+    }
+}
+
+/// The report for a job that ended with `result`.
+fn final_report(result: &JobResult, cancelled: bool) -> JobStatusReport {
+    if cancelled {
+        return cancelled_report();
+    }
+    match &result.status {
+        JobStatus::Succeeded { .. } => JobStatusReport {
+            state: RunnerJobState::Succeeded,
+            exit_code: result.exit_code.or(Some(0)),
+            error: None,
+        },
+        JobStatus::Failed {
+            exit_code, message, ..
+        } => JobStatusReport {
+            state: RunnerJobState::Failed,
+            exit_code: exit_code.or(result.exit_code),
+            error: Some(message.clone()),
+        },
+        JobStatus::Cancelled { .. } => cancelled_report(),
+        JobStatus::Pending | JobStatus::Running { .. } => JobStatusReport {
+            state: RunnerJobState::Failed,
+            exit_code: result.exit_code,
+            error: Some("Job ended in unexpected state".to_string()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn result(status: JobStatus, exit_code: Option<i32>) -> JobResult {
+        JobResult {
+            status,
+            exit_code,
+            artifacts: vec![],
+        }
+    }
+
+    #[test]
+    fn test_final_report_for_failed_job_keeps_exit_code() {
+        let report = final_report(
+            &result(
+                JobStatus::Failed {
+                    started_at: None,
+                    finished_at: Utc::now(),
+                    exit_code: Some(2),
+                    message: "Exit code 2".to_string(),
+                },
+                Some(2),
+            ),
+            false,
+        );
+        assert_eq!(report.state, RunnerJobState::Failed);
+        assert_eq!(report.exit_code, Some(2));
+        assert_eq!(report.error.as_deref(), Some("Exit code 2"));
+    }
+
+    #[test]
+    fn test_final_report_for_cancelled_job() {
+        let now = Utc::now();
+        // A job stopped on request usually exits non-zero; it still counts
+        // as cancelled
+        let report = final_report(
+            &result(
+                JobStatus::Failed {
+                    started_at: Some(now),
+                    finished_at: now,
+                    exit_code: Some(137),
+                    message: "Killed".to_string(),
+                },
+                Some(137),
+            ),
+            true,
+        );
+        assert_eq!(report.state, RunnerJobState::Cancelled);
+
+        let report = final_report(
+            &result(
+                JobStatus::Succeeded {
+                    started_at: now,
+                    finished_at: now,
+                },
+                Some(0),
+            ),
+            false,
+        );
+        assert_eq!(report.state, RunnerJobState::Succeeded);
+        assert_eq!(report.exit_code, Some(0));
+    }
+}
@@ -0,0 +1,181 @@
+//! HTTP client for the runner endpoints of the BuildIt API.
+//!
+//! With [`RunnerClient::with_grpc`], jobs are leased and reported over the
+//! gRPC service instead; registration always uses HTTP.
+
+use anyhow::{Context, Result, bail};
+use buildit_core::executor::LogLine;
+use buildit_core::runner::{
+    JobLease, JobLogBatch, JobReportAck, JobStatusReport, RunnerCredentials, RunnerRegistration,
+};
+use reqwest::StatusCode;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::grpc::GrpcClient;
+
+/// The server rejected the runner's token; the runner was removed.
+#[derive(Debug)]
+pub struct RunnerRemoved;
+
+impl std::fmt::Display for RunnerRemoved {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Runner token was rejected; the runner may have been removed"
+        )
+    }
+}
+
+impl std::error::Error for RunnerRemoved {}
+
+pub struct RunnerClient {
+    base_url: String,
+    http: reqwest::Client,
+    credentials: RunnerCredentials,
+    grpc: Option<GrpcClient>,
+}
+
+impl RunnerClient {
+    pub fn new(api_url: &str, credentials: RunnerCredentials) -> Self {
+        Self {
+            base_url: api_url.trim_end_matches('/').to_string(),
+            http: reqwest::Client::new(),
+            credentials,
+            grpc: None,
+        }
+    }
+
+    /// Lease and report jobs over gRPC.
+    pub fn with_grpc(mut self) -> Result<Self> {
+        self.grpc = Some(GrpcClient::new(&self.base_url, &self.credentials)?);
+        Ok(self)
+    }
+
+    /// Register a new runner using an API token.
+    pub async fn register(
+        api_url: &str,
+        api_token: &str,
+        registration: &RunnerRegistration,
+    ) -> Result<RunnerCredentials> {
+        let url = format!("{}/api/v1/runners", api_url.trim_end_matches('/'));
+        let response = reqwest::Client::new()
+            .post(&url)
+            .bearer_auth(api_token)
+            .json(registration)
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach API at {}", api_url))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            bail!("Runner registration failed ({}): {}", status, body);
+        }
+
+        response
+            .json()
+            .await
+            .with_context(|| format!("Invalid response from {}", url))
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!(
+            "{}/api/v1/runners/{}{}",
+            self.base_url, self.credentials.runner_id, path
+        )
+    }
+
+    /// Wait up to `wait` for a job. `None` if none came up.
+    pub async fn lease(&self, wait: Duration) -> Result<Option<JobLease>> {
+        if let Some(grpc) = &self.grpc {
+            return grpc.lease(wait).await;
+        }
+        let url = self.url("/lease");
+        let response = self
+            .http
+            .post(&url)
+            .bearer_auth(&self.credentials.token)
+            .query(&[("wait", wait.as_secs())])
+            // Leave the server time to answer the long poll
+            .timeout(wait + Duration::from_secs(30))
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach API at {}", self.base_url))?;
+
+        match response.status() {
+            StatusCode::NO_CONTENT => Ok(None),
+            StatusCode::UNAUTHORIZED => Err(RunnerRemoved.into()),
+            status if !status.is_success() => {
+                let body = response.text().await.unwrap_or_default();
+                bail!("Lease request failed ({}): {}", status, body);
+            }
+            _ => response
+                .json()
+                .await
+                .map(Some)
+                .with_context(|| format!("Invalid response from {}", url)),
+        }
+    }
+
+    pub async fn report_status(
+        &self,
+        job_id: uuid::Uuid,
+        report: &JobStatusReport,
+    ) -> Result<JobReportAck> {
+        if let Some(grpc) = &self.grpc {
+            return grpc.report_status(job_id, report).await;
+        }
+        self.report(&format!("/jobs/{}/status", job_id), report)
+            .await
+    }
+
+    pub async fn send_logs(&self, job_id: uuid::Uuid, lines: Vec<LogLine>) -> Result<JobReportAck> {
+        if let Some(grpc) = &self.grpc {
+            return grpc.send_logs(job_id, lines).await;
+        }
+        self.report(&format!("/jobs/{}/logs", job_id), &JobLogBatch { lines })
+            .await
+    }
+
+    /// Freshly signed links to the artifacts a leased job downloads, by
+    /// variable name. Only the gRPC service hands these out; over HTTP the
+    /// links in the job's spec are used as they are.
+    pub async fn artifact_urls(&self, job_id: uuid::Uuid) -> Result<HashMap<String, String>> {
+        match &self.grpc {
+            Some(grpc) => grpc.artifact_urls(job_id).await,
+            None => Ok(HashMap::new()),
+        }
+    }
+
+    /// POST a report. A job the server no longer leases to this runner
+    /// (`409`) is treated as cancelled, so the runner stops it.
+    async fn report<B: Serialize>(&self, path: &str, body: &B) -> Result<JobReportAck> {
+        let url = self.url(path);
+        let response = self
+            .http
+            .post(&url)
+            .bearer_auth(&self.credentials.token)
+            .json(body)
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach API at {}", self.base_url))?;
+
+        let status = response.status();
+        if status == StatusCode::CONFLICT {
+            return Ok(JobReportAck {
+                cancel_requested: true,
+            });
+        }
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            bail!("Report failed ({}): {}", status, body);
+        }
+
+        response
+            .json()
+            .await
+            .with_context(|| format!("Invalid response from {}", url))
+    }
+}
@@ -0,0 +1,184 @@
+//! gRPC client for the runner service of the BuildIt API.
+//!
+//! Used instead of the HTTP endpoints with `--grpc`. Each running job keeps
+//! one log upload stream open, so a log batch costs a message instead of a
+//! request.
+
+use anyhow::{Context, Result};
+use buildit_core::executor::LogLine;
+use buildit_core::runner::{JobLease, JobReportAck, JobStatusReport, RunnerCredentials};
+use buildit_grpc::RUNNER_ID_METADATA;
+use buildit_grpc::runner::runner_client::RunnerClient;
+use buildit_grpc::runner::{
+    GetArtifactUrlsRequest, LeaseJobRequest, LogBatch, ReportAck, ReportStatusRequest,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, mpsc};
+use tonic::metadata::AsciiMetadataValue;
+use tonic::service::Interceptor;
+use tonic::service::interceptor::InterceptedService;
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint};
+use tonic::{Code, Request, Status, Streaming};
+use uuid::Uuid;
+
+use crate::client::RunnerRemoved;
+
+/// Adds the runner's ID and token to every call.
+#[derive(Clone)]
+struct Credentials {
+    runner_id: AsciiMetadataValue,
+    authorization: AsciiMetadataValue,
+}
+
+impl Interceptor for Credentials {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let metadata = request.metadata_mut();
+        metadata.insert(RUNNER_ID_METADATA, self.runner_id.clone());
+        metadata.insert("authorization", self.authorization.clone());
+        Ok(request)
+    }
+}
+
+/// The open log upload of a job.
+struct LogUpload {
+    batches: mpsc::Sender<LogBatch>,
+    acks: Streaming<ReportAck>,
+}
+
+pub struct GrpcClient {
+    client: RunnerClient<InterceptedService<Channel, Credentials>>,
+    uploads: Mutex<HashMap<Uuid, Arc<Mutex<LogUpload>>>>,
+}
+
+/// A rejected runner token means the runner was removed.
+fn call_error(status: Status) -> anyhow::Error {
+    if status.code() == Code::Unauthenticated {
+        RunnerRemoved.into()
+    } else {
+        status.into()
+    }
+}
+
+impl GrpcClient {
+    /// A client for the API at `api_url`. Connects on the first call.
+    pub fn new(api_url: &str, credentials: &RunnerCredentials) -> Result<Self> {
+        let mut endpoint = Endpoint::from_shared(api_url.trim_end_matches('/').to_string())
+            .with_context(|| format!("Invalid API URL {}", api_url))?;
+        if api_url.starts_with("https://") {
+            endpoint = endpoint.tls_config(ClientTlsConfig::new().with_native_roots())?;
+        }
+        let credentials = Credentials {
+            runner_id: credentials.runner_id.to_string().parse()?,
+            authorization: format!("Bearer {}", credentials.token)
+                .parse()
+                .context("Invalid runner token")?,
+        };
+        Ok(Self {
+            client: RunnerClient::with_interceptor(endpoint.connect_lazy(), credentials),
+            uploads: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Wait up to `wait` for a job. `None` if none came up.
+    pub async fn lease(&self, wait: Duration) -> Result<Option<JobLease>> {
+        let mut request = Request::new(LeaseJobRequest {
+            wait_seconds: wait.as_secs().try_into().unwrap_or(u32::MAX),
+        });
+        // Leave the server time to answer the long poll
+        request.set_timeout(wait + Duration::from_secs(30));
+        let response = self
+            .client
+            .clone()
+            .lease_job(request)
+            .await
+            .map_err(call_error)?;
+        Ok(response
+            .into_inner()
+            .lease
+            .map(JobLease::try_from)
+            .transpose()?)
+    }
+
+    pub async fn report_status(
+        &self,
+        job_id: Uuid,
+        report: &JobStatusReport,
+    ) -> Result<JobReportAck> {
+        let ack = self
+            .client
+            .clone()
+            .report_status(ReportStatusRequest::new(job_id, report))
+            .await
+            .map_err(call_error)?;
+        if report.state.is_terminal() {
+            // Closes the job's log upload
+            self.uploads.lock().await.remove(&job_id);
+        }
+        Ok(ack.into_inner().into())
+    }
+
+    /// Send log lines over the job's upload stream, opening it first if
+    /// needed. A failed stream is dropped, so the next batch opens another.
+    pub async fn send_logs(&self, job_id: Uuid, lines: Vec<LogLine>) -> Result<JobReportAck> {
+        let upload = self.upload(job_id).await?;
+        let mut upload = upload.lock().await;
+        let batch = LogBatch {
+            job_id: job_id.to_string(),
+            lines: lines.iter().map(Into::into).collect(),
+        };
+        let ack = async {
+            upload
+                .batches
+                .send(batch)
+                .await
+                .context("Log upload stream closed")?;
+            upload
+                .acks
+                .message()
+                .await
+                .map_err(call_error)?
+                .context("Log upload stream ended")
+        }
+        .await;
+        if ack.is_err() {
+            self.uploads.lock().await.remove(&job_id);
+        }
+        Ok(ack?.into())
+    }
+
+    async fn upload(&self, job_id: Uuid) -> Result<Arc<Mutex<LogUpload>>> {
+        let mut uploads = self.uploads.lock().await;
+        if let Some(upload) = uploads.get(&job_id) {
+            return Ok(upload.clone());
+        }
+        let (batches, receiver) = mpsc::channel(1);
+        let outgoing = futures::stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|batch| (batch, receiver))
+        });
+        let acks = self
+            .client
+            .clone()
+            .upload_logs(outgoing)
+            .await
+            .map_err(call_error)?
+            .into_inner();
+        let upload = Arc::new(Mutex::new(LogUpload { batches, acks }));
+        uploads.insert(job_id, upload.clone());
+        Ok(upload)
+    }
+
+    /// Freshly signed artifact links of a leased job, by variable name.
+    pub async fn artifact_urls(&self, job_id: Uuid) -> Result<HashMap<String, String>> {
+        let response = self
+            .client
+            .clone()
+            .get_artifact_urls(GetArtifactUrlsRequest {
+                job_id: job_id.to_string(),
+            })
+            .await
+            .map_err(call_error)?;
+        Ok(response.into_inner().urls)
+    }
+}
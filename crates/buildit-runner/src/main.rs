@@ -0,0 +1,161 @@
+//! BuildIt self-hosted runner.
+//!
+//! Registers with the API server on first start, then leases jobs and runs
+//! them in local Docker containers, streaming logs and status back. With
+//! `--shell`, jobs run directly on the machine instead, e.g. to build and
+//! sign on macOS. The runner registers the platforms its jobs run on, so it
+//! only gets jobs for them. With `--grpc`, jobs are leased and reported
+//! over the server's gRPC service rather than its REST endpoints.
+
+use anyhow::{Context, Result};
+use buildit_core::executor::Executor;
+use buildit_core::runner::{RunnerCredentials, RunnerRegistration};
+use buildit_executor::{LocalDockerExecutor, SshExecutor, SshTarget};
+use clap::Parser;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::info;
+use tracing_subscriber::EnvFilter;
+
+mod agent;
+mod client;
+mod grpc;
+
+use agent::Agent;
+use client::RunnerClient;
+
+#[derive(Parser)]
+#[command(name = "buildit-runner")]
+#[command(about = "BuildIt self-hosted runner", long_about = None)]
+struct Cli {
+    /// API server URL
+    #[arg(long, env = "BUILDIT_API_URL", default_value = "http://localhost:3000")]
+    api_url: String,
+
+    /// API token with the `runners:register` scope, used on first start
+    #[arg(long, env = "BUILDIT_API_TOKEN")]
+    token: Option<String>,
+
+    /// Runner name shown in the API
+    #[arg(long, env = "BUILDIT_RUNNER_NAME")]
+    name: Option<String>,
+
+    /// Labels stages can select this runner by, comma-separated
+    #[arg(long, env = "BUILDIT_RUNNER_LABELS", value_delimiter = ',')]
+    labels: Vec<String>,
+
+    /// File the runner's credentials are kept in after registering
+    #[arg(
+        long,
+        env = "BUILDIT_RUNNER_CREDENTIALS",
+        default_value = "buildit-runner.json"
+    )]
+    credentials: String,
+
+    /// Run jobs directly on this machine, in workspaces under this
+    /// directory, instead of in Docker containers
+    #[arg(long, env = "BUILDIT_RUNNER_SHELL")]
+    shell: Option<String>,
+
+    /// Lease and report jobs over gRPC instead of HTTP
+    #[arg(long, env = "BUILDIT_RUNNER_GRPC")]
+    grpc: bool,
+
+    /// Number of jobs to run at once
+    #[arg(long, env = "BUILDIT_RUNNER_CONCURRENCY", default_value = "1")]
+    concurrency: usize,
+
+    /// Enable verbose logging
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    let filter = if cli.verbose {
+        EnvFilter::new("buildit=debug,info")
+    } else {
+        EnvFilter::new("buildit=info,warn")
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_target(false)
+        .init();
+
+    let executor: Arc<dyn Executor> = match &cli.shell {
+        Some(work_root) => Arc::new(SshExecutor::new(SshTarget::local(work_root))),
+        None => Arc::new(LocalDockerExecutor::new().context("Failed to connect to Docker")?),
+    };
+    let credentials = load_or_register(&cli, executor.as_ref()).await?;
+
+    info!(
+        runner = %credentials.runner_id,
+        api = %cli.api_url,
+        concurrency = cli.concurrency,
+        grpc = cli.grpc,
+        "Waiting for jobs"
+    );
+    let mut client = RunnerClient::new(&cli.api_url, credentials);
+    if cli.grpc {
+        client = client.with_grpc()?;
+    }
+    Agent::new(client, executor).run(cli.concurrency).await
+}
+
+/// Read saved credentials, or register and save them.
+async fn load_or_register(cli: &Cli, executor: &dyn Executor) -> Result<RunnerCredentials> {
+    let path = Path::new(&cli.credentials);
+    if path.exists() {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        return serde_json::from_str(&contents)
+            .with_context(|| format!("Invalid runner credentials in {}", path.display()));
+    }
+
+    let token = cli.token.as_deref().context(
+        "The runner is not registered yet; pass an API token with --token or BUILDIT_API_TOKEN",
+    )?;
+    let name = cli
+        .name
+        .clone()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .unwrap_or_else(|| "buildit-runner".to_string());
+    let labels: Vec<String> = cli
+        .labels
+        .iter()
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty())
+        .collect();
+    let platforms = executor.platforms().await;
+
+    let credentials = RunnerClient::register(
+        &cli.api_url,
+        token,
+        &RunnerRegistration {
+            name: name.clone(),
+            labels: labels.clone(),
+            platforms: platforms.clone(),
+            version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        },
+    )
+    .await?;
+    write_credentials(path, &credentials)?;
+    let platforms: Vec<String> = platforms.iter().map(|p| p.to_string()).collect();
+    info!(runner = %name, labels = ?labels, platforms = ?platforms, path = %path.display(), "Registered runner");
+    Ok(credentials)
+}
+
+fn write_credentials(path: &Path, credentials: &RunnerCredentials) -> Result<()> {
+    let contents = serde_json::to_string_pretty(credentials)?;
+    std::fs::write(path, contents)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    // The token is a secret
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
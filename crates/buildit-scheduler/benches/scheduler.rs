@@ -0,0 +1,438 @@
+//! Scheduler benchmarks.
+//!
+//! Measures:
+//! - orchestrator DAG progression latency for 1000-stage pipelines
+//! - job claim throughput with many workers contending for the queue
+//! - log ingestion rates
+//!
+//! Results are compared against `benches/baseline.json` and the run fails
+//! if any metric is worse than the baseline by more than the tolerance.
+//! The queue and log benchmarks need PostgreSQL and are skipped unless
+//! `DATABASE_URL` is set.
+//!
+//! ```text
+//! cargo bench -p buildit-scheduler                   # all benchmarks
+//! cargo bench -p buildit-scheduler -- orchestrator   # names containing "orchestrator"
+//! BUILDIT_BENCH_SAVE=1 cargo bench -p buildit-scheduler   # record a new baseline
+//! ```
+//!
+//! `BUILDIT_BENCH_TOLERANCE` sets the allowed regression (default 0.25, i.e. 25%).
+
+use async_trait::async_trait;
+use buildit_core::ResourceId;
+use buildit_core::executor::{
+    Executor, JobHandle, JobResult, JobSpec, JobStatus, LogLine, TerminalSession,
+};
+use buildit_core::pipeline::{Pipeline, Stage, StageAction};
+use buildit_db::{LogRepo, PgLogRepo};
+use buildit_scheduler::{JobQueue, PipelineOrchestrator};
+use chrono::Utc;
+use futures::StreamExt;
+use futures::stream::BoxStream;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use sqlx::postgres::PgPoolOptions;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const DAG_STAGES: usize = 1000;
+const DAG_WIDTH: usize = 50;
+const QUEUE_JOBS: usize = 2000;
+const QUEUE_WORKERS: usize = 16;
+const LOG_LINES: usize = 20_000;
+const LOG_BATCH: usize = 500;
+const SAMPLES: usize = 5;
+
+/// Which direction is an improvement for a metric.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Better {
+    Lower,
+    Higher,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Metric {
+    value: f64,
+    unit: String,
+    better: Better,
+}
+
+type Baseline = BTreeMap<String, Metric>;
+
+fn baseline_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("benches/baseline.json")
+}
+
+/// Median of the samples; benchmarks are noisy and one slow sample should
+/// not fail a run.
+fn median(mut samples: Vec<f64>) -> f64 {
+    samples.sort_by(|a, b| a.total_cmp(b));
+    samples[samples.len() / 2]
+}
+
+/// Executor whose jobs succeed immediately, so only orchestration is timed.
+struct InstantExecutor;
+
+#[async_trait]
+impl Executor for InstantExecutor {
+    fn name(&self) -> &'static str {
+        "instant"
+    }
+
+    async fn can_execute(&self, _spec: &JobSpec) -> bool {
+        true
+    }
+
+    async fn spawn(&self, spec: JobSpec) -> buildit_core::Result<JobHandle> {
+        Ok(JobHandle {
+            id: spec.id,
+            executor_id: spec.id.to_string(),
+            executor_name: "instant".to_string(),
+        })
+    }
+
+    async fn logs(&self, _handle: &JobHandle) -> buildit_core::Result<BoxStream<'static, LogLine>> {
+        Ok(futures::stream::empty().boxed())
+    }
+
+    async fn status(&self, _handle: &JobHandle) -> buildit_core::Result<JobStatus> {
+        let now = Utc::now();
+        Ok(JobStatus::Succeeded {
+            started_at: now,
+            finished_at: now,
+        })
+    }
+
+    async fn wait(&self, handle: &JobHandle) -> buildit_core::Result<JobResult> {
+        Ok(JobResult {
+            status: self.status(handle).await?,
+            exit_code: Some(0),
+            artifacts: vec![],
+        })
+    }
+
+    async fn cancel(&self, _handle: &JobHandle) -> buildit_core::Result<()> {
+        Ok(())
+    }
+
+    async fn exec_interactive(
+        &self,
+        _handle: &JobHandle,
+        _cmd: Vec<String>,
+    ) -> buildit_core::Result<TerminalSession> {
+        Err(buildit_core::Error::Internal(
+            "not supported by the benchmark executor".to_string(),
+        ))
+    }
+}
+
+/// A layered DAG: `DAG_WIDTH` stages per layer, each needing two stages
+/// from the layer before it.
+fn large_pipeline() -> Pipeline {
+    let stages = (0..DAG_STAGES)
+        .map(|i| {
+            let needs = if i < DAG_WIDTH {
+                vec![]
+            } else {
+                let layer_start = (i / DAG_WIDTH - 1) * DAG_WIDTH;
+                vec![
+                    format!("stage-{}", layer_start + i % DAG_WIDTH),
+                    format!("stage-{}", layer_start + (i + 1) % DAG_WIDTH),
+                ]
+            };
+            Stage {
+                name: format!("stage-{}", i),
+                needs,
+                when: None,
+                manual: false,
+                action: StageAction::Run {
+                    image: "alpine".to_string(),
+                    commands: vec!["true".to_string()],
+                    artifacts: vec![],
+                    test_reports: vec![],
+                },
+                env: HashMap::new(),
+                runs_on: vec![],
+                resources: Default::default(),
+                platform: Default::default(),
+                wait_for: vec![],
+                needs_artifacts: vec![],
+                security_context: None,
+                container: Default::default(),
+                pull_policy: None,
+            }
+        })
+        .collect();
+
+    Pipeline {
+        id: ResourceId::new(),
+        name: "bench".to_string(),
+        tenant_id: ResourceId::new(),
+        repository: "https://example.com/bench.git".to_string(),
+        triggers: vec![],
+        stages,
+        env: HashMap::new(),
+        caches: vec![],
+        ownership: Default::default(),
+        checkout: Default::default(),
+        params: vec![],
+    }
+}
+
+async fn bench_orchestrator(results: &mut Baseline) {
+    let orchestrator = PipelineOrchestrator::new(Arc::new(InstantExecutor));
+    let pipeline = large_pipeline();
+
+    let mut samples = Vec::with_capacity(SAMPLES);
+    for _ in 0..SAMPLES {
+        let started = Instant::now();
+        let (mut events, handle) = orchestrator.execute(&pipeline, HashMap::new(), None);
+        let drain = tokio::spawn(async move { while events.recv().await.is_some() {} });
+        let result = handle.await.expect("orchestrator panicked");
+        let _ = drain.await;
+        assert!(result.success, "benchmark pipeline failed");
+        samples.push(started.elapsed().as_secs_f64() * 1000.0);
+    }
+
+    results.insert(
+        "orchestrator.dag_1000.latency_ms".to_string(),
+        Metric {
+            value: median(samples),
+            unit: "ms".to_string(),
+            better: Better::Lower,
+        },
+    );
+}
+
+/// Create a pipeline run to hang queue and log rows off.
+async fn create_run(pool: &PgPool) -> Result<(uuid::Uuid, uuid::Uuid), sqlx::Error> {
+    let pipeline_id = uuid::Uuid::now_v7();
+    let run_id = uuid::Uuid::now_v7();
+    sqlx::query(
+        "INSERT INTO pipelines (id, tenant_id, name, repository) \
+         VALUES ($1, '00000000-0000-0000-0000-000000000001', $2, 'bench')",
+    )
+    .bind(pipeline_id)
+    .bind(format!("bench-{}", pipeline_id))
+    .execute(pool)
+    .await?;
+    sqlx::query("INSERT INTO pipeline_runs (id, pipeline_id, number) VALUES ($1, $2, 1)")
+        .bind(run_id)
+        .bind(pipeline_id)
+        .execute(pool)
+        .await?;
+    Ok((pipeline_id, run_id))
+}
+
+async fn drop_pipeline(pool: &PgPool, pipeline_id: uuid::Uuid) {
+    let _ = sqlx::query("DELETE FROM pipelines WHERE id = $1")
+        .bind(pipeline_id)
+        .execute(pool)
+        .await;
+}
+
+async fn bench_queue(pool: &PgPool, results: &mut Baseline) -> Result<(), sqlx::Error> {
+    let queue = Arc::new(JobQueue::new(pool.clone()));
+    let mut samples = Vec::with_capacity(SAMPLES);
+
+    for _ in 0..SAMPLES {
+        let (pipeline_id, run_id) = create_run(pool).await?;
+        for i in 0..QUEUE_JOBS {
+            queue
+                .enqueue(
+                    ResourceId::from_uuid(run_id),
+                    &format!("stage-{}", i),
+                    (i % 3) as i32,
+                )
+                .await?;
+        }
+
+        let started = Instant::now();
+        let workers: Vec<_> = (0..QUEUE_WORKERS)
+            .map(|w| {
+                let queue = queue.clone();
+                tokio::spawn(async move {
+                    let worker_id = format!("bench-{}", w);
+                    let mut claimed = 0usize;
+                    while let Some(job) = queue.claim(&worker_id).await? {
+                        queue.complete(job.id).await?;
+                        claimed += 1;
+                    }
+                    Ok::<_, sqlx::Error>(claimed)
+                })
+            })
+            .collect();
+
+        let mut claimed = 0;
+        for worker in workers {
+            claimed += worker.await.expect("worker panicked")?;
+        }
+        let elapsed = started.elapsed().as_secs_f64();
+        assert_eq!(claimed, QUEUE_JOBS, "jobs were lost or claimed twice");
+        samples.push(claimed as f64 / elapsed);
+
+        drop_pipeline(pool, pipeline_id).await;
+    }
+
+    results.insert(
+        "queue.claim_16_workers.throughput".to_string(),
+        Metric {
+            value: median(samples),
+            unit: "jobs/s".to_string(),
+            better: Better::Higher,
+        },
+    );
+    Ok(())
+}
+
+async fn bench_logs(pool: &PgPool, results: &mut Baseline) -> Result<(), sqlx::Error> {
+    let repo = PgLogRepo::new(pool.clone());
+    let batch: Vec<(String, String)> = (0..LOG_BATCH)
+        .map(|i| {
+            (
+                "stdout".to_string(),
+                format!(
+                    "Compiling crate-{} v0.1.0 (/workspace/crates/crate-{})",
+                    i, i
+                ),
+            )
+        })
+        .collect();
+
+    let mut samples = Vec::with_capacity(SAMPLES);
+    for _ in 0..SAMPLES {
+        let (pipeline_id, run_id) = create_run(pool).await?;
+        let run_id = ResourceId::from_uuid(run_id);
+
+        let started = Instant::now();
+        for _ in 0..LOG_LINES / LOG_BATCH {
+            repo.append_logs_batch(run_id, "build", &batch)
+                .await
+                .map_err(|e| sqlx::Error::Protocol(e.to_string()))?;
+        }
+        samples.push(LOG_LINES as f64 / started.elapsed().as_secs_f64());
+
+        drop_pipeline(pool, pipeline_id).await;
+    }
+
+    results.insert(
+        "logs.batch_ingest.throughput".to_string(),
+        Metric {
+            value: median(samples),
+            unit: "lines/s".to_string(),
+            better: Better::Higher,
+        },
+    );
+    Ok(())
+}
+
+/// Compare results with the baseline, returning the regressions.
+fn regressions(results: &Baseline, baseline: &Baseline, tolerance: f64) -> Vec<String> {
+    let mut regressions = Vec::new();
+    for (name, metric) in results {
+        let Some(base) = baseline.get(name) else {
+            println!(
+                "{:<40} {:>12.1} {:<8} (no baseline)",
+                name, metric.value, metric.unit
+            );
+            continue;
+        };
+        let change = (metric.value - base.value) / base.value;
+        let regressed = match metric.better {
+            Better::Lower => change > tolerance,
+            Better::Higher => change < -tolerance,
+        };
+        println!(
+            "{:<40} {:>12.1} {:<8} baseline {:>12.1} ({:+.1}%){}",
+            name,
+            metric.value,
+            metric.unit,
+            base.value,
+            change * 100.0,
+            if regressed { "  REGRESSION" } else { "" }
+        );
+        if regressed {
+            regressions.push(name.clone());
+        }
+    }
+    regressions
+}
+
+#[tokio::main]
+async fn main() {
+    // `cargo bench` passes flags such as `--bench`; anything else filters
+    let filter: Vec<String> = std::env::args()
+        .skip(1)
+        .filter(|a| !a.starts_with("--"))
+        .collect();
+    let selected = |name: &str| filter.is_empty() || filter.iter().any(|f| name.contains(f));
+    let tolerance = std::env::var("BUILDIT_BENCH_TOLERANCE")
+        .ok()
+        .and_then(|t| t.parse().ok())
+        .unwrap_or(0.25);
+
+    let mut results = Baseline::new();
+
+    if selected("orchestrator") {
+        bench_orchestrator(&mut results).await;
+    }
+
+    if selected("queue") || selected("logs") {
+        match std::env::var("DATABASE_URL") {
+            Ok(url) => {
+                let pool = PgPoolOptions::new()
+                    .max_connections(QUEUE_WORKERS as u32 + 4)
+                    .acquire_timeout(Duration::from_secs(30))
+                    .connect(&url)
+                    .await
+                    .expect("failed to connect to DATABASE_URL");
+                buildit_db::run_migrations(&pool)
+                    .await
+                    .expect("failed to run migrations");
+
+                if selected("queue") {
+                    bench_queue(&pool, &mut results)
+                        .await
+                        .expect("queue benchmark failed");
+                }
+                if selected("logs") {
+                    bench_logs(&pool, &mut results)
+                        .await
+                        .expect("log benchmark failed");
+                }
+            }
+            Err(_) => println!("DATABASE_URL is not set; skipping queue and log benchmarks"),
+        }
+    }
+
+    let path = baseline_path();
+    let mut baseline: Baseline = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default();
+
+    if std::env::var("BUILDIT_BENCH_SAVE").is_ok() {
+        baseline.extend(results.into_iter().map(|(name, mut metric)| {
+            metric.value = (metric.value * 10.0).round() / 10.0;
+            (name, metric)
+        }));
+        let json = serde_json::to_string_pretty(&baseline).expect("serialize baseline");
+        std::fs::write(&path, json + "\n").expect("write baseline");
+        println!("Saved baseline to {}", path.display());
+        return;
+    }
+
+    let regressions = regressions(&results, &baseline, tolerance);
+    if !regressions.is_empty() {
+        eprintln!(
+            "{} benchmark(s) regressed by more than {:.0}%: {}",
+            regressions.len(),
+            tolerance * 100.0,
+            regressions.join(", ")
+        );
+        std::process::exit(1);
+    }
+}
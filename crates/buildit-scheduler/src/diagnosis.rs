@@ -0,0 +1,258 @@
+//! Why a queued run has not started.
+//!
+//! A queued run is a task waiting for a worker. It may be behind other
+//! work, held until its tenant's quota has room, or unable to start at all
+//! because a stage needs an executor nobody offers. A diagnosis lists every
+//! reason that applies, each worked out the way the queue, the quota
+//! tracker and the executor registry decide when the run is claimed.
+
+use crate::queue::{JobQueue, TaskPosition};
+use crate::quota::{JobResources, QuotaExceeded, QuotaTracker};
+use crate::registry::ExecutorRegistry;
+use buildit_core::pipeline::{Stage, StageAction};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A reason a queued run is waiting.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum QueueReason {
+    /// Nothing is queued to start the run, e.g. queueing it failed.
+    NotQueued,
+    /// Due, and waiting for a free worker behind `ahead` other tasks.
+    WaitingForWorker { ahead: i64 },
+    /// Claimed by a worker that is starting it.
+    Starting { worker: String },
+    /// Put back to be tried again at `until`.
+    Deferred { until: DateTime<Utc> },
+    /// The tenant already runs as many jobs at once as its quota allows,
+    /// for these runs.
+    ConcurrencyLimit {
+        limit: u32,
+        blocking_runs: Vec<Uuid>,
+    },
+    /// The tenant's quota has no room for another job until `retry_at`.
+    QuotaExceeded {
+        message: String,
+        retry_at: DateTime<Utc>,
+    },
+    /// A stage needs labels or a platform no executor offers.
+    NoMatchingExecutor { stage: String, message: String },
+}
+
+impl QueueReason {
+    /// The reason's `kind`, as serialized.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            QueueReason::NotQueued => "not_queued",
+            QueueReason::WaitingForWorker { .. } => "waiting_for_worker",
+            QueueReason::Starting { .. } => "starting",
+            QueueReason::Deferred { .. } => "deferred",
+            QueueReason::ConcurrencyLimit { .. } => "concurrency_limit",
+            QueueReason::QuotaExceeded { .. } => "quota_exceeded",
+            QueueReason::NoMatchingExecutor { .. } => "no_matching_executor",
+        }
+    }
+}
+
+impl std::fmt::Display for QueueReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueueReason::NotQueued => f.write_str("The run is not in the queue"),
+            QueueReason::WaitingForWorker { ahead: 0 } => {
+                f.write_str("Next in line for a free worker")
+            }
+            QueueReason::WaitingForWorker { ahead } => {
+                write!(f, "Waiting for a free worker behind {} other tasks", ahead)
+            }
+            QueueReason::Starting { worker } => write!(f, "Starting on worker {}", worker),
+            QueueReason::Deferred { until } => {
+                write!(f, "Held until {}", until.format("%Y-%m-%d %H:%M:%S UTC"))
+            }
+            QueueReason::ConcurrencyLimit {
+                limit,
+                blocking_runs,
+            } => write!(
+                f,
+                "The tenant's limit of {} jobs running at once is reached by {} running runs",
+                limit,
+                blocking_runs.len()
+            ),
+            QueueReason::QuotaExceeded { message, retry_at } => write!(
+                f,
+                "Tenant quota: {}; checked again at {}",
+                message,
+                retry_at.format("%Y-%m-%d %H:%M:%S UTC")
+            ),
+            QueueReason::NoMatchingExecutor { stage, message } => {
+                write!(f, "Stage '{}' cannot run: {}", stage, message)
+            }
+        }
+    }
+}
+
+/// Why a queued run is waiting, and where it stands in the queue.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QueueDiagnosis {
+    /// Place among due tasks in the order workers claim them, from 1;
+    /// `None` unless the run is waiting for a worker.
+    pub position: Option<i64>,
+    pub reasons: Vec<QueueReason>,
+}
+
+impl QueueDiagnosis {
+    /// Combine where the run's task stands with what holds the run: quota
+    /// reasons explain a task deferred for them, so the deferral is only
+    /// reported on its own when there are none.
+    pub fn new(
+        task: Option<&TaskPosition>,
+        held: Vec<QueueReason>,
+        unroutable: Vec<QueueReason>,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let mut position = None;
+        let mut reasons = unroutable;
+        let task_reason = match task {
+            Some(task) if task.status == "claimed" => Some(QueueReason::Starting {
+                worker: task.claimed_by.clone().unwrap_or_default(),
+            }),
+            Some(task) if task.status == "pending" && task.run_after > now => {
+                held.is_empty().then_some(QueueReason::Deferred {
+                    until: task.run_after,
+                })
+            }
+            Some(task) if task.status == "pending" => {
+                position = Some(task.ahead + 1);
+                Some(QueueReason::WaitingForWorker { ahead: task.ahead })
+            }
+            _ => Some(QueueReason::NotQueued),
+        };
+        reasons.extend(held);
+        reasons.extend(task_reason);
+        Self { position, reasons }
+    }
+}
+
+/// Diagnose a queued run of a tenant's pipeline with `stages`, started by
+/// the task with `task_key`. Stages are only checked against `executors`
+/// when this server has them.
+pub async fn diagnose(
+    queue: &JobQueue,
+    quota: &QuotaTracker,
+    executors: Option<&ExecutorRegistry>,
+    tenant_id: Uuid,
+    task_key: &str,
+    stages: &[Stage],
+) -> Result<QueueDiagnosis, sqlx::Error> {
+    let now = Utc::now();
+
+    let mut unroutable = Vec::new();
+    if let Some(executors) = executors {
+        for stage in stages {
+            if !matches!(stage.action, StageAction::Run { .. }) {
+                continue;
+            }
+            if let Err(message) = executors.check(&stage.runs_on, &stage.platform).await {
+                unroutable.push(QueueReason::NoMatchingExecutor {
+                    stage: stage.name.clone(),
+                    message,
+                });
+            }
+        }
+    }
+
+    let held = match quota.check(tenant_id, JobResources::default()).await? {
+        Ok(()) => vec![],
+        Err((QuotaExceeded::ConcurrentJobs { limit }, _)) => {
+            vec![QueueReason::ConcurrencyLimit {
+                limit,
+                blocking_runs: quota.running_runs(tenant_id).await?,
+            }]
+        }
+        Err((exceeded, _)) => vec![QueueReason::QuotaExceeded {
+            message: exceeded.to_string(),
+            retry_at: exceeded.retry_at(now),
+        }],
+    };
+
+    let task = queue.task_position(task_key).await?;
+    Ok(QueueDiagnosis::new(task.as_ref(), held, unroutable, now))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn task(status: &str, run_after: DateTime<Utc>, ahead: i64) -> TaskPosition {
+        TaskPosition {
+            status: status.to_string(),
+            run_after,
+            claimed_by: (status == "claimed").then(|| "api-1".to_string()),
+            ahead,
+        }
+    }
+
+    #[test]
+    fn test_due_task_reports_position() {
+        let now = Utc::now();
+        let diagnosis = QueueDiagnosis::new(Some(&task("pending", now, 3)), vec![], vec![], now);
+        assert_eq!(diagnosis.position, Some(4));
+        assert_eq!(
+            diagnosis.reasons,
+            vec![QueueReason::WaitingForWorker { ahead: 3 }]
+        );
+    }
+
+    #[test]
+    fn test_quota_explains_deferred_task() {
+        let now = Utc::now();
+        let held = vec![QueueReason::ConcurrencyLimit {
+            limit: 2,
+            blocking_runs: vec![Uuid::nil()],
+        }];
+        let later = now + Duration::seconds(30);
+        let diagnosis =
+            QueueDiagnosis::new(Some(&task("pending", later, 0)), held.clone(), vec![], now);
+        assert_eq!(diagnosis.position, None);
+        assert_eq!(diagnosis.reasons, held);
+
+        let diagnosis = QueueDiagnosis::new(Some(&task("pending", later, 0)), vec![], vec![], now);
+        assert_eq!(
+            diagnosis.reasons,
+            vec![QueueReason::Deferred { until: later }]
+        );
+    }
+
+    #[test]
+    fn test_unroutable_stages_come_first() {
+        let now = Utc::now();
+        let unroutable = vec![QueueReason::NoMatchingExecutor {
+            stage: "train".to_string(),
+            message: "No executor has the labels [gpu] (available: local)".to_string(),
+        }];
+        let diagnosis = QueueDiagnosis::new(
+            Some(&task("claimed", now, 0)),
+            vec![],
+            unroutable.clone(),
+            now,
+        );
+        assert_eq!(diagnosis.reasons[0], unroutable[0]);
+        assert_eq!(
+            diagnosis.reasons[1],
+            QueueReason::Starting {
+                worker: "api-1".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_missing_task_is_not_queued() {
+        let now = Utc::now();
+        for task in [None, Some(task("failed", now, 0))] {
+            let diagnosis = QueueDiagnosis::new(task.as_ref(), vec![], vec![], now);
+            assert_eq!(diagnosis.reasons, vec![QueueReason::NotQueued]);
+        }
+    }
+}
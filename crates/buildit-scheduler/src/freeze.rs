@@ -0,0 +1,411 @@
+//! Deployment freeze windows.
+//!
+//! A freeze window stops deployments to an environment, either for a fixed
+//! date range or repeatedly: from each time a cron expression matches, for
+//! a fixed duration. `0 17 * * FRI` for 63 hours freezes every weekend from
+//! Friday 17:00 until Monday 08:00. All times are UTC.
+
+use chrono::{DateTime, Datelike, Duration, DurationRound, TimeZone, Timelike, Utc};
+use std::str::FromStr;
+
+/// Longest a recurring window may last.
+pub const MAX_DURATION: Duration = Duration::days(31);
+
+/// How far ahead to look for the next time a cron expression matches
+/// before giving up, e.g. for `0 0 31 2 *`.
+const SEARCH_LIMIT: Duration = Duration::days(366 * 5);
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum FreezeError {
+    #[error("invalid cron expression '{expr}': {reason}")]
+    InvalidCron { expr: String, reason: String },
+    #[error("invalid freeze window: {0}")]
+    InvalidWindow(String),
+}
+
+/// A five-field cron expression: minute, hour, day of month, month and
+/// day of week. Fields take `*`, numbers, ranges (`1-5`), steps (`*/15`,
+/// `0-30/10`), lists (`1,15`) and English month and day names.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    expr: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether day of month and day of week were restricted. When both
+    /// are, a day matches if either does, as in cron.
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+const MONTHS: &[&str] = &[
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+const WEEKDAYS: &[&str] = &["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+impl CronSchedule {
+    pub fn parse(expr: &str) -> Result<Self, FreezeError> {
+        let invalid = |reason: String| FreezeError::InvalidCron {
+            expr: expr.to_string(),
+            reason,
+        };
+
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(invalid(format!(
+                "expected 5 fields, found {}",
+                fields.len()
+            )));
+        };
+
+        let mut weekdays = parse_field(weekday, 0, 7, WEEKDAYS, 0).map_err(invalid)?;
+        // Both 0 and 7 are Sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+
+        Ok(Self {
+            expr: fields.join(" "),
+            minutes: parse_field(minute, 0, 59, &[], 0).map_err(invalid)?,
+            hours: parse_field(hour, 0, 23, &[], 0).map_err(invalid)?,
+            days: parse_field(day, 1, 31, &[], 0).map_err(invalid)?,
+            months: parse_field(month, 1, 12, MONTHS, 1).map_err(invalid)?,
+            weekdays,
+            days_restricted: day != "*",
+            weekdays_restricted: weekday != "*",
+        })
+    }
+
+    /// Whether the expression matches the minute `at` falls in.
+    pub fn matches(&self, at: DateTime<Utc>) -> bool {
+        self.minutes & (1 << at.minute()) != 0
+            && self.hours & (1 << at.hour()) != 0
+            && self.matches_day(at)
+    }
+
+    fn matches_day(&self, at: DateTime<Utc>) -> bool {
+        if self.months & (1 << at.month()) == 0 {
+            return false;
+        }
+        let day = self.days & (1 << at.day()) != 0;
+        let weekday = self.weekdays & (1 << at.weekday().num_days_from_sunday()) != 0;
+        if self.days_restricted && self.weekdays_restricted {
+            day || weekday
+        } else {
+            day && weekday
+        }
+    }
+
+    /// The first matching minute at or after `after`.
+    pub fn next_match(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut t = ceil_to_minute(after);
+        let limit = after + SEARCH_LIMIT;
+        while t <= limit {
+            if !self.matches_day(t) {
+                // Skip to the start of the next day
+                t = Utc
+                    .with_ymd_and_hms(t.year(), t.month(), t.day(), 0, 0, 0)
+                    .single()?
+                    + Duration::days(1);
+            } else if self.hours & (1 << t.hour()) == 0 {
+                t = t.duration_trunc(Duration::hours(1)).ok()? + Duration::hours(1);
+            } else if self.minutes & (1 << t.minute()) == 0 {
+                t += Duration::minutes(1);
+            } else {
+                return Some(t);
+            }
+        }
+        None
+    }
+}
+
+impl FromStr for CronSchedule {
+    type Err = FreezeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl std::fmt::Display for CronSchedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.expr)
+    }
+}
+
+/// Parse one cron field into a bit set of the values it allows. `names`
+/// are accepted for values starting at `first_name`.
+fn parse_field(
+    field: &str,
+    min: u32,
+    max: u32,
+    names: &[&str],
+    first_name: u32,
+) -> Result<u64, String> {
+    let value = |s: &str| -> Result<u32, String> {
+        let upper = s.to_ascii_uppercase();
+        if let Some(i) = names.iter().position(|n| *n == upper) {
+            return Ok(i as u32 + first_name);
+        }
+        let n: u32 = s.parse().map_err(|_| format!("'{}' is not a number", s))?;
+        if n < min || n > max {
+            return Err(format!("{} is outside {}-{}", n, min, max));
+        }
+        Ok(n)
+    };
+
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .ok()
+                    .filter(|s| *s > 0)
+                    .ok_or_else(|| format!("invalid step in '{}'", part))?;
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((a, b)) => (value(a)?, value(b)?),
+                // `5/15` means from 5 to the end in steps of 15
+                None if step > 1 => (value(range)?, max),
+                None => {
+                    let n = value(range)?;
+                    (n, n)
+                }
+            },
+        };
+        if start > end {
+            return Err(format!("range '{}' is backwards", range));
+        }
+        for n in (start..=end).step_by(step as usize) {
+            bits |= 1 << n;
+        }
+    }
+    Ok(bits)
+}
+
+fn ceil_to_minute(at: DateTime<Utc>) -> DateTime<Utc> {
+    let floor = at
+        .duration_trunc(Duration::minutes(1))
+        .expect("minutes fit in a timestamp");
+    if floor == at {
+        floor
+    } else {
+        floor + Duration::minutes(1)
+    }
+}
+
+/// When an environment is frozen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FreezeSchedule {
+    /// Frozen from `starts_at` until `ends_at`.
+    Range {
+        starts_at: DateTime<Utc>,
+        ends_at: DateTime<Utc>,
+    },
+    /// Frozen for `duration` each time `cron` matches.
+    Recurring {
+        cron: CronSchedule,
+        duration: Duration,
+    },
+}
+
+impl FreezeSchedule {
+    /// Build a schedule from a window's stored fields: either both ends of
+    /// a range, or a cron expression and a duration in minutes.
+    pub fn new(
+        starts_at: Option<DateTime<Utc>>,
+        ends_at: Option<DateTime<Utc>>,
+        cron: Option<&str>,
+        duration_minutes: Option<i32>,
+    ) -> Result<Self, FreezeError> {
+        match (starts_at, ends_at, cron, duration_minutes) {
+            (Some(starts_at), Some(ends_at), None, None) => {
+                if ends_at <= starts_at {
+                    return Err(FreezeError::InvalidWindow(
+                        "ends_at must be after starts_at".to_string(),
+                    ));
+                }
+                Ok(Self::Range { starts_at, ends_at })
+            }
+            (None, None, Some(cron), Some(minutes)) => {
+                let duration = Duration::minutes(minutes.into());
+                if duration <= Duration::zero() || duration > MAX_DURATION {
+                    return Err(FreezeError::InvalidWindow(format!(
+                        "duration must be between 1 minute and {} days",
+                        MAX_DURATION.num_days()
+                    )));
+                }
+                Ok(Self::Recurring {
+                    cron: cron.parse()?,
+                    duration,
+                })
+            }
+            _ => Err(FreezeError::InvalidWindow(
+                "give either starts_at and ends_at, or cron and duration_minutes".to_string(),
+            )),
+        }
+    }
+
+    /// The occurrence in effect at `at`, as its start and end. Of
+    /// overlapping occurrences, the one ending last.
+    pub fn active_at(&self, at: DateTime<Utc>) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        self.occurrences(at, at + Duration::nanoseconds(1))
+            .into_iter()
+            .max_by_key(|(_, end)| *end)
+    }
+
+    /// Occurrences overlapping `from..to`, in order of their start.
+    pub fn occurrences(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+        match self {
+            Self::Range { starts_at, ends_at } => {
+                if *starts_at < to && *ends_at > from {
+                    vec![(*starts_at, *ends_at)]
+                } else {
+                    Vec::new()
+                }
+            }
+            Self::Recurring { cron, duration } => {
+                let mut found = Vec::new();
+                let mut next = cron.next_match(from - *duration);
+                while let Some(start) = next.filter(|start| *start < to) {
+                    if start + *duration > from {
+                        found.push((start, start + *duration));
+                    }
+                    next = cron.next_match(start + Duration::minutes(1));
+                }
+                found
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_parse_cron_fields() {
+        let cron = CronSchedule::parse("*/15 9-17 * JAN,jul mon-fri").unwrap();
+        assert!(cron.matches(at("2026-01-05T09:45:00Z"))); // Monday
+        assert!(!cron.matches(at("2026-01-05T09:50:00Z")));
+        assert!(!cron.matches(at("2026-01-05T18:00:00Z")));
+        assert!(!cron.matches(at("2026-01-10T10:00:00Z"))); // Saturday
+        assert!(!cron.matches(at("2026-02-02T10:00:00Z")));
+        assert!(cron.matches(at("2026-07-01T17:30:00Z")));
+    }
+
+    #[test]
+    fn test_sunday_is_zero_or_seven() {
+        let zero = CronSchedule::parse("0 0 * * 0").unwrap();
+        let seven = CronSchedule::parse("0 0 * * 7").unwrap();
+        let sunday = at("2026-10-18T00:00:00Z");
+        assert!(zero.matches(sunday));
+        assert!(seven.matches(sunday));
+    }
+
+    #[test]
+    fn test_day_of_month_or_weekday() {
+        // The 1st of the month, and every Friday
+        let cron = CronSchedule::parse("0 0 1 * FRI").unwrap();
+        assert!(cron.matches(at("2026-10-01T00:00:00Z")));
+        assert!(cron.matches(at("2026-10-16T00:00:00Z")));
+        assert!(!cron.matches(at("2026-10-15T00:00:00Z")));
+    }
+
+    #[test]
+    fn test_rejects_invalid_cron() {
+        for expr in [
+            "* * * *",
+            "60 * * * *",
+            "* * * * MON-",
+            "*/0 * * * *",
+            "5-1 * * * *",
+        ] {
+            assert!(CronSchedule::parse(expr).is_err(), "{}", expr);
+        }
+    }
+
+    #[test]
+    fn test_next_match() {
+        let cron = CronSchedule::parse("30 17 * * FRI").unwrap();
+        assert_eq!(
+            cron.next_match(at("2026-10-14T12:00:00Z")),
+            Some(at("2026-10-16T17:30:00Z"))
+        );
+        assert_eq!(
+            cron.next_match(at("2026-10-16T17:30:00Z")),
+            Some(at("2026-10-16T17:30:00Z"))
+        );
+        assert_eq!(
+            cron.next_match(at("2026-10-16T17:30:01Z")),
+            Some(at("2026-10-23T17:30:00Z"))
+        );
+        let never = CronSchedule::parse("0 0 31 FEB *").unwrap();
+        assert_eq!(never.next_match(at("2026-01-01T00:00:00Z")), None);
+    }
+
+    #[test]
+    fn test_recurring_window() {
+        // Every weekend, Friday 17:00 to Monday 08:00
+        let weekend = FreezeSchedule::new(None, None, Some("0 17 * * FRI"), Some(63 * 60)).unwrap();
+        let occurrence = (at("2026-10-16T17:00:00Z"), at("2026-10-19T08:00:00Z"));
+        assert_eq!(weekend.active_at(at("2026-10-16T16:59:59Z")), None);
+        assert_eq!(
+            weekend.active_at(at("2026-10-16T17:00:00Z")),
+            Some(occurrence)
+        );
+        assert_eq!(
+            weekend.active_at(at("2026-10-18T12:00:00Z")),
+            Some(occurrence)
+        );
+        assert_eq!(weekend.active_at(at("2026-10-19T08:00:00Z")), None);
+
+        let october = weekend.occurrences(at("2026-10-01T00:00:00Z"), at("2026-11-01T00:00:00Z"));
+        assert_eq!(october.len(), 5);
+        assert_eq!(october[0].0, at("2026-10-02T17:00:00Z"));
+    }
+
+    #[test]
+    fn test_range_window() {
+        let holidays = FreezeSchedule::new(
+            Some(at("2026-12-20T00:00:00Z")),
+            Some(at("2027-01-04T00:00:00Z")),
+            None,
+            None,
+        )
+        .unwrap();
+        assert!(holidays.active_at(at("2026-12-25T12:00:00Z")).is_some());
+        assert!(holidays.active_at(at("2027-01-04T00:00:00Z")).is_none());
+        assert_eq!(
+            holidays
+                .occurrences(at("2026-12-01T00:00:00Z"), at("2027-01-01T00:00:00Z"))
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_windows() {
+        let now = at("2026-10-16T00:00:00Z");
+        assert!(FreezeSchedule::new(Some(now), Some(now), None, None).is_err());
+        assert!(FreezeSchedule::new(Some(now), None, Some("* * * * *"), Some(5)).is_err());
+        assert!(FreezeSchedule::new(None, None, Some("* * * * *"), Some(0)).is_err());
+        assert!(FreezeSchedule::new(None, None, Some("* * * * *"), Some(60 * 24 * 32)).is_err());
+    }
+}
@@ -0,0 +1,437 @@
+//! Artifact garbage collection.
+//!
+//! Tenants set an [`ArtifactRetention`]: keep the artifacts of each
+//! pipeline's last N runs, collect artifacts past an age, and cap the bytes
+//! all their artifacts take up. The collector periodically applies it to
+//! every tenant that has one, removing run artifacts and published reports
+//! from the artifact store and then from the database. Pinned artifacts are
+//! never collected. An object the store fails to delete keeps its row and is
+//! tried again on the next pass.
+
+use crate::metrics;
+use buildit_core::artifact::ArtifactStore;
+use buildit_core::tenant::ArtifactRetention;
+use buildit_db::repo::artifacts::{ArtifactRecord, ReportRecord};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// Artifact garbage collector settings.
+#[derive(Debug, Clone)]
+pub struct ArtifactGcConfig {
+    /// How often to look for artifacts to collect.
+    pub interval: std::time::Duration,
+}
+
+impl Default for ArtifactGcConfig {
+    fn default() -> Self {
+        Self {
+            interval: std::time::Duration::from_secs(3600),
+        }
+    }
+}
+
+impl ArtifactGcConfig {
+    /// Load settings from `BUILDIT_ARTIFACT_GC_*` environment variables.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            interval: std::env::var("BUILDIT_ARTIFACT_GC_INTERVAL_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|s: &u64| *s > 0)
+                .map(std::time::Duration::from_secs)
+                .unwrap_or(defaults.interval),
+        }
+    }
+}
+
+/// What kind of stored object a GC candidate is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectKind {
+    Artifact,
+    Report,
+}
+
+impl ObjectKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ObjectKind::Artifact => "artifact",
+            ObjectKind::Report => "report",
+        }
+    }
+}
+
+impl TryFrom<String> for ObjectKind {
+    type Error = String;
+
+    fn try_from(kind: String) -> Result<Self, Self::Error> {
+        match kind.as_str() {
+            "artifact" => Ok(ObjectKind::Artifact),
+            "report" => Ok(ObjectKind::Report),
+            other => Err(format!("unknown object kind '{}'", other)),
+        }
+    }
+}
+
+/// An artifact or report of one of a tenant's runs.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct StoredObject {
+    pub id: Uuid,
+    #[sqlx(try_from = "String")]
+    pub kind: ObjectKind,
+    pub pipeline_id: Uuid,
+    pub run_number: i64,
+    pub size_bytes: i64,
+    pub pinned: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Indexes of the objects `retention` collects at `now`.
+pub fn expired(
+    retention: &ArtifactRetention,
+    objects: &[StoredObject],
+    now: DateTime<Utc>,
+) -> BTreeSet<usize> {
+    let mut expired = BTreeSet::new();
+
+    if let Some(keep) = retention.keep_last_runs {
+        let mut runs: HashMap<Uuid, BTreeSet<i64>> = HashMap::new();
+        for object in objects {
+            runs.entry(object.pipeline_id)
+                .or_default()
+                .insert(object.run_number);
+        }
+        let oldest_kept: HashMap<Uuid, i64> = runs
+            .into_iter()
+            .filter_map(|(pipeline, numbers)| {
+                numbers
+                    .into_iter()
+                    .rev()
+                    .nth(keep.saturating_sub(1) as usize)
+                    .map(|n| (pipeline, n))
+            })
+            .collect();
+        for (i, object) in objects.iter().enumerate() {
+            if oldest_kept
+                .get(&object.pipeline_id)
+                .is_some_and(|oldest| object.run_number < *oldest)
+            {
+                expired.insert(i);
+            }
+        }
+    }
+
+    if let Some(days) = retention.max_age_days {
+        let cutoff = now - Duration::days(days as i64);
+        for (i, object) in objects.iter().enumerate() {
+            if object.created_at < cutoff {
+                expired.insert(i);
+            }
+        }
+    }
+
+    expired.retain(|i| !objects[*i].pinned);
+
+    if let Some(max_total) = retention.max_total_bytes {
+        let mut total: i64 = objects
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !expired.contains(i))
+            .map(|(_, o)| o.size_bytes)
+            .sum();
+        let mut oldest_first: Vec<usize> = (0..objects.len())
+            .filter(|i| !expired.contains(i) && !objects[*i].pinned)
+            .collect();
+        oldest_first.sort_by_key(|i| (objects[*i].created_at, objects[*i].run_number));
+        for i in oldest_first {
+            if total <= max_total as i64 {
+                break;
+            }
+            total -= objects[i].size_bytes;
+            expired.insert(i);
+        }
+    }
+
+    expired
+}
+
+/// What one collection pass removed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GcStats {
+    pub artifacts_deleted: u64,
+    pub reports_deleted: u64,
+    pub bytes_freed: u64,
+}
+
+/// Background task that applies the tenants' artifact retention.
+pub struct ArtifactGc {
+    config: ArtifactGcConfig,
+    pool: sqlx::PgPool,
+    store: Arc<dyn ArtifactStore>,
+}
+
+impl ArtifactGc {
+    pub fn new(
+        config: ArtifactGcConfig,
+        pool: sqlx::PgPool,
+        store: Arc<dyn ArtifactStore>,
+    ) -> Self {
+        Self {
+            config,
+            pool,
+            store,
+        }
+    }
+
+    /// Run the collector loop forever.
+    pub async fn run(self) {
+        info!(
+            interval_secs = self.config.interval.as_secs(),
+            "Artifact garbage collector started"
+        );
+
+        let mut ticker = tokio::time::interval(self.config.interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.collect().await {
+                error!(error = %e, "Artifact garbage collection failed");
+            }
+        }
+    }
+
+    /// Collect what every tenant's retention says is expired, once.
+    pub async fn collect(&self) -> Result<GcStats, sqlx::Error> {
+        let tenants: Vec<(Uuid, serde_json::Value)> = sqlx::query_as(
+            "SELECT id, artifact_retention FROM tenants WHERE artifact_retention <> '{}'::jsonb",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut stats = GcStats::default();
+        for (tenant_id, retention) in tenants {
+            let Ok(retention) = serde_json::from_value::<ArtifactRetention>(retention) else {
+                warn!(tenant_id = %tenant_id, "Ignoring unreadable artifact retention");
+                continue;
+            };
+            if retention.is_unlimited() {
+                continue;
+            }
+            self.collect_tenant(tenant_id, &retention, &mut stats)
+                .await?;
+        }
+        if stats != GcStats::default() {
+            info!(
+                artifacts = stats.artifacts_deleted,
+                reports = stats.reports_deleted,
+                bytes = stats.bytes_freed,
+                "Collected expired artifacts"
+            );
+        }
+        Ok(stats)
+    }
+
+    async fn collect_tenant(
+        &self,
+        tenant_id: Uuid,
+        retention: &ArtifactRetention,
+        stats: &mut GcStats,
+    ) -> Result<(), sqlx::Error> {
+        let objects = sqlx::query_as::<_, StoredObject>(
+            r#"
+            SELECT a.id, 'artifact' AS kind, r.pipeline_id, r.number AS run_number,
+                   a.size_bytes, a.pinned, a.created_at
+            FROM run_artifacts a
+            JOIN pipeline_runs r ON r.id = a.pipeline_run_id
+            JOIN pipelines p ON p.id = r.pipeline_id
+            WHERE p.tenant_id = $1
+            UNION ALL
+            SELECT rp.id, 'report' AS kind, r.pipeline_id, r.number AS run_number,
+                   rp.size_bytes, FALSE AS pinned, rp.created_at
+            FROM run_reports rp
+            JOIN pipeline_runs r ON r.id = rp.pipeline_run_id
+            JOIN pipelines p ON p.id = r.pipeline_id
+            WHERE p.tenant_id = $1
+            "#,
+        )
+        .bind(tenant_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        for i in expired(retention, &objects, Utc::now()) {
+            let object = &objects[i];
+            let deleted = match object.kind {
+                ObjectKind::Artifact => self.delete_artifact(object.id).await?,
+                ObjectKind::Report => self.delete_report(object.id).await?,
+            };
+            if !deleted {
+                continue;
+            }
+            match object.kind {
+                ObjectKind::Artifact => stats.artifacts_deleted += 1,
+                ObjectKind::Report => stats.reports_deleted += 1,
+            }
+            stats.bytes_freed += object.size_bytes.max(0) as u64;
+            metrics::record_artifact_collected(object.kind.as_str(), object.size_bytes);
+        }
+        Ok(())
+    }
+
+    /// Delete an artifact from the store, then its row unless it was pinned
+    /// in the meantime. Returns whether it was deleted.
+    async fn delete_artifact(&self, id: Uuid) -> Result<bool, sqlx::Error> {
+        let Some(artifact) = sqlx::query_as::<_, ArtifactRecord>(
+            "SELECT * FROM run_artifacts WHERE id = $1 AND NOT pinned",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?
+        else {
+            return Ok(false);
+        };
+        if let Err(e) = self.store.delete(&artifact.reference()).await {
+            warn!(artifact_id = %id, error = %e, "Failed to delete artifact from the store");
+            return Ok(false);
+        }
+        let result = sqlx::query("DELETE FROM run_artifacts WHERE id = $1 AND NOT pinned")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Delete every file of a report from the store, then its row. Returns
+    /// whether it was deleted.
+    async fn delete_report(&self, id: Uuid) -> Result<bool, sqlx::Error> {
+        let Some(report) =
+            sqlx::query_as::<_, ReportRecord>("SELECT * FROM run_reports WHERE id = $1")
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?
+        else {
+            return Ok(false);
+        };
+        let paths: Vec<String> = report
+            .files
+            .as_object()
+            .map(|files| files.keys().cloned().collect())
+            .unwrap_or_default();
+        for path in paths {
+            let Some(file) = report.file(&path) else {
+                continue;
+            };
+            if let Err(e) = self.store.delete(&report.reference(&path, &file)).await {
+                warn!(report_id = %id, path = %path, error = %e, "Failed to delete report file from the store");
+                return Ok(false);
+            }
+        }
+        let result = sqlx::query("DELETE FROM run_reports WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(pipeline: Uuid, run: i64, size: i64, age_days: i64) -> StoredObject {
+        StoredObject {
+            id: Uuid::now_v7(),
+            kind: ObjectKind::Artifact,
+            pipeline_id: pipeline,
+            run_number: run,
+            size_bytes: size,
+            pinned: false,
+            created_at: now() - Duration::days(age_days),
+        }
+    }
+
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2026-06-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn collected(retention: ArtifactRetention, objects: &[StoredObject]) -> Vec<usize> {
+        expired(&retention, objects, now()).into_iter().collect()
+    }
+
+    #[test]
+    fn test_unlimited_collects_nothing() {
+        let pipeline = Uuid::now_v7();
+        let objects = vec![object(pipeline, 1, 100, 400), object(pipeline, 2, 100, 1)];
+        assert!(collected(ArtifactRetention::default(), &objects).is_empty());
+    }
+
+    #[test]
+    fn test_keep_last_runs_per_pipeline() {
+        let (a, b) = (Uuid::now_v7(), Uuid::now_v7());
+        let objects = vec![
+            object(a, 1, 10, 3),
+            object(a, 2, 10, 2),
+            object(a, 2, 10, 2),
+            object(a, 3, 10, 1),
+            object(b, 7, 10, 5),
+        ];
+        let retention = ArtifactRetention {
+            keep_last_runs: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(collected(retention, &objects), vec![0]);
+    }
+
+    #[test]
+    fn test_max_age() {
+        let pipeline = Uuid::now_v7();
+        let objects = vec![object(pipeline, 1, 10, 31), object(pipeline, 2, 10, 29)];
+        let retention = ArtifactRetention {
+            max_age_days: Some(30),
+            ..Default::default()
+        };
+        assert_eq!(collected(retention, &objects), vec![0]);
+    }
+
+    #[test]
+    fn test_max_total_bytes_collects_oldest_first() {
+        let pipeline = Uuid::now_v7();
+        let objects = vec![
+            object(pipeline, 2, 300, 2),
+            object(pipeline, 1, 300, 3),
+            object(pipeline, 3, 300, 1),
+        ];
+        let retention = ArtifactRetention {
+            max_total_bytes: Some(600),
+            ..Default::default()
+        };
+        assert_eq!(collected(retention, &objects), vec![1]);
+    }
+
+    #[test]
+    fn test_pinned_are_never_collected_but_count_towards_total() {
+        let pipeline = Uuid::now_v7();
+        let mut release = object(pipeline, 1, 500, 90);
+        release.pinned = true;
+        let objects = vec![
+            release,
+            object(pipeline, 2, 300, 2),
+            object(pipeline, 3, 300, 1),
+        ];
+        let retention = ArtifactRetention {
+            keep_last_runs: Some(1),
+            max_age_days: Some(30),
+            max_total_bytes: Some(900),
+        };
+        assert_eq!(collected(retention, &objects), vec![1]);
+
+        let retention = ArtifactRetention {
+            max_total_bytes: Some(100),
+            ..Default::default()
+        };
+        assert_eq!(collected(retention, &objects), vec![1, 2]);
+    }
+}
@@ -1,12 +1,36 @@
 //! Job scheduling for BuildIt CI/CD.
 //!
-//! Manages the job queue and dispatches work to executors.
+//! Manages the job queue and dispatches work to executors, failing over
+//! between them when one is unhealthy and holding jobs to their tenant's
+//! quota, and runs the control plane's background tasks through the same
+//! queue, and explains why queued runs have not started. Also collects run
+//! artifacts past their tenant's retention, checks pipelines against
+//! their organization's pipeline policy and their stages' security context
+//! overrides against their tenant's stage security policy, and right-sizes stage resource
+//! requests from what their jobs used.
 //! Uses PostgreSQL with SKIP LOCKED for distributed job claiming.
 
+pub mod diagnosis;
+pub mod freeze;
+pub mod gc;
+mod metrics;
 pub mod orchestrator;
+pub mod policy;
 pub mod queue;
+pub mod quota;
+pub mod registry;
+pub mod rerun;
+pub mod rightsizing;
+pub mod scan;
+pub mod test_report;
 pub mod worker;
 
-pub use orchestrator::{PipelineEvent, PipelineOrchestrator, PipelineResult, StageState};
-pub use queue::JobQueue;
-pub use worker::Worker;
+pub use diagnosis::{QueueDiagnosis, QueueReason};
+pub use gc::{ArtifactGc, ArtifactGcConfig};
+pub use orchestrator::{
+    AdoptedJob, PipelineEvent, PipelineOrchestrator, PipelineResult, StageState,
+};
+pub use queue::{JobQueue, QueuedTask};
+pub use quota::QuotaTracker;
+pub use registry::{ExecutorRegistry, RoutingDecision, SkippedExecutor};
+pub use worker::{TaskContext, TaskHandler, TaskWorker, TaskWorkerConfig, Worker};
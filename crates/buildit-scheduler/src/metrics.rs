@@ -0,0 +1,71 @@
+//! Scheduler metrics, recorded with the `metrics` crate for whichever
+//! exporter the process installs.
+
+use buildit_core::executor::JobStatus;
+use chrono::{DateTime, Utc};
+
+/// Seconds from `from` to `to`, never negative.
+fn seconds(from: DateTime<Utc>, to: DateTime<Utc>) -> f64 {
+    (to - from).num_milliseconds().max(0) as f64 / 1000.0
+}
+
+/// How long a job waited in the queue before it was claimed.
+pub(crate) fn record_job_wait(created_at: DateTime<Utc>, claimed_at: DateTime<Utc>) {
+    metrics::histogram!("buildit_job_queue_wait_seconds").record(seconds(created_at, claimed_at));
+}
+
+/// How long a task waited to be claimed once it was due.
+pub(crate) fn record_task_wait(kind: &str, due_at: DateTime<Utc>, claimed_at: DateTime<Utc>) {
+    metrics::histogram!("buildit_task_queue_wait_seconds", "kind" => kind.to_string())
+        .record(seconds(due_at, claimed_at));
+}
+
+/// A task attempt that ended `completed`, `deferred`, `failed` or `panicked`.
+pub(crate) fn record_task(kind: &str, outcome: &'static str, elapsed: std::time::Duration) {
+    metrics::counter!("buildit_tasks_total", "kind" => kind.to_string(), "outcome" => outcome)
+        .increment(1);
+    metrics::histogram!("buildit_task_duration_seconds", "kind" => kind.to_string(), "outcome" => outcome)
+        .record(elapsed.as_secs_f64());
+}
+
+/// A stage job that finished on `executor`, with how long it ran.
+pub(crate) fn record_job(executor: &str, status: &JobStatus) {
+    let (label, started_at, finished_at) = match status {
+        JobStatus::Succeeded {
+            started_at,
+            finished_at,
+        } => ("succeeded", Some(*started_at), *finished_at),
+        JobStatus::Failed {
+            started_at,
+            finished_at,
+            ..
+        } => ("failed", *started_at, *finished_at),
+        JobStatus::Cancelled {
+            started_at,
+            cancelled_at,
+        } => ("cancelled", *started_at, *cancelled_at),
+        JobStatus::Pending | JobStatus::Running { .. } => return,
+    };
+    metrics::counter!("buildit_jobs_total", "executor" => executor.to_string(), "status" => label)
+        .increment(1);
+    if let Some(started_at) = started_at {
+        metrics::histogram!("buildit_job_duration_seconds", "executor" => executor.to_string(), "status" => label)
+            .record(seconds(started_at, finished_at));
+    }
+}
+
+/// Items in a queue by status, as sampled by [`crate::JobQueue::record_depth`].
+pub(crate) fn record_depth(queue: &'static str, pending: i64, claimed: i64, oldest_pending: f64) {
+    metrics::gauge!("buildit_queue_depth", "queue" => queue, "status" => "pending")
+        .set(pending as f64);
+    metrics::gauge!("buildit_queue_depth", "queue" => queue, "status" => "claimed")
+        .set(claimed as f64);
+    metrics::gauge!("buildit_queue_oldest_pending_seconds", "queue" => queue).set(oldest_pending);
+}
+
+/// An artifact or report (`kind`) removed by the artifact garbage collector.
+pub(crate) fn record_artifact_collected(kind: &'static str, bytes: i64) {
+    metrics::counter!("buildit_artifacts_collected_total", "kind" => kind).increment(1);
+    metrics::counter!("buildit_artifacts_collected_bytes_total", "kind" => kind)
+        .increment(bytes.max(0) as u64);
+}
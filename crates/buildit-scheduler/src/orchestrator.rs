@@ -1,17 +1,56 @@
 //! Pipeline orchestrator - executes pipeline stages in dependency order.
 
-use buildit_config::VariableContext;
+use buildit_config::env::is_valid_name;
+use buildit_config::{OUTPUT_MARKER, VariableContext};
 use buildit_core::ResourceId;
+use buildit_core::artifact::{ArtifactDependency, ArtifactDependencyRequest, ArtifactResolver};
+use buildit_core::child_pipeline::{
+    ChildRunRequest, ChildRunStatus, PipelineLauncher, TriggerPipelineSpec,
+};
 use buildit_core::executor::{
-    Executor, GitCloneSpec, JobSpec, JobStatus, LogLine, ResourceRequirements, VolumeMount,
+    Executor, GitCloneSpec, JobHandle, JobSpec, JobStatus, LogFrame, LogLine, LogStream, Os,
+    Platform, Probe, VolumeMount, WaitCondition,
 };
 use buildit_core::pipeline::{Pipeline, Stage, StageAction};
+use buildit_core::release::{ReleasePublisher, ReleaseRequest, ReleaseSpec};
+use buildit_core::scan::{Finding, ScanSpec, Scanner};
+use buildit_core::tenant::QuotaAction;
+use buildit_core::test_report::TestCase;
+use chrono::Utc;
 use futures::StreamExt;
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::mpsc;
-use tracing::{error, info};
+use tracing::{Instrument, Span, error, field, info, info_span, warn};
+use uuid::Uuid;
+
+use crate::metrics;
+use crate::quota::{self, Admission, JobOwner, JobResources, QuotaTracker, UsageSampler};
+use crate::registry::{Dispatch, ExecutorRegistry, RoutingDecision};
+use crate::{scan, test_report};
+
+/// How long a finished job's log stream may keep draining.
+const LOG_DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How often a running job's CPU and memory are read, and how long a
+/// reading may take.
+const USAGE_SAMPLE_INTERVAL: Duration = Duration::from_secs(15);
+const USAGE_SAMPLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Environment asking tools to color their output though a job has no
+/// terminal, unless the pipeline sets the variables itself.
+const COLOR_ENV: [(&str, &str); 2] = [("FORCE_COLOR", "1"), ("CLICOLOR_FORCE", "1")];
+
+/// Markers around a report (test results, scan results) a job writes to
+/// its log after its commands, and before each of the report's lines.
+const REPORT_START: &str = "##[buildit:report-start] ";
+const REPORT_LINE: &str = "##[buildit:report] ";
+const REPORT_END: &str = "##[buildit:report-end] ";
+
+/// Largest report read back from a job's log.
+const REPORT_LIMIT: usize = 32 * 1024 * 1024;
 
 /// State of a stage during execution.
 #[derive(Debug, Clone)]
@@ -39,10 +78,186 @@ impl StageState {
 /// Event emitted during pipeline execution.
 #[derive(Debug, Clone)]
 pub enum PipelineEvent {
-    StageStarted { stage: String },
-    StageLog { stage: String, line: LogLine },
-    StageCompleted { stage: String, success: bool },
-    PipelineCompleted { success: bool },
+    StageStarted {
+        stage: String,
+    },
+    /// A job was spawned for the stage; persisting the handle lets a
+    /// restarted server re-adopt the job, and the routing decision shows
+    /// which executor it went to and why.
+    StageDispatched {
+        stage: String,
+        handle: JobHandle,
+        routing: RoutingDecision,
+        /// Image the job runs, pinned to its digest, when it is pinned.
+        image_digest: Option<String>,
+    },
+    /// A line of the stage's log; `step` is the command it was written
+    /// by, and `frame` is set on the lines marking a command's start and
+    /// end, whose content is then the command echo or its exit code.
+    StageLog {
+        stage: String,
+        line: LogLine,
+        step: Option<u32>,
+        frame: Option<LogFrame>,
+    },
+    /// A test report the stage's job wrote, read from `path` in its
+    /// workspace, or why it could not be parsed.
+    TestReport {
+        stage: String,
+        path: String,
+        cases: Result<Vec<TestCase>, String>,
+    },
+    /// A scan stage's scanner ran against `target`: its findings, or why
+    /// they could not be read, and the image's CycloneDX SBOM if the stage
+    /// asked for one.
+    ScanCompleted {
+        stage: String,
+        scanner: Scanner,
+        target: String,
+        findings: Result<Vec<Finding>, String>,
+        sbom: Option<String>,
+    },
+    /// The stage finished; `exit_code` is its job's, when it exited with
+    /// one, and `error` says why it failed.
+    StageCompleted {
+        stage: String,
+        success: bool,
+        exit_code: Option<i32>,
+        error: Option<String>,
+    },
+    /// The stage was not run because a stage it needs did not succeed.
+    StageSkipped {
+        stage: String,
+        reason: String,
+    },
+    PipelineCompleted {
+        success: bool,
+    },
+}
+
+/// A job left running by an earlier execution of the pipeline, to be
+/// watched again instead of spawning the stage a second time.
+#[derive(Debug, Clone)]
+pub struct AdoptedJob {
+    pub handle: JobHandle,
+    /// Log lines already recorded; they are skipped when the job's log is
+    /// read again from the start.
+    pub logged_lines: usize,
+}
+
+/// Follows the step framing of a stage's log: the command each line was
+/// written by, and which lines are markers.
+#[derive(Default)]
+struct StepTracker {
+    current: Option<u32>,
+}
+
+impl StepTracker {
+    /// The step `line` belongs to and the frame it marks, if any. A
+    /// marker's content is replaced with its readable form.
+    fn track(&mut self, line: &mut LogLine) -> (Option<u32>, Option<LogFrame>) {
+        let Some(frame) = LogFrame::parse(&line.content) else {
+            return (self.current, None);
+        };
+        let step = frame.step();
+        self.current = match frame {
+            LogFrame::StepStarted { .. } => Some(step),
+            LogFrame::StepFinished { .. } => None,
+        };
+        line.content = frame.to_string();
+        line.stream = LogStream::System;
+        (Some(step), Some(frame))
+    }
+}
+
+/// A log line as seen by [`ReportCollector`].
+enum Collected {
+    /// An ordinary line of the log.
+    Log,
+    /// A line setting one of the stage's outputs, kept in the log.
+    Output { name: String, value: String },
+    /// A line of a test report, left out of the log.
+    Consumed,
+    /// The last line of a test report, with its path and contents.
+    Report { path: String, content: String },
+}
+
+/// Gathers the test reports a job writes to its log between report
+/// markers, so they reach the control plane whichever executor ran it.
+#[derive(Default)]
+struct ReportCollector {
+    current: Option<(String, String)>,
+}
+
+impl ReportCollector {
+    fn collect(&mut self, line: &LogLine) -> Collected {
+        if let Some((name, value)) = line
+            .content
+            .strip_prefix(OUTPUT_MARKER)
+            .and_then(|output| output.split_once('='))
+            && is_valid_name(name)
+        {
+            return Collected::Output {
+                name: name.to_string(),
+                value: value.trim_end_matches('\r').to_string(),
+            };
+        }
+        if let Some(path) = line.content.strip_prefix(REPORT_START) {
+            self.current = Some((path.to_string(), String::new()));
+            return Collected::Consumed;
+        }
+        let Some((path, content)) = &mut self.current else {
+            return Collected::Log;
+        };
+        if let Some(text) = line.content.strip_prefix(REPORT_LINE) {
+            if content.len() + text.len() < REPORT_LIMIT {
+                content.push_str(text);
+                content.push('\n');
+            }
+            return Collected::Consumed;
+        }
+        if line.content.strip_prefix(REPORT_END) == Some(path.as_str()) {
+            let (path, content) = self.current.take().unwrap_or_default();
+            return Collected::Report { path, content };
+        }
+        Collected::Log
+    }
+}
+
+/// What a job wrote to its log besides its ordinary lines.
+#[derive(Default)]
+struct JobOutput {
+    /// Reports, as paths and contents.
+    reports: Vec<(String, String)>,
+    /// Outputs the job set, by name.
+    outputs: HashMap<String, String>,
+}
+
+/// Quote `s` as a single `sh` word.
+pub(crate) fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Why a stage failed, with its job's exit code if it got that far.
+#[derive(Debug)]
+struct StageFailure {
+    message: String,
+    exit_code: Option<i32>,
+}
+
+impl From<String> for StageFailure {
+    fn from(message: String) -> Self {
+        Self {
+            message,
+            exit_code: None,
+        }
+    }
+}
+
+impl std::fmt::Display for StageFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
 }
 
 /// Result of a pipeline execution.
@@ -52,26 +267,395 @@ pub struct PipelineResult {
     pub stage_states: HashMap<String, StageState>,
 }
 
+/// The quota a pipeline's jobs are held to, and who they are accounted to.
+#[derive(Clone)]
+struct QuotaScope {
+    tracker: Arc<QuotaTracker>,
+    owner: JobOwner,
+}
+
+impl QuotaScope {
+    /// Wait until the stage's job fits its tenant's quota, unless the quota
+    /// says to refuse it or the job could never fit. Returns the job's
+    /// usage record.
+    async fn admit(
+        &self,
+        stage: &str,
+        job: JobResources,
+        tx: &mpsc::Sender<PipelineEvent>,
+    ) -> Result<Uuid, String> {
+        let mut waiting = false;
+        loop {
+            let admission = self
+                .tracker
+                .admit(self.owner, stage, job)
+                .await
+                .map_err(|e| format!("Failed to check quota: {}", e))?;
+            let (exceeded, action) = match admission {
+                Admission::Admitted(id) => {
+                    if waiting {
+                        info!(stage = %stage, "Job fits the quota again");
+                    }
+                    return Ok(id);
+                }
+                Admission::Denied { exceeded, action } => (exceeded, action),
+            };
+            if action == QuotaAction::Reject || exceeded.is_permanent() {
+                return Err(format!("Quota exceeded: {}", exceeded));
+            }
+            if !waiting {
+                info!(stage = %stage, reason = %exceeded, "Holding job for quota");
+                let _ = tx
+                    .send(PipelineEvent::StageLog {
+                        stage: stage.to_string(),
+                        line: LogLine {
+                            timestamp: Utc::now(),
+                            stream: LogStream::System,
+                            content: format!("Waiting for quota: {}", exceeded),
+                        },
+                        step: None,
+                        frame: None,
+                    })
+                    .await;
+                waiting = true;
+            }
+            tokio::time::sleep(quota::RETRY_INTERVAL.to_std().unwrap_or_default()).await;
+        }
+    }
+}
+
+/// Where a run's release stages are published, and for which run.
+struct ReleaseScope {
+    publisher: Arc<dyn ReleasePublisher>,
+    pipeline_id: ResourceId,
+    run_id: ResourceId,
+}
+
+impl ReleaseScope {
+    /// Publish a release stage's release, returning its URL as the stage's
+    /// `url` output.
+    async fn publish(
+        &self,
+        stage: &Stage,
+        spec: &ReleaseSpec,
+        var_ctx: &VariableContext,
+        tx: &mpsc::Sender<PipelineEvent>,
+    ) -> Result<HashMap<String, String>, StageFailure> {
+        let spec = ReleaseSpec {
+            tag: var_ctx.interpolate(&spec.tag),
+            name: spec.name.as_ref().map(|name| var_ctx.interpolate(name)),
+            notes: spec.notes.as_ref().map(|notes| var_ctx.interpolate(notes)),
+            assets: var_ctx.interpolate_vec(&spec.assets),
+            ..spec.clone()
+        };
+        // An unset `${git.tag}` is left as written
+        if spec.tag.is_empty() || spec.tag.contains("${") {
+            return Err(format!(
+                "No tag to release: '{}' has no value; run the stage for a tag or name one",
+                spec.tag
+            )
+            .into());
+        }
+
+        system_log(tx, &stage.name, format!("Publishing release {}", spec.tag)).await;
+        let request = ReleaseRequest {
+            pipeline_id: self.pipeline_id,
+            run_id: self.run_id,
+            stage: stage.name.clone(),
+            spec,
+        };
+        let published = self
+            .publisher
+            .publish(&request)
+            .await
+            .map_err(|e| format!("Failed to publish release {}: {}", request.spec.tag, e))?;
+        info!(stage = %stage.name, tag = %request.spec.tag, url = %published.url, "Published release");
+        let mut summary = format!(
+            "Published {} with {} commit{}",
+            published.url,
+            published.commits,
+            if published.commits == 1 { "" } else { "s" }
+        );
+        if !published.assets.is_empty() {
+            summary.push_str(&format!(" and {}", published.assets.join(", ")));
+        }
+        system_log(tx, &stage.name, summary).await;
+        Ok(HashMap::from([("url".to_string(), published.url)]))
+    }
+}
+
+/// Where a run's stages find the artifacts they need from other pipelines,
+/// and for which run.
+struct ArtifactScope {
+    resolver: Arc<dyn ArtifactResolver>,
+    pipeline_id: ResourceId,
+    run_id: ResourceId,
+}
+
+impl ArtifactScope {
+    /// Resolve the stage's artifact dependencies into commands fetching
+    /// them into the workspace, run before its own. The links are handed
+    /// to the job as environment variables so they stay out of its log.
+    async fn downloads(
+        &self,
+        stage: &Stage,
+        var_ctx: &VariableContext,
+        tx: &mpsc::Sender<PipelineEvent>,
+    ) -> Result<(Vec<String>, HashMap<String, String>), StageFailure> {
+        let mut commands = Vec::with_capacity(stage.needs_artifacts.len());
+        let mut links = HashMap::new();
+        for (index, dependency) in stage.needs_artifacts.iter().enumerate() {
+            let dependency = ArtifactDependency {
+                pipeline: var_ctx.interpolate(&dependency.pipeline),
+                artifact: var_ctx.interpolate(&dependency.artifact),
+                path: dependency
+                    .path
+                    .as_ref()
+                    .map(|path| var_ctx.interpolate(path)),
+                ..dependency.clone()
+            };
+            let request = ArtifactDependencyRequest {
+                pipeline_id: self.pipeline_id,
+                run_id: self.run_id,
+                stage: stage.name.clone(),
+                dependency,
+            };
+            let dependency = &request.dependency;
+            let resolved = self.resolver.resolve(&request).await.map_err(|e| {
+                format!(
+                    "Failed to find {} of {}: {}",
+                    dependency.artifact, dependency.pipeline, e
+                )
+            })?;
+            system_log(
+                tx,
+                &stage.name,
+                format!(
+                    "Fetching {} from {} #{} into {}",
+                    dependency.artifact,
+                    dependency.pipeline,
+                    resolved.run_number,
+                    dependency.destination()
+                ),
+            )
+            .await;
+
+            let variable = format!("BUILDIT_ARTIFACT_URL_{}", index);
+            let destination = dependency.destination();
+            commands.push(match stage.platform.os {
+                Os::Windows => format!(
+                    "curl.exe -fsSL --create-dirs -o \"{}\" \"%{}%\"",
+                    destination, variable
+                ),
+                Os::Linux | Os::Macos => format!(
+                    "mkdir -p \"$(dirname {dest})\" && if command -v curl >/dev/null; \
+                     then curl -fsSL -o {dest} \"${var}\"; else wget -qO {dest} \"${var}\"; fi",
+                    dest = shell_quote(&destination),
+                    var = variable
+                ),
+            });
+            links.insert(variable, resolved.url);
+        }
+        Ok((commands, links))
+    }
+}
+
+/// How often a child run a stage waits for is checked on.
+const CHILD_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Where a run's trigger-pipeline stages start their runs, and for which
+/// run.
+struct ChildScope {
+    launcher: Arc<dyn PipelineLauncher>,
+    pipeline_id: ResourceId,
+    run_id: ResourceId,
+}
+
+impl ChildScope {
+    /// Start a trigger-pipeline stage's run and, if the stage waits for it,
+    /// follow it to the end. The child's run ID, number and status are the
+    /// stage's outputs.
+    async fn trigger(
+        &self,
+        stage: &Stage,
+        spec: &TriggerPipelineSpec,
+        var_ctx: &VariableContext,
+        tx: &mpsc::Sender<PipelineEvent>,
+    ) -> Result<HashMap<String, String>, StageFailure> {
+        let spec = TriggerPipelineSpec {
+            pipeline: var_ctx.interpolate(&spec.pipeline),
+            branch: spec
+                .branch
+                .as_ref()
+                .map(|branch| var_ctx.interpolate(branch)),
+            params: spec
+                .params
+                .iter()
+                .map(|(name, value)| (name.clone(), var_ctx.interpolate(value)))
+                .collect(),
+            ..spec.clone()
+        };
+        let request = ChildRunRequest {
+            pipeline_id: self.pipeline_id,
+            run_id: self.run_id,
+            stage: stage.name.clone(),
+            spec,
+        };
+        let child = self
+            .launcher
+            .launch(&request)
+            .await
+            .map_err(|e| format!("Failed to trigger {}: {}", request.spec.pipeline, e))?;
+        info!(stage = %stage.name, pipeline = %request.spec.pipeline, child_run_id = %child.run_id, "Triggered pipeline");
+        system_log(
+            tx,
+            &stage.name,
+            format!("Triggered {} #{}", request.spec.pipeline, child.number),
+        )
+        .await;
+        let mut outputs = HashMap::from([
+            ("run_id".to_string(), child.run_id.to_string()),
+            ("run_number".to_string(), child.number.to_string()),
+        ]);
+        if !request.spec.wait {
+            return Ok(outputs);
+        }
+
+        let status = loop {
+            match self.launcher.status(&child).await {
+                Ok(ChildRunStatus::Pending) => {}
+                Ok(status) => break status,
+                // A blip reading the status isn't the child failing
+                Err(e) => {
+                    warn!(child_run_id = %child.run_id, error = %e, "Failed to check child run")
+                }
+            }
+            tokio::time::sleep(CHILD_POLL_INTERVAL).await;
+        };
+        system_log(
+            tx,
+            &stage.name,
+            format!(
+                "{} #{} {}",
+                request.spec.pipeline,
+                child.number,
+                status.as_str()
+            ),
+        )
+        .await;
+        outputs.insert("status".to_string(), status.as_str().to_string());
+        if status != ChildRunStatus::Succeeded && request.spec.propagate {
+            return Err(format!(
+                "{} #{} {}",
+                request.spec.pipeline,
+                child.number,
+                status.as_str()
+            )
+            .into());
+        }
+        Ok(outputs)
+    }
+}
+
+/// Write a line of the orchestrator's own to a stage's log.
+async fn system_log(tx: &mpsc::Sender<PipelineEvent>, stage: &str, content: String) {
+    let _ = tx
+        .send(PipelineEvent::StageLog {
+            stage: stage.to_string(),
+            line: LogLine {
+                timestamp: Utc::now(),
+                stream: LogStream::System,
+                content,
+            },
+            step: None,
+            frame: None,
+        })
+        .await;
+}
+
+/// Where the jobs of a run find their workspace, mounted at `/workspace`.
+#[derive(Debug, Clone, PartialEq)]
+enum Workspace {
+    /// A host directory
+    Directory(PathBuf),
+    /// A volume the executors keep for the run and remove after it
+    Volume(String),
+}
+
 /// Orchestrates the execution of a pipeline.
 pub struct PipelineOrchestrator {
-    executor: Arc<dyn Executor>,
+    executors: Arc<ExecutorRegistry>,
     /// Working directory to mount into containers
     working_dir: Option<PathBuf>,
+    /// Whether the jobs of a run share a workspace volume
+    shared_workspace: bool,
+    /// Holds jobs to their tenant's quota when set.
+    quota: Option<Arc<QuotaTracker>>,
+    /// Publishes release stages; they fail without one.
+    releases: Option<Arc<dyn ReleasePublisher>>,
+    /// Starts the runs of trigger-pipeline stages; they fail without one.
+    children: Option<Arc<dyn PipelineLauncher>>,
+    /// Finds the artifacts stages need from other pipelines; stages that
+    /// need one fail without it.
+    artifacts: Option<Arc<dyn ArtifactResolver>>,
 }
 
 impl PipelineOrchestrator {
     pub fn new(executor: Arc<dyn Executor>) -> Self {
+        Self::with_registry(ExecutorRegistry::single(executor))
+    }
+
+    /// Create an orchestrator that routes each job to the first healthy
+    /// executor of the registry able to run it.
+    pub fn with_registry(executors: ExecutorRegistry) -> Self {
         Self {
-            executor,
+            executors: Arc::new(executors),
             working_dir: None,
+            shared_workspace: false,
+            quota: None,
+            releases: None,
+            children: None,
+            artifacts: None,
         }
     }
 
+    /// Hold each job to its tenant's quota, recording its usage.
+    pub fn with_quota(mut self, quota: Arc<QuotaTracker>) -> Self {
+        self.quota = Some(quota);
+        self
+    }
+
+    /// Publish the releases of release stages with `publisher`.
+    pub fn with_releases(mut self, publisher: Arc<dyn ReleasePublisher>) -> Self {
+        self.releases = Some(publisher);
+        self
+    }
+
+    /// Start the runs of trigger-pipeline stages with `launcher`.
+    pub fn with_child_pipelines(mut self, launcher: Arc<dyn PipelineLauncher>) -> Self {
+        self.children = Some(launcher);
+        self
+    }
+
+    /// Find the artifacts stages need from other pipelines with `resolver`.
+    pub fn with_artifact_resolver(mut self, resolver: Arc<dyn ArtifactResolver>) -> Self {
+        self.artifacts = Some(resolver);
+        self
+    }
+
+    /// Whether to give the jobs of each run a workspace volume of their
+    /// own, which the repository is cloned into once and which is removed
+    /// after the run. A working directory takes precedence.
+    pub fn with_shared_workspace(mut self, shared: bool) -> Self {
+        self.shared_workspace = shared;
+        self
+    }
+
     /// Create an orchestrator with a working directory to mount into containers.
     pub fn with_working_dir(executor: Arc<dyn Executor>, working_dir: PathBuf) -> Self {
         Self {
-            executor,
             working_dir: Some(working_dir),
+            ..Self::new(executor)
         }
     }
 
@@ -91,6 +675,11 @@ impl PipelineOrchestrator {
         self.execute_with_git(pipeline, env, var_ctx, None)
     }
 
+    /// Executors jobs are routed to.
+    pub fn executors(&self) -> &ExecutorRegistry {
+        &self.executors
+    }
+
     /// Execute a pipeline with git repository cloning.
     ///
     /// If `git_clone` is provided, each stage will clone the repository before running commands.
@@ -103,28 +692,108 @@ impl PipelineOrchestrator {
     ) -> (
         mpsc::Receiver<PipelineEvent>,
         tokio::task::JoinHandle<PipelineResult>,
+    ) {
+        self.resume_with_git(pipeline, env, var_ctx, git_clone, HashMap::new())
+    }
+
+    /// Execute a pipeline, re-adopting jobs an interrupted execution left
+    /// running. Stages in `adopted` are watched to completion through their
+    /// existing job; they are only spawned again if the job is gone.
+    pub fn resume_with_git(
+        &self,
+        pipeline: &Pipeline,
+        env: HashMap<String, String>,
+        var_ctx: Option<VariableContext>,
+        git_clone: Option<GitCloneSpec>,
+        adopted: HashMap<String, AdoptedJob>,
+    ) -> (
+        mpsc::Receiver<PipelineEvent>,
+        tokio::task::JoinHandle<PipelineResult>,
     ) {
         let (tx, rx) = mpsc::channel(100);
-        let executor = self.executor.clone();
-        let working_dir = self.working_dir.clone();
+        let executors = self.executors.clone();
+        let tenant_id = pipeline.tenant_id;
         let stages = pipeline.stages.clone();
         let var_ctx = var_ctx.unwrap_or_default();
-
-        let handle = tokio::spawn(async move {
-            Self::execute_inner(executor, working_dir, stages, env, var_ctx, git_clone, tx).await
+        let workspace = match &self.working_dir {
+            Some(dir) => Some(Workspace::Directory(dir.clone())),
+            // Runs without an id, e.g. local ones, get a workspace anyway
+            None if self.shared_workspace => Some(Workspace::Volume(
+                Some(var_ctx.run.id.clone())
+                    .filter(|id| !id.is_empty())
+                    .unwrap_or_else(|| ResourceId::new().to_string()),
+            )),
+            None => None,
+        };
+        let quota = self.quota.clone().map(|tracker| QuotaScope {
+            tracker,
+            owner: JobOwner {
+                tenant_id: *pipeline.tenant_id.as_uuid(),
+                pipeline_id: *pipeline.id.as_uuid(),
+                run_id: var_ctx.run.id.parse().ok(),
+            },
+        });
+        let releases = self.releases.clone().map(|publisher| ReleaseScope {
+            publisher,
+            pipeline_id: pipeline.id,
+            run_id: var_ctx.run.id.parse().unwrap_or_default(),
         });
+        let children = self.children.clone().map(|launcher| ChildScope {
+            launcher,
+            pipeline_id: pipeline.id,
+            run_id: var_ctx.run.id.parse().unwrap_or_default(),
+        });
+        let artifacts = self.artifacts.clone().map(|resolver| ArtifactScope {
+            resolver,
+            pipeline_id: pipeline.id,
+            run_id: var_ctx.run.id.parse().unwrap_or_default(),
+        });
+
+        // Stage spans belong to the caller's, e.g. the run's
+        let handle = tokio::spawn(
+            async move {
+                let result = Self::execute_inner(
+                    executors.clone(),
+                    tenant_id,
+                    workspace.clone(),
+                    stages,
+                    env,
+                    var_ctx,
+                    git_clone,
+                    adopted,
+                    quota,
+                    releases,
+                    children,
+                    artifacts,
+                    tx,
+                )
+                .await;
+                if let Some(Workspace::Volume(volume)) = &workspace {
+                    executors.remove_workspace(volume).await;
+                }
+                result
+            }
+            .in_current_span(),
+        );
 
         (rx, handle)
     }
 
     /// Internal execution logic
+    #[allow(clippy::too_many_arguments)]
     async fn execute_inner(
-        executor: Arc<dyn Executor>,
-        working_dir: Option<PathBuf>,
+        executors: Arc<ExecutorRegistry>,
+        tenant_id: ResourceId,
+        workspace: Option<Workspace>,
         stages: Vec<Stage>,
         env: HashMap<String, String>,
         mut var_ctx: VariableContext,
         git_clone: Option<GitCloneSpec>,
+        mut adopted: HashMap<String, AdoptedJob>,
+        quota: Option<QuotaScope>,
+        releases: Option<ReleaseScope>,
+        children: Option<ChildScope>,
+        artifacts: Option<ArtifactScope>,
         tx: mpsc::Sender<PipelineEvent>,
     ) -> PipelineResult {
         let mut stage_states: HashMap<String, StageState> = stages
@@ -132,8 +801,16 @@ impl PipelineOrchestrator {
             .map(|s| (s.name.clone(), StageState::Pending))
             .collect();
 
+        // A run with a stage nothing can run is refused before any starts
+        if adopted.is_empty()
+            && let Some(result) = Self::refuse_unroutable(&executors, &stages, &tx).await
+        {
+            return result;
+        }
+
         // Build execution order using topological sort
         let execution_order = Self::topological_sort(&stages);
+        let images = Self::resolve_images(&executors, &stages, &execution_order, &var_ctx).await;
 
         for (stage_idx, stage_name) in execution_order.iter().enumerate() {
             let stage = stages.iter().find(|s| s.name == *stage_name).unwrap();
@@ -162,12 +839,20 @@ impl PipelineOrchestrator {
                     })
                     .collect();
                 info!(stage = %stage.name, ?failed_deps, "Skipping stage due to failed dependencies");
+                let reason = format!("Dependencies failed: {:?}", failed_deps);
+                var_ctx.record_stage(&stage.name, "skipped", HashMap::new());
                 stage_states.insert(
                     stage.name.clone(),
                     StageState::Skipped {
-                        reason: format!("Dependencies failed: {:?}", failed_deps),
+                        reason: reason.clone(),
                     },
                 );
+                let _ = tx
+                    .send(PipelineEvent::StageSkipped {
+                        stage: stage.name.clone(),
+                        reason,
+                    })
+                    .await;
                 continue;
             }
 
@@ -178,36 +863,66 @@ impl PipelineOrchestrator {
                 let _ = condition;
             }
 
-            // Execute the stage
-            let _ = tx
-                .send(PipelineEvent::StageStarted {
-                    stage: stage.name.clone(),
-                })
-                .await;
+            // Execute the stage; an adopted stage was already reported started
+            let adopted_job = adopted.remove(&stage.name);
+            if adopted_job.is_none() {
+                let _ = tx
+                    .send(PipelineEvent::StageStarted {
+                        stage: stage.name.clone(),
+                    })
+                    .await;
+            }
 
-            match Self::execute_stage(
-                &executor,
-                &working_dir,
+            let span = info_span!(
+                "stage",
+                run_id = %var_ctx.run.id,
+                stage = %stage.name,
+                executor = field::Empty,
+                status = field::Empty,
+            );
+            let result = Self::execute_stage(
+                &executors,
+                tenant_id,
+                &workspace,
+                &images,
                 stage,
                 &env,
                 &var_ctx,
                 &git_clone,
+                adopted_job,
+                quota.as_ref(),
+                releases.as_ref(),
+                children.as_ref(),
+                artifacts.as_ref(),
                 &tx,
             )
-            .await
-            {
-                Ok(()) => {
+            .instrument(span.clone())
+            .await;
+            span.record(
+                "status",
+                if result.is_ok() {
+                    "succeeded"
+                } else {
+                    "failed"
+                },
+            );
+            match result {
+                Ok(outputs) => {
                     info!(stage = %stage.name, "Stage completed successfully");
+                    var_ctx.record_stage(&stage.name, "succeeded", outputs);
                     stage_states.insert(stage.name.clone(), StageState::Succeeded);
                     let _ = tx
                         .send(PipelineEvent::StageCompleted {
                             stage: stage.name.clone(),
                             success: true,
+                            exit_code: Some(0),
+                            error: None,
                         })
                         .await;
                 }
                 Err(e) => {
                     error!(stage = %stage.name, error = %e, "Stage failed");
+                    var_ctx.record_stage(&stage.name, "failed", HashMap::new());
                     stage_states.insert(
                         stage.name.clone(),
                         StageState::Failed {
@@ -218,6 +933,8 @@ impl PipelineOrchestrator {
                         .send(PipelineEvent::StageCompleted {
                             stage: stage.name.clone(),
                             success: false,
+                            exit_code: e.exit_code,
+                            error: Some(e.message),
                         })
                         .await;
                 }
@@ -233,151 +950,727 @@ impl PipelineOrchestrator {
         }
     }
 
+    /// Fail the stages no executor has the labels or platform for, and skip
+    /// the others, if there are any such stages.
+    async fn refuse_unroutable(
+        executors: &ExecutorRegistry,
+        stages: &[Stage],
+        tx: &mpsc::Sender<PipelineEvent>,
+    ) -> Option<PipelineResult> {
+        let mut unroutable = HashMap::new();
+        for stage in stages {
+            if !matches!(stage.action, StageAction::Run { .. } | StageAction::Scan(_)) {
+                continue;
+            }
+            if let Err(e) = executors.check(&stage.runs_on, &stage.platform).await {
+                unroutable.insert(stage.name.clone(), e);
+            }
+        }
+        if unroutable.is_empty() {
+            return None;
+        }
+
+        let mut names: Vec<&String> = unroutable.keys().collect();
+        names.sort();
+        let reason = format!(
+            "No executor can run stage{} {}",
+            if names.len() == 1 { "" } else { "s" },
+            names
+                .iter()
+                .map(|n| format!("'{}'", n))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let mut stage_states = HashMap::new();
+        for stage in stages {
+            let state = match unroutable.remove(&stage.name) {
+                Some(message) => {
+                    error!(stage = %stage.name, error = %message, "Stage cannot be routed");
+                    let _ = tx
+                        .send(PipelineEvent::StageCompleted {
+                            stage: stage.name.clone(),
+                            success: false,
+                            exit_code: None,
+                            error: Some(message.clone()),
+                        })
+                        .await;
+                    StageState::Failed { message }
+                }
+                None => {
+                    let _ = tx
+                        .send(PipelineEvent::StageSkipped {
+                            stage: stage.name.clone(),
+                            reason: reason.clone(),
+                        })
+                        .await;
+                    StageState::Skipped {
+                        reason: reason.clone(),
+                    }
+                }
+            };
+            stage_states.insert(stage.name.clone(), state);
+        }
+        let _ = tx
+            .send(PipelineEvent::PipelineCompleted { success: false })
+            .await;
+        Some(PipelineResult {
+            success: false,
+            stage_states,
+        })
+    }
+
     /// Execute a single stage.
+    #[allow(clippy::too_many_arguments)]
     async fn execute_stage(
-        executor: &Arc<dyn Executor>,
-        working_dir: &Option<PathBuf>,
+        executors: &ExecutorRegistry,
+        tenant_id: ResourceId,
+        workspace: &Option<Workspace>,
+        images: &HashMap<String, String>,
         stage: &Stage,
         env: &HashMap<String, String>,
         var_ctx: &VariableContext,
         git_clone: &Option<GitCloneSpec>,
+        adopted: Option<AdoptedJob>,
+        quota: Option<&QuotaScope>,
+        releases: Option<&ReleaseScope>,
+        children: Option<&ChildScope>,
+        artifacts: Option<&ArtifactScope>,
         tx: &mpsc::Sender<PipelineEvent>,
-    ) -> Result<(), String> {
+    ) -> Result<HashMap<String, String>, StageFailure> {
         match &stage.action {
             StageAction::Run {
                 image,
                 commands,
                 artifacts: _,
+                test_reports,
             } => {
-                // Combine global env with stage env
-                let mut full_env = env.clone();
-                full_env.extend(stage.env.clone());
-
-                // Apply variable interpolation to environment values
-                let full_env = var_ctx.interpolate_map(&full_env);
-
-                // Apply variable interpolation to commands
-                let interpolated_commands = var_ctx.interpolate_vec(commands);
-
-                // Apply variable interpolation to image
-                let interpolated_image = var_ctx.interpolate(image);
-
-                // Build the job spec
-                // We'll run commands as a shell script
-                let script = interpolated_commands.join(" && ");
-                let command = vec!["/bin/sh".to_string(), "-c".to_string(), script];
-
-                // Build volume mounts - mount working directory if provided
-                let volumes = if let Some(wd) = working_dir {
-                    vec![VolumeMount {
-                        name: wd.to_string_lossy().to_string(),
-                        mount_path: "/workspace".to_string(),
-                        read_only: false,
-                    }]
+                // An adopted job already fetched what it needs
+                let (commands, env) = if stage.needs_artifacts.is_empty() || adopted.is_some() {
+                    (commands.clone(), env.clone())
                 } else {
-                    vec![]
+                    let Some(artifacts) = artifacts else {
+                        return Err(
+                            "Only the BuildIt server can fetch artifacts of other pipelines"
+                                .to_string()
+                                .into(),
+                        );
+                    };
+                    let (downloads, links) = artifacts.downloads(stage, var_ctx, tx).await?;
+                    let mut env = env.clone();
+                    env.extend(links);
+                    (downloads.into_iter().chain(commands.clone()).collect(), env)
                 };
+                let mut job_spec = Self::job_spec(
+                    stage,
+                    image,
+                    &commands,
+                    test_reports,
+                    &env,
+                    var_ctx,
+                    workspace,
+                    images,
+                    git_clone,
+                );
+                job_spec.tenant_id = Some(tenant_id);
+                let (result, output) =
+                    Self::run_job(executors, stage, job_spec, adopted, quota, tx).await;
+                for (path, content) in output.reports {
+                    let _ = tx
+                        .send(PipelineEvent::TestReport {
+                            stage: stage.name.clone(),
+                            cases: test_report::parse(&content),
+                            path,
+                        })
+                        .await;
+                }
+                result.map(|()| output.outputs)
+            }
+            StageAction::Scan(spec) => {
+                // Scanner images have the scanner as their entrypoint
+                let mut job_spec = Self::job_spec(
+                    stage,
+                    spec.image(),
+                    &scan::commands(spec),
+                    &scan::outputs(spec),
+                    env,
+                    var_ctx,
+                    workspace,
+                    images,
+                    git_clone,
+                );
+                job_spec.entrypoint = Some(vec![]);
+                job_spec.tenant_id = Some(tenant_id);
+                let (result, output) =
+                    Self::run_job(executors, stage, job_spec, adopted, quota, tx).await;
+                result?;
 
-                // Determine working directory based on git clone or default
-                let job_working_dir = if git_clone.is_some() {
-                    Some("/workspace".to_string())
-                } else if !volumes.is_empty() {
-                    Some("/workspace".to_string())
-                } else {
-                    None
+                let mut findings = Err(format!("{} wrote no results", spec.scanner));
+                let mut sbom = None;
+                for (path, content) in output.reports {
+                    match path.as_str() {
+                        scan::RESULTS_PATH => findings = scan::parse(spec.scanner, &content),
+                        scan::SBOM_PATH => sbom = Some(content),
+                        _ => {}
+                    }
+                }
+                let outcome = match &findings {
+                    Ok(findings) => Self::check_scan(spec, findings),
+                    Err(e) => Err(format!("Failed to read scan results: {}", e).into()),
                 };
+                let _ = tx
+                    .send(PipelineEvent::ScanCompleted {
+                        stage: stage.name.clone(),
+                        scanner: spec.scanner,
+                        target: var_ctx.interpolate(&spec.target),
+                        findings,
+                        sbom,
+                    })
+                    .await;
+                outcome.map(|()| output.outputs)
+            }
+            StageAction::Release(spec) => match releases {
+                Some(releases) => releases.publish(stage, spec, var_ctx, tx).await,
+                None => Err("Release stages can only be published by the BuildIt server"
+                    .to_string()
+                    .into()),
+            },
+            StageAction::TriggerPipeline(spec) => match children {
+                Some(children) => children.trigger(stage, spec, var_ctx, tx).await,
+                None => Err("Only the BuildIt server can trigger other pipelines"
+                    .to_string()
+                    .into()),
+            },
+            StageAction::ImageBuild { .. } => {
+                // TODO: Implement image building
+                Err("Image build not yet implemented".to_string().into())
+            }
+            StageAction::Deploy(_) => {
+                // TODO: Implement deployment
+                Err("Deploy not yet implemented".to_string().into())
+            }
+            StageAction::Parallel { .. } => {
+                // TODO: Implement parallel execution
+                Err("Parallel stages not yet implemented".to_string().into())
+            }
+            StageAction::Matrix { .. } => {
+                // TODO: Implement matrix builds
+                Err("Matrix builds not yet implemented".to_string().into())
+            }
+        }
+    }
 
-                let job_spec = JobSpec {
-                    id: ResourceId::new(),
-                    image: interpolated_image.clone(),
-                    command,
-                    working_dir: job_working_dir,
-                    env: full_env,
-                    resources: ResourceRequirements::default(),
-                    timeout: None,
-                    volumes,
-                    git_clone: git_clone.clone(),
-                };
+    /// Run a stage's job to completion, or watch the adopted one, streaming
+    /// its log. Returns how the job ended and the reports and outputs it
+    /// wrote to its log.
+    async fn run_job(
+        executors: &ExecutorRegistry,
+        stage: &Stage,
+        job_spec: JobSpec,
+        adopted: Option<AdoptedJob>,
+        quota: Option<&QuotaScope>,
+        tx: &mpsc::Sender<PipelineEvent>,
+    ) -> (Result<(), StageFailure>, JobOutput) {
+        let interpolated_image = job_spec.image.clone();
+        let image_digest = Some(interpolated_image.clone()).filter(|image| image.contains('@'));
+
+        // Re-adopt the job of an interrupted execution while its
+        // executor still knows it
+        let adopted = match adopted {
+            Some(job) => match executors.get(&job.handle.executor_name) {
+                Some(executor) => match executor.status(&job.handle).await {
+                    Ok(_) => {
+                        info!(stage = %stage.name, executor_id = %job.handle.executor_id, "Re-adopted running job");
+                        Some((executor, job))
+                    }
+                    Err(e) => {
+                        warn!(stage = %stage.name, error = %e, "Adopted job is gone, spawning it again");
+                        None
+                    }
+                },
+                None => None,
+            },
+            None => None,
+        };
 
-                info!(stage = %stage.name, image = %interpolated_image, "Spawning job");
+        // A re-adopted job is already running and only needs its
+        // usage recorded
+        let job_resources = JobResources::of(&stage.resources);
+        let usage = match (quota, &adopted) {
+            (Some(quota), Some(_)) => match quota
+                .tracker
+                .record(quota.owner, &stage.name, job_resources)
+                .await
+            {
+                Ok(usage) => Some(usage),
+                Err(e) => {
+                    return (
+                        Err(format!("Failed to record usage: {}", e).into()),
+                        JobOutput::default(),
+                    );
+                }
+            },
+            (Some(quota), None) => match quota.admit(&stage.name, job_resources, tx).await {
+                Ok(usage) => Some(usage),
+                Err(e) => return (Err(e.into()), JobOutput::default()),
+            },
+            (None, _) => None,
+        };
 
-                // Spawn the job
-                let handle = executor
-                    .spawn(job_spec)
-                    .await
-                    .map_err(|e| format!("Failed to spawn job: {}", e))?;
+        let (report_tx, mut report_rx) = mpsc::unbounded_channel();
+        let (output_tx, mut output_rx) = mpsc::unbounded_channel();
+        let mut measured = None;
+        let result = async {
+            let (executor, handle, logged_lines) = match adopted {
+                Some((executor, job)) => (executor, job.handle, job.logged_lines),
+                None => {
+                    info!(stage = %stage.name, image = %interpolated_image, "Spawning job");
+                    let dispatch = executors
+                        .spawn(job_spec, &stage.runs_on)
+                        .instrument(info_span!("executor.spawn", image = %interpolated_image))
+                        .await?;
+                    let _ = tx
+                        .send(PipelineEvent::StageDispatched {
+                            stage: stage.name.clone(),
+                            handle: dispatch.handle.clone(),
+                            routing: dispatch.routing,
+                            image_digest,
+                        })
+                        .await;
+                    (dispatch.executor, dispatch.handle, 0)
+                }
+            };
+            Span::current().record("executor", executor.name());
 
-                // Stream logs
-                let log_stream = executor
-                    .logs(&handle)
-                    .await
-                    .map_err(|e| format!("Failed to get logs: {}", e))?;
+            let log_stream = executor
+                .logs(&handle)
+                .await
+                .map_err(|e| format!("Failed to get logs: {}", e))?;
 
-                let stage_name = stage.name.clone();
-                let tx_clone = tx.clone();
+            let stage_name = stage.name.clone();
+            let tx_clone = tx.clone();
+            let report_tx = report_tx.clone();
+            let output_tx = output_tx.clone();
 
-                // Spawn a task to stream logs
-                let log_handle = tokio::spawn(async move {
+            // Spawn a task to stream logs, skipping lines recorded
+            // before a restart but following their steps
+            let log_span = info_span!("stage.logs", lines = field::Empty);
+            let log_handle = tokio::spawn(
+                async move {
                     let mut stream = log_stream;
-                    while let Some(line) = stream.next().await {
+                    let mut steps = StepTracker::default();
+                    let mut reports = ReportCollector::default();
+                    let mut lines = 0u64;
+                    while let Some(mut line) = stream.next().await {
+                        match reports.collect(&line) {
+                            Collected::Log => {}
+                            Collected::Output { name, value } => {
+                                let _ = output_tx.send((name, value));
+                            }
+                            Collected::Consumed => continue,
+                            Collected::Report { path, content } => {
+                                let _ = report_tx.send((path, content));
+                                continue;
+                            }
+                        }
+                        let (step, frame) = steps.track(&mut line);
+                        lines += 1;
+                        if lines <= logged_lines as u64 {
+                            continue;
+                        }
+                        Span::current().record("lines", lines);
                         let _ = tx_clone
                             .send(PipelineEvent::StageLog {
                                 stage: stage_name.clone(),
                                 line,
+                                step,
+                                frame,
                             })
                             .await;
                     }
-                });
+                }
+                .instrument(log_span),
+            );
 
-                // Wait for job completion
-                let result = executor
-                    .wait(&handle)
-                    .await
-                    .map_err(|e| format!("Failed to wait for job: {}", e))?;
+            // Wait for job completion, reading what it uses meanwhile
+            // when its usage is recorded
+            let wait = executor
+                .wait(&handle)
+                .instrument(info_span!("executor.wait"));
+            tokio::pin!(wait);
+            let mut sampler = usage.map(|_| UsageSampler::new(std::time::Instant::now()));
+            let mut samples = tokio::time::interval(USAGE_SAMPLE_INTERVAL);
+            let result = loop {
+                tokio::select! {
+                    result = &mut wait => break result,
+                    _ = samples.tick(), if sampler.is_some() => {
+                        let reading =
+                            tokio::time::timeout(USAGE_SAMPLE_TIMEOUT, executor.usage(&handle)).await;
+                        match reading {
+                            Ok(Ok(Some(reading))) => {
+                                if let Some(sampler) = &mut sampler {
+                                    sampler.sample(std::time::Instant::now(), reading);
+                                }
+                            }
+                            Ok(Ok(None)) => {}
+                            Ok(Err(e)) => {
+                                warn!(stage = %stage.name, error = %e, "Failed to read job usage");
+                            }
+                            Err(_) => {
+                                warn!(stage = %stage.name, "Timed out reading job usage");
+                            }
+                        }
+                    }
+                }
+            };
+            measured = sampler.and_then(|s| s.finish(std::time::Instant::now()));
+            let result = result.map_err(|e| format!("Failed to wait for job: {}", e))?;
 
-                // Abort log streaming task (it may still be following a stopped container)
+            // Let the log stream drain, but abort it if it is still
+            // following a stopped container
+            let mut log_handle = log_handle;
+            if tokio::time::timeout(LOG_DRAIN_TIMEOUT, &mut log_handle)
+                .await
+                .is_err()
+            {
                 log_handle.abort();
                 let _ = log_handle.await;
-
-                // Check result
-                match result.status {
-                    JobStatus::Succeeded { .. } => Ok(()),
-                    JobStatus::Failed { message, .. } => Err(format!("Job failed: {}", message)),
-                    JobStatus::Cancelled { .. } => Err("Job was cancelled".to_string()),
-                    _ => Err("Job ended in unexpected state".to_string()),
-                }
-            }
-            StageAction::ImageBuild { .. } => {
-                // TODO: Implement image building
-                Err("Image build not yet implemented".to_string())
-            }
-            StageAction::Deploy(_) => {
-                // TODO: Implement deployment
-                Err("Deploy not yet implemented".to_string())
-            }
-            StageAction::Parallel { .. } => {
-                // TODO: Implement parallel execution
-                Err("Parallel stages not yet implemented".to_string())
             }
-            StageAction::Matrix { .. } => {
-                // TODO: Implement matrix builds
-                Err("Matrix builds not yet implemented".to_string())
+
+            metrics::record_job(executor.name(), &result.status);
+
+            // Check result
+            match result.status {
+                JobStatus::Succeeded { .. } => Ok(()),
+                JobStatus::Failed {
+                    message, exit_code, ..
+                } => Err(StageFailure {
+                    message: format!("Job failed: {}", message),
+                    exit_code,
+                }),
+                JobStatus::Cancelled { .. } => Err("Job was cancelled".to_string().into()),
+                _ => Err("Job ended in unexpected state".to_string().into()),
             }
         }
-    }
-
-    /// Topological sort of stages based on dependencies.
-    fn topological_sort(stages: &[Stage]) -> Vec<String> {
-        let mut result = Vec::new();
-        let mut visited = HashMap::new();
-        let stage_map: HashMap<&str, &Stage> =
-            stages.iter().map(|s| (s.name.as_str(), s)).collect();
+        .await;
 
-        for stage in stages {
-            Self::topo_visit(&stage.name, &stage_map, &mut visited, &mut result);
+        if let (Some(quota), Some(usage_id)) = (quota, usage)
+            && let Err(e) = quota.tracker.finish(usage_id, measured).await
+        {
+            warn!(stage = %stage.name, error = %e, "Failed to finish usage record");
         }
 
-        result
+        let mut output = JobOutput::default();
+        while let Ok(report) = report_rx.try_recv() {
+            output.reports.push(report);
+        }
+        while let Ok((name, value)) = output_rx.try_recv() {
+            output.outputs.insert(name, value);
+        }
+        (result, output)
+    }
+
+    /// Whether a scan's findings pass its threshold.
+    fn check_scan(spec: &ScanSpec, findings: &[Finding]) -> Result<(), StageFailure> {
+        let violations = spec.violations(findings);
+        let Some(threshold) = spec.fail_on.filter(|_| !violations.is_empty()) else {
+            return Ok(());
+        };
+        let mut ids: Vec<&str> = violations
+            .iter()
+            .map(|f| f.vulnerability_id.as_str())
+            .collect();
+        ids.sort();
+        ids.dedup();
+        let shown = ids.iter().take(5).copied().collect::<Vec<_>>().join(", ");
+        Err(format!(
+            "{} vulnerabilit{} of {} severity or worse: {}{}",
+            ids.len(),
+            if ids.len() == 1 { "y" } else { "ies" },
+            threshold,
+            shown,
+            if ids.len() > 5 { ", ..." } else { "" }
+        )
+        .into())
+    }
+
+    /// Pin the image of each stage that runs a job to the digest its tag
+    /// resolves to when the run starts, so that every stage naming the
+    /// image runs the same one even if the tag moves during the run. Images
+    /// named after the outputs of earlier stages are only known later and
+    /// keep their tags, as do those no executor can resolve.
+    async fn resolve_images(
+        executors: &ExecutorRegistry,
+        stages: &[Stage],
+        execution_order: &[String],
+        var_ctx: &VariableContext,
+    ) -> HashMap<String, String> {
+        let mut var_ctx = var_ctx.clone();
+        let mut resolved = HashMap::new();
+        for (stage_idx, stage_name) in execution_order.iter().enumerate() {
+            let Some(stage) = stages.iter().find(|s| s.name == *stage_name) else {
+                continue;
+            };
+            let image = match &stage.action {
+                StageAction::Run { image, .. } => image.as_str(),
+                StageAction::Scan(spec) => spec.image(),
+                _ => continue,
+            };
+            var_ctx.stage.name = stage.name.clone();
+            var_ctx.stage.index = stage_idx;
+            let image = var_ctx.interpolate(image);
+            if image.contains("${") || resolved.contains_key(&image) {
+                continue;
+            }
+            let pinned = executors
+                .resolve_image(&image, stage.pull_policy, &stage.runs_on, &stage.platform)
+                .await;
+            if let Some(pinned) = &pinned {
+                info!(image = %image, pinned = %pinned, "Pinned image to its digest");
+            }
+            resolved.insert(image, pinned);
+        }
+        resolved
+            .into_iter()
+            .filter_map(|(image, pinned)| Some((image, pinned?)))
+            .collect()
+    }
+
+    /// The job running a `run` stage's commands, with the pipeline's and
+    /// the stage's environment and the run's workspace.
+    #[allow(clippy::too_many_arguments)]
+    fn job_spec(
+        stage: &Stage,
+        image: &str,
+        commands: &[String],
+        test_reports: &[String],
+        env: &HashMap<String, String>,
+        var_ctx: &VariableContext,
+        workspace: &Option<Workspace>,
+        images: &HashMap<String, String>,
+        git_clone: &Option<GitCloneSpec>,
+    ) -> JobSpec {
+        // Combine global env with stage env
+        let mut full_env = env.clone();
+        full_env.extend(stage.env.clone());
+
+        // Logs keep ANSI colors, so ask tools for them without a terminal
+        for (name, value) in COLOR_ENV {
+            full_env
+                .entry(name.to_string())
+                .or_insert_with(|| value.to_string());
+        }
+
+        // Apply variable interpolation to environment values
+        let full_env = var_ctx.interpolate_map(&full_env);
+
+        // Apply variable interpolation to commands and report paths
+        let interpolated_commands = var_ctx.interpolate_vec(commands);
+        let interpolated_reports = var_ctx.interpolate_vec(test_reports);
+
+        // Apply variable interpolation to image, running the digest the
+        // run pinned it to
+        let interpolated_image = var_ctx.interpolate(image);
+        let interpolated_image = images
+            .get(&interpolated_image)
+            .cloned()
+            .unwrap_or(interpolated_image);
+
+        // Apply variable interpolation to what the job waits for
+        let wait_for = stage
+            .wait_for
+            .iter()
+            .map(|condition| WaitCondition {
+                probe: match &condition.probe {
+                    Probe::Tcp { host, port } => Probe::Tcp {
+                        host: var_ctx.interpolate(host),
+                        port: *port,
+                    },
+                    Probe::Http { url } => Probe::Http {
+                        url: var_ctx.interpolate(url),
+                    },
+                    Probe::Shell { command } => Probe::Shell {
+                        command: var_ctx.interpolate(command),
+                    },
+                },
+                timeout_secs: condition.timeout_secs,
+            })
+            .collect();
+
+        // Build the job spec
+        // We'll run commands as a shell script
+        let script = Self::script(
+            &stage.platform,
+            &interpolated_commands,
+            &interpolated_reports,
+        );
+        let command = Self::shell(&stage.platform, script);
+
+        // Build volume mounts - mount working directory if provided
+        let volumes = if let Some(Workspace::Directory(wd)) = workspace {
+            vec![VolumeMount {
+                name: wd.to_string_lossy().to_string(),
+                mount_path: "/workspace".to_string(),
+                read_only: false,
+            }]
+        } else {
+            vec![]
+        };
+        let shared_workspace = match workspace {
+            Some(Workspace::Volume(volume)) => Some(volume.clone()),
+            _ => None,
+        };
+
+        // Determine working directory based on git clone or default
+        let job_working_dir = if git_clone.is_some() || workspace.is_some() {
+            Some("/workspace".to_string())
+        } else {
+            None
+        };
+
+        JobSpec {
+            id: ResourceId::new(),
+            image: interpolated_image,
+            command,
+            entrypoint: stage.container.entrypoint.clone(),
+            user: stage.container.user.clone(),
+            working_dir: job_working_dir,
+            env: full_env,
+            resources: stage.resources.clone(),
+            timeout: None,
+            volumes,
+            git_clone: git_clone.clone(),
+            platform: stage.platform,
+            wait_for,
+            security_context: stage.security_context.clone(),
+            privileged: stage.container.privileged,
+            extra_hosts: stage.container.extra_hosts.clone(),
+            network_mode: stage.container.network_mode.clone(),
+            workspace: shared_workspace,
+            pull_policy: stage.pull_policy,
+            tenant_id: None,
+        }
+    }
+
+    /// Script running `commands` in turn until one fails. With `sh` each
+    /// command is framed by markers echoing it and its exit code, and the
+    /// files matching `test_reports` are written to the log on exit,
+    /// whether the commands failed or not; `cmd` expands a whole line
+    /// before running any of it, so Windows jobs only chain the commands.
+    fn script(platform: &Platform, commands: &[String], test_reports: &[String]) -> String {
+        if platform.os == Os::Windows {
+            return commands.join(" && ");
+        }
+        let mut script = Vec::with_capacity(commands.len() * 5 + 2);
+        if !test_reports.is_empty() {
+            // Patterns are left unquoted for the shell to expand
+            script.push(format!(
+                "__buildit_reports() {{ for __buildit_report in {}; do \
+                 [ -f \"$__buildit_report\" ] || continue; \
+                 printf '%s\\n' \"{REPORT_START}$__buildit_report\"; \
+                 awk '{{ print \"{REPORT_LINE}\" $0 }}' \"$__buildit_report\"; \
+                 printf '%s\\n' \"{REPORT_END}$__buildit_report\"; done; }}",
+                test_reports.join(" ")
+            ));
+            script.push("trap __buildit_reports EXIT".to_string());
+        }
+        for (i, command) in commands.iter().enumerate() {
+            let step = i as u32 + 1;
+            // Markers are one line; a multi-line command echoes its first
+            let mut echo = command.lines().next().unwrap_or_default().to_string();
+            if command.trim_end().contains('\n') {
+                echo.push_str(" ...");
+            }
+            let start = LogFrame::StepStarted {
+                step,
+                command: echo,
+            };
+            script.push(format!("printf '%s\\n' {}", shell_quote(&start.marker())));
+            script.push(command.clone());
+            script.push("__buildit_status=$?".to_string());
+            script.push(format!(
+                "printf '%s\\n' \"##[buildit:step-end {}] $__buildit_status\"",
+                step
+            ));
+            script
+                .push("[ \"$__buildit_status\" -eq 0 ] || exit \"$__buildit_status\"".to_string());
+        }
+        script.join("\n")
+    }
+
+    /// Command running `script` in the platform's shell: `cmd` on Windows,
+    /// `sh` elsewhere.
+    fn shell(platform: &Platform, script: String) -> Vec<String> {
+        match platform.os {
+            Os::Windows => vec![
+                "cmd".to_string(),
+                "/S".to_string(),
+                "/C".to_string(),
+                script,
+            ],
+            Os::Linux | Os::Macos => vec!["/bin/sh".to_string(), "-c".to_string(), script],
+        }
+    }
+
+    /// Spawn a copy of a stage's job that idles for `idle` instead of
+    /// running the stage's commands, so a shell can be opened in it with
+    /// the same image, environment and workspace. The job is not held to
+    /// the tenant's quota.
+    pub async fn spawn_debug(
+        &self,
+        pipeline: &Pipeline,
+        stage: &str,
+        env: HashMap<String, String>,
+        var_ctx: Option<VariableContext>,
+        git_clone: Option<GitCloneSpec>,
+        idle: Duration,
+    ) -> Result<Dispatch, String> {
+        let stage = pipeline
+            .stages
+            .iter()
+            .find(|s| s.name == stage)
+            .ok_or_else(|| format!("Stage '{}' is not part of the pipeline", stage))?;
+        let StageAction::Run {
+            image, commands, ..
+        } = &stage.action
+        else {
+            return Err(format!("Stage '{}' does not run a job", stage.name));
+        };
+        let mut var_ctx = var_ctx.unwrap_or_default();
+        var_ctx.stage.name = stage.name.clone();
+        let mut spec = Self::job_spec(
+            stage,
+            image,
+            commands,
+            &[],
+            &env,
+            &var_ctx,
+            &self.working_dir.clone().map(Workspace::Directory),
+            &HashMap::new(),
+            &git_clone,
+        );
+        let sleep = match stage.platform.os {
+            Os::Windows => format!("ping -n {} 127.0.0.1 >NUL", idle.as_secs() + 1),
+            Os::Linux | Os::Macos => format!("sleep {}", idle.as_secs()),
+        };
+        spec.command = Self::shell(&stage.platform, sleep);
+        spec.timeout = Some(idle);
+        spec.tenant_id = Some(pipeline.tenant_id);
+        info!(stage = %stage.name, image = %spec.image, "Spawning debug job");
+        self.executors.spawn(spec, &stage.runs_on).await
+    }
+
+    /// Topological sort of stages based on dependencies.
+    fn topological_sort(stages: &[Stage]) -> Vec<String> {
+        let mut result = Vec::new();
+        let mut visited = HashMap::new();
+        let stage_map: HashMap<&str, &Stage> =
+            stages.iter().map(|s| (s.name.as_str(), s)).collect();
+
+        for stage in stages {
+            Self::topo_visit(&stage.name, &stage_map, &mut visited, &mut result);
+        }
+
+        result
     }
 
     fn topo_visit(
@@ -405,7 +1698,12 @@ impl PipelineOrchestrator {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use buildit_core::pipeline::StageAction;
+    use buildit_config::VariableContextBuilder;
+    use buildit_core::artifact::{ArtifactVersion, ResolvedArtifact};
+    use buildit_core::child_pipeline::ChildRun;
+    use buildit_core::executor::PullPolicy;
+    use buildit_core::pipeline::{ContainerOptions, StageAction};
+    use buildit_core::release::PublishedRelease;
 
     fn make_stage(name: &str, needs: Vec<&str>) -> Stage {
         Stage {
@@ -417,8 +1715,17 @@ mod tests {
                 image: "alpine".to_string(),
                 commands: vec!["echo hello".to_string()],
                 artifacts: vec![],
+                test_reports: vec![],
             },
             env: HashMap::new(),
+            runs_on: vec![],
+            resources: Default::default(),
+            platform: Default::default(),
+            wait_for: vec![],
+            needs_artifacts: vec![],
+            security_context: None,
+            container: Default::default(),
+            pull_policy: None,
         }
     }
 
@@ -441,6 +1748,898 @@ mod tests {
         assert!(build_idx < deploy_idx);
     }
 
+    /// Executor whose only job is already running: spawning fails, and the
+    /// log replays three lines from the start.
+    struct RunningJobExecutor;
+
+    #[async_trait::async_trait]
+    impl Executor for RunningJobExecutor {
+        fn name(&self) -> &'static str {
+            "running"
+        }
+
+        async fn can_execute(&self, _spec: &JobSpec) -> bool {
+            true
+        }
+
+        async fn spawn(
+            &self,
+            _spec: JobSpec,
+        ) -> buildit_core::Result<buildit_core::executor::JobHandle> {
+            Err(buildit_core::Error::Internal("spawned twice".to_string()))
+        }
+
+        async fn logs(
+            &self,
+            _handle: &buildit_core::executor::JobHandle,
+        ) -> buildit_core::Result<futures::stream::BoxStream<'static, LogLine>> {
+            let lines = ["one", "two", "three"].map(|content| LogLine {
+                timestamp: chrono::Utc::now(),
+                stream: buildit_core::executor::LogStream::Stdout,
+                content: content.to_string(),
+            });
+            Ok(Box::pin(futures::stream::iter(lines)))
+        }
+
+        async fn status(
+            &self,
+            _handle: &buildit_core::executor::JobHandle,
+        ) -> buildit_core::Result<JobStatus> {
+            Ok(JobStatus::Running {
+                started_at: chrono::Utc::now(),
+            })
+        }
+
+        async fn wait(
+            &self,
+            _handle: &buildit_core::executor::JobHandle,
+        ) -> buildit_core::Result<buildit_core::executor::JobResult> {
+            let now = chrono::Utc::now();
+            Ok(buildit_core::executor::JobResult {
+                status: JobStatus::Succeeded {
+                    started_at: now,
+                    finished_at: now,
+                },
+                exit_code: Some(0),
+                artifacts: vec![],
+            })
+        }
+
+        async fn cancel(
+            &self,
+            _handle: &buildit_core::executor::JobHandle,
+        ) -> buildit_core::Result<()> {
+            Ok(())
+        }
+
+        async fn exec_interactive(
+            &self,
+            _handle: &buildit_core::executor::JobHandle,
+            _cmd: Vec<String>,
+        ) -> buildit_core::Result<buildit_core::executor::TerminalSession> {
+            unimplemented!()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resume_adopts_running_job() {
+        let pipeline = Pipeline {
+            id: ResourceId::new(),
+            name: "resume".to_string(),
+            tenant_id: ResourceId::new(),
+            repository: String::new(),
+            triggers: vec![],
+            stages: vec![make_stage("build", vec![])],
+            env: HashMap::new(),
+            caches: vec![],
+            ownership: Default::default(),
+            checkout: Default::default(),
+            params: vec![],
+        };
+        let adopted = HashMap::from([(
+            "build".to_string(),
+            AdoptedJob {
+                handle: JobHandle {
+                    id: ResourceId::new(),
+                    executor_id: "container".to_string(),
+                    executor_name: "running".to_string(),
+                },
+                logged_lines: 2,
+            },
+        )]);
+
+        let orchestrator = PipelineOrchestrator::new(Arc::new(RunningJobExecutor));
+        let (mut rx, result) =
+            orchestrator.resume_with_git(&pipeline, HashMap::new(), None, None, adopted);
+        let mut events = Vec::new();
+        while let Some(event) = rx.recv().await {
+            events.push(event);
+        }
+
+        assert!(result.await.unwrap().success);
+        // Not started or dispatched again, and only the unrecorded line logged
+        assert!(!events.iter().any(|e| matches!(
+            e,
+            PipelineEvent::StageStarted { .. } | PipelineEvent::StageDispatched { .. }
+        )));
+        let logged: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                PipelineEvent::StageLog { line, .. } => Some(line.content.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(logged, ["three"]);
+    }
+
+    /// Executor whose jobs all exit with code 2.
+    struct FailingExecutor;
+
+    #[async_trait::async_trait]
+    impl Executor for FailingExecutor {
+        fn name(&self) -> &'static str {
+            "failing"
+        }
+
+        async fn can_execute(&self, _spec: &JobSpec) -> bool {
+            true
+        }
+
+        async fn spawn(
+            &self,
+            spec: JobSpec,
+        ) -> buildit_core::Result<buildit_core::executor::JobHandle> {
+            Ok(JobHandle {
+                id: spec.id,
+                executor_id: "container".to_string(),
+                executor_name: "failing".to_string(),
+            })
+        }
+
+        async fn logs(
+            &self,
+            _handle: &buildit_core::executor::JobHandle,
+        ) -> buildit_core::Result<futures::stream::BoxStream<'static, LogLine>> {
+            Ok(Box::pin(futures::stream::empty()))
+        }
+
+        async fn status(
+            &self,
+            _handle: &buildit_core::executor::JobHandle,
+        ) -> buildit_core::Result<JobStatus> {
+            unimplemented!()
+        }
+
+        async fn wait(
+            &self,
+            _handle: &buildit_core::executor::JobHandle,
+        ) -> buildit_core::Result<buildit_core::executor::JobResult> {
+            let now = chrono::Utc::now();
+            Ok(buildit_core::executor::JobResult {
+                status: JobStatus::Failed {
+                    started_at: Some(now),
+                    finished_at: now,
+                    exit_code: Some(2),
+                    message: "exit status 2".to_string(),
+                },
+                exit_code: Some(2),
+                artifacts: vec![],
+            })
+        }
+
+        async fn cancel(
+            &self,
+            _handle: &buildit_core::executor::JobHandle,
+        ) -> buildit_core::Result<()> {
+            Ok(())
+        }
+
+        async fn exec_interactive(
+            &self,
+            _handle: &buildit_core::executor::JobHandle,
+            _cmd: Vec<String>,
+        ) -> buildit_core::Result<buildit_core::executor::TerminalSession> {
+            unimplemented!()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_stage_reports_exit_code_and_skips_dependents() {
+        let pipeline = Pipeline {
+            id: ResourceId::new(),
+            name: "failing".to_string(),
+            tenant_id: ResourceId::new(),
+            repository: String::new(),
+            triggers: vec![],
+            stages: vec![
+                make_stage("build", vec![]),
+                make_stage("test", vec!["build"]),
+            ],
+            env: HashMap::new(),
+            caches: vec![],
+            ownership: Default::default(),
+            checkout: Default::default(),
+            params: vec![],
+        };
+
+        let orchestrator = PipelineOrchestrator::new(Arc::new(FailingExecutor));
+        let (mut rx, result) = orchestrator.execute(&pipeline, HashMap::new(), None);
+        let mut events = Vec::new();
+        while let Some(event) = rx.recv().await {
+            events.push(event);
+        }
+
+        assert!(!result.await.unwrap().success);
+        assert!(events.iter().any(|e| matches!(
+            e,
+            PipelineEvent::StageCompleted {
+                stage,
+                success: false,
+                exit_code: Some(2),
+                error: Some(_),
+            } if stage == "build"
+        )));
+        assert!(events.iter().any(|e| matches!(
+            e,
+            PipelineEvent::StageSkipped { stage, .. } if stage == "test"
+        )));
+    }
+
+    /// Executor that records the last job it spawned.
+    #[derive(Default)]
+    struct RecordingExecutor {
+        spawned: std::sync::Mutex<Option<JobSpec>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Executor for RecordingExecutor {
+        fn name(&self) -> &'static str {
+            "recording"
+        }
+
+        async fn can_execute(&self, _spec: &JobSpec) -> bool {
+            true
+        }
+
+        async fn spawn(
+            &self,
+            spec: JobSpec,
+        ) -> buildit_core::Result<buildit_core::executor::JobHandle> {
+            let handle = JobHandle {
+                id: spec.id,
+                executor_id: "container".to_string(),
+                executor_name: "recording".to_string(),
+            };
+            *self.spawned.lock().unwrap() = Some(spec);
+            Ok(handle)
+        }
+
+        async fn logs(
+            &self,
+            _handle: &buildit_core::executor::JobHandle,
+        ) -> buildit_core::Result<futures::stream::BoxStream<'static, LogLine>> {
+            unimplemented!()
+        }
+
+        async fn status(
+            &self,
+            _handle: &buildit_core::executor::JobHandle,
+        ) -> buildit_core::Result<JobStatus> {
+            unimplemented!()
+        }
+
+        async fn wait(
+            &self,
+            _handle: &buildit_core::executor::JobHandle,
+        ) -> buildit_core::Result<buildit_core::executor::JobResult> {
+            unimplemented!()
+        }
+
+        async fn cancel(
+            &self,
+            _handle: &buildit_core::executor::JobHandle,
+        ) -> buildit_core::Result<()> {
+            unimplemented!()
+        }
+
+        async fn exec_interactive(
+            &self,
+            _handle: &buildit_core::executor::JobHandle,
+            _cmd: Vec<String>,
+        ) -> buildit_core::Result<buildit_core::executor::TerminalSession> {
+            unimplemented!()
+        }
+    }
+
+    /// Executor whose jobs succeed, each setting a `VERSION` output, and
+    /// that records the jobs it spawned, the images it resolved and the
+    /// workspaces it removed. With `pin_images` it resolves images to a
+    /// digest.
+    #[derive(Default)]
+    struct OutputExecutor {
+        spawned: std::sync::Mutex<Vec<JobSpec>>,
+        pin_images: bool,
+        resolved: std::sync::Mutex<Vec<String>>,
+        removed_workspaces: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Executor for OutputExecutor {
+        fn name(&self) -> &'static str {
+            "output"
+        }
+
+        async fn can_execute(&self, _spec: &JobSpec) -> bool {
+            true
+        }
+
+        async fn spawn(
+            &self,
+            spec: JobSpec,
+        ) -> buildit_core::Result<buildit_core::executor::JobHandle> {
+            let handle = JobHandle {
+                id: spec.id,
+                executor_id: "container".to_string(),
+                executor_name: "output".to_string(),
+            };
+            self.spawned.lock().unwrap().push(spec);
+            Ok(handle)
+        }
+
+        async fn logs(
+            &self,
+            _handle: &buildit_core::executor::JobHandle,
+        ) -> buildit_core::Result<futures::stream::BoxStream<'static, LogLine>> {
+            let line = LogLine {
+                timestamp: chrono::Utc::now(),
+                stream: LogStream::Stdout,
+                content: format!("{}VERSION=1.4.2", OUTPUT_MARKER),
+            };
+            Ok(Box::pin(futures::stream::iter([line])))
+        }
+
+        async fn status(
+            &self,
+            _handle: &buildit_core::executor::JobHandle,
+        ) -> buildit_core::Result<JobStatus> {
+            unimplemented!()
+        }
+
+        async fn wait(
+            &self,
+            _handle: &buildit_core::executor::JobHandle,
+        ) -> buildit_core::Result<buildit_core::executor::JobResult> {
+            let now = chrono::Utc::now();
+            Ok(buildit_core::executor::JobResult {
+                status: JobStatus::Succeeded {
+                    started_at: now,
+                    finished_at: now,
+                },
+                exit_code: Some(0),
+                artifacts: vec![],
+            })
+        }
+
+        async fn cancel(
+            &self,
+            _handle: &buildit_core::executor::JobHandle,
+        ) -> buildit_core::Result<()> {
+            Ok(())
+        }
+
+        async fn resolve_image(
+            &self,
+            image: &str,
+            _pull_policy: Option<PullPolicy>,
+        ) -> buildit_core::Result<Option<String>> {
+            self.resolved.lock().unwrap().push(image.to_string());
+            Ok(self
+                .pin_images
+                .then(|| format!("{}@sha256:4a1c", image.split(':').next().unwrap())))
+        }
+
+        async fn remove_workspace(&self, workspace: &str) -> buildit_core::Result<()> {
+            self.removed_workspaces
+                .lock()
+                .unwrap()
+                .push(workspace.to_string());
+            Ok(())
+        }
+
+        async fn exec_interactive(
+            &self,
+            _handle: &buildit_core::executor::JobHandle,
+            _cmd: Vec<String>,
+        ) -> buildit_core::Result<buildit_core::executor::TerminalSession> {
+            unimplemented!()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_later_stages_see_earlier_outputs() {
+        let mut release = make_stage("release", vec!["build"]);
+        release.action = StageAction::Run {
+            image: "alpine".to_string(),
+            commands: vec![
+                "echo ${stages.build.outputs.VERSION} ${stages.build.status}".to_string(),
+            ],
+            artifacts: vec![],
+            test_reports: vec![],
+        };
+        let pipeline = Pipeline {
+            id: ResourceId::new(),
+            name: "outputs".to_string(),
+            tenant_id: ResourceId::new(),
+            repository: String::new(),
+            triggers: vec![],
+            stages: vec![make_stage("build", vec![]), release],
+            env: HashMap::new(),
+            caches: vec![],
+            ownership: Default::default(),
+            checkout: Default::default(),
+            params: vec![],
+        };
+        let executor = Arc::new(OutputExecutor::default());
+        let orchestrator = PipelineOrchestrator::new(executor.clone());
+
+        let (_events, handle) = orchestrator.execute(&pipeline, HashMap::new(), None);
+        assert!(handle.await.unwrap().success);
+
+        let spawned = executor.spawned.lock().unwrap();
+        let script = spawned[1].command.join(" ");
+        assert!(script.contains("echo 1.4.2 succeeded"), "{}", script);
+    }
+
+    #[tokio::test]
+    async fn test_stages_run_the_image_pinned_for_the_run() {
+        let mut build = make_stage("build", vec![]);
+        build.pull_policy = Some(PullPolicy::IfNotPresent);
+        let pipeline = Pipeline {
+            id: ResourceId::new(),
+            name: "pinned".to_string(),
+            tenant_id: ResourceId::new(),
+            repository: String::new(),
+            triggers: vec![],
+            stages: vec![build, make_stage("test", vec!["build"])],
+            env: HashMap::new(),
+            caches: vec![],
+            ownership: Default::default(),
+            checkout: Default::default(),
+            params: vec![],
+        };
+        let executor = Arc::new(OutputExecutor {
+            pin_images: true,
+            ..Default::default()
+        });
+        let orchestrator = PipelineOrchestrator::new(executor.clone());
+
+        let (mut events, handle) = orchestrator.execute(&pipeline, HashMap::new(), None);
+        let mut digests = vec![];
+        while let Some(event) = events.recv().await {
+            if let PipelineEvent::StageDispatched { image_digest, .. } = event {
+                digests.push(image_digest);
+            }
+        }
+        assert!(handle.await.unwrap().success);
+
+        // Resolved once when the run starts, for both stages
+        assert_eq!(*executor.resolved.lock().unwrap(), ["alpine"]);
+        let spawned = executor.spawned.lock().unwrap();
+        for spec in spawned.iter() {
+            assert_eq!(spec.image, "alpine@sha256:4a1c");
+        }
+        assert_eq!(spawned[0].pull_policy, Some(PullPolicy::IfNotPresent));
+        assert_eq!(spawned[1].pull_policy, None);
+        assert_eq!(digests, vec![Some("alpine@sha256:4a1c".to_string()); 2]);
+    }
+
+    #[tokio::test]
+    async fn test_stages_share_the_run_workspace() {
+        let pipeline = Pipeline {
+            id: ResourceId::new(),
+            name: "workspace".to_string(),
+            tenant_id: ResourceId::new(),
+            repository: String::new(),
+            triggers: vec![],
+            stages: vec![
+                make_stage("build", vec![]),
+                make_stage("test", vec!["build"]),
+            ],
+            env: HashMap::new(),
+            caches: vec![],
+            ownership: Default::default(),
+            checkout: Default::default(),
+            params: vec![],
+        };
+        let executor = Arc::new(OutputExecutor::default());
+        let orchestrator = PipelineOrchestrator::new(executor.clone()).with_shared_workspace(true);
+        let mut var_ctx = VariableContext::default();
+        var_ctx.run.id = "0191b3c4".to_string();
+
+        let (_events, handle) = orchestrator.execute(&pipeline, HashMap::new(), Some(var_ctx));
+        assert!(handle.await.unwrap().success);
+
+        let spawned = executor.spawned.lock().unwrap();
+        assert_eq!(spawned.len(), 2);
+        for spec in spawned.iter() {
+            assert_eq!(spec.workspace.as_deref(), Some("0191b3c4"));
+            assert_eq!(spec.working_dir.as_deref(), Some("/workspace"));
+            assert!(spec.volumes.is_empty());
+        }
+        assert_eq!(*executor.removed_workspaces.lock().unwrap(), ["0191b3c4"]);
+    }
+
+    #[derive(Default)]
+    struct FakePublisher {
+        published: std::sync::Mutex<Vec<ReleaseRequest>>,
+    }
+
+    #[async_trait::async_trait]
+    impl ReleasePublisher for FakePublisher {
+        async fn publish(&self, request: &ReleaseRequest) -> Result<PublishedRelease, String> {
+            self.published.lock().unwrap().push(request.clone());
+            Ok(PublishedRelease {
+                url: format!("https://example.com/releases/{}", request.spec.tag),
+                commits: 3,
+                assets: vec![],
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_release_stage_is_published_for_the_tag() {
+        let mut release = make_stage("release", vec!["build"]);
+        release.action = StageAction::Release(Box::new(ReleaseSpec {
+            tag: "${git.tag}".to_string(),
+            name: Some("Release ${git.tag}".to_string()),
+            notes: None,
+            changelog: true,
+            assets: vec!["dist-*".to_string()],
+            draft: false,
+            prerelease: false,
+        }));
+        let pipeline = Pipeline {
+            id: ResourceId::new(),
+            name: "release".to_string(),
+            tenant_id: ResourceId::new(),
+            repository: String::new(),
+            triggers: vec![],
+            stages: vec![make_stage("build", vec![]), release],
+            env: HashMap::new(),
+            caches: vec![],
+            ownership: Default::default(),
+            checkout: Default::default(),
+            params: vec![],
+        };
+        let publisher = Arc::new(FakePublisher::default());
+        let orchestrator = PipelineOrchestrator::new(Arc::new(OutputExecutor::default()))
+            .with_releases(publisher.clone());
+        let var_ctx = VariableContextBuilder::new().with_git_tag("v1.4.2").build();
+
+        let (_events, handle) = orchestrator.execute(&pipeline, HashMap::new(), Some(var_ctx));
+        assert!(handle.await.unwrap().success);
+
+        let published = publisher.published.lock().unwrap().clone();
+        assert_eq!(published.len(), 1);
+        assert_eq!(published[0].pipeline_id, pipeline.id);
+        assert_eq!(published[0].spec.tag, "v1.4.2");
+        assert_eq!(published[0].spec.name(), "Release v1.4.2");
+
+        // Without a tag there is nothing to release
+        let (_events, handle) = orchestrator.execute(&pipeline, HashMap::new(), None);
+        let result = handle.await.unwrap();
+        assert!(!result.success);
+        assert!(matches!(
+            result.stage_states["release"],
+            StageState::Failed { .. }
+        ));
+    }
+
+    struct FakeLauncher {
+        launched: std::sync::Mutex<Vec<ChildRunRequest>>,
+        outcome: ChildRunStatus,
+    }
+
+    #[async_trait::async_trait]
+    impl PipelineLauncher for FakeLauncher {
+        async fn launch(&self, request: &ChildRunRequest) -> Result<ChildRun, String> {
+            self.launched.lock().unwrap().push(request.clone());
+            Ok(ChildRun {
+                pipeline_id: ResourceId::new(),
+                run_id: ResourceId::new(),
+                number: 7,
+            })
+        }
+
+        async fn status(&self, _run: &ChildRun) -> Result<ChildRunStatus, String> {
+            Ok(self.outcome)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_trigger_pipeline_stage_takes_on_the_child_outcome() {
+        let mut deploy = make_stage("deploy", vec!["build"]);
+        deploy.action = StageAction::TriggerPipeline(Box::new(TriggerPipelineSpec {
+            pipeline: "deploy".to_string(),
+            branch: Some("${git.branch}".to_string()),
+            params: [("version".to_string(), "${git.sha}".to_string())].into(),
+            wait: true,
+            propagate: true,
+        }));
+        let mut pipeline = Pipeline {
+            id: ResourceId::new(),
+            name: "api".to_string(),
+            tenant_id: ResourceId::new(),
+            repository: String::new(),
+            triggers: vec![],
+            stages: vec![make_stage("build", vec![]), deploy],
+            env: HashMap::new(),
+            caches: vec![],
+            ownership: Default::default(),
+            checkout: Default::default(),
+            params: vec![],
+        };
+        let var_ctx = || {
+            VariableContextBuilder::new()
+                .with_git_branch("main")
+                .with_git_sha("abc123")
+                .build()
+        };
+
+        let launcher = Arc::new(FakeLauncher {
+            launched: Default::default(),
+            outcome: ChildRunStatus::Failed,
+        });
+        let orchestrator = PipelineOrchestrator::new(Arc::new(OutputExecutor::default()))
+            .with_child_pipelines(launcher.clone());
+        let (_events, handle) = orchestrator.execute(&pipeline, HashMap::new(), Some(var_ctx()));
+        let result = handle.await.unwrap();
+        assert!(!result.success);
+        assert!(matches!(
+            result.stage_states["deploy"],
+            StageState::Failed { .. }
+        ));
+        let launched = launcher.launched.lock().unwrap().clone();
+        assert_eq!(launched.len(), 1);
+        assert_eq!(launched[0].pipeline_id, pipeline.id);
+        assert_eq!(launched[0].spec.branch.as_deref(), Some("main"));
+        assert_eq!(launched[0].spec.params["version"], "abc123");
+
+        // Without propagation the child's failure is only reported
+        if let StageAction::TriggerPipeline(spec) = &mut pipeline.stages[1].action {
+            spec.propagate = false;
+        }
+        let (_events, handle) = orchestrator.execute(&pipeline, HashMap::new(), Some(var_ctx()));
+        assert!(handle.await.unwrap().success);
+
+        // Without a launcher there is nothing to start the child with
+        let orchestrator = PipelineOrchestrator::new(Arc::new(OutputExecutor::default()));
+        let (_events, handle) = orchestrator.execute(&pipeline, HashMap::new(), Some(var_ctx()));
+        assert!(!handle.await.unwrap().success);
+    }
+
+    #[derive(Default)]
+    struct FakeResolver {
+        resolved: std::sync::Mutex<Vec<ArtifactDependencyRequest>>,
+    }
+
+    #[async_trait::async_trait]
+    impl ArtifactResolver for FakeResolver {
+        async fn resolve(
+            &self,
+            request: &ArtifactDependencyRequest,
+        ) -> Result<ResolvedArtifact, String> {
+            self.resolved.lock().unwrap().push(request.clone());
+            Ok(ResolvedArtifact {
+                url: "https://ci.example.com/artifacts/signed".to_string(),
+                run_number: 12,
+                size: 1024,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stage_fetches_the_artifacts_it_needs() {
+        let mut build = make_stage("build", vec![]);
+        build.needs_artifacts = vec![ArtifactDependency {
+            pipeline: "libfoo".to_string(),
+            artifact: "dist/libfoo-${git.branch}.so".to_string(),
+            version: ArtifactVersion::LatestSuccessful,
+            path: Some("vendor/".to_string()),
+        }];
+        let pipeline = Pipeline {
+            id: ResourceId::new(),
+            name: "app".to_string(),
+            tenant_id: ResourceId::new(),
+            repository: String::new(),
+            triggers: vec![],
+            stages: vec![build],
+            env: HashMap::new(),
+            caches: vec![],
+            ownership: Default::default(),
+            checkout: Default::default(),
+            params: vec![],
+        };
+        let var_ctx = || {
+            VariableContextBuilder::new()
+                .with_git_branch("main")
+                .build()
+        };
+
+        let executor = Arc::new(OutputExecutor::default());
+        let resolver = Arc::new(FakeResolver::default());
+        let orchestrator =
+            PipelineOrchestrator::new(executor.clone()).with_artifact_resolver(resolver.clone());
+        let (_events, handle) = orchestrator.execute(&pipeline, HashMap::new(), Some(var_ctx()));
+        assert!(handle.await.unwrap().success);
+
+        let resolved = resolver.resolved.lock().unwrap().clone();
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved[0].pipeline_id, pipeline.id);
+        assert_eq!(resolved[0].dependency.artifact, "dist/libfoo-main.so");
+        let spawned = executor.spawned.lock().unwrap().clone();
+        assert_eq!(
+            spawned[0].env["BUILDIT_ARTIFACT_URL_0"],
+            "https://ci.example.com/artifacts/signed"
+        );
+        let script = spawned[0].command.join(" ");
+        assert!(script.contains("-o 'vendor/libfoo-main.so'"), "{}", script);
+        assert!(!script.contains("ci.example.com"), "{}", script);
+
+        // Without a resolver there is nothing to fetch them with
+        let orchestrator = PipelineOrchestrator::new(Arc::new(OutputExecutor::default()));
+        let (_events, handle) = orchestrator.execute(&pipeline, HashMap::new(), Some(var_ctx()));
+        assert!(!handle.await.unwrap().success);
+    }
+
+    #[tokio::test]
+    async fn test_spawn_debug_idles_in_the_stage_job() {
+        let mut stage = make_stage("build", vec![]);
+        stage
+            .env
+            .insert("TARGET".to_string(), "release".to_string());
+        let pipeline = Pipeline {
+            id: ResourceId::new(),
+            name: "debug".to_string(),
+            tenant_id: ResourceId::new(),
+            repository: String::new(),
+            triggers: vec![],
+            stages: vec![stage],
+            env: HashMap::new(),
+            caches: vec![],
+            ownership: Default::default(),
+            checkout: Default::default(),
+            params: vec![],
+        };
+        let executor = Arc::new(RecordingExecutor::default());
+        let orchestrator = PipelineOrchestrator::new(executor.clone());
+        let env = HashMap::from([("CI".to_string(), "true".to_string())]);
+
+        let dispatch = orchestrator
+            .spawn_debug(
+                &pipeline,
+                "build",
+                env,
+                None,
+                None,
+                Duration::from_secs(600),
+            )
+            .await
+            .unwrap();
+
+        let spec = executor.spawned.lock().unwrap().take().unwrap();
+        assert_eq!(dispatch.handle.id, spec.id);
+        assert_eq!(spec.image, "alpine");
+        assert_eq!(spec.command, ["/bin/sh", "-c", "sleep 600"]);
+        assert_eq!(spec.timeout, Some(Duration::from_secs(600)));
+        assert_eq!(spec.env["CI"], "true");
+        assert_eq!(spec.env["TARGET"], "release");
+        assert!(
+            orchestrator
+                .spawn_debug(
+                    &pipeline,
+                    "deploy",
+                    HashMap::new(),
+                    None,
+                    None,
+                    Duration::from_secs(600)
+                )
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refuses_run_with_a_stage_no_executor_runs() {
+        let mut package = make_stage("package", vec!["test"]);
+        package.platform = "windows/amd64".parse().unwrap();
+        let pipeline = Pipeline {
+            id: ResourceId::new(),
+            name: "release".to_string(),
+            tenant_id: ResourceId::new(),
+            repository: String::new(),
+            triggers: vec![],
+            stages: vec![make_stage("test", vec![]), package],
+            env: HashMap::new(),
+            caches: vec![],
+            ownership: Default::default(),
+            checkout: Default::default(),
+            params: vec![],
+        };
+        let executor = Arc::new(RecordingExecutor::default());
+        let orchestrator = PipelineOrchestrator::new(executor.clone());
+
+        let (_events, handle) = orchestrator.execute(&pipeline, HashMap::new(), None);
+        let result = handle.await.unwrap();
+
+        assert!(!result.success);
+        assert!(executor.spawned.lock().unwrap().is_none());
+        match &result.stage_states["package"] {
+            StageState::Failed { message } => {
+                assert!(message.contains("runs windows/amd64 jobs"), "{}", message)
+            }
+            other => panic!("package should have failed: {:?}", other),
+        }
+        assert!(matches!(
+            result.stage_states["test"],
+            StageState::Skipped { .. }
+        ));
+    }
+
+    #[test]
+    fn test_windows_stages_run_in_cmd() {
+        let mut stage = make_stage("build", vec![]);
+        stage.platform = "windows".parse().unwrap();
+        let spec = PipelineOrchestrator::job_spec(
+            &stage,
+            "mcr.microsoft.com/windows/servercore:ltsc2022",
+            &["dir".to_string(), "msbuild".to_string()],
+            &[],
+            &HashMap::new(),
+            &VariableContext::default(),
+            &None,
+            &HashMap::new(),
+            &None,
+        );
+        assert_eq!(spec.command, ["cmd", "/S", "/C", "dir && msbuild"]);
+        assert_eq!(spec.platform.os, Os::Windows);
+    }
+
+    #[test]
+    fn test_container_options_reach_the_job() {
+        let mut stage = make_stage("build", vec![]);
+        stage.container = ContainerOptions {
+            user: Some("root".to_string()),
+            entrypoint: Some(vec!["/usr/bin/tini".to_string(), "--".to_string()]),
+            privileged: true,
+            extra_hosts: vec!["registry.internal:10.0.0.5".to_string()],
+            network_mode: Some("host".to_string()),
+        };
+        let spec = PipelineOrchestrator::job_spec(
+            &stage,
+            "legacy-builder:1",
+            &["make".to_string()],
+            &[],
+            &HashMap::new(),
+            &VariableContext::default(),
+            &None,
+            &HashMap::new(),
+            &None,
+        );
+        assert_eq!(spec.user.as_deref(), Some("root"));
+        assert_eq!(
+            spec.entrypoint,
+            Some(vec!["/usr/bin/tini".to_string(), "--".to_string()])
+        );
+        assert!(spec.privileged);
+        assert_eq!(spec.extra_hosts, ["registry.internal:10.0.0.5"]);
+        assert_eq!(spec.network_mode.as_deref(), Some("host"));
+        // The commands still run through the shell, as the entrypoint's
+        // arguments
+        assert_eq!(spec.command[0], "/bin/sh");
+    }
+
+    #[allow(dead_code)]
     struct MockExecutor;
 
     #[async_trait::async_trait]
@@ -496,4 +2695,94 @@ mod tests {
             unimplemented!()
         }
     }
+
+    #[test]
+    fn test_script_frames_each_command_and_stops_at_failure() {
+        let commands = vec![
+            "echo 'it''s ok'".to_string(),
+            "sh -c 'exit 3'".to_string(),
+            "echo unreachable".to_string(),
+        ];
+        let script = PipelineOrchestrator::script(&Platform::default(), &commands, &[]);
+        let output = std::process::Command::new("/bin/sh")
+            .args(["-c", &script])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(3));
+
+        let mut steps = StepTracker::default();
+        let lines: Vec<_> = String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(|content| {
+                let mut line = LogLine {
+                    timestamp: Utc::now(),
+                    stream: LogStream::Stdout,
+                    content: content.to_string(),
+                };
+                let (step, frame) = steps.track(&mut line);
+                (line.content, step, frame.map(|f| f.kind()))
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                ("$ echo 'it''s ok'".to_string(), Some(1), Some("step_start")),
+                ("its ok".to_string(), Some(1), None),
+                ("Exited with code 0".to_string(), Some(1), Some("step_end")),
+                ("$ sh -c 'exit 3'".to_string(), Some(2), Some("step_start")),
+                ("Exited with code 3".to_string(), Some(2), Some("step_end")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_script_writes_test_reports_after_failure() {
+        let dir = std::env::temp_dir().join(format!("buildit-reports-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("reports")).unwrap();
+        let report = "<testsuite name=\"unit\">\n  <testcase name=\"a\"/>\n</testsuite>";
+        std::fs::write(dir.join("reports/unit.xml"), report).unwrap();
+
+        let commands = vec!["echo testing".to_string(), "sh -c 'exit 2'".to_string()];
+        let test_reports = vec!["reports/*.xml".to_string(), "missing.xml".to_string()];
+        let script = PipelineOrchestrator::script(&Platform::default(), &commands, &test_reports);
+        let output = std::process::Command::new("/bin/sh")
+            .args(["-c", &script])
+            .current_dir(&dir)
+            .output()
+            .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(output.status.code(), Some(2));
+
+        let mut collector = ReportCollector::default();
+        let mut logged = Vec::new();
+        let mut reports = Vec::new();
+        for content in String::from_utf8(output.stdout).unwrap().lines() {
+            let line = LogLine {
+                timestamp: Utc::now(),
+                stream: LogStream::Stdout,
+                content: content.to_string(),
+            };
+            match collector.collect(&line) {
+                Collected::Log | Collected::Output { .. } => logged.push(line.content),
+                Collected::Consumed => {}
+                Collected::Report { path, content } => reports.push((path, content)),
+            }
+        }
+        assert_eq!(logged.len(), 5);
+        assert_eq!(
+            reports,
+            [("reports/unit.xml".to_string(), format!("{}\n", report))]
+        );
+    }
+
+    #[test]
+    fn test_windows_script_chains_commands() {
+        let platform: Platform = "windows".parse().unwrap();
+        let commands = vec!["dir".to_string(), "echo done".to_string()];
+        assert_eq!(
+            PipelineOrchestrator::script(&platform, &commands, &["report.xml".to_string()]),
+            "dir && echo done"
+        );
+    }
 }
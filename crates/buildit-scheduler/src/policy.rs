@@ -0,0 +1,493 @@
+//! Checking pipelines against their organization's pipeline policy and
+//! their tenant's stage security policy.
+//!
+//! [`check`] lists every rule a pipeline's stages break, worded so the
+//! author knows what to change. It runs when a pipeline's config is stored,
+//! when a run is triggered and again when the run is dispatched, since the
+//! policy may have changed in between.
+//!
+//! [`check_security`] does the same for the security context overrides of
+//! a tenant's stages, against the tenant's stage security policy.
+
+use buildit_core::executor::SecurityContext;
+use buildit_core::pipeline::{ContainerOptions, Stage, StageAction};
+use buildit_core::policy::PipelinePolicy;
+use buildit_core::tenant::StageSecurityPolicy;
+
+use crate::quota::{JobResources, cpu_millis, memory_bytes};
+
+/// Check that a policy's resource caps parse, returning what is wrong.
+pub fn validate(policy: &PipelinePolicy) -> Result<(), String> {
+    if let Some(cpu) = &policy.max_cpu_request
+        && cpu_millis(cpu).is_none_or(|m| m == 0)
+    {
+        return Err(format!(
+            "max_cpu_request '{}' is not a positive CPU quantity",
+            cpu
+        ));
+    }
+    if let Some(memory) = &policy.max_memory_request
+        && memory_bytes(memory).is_none_or(|b| b == 0)
+    {
+        return Err(format!(
+            "max_memory_request '{}' is not a positive memory quantity",
+            memory
+        ));
+    }
+    if policy.allowed_images.iter().any(|i| i.trim().is_empty()) {
+        return Err("allowed_images cannot contain an empty entry".to_string());
+    }
+    Ok(())
+}
+
+/// The rules of `policy` the stages break, empty if they follow it.
+pub fn check(policy: &PipelinePolicy, stages: &[Stage]) -> Vec<String> {
+    let mut violations = Vec::new();
+    if policy.require_scan && !stages.iter().any(has_scan) {
+        violations.push(
+            "Pipeline has no security scan stage; the organization requires one (add a stage with a `scan` block)"
+                .to_string(),
+        );
+    }
+    for stage in stages {
+        check_stage(policy, stage, &mut violations);
+    }
+    violations
+}
+
+fn has_scan(stage: &Stage) -> bool {
+    match &stage.action {
+        StageAction::Scan(_) => true,
+        StageAction::Parallel { stages } => stages.iter().any(has_scan),
+        StageAction::Matrix { stage, .. } => has_scan(stage),
+        _ => false,
+    }
+}
+
+fn check_stage(policy: &PipelinePolicy, stage: &Stage, violations: &mut Vec<String>) {
+    let image = match &stage.action {
+        StageAction::Run { image, .. } => Some(image),
+        StageAction::Scan(scan) => scan.image.as_ref(),
+        StageAction::Parallel { stages } => {
+            for stage in stages {
+                check_stage(policy, stage, violations);
+            }
+            None
+        }
+        StageAction::Matrix { stage, .. } => {
+            check_stage(policy, stage, violations);
+            None
+        }
+        _ => None,
+    };
+    if let Some(image) = image
+        && !policy.allowed_images.is_empty()
+        && !policy
+            .allowed_images
+            .iter()
+            .any(|p| image_allowed(p, image))
+    {
+        violations.push(format!(
+            "Stage '{}' uses image '{}', which the organization does not allow; allowed images: {}",
+            stage.name,
+            image,
+            policy.allowed_images.join(", ")
+        ));
+    }
+
+    // What the stage takes up: its request, or its limit if it sets none
+    let resources = &stage.resources;
+    let requested = JobResources::of(resources);
+    if let Some(max) = &policy.max_cpu_request
+        && cpu_millis(max).is_some_and(|max| requested.cpu_millis > max)
+    {
+        let cpu = resources
+            .cpu_request
+            .as_ref()
+            .or(resources.cpu_limit.as_ref());
+        violations.push(format!(
+            "Stage '{}' requests {} CPU, over the organization's limit of {}",
+            stage.name,
+            cpu.map_or("", String::as_str),
+            max
+        ));
+    }
+    if let Some(max) = &policy.max_memory_request
+        && memory_bytes(max).is_some_and(|max| requested.memory_bytes > max)
+    {
+        let memory = resources
+            .memory_request
+            .as_ref()
+            .or(resources.memory_limit.as_ref());
+        violations.push(format!(
+            "Stage '{}' requests {} memory, over the organization's limit of {}",
+            stage.name,
+            memory.map_or("", String::as_str),
+            max
+        ));
+    }
+}
+
+/// The security context overrides of the stages that `policy` does not
+/// allow, empty if they follow it.
+pub fn check_security(policy: &StageSecurityPolicy, stages: &[Stage]) -> Vec<String> {
+    let mut violations = Vec::new();
+    for stage in stages {
+        check_stage_security(policy, stage, &mut violations);
+    }
+    violations
+}
+
+fn check_stage_security(policy: &StageSecurityPolicy, stage: &Stage, violations: &mut Vec<String>) {
+    match &stage.action {
+        StageAction::Parallel { stages } => {
+            for stage in stages {
+                check_stage_security(policy, stage, violations);
+            }
+        }
+        StageAction::Matrix { stage, .. } => check_stage_security(policy, stage, violations),
+        _ => {}
+    }
+    let mut loosened = stage
+        .security_context
+        .as_ref()
+        .map(|context| loosened(policy, context))
+        .unwrap_or_default();
+    for option in container_loosened(policy, &stage.container) {
+        if !loosened.contains(&option) {
+            loosened.push(option);
+        }
+    }
+    for loosened in loosened {
+        violations.push(format!(
+            "Stage '{}' {}, which the tenant's stage security policy does not allow",
+            stage.name, loosened
+        ));
+    }
+}
+
+/// What `context` loosens beyond what `policy` allows.
+fn loosened(policy: &StageSecurityPolicy, context: &SecurityContext) -> Vec<String> {
+    let mut loosened = Vec::new();
+    if !policy.allow_root
+        && (context.run_as_non_root == Some(false) || context.run_as_user == Some(0))
+    {
+        loosened.push("runs as root".to_string());
+    }
+    if !policy.allow_privilege_escalation && context.allow_privilege_escalation == Some(true) {
+        loosened.push("allows privilege escalation".to_string());
+    }
+    if !policy.allow_writable_root_filesystem && context.read_only_root_filesystem == Some(false) {
+        loosened.push("makes the root filesystem writable".to_string());
+    }
+    if !policy.allow_unconfined_seccomp
+        && context
+            .seccomp_profile
+            .as_deref()
+            .is_some_and(|p| p.eq_ignore_ascii_case("unconfined"))
+    {
+        loosened.push("runs without seccomp".to_string());
+    }
+    let any_capability = policy.allowed_capabilities.iter().any(|c| c == "*");
+    for capability in &context.add_capabilities {
+        if !any_capability
+            && !policy
+                .allowed_capabilities
+                .iter()
+                .any(|c| c.eq_ignore_ascii_case(capability))
+        {
+            loosened.push(format!("adds capability {}", capability));
+        }
+    }
+    loosened
+}
+
+/// What the container options loosen beyond what `policy` allows.
+fn container_loosened(policy: &StageSecurityPolicy, container: &ContainerOptions) -> Vec<String> {
+    let mut loosened = Vec::new();
+    if !policy.allow_root && container.runs_as_root() {
+        loosened.push("runs as root".to_string());
+    }
+    if !policy.allow_privileged && container.privileged {
+        loosened.push("runs privileged".to_string());
+    }
+    if let Some(network) = &container.network_mode
+        && !matches!(network.as_str(), "bridge" | "none")
+        && !policy
+            .allowed_network_modes
+            .iter()
+            .any(|n| n == "*" || n == network)
+    {
+        loosened.push(format!("joins network '{}'", network));
+    }
+    loosened
+}
+
+/// Whether the `allowed_images` entry `pattern` allows `image`.
+fn image_allowed(pattern: &str, image: &str) -> bool {
+    let pattern = pattern.trim();
+    if pattern == "*" {
+        return true;
+    }
+    let image = qualified(image);
+    let pattern = qualified(pattern);
+    if let Some(prefix) = pattern.strip_suffix('*') {
+        return image.starts_with(prefix);
+    }
+    if pattern.ends_with('/') {
+        return image.starts_with(&pattern);
+    }
+    image == pattern || repository(&image) == pattern
+}
+
+/// An image reference with its registry spelled out, so that `alpine` and
+/// `docker.io/library/alpine` are the same image.
+fn qualified(image: &str) -> String {
+    match image.split_once('/') {
+        None => format!("docker.io/library/{}", image),
+        Some((host, _)) if host.contains('.') || host.contains(':') || host == "localhost" => {
+            image.to_string()
+        }
+        Some(_) => format!("docker.io/{}", image),
+    }
+}
+
+/// An image reference without its tag or digest.
+fn repository(image: &str) -> &str {
+    let image = image.split_once('@').map_or(image, |(name, _)| name);
+    let name_start = image.rfind('/').map_or(0, |i| i + 1);
+    match image[name_start..].find(':') {
+        Some(tag) => &image[..name_start + tag],
+        None => image,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn run_stage(name: &str, image: &str) -> Stage {
+        Stage {
+            name: name.to_string(),
+            needs: vec![],
+            when: None,
+            manual: false,
+            action: StageAction::Run {
+                image: image.to_string(),
+                commands: vec!["make".to_string()],
+                artifacts: vec![],
+                test_reports: vec![],
+            },
+            env: HashMap::new(),
+            runs_on: vec![],
+            resources: Default::default(),
+            platform: Default::default(),
+            wait_for: vec![],
+            needs_artifacts: vec![],
+            security_context: None,
+            container: Default::default(),
+            pull_policy: None,
+        }
+    }
+
+    fn scan_stage(name: &str) -> Stage {
+        Stage {
+            action: StageAction::Scan(Box::new(
+                serde_json::from_value(serde_json::json!({ "target": "app:latest" })).unwrap(),
+            )),
+            ..run_stage(name, "")
+        }
+    }
+
+    #[test]
+    fn test_empty_policy_allows_anything() {
+        let stages = vec![run_stage("build", "anything:latest")];
+        assert!(check(&PipelinePolicy::default(), &stages).is_empty());
+    }
+
+    #[test]
+    fn test_require_scan() {
+        let policy = PipelinePolicy {
+            require_scan: true,
+            ..Default::default()
+        };
+        let violations = check(&policy, &[run_stage("build", "rust")]);
+        assert_eq!(violations.len(), 1);
+        assert!(violations[0].contains("no security scan stage"));
+
+        let parallel = Stage {
+            action: StageAction::Parallel {
+                stages: vec![scan_stage("scan")],
+            },
+            ..run_stage("checks", "")
+        };
+        assert!(check(&policy, &[run_stage("build", "rust"), parallel]).is_empty());
+    }
+
+    #[test]
+    fn test_image_patterns() {
+        assert!(image_allowed("rust", "rust:1.80"));
+        assert!(image_allowed("rust", "docker.io/library/rust"));
+        assert!(image_allowed("docker.io/library/alpine", "alpine:3.20"));
+        assert!(image_allowed("docker.io/*", "alpine"));
+        assert!(image_allowed("ghcr.io/acme/", "ghcr.io/acme/builder:1"));
+        assert!(image_allowed(
+            "ghcr.io/acme/*",
+            "ghcr.io/acme/builder@sha256:abc"
+        ));
+        assert!(image_allowed("localhost:5000/ci", "localhost:5000/ci:2"));
+        assert!(image_allowed("*", "quay.io/anything"));
+        assert!(!image_allowed("rust", "rustup:1"));
+        assert!(!image_allowed("ghcr.io/acme/*", "ghcr.io/other/builder"));
+        assert!(!image_allowed("docker.io/*", "ghcr.io/acme/builder"));
+    }
+
+    #[test]
+    fn test_disallowed_images_are_named() {
+        let policy = PipelinePolicy {
+            allowed_images: vec!["ghcr.io/acme/*".to_string()],
+            ..Default::default()
+        };
+        let matrix = Stage {
+            action: StageAction::Matrix {
+                variables: HashMap::new(),
+                stage: Box::new(run_stage("test", "node:20")),
+            },
+            ..run_stage("matrix", "")
+        };
+        let stages = vec![run_stage("build", "ghcr.io/acme/builder:1"), matrix];
+        let violations = check(&policy, &stages);
+        assert_eq!(violations.len(), 1);
+        assert!(violations[0].contains("Stage 'test' uses image 'node:20'"));
+        assert!(violations[0].contains("ghcr.io/acme/*"));
+    }
+
+    #[test]
+    fn test_resource_caps() {
+        let policy = PipelinePolicy {
+            max_cpu_request: Some("2".to_string()),
+            max_memory_request: Some("4Gi".to_string()),
+            ..Default::default()
+        };
+        let mut big = run_stage("build", "rust");
+        big.resources.cpu_request = Some("4".to_string());
+        // Without a request, the limit is what the stage takes up
+        big.resources.memory_limit = Some("8Gi".to_string());
+        let mut small = run_stage("lint", "rust");
+        small.resources.cpu_request = Some("500m".to_string());
+        small.resources.memory_request = Some("4Gi".to_string());
+
+        let violations = check(&policy, &[big, small]);
+        assert_eq!(
+            violations,
+            vec![
+                "Stage 'build' requests 4 CPU, over the organization's limit of 2".to_string(),
+                "Stage 'build' requests 8Gi memory, over the organization's limit of 4Gi"
+                    .to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stage_security() {
+        let mut root = run_stage("build", "rust");
+        root.security_context = Some(SecurityContext {
+            run_as_user: Some(0),
+            add_capabilities: vec!["SYS_ADMIN".to_string(), "NET_ADMIN".to_string()],
+            seccomp_profile: Some("Unconfined".to_string()),
+            ..Default::default()
+        });
+        // Tightening the defaults needs no permission
+        let mut strict = run_stage("lint", "rust");
+        strict.security_context = Some(SecurityContext {
+            run_as_non_root: Some(true),
+            read_only_root_filesystem: Some(true),
+            drop_capabilities: vec!["ALL".to_string()],
+            ..Default::default()
+        });
+        let parallel = Stage {
+            action: StageAction::Parallel {
+                stages: vec![root, strict],
+            },
+            ..run_stage("checks", "")
+        };
+        let stages = vec![parallel];
+
+        let violations = check_security(&StageSecurityPolicy::default(), &stages);
+        assert_eq!(
+            violations,
+            vec![
+                "Stage 'build' runs as root, which the tenant's stage security policy does not allow",
+                "Stage 'build' runs without seccomp, which the tenant's stage security policy does not allow",
+                "Stage 'build' adds capability SYS_ADMIN, which the tenant's stage security policy does not allow",
+                "Stage 'build' adds capability NET_ADMIN, which the tenant's stage security policy does not allow",
+            ]
+        );
+
+        let policy = StageSecurityPolicy {
+            allow_root: true,
+            allow_unconfined_seccomp: true,
+            allowed_capabilities: vec!["net_admin".to_string()],
+            ..Default::default()
+        };
+        let violations = check_security(&policy, &stages);
+        assert_eq!(violations.len(), 1);
+        assert!(violations[0].contains("adds capability SYS_ADMIN"));
+
+        let policy = StageSecurityPolicy {
+            allowed_capabilities: vec!["*".to_string()],
+            ..policy
+        };
+        assert!(check_security(&policy, &stages).is_empty());
+    }
+
+    #[test]
+    fn test_container_options() {
+        let mut legacy = run_stage("legacy", "builder:1");
+        legacy.security_context = Some(SecurityContext {
+            run_as_user: Some(0),
+            ..Default::default()
+        });
+        legacy.container = ContainerOptions {
+            user: Some("root:root".to_string()),
+            privileged: true,
+            network_mode: Some("host".to_string()),
+            extra_hosts: vec!["registry.internal:10.0.0.5".to_string()],
+            ..Default::default()
+        };
+        let mut isolated = run_stage("isolated", "builder:1");
+        isolated.container.network_mode = Some("none".to_string());
+        isolated.container.user = Some("1000:1000".to_string());
+        let stages = vec![legacy, isolated];
+
+        let violations = check_security(&StageSecurityPolicy::default(), &stages);
+        assert_eq!(violations.len(), 3, "{:?}", violations);
+        assert!(violations[0].contains("Stage 'legacy' runs as root"));
+        assert!(violations[1].contains("runs privileged"));
+        assert!(violations[2].contains("joins network 'host'"));
+
+        let policy = StageSecurityPolicy {
+            allow_root: true,
+            allow_privileged: true,
+            allowed_network_modes: vec!["host".to_string()],
+            ..Default::default()
+        };
+        assert!(check_security(&policy, &stages).is_empty());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&PipelinePolicy::default()).is_ok());
+        let policy = PipelinePolicy {
+            max_cpu_request: Some("lots".to_string()),
+            ..Default::default()
+        };
+        assert!(validate(&policy).is_err());
+        let policy = PipelinePolicy {
+            allowed_images: vec![" ".to_string()],
+            ..Default::default()
+        };
+        assert!(validate(&policy).is_err());
+    }
+}
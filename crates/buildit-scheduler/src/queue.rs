@@ -1,9 +1,33 @@
 //! Job queue implementation using PostgreSQL.
+//!
+//! Two kinds of work share the queue: stage jobs (`job_queue`), run by
+//! executors, and tasks (`task_queue`), the control plane's background work
+//! such as pipeline runs and stack operations. Both are claimed with
+//! `SKIP LOCKED`, so any number of workers can poll them.
+//!
+//! Claims take the highest priority item first, but a tenant's items lose
+//! priority for every item of that tenant already claimed (see
+//! [`SchedulerConfig`]), so one busy tenant cannot starve the others.
+//!
+//! Queueing an item `NOTIFY`s the queue's channel, and [`JobQueue::listen`]
+//! turns those notifications into wakeups for the workers of every server,
+//! so work starts right away instead of on the next poll. Workers still poll
+//! every [`SchedulerConfig::poll_interval`] for work that became due, or
+//! whose notification was lost with the listening connection.
 
+use crate::metrics;
+use buildit_config::system::SchedulerConfig;
 use buildit_core::ResourceId;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
+use sqlx::postgres::PgListener;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+/// Delay before the first retry of a failed task; doubled on each attempt.
+const TASK_RETRY_DELAY_SECS: i64 = 30;
 
 /// A queued job.
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
@@ -12,23 +36,164 @@ pub struct QueuedJob {
     pub pipeline_run_id: uuid::Uuid,
     pub stage_name: String,
     pub priority: i32,
+    pub tenant_id: Option<uuid::Uuid>,
     pub status: String,
     pub claimed_by: Option<String>,
     pub claimed_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
 }
 
+/// A queued background task.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct QueuedTask {
+    pub id: uuid::Uuid,
+    pub kind: String,
+    /// What to do, as identifiers the handler reloads from the database.
+    pub payload: serde_json::Value,
+    pub idempotency_key: String,
+    /// Progress checkpointed by the handler, for resuming after a crash.
+    pub state: serde_json::Value,
+    pub status: String,
+    pub priority: i32,
+    pub tenant_id: Option<uuid::Uuid>,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub run_after: DateTime<Utc>,
+    pub claimed_by: Option<String>,
+    pub claimed_at: Option<DateTime<Utc>>,
+    pub heartbeat_at: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// Where a task stands in the queue.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TaskPosition {
+    pub status: String,
+    pub run_after: DateTime<Utc>,
+    pub claimed_by: Option<String>,
+    /// Due tasks a worker would claim before this one; 0 unless it is due.
+    pub ahead: i64,
+}
+
+/// Delay before listening again after the listening connection failed.
+const LISTEN_RETRY_DELAY: Duration = Duration::from_secs(5);
+
 /// Job queue backed by PostgreSQL.
 pub struct JobQueue {
     pool: PgPool,
+    config: SchedulerConfig,
+    jobs_queued: watch::Sender<()>,
+    tasks_queued: watch::Sender<()>,
 }
 
 impl JobQueue {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self::with_config(pool, SchedulerConfig::default())
+    }
+
+    /// Create a queue with the given priorities and tenant weights.
+    pub fn with_config(pool: PgPool, config: SchedulerConfig) -> Self {
+        Self {
+            pool,
+            config,
+            jobs_queued: watch::Sender::new(()),
+            tasks_queued: watch::Sender::new(()),
+        }
+    }
+
+    /// Priorities and tenant weights claims are ordered by.
+    pub fn config(&self) -> &SchedulerConfig {
+        &self.config
+    }
+
+    /// Changes whenever jobs may have been queued.
+    pub fn job_wakeups(&self) -> watch::Receiver<()> {
+        self.jobs_queued.subscribe()
     }
 
-    /// Enqueue a new job.
+    /// Changes whenever tasks may have been queued or become due.
+    pub fn task_wakeups(&self) -> watch::Receiver<()> {
+        self.tasks_queued.subscribe()
+    }
+
+    /// Wait until `wakeups` changes, or for the poll interval at most.
+    pub async fn wait(&self, wakeups: &mut watch::Receiver<()>) {
+        let _ = tokio::time::timeout(self.config.poll_interval(), wakeups.changed()).await;
+    }
+
+    /// Wake the workers of every server listening on `channel`. A failure
+    /// only delays the work until the next poll, so it is logged and
+    /// otherwise ignored.
+    async fn notify(&self, channel: &str) {
+        let result = sqlx::query("SELECT pg_notify($1, '')")
+            .bind(channel)
+            .execute(&self.pool)
+            .await;
+        if let Err(e) = result {
+            warn!(channel, error = %e, "Failed to notify queue listeners");
+        }
+    }
+
+    /// Wake this process's workers whenever work is queued on any server.
+    /// Runs until the pool is closed; a lost connection is re-established,
+    /// and the workers woken in case they missed something meanwhile.
+    pub async fn listen(&self) {
+        let channels = [
+            self.config.job_channel.as_str(),
+            self.config.task_channel.as_str(),
+        ];
+        loop {
+            let mut listener = match PgListener::connect_with(&self.pool).await {
+                Ok(listener) => listener,
+                Err(e) => {
+                    warn!(error = %e, "Failed to connect the queue listener");
+                    tokio::time::sleep(LISTEN_RETRY_DELAY).await;
+                    continue;
+                }
+            };
+            if let Err(e) = listener.listen_all(channels).await {
+                warn!(error = %e, "Failed to listen for queued work");
+                tokio::time::sleep(LISTEN_RETRY_DELAY).await;
+                continue;
+            }
+            info!(?channels, "Listening for queued work");
+
+            loop {
+                match listener.try_recv().await {
+                    Ok(Some(notification)) if notification.channel() == channels[0] => {
+                        self.jobs_queued.send_replace(());
+                    }
+                    Ok(Some(_)) => {
+                        self.tasks_queued.send_replace(());
+                    }
+                    Ok(None) => {
+                        warn!("Queue listener reconnected");
+                        self.jobs_queued.send_replace(());
+                        self.tasks_queued.send_replace(());
+                    }
+                    Err(sqlx::Error::PoolClosed) => return,
+                    Err(e) => {
+                        warn!(error = %e, "Queue listener failed");
+                        break;
+                    }
+                }
+            }
+            tokio::time::sleep(LISTEN_RETRY_DELAY).await;
+        }
+    }
+
+    /// Tenant slugs and their weights, as parallel arrays for the claim queries.
+    fn tenant_weights(&self) -> (Vec<String>, Vec<f64>) {
+        self.config
+            .tenant_weights
+            .iter()
+            .map(|(slug, weight)| (slug.clone(), *weight))
+            .unzip()
+    }
+
+    /// Enqueue a new job for the tenant owning the run.
     pub async fn enqueue(
         &self,
         pipeline_run_id: ResourceId,
@@ -37,8 +202,11 @@ impl JobQueue {
     ) -> Result<QueuedJob, sqlx::Error> {
         let job = sqlx::query_as::<_, QueuedJob>(
             r#"
-            INSERT INTO job_queue (id, pipeline_run_id, stage_name, priority, status, created_at)
-            VALUES ($1, $2, $3, $4, 'pending', NOW())
+            INSERT INTO job_queue (id, pipeline_run_id, stage_name, priority, tenant_id, status, created_at)
+            SELECT $1, $2, $3, $4, p.tenant_id, 'pending', NOW()
+            FROM pipeline_runs r
+            JOIN pipelines p ON p.id = r.pipeline_id
+            WHERE r.id = $2
             RETURNING *
             "#,
         )
@@ -48,29 +216,48 @@ impl JobQueue {
         .bind(priority)
         .fetch_one(&self.pool)
         .await?;
+        self.notify(&self.config.job_channel).await;
         Ok(job)
     }
 
-    /// Claim the next available job.
+    /// Claim the next available job, by priority less its tenant's share of
+    /// the claimed jobs.
     /// Uses SKIP LOCKED to prevent contention in distributed environments.
+    /// Jobs of archived pipelines are never claimed.
     pub async fn claim(&self, worker_id: &str) -> Result<Option<QueuedJob>, sqlx::Error> {
+        let (slugs, weights) = self.tenant_weights();
         let job = sqlx::query_as::<_, QueuedJob>(
             r#"
             UPDATE job_queue
             SET status = 'claimed', claimed_by = $1, claimed_at = NOW()
             WHERE id = (
-                SELECT id FROM job_queue
-                WHERE status = 'pending'
-                ORDER BY priority DESC, created_at ASC
-                FOR UPDATE SKIP LOCKED
+                SELECT q.id FROM job_queue q
+                JOIN pipeline_runs r ON r.id = q.pipeline_run_id
+                JOIN pipelines p ON p.id = r.pipeline_id
+                LEFT JOIN tenants t ON t.id = q.tenant_id
+                LEFT JOIN (
+                    SELECT tenant_id, COUNT(*) AS claimed FROM job_queue
+                    WHERE status = 'claimed' GROUP BY tenant_id
+                ) c ON c.tenant_id = q.tenant_id
+                LEFT JOIN unnest($2::text[], $3::float8[]) AS w(slug, weight) ON w.slug = t.slug
+                WHERE q.status = 'pending' AND p.archived_at IS NULL
+                ORDER BY q.priority - $4 * COALESCE(c.claimed, 0) / COALESCE(w.weight, 1) DESC,
+                         q.created_at ASC
+                FOR UPDATE OF q SKIP LOCKED
                 LIMIT 1
             )
             RETURNING *
             "#,
         )
         .bind(worker_id)
+        .bind(slugs)
+        .bind(weights)
+        .bind(self.config.fairness)
         .fetch_optional(&self.pool)
         .await?;
+        if let Some(job) = &job {
+            metrics::record_job_wait(job.created_at, job.claimed_at.unwrap_or_else(Utc::now));
+        }
         Ok(job)
     }
 
@@ -101,6 +288,488 @@ impl JobQueue {
         .bind(job_id)
         .execute(&self.pool)
         .await?;
+        self.notify(&self.config.job_channel).await;
         Ok(())
     }
+
+    /// Enqueue a task, on behalf of a tenant if it does work for one. A task
+    /// with the same idempotency key is returned as-is instead of queueing
+    /// the work twice. Failed tasks are retried with exponential backoff
+    /// until they have been tried `max_attempts` times.
+    pub async fn enqueue_task(
+        &self,
+        kind: &str,
+        payload: serde_json::Value,
+        idempotency_key: &str,
+        priority: i32,
+        tenant_id: Option<ResourceId>,
+        max_attempts: i32,
+    ) -> Result<QueuedTask, sqlx::Error> {
+        let task = sqlx::query_as::<_, QueuedTask>(
+            r#"
+            INSERT INTO task_queue (id, kind, payload, idempotency_key, priority, tenant_id, max_attempts, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, NOW())
+            ON CONFLICT (idempotency_key) DO UPDATE SET idempotency_key = EXCLUDED.idempotency_key
+            RETURNING *
+            "#,
+        )
+        .bind(uuid::Uuid::now_v7())
+        .bind(kind)
+        .bind(payload)
+        .bind(idempotency_key)
+        .bind(priority)
+        .bind(tenant_id.map(|id| *id.as_uuid()))
+        .bind(max_attempts)
+        .fetch_one(&self.pool)
+        .await?;
+        if task.status == "pending" && task.run_after <= Utc::now() {
+            self.notify(&self.config.task_channel).await;
+        }
+        Ok(task)
+    }
+
+    /// Claim the next due task of one of `kinds`, ordered like [`Self::claim`].
+    /// Tasks without a tenant are never held back.
+    /// Uses SKIP LOCKED so workers never wait on each other.
+    pub async fn claim_task(
+        &self,
+        worker_id: &str,
+        kinds: &[String],
+    ) -> Result<Option<QueuedTask>, sqlx::Error> {
+        let (slugs, weights) = self.tenant_weights();
+        let task = sqlx::query_as::<_, QueuedTask>(
+            r#"
+            UPDATE task_queue
+            SET status = 'claimed', claimed_by = $1, claimed_at = NOW(),
+                heartbeat_at = NOW(), attempts = attempts + 1
+            WHERE id = (
+                SELECT q.id FROM task_queue q
+                LEFT JOIN tenants t ON t.id = q.tenant_id
+                LEFT JOIN (
+                    SELECT tenant_id, COUNT(*) AS claimed FROM task_queue
+                    WHERE status = 'claimed' GROUP BY tenant_id
+                ) c ON c.tenant_id = q.tenant_id
+                LEFT JOIN unnest($3::text[], $4::float8[]) AS w(slug, weight) ON w.slug = t.slug
+                WHERE q.status = 'pending' AND q.run_after <= NOW() AND q.kind = ANY($2)
+                ORDER BY q.priority - $5 * COALESCE(c.claimed, 0) / COALESCE(w.weight, 1) DESC,
+                         q.created_at ASC
+                FOR UPDATE OF q SKIP LOCKED
+                LIMIT 1
+            )
+            RETURNING *
+            "#,
+        )
+        .bind(worker_id)
+        .bind(kinds)
+        .bind(slugs)
+        .bind(weights)
+        .bind(self.config.fairness)
+        .fetch_optional(&self.pool)
+        .await?;
+        if let Some(task) = &task {
+            metrics::record_task_wait(
+                &task.kind,
+                task.run_after,
+                task.claimed_at.unwrap_or_else(Utc::now),
+            );
+        }
+        Ok(task)
+    }
+
+    /// Where the task with the key stands: how many due tasks are ahead of
+    /// it in the order [`Self::claim_task`] takes them, of any kind.
+    pub async fn task_position(
+        &self,
+        idempotency_key: &str,
+    ) -> Result<Option<TaskPosition>, sqlx::Error> {
+        let (slugs, weights) = self.tenant_weights();
+        let position = sqlx::query_as::<_, TaskPosition>(
+            r#"
+            WITH due AS (
+                SELECT q.idempotency_key, q.created_at,
+                       q.priority - $4 * COALESCE(c.claimed, 0) / COALESCE(w.weight, 1) AS score
+                FROM task_queue q
+                LEFT JOIN tenants t ON t.id = q.tenant_id
+                LEFT JOIN (
+                    SELECT tenant_id, COUNT(*) AS claimed FROM task_queue
+                    WHERE status = 'claimed' GROUP BY tenant_id
+                ) c ON c.tenant_id = q.tenant_id
+                LEFT JOIN unnest($2::text[], $3::float8[]) AS w(slug, weight) ON w.slug = t.slug
+                WHERE q.status = 'pending' AND q.run_after <= NOW()
+            )
+            SELECT task.status, task.run_after, task.claimed_by,
+                   (SELECT COUNT(*) FROM due, due AS this
+                    WHERE this.idempotency_key = $1
+                      AND (due.score > this.score
+                           OR (due.score = this.score AND due.created_at < this.created_at))
+                   ) AS ahead
+            FROM task_queue task
+            WHERE task.idempotency_key = $1
+            "#,
+        )
+        .bind(idempotency_key)
+        .bind(slugs)
+        .bind(weights)
+        .bind(self.config.fairness)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(position)
+    }
+
+    /// Record that the worker is still running the task. Returns false if
+    /// the task is no longer claimed by this worker.
+    pub async fn heartbeat_task(
+        &self,
+        task_id: uuid::Uuid,
+        worker_id: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE task_queue SET heartbeat_at = NOW() WHERE id = $1 AND claimed_by = $2 AND status = 'claimed'",
+        )
+        .bind(task_id)
+        .bind(worker_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Save the task's progress so a later attempt can resume from it.
+    pub async fn checkpoint_task(
+        &self,
+        task_id: uuid::Uuid,
+        state: &serde_json::Value,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE task_queue SET state = $2 WHERE id = $1")
+            .bind(task_id)
+            .bind(state)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Return a claimed task to the queue to run again at `run_after`,
+    /// without counting the attempt. For work that is blocked rather than
+    /// failing, e.g. a deployment during a freeze.
+    pub async fn defer_task(
+        &self,
+        task_id: uuid::Uuid,
+        run_after: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE task_queue
+            SET status = 'pending', claimed_by = NULL, run_after = $2,
+                attempts = GREATEST(attempts - 1, 0)
+            WHERE id = $1 AND status = 'claimed'
+            "#,
+        )
+        .bind(task_id)
+        .bind(run_after)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Make a pending task due now, e.g. a deferred one whose blocker was
+    /// lifted early. Returns false if there is no pending task with the key.
+    pub async fn run_task_now(&self, idempotency_key: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE task_queue SET run_after = NOW() WHERE idempotency_key = $1 AND status = 'pending'",
+        )
+        .bind(idempotency_key)
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() > 0 {
+            self.notify(&self.config.task_channel).await;
+        }
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Make a tenant's deferred tasks of one kind due now, e.g. runs held
+    /// for a quota that was just raised. Returns how many there were.
+    pub async fn run_tenant_tasks_now(
+        &self,
+        tenant_id: ResourceId,
+        kind: &str,
+    ) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE task_queue SET run_after = NOW()
+            WHERE tenant_id = $1 AND kind = $2 AND status = 'pending' AND run_after > NOW()
+            "#,
+        )
+        .bind(tenant_id.as_uuid())
+        .bind(kind)
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() > 0 {
+            self.notify(&self.config.task_channel).await;
+        }
+        Ok(result.rows_affected())
+    }
+
+    /// Mark a task as completed.
+    pub async fn complete_task(&self, task_id: uuid::Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE task_queue SET status = 'completed', error = NULL, finished_at = NOW() WHERE id = $1",
+        )
+        .bind(task_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Record a failed attempt. The task is retried with exponential backoff
+    /// until it runs out of attempts, then marked failed.
+    pub async fn fail_task(&self, task_id: uuid::Uuid, error: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE task_queue
+            SET error = $2, claimed_by = NULL,
+                status = CASE WHEN attempts < max_attempts THEN 'pending' ELSE 'failed' END,
+                run_after = NOW() + make_interval(secs => $3 * power(2, GREATEST(attempts - 1, 0))),
+                finished_at = CASE WHEN attempts < max_attempts THEN NULL ELSE NOW() END
+            WHERE id = $1
+            "#,
+        )
+        .bind(task_id)
+        .bind(error)
+        .bind(TASK_RETRY_DELAY_SECS as f64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Sample how many jobs and tasks are pending and claimed, and how long
+    /// the oldest pending one has waited, into the queue depth metrics.
+    /// Tasks count as pending once they are due.
+    pub async fn record_depth(&self) -> Result<(), sqlx::Error> {
+        let rows: Vec<(String, i64, i64, f64)> = sqlx::query_as(
+            r#"
+            SELECT 'jobs',
+                   COUNT(*) FILTER (WHERE status = 'pending'),
+                   COUNT(*) FILTER (WHERE status = 'claimed'),
+                   COALESCE(EXTRACT(EPOCH FROM NOW() - MIN(created_at) FILTER (WHERE status = 'pending')), 0)::float8
+            FROM job_queue WHERE status IN ('pending', 'claimed')
+            UNION ALL
+            SELECT 'tasks',
+                   COUNT(*) FILTER (WHERE status = 'pending' AND run_after <= NOW()),
+                   COUNT(*) FILTER (WHERE status = 'claimed'),
+                   COALESCE(EXTRACT(EPOCH FROM NOW() - MIN(run_after) FILTER (WHERE status = 'pending' AND run_after <= NOW())), 0)::float8
+            FROM task_queue WHERE status IN ('pending', 'claimed')
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        for (queue, pending, claimed, oldest_pending) in rows {
+            let queue = if queue == "jobs" { "jobs" } else { "tasks" };
+            metrics::record_depth(queue, pending, claimed, oldest_pending);
+        }
+        Ok(())
+    }
+
+    /// Return tasks whose worker stopped heartbeating (e.g. the process was
+    /// restarted) to the queue. Returns how many were requeued or failed.
+    pub async fn requeue_stale_tasks(&self, stale_after: Duration) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE task_queue
+            SET claimed_by = NULL,
+                error = 'worker stopped responding',
+                status = CASE WHEN attempts < max_attempts THEN 'pending' ELSE 'failed' END,
+                finished_at = CASE WHEN attempts < max_attempts THEN NULL ELSE NOW() END
+            WHERE status = 'claimed' AND heartbeat_at < NOW() - make_interval(secs => $1)
+            "#,
+        )
+        .bind(stale_after.as_secs_f64())
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() > 0 {
+            self.notify(&self.config.task_channel).await;
+        }
+        Ok(result.rows_affected())
+    }
+
+    /// Requeue the tasks of earlier workers whose ID starts with
+    /// `worker_prefix` and that have not heartbeated for `idle`. Called on
+    /// startup to take over a previous process's tasks without waiting for
+    /// them to go stale.
+    pub async fn requeue_worker_tasks(
+        &self,
+        worker_prefix: &str,
+        idle: Duration,
+    ) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE task_queue
+            SET claimed_by = NULL, status = 'pending', error = 'worker restarted'
+            WHERE status = 'claimed' AND starts_with(claimed_by, $1)
+              AND heartbeat_at < NOW() - make_interval(secs => $2)
+            "#,
+        )
+        .bind(worker_prefix)
+        .bind(idle.as_secs_f64())
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() > 0 {
+            self.notify(&self.config.task_channel).await;
+        }
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(poll_interval_secs: u64) -> JobQueue {
+        let pool = PgPool::connect_lazy("postgres://localhost/buildit").unwrap();
+        JobQueue::with_config(
+            pool,
+            SchedulerConfig {
+                poll_interval_secs,
+                ..SchedulerConfig::default()
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn test_wait_wakes_on_queued_work() {
+        let queue = queue(3600);
+        let mut wakeups = queue.job_wakeups();
+        wakeups.borrow_and_update();
+
+        // Work queued between the claim and the wait is not missed
+        queue.jobs_queued.send_replace(());
+        tokio::time::timeout(Duration::from_secs(5), queue.wait(&mut wakeups))
+            .await
+            .expect("queued job did not wake the worker");
+
+        // Task wakeups leave job workers waiting
+        wakeups.borrow_and_update();
+        queue.tasks_queued.send_replace(());
+        let waited = tokio::time::timeout(Duration::from_millis(50), queue.wait(&mut wakeups));
+        assert!(waited.await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_wait_falls_back_to_polling() {
+        let queue = queue(1);
+        let mut wakeups = queue.task_wakeups();
+        wakeups.borrow_and_update();
+        let started = std::time::Instant::now();
+        queue.wait(&mut wakeups).await;
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    /// An organization with a tenant of that slug, for the database tests.
+    async fn tenant(pool: &PgPool, slug: &str) -> uuid::Uuid {
+        let (org, tenant) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        sqlx::query("INSERT INTO organizations (id, name, slug) VALUES ($1, $2, $2)")
+            .bind(org)
+            .bind(slug)
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO tenants (id, name, slug, organization_id) VALUES ($1, $2, $2, $3)",
+        )
+        .bind(tenant)
+        .bind(slug)
+        .bind(org)
+        .execute(pool)
+        .await
+        .unwrap();
+        tenant
+    }
+
+    /// Queue tasks of `kind`, the backlog's count for each tenant in turn,
+    /// then claim `claims` of them; the tenants of the claimed tasks, in
+    /// claim order.
+    async fn claim_order(
+        queue: &JobQueue,
+        kind: &str,
+        backlog: &[(uuid::Uuid, usize)],
+        claims: usize,
+    ) -> Vec<uuid::Uuid> {
+        for (tenant, count) in backlog {
+            for _ in 0..*count {
+                queue
+                    .enqueue_task(
+                        kind,
+                        serde_json::json!({}),
+                        &uuid::Uuid::new_v4().to_string(),
+                        0,
+                        Some(ResourceId::from_uuid(*tenant)),
+                        1,
+                    )
+                    .await
+                    .unwrap();
+            }
+        }
+        let mut order = Vec::new();
+        for _ in 0..claims {
+            let task = queue
+                .claim_task("worker", &[kind.to_string()])
+                .await
+                .unwrap()
+                .expect("a task to claim");
+            order.push(task.tenant_id.unwrap());
+        }
+        order
+    }
+
+    #[tokio::test]
+    async fn test_a_tenants_backlog_does_not_starve_the_others() {
+        let Ok(url) = std::env::var("DATABASE_URL") else {
+            return;
+        };
+        let pool = PgPool::connect(&url).await.unwrap();
+        buildit_db::run_migrations(&pool).await.unwrap();
+        let slug = |name: &str| format!("{}-{}", name, uuid::Uuid::new_v4());
+        let (busy, quiet) = (slug("busy"), slug("quiet"));
+        let (weighted, other) = (slug("weighted"), slug("other"));
+        let tenants = [
+            tenant(&pool, &busy).await,
+            tenant(&pool, &quiet).await,
+            tenant(&pool, &weighted).await,
+            tenant(&pool, &other).await,
+        ];
+        let kinds = [
+            uuid::Uuid::new_v4().to_string(),
+            uuid::Uuid::new_v4().to_string(),
+        ];
+
+        // The quiet tenant queued last, behind 20 tasks of the busy one, yet
+        // every other claim is its own.
+        let queue = JobQueue::new(pool.clone());
+        let order = claim_order(&queue, &kinds[0], &[(tenants[0], 20), (tenants[1], 2)], 4).await;
+        assert_eq!(order, [tenants[0], tenants[1], tenants[0], tenants[1]]);
+
+        // A tenant weighted 2 loses half the priority per claim, so gets
+        // two claims for each of another tenant's.
+        let queue = JobQueue::with_config(
+            pool.clone(),
+            SchedulerConfig {
+                tenant_weights: [(weighted.clone(), 2.0)].into(),
+                ..SchedulerConfig::default()
+            },
+        );
+        let order = claim_order(&queue, &kinds[1], &[(tenants[2], 20), (tenants[3], 20)], 6).await;
+        let weighted_claims = order.iter().filter(|t| **t == tenants[2]).count();
+        assert_eq!(weighted_claims, 4, "{:?}", order);
+
+        sqlx::query("DELETE FROM task_queue WHERE kind = ANY($1)")
+            .bind(&kinds[..])
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "DELETE FROM organizations WHERE id IN (SELECT organization_id FROM tenants WHERE id = ANY($1))",
+        )
+        .bind(&tenants[..])
+        .execute(&pool)
+        .await
+        .unwrap();
+    }
 }
@@ -0,0 +1,756 @@
+//! Tenant resource quotas and usage accounting.
+//!
+//! Every stage job is recorded as a usage row from when it is admitted
+//! until it finishes. A job is only admitted if, together with the tenant's
+//! running jobs, it stays within the tenant's quota: jobs running at once,
+//! CPU and memory requested, and job minutes this month. Admission holds a
+//! per-tenant advisory lock, so jobs admitted at the same time cannot
+//! overshoot the quota together. The same rows are the usage tenants are
+//! billed for. While a job runs its executor's CPU and memory readings are
+//! sampled, and what it measurably used, at peak and on average, is stored
+//! when it finishes, so costs can be estimated from use rather than
+//! requests and stages right-sized from their history.
+
+use buildit_core::executor::{ResourceRequirements, ResourceUsage};
+use buildit_core::tenant::{QuotaAction, TenantQuota};
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgExecutor, PgPool};
+use std::time::Instant;
+use uuid::Uuid;
+
+/// How often work held for quota checks again.
+pub const RETRY_INTERVAL: Duration = Duration::seconds(30);
+
+const GIB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+/// CPU quantity in millicores: `500m`, `2`, `0.25`.
+pub fn cpu_millis(quantity: &str) -> Option<i64> {
+    let quantity = quantity.trim();
+    let millis = match quantity.strip_suffix('m') {
+        Some(millis) => millis.parse::<f64>().ok()?,
+        None => quantity.parse::<f64>().ok()? * 1000.0,
+    };
+    (millis.is_finite() && millis >= 0.0).then(|| millis.round() as i64)
+}
+
+/// Memory quantity in bytes: `512Mi`, `1Gi`, `1G`, or plain bytes.
+pub fn memory_bytes(quantity: &str) -> Option<i64> {
+    let quantity = quantity.trim();
+    let units: [(&str, f64); 8] = [
+        ("Ki", 1024.0),
+        ("Mi", 1024.0 * 1024.0),
+        ("Gi", GIB),
+        ("Ti", GIB * 1024.0),
+        ("K", 1e3),
+        ("M", 1e6),
+        ("G", 1e9),
+        ("T", 1e12),
+    ];
+    let (value, scale) = units
+        .iter()
+        .find_map(|(suffix, scale)| quantity.strip_suffix(suffix).map(|v| (v, *scale)))
+        .unwrap_or((quantity, 1.0));
+    let bytes = value.parse::<f64>().ok()? * scale;
+    (bytes.is_finite() && bytes >= 0.0).then(|| bytes.round() as i64)
+}
+
+/// Check that a quota's limits parse, returning what is wrong.
+pub fn validate(quota: &TenantQuota) -> Result<(), String> {
+    if let Some(cpu) = &quota.max_cpu
+        && cpu_millis(cpu).is_none_or(|m| m == 0)
+    {
+        return Err(format!("max_cpu '{}' is not a positive CPU quantity", cpu));
+    }
+    if let Some(memory) = &quota.max_memory
+        && memory_bytes(memory).is_none_or(|b| b == 0)
+    {
+        return Err(format!(
+            "max_memory '{}' is not a positive memory quantity",
+            memory
+        ));
+    }
+    Ok(())
+}
+
+/// CPU and memory a job takes up: its requests, falling back to its limits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JobResources {
+    pub cpu_millis: i64,
+    pub memory_bytes: i64,
+}
+
+impl JobResources {
+    pub fn of(resources: &ResourceRequirements) -> Self {
+        let quantity = |request: &Option<String>, limit: &Option<String>| {
+            request.clone().or_else(|| limit.clone())
+        };
+        Self {
+            cpu_millis: quantity(&resources.cpu_request, &resources.cpu_limit)
+                .and_then(|q| cpu_millis(&q))
+                .unwrap_or(0),
+            memory_bytes: quantity(&resources.memory_request, &resources.memory_limit)
+                .and_then(|q| memory_bytes(&q))
+                .unwrap_or(0),
+        }
+    }
+}
+
+/// What a tenant's running jobs take up, and the job minutes it used this
+/// month.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, sqlx::FromRow)]
+pub struct CurrentUsage {
+    pub running_jobs: i64,
+    pub cpu_millis: i64,
+    pub memory_bytes: i64,
+    pub run_minutes: f64,
+}
+
+/// Why a job does not fit a tenant's quota.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum QuotaExceeded {
+    #[error("the quota of {limit} job minutes this month is used up")]
+    RunMinutes { limit: u32 },
+    #[error("the quota of {limit} jobs running at once is reached")]
+    ConcurrentJobs { limit: u32 },
+    #[error("the job would take {resource} in use to {requested}, over the quota of {limit}")]
+    Resource {
+        resource: &'static str,
+        requested: String,
+        limit: String,
+    },
+    #[error("the job requests {requested} {resource}, more than the quota of {limit} allows")]
+    Oversized {
+        resource: &'static str,
+        requested: String,
+        limit: String,
+    },
+}
+
+impl QuotaExceeded {
+    /// Whether waiting cannot help, because the job on its own is over the
+    /// quota.
+    pub fn is_permanent(&self) -> bool {
+        matches!(self, QuotaExceeded::Oversized { .. })
+    }
+
+    /// When to check again: the start of next month once the monthly
+    /// minutes are used up, otherwise after [`RETRY_INTERVAL`].
+    pub fn retry_at(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            QuotaExceeded::RunMinutes { .. } => next_month(now),
+            _ => now + RETRY_INTERVAL,
+        }
+    }
+}
+
+/// Whether a job taking up `job` fits the quota next to `usage`.
+pub fn check(
+    quota: &TenantQuota,
+    usage: &CurrentUsage,
+    job: JobResources,
+) -> Result<(), QuotaExceeded> {
+    if let Some(limit) = quota.max_run_minutes_per_month
+        && usage.run_minutes >= limit as f64
+    {
+        return Err(QuotaExceeded::RunMinutes { limit });
+    }
+    if let Some(limit) = quota.max_concurrent_jobs
+        && usage.running_jobs >= limit as i64
+    {
+        return Err(QuotaExceeded::ConcurrentJobs { limit });
+    }
+    let limits = [
+        (
+            "CPU",
+            quota.max_cpu.as_deref().and_then(cpu_millis),
+            usage.cpu_millis,
+            job.cpu_millis,
+            format_cpu as fn(i64) -> String,
+        ),
+        (
+            "memory",
+            quota.max_memory.as_deref().and_then(memory_bytes),
+            usage.memory_bytes,
+            job.memory_bytes,
+            format_memory,
+        ),
+    ];
+    for (resource, limit, used, requested, format) in limits {
+        let Some(limit) = limit else {
+            continue;
+        };
+        if requested > limit {
+            return Err(QuotaExceeded::Oversized {
+                resource,
+                requested: format(requested),
+                limit: format(limit),
+            });
+        }
+        if used + requested > limit {
+            return Err(QuotaExceeded::Resource {
+                resource,
+                requested: format(used + requested),
+                limit: format(limit),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn format_cpu(millis: i64) -> String {
+    if millis % 1000 == 0 {
+        format!("{} cores", millis / 1000)
+    } else {
+        format!("{}m cores", millis)
+    }
+}
+
+fn format_memory(bytes: i64) -> String {
+    format!("{:.1}Gi", bytes as f64 / GIB)
+}
+
+/// Midnight UTC on the first of `now`'s month.
+pub fn month_start(now: DateTime<Utc>) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0)
+        .single()
+        .unwrap_or(now)
+}
+
+/// Midnight UTC on the first of the month after `now`'s.
+pub fn next_month(now: DateTime<Utc>) -> DateTime<Utc> {
+    let (year, month) = if now.month() == 12 {
+        (now.year() + 1, 1)
+    } else {
+        (now.year(), now.month() + 1)
+    };
+    Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0)
+        .single()
+        .unwrap_or(now)
+}
+
+/// The pipeline run a stage job belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JobOwner {
+    pub tenant_id: Uuid,
+    pub pipeline_id: Uuid,
+    pub run_id: Option<Uuid>,
+}
+
+/// Outcome of asking to start a job.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Admission {
+    /// The job may start; its usage is recorded under this ID.
+    Admitted(Uuid),
+    /// The job does not fit, and the tenant's quota says whether to hold
+    /// or refuse it.
+    Denied {
+        exceeded: QuotaExceeded,
+        action: QuotaAction,
+    },
+}
+
+/// Job minutes and resources used over a period, per pipeline.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow)]
+pub struct PipelineUsage {
+    /// `None` for jobs of pipelines deleted since.
+    pub pipeline_id: Option<Uuid>,
+    pub jobs: i64,
+    pub run_minutes: f64,
+    /// CPU requested times minutes run, in core-minutes.
+    pub cpu_core_minutes: f64,
+    /// Memory requested times minutes run, in GiB-minutes.
+    pub memory_gib_minutes: f64,
+}
+
+/// What a job measurably used over its run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct MeasuredUsage {
+    /// CPU used, in core-seconds.
+    pub cpu_seconds: f64,
+    /// Memory held, in GiB-seconds.
+    pub memory_gib_seconds: f64,
+    /// Highest CPU reading, in millicores.
+    pub peak_cpu_millis: u64,
+    /// Highest memory reading, in bytes.
+    pub peak_memory_bytes: u64,
+    /// CPU used on average over the run, in millicores.
+    pub avg_cpu_millis: u64,
+    /// Memory held on average over the run, in bytes.
+    pub avg_memory_bytes: u64,
+}
+
+/// Adds up a job's usage from readings taken while it runs. Each reading
+/// stands for the time until the next one, and the first also for the
+/// time before it.
+#[derive(Debug, Clone)]
+pub struct UsageSampler {
+    start: Instant,
+    since: Instant,
+    last: Option<ResourceUsage>,
+    measured: MeasuredUsage,
+}
+
+impl UsageSampler {
+    /// Start measuring a job that started at `start`.
+    pub fn new(start: Instant) -> Self {
+        Self {
+            start,
+            since: start,
+            last: None,
+            measured: MeasuredUsage::default(),
+        }
+    }
+
+    /// Add a reading taken at `at`.
+    pub fn sample(&mut self, at: Instant, usage: ResourceUsage) {
+        self.add(at, self.last.unwrap_or(usage));
+        self.last = Some(usage);
+        let measured = &mut self.measured;
+        measured.peak_cpu_millis = measured.peak_cpu_millis.max(usage.cpu_millis);
+        measured.peak_memory_bytes = measured.peak_memory_bytes.max(usage.memory_bytes);
+    }
+
+    /// What the job used until it ended at `end`, or `None` without any
+    /// reading.
+    pub fn finish(mut self, end: Instant) -> Option<MeasuredUsage> {
+        let last = self.last?;
+        self.add(end, last);
+        let measured = &mut self.measured;
+        let secs = end.saturating_duration_since(self.start).as_secs_f64();
+        if secs > 0.0 {
+            measured.avg_cpu_millis = (measured.cpu_seconds * 1000.0 / secs).round() as u64;
+            measured.avg_memory_bytes = (measured.memory_gib_seconds * GIB / secs).round() as u64;
+        } else {
+            measured.avg_cpu_millis = last.cpu_millis;
+            measured.avg_memory_bytes = last.memory_bytes;
+        }
+        Some(self.measured)
+    }
+
+    fn add(&mut self, at: Instant, usage: ResourceUsage) {
+        let secs = at.saturating_duration_since(self.since).as_secs_f64();
+        self.measured.cpu_seconds += secs * usage.cpu_millis as f64 / 1000.0;
+        self.measured.memory_gib_seconds += secs * usage.memory_bytes as f64 / GIB;
+        self.since = self.since.max(at);
+    }
+}
+
+/// Admits stage jobs against their tenant's quota and records their usage.
+pub struct QuotaTracker {
+    pool: PgPool,
+}
+
+impl QuotaTracker {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// The tenant's quota; unlimited if none is set.
+    pub async fn quota(&self, tenant_id: Uuid) -> Result<TenantQuota, sqlx::Error> {
+        load_quota(&self.pool, tenant_id).await
+    }
+
+    /// What the tenant's running jobs take up and its minutes this month.
+    pub async fn current(&self, tenant_id: Uuid) -> Result<CurrentUsage, sqlx::Error> {
+        current_usage(&self.pool, tenant_id, Utc::now()).await
+    }
+
+    /// Runs of the tenant with jobs running now, taking up its quota.
+    pub async fn running_runs(&self, tenant_id: Uuid) -> Result<Vec<Uuid>, sqlx::Error> {
+        sqlx::query_scalar(
+            r#"
+            SELECT DISTINCT pipeline_run_id FROM usage_records
+            WHERE tenant_id = $1 AND finished_at IS NULL AND pipeline_run_id IS NOT NULL
+            "#,
+        )
+        .bind(tenant_id)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Whether the tenant could start a job taking up `job` now, and what
+    /// its quota says to do if not. Records nothing.
+    pub async fn check(
+        &self,
+        tenant_id: Uuid,
+        job: JobResources,
+    ) -> Result<Result<(), (QuotaExceeded, QuotaAction)>, sqlx::Error> {
+        let quota = self.quota(tenant_id).await?;
+        if quota.is_unlimited() {
+            return Ok(Ok(()));
+        }
+        let usage = self.current(tenant_id).await?;
+        Ok(check(&quota, &usage, job).map_err(|e| (e, quota.on_exceeded)))
+    }
+
+    /// Start accounting for a stage job if it fits its tenant's quota. A
+    /// stage that already has an open row, e.g. one re-adopted after a
+    /// restart, keeps it.
+    pub async fn admit(
+        &self,
+        owner: JobOwner,
+        stage: &str,
+        job: JobResources,
+    ) -> Result<Admission, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1::text, 0))")
+            .bind(owner.tenant_id)
+            .execute(&mut *tx)
+            .await?;
+        if let Some(id) = open_record(&mut *tx, owner.run_id, stage).await? {
+            tx.commit().await?;
+            return Ok(Admission::Admitted(id));
+        }
+
+        let quota = load_quota(&mut *tx, owner.tenant_id).await?;
+        if !quota.is_unlimited() {
+            let usage = current_usage(&mut *tx, owner.tenant_id, Utc::now()).await?;
+            if let Err(exceeded) = check(&quota, &usage, job) {
+                return Ok(Admission::Denied {
+                    exceeded,
+                    action: quota.on_exceeded,
+                });
+            }
+        }
+        let id = insert_record(&mut *tx, owner, stage, job).await?;
+        tx.commit().await?;
+        Ok(Admission::Admitted(id))
+    }
+
+    /// Account for a stage job that is already running, without checking
+    /// the quota, e.g. one started before the tenant had a quota.
+    pub async fn record(
+        &self,
+        owner: JobOwner,
+        stage: &str,
+        job: JobResources,
+    ) -> Result<Uuid, sqlx::Error> {
+        if let Some(id) = open_record(&self.pool, owner.run_id, stage).await? {
+            return Ok(id);
+        }
+        insert_record(&self.pool, owner, stage, job).await
+    }
+
+    /// Stop accounting for a job, storing what it measurably used if its
+    /// executor could tell.
+    pub async fn finish(
+        &self,
+        usage_id: Uuid,
+        measured: Option<MeasuredUsage>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE usage_records
+            SET finished_at = NOW(), cpu_seconds = $2, memory_gib_seconds = $3,
+                peak_cpu_millis = $4, peak_memory_bytes = $5,
+                avg_cpu_millis = $6, avg_memory_bytes = $7
+            WHERE id = $1 AND finished_at IS NULL
+            "#,
+        )
+        .bind(usage_id)
+        .bind(measured.map(|m| m.cpu_seconds))
+        .bind(measured.map(|m| m.memory_gib_seconds))
+        .bind(measured.map(|m| m.peak_cpu_millis as i64))
+        .bind(measured.map(|m| m.peak_memory_bytes as i64))
+        .bind(measured.map(|m| m.avg_cpu_millis as i64))
+        .bind(measured.map(|m| m.avg_memory_bytes as i64))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Stop accounting for every job of a run still open, e.g. ones whose
+    /// executor went away while the run was interrupted.
+    pub async fn finish_run(&self, run_id: Uuid) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE usage_records SET finished_at = NOW() WHERE pipeline_run_id = $1 AND finished_at IS NULL",
+        )
+        .bind(run_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// The tenant's usage from `from` until `to`, per pipeline, most used
+    /// first. Jobs overlapping either end count only their time inside.
+    pub async fn report(
+        &self,
+        tenant_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<PipelineUsage>, sqlx::Error> {
+        sqlx::query_as::<_, PipelineUsage>(
+            r#"
+            SELECT pipeline_id, COUNT(*) AS jobs,
+                   COALESCE(SUM(secs), 0) / 60 AS run_minutes,
+                   COALESCE(SUM(secs * cpu_millis), 0) / 60000 AS cpu_core_minutes,
+                   COALESCE(SUM(secs * memory_bytes), 0) / 60 / $4 AS memory_gib_minutes
+            FROM (
+                SELECT pipeline_id, cpu_millis, memory_bytes,
+                       GREATEST(EXTRACT(EPOCH FROM LEAST(COALESCE(finished_at, NOW()), $3)
+                                                   - GREATEST(started_at, $2)), 0)::float8 AS secs
+                FROM usage_records
+                WHERE tenant_id = $1 AND started_at < $3 AND COALESCE(finished_at, NOW()) > $2
+            ) u
+            GROUP BY pipeline_id
+            ORDER BY run_minutes DESC
+            "#,
+        )
+        .bind(tenant_id)
+        .bind(from)
+        .bind(to)
+        .bind(GIB)
+        .fetch_all(&self.pool)
+        .await
+    }
+}
+
+async fn load_quota(db: impl PgExecutor<'_>, tenant_id: Uuid) -> Result<TenantQuota, sqlx::Error> {
+    let quota: Option<serde_json::Value> =
+        sqlx::query_scalar("SELECT quota FROM tenants WHERE id = $1")
+            .bind(tenant_id)
+            .fetch_optional(db)
+            .await?;
+    Ok(quota
+        .and_then(|q| serde_json::from_value(q).ok())
+        .unwrap_or_default())
+}
+
+async fn current_usage(
+    db: impl PgExecutor<'_>,
+    tenant_id: Uuid,
+    now: DateTime<Utc>,
+) -> Result<CurrentUsage, sqlx::Error> {
+    sqlx::query_as::<_, CurrentUsage>(
+        r#"
+        SELECT COUNT(*) FILTER (WHERE finished_at IS NULL) AS running_jobs,
+               COALESCE(SUM(cpu_millis) FILTER (WHERE finished_at IS NULL), 0)::BIGINT AS cpu_millis,
+               COALESCE(SUM(memory_bytes) FILTER (WHERE finished_at IS NULL), 0)::BIGINT AS memory_bytes,
+               COALESCE(SUM(EXTRACT(EPOCH FROM COALESCE(finished_at, NOW()) - GREATEST(started_at, $2))), 0)::float8 / 60 AS run_minutes
+        FROM usage_records
+        WHERE tenant_id = $1 AND (finished_at IS NULL OR finished_at > $2)
+        "#,
+    )
+    .bind(tenant_id)
+    .bind(month_start(now))
+    .fetch_one(db)
+    .await
+}
+
+async fn open_record(
+    db: impl PgExecutor<'_>,
+    run_id: Option<Uuid>,
+    stage: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let Some(run_id) = run_id else {
+        return Ok(None);
+    };
+    sqlx::query_scalar(
+        "SELECT id FROM usage_records WHERE pipeline_run_id = $1 AND stage_name = $2 AND finished_at IS NULL",
+    )
+    .bind(run_id)
+    .bind(stage)
+    .fetch_optional(db)
+    .await
+}
+
+async fn insert_record(
+    db: impl PgExecutor<'_>,
+    owner: JobOwner,
+    stage: &str,
+    job: JobResources,
+) -> Result<Uuid, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        INSERT INTO usage_records (id, tenant_id, pipeline_id, pipeline_run_id, stage_name, cpu_millis, memory_bytes, started_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, NOW())
+        RETURNING id
+        "#,
+    )
+    .bind(Uuid::now_v7())
+    .bind(owner.tenant_id)
+    .bind(owner.pipeline_id)
+    .bind(owner.run_id)
+    .bind(stage)
+    .bind(job.cpu_millis)
+    .bind(job.memory_bytes)
+    .fetch_one(db)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quota() -> TenantQuota {
+        TenantQuota {
+            max_concurrent_jobs: Some(2),
+            max_cpu: Some("4".to_string()),
+            max_memory: Some("8Gi".to_string()),
+            max_run_minutes_per_month: Some(1000),
+            on_exceeded: QuotaAction::Queue,
+        }
+    }
+
+    fn job(cpu: &str, memory: &str) -> JobResources {
+        JobResources {
+            cpu_millis: cpu_millis(cpu).unwrap(),
+            memory_bytes: memory_bytes(memory).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_parse_quantities() {
+        assert_eq!(cpu_millis("500m"), Some(500));
+        assert_eq!(cpu_millis("2"), Some(2000));
+        assert_eq!(cpu_millis("0.25"), Some(250));
+        assert_eq!(cpu_millis("lots"), None);
+        assert_eq!(cpu_millis("-1"), None);
+        assert_eq!(memory_bytes("512Mi"), Some(512 * 1024 * 1024));
+        assert_eq!(memory_bytes("1G"), Some(1_000_000_000));
+        assert_eq!(memory_bytes("1024"), Some(1024));
+        assert_eq!(memory_bytes("1Zi"), None);
+    }
+
+    #[test]
+    fn test_job_resources_fall_back_to_limits() {
+        let resources = ResourceRequirements {
+            cpu_request: Some("250m".to_string()),
+            cpu_limit: Some("1".to_string()),
+            memory_request: None,
+            memory_limit: Some("1Gi".to_string()),
+        };
+        assert_eq!(JobResources::of(&resources), job("250m", "1Gi"));
+        assert_eq!(
+            JobResources::of(&ResourceRequirements::default()),
+            JobResources::default()
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&quota()).is_ok());
+        assert!(validate(&TenantQuota::default()).is_ok());
+        let bad_cpu = TenantQuota {
+            max_cpu: Some("0".to_string()),
+            ..quota()
+        };
+        assert!(validate(&bad_cpu).unwrap_err().contains("max_cpu"));
+        let bad_memory = TenantQuota {
+            max_memory: Some("lots".to_string()),
+            ..quota()
+        };
+        assert!(validate(&bad_memory).unwrap_err().contains("max_memory"));
+    }
+
+    #[test]
+    fn test_check_fits() {
+        let usage = CurrentUsage {
+            running_jobs: 1,
+            cpu_millis: 2000,
+            memory_bytes: memory_bytes("4Gi").unwrap(),
+            run_minutes: 10.0,
+        };
+        assert_eq!(check(&quota(), &usage, job("2", "4Gi")), Ok(()));
+        assert_eq!(
+            check(&TenantQuota::default(), &usage, job("64", "1Ti")),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_check_limits() {
+        let usage = CurrentUsage {
+            running_jobs: 1,
+            cpu_millis: 3000,
+            memory_bytes: 0,
+            run_minutes: 10.0,
+        };
+        assert!(matches!(
+            check(&quota(), &usage, job("2", "1Gi")),
+            Err(QuotaExceeded::Resource {
+                resource: "CPU",
+                ..
+            })
+        ));
+
+        let busy = CurrentUsage {
+            running_jobs: 2,
+            ..CurrentUsage::default()
+        };
+        assert_eq!(
+            check(&quota(), &busy, JobResources::default()),
+            Err(QuotaExceeded::ConcurrentJobs { limit: 2 })
+        );
+
+        let spent = CurrentUsage {
+            run_minutes: 1000.0,
+            ..CurrentUsage::default()
+        };
+        assert_eq!(
+            check(&quota(), &spent, JobResources::default()),
+            Err(QuotaExceeded::RunMinutes { limit: 1000 })
+        );
+    }
+
+    #[test]
+    fn test_oversized_job_is_permanent() {
+        let exceeded = check(&quota(), &CurrentUsage::default(), job("1", "16Gi")).unwrap_err();
+        assert!(exceeded.is_permanent());
+        assert_eq!(
+            exceeded.to_string(),
+            "the job requests 16.0Gi memory, more than the quota of 8.0Gi allows"
+        );
+        assert!(!QuotaExceeded::ConcurrentJobs { limit: 1 }.is_permanent());
+    }
+
+    #[test]
+    fn test_retry_at() {
+        let now = Utc.with_ymd_and_hms(2026, 12, 14, 9, 30, 0).unwrap();
+        assert_eq!(
+            QuotaExceeded::RunMinutes { limit: 10 }.retry_at(now),
+            Utc.with_ymd_and_hms(2027, 1, 1, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            QuotaExceeded::ConcurrentJobs { limit: 1 }.retry_at(now),
+            now + RETRY_INTERVAL
+        );
+        assert_eq!(
+            month_start(now),
+            Utc.with_ymd_and_hms(2026, 12, 1, 0, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_usage_sampler() {
+        let start = Instant::now();
+        let at = |secs| start + std::time::Duration::from_secs(secs);
+        assert_eq!(UsageSampler::new(start).finish(at(60)), None);
+
+        let mut sampler = UsageSampler::new(start);
+        // Two cores and 1 GiB from the start until the second reading
+        sampler.sample(
+            at(10),
+            ResourceUsage {
+                cpu_millis: 2000,
+                memory_bytes: GIB as u64,
+            },
+        );
+        // Half a core and 2 GiB from then until the job ends
+        sampler.sample(
+            at(20),
+            ResourceUsage {
+                cpu_millis: 500,
+                memory_bytes: 2 * GIB as u64,
+            },
+        );
+        let measured = sampler.finish(at(40)).unwrap();
+        assert_eq!(measured.cpu_seconds, 2.0 * 20.0 + 0.5 * 20.0);
+        assert_eq!(measured.memory_gib_seconds, 20.0 + 2.0 * 20.0);
+        assert_eq!(measured.peak_cpu_millis, 2000);
+        assert_eq!(measured.peak_memory_bytes, 2 * GIB as u64);
+        assert_eq!(measured.avg_cpu_millis, 1250);
+        assert_eq!(measured.avg_memory_bytes, (1.5 * GIB) as u64);
+    }
+}
@@ -0,0 +1,618 @@
+//! Executor registry - routes jobs to an executor that can run them.
+//!
+//! Executors are registered under a name with capability labels (e.g.
+//! `gpu`). A stage that `runs-on` some labels only goes to executors that
+//! have all of them, and a job only goes to executors that report its
+//! [`Platform`] (e.g. `windows/amd64`) among their capabilities. Matching executors are tried in order of preference,
+//! and a job goes to the first one that is healthy and able to run it, so
+//! when the preferred runner or cluster goes down, jobs that have not
+//! started yet move to the next capable executor instead of failing. Every
+//! dispatch carries a [`RoutingDecision`] explaining where the job went and
+//! why.
+
+use buildit_core::executor::{Executor, JobHandle, JobSpec, Platform, PullPolicy};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// How long an executor that failed a health check is skipped before it is
+/// checked again.
+const UNHEALTHY_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Where a job was sent, recorded with the job for debugging.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoutingDecision {
+    /// Labels the stage asked for with `runs-on`.
+    #[serde(default)]
+    pub runs_on: Vec<String>,
+    /// Platform the job needs.
+    #[serde(default)]
+    pub platform: Platform,
+    /// Executor the job would normally run on.
+    pub preferred: String,
+    /// Executor that runs the job.
+    pub executor: String,
+    /// Executors passed over before `executor`, with the reason for each.
+    pub skipped: Vec<SkippedExecutor>,
+    pub decided_at: DateTime<Utc>,
+}
+
+impl RoutingDecision {
+    /// Whether the job was moved off its preferred executor.
+    pub fn rerouted(&self) -> bool {
+        self.executor != self.preferred
+    }
+}
+
+/// An executor that was not used for a job.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SkippedExecutor {
+    pub executor: String,
+    pub reason: String,
+}
+
+/// A job handed to an executor.
+pub struct Dispatch {
+    pub executor: Arc<dyn Executor>,
+    pub handle: JobHandle,
+    pub routing: RoutingDecision,
+}
+
+/// An executor registered under a name, with the labels it offers.
+struct Registered {
+    name: String,
+    labels: Vec<String>,
+    executor: Arc<dyn Executor>,
+}
+
+impl Registered {
+    /// Whether the executor has every label in `runs_on`. Its name and its
+    /// kind (`docker`, `kubernetes`) count as labels too.
+    fn offers(&self, runs_on: &[String]) -> bool {
+        runs_on.iter().all(|label| {
+            *label == self.name || label == self.executor.name() || self.labels.contains(label)
+        })
+    }
+}
+
+/// Executors available to run jobs, in order of preference.
+pub struct ExecutorRegistry {
+    executors: Vec<Registered>,
+    /// Executors that recently failed a health check, and why.
+    unhealthy: Mutex<HashMap<String, (Instant, String)>>,
+}
+
+impl ExecutorRegistry {
+    /// Create a registry of executors named after their kind, without
+    /// extra labels; the first executor is preferred.
+    pub fn new(executors: Vec<Arc<dyn Executor>>) -> Self {
+        let mut registry = Self {
+            executors: Vec::new(),
+            unhealthy: Mutex::new(HashMap::new()),
+        };
+        for executor in executors {
+            registry.register(executor.name(), vec![], executor);
+        }
+        registry
+    }
+
+    /// Registry with a single executor and nothing to fail over to.
+    pub fn single(executor: Arc<dyn Executor>) -> Self {
+        Self::new(vec![executor])
+    }
+
+    /// Add an executor after the ones registered so far. Its jobs are
+    /// recorded under `name`, so names must be unique.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        labels: Vec<String>,
+        executor: Arc<dyn Executor>,
+    ) {
+        self.executors.push(Registered {
+            name: name.into(),
+            labels,
+            executor,
+        });
+    }
+
+    /// Names of the registered executors, in order of preference.
+    pub fn names(&self) -> Vec<&str> {
+        self.executors.iter().map(|e| e.name.as_str()).collect()
+    }
+
+    /// Look up an executor by name, e.g. to re-attach to one of its jobs.
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Executor>> {
+        self.executors
+            .iter()
+            .find(|e| e.name == name)
+            .map(|e| e.executor.clone())
+    }
+
+    /// Executors that have the `runs_on` labels and run jobs on `platform`,
+    /// in order of preference. Fails with the reason when there are none,
+    /// so a job nothing can run is refused before it is queued.
+    pub async fn check(&self, runs_on: &[String], platform: &Platform) -> Result<(), String> {
+        self.candidates(runs_on, platform).await.map(|_| ())
+    }
+
+    async fn candidates(
+        &self,
+        runs_on: &[String],
+        platform: &Platform,
+    ) -> Result<Vec<&Registered>, String> {
+        let labelled: Vec<&Registered> = self
+            .executors
+            .iter()
+            .filter(|e| e.offers(runs_on))
+            .collect();
+        if labelled.is_empty() {
+            return Err(format!(
+                "No executor has the labels [{}] (available: {})",
+                runs_on.join(", "),
+                self.names().join(", ")
+            ));
+        }
+
+        let mut candidates = Vec::new();
+        let mut available = Vec::new();
+        for entry in labelled {
+            let platforms = entry.executor.platforms().await;
+            if platforms.iter().any(|p| p.supports(platform)) {
+                candidates.push(entry);
+            } else {
+                let platforms: Vec<String> = platforms.iter().map(|p| p.to_string()).collect();
+                available.push(format!(
+                    "{}: {}",
+                    entry.name,
+                    if platforms.is_empty() {
+                        "none".to_string()
+                    } else {
+                        platforms.join(", ")
+                    }
+                ));
+            }
+        }
+        if candidates.is_empty() {
+            let labels = if runs_on.is_empty() {
+                String::new()
+            } else {
+                format!(" with the labels [{}]", runs_on.join(", "))
+            };
+            return Err(format!(
+                "No executor{} runs {} jobs (available: {})",
+                labels,
+                platform,
+                available.join("; ")
+            ));
+        }
+        Ok(candidates)
+    }
+
+    /// Spawn the job on the first healthy executor that has the `runs_on`
+    /// labels, runs jobs on its platform and can run it.
+    ///
+    /// A spawn error moves on to the next executor only when the executor
+    /// turns out to be unhealthy; otherwise it is the job's fault and is
+    /// returned as-is.
+    pub async fn spawn(&self, spec: JobSpec, runs_on: &[String]) -> Result<Dispatch, String> {
+        let candidates = self.candidates(runs_on, &spec.platform).await?;
+        let preferred = candidates[0].name.clone();
+        let mut skipped = Vec::new();
+
+        for entry in candidates {
+            let name = entry.name.as_str();
+            let executor = &entry.executor;
+            let mut skip = |reason: String| {
+                skipped.push(SkippedExecutor {
+                    executor: name.to_string(),
+                    reason,
+                })
+            };
+            if let Some(reason) = self.cooling_down(name) {
+                skip(reason);
+                continue;
+            }
+            if let Err(reason) = self.check_health(entry).await {
+                skip(reason);
+                continue;
+            }
+            if !executor.can_execute(&spec).await {
+                skip("cannot run this job".to_string());
+                continue;
+            }
+
+            match executor.spawn(spec.clone()).await {
+                Ok(mut handle) => {
+                    // Record the registered name so the job can be found
+                    // again among several executors of the same kind
+                    handle.executor_name = name.to_string();
+                    let routing = RoutingDecision {
+                        runs_on: runs_on.to_vec(),
+                        platform: spec.platform,
+                        preferred: preferred.clone(),
+                        executor: name.to_string(),
+                        skipped,
+                        decided_at: Utc::now(),
+                    };
+                    if routing.rerouted() {
+                        info!(job_id = %spec.id, from = %preferred, to = %name, skipped = ?routing.skipped, "Rerouted job");
+                    }
+                    return Ok(Dispatch {
+                        executor: executor.clone(),
+                        handle,
+                        routing,
+                    });
+                }
+                Err(e) => {
+                    let spawn_error = format!("Failed to spawn job: {}", e);
+                    if self.check_health(entry).await.is_ok() {
+                        return Err(spawn_error);
+                    }
+                    skip(spawn_error);
+                }
+            }
+        }
+
+        let reasons: Vec<String> = skipped
+            .iter()
+            .map(|s| format!("{}: {}", s.executor, s.reason))
+            .collect();
+        Err(format!(
+            "No executor can run the job ({})",
+            reasons.join("; ")
+        ))
+    }
+
+    /// Resolve `image` to a digest on the executor a job with the `runs_on`
+    /// labels on `platform` would go to first, skipping ones cooling down.
+    /// `None` when no executor can tell; the job then runs the tag.
+    pub async fn resolve_image(
+        &self,
+        image: &str,
+        pull_policy: Option<PullPolicy>,
+        runs_on: &[String],
+        platform: &Platform,
+    ) -> Option<String> {
+        let candidates = self.candidates(runs_on, platform).await.ok()?;
+        let entry = candidates
+            .into_iter()
+            .find(|entry| self.cooling_down(&entry.name).is_none())?;
+        match entry.executor.resolve_image(image, pull_policy).await {
+            Ok(pinned) => pinned,
+            Err(e) => {
+                warn!(executor = %entry.name, image = %image, error = %e, "Failed to resolve image");
+                None
+            }
+        }
+    }
+
+    /// Remove the shared workspace `workspace` from every executor, since
+    /// the jobs of a run may have been routed to several of them.
+    pub async fn remove_workspace(&self, workspace: &str) {
+        for entry in &self.executors {
+            if let Err(e) = entry.executor.remove_workspace(workspace).await {
+                warn!(executor = %entry.name, workspace = %workspace, error = %e, "Failed to remove workspace");
+            }
+        }
+    }
+
+    /// Reason an executor is still being skipped, if it is.
+    fn cooling_down(&self, name: &str) -> Option<String> {
+        let unhealthy = self.unhealthy.lock().unwrap_or_else(|e| e.into_inner());
+        unhealthy
+            .get(name)
+            .filter(|(since, _)| since.elapsed() < UNHEALTHY_COOLDOWN)
+            .map(|(_, reason)| reason.clone())
+    }
+
+    async fn check_health(&self, entry: &Registered) -> Result<(), String> {
+        let name = &entry.name;
+        let result = entry
+            .executor
+            .health()
+            .await
+            .map_err(|e| format!("unhealthy: {}", e));
+        let mut unhealthy = self.unhealthy.lock().unwrap_or_else(|e| e.into_inner());
+        match &result {
+            Ok(()) => {
+                if unhealthy.remove(name).is_some() {
+                    info!(executor = %name, "Executor is healthy again");
+                }
+            }
+            Err(reason) => {
+                warn!(executor = %name, reason = %reason, "Executor is unhealthy");
+                unhealthy.insert(name.clone(), (Instant::now(), reason.clone()));
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use buildit_core::ResourceId;
+    use buildit_core::executor::{
+        Arch, JobResult, JobStatus, LogLine, Os, ResourceRequirements, TerminalSession,
+    };
+    use futures::stream::BoxStream;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct FakeExecutor {
+        name: &'static str,
+        healthy: bool,
+        capable: bool,
+        platforms: Vec<Platform>,
+        spawned: AtomicUsize,
+    }
+
+    impl FakeExecutor {
+        fn new(name: &'static str, healthy: bool, capable: bool) -> Arc<Self> {
+            Self::on(name, vec![Platform::new(Os::Linux, Arch::Amd64)])
+                .healthy(healthy)
+                .capable(capable)
+        }
+
+        fn on(name: &'static str, platforms: Vec<Platform>) -> Self {
+            Self {
+                name,
+                healthy: true,
+                capable: true,
+                platforms,
+                spawned: AtomicUsize::new(0),
+            }
+        }
+
+        fn healthy(self, healthy: bool) -> Self {
+            Self { healthy, ..self }
+        }
+
+        fn capable(self, capable: bool) -> Arc<Self> {
+            Arc::new(Self { capable, ..self })
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Executor for FakeExecutor {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        async fn can_execute(&self, _spec: &JobSpec) -> bool {
+            self.capable
+        }
+
+        async fn platforms(&self) -> Vec<Platform> {
+            self.platforms.clone()
+        }
+
+        async fn health(&self) -> buildit_core::Result<()> {
+            if self.healthy {
+                Ok(())
+            } else {
+                Err(buildit_core::Error::ExecutionFailed(
+                    "connection refused".to_string(),
+                ))
+            }
+        }
+
+        async fn spawn(&self, spec: JobSpec) -> buildit_core::Result<JobHandle> {
+            self.spawned.fetch_add(1, Ordering::SeqCst);
+            Ok(JobHandle {
+                id: spec.id,
+                executor_id: format!("{}-job", self.name),
+                executor_name: self.name.to_string(),
+            })
+        }
+
+        async fn logs(
+            &self,
+            _handle: &JobHandle,
+        ) -> buildit_core::Result<BoxStream<'static, LogLine>> {
+            unimplemented!()
+        }
+
+        async fn status(&self, _handle: &JobHandle) -> buildit_core::Result<JobStatus> {
+            unimplemented!()
+        }
+
+        async fn wait(&self, _handle: &JobHandle) -> buildit_core::Result<JobResult> {
+            unimplemented!()
+        }
+
+        async fn cancel(&self, _handle: &JobHandle) -> buildit_core::Result<()> {
+            unimplemented!()
+        }
+
+        async fn exec_interactive(
+            &self,
+            _handle: &JobHandle,
+            _cmd: Vec<String>,
+        ) -> buildit_core::Result<TerminalSession> {
+            unimplemented!()
+        }
+    }
+
+    fn job() -> JobSpec {
+        JobSpec {
+            id: ResourceId::new(),
+            image: "alpine".to_string(),
+            command: vec!["true".to_string()],
+            entrypoint: None,
+            user: None,
+            working_dir: None,
+            env: HashMap::new(),
+            resources: ResourceRequirements::default(),
+            timeout: None,
+            volumes: vec![],
+            git_clone: None,
+            platform: Default::default(),
+            wait_for: vec![],
+            security_context: None,
+            privileged: false,
+            extra_hosts: vec![],
+            network_mode: None,
+            workspace: None,
+            tenant_id: None,
+            pull_policy: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prefers_first_executor() {
+        let cluster = FakeExecutor::new("kubernetes", true, true);
+        let local = FakeExecutor::new("docker", true, true);
+        let registry = ExecutorRegistry::new(vec![cluster.clone(), local.clone()]);
+
+        let dispatch = registry.spawn(job(), &[]).await.unwrap();
+
+        assert_eq!(dispatch.handle.executor_name, "kubernetes");
+        assert!(!dispatch.routing.rerouted());
+        assert!(dispatch.routing.skipped.is_empty());
+        assert_eq!(local.spawned.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_reroutes_around_unhealthy_and_incapable_executors() {
+        let cluster = FakeExecutor::new("kubernetes", false, true);
+        let gpu = FakeExecutor::new("gpu", true, false);
+        let local = FakeExecutor::new("docker", true, true);
+        let registry = ExecutorRegistry::new(vec![cluster.clone(), gpu, local]);
+
+        let dispatch = registry.spawn(job(), &[]).await.unwrap();
+
+        assert_eq!(dispatch.handle.executor_name, "docker");
+        assert!(dispatch.routing.rerouted());
+        assert_eq!(dispatch.routing.preferred, "kubernetes");
+        let skipped: Vec<_> = dispatch
+            .routing
+            .skipped
+            .iter()
+            .map(|s| (s.executor.as_str(), s.reason.as_str()))
+            .collect();
+        assert_eq!(
+            skipped,
+            [
+                (
+                    "kubernetes",
+                    "unhealthy: execution failed: connection refused"
+                ),
+                ("gpu", "cannot run this job"),
+            ]
+        );
+        assert_eq!(cluster.spawned.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_fails_when_no_executor_can_run_the_job() {
+        let registry = ExecutorRegistry::single(FakeExecutor::new("docker", false, true));
+
+        let err = registry.spawn(job(), &[]).await.err().unwrap();
+
+        assert!(err.contains("docker: unhealthy"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_routes_by_runs_on_labels() {
+        let local = FakeExecutor::new("docker", true, true);
+        let gpu = FakeExecutor::new("kubernetes", true, true);
+        let mut registry = ExecutorRegistry::single(local.clone());
+        registry.register("gpu-cluster", vec!["gpu".to_string()], gpu.clone());
+
+        let dispatch = registry.spawn(job(), &["gpu".to_string()]).await.unwrap();
+
+        assert_eq!(dispatch.handle.executor_name, "gpu-cluster");
+        assert_eq!(dispatch.routing.preferred, "gpu-cluster");
+        assert_eq!(dispatch.routing.runs_on, ["gpu"]);
+        assert!(registry.get("gpu-cluster").is_some());
+        assert_eq!(local.spawned.load(Ordering::SeqCst), 0);
+
+        // The kind of an executor works as a label too
+        let dispatch = registry
+            .spawn(job(), &["kubernetes".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(dispatch.handle.executor_name, "gpu-cluster");
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_among_labelled_executors() {
+        let busy = FakeExecutor::new("kubernetes", true, false);
+        let spare = FakeExecutor::new("docker", true, true);
+        let other = FakeExecutor::new("docker", true, true);
+        let mut registry = ExecutorRegistry::new(vec![]);
+        registry.register("gpu-a", vec!["gpu".to_string()], busy);
+        registry.register("plain", vec![], other.clone());
+        registry.register("gpu-b", vec!["gpu".to_string()], spare);
+
+        let dispatch = registry.spawn(job(), &["gpu".to_string()]).await.unwrap();
+
+        assert_eq!(dispatch.handle.executor_name, "gpu-b");
+        assert!(dispatch.routing.rerouted());
+        assert_eq!(dispatch.routing.skipped.len(), 1);
+        assert_eq!(other.spawned.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_fails_when_no_executor_has_the_labels() {
+        let registry = ExecutorRegistry::single(FakeExecutor::new("docker", true, true));
+
+        let err = registry
+            .spawn(job(), &["gpu".to_string()])
+            .await
+            .err()
+            .unwrap();
+
+        assert!(err.contains("No executor has the labels [gpu]"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_routes_by_platform() {
+        let linux = FakeExecutor::new("docker", true, true);
+        let windows = FakeExecutor::on("kubernetes", vec![Platform::new(Os::Windows, Arch::Amd64)])
+            .capable(true);
+        let mac =
+            FakeExecutor::on("remote", vec![Platform::new(Os::Macos, Arch::Arm64)]).capable(true);
+        let registry = ExecutorRegistry::new(vec![linux.clone(), windows, mac]);
+
+        let mut spec = job();
+        spec.platform = "windows".parse().unwrap();
+        let dispatch = registry.spawn(spec, &[]).await.unwrap();
+        assert_eq!(dispatch.handle.executor_name, "kubernetes");
+        assert_eq!(dispatch.routing.platform.os, Os::Windows);
+        // Executors of another platform are not candidates, not skipped
+        assert_eq!(dispatch.routing.preferred, "kubernetes");
+        assert!(dispatch.routing.skipped.is_empty());
+
+        let mut spec = job();
+        spec.platform = "macos/arm64".parse().unwrap();
+        let dispatch = registry.spawn(spec, &[]).await.unwrap();
+        assert_eq!(dispatch.handle.executor_name, "remote");
+
+        let dispatch = registry.spawn(job(), &[]).await.unwrap();
+        assert_eq!(dispatch.handle.executor_name, "docker");
+    }
+
+    #[tokio::test]
+    async fn test_refuses_platform_no_executor_runs() {
+        let linux = FakeExecutor::new("docker", true, true);
+        let registry = ExecutorRegistry::single(linux.clone());
+
+        let platform: Platform = "macos/arm64".parse().unwrap();
+        let err = registry.check(&[], &platform).await.err().unwrap();
+        assert_eq!(
+            err,
+            "No executor runs macos/arm64 jobs (available: docker: linux/amd64)"
+        );
+
+        let mut spec = job();
+        spec.platform = "linux/arm64".parse().unwrap();
+        let err = registry.spawn(spec, &[]).await.err().unwrap();
+        assert!(err.contains("runs linux/arm64 jobs"), "{}", err);
+        assert_eq!(linux.spawned.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,173 @@
+//! Re-running a finished pipeline run.
+//!
+//! A re-run is a new run of the same commit. It can run every stage again,
+//! only the stages that did not succeed, or a stage and everything
+//! downstream of it. Stages that are not run again are reused: the new run
+//! takes their result from the original instead of executing them.
+
+use std::collections::{BTreeSet, HashMap};
+
+/// Which stages of the original run a re-run executes again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RerunMode {
+    /// Every stage.
+    All,
+    /// Stages that failed, were skipped or never finished.
+    FailedOnly,
+    /// The named stage and every stage that depends on it.
+    FromStage(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum RerunError {
+    #[error("stage '{0}' is not part of the pipeline")]
+    UnknownStage(String),
+    #[error("every stage of the run succeeded; there is nothing to re-run")]
+    NothingFailed,
+}
+
+/// Stages a re-run can reuse from the original run.
+///
+/// `needs` maps each of the pipeline's current stages to the stages it
+/// depends on, and `succeeded` names the stages that succeeded in the
+/// original run. A stage is only reused while every stage it needs is
+/// reused too, so a stage that runs again also runs everything after it.
+pub fn reused_stages(
+    needs: &HashMap<String, Vec<String>>,
+    succeeded: &BTreeSet<String>,
+    mode: &RerunMode,
+) -> Result<BTreeSet<String>, RerunError> {
+    let mut reused: BTreeSet<String> = match mode {
+        RerunMode::All => return Ok(BTreeSet::new()),
+        RerunMode::FailedOnly => succeeded
+            .iter()
+            .filter(|stage| needs.contains_key(*stage))
+            .cloned()
+            .collect(),
+        RerunMode::FromStage(stage) => {
+            if !needs.contains_key(stage) {
+                return Err(RerunError::UnknownStage(stage.clone()));
+            }
+            let downstream = downstream_of(needs, stage);
+            succeeded
+                .iter()
+                .filter(|s| needs.contains_key(*s) && !downstream.contains(*s))
+                .cloned()
+                .collect()
+        }
+    };
+
+    // Drop reused stages whose dependencies run again, until none are left
+    loop {
+        let rerun: Vec<String> = reused
+            .iter()
+            .filter(|stage| needs[*stage].iter().any(|need| !reused.contains(need)))
+            .cloned()
+            .collect();
+        if rerun.is_empty() {
+            break;
+        }
+        for stage in rerun {
+            reused.remove(&stage);
+        }
+    }
+
+    if *mode == RerunMode::FailedOnly && reused.len() == needs.len() {
+        return Err(RerunError::NothingFailed);
+    }
+    Ok(reused)
+}
+
+/// `stage` and every stage that depends on it, directly or not.
+fn downstream_of(needs: &HashMap<String, Vec<String>>, stage: &str) -> BTreeSet<String> {
+    let mut downstream = BTreeSet::from([stage.to_string()]);
+    loop {
+        let before = downstream.len();
+        for (name, deps) in needs {
+            if deps.iter().any(|dep| downstream.contains(dep)) {
+                downstream.insert(name.clone());
+            }
+        }
+        if downstream.len() == before {
+            return downstream;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// checkout -> build -> (test, lint) -> deploy
+    fn pipeline() -> HashMap<String, Vec<String>> {
+        [
+            ("checkout", vec![]),
+            ("build", vec!["checkout"]),
+            ("test", vec!["build"]),
+            ("lint", vec!["build"]),
+            ("deploy", vec!["test", "lint"]),
+        ]
+        .into_iter()
+        .map(|(name, needs)| {
+            (
+                name.to_string(),
+                needs.into_iter().map(String::from).collect(),
+            )
+        })
+        .collect()
+    }
+
+    fn set(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_all_reuses_nothing() {
+        let succeeded = set(&["checkout", "build", "test", "lint", "deploy"]);
+        let reused = reused_stages(&pipeline(), &succeeded, &RerunMode::All).unwrap();
+        assert!(reused.is_empty());
+    }
+
+    #[test]
+    fn test_failed_only_reuses_succeeded_stages() {
+        // test failed, so deploy was skipped
+        let succeeded = set(&["checkout", "build", "lint"]);
+        let reused = reused_stages(&pipeline(), &succeeded, &RerunMode::FailedOnly).unwrap();
+        assert_eq!(reused, set(&["checkout", "build", "lint"]));
+    }
+
+    #[test]
+    fn test_failed_only_with_nothing_failed() {
+        let succeeded = set(&["checkout", "build", "test", "lint", "deploy"]);
+        assert_eq!(
+            reused_stages(&pipeline(), &succeeded, &RerunMode::FailedOnly),
+            Err(RerunError::NothingFailed)
+        );
+    }
+
+    #[test]
+    fn test_from_stage_reruns_downstream() {
+        let succeeded = set(&["checkout", "build", "test", "lint", "deploy"]);
+        let mode = RerunMode::FromStage("test".to_string());
+        let reused = reused_stages(&pipeline(), &succeeded, &mode).unwrap();
+        assert_eq!(reused, set(&["checkout", "build", "lint"]));
+    }
+
+    #[test]
+    fn test_from_unknown_stage() {
+        let mode = RerunMode::FromStage("package".to_string());
+        assert_eq!(
+            reused_stages(&pipeline(), &BTreeSet::new(), &mode),
+            Err(RerunError::UnknownStage("package".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_stage_after_a_rerun_stage_is_not_reused() {
+        // build was added to the pipeline after the original run, so it
+        // runs, and so does everything after it
+        let succeeded = set(&["checkout", "test", "lint"]);
+        let reused = reused_stages(&pipeline(), &succeeded, &RerunMode::FailedOnly).unwrap();
+        assert_eq!(reused, set(&["checkout"]));
+    }
+}
@@ -0,0 +1,210 @@
+//! Right-sizing stage resource requests from what their jobs used.
+//!
+//! A stage's CPU request should cover what its jobs use on average and its
+//! memory request what they use at peak, since a job short of CPU is only
+//! throttled but one short of memory is killed. Limits leave room for the
+//! peaks on top. Each is the 95th percentile of the stage's measured jobs
+//! plus [`HEADROOM`], rounded up to a tidy quantity. A stage asking for
+//! more than twice the recommendation is over-provisioned; one whose jobs
+//! use more than it asks for is under-provisioned.
+
+use buildit_db::StageUsage;
+use serde::{Deserialize, Serialize};
+
+/// Measured jobs a stage needs before it is right-sized.
+pub const MIN_JOBS: i64 = 3;
+
+/// Share added on top of measured usage.
+pub const HEADROOM: f64 = 0.2;
+
+/// How many times the recommendation a request may be before it counts as
+/// over-provisioned.
+const OVER_PROVISIONED_FACTOR: f64 = 2.0;
+
+const MIB: u64 = 1024 * 1024;
+const GIB: u64 = 1024 * MIB;
+
+/// Recommendations are rounded up to multiples of these.
+const CPU_STEP_MILLIS: u64 = 50;
+const MEMORY_STEP_BYTES: u64 = 16 * MIB;
+
+/// How a stage's request for a resource compares with what its jobs use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Provisioning {
+    /// The stage requests none.
+    Unset,
+    /// The request fits what the jobs use.
+    Fit,
+    /// The request is more than twice what the jobs need.
+    Over,
+    /// The jobs use more than the request.
+    Under,
+}
+
+impl Provisioning {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Provisioning::Unset => "unset",
+            Provisioning::Fit => "fit",
+            Provisioning::Over => "over",
+            Provisioning::Under => "under",
+        }
+    }
+}
+
+/// Requests and limits a stage should set.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recommendation {
+    pub cpu_request_millis: u64,
+    pub cpu_limit_millis: u64,
+    pub memory_request_bytes: u64,
+    pub memory_limit_bytes: u64,
+    pub cpu: Provisioning,
+    pub memory: Provisioning,
+}
+
+impl Recommendation {
+    /// Whether either request is over- or under-provisioned.
+    pub fn flagged(&self) -> bool {
+        [self.cpu, self.memory]
+            .iter()
+            .any(|p| matches!(p, Provisioning::Over | Provisioning::Under))
+    }
+}
+
+/// What a stage should request, or `None` with too few measured jobs.
+pub fn recommend(usage: &StageUsage) -> Option<Recommendation> {
+    if usage.jobs < MIN_JOBS {
+        return None;
+    }
+    let cpu_request_millis = with_headroom(usage.p95_avg_cpu_millis, CPU_STEP_MILLIS);
+    let cpu_limit_millis =
+        with_headroom(usage.p95_peak_cpu_millis, CPU_STEP_MILLIS).max(cpu_request_millis);
+    let memory_request_bytes = with_headroom(usage.p95_peak_memory_bytes, MEMORY_STEP_BYTES);
+    let memory_limit_bytes = with_headroom(usage.max_peak_memory_bytes as f64, MEMORY_STEP_BYTES)
+        .max(memory_request_bytes);
+
+    Some(Recommendation {
+        cpu: provisioning(
+            usage.cpu_request_millis,
+            usage.p95_avg_cpu_millis,
+            cpu_request_millis,
+        ),
+        memory: provisioning(
+            usage.memory_request_bytes,
+            usage.p95_peak_memory_bytes,
+            memory_request_bytes,
+        ),
+        cpu_request_millis,
+        cpu_limit_millis,
+        memory_request_bytes,
+        memory_limit_bytes,
+    })
+}
+
+/// `measured` plus headroom, rounded up to a multiple of `step`.
+fn with_headroom(measured: f64, step: u64) -> u64 {
+    // Shave off float error so an exact multiple is not rounded up a step
+    let steps = (measured.max(0.0) * (1.0 + HEADROOM) / step as f64 - 1e-9).ceil() as u64;
+    steps.max(1) * step
+}
+
+fn provisioning(requested: i64, used: f64, recommended: u64) -> Provisioning {
+    if requested <= 0 {
+        Provisioning::Unset
+    } else if used > requested as f64 {
+        Provisioning::Under
+    } else if requested as f64 > recommended as f64 * OVER_PROVISIONED_FACTOR {
+        Provisioning::Over
+    } else {
+        Provisioning::Fit
+    }
+}
+
+/// A CPU quantity as written in a pipeline: `2` or `250m`.
+pub fn cpu_quantity(millis: u64) -> String {
+    if millis % 1000 == 0 {
+        (millis / 1000).to_string()
+    } else {
+        format!("{}m", millis)
+    }
+}
+
+/// A memory quantity as written in a pipeline, rounded up to a MiB: `2Gi`
+/// or `384Mi`.
+pub fn memory_quantity(bytes: u64) -> String {
+    if bytes % GIB == 0 {
+        format!("{}Gi", bytes / GIB)
+    } else {
+        format!("{}Mi", bytes.div_ceil(MIB))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(cpu_request_millis: i64, memory_request_bytes: i64) -> StageUsage {
+        StageUsage {
+            pipeline_id: uuid::Uuid::nil(),
+            pipeline_name: "app".to_string(),
+            stage_name: "build".to_string(),
+            jobs: 10,
+            cpu_request_millis,
+            memory_request_bytes,
+            p95_avg_cpu_millis: 400.0,
+            p95_peak_cpu_millis: 1500.0,
+            p95_peak_memory_bytes: 900.0 * MIB as f64,
+            max_peak_memory_bytes: (1200 * MIB) as i64,
+        }
+    }
+
+    #[test]
+    fn test_recommendation() {
+        let recommendation = recommend(&usage(500, GIB as i64 + 512 * MIB as i64)).unwrap();
+        // 400m and 1500m plus 20%, in steps of 50m
+        assert_eq!(recommendation.cpu_request_millis, 500);
+        assert_eq!(recommendation.cpu_limit_millis, 1800);
+        // 900Mi and 1200Mi plus 20%, in steps of 16Mi
+        assert_eq!(recommendation.memory_request_bytes, 1088 * MIB);
+        assert_eq!(recommendation.memory_limit_bytes, 1440 * MIB);
+        assert_eq!(recommendation.cpu, Provisioning::Fit);
+        assert_eq!(recommendation.memory, Provisioning::Fit);
+        assert!(!recommendation.flagged());
+    }
+
+    #[test]
+    fn test_provisioning_flags() {
+        let over = recommend(&usage(4000, 8 * GIB as i64)).unwrap();
+        assert_eq!(over.cpu, Provisioning::Over);
+        assert_eq!(over.memory, Provisioning::Over);
+        assert!(over.flagged());
+
+        let under = recommend(&usage(250, 512 * MIB as i64)).unwrap();
+        assert_eq!(under.cpu, Provisioning::Under);
+        assert_eq!(under.memory, Provisioning::Under);
+
+        let unset = recommend(&usage(0, 0)).unwrap();
+        assert_eq!(unset.cpu, Provisioning::Unset);
+        assert!(!unset.flagged());
+    }
+
+    #[test]
+    fn test_too_few_jobs() {
+        let usage = StageUsage {
+            jobs: MIN_JOBS - 1,
+            ..usage(500, GIB as i64)
+        };
+        assert_eq!(recommend(&usage), None);
+    }
+
+    #[test]
+    fn test_quantities() {
+        assert_eq!(cpu_quantity(2000), "2");
+        assert_eq!(cpu_quantity(250), "250m");
+        assert_eq!(memory_quantity(2 * GIB), "2Gi");
+        assert_eq!(memory_quantity(1088 * MIB), "1088Mi");
+        assert_eq!(memory_quantity(MIB + 1), "2Mi");
+    }
+}
@@ -0,0 +1,286 @@
+//! Running vulnerability scanners and reading their results.
+//!
+//! A scan stage's job runs grype or trivy against the image, writing JSON
+//! results and optionally a CycloneDX SBOM into the workspace. Both are
+//! read back from the job's log like test reports.
+
+use buildit_core::scan::{Finding, ScanSpec, Scanner, Severity};
+use serde::Deserialize;
+
+use crate::orchestrator::shell_quote;
+
+/// Where the job writes the scanner's results and the SBOM.
+pub const RESULTS_PATH: &str = "buildit-scan.json";
+pub const SBOM_PATH: &str = "buildit-sbom.cdx.json";
+
+/// Commands running the scan, for a target with variables already
+/// interpolated or left for the job spec to interpolate.
+pub fn commands(spec: &ScanSpec) -> Vec<String> {
+    let target = shell_quote(&spec.target);
+    let mut commands = Vec::new();
+    match spec.scanner {
+        Scanner::Grype => {
+            commands.push(format!("grype {} -o json --file {}", target, RESULTS_PATH));
+            if spec.sbom {
+                commands.push(format!(
+                    "grype {} -o cyclonedx-json --file {}",
+                    target, SBOM_PATH
+                ));
+            }
+        }
+        Scanner::Trivy => {
+            commands.push(format!(
+                "trivy image --quiet --format json --output {} {}",
+                RESULTS_PATH, target
+            ));
+            if spec.sbom {
+                commands.push(format!(
+                    "trivy image --quiet --format cyclonedx --output {} {}",
+                    SBOM_PATH, target
+                ));
+            }
+        }
+    }
+    commands
+}
+
+/// Files the job writes to its log once the scan ran.
+pub fn outputs(spec: &ScanSpec) -> Vec<String> {
+    let mut outputs = vec![RESULTS_PATH.to_string()];
+    if spec.sbom {
+        outputs.push(SBOM_PATH.to_string());
+    }
+    outputs
+}
+
+/// Findings in a scanner's JSON results.
+pub fn parse(scanner: Scanner, json: &str) -> Result<Vec<Finding>, String> {
+    match scanner {
+        Scanner::Grype => parse_grype(json),
+        Scanner::Trivy => parse_trivy(json),
+    }
+}
+
+#[derive(Deserialize)]
+struct GrypeReport {
+    #[serde(default)]
+    matches: Vec<GrypeMatch>,
+}
+
+#[derive(Deserialize)]
+struct GrypeMatch {
+    vulnerability: GrypeVulnerability,
+    artifact: GrypeArtifact,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GrypeVulnerability {
+    id: String,
+    #[serde(default)]
+    severity: Option<String>,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    data_source: Option<String>,
+    #[serde(default)]
+    fix: Option<GrypeFix>,
+}
+
+#[derive(Deserialize)]
+struct GrypeFix {
+    #[serde(default)]
+    versions: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct GrypeArtifact {
+    name: String,
+    #[serde(default)]
+    version: String,
+}
+
+fn parse_grype(json: &str) -> Result<Vec<Finding>, String> {
+    let report: GrypeReport =
+        serde_json::from_str(json).map_err(|e| format!("invalid grype results: {}", e))?;
+    Ok(report
+        .matches
+        .into_iter()
+        .map(|m| Finding {
+            severity: severity(m.vulnerability.severity.as_deref()),
+            fixed_version: m
+                .vulnerability
+                .fix
+                .and_then(|fix| fix.versions.into_iter().next()),
+            vulnerability_id: m.vulnerability.id,
+            package: m.artifact.name,
+            installed_version: m.artifact.version,
+            title: m.vulnerability.description,
+            url: m.vulnerability.data_source,
+        })
+        .collect())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct TrivyReport {
+    #[serde(default)]
+    results: Vec<TrivyResult>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct TrivyResult {
+    #[serde(default)]
+    vulnerabilities: Option<Vec<TrivyVulnerability>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct TrivyVulnerability {
+    #[serde(rename = "VulnerabilityID")]
+    vulnerability_id: String,
+    pkg_name: String,
+    #[serde(default)]
+    installed_version: String,
+    #[serde(default)]
+    fixed_version: Option<String>,
+    #[serde(default)]
+    severity: Option<String>,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(rename = "PrimaryURL", default)]
+    primary_url: Option<String>,
+}
+
+fn parse_trivy(json: &str) -> Result<Vec<Finding>, String> {
+    let report: TrivyReport =
+        serde_json::from_str(json).map_err(|e| format!("invalid trivy results: {}", e))?;
+    Ok(report
+        .results
+        .into_iter()
+        .flat_map(|r| r.vulnerabilities.unwrap_or_default())
+        .map(|v| Finding {
+            severity: severity(v.severity.as_deref()),
+            vulnerability_id: v.vulnerability_id,
+            package: v.pkg_name,
+            installed_version: v.installed_version,
+            // Trivy lists several fixed versions comma-separated
+            fixed_version: v
+                .fixed_version
+                .and_then(|f| f.split(',').next().map(|f| f.trim().to_string()))
+                .filter(|f| !f.is_empty()),
+            title: v.title,
+            url: v.primary_url,
+        })
+        .collect())
+}
+
+fn severity(value: Option<&str>) -> Severity {
+    value
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(Severity::Unknown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(scanner: Scanner) -> ScanSpec {
+        ScanSpec {
+            target: "registry.example.com/api:1.2".to_string(),
+            scanner,
+            image: None,
+            fail_on: Some(Severity::High),
+            ignore: vec!["CVE-2024-0002".to_string()],
+            ignore_unfixed: true,
+            sbom: true,
+        }
+    }
+
+    #[test]
+    fn test_commands() {
+        let grype = commands(&spec(Scanner::Grype));
+        assert_eq!(
+            grype[0],
+            "grype 'registry.example.com/api:1.2' -o json --file buildit-scan.json"
+        );
+        assert!(grype[1].contains("cyclonedx-json"));
+        let trivy = commands(&spec(Scanner::Trivy));
+        assert_eq!(trivy.len(), 2);
+        assert_eq!(
+            outputs(&spec(Scanner::Trivy)),
+            [RESULTS_PATH, SBOM_PATH].map(String::from)
+        );
+    }
+
+    #[test]
+    fn test_parse_grype() {
+        let json = r#"{"matches": [
+            {"vulnerability": {"id": "CVE-2024-0001", "severity": "Critical",
+                "dataSource": "https://nvd.nist.gov/vuln/detail/CVE-2024-0001",
+                "fix": {"versions": ["3.0.8"], "state": "fixed"}},
+             "artifact": {"name": "openssl", "version": "3.0.7"}},
+            {"vulnerability": {"id": "CVE-2024-0002", "severity": "Negligible",
+                "fix": {"versions": [], "state": "not-fixed"}},
+             "artifact": {"name": "zlib", "version": "1.2.13"}}
+        ]}"#;
+
+        let findings = parse(Scanner::Grype, json).unwrap();
+        assert_eq!(findings.len(), 2);
+        assert_eq!(findings[0].severity, Severity::Critical);
+        assert_eq!(findings[0].fixed_version.as_deref(), Some("3.0.8"));
+        assert_eq!(findings[0].package, "openssl");
+        assert_eq!(findings[1].severity, Severity::Negligible);
+        assert_eq!(findings[1].fixed_version, None);
+    }
+
+    #[test]
+    fn test_parse_trivy() {
+        let json = r#"{"SchemaVersion": 2, "Results": [
+            {"Target": "alpine", "Vulnerabilities": [
+                {"VulnerabilityID": "CVE-2024-0003", "PkgName": "busybox",
+                 "InstalledVersion": "1.36.1-r0", "FixedVersion": "1.36.1-r1, 1.37.0-r0",
+                 "Severity": "HIGH", "Title": "busybox: overflow",
+                 "PrimaryURL": "https://avd.aquasec.com/nvd/cve-2024-0003"}
+            ]},
+            {"Target": "app", "Vulnerabilities": null}
+        ]}"#;
+
+        let findings = parse(Scanner::Trivy, json).unwrap();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].severity, Severity::High);
+        assert_eq!(findings[0].fixed_version.as_deref(), Some("1.36.1-r1"));
+        assert!(parse(Scanner::Trivy, "not json").is_err());
+    }
+
+    #[test]
+    fn test_violations() {
+        let finding = |id: &str, severity, fixed: Option<&str>| Finding {
+            vulnerability_id: id.to_string(),
+            package: "pkg".to_string(),
+            installed_version: "1".to_string(),
+            fixed_version: fixed.map(String::from),
+            severity,
+            title: None,
+            url: None,
+        };
+        let findings = vec![
+            finding("CVE-2024-0001", Severity::Critical, Some("2")),
+            finding("CVE-2024-0002", Severity::Critical, Some("2")),
+            finding("CVE-2024-0003", Severity::High, None),
+            finding("CVE-2024-0004", Severity::Medium, Some("2")),
+        ];
+
+        let spec = spec(Scanner::Grype);
+        let violations = spec.violations(&findings);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].vulnerability_id, "CVE-2024-0001");
+
+        let report_only = ScanSpec {
+            fail_on: None,
+            ..spec
+        };
+        assert!(report_only.violations(&findings).is_empty());
+    }
+}
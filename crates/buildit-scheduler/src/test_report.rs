@@ -0,0 +1,212 @@
+//! Parsing of the test reports stages write.
+//!
+//! Reads JUnit XML, as written by most test runners (a `<testsuites>` or
+//! `<testsuite>` root, nested suites included), and xUnit.net v2 XML (an
+//! `<assemblies>` or `<assembly>` root), into test cases.
+
+use buildit_core::test_report::{TestCase, TestStatus};
+use roxmltree::{Document, Node};
+
+/// Longest failure details kept per test; the rest is cut off.
+const DETAILS_LIMIT: usize = 64 * 1024;
+
+/// Test cases of a JUnit or xUnit.net XML report.
+pub fn parse(xml: &str) -> Result<Vec<TestCase>, String> {
+    let doc = Document::parse(xml).map_err(|e| format!("invalid XML: {}", e))?;
+    let root = doc.root_element();
+    let mut cases = Vec::new();
+    match root.tag_name().name() {
+        "testsuites" | "testsuite" => junit_suite(root, "", &mut cases),
+        "assemblies" | "assembly" => xunit(root, &mut cases),
+        other => return Err(format!("unsupported report format <{}>", other)),
+    }
+    Ok(cases)
+}
+
+fn junit_suite(node: Node, parent: &str, cases: &mut Vec<TestCase>) {
+    let suite = match node.attribute("name") {
+        Some(name) if node.has_tag_name("testsuite") => name,
+        _ => parent,
+    };
+    for child in node.children().filter(Node::is_element) {
+        match child.tag_name().name() {
+            "testsuite" => junit_suite(child, suite, cases),
+            "testcase" => cases.push(junit_case(child, suite)),
+            _ => {}
+        }
+    }
+}
+
+fn junit_case(node: Node, suite: &str) -> TestCase {
+    let outcome = node.children().filter(Node::is_element).find_map(|child| {
+        let status = match child.tag_name().name() {
+            "failure" => TestStatus::Failed,
+            "error" => TestStatus::Errored,
+            "skipped" => TestStatus::Skipped,
+            _ => return None,
+        };
+        Some((status, child))
+    });
+    let (status, message, details) = match outcome {
+        Some((status, child)) => (
+            status,
+            child.attribute("message").map(str::to_string),
+            child.text().map(str::trim).and_then(details),
+        ),
+        None => (TestStatus::Passed, None, None),
+    };
+    TestCase {
+        suite: suite.to_string(),
+        classname: node.attribute("classname").unwrap_or_default().to_string(),
+        name: node.attribute("name").unwrap_or_default().to_string(),
+        status,
+        duration_secs: seconds(node.attribute("time")),
+        message,
+        details,
+    }
+}
+
+fn xunit(root: Node, cases: &mut Vec<TestCase>) {
+    for assembly in root.descendants().filter(|n| n.has_tag_name("assembly")) {
+        let suite = assembly.attribute("name").unwrap_or_default();
+        for test in assembly.descendants().filter(|n| n.has_tag_name("test")) {
+            let status = match test.attribute("result") {
+                Some("Fail") => TestStatus::Failed,
+                Some("Skip") => TestStatus::Skipped,
+                Some("NotRun") => TestStatus::Skipped,
+                _ => TestStatus::Passed,
+            };
+            let failure = test.children().find(|n| n.has_tag_name("failure"));
+            let text = |parent: Option<Node>, tag: &str| {
+                parent
+                    .and_then(|p| p.children().find(|n| n.has_tag_name(tag)))
+                    .and_then(|n| n.text())
+                    .map(str::trim)
+                    .filter(|t| !t.is_empty())
+                    .map(str::to_string)
+            };
+            let message = match status {
+                TestStatus::Skipped => text(Some(test), "reason"),
+                _ => text(failure, "message"),
+            };
+            cases.push(TestCase {
+                suite: suite.to_string(),
+                classname: test.attribute("type").unwrap_or_default().to_string(),
+                name: test
+                    .attribute("method")
+                    .or(test.attribute("name"))
+                    .unwrap_or_default()
+                    .to_string(),
+                status,
+                duration_secs: seconds(test.attribute("time")),
+                message,
+                details: text(failure, "stack-trace").and_then(|t| details(&t)),
+            });
+        }
+    }
+}
+
+/// A `time` attribute in seconds; some runners group thousands with commas.
+fn seconds(time: Option<&str>) -> f64 {
+    time.and_then(|t| t.replace(',', "").trim().parse::<f64>().ok())
+        .filter(|t| t.is_finite() && *t >= 0.0)
+        .unwrap_or(0.0)
+}
+
+fn details(text: &str) -> Option<String> {
+    if text.is_empty() {
+        return None;
+    }
+    let mut end = text.len().min(DETAILS_LIMIT);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    Some(text[..end].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_junit() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <testsuites>
+              <testsuite name="api" tests="4">
+                <testcase classname="api.auth" name="login" time="0.25"/>
+                <testcase classname="api.auth" name="logout" time="1,200.5">
+                  <failure message="expected 200, got 500">assertion failed
+                    at auth.rs:42</failure>
+                </testcase>
+                <testcase classname="api.db" name="migrate">
+                  <error message="panicked"/>
+                </testcase>
+                <testsuite name="slow">
+                  <testcase classname="api.slow" name="soak">
+                    <skipped message="ignored"/>
+                  </testcase>
+                </testsuite>
+              </testsuite>
+            </testsuites>"#;
+
+        let cases = parse(xml).unwrap();
+        assert_eq!(cases.len(), 4);
+        assert_eq!(cases[0].suite, "api");
+        assert_eq!(cases[0].status, TestStatus::Passed);
+        assert_eq!(cases[0].duration_secs, 0.25);
+        assert_eq!(cases[1].status, TestStatus::Failed);
+        assert_eq!(cases[1].duration_secs, 1200.5);
+        assert_eq!(cases[1].message.as_deref(), Some("expected 200, got 500"));
+        assert!(
+            cases[1]
+                .details
+                .as_deref()
+                .unwrap()
+                .ends_with("at auth.rs:42")
+        );
+        assert_eq!(cases[2].status, TestStatus::Errored);
+        assert_eq!(cases[2].details, None);
+        assert_eq!(cases[3].suite, "slow");
+        assert_eq!(cases[3].status, TestStatus::Skipped);
+    }
+
+    #[test]
+    fn test_parse_single_testsuite() {
+        let xml = r#"<testsuite name="pytest"><testcase classname="tests.test_x" name="test_y" time="0.01"/></testsuite>"#;
+        let cases = parse(xml).unwrap();
+        assert_eq!(cases.len(), 1);
+        assert_eq!(cases[0].suite, "pytest");
+        assert_eq!(cases[0].classname, "tests.test_x");
+    }
+
+    #[test]
+    fn test_parse_xunit() {
+        let xml = r#"<assemblies>
+              <assembly name="Api.Tests.dll">
+                <collection name="Auth">
+                  <test name="Auth.Login" type="Api.Tests.Auth" method="Login" time="0.5" result="Pass"/>
+                  <test name="Auth.Logout" type="Api.Tests.Auth" method="Logout" time="0.1" result="Fail">
+                    <failure><message>Assert.Equal() Failure</message><stack-trace>at Auth.cs:10</stack-trace></failure>
+                  </test>
+                  <test name="Auth.Soak" type="Api.Tests.Auth" method="Soak" time="0" result="Skip"><reason>slow</reason></test>
+                </collection>
+              </assembly>
+            </assemblies>"#;
+
+        let cases = parse(xml).unwrap();
+        assert_eq!(cases.len(), 3);
+        assert_eq!(cases[0].suite, "Api.Tests.dll");
+        assert_eq!(cases[0].name, "Login");
+        assert_eq!(cases[1].status, TestStatus::Failed);
+        assert_eq!(cases[1].message.as_deref(), Some("Assert.Equal() Failure"));
+        assert_eq!(cases[1].details.as_deref(), Some("at Auth.cs:10"));
+        assert_eq!(cases[2].status, TestStatus::Skipped);
+        assert_eq!(cases[2].message.as_deref(), Some("slow"));
+    }
+
+    #[test]
+    fn test_parse_rejects_other_documents() {
+        assert!(parse("<html></html>").unwrap_err().contains("unsupported"));
+        assert!(parse("not xml").unwrap_err().contains("invalid XML"));
+    }
+}
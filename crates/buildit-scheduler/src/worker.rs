@@ -1,11 +1,18 @@
-//! Worker that processes jobs from the queue.
+//! Workers that process jobs and tasks from the queue.
 
-use crate::queue::JobQueue;
+use crate::metrics;
+use crate::queue::{JobQueue, QueuedTask};
+use async_trait::async_trait;
 use buildit_executor::Executor;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
 use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
 use tokio::time::sleep;
-use tracing::{info, warn};
+use tracing::{error, info, warn};
 
 /// A worker that claims and executes jobs.
 pub struct Worker {
@@ -28,7 +35,9 @@ impl Worker {
     pub async fn run(&self) {
         info!(worker_id = %self.id, "Starting worker");
 
+        let mut wakeups = self.queue.job_wakeups();
         loop {
+            wakeups.borrow_and_update();
             match self.queue.claim(&self.id).await {
                 Ok(Some(job)) => {
                     info!(job_id = %job.id, stage = %job.stage_name, "Claimed job");
@@ -40,8 +49,8 @@ impl Worker {
                     }
                 }
                 Ok(None) => {
-                    // No jobs available, wait before polling again
-                    sleep(Duration::from_secs(1)).await;
+                    // No jobs available, wait until one is queued
+                    self.queue.wait(&mut wakeups).await;
                 }
                 Err(e) => {
                     warn!(error = %e, "Failed to claim job");
@@ -51,3 +60,244 @@ impl Worker {
         }
     }
 }
+
+/// Runs the tasks of the kinds it supports.
+#[async_trait]
+pub trait TaskHandler: Send + Sync {
+    /// Task kinds this process can run; other kinds are left for workers
+    /// that can (e.g. ones with an executor configured).
+    fn kinds(&self) -> Vec<String>;
+
+    /// Run a task. Handlers must be safe to run again after a crash: they
+    /// reload what they need from the payload's IDs and skip work that the
+    /// database or the task's checkpoint shows is already done.
+    async fn handle(&self, task: TaskContext) -> Result<(), String>;
+}
+
+/// A claimed task as seen by its handler.
+pub struct TaskContext {
+    queue: Arc<JobQueue>,
+    pub task: QueuedTask,
+    deferred: Arc<AtomicBool>,
+}
+
+impl TaskContext {
+    /// Deserialize the task's payload.
+    pub fn payload<T: DeserializeOwned>(&self) -> Result<T, String> {
+        serde_json::from_value(self.task.payload.clone())
+            .map_err(|e| format!("invalid {} task payload: {}", self.task.kind, e))
+    }
+
+    /// The last checkpoint, or the default state on the first attempt.
+    pub fn state<T: DeserializeOwned + Default>(&self) -> T {
+        serde_json::from_value(self.task.state.clone()).unwrap_or_default()
+    }
+
+    /// Save progress for a later attempt to resume from.
+    pub async fn checkpoint<T: Serialize>(&self, state: &T) -> Result<(), String> {
+        let state = serde_json::to_value(state).map_err(|e| e.to_string())?;
+        self.queue
+            .checkpoint_task(self.task.id, &state)
+            .await
+            .map_err(|e| format!("failed to checkpoint task: {}", e))
+    }
+
+    /// Run the task again at `run_after` instead of completing it when the
+    /// handler returns. The attempt does not count towards its retries.
+    pub async fn defer_until(&self, run_after: DateTime<Utc>) -> Result<(), String> {
+        self.queue
+            .defer_task(self.task.id, run_after)
+            .await
+            .map_err(|e| format!("failed to defer task: {}", e))?;
+        self.deferred.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+/// Configuration for a task worker.
+#[derive(Debug, Clone)]
+pub struct TaskWorkerConfig {
+    /// Tasks run at the same time.
+    pub concurrency: usize,
+    /// How often a running task's heartbeat is recorded.
+    pub heartbeat_interval: Duration,
+    /// Claimed tasks without a heartbeat for this long are requeued.
+    pub stale_after: Duration,
+}
+
+impl Default for TaskWorkerConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: 4,
+            heartbeat_interval: Duration::from_secs(15),
+            stale_after: Duration::from_secs(120),
+        }
+    }
+}
+
+impl TaskWorkerConfig {
+    /// Read `BUILDIT_TASK_CONCURRENCY` and `BUILDIT_TASK_STALE_SECS`.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let env = |key: &str| std::env::var(key).ok().and_then(|v| v.parse::<u64>().ok());
+        Self {
+            concurrency: env("BUILDIT_TASK_CONCURRENCY")
+                .map(|n| n as usize)
+                .unwrap_or(defaults.concurrency),
+            stale_after: env("BUILDIT_TASK_STALE_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.stale_after),
+            ..defaults
+        }
+    }
+}
+
+/// A worker that claims tasks and runs them with a [`TaskHandler`].
+pub struct TaskWorker {
+    id: String,
+    queue: Arc<JobQueue>,
+    handler: Arc<dyn TaskHandler>,
+    config: TaskWorkerConfig,
+}
+
+impl TaskWorker {
+    pub fn new(
+        id: impl Into<String>,
+        queue: Arc<JobQueue>,
+        handler: Arc<dyn TaskHandler>,
+        config: TaskWorkerConfig,
+    ) -> Self {
+        Self {
+            id: id.into(),
+            queue,
+            handler,
+            config,
+        }
+    }
+
+    /// Run the worker loop.
+    pub async fn run(self) {
+        let kinds = self.handler.kinds();
+        info!(worker_id = %self.id, ?kinds, concurrency = self.config.concurrency, "Starting task worker");
+        if kinds.is_empty() || self.config.concurrency == 0 {
+            return;
+        }
+
+        let slots = Arc::new(Semaphore::new(self.config.concurrency));
+        let mut wakeups = self.queue.task_wakeups();
+        let mut last_sweep: Option<Instant> = None;
+        loop {
+            if last_sweep.is_none_or(|t| t.elapsed() >= self.config.stale_after / 2) {
+                match self
+                    .queue
+                    .requeue_stale_tasks(self.config.stale_after)
+                    .await
+                {
+                    Ok(0) => {}
+                    Ok(n) => warn!(count = n, "Requeued tasks of unresponsive workers"),
+                    Err(e) => warn!(error = %e, "Failed to requeue stale tasks"),
+                }
+                last_sweep = Some(Instant::now());
+            }
+
+            let Ok(slot) = slots.clone().acquire_owned().await else {
+                return;
+            };
+            wakeups.borrow_and_update();
+            match self.queue.claim_task(&self.id, &kinds).await {
+                Ok(Some(task)) => {
+                    info!(task_id = %task.id, kind = %task.kind, attempt = task.attempts, "Claimed task");
+                    let worker = self.task_runner();
+                    tokio::spawn(async move {
+                        worker.process(task).await;
+                        drop(slot);
+                    });
+                }
+                Ok(None) => {
+                    drop(slot);
+                    self.queue.wait(&mut wakeups).await;
+                }
+                Err(e) => {
+                    drop(slot);
+                    warn!(error = %e, "Failed to claim task");
+                    sleep(Duration::from_secs(5)).await;
+                }
+            }
+        }
+    }
+
+    fn task_runner(&self) -> TaskRunner {
+        TaskRunner {
+            id: self.id.clone(),
+            queue: self.queue.clone(),
+            handler: self.handler.clone(),
+            heartbeat_interval: self.config.heartbeat_interval,
+        }
+    }
+}
+
+/// Runs one claimed task, heartbeating until it finishes.
+struct TaskRunner {
+    id: String,
+    queue: Arc<JobQueue>,
+    handler: Arc<dyn TaskHandler>,
+    heartbeat_interval: Duration,
+}
+
+impl TaskRunner {
+    async fn process(self, task: QueuedTask) {
+        let task_id = task.id;
+        let kind = task.kind.clone();
+        let deferred = Arc::new(AtomicBool::new(false));
+        let context = TaskContext {
+            queue: self.queue.clone(),
+            task,
+            deferred: deferred.clone(),
+        };
+        let handler = self.handler.clone();
+        let started = Instant::now();
+        // Run on its own task so a panicking handler fails the task instead
+        // of leaving it claimed.
+        let mut run = tokio::spawn(async move { handler.handle(context).await });
+
+        let mut heartbeat = tokio::time::interval(self.heartbeat_interval);
+        heartbeat.tick().await;
+        let result = loop {
+            tokio::select! {
+                result = &mut run => break result,
+                _ = heartbeat.tick() => {
+                    if let Err(e) = self.queue.heartbeat_task(task_id, &self.id).await {
+                        warn!(task_id = %task_id, error = %e, "Failed to record task heartbeat");
+                    }
+                }
+            }
+        };
+
+        let outcome = match result {
+            Ok(Ok(())) if deferred.load(Ordering::SeqCst) => {
+                metrics::record_task(&kind, "deferred", started.elapsed());
+                info!(task_id = %task_id, kind = %kind, "Task deferred");
+                Ok(())
+            }
+            Ok(Ok(())) => {
+                metrics::record_task(&kind, "completed", started.elapsed());
+                self.queue.complete_task(task_id).await
+            }
+            Ok(Err(message)) => {
+                metrics::record_task(&kind, "failed", started.elapsed());
+                warn!(task_id = %task_id, kind = %kind, error = %message, "Task failed");
+                self.queue.fail_task(task_id, &message).await
+            }
+            Err(e) => {
+                metrics::record_task(&kind, "panicked", started.elapsed());
+                error!(task_id = %task_id, kind = %kind, error = %e, "Task panicked");
+                self.queue
+                    .fail_task(task_id, &format!("task panicked: {}", e))
+                    .await
+            }
+        };
+        if let Err(e) = outcome {
+            error!(task_id = %task_id, error = %e, "Failed to record task outcome");
+        }
+    }
+}
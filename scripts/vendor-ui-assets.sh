@@ -0,0 +1,23 @@
+#!/bin/sh
+# Download the UI's third-party libraries into crates/buildit-api/static/vendor
+# so the server binary embeds them instead of linking their CDN. Keep the
+# list in sync with VENDORED in crates/buildit-api/src/routes/assets.rs.
+set -e
+
+dir="$(dirname "$0")/../crates/buildit-api/static/vendor"
+mkdir -p "$dir"
+
+fetch() {
+    echo "Fetching $1"
+    curl -fsSL -o "$dir/$1" "$2"
+}
+
+fetch tailwindcss.js "https://cdn.tailwindcss.com"
+fetch htmx.min.js "https://unpkg.com/htmx.org@2.0.4"
+fetch htmx-ext-ws.js "https://unpkg.com/htmx-ext-ws@2.0.2/ws.js"
+fetch htmx-ext-sse.js "https://unpkg.com/htmx-ext-sse@2.2.2/sse.js"
+fetch xterm.css "https://unpkg.com/@xterm/xterm@5.5.0/css/xterm.css"
+fetch xterm.js "https://unpkg.com/@xterm/xterm@5.5.0/lib/xterm.js"
+fetch addon-fit.js "https://unpkg.com/@xterm/addon-fit@0.10.0/lib/addon-fit.js"
+fetch swagger-ui.css "https://unpkg.com/swagger-ui-dist@5/swagger-ui.css"
+fetch swagger-ui-bundle.js "https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"